@@ -1,5 +1,7 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use std::hint::black_box;
+use std::time::{Duration, Instant};
+use synckit_core::crdt::text_fugue::FugueTextOptions;
 use synckit_core::crdt::FugueText;
 
 /// Benchmark single character insert (target: <1ms)
@@ -41,6 +43,42 @@ fn bench_large_batch_insert(c: &mut Criterion) {
     });
 }
 
+/// Benchmark loading a 1MB document of paragraphs: the insert loop an app
+/// would write against building the blocks directly (target: 10x faster)
+fn bench_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fugue_load_1mb");
+    group.sample_size(10);
+
+    let paragraphs: Vec<String> = (0..1_000)
+        .map(|i| format!("{:04} {}\n", i, "lorem ipsum dolor sit amet ".repeat(40)))
+        .collect();
+    let content = paragraphs.concat();
+    assert!(content.len() >= 1_000_000);
+
+    group.bench_function("insert_loop", |b| {
+        b.iter(|| {
+            let mut text = FugueText::new("client1".to_string());
+            for paragraph in &paragraphs {
+                text.insert(text.len(), paragraph).unwrap();
+            }
+            black_box(text)
+        });
+    });
+    group.bench_function("from_paragraphs", |b| {
+        b.iter(|| {
+            black_box(FugueText::from_paragraphs(
+                "client1".to_string(),
+                &paragraphs,
+            ))
+        });
+    });
+    group.bench_function("from_str", |b| {
+        b.iter(|| black_box(FugueText::from_str("client1".to_string(), &content)));
+    });
+
+    group.finish();
+}
+
 /// Benchmark delete operations
 fn bench_delete(c: &mut Criterion) {
     c.bench_function("fugue_delete_1000_chars", |b| {
@@ -59,6 +97,58 @@ fn bench_delete(c: &mut Criterion) {
     });
 }
 
+/// Benchmark deleting single characters at random positions in a document
+/// of many small blocks
+fn bench_delete_random(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fugue_delete_random_char");
+    group.sample_size(10);
+
+    for blocks in [10_000, 100_000].iter() {
+        // Deleting every other character leaves `blocks` one-character
+        // blocks with tombstones between them, which re-chunking would
+        // join back up as the text shrinks
+        let options = FugueTextOptions {
+            max_blocks_per_char: 0,
+            ..FugueTextOptions::default()
+        };
+        let mut text = FugueText::with_options("client1".to_string(), options);
+        text.insert(0, &"a".repeat(2 * blocks)).unwrap();
+        for position in 0..*blocks {
+            text.delete(position, 1).unwrap();
+        }
+        let mut seed = 0x9E37_79B9_7F4A_7C15_u64;
+        let mut random_position = move |len: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % len as u64) as usize
+        };
+
+        group.bench_with_input(BenchmarkId::from_parameter(blocks), &text, |b, text| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                let mut left = iters;
+                while left > 0 {
+                    // A fresh copy for every half of the text deleted; the
+                    // first delete copies the shared blocks, untimed
+                    let mut text = text.clone();
+                    text.delete(random_position(text.len()), 1).unwrap();
+                    let run = left.min(text.len() as u64 / 2);
+                    let start = Instant::now();
+                    for _ in 0..run {
+                        black_box(text.delete(random_position(text.len()), 1).unwrap());
+                    }
+                    elapsed += start.elapsed();
+                    left -= run;
+                }
+                elapsed
+            });
+        });
+    }
+
+    group.finish();
+}
+
 /// Benchmark the famous Yjs 260K operations test
 /// Target: < 500ms total
 /// This simulates 260K sequential character insertions
@@ -110,6 +200,23 @@ fn bench_merge(c: &mut Criterion) {
     });
 }
 
+/// Benchmark speculative forks: clone a 1000-block document 1000 times and
+/// edit one of the forks
+fn bench_fork(c: &mut Criterion) {
+    let mut text = FugueText::new("client1".to_string());
+    for i in 0..1000 {
+        text.insert(i * 10, "abcdefghij").unwrap();
+    }
+
+    c.bench_function("fugue_fork_1000_mutate_once", |b| {
+        b.iter(|| {
+            let mut forks: Vec<FugueText> = (0..1000).map(|_| text.clone()).collect();
+            forks[0].insert(0, "b").unwrap();
+            black_box(forks);
+        });
+    });
+}
+
 /// Benchmark concurrent edits convergence
 fn bench_concurrent_convergence(c: &mut Criterion) {
     c.bench_function("fugue_concurrent_3way_convergence", |b| {
@@ -183,18 +290,56 @@ fn bench_rle_efficiency(c: &mut Criterion) {
     });
 }
 
+/// Benchmark merging a loaded replica whose 2K blocks a UUID client
+/// wrote, and report their metadata per block: the client ID is shared by
+/// all of them rather than copied into each
+fn bench_uuid_clients(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fugue_uuid_clients");
+    group.sample_size(10);
+
+    let mut remote = FugueText::new("6f1c2a7e-93d4-4b8e-a1f0-2c5d9e7b3a61".to_string());
+    for _ in 0..2_000 {
+        // Typing backwards: every keystroke is a block of its own
+        remote.insert(0, "a").unwrap();
+    }
+    let remote: FugueText = serde_json::from_str(&serde_json::to_string(&remote).unwrap()).unwrap();
+    let stats = remote.memory_stats();
+    println!(
+        "uuid client: {} blocks, {} metadata bytes per block",
+        stats.block_count,
+        stats.metadata_bytes / stats.block_count
+    );
+
+    group.bench_function("merge_2k_blocks", |b| {
+        b.iter_batched(
+            || FugueText::new("0d9b8c4f-1e2a-4f6b-9c3d-7a8e5b2f1c04".to_string()),
+            |mut local| {
+                local.merge(&remote).unwrap();
+                black_box(local)
+            },
+            criterion::BatchSize::SmallInput,
+        );
+    });
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_single_insert,
     bench_sequential_typing,
     bench_large_batch_insert,
+    bench_load,
     bench_delete,
+    bench_delete_random,
     bench_yjs_260k_ops,
     bench_merge,
+    bench_fork,
     bench_concurrent_convergence,
     bench_serialization,
     bench_deserialization,
     bench_rle_efficiency,
+    bench_uuid_clients,
 );
 
 criterion_main!(benches);
@@ -0,0 +1,52 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+use synckit_core::crdt::text_fugue::{LineIndex, LinePosition, TextEvent};
+
+/// `lines` lines of 40 characters
+fn source(lines: usize) -> String {
+    let line = format!("{}\n", "x".repeat(39));
+    line.repeat(lines)
+}
+
+/// One keystroke in the middle of the text, then the conversions an
+/// editor makes for it; the insert is deleted again so the size holds
+fn keystroke(index: &mut LineIndex) {
+    let middle = index.len() / 2;
+    index
+        .apply(&[TextEvent::Insert {
+            position: middle,
+            text: "a\n".to_string(),
+        }])
+        .unwrap();
+    let position = index.offset_to_position(middle + 2).unwrap();
+    black_box(index.position_to_offset(LinePosition {
+        line: position.line,
+        column: 0,
+    }));
+    index
+        .apply(&[TextEvent::Delete {
+            position: middle,
+            length: 2,
+        }])
+        .unwrap();
+}
+
+/// Per-edit cost of the incremental index against rebuilding it, by
+/// document size: the first should barely grow with the size
+fn bench_edit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("line_index_edit");
+    for lines in [1_000, 10_000, 100_000] {
+        let text = source(lines);
+        let mut index = LineIndex::new(&text);
+        group.bench_with_input(BenchmarkId::new("incremental", lines), &lines, |b, _| {
+            b.iter(|| keystroke(&mut index));
+        });
+        group.bench_with_input(BenchmarkId::new("rebuild", lines), &text, |b, text| {
+            b.iter(|| black_box(LineIndex::new(text)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_edit);
+criterion_main!(benches);
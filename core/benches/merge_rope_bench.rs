@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::hint::black_box;
+use synckit_core::crdt::text_fugue::FugueText;
+
+/// A 500k-char document written as 100 paragraphs at scattered positions,
+/// and a replica of it that typed one character in the middle
+fn replicas() -> (FugueText, FugueText) {
+    let mut local = FugueText::new("local".to_string());
+    let paragraph = format!("{}\n", "lorem ipsum dolor sit amet ".repeat(186));
+    let mut seed = 1u64;
+    for _ in 0..100 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let position = (seed % (local.len() as u64 + 1)) as usize;
+        local.insert(position, &paragraph).unwrap();
+    }
+    assert!(local.len() >= 500_000);
+
+    let mut remote = FugueText::new("remote".to_string());
+    remote.merge(&local).unwrap();
+    remote.insert(remote.len() / 2, "x").unwrap();
+    (local, remote)
+}
+
+/// Bring a 500k-char document up to date with one remote keystroke, by
+/// state-based merge and by delta
+fn bench_single_remote_block(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fugue_single_remote_block_500k");
+
+    let (local, remote) = replicas();
+    let delta = remote.diff_since(&local.state_vector());
+    group.bench_with_input(
+        BenchmarkId::from_parameter("merge"),
+        &remote,
+        |b, remote| {
+            b.iter_batched(
+                || local.clone(),
+                |mut local| {
+                    local.merge(remote).unwrap();
+                    black_box(local)
+                },
+                BatchSize::LargeInput,
+            );
+        },
+    );
+    group.bench_with_input(BenchmarkId::from_parameter("delta"), &delta, |b, delta| {
+        b.iter_batched(
+            || local.clone(),
+            |mut local| black_box(local.apply_delta(delta).unwrap()),
+            BatchSize::LargeInput,
+        );
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_single_remote_block);
+criterion_main!(benches);
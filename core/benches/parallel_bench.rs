@@ -0,0 +1,85 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use serde_json::json;
+use std::collections::HashMap;
+use std::hint::black_box;
+use synckit_core::crdt::FugueText;
+use synckit_core::{parallel, Document, DocumentID};
+
+fn workspace(client: &str, docs: usize, clock: u64) -> HashMap<DocumentID, Document> {
+    (0..docs)
+        .map(|d| {
+            let id = format!("doc{}", d);
+            let mut document = Document::new(id.clone());
+            for f in 0..50 {
+                document.set_field(
+                    format!("field{}", f),
+                    json!(format!("{}-{}-{}", client, d, f)),
+                    clock + (f % 2) as u64,
+                    client.to_string(),
+                );
+            }
+            (id, document)
+        })
+        .collect()
+}
+
+/// Merge a day of divergence across 2000 documents (should scale with threads)
+fn bench_merge_documents(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel_merge_documents");
+    group.sample_size(10);
+
+    let local = workspace("alice", 2000, 1);
+    let remote = workspace("bob", 2000, 2);
+    for threads in [1, 2, 4, 8] {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, _| {
+            b.iter(|| {
+                let mut merged = local.clone();
+                pool.install(|| black_box(parallel::merge_documents(&mut merged, &remote)));
+            });
+        });
+    }
+
+    group.finish();
+}
+
+/// Merge two large text replicas edited by many clients
+fn bench_merge_text(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel_merge_text");
+    group.sample_size(10);
+
+    let mut base = FugueText::new("base".to_string());
+    base.insert(0, &"lorem ipsum ".repeat(200)).unwrap();
+    let mut remote = base.clone();
+    for client in 0..32 {
+        let mut replica = FugueText::new(format!("client{}", client));
+        replica.merge(&base).unwrap();
+        for i in 0..50 {
+            replica
+                .insert((i * 37 + client) % replica.len(), "x")
+                .unwrap();
+        }
+        remote.merge(&replica).unwrap();
+    }
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            let mut local = base.clone();
+            local.merge(black_box(&remote)).unwrap();
+        });
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| {
+            let mut local = base.clone();
+            local.merge_parallel(black_box(&remote)).unwrap();
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_merge_documents, bench_merge_text);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+use synckit_core::crdt::text_fugue::FugueText;
+
+/// A text of `chars` characters, typed as lines of 40
+fn document(chars: usize) -> FugueText {
+    let mut text = FugueText::new("alice".to_string());
+    let line = format!("{}\n", "x".repeat(39));
+    text.insert(0, &line.repeat(chars / 40)).unwrap();
+    text
+}
+
+/// Reading 100 characters from the middle, by document size: slicing
+/// should stay flat while copying the whole text grows with it
+fn bench_viewport(c: &mut Criterion) {
+    let mut group = c.benchmark_group("slice_viewport");
+    for chars in [10_000, 100_000, 1_000_000] {
+        let text = document(chars);
+        let middle = text.len() / 2;
+        group.bench_with_input(BenchmarkId::new("slice", chars), &text, |b, text| {
+            b.iter(|| black_box(text.slice(middle..middle + 100).unwrap()));
+        });
+        group.bench_with_input(BenchmarkId::new("graphemes_in", chars), &text, |b, text| {
+            b.iter(|| black_box(text.graphemes_in(middle..middle + 100).unwrap().count()));
+        });
+        group.bench_with_input(BenchmarkId::new("to_string", chars), &text, |b, text| {
+            b.iter(|| black_box(text.to_string()[middle..middle + 100].to_string()));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_viewport);
+criterion_main!(benches);
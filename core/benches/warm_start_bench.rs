@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+use synckit_core::crdt::text_fugue::{FugueText, TextEncodeOptions};
+
+/// A 500k-char document written as 100 paragraphs at scattered positions
+fn document() -> FugueText {
+    let mut text = FugueText::new("client1".to_string());
+    let paragraph = format!("{}\n", "lorem ipsum dolor sit amet ".repeat(186));
+    let mut seed = 1u64;
+    for _ in 0..100 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let position = (seed % (text.len() as u64 + 1)) as usize;
+        text.insert(position, &paragraph).unwrap();
+    }
+    assert!(text.len() >= 500_000);
+    text
+}
+
+/// Decode a stored document and type its first character (the
+/// first-keystroke hitch), with and without the warm position cache
+fn bench_first_edit_after_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fugue_first_edit_after_load_500k");
+    group.sample_size(10);
+
+    let text = document();
+    for (name, options) in [
+        ("cold", TextEncodeOptions::network()),
+        ("warm", TextEncodeOptions::local()),
+    ] {
+        let bytes = text.encode(&options).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(name), &bytes, |b, bytes| {
+            b.iter(|| {
+                let mut loaded = FugueText::decode(bytes).unwrap();
+                let middle = loaded.len() / 2;
+                black_box(loaded.insert(middle, "x").unwrap());
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_first_edit_after_load);
+criterion_main!(benches);
@@ -1,9 +1,12 @@
 // Build script to generate Rust code from Protocol Buffers
-// Only runs when prost feature is enabled (for core, not core-lite)
+// Only runs with the protocol-binary feature (for core, not core-lite)
+//
+// With the ffi feature it also compiles the C test program in tests/ffi/
+// and links it into test targets only.
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Only compile protobufs if prost feature is enabled
-    #[cfg(feature = "prost")]
+    // Only compile protobufs with the protocol-binary feature
+    #[cfg(feature = "protocol-binary")]
     {
         // Set up vendored protoc
         std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
@@ -24,11 +27,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             // Compile the proto files
             .compile_protos(&proto_files, &["../protocol/specs/"])?;
 
+        // gRPC service stubs, referring to the messages generated above
+        #[cfg(feature = "grpc")]
+        tonic_prost_build::configure()
+            .extern_path(".synckit.protocol", "crate::protocol")
+            .compile_protos(&["../protocol/specs/sync.proto"], &["../protocol/specs/"])?;
+
         // Tell cargo to recompile if proto files change
         for proto in &proto_files {
             println!("cargo:rerun-if-changed={}", proto);
         }
     }
 
+    // Compile the C FFI test program (linked into tests, not the library)
+    #[cfg(feature = "ffi")]
+    {
+        cc::Build::new()
+            .file("tests/ffi/ffi_test.c")
+            .include("include")
+            .warnings(true)
+            .cargo_metadata(false)
+            .compile("synckit_ffi_test");
+
+        let out_dir = std::env::var("OUT_DIR")?;
+        println!(
+            "cargo:rustc-link-arg-tests={}/libsynckit_ffi_test.a",
+            out_dir
+        );
+        println!("cargo:rerun-if-changed=tests/ffi/ffi_test.c");
+        println!("cargo:rerun-if-changed=include/synckit.h");
+    }
+
     Ok(())
 }
@@ -0,0 +1,591 @@
+//! `#[derive(SyncModel)]` for SyncKit
+//!
+//! Use it through `synckit_core::model::SyncModel` (the `derive` feature
+//! of `synckit-core`), whose docs describe the field kinds and the code
+//! generated for them. This crate only parses the struct and emits calls
+//! into `synckit_core::model`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote, quote_spanned};
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
+use syn::{
+    Data, DeriveInput, Fields, GenericArgument, Ident, LitInt, LitStr, PathArguments, Type,
+    Visibility,
+};
+
+/// Methods of the generated wrapper, which a field's getter would clash
+/// with
+const WRAPPER_METHODS: &[&str] = &[
+    "new",
+    "open",
+    "document",
+    "document_mut",
+    "into_document",
+    "snapshot",
+    "subscribe",
+];
+
+/// Store a struct as a SyncKit document (see `synckit_core::model`)
+#[proc_macro_derive(SyncModel, attributes(synckit))]
+pub fn derive_sync_model(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match Model::parse(&input) {
+        Ok(model) => model.expand().into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// How a field is stored
+enum Kind {
+    Value,
+    Counter,
+    Text,
+    /// An `ORSet` of the given members
+    Set(Box<Type>),
+}
+
+struct Field {
+    ident: Ident,
+    ty: Type,
+    path: String,
+    kind: Kind,
+}
+
+impl Field {
+    fn parse(field: &syn::Field) -> syn::Result<Self> {
+        let ident = field.ident.clone().expect("named fields have names");
+        let mut kind: Option<(&'static str, Span)> = None;
+        let mut path = None;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("synckit"))
+        {
+            attr.parse_nested_meta(|meta| {
+                let name = meta.path.get_ident().map(Ident::to_string);
+                let named = match name.as_deref() {
+                    Some("counter") => Some("counter"),
+                    Some("text") => Some("text"),
+                    Some("set") => Some("set"),
+                    _ => None,
+                };
+                match (named, name.as_deref()) {
+                    (Some(named), _) => {
+                        if let Some((previous, _)) = kind {
+                            return Err(meta.error(format!(
+                                "field is already `{}`; a field has one kind",
+                                previous
+                            )));
+                        }
+                        kind = Some((named, meta.path.span()));
+                        Ok(())
+                    }
+                    (None, Some("rename")) => {
+                        let renamed: LitStr = meta.value()?.parse()?;
+                        path = Some((renamed.value(), renamed.span()));
+                        Ok(())
+                    }
+                    _ => Err(meta.error(
+                        "unknown synckit field attribute; expected `counter`, `text`, `set` or `rename = \"...\"`",
+                    )),
+                }
+            })?;
+        }
+
+        let (path, path_span) = path.unwrap_or_else(|| (ident.unraw().to_string(), ident.span()));
+        if path.is_empty() || path.starts_with('$') {
+            return Err(syn::Error::new(
+                path_span,
+                "paths starting with `$` are reserved for SyncKit, and paths can't be empty",
+            ));
+        }
+
+        let ty = field.ty.clone();
+        let kind = match kind {
+            Some(("counter", span)) => {
+                if !is_named(&ty, "i64") {
+                    return Err(syn::Error::new(span, "`counter` fields must be `i64`"));
+                }
+                Kind::Counter
+            }
+            Some(("text", span)) => {
+                if !is_named(&ty, "String") {
+                    return Err(syn::Error::new(span, "`text` fields must be `String`"));
+                }
+                Kind::Text
+            }
+            Some((_, span)) => match set_member(&ty) {
+                Some(member) => Kind::Set(Box::new(member)),
+                None => return Err(syn::Error::new(span, "`set` fields must be `ORSet<T>`")),
+            },
+            None => match set_member(&ty) {
+                Some(member) => Kind::Set(Box::new(member)),
+                None => {
+                    check_value_type(&ty)?;
+                    Kind::Value
+                }
+            },
+        };
+        Ok(Self {
+            ident,
+            ty,
+            path,
+            kind,
+        })
+    }
+
+    /// Name of the field's variant in the change enum
+    fn variant(&self) -> Ident {
+        let name: String = self
+            .ident
+            .unraw()
+            .to_string()
+            .split('_')
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                let mut chars = word.chars();
+                let first = chars.next().map(|c| c.to_ascii_uppercase());
+                first.into_iter().chain(chars).collect::<String>()
+            })
+            .collect();
+        Ident::new(&name, self.ident.span())
+    }
+}
+
+/// Check if `ty` is a plain path ending in `name`, like `String` or
+/// `std::string::String`
+fn is_named(ty: &Type, name: &str) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last().is_some_and(|last| {
+            last.ident == name && matches!(last.arguments, PathArguments::None)
+        }),
+        _ => false,
+    }
+}
+
+/// The member type of an `ORSet<T>`
+fn set_member(ty: &Type) -> Option<Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let last = path.path.segments.last()?;
+    if last.ident != "ORSet" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(arguments) = &last.arguments else {
+        return None;
+    };
+    match arguments.args.iter().collect::<Vec<_>>().as_slice() {
+        [GenericArgument::Type(member)] => Some(member.clone()),
+        _ => None,
+    }
+}
+
+/// Reject types a document field can't own; serde bounds catch the rest
+fn check_value_type(ty: &Type) -> syn::Result<()> {
+    let problem = match ty {
+        Type::Reference(_) => "borrowed fields aren't supported; use an owned type",
+        Type::Ptr(_) => "raw pointer fields aren't supported",
+        Type::BareFn(_) | Type::TraitObject(_) | Type::ImplTrait(_) => {
+            "field types must be data that serializes to JSON"
+        }
+        Type::Paren(inner) => return check_value_type(&inner.elem),
+        Type::Group(inner) => return check_value_type(&inner.elem),
+        Type::Array(array) => return check_value_type(&array.elem),
+        Type::Slice(_) => "unsized fields aren't supported; use a `Vec`",
+        Type::Tuple(tuple) => {
+            return tuple.elems.iter().try_for_each(check_value_type);
+        }
+        _ => return Ok(()),
+    };
+    Err(syn::Error::new(ty.span(), problem))
+}
+
+struct Model {
+    ident: Ident,
+    vis: Visibility,
+    version: u32,
+    fields: Vec<Field>,
+}
+
+impl Model {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let named = match &input.data {
+            Data::Struct(data) => match &data.fields {
+                Fields::Named(named) => named,
+                _ => {
+                    return Err(syn::Error::new(
+                        input.ident.span(),
+                        "SyncModel needs a struct with named fields",
+                    ))
+                }
+            },
+            _ => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "SyncModel can only be derived for structs",
+                ))
+            }
+        };
+        if !input.generics.params.is_empty() {
+            return Err(syn::Error::new(
+                input.generics.span(),
+                "SyncModel can't be derived for generic structs",
+            ));
+        }
+
+        let mut version = 1;
+        for attr in input
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("synckit"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("version") {
+                    let lit: LitInt = meta.value()?.parse()?;
+                    version = lit.base10_parse()?;
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown synckit struct attribute; expected `version = N`"))
+                }
+            })?;
+        }
+
+        let mut fields: Vec<Field> = Vec::new();
+        for field in &named.named {
+            let parsed = Field::parse(field)?;
+            let getter = parsed.ident.unraw().to_string();
+            if WRAPPER_METHODS.contains(&getter.as_str()) {
+                return Err(syn::Error::new(
+                    parsed.ident.span(),
+                    format!(
+                        "a field named `{}` would clash with `{}Document::{}`; rename the field",
+                        getter, input.ident, getter
+                    ),
+                ));
+            }
+            if let Some(other) = fields.iter().find(|other| overlaps(other, &parsed)) {
+                return Err(syn::Error::new(
+                    parsed.ident.span(),
+                    format!("path `{}` is also used by `{}`", parsed.path, other.ident),
+                ));
+            }
+            fields.push(parsed);
+        }
+
+        Ok(Self {
+            ident: input.ident.clone(),
+            vis: input.vis.clone(),
+            version,
+            fields,
+        })
+    }
+
+    fn expand(&self) -> TokenStream2 {
+        let Self {
+            ident,
+            vis,
+            version,
+            ..
+        } = self;
+        let wrapper = format_ident!("{}Document", ident);
+        let change = format_ident!("{}Change", ident);
+        let model = quote!(::synckit_core::model);
+        let result = quote!(::synckit_core::Result);
+
+        let variants: Vec<Ident> = self.fields.iter().map(Field::variant).collect();
+        let variant_docs = self
+            .fields
+            .iter()
+            .map(|field| format!("`{}` changed", field.ident.unraw()));
+
+        let reads = self.fields.iter().map(|field| {
+            let Field {
+                ident, ty, path, ..
+            } = field;
+            // Spanned so a type serde can't handle is reported on the field
+            let value = match &field.kind {
+                Kind::Value => {
+                    quote_spanned!(ty.span()=> ::synckit_core::model::read_value(document, #path)?)
+                }
+                Kind::Counter => quote!(#model::read_counter(document, #path)),
+                Kind::Text => quote!(#model::read_text(document, #path)),
+                Kind::Set(member) => quote_spanned!(member.span()=> {
+                    let mut set = <#ty>::new(::std::string::ToString::to_string(client_id));
+                    for member in ::synckit_core::model::read_set(document, #path)? {
+                        set.add(member);
+                    }
+                    set
+                }),
+            };
+            quote!(#ident: #value)
+        });
+
+        let writes = self.fields.iter().map(|field| {
+            let Field {
+                ident, ty, path, ..
+            } = field;
+            match &field.kind {
+                Kind::Value => quote_spanned!(ty.span()=> model.write_value(#path, &self.#ident)?;),
+                Kind::Counter => quote!(model.increment(#path, self.#ident);),
+                Kind::Text => quote!(model.write_text(#path, &self.#ident);),
+                Kind::Set(member) => quote_spanned! {member.span()=>
+                    for member in self.#ident.iter() {
+                        model.add_to_set(#path, member)?;
+                    }
+                },
+            }
+        });
+
+        let matches = self.fields.iter().zip(&variants).map(|(field, variant)| {
+            let path = &field.path;
+            match field.kind {
+                Kind::Counter => quote! {
+                    if path.strip_prefix(#path).is_some_and(|rest| rest.starts_with('.')) {
+                        return ::std::option::Option::Some(#change::#variant);
+                    }
+                },
+                _ => quote! {
+                    if path == #path {
+                        return ::std::option::Option::Some(#change::#variant);
+                    }
+                },
+            }
+        });
+
+        let accessors = self
+            .fields
+            .iter()
+            .map(|field| field.accessors(&model, &result));
+
+        let wrapper_doc = format!(
+            "A document holding a [`{0}`], with typed accessors for its fields\n\n\
+             Generated by `#[derive(SyncModel)]`; see `synckit_core::model`.",
+            ident
+        );
+        let change_doc = format!(
+            "A change to a field of a [`{}Document`], projected from the document's field events",
+            ident
+        );
+
+        quote! {
+            impl #model::SyncModel for #ident {
+                const SCHEMA_VERSION: u32 = #version;
+
+                type Change = #change;
+
+                #[allow(unused_variables)]
+                fn read(document: &::synckit_core::Document, client_id: &str) -> #result<Self> {
+                    ::std::result::Result::Ok(Self {
+                        #(#reads,)*
+                    })
+                }
+
+                #[allow(unused_variables)]
+                fn write(self, model: &mut #model::ModelDocument) -> #result<()> {
+                    #(#writes)*
+                    ::std::result::Result::Ok(())
+                }
+
+                #[allow(unused_variables)]
+                fn change_for(path: &str) -> ::std::option::Option<#change> {
+                    #(#matches)*
+                    ::std::option::Option::None
+                }
+            }
+
+            #[doc = #change_doc]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+            #vis enum #change {
+                #(
+                    #[doc = #variant_docs]
+                    #variants,
+                )*
+            }
+
+            #[doc = #wrapper_doc]
+            #[derive(Debug)]
+            #vis struct #wrapper {
+                model: #model::ModelDocument,
+            }
+
+            impl #wrapper {
+                /// A new document holding `value`, written by `client_id`
+                pub fn new(
+                    value: #ident,
+                    id: ::synckit_core::DocumentID,
+                    client_id: ::synckit_core::ClientID,
+                ) -> #result<Self> {
+                    let mut model = #model::ModelDocument::create(
+                        id,
+                        client_id,
+                        <#ident as #model::SyncModel>::SCHEMA_VERSION,
+                    );
+                    #model::SyncModel::write(value, &mut model)?;
+                    ::std::result::Result::Ok(Self { model })
+                }
+
+                /// Edit `document` as `client_id`, checking its schema version
+                pub fn open(
+                    document: ::synckit_core::Document,
+                    client_id: ::synckit_core::ClientID,
+                ) -> #result<Self> {
+                    let model = #model::ModelDocument::open(
+                        document,
+                        client_id,
+                        <#ident as #model::SyncModel>::SCHEMA_VERSION,
+                    )?;
+                    ::std::result::Result::Ok(Self { model })
+                }
+
+                /// The underlying document
+                pub fn document(&self) -> &::synckit_core::Document {
+                    self.model.document()
+                }
+
+                /// The underlying document, e.g. to apply an incoming delta
+                pub fn document_mut(&mut self) -> &mut ::synckit_core::Document {
+                    self.model.document_mut()
+                }
+
+                /// Unwrap the underlying document
+                pub fn into_document(self) -> ::synckit_core::Document {
+                    self.model.into_document()
+                }
+
+                /// Read every field
+                pub fn snapshot(&self) -> #result<#ident> {
+                    <#ident as #model::SyncModel>::read(self.model.document(), self.model.client_id())
+                }
+
+                /// Call `callback` with the fields each batch of document
+                /// changes touched
+                pub fn subscribe(
+                    &mut self,
+                    mut callback: impl FnMut(&[#change]) + Send + Sync + 'static,
+                ) -> ::synckit_core::notify::SubscriptionId {
+                    self.model.document_mut().subscribe(move |events| {
+                        let changes = <#ident as #model::SyncModel>::changes(events);
+                        if !changes.is_empty() {
+                            callback(&changes);
+                        }
+                    })
+                }
+
+                #(#accessors)*
+            }
+        }
+    }
+}
+
+impl Field {
+    fn accessors(&self, model: &TokenStream2, result: &TokenStream2) -> TokenStream2 {
+        let Field {
+            ident, ty, path, ..
+        } = self;
+        let name = ident.unraw();
+        let doc = |what: &str| format!("{} `{}`", what, name);
+        match &self.kind {
+            Kind::Value => {
+                let set = format_ident!("set_{}", name);
+                let (get_doc, set_doc) = (doc("Read"), doc("Write"));
+                let read = quote_spanned!(ty.span()=> ::synckit_core::model::read_value(self.model.document(), #path));
+                let write = quote_spanned!(ty.span()=> self.model.write_value(#path, &value));
+                quote! {
+                    #[doc = #get_doc]
+                    pub fn #ident(&self) -> #result<#ty> {
+                        #read
+                    }
+
+                    #[doc = #set_doc]
+                    pub fn #set(&mut self, value: #ty) -> #result<()> {
+                        #write
+                    }
+                }
+            }
+            Kind::Counter => {
+                let increment = format_ident!("increment_{}", name);
+                let (get_doc, add_doc) = (doc("Read"), doc("Add to (or, negative, subtract from)"));
+                quote! {
+                    #[doc = #get_doc]
+                    pub fn #ident(&self) -> i64 {
+                        #model::read_counter(self.model.document(), #path)
+                    }
+
+                    #[doc = #add_doc]
+                    pub fn #increment(&mut self, by: i64) {
+                        self.model.increment(#path, by)
+                    }
+                }
+            }
+            Kind::Text => {
+                let set = format_ident!("set_{}", name);
+                let splice = format_ident!("splice_{}", name);
+                let (get_doc, set_doc, splice_doc) = (
+                    doc("Read"),
+                    doc("Write, editing only the changed part of,"),
+                    doc("Replace `delete` characters at `index` with `insert` in"),
+                );
+                quote! {
+                    #[doc = #get_doc]
+                    pub fn #ident(&self) -> ::std::string::String {
+                        #model::read_text(self.model.document(), #path)
+                    }
+
+                    #[doc = #set_doc]
+                    pub fn #set(&mut self, text: &str) {
+                        self.model.write_text(#path, text)
+                    }
+
+                    #[doc = #splice_doc]
+                    pub fn #splice(&mut self, index: usize, delete: usize, insert: &str) -> #result<()> {
+                        self.model.splice_text(#path, index, delete, insert)
+                    }
+                }
+            }
+            Kind::Set(member) => {
+                let add = format_ident!("add_{}", name);
+                let remove = format_ident!("remove_{}", name);
+                let (get_doc, add_doc, remove_doc) = (
+                    doc("Read the members of"),
+                    doc("Add a member to"),
+                    doc("Remove a member from"),
+                );
+                let span = member.span();
+                let read = quote_spanned!(span=> ::synckit_core::model::read_set(self.model.document(), #path));
+                let add_call = quote_spanned!(span=> self.model.add_to_set(#path, &member));
+                let remove_call = quote_spanned!(span=> self.model.remove_from_set(#path, member));
+                quote! {
+                    #[doc = #get_doc]
+                    pub fn #ident(&self) -> #result<::std::vec::Vec<#member>> {
+                        #read
+                    }
+
+                    #[doc = #add_doc]
+                    pub fn #add(&mut self, member: #member) -> #result<bool> {
+                        #add_call
+                    }
+
+                    #[doc = #remove_doc]
+                    pub fn #remove(&mut self, member: &#member) -> #result<bool> {
+                        #remove_call
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Check if two fields would write the same document paths
+fn overlaps(a: &Field, b: &Field) -> bool {
+    let counted = |counter: &Field, other: &Field| {
+        matches!(counter.kind, Kind::Counter)
+            && other
+                .path
+                .strip_prefix(&counter.path)
+                .is_some_and(|rest| rest.starts_with('.'))
+    };
+    a.path == b.path || counted(a, b) || counted(b, a)
+}
@@ -0,0 +1,338 @@
+/*
+ * SyncKit C ABI
+ *
+ * Ownership:
+ *   - String arguments are borrowed NUL-terminated UTF-8, read only during the call.
+ *   - Returned `char *` strings are owned by the caller: free with synckit_string_free.
+ *   - Returned `uint8_t *` buffers are owned by the caller: free with
+ *     synckit_bytes_free, passing the length reported through `out_len`.
+ *   - synckit_last_error_message returns a borrowed pointer, valid until the
+ *     next SyncKit call on the same thread.
+ *
+ * Thread safety:
+ *   - SynckitDocument and SynckitText handles may be moved between threads but
+ *     must not be used from two threads at once.
+ *   - Last-error storage is per thread.
+ *   - synckit_string_free, synckit_bytes_free and synckit_version may be called
+ *     from any thread.
+ */
+
+#ifndef SYNCKIT_H
+#define SYNCKIT_H
+
+/* Generated by cbindgen from src/ffi/. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result code returned by fallible FFI calls
+ */
+typedef enum SynckitStatus {
+  /**
+   * The call succeeded
+   */
+  SYNCKIT_STATUS_OK = 0,
+  /**
+   * A required pointer argument was NULL
+   */
+  SYNCKIT_STATUS_NULL_POINTER = 1,
+  /**
+   * A string argument was not valid UTF-8
+   */
+  SYNCKIT_STATUS_INVALID_UTF8 = 2,
+  /**
+   * A JSON argument could not be parsed
+   */
+  SYNCKIT_STATUS_INVALID_JSON = 3,
+  /**
+   * Serialized bytes could not be decoded
+   */
+  SYNCKIT_STATUS_INVALID_BYTES = 4,
+  /**
+   * A text operation failed (e.g. position out of bounds)
+   */
+  SYNCKIT_STATUS_TEXT_ERROR = 5,
+  /**
+   * Rust code panicked; the handle should be discarded
+   */
+  SYNCKIT_STATUS_PANIC = 6,
+} SynckitStatus;
+
+/**
+ * Opaque document handle
+ *
+ * Not thread-safe: may be moved between threads, but must not be used
+ * from two threads at once.
+ */
+typedef struct SynckitDocument SynckitDocument;
+
+/**
+ * Opaque collaborative text handle
+ *
+ * Not thread-safe: may be moved between threads, but must not be used
+ * from two threads at once.
+ */
+typedef struct SynckitText SynckitText;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Get the last error message for the calling thread
+ *
+ * Returns NULL if the last call on this thread succeeded. The pointer is
+ * borrowed and stays valid until the next SyncKit call on this thread.
+ */
+const char *synckit_last_error_message(void);
+
+/**
+ * Get the `SyncKitError` code of the last error on the calling thread
+ *
+ * Codes are stable across releases and match `SyncKitError::code` in Rust
+ * and `err.code` in JavaScript (e.g. 1001 for a text position out of
+ * bounds). Returns 0 if the last call succeeded or panicked.
+ */
+uint32_t synckit_last_error_code(void);
+
+/**
+ * Free a string returned by SyncKit
+ *
+ * # Safety
+ *
+ * `s` must be NULL or a string returned by SyncKit that has not been freed
+ */
+void synckit_string_free(char *s);
+
+/**
+ * Free a byte buffer returned by SyncKit
+ *
+ * # Safety
+ *
+ * `bytes` must be NULL or a buffer returned by SyncKit that has not been
+ * freed, and `len` must be the length SyncKit reported for it
+ */
+void synckit_bytes_free(uint8_t *bytes, size_t len);
+
+/**
+ * Get the SyncKit version as a static NUL-terminated string
+ */
+const char *synckit_version(void);
+
+/**
+ * Create an empty document
+ *
+ * Returns NULL on error. Free with `synckit_document_free`.
+ *
+ * # Safety
+ *
+ * `id` must be a NUL-terminated string
+ */
+struct SynckitDocument *synckit_document_new(const char *id);
+
+/**
+ * Free a document handle
+ *
+ * # Safety
+ *
+ * `doc` must be NULL or a handle from SyncKit that has not been freed
+ */
+void synckit_document_free(struct SynckitDocument *doc);
+
+/**
+ * Set a field from a JSON value (last-writer-wins on `clock`, `client_id`)
+ *
+ * # Safety
+ *
+ * `doc` must be a live handle; string arguments must be NUL-terminated
+ */
+enum SynckitStatus synckit_document_set_field_json(struct SynckitDocument *doc,
+                                                   const char *path,
+                                                   const char *value_json,
+                                                   uint64_t clock,
+                                                   const char *client_id);
+
+/**
+ * Get a field's value as JSON
+ *
+ * Returns NULL if the field doesn't exist (last error unset) or on error
+ * (last error set). Free the result with `synckit_string_free`.
+ *
+ * # Safety
+ *
+ * `doc` must be a live handle; `path` must be NUL-terminated
+ */
+char *synckit_document_get_field_json(struct SynckitDocument *doc, const char *path);
+
+/**
+ * Delete a field
+ *
+ * # Safety
+ *
+ * `doc` must be a live handle; `path` must be NUL-terminated
+ */
+enum SynckitStatus synckit_document_delete_field(struct SynckitDocument *doc, const char *path);
+
+/**
+ * Merge `other` into `doc`
+ *
+ * # Safety
+ *
+ * Both must be live handles; they may be the same handle
+ */
+enum SynckitStatus synckit_document_merge(struct SynckitDocument *doc,
+                                          const struct SynckitDocument *other);
+
+/**
+ * Merge a document serialized with `synckit_document_to_bytes` into `doc`
+ *
+ * # Safety
+ *
+ * `doc` must be a live handle; `bytes` must point to `len` readable bytes
+ */
+enum SynckitStatus synckit_document_merge_bytes(struct SynckitDocument *doc,
+                                                const uint8_t *bytes,
+                                                size_t len);
+
+/**
+ * Get the document's field values as a JSON object
+ *
+ * Returns NULL on error. Free the result with `synckit_string_free`.
+ *
+ * # Safety
+ *
+ * `doc` must be a live handle
+ */
+char *synckit_document_to_json(struct SynckitDocument *doc);
+
+/**
+ * Serialize the document, including LWW metadata, for storage or sync
+ *
+ * Returns NULL on error. Free the result with `synckit_bytes_free`.
+ *
+ * # Safety
+ *
+ * `doc` must be a live handle; `out_len` must point to writable memory
+ */
+uint8_t *synckit_document_to_bytes(struct SynckitDocument *doc, size_t *out_len);
+
+/**
+ * Load a document serialized with `synckit_document_to_bytes`
+ *
+ * Returns NULL on error. Free with `synckit_document_free`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes
+ */
+struct SynckitDocument *synckit_document_from_bytes(const uint8_t *bytes, size_t len);
+
+/**
+ * Create an empty text replica
+ *
+ * Returns NULL on error. Free with `synckit_text_free`.
+ *
+ * # Safety
+ *
+ * `client_id` must be a NUL-terminated string
+ */
+struct SynckitText *synckit_text_new(const char *client_id);
+
+/**
+ * Free a text handle
+ *
+ * # Safety
+ *
+ * `text` must be NULL or a handle from SyncKit that has not been freed
+ */
+void synckit_text_free(struct SynckitText *text);
+
+/**
+ * Insert UTF-8 text at a character position
+ *
+ * # Safety
+ *
+ * `text` must be a live handle; `utf8` must be NUL-terminated
+ */
+enum SynckitStatus synckit_text_insert(struct SynckitText *text, size_t position, const char *utf8);
+
+/**
+ * Delete `length` characters starting at `position`
+ *
+ * # Safety
+ *
+ * `text` must be a live handle
+ */
+enum SynckitStatus synckit_text_delete(struct SynckitText *text, size_t position, size_t length);
+
+/**
+ * Get the length in characters (0 if `text` is NULL)
+ *
+ * # Safety
+ *
+ * `text` must be NULL or a live handle
+ */
+size_t synckit_text_len(const struct SynckitText *text);
+
+/**
+ * Get the current text as a UTF-8 string
+ *
+ * Returns NULL on error. Free the result with `synckit_string_free`.
+ *
+ * # Safety
+ *
+ * `text` must be a live handle
+ */
+char *synckit_text_to_string(struct SynckitText *text);
+
+/**
+ * Merge `other` into `text`
+ *
+ * # Safety
+ *
+ * Both must be live handles; they may be the same handle
+ */
+enum SynckitStatus synckit_text_merge(struct SynckitText *text, const struct SynckitText *other);
+
+/**
+ * Merge a replica serialized with `synckit_text_to_bytes` into `text`
+ *
+ * # Safety
+ *
+ * `text` must be a live handle; `bytes` must point to `len` readable bytes
+ */
+enum SynckitStatus synckit_text_merge_bytes(struct SynckitText *text,
+                                            const uint8_t *bytes,
+                                            size_t len);
+
+/**
+ * Serialize the replica, including CRDT metadata, for storage or sync
+ *
+ * Returns NULL on error. Free the result with `synckit_bytes_free`.
+ *
+ * # Safety
+ *
+ * `text` must be a live handle; `out_len` must point to writable memory
+ */
+uint8_t *synckit_text_to_bytes(struct SynckitText *text, size_t *out_len);
+
+/**
+ * Load a replica serialized with `synckit_text_to_bytes`
+ *
+ * Returns NULL on error. Free with `synckit_text_free`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes
+ */
+struct SynckitText *synckit_text_from_bytes(const uint8_t *bytes, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SYNCKIT_H */
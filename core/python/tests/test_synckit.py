@@ -0,0 +1,126 @@
+"""Tests for the synckit Python bindings.
+
+Run from core/python after building the extension:
+
+    maturin develop
+    pytest
+"""
+
+import pathlib
+import threading
+
+import pytest
+
+import synckit
+
+FIXTURES = pathlib.Path(__file__).resolve().parents[2] / "tests" / "fixtures"
+
+
+def test_document_dict_api():
+    doc = synckit.Document("doc-1", client_id="etl")
+    doc["user.name"] = "Ada"
+    doc["tags"] = ["a", {"nested": True}]
+
+    assert doc["user.name"] == "Ada"
+    assert dict(doc) == {"user.name": "Ada", "tags": ["a", {"nested": True}]}
+    assert doc.to_dict() == dict(doc)
+    assert "tags" in doc
+    assert len(doc) == 2
+
+    del doc["tags"]
+    with pytest.raises(KeyError):
+        doc["tags"]
+    with pytest.raises(TypeError):
+        doc["bad"] = object()
+
+
+def test_document_convergence():
+    a = synckit.Document("doc-1", client_id="a")
+    b = synckit.Document("doc-1", client_id="b")
+    a["title"] = "from a"
+    b["title"] = "from b"
+    b["body"] = "text"
+
+    a.merge(b)
+    b.merge(a)
+    assert dict(a) == dict(b)
+
+    # Writes after a merge win over everything merged so far
+    a["title"] = "latest"
+    b.apply_delta(a.delta(b))
+    assert b["title"] == "latest"
+    assert dict(a) == dict(b)
+
+
+def test_round_trip_document_from_rust():
+    # Written by the Rust test suite (src/python/mod.rs)
+    data = (FIXTURES / "python" / "document.json").read_bytes()
+    doc = synckit.Document.load(data)
+
+    assert doc.id == "report-1"
+    assert doc["title"] == "Quarterly report"
+    assert doc["stats"] == {"views": 42, "ratio": 0.5, "tags": ["q3", None, False]}
+    assert dict(synckit.Document.load(doc.save())) == dict(doc)
+
+
+def test_text_convergence_and_delta_sync():
+    t1 = synckit.FugueText("c1")
+    t2 = synckit.FugueText("c2")
+    t1.insert(0, "Hello")
+    t2.insert(0, "World")
+
+    t1.merge(t2)
+    t2.merge(t1)
+    assert str(t1) == str(t2)
+
+    t3 = synckit.FugueText.load(t1.save())
+    t3.insert(len(t3), "!")
+    events = t1.apply_diff(t3.diff(t1.state_vector()))
+    assert str(t1) == str(t3)
+    assert events == [{"type": "insert", "position": 10, "text": "!"}]
+
+    with pytest.raises(IndexError):
+        t1.delete(100, 1)
+
+
+def test_counter_and_set_convergence():
+    c1, c2 = synckit.Counter("c1"), synckit.Counter("c2")
+    c1.increment(5)
+    c2.decrement(2)
+    c1.merge(c2)
+    c2.merge(c1)
+    assert c1.value == c2.value == 3
+
+    s1, s2 = synckit.Set("c1"), synckit.Set("c2")
+    s1.add("x")
+    s2.add("y")
+    s1.apply_delta(s2.split_delta())
+    s2.merge(s1)
+    assert set(s1) == set(s2) == {"x", "y"}
+
+    s1.remove("x")
+    with pytest.raises(KeyError):
+        s1.remove("x")
+    assert list(s1) == ["y"]
+
+
+def test_merge_releases_gil():
+    big = synckit.Document("doc-1", client_id="big")
+    for i in range(20_000):
+        big[f"field{i}"] = i
+
+    ticks = []
+    stop = threading.Event()
+
+    def tick():
+        while not stop.is_set():
+            ticks.append(1)
+
+    thread = threading.Thread(target=tick)
+    thread.start()
+    for _ in range(5):
+        synckit.Document("doc-1").merge(big)
+    stop.set()
+    thread.join()
+
+    assert ticks
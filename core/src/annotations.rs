@@ -0,0 +1,345 @@
+//! Operation annotations: app-level tags on edits that sync with them
+//!
+//! An app can tag what a batch of edits was ("imported from CSV", a
+//! session ID, an undo group) and show the tag on other replicas, e.g. in
+//! an audit view. An [`Annotation`] covers a range of one client's clocks
+//! rather than single fields, so tagging a large import costs one entry;
+//! a field is covered when its timestamp falls in the range, and a text
+//! character when its ID does.
+//!
+//! Annotations merge as a grow-only set, so every replica ends up with
+//! the same ones, and travel in deltas next to the writes they cover.
+//! Payloads are limited to [`MAX_ANNOTATION_BYTES`] of JSON.
+//!
+//! # Retention
+//!
+//! [`AnnotationRetention`] bounds how many annotations a replica keeps
+//! and for how long. Pruning raises a horizon: annotations created before
+//! it are dropped, also when they arrive again from a replica that still
+//! has them.
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde_json::json;
+//! use synckit_core::Document;
+//!
+//! let mut doc = Document::new("doc-1".to_string());
+//! doc.annotated_transaction(json!({"source": "csv"}), |doc| {
+//!     doc.set_field("name".to_string(), json!("Ada"), 1, "alice".to_string());
+//!     doc.set_field("role".to_string(), json!("admin"), 2, "alice".to_string());
+//! })
+//! .unwrap();
+//!
+//! let annotation = doc.annotations_for(&"role".to_string()).unwrap();
+//! assert_eq!(annotation.payload, json!({"source": "csv"}));
+//! ```
+
+use crate::error::{Result, SyncError};
+use crate::memory::HeapSize;
+use crate::ClientID;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Largest annotation payload, in bytes of JSON
+pub const MAX_ANNOTATION_BYTES: usize = 1024;
+
+/// App-level tag on the edits one client made with clocks
+/// `start..=end`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// Client that made the edits
+    pub client_id: ClientID,
+
+    /// First clock covered
+    pub start: u64,
+
+    /// Last clock covered
+    pub end: u64,
+
+    /// What the app tagged the edits with
+    #[serde(with = "crate::codec::json_value")]
+    pub payload: JsonValue,
+
+    /// When the annotation was made, in milliseconds since the Unix epoch
+    pub created_ms: u64,
+}
+
+impl Annotation {
+    /// Check that a payload is within [`MAX_ANNOTATION_BYTES`]
+    ///
+    /// # Errors
+    ///
+    /// Fails with `ANNOTATION_TOO_LARGE` if it isn't
+    pub fn check_payload(payload: &JsonValue) -> Result<()> {
+        let size = serde_json::to_vec(payload).map_or(usize::MAX, |json| json.len());
+        if size > MAX_ANNOTATION_BYTES {
+            return Err(SyncError::AnnotationTooLarge {
+                size,
+                limit: MAX_ANNOTATION_BYTES,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Whether the annotation covers `client_id`'s edit at `clock`
+    pub fn covers(&self, client_id: &str, clock: u64) -> bool {
+        self.client_id == client_id && self.start <= clock && clock <= self.end
+    }
+
+    /// Merge order between two annotations of the same range start: the
+    /// higher one is kept
+    fn rank(&self) -> (u64, u64, String) {
+        (self.end, self.created_ms, self.payload.to_string())
+    }
+}
+
+/// How many annotations to keep, and for how long
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnnotationRetention {
+    /// Drop annotations older than this
+    pub max_age: Option<Duration>,
+
+    /// Keep at most this many, dropping the oldest
+    pub max_count: Option<usize>,
+}
+
+/// The annotations of a document or text (see the module docs)
+///
+/// A state-based CRDT: [`Annotations::merge`] is commutative, associative
+/// and idempotent.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StoredAnnotations", into = "StoredAnnotations")]
+pub struct Annotations {
+    entries: BTreeMap<(ClientID, u64), Annotation>,
+    horizon_ms: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredAnnotations {
+    entries: Vec<Annotation>,
+    #[serde(default)]
+    horizon_ms: u64,
+}
+
+impl From<StoredAnnotations> for Annotations {
+    fn from(stored: StoredAnnotations) -> Self {
+        let mut annotations = Annotations {
+            entries: BTreeMap::new(),
+            horizon_ms: stored.horizon_ms,
+        };
+        for annotation in stored.entries {
+            annotations.insert(annotation);
+        }
+        annotations
+    }
+}
+
+impl From<Annotations> for StoredAnnotations {
+    fn from(annotations: Annotations) -> Self {
+        StoredAnnotations {
+            entries: annotations.entries.into_values().collect(),
+            horizon_ms: annotations.horizon_ms,
+        }
+    }
+}
+
+impl Annotations {
+    /// Add an annotation; returns whether anything changed
+    ///
+    /// Ignored if its payload is too large or it is older than the
+    /// retention horizon.
+    pub fn insert(&mut self, annotation: Annotation) -> bool {
+        if annotation.created_ms < self.horizon_ms
+            || annotation.start > annotation.end
+            || Annotation::check_payload(&annotation.payload).is_err()
+        {
+            return false;
+        }
+        let key = (annotation.client_id.clone(), annotation.start);
+        match self.entries.get(&key) {
+            Some(existing) if existing.rank() >= annotation.rank() => false,
+            _ => {
+                self.entries.insert(key, annotation);
+                true
+            }
+        }
+    }
+
+    /// The annotation covering `client_id`'s edit at `clock`, if any
+    ///
+    /// If several do, the one starting last.
+    pub fn covering(&self, client_id: &str, clock: u64) -> Option<&Annotation> {
+        self.entries
+            .range(..=(client_id.to_string(), clock))
+            .rev()
+            .take_while(|((client, _), _)| client == client_id)
+            .map(|(_, annotation)| annotation)
+            .find(|annotation| annotation.covers(client_id, clock))
+    }
+
+    /// Every annotation, by client and range start
+    pub fn iter(&self) -> impl Iterator<Item = &Annotation> + '_ {
+        self.entries.values()
+    }
+
+    /// Number of annotations
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if there are no annotations
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Annotations `other` lacks, to send it
+    pub fn missing_from(&self, other: &Annotations) -> Vec<Annotation> {
+        self.entries
+            .iter()
+            .filter(|(key, annotation)| other.entries.get(*key) != Some(annotation))
+            .map(|(_, annotation)| annotation.clone())
+            .collect()
+    }
+
+    /// Merge a remote replica's annotations and retention horizon;
+    /// returns the ones added or replaced
+    pub fn merge(&mut self, remote: &Annotations) -> Vec<Annotation> {
+        self.raise_horizon(remote.horizon_ms);
+        self.extend(remote.entries.values().cloned())
+    }
+
+    /// Add annotations received in a delta; returns the ones added or
+    /// replaced
+    pub fn extend(&mut self, annotations: impl IntoIterator<Item = Annotation>) -> Vec<Annotation> {
+        annotations
+            .into_iter()
+            .filter(|annotation| self.insert(annotation.clone()))
+            .collect()
+    }
+
+    /// Drop what `retention` doesn't keep at `now_ms`; returns how many
+    /// were dropped
+    pub fn prune(&mut self, retention: &AnnotationRetention, now_ms: u64) -> usize {
+        let before = self.entries.len();
+        if let Some(max_age) = retention.max_age {
+            self.raise_horizon(now_ms.saturating_sub(max_age.as_millis() as u64));
+        }
+        if let Some(max_count) = retention.max_count {
+            if self.entries.len() > max_count {
+                let mut created: Vec<u64> = self.entries.values().map(|a| a.created_ms).collect();
+                created.sort_unstable();
+                // Everything up to the newest one over the limit goes
+                let cutoff = created[self.entries.len() - max_count - 1];
+                self.raise_horizon(cutoff.saturating_add(1));
+            }
+        }
+        before - self.entries.len()
+    }
+
+    fn raise_horizon(&mut self, horizon_ms: u64) {
+        if horizon_ms > self.horizon_ms {
+            self.horizon_ms = horizon_ms;
+            self.entries
+                .retain(|_, annotation| annotation.created_ms >= horizon_ms);
+        }
+    }
+}
+
+impl HeapSize for Annotation {
+    fn heap_size(&self) -> usize {
+        self.client_id.heap_size() + self.payload.heap_size()
+    }
+}
+
+impl HeapSize for Annotations {
+    fn heap_size(&self) -> usize {
+        self.entries.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tag(client_id: &str, start: u64, end: u64, created_ms: u64) -> Annotation {
+        Annotation {
+            client_id: client_id.to_string(),
+            start,
+            end,
+            payload: json!({"by": client_id, "at": created_ms}),
+            created_ms,
+        }
+    }
+
+    #[test]
+    fn test_covering_finds_the_range() {
+        let mut annotations = Annotations::default();
+        annotations.insert(tag("alice", 1, 5, 10));
+        annotations.insert(tag("alice", 8, 9, 20));
+        annotations.insert(tag("bob", 1, 100, 30));
+
+        assert_eq!(annotations.covering("alice", 5).unwrap().created_ms, 10);
+        assert!(annotations.covering("alice", 6).is_none());
+        assert_eq!(annotations.covering("alice", 9).unwrap().created_ms, 20);
+        assert!(annotations.covering("alice", 10).is_none());
+        assert_eq!(annotations.covering("bob", 50).unwrap().created_ms, 30);
+        assert!(annotations.covering("carol", 1).is_none());
+    }
+
+    #[test]
+    fn test_merge_converges_and_respects_horizon() {
+        let mut alice = Annotations::default();
+        alice.insert(tag("alice", 1, 2, 100));
+        alice.insert(tag("alice", 3, 4, 200));
+        let mut bob = Annotations::default();
+        bob.insert(tag("bob", 1, 1, 300));
+
+        let mut alice_first = alice.clone();
+        alice_first.merge(&bob);
+        let mut bob_first = bob.clone();
+        bob_first.merge(&alice);
+        assert_eq!(alice_first, bob_first);
+        assert!(alice_first.merge(&bob_first).is_empty());
+
+        // Pruned annotations don't come back from replicas that have them
+        let retention = AnnotationRetention {
+            max_age: Some(Duration::from_millis(150)),
+            max_count: None,
+        };
+        assert_eq!(alice_first.prune(&retention, 400), 2);
+        assert!(alice_first.merge(&alice).is_empty());
+        assert_eq!(alice_first.len(), 1);
+
+        // Bob learns the horizon on merge
+        bob.merge(&alice);
+        bob.merge(&alice_first);
+        assert_eq!(bob, alice_first);
+
+        let retention = AnnotationRetention {
+            max_age: None,
+            max_count: Some(1),
+        };
+        let mut all = alice.clone();
+        all.merge(&bob_first);
+        assert_eq!(all.prune(&retention, 400), 2);
+        assert_eq!(all.iter().next().unwrap().client_id, "bob");
+    }
+
+    #[test]
+    fn test_rejects_oversized_payload() {
+        let payload = json!("x".repeat(MAX_ANNOTATION_BYTES));
+        let error = Annotation::check_payload(&payload).unwrap_err();
+        assert_eq!(error.code_name(), "ANNOTATION_TOO_LARGE");
+        assert!(Annotation::check_payload(&json!("fits")).is_ok());
+
+        let mut annotations = Annotations::default();
+        let mut oversized = tag("alice", 1, 1, 0);
+        oversized.payload = payload;
+        assert!(!annotations.insert(oversized));
+    }
+}
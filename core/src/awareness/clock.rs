@@ -5,6 +5,7 @@
 /// - Awareness state is ephemeral (no complex merge semantics)
 /// - Last-write-wins at field level is acceptable
 /// - Simpler = faster for high-frequency updates (cursor positions)
+use crate::time::TimeProvider;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Thread-safe increasing clock
@@ -57,6 +58,26 @@ impl Clone for IncreasingClock {
     }
 }
 
+/// Injected wall clock in milliseconds (fake clocks in tests)
+pub(crate) struct TimeSource(Box<dyn TimeProvider + Send>);
+
+impl TimeSource {
+    pub(crate) fn new(now_ms: impl TimeProvider + Send + 'static) -> Self {
+        Self(Box::new(now_ms))
+    }
+
+    /// Current time in milliseconds
+    pub(crate) fn now_ms(&self) -> u64 {
+        self.0.now_ms()
+    }
+}
+
+impl std::fmt::Debug for TimeSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TimeSource")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,183 @@
+/// Diff-based awareness exchange
+///
+/// Instead of rebroadcasting every state in a room, a peer sends its
+/// `AwarenessVersion` (the newest clock it has seen per client) and gets
+/// back an `AwarenessDiff` with only the clients that changed or left since.
+/// Payloads scale with churn rather than room size.
+use super::state::AwarenessUpdate;
+use crate::ClientID;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Newest awareness clock seen per client, including leaves
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AwarenessVersion {
+    clocks: HashMap<ClientID, u64>,
+}
+
+impl AwarenessVersion {
+    /// Create an empty version (a diff against it contains everything)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Newest clock seen for a client, 0 if never seen
+    pub fn get(&self, client_id: &str) -> u64 {
+        self.clocks.get(client_id).copied().unwrap_or(0)
+    }
+
+    /// Record a clock for a client, keeping the max
+    pub fn observe(&mut self, client_id: &str, clock: u64) {
+        let entry = self.clocks.entry(client_id.to_string()).or_insert(0);
+        *entry = (*entry).max(clock);
+    }
+
+    /// Number of clients tracked
+    pub fn len(&self) -> usize {
+        self.clocks.len()
+    }
+
+    /// Whether no clients are tracked
+    pub fn is_empty(&self) -> bool {
+        self.clocks.is_empty()
+    }
+}
+
+/// Awareness updates a peer is missing relative to its version
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AwarenessDiff {
+    /// One update per changed client; `state: None` for clients that left
+    pub updates: Vec<AwarenessUpdate>,
+}
+
+impl AwarenessDiff {
+    /// Number of clients in the diff
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    /// Whether the peer was already up to date
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::awareness::{Awareness, AwarenessState};
+    use serde_json::json;
+
+    fn snapshot(awareness: &Awareness) -> HashMap<String, (serde_json::Value, u64)> {
+        awareness
+            .get_states()
+            .iter()
+            .map(|(id, AwarenessState { state, clock, .. })| (id.clone(), (state.clone(), *clock)))
+            .collect()
+    }
+
+    /// Exchange diffs both ways between two relays
+    fn exchange(a: &mut Awareness, b: &mut Awareness) -> usize {
+        let to_b = a.encode_diff(&b.version());
+        let to_a = b.encode_diff(&a.version());
+        let sent = to_b.len() + to_a.len();
+        b.apply_diff(to_b);
+        a.apply_diff(to_a);
+        sent
+    }
+
+    #[test]
+    fn test_diff_size_tracks_churn_not_room_size() {
+        let mut clients: Vec<Awareness> = (0..100)
+            .map(|i| Awareness::new(format!("client-{}", i)))
+            .collect();
+        let mut relay = Awareness::new("relay".to_string());
+        for client in &mut clients {
+            relay.apply_update(client.set_local_state(json!({"cursor": 0})).unwrap());
+        }
+
+        let mut peer = Awareness::new("peer".to_string());
+        assert_eq!(relay.encode_diff(&peer.version()).len(), 100);
+        peer.apply_diff(relay.encode_diff(&peer.version()));
+        assert!(relay.encode_diff(&peer.version()).is_empty());
+
+        // Five cursors move, two clients leave
+        for client in &mut clients[..5] {
+            relay.apply_update(client.set_local_state(json!({"cursor": 1})).unwrap());
+        }
+        for client in &clients[90..92] {
+            relay.apply_update(client.create_leave_update());
+        }
+
+        let diff = relay.encode_diff(&peer.version());
+        assert_eq!(diff.len(), 7);
+        assert_eq!(diff.updates.iter().filter(|u| u.state.is_none()).count(), 2);
+
+        let full = serde_json::to_vec(relay.get_states()).unwrap().len();
+        assert!(serde_json::to_vec(&diff).unwrap().len() * 5 < full);
+
+        peer.apply_diff(diff);
+        assert_eq!(snapshot(&peer), snapshot(&relay));
+    }
+
+    #[test]
+    fn test_three_relays_converge() {
+        let mut relays: Vec<Awareness> = (0..3)
+            .map(|i| Awareness::new(format!("relay-{}", i)))
+            .collect();
+        let mut clients: Vec<Awareness> = (0..100)
+            .map(|i| Awareness::new(format!("client-{}", i)))
+            .collect();
+
+        // Each client is connected to one relay
+        for (i, client) in clients.iter_mut().enumerate() {
+            relays[i % 3].apply_update(client.set_local_state(json!({"n": i})).unwrap());
+        }
+        for round in 0..3 {
+            for (i, client) in clients.iter_mut().enumerate().skip(round * 7).take(10) {
+                let update = if i % 4 == 0 {
+                    client.create_leave_update()
+                } else {
+                    client
+                        .set_local_state(json!({"n": i, "round": round}))
+                        .unwrap()
+                };
+                relays[i % 3].apply_update(update);
+            }
+
+            let (left, right) = relays.split_at_mut(1);
+            exchange(&mut left[0], &mut right[0]);
+            let (left, right) = relays.split_at_mut(2);
+            exchange(&mut left[1], &mut right[0]);
+            exchange(&mut left[0], &mut right[0]);
+        }
+
+        // One more pass propagates anything the last round left behind
+        let (left, right) = relays.split_at_mut(1);
+        exchange(&mut left[0], &mut right[0]);
+        let (left, right) = relays.split_at_mut(2);
+        exchange(&mut left[1], &mut right[0]);
+        assert_eq!(exchange(&mut left[0], &mut right[0]), 0);
+
+        assert_eq!(snapshot(&relays[0]), snapshot(&relays[1]));
+        assert_eq!(snapshot(&relays[1]), snapshot(&relays[2]));
+        assert!(relays[0].client_count() < 100);
+    }
+
+    #[test]
+    fn test_stale_diff_does_not_resurrect() {
+        let mut client = Awareness::new("client-2".to_string());
+        let mut a = Awareness::new("a".to_string());
+        let mut b = Awareness::new("b".to_string());
+
+        a.apply_update(client.set_local_state(json!({})).unwrap());
+        let stale = a.encode_diff(&AwarenessVersion::new());
+        a.apply_update(client.create_leave_update());
+        b.apply_diff(a.encode_diff(&b.version()));
+        b.apply_diff(stale);
+
+        assert!(b.get_state("client-2").is_none());
+        assert_eq!(b.version().get("client-2"), 2);
+    }
+}
@@ -0,0 +1,224 @@
+/// Presence change events
+///
+/// Fired by `Awareness` whenever the set of visible states changes, so
+/// presence UIs don't have to poll `get_states()` and diff the JSON.
+/// Updates that don't change anything (redelivered, stale, or a heartbeat
+/// carrying the same state) fire nothing.
+use crate::ClientID;
+use serde::{Deserialize, Serialize};
+
+/// A change to one client's presence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AwarenessEvent {
+    /// A client appeared
+    Joined {
+        client_id: ClientID,
+        #[serde(with = "crate::codec::json_value")]
+        state: serde_json::Value,
+    },
+
+    /// A client came back within the grace period after timing out
+    /// (`Awareness::set_grace_period`), with its current state
+    Rejoined {
+        client_id: ClientID,
+        #[serde(with = "crate::codec::json_value")]
+        state: serde_json::Value,
+    },
+
+    /// A known client's state changed
+    Updated {
+        client_id: ClientID,
+        #[serde(with = "crate::codec::json_value")]
+        old: serde_json::Value,
+        #[serde(with = "crate::codec::json_value")]
+        new: serde_json::Value,
+    },
+
+    /// A client left or timed out
+    Left { client_id: ClientID },
+
+    /// A remote state failed validation (`Awareness::set_validator`): it
+    /// was dropped, or applied without the `stripped` keys if sanitizing
+    /// fixed it
+    Invalid {
+        client_id: ClientID,
+        reason: String,
+        stripped: Vec<String>,
+    },
+}
+
+// Same handle as `Document::subscribe` and `FugueText::subscribe`
+pub use crate::notify::SubscriptionId;
+
+type Callback = Box<dyn FnMut(&AwarenessEvent) + Send>;
+
+/// Registered observers, called in subscription order
+#[derive(Default)]
+pub(super) struct Subscribers {
+    next_id: u64,
+    callbacks: Vec<(SubscriptionId, Callback)>,
+}
+
+impl Subscribers {
+    pub(super) fn add(&mut self, callback: Callback) -> SubscriptionId {
+        let id = SubscriptionId::new(self.next_id);
+        self.next_id += 1;
+        self.callbacks.push((id, callback));
+        id
+    }
+
+    pub(super) fn remove(&mut self, id: SubscriptionId) -> bool {
+        let before = self.callbacks.len();
+        self.callbacks.retain(|(existing, _)| *existing != id);
+        self.callbacks.len() != before
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+
+    pub(super) fn emit(&mut self, event: &AwarenessEvent) {
+        for (_, callback) in &mut self.callbacks {
+            callback(event);
+        }
+    }
+}
+
+impl std::fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscribers")
+            .field("count", &self.callbacks.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::awareness::{Awareness, AwarenessUpdate};
+    use crate::time::MockTime;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    fn recording(awareness: &mut Awareness) -> Arc<Mutex<Vec<AwarenessEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        awareness.subscribe(move |event| sink.lock().unwrap().push(event.clone()));
+        events
+    }
+
+    fn update(state: Option<serde_json::Value>, clock: u64) -> AwarenessUpdate {
+        AwarenessUpdate {
+            client_id: "client-2".to_string(),
+            state,
+            clock,
+            base_clock: None,
+        }
+    }
+
+    #[test]
+    fn test_join_update_leave_events() {
+        let mut awareness = Awareness::new("client-1".to_string());
+        let events = recording(&mut awareness);
+
+        awareness.set_local_state(json!({"cursor": 0})).unwrap();
+        awareness.apply_update(update(Some(json!({"cursor": 1})), 1));
+        awareness.apply_update(update(Some(json!({"cursor": 2})), 2));
+        awareness.apply_update(update(None, 3));
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                AwarenessEvent::Joined {
+                    client_id: "client-1".to_string(),
+                    state: json!({"cursor": 0}),
+                },
+                AwarenessEvent::Joined {
+                    client_id: "client-2".to_string(),
+                    state: json!({"cursor": 1}),
+                },
+                AwarenessEvent::Updated {
+                    client_id: "client-2".to_string(),
+                    old: json!({"cursor": 1}),
+                    new: json!({"cursor": 2}),
+                },
+                AwarenessEvent::Left {
+                    client_id: "client-2".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redelivery_and_heartbeats_fire_nothing() {
+        let mut awareness = Awareness::new("client-1".to_string());
+        let events = recording(&mut awareness);
+
+        let first = update(Some(json!({"cursor": 1})), 1);
+        awareness.apply_update(first.clone());
+        awareness.apply_update(first);
+        // Newer clock, same state
+        awareness.apply_update(update(Some(json!({"cursor": 1})), 2));
+        awareness.apply_update(update(None, 3));
+        awareness.apply_update(update(None, 3));
+        // Leave for a client that isn't there
+        awareness.apply_update(AwarenessUpdate {
+            client_id: "client-9".to_string(),
+            state: None,
+            clock: 1,
+            base_clock: None,
+        });
+
+        assert_eq!(events.lock().unwrap().len(), 2);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_stale_removal_fires_left() {
+        let now = MockTime::new(0);
+        let mut awareness = Awareness::new("client-1".to_string());
+        awareness.set_time_source(now.clone());
+        awareness.apply_update(update(Some(json!({})), 1));
+        let events = recording(&mut awareness);
+
+        now.advance(2);
+        let removed = awareness.remove_stale_clients(std::time::Duration::from_millis(1));
+
+        assert_eq!(removed, vec!["client-2".to_string()]);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![AwarenessEvent::Left {
+                client_id: "client-2".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unsubscribe_stops_events() {
+        let mut awareness = Awareness::new("client-1".to_string());
+        let events = Arc::new(Mutex::new(0));
+        let sink = events.clone();
+        let id = awareness.subscribe(move |_| *sink.lock().unwrap() += 1);
+
+        awareness.set_local_state(json!({"a": 1})).unwrap();
+        assert!(awareness.unsubscribe(id));
+        assert!(!awareness.unsubscribe(id));
+        awareness.set_local_state(json!({"a": 2})).unwrap();
+
+        assert_eq!(*events.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_event_json_shape() {
+        let event = AwarenessEvent::Updated {
+            client_id: "c".to_string(),
+            old: json!(1),
+            new: json!(2),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"type": "updated", "client_id": "c", "old": 1, "new": 2})
+        );
+    }
+}
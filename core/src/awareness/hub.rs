@@ -0,0 +1,292 @@
+/// Per-document awareness rooms with a shared client identity
+///
+/// A user with several documents open has one presence per document
+/// (a cursor only makes sense inside its own document) but one identity
+/// (name, color). The hub keeps an `Awareness` per document id and merges
+/// the identity fields into the local state of every room. Updates leave
+/// the hub tagged with their document id and are only applied to rooms
+/// the hub has opened.
+use super::state::{Awareness, AwarenessUpdate, UpdateOutcome};
+use crate::DocumentID;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// An awareness update tagged with the document it belongs to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomUpdate {
+    pub document_id: DocumentID,
+    pub update: AwarenessUpdate,
+}
+
+/// Awareness rooms keyed by document id
+#[derive(Debug)]
+pub struct AwarenessHub {
+    client_id: String,
+    identity: Map<String, Value>,
+    rooms: HashMap<DocumentID, Awareness>,
+}
+
+impl AwarenessHub {
+    /// Create a hub with no rooms and an empty identity
+    pub fn new(client_id: String) -> Self {
+        Self {
+            client_id,
+            identity: Map::new(),
+            rooms: HashMap::new(),
+        }
+    }
+
+    /// Get the local client ID
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Shared identity fields
+    pub fn identity(&self) -> &Map<String, Value> {
+        &self.identity
+    }
+
+    /// Awareness for a document, opening the room if needed
+    pub fn room(&mut self, document_id: &str) -> &mut Awareness {
+        self.rooms
+            .entry(document_id.to_string())
+            .or_insert_with(|| Awareness::new(self.client_id.clone()))
+    }
+
+    /// Awareness for a document, if its room is open
+    pub fn get_room(&self, document_id: &str) -> Option<&Awareness> {
+        self.rooms.get(document_id)
+    }
+
+    /// Ids of the open rooms
+    pub fn rooms(&self) -> impl Iterator<Item = &DocumentID> {
+        self.rooms.keys()
+    }
+
+    /// Replace the identity and propagate it to every room we have
+    /// announced ourselves in
+    ///
+    /// Identity keys override room state keys of the same name.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from a room whose validator rejects the new
+    /// identity; rooms before it are already updated
+    pub fn set_identity(&mut self, identity: Map<String, Value>) -> crate::Result<Vec<RoomUpdate>> {
+        // Removed identity keys are deleted from every room
+        let mut patch: Map<String, Value> = self
+            .identity
+            .keys()
+            .filter(|key| !identity.contains_key(*key))
+            .map(|key| (key.clone(), Value::Null))
+            .collect();
+        patch.extend(identity.clone());
+        self.identity = identity;
+
+        let mut updates: Vec<RoomUpdate> = self
+            .rooms
+            .iter_mut()
+            .filter(|(_, room)| room.get_local_state().is_some())
+            .map(|(document_id, room)| {
+                Ok(RoomUpdate {
+                    document_id: document_id.clone(),
+                    update: room.update_local_state(Value::Object(patch.clone()))?,
+                })
+            })
+            .collect::<crate::Result<_>>()?;
+        updates.sort_by(|a, b| a.document_id.cmp(&b.document_id));
+        Ok(updates)
+    }
+
+    /// Replace our ephemeral state in one room (the identity is merged in)
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if the room's validator rejects
+    /// the state
+    pub fn set_room_state(
+        &mut self,
+        document_id: &str,
+        state: Map<String, Value>,
+    ) -> crate::Result<RoomUpdate> {
+        let mut state = state;
+        state.extend(self.identity.clone());
+        Ok(RoomUpdate {
+            document_id: document_id.to_string(),
+            update: self
+                .room(document_id)
+                .set_local_state(Value::Object(state))?,
+        })
+    }
+
+    /// Patch our ephemeral state in one room (identity keys in the patch
+    /// are ignored; use `set_identity`)
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if the room's validator rejects
+    /// the patched state
+    pub fn update_room_state(
+        &mut self,
+        document_id: &str,
+        patch: Map<String, Value>,
+    ) -> crate::Result<RoomUpdate> {
+        let patch: Map<String, Value> = patch
+            .into_iter()
+            .filter(|(key, _)| !self.identity.contains_key(key))
+            .collect();
+        if self.room(document_id).get_local_state().is_none() {
+            return self.set_room_state(document_id, patch);
+        }
+
+        Ok(RoomUpdate {
+            document_id: document_id.to_string(),
+            update: self
+                .room(document_id)
+                .update_local_state(Value::Object(patch))?,
+        })
+    }
+
+    /// Apply a remote update to its room
+    ///
+    /// Updates for rooms the hub hasn't opened are ignored, so presence
+    /// from documents we aren't subscribed to never shows up.
+    pub fn apply_update(&mut self, update: RoomUpdate) -> UpdateOutcome {
+        match self.rooms.get_mut(&update.document_id) {
+            Some(room) => room.apply_update(update.update),
+            None => UpdateOutcome::Ignored,
+        }
+    }
+
+    /// Leave a room; returns the leave update to broadcast if it was open
+    pub fn close_room(&mut self, document_id: &str) -> Option<RoomUpdate> {
+        let room = self.rooms.remove(document_id)?;
+        Some(RoomUpdate {
+            document_id: document_id.to_string(),
+            update: room.create_leave_update(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("not an object"),
+        }
+    }
+
+    /// Deliver every update from one hub to another
+    fn deliver(to: &mut AwarenessHub, updates: impl IntoIterator<Item = RoomUpdate>) {
+        for update in updates {
+            to.apply_update(update);
+        }
+    }
+
+    #[test]
+    fn test_updates_stay_in_their_room() {
+        let mut alice = AwarenessHub::new("alice".to_string());
+        let mut bob = AwarenessHub::new("bob".to_string());
+        bob.room("doc-a");
+        bob.room("doc-b");
+
+        let update = alice
+            .set_room_state("doc-a", object(json!({"cursor": 4})))
+            .unwrap();
+        deliver(&mut bob, [update]);
+
+        assert!(bob.get_room("doc-a").unwrap().get_state("alice").is_some());
+        assert!(bob.get_room("doc-b").unwrap().get_state("alice").is_none());
+
+        // A room bob never opened is not created by incoming updates
+        let update = alice
+            .set_room_state("doc-c", object(json!({"cursor": 1})))
+            .unwrap();
+        assert_eq!(bob.apply_update(update), UpdateOutcome::Ignored);
+        assert!(bob.get_room("doc-c").is_none());
+    }
+
+    #[test]
+    fn test_identity_propagates_to_all_rooms() {
+        let mut alice = AwarenessHub::new("alice".to_string());
+        let mut bob = AwarenessHub::new("bob".to_string());
+        for document_id in ["doc-a", "doc-b"] {
+            bob.room(document_id);
+        }
+
+        alice
+            .set_identity(object(json!({"name": "Alice", "color": "red"})))
+            .unwrap();
+        let opened = [
+            alice
+                .set_room_state("doc-a", object(json!({"cursor": 1})))
+                .unwrap(),
+            alice
+                .set_room_state("doc-b", object(json!({"cursor": 9})))
+                .unwrap(),
+        ];
+        deliver(&mut bob, opened);
+
+        let updates = alice
+            .set_identity(object(json!({"name": "Alice B."})))
+            .unwrap();
+        assert_eq!(updates.len(), 2);
+        deliver(&mut bob, updates);
+
+        let a = &bob
+            .get_room("doc-a")
+            .unwrap()
+            .get_state("alice")
+            .unwrap()
+            .state;
+        let b = &bob
+            .get_room("doc-b")
+            .unwrap()
+            .get_state("alice")
+            .unwrap()
+            .state;
+        assert_eq!(*a, json!({"name": "Alice B.", "cursor": 1}));
+        assert_eq!(*b, json!({"name": "Alice B.", "cursor": 9}));
+    }
+
+    #[test]
+    fn test_room_state_cannot_override_identity() {
+        let mut hub = AwarenessHub::new("alice".to_string());
+        hub.set_identity(object(json!({"name": "Alice"}))).unwrap();
+        hub.set_room_state("doc", object(json!({"name": "Mallory", "cursor": 1})))
+            .unwrap();
+        let update = hub
+            .update_room_state("doc", object(json!({"name": "Eve", "cursor": 2})))
+            .unwrap();
+
+        assert_eq!(update.update.state, Some(json!({"cursor": 2})));
+        assert_eq!(
+            hub.get_room("doc")
+                .unwrap()
+                .get_local_state()
+                .unwrap()
+                .state,
+            json!({"name": "Alice", "cursor": 2})
+        );
+    }
+
+    #[test]
+    fn test_close_room_sends_leave() {
+        let mut alice = AwarenessHub::new("alice".to_string());
+        let mut bob = AwarenessHub::new("bob".to_string());
+        bob.room("doc");
+        deliver(&mut bob, [alice.set_room_state("doc", Map::new()).unwrap()]);
+
+        let leave = alice.close_room("doc").unwrap();
+        assert!(leave.update.state.is_none());
+        deliver(&mut bob, [leave]);
+
+        assert_eq!(bob.get_room("doc").unwrap().client_count(), 0);
+        assert!(alice.close_room("doc").is_none());
+    }
+}
@@ -0,0 +1,362 @@
+/// Limits on what remote clients may put into awareness
+///
+/// One misbehaving client could otherwise set a multi-megabyte state or
+/// flood presence updates that every peer then rebroadcasts. Offending
+/// updates are rejected whole (never truncated) and reported as a
+/// `LimitViolation` naming the client and the limit, so the server can
+/// tell the offender.
+use super::clock::TimeSource;
+use crate::ClientID;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Token bucket: up to `burst` updates at once, refilled at `per_second`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+/// Limits applied to remote updates in `Awareness::apply_update`
+///
+/// Every limit is off by default. Leave updates are never rejected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AwarenessLimits {
+    /// Max size of one client's state, serialized as JSON
+    pub max_state_bytes: Option<usize>,
+    /// Max number of remote clients tracked at once
+    pub max_clients: Option<usize>,
+    /// Max update rate per client
+    pub rate: Option<RateLimit>,
+}
+
+/// An update rejected by a limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitViolation {
+    /// The client's state would serialize to `bytes` > `limit`
+    StateTooLarge {
+        client_id: ClientID,
+        bytes: usize,
+        limit: usize,
+    },
+    /// A new client arrived while `limit` clients were already tracked
+    TooManyClients { client_id: ClientID, limit: usize },
+    /// The client is sending updates faster than its rate limit
+    RateLimited { client_id: ClientID },
+    /// The client's state failed the validator
+    /// (`Awareness::set_validator`)
+    InvalidState { client_id: ClientID, reason: String },
+}
+
+impl LimitViolation {
+    /// The offending client
+    pub fn client_id(&self) -> &str {
+        match self {
+            LimitViolation::StateTooLarge { client_id, .. }
+            | LimitViolation::TooManyClients { client_id, .. }
+            | LimitViolation::RateLimited { client_id }
+            | LimitViolation::InvalidState { client_id, .. } => client_id,
+        }
+    }
+
+    /// Protocol error to send back to the offending client
+    #[cfg(feature = "protocol-binary")]
+    pub fn to_error_message(&self) -> crate::protocol::ErrorMessage {
+        use crate::protocol::{ErrorMessage, Status};
+
+        let mut details = HashMap::new();
+        details.insert("client_id".to_string(), self.client_id().to_string());
+        let status = match self {
+            LimitViolation::StateTooLarge { bytes, limit, .. } => {
+                details.insert("limit".to_string(), "max_state_bytes".to_string());
+                details.insert("max".to_string(), limit.to_string());
+                details.insert("actual".to_string(), bytes.to_string());
+                Status::InvalidRequest
+            }
+            LimitViolation::TooManyClients { limit, .. } => {
+                details.insert("limit".to_string(), "max_clients".to_string());
+                details.insert("max".to_string(), limit.to_string());
+                Status::InvalidRequest
+            }
+            LimitViolation::RateLimited { .. } => {
+                details.insert("limit".to_string(), "rate".to_string());
+                Status::RateLimited
+            }
+            LimitViolation::InvalidState { .. } => {
+                details.insert("limit".to_string(), "validator".to_string());
+                Status::InvalidRequest
+            }
+        };
+
+        ErrorMessage {
+            status: status as i32,
+            message: self.to_string(),
+            details,
+        }
+    }
+}
+
+impl fmt::Display for LimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitViolation::StateTooLarge {
+                client_id,
+                bytes,
+                limit,
+            } => write!(
+                f,
+                "awareness state of {} is {} bytes (limit {})",
+                client_id, bytes, limit
+            ),
+            LimitViolation::TooManyClients { client_id, limit } => write!(
+                f,
+                "cannot track {}: already tracking {} clients",
+                client_id, limit
+            ),
+            LimitViolation::RateLimited { client_id } => {
+                write!(f, "{} is sending awareness updates too fast", client_id)
+            }
+            LimitViolation::InvalidState { client_id, reason } => {
+                write!(f, "invalid awareness state from {}: {}", client_id, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitViolation {}
+
+struct TokenBucket {
+    tokens: f64,
+    updated_ms: u64,
+}
+
+/// Limits plus the per-client state needed to enforce them
+pub(super) struct Limiter {
+    pub(super) limits: AwarenessLimits,
+    buckets: HashMap<ClientID, TokenBucket>,
+    clock: TimeSource,
+}
+
+impl Limiter {
+    pub(super) fn new(limits: AwarenessLimits, clock: TimeSource) -> Self {
+        Self {
+            limits,
+            buckets: HashMap::new(),
+            clock,
+        }
+    }
+
+    /// Take one token from the client's bucket, if rate limited
+    pub(super) fn check_rate(&mut self, client_id: &str) -> Result<(), LimitViolation> {
+        let Some(rate) = self.limits.rate else {
+            return Ok(());
+        };
+        let now = self.clock.now_ms();
+        let bucket = self
+            .buckets
+            .entry(client_id.to_string())
+            .or_insert(TokenBucket {
+                tokens: f64::from(rate.burst),
+                updated_ms: now,
+            });
+
+        let elapsed = now.saturating_sub(bucket.updated_ms) as f64 / 1000.0;
+        bucket.tokens = (bucket.tokens + elapsed * rate.per_second).min(f64::from(rate.burst));
+        bucket.updated_ms = now;
+        if bucket.tokens < 1.0 {
+            return Err(LimitViolation::RateLimited {
+                client_id: client_id.to_string(),
+            });
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Forget a client's bucket (it left)
+    pub(super) fn forget(&mut self, client_id: &str) {
+        self.buckets.remove(client_id);
+    }
+}
+
+impl fmt::Debug for Limiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Limiter")
+            .field("limits", &self.limits)
+            .field("buckets", &self.buckets.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::awareness::{Awareness, UpdateOutcome};
+    use crate::time::MockTime;
+    use serde_json::json;
+
+    /// Receiver with `limits` and a clock the test advances by hand
+    fn limited(limits: AwarenessLimits) -> (Awareness, MockTime) {
+        let now = MockTime::new(0);
+        let mut awareness = Awareness::new("server".to_string());
+        awareness.set_limits(limits, now.clone());
+        (awareness, now)
+    }
+
+    #[test]
+    fn test_oversized_state_is_rejected_whole() {
+        let (mut receiver, _) = limited(AwarenessLimits {
+            max_state_bytes: Some(64),
+            ..Default::default()
+        });
+        let mut sender = Awareness::new("alice".to_string());
+
+        let update = sender.set_local_state(json!({"name": "Alice"})).unwrap();
+        assert_eq!(receiver.apply_update(update), UpdateOutcome::Applied);
+
+        // A small patch that grows the merged state past the limit
+        let update = sender
+            .update_local_state(json!({"bio": "x".repeat(100)}))
+            .unwrap();
+        let UpdateOutcome::Rejected(violation) = receiver.apply_update(update) else {
+            panic!("oversized update accepted");
+        };
+        assert!(matches!(
+            violation,
+            LimitViolation::StateTooLarge { ref client_id, limit: 64, bytes } if client_id == "alice" && bytes > 64
+        ));
+        assert_eq!(
+            receiver.get_state("alice").unwrap().state,
+            json!({"name": "Alice"})
+        );
+    }
+
+    #[test]
+    fn test_client_cap_rejects_newcomers_only() {
+        let (mut receiver, _) = limited(AwarenessLimits {
+            max_clients: Some(2),
+            ..Default::default()
+        });
+        // Our own state doesn't count towards the cap
+        receiver.set_local_state(json!({})).unwrap();
+
+        let mut clients: Vec<Awareness> = ["a", "b", "c"]
+            .iter()
+            .map(|id| Awareness::new(id.to_string()))
+            .collect();
+        for client in &mut clients[..2] {
+            let update = client.set_local_state(json!({"n": 1})).unwrap();
+            assert_eq!(receiver.apply_update(update), UpdateOutcome::Applied);
+        }
+
+        let update = clients[2].set_local_state(json!({"n": 1})).unwrap();
+        assert_eq!(
+            receiver.apply_update(update),
+            UpdateOutcome::Rejected(LimitViolation::TooManyClients {
+                client_id: "c".to_string(),
+                limit: 2,
+            })
+        );
+
+        // Tracked clients keep updating; a leave frees a slot
+        let update = clients[0].set_local_state(json!({"n": 2})).unwrap();
+        assert_eq!(receiver.apply_update(update), UpdateOutcome::Applied);
+        receiver.apply_update(clients[1].create_leave_update());
+        let update = clients[2].set_local_state(json!({"n": 2})).unwrap();
+        assert_eq!(receiver.apply_update(update), UpdateOutcome::Applied);
+    }
+
+    #[test]
+    fn test_throttled_client_does_not_affect_others() {
+        let (mut receiver, now) = limited(AwarenessLimits {
+            rate: Some(RateLimit {
+                burst: 3,
+                per_second: 2.0,
+            }),
+            ..Default::default()
+        });
+        let mut spammer = Awareness::new("spammer".to_string());
+        let mut alice = Awareness::new("alice".to_string());
+
+        let mut rejected = Vec::new();
+        for i in 0..10 {
+            let update = spammer.set_local_state(json!({"cursor": i})).unwrap();
+            if let UpdateOutcome::Rejected(violation) = receiver.apply_update(update) {
+                rejected.push(violation);
+            }
+            if i % 5 == 0 {
+                let update = alice.set_local_state(json!({"cursor": i})).unwrap();
+                assert_eq!(receiver.apply_update(update), UpdateOutcome::Applied);
+            }
+        }
+        // Only the burst got through
+        assert_eq!(rejected.len(), 7);
+        assert!(rejected.iter().all(|v| v.client_id() == "spammer"));
+        assert_eq!(
+            receiver.get_state("spammer").unwrap().state,
+            json!({"cursor": 2})
+        );
+
+        // Half a second refills one token
+        now.set(500);
+        let update = spammer.set_local_state(json!({"cursor": 10})).unwrap();
+        assert_eq!(receiver.apply_update(update), UpdateOutcome::Applied);
+        let update = spammer.set_local_state(json!({"cursor": 11})).unwrap();
+        assert!(matches!(
+            receiver.apply_update(update),
+            UpdateOutcome::Rejected(LimitViolation::RateLimited { .. })
+        ));
+
+        // Leaving is never throttled
+        assert_eq!(
+            receiver.apply_update(spammer.create_leave_update()),
+            UpdateOutcome::Applied
+        );
+        assert!(receiver.get_state("spammer").is_none());
+    }
+
+    #[test]
+    fn test_apply_diff_reports_violations() {
+        let (mut receiver, _) = limited(AwarenessLimits {
+            max_state_bytes: Some(32),
+            ..Default::default()
+        });
+        let mut peer = Awareness::new("peer".to_string());
+        let mut small = Awareness::new("small".to_string());
+        let mut large = Awareness::new("large".to_string());
+        peer.apply_update(small.set_local_state(json!({"n": 1})).unwrap());
+        peer.apply_update(
+            large
+                .set_local_state(json!({"bio": "x".repeat(64)}))
+                .unwrap(),
+        );
+
+        let violations = receiver.apply_diff(peer.encode_diff(&receiver.version()));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].client_id(), "large");
+        assert!(receiver.get_state("small").is_some());
+        assert!(receiver.get_state("large").is_none());
+    }
+
+    #[cfg(feature = "protocol-binary")]
+    #[test]
+    fn test_violation_converts_to_protocol_error() {
+        use crate::protocol::Status;
+
+        let error = LimitViolation::RateLimited {
+            client_id: "spammer".to_string(),
+        }
+        .to_error_message();
+        assert_eq!(error.status, Status::RateLimited as i32);
+        assert_eq!(error.details["client_id"], "spammer");
+
+        let error = LimitViolation::StateTooLarge {
+            client_id: "alice".to_string(),
+            bytes: 100,
+            limit: 64,
+        }
+        .to_error_message();
+        assert_eq!(error.status, Status::InvalidRequest as i32);
+        assert_eq!(error.details["limit"], "max_state_bytes");
+        assert_eq!(error.details["actual"], "100");
+    }
+}
@@ -1,4 +1,10 @@
 mod clock;
+mod diff;
+mod events;
+mod hub;
+mod limits;
+#[cfg(feature = "text-crdt")]
+mod presence;
 /// Awareness Protocol - Ephemeral user presence and state
 ///
 /// Unlike CRDTs which persist data, Awareness tracks ephemeral state like:
@@ -13,9 +19,18 @@ mod clock;
 /// - Simpler conflict resolution (increasing clock, not vector clocks)
 /// - Separate broadcast channel (doesn't mix with CRDT operations)
 mod state;
+mod validation;
 
 pub use clock::IncreasingClock;
-pub use state::{Awareness, AwarenessState, AwarenessUpdate};
+pub(crate) use clock::TimeSource;
+pub use diff::{AwarenessDiff, AwarenessVersion};
+pub use events::{AwarenessEvent, SubscriptionId};
+pub use hub::{AwarenessHub, RoomUpdate};
+pub use limits::{AwarenessLimits, LimitViolation, RateLimit};
+#[cfg(feature = "text-crdt")]
+pub use presence::{CursorState, ResolvedCursor};
+pub use state::{Awareness, AwarenessState, AwarenessUpdate, UpdateOutcome};
+pub use validation::{StateSchema, ValidationPolicy};
 
 use std::time::Duration;
 
@@ -0,0 +1,210 @@
+/// Cursor presence bound to text anchors
+///
+/// Cursors are stored in the local awareness state under
+/// `cursors.<document_id>` as anchors rather than offsets, so each replica
+/// resolves them against its own copy of the text and they stay on the
+/// same characters as remote edits arrive.
+use super::state::{Awareness, AwarenessUpdate};
+use crate::crdt::text_fugue::{Anchor, AnchorBias, FugueText};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Key of the per-document cursor map in awareness state
+const CURSORS_KEY: &str = "cursors";
+
+/// A cursor (and optional selection) as shipped in awareness state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CursorState {
+    pub anchor: Anchor,
+    /// Other end of the selection, if any
+    #[serde(default)]
+    pub selection_end: Option<Anchor>,
+    /// Free-form data rendered with the cursor (label, color override, ...)
+    #[serde(default, with = "crate::codec::json_value")]
+    pub meta: Value,
+}
+
+/// A remote cursor resolved against the local text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedCursor {
+    pub position: usize,
+    /// Other end of the selection, if it still resolves
+    pub selection_end: Option<usize>,
+    #[serde(with = "crate::codec::json_value")]
+    pub meta: Value,
+}
+
+impl Awareness {
+    /// Put our cursor at `position` in a document's text, optionally
+    /// selecting up to `selection` (returns update to broadcast)
+    ///
+    /// The cursor sticks to the character after it; the selection end to
+    /// the character before it, so typing at either edge stays outside the
+    /// selection.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::PositionOutOfBounds` if either position is past
+    /// the end of the text, or `ErrorKind::InvalidInput` if the validator
+    /// rejects the new state
+    pub fn set_cursor(
+        &mut self,
+        document_id: &str,
+        text: &mut FugueText,
+        position: usize,
+        selection: Option<usize>,
+    ) -> crate::Result<AwarenessUpdate> {
+        let cursor = CursorState {
+            anchor: text.create_anchor(position, AnchorBias::Right)?,
+            selection_end: selection
+                .map(|end| text.create_anchor(end, AnchorBias::Left))
+                .transpose()?,
+            meta: Value::Null,
+        };
+        self.set_cursor_state(document_id, cursor)
+    }
+
+    /// Replace our cursor in a document (returns update to broadcast)
+    ///
+    /// Sent as a patch: a serialized cursor always carries every field
+    /// (`null` for absent ones), so it overwrites the previous cursor,
+    /// except that object-valued `meta` is merged key by key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if the validator rejects the new
+    /// state
+    pub fn set_cursor_state(
+        &mut self,
+        document_id: &str,
+        cursor: CursorState,
+    ) -> crate::Result<AwarenessUpdate> {
+        let mut cursors = serde_json::Map::new();
+        cursors.insert(
+            document_id.to_string(),
+            serde_json::to_value(cursor).expect("cursor serializes"),
+        );
+        self.update_local_state(json!({ CURSORS_KEY: cursors }))
+    }
+
+    /// Other clients' cursors in a document, resolved to current positions
+    /// in `text`
+    ///
+    /// Cursors whose anchor this replica can't resolve (text it hasn't
+    /// received yet) are left out. Sorted by client id.
+    pub fn remote_cursors(
+        &self,
+        document_id: &str,
+        text: &mut FugueText,
+    ) -> Vec<(String, ResolvedCursor)> {
+        let mut cursors: Vec<(String, ResolvedCursor)> = self
+            .get_states()
+            .iter()
+            .filter(|(client_id, _)| *client_id != self.client_id())
+            .filter_map(|(client_id, state)| {
+                let cursor: CursorState =
+                    serde_json::from_value(state.state.get(CURSORS_KEY)?.get(document_id)?.clone())
+                        .ok()?;
+                let resolved = ResolvedCursor {
+                    position: text.resolve_anchor(&cursor.anchor)?,
+                    selection_end: cursor
+                        .selection_end
+                        .and_then(|end| text.resolve_anchor(&end)),
+                    meta: cursor.meta,
+                };
+                Some((client_id.clone(), resolved))
+            })
+            .collect();
+        cursors.sort_by(|a, b| a.0.cmp(&b.0));
+        cursors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alice and Bob share "Hello World"; Bob's cursor is at `position`
+    fn setup(position: usize, selection: Option<usize>) -> (Awareness, FugueText, FugueText) {
+        let mut alice_text = FugueText::new("alice".to_string());
+        alice_text.insert(0, "Hello World").unwrap();
+        let mut bob_text = FugueText::new("bob".to_string());
+        bob_text.merge(&alice_text).unwrap();
+
+        let mut bob = Awareness::new("bob".to_string());
+        bob.set_local_state(json!({"name": "Bob"})).unwrap();
+        let mut alice = Awareness::new("alice".to_string());
+        alice.apply_update(bob.local_state_update().unwrap());
+        alice.apply_update(
+            bob.set_cursor("doc", &mut bob_text, position, selection)
+                .unwrap(),
+        );
+        (alice, alice_text, bob_text)
+    }
+
+    #[test]
+    fn test_remote_insert_before_cursor_shifts_it() {
+        let (alice, mut alice_text, mut bob_text) = setup(6, Some(11));
+        assert_eq!(
+            alice.remote_cursors("doc", &mut alice_text),
+            vec![(
+                "bob".to_string(),
+                ResolvedCursor {
+                    position: 6,
+                    selection_end: Some(11),
+                    meta: Value::Null,
+                }
+            )]
+        );
+
+        // Someone else prepends text; Bob's cursor still sits before "World"
+        let mut carol_text = FugueText::new("carol".to_string());
+        carol_text.merge(&bob_text).unwrap();
+        carol_text.insert(0, ">> ").unwrap();
+        alice_text.merge(&carol_text).unwrap();
+        bob_text.merge(&carol_text).unwrap();
+
+        let (_, cursor) = &alice.remote_cursors("doc", &mut alice_text)[0];
+        assert_eq!(cursor.position, 9);
+        assert_eq!(cursor.selection_end, Some(14));
+        assert_eq!(&alice_text.to_string()[9..14], "World");
+    }
+
+    #[test]
+    fn test_deletion_covering_cursor_snaps_it() {
+        let (alice, mut alice_text, _) = setup(8, None);
+
+        // "o Wor" removed: the cursor's character (the second 'r') is gone
+        alice_text.delete(4, 5).unwrap();
+        let (_, cursor) = &alice.remote_cursors("doc", &mut alice_text)[0];
+        assert_eq!(cursor.position, 4);
+    }
+
+    #[test]
+    fn test_cursor_update_is_a_small_patch_and_replaces_selection() {
+        let mut text = FugueText::new("bob".to_string());
+        text.insert(0, "Hello").unwrap();
+        let mut bob = Awareness::new("bob".to_string());
+        let mut alice = Awareness::new("alice".to_string());
+
+        alice.apply_update(bob.set_cursor("doc", &mut text, 1, Some(3)).unwrap());
+        let update = bob.set_cursor("doc", &mut text, 2, None).unwrap();
+        assert!(update.base_clock.is_some());
+        alice.apply_update(update);
+
+        let cursors = alice.remote_cursors("doc", &mut text);
+        assert_eq!(cursors[0].1.position, 2);
+        assert_eq!(cursors[0].1.selection_end, None);
+        // Our own cursor and other documents are not reported
+        assert!(bob.remote_cursors("doc", &mut text).is_empty());
+        assert!(alice.remote_cursors("other", &mut text).is_empty());
+    }
+
+    #[test]
+    fn test_unresolvable_cursor_is_dropped() {
+        let (alice, _, _) = setup(3, None);
+        let mut unrelated = FugueText::new("zed".to_string());
+        unrelated.insert(0, "abcdef").unwrap();
+        assert!(alice.remote_cursors("doc", &mut unrelated).is_empty());
+    }
+}
@@ -2,13 +2,20 @@
 ///
 /// Tracks ephemeral state for all connected clients.
 /// State is stored as arbitrary JSON and merged at the field level.
-use super::clock::IncreasingClock;
+use super::clock::{IncreasingClock, TimeSource};
+use super::diff::{AwarenessDiff, AwarenessVersion};
+use super::events::{AwarenessEvent, Subscribers, SubscriptionId};
+use super::limits::{AwarenessLimits, LimitViolation, Limiter};
+use super::validation::{ValidationPolicy, Validator};
+use crate::time::{self, TimeProvider};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use std::time::Duration;
+
 // Time tracking only available on non-WASM targets
 #[cfg(not(target_arch = "wasm32"))]
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
 /// Awareness state for a single client
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +24,7 @@ pub struct AwarenessState {
     pub client_id: String,
 
     /// Arbitrary JSON state (user info, cursor, selection, etc.)
+    #[serde(with = "crate::codec::json_value")]
     pub state: serde_json::Value,
 
     /// Logical clock for conflict resolution
@@ -33,8 +41,38 @@ pub struct AwarenessState {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AwarenessUpdate {
     pub client_id: String,
+    #[serde(default, with = "crate::codec::json_value::option")]
     pub state: Option<serde_json::Value>, // None = client left
+    /// Per-client sequence: strictly increasing across the updates one
+    /// client sends, so receivers can drop anything older than what they
+    /// already have
     pub clock: u64,
+    /// Set for partial updates: `state` is then a JSON merge patch
+    /// (RFC 7386) onto the state the sender had at this clock
+    #[serde(default)]
+    pub base_clock: Option<u64>,
+}
+
+/// What `Awareness::apply_update` did with an update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    /// The update was newer and is now reflected in the states
+    Applied,
+    /// The update was stale or a duplicate
+    Ignored,
+    /// A patch whose base state we don't have (an earlier patch was
+    /// dropped); ask the sender for its full state
+    /// (`Awareness::local_state_update`)
+    NeedsFullState,
+    /// The update broke one of the `AwarenessLimits` and was dropped
+    Rejected(LimitViolation),
+}
+
+/// A timed-out client kept through the grace period
+#[derive(Debug)]
+struct HeldState {
+    state: AwarenessState,
+    left_at_ms: u64,
 }
 
 /// Awareness manager tracking all client states
@@ -42,7 +80,23 @@ pub struct AwarenessUpdate {
 pub struct Awareness {
     client_id: String,
     states: HashMap<String, AwarenessState>,
+    /// Clock of the leave update for each departed client, so a stale
+    /// update arriving after the leave can't bring the client back
+    departed: HashMap<String, u64>,
     clock: IncreasingClock,
+    subscribers: Subscribers,
+    limiter: Option<Limiter>,
+    validator: Option<Validator>,
+    time_source: Option<TimeSource>,
+    /// When each client (including us) was last heard from, in ms; only
+    /// kept once a time source is set or a heartbeat was created
+    last_seen_ms: HashMap<String, u64>,
+    /// Timed-out clients that may still reconnect (`set_grace_period`)
+    recently_left: HashMap<String, HeldState>,
+    grace_period_ms: Option<u64>,
+    /// Fallback time base for the grace period without a time source
+    #[cfg(not(target_arch = "wasm32"))]
+    started: Instant,
 }
 
 impl Awareness {
@@ -51,7 +105,17 @@ impl Awareness {
         Self {
             client_id,
             states: HashMap::new(),
+            departed: HashMap::new(),
             clock: IncreasingClock::new(),
+            subscribers: Subscribers::default(),
+            limiter: None,
+            validator: None,
+            time_source: None,
+            last_seen_ms: HashMap::new(),
+            recently_left: HashMap::new(),
+            grace_period_ms: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            started: Instant::now(),
         }
     }
 
@@ -60,6 +124,88 @@ impl Awareness {
         &self.client_id
     }
 
+    /// Enforce `limits` on remote updates from now on
+    ///
+    /// `now_ms` is the current time in milliseconds, used to refill the
+    /// rate limit buckets: any [`TimeProvider`], such as a
+    /// [`crate::time::MockTime`] in tests.
+    pub fn set_limits(
+        &mut self,
+        limits: AwarenessLimits,
+        now_ms: impl TimeProvider + Send + 'static,
+    ) {
+        self.limiter = Some(Limiter::new(limits, TimeSource::new(now_ms)));
+    }
+
+    /// Check every state change with `validate` (client id, state) from
+    /// now on
+    ///
+    /// Invalid local states are refused by `set_local_state` and
+    /// `update_local_state`. Invalid remote states are handled by `policy`
+    /// and reported both as an `AwarenessEvent::Invalid` and, when
+    /// dropped, as `LimitViolation::InvalidState`. Updates that leave a
+    /// state unchanged (heartbeats) are not validated.
+    pub fn set_validator(
+        &mut self,
+        validate: impl Fn(&str, &serde_json::Value) -> Result<(), String> + Send + 'static,
+        policy: ValidationPolicy,
+    ) {
+        self.validator = Some(Validator::new(Box::new(validate), policy));
+    }
+
+    /// Measure staleness with `now_ms` (current time in milliseconds)
+    /// instead of `Instant`
+    ///
+    /// Every update, heartbeat included, then records when its client was
+    /// last seen, and `remove_stale_clients` compares against that. This
+    /// also makes stale client removal work on WASM.
+    pub fn set_time_source(&mut self, now_ms: impl TimeProvider + Send + 'static) {
+        self.time_source = Some(TimeSource::new(now_ms));
+    }
+
+    /// Keep timed-out clients for `grace` before they leave for good
+    ///
+    /// `remove_stale_clients` then moves stale clients to
+    /// `recently_left` without an event. An update from one of them
+    /// within the grace period restores it with `Rejoined` instead of
+    /// `Joined`; after it, a later `remove_stale_clients` fires `Left`.
+    /// Explicit leave updates still take effect immediately.
+    pub fn set_grace_period(&mut self, grace: Duration) {
+        self.grace_period_ms = Some(grace.as_millis() as u64);
+    }
+
+    /// Timed-out clients still within the grace period (for a
+    /// "reconnecting…" UI), with the state they had
+    pub fn recently_left(&self) -> impl Iterator<Item = &AwarenessState> {
+        self.recently_left.values().map(|held| &held.state)
+    }
+
+    /// Current time in ms from the time source, else since creation
+    /// (None on WASM without a time source)
+    fn now_ms(&self) -> Option<u64> {
+        if let Some(time_source) = &self.time_source {
+            return Some(time_source.now_ms());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        return Some(self.started.elapsed().as_millis() as u64);
+        #[cfg(target_arch = "wasm32")]
+        None
+    }
+
+    /// Record that `client_id` was just heard from (no-op without a time
+    /// source)
+    fn touch(&mut self, client_id: &str) {
+        if let Some(time_source) = &self.time_source {
+            self.last_seen_ms
+                .insert(client_id.to_string(), time_source.now_ms());
+        }
+    }
+
+    /// Limits currently enforced, if any
+    pub fn limits(&self) -> Option<&AwarenessLimits> {
+        self.limiter.as_ref().map(|limiter| &limiter.limits)
+    }
+
     /// Get all current client states
     pub fn get_states(&self) -> &HashMap<String, AwarenessState> {
         &self.states
@@ -75,8 +221,106 @@ impl Awareness {
         self.get_state(&self.client_id)
     }
 
+    /// Call `callback` for every join, state change and leave
+    ///
+    /// Fired by `set_local_state`, `apply_update` (and so `apply_diff`)
+    /// and `remove_stale_clients`.
+    pub fn subscribe(
+        &mut self,
+        callback: impl FnMut(&AwarenessEvent) + Send + 'static,
+    ) -> SubscriptionId {
+        self.subscribers.add(Box::new(callback))
+    }
+
+    /// Remove a callback; returns false if it was already removed
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.subscribers.remove(id)
+    }
+
+    /// Notify subscribers that `client_id` went from `old` to `new`
+    /// (`None` meaning absent); no-op if nothing visible changed
+    fn notify(
+        &mut self,
+        client_id: &str,
+        old: Option<serde_json::Value>,
+        new: Option<&serde_json::Value>,
+    ) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let client_id = client_id.to_string();
+        let event = match (old, new) {
+            (None, Some(state)) => AwarenessEvent::Joined {
+                client_id,
+                state: state.clone(),
+            },
+            (Some(old), Some(new)) if old != *new => AwarenessEvent::Updated {
+                client_id,
+                old,
+                new: new.clone(),
+            },
+            (Some(_), None) => AwarenessEvent::Left { client_id },
+            _ => return,
+        };
+        self.subscribers.emit(&event);
+    }
+
     /// Set local client's state (returns update to broadcast)
-    pub fn set_local_state(&mut self, state: serde_json::Value) -> AwarenessUpdate {
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if the validator rejects the state
+    pub fn set_local_state(&mut self, state: serde_json::Value) -> crate::Result<AwarenessUpdate> {
+        self.validate_local(&state)?;
+        Ok(self.publish_state(state))
+    }
+
+    /// Merge `patch` into the local state (JSON merge patch, RFC 7386:
+    /// nested objects merge, `null` removes a key)
+    ///
+    /// Returns an update carrying only the keys that changed. Receivers
+    /// that missed an earlier update get `UpdateOutcome::NeedsFullState`
+    /// instead of applying the patch onto the wrong base.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if the validator rejects the
+    /// patched state
+    pub fn update_local_state(
+        &mut self,
+        patch: serde_json::Value,
+    ) -> crate::Result<AwarenessUpdate> {
+        if self.validator.is_some() {
+            let mut state = self
+                .get_local_state()
+                .map(|local| local.state.clone())
+                .unwrap_or_default();
+            merge_patch(&mut state, &patch);
+            self.validate_local(&state)?;
+        }
+        Ok(self.publish_patch(patch))
+    }
+
+    /// Run the validator on a new local state
+    fn validate_local(&self, state: &serde_json::Value) -> crate::Result<()> {
+        let Some(validator) = &self.validator else {
+            return Ok(());
+        };
+        if self
+            .get_local_state()
+            .is_some_and(|local| local.state == *state)
+        {
+            return Ok(());
+        }
+        validator
+            .check_local(&self.client_id, state)
+            .map_err(|reason| {
+                crate::SyncKitError::invalid_input(format!("Invalid awareness state: {}", reason))
+            })
+    }
+
+    /// Replace the local state, unvalidated
+    fn publish_state(&mut self, state: serde_json::Value) -> AwarenessUpdate {
         let clock = self.clock.increment();
 
         let awareness_state = AwarenessState {
@@ -87,85 +331,462 @@ impl Awareness {
             last_updated: Some(Instant::now()),
         };
 
-        self.states.insert(self.client_id.clone(), awareness_state);
+        let old = self.states.insert(self.client_id.clone(), awareness_state);
+        let client_id = self.client_id.clone();
+        self.touch(&client_id);
+        self.notify(&client_id, old.map(|old| old.state), Some(&state));
 
         AwarenessUpdate {
             client_id: self.client_id.clone(),
             state: Some(state),
             clock,
+            base_clock: None,
+        }
+    }
+
+    /// Patch the local state, unvalidated
+    fn publish_patch(&mut self, patch: serde_json::Value) -> AwarenessUpdate {
+        let (old, base_clock) = match self.states.get(&self.client_id) {
+            Some(local) if local.state.is_object() && patch.is_object() => {
+                (local.state.clone(), local.clock)
+            }
+            // Nothing to patch onto: send the full state
+            local => {
+                let mut state = local.map(|local| local.state.clone()).unwrap_or_default();
+                merge_patch(&mut state, &patch);
+                return self.publish_state(state);
+            }
+        };
+
+        let mut new = old.clone();
+        merge_patch(&mut new, &patch);
+        let changed: serde_json::Map<String, serde_json::Value> = patch
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| new.get(key.as_str()) != old.get(key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        let clock = self.clock.increment();
+        self.states.insert(
+            self.client_id.clone(),
+            AwarenessState {
+                client_id: self.client_id.clone(),
+                state: new.clone(),
+                clock,
+                #[cfg(not(target_arch = "wasm32"))]
+                last_updated: Some(Instant::now()),
+            },
+        );
+        let client_id = self.client_id.clone();
+        self.touch(&client_id);
+        self.notify(&client_id, Some(old), Some(&new));
+
+        AwarenessUpdate {
+            client_id,
+            state: Some(serde_json::Value::Object(changed)),
+            clock,
+            base_clock: Some(base_clock),
+        }
+    }
+
+    /// Full local state at its current clock, to answer a peer that got
+    /// `UpdateOutcome::NeedsFullState`
+    pub fn local_state_update(&self) -> Option<AwarenessUpdate> {
+        let local = self.get_local_state()?;
+        Some(AwarenessUpdate {
+            client_id: self.client_id.clone(),
+            state: Some(local.state.clone()),
+            clock: local.clock,
+            base_clock: None,
+        })
+    }
+
+    /// Whether our last update is at least `interval_ms` old, so a
+    /// heartbeat should be sent
+    ///
+    /// False until we have a local state. Without a time source only
+    /// heartbeats reset the timer, so the first call after setting state
+    /// is always due.
+    pub fn heartbeat_due(&self, now_ms: u64, interval_ms: u64) -> bool {
+        if self.get_local_state().is_none() {
+            return false;
+        }
+        self.last_seen_ms
+            .get(&self.client_id)
+            .is_none_or(|&last| now_ms.saturating_sub(last) >= interval_ms)
+    }
+
+    /// "Still here" update: bumps our sequence without changing the state
+    ///
+    /// Sent as an empty patch, so receivers refresh our last-seen time
+    /// without firing `Updated`. A receiver that already expired us
+    /// answers `NeedsFullState`, which is how a reconnect (or a tab that
+    /// was hidden past the timeout) gets its presence back. Returns None
+    /// without a local state.
+    pub fn create_heartbeat_update(&mut self, now_ms: u64) -> Option<AwarenessUpdate> {
+        let local = self.get_local_state()?;
+        let update = if local.state.is_object() {
+            self.publish_patch(serde_json::Value::Object(serde_json::Map::new()))
+        } else {
+            let state = local.state.clone();
+            self.publish_state(state)
+        };
+        self.last_seen_ms.insert(self.client_id.clone(), now_ms);
+        Some(update)
+    }
+
+    /// [`Awareness::heartbeat_due`] at the time source's current time,
+    /// else the thread's default [`TimeProvider`]
+    pub fn heartbeat_due_now(&self, interval_ms: u64) -> bool {
+        self.heartbeat_due(self.wall_clock_ms(), interval_ms)
+    }
+
+    /// [`Awareness::create_heartbeat_update`] at the time source's
+    /// current time, else the thread's default [`TimeProvider`]
+    pub fn create_heartbeat_update_now(&mut self) -> Option<AwarenessUpdate> {
+        let now_ms = self.wall_clock_ms();
+        self.create_heartbeat_update(now_ms)
+    }
+
+    /// Time source reading, else the thread's default provider
+    fn wall_clock_ms(&self) -> u64 {
+        match &self.time_source {
+            Some(time_source) => time_source.now_ms(),
+            None => time::now_ms(),
         }
     }
 
     /// Apply remote awareness update
-    pub fn apply_update(&mut self, update: AwarenessUpdate) {
+    ///
+    /// Updates carrying a clock at or below the newest one seen for that
+    /// client (including its leave) are ignored, so reordered or replayed
+    /// updates never overwrite newer state. With `set_limits`, updates that
+    /// break a limit are dropped whole and returned as
+    /// `UpdateOutcome::Rejected`; leaves are always accepted.
+    pub fn apply_update(&mut self, update: AwarenessUpdate) -> UpdateOutcome {
         // Update our clock to maintain monotonicity
         self.clock.update_to_max(update.clock);
 
-        match update.state {
-            Some(state) => {
-                // Client is online with new state
-                let should_update = self
-                    .states
+        let latest = self
+            .states
+            .get(&update.client_id)
+            .map(|existing| existing.clock)
+            .max(self.departed.get(&update.client_id).copied())
+            .max(
+                self.recently_left
                     .get(&update.client_id)
-                    .map(|existing| update.clock > existing.clock)
-                    .unwrap_or(true);
-
-                if should_update {
-                    self.states.insert(
-                        update.client_id.clone(),
-                        AwarenessState {
-                            client_id: update.client_id,
-                            state,
-                            clock: update.clock,
-                            #[cfg(not(target_arch = "wasm32"))]
-                            last_updated: Some(Instant::now()),
-                        },
-                    );
+                    .map(|held| held.state.clock),
+            );
+        if latest.is_some_and(|latest| update.clock <= latest) {
+            return UpdateOutcome::Ignored;
+        }
+
+        let Some(incoming) = update.state else {
+            // Client left gracefully
+            let old = self.states.remove(&update.client_id).or_else(|| {
+                self.recently_left
+                    .remove(&update.client_id)
+                    .map(|held| held.state)
+            });
+            self.notify(&update.client_id, old.map(|old| old.state), None);
+            if let Some(limiter) = &mut self.limiter {
+                limiter.forget(&update.client_id);
+            }
+            self.last_seen_ms.remove(&update.client_id);
+            self.departed.insert(update.client_id, update.clock);
+            return UpdateOutcome::Applied;
+        };
+
+        let state = if update.base_clock.is_some() {
+            let Some(existing) = self
+                .states
+                .get(&update.client_id)
+                .or_else(|| {
+                    self.recently_left
+                        .get(&update.client_id)
+                        .map(|held| &held.state)
+                })
+                .filter(|existing| Some(existing.clock) == update.base_clock)
+            else {
+                return UpdateOutcome::NeedsFullState;
+            };
+            let mut merged = existing.state.clone();
+            merge_patch(&mut merged, &incoming);
+            merged
+        } else {
+            incoming
+        };
+
+        let state = match self.validate_remote(&update.client_id, state) {
+            Ok(state) => state,
+            Err(violation) => return UpdateOutcome::Rejected(violation),
+        };
+        if let Err(violation) = self.check_limits(&update.client_id, &state) {
+            return UpdateOutcome::Rejected(violation);
+        }
+
+        // Client is online with new state
+        self.departed.remove(&update.client_id);
+        let old = self.states.insert(
+            update.client_id.clone(),
+            AwarenessState {
+                client_id: update.client_id.clone(),
+                state: state.clone(),
+                clock: update.clock,
+                #[cfg(not(target_arch = "wasm32"))]
+                last_updated: Some(Instant::now()),
+            },
+        );
+        self.touch(&update.client_id);
+        if self.recently_left.remove(&update.client_id).is_some() {
+            self.subscribers.emit(&AwarenessEvent::Rejoined {
+                client_id: update.client_id,
+                state,
+            });
+        } else {
+            self.notify(&update.client_id, old.map(|old| old.state), Some(&state));
+        }
+        UpdateOutcome::Applied
+    }
+
+    /// Run the validator on a remote client's would-be state (skipped if
+    /// unchanged), reporting failures as `AwarenessEvent::Invalid`
+    fn validate_remote(
+        &mut self,
+        client_id: &str,
+        state: serde_json::Value,
+    ) -> Result<serde_json::Value, LimitViolation> {
+        let Some(validator) = &self.validator else {
+            return Ok(state);
+        };
+        let unchanged = self
+            .states
+            .get(client_id)
+            .or_else(|| self.recently_left.get(client_id).map(|held| &held.state))
+            .is_some_and(|existing| existing.state == state);
+        if unchanged {
+            return Ok(state);
+        }
+
+        match validator.check_remote(client_id, state) {
+            Ok(accepted) => {
+                if let Some(reason) = accepted.reason {
+                    self.subscribers.emit(&AwarenessEvent::Invalid {
+                        client_id: client_id.to_string(),
+                        reason,
+                        stripped: accepted.stripped,
+                    });
                 }
+                Ok(accepted.state)
             }
-            None => {
-                // Client left gracefully
-                self.states.remove(&update.client_id);
+            Err(reason) => {
+                self.subscribers.emit(&AwarenessEvent::Invalid {
+                    client_id: client_id.to_string(),
+                    reason: reason.clone(),
+                    stripped: Vec::new(),
+                });
+                Err(LimitViolation::InvalidState {
+                    client_id: client_id.to_string(),
+                    reason,
+                })
+            }
+        }
+    }
+
+    /// Check a remote client's would-be state against the limits (takes a
+    /// rate limit token)
+    fn check_limits(
+        &mut self,
+        client_id: &str,
+        state: &serde_json::Value,
+    ) -> Result<(), LimitViolation> {
+        let Some(limiter) = &mut self.limiter else {
+            return Ok(());
+        };
+
+        if let Some(limit) = limiter.limits.max_clients {
+            let tracked = self
+                .states
+                .keys()
+                .filter(|id| **id != self.client_id)
+                .count();
+            if !self.states.contains_key(client_id) && tracked >= limit {
+                return Err(LimitViolation::TooManyClients {
+                    client_id: client_id.to_string(),
+                    limit,
+                });
             }
         }
+
+        limiter.check_rate(client_id)?;
+
+        if let Some(limit) = limiter.limits.max_state_bytes {
+            let bytes = serde_json::to_vec(state).map_or(usize::MAX, |bytes| bytes.len());
+            if bytes > limit {
+                return Err(LimitViolation::StateTooLarge {
+                    client_id: client_id.to_string(),
+                    bytes,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Newest clock seen for every client, to send to a peer that will
+    /// answer with [`Awareness::encode_diff`]
+    pub fn version(&self) -> AwarenessVersion {
+        let mut version = AwarenessVersion::new();
+        for (client_id, state) in &self.states {
+            version.observe(client_id, state.clock);
+        }
+        for (client_id, &clock) in &self.departed {
+            version.observe(client_id, clock);
+        }
+        version
+    }
+
+    /// Clients whose state changed or who left since `since`
+    pub fn encode_diff(&self, since: &AwarenessVersion) -> AwarenessDiff {
+        let changed = self
+            .states
+            .values()
+            .filter(|state| state.clock > since.get(&state.client_id))
+            .map(|state| AwarenessUpdate {
+                client_id: state.client_id.clone(),
+                state: Some(state.state.clone()),
+                clock: state.clock,
+                base_clock: None,
+            });
+        let left = self
+            .departed
+            .iter()
+            .filter(|(client_id, &clock)| clock > since.get(client_id))
+            .map(|(client_id, &clock)| AwarenessUpdate {
+                client_id: client_id.clone(),
+                state: None,
+                clock,
+                base_clock: None,
+            });
+
+        AwarenessDiff {
+            updates: changed.chain(left).collect(),
+        }
+    }
+
+    /// Apply a diff from a peer (stale entries are ignored as in
+    /// [`Awareness::apply_update`]); returns the entries rejected by limits
+    pub fn apply_diff(&mut self, diff: AwarenessDiff) -> Vec<LimitViolation> {
+        diff.updates
+            .into_iter()
+            .filter_map(|update| match self.apply_update(update) {
+                UpdateOutcome::Rejected(violation) => Some(violation),
+                _ => None,
+            })
+            .collect()
     }
 
     /// Remove clients that haven't updated within timeout
-    /// Returns list of removed client IDs
+    /// Returns list of removed client IDs (each also fires `Left`)
+    ///
+    /// Uses the time source if one is set, `Instant` otherwise. With a
+    /// grace period, stale clients are held in `recently_left` first and
+    /// only returned once the grace period is over.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn remove_stale_clients(&mut self, timeout: Duration) -> Vec<String> {
-        let now = Instant::now();
+        let stale: Vec<String> = match &self.time_source {
+            Some(time_source) => {
+                self.unseen_clients(time_source.now_ms(), timeout.as_millis() as u64)
+            }
+            None => {
+                let now = Instant::now();
+                self.states
+                    .values()
+                    .filter(|state| {
+                        state
+                            .last_updated
+                            .is_some_and(|last_updated| now.duration_since(last_updated) > timeout)
+                    })
+                    .map(|state| state.client_id.clone())
+                    .collect()
+            }
+        };
+        self.retire_clients(stale)
+    }
+
+    /// Remove clients that haven't updated within timeout
+    /// Returns list of removed client IDs (each also fires `Left`)
+    /// WASM version: needs a time source (`set_time_source`), no-op without
+    #[cfg(target_arch = "wasm32")]
+    pub fn remove_stale_clients(&mut self, timeout_ms: u64) -> Vec<String> {
+        let stale = match &self.time_source {
+            Some(time_source) => self.unseen_clients(time_source.now_ms(), timeout_ms),
+            None => return Vec::new(),
+        };
+        self.retire_clients(stale)
+    }
+
+    /// Clients last seen more than `timeout_ms` before `now_ms`
+    fn unseen_clients(&self, now_ms: u64, timeout_ms: u64) -> Vec<String> {
+        self.states
+            .keys()
+            .filter(|client_id| {
+                self.last_seen_ms
+                    .get(*client_id)
+                    .is_some_and(|&last| now_ms.saturating_sub(last) > timeout_ms)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Move `stale` clients to the grace period holding area (or drop them
+    /// if there is none) and drop held clients whose grace period is over;
+    /// fires `Left` for and returns the clients dropped
+    fn retire_clients(&mut self, stale: Vec<String>) -> Vec<String> {
+        let now_ms = self.now_ms();
         let mut removed = Vec::new();
 
-        self.states.retain(|client_id, state| {
-            if let Some(last_updated) = state.last_updated {
-                if now.duration_since(last_updated) > timeout {
+        if let (Some(grace_ms), Some(now_ms)) = (self.grace_period_ms, now_ms) {
+            self.recently_left.retain(|client_id, held| {
+                if now_ms.saturating_sub(held.left_at_ms) > grace_ms {
                     removed.push(client_id.clone());
                     return false;
                 }
+                true
+            });
+        }
+
+        for client_id in stale {
+            let Some(state) = self.states.remove(&client_id) else {
+                continue;
+            };
+            self.last_seen_ms.remove(&client_id);
+            match (self.grace_period_ms, now_ms) {
+                (Some(_), Some(left_at_ms)) => {
+                    self.recently_left
+                        .insert(client_id, HeldState { state, left_at_ms });
+                }
+                _ => removed.push(client_id),
             }
-            true
-        });
+        }
 
+        for client_id in &removed {
+            self.subscribers.emit(&AwarenessEvent::Left {
+                client_id: client_id.clone(),
+            });
+        }
         removed
     }
 
-    /// Remove clients that haven't updated within timeout
-    /// Returns list of removed client IDs
-    /// WASM version: No-op since time tracking is not available
-    #[cfg(target_arch = "wasm32")]
-    pub fn remove_stale_clients(&mut self, _timeout_ms: u64) -> Vec<String> {
-        // Time tracking not available in WASM
-        // Stale client removal should be handled server-side
-        Vec::new()
-    }
-
     /// Create update to signal local client leaving
     pub fn create_leave_update(&self) -> AwarenessUpdate {
         AwarenessUpdate {
             client_id: self.client_id.clone(),
             state: None,
             clock: self.clock.increment(),
+            base_clock: None,
         }
     }
 
@@ -186,9 +807,33 @@ impl Awareness {
     }
 }
 
+/// Apply a JSON merge patch (RFC 7386) in place
+fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let serde_json::Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(
+                    target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                    value,
+                );
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::time::MockTime;
     use serde_json::json;
 
     #[test]
@@ -200,7 +845,7 @@ mod tests {
             "color": "#FF0000",
         });
 
-        let update = awareness.set_local_state(state.clone());
+        let update = awareness.set_local_state(state.clone()).unwrap();
 
         assert_eq!(update.client_id, "client-1");
         assert_eq!(update.state, Some(state));
@@ -216,6 +861,7 @@ mod tests {
             client_id: "client-2".to_string(),
             state: Some(json!({"name": "Bob"})),
             clock: 5,
+            base_clock: None,
         };
 
         awareness.apply_update(update);
@@ -233,11 +879,12 @@ mod tests {
             client_id: "client-2".to_string(),
             state: Some(json!({})),
             clock: 100,
+            base_clock: None,
         };
         awareness.apply_update(update);
 
         // Local clock should be at least 100
-        let local_update = awareness.set_local_state(json!({}));
+        let local_update = awareness.set_local_state(json!({})).unwrap();
         assert!(local_update.clock > 100);
     }
 
@@ -250,6 +897,7 @@ mod tests {
             client_id: "client-2".to_string(),
             state: Some(json!({"name": "Bob"})),
             clock: 1,
+            base_clock: None,
         });
         assert_eq!(awareness.client_count(), 1);
 
@@ -258,6 +906,7 @@ mod tests {
             client_id: "client-2".to_string(),
             state: None,
             clock: 2,
+            base_clock: None,
         });
         assert_eq!(awareness.client_count(), 0);
     }
@@ -267,7 +916,7 @@ mod tests {
         let mut awareness = Awareness::new("client-1".to_string());
 
         // Add self
-        awareness.set_local_state(json!({}));
+        awareness.set_local_state(json!({})).unwrap();
         assert_eq!(awareness.other_client_count(), 0);
 
         // Add another client
@@ -275,7 +924,351 @@ mod tests {
             client_id: "client-2".to_string(),
             state: Some(json!({})),
             clock: 1,
+            base_clock: None,
         });
         assert_eq!(awareness.other_client_count(), 1);
     }
+
+    fn update(client_id: &str, state: Option<serde_json::Value>, clock: u64) -> AwarenessUpdate {
+        AwarenessUpdate {
+            client_id: client_id.to_string(),
+            state,
+            clock,
+            base_clock: None,
+        }
+    }
+
+    #[test]
+    fn test_out_of_order_updates_keep_newest() {
+        let mut sender = Awareness::new("client-2".to_string());
+        let first = sender.set_local_state(json!({"cursor": 1})).unwrap();
+        let second = sender.set_local_state(json!({"cursor": 2})).unwrap();
+        let third = sender.set_local_state(json!({"cursor": 3})).unwrap();
+
+        let mut awareness = Awareness::new("client-1".to_string());
+        awareness.apply_update(third.clone());
+        awareness.apply_update(first);
+        awareness.apply_update(second);
+        // Replaying the same update is a no-op too
+        awareness.apply_update(third);
+
+        let state = awareness.get_state("client-2").unwrap();
+        assert_eq!(state.state, json!({"cursor": 3}));
+        assert_eq!(state.clock, 3);
+    }
+
+    #[test]
+    fn test_stale_update_after_leave_does_not_resurrect() {
+        let mut awareness = Awareness::new("client-1".to_string());
+        awareness.apply_update(update("client-2", Some(json!({"cursor": 1})), 1));
+        awareness.apply_update(update("client-2", None, 3));
+        awareness.apply_update(update("client-2", Some(json!({"cursor": 2})), 2));
+        assert!(awareness.get_state("client-2").is_none());
+
+        // A newer update after the leave means the client came back
+        awareness.apply_update(update("client-2", Some(json!({"cursor": 4})), 4));
+        assert_eq!(
+            awareness.get_state("client-2").unwrap().state,
+            json!({"cursor": 4})
+        );
+    }
+
+    #[test]
+    fn test_stale_leave_does_not_remove_newer_state() {
+        let mut awareness = Awareness::new("client-1".to_string());
+        awareness.apply_update(update("client-2", Some(json!({"cursor": 5})), 5));
+        awareness.apply_update(update("client-2", None, 4));
+        assert_eq!(awareness.client_count(), 1);
+    }
+
+    #[test]
+    fn test_leave_update_bumps_clock() {
+        let mut awareness = Awareness::new("client-1".to_string());
+        let set = awareness.set_local_state(json!({})).unwrap();
+        let leave = awareness.create_leave_update();
+        assert!(leave.clock > set.clock);
+        assert!(leave.state.is_none());
+    }
+
+    fn profile() -> serde_json::Value {
+        json!({
+            "name": "Alice",
+            "color": "#FF0000",
+            "avatar": "https://example.com/avatars/alice-large.png",
+            "cursor": {"line": 1, "column": 1},
+        })
+    }
+
+    #[test]
+    fn test_cursor_patch_is_small() {
+        let mut sender = Awareness::new("client-2".to_string());
+        let full = sender.set_local_state(profile()).unwrap();
+        let patch = sender
+            .update_local_state(json!({"cursor": {"column": 2}}))
+            .unwrap();
+
+        assert_eq!(patch.state, Some(json!({"cursor": {"column": 2}})));
+        assert_eq!(patch.base_clock, Some(full.clock));
+        let full_len = serde_json::to_vec(&full).unwrap().len();
+        assert!(serde_json::to_vec(&patch).unwrap().len() * 2 < full_len);
+        assert_eq!(
+            sender.get_local_state().unwrap().state["cursor"],
+            json!({"line": 1, "column": 2})
+        );
+
+        let mut receiver = Awareness::new("client-1".to_string());
+        assert_eq!(receiver.apply_update(full), UpdateOutcome::Applied);
+        assert_eq!(receiver.apply_update(patch), UpdateOutcome::Applied);
+        assert_eq!(
+            receiver.get_state("client-2").unwrap().state,
+            sender.get_local_state().unwrap().state
+        );
+    }
+
+    #[test]
+    fn test_patch_only_carries_changed_keys() {
+        let mut sender = Awareness::new("client-2".to_string());
+        sender.set_local_state(profile()).unwrap();
+        let patch = sender
+            .update_local_state(json!({"name": "Alice", "color": null}))
+            .unwrap();
+
+        assert_eq!(patch.state, Some(json!({"color": null})));
+        assert!(sender
+            .get_local_state()
+            .unwrap()
+            .state
+            .get("color")
+            .is_none());
+    }
+
+    #[test]
+    fn test_dropped_patch_falls_back_to_full_state() {
+        let mut sender = Awareness::new("client-2".to_string());
+        let mut receiver = Awareness::new("client-1".to_string());
+        receiver.apply_update(sender.set_local_state(profile()).unwrap());
+
+        let _dropped = sender
+            .update_local_state(json!({"color": "#00FF00"}))
+            .unwrap();
+        let patch = sender
+            .update_local_state(json!({"cursor": {"line": 9}}))
+            .unwrap();
+
+        // Applying onto the wrong base would lose the color change
+        assert_eq!(receiver.apply_update(patch), UpdateOutcome::NeedsFullState);
+        assert_eq!(receiver.get_state("client-2").unwrap().state, profile());
+
+        let full = sender.local_state_update().unwrap();
+        assert_eq!(receiver.apply_update(full), UpdateOutcome::Applied);
+        assert_eq!(
+            receiver.get_state("client-2").unwrap().state,
+            sender.get_local_state().unwrap().state
+        );
+    }
+
+    #[test]
+    fn test_patch_without_local_state_sends_full_state() {
+        let mut awareness = Awareness::new("client-1".to_string());
+        let update = awareness
+            .update_local_state(json!({"cursor": 3, "gone": null}))
+            .unwrap();
+        assert_eq!(update.base_clock, None);
+        assert_eq!(update.state, Some(json!({"cursor": 3})));
+
+        let mut receiver = Awareness::new("client-2".to_string());
+        assert_eq!(
+            receiver.apply_update(AwarenessUpdate {
+                base_clock: Some(1),
+                ..update.clone()
+            }),
+            UpdateOutcome::NeedsFullState
+        );
+        assert_eq!(receiver.apply_update(update), UpdateOutcome::Applied);
+    }
+
+    /// Receiver whose time source the test advances by hand
+    fn receiver_with_clock() -> (Awareness, MockTime) {
+        let now = MockTime::new(0);
+        let mut awareness = Awareness::new("receiver".to_string());
+        awareness.set_time_source(now.clone());
+        (awareness, now)
+    }
+
+    #[test]
+    fn test_heartbeats_keep_clients_alive() {
+        let (mut receiver, now) = receiver_with_clock();
+        let mut alive = Awareness::new("alive".to_string());
+        alive.set_time_source(now.clone());
+        let mut silent = Awareness::new("silent".to_string());
+        receiver.apply_update(alive.set_local_state(json!({"name": "A"})).unwrap());
+        receiver.apply_update(silent.set_local_state(json!({"name": "S"})).unwrap());
+
+        for t in [10_000, 20_000, 30_000] {
+            now.set(t);
+            assert!(alive.heartbeat_due_now(10_000));
+            let heartbeat = alive.create_heartbeat_update_now().unwrap();
+            assert!(!alive.heartbeat_due_now(10_000));
+            assert_eq!(receiver.apply_update(heartbeat), UpdateOutcome::Applied);
+        }
+
+        now.set(35_000);
+        assert_eq!(
+            receiver.remove_stale_clients(Duration::from_secs(30)),
+            vec!["silent".to_string()]
+        );
+        assert_eq!(
+            receiver.get_state("alive").unwrap().state,
+            json!({"name": "A"})
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_fires_no_events() {
+        let mut sender = Awareness::new("sender".to_string());
+        let mut receiver = Awareness::new("receiver".to_string());
+        receiver.apply_update(sender.set_local_state(json!({"name": "S"})).unwrap());
+
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        for awareness in [&mut sender, &mut receiver] {
+            let sink = events.clone();
+            awareness.subscribe(move |event| sink.lock().unwrap().push(event.clone()));
+        }
+
+        let heartbeat = sender.create_heartbeat_update(1_000).unwrap();
+        assert_eq!(heartbeat.state, Some(json!({})));
+        assert_eq!(receiver.apply_update(heartbeat), UpdateOutcome::Applied);
+        assert!(events.lock().unwrap().is_empty());
+        assert_eq!(
+            receiver.get_state("sender").unwrap().clock,
+            sender.get_local_state().unwrap().clock
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_due_follows_last_update() {
+        let mut awareness = Awareness::new("client-1".to_string());
+        assert!(!awareness.heartbeat_due(0, 10_000));
+        assert!(awareness.create_heartbeat_update(0).is_none());
+
+        awareness.set_local_state(json!("away")).unwrap();
+        assert!(awareness.heartbeat_due(0, 10_000));
+        // Non-object states are resent whole
+        let heartbeat = awareness.create_heartbeat_update(1_000).unwrap();
+        assert_eq!(heartbeat.state, Some(json!("away")));
+        assert!(!awareness.heartbeat_due(10_999, 10_000));
+        assert!(awareness.heartbeat_due(11_000, 10_000));
+
+        // With a time source, ordinary updates reset the timer too
+        let (mut awareness, now) = receiver_with_clock();
+        now.set(50_000);
+        awareness.set_local_state(json!({"cursor": 1})).unwrap();
+        assert!(!awareness.heartbeat_due(55_000, 10_000));
+        assert!(awareness.heartbeat_due(60_000, 10_000));
+    }
+
+    #[test]
+    fn test_heartbeat_after_expiry_asks_for_full_state() {
+        let (mut receiver, now) = receiver_with_clock();
+        let mut sender = Awareness::new("sender".to_string());
+        receiver.apply_update(sender.set_local_state(json!({"name": "S"})).unwrap());
+
+        // The sender's tab was hidden past the timeout
+        now.set(60_000);
+        receiver.remove_stale_clients(Duration::from_secs(30));
+        let heartbeat = sender.create_heartbeat_update(60_000).unwrap();
+        assert_eq!(
+            receiver.apply_update(heartbeat),
+            UpdateOutcome::NeedsFullState
+        );
+        receiver.apply_update(sender.local_state_update().unwrap());
+        assert!(receiver.get_state("sender").is_some());
+    }
+
+    /// Receiver holding timed-out clients for 10s, with `bob` joined at
+    /// t=0 and timed out (30s timeout) at t=35s; returns the events seen
+    /// from then on
+    fn bob_timed_out() -> (
+        Awareness,
+        Awareness,
+        MockTime,
+        std::sync::Arc<std::sync::Mutex<Vec<AwarenessEvent>>>,
+    ) {
+        let (mut receiver, now) = receiver_with_clock();
+        receiver.set_grace_period(Duration::from_secs(10));
+        let mut bob = Awareness::new("bob".to_string());
+        receiver.apply_update(bob.set_local_state(json!({"name": "Bob"})).unwrap());
+
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        receiver.subscribe(move |event| sink.lock().unwrap().push(event.clone()));
+
+        now.set(35_000);
+        assert!(receiver
+            .remove_stale_clients(Duration::from_secs(30))
+            .is_empty());
+        (receiver, bob, now, events)
+    }
+
+    #[test]
+    fn test_rejoin_within_grace_period() {
+        let (mut receiver, mut bob, now, events) = bob_timed_out();
+        assert!(receiver.get_state("bob").is_none());
+        let held: Vec<_> = receiver.recently_left().collect();
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].state, json!({"name": "Bob"}));
+        assert!(events.lock().unwrap().is_empty());
+
+        now.set(40_000);
+        let heartbeat = bob.create_heartbeat_update(40_000).unwrap();
+        assert_eq!(receiver.apply_update(heartbeat), UpdateOutcome::Applied);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![AwarenessEvent::Rejoined {
+                client_id: "bob".to_string(),
+                state: json!({"name": "Bob"}),
+            }]
+        );
+        assert!(receiver.get_state("bob").is_some());
+        assert_eq!(receiver.recently_left().count(), 0);
+    }
+
+    #[test]
+    fn test_rejoin_after_grace_period_is_a_new_join() {
+        let (mut receiver, mut bob, now, events) = bob_timed_out();
+
+        now.set(46_000);
+        assert_eq!(
+            receiver.remove_stale_clients(Duration::from_secs(30)),
+            vec!["bob".to_string()]
+        );
+        assert_eq!(receiver.recently_left().count(), 0);
+
+        let heartbeat = bob.create_heartbeat_update(46_000).unwrap();
+        assert_eq!(
+            receiver.apply_update(heartbeat),
+            UpdateOutcome::NeedsFullState
+        );
+        receiver.apply_update(bob.local_state_update().unwrap());
+
+        let events = events.lock().unwrap();
+        assert!(matches!(events[0], AwarenessEvent::Left { .. }));
+        assert!(matches!(events[1], AwarenessEvent::Joined { .. }));
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn test_leave_during_grace_period_is_final() {
+        let (mut receiver, bob, _, events) = bob_timed_out();
+
+        receiver.apply_update(bob.create_leave_update());
+        assert_eq!(receiver.recently_left().count(), 0);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![AwarenessEvent::Left {
+                client_id: "bob".to_string()
+            }]
+        );
+    }
 }
@@ -0,0 +1,463 @@
+/// Application-defined checks on awareness states
+///
+/// Presence payloads are free-form JSON, so one buggy client can publish
+/// a state that breaks every peer's presence rendering. A validator set
+/// with `Awareness::set_validator` runs on every state change: local ones
+/// that fail are refused with an error, remote ones are dropped or
+/// sanitized according to a `ValidationPolicy`. Updates that leave the
+/// state unchanged (heartbeats) skip validation.
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// What to do with a remote state that fails validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationPolicy {
+    /// Drop the update and report it
+    Reject,
+    /// Strip top-level keys not in `allowed_keys` and validate again;
+    /// drop the update if it still fails
+    Sanitize { allowed_keys: Vec<String> },
+}
+
+type Check = Box<dyn Fn(&str, &Value) -> Result<(), String> + Send>;
+
+/// Validator plus its policy for remote states
+pub(super) struct Validator {
+    check: Check,
+    policy: ValidationPolicy,
+}
+
+/// A remote state that passed (possibly after sanitizing)
+pub(super) struct Accepted {
+    pub(super) state: Value,
+    /// Keys removed by `ValidationPolicy::Sanitize`
+    pub(super) stripped: Vec<String>,
+    /// Why the original state failed, if it was sanitized
+    pub(super) reason: Option<String>,
+}
+
+impl Validator {
+    pub(super) fn new(check: Check, policy: ValidationPolicy) -> Self {
+        Self { check, policy }
+    }
+
+    /// Check a state we are about to publish
+    pub(super) fn check_local(&self, client_id: &str, state: &Value) -> Result<(), String> {
+        (self.check)(client_id, state)
+    }
+
+    /// Check a remote state, sanitizing it if the policy allows
+    pub(super) fn check_remote(&self, client_id: &str, state: Value) -> Result<Accepted, String> {
+        let reason = match (self.check)(client_id, &state) {
+            Ok(()) => {
+                return Ok(Accepted {
+                    state,
+                    stripped: Vec::new(),
+                    reason: None,
+                })
+            }
+            Err(reason) => reason,
+        };
+
+        let ValidationPolicy::Sanitize { allowed_keys } = &self.policy else {
+            return Err(reason);
+        };
+        let Value::Object(map) = state else {
+            return Err(reason);
+        };
+        let (kept, stripped): (Map<String, Value>, Map<String, Value>) = map
+            .into_iter()
+            .partition(|(key, _)| allowed_keys.contains(key));
+        let state = Value::Object(kept);
+        (self.check)(client_id, &state)?;
+
+        let mut stripped: Vec<String> = stripped.into_iter().map(|(key, _)| key).collect();
+        stripped.sort();
+        Ok(Accepted {
+            state,
+            stripped,
+            reason: Some(reason),
+        })
+    }
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Validator")
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+/// A small JSON Schema subset for awareness states
+///
+/// Supported: `type`, `required`, `properties`, `additionalProperties:
+/// false`, `enum`, `minLength`/`maxLength`, `minimum`/`maximum`, and
+/// `items` for arrays. Any other keyword is refused by `from_json` rather
+/// than silently ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateSchema {
+    schema: Value,
+}
+
+const SCHEMA_KEYWORDS: &[&str] = &[
+    "$schema",
+    "title",
+    "description",
+    "type",
+    "required",
+    "properties",
+    "additionalProperties",
+    "enum",
+    "minLength",
+    "maxLength",
+    "minimum",
+    "maximum",
+    "items",
+];
+
+impl StateSchema {
+    /// Parse a schema
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::InvalidInput` if the schema is not an object or
+    /// uses an unsupported keyword
+    pub fn from_json(schema: &Value) -> crate::Result<Self> {
+        check_keywords(schema, "")?;
+        Ok(Self {
+            schema: schema.clone(),
+        })
+    }
+
+    /// Top-level property names (the keys `ValidationPolicy::Sanitize`
+    /// should keep)
+    pub fn property_names(&self) -> Vec<String> {
+        self.schema
+            .get("properties")
+            .and_then(Value::as_object)
+            .map(|properties| properties.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Check a state against the schema; the error names the offending
+    /// path
+    pub fn validate(&self, state: &Value) -> Result<(), String> {
+        validate_node(&self.schema, state, "$")
+    }
+}
+
+fn check_keywords(schema: &Value, path: &str) -> crate::Result<()> {
+    let Some(map) = schema.as_object() else {
+        return Err(crate::SyncKitError::invalid_input(format!(
+            "schema{} must be an object",
+            path
+        )));
+    };
+    if let Some(keyword) = map
+        .keys()
+        .find(|keyword| !SCHEMA_KEYWORDS.contains(&keyword.as_str()))
+    {
+        return Err(crate::SyncKitError::invalid_input(format!(
+            "unsupported schema keyword `{}` at schema{}",
+            keyword, path
+        )));
+    }
+    if let Some(properties) = map.get("properties").and_then(Value::as_object) {
+        for (name, property) in properties {
+            check_keywords(property, &format!("{}.properties.{}", path, name))?;
+        }
+    }
+    if let Some(items) = map.get("items") {
+        check_keywords(items, &format!("{}.items", path))?;
+    }
+    Ok(())
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+fn validate_node(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    match schema.get("type") {
+        Some(Value::String(expected)) if !type_matches(expected, value) => {
+            return Err(format!("{}: expected {}", path, expected));
+        }
+        Some(Value::Array(types))
+            if !types
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|expected| type_matches(expected, value)) =>
+        {
+            return Err(format!("{}: unexpected type", path));
+        }
+        _ => {}
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!("{}: not one of the allowed values", path));
+        }
+    }
+
+    if let Some(text) = value.as_str() {
+        let length = text.chars().count() as u64;
+        if schema
+            .get("minLength")
+            .and_then(Value::as_u64)
+            .is_some_and(|min| length < min)
+        {
+            return Err(format!("{}: shorter than minLength", path));
+        }
+        if schema
+            .get("maxLength")
+            .and_then(Value::as_u64)
+            .is_some_and(|max| length > max)
+        {
+            return Err(format!("{}: longer than maxLength", path));
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if schema
+            .get("minimum")
+            .and_then(Value::as_f64)
+            .is_some_and(|min| number < min)
+        {
+            return Err(format!("{}: below minimum", path));
+        }
+        if schema
+            .get("maximum")
+            .and_then(Value::as_f64)
+            .is_some_and(|max| number > max)
+        {
+            return Err(format!("{}: above maximum", path));
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for required in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !object.contains_key(required) {
+                return Err(format!("{}: missing required `{}`", path, required));
+            }
+        }
+        for (key, child) in object {
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => validate_node(property, child, &format!("{}.{}", path, key))?,
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    return Err(format!("{}: unknown key `{}`", path, key));
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (index, item) in array.iter().enumerate() {
+            validate_node(items, item, &format!("{}[{}]", path, index))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn presence_schema() -> StateSchema {
+        StateSchema::from_json(&json!({
+            "type": "object",
+            "required": ["name"],
+            "additionalProperties": false,
+            "properties": {
+                "name": {"type": "string", "minLength": 1, "maxLength": 40},
+                "color": {"type": "string", "minLength": 7, "maxLength": 7},
+                "cursor": {"type": "array", "items": {"type": "integer", "minimum": 0}}
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_schema_accepts_and_rejects() {
+        let schema = presence_schema();
+        assert!(schema
+            .validate(&json!({"name": "Alice", "color": "#ff0000", "cursor": [1, 2]}))
+            .is_ok());
+
+        assert_eq!(
+            schema.validate(&json!({"color": "#ff0000"})),
+            Err("$: missing required `name`".to_string())
+        );
+        assert_eq!(
+            schema.validate(&json!({"name": "Bob", "color": "red"})),
+            Err("$.color: shorter than minLength".to_string())
+        );
+        assert_eq!(
+            schema.validate(&json!({"name": "Bob", "cursor": [1, -2]})),
+            Err("$.cursor[1]: below minimum".to_string())
+        );
+        assert_eq!(
+            schema.validate(&json!({"name": "Bob", "debug": true})),
+            Err("$: unknown key `debug`".to_string())
+        );
+
+        let mut names = schema.property_names();
+        names.sort();
+        assert_eq!(names, vec!["color", "cursor", "name"]);
+    }
+
+    #[test]
+    fn test_unsupported_keywords_are_refused() {
+        let error = StateSchema::from_json(&json!({
+            "properties": {"color": {"type": "string", "pattern": "^#[0-9a-f]{6}$"}}
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("`pattern`"));
+        assert!(StateSchema::from_json(&json!(true)).is_err());
+    }
+
+    mod awareness {
+        use super::*;
+        use crate::awareness::{
+            Awareness, AwarenessEvent, AwarenessUpdate, LimitViolation, UpdateOutcome,
+        };
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        fn with_schema(policy: ValidationPolicy) -> (Awareness, Arc<Mutex<Vec<AwarenessEvent>>>) {
+            let schema = presence_schema();
+            let mut awareness = Awareness::new("receiver".to_string());
+            awareness.set_validator(move |_, state| schema.validate(state), policy);
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let sink = events.clone();
+            awareness.subscribe(move |event| sink.lock().unwrap().push(event.clone()));
+            (awareness, events)
+        }
+
+        fn remote(state: Value) -> AwarenessUpdate {
+            AwarenessUpdate {
+                client_id: "buggy".to_string(),
+                state: Some(state),
+                clock: 1,
+                base_clock: None,
+            }
+        }
+
+        #[test]
+        fn test_invalid_local_state_is_an_error() {
+            let (mut awareness, _) = with_schema(ValidationPolicy::Reject);
+            assert!(awareness
+                .set_local_state(json!({"color": "#00ff00"}))
+                .is_err());
+            assert!(awareness.get_local_state().is_none());
+
+            awareness.set_local_state(json!({"name": "Alice"})).unwrap();
+            let error = awareness
+                .update_local_state(json!({"name": null}))
+                .unwrap_err();
+            assert_eq!(error.code_name(), "INVALID_INPUT");
+            assert_eq!(
+                awareness.get_local_state().unwrap().state,
+                json!({"name": "Alice"})
+            );
+        }
+
+        #[test]
+        fn test_reject_policy_drops_and_reports() {
+            let (mut awareness, events) = with_schema(ValidationPolicy::Reject);
+            let outcome = awareness.apply_update(remote(json!({"name": 42})));
+
+            assert_eq!(
+                outcome,
+                UpdateOutcome::Rejected(LimitViolation::InvalidState {
+                    client_id: "buggy".to_string(),
+                    reason: "$.name: expected string".to_string(),
+                })
+            );
+            assert!(awareness.get_state("buggy").is_none());
+            assert_eq!(
+                *events.lock().unwrap(),
+                vec![AwarenessEvent::Invalid {
+                    client_id: "buggy".to_string(),
+                    reason: "$.name: expected string".to_string(),
+                    stripped: Vec::new(),
+                }]
+            );
+        }
+
+        #[test]
+        fn test_sanitize_policy_strips_unknown_keys() {
+            let schema = presence_schema();
+            let (mut awareness, events) = with_schema(ValidationPolicy::Sanitize {
+                allowed_keys: schema.property_names(),
+            });
+
+            let outcome =
+                awareness.apply_update(remote(json!({"name": "Bob", "debug": {"fps": 60}})));
+            assert_eq!(outcome, UpdateOutcome::Applied);
+            assert_eq!(
+                awareness.get_state("buggy").unwrap().state,
+                json!({"name": "Bob"})
+            );
+            assert!(matches!(
+                &events.lock().unwrap()[0],
+                AwarenessEvent::Invalid { stripped, .. } if *stripped == vec!["debug".to_string()]
+            ));
+
+            // Stripping can't add the missing name: still dropped
+            let outcome = awareness.apply_update(AwarenessUpdate {
+                clock: 2,
+                ..remote(json!({"debug": true}))
+            });
+            assert!(matches!(outcome, UpdateOutcome::Rejected(_)));
+            assert_eq!(
+                awareness.get_state("buggy").unwrap().state,
+                json!({"name": "Bob"})
+            );
+        }
+
+        #[test]
+        fn test_heartbeats_skip_validation() {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let mut receiver = Awareness::new("receiver".to_string());
+            let mut sender = Awareness::new("sender".to_string());
+            for awareness in [&mut receiver, &mut sender] {
+                let calls = calls.clone();
+                awareness.set_validator(
+                    move |_, _| {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        Ok(())
+                    },
+                    ValidationPolicy::Reject,
+                );
+            }
+
+            receiver.apply_update(sender.set_local_state(json!({"name": "S"})).unwrap());
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+            for now_ms in [10_000, 20_000] {
+                let heartbeat = sender.create_heartbeat_update(now_ms).unwrap();
+                assert_eq!(receiver.apply_update(heartbeat), UpdateOutcome::Applied);
+            }
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+        }
+    }
+}
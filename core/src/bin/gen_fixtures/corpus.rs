@@ -0,0 +1,456 @@
+//! The state fixture corpus: what each fixture in `tests/fixtures/state/`
+//! holds, and what its state hash covers
+//!
+//! Shared by the `gen-fixtures` binary, which writes the corpus, and
+//! `tests/fixture_compat.rs`, which checks it still loads.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use synckit_core::list::List;
+use synckit_core::migrate::{StateKind, VersionedState};
+use synckit_core::register::MVRegister;
+use synckit_core::sync::LWWField;
+use synckit_core::time::MockTime;
+use synckit_core::{Document, Timestamp, VectorClock};
+
+#[cfg(feature = "text-crdt")]
+use synckit_core::crdt::text_fugue::MarkExpand;
+#[cfg(feature = "fractional-index")]
+use synckit_core::crdt::FractionalIndex;
+#[cfg(feature = "text-crdt")]
+use synckit_core::crdt::FugueText;
+#[cfg(feature = "sets")]
+use synckit_core::crdt::ORSet;
+#[cfg(feature = "counters")]
+use synckit_core::crdt::PNCounter;
+
+/// Where the corpus lives
+pub const FIXTURE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/state");
+
+/// A fixture file: a saved state and the hash of what it reads as
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    /// Type of the state
+    pub kind: StateKind,
+
+    /// Format the state was saved in
+    pub format: u32,
+
+    /// [`state_hash`] of the state when it was saved, as 16 hex digits
+    pub state_hash: String,
+
+    /// The state's serde JSON
+    pub state: JsonValue,
+}
+
+impl Fixture {
+    /// Fixture of a saved state, hashed as this build reads it
+    pub fn of(saved: VersionedState) -> Self {
+        let current = saved
+            .clone()
+            .into_current()
+            .expect("fixture state upgrades");
+        let state_hash = state_hash(saved.kind, &current)
+            .unwrap_or_else(|| panic!("{} isn't compiled in", saved.kind.as_str()));
+        Self {
+            kind: saved.kind,
+            format: saved.format,
+            state_hash: format!("{:016x}", state_hash),
+            state: saved.state,
+        }
+    }
+
+    /// File name: the fixture's name and format
+    pub fn file_name(name: &str, format: u32) -> String {
+        format!("{}.v{}.json", name, format)
+    }
+
+    /// The bytes written to the fixture file: pretty JSON with sorted keys
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let value = serde_json::to_value(self).expect("fixtures serialize");
+        let mut bytes = serde_json::to_vec_pretty(&value).expect("fixtures serialize");
+        bytes.push(b'\n');
+        bytes
+    }
+}
+
+/// A persisted type and what it reads as
+pub trait FixtureState: Serialize + DeserializeOwned {
+    /// What the state reads as; covered by the state hash, so it must not
+    /// depend on hash map order
+    fn observe(&self) -> JsonValue;
+}
+
+/// Call `$check::<T>($args)` with `T` the type of `$kind`, or give `None`
+/// if that type's feature is off
+macro_rules! with_state_type {
+    ($kind:expr, $check:ident ( $($arg:expr),* )) => {{
+        use synckit_core::migrate::StateKind;
+        #[allow(unreachable_patterns)]
+        match $kind {
+            StateKind::Document => Some($check::<synckit_core::Document>($($arg),*)),
+            StateKind::VectorClock => Some($check::<synckit_core::VectorClock>($($arg),*)),
+            StateKind::LwwField => Some($check::<synckit_core::sync::LWWField>($($arg),*)),
+            StateKind::MvRegister => {
+                Some($check::<synckit_core::register::MVRegister>($($arg),*))
+            }
+            StateKind::List => Some($check::<synckit_core::list::List>($($arg),*)),
+            #[cfg(feature = "counters")]
+            StateKind::PnCounter => Some($check::<synckit_core::crdt::PNCounter>($($arg),*)),
+            #[cfg(feature = "sets")]
+            StateKind::OrSet => Some($check::<synckit_core::crdt::ORSet<String>>($($arg),*)),
+            #[cfg(feature = "text-crdt")]
+            StateKind::FugueText => Some($check::<synckit_core::crdt::FugueText>($($arg),*)),
+            #[cfg(feature = "fractional-index")]
+            StateKind::FractionalIndex => {
+                Some($check::<Vec<synckit_core::crdt::FractionalIndex>>($($arg),*))
+            }
+            _ => None,
+        }
+    }};
+}
+
+/// 64-bit FNV-1a of what a state reads as, or `None` if the state's type
+/// isn't compiled in
+///
+/// # Panics
+///
+/// If the state doesn't deserialize as its kind
+pub fn state_hash(kind: StateKind, state: &JsonValue) -> Option<u64> {
+    fn observed<T: FixtureState>(state: &JsonValue) -> JsonValue {
+        let value: T = serde_json::from_value(state.clone()).expect("fixture state loads");
+        value.observe()
+    }
+    let observed = with_state_type!(kind, observed(state))?;
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in observed.to_string().bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    Some(hash)
+}
+
+impl FixtureState for Document {
+    fn observe(&self) -> JsonValue {
+        json!({
+            "json": self.to_json(),
+            "content_hash": format!("{:016x}", self.content_hash()),
+            "version": self.version(),
+            "locks": self.locks(),
+            "annotations": self.annotations(),
+        })
+    }
+}
+
+impl FixtureState for VectorClock {
+    fn observe(&self) -> JsonValue {
+        json!(self.clocks())
+    }
+}
+
+impl FixtureState for LWWField {
+    fn observe(&self) -> JsonValue {
+        json!({"value": self.value, "timestamp": self.timestamp})
+    }
+}
+
+impl FixtureState for MVRegister {
+    fn observe(&self) -> JsonValue {
+        json!({"json": self.to_json(), "seen": self.seen()})
+    }
+}
+
+impl FixtureState for List {
+    fn observe(&self) -> JsonValue {
+        let ids: Vec<_> = self.iter().map(|(id, _)| id).collect();
+        json!({"json": self.to_json(), "ids": ids})
+    }
+}
+
+#[cfg(feature = "counters")]
+impl FixtureState for PNCounter {
+    fn observe(&self) -> JsonValue {
+        json!(self.value())
+    }
+}
+
+#[cfg(feature = "sets")]
+impl FixtureState for ORSet<String> {
+    fn observe(&self) -> JsonValue {
+        let mut elements: Vec<&String> = self.iter().collect();
+        elements.sort();
+        json!(elements)
+    }
+}
+
+#[cfg(feature = "text-crdt")]
+impl FixtureState for FugueText {
+    fn observe(&self) -> JsonValue {
+        json!({"text": self.to_string(), "clock": self.clock()})
+    }
+}
+
+#[cfg(feature = "fractional-index")]
+impl FixtureState for Vec<FractionalIndex> {
+    fn observe(&self) -> JsonValue {
+        json!(self.iter().map(FractionalIndex::as_str).collect::<Vec<_>>())
+    }
+}
+
+/// Every fixture this build writes, by name
+///
+/// Cover what a stored state can hold: several replicas' histories,
+/// tombstones, non-ASCII text and clocks near `u64::MAX`.
+#[cfg(all(
+    feature = "counters",
+    feature = "sets",
+    feature = "text-crdt",
+    feature = "fractional-index"
+))]
+pub fn build() -> Vec<(&'static str, VersionedState)> {
+    vec![
+        ("document", save(StateKind::Document, &document())),
+        (
+            "document_large_clocks",
+            save(StateKind::Document, &document_large_clocks()),
+        ),
+        (
+            "vector_clock",
+            save(StateKind::VectorClock, &vector_clock()),
+        ),
+        ("lww_field", save(StateKind::LwwField, &lww_field())),
+        ("mv_register", save(StateKind::MvRegister, &mv_register())),
+        ("list", save(StateKind::List, &list())),
+        ("pn_counter", save(StateKind::PnCounter, &pn_counter())),
+        ("or_set", save(StateKind::OrSet, &or_set())),
+        ("fugue_text", save(StateKind::FugueText, &fugue_text())),
+        (
+            "fractional_index",
+            save(StateKind::FractionalIndex, &fractional_index()),
+        ),
+    ]
+}
+
+/// States as releases using an earlier format saved them, by name
+pub fn legacy() -> Vec<(&'static str, VersionedState)> {
+    vec![(
+        "document",
+        VersionedState {
+            kind: StateKind::Document,
+            format: 1,
+            state: json!({
+                "id": "fixture-doc",
+                "fields": {
+                    "title": {
+                        "value": "Grüße",
+                        "timestamp": {"clock": 3, "client_id": "bob"}
+                    },
+                    "tags": {
+                        "value": ["a", "ü", null],
+                        "timestamp": {"clock": 1, "client_id": "alice"}
+                    },
+                    "big": {
+                        "value": u64::MAX,
+                        "timestamp": {"clock": u64::MAX, "client_id": "zoë-🦀"}
+                    }
+                },
+                "version": {"clocks": {"alice": 1, "bob": 3, "zoë-🦀": u64::MAX}}
+            }),
+        },
+    )]
+}
+
+fn save<T: Serialize>(kind: StateKind, value: &T) -> VersionedState {
+    VersionedState::new(kind, value).expect("fixture states serialize")
+}
+
+/// A value long enough to go to a document's shared `values` table
+const SHARED: &str = "Ein längerer Text, der in zwei Feldern steht: einmal gespeichert 📦";
+
+/// Three replicas editing one document: plain, multi-value and ref
+/// fields, a list with removed items, a lock and annotations
+fn document() -> Document {
+    let time = MockTime::new(1_700_000_000_000);
+    let mut alice = Document::new("fixture-doc".to_string());
+    alice.set_time_provider(time.clone());
+    let mut bob = alice.clone();
+    let mut zoe = alice.clone();
+    let (a, b, z) = ("alice".to_string(), "bob".to_string(), "zoë-🦀".to_string());
+
+    alice.set_field("title".to_string(), json!("Grüße aus Zürich"), 1, a.clone());
+    alice.set_field("body".to_string(), json!("שלום עולם · 你好"), 2, a.clone());
+    alice.set_field("summary".to_string(), json!(SHARED), 3, a.clone());
+    alice.set_field("draft".to_string(), json!(true), 3, a.clone());
+    alice.delete_field(&"draft".to_string());
+    alice
+        .annotate(a.clone(), 1..=2, json!({"reason": "import"}))
+        .unwrap();
+    alice.acquire_lock(
+        &"title".to_string(),
+        "alice",
+        std::time::Duration::from_secs(30),
+    );
+    alice.version.update(&a, 3);
+
+    bob.set_field("title".to_string(), json!("Hello"), 1, b.clone());
+    bob.set_field(
+        "meta".to_string(),
+        json!({"tags": ["a", null], "score": 1.5, "big": u64::MAX}),
+        2,
+        b.clone(),
+    );
+    bob.set_field("summary_copy".to_string(), json!(SHARED), 3, b.clone());
+    bob.set_multi_field("address".to_string(), json!("12 Main St"), 4, b.clone());
+    bob.set_ref("owner".to_string(), "user-1".to_string(), 5, b.clone());
+    bob.version.update(&b, 5);
+
+    zoe.set_multi_field("address".to_string(), json!("4 High St"), 1, z.clone());
+    zoe.set_ref("owner".to_string(), "user-2".to_string(), 6, z.clone());
+    zoe.clear_ref("owner".to_string(), 7, z.clone());
+    {
+        let mut items = zoe.list_mut("items".to_string(), z.clone());
+        items.push(json!("one"));
+        items.push(json!({"n": 2}));
+        items.push(json!("three ☃"));
+        items.push(json!(4));
+        items.remove(1).unwrap();
+        items.move_item(2, 0).unwrap();
+    }
+    zoe.version.update(&z, 7);
+
+    alice.merge(&bob);
+    alice.merge(&zoe);
+    alice
+}
+
+/// Writes by replicas whose clocks have run to the top of the range
+fn document_large_clocks() -> Document {
+    let mut doc = Document::new("fixture-clocks".to_string());
+    let (a, b) = ("a".to_string(), "b".to_string());
+    doc.set_field("x".to_string(), json!(1), u64::MAX - 1, a.clone());
+    doc.set_field("x".to_string(), json!(2), u64::MAX - 1, b.clone());
+    doc.set_field("y".to_string(), json!("top"), u64::MAX, a.clone());
+    doc.version.update(&a, u64::MAX);
+    doc.version.update(&b, u64::MAX - 1);
+    doc
+}
+
+fn vector_clock() -> VectorClock {
+    let mut clock = VectorClock::new();
+    for (client, value) in [
+        ("alice", 1),
+        ("bob", 42),
+        ("zoë-🦀", 7),
+        ("max", u64::MAX),
+        ("near-max", u64::MAX - 1),
+    ] {
+        clock.update(client, value);
+    }
+    clock
+}
+
+fn lww_field() -> LWWField {
+    let older = LWWField::new(json!("old"), Timestamp::new(3, "bob".to_string()));
+    let newer = LWWField::new(
+        json!({"emoji": "👩‍👩‍👧", "nested": [1, [2, {"deep": null}]]}),
+        Timestamp::new(u64::MAX - 7, "zoë".to_string()),
+    );
+    older.merge(&newer)
+}
+
+fn mv_register() -> MVRegister {
+    let mut alice = MVRegister::new();
+    let mut bob = MVRegister::new();
+    alice.write(json!("12 Main St"), Timestamp::new(1, "alice".to_string()));
+    alice.write(json!("13 Main St"), Timestamp::new(2, "alice".to_string()));
+    bob.write(json!("4 High St ✉"), Timestamp::new(1, "bob".to_string()));
+    alice.merge(&bob);
+    alice
+}
+
+fn list() -> List {
+    let mut alice = Document::new("fixture-list".to_string());
+    let mut bob = alice.clone();
+    let path = "items".to_string();
+    {
+        let mut items = alice.list_mut(path.clone(), "alice".to_string());
+        items.push(json!("α"));
+        items.push(json!("β"));
+        items.push(json!("γ"));
+    }
+    bob.merge(&alice);
+    {
+        let mut items = bob.list_mut(path.clone(), "bob".to_string());
+        items.remove(0).unwrap();
+        items.insert(1, json!("bob's 🥐")).unwrap();
+    }
+    {
+        let mut items = alice.list_mut(path.clone(), "alice".to_string());
+        items.move_item(2, 0).unwrap();
+        items.set(1, json!("B")).unwrap();
+    }
+    alice.merge(&bob);
+    alice.list(&path).expect("list was written").clone()
+}
+
+#[cfg(feature = "counters")]
+fn pn_counter() -> PNCounter {
+    let mut alice = PNCounter::new("alice".to_string());
+    let mut bob = PNCounter::new("bob".to_string());
+    let mut zoe = PNCounter::new("zoë".to_string());
+    alice.increment(10);
+    alice.decrement(3);
+    bob.increment(i64::MAX / 2);
+    zoe.decrement(1_000);
+    alice.merge(&bob);
+    alice.merge(&zoe);
+    alice
+}
+
+#[cfg(feature = "sets")]
+fn or_set() -> ORSet<String> {
+    let mut alice = ORSet::new("alice".to_string());
+    let mut bob = ORSet::new("bob".to_string());
+    alice.add_at("apple".to_string(), 1_000);
+    alice.add_at("Äpfel".to_string(), 1_001);
+    alice.add_at("🍐".to_string(), 1_002);
+    bob.merge(&alice);
+    // Concurrent remove and re-add: the add wins
+    bob.remove(&"apple".to_string());
+    alice.add_at("apple".to_string(), 2_000);
+    bob.remove(&"🍐".to_string());
+    bob.add_at("梨".to_string(), 2_001);
+    alice.merge(&bob);
+    alice
+}
+
+#[cfg(feature = "text-crdt")]
+fn fugue_text() -> FugueText {
+    let mut alice = FugueText::new("alice".to_string());
+    alice.insert(0, "Hello wörld").unwrap();
+    let mut bob = FugueText::new("bob".to_string());
+    bob.merge(&alice).unwrap();
+
+    alice.insert(5, ", 👋🏽").unwrap();
+    alice.delete(0, 1).unwrap();
+    alice.insert(0, "h").unwrap();
+    bob.insert(11, " — e\u{301}t 日本語").unwrap();
+    bob.delete(6, 1).unwrap();
+    alice.merge(&bob).unwrap();
+    alice
+        .add_mark(0..5, "bold", json!(true), MarkExpand::After)
+        .unwrap();
+    alice.remove_mark(2..4, "bold").unwrap();
+    alice
+}
+
+#[cfg(feature = "fractional-index")]
+fn fractional_index() -> Vec<FractionalIndex> {
+    let first = FractionalIndex::first();
+    let last = FractionalIndex::last();
+    let middle = FractionalIndex::between(&first, &last);
+    let early = FractionalIndex::between(&first, &middle);
+    let late = FractionalIndex::after(&middle);
+    vec![first, early, middle, late, last]
+}
@@ -0,0 +1,53 @@
+//! Write the state fixture corpus to `tests/fixtures/state/`
+//!
+//! ```text
+//! cargo run --bin gen-fixtures --features advanced,text [-- --force]
+//! ```
+//!
+//! Writes every fixture of the current format that doesn't exist yet, and
+//! with `--force` rewrites the existing ones too. Fixtures of earlier
+//! formats are only ever added, never rewritten: they stand for what older
+//! releases saved, and `tests/fixture_compat.rs` checks they still load.
+
+#[macro_use]
+mod corpus;
+
+use corpus::{Fixture, FIXTURE_DIR};
+use std::path::Path;
+use std::process::ExitCode;
+use synckit_core::migrate::FORMAT_VERSION;
+
+fn main() -> ExitCode {
+    let force = match std::env::args().nth(1).as_deref() {
+        None => false,
+        Some("--force") => true,
+        Some(other) => {
+            eprintln!(
+                "unknown argument {:?}; usage: gen-fixtures [--force]",
+                other
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let dir = Path::new(FIXTURE_DIR);
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        eprintln!("can't create {}: {}", dir.display(), e);
+        return ExitCode::FAILURE;
+    }
+    let states = corpus::build().into_iter().chain(corpus::legacy());
+    for (name, saved) in states {
+        let path = dir.join(Fixture::file_name(name, saved.format));
+        let rewrite = force && saved.format == FORMAT_VERSION;
+        if path.exists() && !rewrite {
+            println!("kept    {}", path.display());
+            continue;
+        }
+        if let Err(e) = std::fs::write(&path, Fixture::of(saved).to_bytes()) {
+            eprintln!("can't write {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+        println!("wrote   {}", path.display());
+    }
+    ExitCode::SUCCESS
+}
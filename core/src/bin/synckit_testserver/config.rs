@@ -0,0 +1,113 @@
+//! Command-line flags
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+usage: synckit-testserver [flags]
+
+  --listen ADDR            WebSocket sync endpoint (default 127.0.0.1:8080)
+  --http ADDR              introspection endpoint (default 127.0.0.1:8081)
+  --latency DURATION       delay added to each message, both ways (e.g. 50ms)
+  --jitter DURATION        up to this much more delay, at random
+  --drop-rate RATE         share of messages dropped, both ways (0 to 1)
+  --disconnect-every DURATION
+                           close every connection this long after it opened
+  --scenario FILE          scripted server actions (see the binary's docs)
+  --auth-token TOKEN       accept only this token; any client otherwise
+  --seed N                 seed for jitter and drops (default: from the clock)
+
+Port 0 picks a free port. The first line printed is the bound addresses as
+JSON: {\"ws\":\"ws://...\",\"http\":\"http://...\"}";
+
+/// How the server is set up and how it impairs the link
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub listen: SocketAddr,
+    pub http: SocketAddr,
+    pub latency: Duration,
+    pub jitter: Duration,
+    pub drop_rate: f64,
+    pub disconnect_every: Option<Duration>,
+    pub scenario: Option<PathBuf>,
+    pub auth_token: Option<String>,
+    pub seed: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            http: SocketAddr::from(([127, 0, 0, 1], 8081)),
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            drop_rate: 0.0,
+            disconnect_every: None,
+            scenario: None,
+            auth_token: None,
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    /// Parse the flags after the program name
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Config::default();
+        while let Some(flag) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", flag));
+            match flag.as_str() {
+                "--listen" => config.listen = parse_addr(&value()?)?,
+                "--http" => config.http = parse_addr(&value()?)?,
+                "--latency" => config.latency = parse_duration(&value()?)?,
+                "--jitter" => config.jitter = parse_duration(&value()?)?,
+                "--drop-rate" => {
+                    let value = value()?;
+                    config.drop_rate = value
+                        .parse()
+                        .ok()
+                        .filter(|rate| (0.0..=1.0).contains(rate))
+                        .ok_or_else(|| format!("drop rate {:?} isn't between 0 and 1", value))?;
+                }
+                "--disconnect-every" => {
+                    let every = parse_duration(&value()?)?;
+                    if every.is_zero() {
+                        return Err("--disconnect-every must be above zero".to_string());
+                    }
+                    config.disconnect_every = Some(every);
+                }
+                "--scenario" => config.scenario = Some(PathBuf::from(value()?)),
+                "--auth-token" => config.auth_token = Some(value()?),
+                "--seed" => {
+                    let value = value()?;
+                    let seed = value
+                        .parse()
+                        .map_err(|_| format!("seed {:?} isn't a number", value))?;
+                    config.seed = Some(seed);
+                }
+                other => return Err(format!("unknown flag {:?}", other)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse_addr(value: &str) -> Result<SocketAddr, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{:?} isn't an address like 127.0.0.1:8080", value))
+}
+
+/// Parse `250ms`, `2s` or `1.5s`
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("{:?} isn't a duration like 250ms or 2s", value);
+    if let Some(ms) = value.strip_suffix("ms") {
+        return ms.parse().map(Duration::from_millis).map_err(|_| invalid());
+    }
+    let seconds: f64 = value
+        .strip_suffix('s')
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(invalid)?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
@@ -0,0 +1,131 @@
+//! One WebSocket client: reading, writing and impairing its messages
+//!
+//! Each message waits the configured latency plus jitter before it is
+//! handled (client to server) or sent (server to client), in order, like
+//! over a slow TCP link; each may be dropped instead.
+
+use crate::state::{Metrics, Outgoing, Server};
+use crate::wire::{self, Message};
+use futures_util::{SinkExt, StreamExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, sleep_until, Instant};
+use tokio_tungstenite::tungstenite::Message as Frame;
+
+/// Serve one client until it or the server closes the connection
+pub async fn serve(server: Arc<Server>, stream: TcpStream) {
+    let socket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("synckit-testserver: WebSocket handshake failed: {}", e);
+            return;
+        }
+    };
+    let (mut sink, mut frames) = socket.split();
+    let (outbound, mut outgoing) = mpsc::unbounded_channel();
+    let id = server.connect(outbound.clone());
+    // Reply in the format the client last used
+    let binary = Arc::new(AtomicBool::new(true));
+
+    let writer = {
+        let server = server.clone();
+        let binary = binary.clone();
+        tokio::spawn(async move {
+            let mut due = Instant::now();
+            while let Some(next) = outgoing.recv().await {
+                due = due.max(Instant::now() + server.delay());
+                sleep_until(due).await;
+                let message = match next {
+                    Outgoing::Message(message) => message,
+                    Outgoing::Close => break,
+                };
+                if server.should_drop() {
+                    Metrics::bump(&server.metrics.dropped_outgoing, 1);
+                    continue;
+                }
+                let frame = match binary.load(Ordering::Relaxed) {
+                    true => Frame::Binary(wire::encode_binary(&message).into()),
+                    false => Frame::Text(wire::encode_text(&message).into()),
+                };
+                Metrics::bump(&server.metrics.messages_sent, 1);
+                Metrics::bump(&server.metrics.bytes_sent, frame.len() as u64);
+                if sink.send(frame).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        })
+    };
+
+    let (inbound, mut incoming) = mpsc::unbounded_channel::<(Instant, Message)>();
+    let handler = {
+        let server = server.clone();
+        tokio::spawn(async move {
+            while let Some((due, message)) = incoming.recv().await {
+                sleep_until(due).await;
+                server.handle(id, message);
+            }
+        })
+    };
+
+    let forced_disconnect = async {
+        match server.config.disconnect_every {
+            Some(every) => sleep(every).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(forced_disconnect);
+    let mut due = Instant::now();
+    loop {
+        let frame = tokio::select! {
+            frame = frames.next() => frame,
+            _ = &mut forced_disconnect => {
+                Metrics::bump(&server.metrics.forced_disconnects, 1);
+                break;
+            }
+        };
+        let frame = match frame {
+            Some(Ok(frame)) => frame,
+            _ => break,
+        };
+        let decoded = match &frame {
+            Frame::Binary(bytes) => {
+                binary.store(true, Ordering::Relaxed);
+                wire::decode_binary(bytes)
+            }
+            Frame::Text(text) => {
+                binary.store(false, Ordering::Relaxed);
+                wire::decode_text(text)
+            }
+            Frame::Close(_) => break,
+            _ => continue,
+        };
+        Metrics::bump(&server.metrics.messages_received, 1);
+        Metrics::bump(&server.metrics.bytes_received, frame.len() as u64);
+        let message = match decoded {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("synckit-testserver: connection {}: {}", id, e);
+                continue;
+            }
+        };
+        if server.should_drop() {
+            Metrics::bump(&server.metrics.dropped_incoming, 1);
+            continue;
+        }
+        due = due.max(Instant::now() + server.delay());
+        if inbound.send((due, message)).is_err() {
+            break;
+        }
+    }
+
+    // Messages already received are still handled, then the writer
+    // flushes what is queued and closes
+    drop(inbound);
+    let _ = handler.await;
+    server.disconnect(id);
+    let _ = outbound.send(Outgoing::Close);
+    let _ = writer.await;
+}
@@ -0,0 +1,66 @@
+//! The introspection endpoint
+//!
+//! `GET /state` returns every document's checksum, clock and fields, the
+//! workspace checksum, and the server's counters:
+//!
+//! ```json
+//! {
+//!   "documents": {"doc-1": {"checksum": "…", "version": {…}, "fields": {…}}},
+//!   "workspace_checksum": "…",
+//!   "metrics": {"connections_open": 1, "dropped_outgoing": 0, …}
+//! }
+//! ```
+//!
+//! Checksums are 16 hex digits; two servers holding the same documents
+//! report the same ones. `GET /health` returns `ok`.
+
+use crate::state::Server;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// Answer one request and close the connection
+pub async fn serve(server: Arc<Server>, stream: TcpStream) {
+    let mut stream = BufReader::new(stream);
+    let mut request_line = String::new();
+    if stream.read_line(&mut request_line).await.is_err() {
+        return;
+    }
+    // Headers are read and ignored; nothing here takes a body
+    let mut header = String::new();
+    loop {
+        header.clear();
+        match stream.read_line(&mut header).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) if header.trim().is_empty() => break,
+            Ok(_) => {}
+        }
+    }
+
+    let mut words = request_line.split_whitespace();
+    let (status, content_type, body) = match (words.next(), words.next()) {
+        (Some("GET"), Some("/state")) => (
+            "200 OK",
+            "application/json",
+            server.introspect().to_string(),
+        ),
+        (Some("GET"), Some("/health")) => ("200 OK", "text/plain", "ok".to_string()),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\n\
+         access-control-allow-origin: *\r\nconnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    let stream = stream.get_mut();
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
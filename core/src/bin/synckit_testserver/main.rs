@@ -0,0 +1,119 @@
+//! synckit-testserver: a sync server with a bad network, for local
+//! development and SDK integration tests
+//!
+//! Serves the WebSocket sync protocol the SDK speaks (auth, subscribe,
+//! sync_request, delta, ping) over a [`Workspace`], with last-writer-wins
+//! fields as the TypeScript server keeps them, and makes the link as bad
+//! as asked:
+//!
+//! ```text
+//! synckit-testserver --listen 127.0.0.1:0 --http 127.0.0.1:0 \
+//!     --latency 80ms --jitter 40ms --drop-rate 0.05 \
+//!     --disconnect-every 10s --scenario edits.txt --seed 7
+//! ```
+//!
+//! The first line on stdout is where it listens, as JSON, so a test can
+//! start it on free ports and read them back:
+//!
+//! ```json
+//! {"ws":"ws://127.0.0.1:41234","http":"http://127.0.0.1:41235"}
+//! ```
+//!
+//! The HTTP endpoint reports document checksums and counters (see
+//! [`http`]); scenario scripts make server-side writes and disconnects on
+//! a schedule (see [`scenario`]). `tests/integration/helpers/
+//! rust-test-server.ts` starts it from the JS test suite.
+//!
+//! [`Workspace`]: synckit_core::server::Workspace
+
+mod config;
+mod connection;
+mod http;
+mod scenario;
+mod state;
+mod wire;
+
+use config::{Config, USAGE};
+use serde_json::json;
+use state::Server;
+use std::io::Write;
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let config = match Config::parse(args.into_iter()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("synckit-testserver: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let steps = match &config.scenario {
+        Some(path) => match std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|script| scenario::parse(&script))
+        {
+            Ok(steps) => steps,
+            Err(e) => {
+                eprintln!("synckit-testserver: {}: {}", path.display(), e);
+                return ExitCode::from(2);
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let (ws, http) = match (
+        TcpListener::bind(config.listen).await,
+        TcpListener::bind(config.http).await,
+    ) {
+        (Ok(ws), Ok(http)) => (ws, http),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("synckit-testserver: can't listen: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let (Ok(ws_addr), Ok(http_addr)) = (ws.local_addr(), http.local_addr()) else {
+        eprintln!("synckit-testserver: can't read the bound addresses");
+        return ExitCode::FAILURE;
+    };
+    let mut stdout = std::io::stdout();
+    let _ = writeln!(
+        stdout,
+        "{}",
+        json!({
+            "ws": format!("ws://{}", ws_addr),
+            "http": format!("http://{}", http_addr),
+        })
+    );
+    let _ = stdout.flush();
+
+    let server = Arc::new(Server::new(config));
+    tokio::spawn({
+        let server = server.clone();
+        async move { scenario::run(&server, steps).await }
+    });
+    tokio::spawn({
+        let server = server.clone();
+        async move {
+            while let Ok((stream, _)) = http.accept().await {
+                tokio::spawn(http::serve(server.clone(), stream));
+            }
+        }
+    });
+    loop {
+        match ws.accept().await {
+            Ok((stream, _)) => {
+                let _ = stream.set_nodelay(true);
+                tokio::spawn(connection::serve(server.clone(), stream));
+            }
+            Err(e) => eprintln!("synckit-testserver: accept failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Scripted server actions
+//!
+//! One step per line, timed from when the first client connects:
+//!
+//! ```text
+//! # comments and blank lines are skipped
+//! at 500ms write doc-1 title "Hello"
+//! at 1s    write doc-1 meta {"tags": ["a", "b"]}
+//! at 2s    delete doc-1 title
+//! at 3s    disconnect
+//! ```
+//!
+//! `write` sets a field to a JSON value and `delete` deletes it, both as
+//! client `scenario` at the current time, broadcast to subscribers like a
+//! client's delta. `disconnect` closes every connection.
+
+use crate::config::parse_duration;
+use crate::state::{now_ms, Metrics, Server};
+use serde_json::{json, Map, Value as JsonValue};
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+
+/// Client ID scenario writes are made as
+const CLIENT_ID: &str = "scenario";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Write {
+        document_id: String,
+        field: String,
+        value: JsonValue,
+    },
+    Delete {
+        document_id: String,
+        field: String,
+    },
+    Disconnect,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub at: Duration,
+    pub action: Action,
+}
+
+/// Parse a scenario script; steps must be in time order
+pub fn parse(script: &str) -> Result<Vec<Step>, String> {
+    let mut steps: Vec<Step> = Vec::new();
+    for (index, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let step = parse_step(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        if steps.last().is_some_and(|last| last.at > step.at) {
+            return Err(format!("line {}: steps must be in time order", index + 1));
+        }
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+fn parse_step(line: &str) -> Result<Step, String> {
+    let mut words = Words(line);
+    if words.next() != Some("at") {
+        return Err("expected `at DURATION ACTION`".to_string());
+    }
+    let at = parse_duration(words.next().ok_or("missing time")?)?;
+    let action = match words.next() {
+        Some("write") => {
+            let document_id = words.next().ok_or("missing document ID")?.to_string();
+            let field = words.next().ok_or("missing field")?.to_string();
+            let value = serde_json::from_str(words.rest())
+                .map_err(|e| format!("value isn't JSON: {}", e))?;
+            return Ok(Step {
+                at,
+                action: Action::Write {
+                    document_id,
+                    field,
+                    value,
+                },
+            });
+        }
+        Some("delete") => Action::Delete {
+            document_id: words.next().ok_or("missing document ID")?.to_string(),
+            field: words.next().ok_or("missing field")?.to_string(),
+        },
+        Some("disconnect") => Action::Disconnect,
+        Some(other) => return Err(format!("unknown action {:?}", other)),
+        None => return Err("missing action".to_string()),
+    };
+    match words.rest() {
+        "" => Ok(Step { at, action }),
+        extra => Err(format!("unexpected {:?}", extra)),
+    }
+}
+
+/// Whitespace-separated words, keeping what's left for a JSON value
+struct Words<'a>(&'a str);
+
+impl<'a> Words<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.0.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        self.0 = &rest[end..];
+        Some(&rest[..end])
+    }
+
+    fn rest(&self) -> &'a str {
+        self.0.trim()
+    }
+}
+
+/// Run the steps, starting the clock when the first client connects
+pub async fn run(server: &Server, steps: Vec<Step>) {
+    server.first_connection().await;
+    let start = Instant::now();
+    for step in steps {
+        sleep_until(start + step.at).await;
+        Metrics::bump(&server.metrics.scenario_steps, 1);
+        let (document_id, field, value) = match step.action {
+            Action::Disconnect => {
+                server.disconnect_all();
+                continue;
+            }
+            Action::Write {
+                document_id,
+                field,
+                value,
+            } => (document_id, field, value),
+            Action::Delete { document_id, field } => {
+                (document_id, field, json!({"__deleted": true}))
+            }
+        };
+        let delta = Map::from_iter([(field, value)]);
+        let applied = server.apply(&document_id, delta, now_ms(), CLIENT_ID, None);
+        server.broadcast(&document_id, applied);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let steps = parse(
+            "# setup\n\
+             at 500ms write doc-1 meta {\"tags\": [\"a b\"]}\n\
+             \n\
+             at 2s delete doc-1 title\n\
+             at 2s disconnect\n",
+        )
+        .unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                Step {
+                    at: Duration::from_millis(500),
+                    action: Action::Write {
+                        document_id: "doc-1".to_string(),
+                        field: "meta".to_string(),
+                        value: json!({"tags": ["a b"]}),
+                    },
+                },
+                Step {
+                    at: Duration::from_secs(2),
+                    action: Action::Delete {
+                        document_id: "doc-1".to_string(),
+                        field: "title".to_string(),
+                    },
+                },
+                Step {
+                    at: Duration::from_secs(2),
+                    action: Action::Disconnect,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let err = parse("at 1s disconnect\nat 2s write doc-1 title nope").unwrap_err();
+        assert!(err.starts_with("line 2: value isn't JSON"), "{}", err);
+        let err = parse("at 2s disconnect\nat 1s disconnect").unwrap_err();
+        assert_eq!(err, "line 2: steps must be in time order");
+        assert!(parse("at 1s disconnect now").is_err());
+        assert!(parse("in 1s disconnect").is_err());
+    }
+}
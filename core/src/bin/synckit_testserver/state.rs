@@ -0,0 +1,508 @@
+//! The server's documents, connections and counters, and what it does with
+//! each message
+
+use crate::config::Config;
+use crate::wire::{message_type, Message};
+use serde::Serialize;
+use serde_json::{json, Map, Value as JsonValue};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use synckit_core::server::Workspace;
+use synckit_core::{Document, DocumentID, VectorClock};
+use tokio::sync::{mpsc, Notify};
+
+/// Marker value of a deleted field in deltas
+const DELETED: &str = "__deleted";
+
+/// What a connection's writer sends next
+#[derive(Debug)]
+pub enum Outgoing {
+    Message(Message),
+    Close,
+}
+
+/// A client connected to the server
+struct Connection {
+    outbound: mpsc::UnboundedSender<Outgoing>,
+    authenticated: bool,
+    client_id: Option<String>,
+    subscriptions: HashSet<DocumentID>,
+}
+
+/// Counters reported by the introspection endpoint
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub connections_opened: AtomicU64,
+    pub forced_disconnects: AtomicU64,
+    pub messages_received: AtomicU64,
+    pub messages_sent: AtomicU64,
+    pub bytes_received: AtomicU64,
+    pub bytes_sent: AtomicU64,
+    pub dropped_incoming: AtomicU64,
+    pub dropped_outgoing: AtomicU64,
+    pub deltas_applied: AtomicU64,
+    pub errors_sent: AtomicU64,
+    pub scenario_steps: AtomicU64,
+}
+
+impl Metrics {
+    pub fn bump(counter: &AtomicU64, by: u64) {
+        counter.fetch_add(by, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, connections_open: usize) -> JsonValue {
+        let read = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        json!({
+            "connections_open": connections_open,
+            "connections_opened": read(&self.connections_opened),
+            "forced_disconnects": read(&self.forced_disconnects),
+            "messages_received": read(&self.messages_received),
+            "messages_sent": read(&self.messages_sent),
+            "bytes_received": read(&self.bytes_received),
+            "bytes_sent": read(&self.bytes_sent),
+            "dropped_incoming": read(&self.dropped_incoming),
+            "dropped_outgoing": read(&self.dropped_outgoing),
+            "deltas_applied": read(&self.deltas_applied),
+            "errors_sent": read(&self.errors_sent),
+            "scenario_steps": read(&self.scenario_steps),
+        })
+    }
+}
+
+/// One document's state as reported by the introspection endpoint
+#[derive(Serialize)]
+struct DocumentState {
+    /// `Document::content_hash`, as 16 hex digits
+    checksum: String,
+    /// The clock clients reported for it, `{client: counter}`
+    version: JsonValue,
+    fields: JsonValue,
+}
+
+/// Xorshift64*: enough randomness for jitter and drops, and replayable
+/// from `--seed`
+struct Rng(u64);
+
+impl Rng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let value = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// State shared by every connection
+pub struct Server {
+    pub config: Config,
+    pub metrics: Metrics,
+    workspace: Workspace,
+    /// Each document's vector clock as clients report it, as the
+    /// TypeScript server keeps it
+    clocks: Mutex<HashMap<DocumentID, VectorClock>>,
+    connections: Mutex<HashMap<u64, Connection>>,
+    next_connection: AtomicU64,
+    connected: Notify,
+    rng: Mutex<Rng>,
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl Server {
+    pub fn new(config: Config) -> Self {
+        let seed = config.seed.unwrap_or_else(now_ms);
+        Self {
+            config,
+            metrics: Metrics::default(),
+            workspace: Workspace::new(),
+            clocks: Mutex::new(HashMap::new()),
+            connections: Mutex::new(HashMap::new()),
+            next_connection: AtomicU64::new(1),
+            connected: Notify::new(),
+            // Xorshift can't start from zero
+            rng: Mutex::new(Rng(seed | 1)),
+        }
+    }
+
+    fn connections(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Connection>> {
+        self.connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Merge `update` into the document's clock, returning the result
+    fn merge_clock(&self, document_id: &str, update: Option<&VectorClock>) -> VectorClock {
+        let mut clocks = self.clocks.lock().unwrap_or_else(PoisonError::into_inner);
+        let clock = clocks.entry(document_id.to_string()).or_default();
+        if let Some(update) = update {
+            clock.merge(update);
+        }
+        clock.clone()
+    }
+
+    /// Whether to drop the next message
+    pub fn should_drop(&self) -> bool {
+        let rate = self.config.drop_rate;
+        rate > 0.0 && self.random() < rate
+    }
+
+    /// Latency plus a random share of the jitter
+    pub fn delay(&self) -> Duration {
+        let jitter = self.config.jitter;
+        if jitter.is_zero() {
+            return self.config.latency;
+        }
+        self.config.latency + jitter.mul_f64(self.random())
+    }
+
+    /// A number in `[0, 1)`
+    fn random(&self) -> f64 {
+        self.rng
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .next_f64()
+    }
+
+    /// Register a connection whose writer reads from `outbound`
+    pub fn connect(&self, outbound: mpsc::UnboundedSender<Outgoing>) -> u64 {
+        let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        let connection = Connection {
+            outbound,
+            authenticated: false,
+            client_id: None,
+            subscriptions: HashSet::new(),
+        };
+        self.connections().insert(id, connection);
+        Metrics::bump(&self.metrics.connections_opened, 1);
+        self.connected.notify_one();
+        id
+    }
+
+    /// Wait until a client has connected
+    pub async fn first_connection(&self) {
+        if self.metrics.connections_opened.load(Ordering::Relaxed) == 0 {
+            self.connected.notified().await;
+        }
+    }
+
+    pub fn disconnect(&self, id: u64) {
+        self.connections().remove(&id);
+    }
+
+    /// Close every connection
+    pub fn disconnect_all(&self) {
+        for connection in self.connections().values() {
+            let _ = connection.outbound.send(Outgoing::Close);
+        }
+    }
+
+    fn send(&self, id: u64, message: Message) {
+        if let Some(connection) = self.connections().get(&id) {
+            // A closed writer means the connection is going away anyway
+            let _ = connection.outbound.send(Outgoing::Message(message));
+        }
+    }
+
+    fn send_error(&self, id: u64, error: &str, details: JsonValue) {
+        Metrics::bump(&self.metrics.errors_sent, 1);
+        self.send(
+            id,
+            reply("error", json!({"error": error, "details": details})),
+        );
+    }
+
+    /// Handle a message from connection `id`
+    pub fn handle(&self, id: u64, message: Message) {
+        match message_type(&message) {
+            "auth" => self.auth(id, &message),
+            "connect" => {
+                let client_id = message.get("clientId").and_then(JsonValue::as_str);
+                if let Some(connection) = self.connections().get_mut(&id) {
+                    connection.client_id = client_id.map(str::to_string);
+                }
+            }
+            "ping" => self.send(id, reply("pong", json!({}))),
+            "pong" | "ack" => {}
+            kind @ ("subscribe" | "sync_request" | "unsubscribe" | "delta") => {
+                if !self.is_authenticated(id) {
+                    self.send_error(id, "Not authenticated", JsonValue::Null);
+                    return;
+                }
+                let Some(document_id) = message.get("documentId").and_then(JsonValue::as_str)
+                else {
+                    self.send_error(id, "Missing documentId", JsonValue::Null);
+                    return;
+                };
+                match kind {
+                    "unsubscribe" => {
+                        if let Some(connection) = self.connections().get_mut(&id) {
+                            connection.subscriptions.remove(document_id);
+                        }
+                    }
+                    "delta" => self.delta(id, document_id, &message),
+                    _ => self.subscribe(id, document_id, &message),
+                }
+            }
+            other => self.send_error(id, "Unsupported message type", json!({"type": other})),
+        }
+    }
+
+    fn is_authenticated(&self, id: u64) -> bool {
+        self.connections()
+            .get(&id)
+            .is_some_and(|connection| connection.authenticated)
+    }
+
+    fn auth(&self, id: u64, message: &Message) {
+        let token = message.get("token").and_then(JsonValue::as_str);
+        let user_id = match (&self.config.auth_token, token) {
+            (None, None) => "anonymous",
+            (None, Some(_)) => "test-user",
+            (Some(expected), Some(token)) if expected == token => "test-user",
+            (Some(_), _) => {
+                self.send(
+                    id,
+                    reply("auth_error", json!({"error": "Authentication failed"})),
+                );
+                self.send_close(id);
+                return;
+            }
+        };
+        if let Some(connection) = self.connections().get_mut(&id) {
+            connection.authenticated = true;
+        }
+        self.send(
+            id,
+            reply(
+                "auth_success",
+                json!({
+                    "userId": user_id,
+                    "permissions": {"canRead": ["*"], "canWrite": ["*"], "isAdmin": false},
+                }),
+            ),
+        );
+    }
+
+    fn send_close(&self, id: u64) {
+        if let Some(connection) = self.connections().get(&id) {
+            let _ = connection.outbound.send(Outgoing::Close);
+        }
+    }
+
+    /// Subscribe and send the document's state
+    fn subscribe(&self, id: u64, document_id: &str, message: &Message) {
+        if let Some(connection) = self.connections().get_mut(&id) {
+            connection.subscriptions.insert(document_id.to_string());
+        }
+        let state = self.workspace.get_or_create(document_id).to_json();
+        let update = message
+            .get("vectorClock")
+            .or_else(|| message.get("clock"))
+            .and_then(vector_clock);
+        let clock = self.merge_clock(document_id, update.as_ref());
+        self.send(
+            id,
+            reply(
+                "sync_response",
+                json!({
+                    "requestId": message.get("id").cloned().unwrap_or_default(),
+                    "documentId": document_id,
+                    "state": state,
+                    "deltas": [],
+                    "clock": clock_json(&clock),
+                }),
+            ),
+        );
+    }
+
+    /// Apply a client's delta, acknowledge it and broadcast the result
+    fn delta(&self, id: u64, document_id: &str, message: &Message) {
+        let delta = match (message.get("delta"), message.get("field")) {
+            (Some(JsonValue::Object(delta)), _) if !delta.is_empty() => delta.clone(),
+            (_, Some(JsonValue::String(field))) => {
+                let value = message.get("value").cloned().unwrap_or_default();
+                Map::from_iter([(field.clone(), value)])
+            }
+            _ => {
+                self.send_error(
+                    id,
+                    "Invalid delta message: missing or empty delta",
+                    JsonValue::Null,
+                );
+                return;
+            }
+        };
+        let client_id = self
+            .connections()
+            .get_mut(&id)
+            .map(|connection| {
+                connection.subscriptions.insert(document_id.to_string());
+                connection
+                    .client_id
+                    .clone()
+                    .unwrap_or_else(|| format!("connection-{}", id))
+            })
+            .unwrap_or_default();
+        let clock = message
+            .get("timestamp")
+            .and_then(JsonValue::as_u64)
+            .unwrap_or_else(now_ms);
+        let version = message
+            .get("vectorClock")
+            .or_else(|| message.get("clock"))
+            .and_then(vector_clock);
+
+        let applied = self.apply(document_id, delta, clock, &client_id, version.as_ref());
+        let acked = message
+            .get("messageId")
+            .or_else(|| message.get("id"))
+            .cloned()
+            .unwrap_or_default();
+        self.send(id, reply("ack", json!({"messageId": acked})));
+        self.broadcast(document_id, applied);
+    }
+
+    /// Write a delta with LWW at `clock`, returning each field's value
+    /// after it (a deletion marker for deleted fields)
+    pub fn apply(
+        &self,
+        document_id: &str,
+        delta: Map<String, JsonValue>,
+        clock: u64,
+        client_id: &str,
+        version: Option<&VectorClock>,
+    ) -> Map<String, JsonValue> {
+        let document = self.workspace.get_or_create(document_id);
+        let applied = document.write(|document| {
+            let mut applied = Map::new();
+            for (field, value) in delta {
+                let outcome = if is_deletion(&value) {
+                    delete_field(document, &field, clock, client_id)
+                } else {
+                    document.set_field(field.clone(), value, clock, client_id.to_string());
+                    document.get_field(&field).cloned().unwrap_or_default()
+                };
+                applied.insert(field, outcome);
+            }
+            applied
+        });
+        self.merge_clock(document_id, version);
+        Metrics::bump(&self.metrics.deltas_applied, 1);
+        applied
+    }
+
+    /// Send each field of `applied` to the document's subscribers, sender
+    /// included, as the TypeScript server does
+    pub fn broadcast(&self, document_id: &str, applied: Map<String, JsonValue>) {
+        let clock = clock_json(&self.merge_clock(document_id, None));
+        let subscribers: Vec<u64> = self
+            .connections()
+            .iter()
+            .filter(|(_, connection)| {
+                connection.authenticated && connection.subscriptions.contains(document_id)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for (field, value) in applied {
+            for id in &subscribers {
+                self.send(
+                    *id,
+                    reply(
+                        "delta",
+                        json!({
+                            "documentId": document_id,
+                            "field": field,
+                            "value": value,
+                            "clock": clock,
+                            "clientId": "server",
+                        }),
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Documents, checksums and counters, for the introspection endpoint
+    pub fn introspect(&self) -> JsonValue {
+        let mut documents = Map::new();
+        for document in self.workspace.documents() {
+            let snapshot = document.read_snapshot();
+            let state = DocumentState {
+                checksum: format!("{:016x}", snapshot.content_hash()),
+                version: clock_json(&self.merge_clock(snapshot.id(), None)),
+                fields: snapshot.to_json(),
+            };
+            documents.insert(
+                snapshot.id().clone(),
+                serde_json::to_value(state).expect("document state serializes"),
+            );
+        }
+        let connections_open = self.connections().len();
+        json!({
+            "documents": documents,
+            "workspace_checksum": format!("{:016x}", self.workspace.checksum()),
+            "metrics": self.metrics.snapshot(connections_open),
+        })
+    }
+}
+
+/// A server message of type `kind` with `fields`
+fn reply(kind: &str, fields: JsonValue) -> Message {
+    let mut message = match fields {
+        JsonValue::Object(fields) => fields,
+        _ => Map::new(),
+    };
+    message.insert("type".to_string(), JsonValue::from(kind));
+    message.insert(
+        "id".to_string(),
+        JsonValue::from(format!("srv-{}", next_message_id())),
+    );
+    message.insert("timestamp".to_string(), JsonValue::from(now_ms()));
+    message
+}
+
+fn next_message_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+fn is_deletion(value: &JsonValue) -> bool {
+    value.get(DELETED) == Some(&JsonValue::Bool(true))
+}
+
+/// Delete `field` unless a later write holds it; the field's value after
+fn delete_field(document: &mut Document, field: &str, clock: u64, client_id: &str) -> JsonValue {
+    let field = field.to_string();
+    let later_write = document.fields().get(&field).filter(|existing| {
+        let existing = &existing.timestamp;
+        (existing.clock, existing.client_id.as_str()) > (clock, client_id)
+    });
+    match later_write {
+        Some(existing) => existing.value.clone(),
+        None => {
+            document.delete_field(&field);
+            json!({ DELETED: true })
+        }
+    }
+}
+
+/// A vector clock sent as `{client: counter}`
+fn vector_clock(value: &JsonValue) -> Option<VectorClock> {
+    let mut clock = VectorClock::new();
+    for (client, counter) in value.as_object()? {
+        clock.update(client, counter.as_u64()?);
+    }
+    Some(clock)
+}
+
+fn clock_json(clock: &VectorClock) -> JsonValue {
+    json!(clock.clocks())
+}
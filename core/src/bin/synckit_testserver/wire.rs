@@ -0,0 +1,119 @@
+//! The SDK's WebSocket wire format
+//!
+//! Binary frames carry a 13-byte header and a JSON payload:
+//!
+//! ```text
+//! type (u8) | timestamp ms (i64 BE) | payload length (u32 BE) | payload JSON
+//! ```
+//!
+//! Text frames (and binary frames starting with `{`) are the whole message
+//! as JSON, the legacy format. Messages are JSON objects with a `type`
+//! name either way; replies use the format the client last sent.
+
+use serde_json::{Map, Value as JsonValue};
+
+/// A message: a JSON object with `type`, `id` and `timestamp`
+pub type Message = Map<String, JsonValue>;
+
+/// Type names and their binary codes, as in the TypeScript server
+const TYPES: &[(&str, u8)] = &[
+    ("auth", 0x01),
+    ("auth_success", 0x02),
+    ("auth_error", 0x03),
+    ("subscribe", 0x10),
+    ("unsubscribe", 0x11),
+    ("sync_request", 0x12),
+    ("sync_response", 0x13),
+    ("sync_step1", 0x14),
+    ("sync_step2", 0x15),
+    ("delta", 0x20),
+    ("ack", 0x21),
+    ("delta_batch_chunk", 0x23),
+    ("ping", 0x30),
+    ("pong", 0x31),
+    ("awareness_update", 0x40),
+    ("awareness_subscribe", 0x41),
+    ("awareness_state", 0x42),
+    ("delta_batch", 0x50),
+    ("error", 0xff),
+];
+
+const HEADER_LEN: usize = 13;
+
+/// The message's `type`, or "" if it has none
+pub fn message_type(message: &Message) -> &str {
+    message
+        .get("type")
+        .and_then(JsonValue::as_str)
+        .unwrap_or_default()
+}
+
+/// Parse a text frame
+pub fn decode_text(text: &str) -> Result<Message, String> {
+    match serde_json::from_str(text) {
+        Ok(JsonValue::Object(message)) => Ok(message),
+        Ok(_) => Err("message isn't a JSON object".to_string()),
+        Err(e) => Err(format!("invalid JSON: {}", e)),
+    }
+}
+
+/// Parse a binary frame
+pub fn decode_binary(bytes: &[u8]) -> Result<Message, String> {
+    if bytes.first() == Some(&b'{') {
+        let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+        return decode_text(text);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(format!("frame of {} bytes is too short", bytes.len()));
+    }
+    let code = bytes[0];
+    let timestamp = i64::from_be_bytes(bytes[1..9].try_into().expect("8 bytes"));
+    let length = u32::from_be_bytes(bytes[9..13].try_into().expect("4 bytes")) as usize;
+    let payload = bytes
+        .get(HEADER_LEN..HEADER_LEN + length)
+        .ok_or_else(|| format!("payload length {} is past the frame", length))?;
+    let name = TYPES
+        .iter()
+        .find(|(_, c)| *c == code)
+        .map(|(name, _)| *name)
+        .ok_or_else(|| format!("unknown type code {:#04x}", code))?;
+
+    let mut message = match serde_json::from_slice(payload) {
+        Ok(JsonValue::Object(payload)) => payload,
+        Ok(_) => return Err("payload isn't a JSON object".to_string()),
+        Err(e) => return Err(format!("invalid payload JSON: {}", e)),
+    };
+    message.insert("type".to_string(), JsonValue::from(name));
+    message.insert("timestamp".to_string(), JsonValue::from(timestamp));
+    Ok(message)
+}
+
+/// Encode a message as a binary frame
+pub fn encode_binary(message: &Message) -> Vec<u8> {
+    let name = message_type(message);
+    let code = TYPES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, code)| *code)
+        .unwrap_or_else(|| panic!("server sent unknown message type {:?}", name));
+    let timestamp = message
+        .get("timestamp")
+        .and_then(JsonValue::as_i64)
+        .unwrap_or_default();
+    let mut payload = message.clone();
+    payload.remove("type");
+    payload.remove("timestamp");
+    let payload = serde_json::to_vec(&payload).expect("JSON objects serialize");
+
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.push(code);
+    frame.extend_from_slice(&timestamp.to_be_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// Encode a message as a text frame
+pub fn encode_text(message: &Message) -> String {
+    JsonValue::Object(message.clone()).to_string()
+}
@@ -0,0 +1,214 @@
+//! Serde support for compact, non-self-describing formats
+//!
+//! Every CRDT type serializes through serde, and the JSON output is the
+//! canonical storage format. Binary formats such as postcard and bincode
+//! don't describe their own structure, so a type can only be decoded if it
+//! never relies on `deserialize_any`. The types in this crate follow these
+//! rules:
+//!
+//! - JSON values stored in documents (`serde_json::Value`) go through
+//!   [`json_value`]: unchanged in human-readable formats, and as their JSON
+//!   text in binary ones.
+//! - No `skip_serializing_if`, `flatten` or untagged enums on persisted
+//!   types; `#[serde(skip)]` is only used for caches rebuilt on load.
+//! - `TextEvent` is internally tagged and stays JSON-only. It is a change
+//!   notification, not persisted state.
+//!
+//! With the `serde-compact` feature, [`to_postcard`] and [`from_postcard`]
+//! wrap postcard with `SyncKitError` handling.
+//!
+//! Protobuf messages carry free-form JSON (awareness state) as bytes in a
+//! [`ValueFormat`]: JSON text, or CBOR with the `cbor` feature.
+
+use serde_json::Value as JsonValue;
+
+/// `#[serde(with = "crate::codec::json_value")]` for `serde_json::Value`
+/// fields
+pub mod json_value {
+    use super::JsonValue;
+    use alloc::string::{String, ToString};
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &JsonValue, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serde::Serialize::serialize(value, serializer)
+        } else {
+            serializer.serialize_str(&value.to_string())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<JsonValue, D::Error> {
+        if deserializer.is_human_readable() {
+            JsonValue::deserialize(deserializer)
+        } else {
+            let text = String::deserialize(deserializer)?;
+            serde_json::from_str(&text).map_err(D::Error::custom)
+        }
+    }
+
+    /// Same strategy for `Option<serde_json::Value>`
+    pub mod option {
+        use super::JsonValue;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        struct Borrowed<'a>(&'a JsonValue);
+
+        impl Serialize for Borrowed<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                super::serialize(self.0, serializer)
+            }
+        }
+
+        #[derive(Deserialize)]
+        struct Owned(#[serde(with = "super")] JsonValue);
+
+        pub fn serialize<S: Serializer>(
+            value: &Option<JsonValue>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => serializer.serialize_some(&Borrowed(value)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<JsonValue>, D::Error> {
+            Ok(Option::<Owned>::deserialize(deserializer)?.map(|Owned(value)| value))
+        }
+    }
+}
+
+/// Encode a value with postcard
+#[cfg(feature = "serde-compact")]
+pub fn to_postcard<T: serde::Serialize + ?Sized>(value: &T) -> crate::Result<Vec<u8>> {
+    postcard::to_stdvec(value).map_err(crate::SyncKitError::serialization)
+}
+
+/// Decode a value encoded with [`to_postcard`]
+///
+/// # Errors
+///
+/// Returns `SyncError::DeserializationError` if the bytes are truncated or
+/// were produced for a different type
+#[cfg(feature = "serde-compact")]
+pub fn from_postcard<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> crate::Result<T> {
+    postcard::from_bytes(bytes).map_err(crate::SyncKitError::deserialization)
+}
+
+/// How a `serde_json::Value` is carried as bytes inside binary messages
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ValueFormat {
+    /// JSON text
+    #[default]
+    Json,
+    /// CBOR: smaller and faster to parse for small structured values
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+/// Encode a JSON value in `format`
+#[cfg(feature = "std")]
+pub fn encode_value(value: &JsonValue, format: ValueFormat) -> crate::Result<Vec<u8>> {
+    match format {
+        ValueFormat::Json => serde_json::to_vec(value).map_err(crate::SyncKitError::serialization),
+        #[cfg(feature = "cbor")]
+        ValueFormat::Cbor => {
+            let mut bytes = Vec::new();
+            ciborium::into_writer(value, &mut bytes).map_err(crate::SyncKitError::serialization)?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Decode a JSON value encoded with [`encode_value`]
+///
+/// # Errors
+///
+/// Returns `SyncError::DeserializationError` if the bytes are not valid in
+/// `format`
+#[cfg(feature = "std")]
+pub fn decode_value(bytes: &[u8], format: ValueFormat) -> crate::Result<JsonValue> {
+    match format {
+        ValueFormat::Json => {
+            serde_json::from_slice(bytes).map_err(crate::SyncKitError::deserialization)
+        }
+        #[cfg(feature = "cbor")]
+        ValueFormat::Cbor => {
+            ciborium::from_reader(bytes).map_err(crate::SyncKitError::deserialization)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Holder {
+        #[serde(with = "json_value")]
+        value: JsonValue,
+        #[serde(with = "json_value::option")]
+        maybe: Option<JsonValue>,
+    }
+
+    #[test]
+    fn test_json_output_is_unchanged() {
+        let holder = Holder {
+            value: json!({"a": [1, null]}),
+            maybe: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&holder).unwrap(),
+            r#"{"value":{"a":[1,null]},"maybe":null}"#
+        );
+    }
+
+    #[test]
+    fn test_binary_formats_round_trip() {
+        let holder = Holder {
+            value: json!({"nested": {"n": 1.5, "s": "x", "b": true}}),
+            maybe: Some(json!([1, 2])),
+        };
+
+        let bytes = bincode::serialize(&holder).unwrap();
+        assert_eq!(bincode::deserialize::<Holder>(&bytes).unwrap(), holder);
+
+        #[cfg(feature = "serde-compact")]
+        assert_eq!(
+            from_postcard::<Holder>(&to_postcard(&holder).unwrap()).unwrap(),
+            holder
+        );
+    }
+
+    #[cfg(feature = "serde-compact")]
+    #[test]
+    fn test_truncated_bytes_rejected() {
+        let bytes = to_postcard(&Holder {
+            value: json!("long enough"),
+            maybe: None,
+        })
+        .unwrap();
+        let error = from_postcard::<Holder>(&bytes[..3]).unwrap_err();
+        assert_eq!(error.code(), 3002);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_value_formats_round_trip() {
+        let value = json!({"cursor": [12, 40], "name": "Alice", "away": null});
+        let json = encode_value(&value, ValueFormat::Json).unwrap();
+        assert_eq!(decode_value(&json, ValueFormat::Json).unwrap(), value);
+
+        #[cfg(feature = "cbor")]
+        {
+            let cbor = encode_value(&value, ValueFormat::Cbor).unwrap();
+            assert_eq!(decode_value(&cbor, ValueFormat::Cbor).unwrap(), value);
+            assert!(cbor.len() < json.len());
+        }
+    }
+}
@@ -0,0 +1,10 @@
+//! Hash maps for the CRDT kernel
+//!
+//! std's maps with the `std` feature, so public fields keep their types,
+//! and hashbrown's (the same implementation) in `no_std` builds.
+
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{HashMap, HashSet};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use hashbrown::{HashMap, HashSet};
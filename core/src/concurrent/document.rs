@@ -0,0 +1,285 @@
+//! Shared handle for `Document`
+
+use super::SharedCell;
+use crate::document::{Document, Field};
+use crate::error::Result;
+use crate::sync::VectorClock;
+use crate::{ClientID, DocumentID, FieldPath};
+use serde_json::Value as JsonValue;
+use std::sync::Arc;
+
+#[cfg(feature = "async")]
+use tokio::sync::watch;
+
+/// Clone-able, thread-safe handle to a `Document`
+///
+/// Clones share the same document. See the [module docs](super) for the
+/// locking rules.
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::concurrent::SharedDocument;
+///
+/// let doc = SharedDocument::new("doc-1".to_string());
+/// let handle = doc.clone();
+/// std::thread::spawn(move || {
+///     handle.set_field("title".to_string(), serde_json::json!("Hi"), 1, "a".to_string());
+/// })
+/// .join()
+/// .unwrap();
+///
+/// assert_eq!(doc.get_field(&"title".to_string()), Some(serde_json::json!("Hi")));
+/// ```
+#[derive(Clone)]
+pub struct SharedDocument {
+    id: DocumentID,
+    cell: Arc<SharedCell<Document>>,
+}
+
+impl SharedDocument {
+    /// Create a handle to a new empty document
+    pub fn new(id: DocumentID) -> Self {
+        Document::new(id).into()
+    }
+
+    /// Get document ID (no locking)
+    pub fn id(&self) -> &DocumentID {
+        &self.id
+    }
+
+    /// Whether both handles refer to the same document
+    pub fn ptr_eq(&self, other: &SharedDocument) -> bool {
+        Arc::ptr_eq(&self.cell, &other.cell)
+    }
+
+    /// Set a field value (LWW, see `Document::set_field`)
+    pub fn set_field(
+        &self,
+        field_path: FieldPath,
+        value: JsonValue,
+        clock: u64,
+        client_id: ClientID,
+    ) {
+        self.cell.write(
+            |doc| doc.set_field(field_path, value, clock, client_id),
+            |_| true,
+        );
+    }
+
+    /// Like `set_field`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_set_field(
+        &self,
+        field_path: FieldPath,
+        value: JsonValue,
+        clock: u64,
+        client_id: ClientID,
+    ) -> Result<()> {
+        self.cell.try_write(
+            |doc| doc.set_field(field_path, value, clock, client_id),
+            |_| true,
+        )
+    }
+
+    /// Get a copy of a field value
+    pub fn get_field(&self, field_path: &FieldPath) -> Option<JsonValue> {
+        self.cell.read(|doc| doc.get_field(field_path).cloned())
+    }
+
+    /// Like `get_field`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_get_field(&self, field_path: &FieldPath) -> Result<Option<JsonValue>> {
+        self.cell.try_read(|doc| doc.get_field(field_path).cloned())
+    }
+
+    /// Merge a remote field (LWW); returns true if the local field changed
+    pub fn merge_field(&self, field_path: FieldPath, remote_field: Field) -> bool {
+        self.cell.write(
+            |doc| doc.merge_field(field_path, remote_field),
+            |changed| *changed,
+        )
+    }
+
+    /// Merge a remote document; returns the number of fields updated
+    pub fn merge(&self, remote: &Document) -> usize {
+        self.cell
+            .write(|doc| doc.merge(remote), |updated| *updated > 0)
+    }
+
+    /// Like `merge`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_merge(&self, remote: &Document) -> Result<usize> {
+        self.cell
+            .try_write(|doc| doc.merge(remote), |updated| *updated > 0)
+    }
+
+    /// Merge another shared document into this one
+    ///
+    /// Snapshots `other` before locking `self`, so the two locks are never
+    /// held together and opposite merges on two threads cannot deadlock.
+    pub fn merge_shared(&self, other: &SharedDocument) -> usize {
+        if self.ptr_eq(other) {
+            return 0;
+        }
+        let remote = other.read_snapshot();
+        self.merge(&remote)
+    }
+
+    /// Delete a field
+    pub fn delete_field(&self, field_path: &FieldPath) {
+        self.cell
+            .write(|doc| doc.delete_field(field_path), |_| true);
+    }
+
+    /// Convert document to JSON
+    pub fn to_json(&self) -> JsonValue {
+        self.cell.read(Document::to_json)
+    }
+
+    /// Get number of fields
+    pub fn field_count(&self) -> usize {
+        self.cell.read(Document::field_count)
+    }
+
+    /// Check if document has any fields
+    pub fn is_empty(&self) -> bool {
+        self.cell.read(Document::is_empty)
+    }
+
+    /// Get a copy of the document version (vector clock)
+    pub fn version(&self) -> VectorClock {
+        self.cell.read(|doc| doc.version().clone())
+    }
+
+    /// Get the current document without copying it
+    ///
+    /// The snapshot is immutable and unaffected by later writes; the next
+    /// write copies the document only while a snapshot is alive.
+    pub fn read_snapshot(&self) -> Arc<Document> {
+        self.cell.snapshot()
+    }
+
+    /// Like `read_snapshot`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_read_snapshot(&self) -> Result<Arc<Document>> {
+        self.cell.try_snapshot()
+    }
+
+    /// Run `f` with the read lock held
+    ///
+    /// `f` must not call back into any shared handle.
+    pub fn read<R>(&self, f: impl FnOnce(&Document) -> R) -> R {
+        self.cell.read(f)
+    }
+
+    /// Run `f` with the write lock held, e.g. to apply several changes
+    /// atomically; subscribers are notified afterwards
+    ///
+    /// `f` must not call back into any shared handle.
+    pub fn write<R>(&self, f: impl FnOnce(&mut Document) -> R) -> R {
+        self.cell.write(f, |_| true)
+    }
+
+    /// Like `write`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_write<R>(&self, f: impl FnOnce(&mut Document) -> R) -> Result<R> {
+        self.cell.try_write(f, |_| true)
+    }
+
+    /// Subscribe to changes
+    ///
+    /// The value is a counter bumped after every write that changed the
+    /// document; use `changed().await` and then `read_snapshot()`.
+    #[cfg(feature = "async")]
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.cell.subscribe()
+    }
+
+    /// Number of handles to the document, this one included
+    #[cfg(feature = "server")]
+    pub(crate) fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.cell)
+    }
+
+    /// The counter `subscribe` receivers see
+    #[cfg(feature = "server")]
+    pub(crate) fn change_count(&self) -> u64 {
+        *self.cell.changes.borrow()
+    }
+}
+
+impl From<Document> for SharedDocument {
+    fn from(document: Document) -> Self {
+        Self {
+            id: document.id().clone(),
+            cell: Arc::new(SharedCell::new(document)),
+        }
+    }
+}
+
+impl std::fmt::Debug for SharedDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedDocument")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_snapshot_is_isolated_from_later_writes() {
+        let doc = SharedDocument::new("doc-1".to_string());
+        doc.set_field("a".to_string(), json!(1), 1, "c1".to_string());
+
+        let snapshot = doc.read_snapshot();
+        doc.set_field("a".to_string(), json!(2), 2, "c1".to_string());
+
+        assert_eq!(snapshot.get_field(&"a".to_string()), Some(&json!(1)));
+        assert_eq!(doc.get_field(&"a".to_string()), Some(json!(2)));
+    }
+
+    #[test]
+    fn test_merge_shared_in_both_directions() {
+        let a = SharedDocument::new("doc-1".to_string());
+        let b = SharedDocument::new("doc-1".to_string());
+        a.set_field("x".to_string(), json!("a"), 1, "a".to_string());
+        b.set_field("y".to_string(), json!("b"), 1, "b".to_string());
+
+        assert_eq!(a.merge_shared(&b), 1);
+        assert_eq!(b.merge_shared(&a), 1);
+        assert_eq!(a.merge_shared(&a.clone()), 0);
+        assert_eq!(a.to_json(), b.to_json());
+    }
+
+    #[test]
+    fn test_try_set_field_while_reading() {
+        let doc = SharedDocument::new("doc-1".to_string());
+        doc.read(|_| {
+            let error = doc
+                .try_set_field("a".to_string(), json!(1), 1, "c1".to_string())
+                .unwrap_err();
+            assert!(error.is_retryable());
+        });
+
+        doc.try_set_field("a".to_string(), json!(1), 1, "c1".to_string())
+            .unwrap();
+        assert_eq!(doc.field_count(), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_subscribers_see_only_real_changes() {
+        let doc = SharedDocument::new("doc-1".to_string());
+        let mut changes = doc.subscribe();
+
+        doc.set_field("a".to_string(), json!(1), 2, "c1".to_string());
+        assert!(changes.has_changed().unwrap());
+        changes.borrow_and_update();
+
+        // An older write loses LWW and changes nothing
+        let mut stale = Document::new("doc-1".to_string());
+        stale.set_field("a".to_string(), json!(0), 1, "c1".to_string());
+        assert_eq!(doc.merge(&stale), 0);
+        assert!(!changes.has_changed().unwrap());
+    }
+}
@@ -0,0 +1,197 @@
+//! Thread-safe shared handles for servers
+//!
+//! `Document` and `FugueText` need `&mut` for every write, so a server that
+//! shares one document between request handlers, a persistence task and a
+//! broadcast task ends up wrapping it in `Arc<RwLock<_>>` and spreading lock
+//! handling across the codebase. [`SharedDocument`] and `SharedText` (with
+//! the `text-crdt` feature) do that once:
+//!
+//! - handles are `Clone + Send + Sync`; clones refer to the same value,
+//! - every method takes the lock internally and releases it before
+//!   returning,
+//! - `try_` variants return `SyncKitError` with code `WOULD_BLOCK` instead of
+//!   waiting for the lock,
+//! - `read_snapshot()` returns an `Arc` of the current value in O(1). The
+//!   next write copies the value only if a snapshot is still alive
+//!   (copy-on-write), so snapshots are cheap to take and never block
+//!   writers for long,
+//! - with the `async` feature, `subscribe()` returns a
+//!   `tokio::sync::watch::Receiver` that is bumped after every change.
+//!
+//! # Deadlock avoidance
+//!
+//! **Never hold one handle's lock while taking another's.** Concretely:
+//!
+//! - Merging two shared handles goes through `merge_shared`, which takes a
+//!   snapshot of the other handle (read lock, released immediately) and
+//!   only then write-locks `self`. At no point are both locks held, so
+//!   `a.merge_shared(&b)` and `b.merge_shared(&a)` on two threads cannot
+//!   deadlock. Merging a handle with itself is a no-op.
+//! - Closures passed to `read`/`write` must not call methods on any shared
+//!   handle, including the one being locked (the lock is not re-entrant).
+//!
+//! A poisoned lock (a panic while writing) is recovered rather than
+//! propagated: the CRDTs stay mergeable after a partial write, and failing
+//! every later call would take the whole server down.
+
+mod document;
+#[cfg(feature = "text-crdt")]
+mod text;
+
+pub use document::SharedDocument;
+#[cfg(feature = "text-crdt")]
+pub use text::SharedText;
+
+use crate::error::{Result, SyncKitError};
+use std::sync::{Arc, PoisonError, RwLock, TryLockError};
+
+#[cfg(feature = "async")]
+use tokio::sync::watch;
+
+/// Lock plus change notification shared by the handle types
+struct SharedCell<T> {
+    state: RwLock<Arc<T>>,
+    #[cfg(feature = "async")]
+    changes: watch::Sender<u64>,
+}
+
+impl<T: Clone> SharedCell<T> {
+    fn new(value: T) -> Self {
+        Self {
+            state: RwLock::new(Arc::new(value)),
+            #[cfg(feature = "async")]
+            changes: watch::Sender::new(0),
+        }
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let guard = self.state.read().unwrap_or_else(PoisonError::into_inner);
+        f(&guard)
+    }
+
+    fn try_read<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R> {
+        let guard = match self.state.try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(SyncKitError::would_block()),
+        };
+        Ok(f(&guard))
+    }
+
+    /// Run `f` under the write lock, notifying subscribers if `changed`
+    /// says the result altered the value
+    fn write<R>(&self, f: impl FnOnce(&mut T) -> R, changed: impl FnOnce(&R) -> bool) -> R {
+        let result = {
+            let mut guard = self.state.write().unwrap_or_else(PoisonError::into_inner);
+            f(Arc::make_mut(&mut guard))
+        };
+        if changed(&result) {
+            self.notify();
+        }
+        result
+    }
+
+    fn try_write<R>(
+        &self,
+        f: impl FnOnce(&mut T) -> R,
+        changed: impl FnOnce(&R) -> bool,
+    ) -> Result<R> {
+        let result = {
+            let mut guard = match self.state.try_write() {
+                Ok(guard) => guard,
+                Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                Err(TryLockError::WouldBlock) => return Err(SyncKitError::would_block()),
+            };
+            f(Arc::make_mut(&mut guard))
+        };
+        if changed(&result) {
+            self.notify();
+        }
+        Ok(result)
+    }
+
+    fn snapshot(&self) -> Arc<T> {
+        self.read_arc(Arc::clone)
+    }
+
+    fn try_snapshot(&self) -> Result<Arc<T>> {
+        match self.state.try_read() {
+            Ok(guard) => Ok(Arc::clone(&guard)),
+            Err(TryLockError::Poisoned(e)) => Ok(Arc::clone(&e.into_inner())),
+            Err(TryLockError::WouldBlock) => Err(SyncKitError::would_block()),
+        }
+    }
+
+    fn read_arc<R>(&self, f: impl FnOnce(&Arc<T>) -> R) -> R {
+        let guard = self.state.read().unwrap_or_else(PoisonError::into_inner);
+        f(&guard)
+    }
+
+    #[cfg(feature = "async")]
+    fn notify(&self) {
+        self.changes.send_modify(|version| *version += 1);
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn notify(&self) {}
+
+    #[cfg(feature = "async")]
+    fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_copies_only_while_snapshot_is_alive() {
+        let cell = SharedCell::new(vec![1]);
+
+        let snapshot = cell.snapshot();
+        cell.write(|v| v.push(2), |_| true);
+        assert_eq!(*snapshot, vec![1]);
+        assert_eq!(*cell.snapshot(), vec![1, 2]);
+
+        drop(snapshot);
+        let before = cell.read_arc(Arc::as_ptr);
+        cell.write(|v| v.push(3), |_| true);
+        assert_eq!(cell.read_arc(Arc::as_ptr), before);
+    }
+
+    #[test]
+    fn test_try_variants_fail_fast_while_locked() {
+        let cell = SharedCell::new(0u32);
+
+        cell.write(
+            |_| {
+                assert_eq!(cell.try_read(|v| *v).unwrap_err().code(), 5003);
+                assert!(cell.try_write(|v| *v += 1, |_| true).is_err());
+                assert!(cell.try_snapshot().is_err());
+            },
+            |_| false,
+        );
+        assert_eq!(cell.try_read(|v| *v).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_poisoned_lock_is_recovered() {
+        let cell = Arc::new(SharedCell::new(vec![1]));
+        let clone = Arc::clone(&cell);
+        let _ = std::thread::spawn(move || {
+            clone.write(
+                |v| {
+                    v.push(2);
+                    panic!("writer died");
+                },
+                |_: &()| true,
+            )
+        })
+        .join();
+
+        assert_eq!(cell.read(|v| v.clone()), vec![1, 2]);
+        cell.write(|v| v.push(3), |_| true);
+        assert_eq!(*cell.snapshot(), vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,223 @@
+//! Shared handle for `FugueText`
+
+use super::SharedCell;
+use crate::crdt::{FugueText, NodeId};
+use crate::error::Result;
+use std::sync::Arc;
+
+#[cfg(feature = "async")]
+use tokio::sync::watch;
+
+/// Clone-able, thread-safe handle to a `FugueText` replica
+///
+/// All clones edit as the same replica (one client ID); edits from
+/// different threads are applied in lock order. See the
+/// [module docs](super) for the locking rules.
+#[derive(Clone)]
+pub struct SharedText {
+    cell: Arc<SharedCell<FugueText>>,
+}
+
+impl SharedText {
+    /// Create a handle to a new empty text replica
+    pub fn new(client_id: String) -> Self {
+        FugueText::new(client_id).into()
+    }
+
+    /// Whether both handles refer to the same replica
+    pub fn ptr_eq(&self, other: &SharedText) -> bool {
+        Arc::ptr_eq(&self.cell, &other.cell)
+    }
+
+    /// Insert text at a character position
+    pub fn insert(&self, position: usize, text: &str) -> Result<NodeId> {
+        self.cell
+            .write(|t| t.insert(position, text), |r| r.is_ok())
+            .map_err(Into::into)
+    }
+
+    /// Like `insert`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_insert(&self, position: usize, text: &str) -> Result<NodeId> {
+        Ok(self
+            .cell
+            .try_write(|t| t.insert(position, text), |r| r.is_ok())??)
+    }
+
+    /// Delete `length` characters starting at `position`
+    pub fn delete(&self, position: usize, length: usize) -> Result<Vec<NodeId>> {
+        self.cell
+            .write(|t| t.delete(position, length), |r| r.is_ok())
+            .map_err(Into::into)
+    }
+
+    /// Like `delete`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_delete(&self, position: usize, length: usize) -> Result<Vec<NodeId>> {
+        Ok(self
+            .cell
+            .try_write(|t| t.delete(position, length), |r| r.is_ok())??)
+    }
+
+    /// Merge a remote replica into this one
+    pub fn merge(&self, remote: &FugueText) -> Result<()> {
+        self.cell
+            .write(|t| t.merge(remote), |r| r.is_ok())
+            .map_err(Into::into)
+    }
+
+    /// Like `merge`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_merge(&self, remote: &FugueText) -> Result<()> {
+        Ok(self.cell.try_write(|t| t.merge(remote), |r| r.is_ok())??)
+    }
+
+    /// Merge another shared replica into this one
+    ///
+    /// Snapshots `other` before locking `self`, so the two locks are never
+    /// held together and opposite merges on two threads cannot deadlock.
+    pub fn merge_shared(&self, other: &SharedText) -> Result<()> {
+        if self.ptr_eq(other) {
+            return Ok(());
+        }
+        let remote = other.read_snapshot();
+        self.merge(&remote)
+    }
+
+    /// Get the stable NodeId of the character at `position`
+    pub fn get_node_id_at_position(&self, position: usize) -> Result<NodeId> {
+        // Takes the write lock only to refresh the internal position cache
+        self.cell
+            .write(|t| t.get_node_id_at_position(position), |_| false)
+            .map_err(Into::into)
+    }
+
+    /// Get the current position of a NodeId, if it is still visible
+    pub fn get_position_of_node_id(&self, node_id: &NodeId) -> Option<usize> {
+        self.cell
+            .write(|t| t.get_position_of_node_id(node_id), |_| false)
+    }
+
+    /// Get the length in characters
+    pub fn len(&self) -> usize {
+        self.cell.read(FugueText::len)
+    }
+
+    /// Check if the text is empty
+    pub fn is_empty(&self) -> bool {
+        self.cell.read(FugueText::is_empty)
+    }
+
+    /// Get the client ID of the replica
+    pub fn client_id(&self) -> String {
+        self.cell.read(|t| t.client_id().to_string())
+    }
+
+    /// Get the current replica without copying it
+    ///
+    /// The snapshot is immutable and unaffected by later writes; the next
+    /// write copies the replica only while a snapshot is alive.
+    pub fn read_snapshot(&self) -> Arc<FugueText> {
+        self.cell.snapshot()
+    }
+
+    /// Like `read_snapshot`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_read_snapshot(&self) -> Result<Arc<FugueText>> {
+        self.cell.try_snapshot()
+    }
+
+    /// Run `f` with the read lock held
+    ///
+    /// `f` must not call back into any shared handle.
+    pub fn read<R>(&self, f: impl FnOnce(&FugueText) -> R) -> R {
+        self.cell.read(f)
+    }
+
+    /// Run `f` with the write lock held; subscribers are notified afterwards
+    ///
+    /// `f` must not call back into any shared handle.
+    pub fn write<R>(&self, f: impl FnOnce(&mut FugueText) -> R) -> R {
+        self.cell.write(f, |_| true)
+    }
+
+    /// Like `write`, but fails with `WOULD_BLOCK` instead of waiting
+    pub fn try_write<R>(&self, f: impl FnOnce(&mut FugueText) -> R) -> Result<R> {
+        self.cell.try_write(f, |_| true)
+    }
+
+    /// Subscribe to changes
+    ///
+    /// The value is a counter bumped after every successful edit or merge;
+    /// use `changed().await` and then `read_snapshot()`.
+    #[cfg(feature = "async")]
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.cell.subscribe()
+    }
+}
+
+impl From<FugueText> for SharedText {
+    fn from(text: FugueText) -> Self {
+        Self {
+            cell: Arc::new(SharedCell::new(text)),
+        }
+    }
+}
+
+impl std::fmt::Display for SharedText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = self.cell.read(FugueText::to_string);
+        f.write_str(&text)
+    }
+}
+
+impl std::fmt::Debug for SharedText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedText")
+            .field("client_id", &self.client_id())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_edit_the_same_replica() {
+        let text = SharedText::new("client1".to_string());
+        let handle = text.clone();
+
+        text.insert(0, "Hello").unwrap();
+        handle.insert(5, " World").unwrap();
+        handle.delete(0, 6).unwrap();
+
+        assert_eq!(text.to_string(), "World");
+        assert_eq!(text.len(), 5);
+    }
+
+    #[test]
+    fn test_errors_keep_text_context() {
+        let text = SharedText::new("client1".to_string());
+        let error = text.insert(3, "x").unwrap_err();
+        assert_eq!(error.code(), 1001);
+        assert_eq!(error.context().position, Some(3));
+    }
+
+    #[test]
+    fn test_merge_shared_converges() {
+        let a = SharedText::new("a".to_string());
+        let b = SharedText::new("b".to_string());
+        a.insert(0, "left").unwrap();
+        b.insert(0, "right").unwrap();
+
+        a.merge_shared(&b).unwrap();
+        b.merge_shared(&a).unwrap();
+        assert_eq!(a.to_string(), b.to_string());
+        assert_eq!(a.len(), 9);
+    }
+
+    #[test]
+    fn test_try_insert_while_reading() {
+        let text = SharedText::new("client1".to_string());
+        text.read(|_| assert_eq!(text.try_insert(0, "x").unwrap_err().code(), 5003));
+        text.try_insert(0, "x").unwrap();
+        assert_eq!(text.to_string(), "x");
+    }
+}
@@ -0,0 +1,296 @@
+//! Conflict inbox: the values that lost last-writer-wins, kept for the
+//! user to look at later
+//!
+//! LWW settles concurrent writes to a field silently: one value wins and
+//! the other is gone. Some apps want to show what was lost ("Bob's edit
+//! of the title was overwritten") and let the user pick. Every time a
+//! merge or an incoming delta meets a field holding a different value, the
+//! document records a [`Conflict`] in its [`ConflictLog`]: both values,
+//! their timestamps and which one won. Conflicts are recorded both ways:
+//! when the local value loses to a remote write and when a remote write
+//! loses to the local value, as it does on a server.
+//!
+//! The log is local to the replica: it is saved with the document but
+//! doesn't merge. A conflict's [`ConflictId`] comes from the path and the
+//! losing write, so the same losing write arriving again (a sync echo, a
+//! resend) never makes a second entry, and every replica that records it
+//! gives it the same ID. [`Document::resolve_conflict`] writes the value
+//! the user picked as a new write and removes the entry; losing writes on
+//! that path up to the resolved ones aren't recorded again.
+//!
+//! The log keeps at most [`DEFAULT_CONFLICT_LIMIT`] entries (see
+//! [`ConflictLog::set_limit`]), dropping the oldest.
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde_json::json;
+//! use synckit_core::conflicts::Keep;
+//! use synckit_core::Document;
+//!
+//! let mut alice = Document::new("doc-1".to_string());
+//! let mut bob = Document::new("doc-1".to_string());
+//! alice.set_field("title".to_string(), json!("Alice's"), 1, "alice".to_string());
+//! bob.set_field("title".to_string(), json!("Bob's"), 1, "bob".to_string());
+//!
+//! // Bob's write wins the tie, Alice's value lands in her inbox
+//! alice.merge(&bob);
+//! let conflict = &alice.conflicts().entries()[0];
+//! assert_eq!(conflict.lost().value, json!("Alice's"));
+//!
+//! let id = conflict.id;
+//! alice.resolve_conflict(&id, Keep::Local, 2, "alice".to_string()).unwrap();
+//! assert_eq!(alice.get_field(&"title".to_string()), Some(&json!("Alice's")));
+//! assert!(alice.conflicts().is_empty());
+//! ```
+//!
+//! [`Document::resolve_conflict`]: crate::Document::resolve_conflict
+
+use crate::document::{Field, Fnv1a};
+use crate::memory::HeapSize;
+use crate::sync::Timestamp;
+use crate::FieldPath;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// How many conflicts a log keeps unless told otherwise
+pub const DEFAULT_CONFLICT_LIMIT: usize = 100;
+
+/// Identifies a conflict by its path and losing write
+///
+/// Written as 16 hex digits, so it survives JSON in JavaScript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct ConflictId(u64);
+
+impl ConflictId {
+    fn of(path: &str, lost: &Timestamp) -> Self {
+        let mut hash = Fnv1a::new();
+        hash.write(path.as_bytes());
+        hash.write(lost.client_id.as_bytes());
+        hash.write(&lost.clock.to_le_bytes());
+        Self(hash.finish())
+    }
+}
+
+impl fmt::Display for ConflictId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for ConflictId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(Self)
+    }
+}
+
+impl From<ConflictId> for String {
+    fn from(id: ConflictId) -> Self {
+        id.to_string()
+    }
+}
+
+impl TryFrom<String> for ConflictId {
+    type Error = std::num::ParseIntError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A field write that lost LWW to a different value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Conflict {
+    /// From the path and the losing write
+    pub id: ConflictId,
+
+    /// Path of the field
+    pub path: FieldPath,
+
+    /// The value this replica held
+    pub local: Field,
+
+    /// The value that arrived
+    pub remote: Field,
+
+    /// Whether the remote value won
+    pub remote_won: bool,
+
+    /// When the conflict was recorded, in milliseconds since the Unix
+    /// epoch
+    pub recorded_ms: u64,
+}
+
+impl Conflict {
+    /// The value LWW dropped
+    pub fn lost(&self) -> &Field {
+        match self.remote_won {
+            true => &self.local,
+            false => &self.remote,
+        }
+    }
+
+    /// The value LWW kept
+    pub fn won(&self) -> &Field {
+        match self.remote_won {
+            true => &self.remote,
+            false => &self.local,
+        }
+    }
+}
+
+/// Which value [`Document::resolve_conflict`] writes
+///
+/// In JSON: `"local"`, `"remote"` or `{"custom": value}`.
+///
+/// [`Document::resolve_conflict`]: crate::Document::resolve_conflict
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Keep {
+    /// The value this replica held
+    Local,
+    /// The value that arrived
+    Remote,
+    /// Something else, e.g. the two combined by the user
+    Custom(#[serde(with = "crate::codec::json_value")] JsonValue),
+}
+
+/// A document's unresolved conflicts, oldest first (see the module docs)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConflictLog {
+    entries: Vec<Conflict>,
+    limit: usize,
+    /// Per path, the newest write a resolution covered
+    resolved: BTreeMap<FieldPath, Timestamp>,
+}
+
+impl Default for ConflictLog {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            limit: DEFAULT_CONFLICT_LIMIT,
+            resolved: BTreeMap::new(),
+        }
+    }
+}
+
+impl ConflictLog {
+    /// Unresolved conflicts, oldest first
+    pub fn entries(&self) -> &[Conflict] {
+        &self.entries
+    }
+
+    /// The conflict with `id`, if unresolved
+    pub fn get(&self, id: &ConflictId) -> Option<&Conflict> {
+        self.entries.iter().find(|conflict| conflict.id == *id)
+    }
+
+    /// Unresolved conflicts on `path`
+    pub fn for_path<'a>(&'a self, path: &'a str) -> impl Iterator<Item = &'a Conflict> + 'a {
+        self.entries
+            .iter()
+            .filter(move |conflict| conflict.path == path)
+    }
+
+    /// Number of unresolved conflicts
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if there are no unresolved conflicts
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Most conflicts kept
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Keep at most `limit` conflicts; returns how many of the oldest
+    /// were dropped to fit
+    pub fn set_limit(&mut self, limit: usize) -> usize {
+        self.limit = limit;
+        self.trim()
+    }
+
+    /// Record that `local` met `remote` at `path`; returns false if they
+    /// don't conflict or the losing write is already known
+    pub(crate) fn record(
+        &mut self,
+        path: &FieldPath,
+        local: &Field,
+        remote: &Field,
+        now_ms: u64,
+    ) -> bool {
+        if local.value == remote.value {
+            return false;
+        }
+        let remote_won = remote.wins_over(local);
+        let lost = match remote_won {
+            true => &local.timestamp,
+            false => &remote.timestamp,
+        };
+        let covered = self
+            .resolved
+            .get(path)
+            .is_some_and(|resolved| lost.compare_lww(resolved) != Ordering::Greater);
+        let id = ConflictId::of(path, lost);
+        if covered || self.get(&id).is_some() {
+            return false;
+        }
+        self.entries.push(Conflict {
+            id,
+            path: path.clone(),
+            local: local.clone(),
+            remote: remote.clone(),
+            remote_won,
+            recorded_ms: now_ms,
+        });
+        self.trim();
+        true
+    }
+
+    /// Remove a conflict, so neither of its writes is recorded again
+    pub(crate) fn take(&mut self, id: &ConflictId) -> Option<Conflict> {
+        let index = self
+            .entries
+            .iter()
+            .position(|conflict| conflict.id == *id)?;
+        let conflict = self.entries.remove(index);
+        let newest = conflict.won().timestamp.clone();
+        let resolved = self
+            .resolved
+            .entry(conflict.path.clone())
+            .or_insert(newest.clone());
+        if newest.is_newer_than(resolved) {
+            *resolved = newest;
+        }
+        Some(conflict)
+    }
+
+    fn trim(&mut self) -> usize {
+        let excess = self.entries.len().saturating_sub(self.limit);
+        self.entries.drain(..excess);
+        excess
+    }
+}
+
+impl HeapSize for Conflict {
+    fn heap_size(&self) -> usize {
+        self.path.heap_size() + self.local.heap_size() + self.remote.heap_size()
+    }
+}
+
+impl HeapSize for ConflictLog {
+    fn heap_size(&self) -> usize {
+        self.entries.heap_size() + self.resolved.heap_size()
+    }
+}
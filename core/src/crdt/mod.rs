@@ -24,27 +24,34 @@
 //! - "Fugue: A CRDT for Shared Text Editing" by Weihai Yu et al.
 
 // Conditionally compile each CRDT based on features
-#[cfg(feature = "counters")]
+#[cfg(any(feature = "counters", feature = "alloc-kernel"))]
 pub mod pn_counter;
 
-#[cfg(feature = "sets")]
+#[cfg(any(feature = "sets", feature = "alloc-kernel"))]
 pub mod or_set;
 
 #[cfg(feature = "fractional-index")]
 pub mod fractional_index;
 
-#[cfg(feature = "text-crdt")]
+#[cfg(any(feature = "text-crdt", feature = "alloc-kernel"))]
 pub mod text_fugue;
 
 // Re-exports (only if features enabled)
-#[cfg(feature = "counters")]
+#[cfg(any(feature = "counters", feature = "alloc-kernel"))]
 pub use pn_counter::PNCounter;
 
-#[cfg(feature = "sets")]
+#[cfg(any(feature = "sets", feature = "alloc-kernel"))]
 pub use or_set::ORSet;
 
 #[cfg(feature = "fractional-index")]
 pub use fractional_index::FractionalIndex;
 
+#[cfg(any(feature = "text-crdt", feature = "alloc-kernel"))]
+pub use text_fugue::{FugueBlock, LamportClock, NodeId};
+
 #[cfg(feature = "text-crdt")]
-pub use text_fugue::{FugueBlock, FugueText, LamportClock, NodeId, TextError};
+pub use text_fugue::{
+    Anchor, AnchorBias, DeleteRange, FugueText, HistoryRetention, LineIndex, LinePosition,
+    MarkdownImport, MarkdownOptions, MarkdownSpan, MarkdownStyle, TextDelta, TextError, TextEvent,
+    TextMergeJob, TextSnapshot,
+};
@@ -12,6 +12,7 @@
 //! # Example
 //!
 //! ```
+//! # #[cfg(feature = "std")] {
 //! use synckit_core::crdt::ORSet;
 //!
 //! let mut set1 = ORSet::new("replica1".to_string());
@@ -26,17 +27,55 @@
 //!
 //! assert!(set1.contains(&"apple".to_string()));
 //! assert!(set1.contains(&"banana".to_string()));
+//! # }
 //! ```
+//!
+//! # Clearing
+//!
+//! [`ORSet::clear`] (also available as [`ORSet::clear_all_observed`])
+//! removes the adds this replica has seen, like calling `remove` on every
+//! element. An add made concurrently on another replica was not seen, so
+//! it survives the merge, in either merge order. That is what a "remove
+//! all tags" button wants: a tag someone else added at the same time
+//! isn't silently lost.
+//!
+//! [`ORSet::reset`] is the stronger operation: it starts a new *epoch*,
+//! and every add from an earlier epoch is dropped wherever the reset
+//! reaches, including adds made concurrently with it. Use it to wipe the
+//! set for everyone, accepting that concurrent work is lost.
+//!
+//! ```
+//! # #[cfg(feature = "std")] {
+//! use synckit_core::crdt::ORSet;
+//!
+//! let mut alice = ORSet::new("alice".to_string());
+//! let mut bob = ORSet::new("bob".to_string());
+//! alice.add("draft".to_string());
+//! bob.merge(&alice);
+//!
+//! // Alice clears while Bob concurrently adds
+//! alice.clear();
+//! bob.add("urgent".to_string());
+//! alice.merge(&bob);
+//! assert_eq!(alice.iter().collect::<Vec<_>>(), vec!["urgent"]);
+//!
+//! // A reset drops Bob's concurrent add too
+//! alice.reset();
+//! bob.add("later".to_string());
+//! alice.merge(&bob);
+//! assert!(alice.is_empty());
+//! # }
+//! ```
+//!
+//! # Delta-state sync
+//!
+//! `split_delta` returns the tags added and removed locally since the last
+//! call, packaged as a small OR-Set. Applying it is a plain merge, so
+//! deltas can be redelivered or reordered safely.
 
+use crate::collections::{HashMap, HashSet};
 use crate::ClientID;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-
-#[cfg(not(target_arch = "wasm32"))]
-use std::time::{SystemTime, UNIX_EPOCH};
-
-#[cfg(target_arch = "wasm32")]
-use js_sys::Date;
 
 /// Unique identifier for an element in the set
 ///
@@ -46,14 +85,18 @@ struct UniqueTag {
     replica_id: ClientID,
     timestamp: u64,
     sequence: u64, // For same-timestamp operations
+    /// `reset` epoch the add was made in
+    #[serde(default)]
+    epoch: u64,
 }
 
 impl UniqueTag {
-    fn new(replica_id: ClientID, timestamp: u64, sequence: u64) -> Self {
+    fn new(replica_id: ClientID, timestamp: u64, sequence: u64, epoch: u64) -> Self {
         Self {
             replica_id,
             timestamp,
             sequence,
+            epoch,
         }
     }
 }
@@ -62,10 +105,10 @@ impl UniqueTag {
 ///
 /// Maintains a set of elements where each add operation is tagged uniquely.
 /// Removes are tracked separately to handle concurrent operations correctly.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ORSet<T>
 where
-    T: Clone + Eq + std::hash::Hash + Serialize,
+    T: Clone + Eq + core::hash::Hash + Serialize,
 {
     /// Replica identifier
     replica_id: ClientID,
@@ -80,11 +123,37 @@ where
 
     /// Sequence counter for this replica (for same-timestamp operations)
     sequence: u64,
+
+    /// Number of `reset`s seen; tags from earlier epochs are dropped
+    #[serde(default)]
+    epoch: u64,
+
+    /// Tags added locally since the last `split_delta`
+    #[serde(skip)]
+    delta_elements: HashMap<T, HashSet<UniqueTag>>,
+
+    /// Tags removed locally since the last `split_delta`
+    #[serde(skip)]
+    delta_removed_tags: HashSet<UniqueTag>,
+}
+
+impl<T> PartialEq for ORSet<T>
+where
+    T: Clone + Eq + core::hash::Hash + Serialize,
+{
+    fn eq(&self, other: &Self) -> bool {
+        // Pending delta bookkeeping is local, not part of the CRDT state
+        self.replica_id == other.replica_id
+            && self.elements == other.elements
+            && self.removed_tags == other.removed_tags
+            && self.sequence == other.sequence
+            && self.epoch == other.epoch
+    }
 }
 
 impl<T> ORSet<T>
 where
-    T: Clone + Eq + std::hash::Hash + Serialize,
+    T: Clone + Eq + core::hash::Hash + Serialize,
 {
     /// Create a new OR-Set for the given replica
     pub fn new(replica_id: ClientID) -> Self {
@@ -93,25 +162,41 @@ where
             elements: HashMap::new(),
             removed_tags: HashSet::new(),
             sequence: 0,
+            epoch: 0,
+            delta_elements: HashMap::new(),
+            delta_removed_tags: HashSet::new(),
         }
     }
 
     /// Add an element to the set
     ///
     /// Creates a unique tag for this add operation.
+    #[cfg(feature = "std")]
     pub fn add(&mut self, element: T) {
-        #[cfg(not(target_arch = "wasm32"))]
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_micros() as u64;
+        // From the thread's default clock (see `crate::time`)
+        self.add_at(element, crate::time::now_ms());
+    }
 
-        #[cfg(target_arch = "wasm32")]
-        let timestamp = (Date::now() * 1000.0) as u64; // Convert ms to microseconds
+    /// Add an element, tagged with a caller-supplied time in milliseconds
+    ///
+    /// For `no_std` builds, which have no clock. Tags stay unique through
+    /// the replica's sequence number, so any monotonic time (or 0) works.
+    pub fn add_at(&mut self, element: T, now_ms: u64) {
+        // Microseconds
+        let timestamp = now_ms * 1000;
 
         self.sequence += 1;
-        let tag = UniqueTag::new(self.replica_id.clone(), timestamp, self.sequence);
-
+        let tag = UniqueTag::new(
+            self.replica_id.clone(),
+            timestamp,
+            self.sequence,
+            self.epoch,
+        );
+
+        self.delta_elements
+            .entry(element.clone())
+            .or_default()
+            .insert(tag.clone());
         self.elements.entry(element).or_default().insert(tag);
     }
 
@@ -124,6 +209,7 @@ where
             // Mark all tags for this element as removed
             for tag in tags {
                 self.removed_tags.insert(tag.clone());
+                self.delta_removed_tags.insert(tag.clone());
             }
         }
     }
@@ -163,32 +249,129 @@ where
 
     /// Merge another OR-Set's state into this one
     ///
-    /// Takes the union of all elements and removed tags.
+    /// Takes the union of all elements and removed tags, then drops the
+    /// tags of epochs that either side has reset.
     pub fn merge(&mut self, other: &ORSet<T>) {
+        let epoch = self.epoch.max(other.epoch);
+
         // Merge elements (union of tags)
         for (element, tags) in &other.elements {
+            let live = tags.iter().filter(|tag| tag.epoch >= epoch).cloned();
             self.elements
                 .entry(element.clone())
                 .or_default()
-                .extend(tags.clone());
+                .extend(live);
         }
 
         // Merge removed tags (union)
-        self.removed_tags.extend(other.removed_tags.clone());
+        self.removed_tags.extend(
+            other
+                .removed_tags
+                .iter()
+                .filter(|tag| tag.epoch >= epoch)
+                .cloned(),
+        );
+
+        if epoch > self.epoch {
+            self.epoch = epoch;
+            self.drop_old_epochs();
+        }
     }
 
-    /// Clear all elements from the set
+    /// Remove every element this replica has seen (see the module docs)
+    ///
+    /// Same as [`ORSet::clear_all_observed`]: an add made concurrently on
+    /// another replica survives the merge.
     pub fn clear(&mut self) {
+        self.clear_all_observed();
+    }
+
+    /// Remove every element this replica has seen, like `remove` on each
+    ///
+    /// Only the adds observed at call time are removed, so an add made
+    /// concurrently on another replica, which this one hasn't seen yet,
+    /// survives the merge in either order. Use [`ORSet::reset`] to drop
+    /// concurrent adds too.
+    pub fn clear_all_observed(&mut self) {
         // Mark all current tags as removed
         for tags in self.elements.values() {
             for tag in tags {
                 self.removed_tags.insert(tag.clone());
+                self.delta_removed_tags.insert(tag.clone());
             }
         }
     }
+
+    /// Empty the set on every replica the reset reaches, concurrent adds
+    /// included
+    ///
+    /// Starts a new epoch: merging this replica (or its deltas) into
+    /// another drops every add made there before it saw the reset, even
+    /// one made concurrently with it. Adds made after seeing the reset are
+    /// kept. Concurrent resets are a single reset.
+    pub fn reset(&mut self) {
+        self.epoch += 1;
+        self.drop_old_epochs();
+    }
+
+    /// Forget the tags of epochs before the current one
+    fn drop_old_epochs(&mut self) {
+        let epoch = self.epoch;
+        for tags in self.elements.values_mut() {
+            tags.retain(|tag| tag.epoch >= epoch);
+        }
+        self.elements.retain(|_, tags| !tags.is_empty());
+        self.removed_tags.retain(|tag| tag.epoch >= epoch);
+        for tags in self.delta_elements.values_mut() {
+            tags.retain(|tag| tag.epoch >= epoch);
+        }
+        self.delta_elements.retain(|_, tags| !tags.is_empty());
+        self.delta_removed_tags.retain(|tag| tag.epoch >= epoch);
+    }
+
+    /// Take the adds and removes made locally since the last call
+    ///
+    /// Returns an OR-Set holding only those tags (empty if nothing changed).
+    /// Send it to other replicas and apply it there with `apply_delta`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use synckit_core::crdt::ORSet;
+    ///
+    /// let mut set1 = ORSet::new("replica1".to_string());
+    /// let mut set2 = ORSet::new("replica2".to_string());
+    ///
+    /// set1.add("apple".to_string());
+    /// set2.apply_delta(&set1.split_delta());
+    ///
+    /// assert!(set2.contains(&"apple".to_string()));
+    /// # }
+    /// ```
+    pub fn split_delta(&mut self) -> ORSet<T> {
+        ORSet {
+            replica_id: self.replica_id.clone(),
+            elements: core::mem::take(&mut self.delta_elements),
+            removed_tags: core::mem::take(&mut self.delta_removed_tags),
+            sequence: 0,
+            // Carries any reset made since the last call
+            epoch: self.epoch,
+            delta_elements: HashMap::new(),
+            delta_removed_tags: HashSet::new(),
+        }
+    }
+
+    /// Apply a delta produced by `split_delta` on another replica
+    ///
+    /// Equivalent to `merge`, so duplicated or reordered deltas are safe.
+    pub fn apply_delta(&mut self, delta: &ORSet<T>) {
+        self.merge(delta);
+    }
 }
 
-#[cfg(test)]
+// The tests add through `add`, which reads the std clock
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -340,6 +523,118 @@ mod tests {
         assert!(!set.contains(&"banana".to_string()));
     }
 
+    fn elements(set: &ORSet<String>) -> Vec<String> {
+        let mut elements: Vec<String> = set.iter().cloned().collect();
+        elements.sort();
+        elements
+    }
+
+    /// Three replicas share {a, b}; r1 clears while r2 adds b again and
+    /// r3 adds c, none having seen the others' changes
+    fn concurrent_clear() -> [ORSet<String>; 3] {
+        let mut r1 = ORSet::new("r1".to_string());
+        r1.add("a".to_string());
+        r1.add("b".to_string());
+        let mut r2 = ORSet::new("r2".to_string());
+        let mut r3 = ORSet::new("r3".to_string());
+        r2.merge(&r1);
+        r3.merge(&r1);
+
+        r1.clear();
+        r2.add("b".to_string());
+        r3.add("c".to_string());
+        [r1, r2, r3]
+    }
+
+    #[test]
+    fn test_clear_keeps_concurrent_adds_in_any_merge_order() {
+        let [r1, r2, r3] = concurrent_clear();
+        let orders: [[&ORSet<String>; 3]; 4] = [
+            [&r1, &r2, &r3],
+            [&r3, &r2, &r1],
+            [&r2, &r1, &r3],
+            [&r3, &r1, &r2],
+        ];
+        for order in orders {
+            let mut merged = order[0].clone();
+            merged.merge(order[1]);
+            merged.merge(order[2]);
+            // The observed a and b are gone; the unseen adds survive
+            assert_eq!(elements(&merged), vec!["b", "c"]);
+        }
+
+        // Deltas converge to the same state
+        let [mut r1, mut r2, mut r3] = concurrent_clear();
+        let deltas = [r1.split_delta(), r2.split_delta(), r3.split_delta()];
+        for replica in [&mut r1, &mut r2, &mut r3] {
+            for delta in deltas.iter().rev() {
+                replica.apply_delta(delta);
+            }
+            assert_eq!(elements(replica), vec!["b", "c"]);
+        }
+    }
+
+    #[test]
+    fn test_reset_drops_concurrent_adds_in_any_merge_order() {
+        let [mut r1, r2, r3] = concurrent_clear();
+        r1.reset();
+        let mut expected = r1.clone();
+        expected.merge(&r2);
+        expected.merge(&r3);
+        assert!(expected.is_empty());
+
+        for (first, second) in [(&r2, &r3), (&r3, &r2)] {
+            let mut merged = first.clone();
+            merged.merge(&r1);
+            merged.merge(second);
+            assert!(merged.is_empty());
+
+            // Adds made after seeing the reset are kept
+            merged.add("d".to_string());
+            let mut other = second.clone();
+            other.merge(&merged);
+            other.merge(&r1);
+            assert_eq!(elements(&other), vec!["d"]);
+        }
+
+        // The reset travels in deltas too, and old tags are forgotten
+        let mut r2 = r2;
+        let mut r1 = ORSet::new("r1".to_string());
+        r1.add("x".to_string());
+        r2.merge(&r1);
+        r1.split_delta();
+        r1.reset();
+        r2.apply_delta(&r1.split_delta());
+        assert!(r2.is_empty());
+        assert!(r2.elements.is_empty() && r2.removed_tags.is_empty());
+    }
+
+    #[test]
+    fn test_split_delta() {
+        let mut set1 = ORSet::new("replica1".to_string());
+        let mut set2 = ORSet::new("replica2".to_string());
+
+        set1.add("apple".to_string());
+        set1.add("banana".to_string());
+        set2.apply_delta(&set1.split_delta());
+
+        set1.remove(&"apple".to_string());
+        let delta = set1.split_delta();
+        set2.apply_delta(&delta);
+
+        assert!(!set2.contains(&"apple".to_string()));
+        assert!(set2.contains(&"banana".to_string()));
+
+        // Redelivery is idempotent
+        set2.apply_delta(&delta);
+        assert_eq!(set2.len(), 1);
+
+        // Nothing changed since the last split
+        let empty = set1.split_delta();
+        assert!(empty.elements.is_empty());
+        assert!(empty.removed_tags.is_empty());
+    }
+
     #[test]
     fn test_iter() {
         let mut set = ORSet::new("replica1".to_string());
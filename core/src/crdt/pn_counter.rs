@@ -26,16 +26,23 @@
 //!
 //! assert_eq!(counter1.value(), 8);
 //! ```
+//!
+//! # Delta-state sync
+//!
+//! Instead of shipping the whole state on every change, a replica can hand
+//! out only what changed locally since the last call to `split_delta`.
+//! Deltas are themselves counters, so applying one is just a merge and
+//! redelivering it is harmless.
 
+use crate::collections::HashMap;
 use crate::ClientID;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
 /// Positive-Negative Counter CRDT
 ///
 /// Tracks increments and decrements across multiple replicas.
 /// Each replica maintains its own positive and negative counters.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PNCounter {
     /// Replica identifier
     replica_id: ClientID,
@@ -45,6 +52,19 @@ pub struct PNCounter {
 
     /// Negative counters (decrements) per replica
     negative: HashMap<ClientID, i64>,
+
+    /// Whether local changes happened since the last `split_delta`
+    #[serde(skip)]
+    delta_pending: bool,
+}
+
+impl PartialEq for PNCounter {
+    fn eq(&self, other: &Self) -> bool {
+        // Pending delta bookkeeping is local, not part of the CRDT state
+        self.replica_id == other.replica_id
+            && self.positive == other.positive
+            && self.negative == other.negative
+    }
 }
 
 impl PNCounter {
@@ -61,6 +81,7 @@ impl PNCounter {
             replica_id,
             positive,
             negative,
+            delta_pending: false,
         }
     }
 
@@ -79,6 +100,7 @@ impl PNCounter {
         let current = self.positive.get(&self.replica_id).unwrap_or(&0);
         self.positive
             .insert(self.replica_id.clone(), current + amount);
+        self.delta_pending = true;
     }
 
     /// Decrement the counter by the given amount
@@ -96,6 +118,7 @@ impl PNCounter {
         let current = self.negative.get(&self.replica_id).unwrap_or(&0);
         self.negative
             .insert(self.replica_id.clone(), current + amount);
+        self.delta_pending = true;
     }
 
     /// Get the current counter value
@@ -125,6 +148,54 @@ impl PNCounter {
         }
     }
 
+    /// Take the changes made locally since the last call
+    ///
+    /// Returns a counter holding only this replica's entries, or an empty
+    /// counter if nothing changed. Send it to other replicas and apply it
+    /// there with `apply_delta`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use synckit_core::crdt::PNCounter;
+    ///
+    /// let mut counter1 = PNCounter::new("replica1".to_string());
+    /// let mut counter2 = PNCounter::new("replica2".to_string());
+    ///
+    /// counter1.increment(5);
+    /// counter2.apply_delta(&counter1.split_delta());
+    ///
+    /// assert_eq!(counter2.value(), 5);
+    /// ```
+    pub fn split_delta(&mut self) -> PNCounter {
+        let mut delta = PNCounter {
+            replica_id: self.replica_id.clone(),
+            positive: HashMap::new(),
+            negative: HashMap::new(),
+            delta_pending: false,
+        };
+
+        if core::mem::take(&mut self.delta_pending) {
+            for (counters, delta_counters) in [
+                (&self.positive, &mut delta.positive),
+                (&self.negative, &mut delta.negative),
+            ] {
+                if let Some(&count) = counters.get(&self.replica_id) {
+                    delta_counters.insert(self.replica_id.clone(), count);
+                }
+            }
+        }
+
+        delta
+    }
+
+    /// Apply a delta produced by `split_delta` on another replica
+    ///
+    /// Equivalent to `merge`, so duplicated or reordered deltas are safe.
+    pub fn apply_delta(&mut self, delta: &PNCounter) {
+        self.merge(delta);
+    }
+
     /// Get the replica ID
     pub fn replica_id(&self) -> &ClientID {
         &self.replica_id
@@ -278,6 +349,39 @@ mod tests {
         assert_eq!(counter.value(), 0);
     }
 
+    #[test]
+    fn test_split_delta() {
+        let mut counter1 = PNCounter::new("replica1".to_string());
+        let mut counter2 = PNCounter::new("replica2".to_string());
+
+        counter1.increment(10);
+        counter1.decrement(3);
+
+        let delta = counter1.split_delta();
+        counter2.apply_delta(&delta);
+        assert_eq!(counter2.value(), 7);
+
+        // Nothing changed since the last split
+        assert_eq!(counter1.split_delta().value(), 0);
+
+        // Redelivery is idempotent
+        counter2.apply_delta(&delta);
+        assert_eq!(counter2.value(), 7);
+    }
+
+    #[test]
+    fn test_split_delta_excludes_merged_state() {
+        let mut counter1 = PNCounter::new("replica1".to_string());
+        let mut counter2 = PNCounter::new("replica2".to_string());
+
+        counter2.increment(4);
+        counter1.merge(&counter2);
+        counter1.increment(1);
+
+        // Only replica1's own entries travel in its delta
+        assert_eq!(counter1.split_delta().value(), 1);
+    }
+
     #[test]
     #[should_panic(expected = "Increment amount must be non-negative")]
     fn test_increment_negative_panics() {
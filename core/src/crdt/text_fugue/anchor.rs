@@ -0,0 +1,184 @@
+//! Anchors: positions that follow the text through concurrent edits
+//!
+//! A position like "offset 5" goes stale as soon as a remote insert lands
+//! before it. An anchor instead names the character next to the position
+//! by its `NodeId`, so it resolves to the same logical spot after any
+//! merge. Anchors serialize with serde, so they can be shipped to other
+//! replicas (e.g. inside awareness state).
+
+use super::node::NodeId;
+use super::text::{FugueText, TextError};
+use serde::{Deserialize, Serialize};
+
+/// Which neighbouring character an anchor sticks to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnchorBias {
+    /// Stick to the character before the position: text inserted at the
+    /// anchor goes after it
+    Left,
+    /// Stick to the character after the position: text inserted at the
+    /// anchor goes before it
+    Right,
+}
+
+/// A stable position in a `FugueText`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Anchor {
+    /// The character the anchor sticks to; `None` is the start (left
+    /// bias) or end (right bias) of the text
+    #[serde(default)]
+    pub target: Option<NodeId>,
+    pub bias: AnchorBias,
+}
+
+impl FugueText {
+    /// Create an anchor at `position` (0..=len)
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::PositionOutOfBounds` if `position > len`
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::{AnchorBias, FugueText};
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "World").unwrap();
+    /// let anchor = text.create_anchor(2, AnchorBias::Right).unwrap();
+    ///
+    /// text.insert(0, "Hello ").unwrap();
+    /// assert_eq!(text.resolve_anchor(&anchor), Some(8));
+    /// ```
+    pub fn create_anchor(
+        &mut self,
+        position: usize,
+        bias: AnchorBias,
+    ) -> Result<Anchor, TextError> {
+        let length = self.len();
+        if position > length {
+            return Err(TextError::PositionOutOfBounds { position, length });
+        }
+
+        let target = match bias {
+            AnchorBias::Left if position == 0 => None,
+            AnchorBias::Left => Some(self.get_node_id_at_position(position - 1)?),
+            AnchorBias::Right if position == length => None,
+            AnchorBias::Right => Some(self.get_node_id_at_position(position)?),
+        };
+        Ok(Anchor { target, bias })
+    }
+
+    /// Current position of an anchor
+    ///
+    /// If the anchored character was deleted, the anchor snaps to where it
+    /// was, i.e. between the nearest live characters on either side.
+    /// Returns None if this replica has not seen the character yet, or
+    /// has garbage-collected its tombstone (see [`FugueText::gc`]).
+    pub fn resolve_anchor(&mut self, anchor: &Anchor) -> Option<usize> {
+        let Some(target) = &anchor.target else {
+            return Some(match anchor.bias {
+                AnchorBias::Left => 0,
+                AnchorBias::Right => self.len(),
+            });
+        };
+
+        let (position, visible) = self.locate_node_id(target)?;
+        Some(match anchor.bias {
+            AnchorBias::Left if visible => position + 1,
+            _ => position,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchor_survives_remote_prepend() {
+        let mut local = FugueText::new("alice".to_string());
+        local.insert(0, "Hello World").unwrap();
+        let anchor = local.create_anchor(5, AnchorBias::Right).unwrap();
+
+        let mut remote = FugueText::new("bob".to_string());
+        remote.merge(&local).unwrap();
+        remote.insert(0, &"x".repeat(100)).unwrap();
+        local.merge(&remote).unwrap();
+
+        assert_eq!(local.resolve_anchor(&anchor), Some(105));
+        assert_eq!(&local.to_string()[105..106], " ");
+    }
+
+    #[test]
+    fn test_bias_decides_side_of_insert_at_anchor() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "ab").unwrap();
+        let left = text.create_anchor(1, AnchorBias::Left).unwrap();
+        let right = text.create_anchor(1, AnchorBias::Right).unwrap();
+
+        text.insert(1, "XYZ").unwrap();
+        assert_eq!(text.resolve_anchor(&left), Some(1));
+        assert_eq!(text.resolve_anchor(&right), Some(4));
+    }
+
+    #[test]
+    fn test_anchor_snaps_when_character_deleted() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "Hello World").unwrap();
+        let left = text.create_anchor(8, AnchorBias::Left).unwrap();
+        let right = text.create_anchor(8, AnchorBias::Right).unwrap();
+
+        // Delete "o Wor", which covers both anchored characters
+        text.delete(4, 5).unwrap();
+        assert_eq!(text.to_string(), "Hellld");
+        assert_eq!(text.resolve_anchor(&left), Some(4));
+        assert_eq!(text.resolve_anchor(&right), Some(4));
+    }
+
+    #[test]
+    fn test_edge_anchors_and_unknown_targets() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "abc").unwrap();
+        let start = text.create_anchor(0, AnchorBias::Left).unwrap();
+        let end = text.create_anchor(3, AnchorBias::Right).unwrap();
+        assert!(start.target.is_none() && end.target.is_none());
+        assert!(text.create_anchor(4, AnchorBias::Left).is_err());
+
+        text.insert(3, "de").unwrap();
+        text.insert(0, "_").unwrap();
+        assert_eq!(text.resolve_anchor(&start), Some(0));
+        assert_eq!(text.resolve_anchor(&end), Some(6));
+
+        let mut other = FugueText::new("bob".to_string());
+        other.insert(0, "zzz").unwrap();
+        let foreign = other.create_anchor(1, AnchorBias::Right).unwrap();
+        assert_eq!(text.resolve_anchor(&foreign), None);
+    }
+
+    #[test]
+    fn test_anchor_to_collected_character_resolves_to_none() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "abc").unwrap();
+        text.insert(3, "def").unwrap();
+        let gone = text.create_anchor(4, AnchorBias::Right).unwrap();
+        let kept = text.create_anchor(2, AnchorBias::Left).unwrap();
+
+        text.delete(3, 3).unwrap();
+        assert_eq!(text.resolve_anchor(&gone), Some(3));
+        assert!(text.gc(&text.state_vector()) > 0);
+        assert_eq!(text.resolve_anchor(&gone), None);
+        assert_eq!(text.resolve_anchor(&kept), Some(2));
+    }
+
+    #[test]
+    fn test_anchor_serde_round_trip() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "abc").unwrap();
+        let anchor = text.create_anchor(1, AnchorBias::Left).unwrap();
+
+        let json = serde_json::to_string(&anchor).unwrap();
+        assert_eq!(serde_json::from_str::<Anchor>(&json).unwrap(), anchor);
+    }
+}
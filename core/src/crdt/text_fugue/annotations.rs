@@ -0,0 +1,155 @@
+//! Annotations on text edits (see [`crate::annotations`])
+//!
+//! Characters are covered by their insert clock, so an annotation follows
+//! the text it tags through concurrent edits and merges. Deletions don't
+//! record who made them and aren't tagged.
+
+use super::text::{FugueText, TextError};
+use crate::annotations::{Annotation, AnnotationRetention, Annotations};
+use crate::time::TimeProvider;
+use serde_json::Value as JsonValue;
+use std::ops::Range;
+
+impl FugueText {
+    /// Run `f` as one [`FugueText::transaction`] and tag the characters it
+    /// inserts with `payload`
+    ///
+    /// The annotation covers the clocks this replica hands out while `f`
+    /// runs.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `ANNOTATION_TOO_LARGE`, without running `f`, if the
+    /// payload is over [`crate::annotations::MAX_ANNOTATION_BYTES`]
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use serde_json::json;
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("alice".to_string());
+    /// text.insert(0, "Hello").unwrap();
+    /// text.annotated(json!({"source": "paste"}), |text| text.insert(5, " World"))
+    ///     .unwrap()
+    ///     .unwrap();
+    ///
+    /// assert!(text.annotations_for(0..5).unwrap().is_empty());
+    /// let tags = text.annotations_for(5..11).unwrap();
+    /// assert_eq!(tags[0].payload, json!({"source": "paste"}));
+    /// ```
+    pub fn annotated<R>(
+        &mut self,
+        payload: JsonValue,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> crate::Result<R> {
+        Annotation::check_payload(&payload)?;
+        let start = self.clock.value() + 1;
+        let result = self.transaction(f);
+        let end = self.clock.value();
+        if end >= start {
+            self.touch();
+            self.annotations.insert(Annotation {
+                client_id: self.client_id.to_string(),
+                start,
+                end,
+                payload,
+                created_ms: self.time_provider().now_ms(),
+            });
+        }
+        Ok(result)
+    }
+
+    /// The annotations on the characters at `range`, in the order they
+    /// are first met
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::RangeOutOfBounds` if the range ends past the
+    /// text
+    pub fn annotations_for(&mut self, range: Range<usize>) -> Result<Vec<&Annotation>, TextError> {
+        let length = self.len();
+        if range.end > length {
+            return Err(TextError::RangeOutOfBounds {
+                start: range.start,
+                end: range.end,
+                length,
+            });
+        }
+        let mut ids = Vec::with_capacity(range.len());
+        for position in range {
+            ids.push(self.get_node_id_at_position(position)?);
+        }
+
+        let mut found: Vec<&Annotation> = Vec::new();
+        for id in &ids {
+            if let Some(annotation) = self.annotations.covering(&id.client_id, id.clock) {
+                if !found.contains(&annotation) {
+                    found.push(annotation);
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Every annotation on the text
+    pub fn annotations(&self) -> &Annotations {
+        &self.annotations
+    }
+
+    /// Drop the annotations `retention` doesn't keep, timed by
+    /// [`FugueText::time_provider`]; returns how many were dropped
+    pub fn prune_annotations(&mut self, retention: &AnnotationRetention) -> usize {
+        let now_ms = self.time_provider().now_ms();
+        self.annotations.prune(retention, now_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::MAX_ANNOTATION_BYTES;
+    use serde_json::json;
+
+    #[test]
+    fn test_tag_travels_with_delta_and_merge() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        alice.insert(0, "one ").unwrap();
+        alice
+            .annotated(json!({"tag": "import"}), |text| {
+                text.insert(4, "two")?;
+                text.insert(7, " three")
+            })
+            .unwrap()
+            .unwrap();
+
+        bob.apply_delta(&alice.diff_since(&bob.state_vector()))
+            .unwrap();
+        bob.insert(0, ">").unwrap();
+        let tags = bob.annotations_for(5..12).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].payload, json!({"tag": "import"}));
+        assert!(bob.annotations_for(0..5).unwrap().is_empty());
+
+        let mut carol = FugueText::new("carol".to_string());
+        carol.merge(&bob).unwrap();
+        assert_eq!(carol.annotations(), alice.annotations());
+
+        let restored: FugueText =
+            serde_json::from_str(&serde_json::to_string(&carol).unwrap()).unwrap();
+        assert_eq!(restored.annotations(), alice.annotations());
+    }
+
+    #[test]
+    fn test_oversized_tag_is_refused() {
+        let mut text = FugueText::new("alice".to_string());
+        let payload = json!("x".repeat(MAX_ANNOTATION_BYTES));
+        let error = text
+            .annotated(payload, |text| text.insert(0, "never"))
+            .unwrap_err();
+        assert_eq!(error.code_name(), "ANNOTATION_TOO_LARGE");
+        assert_eq!(text.to_string(), "");
+        assert!(text.annotations().is_empty());
+    }
+}
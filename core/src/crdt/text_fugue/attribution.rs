@@ -0,0 +1,168 @@
+//! Who wrote each part of the text
+//!
+//! Every character keeps the ID its author gave it, so attribution is read
+//! straight off the live blocks: no history is needed, and replicas that
+//! merged the same edits attribute the same ranges.
+
+use super::text::FugueText;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+/// A run of visible text written by one client, as reported by
+/// [`FugueText::attributed_spans`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AttributedSpan<'a> {
+    /// Positions covered
+    pub range: Range<usize>,
+
+    /// The client that inserted the text
+    pub client_id: &'a str,
+
+    /// Clock of the first character of the run
+    pub clock: u64,
+}
+
+impl FugueText {
+    /// The visible text as runs by author, in order
+    ///
+    /// Neighbouring blocks from the same client are joined into one span,
+    /// so spans change author at every boundary.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut alice = FugueText::new("alice".to_string());
+    /// alice.insert(0, "Hello").unwrap();
+    /// let mut bob = FugueText::new("bob".to_string());
+    /// bob.merge(&alice).unwrap();
+    /// bob.insert(5, " World").unwrap();
+    ///
+    /// let spans = bob.attributed_spans();
+    /// assert_eq!(spans.len(), 2);
+    /// assert_eq!((spans[0].client_id, spans[0].range.clone()), ("alice", 0..5));
+    /// assert_eq!((spans[1].client_id, spans[1].range.clone()), ("bob", 5..11));
+    /// ```
+    pub fn attributed_spans(&self) -> Vec<AttributedSpan<'_>> {
+        let mut spans: Vec<AttributedSpan<'_>> = Vec::new();
+        let mut position = 0;
+
+        for id in self.get_document_order() {
+            let Some((id, block)) = self.blocks.get_key_value(&id) else {
+                continue;
+            };
+            let len = block.len();
+            if len == 0 {
+                continue;
+            }
+            let end = position + len;
+            match spans.last_mut() {
+                Some(span) if span.client_id == id.client_id => span.range.end = end,
+                _ => spans.push(AttributedSpan {
+                    range: position..end,
+                    client_id: &id.client_id,
+                    clock: id.clock + 1 - len as u64,
+                }),
+            }
+            position = end;
+        }
+
+        spans
+    }
+
+    /// The client that wrote the character at `position`, or None past the
+    /// end of the text
+    ///
+    /// O(log n) once the position cache is built.
+    pub fn author_at(&mut self, position: usize) -> Option<&str> {
+        if position >= self.len() {
+            return None;
+        }
+        if !self.cache_valid {
+            self.rebuild_position_cache();
+            self.cache_valid = true;
+        }
+
+        let index = self
+            .cached_blocks
+            .binary_search_by(|id| {
+                let block = &self.blocks[id];
+                let start = self.cached_position(id).unwrap_or(0);
+                if position < start {
+                    Ordering::Greater
+                } else if position >= start + block.len() {
+                    Ordering::Less
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .ok()?;
+        Some(&self.cached_blocks[index].client_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans_follow_blocks_across_three_clients() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "The quick fox").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        let mut carol = FugueText::new("carol".to_string());
+        bob.merge(&alice).unwrap();
+        carol.merge(&alice).unwrap();
+
+        // Splits alice's block on each side
+        bob.insert(10, "brown ").unwrap();
+        carol.delete(4, 6).unwrap();
+        carol.insert(4, "lazy ").unwrap();
+        alice.insert(13, "!").unwrap();
+
+        alice.merge(&bob).unwrap();
+        alice.merge(&carol).unwrap();
+        bob.merge(&alice).unwrap();
+        carol.merge(&alice).unwrap();
+        assert_eq!(alice.to_string(), "The brown lazy fox!");
+
+        let spans = alice.attributed_spans();
+        let authors: Vec<(&str, Range<usize>)> = spans
+            .iter()
+            .map(|span| (span.client_id, span.range.clone()))
+            .collect();
+        assert_eq!(
+            authors,
+            [
+                ("alice", 0..4),
+                ("bob", 4..10),
+                ("carol", 10..15),
+                ("alice", 15..19),
+            ]
+        );
+        let first = alice.clone().get_node_id_at_position(10).unwrap();
+        assert_eq!(spans[2].clock, first.clock);
+        assert_eq!(bob.attributed_spans(), spans);
+
+        // Every boundary is where a live block starts
+        let mut block_starts = vec![0];
+        for id in alice.get_document_order() {
+            let end = block_starts.last().unwrap() + alice.blocks[&id].len();
+            block_starts.push(end);
+        }
+        for span in &spans {
+            assert!(block_starts.contains(&span.range.start));
+            assert!(block_starts.contains(&span.range.end));
+        }
+
+        for span in alice.attributed_spans() {
+            let (client_id, range) = (span.client_id.to_string(), span.range);
+            for position in range {
+                assert_eq!(carol.author_at(position), Some(client_id.as_str()));
+            }
+        }
+        assert_eq!(carol.author_at(19), None);
+    }
+}
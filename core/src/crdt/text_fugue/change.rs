@@ -0,0 +1,284 @@
+//! Text changes as retain/insert/delete spans (the Quill delta format)
+//!
+//! The [`TextEvent`]s of a batch apply one after another, each at a
+//! position in the text the events before it left. A [`TextChange`] says
+//! the same in one pass over the text before the batch: keep so many
+//! units, insert this, delete so many. Editor views kept in step with a
+//! text usually take changes in this form.
+
+use super::delta::TextEvent;
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// One span of a [`TextChange`]
+///
+/// Serializes as Quill does: `{"retain": 5}`, `{"insert": "abc"}`,
+/// `{"delete": 2}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeSpan {
+    /// The next units are unchanged
+    Retain(usize),
+    /// Text inserted here
+    Insert(String),
+    /// The next units were deleted
+    Delete(usize),
+}
+
+/// A change to the text as spans in document order
+///
+/// Retained and deleted spans together walk the text as it was before,
+/// in the units of `FugueText::len()`. Inserts come before deletes where
+/// both are at one place, and unchanged text at the end is left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TextChange {
+    spans: Vec<ChangeSpan>,
+}
+
+/// Part of the text while the events are composed
+enum Piece {
+    Kept(usize),
+    Removed(usize),
+    Added(Vec<String>),
+}
+
+impl Piece {
+    /// Units this piece has in the text after
+    fn width(&self) -> usize {
+        match self {
+            Piece::Kept(length) => *length,
+            Piece::Removed(_) => 0,
+            Piece::Added(units) => units.len(),
+        }
+    }
+
+    /// Cut off and return everything from `at` units on
+    fn split_off(&mut self, at: usize) -> Piece {
+        match self {
+            Piece::Kept(length) => {
+                let rest = *length - at;
+                *length = at;
+                Piece::Kept(rest)
+            }
+            Piece::Removed(_) => unreachable!("removed pieces have no width to split"),
+            Piece::Added(units) => Piece::Added(units.split_off(at)),
+        }
+    }
+}
+
+/// The pieces of the text, starting as all of it kept
+struct Pieces(Vec<Piece>);
+
+impl Pieces {
+    /// Index of the piece starting at `position` in the text after,
+    /// splitting the piece `position` falls inside of
+    fn boundary(&mut self, position: usize) -> usize {
+        let mut start = 0;
+        for i in 0..self.0.len() {
+            let width = self.0[i].width();
+            if position == start {
+                return i;
+            }
+            if position < start + width {
+                let rest = self.0[i].split_off(position - start);
+                self.0.insert(i + 1, rest);
+                return i + 1;
+            }
+            start += width;
+        }
+        self.0.len()
+    }
+
+    fn apply(&mut self, event: &TextEvent) {
+        match event {
+            TextEvent::Insert { position, text } => {
+                let at = self.boundary(*position);
+                let units = text.graphemes(true).map(str::to_string).collect();
+                self.0.insert(at, Piece::Added(units));
+            }
+            TextEvent::Delete { position, length } => {
+                let start = self.boundary(*position);
+                let end = self.boundary(position + length);
+                for piece in &mut self.0[start..end] {
+                    match piece {
+                        Piece::Kept(length) => *piece = Piece::Removed(*length),
+                        Piece::Added(units) => units.clear(),
+                        Piece::Removed(_) => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl TextChange {
+    /// The change a batch of events makes, applied in order
+    ///
+    /// Inserted text is split into units by grapheme cluster.
+    pub fn from_events(events: &[TextEvent]) -> Self {
+        let mut pieces = Pieces(Vec::new());
+        // Past the end of every event, so any position splits it
+        let reach = events
+            .iter()
+            .map(|event| match event {
+                TextEvent::Insert { position, .. } => *position,
+                TextEvent::Delete { position, length } => position + length,
+            })
+            .sum::<usize>();
+        pieces.0.push(Piece::Kept(reach));
+
+        for event in events {
+            pieces.apply(event);
+        }
+
+        let mut change = TextChange::default();
+        // Inserts and deletes between two retains, joined
+        let (mut inserted, mut deleted) = (String::new(), 0);
+        for piece in pieces.0 {
+            match piece {
+                Piece::Kept(0) => {}
+                Piece::Kept(length) => {
+                    change.flush(&mut inserted, &mut deleted);
+                    match change.spans.last_mut() {
+                        Some(ChangeSpan::Retain(retained)) => *retained += length,
+                        _ => change.spans.push(ChangeSpan::Retain(length)),
+                    }
+                }
+                Piece::Removed(length) => deleted += length,
+                Piece::Added(units) => inserted.extend(units),
+            }
+        }
+        change.flush(&mut inserted, &mut deleted);
+        if let Some(ChangeSpan::Retain(_)) = change.spans.last() {
+            change.spans.pop();
+        }
+        change
+    }
+
+    fn flush(&mut self, inserted: &mut String, deleted: &mut usize) {
+        if !inserted.is_empty() {
+            self.spans
+                .push(ChangeSpan::Insert(std::mem::take(inserted)));
+        }
+        if *deleted > 0 {
+            self.spans.push(ChangeSpan::Delete(std::mem::take(deleted)));
+        }
+    }
+
+    /// A change of spans already in order, joined as `from_events` does
+    pub(super) fn from_spans(spans: Vec<ChangeSpan>) -> Self {
+        let mut change = TextChange { spans };
+        if let Some(ChangeSpan::Retain(_)) = change.spans.last() {
+            change.spans.pop();
+        }
+        change
+    }
+
+    /// The spans, in document order
+    pub fn spans(&self) -> &[ChangeSpan] {
+        &self.spans
+    }
+
+    /// The change as `FugueText::splice` arguments, `(position,
+    /// delete_len, text)`, to apply one after another
+    pub fn splices(&self) -> Vec<(usize, usize, &str)> {
+        let mut splices: Vec<(usize, usize, &str)> = Vec::new();
+        let mut position = 0;
+        for span in &self.spans {
+            match span {
+                ChangeSpan::Retain(length) => position += length,
+                ChangeSpan::Insert(text) => {
+                    splices.push((position, 0, text));
+                    position += text.graphemes(true).count();
+                }
+                ChangeSpan::Delete(length) => match splices.last_mut() {
+                    // A delete right after an insert goes in its splice
+                    Some(last)
+                        if last.1 == 0 && last.0 + last.2.graphemes(true).count() == position =>
+                    {
+                        last.1 = *length;
+                    }
+                    _ => splices.push((position, *length, "")),
+                },
+            }
+        }
+        splices
+    }
+
+    /// True if the change leaves the text as it was
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Apply the change to `text`, counting its units by grapheme cluster
+    pub fn apply(&self, text: &str) -> String {
+        let mut units = text.graphemes(true);
+        let mut result = String::with_capacity(text.len());
+        for span in &self.spans {
+            match span {
+                ChangeSpan::Retain(length) => result.extend(units.by_ref().take(*length)),
+                ChangeSpan::Insert(inserted) => result.push_str(inserted),
+                ChangeSpan::Delete(length) => {
+                    units.by_ref().take(*length).for_each(drop);
+                }
+            }
+        }
+        result.extend(units);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(position: usize, text: &str) -> TextEvent {
+        TextEvent::Insert {
+            position,
+            text: text.to_string(),
+        }
+    }
+
+    fn delete(position: usize, length: usize) -> TextEvent {
+        TextEvent::Delete { position, length }
+    }
+
+    #[test]
+    fn test_events_compose_into_spans() {
+        let change = TextChange::from_events(&[insert(5, ","), delete(7, 5), insert(7, "there")]);
+        assert_eq!(
+            change.spans(),
+            [
+                ChangeSpan::Retain(5),
+                ChangeSpan::Insert(",".to_string()),
+                ChangeSpan::Retain(1),
+                ChangeSpan::Insert("there".to_string()),
+                ChangeSpan::Delete(5),
+            ]
+        );
+        assert_eq!(change.apply("Hello World!"), "Hello, there!");
+    }
+
+    #[test]
+    fn test_deleting_inserted_text_cancels_out() {
+        let change = TextChange::from_events(&[insert(2, "abc"), delete(3, 1), delete(0, 3)]);
+        assert_eq!(
+            change.spans(),
+            [ChangeSpan::Insert("c".to_string()), ChangeSpan::Delete(2)]
+        );
+        assert_eq!(change.apply("xyz"), "cz");
+
+        let none = TextChange::from_events(&[insert(1, "ab"), delete(1, 2)]);
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn test_change_serializes_like_quill() {
+        let change = TextChange::from_events(&[insert(1, "👋🏽"), delete(2, 1)]);
+        assert_eq!(
+            serde_json::to_string(&change).unwrap(),
+            r#"[{"retain":1},{"insert":"👋🏽"},{"delete":1}]"#
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! One copy of each client ID per text
+//!
+//! Every block ID, origin and deletion stamp names a client, so with
+//! UUID client IDs the strings would outweigh the rest of a block's
+//! metadata. A [`ClientId`] is shared instead of copied, and each text
+//! keeps a table of the ones it holds: its own edits reuse its own ID,
+//! and blocks that arrive from elsewhere (merges, deltas, remote
+//! operations, loaded state) are interned against the table as they are
+//! stored, since deserializing allocates a fresh string per ID.
+
+use super::block::FugueBlock;
+use super::node::{ClientId, NodeId};
+use super::text::FugueText;
+use std::collections::BTreeSet;
+use std::mem::size_of;
+use std::sync::Arc;
+
+/// The client IDs a text holds, one allocation each
+#[derive(Debug, Clone, Default)]
+pub(super) struct ClientTable(BTreeSet<ClientId>);
+
+impl ClientTable {
+    /// The table's copy of `client`, added if it is new
+    pub(super) fn intern(&mut self, client: &ClientId) -> ClientId {
+        match self.0.get(client.as_str()) {
+            Some(known) => known.clone(),
+            None => {
+                self.0.insert(client.clone());
+                client.clone()
+            }
+        }
+    }
+
+    /// `id`, naming its client with the table's copy
+    pub(super) fn intern_node(&mut self, id: &NodeId) -> NodeId {
+        NodeId {
+            client_id: self.intern(&id.client_id),
+            ..id.clone()
+        }
+    }
+
+    /// Intern every ID in `block`
+    pub(super) fn intern_block(&mut self, block: &mut FugueBlock) {
+        block.id.client_id = self.intern(&block.id.client_id);
+        for id in [
+            &mut block.left_origin,
+            &mut block.right_origin,
+            &mut block.deleted_at,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            id.client_id = self.intern(&id.client_id);
+        }
+    }
+
+    /// Number of distinct clients
+    pub(super) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Heap bytes of the table and the strings it shares out: each
+    /// string once, with its reference counts
+    pub(super) fn heap_size(&self) -> usize {
+        self.0
+            .iter()
+            .map(|client| size_of::<ClientId>() + 2 * size_of::<usize>() + client.len())
+            .sum()
+    }
+}
+
+impl FugueText {
+    /// Store a block from another replica, interning its client IDs
+    pub(super) fn store_remote_block(&mut self, mut block: FugueBlock) {
+        self.clients.intern_block(&mut block);
+        self.blocks_mut().insert(block.id.clone(), Arc::new(block));
+    }
+
+    /// Take on the deletion of `remote`, a copy of the block stored under
+    /// `id`; returns the stored block's length, or None if there is none
+    pub(super) fn merge_remote_deletion(
+        &mut self,
+        id: &NodeId,
+        remote: &FugueBlock,
+    ) -> Option<u64> {
+        let stamp = remote
+            .deleted_at
+            .as_ref()
+            .map(|stamp| self.clients.intern_node(stamp));
+        if !remote.is_deleted() {
+            return self.blocks.get(id).map(|local| local.len() as u64);
+        }
+        let local = self.block_mut(id)?;
+        match stamp {
+            Some(stamp) => local.mark_deleted_at(stamp),
+            None => local.mark_deleted(),
+        }
+        Some(local.len() as u64)
+    }
+
+    /// Intern every ID of a text just built from serialized parts
+    pub(super) fn intern_all_clients(&mut self) {
+        self.client_id = self.clients.intern(&self.client_id);
+        let blocks = std::mem::take(self.blocks_mut());
+        let blocks = blocks
+            .into_values()
+            .map(|block| {
+                let mut block = Arc::unwrap_or_clone(block);
+                self.clients.intern_block(&mut block);
+                (block.id.clone(), Arc::new(block))
+            })
+            .collect();
+        *self.blocks_mut() = blocks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "6f1c2a7e-93d4-4b8e-a1f0-2c5d9e7b3a61";
+    const BOB: &str = "0d9b8c4f-1e2a-4f6b-9c3d-7a8e5b2f1c04";
+
+    /// Whether every ID in the text shares one string per client
+    fn fully_interned(text: &FugueText) -> bool {
+        let mut table = ClientTable::default();
+        table.intern(&text.client_id);
+        text.blocks.iter().all(|(key, block)| {
+            [
+                Some(key),
+                Some(&block.id),
+                block.left_origin.as_ref(),
+                block.right_origin.as_ref(),
+                block.deleted_at.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .all(|id| table.intern(&id.client_id).shares_with(&id.client_id))
+        })
+    }
+
+    #[test]
+    fn test_merged_and_loaded_ids_are_shared() {
+        let mut alice = FugueText::new(ALICE.to_string());
+        let mut bob = FugueText::new(BOB.to_string());
+        for i in 0..20 {
+            alice.insert(i % 3, "a").unwrap();
+            bob.insert(0, "b").unwrap();
+        }
+        bob.delete(0, 5).unwrap();
+
+        // A round trip through JSON gives every ID its own string
+        let mut bob: FugueText =
+            serde_json::from_str(&serde_json::to_string(&bob).unwrap()).unwrap();
+        assert!(fully_interned(&bob));
+
+        let delta = bob.diff_since(&alice.state_vector());
+        let delta = serde_json::from_str(&serde_json::to_string(&delta).unwrap()).unwrap();
+        alice.apply_delta(&delta).unwrap();
+        alice.delete(3, 10).unwrap();
+        bob.merge(&alice).unwrap();
+
+        assert_eq!(alice.to_string(), bob.to_string());
+        assert!(fully_interned(&alice));
+        assert!(fully_interned(&bob));
+        assert_eq!(alice.clients.len(), 2);
+    }
+
+    #[test]
+    fn test_metadata_does_not_grow_with_id_length() {
+        let metadata = |client: &str| {
+            let mut text = FugueText::new(client.to_string());
+            for _ in 0..100 {
+                text.insert(0, "x").unwrap();
+            }
+            let json = serde_json::to_string(&text).unwrap();
+            let text: FugueText = serde_json::from_str(&json).unwrap();
+            assert_eq!(text.memory_stats().block_count, 100);
+            text.memory_stats().metadata_bytes
+        };
+
+        // One copy of the ID for all 100 blocks and their origins
+        assert_eq!(metadata(ALICE) - metadata("a"), ALICE.len() - 1);
+    }
+}
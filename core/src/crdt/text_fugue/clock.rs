@@ -0,0 +1,95 @@
+//! Lamport clock for Fugue character IDs
+
+use serde::{Deserialize, Serialize};
+
+/// Lamport timestamp for causality tracking
+///
+/// Lamport clocks provide a "happens-before" partial ordering of events
+/// in a distributed system. Each replica maintains its own clock and
+/// increments it on local operations.
+///
+/// # Properties
+///
+/// - Monotonically increasing: clock never decreases
+/// - Always > 0: clock starts at 1 (0 reserved for initial state)
+/// - Update on merge: clock = max(local, remote) + 1
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::crdt::text_fugue::LamportClock;
+///
+/// let mut clock = LamportClock::new();
+/// assert_eq!(clock.value(), 0);
+///
+/// let ts1 = clock.tick();
+/// assert_eq!(ts1, 1);
+///
+/// clock.update(5);  // Merge from remote
+/// let ts2 = clock.tick();
+/// assert_eq!(ts2, 6);  // max(1, 5) + 1
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LamportClock {
+    value: u64,
+}
+
+impl LamportClock {
+    /// Create a new Lamport clock starting at 0
+    pub fn new() -> Self {
+        Self { value: 0 }
+    }
+
+    /// Get the current clock value
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    /// Increment clock and return new value (for local operations)
+    pub fn tick(&mut self) -> u64 {
+        self.value += 1;
+        self.value
+    }
+
+    /// Tick by N values (for per-character clock allocation)
+    ///
+    /// This method increments the clock by N values instead of 1, enabling
+    /// per-character clock allocation for RLE blocks. Each character in a block
+    /// gets its own unique clock value.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Number of clock values to allocate
+    ///
+    /// # Returns
+    ///
+    /// The new clock value after incrementing by count
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::LamportClock;
+    ///
+    /// let mut clock = LamportClock::new();
+    /// let ts = clock.tick_by(5);  // Allocate 5 clock values
+    /// assert_eq!(ts, 5);
+    /// assert_eq!(clock.value(), 5);
+    /// ```
+    pub fn tick_by(&mut self, count: usize) -> u64 {
+        self.value += count as u64;
+        self.value
+    }
+
+    /// Update clock from remote timestamp (for merge operations)
+    ///
+    /// Sets clock to max(local, remote) to maintain causality
+    pub fn update(&mut self, remote: u64) {
+        self.value = self.value.max(remote);
+    }
+}
+
+impl Default for LamportClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,953 @@
+//! TextDelta: State-vector based incremental sync for FugueText
+//!
+//! `FugueText::merge` needs the whole remote replica, which is too much to
+//! ship over the network when only a few characters changed. Delta sync
+//! works like Yjs updates instead:
+//!
+//! 1. The receiver sends its state vector (highest insert clock seen per client)
+//! 2. The sender replies with the blocks above that frontier plus its delete set
+//! 3. The receiver integrates the blocks and applies the deletions
+//!
+//! Deletions don't advance a client's insert frontier, so the delete set is
+//! always sent in full. It is coalesced into clock ranges, one or more per
+//! deletion, which keeps it small even for documents with a long editing
+//! history.
+
+use super::block::FugueBlock;
+use super::marks::Mark;
+use super::node::{ClientId, NodeId};
+use super::snapshot::TextSnapshot;
+use super::text::{FugueText, TextError};
+use crate::annotations::Annotation;
+use crate::memory::HeapSize;
+use crate::sync::{ChangeOrigin, VectorClock};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A contiguous range of deleted characters inserted by one client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeleteRange {
+    /// Client that inserted the deleted characters
+    pub client_id: ClientId,
+
+    /// First deleted clock value (inclusive)
+    pub start: u64,
+
+    /// Last deleted clock value (inclusive)
+    pub end: u64,
+
+    /// Stamp of the deletion, if known (see `FugueBlock::deleted_at`)
+    #[serde(default)]
+    pub deleted_at: Option<NodeId>,
+}
+
+/// Blocks and deletions a remote replica hasn't seen yet
+///
+/// Produced by [`FugueText::diff_since`] and consumed by
+/// [`FugueText::apply_delta`]. Applying the same delta twice is a no-op.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextDelta {
+    /// Blocks whose insert clock is above the remote state vector
+    pub blocks: Vec<FugueBlock>,
+
+    /// Every deleted clock range known to the sender
+    pub deleted: Vec<DeleteRange>,
+
+    /// Sender's Lamport clock (keeps receiver clocks ahead of the sender)
+    pub clock: u64,
+
+    /// Annotations on the blocks' clocks (see [`crate::annotations`])
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+
+    /// Formatting marks whose clock is above the remote state vector
+    #[serde(default)]
+    pub marks: Vec<Mark>,
+}
+
+impl TextDelta {
+    /// Check if the delta carries no blocks, deletions, annotations or
+    /// marks
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+            && self.deleted.is_empty()
+            && self.annotations.is_empty()
+            && self.marks.is_empty()
+    }
+}
+
+/// What the last `take_dirty` handed out
+///
+/// A loaded text computes its baseline lazily (`stale`), on the first
+/// change or `take_dirty`, so loading and replaying chunks stay O(n).
+#[derive(Debug, Clone, Default)]
+pub(super) struct Persisted {
+    state_vector: VectorClock,
+    deleted: Vec<DeleteRange>,
+    dirty: bool,
+    stale: bool,
+}
+
+impl Persisted {
+    /// Baseline for a text just deserialized from a snapshot
+    pub(super) fn loaded() -> Self {
+        Self {
+            stale: true,
+            ..Self::default()
+        }
+    }
+
+    fn of(text: &FugueText) -> Self {
+        Self {
+            state_vector: text.state_vector(),
+            deleted: text.delete_set(),
+            dirty: false,
+            stale: false,
+        }
+    }
+}
+
+/// A change to the visible text
+///
+/// Positions and lengths are in the same units as `FugueText::len()`.
+/// Events are ordered so they can be applied one after another to a copy
+/// of the text taken before the change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TextEvent {
+    /// Text was inserted at `position`
+    Insert { position: usize, text: String },
+
+    /// `length` characters were removed starting at `position`
+    Delete { position: usize, length: usize },
+}
+
+impl TextEvent {
+    /// Compute the events that turn `before` into `after`
+    ///
+    /// Uses a common prefix/suffix scan, so concurrent changes at several
+    /// places collapse into a single delete + insert covering all of them.
+    /// Positions count the grapheme clusters of each string, which is what
+    /// `FugueText::len()` counts unless a cluster was typed in parts.
+    pub fn diff(before: &str, after: &str) -> Vec<TextEvent> {
+        let before: Vec<&str> = before.graphemes(true).collect();
+        let after: Vec<&str> = after.graphemes(true).collect();
+        Self::diff_units(&before, &after)
+    }
+
+    /// The events that turn one snapshot into another, in their units
+    pub(super) fn between(before: &TextSnapshot, after: &TextSnapshot) -> Vec<TextEvent> {
+        let (before_text, after_text) = (before.to_string(), after.to_string());
+        let before: Vec<&str> = before.units(&before_text).collect();
+        let after: Vec<&str> = after.units(&after_text).collect();
+        Self::diff_units(&before, &after)
+    }
+
+    fn diff_units(before: &[&str], after: &[&str]) -> Vec<TextEvent> {
+        let prefix = before
+            .iter()
+            .zip(after.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = before[prefix..]
+            .iter()
+            .rev()
+            .zip(after[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut events = Vec::new();
+
+        let deleted = before.len() - prefix - suffix;
+        if deleted > 0 {
+            events.push(TextEvent::Delete {
+                position: prefix,
+                length: deleted,
+            });
+        }
+
+        let inserted: String = after[prefix..after.len() - suffix].concat();
+        if !inserted.is_empty() {
+            events.push(TextEvent::Insert {
+                position: prefix,
+                text: inserted,
+            });
+        }
+
+        events
+    }
+}
+
+/// The characters of block `id` after clock `seen` of its client, as a
+/// block of their own anchored after the character at `seen`
+fn unseen_part(id: &NodeId, block: &FugueBlock, seen: u64) -> FugueBlock {
+    let len = block.len() as u64;
+    let first = (id.clock + 1).saturating_sub(len);
+    if seen < first {
+        return block.clone();
+    }
+    let offset = (seen + 1 - first) as usize;
+    let text = match block.one_unit_per_byte() {
+        true => block.text[offset..].to_string(),
+        false => block.text.graphemes(true).skip(offset).collect(),
+    };
+    let left_origin = NodeId::new(id.client_id.clone(), seen, 0);
+    let mut unseen = FugueBlock::new(
+        id.clone(),
+        text,
+        Some(left_origin),
+        block.right_origin.clone(),
+    );
+    unseen.merge_deletion(block);
+    unseen
+}
+
+impl FugueText {
+    /// Get the state vector: the highest insert (or mark) clock seen from
+    /// each client
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello").unwrap();
+    ///
+    /// assert_eq!(text.state_vector().get("client1"), 5);
+    /// ```
+    pub fn state_vector(&self) -> VectorClock {
+        // Inserts whose tombstones `gc` removed were seen all the same
+        let mut state_vector = self.collected.clone();
+        let marks = self
+            .marks
+            .iter()
+            .map(|mark| (mark.client_id.as_str(), mark.clock));
+        for (client_id, clock) in self
+            .blocks
+            .keys()
+            .map(|id| (id.client_id.as_str(), id.clock))
+            .chain(marks)
+        {
+            if clock > state_vector.get(client_id) {
+                state_vector.update(client_id, clock);
+            }
+        }
+        state_vector
+    }
+
+    /// Fast-forward our clock past every insert of ours that `remote` has
+    /// seen
+    ///
+    /// A replica restored from an old snapshot starts with the clock it had
+    /// back then, so its next inserts would reuse IDs it already handed
+    /// out. Call this with the server's state vector (from the sync
+    /// handshake) before editing; `merge` and `apply_delta` do the same
+    /// for everything they receive. Once we edit, our own state vector
+    /// jumps past the inserts we are still missing, so request them with
+    /// the state vector sent in the handshake.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    /// use synckit_core::sync::VectorClock;
+    ///
+    /// let mut text = FugueText::new("alice".to_string());
+    /// let mut server = VectorClock::new();
+    /// server.update("alice", 42);
+    ///
+    /// text.observe_state_vector(&server);
+    /// assert_eq!(text.insert(0, "a").unwrap().clock, 43);
+    /// ```
+    pub fn observe_state_vector(&mut self, remote: &VectorClock) {
+        let own = remote.get(&self.client_id);
+        if own > self.clock.value() {
+            trace_debug!(from = self.clock.value(), to = own, "fast-forwarding clock");
+            self.clock.update(own);
+        }
+    }
+
+    /// Compute the delta a replica with the given state vector is missing
+    ///
+    /// A block the replica has the start of (typing extends blocks in
+    /// place) is cut down to the characters it hasn't seen, as a split
+    /// there would leave them.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text1 = FugueText::new("client1".to_string());
+    /// let mut text2 = FugueText::new("client2".to_string());
+    ///
+    /// text1.insert(0, "Hello").unwrap();
+    ///
+    /// let delta = text1.diff_since(&text2.state_vector());
+    /// text2.apply_delta(&delta).unwrap();
+    ///
+    /// assert_eq!(text2.to_string(), "Hello");
+    /// ```
+    pub fn diff_since(&self, remote: &VectorClock) -> TextDelta {
+        let blocks = self
+            .blocks
+            .iter()
+            .filter(|(id, _)| id.clock > remote.get(&id.client_id))
+            .map(|(id, block)| unseen_part(id, block, remote.get(&id.client_id)))
+            .collect();
+
+        let annotations = self
+            .annotations
+            .iter()
+            .filter(|annotation| annotation.end > remote.get(&annotation.client_id))
+            .cloned()
+            .collect();
+
+        let marks = self
+            .marks
+            .iter()
+            .filter(|mark| mark.clock > remote.get(&mark.client_id))
+            .cloned()
+            .collect();
+
+        TextDelta {
+            blocks,
+            deleted: self.delete_set(),
+            clock: self.clock.value(),
+            annotations,
+            marks,
+        }
+    }
+
+    /// Apply a delta produced by `diff_since` on another replica
+    ///
+    /// The delta is validated before anything is changed, so a malformed
+    /// delta leaves the replica untouched.
+    ///
+    /// # Returns
+    ///
+    /// The changes to the visible text caused by the delta
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::InvalidDelta` if a block or range is malformed,
+    /// `TextError::ReplicaConflict` if a block reuses one of our
+    /// character IDs for different text, and `TextError::LimitExceeded`
+    /// if the new blocks would take the text past its limits
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "FugueText::apply_delta",
+            skip_all,
+            fields(
+                client_id = %self.client_id,
+                blocks = delta.blocks.len(),
+                deleted_ranges = delta.deleted.len(),
+                rope = tracing::field::Empty,
+            )
+        )
+    )]
+    pub fn apply_delta(&mut self, delta: &TextDelta) -> Result<Vec<TextEvent>, TextError> {
+        self.touch();
+        let events = self.integrate_delta(delta)?;
+        self.notifier.extend(events.iter().cloned());
+        Ok(events)
+    }
+
+    pub(super) fn integrate_delta(
+        &mut self,
+        delta: &TextDelta,
+    ) -> Result<Vec<TextEvent>, TextError> {
+        // 1. Validate (clocks start at 1, so a block can't hold more
+        //    characters than its end clock)
+        for block in &delta.blocks {
+            let block_len = block.len() as u64;
+            if block_len > block.id.clock {
+                return Err(TextError::InvalidDelta(format!(
+                    "block {} has {} characters but ends at clock {}",
+                    block.id, block_len, block.id.clock
+                )));
+            }
+        }
+        for range in &delta.deleted {
+            if range.start > range.end {
+                return Err(TextError::InvalidDelta(format!(
+                    "delete range {}..={} for {} is reversed",
+                    range.start, range.end, range.client_id
+                )));
+            }
+        }
+        self.check_replica_conflicts(&delta.blocks)?;
+        self.check_remote_limits(delta.blocks.iter().map(|block| (&block.id, block)))?;
+
+        let before = self.snapshot();
+        let visible = self.visible_runs();
+
+        // 2. Integrate blocks we haven't seen
+        let mut integrated = Vec::new();
+        for block in &delta.blocks {
+            match self.merge_remote_deletion(&block.id, block) {
+                Some(_) => {}
+                None => {
+                    let block_len = block.len() as u64;
+                    let start = block.id.clock.saturating_sub(block_len.saturating_sub(1));
+                    let overlaps = block_len > 0
+                        && self.overlaps_local_clock_range(
+                            &block.id.client_id,
+                            start,
+                            block.id.clock,
+                        );
+                    if !overlaps
+                        && block_len > 0
+                        && block.id.clock <= self.collected.get(&block.id.client_id)
+                    {
+                        trace_debug!(block = %block.id, "dropping a collected tombstone");
+                    } else if !overlaps {
+                        trace_debug!(block = %block.id, len = block_len, "integrating remote block");
+                        self.store_remote_block(block.clone());
+                        integrated.push(block.id.clone());
+                    } else if let Some(tail) = self.missing_tail(&block.id, block) {
+                        trace_debug!(block = %block.id, len = tail.len(), "integrating the new tail of a re-chunked block");
+                        self.store_remote_block(tail);
+                        integrated.push(block.id.clone());
+                    }
+                }
+            }
+        }
+
+        // 3. Split blocks the new ones were inserted into the middle of, then
+        //    apply the delete set (splits local blocks where needed)
+        self.split_at_new_block_origins(&integrated);
+        for range in &delta.deleted {
+            self.propagate_clock_range_deletion(
+                &range.client_id,
+                range.start,
+                range.end,
+                range.deleted_at.as_ref(),
+            );
+        }
+
+        // 4. Keep our Lamport clock ahead of everything we've seen,
+        //    deletions included
+        let max_block_clock = delta.blocks.iter().map(|b| b.id.clock).max().unwrap_or(0);
+        let max_mark_clock = delta.marks.iter().map(|m| m.clock).max().unwrap_or(0);
+        let max_deletion_clock = delta
+            .blocks
+            .iter()
+            .filter_map(|b| b.deleted_at.as_ref())
+            .chain(delta.deleted.iter().filter_map(|r| r.deleted_at.as_ref()))
+            .map(|stamp| stamp.clock)
+            .max()
+            .unwrap_or(0);
+        self.clock.update(
+            delta
+                .clock
+                .max(max_block_clock)
+                .max(max_mark_clock)
+                .max(max_deletion_clock),
+        );
+        self.annotations.extend(delta.annotations.iter().cloned());
+        self.marks.extend(delta.marks.iter().cloned());
+
+        Ok(self
+            .update_rope(visible)
+            .unwrap_or_else(|| TextEvent::between(&before, &self.snapshot())))
+    }
+
+    /// Apply a delta and tag the resulting events with their origin
+    ///
+    /// The delta counts as ours when every block in it was inserted by
+    /// this replica (deletions don't record who made them, so they don't
+    /// decide). Our own delta coming back from the server changes nothing
+    /// and is tagged `ChangeOrigin::Echo`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::InvalidDelta` if a block or range is malformed
+    pub fn apply_delta_tagged(
+        &mut self,
+        delta: &TextDelta,
+    ) -> Result<(ChangeOrigin, Vec<TextEvent>), TextError> {
+        let ours = delta
+            .blocks
+            .iter()
+            .all(|block| block.id.client_id == self.client_id);
+
+        let events = self.apply_delta(delta)?;
+        let origin = if ours {
+            ChangeOrigin::classify(&self.client_id, &self.client_id, !events.is_empty())
+        } else {
+            ChangeOrigin::Remote
+        };
+        Ok((origin, events))
+    }
+
+    /// Check if anything may have changed since the last `take_dirty`
+    ///
+    /// O(1). Set by every edit, merge and applied delta, even one that
+    /// turns out to change nothing.
+    pub fn is_dirty(&self) -> bool {
+        self.persisted.dirty
+    }
+
+    /// Take the blocks and deletions added since the last call
+    ///
+    /// Append the result to an op log instead of rewriting the whole text
+    /// after every keystroke; `apply_dirty` replays the chunks over the
+    /// last full snapshot. A freshly created or loaded text starts clean
+    /// against its own state.
+    pub fn take_dirty(&mut self) -> TextDelta {
+        if self.persisted.stale {
+            self.persisted = Persisted::of(self);
+        }
+        let deleted = self.delete_set();
+        let since = self.diff_since(&self.persisted.state_vector);
+        let delta = TextDelta {
+            blocks: since.blocks,
+            deleted: deleted
+                .iter()
+                .filter(|range| {
+                    self.persisted
+                        .deleted
+                        .binary_search_by(|old| {
+                            (&old.client_id, old.start, old.end).cmp(&(
+                                &range.client_id,
+                                range.start,
+                                range.end,
+                            ))
+                        })
+                        .is_err()
+                })
+                .cloned()
+                .collect(),
+            clock: self.clock.value(),
+            annotations: since.annotations,
+            marks: since.marks,
+        };
+
+        self.persisted = Persisted {
+            state_vector: self.state_vector(),
+            deleted,
+            dirty: false,
+            stale: false,
+        };
+        delta
+    }
+
+    /// Replay a chunk from `take_dirty` (restoring from storage)
+    ///
+    /// Unlike `apply_delta`, this doesn't make the text dirty: the chunk
+    /// is already persisted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::InvalidDelta` if a block or range is malformed
+    pub fn apply_dirty(&mut self, delta: &TextDelta) -> Result<(), TextError> {
+        let events = self.integrate_delta(delta)?;
+        self.revision += 1;
+        self.notifier.extend(events);
+        if !self.persisted.dirty {
+            self.persisted.stale = true;
+        }
+        Ok(())
+    }
+
+    /// Mark the text dirty and bump its revision before a change, fixing
+    /// the baseline first if it is still pending from a load
+    pub(super) fn touch(&mut self) {
+        if self.persisted.stale {
+            self.persisted = Persisted::of(self);
+        }
+        self.persisted.dirty = true;
+        self.revision += 1;
+    }
+
+    /// Collect deleted clock ranges, coalescing adjacent ranges per client
+    /// and deletion
+    fn delete_set(&self) -> Vec<DeleteRange> {
+        let mut ranges: Vec<DeleteRange> = self
+            .blocks
+            .iter()
+            .filter(|(_, block)| block.is_deleted() && !block.is_empty())
+            .map(|(id, block)| DeleteRange {
+                client_id: id.client_id.clone(),
+                start: id.clock.saturating_sub(block.len() as u64 - 1),
+                end: id.clock,
+                deleted_at: block.deleted_at.clone(),
+            })
+            .collect();
+
+        ranges.sort_by(|a, b| (&a.client_id, a.start).cmp(&(&b.client_id, b.start)));
+
+        let mut coalesced: Vec<DeleteRange> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match coalesced.last_mut() {
+                Some(last)
+                    if last.client_id == range.client_id
+                        && last.deleted_at == range.deleted_at
+                        && range.start <= last.end + 1 =>
+                {
+                    last.end = last.end.max(range.end);
+                }
+                _ => coalesced.push(range),
+            }
+        }
+        coalesced
+    }
+}
+
+impl HeapSize for DeleteRange {
+    fn heap_size(&self) -> usize {
+        self.client_id.heap_size() + self.deleted_at.heap_size()
+    }
+}
+
+impl HeapSize for Persisted {
+    fn heap_size(&self) -> usize {
+        self.state_vector.heap_size() + self.deleted.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sync(from: &FugueText, to: &mut FugueText) -> Vec<TextEvent> {
+        let delta = from.diff_since(&to.state_vector());
+        to.apply_delta(&delta).unwrap()
+    }
+
+    #[test]
+    fn test_state_vector() {
+        let mut text = FugueText::new("client1".to_string());
+        assert!(text.state_vector().clocks.is_empty());
+
+        text.insert(0, "Hello").unwrap();
+        text.insert(5, "!").unwrap();
+
+        assert_eq!(text.state_vector().get("client1"), 6);
+    }
+
+    #[test]
+    fn test_diff_contains_only_unseen_blocks() {
+        let mut text1 = FugueText::new("client1".to_string());
+        let mut text2 = FugueText::new("client2".to_string());
+
+        text1.insert(0, "Hello").unwrap();
+        sync(&text1, &mut text2);
+
+        text1.insert(5, " World").unwrap();
+        let delta = text1.diff_since(&text2.state_vector());
+
+        assert_eq!(delta.blocks.len(), 1);
+        assert_eq!(delta.blocks[0].text, " World");
+    }
+
+    #[test]
+    fn test_concurrent_edits_converge() {
+        let mut text1 = FugueText::new("client1".to_string());
+        let mut text2 = FugueText::new("client2".to_string());
+
+        text1.insert(0, "Hello World").unwrap();
+        sync(&text1, &mut text2);
+
+        text1.delete(5, 6).unwrap();
+        text1.insert(5, "!").unwrap();
+        text2.insert(0, ">> ").unwrap();
+        text2.delete(3, 1).unwrap();
+
+        let delta1 = text1.diff_since(&text2.state_vector());
+        let delta2 = text2.diff_since(&text1.state_vector());
+        text2.apply_delta(&delta1).unwrap();
+        text1.apply_delta(&delta2).unwrap();
+
+        assert_eq!(text1.to_string(), text2.to_string());
+        assert_eq!(text1.to_string(), ">> ello!");
+    }
+
+    #[test]
+    fn test_delta_matches_full_merge() {
+        let mut base = FugueText::new("client1".to_string());
+        base.insert(0, "The quick brown fox").unwrap();
+
+        let mut remote = FugueText::new("client2".to_string());
+        remote.merge(&base).unwrap();
+        remote.insert(4, "very ").unwrap();
+        remote.delete(0, 4).unwrap();
+
+        let mut via_merge = base.clone();
+        via_merge.merge(&remote).unwrap();
+
+        let mut via_delta = base.clone();
+        sync(&remote, &mut via_delta);
+
+        assert_eq!(via_delta.to_string(), via_merge.to_string());
+    }
+
+    #[test]
+    fn test_apply_delta_idempotent() {
+        let mut text1 = FugueText::new("client1".to_string());
+        let mut text2 = FugueText::new("client2".to_string());
+
+        text1.insert(0, "Hello").unwrap();
+        text1.delete(1, 2).unwrap();
+
+        let delta = text1.diff_since(&text2.state_vector());
+        let first = text2.apply_delta(&delta).unwrap();
+        let second = text2.apply_delta(&delta).unwrap();
+
+        assert_eq!(text2.to_string(), "Hlo");
+        assert!(!first.is_empty());
+        assert!(second.is_empty());
+    }
+
+    /// Current content, state vector and delete set
+    fn observable(text: &FugueText) -> (String, VectorClock, Vec<DeleteRange>) {
+        (text.to_string(), text.state_vector(), text.delete_set())
+    }
+
+    #[test]
+    fn test_dirty_chunks_replay_over_snapshot() {
+        let mut text = FugueText::new("alice".to_string());
+        let mut peer = FugueText::new("bob".to_string());
+        text.insert(0, "The quick brown fox").unwrap();
+        let snapshot = serde_json::to_string(&text).unwrap();
+        text.take_dirty();
+        assert!(!text.is_dirty());
+
+        // Deterministic mix of local edits, remote merges and checkpoints
+        let mut chunks = Vec::new();
+        let mut seed = 7u64;
+        for step in 0..60 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let roll = (seed >> 33) as usize;
+            match step % 5 {
+                0 | 1 => {
+                    let at = roll % (text.len() + 1);
+                    text.insert(at, &format!("{}", step % 10)).unwrap();
+                }
+                2 if text.len() > 2 => {
+                    let at = roll % (text.len() - 2);
+                    text.delete(at, 1 + roll % 2).unwrap();
+                }
+                3 => {
+                    peer.apply_delta(&text.diff_since(&peer.state_vector()))
+                        .unwrap();
+                    peer.insert(roll % (peer.len() + 1), "b").unwrap();
+                    if peer.len() > 4 {
+                        peer.delete(roll % (peer.len() - 1), 1).unwrap();
+                    }
+                    text.apply_delta(&peer.diff_since(&text.state_vector()))
+                        .unwrap();
+                }
+                _ => {
+                    assert!(text.is_dirty());
+                    let chunk = serde_json::to_string(&text.take_dirty()).unwrap();
+                    chunks.push(chunk);
+                }
+            }
+        }
+        chunks.push(serde_json::to_string(&text.take_dirty()).unwrap());
+
+        let mut restored: FugueText = serde_json::from_str(&snapshot).unwrap();
+        for chunk in &chunks {
+            restored
+                .apply_dirty(&serde_json::from_str(chunk).unwrap())
+                .unwrap();
+        }
+        assert_eq!(observable(&restored), observable(&text));
+        assert!(!restored.is_dirty());
+
+        // The restored replica tracks further edits against its new state
+        restored.insert(0, ">").unwrap();
+        let chunk = restored.take_dirty();
+        assert_eq!(chunk.blocks.len(), 1);
+        assert!(chunk.deleted.is_empty());
+    }
+
+    #[test]
+    fn test_take_dirty_is_incremental() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello").unwrap();
+        assert_eq!(text.take_dirty().blocks.len(), 1);
+        assert!(text.take_dirty().is_empty());
+
+        text.delete(0, 1).unwrap();
+        let chunk = text.take_dirty();
+        assert!(chunk.blocks.is_empty());
+        assert_eq!(chunk.deleted.len(), 1);
+
+        // A loaded text is clean against the snapshot it came from
+        let mut loaded: FugueText =
+            serde_json::from_str(&serde_json::to_string(&text).unwrap()).unwrap();
+        assert!(!loaded.is_dirty());
+        assert!(loaded.take_dirty().is_empty());
+    }
+
+    #[test]
+    fn test_echoed_delta_is_tagged() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut server = FugueText::new("server".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+
+        alice.insert(0, "Hi").unwrap();
+        let (origin, _) = server
+            .apply_delta_tagged(&alice.diff_since(&server.state_vector()))
+            .unwrap();
+        assert_eq!(origin, ChangeOrigin::Remote);
+
+        // The broadcast goes to everyone, including the author
+        let broadcast = server.diff_since(&VectorClock::new());
+        let (origin, events) = bob.apply_delta_tagged(&broadcast).unwrap();
+        assert_eq!(origin, ChangeOrigin::Remote);
+        assert_eq!(events.len(), 1);
+        let (origin, events) = alice.apply_delta_tagged(&broadcast).unwrap();
+        assert_eq!(origin, ChangeOrigin::Echo);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_apply_delta_returns_events() {
+        let mut text1 = FugueText::new("client1".to_string());
+        let mut text2 = FugueText::new("client2".to_string());
+
+        text1.insert(0, "Hello").unwrap();
+        sync(&text1, &mut text2);
+
+        text1.insert(5, " World").unwrap();
+        let events = sync(&text1, &mut text2);
+
+        assert_eq!(
+            events,
+            vec![TextEvent::Insert {
+                position: 5,
+                text: " World".to_string()
+            }]
+        );
+
+        text1.delete(0, 6).unwrap();
+        let events = sync(&text1, &mut text2);
+
+        assert_eq!(
+            events,
+            vec![TextEvent::Delete {
+                position: 0,
+                length: 6
+            }]
+        );
+    }
+
+    #[test]
+    fn test_events_count_clusters() {
+        let mut text1 = FugueText::new("client1".to_string());
+        let mut text2 = FugueText::new("client2".to_string());
+        text1.insert(0, "🇺🇸漢e\u{301}").unwrap();
+        sync(&text1, &mut text2);
+
+        text1.insert(3, "👋🏽").unwrap();
+        text1.delete(1, 1).unwrap();
+        let events = sync(&text1, &mut text2);
+        assert_eq!(
+            events,
+            vec![
+                TextEvent::Delete {
+                    position: 1,
+                    length: 1
+                },
+                TextEvent::Insert {
+                    position: 2,
+                    text: "👋🏽".to_string()
+                },
+            ]
+        );
+        assert_eq!(text2.len(), 3);
+    }
+
+    #[test]
+    fn test_delete_set_coalesces_ranges() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello World").unwrap();
+        text.delete(6, 1).unwrap();
+        // Split into pieces either side of the first deletion
+        text.delete(2, 6).unwrap();
+
+        let stamp = |clock| Some(super::super::NodeId::new("client1".to_string(), clock, 0));
+        let delta = text.diff_since(&VectorClock::new());
+        assert_eq!(
+            delta.deleted,
+            vec![
+                DeleteRange {
+                    client_id: "client1".into(),
+                    start: 3,
+                    end: 6,
+                    deleted_at: stamp(13),
+                },
+                DeleteRange {
+                    client_id: "client1".into(),
+                    start: 7,
+                    end: 7,
+                    deleted_at: stamp(12),
+                },
+                DeleteRange {
+                    client_id: "client1".into(),
+                    start: 8,
+                    end: 9,
+                    deleted_at: stamp(13),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_delta_rejected() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello").unwrap();
+
+        let delta = TextDelta {
+            blocks: vec![FugueBlock::new(
+                super::super::NodeId::new("client2".to_string(), 1, 0),
+                "too long".to_string(),
+                None,
+                None,
+            )],
+            deleted: Vec::new(),
+            clock: 1,
+            annotations: Vec::new(),
+            marks: Vec::new(),
+        };
+
+        assert!(matches!(
+            text.apply_delta(&delta),
+            Err(TextError::InvalidDelta(_))
+        ));
+        assert_eq!(text.to_string(), "Hello");
+    }
+
+    #[test]
+    fn test_event_diff() {
+        assert!(TextEvent::diff("abc", "abc").is_empty());
+        assert_eq!(
+            TextEvent::diff("abc", "aXc"),
+            vec![
+                TextEvent::Delete {
+                    position: 1,
+                    length: 1
+                },
+                TextEvent::Insert {
+                    position: 1,
+                    text: "X".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            TextEvent::diff("héllo", "héllo!"),
+            vec![TextEvent::Insert {
+                position: 5,
+                text: "!".to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Compact binary encoding of a text, with an optional warm position cache
+//! (`serde-compact` feature)
+//!
+//! Loading a text builds its rope in document order and leaves the
+//! position cache empty, so the first edit after a load walks the block
+//! tree a second time. On a large document that is a visible hitch on the
+//! first keystroke. [`FugueText::encode`] can append the cache (the
+//! visible blocks in document order, and the text length) so
+//! [`FugueText::decode`] builds the rope straight from it and the first
+//! edit finds the cache ready.
+//!
+//! The cache is only trusted after cheap checks: the same number of
+//! visible blocks, each one known and visible, and the same length. If any
+//! fails, or the cache was written in an older format, decoding falls
+//! back to the full rebuild. It is meant for local persistence; leave it
+//! out of anything sent over the network, where it only costs bandwidth.
+
+use super::node::NodeId;
+use super::text::{FugueText, TextParts};
+use super::units::UnitTable;
+use crate::codec::{from_postcard, to_postcard};
+use crate::error::Result;
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Layout of the warm cache; bump on any change so old caches are ignored
+const WARM_CACHE_FORMAT: u32 = 1;
+
+/// What [`FugueText::encode`] writes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextEncodeOptions {
+    /// Include the position cache, so decoding skips rebuilding it
+    pub warm_cache: bool,
+}
+
+impl TextEncodeOptions {
+    /// For local persistence: with the warm cache
+    pub fn local() -> Self {
+        Self { warm_cache: true }
+    }
+
+    /// For the network: text only
+    pub fn network() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct WarmCache {
+    /// Visible blocks in document order
+    order: Vec<NodeId>,
+    /// `FugueText::len` when encoded
+    len: usize,
+}
+
+/// The cache is carried as its format and its own bytes, so a cache in a
+/// layout this build doesn't know is skipped rather than failing the load
+#[derive(Serialize)]
+struct Encoded<'a> {
+    text: &'a FugueText,
+    cache: Option<(u32, Vec<u8>)>,
+}
+
+#[derive(Deserialize)]
+struct Decoded {
+    text: TextParts,
+    cache: Option<(u32, Vec<u8>)>,
+}
+
+impl FugueText {
+    /// Encode with postcard, with the warm cache if `options` ask for it
+    ///
+    /// # Errors
+    ///
+    /// Returns `SyncError::SerializationError` if encoding fails
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::{FugueText, TextEncodeOptions};
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello").unwrap();
+    /// text.insert(5, " World").unwrap();
+    ///
+    /// let bytes = text.encode(&TextEncodeOptions::local()).unwrap();
+    /// let loaded = FugueText::decode(&bytes).unwrap();
+    /// assert_eq!(loaded.to_string(), "Hello World");
+    /// assert!(loaded.has_position_cache());
+    /// ```
+    pub fn encode(&self, options: &TextEncodeOptions) -> Result<Vec<u8>> {
+        let cache = match options.warm_cache {
+            true => {
+                let order = match self.cache_valid {
+                    true => self.cached_blocks.to_vec(),
+                    false => self.get_document_order(),
+                };
+                let cache = WarmCache {
+                    order,
+                    len: self.len(),
+                };
+                Some((WARM_CACHE_FORMAT, to_postcard(&cache)?))
+            }
+            false => None,
+        };
+        to_postcard(&Encoded { text: self, cache })
+    }
+
+    /// Decode bytes from [`encode`](Self::encode), using the warm cache if
+    /// it is there and checks out
+    ///
+    /// # Errors
+    ///
+    /// Returns `SyncError::DeserializationError` if the bytes are
+    /// truncated or aren't an encoded text
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let decoded: Decoded = from_postcard(bytes)?;
+        let mut text = decoded.text.into_text();
+        let cache = decoded
+            .cache
+            .filter(|(format, _)| *format == WARM_CACHE_FORMAT)
+            .and_then(|(_, bytes)| from_postcard::<WarmCache>(&bytes).ok());
+        let warmed = cache.is_some_and(|cache| text.warm_start(cache));
+        if !warmed {
+            trace_debug!(client_id = %text.client_id, "no usable warm cache, rebuilding");
+            text.rebuild_rope();
+        }
+        text.restart_history();
+        Ok(text)
+    }
+
+    /// Check if the position cache is built, so the next edit doesn't have
+    /// to walk the block tree
+    pub fn has_position_cache(&self) -> bool {
+        self.cache_valid
+    }
+
+    /// Build the rope and position cache from a warm cache, or return
+    /// false (changing nothing) if it doesn't match the blocks
+    fn warm_start(&mut self, cache: WarmCache) -> bool {
+        let visible = self
+            .blocks
+            .values()
+            .filter(|block| !block.is_deleted())
+            .count();
+        if cache.order.len() != visible {
+            return false;
+        }
+        let mut seen = HashSet::with_capacity(visible);
+        let mut content = String::new();
+        let mut units = UnitTable::default();
+        for id in &cache.order {
+            match self.blocks.get(id) {
+                Some(block) if !block.is_deleted() && seen.insert(id) => {
+                    content.push_str(&block.text);
+                    units.push(block.text.as_str());
+                }
+                _ => return false,
+            }
+        }
+        if units.len() != cache.len {
+            return false;
+        }
+
+        self.rope = Rope::from_str(&content);
+        self.units = units;
+        let mut position = 0;
+        let mut positions = HashMap::with_capacity(cache.order.len());
+        for id in &cache.order {
+            positions.insert(id.clone(), position);
+            position += self.blocks[id].len();
+        }
+        self.cached_positions = Arc::new(positions);
+        self.cached_blocks = Arc::new(cache.order);
+        self.cache_valid = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two replicas' interleaved edits, with deletions
+    fn edited() -> FugueText {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        alice.insert(0, "The quick fox").unwrap();
+        bob.merge(&alice).unwrap();
+        alice.insert(10, "brown ").unwrap();
+        bob.insert(0, ">> ").unwrap();
+        bob.delete(7, 3).unwrap();
+        alice.merge(&bob).unwrap();
+        alice.insert(alice.len(), " jumps").unwrap();
+        alice
+    }
+
+    #[test]
+    fn test_warm_and_cold_decode_agree() {
+        let text = edited();
+        let warm = FugueText::decode(&text.encode(&TextEncodeOptions::local()).unwrap()).unwrap();
+        let cold = FugueText::decode(&text.encode(&TextEncodeOptions::network()).unwrap()).unwrap();
+        assert!(warm.has_position_cache());
+        assert!(!cold.has_position_cache());
+        assert_eq!(warm.to_string(), text.to_string());
+        assert_eq!(cold.to_string(), text.to_string());
+
+        // Both edit exactly like the original
+        for mut copy in [warm, cold] {
+            copy.insert(5, "!").unwrap();
+            copy.delete(12, 2).unwrap();
+            let mut expected = text.clone();
+            expected.insert(5, "!").unwrap();
+            expected.delete(12, 2).unwrap();
+            assert_eq!(copy.to_string(), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn test_bad_or_unknown_cache_falls_back() {
+        let text = edited();
+        let order = text.get_document_order();
+        let len = text.len();
+        let mismatched = [
+            // A block too few
+            (WARM_CACHE_FORMAT, order[1..].to_vec(), len),
+            // Length off
+            (WARM_CACHE_FORMAT, order.clone(), len + 1),
+            // A block twice
+            (
+                WARM_CACHE_FORMAT,
+                [&order[..1], &order[..order.len() - 1]].concat(),
+                len,
+            ),
+            // Newer format
+            (WARM_CACHE_FORMAT + 1, order.clone(), len),
+        ];
+        for (format, order, len) in mismatched {
+            let cache = to_postcard(&WarmCache { order, len }).unwrap();
+            let bytes = to_postcard(&Encoded {
+                text: &text,
+                cache: Some((format, cache)),
+            })
+            .unwrap();
+            let decoded = FugueText::decode(&bytes).unwrap();
+            assert_eq!(decoded.to_string(), text.to_string());
+            assert!(!decoded.has_position_cache());
+        }
+    }
+
+    #[test]
+    fn test_truncated_bytes_fail() {
+        let bytes = edited().encode(&TextEncodeOptions::local()).unwrap();
+        assert!(FugueText::decode(&bytes[..bytes.len() / 2]).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Fingerprints for checking that replicas converged
+//!
+//! Two hashes, both stable across platforms and Rust releases (FNV-1a,
+//! see [`crate::document`]) and not cryptographic:
+//!
+//! - [`FugueText::content_hash`] covers the visible text only, so replicas
+//!   that read the same hash equally however they got there.
+//! - [`FugueText::structure_hash`] covers every character ever inserted,
+//!   tombstones included, with its ID and deletion flag. It tells identical
+//!   CRDT state apart from the same text reached by different edits.
+//!
+//! Replicas split and join blocks differently as they edit and merge, so
+//! the structure is hashed as runs of consecutive clocks from one client,
+//! in document order, whatever blocks hold them.
+
+use super::text::FugueText;
+use crate::document::Fnv1a;
+
+/// Consecutive characters of one client, deleted or not alike
+struct Run<'a> {
+    client_id: &'a str,
+    first: u64,
+    last: u64,
+    deleted: bool,
+    text: String,
+}
+
+impl Run<'_> {
+    fn write(&self, hasher: &mut Fnv1a) {
+        hasher.write(self.client_id.as_bytes());
+        hasher.write(&self.first.to_le_bytes());
+        hasher.write(&self.last.to_le_bytes());
+        hasher.write(&[u8::from(self.deleted)]);
+        hasher.write(self.text.as_bytes());
+    }
+}
+
+impl FugueText {
+    /// Stable 64-bit hash of the visible text
+    ///
+    /// Equal texts hash equally, whoever wrote them. Not cryptographic.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut alice = FugueText::new("alice".to_string());
+    /// alice.insert(0, "Hello").unwrap();
+    /// let mut bob = FugueText::new("bob".to_string());
+    /// bob.insert(0, "Hello").unwrap();
+    ///
+    /// assert_eq!(alice.content_hash(), bob.content_hash());
+    /// ```
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        for chunk in self.rope.chunks() {
+            hasher.write_part(chunk.as_bytes());
+        }
+        hasher.write(&[]);
+        hasher.finish()
+    }
+
+    /// Stable 64-bit hash of the CRDT state: every character with its ID,
+    /// deletion flag and place, tombstones included
+    ///
+    /// Replicas that merged the same edits hash equally, however their
+    /// blocks are split. Tombstones `gc` removed are no longer part of the
+    /// state; formatting marks and annotations aren't included. Not
+    /// cryptographic.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut alice = FugueText::new("alice".to_string());
+    /// alice.insert(0, "Hello").unwrap();
+    /// let mut bob = FugueText::new("bob".to_string());
+    /// bob.insert(0, "Hello").unwrap();
+    ///
+    /// // Same text, written by different replicas
+    /// assert_eq!(alice.content_hash(), bob.content_hash());
+    /// assert_ne!(alice.structure_hash(), bob.structure_hash());
+    /// ```
+    pub fn structure_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        let order = self.document_order_with_tombstones();
+        let mut run: Option<Run<'_>> = None;
+        for id in &order {
+            let block = &self.blocks[id];
+            let len = block.len() as u64;
+            let first = (id.clock + 1).saturating_sub(len);
+            if let Some(current) = &mut run {
+                if len > 0
+                    && current.client_id == id.client_id
+                    && current.last + 1 == first
+                    && current.deleted == block.is_deleted()
+                {
+                    current.last = id.clock;
+                    current.text.push_str(block.text.as_str());
+                    continue;
+                }
+            }
+            if let Some(done) = run.take() {
+                done.write(&mut hasher);
+            }
+            let current = Run {
+                client_id: &id.client_id,
+                first: first.min(id.clock),
+                last: id.clock,
+                deleted: block.is_deleted(),
+                text: block.text.as_str().to_string(),
+            };
+            match len {
+                // An empty block is a run of its own
+                0 => current.write(&mut hasher),
+                _ => run = Some(current),
+            }
+        }
+        if let Some(done) = run {
+            done.write(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashes_match_when_replicas_converge() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello World").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        alice.insert(5, ",").unwrap();
+        alice.delete(0, 1).unwrap();
+        bob.insert(11, "!").unwrap();
+        bob.delete(6, 5).unwrap();
+        assert_ne!(alice.content_hash(), bob.content_hash());
+
+        // Merging splits the blocks differently on each side
+        alice.merge(&bob).unwrap();
+        bob.merge(&alice).unwrap();
+        assert_eq!(alice.to_string(), bob.to_string());
+        assert_eq!(alice.content_hash(), bob.content_hash());
+        assert_eq!(alice.structure_hash(), bob.structure_hash());
+    }
+
+    #[test]
+    fn test_structure_hash_sees_tombstones() {
+        let mut base = FugueText::new("alice".to_string());
+        base.insert(0, "abc").unwrap();
+
+        // Same visible text, but one replica deleted and retyped the "b"
+        let mut retyped = base.clone();
+        retyped.delete(1, 1).unwrap();
+        retyped.insert(1, "b").unwrap();
+        assert_eq!(retyped.content_hash(), base.content_hash());
+        assert_ne!(retyped.structure_hash(), base.structure_hash());
+
+        // Only a deletion flag differs
+        let mut deleted = base.clone();
+        deleted.delete(1, 1).unwrap();
+        let mut restored = deleted.clone();
+        let tombstone = restored
+            .blocks
+            .iter()
+            .find(|(_, block)| block.is_deleted())
+            .map(|(id, _)| id.clone())
+            .unwrap();
+        restored.block_mut(&tombstone).unwrap().deleted = false;
+        restored.rebuild_rope();
+        assert_eq!(restored.to_string(), "abc");
+        assert_ne!(deleted.structure_hash(), base.structure_hash());
+        // Split into three blocks, but the same characters as the base
+        assert_eq!(restored.structure_hash(), base.structure_hash());
+    }
+}
@@ -0,0 +1,461 @@
+//! Tombstone garbage collection
+//!
+//! `delete` only marks blocks deleted, since concurrent inserts may still
+//! name the deleted characters as origins. Once every replica has seen a
+//! deletion, nothing new can: [`FugueText::gc`] removes those tombstones
+//! for good.
+//!
+//! Removing a block must not move anything else, here or on replicas that
+//! still have it, so a tombstone only goes when the Fugue tree keeps its
+//! shape without it:
+//!
+//! - No block names one of its characters as an origin: it is a leaf.
+//! - One block names it, hangs from it as its only child and is itself
+//!   stable: that block takes over the tombstone's origins, and with them
+//!   its place. This is the common chain of typed-then-deleted text, which
+//!   goes one link at a time, newest first.
+//!
+//! A tombstone whose children share its parent with blocks ordered between
+//! them stays, as does one named by several blocks or holding a character
+//! a formatting mark is anchored to (the mark would lose its edge).
+//!
+//! A collected tombstone can still arrive from a replica that hasn't
+//! collected it; merges and deltas drop it (until the text is reloaded, as
+//! the record of what was collected isn't serialized).
+
+use super::node::{ClientId, NodeId};
+use super::order;
+use super::text::FugueText;
+use crate::sync::VectorClock;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+/// Blocks by client and last clock, for finding the block that holds a
+/// character without scanning
+struct ClockIndex(HashMap<ClientId, BTreeMap<u64, (u64, NodeId)>>);
+
+impl ClockIndex {
+    fn new(text: &FugueText) -> Self {
+        let mut index: HashMap<ClientId, BTreeMap<u64, (u64, NodeId)>> = HashMap::new();
+        for (id, block) in text.blocks.iter() {
+            let len = block.len() as u64;
+            if len > 0 {
+                index
+                    .entry(id.client_id.clone())
+                    .or_default()
+                    .insert(id.clock, (id.clock + 1 - len, id.clone()));
+            }
+        }
+        Self(index)
+    }
+
+    fn block_containing(&self, node_id: &NodeId) -> Option<NodeId> {
+        let (_, (start, id)) = self
+            .0
+            .get(&node_id.client_id)?
+            .range(node_id.clock..)
+            .next()?;
+        (*start <= node_id.clock).then(|| id.clone())
+    }
+}
+
+/// The Fugue tree and who names whom, kept up to date while blocks go
+struct Tree {
+    /// Parent and side of each block
+    placements: BTreeMap<NodeId, (Option<NodeId>, bool)>,
+    /// Children of each block, and of None for the roots
+    children: HashMap<Option<NodeId>, Vec<NodeId>>,
+    /// Blocks each block's origins fall in
+    origins: HashMap<NodeId, Vec<NodeId>>,
+    /// Blocks whose origins fall in each block
+    named_by: HashMap<NodeId, BTreeSet<NodeId>>,
+}
+
+impl Tree {
+    fn new(text: &FugueText) -> Self {
+        let placements = order::placements(&text.blocks);
+        let mut children: HashMap<Option<NodeId>, Vec<NodeId>> = HashMap::new();
+        for (id, (parent, _)) in &placements {
+            children.entry(parent.clone()).or_default().push(id.clone());
+        }
+
+        let index = ClockIndex::new(text);
+        let mut origins = HashMap::new();
+        let mut named_by: HashMap<NodeId, BTreeSet<NodeId>> = HashMap::new();
+        for (id, block) in text.blocks.iter() {
+            let named: Vec<NodeId> = [&block.left_origin, &block.right_origin]
+                .into_iter()
+                .flatten()
+                .filter_map(|origin| index.block_containing(origin))
+                .collect();
+            for target in &named {
+                named_by
+                    .entry(target.clone())
+                    .or_default()
+                    .insert(id.clone());
+            }
+            origins.insert(id.clone(), named);
+        }
+
+        Self {
+            placements,
+            children,
+            origins,
+            named_by,
+        }
+    }
+
+    fn children(&self, id: &NodeId) -> &[NodeId] {
+        self.children
+            .get(&Some(id.clone()))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Take `id` out; nothing may name it any more
+    fn remove(&mut self, id: &NodeId) {
+        for target in self.origins.remove(id).unwrap_or_default() {
+            if let Some(named_by) = self.named_by.get_mut(&target) {
+                named_by.remove(id);
+            }
+        }
+        if let Some((parent, _)) = self.placements.remove(id) {
+            if let Some(siblings) = self.children.get_mut(&parent) {
+                siblings.retain(|sibling| sibling != id);
+            }
+        }
+        self.children.remove(&Some(id.clone()));
+        self.named_by.remove(id);
+    }
+
+    /// Move `child` into the place of `parent`, whose origins it takes
+    fn replace(&mut self, parent: &NodeId, child: &NodeId) {
+        for target in self.origins.remove(child).unwrap_or_default() {
+            if let Some(named_by) = self.named_by.get_mut(&target) {
+                named_by.remove(child);
+            }
+        }
+        let origins = self.origins.get(parent).cloned().unwrap_or_default();
+        for target in &origins {
+            self.named_by
+                .entry(target.clone())
+                .or_default()
+                .insert(child.clone());
+        }
+        self.origins.insert(child.clone(), origins);
+
+        let placement = self.placements[parent].clone();
+        if let Some(siblings) = self.children.get_mut(&placement.0) {
+            for sibling in siblings.iter_mut().filter(|sibling| *sibling == parent) {
+                *sibling = child.clone();
+            }
+        }
+        self.children.remove(&Some(parent.clone()));
+        self.placements.insert(child.clone(), placement);
+        self.placements.remove(parent);
+        for target in self.origins.remove(parent).unwrap_or_default() {
+            if let Some(named_by) = self.named_by.get_mut(&target) {
+                named_by.remove(parent);
+            }
+        }
+        self.named_by.remove(parent);
+    }
+}
+
+impl FugueText {
+    /// Number of deleted blocks the text still keeps
+    ///
+    /// They grow with every deletion until [`FugueText::gc`] removes them;
+    /// see also the "tombstones" entry of [`FugueText::memory_usage`].
+    pub fn tombstone_count(&self) -> usize {
+        self.blocks
+            .values()
+            .filter(|block| block.is_deleted())
+            .count()
+    }
+
+    /// Remove tombstones every replica is done with, returning how many
+    /// blocks went (see the module docs for which can)
+    ///
+    /// `safe_vector` must be stable: every replica has seen every insert
+    /// up to it, and the deletions of those characters, and anything a
+    /// replica sends later was created after that. Usually it is the
+    /// minimum of the state vectors all replicas acknowledged after
+    /// syncing. Only tombstones within it are removed, so replicas that
+    /// collect with the same vector keep converging with each other and
+    /// with replicas that don't collect at all.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello World").unwrap();
+    /// text.delete(5, 6).unwrap();
+    /// assert_eq!(text.tombstone_count(), 1);
+    ///
+    /// text.gc(&text.state_vector());
+    /// assert_eq!(text.tombstone_count(), 0);
+    /// assert_eq!(text.to_string(), "Hello");
+    /// ```
+    pub fn gc(&mut self, safe_vector: &VectorClock) -> usize {
+        let stable = |id: &NodeId| id.clock <= safe_vector.get(&id.client_id);
+        let mut candidates: Vec<NodeId> = self
+            .blocks
+            .iter()
+            .filter(|(id, block)| block.is_deleted() && stable(id))
+            .map(|(id, _)| id.clone())
+            .collect();
+        if !self.marks.is_empty() && !candidates.is_empty() {
+            let index = ClockIndex::new(self);
+            let anchored: BTreeSet<NodeId> = self
+                .marks
+                .iter()
+                .flat_map(|mark| [&mark.start.target, &mark.end.target])
+                .flatten()
+                .filter_map(|target| index.block_containing(target))
+                .collect();
+            candidates.retain(|id| !anchored.contains(id));
+        }
+        if candidates.is_empty() {
+            return 0;
+        }
+        // Newest first, so a chain of tombstones collapses in one go
+        candidates.sort_by_cached_key(|id| std::cmp::Reverse(order::sibling_key(&self.blocks, id)));
+
+        let mut tree = Tree::new(self);
+        let mut removed = Vec::new();
+        let mut rewired = Vec::new();
+        for id in candidates {
+            let named_by: Vec<NodeId> = tree
+                .named_by
+                .get(&id)
+                .map(|named_by| named_by.iter().cloned().collect())
+                .unwrap_or_default();
+            match named_by.as_slice() {
+                [] => tree.remove(&id),
+                [child] if tree.children(&id) == std::slice::from_ref(child) && stable(child) => {
+                    if !self.can_take_place(&tree, &id, child) {
+                        continue;
+                    }
+                    tree.replace(&id, child);
+                    rewired.push((child.clone(), id.clone()));
+                }
+                _ => continue,
+            }
+            removed.push(id);
+        }
+        if removed.is_empty() {
+            return 0;
+        }
+
+        self.touch();
+        let blocks = self.blocks_mut();
+        for (child, parent) in &rewired {
+            let origins = blocks
+                .get(parent)
+                .map(|parent| (parent.left_origin.clone(), parent.right_origin.clone()));
+            if let (Some((left, right)), Some(block)) =
+                (origins, blocks.get_mut(child).map(Arc::make_mut))
+            {
+                block.left_origin = left;
+                block.right_origin = right;
+            }
+        }
+        for id in &removed {
+            blocks.remove(id);
+        }
+        for id in &removed {
+            let floor = self.collected.get(&id.client_id).max(id.clock);
+            self.collected.update(&id.client_id, floor);
+        }
+        self.cache_valid = false;
+        self.rechunk.restart();
+        trace_debug!(
+            removed = removed.len(),
+            rewired = rewired.len(),
+            "collected tombstones"
+        );
+        removed.len()
+    }
+
+    /// Whether `child`, the only block hanging from `parent`, can take
+    /// its place among its siblings: nothing sorts between the two
+    fn can_take_place(&self, tree: &Tree, parent: &NodeId, child: &NodeId) -> bool {
+        let (grandparent, left) = &tree.placements[parent];
+        let (low, high) = (
+            order::sibling_key(&self.blocks, parent),
+            order::sibling_key(&self.blocks, child),
+        );
+        tree.children.get(grandparent).is_none_or(|siblings| {
+            !siblings.iter().any(|sibling| {
+                let key = order::sibling_key(&self.blocks, sibling);
+                tree.placements[sibling].1 == *left && low < key && key < high
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: u32 = 8;
+    const ROUNDS: usize = 40;
+
+    /// Type `text` one keystroke at a time, the way editors send it
+    fn type_at(text: &mut FugueText, position: usize, typed: &str) {
+        for (i, c) in typed.chars().enumerate() {
+            text.insert(position + i, &c.to_string()).unwrap();
+        }
+    }
+
+    fn sync(a: &mut FugueText, b: &mut FugueText) {
+        a.merge(b).unwrap();
+        b.merge(a).unwrap();
+    }
+
+    #[test]
+    fn test_typed_and_deleted_chains_are_collected() {
+        let mut text = FugueText::new("alice".to_string());
+        type_at(&mut text, 0, "Hello brave new world");
+        text.delete(6, 10).unwrap();
+        type_at(&mut text, 11, "!!");
+        text.delete(11, 1).unwrap();
+        assert_eq!(text.to_string(), "Hello world!");
+        assert!(text.tombstone_count() > 0);
+
+        let removed = text.gc(&text.state_vector());
+        assert!(removed > 0);
+        assert_eq!(text.tombstone_count(), 0);
+        assert_eq!(text.to_string(), "Hello world!");
+
+        // Still editable at every position, and as a fresh replica sees it
+        let mut fresh = FugueText::new("bob".to_string());
+        fresh.merge(&text).unwrap();
+        assert_eq!(fresh.to_string(), "Hello world!");
+        text.insert(6, "big ").unwrap();
+        fresh.merge(&text).unwrap();
+        assert_eq!(fresh.to_string(), "Hello big world!");
+    }
+
+    #[test]
+    fn test_nothing_past_the_safe_vector_is_collected() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "abc").unwrap();
+        let safe = text.state_vector();
+        text.insert(3, "def").unwrap();
+        text.delete(2, 3).unwrap();
+
+        // "abcdef" was typed into one block, "cde" is one tombstone
+        assert_eq!(text.gc(&safe), 0);
+        assert_eq!(text.tombstone_count(), 1);
+        text.gc(&text.state_vector());
+        assert_eq!(text.tombstone_count(), 0);
+        assert_eq!(text.to_string(), "abf");
+    }
+
+    #[test]
+    fn test_collecting_replicas_converge_with_one_that_does_not() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        let mut carol = FugueText::new("carol".to_string());
+        type_at(&mut alice, 0, "The quick brown fox");
+        sync(&mut alice, &mut bob);
+        type_at(&mut bob, 4, "very ");
+        alice.delete(10, 6).unwrap();
+        sync(&mut alice, &mut bob);
+        carol.merge(&alice).unwrap();
+        bob.delete(0, 4).unwrap();
+        type_at(&mut alice, 0, "A ");
+        sync(&mut alice, &mut bob);
+        carol.merge(&bob).unwrap();
+        assert_eq!(alice.to_string(), carol.to_string());
+
+        // Everyone has seen everything: alice and bob collect, carol doesn't
+        let safe = alice.state_vector();
+        let tombstones = carol.tombstone_count();
+        alice.gc(&safe);
+        bob.gc(&safe);
+        assert!(alice.tombstone_count() < tombstones);
+        assert_eq!(alice.to_string(), carol.to_string());
+
+        // Concurrent edits everywhere, next to collected text
+        type_at(&mut alice, 2, "x");
+        type_at(&mut bob, 6, "y");
+        let len = carol.len();
+        type_at(&mut carol, len, " jumps");
+        carol.delete(0, 1).unwrap();
+
+        sync(&mut alice, &mut carol);
+        sync(&mut bob, &mut carol);
+        sync(&mut alice, &mut bob);
+        assert_eq!(alice.to_string(), bob.to_string());
+        assert_eq!(alice.to_string(), carol.to_string());
+        // Carol's copies of the collected tombstones don't come back
+        assert!(alice.tombstone_count() < carol.tombstone_count());
+
+        // Deltas agree too
+        let mut dave = FugueText::new("dave".to_string());
+        dave.apply_delta(&carol.diff_since(&dave.state_vector()))
+            .unwrap();
+        dave.apply_delta(&alice.diff_since(&dave.state_vector()))
+            .unwrap();
+        assert_eq!(dave.to_string(), carol.to_string());
+    }
+
+    #[test]
+    fn test_random_edits_with_gc_converge() {
+        for seed in 1..=SEEDS {
+            let mut seed: u32 = 0x9e37_79b9 ^ seed;
+            let mut next = |bound: usize| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as usize % bound.max(1)
+            };
+            let mut replicas: Vec<FugueText> = ["alice", "bob", "carol"]
+                .iter()
+                .map(|name| FugueText::new(name.to_string()))
+                .collect();
+            for round in 0..ROUNDS {
+                for replica in replicas.iter_mut() {
+                    let len = replica.len();
+                    if len > 0 && next(3) == 0 {
+                        let position = next(len);
+                        let length = 1 + next((len - position).min(5));
+                        replica.delete(position, length).unwrap();
+                    } else {
+                        let position = next(len + 1);
+                        type_at(replica, position, ["ab", "c", "xyz"][next(3)]);
+                    }
+                }
+                let (a, b) = (next(3), next(3));
+                if a != b {
+                    let remote = replicas[b].clone();
+                    replicas[a].merge(&remote).unwrap();
+                }
+                if round % 5 == 4 {
+                    // Full sync, then everyone but carol collects
+                    for _ in 0..2 {
+                        for a in 0..3 {
+                            for b in 0..3 {
+                                let remote = replicas[b].clone();
+                                replicas[a].merge(&remote).unwrap();
+                            }
+                        }
+                    }
+                    let safe = replicas[0].state_vector();
+                    replicas[0].gc(&safe);
+                    replicas[1].gc(&safe);
+                    let text = replicas[2].to_string();
+                    assert!(
+                        replicas.iter().all(|r| r.to_string() == text),
+                        "seed {}",
+                        seed
+                    );
+                }
+            }
+        }
+    }
+}
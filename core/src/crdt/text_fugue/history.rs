@@ -0,0 +1,657 @@
+//! Position history: carrying offsets from an old version to today's text
+//!
+//! [Anchors](super::Anchor) are the way to keep a place in a text, but
+//! some systems only store a plain offset and the state vector it was taken
+//! at ("comment at 142 as of V"). The text keeps a log of how positions
+//! moved since: the position-space inserts and deletes of local edits and
+//! of every merge, grouped into epochs that begin whenever a merge or delta
+//! changes the other clients' part of the state vector.
+//! [`FugueText::transform_position`] replays the log from V. The log names
+//! no blocks, so it survives block splits and tombstone collection; only
+//! its retention window ([`HistoryRetention`]) bounds how old V may be.
+//!
+//! Deletes don't move the state vector, so a version stands for the *last*
+//! state the text had with that state vector: an offset taken before a
+//! delete, with no insert since, is read as taken after it.
+//!
+//! The log is kept in memory only. A text loaded from JSON starts a fresh
+//! log at its state vector, and [`HistoryRetention`] reverts to the default.
+
+use super::node::ClientId;
+use super::text::FugueText;
+use crate::memory::HeapSize;
+use crate::sync::VectorClock;
+use crate::ClientID;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::mem::size_of;
+
+/// Operations [`HistoryRetention::default`] keeps
+pub const DEFAULT_HISTORY_OPS: usize = 1_000;
+
+/// How much position history a text keeps
+///
+/// Versions older than the oldest retained operation can't be transformed
+/// any more. `None` leaves a dimension unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryRetention {
+    /// Keep at most this many inserts and deletes
+    pub max_ops: Option<usize>,
+    /// Keep at most this many epochs (one per merge that brought in other
+    /// clients' inserts)
+    pub max_epochs: Option<usize>,
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        Self {
+            max_ops: Some(DEFAULT_HISTORY_OPS),
+            max_epochs: None,
+        }
+    }
+}
+
+/// How one change moved positions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PositionOp {
+    /// `length` graphemes appeared at `position`
+    Insert { position: usize, length: usize },
+    /// `length` graphemes starting at `position` went away
+    Delete { position: usize, length: usize },
+}
+
+impl PositionOp {
+    /// Where `position` ends up after this change
+    ///
+    /// An insert at the position itself pushes it right, so it stays on the
+    /// same character; a position inside a deleted range moves to where the
+    /// range was.
+    fn apply(self, position: usize) -> usize {
+        match self {
+            PositionOp::Insert {
+                position: at,
+                length,
+            } if at <= position => position + length,
+            PositionOp::Delete {
+                position: at,
+                length,
+            } if position >= at + length => position - length,
+            PositionOp::Delete { position: at, .. } if position > at => at,
+            _ => position,
+        }
+    }
+
+    /// Fold `next` into this op if it continues it
+    pub(super) fn extend(&mut self, next: PositionOp) -> bool {
+        match (self, next) {
+            (
+                PositionOp::Delete { position, length },
+                PositionOp::Delete {
+                    position: next_position,
+                    length: next_length,
+                },
+            ) if *position == next_position => {
+                *length += next_length;
+                true
+            }
+            (
+                PositionOp::Insert { position, length },
+                PositionOp::Insert {
+                    position: next_position,
+                    length: next_length,
+                },
+            ) if *position + *length == next_position => {
+                *length += next_length;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A local edit, or part of a merge within the same epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Change {
+    /// Our own state vector entry after the change
+    own: u64,
+    op: PositionOp,
+}
+
+/// A stretch of history during which the other clients' entries of the
+/// state vector stayed put
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Epoch {
+    /// Every non-zero state vector entry except our own
+    frontier: BTreeMap<ClientID, u64>,
+    /// Our own entry when the epoch began
+    start: u64,
+    /// How the change that opened the epoch moved positions (empty for the
+    /// oldest epoch)
+    opening: Vec<PositionOp>,
+    changes: Vec<Change>,
+}
+
+impl Epoch {
+    fn own(&self) -> u64 {
+        self.changes.last().map_or(self.start, |change| change.own)
+    }
+}
+
+/// The position log of one text (see the module docs)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct PositionHistory {
+    /// Oldest first; never empty
+    epochs: VecDeque<Epoch>,
+    retention: HistoryRetention,
+}
+
+impl Default for PositionHistory {
+    /// The log of an empty text
+    fn default() -> Self {
+        Self::starting_at(&VectorClock::new(), "")
+    }
+}
+
+impl PositionHistory {
+    /// A log with nothing in it yet, starting at `version`
+    pub(super) fn starting_at(version: &VectorClock, client_id: &str) -> Self {
+        let (frontier, start) = split_version(version, client_id);
+        Self {
+            epochs: VecDeque::from([Epoch {
+                frontier,
+                start,
+                opening: Vec::new(),
+                changes: Vec::new(),
+            }]),
+            retention: HistoryRetention::default(),
+        }
+    }
+
+    /// Inserts and deletes retained
+    fn op_count(&self) -> usize {
+        self.epochs
+            .iter()
+            .map(|epoch| epoch.opening.len() + epoch.changes.len())
+            .sum()
+    }
+
+    fn current(&mut self) -> &mut Epoch {
+        self.epochs.back_mut().expect("history has an epoch")
+    }
+
+    /// Log a local insert, which moved our own state vector entry to `own`
+    pub(super) fn record_insert(&mut self, position: usize, length: usize, own: u64) {
+        self.record_local(Change {
+            own,
+            op: PositionOp::Insert { position, length },
+        });
+    }
+
+    /// Log a local delete
+    pub(super) fn record_delete(&mut self, position: usize, length: usize) {
+        let own = self.current().own();
+        self.record_local(Change {
+            own,
+            op: PositionOp::Delete { position, length },
+        });
+    }
+
+    fn record_local(&mut self, change: Change) {
+        self.current().changes.push(change);
+        let retention = self.retention;
+        self.prune(&retention);
+    }
+
+    /// Log a merge or delta that moved positions by `ops` and left the
+    /// state vector at `version`
+    pub(super) fn record_remote(
+        &mut self,
+        ops: Vec<PositionOp>,
+        version: &VectorClock,
+        client_id: &str,
+    ) {
+        let (frontier, own) = split_version(version, client_id);
+        let current = self.current();
+        if current.frontier == frontier && current.own() == own {
+            current
+                .changes
+                .extend(ops.into_iter().map(|op| Change { own, op }));
+        } else {
+            self.epochs.push_back(Epoch {
+                frontier,
+                start: own,
+                opening: ops,
+                changes: Vec::new(),
+            });
+        }
+        let retention = self.retention;
+        self.prune(&retention);
+    }
+
+    /// Where `position`, taken at `version`, is now
+    pub(super) fn transform(
+        &self,
+        position: usize,
+        version: &VectorClock,
+        client_id: &str,
+    ) -> Option<usize> {
+        let (frontier, own) = split_version(version, client_id);
+        let (epoch, from) = self
+            .epochs
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, epoch)| epoch.frontier == frontier)
+            .find_map(|(index, epoch)| {
+                match epoch.changes.iter().rposition(|change| change.own == own) {
+                    Some(last) => Some((index, last + 1)),
+                    None => (epoch.start == own).then_some((index, 0)),
+                }
+            })?;
+
+        let later = self.epochs.iter().skip(epoch + 1).flat_map(|epoch| {
+            epoch
+                .opening
+                .iter()
+                .copied()
+                .chain(epoch.changes.iter().map(|change| change.op))
+        });
+        let ops = self.epochs[epoch].changes[from..]
+            .iter()
+            .map(|change| change.op)
+            .chain(later);
+        Some(ops.fold(position, |position, op| op.apply(position)))
+    }
+
+    pub(super) fn retention(&self) -> HistoryRetention {
+        self.retention
+    }
+
+    pub(super) fn set_retention(&mut self, retention: HistoryRetention) -> usize {
+        self.retention = retention;
+        self.prune(&retention)
+    }
+
+    /// Drop the oldest operations beyond `retention`, returning how many
+    /// went
+    ///
+    /// The newest epoch always stays, so the current version can still be
+    /// transformed (to itself).
+    pub(super) fn prune(&mut self, retention: &HistoryRetention) -> usize {
+        let mut dropped = 0;
+        if let Some(max_epochs) = retention.max_epochs {
+            while self.epochs.len() > max_epochs.max(1) {
+                dropped += self.drop_oldest_epoch();
+            }
+        }
+        if let Some(max_ops) = retention.max_ops {
+            let mut excess = self.op_count().saturating_sub(max_ops);
+            while excess > 0 {
+                if self.epochs[0].changes.len() <= excess && self.epochs.len() > 1 {
+                    let count = self.drop_oldest_epoch();
+                    dropped += count;
+                    excess = excess.saturating_sub(count);
+                    continue;
+                }
+                let oldest = &mut self.epochs[0];
+                let count = excess.min(oldest.changes.len());
+                if count == 0 {
+                    break;
+                }
+                oldest.start = oldest.changes[count - 1].own;
+                oldest.changes.drain(..count);
+                dropped += count;
+                excess -= count;
+            }
+        }
+        dropped
+    }
+
+    /// Drop the oldest epoch and the opening of the one after it, which
+    /// only leads away from it
+    fn drop_oldest_epoch(&mut self) -> usize {
+        let oldest = self.epochs.pop_front().expect("history has an epoch");
+        let next = self
+            .epochs
+            .front_mut()
+            .expect("history keeps its newest epoch");
+        let opening = std::mem::take(&mut next.opening);
+        oldest.opening.len() + oldest.changes.len() + opening.len()
+    }
+}
+
+/// The frontier (other clients' non-zero entries) and our own entry of a
+/// state vector
+fn split_version(version: &VectorClock, client_id: &str) -> (BTreeMap<ClientID, u64>, u64) {
+    let frontier = version
+        .clocks
+        .iter()
+        .filter(|(client, &clock)| client.as_str() != client_id && clock > 0)
+        .map(|(client, &clock)| (client.clone(), clock))
+        .collect();
+    (
+        frontier,
+        version.clocks.get(client_id).copied().unwrap_or(0),
+    )
+}
+
+/// Position ops turning the visible characters `before` into `after`
+///
+/// Characters that survive keep their relative order, so one walk over
+/// both sequences finds every run that went or came.
+fn diff_chars(before: &[CharRun], after: &[CharRun]) -> Vec<PositionOp> {
+    let before = expand(before);
+    let after = expand(after);
+    let kept_before: HashSet<_> = before.iter().copied().collect();
+    let kept_after: HashSet<_> = after.iter().copied().collect();
+
+    let mut ops: Vec<PositionOp> = Vec::new();
+    let mut push = |op| {
+        if !ops.last_mut().is_some_and(|last| last.extend(op)) {
+            ops.push(op);
+        }
+    };
+    let (mut old, mut new, mut position) = (0, 0, 0);
+    loop {
+        if old < before.len() && !kept_after.contains(&before[old]) {
+            push(PositionOp::Delete {
+                position,
+                length: 1,
+            });
+            old += 1;
+        } else if new < after.len() && !kept_before.contains(&after[new]) {
+            push(PositionOp::Insert {
+                position,
+                length: 1,
+            });
+            position += 1;
+            new += 1;
+        } else if old < before.len() && new < after.len() {
+            debug_assert_eq!(before[old], after[new], "survivors keep their order");
+            old += 1;
+            new += 1;
+            position += 1;
+        } else {
+            break;
+        }
+    }
+    ops
+}
+
+/// Visible characters of one block: its client and its clock range
+pub(super) type CharRun = (ClientId, u64, usize);
+
+/// One `(client, clock)` per character
+fn expand(runs: &[CharRun]) -> Vec<(&str, u64)> {
+    runs.iter()
+        .flat_map(|(client, end, len)| {
+            let start = end + 1 - *len as u64;
+            (start..=*end).map(move |clock| (client.as_str(), clock))
+        })
+        .collect()
+}
+
+impl FugueText {
+    /// Map `position`, an offset into this text as it was at
+    /// `from_version`, onto the text as it is now
+    ///
+    /// `from_version` is the [`state_vector`](Self::state_vector) the
+    /// offset was taken at. The offset keeps pointing at the same
+    /// character across later inserts, deletes and merges; if that
+    /// character was deleted, it lands where the character was. Returns
+    /// `None` if `from_version` is older than the retained history (see
+    /// [`set_history_retention`](Self::set_history_retention)) or was never
+    /// a version of this replica.
+    ///
+    /// Deletes don't change the state vector, so `from_version` means the
+    /// last state with that state vector; store offsets together with the
+    /// version right after taking them, before deleting more.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello World").unwrap();
+    /// let version = text.state_vector();
+    /// let world = 6;
+    ///
+    /// text.insert(0, "Oh, ").unwrap();
+    /// text.delete(4, 6).unwrap(); // "Hello "
+    ///
+    /// let now = text.transform_position(world, &version).unwrap();
+    /// assert_eq!(text.to_string(), "Oh, World");
+    /// assert_eq!(now, 4);
+    /// ```
+    pub fn transform_position(&self, position: usize, from_version: &VectorClock) -> Option<usize> {
+        self.history
+            .transform(position, from_version, &self.client_id)
+            .map(|position| position.min(self.len()))
+    }
+
+    /// Current position history retention
+    pub fn history_retention(&self) -> HistoryRetention {
+        self.history.retention()
+    }
+
+    /// Change how much position history the text keeps, dropping what no
+    /// longer fits; returns how many operations were dropped
+    pub fn set_history_retention(&mut self, retention: HistoryRetention) -> usize {
+        self.history.set_retention(retention)
+    }
+
+    /// Drop position history beyond `retention` once, without changing the
+    /// standing retention; returns how many operations were dropped
+    ///
+    /// A garbage collection pass for texts that normally keep a long
+    /// history.
+    pub fn prune_history(&mut self, retention: &HistoryRetention) -> usize {
+        self.history.prune(retention)
+    }
+
+    /// Visible characters in document order, for `record_remote_change`
+    ///
+    /// Reads the position cache if it is valid, but doesn't rebuild it:
+    /// that would store cached positions in blocks a delta then carries.
+    pub(super) fn visible_runs(&self) -> Vec<CharRun> {
+        let order = match self.cache_valid {
+            true => None,
+            false => Some(self.get_document_order()),
+        };
+        order
+            .as_deref()
+            .unwrap_or(&self.cached_blocks)
+            .iter()
+            .map(|id| (id, &self.blocks[id]))
+            .filter(|(_, block)| !block.is_deleted() && !block.is_empty())
+            .map(|(id, block)| (id.client_id.clone(), id.clock, block.len()))
+            .collect()
+    }
+
+    /// Log how a merge or delta moved positions, given `visible_runs` from
+    /// before it
+    ///
+    /// Also starts any re-chunking pass over: the remote blocks bring
+    /// origins it hasn't indexed.
+    pub(super) fn record_remote_change(&mut self, before: Vec<CharRun>) {
+        self.rechunk.restart();
+        let after = self.visible_runs();
+        self.record_remote_ops(diff_chars(&before, &after));
+    }
+
+    /// Log a remote change whose position moves are already known
+    pub(super) fn record_remote_ops(&mut self, ops: Vec<PositionOp>) {
+        let version = self.state_vector();
+        self.history.record_remote(ops, &version, &self.client_id);
+    }
+
+    /// Start the history over at the current state vector
+    pub(super) fn restart_history(&mut self) {
+        let retention = self.history.retention();
+        self.history = PositionHistory::starting_at(&self.state_vector(), &self.client_id);
+        self.history.retention = retention;
+    }
+}
+
+impl HeapSize for PositionHistory {
+    fn heap_size(&self) -> usize {
+        self.epochs.capacity() * size_of::<Epoch>()
+            + self
+                .epochs
+                .iter()
+                .map(|epoch| {
+                    epoch.frontier.heap_size()
+                        + epoch.opening.capacity() * size_of::<PositionOp>()
+                        + epoch.changes.capacity() * size_of::<Change>()
+                })
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift64, so every run does the same edits
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n.max(1) as u64) as usize
+        }
+    }
+
+    /// Insert or delete somewhere, never touching the marked characters
+    /// (upper case) so they can be found afterwards
+    fn edit(text: &mut FugueText, rng: &mut Rng) {
+        let len = text.len();
+        if len > 40 && rng.below(2) == 0 {
+            let chars: Vec<char> = text.to_string().chars().collect();
+            let position = rng.below(len);
+            let length = chars[position..]
+                .iter()
+                .take(4)
+                .take_while(|c| !c.is_ascii_uppercase())
+                .count();
+            if length > 0 {
+                text.delete(position, length).unwrap();
+            }
+        } else {
+            let position = rng.below(len + 1);
+            text.insert(position, "xyz").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_positions_follow_their_characters() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        alice.set_history_retention(HistoryRetention {
+            max_ops: None,
+            max_epochs: None,
+        });
+        alice.insert(0, "aaaa A bbbb").unwrap();
+        bob.merge(&alice).unwrap();
+
+        let mut rng = Rng(7);
+        let mut stored = vec![("A", 5, alice.state_vector())];
+        for (round, mark) in ["B", "C", "D"].into_iter().enumerate() {
+            for _ in 0..40 {
+                edit(&mut alice, &mut rng);
+                edit(&mut bob, &mut rng);
+            }
+            let position = rng.below(alice.len() + 1);
+            alice.insert(position, mark).unwrap();
+            stored.push((mark, position, alice.state_vector()));
+            // Deletes don't move the version, so move it on before the
+            // next one (see the module docs)
+            alice.insert(0, "xyz").unwrap();
+            if round % 2 == 0 {
+                alice.merge(&bob).unwrap();
+                bob.merge(&alice).unwrap();
+            }
+        }
+        for _ in 0..60 {
+            edit(&mut alice, &mut rng);
+            edit(&mut bob, &mut rng);
+        }
+        alice.merge(&bob).unwrap();
+
+        // A GC pass dropping the oldest 20 operations: B, C and D are
+        // still within reach, A is not
+        let retained = alice.history.op_count();
+        let dropped = alice.prune_history(&HistoryRetention {
+            max_ops: Some(retained - 20),
+            max_epochs: None,
+        });
+        assert_eq!(dropped, 20);
+        assert_eq!(alice.transform_position(5, &stored[0].2), None);
+
+        let chars: Vec<char> = alice.to_string().chars().collect();
+        for (mark, position, version) in &stored[1..] {
+            let now = alice
+                .transform_position(*position, version)
+                .unwrap_or_else(|| panic!("{} fell out of the history", mark));
+            assert_eq!(chars[now].to_string(), *mark, "{} moved to {}", mark, now);
+        }
+    }
+
+    #[test]
+    fn test_retention_bounds_the_log() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "Hello").unwrap();
+        let start = text.state_vector();
+        let mut rng = Rng(3);
+        for _ in 0..50 {
+            edit(&mut text, &mut rng);
+        }
+        assert!(text.transform_position(0, &start).is_some());
+
+        let dropped = text.set_history_retention(HistoryRetention {
+            max_ops: Some(10),
+            max_epochs: None,
+        });
+        assert_eq!(dropped, 40);
+        assert_eq!(text.transform_position(0, &start), None);
+        for _ in 0..50 {
+            edit(&mut text, &mut rng);
+        }
+        assert_eq!(text.history.op_count(), 10);
+
+        let current = text.state_vector();
+        assert_eq!(text.transform_position(3, &current), Some(3));
+        let mut other = VectorClock::new();
+        other.update("carol", 4);
+        assert_eq!(text.transform_position(3, &other), None);
+    }
+
+    #[test]
+    fn test_merges_open_epochs() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        alice.insert(0, "0123456789").unwrap();
+        bob.merge(&alice).unwrap();
+        let version = alice.state_vector();
+
+        bob.insert(0, "bob:").unwrap();
+        bob.delete(6, 2).unwrap(); // "bob:0145..." loses "23"
+        alice.merge(&bob).unwrap();
+        assert_eq!(alice.to_string(), "bob:01456789");
+        assert_eq!(alice.history.epochs.len(), 2);
+
+        // "5" was at 5, "3" was deleted and lands where it was
+        assert_eq!(alice.transform_position(5, &version), Some(7));
+        assert_eq!(alice.transform_position(3, &version), Some(6));
+
+        alice.set_history_retention(HistoryRetention {
+            max_ops: None,
+            max_epochs: Some(1),
+        });
+        assert_eq!(alice.transform_position(5, &version), None);
+        let now = alice.state_vector();
+        assert_eq!(alice.transform_position(5, &now), Some(5));
+    }
+}
@@ -0,0 +1,347 @@
+//! Bounds on how large a text may grow
+//!
+//! A peer that sends a merge payload with millions of tiny blocks can
+//! exhaust memory, and on the WASM heap that aborts the page. With
+//! [`TextLimits`] set, edits, merges and deltas that would take a text
+//! past them fail with `TextError::LimitExceeded` before anything is
+//! allocated for the new content, and leave the text as it was.
+//!
+//! Only loads given limits are bounded: [`FugueText::from_json_with_limits`],
+//! `FugueText::from_proto_bytes_with_limits` (`protocol-binary` feature)
+//! and deserializing with [`TextLimits`] as a `DeserializeSeed`. A plain
+//! `Deserialize` or `from_proto_bytes` loads the text unlimited.
+//!
+//! Incoming blocks are counted as they arrive: a merge is refused if the
+//! blocks it adds, and their visible characters, would exceed the limits,
+//! even if deletions in the same merge would bring the text back under.
+//! Blocks split in two by edits and merges aren't counted.
+
+use super::block::FugueBlock;
+use super::node::NodeId;
+use super::text::{FugueText, RemoteBlock, TextError, TextParts};
+use serde::de::{DeserializeSeed, Error as _};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Largest text a [`FugueText`] accepts; unlimited by default
+///
+/// Characters are counted as [`FugueText::len`] counts them.
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::crdt::text_fugue::{FugueText, TextError, TextLimits};
+///
+/// let limits = TextLimits {
+///     max_chars: 10,
+///     ..TextLimits::default()
+/// };
+/// let mut text = FugueText::with_limits("client1".to_string(), limits);
+/// text.insert(0, "Hello").unwrap();
+/// assert!(matches!(
+///     text.insert(5, " World"),
+///     Err(TextError::LimitExceeded { .. })
+/// ));
+/// assert_eq!(text.to_string(), "Hello");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextLimits {
+    /// Visible characters
+    pub max_chars: usize,
+
+    /// Blocks, tombstones included
+    pub max_blocks: usize,
+
+    /// Characters in any one block, i.e. one insert
+    pub max_block_text_len: usize,
+}
+
+impl Default for TextLimits {
+    fn default() -> Self {
+        Self {
+            max_chars: usize::MAX,
+            max_blocks: usize::MAX,
+            max_block_text_len: usize::MAX,
+        }
+    }
+}
+
+/// Which of the [`TextLimits`] an operation would exceed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextLimit {
+    /// `max_chars`
+    Chars,
+    /// `max_blocks`
+    Blocks,
+    /// `max_block_text_len`
+    BlockTextLen,
+}
+
+impl fmt::Display for TextLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TextLimit::Chars => "characters",
+            TextLimit::Blocks => "blocks",
+            TextLimit::BlockTextLen => "characters in one block",
+        })
+    }
+}
+
+/// Running totals of a text, checked against its limits as content is
+/// added to them
+#[derive(Debug)]
+pub(super) struct Tally {
+    limits: TextLimits,
+    chars: usize,
+    blocks: usize,
+}
+
+impl Tally {
+    /// Count one more block of `len` characters, `visible` or deleted
+    fn add(&mut self, len: usize, visible: bool) -> Result<(), TextError> {
+        check(TextLimit::BlockTextLen, len, self.limits.max_block_text_len)?;
+        self.blocks = self.blocks.saturating_add(1);
+        check(TextLimit::Blocks, self.blocks, self.limits.max_blocks)?;
+        if visible {
+            self.chars = self.chars.saturating_add(len);
+            check(TextLimit::Chars, self.chars, self.limits.max_chars)?;
+        }
+        Ok(())
+    }
+
+    /// Count a remote block if `action` stores it
+    pub(super) fn add_remote(
+        &mut self,
+        action: RemoteBlock,
+        block: &FugueBlock,
+    ) -> Result<(), TextError> {
+        match action {
+            RemoteBlock::New | RemoteBlock::Empty => self.add(block.len(), !block.is_deleted()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn check(limit: TextLimit, size: usize, max: usize) -> Result<(), TextError> {
+    match size > max {
+        true => Err(TextError::LimitExceeded { limit, size, max }),
+        false => Ok(()),
+    }
+}
+
+impl FugueText {
+    /// Create an empty text bounded by `limits`
+    pub fn with_limits(client_id: String, limits: TextLimits) -> Self {
+        let mut text = Self::new(client_id);
+        text.limits = limits;
+        text
+    }
+
+    /// How large this text may grow
+    pub fn limits(&self) -> TextLimits {
+        self.limits
+    }
+
+    /// Change how large this text may grow; content already over the new
+    /// limits stays, but nothing more can be added
+    pub fn set_limits(&mut self, limits: TextLimits) {
+        self.limits = limits;
+    }
+
+    /// Load a text serialized as JSON, refusing one over `limits`; the
+    /// text keeps them for later edits and merges
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::LimitExceeded` if the text is over `limits`,
+    /// checked before its rope and caches are built, or
+    /// `SyncError::DeserializationError` if `json` isn't a text
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::{FugueText, TextLimits};
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "World").unwrap();
+    /// text.insert(0, "Hello ").unwrap();
+    /// let json = serde_json::to_string(&text).unwrap();
+    ///
+    /// let small = TextLimits { max_blocks: 1, ..TextLimits::default() };
+    /// let error = FugueText::from_json_with_limits(&json, small).unwrap_err();
+    /// assert_eq!(error.code_name(), "LIMIT_EXCEEDED");
+    /// assert!(FugueText::from_json_with_limits(&json, TextLimits::default()).is_ok());
+    /// ```
+    pub fn from_json_with_limits(json: &str, limits: TextLimits) -> crate::error::Result<Self> {
+        let parts: TextParts =
+            serde_json::from_str(json).map_err(crate::error::SyncKitError::deserialization)?;
+        Ok(parts.into_limited_text(limits)?)
+    }
+
+    /// The running totals of this text, to check additions against
+    pub(super) fn tally(&self) -> Tally {
+        Tally {
+            limits: self.limits,
+            chars: self.len(),
+            blocks: self.blocks.len(),
+        }
+    }
+
+    /// Check that inserting `text` in place of `replaced` visible
+    /// characters stays within the limits
+    pub(super) fn check_insert_limits(&self, text: &str, replaced: usize) -> Result<(), TextError> {
+        if self.limits == TextLimits::default() {
+            return Ok(());
+        }
+        let mut tally = self.tally();
+        tally.chars = tally.chars.saturating_sub(replaced);
+        tally.add(text.graphemes(true).count(), true)
+    }
+
+    /// Check that storing the remote blocks `merge` would find new stays
+    /// within the limits
+    pub(super) fn check_remote_limits<'a>(
+        &self,
+        blocks: impl IntoIterator<Item = (&'a NodeId, &'a FugueBlock)>,
+    ) -> Result<(), TextError> {
+        if self.limits == TextLimits::default() {
+            return Ok(());
+        }
+        let mut tally = self.tally();
+        for (id, block) in blocks {
+            let action = self.classify_remote_block(id, block, |start, end| {
+                self.overlaps_local_clock_range(&id.client_id, start, end)
+            });
+            tally.add_remote(action, block)?;
+        }
+        Ok(())
+    }
+}
+
+impl TextParts {
+    /// Check the parts against `limits`, then build the text with them
+    pub(super) fn into_limited_text(self, limits: TextLimits) -> Result<FugueText, TextError> {
+        let mut tally = Tally {
+            limits,
+            chars: 0,
+            blocks: 0,
+        };
+        for (_, block) in &self.blocks {
+            tally.add(block.len(), !block.is_deleted())?;
+        }
+        let mut text = self.into_text();
+        text.limits = limits;
+        text.rebuild_rope();
+        text.restart_history();
+        Ok(text)
+    }
+}
+
+/// Deserialize a text with these limits, e.g. from a format other than
+/// JSON (see [`FugueText::from_json_with_limits`])
+impl<'de> DeserializeSeed<'de> for TextLimits {
+    type Value = FugueText;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<FugueText, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        TextParts::deserialize(deserializer)?
+            .into_limited_text(self)
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge_job::MergeBudget;
+
+    fn limits(max_chars: usize, max_blocks: usize, max_block_text_len: usize) -> TextLimits {
+        TextLimits {
+            max_chars,
+            max_blocks,
+            max_block_text_len,
+        }
+    }
+
+    fn exceeded(result: Result<impl fmt::Debug, TextError>) -> TextLimit {
+        match result {
+            Err(TextError::LimitExceeded { limit, .. }) => limit,
+            other => panic!("expected LimitExceeded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_insert_is_refused_past_each_limit() {
+        let mut text = FugueText::with_limits("alice".to_string(), limits(8, 3, 5));
+        text.insert(0, "Hello").unwrap();
+        assert_eq!(exceeded(text.insert(5, " World")), TextLimit::BlockTextLen);
+        assert_eq!(exceeded(text.insert(5, " Bob!")), TextLimit::Chars);
+        text.delete(0, 4).unwrap();
+        text.insert(0, "J").unwrap();
+        assert_eq!(exceeded(text.insert(0, "X")), TextLimit::Blocks);
+        assert_eq!(text.to_string(), "Jo");
+    }
+
+    #[test]
+    fn test_merge_over_limits_is_rejected_atomically() {
+        let mut alice = FugueText::with_limits("alice".to_string(), limits(100, 4, 100));
+        alice.insert(0, "Hello").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+        for i in 0..10 {
+            bob.insert(5 + i, "!").unwrap();
+            bob.insert(0, "¡").unwrap();
+        }
+        bob.delete(0, 3).unwrap();
+
+        let before = serde_json::to_string(&alice).unwrap();
+        let (blocks, clock) = (alice.memory_stats().block_count, alice.clock());
+        assert_eq!(exceeded(alice.merge(&bob)), TextLimit::Blocks);
+        let delta = bob.diff_since(&alice.state_vector());
+        assert_eq!(exceeded(alice.apply_delta(&delta)), TextLimit::Blocks);
+        let mut job = alice.start_merge(bob.clone());
+        let sliced = loop {
+            match job.run_for(&mut alice, MergeBudget::Ops(4)) {
+                Ok(progress) => assert!(!progress.complete),
+                Err(error) => break Err::<(), _>(error),
+            }
+        };
+        assert_eq!(exceeded(sliced), TextLimit::Blocks);
+        assert_eq!(serde_json::to_string(&alice).unwrap(), before);
+        assert_eq!(alice.memory_stats().block_count, blocks);
+        assert_eq!(alice.clock(), clock);
+        assert_eq!(alice.to_string(), "Hello");
+
+        // Within the limits, the same merge goes through
+        alice.set_limits(TextLimits::default());
+        alice.merge(&bob).unwrap();
+        assert_eq!(alice.to_string(), bob.to_string());
+    }
+
+    #[test]
+    fn test_loading_checks_limits_before_building() {
+        let mut text = FugueText::new("alice".to_string());
+        for _ in 0..10 {
+            text.insert(0, "ab").unwrap();
+        }
+        text.delete(0, 6).unwrap();
+        let json = serde_json::to_string(&text).unwrap();
+
+        let error = FugueText::from_json_with_limits(&json, limits(13, 100, 100)).unwrap_err();
+        assert_eq!(error.code(), 1011);
+        let loaded = FugueText::from_json_with_limits(&json, limits(14, 100, 100)).unwrap();
+        assert_eq!(loaded.to_string(), text.to_string());
+        assert_eq!(loaded.limits().max_chars, 14);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        let error = limits(100, 5, 100)
+            .deserialize(&mut deserializer)
+            .unwrap_err();
+        assert!(error.to_string().contains("blocks"), "{}", error);
+    }
+}
@@ -0,0 +1,873 @@
+//! Incremental line index: converts between offsets and (line, column)
+//! without rescanning the text
+//!
+//! Editors convert positions on every keystroke (cursor, diagnostics,
+//! LSP requests). Rebuilding the line starts from `to_string()` each time
+//! costs O(n) per edit. A [`LineIndex`] keeps the length of every line in
+//! a balanced tree (a treap ordered by line number, each node knowing the
+//! lines and characters under it) and updates it from the text's
+//! [`TextEvent`]s, so an edit costs O(log n) plus the lines it adds or
+//! removes.
+//!
+//! Offsets and columns are in the units of `FugueText::len()`; a line's
+//! length includes its `\n` (one unit, as is `\r\n` typed in one go),
+//! except for the last line, which has none.
+//! [`FugueText::track_lines`] keeps an index up to date by subscribing to
+//! the text; the index matches the text after every delivered batch.
+//!
+//! For one-off lookups without an index, `FugueText::line`,
+//! `line_to_char`, `char_to_line_col` and `insert_at_line_col` read the
+//! lines from the text's rope, with the same lines and columns.
+//!
+//! Each batch applied bumps [`LineIndex::generation`], and
+//! [`LineIndex::lines_changed_since`] tells which lines to re-render since
+//! one the caller saw.
+//!
+//! # Example
+//!
+//! ```rust
+//! use synckit_core::crdt::text_fugue::{FugueText, LinePosition};
+//!
+//! let mut text = FugueText::new("client1".to_string());
+//! text.insert(0, "fn main() {\n}\n").unwrap();
+//! let lines = text.track_lines();
+//!
+//! text.insert(12, "    println!();\n").unwrap();
+//! let lines = lines.lock().unwrap();
+//! assert_eq!(lines.line_count(), 4);
+//! assert_eq!(
+//!     lines.offset_to_position(16),
+//!     Some(LinePosition { line: 1, column: 4 })
+//! );
+//! assert_eq!(lines.position_to_offset(LinePosition { line: 2, column: 0 }), Some(28));
+//! assert_eq!(lines.lines_changed_since(0), Some(1..3));
+//! ```
+
+use super::delta::TextEvent;
+use super::node::NodeId;
+use super::text::{FugueText, TextError};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How many past line changes [`LineIndex::lines_changed_since`] can look
+/// back over
+const TRACKED_CHANGES: usize = 1024;
+
+const NIL: u32 = u32::MAX;
+
+/// A zero-based line and column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct LinePosition {
+    /// Line number
+    pub line: usize,
+
+    /// Characters from the start of the line
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    /// Length of this line, with its `\n`
+    len: usize,
+    priority: u32,
+    left: u32,
+    right: u32,
+    /// Lines in this subtree
+    lines: usize,
+    /// Characters in this subtree
+    chars: usize,
+}
+
+/// Lines `start..start + removed` replaced by `added` lines
+#[derive(Debug, Clone, Copy)]
+struct LineChange {
+    generation: u64,
+    start: usize,
+    removed: usize,
+    added: usize,
+}
+
+/// Line start offsets of a text, updated from its edits (see the module
+/// docs)
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    nodes: Vec<Node>,
+    free: Vec<u32>,
+    root: u32,
+    seed: u32,
+    generation: u64,
+    changes: VecDeque<LineChange>,
+    /// Changes after this generation are all in `changes`
+    tracked_since: u64,
+}
+
+impl LineIndex {
+    /// Index `text`, counting its grapheme clusters
+    pub fn new(text: &str) -> Self {
+        Self::with_lines(line_lengths(text.graphemes(true)))
+    }
+
+    /// Index the text's current content
+    ///
+    /// Counts the text's own units, so this matches `FugueText::len()`
+    /// even where a cluster was typed in parts.
+    pub fn of(text: &FugueText) -> Self {
+        let snapshot = text.snapshot();
+        let content = snapshot.to_string();
+        Self::with_lines(line_lengths(snapshot.units(&content)))
+    }
+
+    fn with_lines(lengths: Vec<usize>) -> Self {
+        let mut index = Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: NIL,
+            seed: 0x9e37_79b9,
+            generation: 0,
+            changes: VecDeque::new(),
+            tracked_since: 0,
+        };
+        index.root = index.build(lengths);
+        index
+    }
+
+    /// Number of lines; an empty text has one
+    pub fn line_count(&self) -> usize {
+        self.lines(self.root)
+    }
+
+    /// Length of the text
+    pub fn len(&self) -> usize {
+        self.chars(self.root)
+    }
+
+    /// Check if the text is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Batches applied so far
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The line and column of `offset`, or None past the end
+    pub fn offset_to_position(&self, offset: usize) -> Option<LinePosition> {
+        match offset.cmp(&self.len()) {
+            std::cmp::Ordering::Greater => None,
+            std::cmp::Ordering::Equal => {
+                let line = self.line_count() - 1;
+                Some(LinePosition {
+                    line,
+                    column: self.line_len(line),
+                })
+            }
+            std::cmp::Ordering::Less => Some(self.locate(offset)),
+        }
+    }
+
+    /// The offset of `position`, or None if the line doesn't exist or
+    /// the column is past its end (its `\n` excluded)
+    pub fn position_to_offset(&self, position: LinePosition) -> Option<usize> {
+        let range = self.line_range(position.line)?;
+        (position.column <= range.len()).then_some(range.start + position.column)
+    }
+
+    /// The offsets of `line`, without its line break
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        if line >= self.line_count() {
+            return None;
+        }
+        let start = self.line_start(line);
+        let mut len = self.line_len(line);
+        if line + 1 < self.line_count() {
+            len -= 1;
+        }
+        Some(start..start + len)
+    }
+
+    /// The lines, in current numbering, changed by the batches applied
+    /// after `generation`, or None if nothing changed
+    ///
+    /// Covers removed lines too: their neighbours are included. Every
+    /// line is returned when `generation` is older than the index
+    /// remembers.
+    pub fn lines_changed_since(&self, generation: u64) -> Option<Range<usize>> {
+        if generation >= self.generation {
+            return None;
+        }
+        if generation < self.tracked_since {
+            return Some(0..self.line_count());
+        }
+        let mut changed: Option<Range<usize>> = None;
+        for change in self.changes.iter().filter(|c| c.generation > generation) {
+            let end = change.start + change.removed;
+            let shift = |line: usize| match line {
+                line if line <= change.start => line,
+                line if line <= end => change.start + change.added,
+                line => line + change.added - change.removed,
+            };
+            let range = match changed {
+                None => change.start..change.start + change.added,
+                Some(range) => {
+                    shift(range.start).min(change.start)
+                        ..shift(range.end).max(change.start + change.added)
+                }
+            };
+            changed = Some(range);
+        }
+        changed.map(|range| range.start..range.end.min(self.line_count()))
+    }
+
+    /// Apply one batch of events from the text, in order
+    ///
+    /// # Errors
+    ///
+    /// Fails, at the first event that doesn't fit the indexed text, with
+    /// `PositionOutOfBounds` or `RangeOutOfBounds`; the index is then out
+    /// of step with the text and should be rebuilt.
+    pub fn apply(&mut self, events: &[TextEvent]) -> Result<(), TextError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.generation += 1;
+        for event in events {
+            match event {
+                TextEvent::Insert { position, text } => self.insert(*position, text)?,
+                TextEvent::Delete { position, length } => self.delete(*position, *length)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, position: usize, text: &str) -> Result<(), TextError> {
+        let length = self.len();
+        if position > length {
+            return Err(TextError::PositionOutOfBounds { position, length });
+        }
+        let at = self.offset_to_position(position).expect("position checked");
+        let old_len = self.line_len(at.line);
+        let mut lengths = line_lengths(text.graphemes(true));
+        let last = lengths.len() - 1;
+        lengths[0] += at.column;
+        lengths[last] += old_len - at.column;
+        self.replace_lines(at.line..at.line + 1, lengths);
+        Ok(())
+    }
+
+    fn delete(&mut self, position: usize, length: usize) -> Result<(), TextError> {
+        let text_len = self.len();
+        let end = position.saturating_add(length);
+        if end > text_len {
+            return Err(TextError::RangeOutOfBounds {
+                start: position,
+                end,
+                length: text_len,
+            });
+        }
+        if length == 0 {
+            return Ok(());
+        }
+        let from = self.offset_to_position(position).expect("range checked");
+        let to = self.offset_to_position(end).expect("range checked");
+        let len = from.column + self.line_len(to.line) - to.column;
+        self.replace_lines(from.line..to.line + 1, vec![len]);
+        Ok(())
+    }
+
+    /// Replace `lines` with lines of `lengths`
+    fn replace_lines(&mut self, lines: Range<usize>, lengths: Vec<usize>) {
+        let added = lengths.len();
+        if lines.len() == 1 && added == 1 {
+            self.set_len(lines.start, lengths[0]);
+        } else {
+            let (before, rest) = self.split(self.root, lines.start);
+            let (removed, after) = self.split(rest, lines.len());
+            self.release(removed);
+            let middle = self.build(lengths);
+            let joined = self.merge(before, middle);
+            self.root = self.merge(joined, after);
+        }
+
+        self.changes.push_back(LineChange {
+            generation: self.generation,
+            start: lines.start,
+            removed: lines.len(),
+            added,
+        });
+        if self.changes.len() > TRACKED_CHANGES {
+            if let Some(dropped) = self.changes.pop_front() {
+                self.tracked_since = dropped.generation;
+            }
+        }
+    }
+
+    /// Line and column of `offset`, which is before the end
+    fn locate(&self, mut offset: usize) -> LinePosition {
+        let mut node = self.root;
+        let mut line = 0;
+        loop {
+            let Node {
+                left, right, len, ..
+            } = self.nodes[node as usize];
+            let left_chars = self.chars(left);
+            if offset < left_chars {
+                node = left;
+            } else if offset < left_chars + len {
+                return LinePosition {
+                    line: line + self.lines(left),
+                    column: offset - left_chars,
+                };
+            } else {
+                offset -= left_chars + len;
+                line += self.lines(left) + 1;
+                node = right;
+            }
+        }
+    }
+
+    /// Offset where `line` starts, which exists
+    fn line_start(&self, mut line: usize) -> usize {
+        let mut node = self.root;
+        let mut offset = 0;
+        loop {
+            let Node {
+                left, right, len, ..
+            } = self.nodes[node as usize];
+            let left_lines = self.lines(left);
+            if line < left_lines {
+                node = left;
+            } else if line == left_lines {
+                return offset + self.chars(left);
+            } else {
+                offset += self.chars(left) + len;
+                line -= left_lines + 1;
+                node = right;
+            }
+        }
+    }
+
+    /// Length of `line`, which exists, with its `\n`
+    fn line_len(&self, line: usize) -> usize {
+        self.nodes[self.find(line) as usize].len
+    }
+
+    fn set_len(&mut self, line: usize, len: usize) {
+        // Walk down again, fixing the character counts on the path
+        let mut node = self.root;
+        let mut line = line;
+        let mut path = Vec::new();
+        loop {
+            path.push(node);
+            let Node { left, right, .. } = self.nodes[node as usize];
+            let left_lines = self.lines(left);
+            if line < left_lines {
+                node = left;
+            } else if line == left_lines {
+                break;
+            } else {
+                line -= left_lines + 1;
+                node = right;
+            }
+        }
+        self.nodes[node as usize].len = len;
+        for node in path.into_iter().rev() {
+            self.update(node);
+        }
+    }
+
+    fn find(&self, mut line: usize) -> u32 {
+        let mut node = self.root;
+        loop {
+            let Node { left, right, .. } = self.nodes[node as usize];
+            let left_lines = self.lines(left);
+            if line < left_lines {
+                node = left;
+            } else if line == left_lines {
+                return node;
+            } else {
+                line -= left_lines + 1;
+                node = right;
+            }
+        }
+    }
+
+    fn lines(&self, node: u32) -> usize {
+        match node {
+            NIL => 0,
+            node => self.nodes[node as usize].lines,
+        }
+    }
+
+    fn chars(&self, node: u32) -> usize {
+        match node {
+            NIL => 0,
+            node => self.nodes[node as usize].chars,
+        }
+    }
+
+    fn update(&mut self, node: u32) {
+        let Node {
+            left, right, len, ..
+        } = self.nodes[node as usize];
+        let lines = self.lines(left) + 1 + self.lines(right);
+        let chars = self.chars(left) + len + self.chars(right);
+        let node = &mut self.nodes[node as usize];
+        node.lines = lines;
+        node.chars = chars;
+    }
+
+    fn alloc(&mut self, len: usize) -> u32 {
+        // xorshift32: deterministic, so indexes of the same text are
+        // shaped alike
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        let node = Node {
+            len,
+            priority: self.seed,
+            left: NIL,
+            right: NIL,
+            lines: 1,
+            chars: len,
+        };
+        match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot as usize] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                (self.nodes.len() - 1) as u32
+            }
+        }
+    }
+
+    /// A tree of lines of `lengths`, in order
+    fn build(&mut self, lengths: impl IntoIterator<Item = usize>) -> u32 {
+        // Cartesian tree on a stack: linear time
+        let mut spine: Vec<u32> = Vec::new();
+        for len in lengths {
+            let node = self.alloc(len);
+            let mut last = NIL;
+            while let Some(&top) = spine.last() {
+                if self.nodes[top as usize].priority >= self.nodes[node as usize].priority {
+                    break;
+                }
+                spine.pop();
+                self.update(top);
+                last = top;
+            }
+            self.nodes[node as usize].left = last;
+            if let Some(&top) = spine.last() {
+                self.nodes[top as usize].right = node;
+            }
+            spine.push(node);
+        }
+        let root = spine.first().copied().unwrap_or(NIL);
+        while let Some(top) = spine.pop() {
+            self.update(top);
+        }
+        root
+    }
+
+    /// Split off the first `lines` lines of `node`
+    fn split(&mut self, node: u32, lines: usize) -> (u32, u32) {
+        if node == NIL {
+            return (NIL, NIL);
+        }
+        let Node { left, right, .. } = self.nodes[node as usize];
+        let left_lines = self.lines(left);
+        if lines <= left_lines {
+            let (first, rest) = self.split(left, lines);
+            self.nodes[node as usize].left = rest;
+            self.update(node);
+            (first, node)
+        } else {
+            let (first, rest) = self.split(right, lines - left_lines - 1);
+            self.nodes[node as usize].right = first;
+            self.update(node);
+            (node, rest)
+        }
+    }
+
+    fn merge(&mut self, left: u32, right: u32) -> u32 {
+        if left == NIL {
+            return right;
+        }
+        if right == NIL {
+            return left;
+        }
+        if self.nodes[left as usize].priority > self.nodes[right as usize].priority {
+            let merged = self.merge(self.nodes[left as usize].right, right);
+            self.nodes[left as usize].right = merged;
+            self.update(left);
+            left
+        } else {
+            let merged = self.merge(left, self.nodes[right as usize].left);
+            self.nodes[right as usize].left = merged;
+            self.update(right);
+            right
+        }
+    }
+
+    /// Free every node of a subtree
+    fn release(&mut self, node: u32) {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if node != NIL {
+                let Node { left, right, .. } = self.nodes[node as usize];
+                stack.extend([left, right]);
+                self.free.push(node);
+            }
+        }
+    }
+}
+
+/// A [`LineIndex`] shared with the subscription that keeps it up to date
+pub type SharedLineIndex = Arc<Mutex<LineIndex>>;
+
+/// Length of each line of `units`, with its line break; the last line
+/// has none, and a text without units is one empty line
+fn line_lengths<'a>(units: impl Iterator<Item = &'a str>) -> Vec<usize> {
+    let mut lengths = vec![0];
+    for unit in units {
+        *lengths.last_mut().expect("never empty") += 1;
+        // `\n` or `\r\n`, a single cluster
+        if unit.ends_with('\n') {
+            lengths.push(0);
+        }
+    }
+    lengths
+}
+
+impl FugueText {
+    /// A line index of this text, kept up to date by a subscription (see
+    /// [`FugueText::subscribe`])
+    ///
+    /// The index matches the text after every delivered batch; with
+    /// coalescing on, it lags the text by the held-back changes.
+    pub fn track_lines(&mut self) -> SharedLineIndex {
+        let index = Arc::new(Mutex::new(LineIndex::of(self)));
+        let tracked = index.clone();
+        self.subscribe(move |events| {
+            let mut index = tracked.lock().unwrap_or_else(|e| e.into_inner());
+            if index.apply(events).is_err() {
+                trace_debug!("line index out of step with its text");
+            }
+        });
+        index
+    }
+
+    /// Number of lines, read from the rope; an empty text has one and a
+    /// trailing `\n` starts an empty last line
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// The text of `line` without its line break (`\n` or `\r\n`), or
+    /// None past the last line
+    pub fn line(&self, line: usize) -> Option<String> {
+        let chars = self.line_chars(line)?;
+        Some(self.rope.slice(chars).to_string())
+    }
+
+    /// The offset at which `line` starts, or None past the last line
+    pub fn line_to_char(&self, line: usize) -> Option<usize> {
+        let chars = self.line_chars(line)?;
+        Some(self.units.units_before_char(chars.start))
+    }
+
+    /// The line and column of `position`, or None past the end
+    pub fn char_to_line_col(&self, position: usize) -> Option<LinePosition> {
+        if position > self.len() {
+            return None;
+        }
+        let char = self.units.char_offset(position);
+        let line = self.rope.char_to_line(char);
+        let start = self.units.units_before_char(self.rope.line_to_char(line));
+        Some(LinePosition {
+            line,
+            column: position - start,
+        })
+    }
+
+    /// Insert `text` at `column` of `line`
+    ///
+    /// A column past the end of the line is clamped to its end, before
+    /// the line break, as editors place a cursor.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::PositionOutOfBounds` (with line numbers) if
+    /// the line doesn't exist
+    pub fn insert_at_line_col(
+        &mut self,
+        line: usize,
+        column: usize,
+        text: &str,
+    ) -> Result<NodeId, TextError> {
+        let chars = self
+            .line_chars(line)
+            .ok_or(TextError::PositionOutOfBounds {
+                position: line,
+                length: self.line_count(),
+            })?;
+        let start = self.units.units_before_char(chars.start);
+        let end = self.units.units_before_char(chars.end);
+        self.insert((start + column).min(end), text)
+    }
+
+    /// Rope chars of `line`, without its line break
+    fn line_chars(&self, line: usize) -> Option<Range<usize>> {
+        if line >= self.rope.len_lines() {
+            return None;
+        }
+        let content = self.rope.line(line);
+        let mut len = content.len_chars();
+        if len > 0 && content.char(len - 1) == '\n' {
+            len -= 1;
+            if len > 0 && content.char(len - 1) == '\r' {
+                len -= 1;
+            }
+        }
+        let start = self.rope.line_to_char(line);
+        Some(start..start + len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Line ranges recomputed from scratch
+    fn naive_lines(text: &str) -> Vec<Range<usize>> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (offset, grapheme) in text.graphemes(true).enumerate() {
+            if grapheme.ends_with('\n') {
+                lines.push(start..offset);
+                start = offset + 1;
+            }
+        }
+        lines.push(start..text.graphemes(true).count());
+        lines
+    }
+
+    fn check(index: &LineIndex, text: &str) {
+        let lines = naive_lines(text);
+        assert_eq!(index.line_count(), lines.len());
+        assert_eq!(index.len(), text.graphemes(true).count());
+        for (line, range) in lines.iter().enumerate() {
+            assert_eq!(index.line_range(line).as_ref(), Some(range));
+            for column in 0..=range.len() {
+                let position = LinePosition { line, column };
+                let offset = range.start + column;
+                assert_eq!(index.offset_to_position(offset), Some(position));
+                assert_eq!(index.position_to_offset(position), Some(offset));
+            }
+            let past = LinePosition {
+                line,
+                column: range.len() + 1,
+            };
+            assert_eq!(index.position_to_offset(past), None);
+        }
+        assert_eq!(index.offset_to_position(index.len() + 1), None);
+        assert_eq!(index.line_range(lines.len()), None);
+    }
+
+    #[test]
+    fn test_random_edits_match_naive_recomputation() {
+        let mut text = FugueText::new("alice".to_string());
+        let mut other = FugueText::new("bob".to_string());
+        let index = text.track_lines();
+        let pieces = [
+            "a",
+            "\n",
+            "xy\nz",
+            "\n\n",
+            "é\nü",
+            "line\n",
+            "  ",
+            "👨\u{200D}👩\u{200D}👧 漢\r\n",
+        ];
+        let mut seed: u32 = 0x2545_f491;
+        let mut next = |bound: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize % bound.max(1)
+        };
+        for round in 0..200 {
+            let len = text.len();
+            if len > 0 && next(3) == 0 {
+                let position = next(len);
+                let length = 1 + next((len - position).min(8));
+                text.delete(position, length).unwrap();
+            } else {
+                let piece = pieces[next(pieces.len())];
+                text.insert(next(len + 1), piece).unwrap();
+            }
+            // Concurrent edits arrive as multi-event batches
+            if round % 25 == 0 {
+                let len = other.len();
+                other.insert(next(len + 1), "remote\nedit").unwrap();
+                text.merge(&other).unwrap();
+                other.merge(&text).unwrap();
+            }
+            check(&index.lock().unwrap(), &text.to_string());
+        }
+    }
+
+    #[test]
+    fn test_lines_changed_since() {
+        let mut index = LineIndex::new("a\nb\nc\nd\ne");
+        let start = index.generation();
+        assert_eq!(index.lines_changed_since(start), None);
+
+        // Split line 1, then join lines 3 and 4
+        index
+            .apply(&[TextEvent::Insert {
+                position: 3,
+                text: "\n".to_string(),
+            }])
+            .unwrap();
+        let middle = index.generation();
+        assert_eq!(index.lines_changed_since(start), Some(1..3));
+        index
+            .apply(&[TextEvent::Delete {
+                position: 8,
+                length: 1,
+            }])
+            .unwrap();
+        assert_eq!(index.lines_changed_since(middle), Some(4..5));
+        assert_eq!(index.lines_changed_since(start), Some(1..5));
+
+        // Deleting lines shrinks an earlier range
+        index
+            .apply(&[TextEvent::Delete {
+                position: 0,
+                length: 4,
+            }])
+            .unwrap();
+        assert_eq!(index.line_count(), 3);
+        assert_eq!(index.lines_changed_since(start), Some(0..3));
+    }
+
+    #[test]
+    fn test_out_of_range_events_fail() {
+        let mut index = LineIndex::new("ab\ncd");
+        let insert = TextEvent::Insert {
+            position: 6,
+            text: "x".to_string(),
+        };
+        let delete = TextEvent::Delete {
+            position: 4,
+            length: 2,
+        };
+        assert!(matches!(
+            index.apply(&[insert]),
+            Err(TextError::PositionOutOfBounds { .. })
+        ));
+        assert!(matches!(
+            index.apply(&[delete]),
+            Err(TextError::RangeOutOfBounds { .. })
+        ));
+        check(&index, "ab\ncd");
+    }
+
+    #[test]
+    fn test_columns_count_clusters() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "e\u{301}👨\u{200D}👩\u{200D}👧\r\n漢字")
+            .unwrap();
+        // An accent typed on its own stays a unit of its own
+        text.insert(1, "\u{302}").unwrap();
+        let index = LineIndex::of(&text);
+        assert_eq!(index.len(), text.len());
+        assert_eq!(index.line_range(0), Some(0..3));
+        assert_eq!(index.line_range(1), Some(4..6));
+        assert_eq!(
+            index.offset_to_position(5),
+            Some(LinePosition { line: 1, column: 1 })
+        );
+    }
+
+    #[test]
+    fn test_line_api_on_empty_text() {
+        let mut text = FugueText::new("alice".to_string());
+        assert_eq!(text.line_count(), 1);
+        assert_eq!(text.line(0).as_deref(), Some(""));
+        assert_eq!(text.line(1), None);
+        assert_eq!(text.line_to_char(0), Some(0));
+        assert_eq!(
+            text.char_to_line_col(0),
+            Some(LinePosition { line: 0, column: 0 })
+        );
+        assert_eq!(text.char_to_line_col(1), None);
+
+        text.insert_at_line_col(0, 5, "x").unwrap();
+        assert_eq!(text.to_string(), "x");
+        assert!(matches!(
+            text.insert_at_line_col(1, 0, "y"),
+            Err(TextError::PositionOutOfBounds {
+                position: 1,
+                length: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_line_api_with_crlf_and_trailing_newline() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "ab\r\ncé\n").unwrap();
+        text.insert(3, "a\rb").unwrap();
+
+        assert_eq!(text.to_string(), "ab\r\na\rbcé\n");
+        assert_eq!(text.line_count(), 3);
+        assert_eq!(text.line(0).as_deref(), Some("ab"));
+        assert_eq!(text.line(1).as_deref(), Some("a\rbcé"));
+        assert_eq!(text.line(2).as_deref(), Some(""));
+        assert_eq!(text.line_to_char(1), Some(3));
+        assert_eq!(text.line_to_char(2), Some(9));
+
+        let index = LineIndex::of(&text);
+        for position in 0..=text.len() {
+            assert_eq!(
+                text.char_to_line_col(position),
+                index.offset_to_position(position)
+            );
+        }
+
+        // Past the end of the line lands before its `\r\n`
+        text.insert_at_line_col(0, 10, "!").unwrap();
+        text.insert_at_line_col(2, 3, "end").unwrap();
+        assert_eq!(text.to_string(), "ab!\r\na\rbcé\nend");
+    }
+
+    #[test]
+    fn test_insert_at_line_after_remote_lines_above() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "line one\nline two\nline three\n").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        // An editor showing "line two" as line 1 hears of two lines
+        // added above it, then the user types at line 3, column 0
+        bob.insert(0, "// header\n\n").unwrap();
+        alice.merge(&bob).unwrap();
+        assert_eq!(alice.line(3).as_deref(), Some("line two"));
+        alice.insert_at_line_col(3, 0, "> ").unwrap();
+
+        bob.merge(&alice).unwrap();
+        assert_eq!(bob.line(3).as_deref(), Some("> line two"));
+        assert_eq!(
+            bob.char_to_line_col(bob.line_to_char(3).unwrap() + 2),
+            Some(LinePosition { line: 3, column: 2 })
+        );
+        assert_eq!(bob.line_count(), 6);
+    }
+}
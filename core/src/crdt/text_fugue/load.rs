@@ -0,0 +1,155 @@
+//! Building a text from existing content in one pass
+//!
+//! Loading a document through `insert` pays for origin lookups, cache
+//! updates and rope edits on every piece. Content that is simply appended
+//! piece after piece needs none of that: each block goes after the last
+//! character of the one before, with the next clocks, exactly as
+//! `insert(len, piece)` would have placed it, so the result merges like a
+//! replica that typed the same pieces.
+
+use super::block::FugueBlock;
+use super::node::NodeId;
+use super::text::FugueText;
+use super::units::UnitTable;
+use ropey::Rope;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+impl FugueText {
+    /// A text holding `content`, written by `client_id`
+    ///
+    /// The same as `insert(0, content)` on a new text, without the
+    /// bookkeeping of an edit.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let text = FugueText::from_str("client1".to_string(), "Hello World");
+    /// assert_eq!(text.to_string(), "Hello World");
+    /// assert_eq!(text.clock(), 11);
+    /// ```
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(client_id: String, content: &str) -> Self {
+        Self::from_paragraphs(client_id, [content])
+    }
+
+    /// A text holding `paragraphs` one after another, one block each
+    ///
+    /// The text and clocks are those of appending each piece in turn, but
+    /// where `insert` at the end would extend the previous block, each
+    /// paragraph keeps its own.
+    ///
+    /// The pieces are joined as given, so they should carry their own line
+    /// breaks. Keeping paragraphs apart lets later edits split small blocks
+    /// instead of one that spans the document. O(n) in the length of the
+    /// content.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let text = FugueText::from_paragraphs(
+    ///     "client1".to_string(),
+    ///     ["First paragraph.\n", "Second paragraph.\n"],
+    /// );
+    /// assert_eq!(text.to_string(), "First paragraph.\nSecond paragraph.\n");
+    /// assert_eq!(text.memory_usage().counter("block_count"), 2);
+    /// ```
+    pub fn from_paragraphs<I>(client_id: String, paragraphs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut text = FugueText::new(client_id);
+        text.touch();
+
+        let mut content = String::new();
+        let mut blocks = BTreeMap::new();
+        let mut cached_blocks = Vec::new();
+        let mut cached_positions = HashMap::new();
+        let mut units = UnitTable::default();
+        let mut left_origin: Option<NodeId> = None;
+
+        for paragraph in paragraphs {
+            let paragraph = paragraph.as_ref();
+            if paragraph.is_empty() {
+                continue;
+            }
+            // The clock is only known once the block has counted its units
+            let mut block = FugueBlock::new(
+                NodeId::new(text.client_id.clone(), 0, 0),
+                paragraph.to_string(),
+                left_origin.take(),
+                None,
+            );
+            let len = block.len();
+            let id = NodeId::new(text.client_id.clone(), text.clock.tick_by(len), 0);
+            block.id = id.clone();
+            cached_positions.insert(id.clone(), units.len());
+
+            units.push(paragraph);
+            content.push_str(paragraph);
+            left_origin = Some(id.clone());
+            cached_blocks.push(id.clone());
+            blocks.insert(id, Arc::new(block));
+        }
+
+        text.rope = Rope::from_str(&content);
+        text.units = units;
+        text.blocks = Arc::new(blocks);
+        text.cached_blocks = Arc::new(cached_blocks);
+        text.cached_positions = Arc::new(cached_positions);
+        text.cache_valid = true;
+        text.restart_history();
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_state_as_inserting_at_the_end() {
+        let paragraphs = ["Hello 👋\n", "", "Wörld\r\n", "👨‍👩‍👧 family\n"];
+        let loaded = FugueText::from_paragraphs("client1".to_string(), paragraphs);
+
+        let mut typed = FugueText::new("client1".to_string());
+        // An empty insert would leave an empty block
+        for paragraph in paragraphs.iter().filter(|p| !p.is_empty()) {
+            typed.insert(typed.len(), paragraph).unwrap();
+        }
+        assert_eq!(loaded.to_string(), typed.to_string());
+        assert_eq!(loaded.len(), typed.len());
+        assert_eq!(loaded.clock(), typed.clock());
+        assert_eq!(loaded.structure_hash(), typed.structure_hash());
+
+        let whole = FugueText::from_str("client1".to_string(), &paragraphs.concat());
+        assert_eq!(whole.to_string(), loaded.to_string());
+        assert_eq!(FugueText::from_str("client1".to_string(), "").len(), 0);
+    }
+
+    #[test]
+    fn test_loaded_replica_merges_with_edited_one() {
+        let mut loaded =
+            FugueText::from_paragraphs("alice".to_string(), ["The quick\n", "brown fox\n"]);
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&loaded).unwrap();
+
+        // Edits straight after loading use the cache built with the blocks
+        loaded.insert(4, "very ").unwrap();
+        loaded.delete(0, 4).unwrap();
+        bob.insert(0, "> ").unwrap();
+        bob.insert(bob.len(), "jumps\n").unwrap();
+        bob.delete(12, 6).unwrap();
+
+        loaded.merge(&bob).unwrap();
+        bob.merge(&loaded).unwrap();
+        assert_eq!(loaded.to_string(), "> very quick\nfox\njumps\n");
+        assert_eq!(bob.to_string(), loaded.to_string());
+        assert_eq!(bob.structure_hash(), loaded.structure_hash());
+    }
+}
@@ -0,0 +1,921 @@
+//! Markdown export and import for FugueText
+//!
+//! Exports render the visible text as Markdown so collaborative documents
+//! can be stored and diffed in git. Formatting is passed in as
+//! [`MarkdownSpan`]s over text positions and rendered as inline
+//! Markdown (`**bold**`, `*italic*`, `~~strike~~`, `` `code` ``,
+//! `[link](href)`). Markdown control characters in the text itself are
+//! backslash-escaped, so the export renders back to the same text.
+//!
+//! With [`MarkdownOptions::attribution`], each run of text from a different
+//! author is preceded by an HTML comment such as
+//! `<!-- author: client1 clock: 42 -->`. Comments starting a line sit on a
+//! line of their own so they don't turn the text after them into raw HTML.
+//!
+//! # Lossy import
+//!
+//! [`FugueText::from_markdown`] parses inline formatting back into spans
+//! and drops comments. It is deliberately lossy:
+//!
+//! - Block syntax (headings, lists, quotes) is kept as literal text.
+//! - Inline formatting cannot cross line breaks in Markdown, so the exporter
+//!   closes spans at the end of each line and reopens them on the next. The
+//!   importer joins spans of the same style separated by a single line break,
+//!   which also joins two spans that happened to end and start there.
+//! - The imported text is a fresh replica with a single author; attribution
+//!   comments are not turned back into history.
+
+use super::text::{FugueText, TextError};
+use super::units::units_by_char;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+/// Inline formatting understood by the Markdown exporter and importer
+///
+/// Variants are ordered from outermost to innermost: when several styles
+/// open at the same position, links wrap emphasis and code is always
+/// innermost (nothing can be formatted inside a code span).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MarkdownStyle {
+    /// `[text](href)`
+    Link(String),
+
+    /// `**text**`
+    Bold,
+
+    /// `*text*`
+    Italic,
+
+    /// `~~text~~`
+    Strikethrough,
+
+    /// `` `text` ``
+    Code,
+}
+
+/// A formatted range of visible text
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MarkdownSpan {
+    /// Positions covered, in the units of `FugueText::len()` (end exclusive)
+    pub range: Range<usize>,
+
+    /// Formatting applied to the range
+    pub style: MarkdownStyle,
+}
+
+impl MarkdownSpan {
+    /// Create a new span
+    pub fn new(range: Range<usize>, style: MarkdownStyle) -> Self {
+        Self { range, style }
+    }
+}
+
+/// Options for [`FugueText::to_markdown`]
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownOptions {
+    /// Emit an `<!-- author: … clock: … -->` comment before each run of text
+    /// written by a different client, carrying the clock of the run's first
+    /// block ID
+    pub attribution: bool,
+
+    /// Formatting to render, in positions of the visible text
+    ///
+    /// Spans may overlap and nest; ranges past the end are clamped.
+    pub spans: Vec<MarkdownSpan>,
+}
+
+/// Result of [`FugueText::from_markdown`]
+#[derive(Debug, Clone)]
+pub struct MarkdownImport {
+    /// Plain text with Markdown syntax stripped
+    pub text: FugueText,
+
+    /// Inline formatting found in the source, sorted by position
+    pub spans: Vec<MarkdownSpan>,
+}
+
+/// Start of a run of visible text written by one client
+struct AuthorRun<'a> {
+    start: usize,
+    client_id: &'a str,
+    clock: u64,
+}
+
+impl FugueText {
+    /// Render the visible text as Markdown
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::{
+    ///     FugueText, MarkdownOptions, MarkdownSpan, MarkdownStyle,
+    /// };
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello *world*").unwrap();
+    ///
+    /// let options = MarkdownOptions {
+    ///     spans: vec![MarkdownSpan::new(0..5, MarkdownStyle::Bold)],
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(text.to_markdown(&options), r"**Hello** \*world\*");
+    /// ```
+    pub fn to_markdown(&self, options: &MarkdownOptions) -> String {
+        let chars: Vec<char> = self.rope.chars().collect();
+        let len = chars.len();
+        // Spans are in position units; the rendering works on chars
+        let spans: Vec<MarkdownSpan> = options
+            .spans
+            .iter()
+            .map(|span| {
+                let range = self.units.char_range(span.range.clone());
+                MarkdownSpan::new(range, span.style.clone())
+            })
+            .collect();
+
+        let authors = if options.attribution {
+            self.author_runs()
+        } else {
+            Vec::new()
+        };
+
+        // Split the text wherever formatting or authorship changes; line
+        // breaks are segments of their own so every style closes before them
+        let mut boundaries = vec![0, len];
+        for span in &spans {
+            boundaries.push(span.range.start.min(len));
+            boundaries.push(span.range.end.min(len));
+        }
+        for (i, c) in chars.iter().enumerate() {
+            if *c == '\n' {
+                boundaries.push(i);
+                boundaries.push(i + 1);
+            }
+        }
+        boundaries.extend(authors.iter().map(|run| run.start));
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let segments: Vec<(usize, usize, BTreeSet<&MarkdownStyle>)> = boundaries
+            .windows(2)
+            .map(|w| {
+                let (start, end) = (w[0], w[1]);
+                let active = if chars[start] == '\n' {
+                    BTreeSet::new()
+                } else {
+                    spans
+                        .iter()
+                        .filter(|s| s.range.start <= start && end <= s.range.end)
+                        .map(|s| &s.style)
+                        .collect()
+                };
+                (start, end, active)
+            })
+            .collect();
+
+        let mut out = String::new();
+        // Open styles with the delimiter each was opened with
+        let mut open: Vec<(&MarkdownStyle, String)> = Vec::new();
+        let mut authors = authors.iter().peekable();
+
+        for (index, (start, end, active)) in segments.iter().enumerate() {
+            // Close everything from the first style that ends here; code
+            // also closes when another style opens, since it must be innermost
+            let is_open = |style: &MarkdownStyle, open: &[(&MarkdownStyle, String)]| {
+                open.iter().any(|(s, _)| *s == style)
+            };
+            let opening = active.iter().any(|s| !is_open(s, &open));
+            let keep = open
+                .iter()
+                .position(|(s, _)| !active.contains(s) || (**s == MarkdownStyle::Code && opening))
+                .unwrap_or(open.len());
+            for (style, delimiter) in open.drain(keep..).rev() {
+                write_closer(&mut out, style, &delimiter);
+            }
+
+            if let Some(run) = authors.next_if(|run| run.start == *start) {
+                out.push_str(&format!(
+                    "<!-- author: {} clock: {} -->",
+                    run.client_id.replace("-->", "--&gt;"),
+                    run.clock
+                ));
+                if *start == 0 || chars[start - 1] == '\n' {
+                    out.push('\n');
+                }
+            }
+
+            let opened: Vec<&MarkdownStyle> = active
+                .iter()
+                .copied()
+                .filter(|s| !is_open(s, &open))
+                .collect();
+            for style in opened {
+                let delimiter = match style {
+                    MarkdownStyle::Link(_) => "[".to_string(),
+                    MarkdownStyle::Code => {
+                        // The code span stays open while the active styles are unchanged
+                        let code_end = segments[index..]
+                            .iter()
+                            .take_while(|(_, _, next)| next == active)
+                            .last()
+                            .map_or(*end, |(_, next_end, _)| *next_end);
+                        code_delimiter(&chars[*start..code_end])
+                    }
+                    // Switch to underscores after a `*` so delimiter runs
+                    // like `****` can't form when a span closes and another opens
+                    MarkdownStyle::Bold if out.ends_with('*') => "__".to_string(),
+                    MarkdownStyle::Bold => "**".to_string(),
+                    MarkdownStyle::Italic if out.ends_with('*') => "_".to_string(),
+                    MarkdownStyle::Italic => "*".to_string(),
+                    MarkdownStyle::Strikethrough => "~~".to_string(),
+                };
+                out.push_str(&delimiter);
+                open.push((style, delimiter));
+            }
+
+            let in_code = open.last().is_some_and(|(s, _)| **s == MarkdownStyle::Code);
+            for i in *start..*end {
+                if !in_code && needs_escape(&chars, i) {
+                    out.push('\\');
+                }
+                out.push(chars[i]);
+            }
+        }
+
+        for (style, delimiter) in open.into_iter().rev() {
+            write_closer(&mut out, style, &delimiter);
+        }
+
+        out
+    }
+
+    /// Parse Markdown into plain text plus inline formatting spans
+    ///
+    /// Inverse of [`FugueText::to_markdown`] for its own output; see the
+    /// module docs for what is lost on arbitrary Markdown.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TextError` if inserting the parsed text fails
+    pub fn from_markdown(client_id: String, markdown: &str) -> Result<MarkdownImport, TextError> {
+        let (plain, mut spans) = parse_markdown(markdown);
+        let units = units_by_char(&plain);
+        for span in &mut spans {
+            span.range = units[span.range.start]..units[span.range.end];
+        }
+
+        let mut text = FugueText::new(client_id);
+        if !plain.is_empty() {
+            text.insert(0, &plain)?;
+        }
+
+        Ok(MarkdownImport { text, spans })
+    }
+
+    /// Visible text char offsets where the author changes
+    fn author_runs(&self) -> Vec<AuthorRun<'_>> {
+        let mut runs: Vec<AuthorRun<'_>> = Vec::new();
+        let mut position = 0;
+
+        for id in self.get_document_order() {
+            let Some(block) = self.blocks.get(&id) else {
+                continue;
+            };
+
+            if runs.last().map(|run| run.client_id) != Some(block.id.client_id.as_str()) {
+                runs.push(AuthorRun {
+                    start: position,
+                    client_id: &block.id.client_id,
+                    clock: block.id.clock,
+                });
+            }
+            position += block.text.chars().count();
+        }
+
+        runs
+    }
+}
+
+/// Whether the character at `i` would be read as Markdown syntax
+fn needs_escape(chars: &[char], i: usize) -> bool {
+    let at_line_start = |i: usize| i == 0 || chars[i - 1] == '\n';
+
+    match chars[i] {
+        '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '~' | '&' => true,
+        // Headings, quotes, bullets and setext underlines
+        '#' | '>' | '-' | '+' | '=' => at_line_start(i),
+        // Ordered list markers ("1." / "1)")
+        '.' | ')' => {
+            let digits = chars[..i]
+                .iter()
+                .rev()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+            digits > 0 && at_line_start(i - digits)
+        }
+        _ => false,
+    }
+}
+
+/// Pick a backtick fence longer than any backtick run in the code, padding
+/// it with spaces where Markdown would otherwise eat or merge characters
+fn code_delimiter(code: &[char]) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in code {
+        run = if *c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+
+    let fence = "`".repeat(longest + 1);
+    let first = code.first().copied();
+    let last = code.last().copied();
+    let padded = first == Some('`')
+        || last == Some('`')
+        || (first == Some(' ') && last == Some(' ') && code.iter().any(|c| *c != ' '));
+
+    if padded {
+        format!("{} ", fence)
+    } else {
+        fence
+    }
+}
+
+/// Write the closing delimiter matching `opener`
+fn write_closer(out: &mut String, style: &MarkdownStyle, opener: &str) {
+    match style {
+        MarkdownStyle::Link(href) => {
+            out.push_str("](");
+            out.push_str(&href.replace(' ', "%20").replace(')', "%29"));
+            out.push(')');
+        }
+        _ => out.extend(opener.chars().rev()),
+    }
+}
+
+/// A piece of inline Markdown
+enum Token {
+    Text(String),
+    Code(String),
+    /// Emphasis or link delimiter; `partner` is set once matched
+    Delim {
+        style: MarkdownStyle,
+        literal: String,
+        partner: Option<usize>,
+    },
+}
+
+/// Split Markdown into plain text and formatting spans
+fn parse_markdown(markdown: &str) -> (String, Vec<MarkdownSpan>) {
+    let chars: Vec<char> = markdown.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    // Unmatched openers on the current line: (token index, style)
+    let mut stack: Vec<(usize, MarkdownStyle)> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let at_line_start = i == 0 || chars[i - 1] == '\n';
+
+        match c {
+            '\\' if chars.get(i + 1).is_some_and(|n| n.is_ascii_punctuation()) => {
+                push_text(&mut tokens, chars[i + 1]);
+                i += 2;
+            }
+            '<' if starts_with(&chars, i, "<!--") => {
+                match find(&chars, i + 4, "-->") {
+                    Some(close) => {
+                        i = close + 3;
+                        // Comments on a line of their own take the line break with them
+                        if at_line_start && chars.get(i) == Some(&'\n') {
+                            i += 1;
+                        }
+                    }
+                    None => {
+                        push_text(&mut tokens, c);
+                        i += 1;
+                    }
+                }
+            }
+            '`' => {
+                let fence = run_length(&chars, i, '`');
+                match find_code_close(&chars, i + fence, fence) {
+                    Some(close) => {
+                        let mut code: String = chars[i + fence..close].iter().collect();
+                        if code.len() > 2
+                            && code.starts_with(' ')
+                            && code.ends_with(' ')
+                            && code.chars().any(|c| c != ' ')
+                        {
+                            code = code[1..code.len() - 1].to_string();
+                        }
+                        tokens.push(Token::Code(code));
+                        i = close + fence;
+                    }
+                    None => {
+                        for _ in 0..fence {
+                            push_text(&mut tokens, '`');
+                        }
+                        i += fence;
+                    }
+                }
+            }
+            '*' | '_' => {
+                let run = run_length(&chars, i, c);
+                let styles = match run {
+                    1 => vec![MarkdownStyle::Italic],
+                    2 => vec![MarkdownStyle::Bold],
+                    // Close in the order the openers were pushed
+                    3 if top_styles_are(&stack, &MarkdownStyle::Bold, &MarkdownStyle::Italic) => {
+                        vec![MarkdownStyle::Italic, MarkdownStyle::Bold]
+                    }
+                    3 if top_styles_are(&stack, &MarkdownStyle::Italic, &MarkdownStyle::Bold) => {
+                        vec![MarkdownStyle::Bold, MarkdownStyle::Italic]
+                    }
+                    3 => vec![MarkdownStyle::Bold, MarkdownStyle::Italic],
+                    _ => Vec::new(),
+                };
+
+                if styles.is_empty() {
+                    for _ in 0..run {
+                        push_text(&mut tokens, c);
+                    }
+                }
+                for style in styles {
+                    let literal = if style == MarkdownStyle::Bold {
+                        format!("{}{}", c, c)
+                    } else {
+                        c.to_string()
+                    };
+                    push_delim(&mut tokens, &mut stack, style, literal);
+                }
+                i += run;
+            }
+            '~' if chars.get(i + 1) == Some(&'~') => {
+                push_delim(
+                    &mut tokens,
+                    &mut stack,
+                    MarkdownStyle::Strikethrough,
+                    "~~".to_string(),
+                );
+                i += 2;
+            }
+            '[' => {
+                // Link openers match any href; the closer carries the real one
+                push_delim(
+                    &mut tokens,
+                    &mut stack,
+                    MarkdownStyle::Link(String::new()),
+                    "[".to_string(),
+                );
+                i += 1;
+            }
+            ']' if chars.get(i + 1) == Some(&'(') => match link_target_end(&chars, i + 2) {
+                Some(close) => {
+                    let href: String = chars[i + 2..close].iter().collect();
+                    let href = href.replace("%20", " ").replace("%29", ")");
+                    let literal: String = chars[i..=close].iter().collect();
+                    push_delim(&mut tokens, &mut stack, MarkdownStyle::Link(href), literal);
+                    i = close + 1;
+                }
+                None => {
+                    push_text(&mut tokens, c);
+                    i += 1;
+                }
+            },
+            '\n' => {
+                // Inline formatting never crosses a line break
+                stack.clear();
+                push_text(&mut tokens, c);
+                i += 1;
+            }
+            _ => {
+                push_text(&mut tokens, c);
+                i += 1;
+            }
+        }
+    }
+
+    let mut plain = String::new();
+    let mut position = 0;
+    let mut opened_at = vec![0; tokens.len()];
+    let mut spans = Vec::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Text(text) => {
+                plain.push_str(text);
+                position += text.chars().count();
+            }
+            Token::Code(code) => {
+                let len = code.chars().count();
+                if len > 0 {
+                    spans.push(MarkdownSpan::new(
+                        position..position + len,
+                        MarkdownStyle::Code,
+                    ));
+                }
+                plain.push_str(code);
+                position += len;
+            }
+            Token::Delim {
+                partner: None,
+                literal,
+                ..
+            } => {
+                plain.push_str(literal);
+                position += literal.chars().count();
+            }
+            Token::Delim {
+                partner: Some(partner),
+                style,
+                ..
+            } => {
+                if *partner > index {
+                    opened_at[index] = position;
+                } else if opened_at[*partner] < position {
+                    spans.push(MarkdownSpan::new(
+                        opened_at[*partner]..position,
+                        style.clone(),
+                    ));
+                }
+            }
+        }
+    }
+
+    let spans = join_across_lines(&plain, spans);
+    (plain, spans)
+}
+
+fn push_text(tokens: &mut Vec<Token>, c: char) {
+    match tokens.last_mut() {
+        Some(Token::Text(text)) => text.push(c),
+        _ => tokens.push(Token::Text(c.to_string())),
+    }
+}
+
+/// Push a delimiter, matching it with the nearest open delimiter of the same
+/// style; delimiters opened in between stay unmatched (literal)
+fn push_delim(
+    tokens: &mut Vec<Token>,
+    stack: &mut Vec<(usize, MarkdownStyle)>,
+    style: MarkdownStyle,
+    literal: String,
+) {
+    let index = tokens.len();
+    let is_link_close = matches!(&style, MarkdownStyle::Link(href) if !href.is_empty());
+
+    let opener = stack.iter().rposition(|(_, open)| match (open, &style) {
+        (MarkdownStyle::Link(_), MarkdownStyle::Link(_)) => is_link_close,
+        (open, style) => open == style,
+    });
+
+    match opener {
+        Some(position) => {
+            let (open_index, _) = stack[position];
+            stack.truncate(position);
+            if let Token::Delim { partner, .. } = &mut tokens[open_index] {
+                *partner = Some(index);
+            }
+            tokens.push(Token::Delim {
+                style,
+                literal,
+                partner: Some(open_index),
+            });
+        }
+        None if is_link_close => tokens.push(Token::Delim {
+            style,
+            literal,
+            partner: None,
+        }),
+        None => {
+            stack.push((index, style.clone()));
+            tokens.push(Token::Delim {
+                style,
+                literal,
+                partner: None,
+            });
+        }
+    }
+}
+
+/// Whether the two innermost open delimiters are `outer` then `inner`
+fn top_styles_are(
+    stack: &[(usize, MarkdownStyle)],
+    outer: &MarkdownStyle,
+    inner: &MarkdownStyle,
+) -> bool {
+    matches!(stack, [.., (_, a), (_, b)] if a == outer && b == inner)
+}
+
+/// Join spans of the same style that touch or are separated only by a
+/// line break (the exporter splits spans at both)
+fn join_across_lines(plain: &str, mut spans: Vec<MarkdownSpan>) -> Vec<MarkdownSpan> {
+    let chars: Vec<char> = plain.chars().collect();
+    spans.sort_by(|a, b| {
+        (&a.style, a.range.start, a.range.end).cmp(&(&b.style, b.range.start, b.range.end))
+    });
+
+    let mut joined: Vec<MarkdownSpan> = Vec::with_capacity(spans.len());
+    for span in spans {
+        if let Some(last) = joined.last_mut() {
+            let touching = span.range.start <= last.range.end
+                || (last.range.end + 1 == span.range.start
+                    && chars.get(last.range.end) == Some(&'\n'));
+            if last.style == span.style && touching {
+                last.range.end = last.range.end.max(span.range.end);
+                continue;
+            }
+        }
+        joined.push(span);
+    }
+
+    joined.sort_by(|a, b| {
+        (a.range.start, a.range.end, &a.style).cmp(&(b.range.start, b.range.end, &b.style))
+    });
+    joined
+}
+
+fn starts_with(chars: &[char], at: usize, pattern: &str) -> bool {
+    pattern
+        .chars()
+        .enumerate()
+        .all(|(i, p)| chars.get(at + i) == Some(&p))
+}
+
+fn find(chars: &[char], from: usize, pattern: &str) -> Option<usize> {
+    (from..chars.len()).find(|&i| starts_with(chars, i, pattern))
+}
+
+fn run_length(chars: &[char], at: usize, c: char) -> usize {
+    chars[at..].iter().take_while(|x| **x == c).count()
+}
+
+/// Find a backtick run of exactly `fence` length on the same line
+fn find_code_close(chars: &[char], from: usize, fence: usize) -> Option<usize> {
+    let mut i = from;
+    while i < chars.len() && chars[i] != '\n' {
+        if chars[i] == '`' {
+            let run = run_length(chars, i, '`');
+            if run == fence {
+                return Some(i);
+            }
+            i += run;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Find the `)` ending a link target on the same line
+fn link_target_end(chars: &[char], from: usize) -> Option<usize> {
+    (from..chars.len())
+        .take_while(|&i| chars[i] != '\n' && chars[i] != ' ')
+        .find(|&i| chars[i] == ')')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn golden(name: &str) -> String {
+        let path = format!(
+            "{}/tests/fixtures/markdown/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    fn text(s: &str) -> FugueText {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, s).unwrap();
+        text
+    }
+
+    fn spans(spans: &[(Range<usize>, MarkdownStyle)]) -> MarkdownOptions {
+        MarkdownOptions {
+            spans: spans
+                .iter()
+                .map(|(range, style)| MarkdownSpan::new(range.clone(), style.clone()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Release notes written by two clients, with nested formatting, a
+    /// link, code containing backticks and a bold span over a line break
+    fn representative_document() -> (FugueText, MarkdownOptions) {
+        let mut alice = FugueText::new("alice".to_string());
+        alice
+            .insert(0, "# Release notes\nSync is *fast* now.\n")
+            .unwrap();
+
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+        let end = bob.len();
+        bob.insert(end, "Run `cargo test` before\nyou ship: 1. see docs")
+            .unwrap();
+        alice.merge(&bob).unwrap();
+
+        let content = alice.to_string();
+        let find = |s: &str| {
+            let byte = content.find(s).unwrap();
+            let start = content[..byte].chars().count();
+            start..start + s.chars().count()
+        };
+
+        let options = spans(&[
+            (find("Release notes"), MarkdownStyle::Bold),
+            (find("notes"), MarkdownStyle::Italic),
+            (find("`cargo test`"), MarkdownStyle::Code),
+            (find("before\nyou ship"), MarkdownStyle::Bold),
+            (
+                find("docs"),
+                MarkdownStyle::Link("https://example.com/a b".to_string()),
+            ),
+        ]);
+        (alice, options)
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(
+            text("Hello World").to_markdown(&MarkdownOptions::default()),
+            "Hello World"
+        );
+        assert_eq!(
+            FugueText::new("client1".to_string()).to_markdown(&MarkdownOptions::default()),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_spans_count_clusters() {
+        let options = MarkdownOptions {
+            spans: vec![MarkdownSpan::new(1..3, MarkdownStyle::Bold)],
+            ..Default::default()
+        };
+        let markdown = text("👨\u{200D}👩\u{200D}👧e\u{301}漢!").to_markdown(&options);
+        assert_eq!(markdown, "👨\u{200D}👩\u{200D}👧**e\u{301}漢**!");
+
+        let imported = FugueText::from_markdown("client1".to_string(), &markdown).unwrap();
+        assert_eq!(imported.spans, options.spans);
+    }
+
+    #[test]
+    fn test_escapes_control_characters() {
+        let markdown = text("# not *a* heading\n- [x] 1. a_b <tag> & 2) ~x~ \\")
+            .to_markdown(&MarkdownOptions::default());
+        assert_eq!(
+            markdown,
+            r"\# not \*a\* heading
+\- \[x\] 1. a\_b \<tag> \& 2) \~x\~ \\"
+        );
+
+        let imported = FugueText::from_markdown("client1".to_string(), &markdown).unwrap();
+        assert_eq!(
+            imported.text.to_string(),
+            "# not *a* heading\n- [x] 1. a_b <tag> & 2) ~x~ \\"
+        );
+        assert!(imported.spans.is_empty());
+    }
+
+    #[test]
+    fn test_escapes_ordered_list_marker_at_line_start() {
+        let markdown = text("12. item\n3) item").to_markdown(&MarkdownOptions::default());
+        assert_eq!(markdown, "12\\. item\n3\\) item");
+    }
+
+    #[test]
+    fn test_nested_marks() {
+        let text = text("Hello World");
+        let options = spans(&[
+            (0..11, MarkdownStyle::Bold),
+            (6..11, MarkdownStyle::Italic),
+            (0..5, MarkdownStyle::Strikethrough),
+        ]);
+
+        let markdown = text.to_markdown(&options);
+        assert_eq!(markdown, "**~~Hello~~ *World***");
+
+        let imported = FugueText::from_markdown("client1".to_string(), &markdown).unwrap();
+        assert_eq!(imported.text.to_string(), "Hello World");
+        let mut expected = options.spans.clone();
+        expected.sort_by_key(|s| (s.range.start, s.range.end));
+        assert_eq!(imported.spans, expected);
+    }
+
+    #[test]
+    fn test_overlapping_marks() {
+        let options = spans(&[(0..7, MarkdownStyle::Bold), (4..11, MarkdownStyle::Italic)]);
+        let markdown = text("Hello World").to_markdown(&options);
+        assert_eq!(markdown, "**Hell*o W***_orld_");
+
+        let imported = FugueText::from_markdown("client1".to_string(), &markdown).unwrap();
+        assert_eq!(imported.text.to_string(), "Hello World");
+        assert_eq!(
+            imported.spans,
+            vec![
+                MarkdownSpan::new(0..7, MarkdownStyle::Bold),
+                MarkdownSpan::new(4..11, MarkdownStyle::Italic),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_marks_spanning_line_breaks() {
+        let options = spans(&[(2..9, MarkdownStyle::Bold)]);
+        let markdown = text("one\ntwo\nthree").to_markdown(&options);
+        assert_eq!(markdown, "on**e**\n**two**\n**t**hree");
+
+        let imported = FugueText::from_markdown("client1".to_string(), &markdown).unwrap();
+        assert_eq!(imported.spans, options.spans);
+    }
+
+    #[test]
+    fn test_code_with_backticks() {
+        let options = spans(&[(4..7, MarkdownStyle::Code), (4..7, MarkdownStyle::Bold)]);
+        let markdown = text("run `x` *now*").to_markdown(&options);
+        assert_eq!(markdown, "run **`` `x` ``** \\*now\\*");
+
+        let imported = FugueText::from_markdown("client1".to_string(), &markdown).unwrap();
+        assert_eq!(imported.text.to_string(), "run `x` *now*");
+        assert_eq!(
+            imported.spans,
+            vec![
+                MarkdownSpan::new(4..7, MarkdownStyle::Bold),
+                MarkdownSpan::new(4..7, MarkdownStyle::Code),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_round_trip() {
+        let options = spans(&[(0..4, MarkdownStyle::Link("https://x.dev/a (b)".to_string()))]);
+        let markdown = text("docs").to_markdown(&options);
+        assert_eq!(markdown, "[docs](https://x.dev/a%20(b%29)");
+
+        let imported = FugueText::from_markdown("client1".to_string(), &markdown).unwrap();
+        assert_eq!(imported.spans, options.spans);
+    }
+
+    #[test]
+    fn test_unmatched_delimiters_are_literal() {
+        let imported =
+            FugueText::from_markdown("client1".to_string(), "a **b\nc* [d] e`f").unwrap();
+        assert_eq!(imported.text.to_string(), "a **b\nc* [d] e`f");
+        assert!(imported.spans.is_empty());
+    }
+
+    #[test]
+    fn test_attribution_comments() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello\n").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+        bob.insert(6, "World").unwrap();
+
+        let markdown = bob.to_markdown(&MarkdownOptions {
+            attribution: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            markdown,
+            "<!-- author: alice clock: 6 -->\nHello\n<!-- author: bob clock: 11 -->\nWorld"
+        );
+
+        let imported = FugueText::from_markdown("carol".to_string(), &markdown).unwrap();
+        assert_eq!(imported.text.to_string(), "Hello\nWorld");
+    }
+
+    #[test]
+    fn test_golden_export() {
+        let (text, options) = representative_document();
+        assert_eq!(text.to_markdown(&options), golden("release_notes.md"));
+    }
+
+    #[test]
+    fn test_golden_export_with_attribution() {
+        let (text, mut options) = representative_document();
+        options.attribution = true;
+        assert_eq!(
+            text.to_markdown(&options),
+            golden("release_notes_attributed.md")
+        );
+    }
+
+    #[test]
+    fn test_golden_import() {
+        let (text, options) = representative_document();
+        let mut expected = options.spans.clone();
+        expected.sort_by_key(|s| (s.range.start, s.range.end));
+
+        for name in ["release_notes.md", "release_notes_attributed.md"] {
+            let imported = FugueText::from_markdown("client1".to_string(), &golden(name)).unwrap();
+            assert_eq!(imported.text.to_string(), text.to_string(), "{}", name);
+            assert_eq!(imported.spans, expected, "{}", name);
+        }
+    }
+}
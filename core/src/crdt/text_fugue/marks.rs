@@ -0,0 +1,523 @@
+//! Rich-text formatting marks on ranges of a `FugueText`
+//!
+//! A [`Mark`] sets a formatting key (`"bold"`, `"link"`) to a JSON value
+//! between two [`Anchor`]s, so it follows its characters through
+//! concurrent edits the way a cursor does. Removing a key adds a mark
+//! without a value over the range. Marks merge as a grow-only set and
+//! travel in deltas; where several cover a character with the same key,
+//! the one with the highest Lamport clock wins (then the higher client
+//! ID), so replicas holding the same marks show the same formatting.
+//!
+//! [`MarkExpand`] decides whether text typed at the edges of a mark takes
+//! it: bold usually grows with text typed after it, a link doesn't.
+//!
+//! Marks take their clocks from the text's Lamport clock and count in its
+//! state vector, and `gc` keeps the tombstones they are anchored to.
+
+use super::anchor::{Anchor, AnchorBias};
+use super::text::{FugueText, TextError};
+use crate::memory::HeapSize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Whether text inserted at the edges of a mark is covered by it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkExpand {
+    /// Neither edge grows (links)
+    None,
+    /// Text inserted at the start is covered
+    Before,
+    /// Text inserted at the end is covered (bold, italic)
+    #[default]
+    After,
+    /// Both edges grow
+    Both,
+}
+
+impl MarkExpand {
+    fn start_bias(self) -> AnchorBias {
+        match self {
+            MarkExpand::Before | MarkExpand::Both => AnchorBias::Left,
+            MarkExpand::None | MarkExpand::After => AnchorBias::Right,
+        }
+    }
+
+    fn end_bias(self) -> AnchorBias {
+        match self {
+            MarkExpand::After | MarkExpand::Both => AnchorBias::Right,
+            MarkExpand::None | MarkExpand::Before => AnchorBias::Left,
+        }
+    }
+}
+
+/// A formatting key set (or removed) over a range of text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mark {
+    /// Client that made the mark
+    pub client_id: String,
+
+    /// Lamport clock of the mark; with `client_id`, its ID and its rank
+    pub clock: u64,
+
+    /// Where the range starts
+    pub start: Anchor,
+
+    /// Where the range ends (exclusive)
+    pub end: Anchor,
+
+    /// Formatting key, e.g. `"bold"`
+    pub key: String,
+
+    /// Value of the key over the range; None removes the key
+    #[serde(default, with = "crate::codec::json_value::option")]
+    pub value: Option<JsonValue>,
+}
+
+impl Mark {
+    /// Conflict order between marks on the same key: the higher one wins
+    fn rank(&self) -> (u64, &str) {
+        (self.clock, &self.client_id)
+    }
+}
+
+/// A key's value over a range of text, as reported by
+/// [`FugueText::marked_ranges`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MarkedRange {
+    /// Positions covered
+    pub range: Range<usize>,
+
+    /// Formatting key
+    pub key: String,
+
+    /// Value of the key over the whole range
+    pub value: JsonValue,
+}
+
+/// The marks on a text (see the module docs)
+///
+/// A state-based CRDT: [`Marks::merge`] is commutative, associative and
+/// idempotent.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Mark>", into = "Vec<Mark>")]
+pub struct Marks {
+    entries: BTreeMap<(String, u64), Mark>,
+}
+
+impl From<Vec<Mark>> for Marks {
+    fn from(entries: Vec<Mark>) -> Self {
+        let mut marks = Marks::default();
+        marks.extend(entries);
+        marks
+    }
+}
+
+impl From<Marks> for Vec<Mark> {
+    fn from(marks: Marks) -> Self {
+        marks.entries.into_values().collect()
+    }
+}
+
+impl Marks {
+    /// Add a mark; returns whether anything changed
+    ///
+    /// Two different marks under one ID (a replica restored from an old
+    /// snapshot reused a clock) keep the one whose JSON sorts last, so
+    /// replicas still agree.
+    pub fn insert(&mut self, mark: Mark) -> bool {
+        let key = (mark.client_id.clone(), mark.clock);
+        match self.entries.get(&key) {
+            Some(existing) if existing == &mark => false,
+            Some(existing) if json(existing) >= json(&mark) => false,
+            _ => {
+                self.entries.insert(key, mark);
+                true
+            }
+        }
+    }
+
+    /// Every mark, by client and clock
+    pub fn iter(&self) -> impl Iterator<Item = &Mark> + '_ {
+        self.entries.values()
+    }
+
+    /// Number of marks
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if there are no marks
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Merge a remote replica's marks; returns how many were added or
+    /// replaced
+    pub fn merge(&mut self, remote: &Marks) -> usize {
+        self.extend(remote.entries.values().cloned())
+    }
+
+    /// Add marks received in a delta; returns how many were added or
+    /// replaced
+    pub fn extend(&mut self, marks: impl IntoIterator<Item = Mark>) -> usize {
+        marks
+            .into_iter()
+            .filter(|mark| self.insert(mark.clone()))
+            .count()
+    }
+}
+
+fn json(mark: &Mark) -> String {
+    serde_json::to_string(mark).unwrap_or_default()
+}
+
+impl HeapSize for Mark {
+    fn heap_size(&self) -> usize {
+        let anchor = |anchor: &Anchor| anchor.target.as_ref().map_or(0, |id| id.heap_size());
+        self.client_id.heap_size()
+            + anchor(&self.start)
+            + anchor(&self.end)
+            + self.key.heap_size()
+            + self.value.heap_size()
+    }
+}
+
+impl HeapSize for Marks {
+    fn heap_size(&self) -> usize {
+        self.entries.heap_size()
+    }
+}
+
+impl FugueText {
+    /// Set `key` to `value` over `range`
+    ///
+    /// `expand` decides whether text inserted at either edge later is
+    /// covered too. An empty range adds nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::RangeOutOfBounds` if the range is reversed or
+    /// ends past the text
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use serde_json::json;
+    /// use synckit_core::crdt::text_fugue::{FugueText, MarkExpand};
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello World").unwrap();
+    /// text.add_mark(0..5, "bold", json!(true), MarkExpand::After).unwrap();
+    ///
+    /// // Typed at the end of the bold text: bold too
+    /// text.insert(5, "!").unwrap();
+    /// let ranges = text.marked_ranges();
+    /// assert_eq!(ranges[0].range, 0..6);
+    /// assert_eq!(text.marks_at(7).unwrap().get("bold"), None);
+    /// ```
+    pub fn add_mark(
+        &mut self,
+        range: Range<usize>,
+        key: &str,
+        value: JsonValue,
+        expand: MarkExpand,
+    ) -> Result<(), TextError> {
+        self.push_mark(range, key, Some(value), expand)
+    }
+
+    /// Remove `key` from `range`
+    ///
+    /// The removal grows with text typed at its end, like
+    /// [`MarkExpand::After`], so typing on after unformatted text stays
+    /// unformatted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::RangeOutOfBounds` if the range is reversed or
+    /// ends past the text
+    pub fn remove_mark(&mut self, range: Range<usize>, key: &str) -> Result<(), TextError> {
+        self.push_mark(range, key, None, MarkExpand::After)
+    }
+
+    fn push_mark(
+        &mut self,
+        range: Range<usize>,
+        key: &str,
+        value: Option<JsonValue>,
+        expand: MarkExpand,
+    ) -> Result<(), TextError> {
+        let length = self.len();
+        if range.start > range.end || range.end > length {
+            return Err(TextError::RangeOutOfBounds {
+                start: range.start,
+                end: range.end,
+                length,
+            });
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        let start = self.create_anchor(range.start, expand.start_bias())?;
+        let end = self.create_anchor(range.end, expand.end_bias())?;
+        self.touch();
+        let clock = self.clock.tick();
+        self.marks.insert(Mark {
+            client_id: self.client_id.to_string(),
+            clock,
+            start,
+            end,
+            key: key.to_string(),
+            value,
+        });
+        Ok(())
+    }
+
+    /// Every mark on the text, including removals and marks whose text
+    /// is gone
+    pub fn marks(&self) -> &Marks {
+        &self.marks
+    }
+
+    /// The formatting of the character at `position`, by key
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::PositionOutOfBounds` if there is no character
+    /// at `position`
+    pub fn marks_at(&mut self, position: usize) -> Result<BTreeMap<String, JsonValue>, TextError> {
+        let length = self.len();
+        if position >= length {
+            return Err(TextError::PositionOutOfBounds { position, length });
+        }
+
+        let mut winners: BTreeMap<&str, &Mark> = BTreeMap::new();
+        for (range, mark) in self.resolved_marks() {
+            if !range.contains(&position) {
+                continue;
+            }
+            let winner = winners.entry(&mark.key).or_insert(mark);
+            if mark.rank() > winner.rank() {
+                *winner = mark;
+            }
+        }
+        Ok(winners
+            .into_iter()
+            .filter_map(|(key, mark)| Some((key.to_string(), mark.value.clone()?)))
+            .collect())
+    }
+
+    /// The formatted ranges of the text: for each key, the longest runs
+    /// with one value, by start and key
+    ///
+    /// Replicas holding the same characters and marks report the same
+    /// ranges.
+    pub fn marked_ranges(&mut self) -> Vec<MarkedRange> {
+        let mut by_key: BTreeMap<&str, Vec<(usize, bool, &Mark)>> = BTreeMap::new();
+        for (range, mark) in self.resolved_marks() {
+            let boundaries = by_key.entry(&mark.key).or_default();
+            boundaries.push((range.start, true, mark));
+            boundaries.push((range.end, false, mark));
+        }
+
+        let mut ranges: Vec<MarkedRange> = Vec::new();
+        for (key, mut boundaries) in by_key {
+            boundaries.sort_by_key(|(position, _, _)| *position);
+            // Marks covering the stretch being swept, by rank
+            let mut active = BTreeMap::new();
+            let mut i = 0;
+            while let Some(&(position, _, _)) = boundaries.get(i) {
+                while let Some(&(_, opens, mark)) =
+                    boundaries.get(i).filter(|(at, _, _)| *at == position)
+                {
+                    match opens {
+                        true => active.insert(mark.rank(), mark),
+                        false => active.remove(&mark.rank()),
+                    };
+                    i += 1;
+                }
+                let Some(&(next, _, _)) = boundaries.get(i) else {
+                    break;
+                };
+                let Some(value) = active
+                    .values()
+                    .next_back()
+                    .and_then(|mark| mark.value.as_ref())
+                else {
+                    continue;
+                };
+                match ranges.last_mut() {
+                    Some(last)
+                        if last.key == key
+                            && last.range.end == position
+                            && last.value == *value =>
+                    {
+                        last.range.end = next
+                    }
+                    _ => ranges.push(MarkedRange {
+                        range: position..next,
+                        key: key.to_string(),
+                        value: value.clone(),
+                    }),
+                }
+            }
+        }
+        ranges.sort_by(|a, b| (a.range.start, &a.key).cmp(&(b.range.start, &b.key)));
+        ranges
+    }
+
+    /// Marks with the positions they cover now, leaving out those that
+    /// cover nothing or whose anchors this replica can't place
+    fn resolved_marks(&mut self) -> Vec<(Range<usize>, &Mark)> {
+        let anchors: Vec<(Anchor, Anchor)> = self
+            .marks
+            .iter()
+            .map(|mark| (mark.start.clone(), mark.end.clone()))
+            .collect();
+        let ranges: Vec<Option<Range<usize>>> = anchors
+            .iter()
+            .map(|(start, end)| Some(self.resolve_anchor(start)?..self.resolve_anchor(end)?))
+            .collect();
+        ranges
+            .into_iter()
+            .zip(self.marks.iter())
+            .filter_map(|(range, mark)| Some((range.filter(|range| !range.is_empty())?, mark)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bold(text: &mut FugueText, range: Range<usize>) {
+        text.add_mark(range, "bold", json!(true), MarkExpand::After)
+            .unwrap();
+    }
+
+    fn spans(text: &mut FugueText) -> Vec<(Range<usize>, String, JsonValue)> {
+        text.marked_ranges()
+            .into_iter()
+            .map(|marked| (marked.range, marked.key, marked.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_concurrent_overlapping_bold_converges() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello brave new world").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        bold(&mut alice, 0..11);
+        bold(&mut bob, 6..15);
+        bob.insert(0, ">> ").unwrap();
+        alice.merge(&bob).unwrap();
+        bob.merge(&alice).unwrap();
+
+        assert_eq!(alice.marked_ranges(), bob.marked_ranges());
+        assert_eq!(spans(&mut alice), vec![(3..18, "bold".into(), json!(true))]);
+        assert_eq!(alice.marks_at(3).unwrap().get("bold"), Some(&json!(true)));
+        assert!(alice.marks_at(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_last_writer_wins_per_key() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "abcdef").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        // Bob has seen Alice's link, so his wins where they overlap
+        alice
+            .add_mark(0..4, "link", json!("a.example"), MarkExpand::None)
+            .unwrap();
+        bob.merge(&alice).unwrap();
+        bob.add_mark(2..6, "link", json!("b.example"), MarkExpand::None)
+            .unwrap();
+        // Concurrently, Alice un-bolds what nobody bolded
+        alice.remove_mark(0..6, "bold").unwrap();
+
+        alice.merge(&bob).unwrap();
+        bob.merge(&alice).unwrap();
+        let expected = vec![
+            (0..2, "link".to_string(), json!("a.example")),
+            (2..6, "link".to_string(), json!("b.example")),
+        ];
+        assert_eq!(spans(&mut alice), expected);
+        assert_eq!(spans(&mut bob), expected);
+
+        // A later removal clears the middle
+        alice.remove_mark(1..3, "link").unwrap();
+        bob.merge(&alice).unwrap();
+        assert_eq!(
+            spans(&mut bob),
+            vec![
+                (0..1, "link".to_string(), json!("a.example")),
+                (3..6, "link".to_string(), json!("b.example")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_decides_edges() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "[ab]").unwrap();
+        bold(&mut text, 1..3);
+        text.add_mark(1..3, "link", json!("x"), MarkExpand::None)
+            .unwrap();
+        text.add_mark(1..3, "code", json!(true), MarkExpand::Both)
+            .unwrap();
+
+        text.insert(3, "c").unwrap();
+        text.insert(1, "_").unwrap();
+        assert_eq!(text.to_string(), "[_abc]");
+        assert_eq!(
+            spans(&mut text),
+            vec![
+                (1..5, "code".to_string(), json!(true)),
+                (2..5, "bold".to_string(), json!(true)),
+                (2..4, "link".to_string(), json!("x")),
+            ]
+        );
+
+        // Typing on after removed formatting stays unformatted
+        text.remove_mark(2..5, "bold").unwrap();
+        text.insert(5, "d").unwrap();
+        let formatting = text.marks_at(5).unwrap();
+        assert_eq!(formatting.get("bold"), None);
+        assert_eq!(formatting.get("code"), Some(&json!(true)));
+    }
+
+    #[test]
+    fn test_marks_follow_deletes_and_sync() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello World").unwrap();
+        bold(&mut alice, 4..8);
+        alice.delete(3, 3).unwrap();
+        assert_eq!(alice.to_string(), "HelWorld");
+        assert_eq!(spans(&mut alice), vec![(3..5, "bold".into(), json!(true))]);
+
+        // Deltas, serde and gc keep them
+        let mut bob = FugueText::new("bob".to_string());
+        bob.apply_delta(&alice.diff_since(&bob.state_vector()))
+            .unwrap();
+        assert_eq!(bob.marks(), alice.marks());
+        assert!(alice.diff_since(&bob.state_vector()).marks.is_empty());
+
+        let mut restored: FugueText =
+            serde_json::from_str(&serde_json::to_string(&alice).unwrap()).unwrap();
+        assert_eq!(restored.marks(), alice.marks());
+        restored.gc(&restored.state_vector());
+        assert_eq!(spans(&mut restored), spans(&mut alice));
+
+        assert!(alice
+            .add_mark(3..9, "bold", json!(1), MarkExpand::After)
+            .is_err());
+        assert!(alice.marks_at(8).is_err());
+    }
+}
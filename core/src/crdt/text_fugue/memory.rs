@@ -0,0 +1,228 @@
+//! Heap use of a text, by subsystem (see [`crate::memory`])
+//!
+//! Deleted blocks are reported apart from live ones as "tombstones", since
+//! they are what keeps growing under steady editing.
+
+use super::block::FugueBlock;
+use super::node::{ClientId, NodeId};
+use super::text::FugueText;
+use crate::memory::{HeapSize, MemoryUsage};
+use serde::Serialize;
+use std::mem::size_of;
+
+/// How compactly a text stores its characters, from
+/// [`FugueText::memory_stats`]
+///
+/// Byte figures are estimates, like those of [`MemoryUsage`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TextMemoryStats {
+    /// Blocks, tombstones included
+    pub block_count: usize,
+
+    /// Deleted blocks kept for merging
+    pub tombstone_count: usize,
+
+    /// Visible characters (see `FugueText::len`)
+    pub visible_chars: usize,
+
+    /// Bytes held by the rope
+    pub rope_bytes: usize,
+
+    /// Bytes of the blocks: their fixed size, text too long to keep
+    /// inline, and the client IDs they share
+    pub metadata_bytes: usize,
+
+    /// Rope and metadata bytes per visible character (0 for an empty
+    /// text); what run-length encoding keeps low
+    pub bytes_per_char: f64,
+}
+
+/// Client IDs are shared (see `clients.rs`); a text counts each once,
+/// under "client_ids"
+impl HeapSize for ClientId {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for NodeId {
+    fn heap_size(&self) -> usize {
+        self.client_id.heap_size()
+    }
+}
+
+impl HeapSize for FugueBlock {
+    fn heap_size(&self) -> usize {
+        let text = match self.text.is_inline() {
+            true => 0,
+            false => self.text.as_str().len(),
+        };
+        self.id.heap_size()
+            + text
+            + self.left_origin.heap_size()
+            + self.right_origin.heap_size()
+            + self.deleted_at.heap_size()
+    }
+}
+
+impl FugueText {
+    /// Estimated heap use of each part of the text
+    ///
+    /// Blocks a clone still shares with this text are counted by both.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello").unwrap();
+    /// text.delete(0, 5).unwrap();
+    ///
+    /// let usage = text.memory_usage();
+    /// assert_eq!(usage.get("blocks"), 0);
+    /// assert!(usage.get("tombstones") > 0);
+    /// ```
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::new();
+        usage.record("rope", self.rope.capacity());
+        for (id, block) in self.blocks.iter() {
+            let bytes = block_bytes(id, block);
+            match block.deleted {
+                true => usage.record("tombstones", bytes),
+                false => usage.record("blocks", bytes),
+            }
+        }
+        usage.record("client_ids", self.clients.heap_size());
+        usage.record("units", self.units.heap_size());
+        usage.record(
+            "position_cache",
+            self.cached_blocks.heap_size() + self.cached_positions.heap_size(),
+        );
+        usage.record("persisted", self.persisted.heap_size());
+        usage.record("annotations", self.annotations.heap_size());
+        usage.record("marks", self.marks.heap_size());
+        usage.record("history", self.history.heap_size());
+        usage.record("notifier", self.notifier.queued_bytes());
+        usage.record("rechunk", self.rechunk.heap_size());
+        usage.count("block_count", self.blocks.len() as u64);
+        usage.count("clients", self.clients.len() as u64);
+        usage.count("rechunk_passes", self.rechunk.passes());
+        usage.count("blocks_joined", self.rechunk.joined());
+        usage
+    }
+
+    /// Block and byte counts against the visible text, for checking that
+    /// run-length encoding keeps blocks few
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// for c in "Hello World".chars() {
+    ///     text.insert(text.len(), &c.to_string()).unwrap();
+    /// }
+    ///
+    /// // Typing on at the end extends the block the last keystroke made
+    /// let stats = text.memory_stats();
+    /// assert_eq!(stats.block_count, 1);
+    /// assert_eq!(stats.visible_chars, 11);
+    /// ```
+    pub fn memory_stats(&self) -> TextMemoryStats {
+        let mut tombstone_count = 0;
+        let mut metadata_bytes = self.clients.heap_size();
+        for (id, block) in self.blocks.iter() {
+            tombstone_count += usize::from(block.deleted);
+            metadata_bytes += block_bytes(id, block);
+        }
+        let rope_bytes = self.rope.capacity();
+        let visible_chars = self.len();
+        let bytes_per_char = match visible_chars {
+            0 => 0.0,
+            chars => (rope_bytes + metadata_bytes) as f64 / chars as f64,
+        };
+
+        TextMemoryStats {
+            block_count: self.blocks.len(),
+            tombstone_count,
+            visible_chars,
+            rope_bytes,
+            metadata_bytes,
+            bytes_per_char,
+        }
+    }
+}
+
+/// Estimated bytes of one entry of the block tree
+fn block_bytes(id: &NodeId, block: &FugueBlock) -> usize {
+    size_of::<NodeId>() + size_of::<FugueBlock>() + id.heap_size() + block.heap_size()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_follows_edits() {
+        let mut text = FugueText::new("client1".to_string());
+        let empty = text.memory_usage();
+        assert_eq!(empty.get("blocks") + empty.get("tombstones"), 0);
+
+        text.insert(0, &"x".repeat(1000)).unwrap();
+        let written = text.memory_usage();
+        assert!(written.get("blocks") >= 1000);
+        assert!(written.get("rope") >= 1000);
+        assert_eq!(written.get("tombstones"), 0);
+
+        text.delete(0, 1000).unwrap();
+        let deleted = text.memory_usage();
+        assert_eq!(deleted.get("blocks"), 0);
+        assert!(deleted.get("tombstones") >= 1000);
+    }
+
+    #[test]
+    fn test_stats_show_run_length_encoding() {
+        const CHARS: usize = 100_000;
+
+        // Appended runs continue the block before them
+        let mut runs = FugueText::new("client1".to_string());
+        for _ in 0..CHARS / 1000 {
+            runs.insert(runs.len(), &"x".repeat(1000)).unwrap();
+        }
+        // One block per character, as when every keystroke went elsewhere
+        let single = FugueText::from_paragraphs("client1".to_string(), vec!["x"; CHARS]);
+
+        let runs = runs.memory_stats();
+        let single = single.memory_stats();
+        assert_eq!(runs.visible_chars, CHARS);
+        assert_eq!(single.visible_chars, CHARS);
+        assert_eq!(runs.block_count, 1);
+        assert_eq!(single.block_count, CHARS);
+        assert!(runs.rope_bytes >= CHARS);
+        // The block keeps its own copy of the text
+        assert!(runs.metadata_bytes < CHARS + 1000, "{:?}", runs);
+        assert!(runs.bytes_per_char < 3.0, "{:?}", runs);
+        assert!(single.bytes_per_char > 50.0 * runs.bytes_per_char);
+        assert_eq!(
+            single.bytes_per_char,
+            (single.rope_bytes + single.metadata_bytes) as f64 / CHARS as f64
+        );
+
+        // Typing on at the end, one character at a time, stays one block
+        let mut typed = FugueText::new("client1".to_string());
+        for _ in 0..1000 {
+            typed.insert(typed.len(), "x").unwrap();
+        }
+        typed.delete(0, 500).unwrap();
+        let typed = typed.memory_stats();
+        assert_eq!(typed.block_count, 2);
+        assert_eq!(typed.tombstone_count, 1);
+        assert!(
+            typed.bytes_per_char < single.bytes_per_char / 10.0,
+            "{:?}",
+            typed
+        );
+    }
+}
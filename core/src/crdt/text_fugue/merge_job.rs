@@ -0,0 +1,428 @@
+//! Sliced `FugueText` merge (see [`crate::merge_job`])
+//!
+//! The job runs the phases of `merge` on a private copy of the text, one
+//! remote block per step: split-to-match passes, then an index of the
+//! local clock ranges, then conflict check, classification and
+//! integration of each remote block, then the deletion propagation and
+//! rope rebuild. Classification uses the clock ranges from before
+//! integration, like `merge_parallel`, so the result is the same as
+//! `merge`.
+//!
+//! The text itself is only touched by the last step, which replays the
+//! edits made to it since `start_merge` onto the merged copy and swaps
+//! the result in.
+
+use super::block::FugueBlock;
+use super::limits::Tally;
+use super::node::{ClientId, NodeId};
+use super::text::{check_block_conflict, FugueText, PendingMerge, TextError};
+use crate::merge_job::{MergeBudget, MergeProgress, Slice};
+use crate::sync::VectorClock;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Clock ranges of non-empty local blocks, per client: first clock and
+/// block ID (whose clock is the last)
+type ClockIndex = HashMap<ClientId, Vec<(u64, NodeId)>>;
+
+/// A [`FugueText::merge`] in progress, from [`FugueText::start_merge`]
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::crdt::text_fugue::FugueText;
+/// use synckit_core::merge_job::MergeBudget;
+///
+/// let mut local = FugueText::new("alice".to_string());
+/// let mut remote = FugueText::new("bob".to_string());
+/// for word in ["one ", "two ", "three "] {
+///     remote.insert(remote.len(), word).unwrap();
+/// }
+///
+/// let mut job = local.start_merge(remote);
+/// // Typing while the job runs is kept
+/// job.run_for(&mut local, MergeBudget::Ops(2)).unwrap();
+/// local.insert(0, "> ").unwrap();
+/// while !job.run_for(&mut local, MergeBudget::Ops(2)).unwrap().complete {}
+///
+/// assert_eq!(local.len(), 16);
+/// assert!(local.to_string().contains("one two three "));
+/// ```
+#[derive(Debug)]
+pub struct TextMergeJob {
+    /// The text as of `start_merge`, with the remote merged into it
+    working: FugueText,
+    remote: FugueText,
+    /// The text's revision at `start_merge`
+    revision: u64,
+    /// The text's state vector at `start_merge` (set by the index step)
+    state_vector: VectorClock,
+    phase: Phase,
+    done: usize,
+    total: usize,
+}
+
+#[derive(Debug)]
+enum Phase {
+    /// Split local blocks to match the remote's, in passes until one
+    /// splits nothing
+    Split {
+        after: Option<NodeId>,
+        split: bool,
+    },
+
+    /// Index local clock ranges
+    Index,
+
+    /// Check, classify and integrate each remote block
+    Integrate {
+        after: Option<NodeId>,
+        index: ClockIndex,
+        pending: PendingMerge,
+        max_clock: u64,
+        tally: Tally,
+    },
+
+    /// Propagate deletions and rebuild the rope
+    Finish {
+        pending: PendingMerge,
+        max_clock: u64,
+    },
+
+    /// Replay local edits and swap the result into the text
+    Swap,
+
+    Done,
+}
+
+impl FugueText {
+    /// Start merging `remote` in slices (see [`TextMergeJob`])
+    ///
+    /// Nothing changes until the job's last step; the text stays readable
+    /// and editable meanwhile.
+    pub fn start_merge(&self, remote: FugueText) -> TextMergeJob {
+        TextMergeJob {
+            total: 2 * remote.blocks.len() + 3,
+            working: self.clone(),
+            remote,
+            revision: self.revision,
+            state_vector: VectorClock::new(),
+            phase: Phase::Split {
+                after: None,
+                split: false,
+            },
+            done: 0,
+        }
+    }
+}
+
+impl TextMergeJob {
+    /// Run the next slice against `text`, the one the job was started on
+    ///
+    /// The merged text becomes visible, and observers see its changes as
+    /// one batch, in the slice that completes the job. Once `complete`,
+    /// further calls do nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::ReplicaConflict` if the remote holds different
+    /// text under one of our character IDs, or `TextError::LimitExceeded`
+    /// if its new blocks would take the text past its limits (as `merge`
+    /// does). The text is left unchanged, and running the job again fails
+    /// the same way.
+    pub fn run_for(
+        &mut self,
+        text: &mut FugueText,
+        budget: MergeBudget,
+    ) -> Result<MergeProgress, TextError> {
+        let mut slice = Slice::new(budget, text.time_provider());
+        while slice.has_budget() && !matches!(self.phase, Phase::Done) {
+            self.step(text)?;
+            self.done += 1;
+            slice.spend();
+        }
+        Ok(self.progress())
+    }
+
+    /// How far the job got
+    pub fn progress(&self) -> MergeProgress {
+        MergeProgress {
+            done: self.done,
+            total: self.total.max(self.done),
+            complete: matches!(self.phase, Phase::Done),
+        }
+    }
+
+    fn step(&mut self, text: &mut FugueText) -> Result<(), TextError> {
+        let Self {
+            working,
+            remote,
+            revision,
+            state_vector,
+            phase,
+            total,
+            ..
+        } = self;
+        match phase {
+            Phase::Split { after, split } => match next_block(&remote.blocks, after) {
+                Some((id, block)) => {
+                    let remote_len = block.len();
+                    if working
+                        .blocks
+                        .get(id)
+                        .is_some_and(|local| remote_len < local.len())
+                    {
+                        working.split_block_to_match(id, remote_len);
+                        *split = true;
+                    }
+                    *after = Some(id.clone());
+                }
+                None if *split => {
+                    // Splits can cascade: go again
+                    *after = None;
+                    *split = false;
+                    *total += remote.blocks.len();
+                }
+                None => *phase = Phase::Index,
+            },
+            Phase::Index => {
+                let mut index = ClockIndex::new();
+                for (id, block) in working.blocks.iter() {
+                    let len = block.len() as u64;
+                    if len > 0 {
+                        index
+                            .entry(id.client_id.clone())
+                            .or_default()
+                            .push((id.clock + 1 - len, id.clone()));
+                    }
+                }
+                // Splits don't move the highest clock per client
+                *state_vector = working.state_vector();
+                *phase = Phase::Integrate {
+                    after: None,
+                    index,
+                    pending: PendingMerge::default(),
+                    tally: working.tally(),
+                    // Marks and ticks that left no block take clocks too
+                    // (blocks are counted as they are integrated)
+                    max_clock: remote
+                        .marks
+                        .iter()
+                        .map(|m| m.clock)
+                        .fold(remote.clock.value(), u64::max),
+                };
+            }
+            Phase::Integrate {
+                after,
+                index,
+                pending,
+                max_clock,
+                tally,
+            } => match next_block(&remote.blocks, after) {
+                Some((id, block)) => {
+                    let ranges = index
+                        .get(id.client_id.as_str())
+                        .map_or(&[][..], Vec::as_slice);
+                    let remote_len = block.len() as u64;
+                    if remote_len > 0 && remote_len <= id.clock {
+                        let remote_start = id.clock + 1 - remote_len;
+                        for (local_start, local_id) in ranges {
+                            if let Some(local) = working.blocks.get(local_id) {
+                                check_block_conflict(local, *local_start, block, remote_start)?;
+                            }
+                        }
+                    }
+                    let action = working.classify_remote_block(id, block, |start, end| {
+                        ranges.iter().any(|(local_start, local_id)| {
+                            start <= local_id.clock && *local_start <= end
+                        })
+                    });
+                    tally.add_remote(action, block)?;
+                    working.integrate_remote_block(id, block, action, pending);
+                    let deleted_at = block.deleted_at.as_ref().map_or(0, |stamp| stamp.clock);
+                    *max_clock = (*max_clock).max(id.clock).max(deleted_at);
+                    *after = Some(id.clone());
+                }
+                None => {
+                    *phase = Phase::Finish {
+                        pending: std::mem::take(pending),
+                        max_clock: *max_clock,
+                    }
+                }
+            },
+            Phase::Finish { pending, max_clock } => {
+                working.finish_merge(std::mem::take(pending), *max_clock);
+                working.rebuild_rope();
+                *phase = Phase::Swap;
+            }
+            Phase::Swap => {
+                if text.revision != *revision {
+                    let edits = text.diff_since(state_vector);
+                    working.integrate_delta(&edits)?;
+                }
+                let before = text.text_before_change();
+                let visible = text.visible_runs();
+                text.touch();
+                std::mem::swap(&mut text.rope, &mut working.rope);
+                std::mem::swap(&mut text.units, &mut working.units);
+                std::mem::swap(&mut text.blocks, &mut working.blocks);
+                std::mem::swap(&mut text.cached_blocks, &mut working.cached_blocks);
+                std::mem::swap(&mut text.cached_positions, &mut working.cached_positions);
+                text.cache_valid = working.cache_valid;
+                text.clock.update(working.clock.value());
+                text.annotations.merge(&remote.annotations);
+                text.marks.merge(&remote.marks);
+                text.collected.merge(&remote.collected);
+                text.record_remote_change(visible);
+                text.notify_changes_since(before);
+                *phase = Phase::Done;
+            }
+            Phase::Done => {}
+        }
+        Ok(())
+    }
+}
+
+/// The block after `after` (the first one if None)
+fn next_block<'a>(
+    blocks: &'a BTreeMap<NodeId, Arc<FugueBlock>>,
+    after: &Option<NodeId>,
+) -> Option<(&'a NodeId, &'a FugueBlock)> {
+    let next = match after {
+        Some(id) => blocks.range((Bound::Excluded(id), Bound::Unbounded)).next(),
+        None => blocks.iter().next(),
+    };
+    next.map(|(id, block)| (id, block.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::TextEvent;
+    use crate::time::{MockTime, TimeProvider};
+
+    /// Two replicas that each hold edits the other lacks, with deletions
+    /// splitting blocks differently on each side
+    fn divergent_pair() -> (FugueText, FugueText) {
+        let mut base = FugueText::new("base".to_string());
+        base.insert(0, &"shared text ".repeat(10)).unwrap();
+
+        let mut left = FugueText::new("left".to_string());
+        let mut right = FugueText::new("right".to_string());
+        left.merge(&base).unwrap();
+        right.merge(&base).unwrap();
+        let mut seed = 7u64;
+        for round in 0..30 {
+            for replica in [&mut left, &mut right] {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let roll = (seed >> 33) as usize;
+                if round % 3 == 2 {
+                    replica
+                        .delete(roll % (replica.len() - 3), 1 + roll % 3)
+                        .unwrap();
+                } else {
+                    replica.insert(roll % (replica.len() + 1), "ab").unwrap();
+                }
+            }
+        }
+        (left, right)
+    }
+
+    fn run_to_end(job: &mut TextMergeJob, text: &mut FugueText, budget: MergeBudget) -> usize {
+        let mut slices = 1;
+        while !job.run_for(text, budget).unwrap().complete {
+            slices += 1;
+        }
+        slices
+    }
+
+    #[test]
+    fn test_sliced_merge_is_bit_identical() {
+        let (left, right) = divergent_pair();
+
+        for (a, b) in [(&left, &right), (&right, &left)] {
+            let mut monolithic = a.clone();
+            monolithic.merge(b).unwrap();
+
+            for ops in [1, 7] {
+                let mut sliced = a.clone();
+                let mut job = sliced.start_merge(b.clone());
+                let slices = run_to_end(&mut job, &mut sliced, MergeBudget::Ops(ops));
+                assert!(slices > 1);
+
+                assert_eq!(
+                    serde_json::to_vec(&sliced).unwrap(),
+                    serde_json::to_vec(&monolithic).unwrap()
+                );
+                assert_eq!(sliced.to_string(), monolithic.to_string());
+                assert_eq!(sliced.clock(), monolithic.clock());
+            }
+        }
+    }
+
+    #[test]
+    fn test_local_edits_mid_job_are_replayed() {
+        let (mut left, right) = divergent_pair();
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        left.subscribe(move |batch| sink.lock().unwrap().push(batch.to_vec()));
+
+        let mut job = left.start_merge(right.clone());
+        job.run_for(&mut left, MergeBudget::Ops(20)).unwrap();
+        let before = left.to_string();
+        left.insert(0, "typed ").unwrap();
+        left.delete(10, 4).unwrap();
+        let edited = left.clone();
+        // Nothing of the merge is visible yet
+        assert_eq!(events.lock().unwrap().len(), 2);
+        assert_eq!(left.len(), before.chars().count() + 6 - 4);
+
+        run_to_end(&mut job, &mut left, MergeBudget::Ops(20));
+        let mut expected = edited;
+        expected.merge(&right).unwrap();
+        assert_eq!(
+            serde_json::to_vec(&left).unwrap(),
+            serde_json::to_vec(&expected).unwrap()
+        );
+        assert!(left.to_string().starts_with("typed "));
+
+        // The merge arrives as one batch that turns the edited text into
+        // the merged one
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 3);
+        let mut replayed: Vec<char> = before.chars().collect();
+        for event in events.iter().flatten() {
+            match event {
+                TextEvent::Insert { position, text } => {
+                    replayed.splice(*position..*position, text.chars());
+                }
+                TextEvent::Delete { position, length } => {
+                    replayed.drain(*position..*position + *length);
+                }
+            }
+        }
+        assert_eq!(replayed.into_iter().collect::<String>(), left.to_string());
+    }
+
+    #[test]
+    fn test_time_budget_uses_the_text_clock() {
+        let (mut left, right) = divergent_pair();
+        let time = MockTime::new(0);
+        let clock = time.clone();
+        // Every reading moves the clock by 1ms
+        left.set_time_provider(move || {
+            clock.advance(1);
+            clock.now_ms()
+        });
+
+        let mut job = left.start_merge(right);
+        let progress = job.run_for(&mut left, MergeBudget::Millis(10)).unwrap();
+        assert_eq!(progress.done, 10);
+        assert!(!progress.complete);
+        assert!(progress.total > progress.done);
+        assert_eq!(time.now_ms(), 11);
+    }
+}
@@ -26,9 +26,19 @@
 //! - **BTreeMap**: CRDT metadata maintaining Fugue ordering
 //! - **RLE**: Consecutive chars from same operation stored in single block
 //!
+//! # Positions
+//!
+//! Positions and lengths count grapheme clusters as each insert wrote
+//! them: a ZWJ family emoji, a letter with its accent or a flag is one,
+//! whatever its char or byte length. A cluster that only forms when a
+//! later insert adds to it, such as an accent typed after its letter,
+//! keeps counting as its parts. `len()`, `insert`, `delete`, snapshots,
+//! events, line indexes and anchors all use this unit.
+//!
 //! # Example
 //!
 //! ```rust
+//! # #[cfg(feature = "text-crdt")] {
 //! use synckit_core::crdt::text_fugue::FugueText;
 //!
 //! let mut text1 = FugueText::new("client1".to_string());
@@ -44,6 +54,7 @@
 //!
 //! // Both replicas converge to same result
 //! assert_eq!(text1.to_string(), text2.to_string());
+//! # }
 //! ```
 //!
 //! # References
@@ -51,10 +62,114 @@
 //! - **Paper**: "Fugue: A CRDT for Collaborative Text Editing" (arXiv:2305.00583)
 //! - **Loro CRDT**: Production implementation using Fugue
 
+// Kernel: block metadata, clocks and ordering (no_std + alloc)
 mod block;
+mod clock;
 mod node;
+pub mod order;
+mod small_text;
+
+// Rope-backed text and everything built on it (std)
+#[cfg(feature = "text-crdt")]
+mod anchor;
+#[cfg(feature = "text-crdt")]
+mod annotations;
+#[cfg(feature = "text-crdt")]
+mod attribution;
+#[cfg(feature = "text-crdt")]
+mod change;
+#[cfg(feature = "text-crdt")]
+mod clients;
+#[cfg(feature = "text-crdt")]
+mod delta;
+#[cfg(all(feature = "text-crdt", feature = "serde-compact"))]
+mod encoding;
+#[cfg(feature = "text-crdt")]
+mod fingerprint;
+#[cfg(feature = "text-crdt")]
+mod gc;
+#[cfg(feature = "text-crdt")]
+mod history;
+#[cfg(feature = "text-crdt")]
+mod limits;
+#[cfg(feature = "text-crdt")]
+mod line_index;
+#[cfg(feature = "text-crdt")]
+mod load;
+#[cfg(feature = "text-crdt")]
+mod markdown;
+#[cfg(feature = "text-crdt")]
+mod marks;
+#[cfg(feature = "text-crdt")]
+mod memory;
+#[cfg(feature = "text-crdt")]
+mod merge_job;
+#[cfg(feature = "text-crdt")]
+mod notify;
+#[cfg(all(feature = "parallel", feature = "text-crdt"))]
+mod parallel;
+#[cfg(all(test, feature = "text-crdt"))]
+mod proptests;
+#[cfg(feature = "text-crdt")]
+mod rechunk;
+#[cfg(feature = "text-crdt")]
+mod remote_ops;
+#[cfg(feature = "text-crdt")]
+mod rope_update;
+#[cfg(feature = "text-crdt")]
+mod search;
+#[cfg(feature = "text-crdt")]
+mod slice;
+#[cfg(feature = "text-crdt")]
+mod snapshot;
+#[cfg(feature = "text-crdt")]
 mod text;
+#[cfg(feature = "text-crdt")]
+mod text_diff;
+#[cfg(feature = "text-crdt")]
+mod time_travel;
+#[cfg(feature = "text-crdt")]
+mod undo;
+#[cfg(feature = "text-crdt")]
+mod units;
+#[cfg(feature = "text-crdt")]
+mod utf16;
+
+#[cfg(feature = "yjs-interop")]
+mod yjs;
 
 pub use block::FugueBlock;
-pub use node::NodeId;
-pub use text::{FugueText, LamportClock, TextError};
+pub use clock::LamportClock;
+pub use node::{ClientId, NodeId};
+pub use small_text::{BlockText, INLINE_CAPACITY};
+
+#[cfg(feature = "text-crdt")]
+pub use anchor::{Anchor, AnchorBias};
+#[cfg(feature = "text-crdt")]
+pub use attribution::AttributedSpan;
+#[cfg(feature = "text-crdt")]
+pub use change::{ChangeSpan, TextChange};
+#[cfg(feature = "text-crdt")]
+pub use delta::{DeleteRange, TextDelta, TextEvent};
+#[cfg(all(feature = "text-crdt", feature = "serde-compact"))]
+pub use encoding::TextEncodeOptions;
+#[cfg(feature = "text-crdt")]
+pub use history::{HistoryRetention, DEFAULT_HISTORY_OPS};
+#[cfg(feature = "text-crdt")]
+pub use limits::{TextLimit, TextLimits};
+#[cfg(feature = "text-crdt")]
+pub use line_index::{LineIndex, LinePosition, SharedLineIndex};
+#[cfg(feature = "text-crdt")]
+pub use markdown::{MarkdownImport, MarkdownOptions, MarkdownSpan, MarkdownStyle};
+#[cfg(feature = "text-crdt")]
+pub use marks::{Mark, MarkExpand, MarkedRange, Marks};
+#[cfg(feature = "text-crdt")]
+pub use memory::TextMemoryStats;
+#[cfg(feature = "text-crdt")]
+pub use merge_job::TextMergeJob;
+#[cfg(feature = "text-crdt")]
+pub use rechunk::FugueTextOptions;
+#[cfg(feature = "text-crdt")]
+pub use snapshot::TextSnapshot;
+#[cfg(feature = "text-crdt")]
+pub use text::{FugueText, SpliceResult, TextError};
@@ -4,8 +4,149 @@
 //! The Ord implementation defines how blocks are ordered in the BTreeMap,
 //! which maintains the Fugue CRDT structure.
 
-use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::ops::Deref;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A replica's identifier, shared between the IDs that carry it
+///
+/// Every block ID and origin names its client, and client IDs are often
+/// UUIDs: 36 bytes that would otherwise be copied into each. A `ClientId`
+/// is a reference-counted string instead, so cloning one is a counter
+/// bump, and a text keeps one copy per client (see `ClientTable::intern`,
+/// `intern_node` and `intern_block`, and `FugueText::intern_all_clients`
+/// on load). It compares, hashes and serializes as the plain string.
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::crdt::text_fugue::ClientId;
+///
+/// let id = ClientId::from("client1");
+/// assert_eq!(id, "client1");
+/// assert_eq!(serde_json::to_string(&id).unwrap(), "\"client1\"");
+/// ```
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClientId(Arc<str>);
+
+impl ClientId {
+    /// The identifier as a string slice
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether both name the same allocation, not just the same client
+    pub fn shares_with(&self, other: &ClientId) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Deref for ClientId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for ClientId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for ClientId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for ClientId {
+    fn from(id: String) -> Self {
+        Self(id.into())
+    }
+}
+
+impl From<&str> for ClientId {
+    fn from(id: &str) -> Self {
+        Self(id.into())
+    }
+}
+
+impl From<&String> for ClientId {
+    fn from(id: &String) -> Self {
+        Self(id.as_str().into())
+    }
+}
+
+impl From<ClientId> for String {
+    fn from(id: ClientId) -> Self {
+        id.as_str().into()
+    }
+}
+
+impl PartialEq<str> for ClientId {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ClientId {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for ClientId {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<ClientId> for str {
+    fn eq(&self, other: &ClientId) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<ClientId> for &str {
+    fn eq(&self, other: &ClientId) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<ClientId> for String {
+    fn eq(&self, other: &ClientId) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl core::fmt::Debug for ClientId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl core::fmt::Display for ClientId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ClientId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ClientId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
 
 /// Unique identifier for a Fugue block
 ///
@@ -44,7 +185,7 @@ use std::cmp::Ordering;
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct NodeId {
     /// Client/replica identifier
-    pub client_id: String,
+    pub client_id: ClientId,
 
     /// Lamport timestamp (logical clock)
     pub clock: u64,
@@ -58,7 +199,8 @@ impl NodeId {
     ///
     /// # Arguments
     ///
-    /// * `client_id` - Unique identifier for this replica
+    /// * `client_id` - Unique identifier for this replica (a `String`, or a
+    ///   shared [`ClientId`] to avoid copying it)
     /// * `clock` - Current Lamport timestamp
     /// * `offset` - Position within batch operation (0 for single insertions)
     ///
@@ -69,9 +211,9 @@ impl NodeId {
     ///
     /// let id = NodeId::new("client1".to_string(), 42, 0);
     /// ```
-    pub fn new(client_id: String, clock: u64, offset: usize) -> Self {
+    pub fn new(client_id: impl Into<ClientId>, clock: u64, offset: usize) -> Self {
         Self {
-            client_id,
+            client_id: client_id.into(),
             clock,
             offset,
         }
@@ -114,8 +256,8 @@ impl PartialOrd for NodeId {
     }
 }
 
-impl std::fmt::Display for NodeId {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for NodeId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}@{}:{}", self.client_id, self.clock, self.offset)
     }
 }
@@ -190,5 +332,7 @@ mod tests {
         let deserialized: NodeId = serde_json::from_str(&json).unwrap();
 
         assert_eq!(id, deserialized);
+        // The shared client ID serializes as a plain string
+        assert_eq!(json, r#"{"client_id":"client1","clock":42,"offset":5}"#);
     }
 }
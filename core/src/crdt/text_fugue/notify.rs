@@ -0,0 +1,199 @@
+//! Change notifications for `FugueText` (see [`crate::notify`])
+//!
+//! Local edits report exactly what they did. Merges and applied deltas
+//! report the difference between the text before and after, computed only
+//! while someone is subscribed. `subscribe_changes` gets the same batches
+//! as retain/insert/delete spans.
+
+use super::change::TextChange;
+use super::delta::TextEvent;
+use super::snapshot::TextSnapshot;
+use super::text::FugueText;
+use crate::notify::{CoalescePolicy, SubscriptionId};
+use crate::time::{self, SharedTime, TimeProvider};
+
+impl FugueText {
+    /// Call `callback` with each batch of text changes
+    ///
+    /// Everything one call changes (a `merge`, an applied delta, an undo,
+    /// a `transaction`) arrives as one batch. Applying a batch's events in
+    /// order to the text as it was before reproduces the text after.
+    /// Observers are not cloned or serialized with the text.
+    pub fn subscribe(
+        &mut self,
+        callback: impl FnMut(&[TextEvent]) + Send + Sync + 'static,
+    ) -> SubscriptionId {
+        self.notifier.add(Box::new(callback))
+    }
+
+    /// Call `callback` with each batch of text changes as one
+    /// [`TextChange`]: spans over the text as it was before the batch
+    pub fn subscribe_changes(
+        &mut self,
+        mut callback: impl FnMut(&TextChange) + Send + Sync + 'static,
+    ) -> SubscriptionId {
+        self.subscribe(move |events| callback(&TextChange::from_events(events)))
+    }
+
+    /// Remove a callback; returns false if it was already removed
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.notifier.remove(id)
+    }
+
+    /// Run `f` as one operation: its changes are delivered as one batch
+    /// when it returns
+    pub fn transaction<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.notifier.begin();
+        let result = f(self);
+        self.notifier.end();
+        result
+    }
+
+    /// Merge consecutive batches according to `policy` before delivering
+    /// them (see [`CoalescePolicy`])
+    ///
+    /// `now_ms` measures `max_delay`; any [`TimeProvider`], including a
+    /// `Fn() -> u64` closure or a [`crate::time::MockTime`] in tests.
+    pub fn set_coalescing(
+        &mut self,
+        policy: CoalescePolicy,
+        now_ms: impl TimeProvider + Send + Sync + 'static,
+    ) {
+        self.notifier
+            .set_coalescing(policy, SharedTime::new(now_ms));
+    }
+
+    /// Like [`FugueText::set_coalescing`], timed by
+    /// [`FugueText::time_provider`]
+    pub fn set_coalescing_policy(&mut self, policy: CoalescePolicy) {
+        let time = self.time_provider();
+        self.notifier.set_coalescing(policy, time);
+    }
+
+    /// Use `provider` wherever this text needs the current time
+    pub fn set_time_provider(&mut self, provider: impl TimeProvider + Send + Sync + 'static) {
+        self.time = Some(SharedTime::new(provider));
+    }
+
+    /// The text's clock: the one set with [`FugueText::set_time_provider`],
+    /// else the thread default
+    pub fn time_provider(&self) -> SharedTime {
+        self.time.clone().unwrap_or_else(time::default_provider)
+    }
+
+    /// Deliver held changes and deliver every batch right away from now on
+    pub fn stop_coalescing(&mut self) {
+        self.notifier.stop_coalescing();
+    }
+
+    /// Deliver changes held back by the coalescer now
+    pub fn flush_notifications(&mut self) {
+        self.notifier.flush();
+    }
+
+    /// The visible text, if a change to it has to be reported
+    pub(super) fn text_before_change(&self) -> Option<TextSnapshot> {
+        self.notifier.is_active().then(|| self.snapshot())
+    }
+
+    /// Report what changed since `text_before_change`
+    pub(super) fn notify_changes_since(&mut self, before: Option<TextSnapshot>) {
+        if let Some(before) = before {
+            let events = TextEvent::between(&before, &self.snapshot());
+            self.notifier.extend(events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::text_fugue::ChangeSpan;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_edits_and_undo_notify_in_batches() {
+        let mut text = FugueText::new("alice".to_string());
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = batches.clone();
+        text.subscribe(move |events| sink.lock().unwrap().push(events.to_vec()));
+
+        let mut undo = crate::undo::UndoManager::new("alice".to_string());
+        undo.insert_text(&mut text, 0, "Hello World").unwrap();
+        text.transaction(|text| {
+            text.delete(5, 6).unwrap();
+            text.insert(5, "!").unwrap();
+        });
+        assert_eq!(
+            batches.lock().unwrap().last().unwrap(),
+            &TextEvent::diff("Hello World", "Hello!")
+        );
+
+        undo.undo(&mut text).unwrap();
+        assert_eq!(text.to_string(), "!");
+        assert_eq!(batches.lock().unwrap().len(), 3);
+        assert_eq!(
+            batches.lock().unwrap().last().unwrap(),
+            &vec![TextEvent::Delete {
+                position: 0,
+                length: 5
+            }]
+        );
+    }
+
+    #[test]
+    fn test_changes_report_spans_of_local_edits() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "Hello World").unwrap();
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let sink = changes.clone();
+        let id = text.subscribe_changes(move |change| sink.lock().unwrap().push(change.clone()));
+
+        text.insert(5, ",").unwrap();
+        text.transaction(|text| {
+            text.delete(0, 1).unwrap();
+            text.insert(0, "J").unwrap();
+        });
+        assert_eq!(
+            changes.lock().unwrap()[0].spans(),
+            [ChangeSpan::Retain(5), ChangeSpan::Insert(",".to_string())]
+        );
+        assert_eq!(
+            changes.lock().unwrap()[1].spans(),
+            [ChangeSpan::Insert("J".to_string()), ChangeSpan::Delete(1)]
+        );
+
+        assert!(text.unsubscribe(id));
+        text.delete(0, 1).unwrap();
+        assert_eq!(changes.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_changes_keep_a_mirror_in_step_through_merges() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello World").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        let mirror = Arc::new(Mutex::new(alice.to_string()));
+        let view = mirror.clone();
+        alice.subscribe_changes(move |change| {
+            let mut view = view.lock().unwrap();
+            *view = change.apply(&view);
+        });
+
+        alice.insert(11, "!").unwrap();
+        bob.delete(0, 6).unwrap();
+        bob.insert(5, ", again").unwrap();
+        bob.insert(0, "> ").unwrap();
+        alice.merge(&bob).unwrap();
+        assert_eq!(*mirror.lock().unwrap(), alice.to_string());
+
+        bob.splice(2, 5, "Earth").unwrap();
+        alice
+            .apply_delta(&bob.diff_since(&alice.state_vector()))
+            .unwrap();
+        assert_eq!(alice.to_string(), "> Earth!, again");
+        assert_eq!(*mirror.lock().unwrap(), alice.to_string());
+    }
+}
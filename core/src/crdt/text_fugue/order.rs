@@ -0,0 +1,350 @@
+//! Fugue ordering over block metadata
+//!
+//! Turns the blocks of a text (their left and right origins) into document
+//! order, without the rope. This is the part of the text CRDT every
+//! replica must compute identically, so it lives in the `no_std` kernel:
+//! `FugueText` uses it for positions, and embedded replicas that only keep
+//! blocks can use it to render or to check convergence.
+
+use super::block::FugueBlock;
+use super::node::{ClientId, NodeId};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+
+/// Side of a node in the Fugue tree (left or right child of parent)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Tree node for Fugue tree reconstruction.
+///
+/// The Fugue algorithm requires building an explicit tree structure from
+/// the implicit tree encoded in left_origin/right_origin metadata.
+/// This struct represents a node in that reconstructed tree.
+#[derive(Debug, Clone)]
+struct TreeNode {
+    id: NodeId,
+    parent: Option<NodeId>,
+    side: Side,
+    deleted: bool,
+}
+
+/// Reconstructed Fugue tree, by block ID
+type Tree = BTreeMap<NodeId, TreeNode>;
+
+/// Children of each node (roots under None) with their sibling keys,
+/// sorted by them
+type Children<'a> = BTreeMap<Option<&'a NodeId>, Vec<(Side, (u64, ClientId, u64), &'a NodeId)>>;
+
+/// First clock of every non-empty block, by client and last clock, for
+/// finding the block that holds a character without scanning
+struct Starts<'a>(BTreeMap<(&'a str, u64), (u64, &'a NodeId)>);
+
+impl<'a> Starts<'a> {
+    fn new<B: Borrow<FugueBlock>>(blocks: &'a BTreeMap<NodeId, B>) -> Self {
+        Starts(
+            blocks
+                .iter()
+                .map(|(id, block)| (id, block.borrow()))
+                .filter(|(_, block)| !block.is_empty())
+                .map(|(id, block)| {
+                    let start = id.clock.saturating_sub(block.len() as u64 - 1);
+                    ((id.client_id.as_str(), id.clock), (start, id))
+                })
+                .collect(),
+        )
+    }
+
+    /// Same as [`block_containing`]
+    fn block_containing(&self, node_id: &NodeId) -> Option<NodeId> {
+        let ((client, _), (start, id)) = self
+            .0
+            .range((node_id.client_id.as_str(), node_id.clock)..)
+            .next()?;
+        (*client == node_id.client_id && *start <= node_id.clock).then(|| (*id).clone())
+    }
+}
+
+/// Block IDs in document order, using Fugue tree traversal
+///
+/// BTreeMap iteration gives causal/timestamp order, NOT document order.
+/// Deleted blocks are left out unless `include_deleted` is set; exporters
+/// need them to keep tombstones, and anchors to place deleted characters.
+/// The blocks may be held directly or behind a pointer such as `Arc`.
+///
+/// # Algorithm
+/// 1. Reconstruct Fugue tree from left_origin/right_origin metadata
+/// 2. Perform in-order traversal of the tree
+/// 3. Return NodeIds in document order
+///
+/// # Complexity
+/// - Time: O(n log n) for tree reconstruction and traversal
+/// - Space: O(n) for tree storage
+pub fn document_order<B: Borrow<FugueBlock>>(
+    blocks: &BTreeMap<NodeId, B>,
+    include_deleted: bool,
+) -> Vec<NodeId> {
+    let tree = reconstruct_fugue_tree(blocks);
+    in_order_traversal(blocks, &tree, include_deleted)
+}
+
+/// Where each block hangs in the Fugue tree: its parent (None for roots)
+/// and whether it is a left child
+///
+/// For passes that rewrite blocks and have to keep the tree's shape.
+#[cfg(feature = "text-crdt")]
+pub(crate) fn placements<B: Borrow<FugueBlock>>(
+    blocks: &BTreeMap<NodeId, B>,
+) -> BTreeMap<NodeId, (Option<NodeId>, bool)> {
+    reconstruct_fugue_tree(blocks)
+        .into_iter()
+        .map(|(id, node)| (id, (node.parent, node.side == Side::Left)))
+        .collect()
+}
+
+/// Find the block that contains a given character-level NodeId.
+///
+/// With per-character clock allocation, each block represents a RANGE of clock values,
+/// not just a single clock. Block IDs always have offset=0.
+///
+/// # Clock Range Calculation
+///
+/// A block with ID `client@5:0` and length=3 contains characters at clocks [3, 4, 5]:
+/// - block_start_clock = 5 - 3 + 1 = 3
+/// - block_end_clock = 5
+///
+/// # Arguments
+/// * `node_id` - Character-level NodeId pointing to a specific clock value
+///
+/// # Returns
+/// Block ID that contains this clock value, None if not found
+pub fn block_containing<B: Borrow<FugueBlock>>(
+    blocks: &BTreeMap<NodeId, B>,
+    node_id: &NodeId,
+) -> Option<NodeId> {
+    // Find block with matching client_id whose clock range contains node_id.clock
+    for (block_id, block) in blocks.iter() {
+        if block_id.client_id == node_id.client_id {
+            // Block represents clock range [start_clock, end_clock]
+            // Example: block@5:0 with len=3 → clocks [3, 4, 5]
+            let block_len = block.borrow().len() as u64;
+            if block_len == 0 {
+                continue; // Empty blocks don't contain any characters
+            }
+
+            let block_start_clock = block_id.clock.saturating_sub(block_len - 1);
+            let block_end_clock = block_id.clock;
+
+            if node_id.clock >= block_start_clock && node_id.clock <= block_end_clock {
+                return Some(block_id.clone());
+            }
+        }
+    }
+    None
+}
+
+/// Ordering key for sibling blocks: the ID of the block's first character
+///
+/// Block IDs name the *last* character, which changes when a block is
+/// split, so ordering by block ID would let replicas that split a block
+/// differently order its siblings differently. The first character's ID
+/// is the same for the whole block and for its first split piece.
+pub(crate) fn sibling_key<B: Borrow<FugueBlock>>(
+    blocks: &BTreeMap<NodeId, B>,
+    id: &NodeId,
+) -> (u64, ClientId, u64) {
+    let len = blocks.get(id).map_or(0, |b| b.borrow().len()) as u64;
+    let start_clock = id.clock.saturating_sub(len.saturating_sub(1));
+    (start_clock, id.client_id.clone(), id.clock)
+}
+
+/// Reconstruct the Fugue tree from left_origin and right_origin metadata.
+///
+/// This builds an explicit tree structure with parent-child relationships
+/// from the implicit tree encoded in left_origin/right_origin fields.
+///
+/// **NOTE**: This works at BLOCK level. Blocks are the atomic units in the tree.
+/// Character-level NodeIds in origins are mapped to their containing blocks.
+///
+/// # Algorithm
+/// Process blocks in timestamp order (NodeId order). For each block:
+/// 1. Map character-level origins to their containing blocks
+/// 2. Check if left_origin block is an ancestor of right_origin block
+/// 3. If YES: block becomes left child of right_origin block
+/// 4. If NO: block becomes right child of left_origin block
+///
+/// # Returns
+/// Map from NodeId → TreeNode with parent/side information
+fn reconstruct_fugue_tree<B: Borrow<FugueBlock>>(blocks: &BTreeMap<NodeId, B>) -> Tree {
+    let mut tree = BTreeMap::new();
+    let starts = Starts::new(blocks);
+
+    // Process blocks in timestamp order (critical for ancestor checks)
+    let mut sorted_blocks: Vec<_> = blocks.iter().collect();
+    sorted_blocks.sort_by_cached_key(|(id, _)| sibling_key(blocks, id));
+
+    for (id, block) in sorted_blocks {
+        let block = block.borrow();
+        // Map character-level NodeIds to their containing blocks
+        let left_block = block
+            .left_origin
+            .as_ref()
+            .and_then(|node_id| starts.block_containing(node_id));
+        let right_block = block
+            .right_origin
+            .as_ref()
+            .and_then(|node_id| starts.block_containing(node_id));
+
+        let (parent, side) = determine_parent_and_side(&left_block, &right_block, &tree);
+
+        tree.insert(
+            id.clone(),
+            TreeNode {
+                id: id.clone(),
+                parent,
+                side,
+                deleted: block.is_deleted(),
+            },
+        );
+    }
+
+    tree
+}
+
+/// Determine parent and side for a new node based on Fugue algorithm.
+///
+/// # Fugue Rule
+/// When inserting between positions a and b:
+/// - If a is NOT an ancestor of b: new node is right child of a
+/// - If a IS an ancestor of b: new node is left child of b
+///
+/// # Arguments
+/// * `left_origin` - Block to the left (a)
+/// * `right_origin` - Block to the right (b)
+/// * `tree` - Partial tree built from earlier blocks
+fn determine_parent_and_side(
+    left_origin: &Option<NodeId>,
+    right_origin: &Option<NodeId>,
+    tree: &Tree,
+) -> (Option<NodeId>, Side) {
+    match (left_origin, right_origin) {
+        // No origins: root node (first block ever inserted)
+        (None, None) => (None, Side::Right),
+
+        // Only left origin: insert at end
+        (Some(a), None) => (Some(a.clone()), Side::Right),
+
+        // Only right origin: insert at start
+        (None, Some(b)) => (Some(b.clone()), Side::Left),
+
+        // Both origins: check ancestor relationship
+        (Some(a), Some(b)) => {
+            if is_ancestor_in_tree(a, b, tree) {
+                // a is ancestor of b → new node is left child of b
+                (Some(b.clone()), Side::Left)
+            } else {
+                // a is NOT ancestor of b → new node is right child of a
+                (Some(a.clone()), Side::Right)
+            }
+        }
+    }
+}
+
+/// Check if node `a` is an ancestor of node `b` in the tree.
+///
+/// Walks up from b to root, checking if we encounter a.
+///
+/// # Arguments
+/// * `a` - Potential ancestor
+/// * `b` - Potential descendant
+/// * `tree` - Tree structure to search
+fn is_ancestor_in_tree(a: &NodeId, b: &NodeId, tree: &Tree) -> bool {
+    let mut current = Some(b.clone());
+
+    while let Some(node_id) = current {
+        if &node_id == a {
+            return true;
+        }
+
+        // Walk to parent
+        current = tree.get(&node_id).and_then(|node| node.parent.clone());
+    }
+
+    false
+}
+
+/// Perform in-order traversal of the Fugue tree to get document order.
+///
+/// # In-Order Traversal Algorithm
+/// 1. Traverse left children (sorted by NodeId for determinism)
+/// 2. Visit the node
+/// 3. Traverse right children (sorted by NodeId)
+///
+/// This produces the correct document order for Fugue CRDT.
+///
+/// # Arguments
+/// * `tree` - Reconstructed Fugue tree
+/// * `include_deleted` - Whether deleted blocks appear in the result
+///
+/// # Returns
+/// Vector of NodeIds in document order
+fn in_order_traversal<B: Borrow<FugueBlock>>(
+    blocks: &BTreeMap<NodeId, B>,
+    tree: &Tree,
+    include_deleted: bool,
+) -> Vec<NodeId> {
+    // Group children by parent once, sorted by causal dot for
+    // deterministic ordering; roots (no parent) sort the same way, so
+    // concurrent inserts at position 0 converge
+    let mut children: Children = BTreeMap::new();
+    for node in tree.values() {
+        children.entry(node.parent.as_ref()).or_default().push((
+            node.side,
+            sibling_key(blocks, &node.id),
+            &node.id,
+        ));
+    }
+    for siblings in children.values_mut() {
+        siblings.sort_by(|a, b| a.1.cmp(&b.1));
+    }
+
+    let mut result = Vec::new();
+
+    // Traverse from each root (usually just one, but handle multiple)
+    for (_, _, root_id) in children.get(&None).into_iter().flatten() {
+        in_order_visit(root_id, tree, &children, include_deleted, &mut result);
+    }
+
+    result
+}
+
+/// Recursive in-order tree traversal helper.
+fn in_order_visit(
+    node_id: &NodeId,
+    tree: &Tree,
+    children: &Children,
+    include_deleted: bool,
+    result: &mut Vec<NodeId>,
+) {
+    let node = &tree[node_id];
+    // IMPORTANT: Include deleted nodes in traversal (they may have non-deleted children)
+    let siblings = children.get(&Some(node_id)).map_or(&[][..], Vec::as_slice);
+
+    // 1. Traverse left children (sorted by causal dot)
+    for (_, _, child_id) in siblings.iter().filter(|(side, _, _)| *side == Side::Left) {
+        in_order_visit(child_id, tree, children, include_deleted, result);
+    }
+
+    // 2. Visit this node (if not deleted)
+    if include_deleted || !node.deleted {
+        result.push(node_id.clone());
+    }
+
+    // 3. Traverse right children (sorted by causal dot)
+    for (_, _, child_id) in siblings.iter().filter(|(side, _, _)| *side == Side::Right) {
+        in_order_visit(child_id, tree, children, include_deleted, result);
+    }
+}
@@ -0,0 +1,141 @@
+//! Sharded merge for large replicas (`parallel` feature)
+//!
+//! Most of `merge` on a big document is deciding, for every remote block,
+//! whether it is known, a split piece of a known insert, or new. That only
+//! reads local blocks from the same client, so remote blocks are sharded
+//! by client ID and classified on the rayon pool. Integration, deletion
+//! propagation and the rope rebuild stay single-threaded, in the same
+//! order as `merge`, so the result is identical.
+
+use super::block::FugueBlock;
+use super::node::NodeId;
+use super::text::{FugueText, RemoteBlock, TextError};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+impl FugueText {
+    /// Merge with another replica, classifying its blocks in parallel
+    ///
+    /// Produces exactly the same state as [`FugueText::merge`]; worth it
+    /// for large replicas with many clients, where classification
+    /// dominates.
+    pub fn merge_parallel(&mut self, remote: &FugueText) -> Result<(), TextError> {
+        self.check_replica_conflicts(remote.blocks.values().map(Arc::as_ref))?;
+        self.check_remote_limits(remote.blocks.iter().map(|(id, block)| (id, block.as_ref())))?;
+        self.touch();
+        let before = self.text_before_change();
+        let visible = self.visible_runs();
+        self.split_to_match(remote);
+
+        let classified = self.classify_sharded(remote);
+        self.merge_blocks(remote, |_, id, _| classified[id]);
+        self.update_rope(visible);
+        self.notify_changes_since(before);
+        Ok(())
+    }
+
+    fn classify_sharded(&self, remote: &FugueText) -> HashMap<NodeId, RemoteBlock> {
+        // Clock ranges of non-empty local blocks, per client
+        let mut local: HashMap<&str, Vec<(u64, u64)>> = HashMap::new();
+        for (id, block) in self.blocks.iter() {
+            let len = block.len() as u64;
+            if len > 0 {
+                local
+                    .entry(id.client_id.as_str())
+                    .or_default()
+                    .push((id.clock.saturating_sub(len - 1), id.clock));
+            }
+        }
+
+        let mut shards: HashMap<&str, Vec<(&NodeId, &FugueBlock)>> = HashMap::new();
+        for (id, block) in remote.blocks.iter() {
+            shards
+                .entry(id.client_id.as_str())
+                .or_default()
+                .push((id, block));
+        }
+
+        shards
+            .into_par_iter()
+            .flat_map_iter(|(client_id, blocks)| {
+                let ranges = local.get(client_id).map_or(&[][..], Vec::as_slice);
+                blocks.into_iter().map(move |(id, block)| {
+                    let action = self.classify_remote_block(id, block, |start, end| {
+                        ranges.iter().any(|&(local_start, local_end)| {
+                            start <= local_end && local_start <= end
+                        })
+                    });
+                    (id.clone(), action)
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Several clients edit concurrently, then exchange deltas partially
+    fn divergent_pair() -> (FugueText, FugueText) {
+        let mut hub = FugueText::new("hub".to_string());
+        hub.insert(0, &"base text ".repeat(20)).unwrap();
+
+        let mut seed = 11u64;
+        let mut replicas: Vec<FugueText> = (0..6)
+            .map(|i| {
+                let mut replica = FugueText::new(format!("client{}", i));
+                replica.merge(&hub).unwrap();
+                replica
+            })
+            .collect();
+        for round in 0..40 {
+            for replica in &mut replicas {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let roll = (seed >> 33) as usize;
+                if round % 3 == 2 && replica.len() > 3 {
+                    replica
+                        .delete(roll % (replica.len() - 3), 1 + roll % 3)
+                        .unwrap();
+                } else {
+                    replica.insert(roll % (replica.len() + 1), "xy").unwrap();
+                }
+            }
+        }
+
+        let mut left = hub.clone();
+        let mut right = FugueText::new("server".to_string());
+        right.merge(&hub).unwrap();
+        for (i, replica) in replicas.iter().enumerate() {
+            if i % 2 == 0 {
+                left.merge(replica).unwrap();
+            }
+            if i % 3 != 0 {
+                right.merge(replica).unwrap();
+            }
+        }
+        (left, right)
+    }
+
+    #[test]
+    fn test_parallel_merge_is_bit_identical() {
+        let (left, right) = divergent_pair();
+
+        for (a, b) in [(&left, &right), (&right, &left)] {
+            let mut sequential = a.clone();
+            sequential.merge(b).unwrap();
+            let mut parallel = a.clone();
+            parallel.merge_parallel(b).unwrap();
+
+            assert_eq!(
+                serde_json::to_vec(&parallel).unwrap(),
+                serde_json::to_vec(&sequential).unwrap()
+            );
+            assert_eq!(parallel.to_string(), sequential.to_string());
+            assert_eq!(parallel.clock(), sequential.clock());
+        }
+    }
+}
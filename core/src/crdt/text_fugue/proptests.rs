@@ -0,0 +1,324 @@
+//! Property tests: random edit scripts across replicas
+//!
+//! A script runs inserts, deletes and syncs on 2-4 replicas, each sync a
+//! whole-state `merge` or a `diff_since`/`apply_delta` round, at random
+//! points. Properties checked:
+//!
+//! - convergence: once every replica has synced with every other, all
+//!   hold the same text and the same structure (`structure_hash`)
+//! - idempotence: merging a replica a second time changes nothing
+//! - commutativity: merging `b` into `a` and `a` into `b` agree
+//! - non-interleaving: words typed concurrently at one spot, forwards or
+//!   backwards, come out whole
+//!
+//! Steps name replicas and positions with plain numbers, taken modulo the
+//! replica count and text length when the script runs, so every script is
+//! valid and proptest shrinks a failure to a short script of small
+//! numbers, printed one step per line.
+//!
+//! Each property runs 64 cases; the `long-proptests` feature runs 2048.
+
+use super::rechunk::FugueTextOptions;
+use super::text::FugueText;
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use std::fmt;
+
+const CASES: u32 = if cfg!(feature = "long-proptests") {
+    2_048
+} else {
+    64
+};
+
+/// Longest script generated
+const MAX_STEPS: usize = 24;
+
+/// One step of a script; replicas and positions wrap around
+#[derive(Clone)]
+enum Step {
+    Insert {
+        replica: usize,
+        position: usize,
+        text: String,
+    },
+    Delete {
+        replica: usize,
+        position: usize,
+        len: usize,
+    },
+    /// `to` merges the state of `from`
+    Merge { from: usize, to: usize },
+    /// `to` applies what `from` has and it lacks, as a delta
+    Delta { from: usize, to: usize },
+}
+
+impl fmt::Debug for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Insert {
+                replica,
+                position,
+                text,
+            } => write!(f, "r{}.insert({}, {:?})", replica, position, text),
+            Step::Delete {
+                replica,
+                position,
+                len,
+            } => write!(f, "r{}.delete({}, {})", replica, position, len),
+            Step::Merge { from, to } => write!(f, "r{}.merge(&r{})", to, from),
+            Step::Delta { from, to } => write!(f, "r{}.apply_delta(&r{}.diff)", to, from),
+        }
+    }
+}
+
+/// A replayable sequence of steps on `replicas` replicas
+#[derive(Clone)]
+struct Script {
+    replicas: usize,
+    steps: Vec<Step>,
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Script on {} replicas [", self.replicas)?;
+        for step in &self.steps {
+            writeln!(f, "    {:?}", step)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Low enough that re-chunking joins blocks between steps
+fn options() -> FugueTextOptions {
+    FugueTextOptions {
+        max_blocks_per_char: 1,
+        min_blocks: 8,
+        rechunk_budget: 4,
+    }
+}
+
+fn sync(replicas: &mut [FugueText], from: usize, to: usize, by_delta: bool) {
+    if from == to {
+        return;
+    }
+    let source = replicas[from].clone();
+    let target = &mut replicas[to];
+    match by_delta {
+        true => {
+            let delta = source.diff_since(&target.state_vector());
+            target.apply_delta(&delta).unwrap();
+        }
+        false => target.merge(&source).unwrap(),
+    }
+}
+
+/// Two rounds of all-pairs merges reach every replica with everything
+fn sync_all(replicas: &mut [FugueText]) {
+    for _ in 0..2 {
+        for to in 0..replicas.len() {
+            for from in 0..replicas.len() {
+                sync(replicas, from, to, false);
+            }
+        }
+    }
+}
+
+impl Script {
+    fn run(&self) -> Vec<FugueText> {
+        let n = self.replicas;
+        let mut replicas: Vec<FugueText> = (0..n)
+            .map(|i| FugueText::with_options(format!("r{}", i), options()))
+            .collect();
+        for step in &self.steps {
+            match step {
+                Step::Insert {
+                    replica,
+                    position,
+                    text,
+                } => {
+                    let replica = &mut replicas[replica % n];
+                    let position = position % (replica.len() + 1);
+                    replica.insert(position, text).unwrap();
+                }
+                Step::Delete {
+                    replica,
+                    position,
+                    len,
+                } => {
+                    let replica = &mut replicas[replica % n];
+                    if !replica.is_empty() {
+                        let position = position % replica.len();
+                        let len = (*len).min(replica.len() - position);
+                        replica.delete(position, len).unwrap();
+                    }
+                }
+                Step::Merge { from, to } => sync(&mut replicas, from % n, to % n, false),
+                Step::Delta { from, to } => sync(&mut replicas, from % n, to % n, true),
+            }
+        }
+        replicas
+    }
+}
+
+/// What converged replicas must agree on
+fn state(text: &FugueText) -> (String, u64) {
+    (text.to_string(), text.structure_hash())
+}
+
+fn merged(a: &FugueText, b: &FugueText) -> FugueText {
+    let mut merged = a.clone();
+    merged.merge(b).unwrap();
+    merged
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        3 => (0..4usize, 0..16usize, "[abé👋]{1,3}").prop_map(|(replica, position, text)| {
+            Step::Insert { replica, position, text }
+        }),
+        2 => (0..4usize, 0..16usize, 1..4usize).prop_map(|(replica, position, len)| {
+            Step::Delete { replica, position, len }
+        }),
+        1 => (0..4usize, 0..4usize).prop_map(|(from, to)| Step::Merge { from, to }),
+        1 => (0..4usize, 0..4usize).prop_map(|(from, to)| Step::Delta { from, to }),
+    ]
+}
+
+fn script() -> impl Strategy<Value = Script> {
+    (2..=4usize, prop::collection::vec(step(), 0..=MAX_STEPS))
+        .prop_map(|(replicas, steps)| Script { replicas, steps })
+}
+
+fn converges(script: &Script) -> Result<(), TestCaseError> {
+    let mut replicas = script.run();
+    sync_all(&mut replicas);
+    for replica in &replicas[1..] {
+        prop_assert_eq!(state(replica), state(&replicas[0]));
+    }
+    Ok(())
+}
+
+fn merge_is_idempotent(script: &Script) -> Result<(), TestCaseError> {
+    let replicas = script.run();
+    for a in &replicas {
+        prop_assert_eq!(state(&merged(a, a)), state(a));
+        for b in &replicas {
+            let once = merged(a, b);
+            prop_assert_eq!(state(&merged(&once, b)), state(&once));
+        }
+    }
+    Ok(())
+}
+
+fn merge_commutes(script: &Script) -> Result<(), TestCaseError> {
+    let replicas = script.run();
+    for (i, a) in replicas.iter().enumerate() {
+        for b in &replicas[i + 1..] {
+            prop_assert_eq!(state(&merged(a, b)), state(&merged(b, a)));
+        }
+    }
+    Ok(())
+}
+
+/// After `script` and a full sync, each replica types a word of its own
+/// digit at `position`, one keystroke at a time, then all sync again
+fn words_stay_whole(
+    script: &Script,
+    position: usize,
+    lens: &[usize],
+    backwards: bool,
+) -> Result<(), TestCaseError> {
+    let mut replicas = script.run();
+    sync_all(&mut replicas);
+    let position = position % (replicas[0].len() + 1);
+    let mut words = Vec::new();
+    for (i, replica) in replicas.iter_mut().enumerate() {
+        let word = i.to_string().repeat(lens[i]);
+        for k in 0..word.len() {
+            match backwards {
+                true => replica.insert(position, &word[word.len() - k - 1..][..1]),
+                false => replica.insert(position + k, &word[k..k + 1]),
+            }
+            .unwrap();
+        }
+        words.push(word);
+    }
+    sync_all(&mut replicas);
+    let text = replicas[0].to_string();
+    for word in &words {
+        prop_assert!(
+            text.contains(word.as_str()),
+            "{:?} split in {:?}",
+            word,
+            text
+        );
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+    #[test]
+    fn prop_replicas_converge(script in script()) {
+        converges(&script)?;
+    }
+
+    #[test]
+    fn prop_merge_is_idempotent(script in script()) {
+        merge_is_idempotent(&script)?;
+    }
+
+    #[test]
+    fn prop_merge_commutes(script in script()) {
+        merge_commutes(&script)?;
+    }
+
+    #[test]
+    fn prop_concurrent_words_do_not_interleave(
+        script in script(),
+        position in 0..16usize,
+        lens in prop::collection::vec(1..6usize, 4),
+        backwards in any::<bool>(),
+    ) {
+        words_stay_whole(&script, position, &lens, backwards)?;
+    }
+}
+
+#[test]
+fn test_fixed_script_holds_every_property() {
+    let insert = |replica, position, text: &str| Step::Insert {
+        replica,
+        position,
+        text: text.to_string(),
+    };
+    let delete = |replica, position, len| Step::Delete {
+        replica,
+        position,
+        len,
+    };
+    let script = Script {
+        replicas: 3,
+        steps: vec![
+            insert(0, 0, "Hello world"),
+            Step::Merge { from: 0, to: 1 },
+            Step::Delta { from: 0, to: 2 },
+            delete(0, 5, 6),
+            insert(1, 6, "there, "),
+            delete(2, 0, 1),
+            insert(2, 0, "J"),
+            Step::Delta { from: 1, to: 0 },
+            Step::Merge { from: 2, to: 1 },
+            insert(0, 3, "👋"),
+        ],
+    };
+    let mut replicas = script.run();
+    sync_all(&mut replicas);
+    assert_eq!(replicas[0].to_string(), "Jel👋lothere, ");
+
+    converges(&script).unwrap();
+    merge_is_idempotent(&script).unwrap();
+    merge_commutes(&script).unwrap();
+    words_stay_whole(&script, 4, &[3, 2, 4], false).unwrap();
+    words_stay_whole(&script, 4, &[3, 2, 4], true).unwrap();
+}
@@ -0,0 +1,530 @@
+//! Re-chunking: joining blocks that editing left split
+//!
+//! Inserts that don't continue the previous one are blocks of their own,
+//! and deletes split blocks further, so some edit patterns (typing at two
+//! cursors in turn, backspacing while typing) leave more blocks than
+//! characters. Two blocks can be joined
+//! back into one when the second is exactly what `split_block_at` would
+//! have cut off the first: same client, consecutive clocks, the second
+//! anchored after the first's last character with the same right origin,
+//! both deleted or both not, and no other block anchored at the seam. The
+//! joined block takes the second one's ID and orders like the pair did.
+//! Joined tombstones keep the earlier deletion stamp, the one replicas
+//! settle on anyway once they exchange both.
+//!
+//! Typing appends to the block the previous keystroke went into right
+//! away, when the two are joinable: a new block whose clocks follow on
+//! from its left origin's can't have anything else anchored at its seam,
+//! since a block anchored there would have moved the clock on.
+//!
+//! Once a text holds more blocks than its [`FugueTextOptions`] allow, every
+//! local edit does a slice of a re-chunking pass: it indexes the origins
+//! of a bounded number of blocks, then looks for pairs among them, then
+//! joins them, so no single edit pays for more than
+//! [`FugueTextOptions::rechunk_budget`] blocks. Merges and applied deltas
+//! start the pass over, since the blocks they bring haven't been indexed.
+
+use super::block::FugueBlock;
+use super::node::NodeId;
+use super::text::FugueText;
+use crate::memory::HeapSize;
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use std::ops::Bound;
+use std::sync::Arc;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Tuning for a `FugueText`
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::crdt::text_fugue::{FugueText, FugueTextOptions};
+///
+/// let options = FugueTextOptions {
+///     max_blocks_per_char: 4,
+///     ..FugueTextOptions::default()
+/// };
+/// let text = FugueText::with_options("client1".to_string(), options);
+/// assert_eq!(text.options().max_blocks_per_char, 4);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FugueTextOptions {
+    /// Re-chunk once there are more than this many blocks (tombstones
+    /// included) per visible character; 0 never re-chunks
+    pub max_blocks_per_char: usize,
+
+    /// Leave texts with at most this many blocks alone, however short
+    pub min_blocks: usize,
+
+    /// Blocks one edit may index, check or join on top of its own work
+    pub rechunk_budget: usize,
+}
+
+impl Default for FugueTextOptions {
+    fn default() -> Self {
+        Self {
+            max_blocks_per_char: 2,
+            min_blocks: 1024,
+            rechunk_budget: 64,
+        }
+    }
+}
+
+/// Re-chunking state of a text: the pass in progress and what passes did
+#[derive(Debug, Clone, Default)]
+pub(super) struct Rechunk {
+    pass: Option<Pass>,
+    /// Passes run to the end
+    passes: u64,
+    /// Blocks joined into their right neighbour
+    joined: u64,
+}
+
+impl Rechunk {
+    /// Drop the pass in progress, if any
+    pub(super) fn restart(&mut self) {
+        self.pass = None;
+    }
+
+    /// Passes run to the end
+    pub(super) fn passes(&self) -> u64 {
+        self.passes
+    }
+
+    /// Blocks joined into their right neighbour
+    pub(super) fn joined(&self) -> u64 {
+        self.joined
+    }
+
+    /// Count the origins of a block added while a pass runs
+    pub(super) fn note_origins(&mut self, left: Option<&NodeId>, right: Option<&NodeId>) {
+        if let Some(pass) = &mut self.pass {
+            pass.index_origins(left, right);
+        }
+    }
+}
+
+impl HeapSize for Rechunk {
+    fn heap_size(&self) -> usize {
+        let Some(pass) = &self.pass else {
+            return 0;
+        };
+        pass.left_refs.heap_size()
+            + pass.right_refs.heap_size()
+            + pass.starts.heap_size()
+            + pass.plan.heap_size()
+            + size_of::<Pass>()
+    }
+}
+
+/// One pass over the blocks, in `NodeId` order
+#[derive(Debug, Clone, Default)]
+struct Pass {
+    phase: Phase,
+    /// How many blocks name each character as left origin
+    left_refs: HashMap<NodeId, usize>,
+    /// Characters named as a right origin
+    right_refs: HashSet<NodeId>,
+    /// Block IDs by the ID of their first character
+    starts: HashMap<NodeId, NodeId>,
+    /// Pairs to join, left block then right block
+    plan: Vec<(NodeId, NodeId)>,
+}
+
+#[derive(Debug, Clone)]
+enum Phase {
+    /// Indexing origins, resuming after the given block
+    Index(Option<NodeId>),
+    /// Looking for pairs, resuming after the given block
+    Plan(Option<NodeId>),
+    /// Joining pairs, from the given one in the plan
+    Join(usize),
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Phase::Index(None)
+    }
+}
+
+impl Pass {
+    fn index_origins(&mut self, left: Option<&NodeId>, right: Option<&NodeId>) {
+        if let Some(left) = left {
+            *self.left_refs.entry(left.clone()).or_default() += 1;
+        }
+        if let Some(right) = right {
+            self.right_refs.insert(right.clone());
+        }
+    }
+
+    /// Whether only `right` is anchored at the seam between the blocks
+    fn seam_is_free(&self, left_id: &NodeId, right_first: &NodeId) -> bool {
+        self.left_refs.get(left_id).copied().unwrap_or(0) <= 1
+            && !self.right_refs.contains(right_first)
+    }
+}
+
+/// ID of a block's first character
+fn first_char(id: &NodeId, block: &FugueBlock) -> NodeId {
+    let len = block.len() as u64;
+    NodeId::new(
+        id.client_id.clone(),
+        id.clock.saturating_sub(len.saturating_sub(1)),
+        0,
+    )
+}
+
+/// Whether `right` can be joined onto the end of `left`
+fn joinable(left_id: &NodeId, left: &FugueBlock, right_id: &NodeId, right: &FugueBlock) -> bool {
+    let right_len = right.len() as u64;
+    left_id.client_id == right_id.client_id
+        && !left.is_empty()
+        && right_len > 0
+        && right_id.clock == left_id.clock + right_len
+        && right.left_origin.as_ref() == Some(left_id)
+        && right.right_origin == left.right_origin
+        && right.is_deleted() == left.is_deleted()
+}
+
+impl FugueText {
+    /// Create an empty text tuned by `options`
+    pub fn with_options(client_id: String, options: FugueTextOptions) -> Self {
+        let mut text = Self::new(client_id);
+        text.options = options;
+        text
+    }
+
+    /// How this text is tuned
+    pub fn options(&self) -> FugueTextOptions {
+        self.options
+    }
+
+    /// Change how this text is tuned; takes effect from the next edit
+    pub fn set_options(&mut self, options: FugueTextOptions) {
+        self.options = options;
+    }
+
+    /// Whether the text holds more blocks than its options allow
+    fn over_block_bound(&self) -> bool {
+        let options = &self.options;
+        options.max_blocks_per_char > 0
+            && self.blocks.len()
+                > options
+                    .min_blocks
+                    .max(options.max_blocks_per_char.saturating_mul(self.len()))
+    }
+
+    /// Do one edit's share of re-chunking: start a pass if the text is
+    /// over its bound, and move the pass on by at most the budget
+    pub(super) fn rechunk_step(&mut self) {
+        let Some(mut pass) = self.rechunk.pass.take() else {
+            if !self.over_block_bound() {
+                return;
+            }
+            self.rechunk.pass = Some(Pass::default());
+            return self.rechunk_step();
+        };
+
+        let mut budget = self.options.rechunk_budget.max(1);
+        let mut joined = 0;
+        while budget > 0 {
+            match pass.phase.clone() {
+                Phase::Index(after) => {
+                    let mut last = None;
+                    for (id, block) in self.blocks_after(after.as_ref()).take(budget) {
+                        pass.index_origins(block.left_origin.as_ref(), block.right_origin.as_ref());
+                        pass.starts.insert(first_char(id, block), id.clone());
+                        last = Some(id.clone());
+                        budget -= 1;
+                    }
+                    pass.phase = match last {
+                        Some(id) => Phase::Index(Some(id)),
+                        None => Phase::Plan(None),
+                    };
+                }
+                Phase::Plan(after) => {
+                    let mut last = None;
+                    for (left_id, left) in self.blocks_after(after.as_ref()).take(budget) {
+                        last = Some(left_id.clone());
+                        budget -= 1;
+                        let next = NodeId::new(left_id.client_id.clone(), left_id.clock + 1, 0);
+                        let Some(right_id) = pass.starts.get(&next) else {
+                            continue;
+                        };
+                        let Some(right) = self.blocks.get(right_id) else {
+                            continue;
+                        };
+                        if joinable(left_id, left, right_id, right)
+                            && pass.seam_is_free(left_id, &next)
+                        {
+                            pass.plan.push((left_id.clone(), right_id.clone()));
+                        }
+                    }
+                    pass.phase = match last {
+                        Some(id) => Phase::Plan(Some(id)),
+                        None => Phase::Join(0),
+                    };
+                }
+                Phase::Join(next) => {
+                    let end = pass.plan.len().min(next + budget);
+                    for index in next..end {
+                        let (left_id, right_id) = &pass.plan[index];
+                        if self.join_blocks(&pass, left_id, right_id) {
+                            joined += 1;
+                        }
+                    }
+                    budget -= end - next;
+                    if end == pass.plan.len() {
+                        self.rechunk.passes += 1;
+                        self.rechunk.joined += joined;
+                        if joined > 0 {
+                            self.cache_valid = false;
+                        }
+                        return;
+                    }
+                    pass.phase = Phase::Join(end);
+                }
+            }
+        }
+
+        self.rechunk.joined += joined;
+        if joined > 0 {
+            self.cache_valid = false;
+        }
+        self.rechunk.pass = Some(pass);
+    }
+
+    /// Join a new local `block` onto the block ending at its left origin
+    /// if they are joinable; returns the block if it wasn't joined
+    pub(super) fn extend_typed_block(&mut self, block: FugueBlock) -> Option<FugueBlock> {
+        let Some(left_id) = block.left_origin.clone() else {
+            return Some(block);
+        };
+        let Some(left) = self.blocks.get(&left_id) else {
+            return Some(block);
+        };
+        if !joinable(&left_id, left, &block.id, &block) {
+            return Some(block);
+        }
+        let mut text = String::with_capacity(left.byte_len() + block.byte_len());
+        text.push_str(&left.text);
+        text.push_str(&block.text);
+        if text.graphemes(true).count() != left.len() + block.len() {
+            return Some(block);
+        }
+
+        let blocks = self.blocks_mut();
+        let left = blocks.remove(&left_id).expect("checked above");
+        let mut joined = block;
+        joined.text = text.into();
+        joined.left_origin = left.left_origin.clone();
+        blocks.insert(joined.id.clone(), Arc::new(joined));
+        None
+    }
+
+    /// Blocks after `after` in `NodeId` order (all of them for None)
+    fn blocks_after<'a>(
+        &'a self,
+        after: Option<&NodeId>,
+    ) -> impl Iterator<Item = (&'a NodeId, &'a FugueBlock)> + 'a {
+        let start = match after {
+            Some(id) => Bound::Excluded(id.clone()),
+            None => Bound::Unbounded,
+        };
+        self.blocks
+            .range((start, Bound::Unbounded))
+            .map(|(id, block)| (id, block.as_ref()))
+    }
+
+    /// Join `right_id` onto the end of `left_id`, if edits since the pair
+    /// was planned haven't made that unsafe
+    fn join_blocks(&mut self, pass: &Pass, left_id: &NodeId, right_id: &NodeId) -> bool {
+        let (Some(left), Some(right)) = (self.blocks.get(left_id), self.blocks.get(right_id))
+        else {
+            return false;
+        };
+        let next = NodeId::new(left_id.client_id.clone(), left_id.clock + 1, 0);
+        if !joinable(left_id, left, right_id, right) || !pass.seam_is_free(left_id, &next) {
+            return false;
+        }
+        let mut text = String::with_capacity(left.byte_len() + right.byte_len());
+        text.push_str(&left.text);
+        text.push_str(&right.text);
+        // A combining mark at the seam would fuse two characters into one
+        if text.graphemes(true).count() != left.len() + right.len() {
+            return false;
+        }
+
+        let blocks = self.blocks_mut();
+        let Some(left) = blocks.remove(left_id) else {
+            return false;
+        };
+        let Some(right) = blocks.get_mut(right_id).map(Arc::make_mut) else {
+            return false;
+        };
+        right.text = text.into();
+        right.merge_deletion(&left);
+        right.left_origin = left.left_origin.clone();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(min_blocks: usize) -> FugueTextOptions {
+        FugueTextOptions {
+            max_blocks_per_char: 1,
+            min_blocks,
+            rechunk_budget: 4,
+        }
+    }
+
+    /// Type `s` one character at a time at `position`
+    fn type_at(text: &mut FugueText, position: usize, s: &str) {
+        for (i, c) in s.chars().enumerate() {
+            text.insert(position + i, &c.to_string()).unwrap();
+        }
+    }
+
+    /// Split every block into one per character, as typing left text
+    /// before it extended blocks (saved texts still hold such runs)
+    fn split_into_chars(text: &mut FugueText) {
+        let ids: Vec<_> = text.blocks.keys().cloned().collect();
+        for id in ids {
+            let first = first_char(&id, &text.blocks[&id]).clock;
+            for clock in first..id.clock {
+                let left = NodeId::new(id.client_id.clone(), clock, 0);
+                text.split_at_origins(Some(&left), None);
+            }
+        }
+        text.cache_valid = false;
+    }
+
+    /// Run a whole pass, whatever the bound says
+    fn run_pass(text: &mut FugueText) {
+        text.rechunk.pass = Some(Pass::default());
+        while text.rechunk.pass.is_some() {
+            text.rechunk_step();
+        }
+    }
+
+    #[test]
+    fn test_typed_runs_are_joined() {
+        let mut text = FugueText::with_options("client1".to_string(), options(4));
+        type_at(&mut text, 0, "hello world");
+        split_into_chars(&mut text);
+        assert_eq!(text.blocks.len(), 11);
+        // Over the bound: the delete starts a pass
+        text.delete(5, 6).unwrap();
+        assert!(text.rechunk.pass.is_some());
+        while text.rechunk.pass.is_some() {
+            text.rechunk_step();
+        }
+
+        assert_eq!(text.blocks.len(), 2);
+        assert_eq!(text.to_string(), "hello");
+        let usage = text.memory_usage();
+        assert_eq!(usage.counter("rechunk_passes"), 1);
+        assert_eq!(usage.counter("blocks_joined"), 9);
+        assert_eq!(usage.counter("block_count"), 2);
+
+        // Positions still map to the same characters
+        let id = text.get_node_id_at_position(4).unwrap();
+        assert_eq!(id, NodeId::new("client1".to_string(), 5, 0));
+        assert_eq!(text.get_position_of_node_id(&id), Some(4));
+        text.insert(2, "y").unwrap();
+        assert_eq!(text.to_string(), "heyllo");
+    }
+
+    #[test]
+    fn test_seams_other_blocks_are_anchored_at_stay() {
+        let mut text = FugueText::new("client1".to_string());
+        type_at(&mut text, 0, "ab");
+        // Inserted between "a" and "b": both seams are origins
+        text.insert(1, "x").unwrap();
+        run_pass(&mut text);
+        assert_eq!(text.to_string(), "axb");
+        assert_eq!(text.blocks.len(), 3);
+    }
+
+    #[test]
+    fn test_replicas_with_part_of_a_joined_run_get_the_rest() {
+        let mut writer = FugueText::new("writer".to_string());
+        type_at(&mut writer, 0, "ab");
+        split_into_chars(&mut writer);
+        let mut by_delta = FugueText::new("reader1".to_string());
+        by_delta.merge(&writer).unwrap();
+        let mut by_merge = by_delta.clone();
+
+        type_at(&mut writer, 2, "cd");
+        run_pass(&mut writer);
+        assert_eq!(writer.blocks.len(), 1);
+
+        by_delta
+            .apply_delta(&writer.diff_since(&by_delta.state_vector()))
+            .unwrap();
+        by_merge.merge(&writer).unwrap();
+        for reader in [&by_delta, &by_merge] {
+            assert_eq!(reader.to_string(), "abcd");
+            assert_eq!(reader.blocks.len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_combining_marks_are_not_joined() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "e").unwrap();
+        text.insert(1, "\u{301}").unwrap();
+        run_pass(&mut text);
+        assert_eq!(text.blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_typing_extends_one_block() {
+        let mut text = FugueText::new("client1".to_string());
+        let typed = "The quick brown fox jumps over the lazy dog.\n".repeat(23);
+        type_at(&mut text, 0, &typed[..1_000]);
+        assert_eq!(text.to_string(), &typed[..1_000]);
+        assert_eq!(text.blocks.len(), 1);
+
+        // Typing in the middle extends a block of its own
+        type_at(&mut text, 500, "inserted");
+        assert_eq!(text.blocks.len(), 3);
+        assert_eq!(text.slice(500..508).unwrap(), "inserted");
+    }
+
+    #[test]
+    fn test_replicas_that_saw_part_of_a_typed_run_converge() {
+        let mut writer = FugueText::new("writer".to_string());
+        let mut by_merge = FugueText::new("reader".to_string());
+        type_at(&mut writer, 0, "Hello");
+        by_merge.merge(&writer).unwrap();
+        let mut by_delta = by_merge.clone();
+
+        // The readers edit inside the run while the writer extends it
+        type_at(&mut writer, 5, " world");
+        by_merge.insert(2, "_").unwrap();
+        by_delta.delete(0, 1).unwrap();
+        assert_eq!(writer.blocks.len(), 1);
+
+        let delta = writer.diff_since(&by_delta.state_vector());
+        assert_eq!(delta.blocks.len(), 1);
+        assert_eq!(delta.blocks[0].text.to_string(), " world");
+        by_delta.apply_delta(&delta).unwrap();
+        by_merge.merge(&writer).unwrap();
+        assert_eq!(by_merge.to_string(), "He_llo world");
+        assert_eq!(by_delta.to_string(), "ello world");
+
+        for reader in [&by_merge, &by_delta] {
+            writer.merge(reader).unwrap();
+        }
+        by_merge.merge(&by_delta).unwrap();
+        by_delta.merge(&by_merge).unwrap();
+        assert_eq!(writer.to_string(), "e_llo world");
+        assert_eq!(by_merge.to_string(), writer.to_string());
+        assert_eq!(by_delta.to_string(), writer.to_string());
+    }
+}
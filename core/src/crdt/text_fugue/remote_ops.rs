@@ -0,0 +1,531 @@
+//! Operation-based remote apply: one insert or delete at a time
+//!
+//! `merge` and `apply_delta` rebuild the rope from the blocks, which costs
+//! O(n) even for a single remote keystroke. A client that receives edits
+//! one at a time, over a WebSocket say, can apply each as it arrives
+//! instead: an inserted block is placed by the Fugue ordering and spliced
+//! into the rope at its char offset, and deleted characters are cut out of
+//! it.
+//!
+//! A block that doesn't fit that way, because we hold some of its
+//! characters already (split or re-chunked differently), it is a
+//! tombstone, or blocks that arrived before it name it as an origin, goes
+//! through delta integration instead. Any block `diff_since` could send
+//! is accepted, so a client can fall back to delta sync at any time.
+
+use super::block::FugueBlock;
+use super::delta::{TextDelta, TextEvent};
+use super::history::PositionOp;
+use super::node::NodeId;
+use super::text::{FugueText, RemoteBlock, TextError};
+use std::sync::Arc;
+
+impl FugueText {
+    /// The block with this ID, such as the one `insert` just returned, to
+    /// send to other replicas as an operation
+    ///
+    /// Re-chunking may already have joined earlier characters onto the
+    /// front of it; `apply_remote_insert` takes the joined block too.
+    pub fn block(&self, id: &NodeId) -> Option<&FugueBlock> {
+        self.blocks.get(id).map(Arc::as_ref)
+    }
+
+    /// Integrate one block inserted on another replica
+    ///
+    /// Unlike `apply_delta`, the rope isn't rebuilt: the block goes in at
+    /// the position the Fugue ordering gives it. The result is the same as
+    /// merging the sending replica once it has everything that replica had
+    /// when it made the block.
+    ///
+    /// # Returns
+    ///
+    /// The changes to the visible text
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::InvalidDelta` if the block holds more characters
+    /// than its end clock allows, `TextError::ReplicaConflict` if it
+    /// reuses one of our character IDs for different text, and
+    /// `TextError::LimitExceeded` if it would take the text past its limits
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut alice = FugueText::new("alice".to_string());
+    /// let mut bob = FugueText::new("bob".to_string());
+    ///
+    /// let id = alice.insert(0, "Hello").unwrap();
+    /// bob.apply_remote_insert(alice.block(&id).unwrap().clone()).unwrap();
+    ///
+    /// assert_eq!(bob.to_string(), "Hello");
+    /// ```
+    pub fn apply_remote_insert(&mut self, block: FugueBlock) -> Result<Vec<TextEvent>, TextError> {
+        let length = block.len() as u64;
+        if length > block.id.clock {
+            return Err(TextError::InvalidDelta(format!(
+                "block {} has {} characters but ends at clock {}",
+                block.id, length, block.id.clock
+            )));
+        }
+        self.check_replica_conflicts(std::iter::once(&block))?;
+        self.check_remote_limits(std::iter::once((&block.id, &block)))?;
+        if !self.splices_in(&block) {
+            return self.apply_delta(&TextDelta {
+                blocks: vec![block],
+                deleted: Vec::new(),
+                clock: 0,
+                annotations: Vec::new(),
+                marks: Vec::new(),
+            });
+        }
+
+        self.touch();
+        let id = block.id.clone();
+        self.rechunk
+            .note_origins(block.left_origin.as_ref(), block.right_origin.as_ref());
+        self.store_remote_block(block);
+        self.split_at_new_block_origins(std::slice::from_ref(&id));
+
+        // Place it among the blocks in document order
+        self.rebuild_position_cache();
+        self.cache_valid = true;
+        let block = &self.blocks[&id];
+        let position = self.cached_position(&id).unwrap_or(0);
+        let length = block.len();
+        let text = block.text.to_string();
+
+        let char_pos = self.units.char_offset(position);
+        self.rope.insert(char_pos, &text);
+        self.units.insert(position, &text);
+        self.update_cache_after_insert(position, length, &id);
+
+        self.clock.update(id.clock);
+        self.record_remote_ops(vec![PositionOp::Insert { position, length }]);
+        let events = vec![TextEvent::Insert { position, text }];
+        self.notifier.extend(events.iter().cloned());
+        self.rechunk_step();
+        Ok(events)
+    }
+
+    /// Delete characters on behalf of another replica
+    ///
+    /// `ids` name characters one clock each, the way
+    /// `get_node_id_at_position` does; the sender looks them up before
+    /// deleting. Characters already deleted here are skipped. The rope
+    /// isn't rebuilt.
+    ///
+    /// # Returns
+    ///
+    /// The changes to the visible text
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::InvalidDelta` if a character hasn't been
+    /// inserted here yet; nothing is changed then. Apply operations in the
+    /// order they were made.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut alice = FugueText::new("alice".to_string());
+    /// alice.insert(0, "Hello").unwrap();
+    /// let mut bob = FugueText::new("bob".to_string());
+    /// bob.merge(&alice).unwrap();
+    ///
+    /// let ids: Vec<_> = (1..3)
+    ///     .map(|position| alice.get_node_id_at_position(position).unwrap())
+    ///     .collect();
+    /// alice.delete(1, 2).unwrap();
+    /// bob.apply_remote_delete(&ids).unwrap();
+    ///
+    /// assert_eq!(bob.to_string(), "Hlo");
+    /// ```
+    pub fn apply_remote_delete(&mut self, ids: &[NodeId]) -> Result<Vec<TextEvent>, TextError> {
+        self.delete_remote(ids, None)
+    }
+
+    /// Like [`apply_remote_delete`](Self::apply_remote_delete), for a
+    /// deletion the sender stamped `stamp`: its client ID and its
+    /// [`clock`](Self::clock) right after the delete
+    ///
+    /// The tombstones keep the stamp (see [`FugueBlock::deleted_at`]), and
+    /// our clock moves past it, so our next operation is ordered after the
+    /// deletion just as it would be after merging the sender's state.
+    ///
+    /// # Errors
+    ///
+    /// As for `apply_remote_delete`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::{FugueText, NodeId};
+    ///
+    /// let mut alice = FugueText::new("alice".to_string());
+    /// alice.insert(0, "Hello").unwrap();
+    /// let mut bob = FugueText::new("bob".to_string());
+    /// bob.merge(&alice).unwrap();
+    ///
+    /// let ids = vec![alice.get_node_id_at_position(0).unwrap()];
+    /// alice.delete(0, 1).unwrap();
+    /// let stamp = NodeId::new(alice.client_id(), alice.clock(), 0);
+    /// bob.apply_remote_delete_at(&ids, &stamp).unwrap();
+    ///
+    /// assert_eq!(bob.to_string(), "ello");
+    /// assert_eq!(bob.clock(), 6);
+    /// ```
+    pub fn apply_remote_delete_at(
+        &mut self,
+        ids: &[NodeId],
+        stamp: &NodeId,
+    ) -> Result<Vec<TextEvent>, TextError> {
+        let events = self.delete_remote(ids, Some(stamp))?;
+        self.clock.update(stamp.clock);
+        Ok(events)
+    }
+
+    fn delete_remote(
+        &mut self,
+        ids: &[NodeId],
+        stamp: Option<&NodeId>,
+    ) -> Result<Vec<TextEvent>, TextError> {
+        // 1. Coalesce into clock ranges per client
+        let mut clocks: Vec<(&str, u64)> = ids
+            .iter()
+            .map(|id| (id.client_id.as_str(), id.clock))
+            .collect();
+        clocks.sort_unstable();
+        clocks.dedup();
+        let mut ranges: Vec<(&str, u64, u64)> = Vec::new();
+        for (client, clock) in clocks {
+            match ranges.last_mut() {
+                Some(last) if last.0 == client && last.2 + 1 == clock => last.2 = clock,
+                _ => ranges.push((client, clock, clock)),
+            }
+        }
+
+        // 2. Validate: every character is one we have, or had and collected
+        if let Some(id) = ids.iter().find(|id| {
+            id.clock > self.collected.get(&id.client_id) && self.find_block_for_nodeid(id).is_none()
+        }) {
+            return Err(TextError::InvalidDelta(format!(
+                "deleted character {} was never inserted here",
+                id
+            )));
+        }
+        self.touch();
+
+        // 3. Visible runs of those characters, in document order
+        if !self.cache_valid {
+            self.rebuild_position_cache();
+            self.cache_valid = true;
+        }
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for id in self.cached_blocks.iter() {
+            let block = &self.blocks[id];
+            let block_len = block.len() as u64;
+            if block_len == 0 {
+                continue;
+            }
+            let block_start = id.clock + 1 - block_len;
+            let block_pos = self.cached_position(id).unwrap_or(0);
+            let first = ranges.partition_point(|&(client, _, end)| {
+                (client, end) < (id.client_id.as_str(), block_start)
+            });
+            for &(_, start, end) in ranges[first..]
+                .iter()
+                .take_while(|&&(client, start, _)| client == id.client_id && start <= id.clock)
+            {
+                let position = block_pos + (start.max(block_start) - block_start) as usize;
+                let length = (end.min(id.clock) + 1 - start.max(block_start)) as usize;
+                match runs.last_mut() {
+                    Some(last) if last.0 + last.1 == position => last.1 += length,
+                    _ => runs.push((position, length)),
+                }
+            }
+        }
+        if runs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // 4. Tombstone them and cut them out of the rope
+        for &(client, start, end) in &ranges {
+            self.propagate_clock_range_deletion(client, start, end, stamp);
+        }
+        let mut ops = Vec::new();
+        let mut events = Vec::new();
+        let mut removed = 0;
+        for (position, length) in runs {
+            let position = position - removed;
+            let chars = self.units.char_range(position..position + length);
+            self.rope.remove(chars);
+            self.units.remove(position, length);
+            removed += length;
+            ops.push(PositionOp::Delete { position, length });
+            events.push(TextEvent::Delete { position, length });
+        }
+        self.cache_valid = false;
+
+        self.record_remote_ops(ops);
+        self.notifier.extend(events.iter().cloned());
+        self.rechunk_step();
+        Ok(events)
+    }
+
+    /// Whether `block` can be spliced into the rope without moving
+    /// anything already there
+    fn splices_in(&self, block: &FugueBlock) -> bool {
+        let id = &block.id;
+        let length = block.len() as u64;
+        if block.is_deleted() || length == 0 {
+            return false;
+        }
+        let classified = self.classify_remote_block(id, block, |start, end| {
+            self.overlaps_local_clock_range(&id.client_id, start, end)
+        });
+        if classified != RemoteBlock::New {
+            return false;
+        }
+
+        // Blocks that arrived first and hang from it were placed without it
+        let start = id.clock + 1 - length;
+        let names_it = |origin: &Option<NodeId>| {
+            origin.as_ref().is_some_and(|origin| {
+                origin.client_id == id.client_id && (start..=id.clock).contains(&origin.clock)
+            })
+        };
+        !self
+            .blocks
+            .values()
+            .any(|other| names_it(&other.left_origin) || names_it(&other.right_origin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::text_fugue::FugueTextOptions;
+
+    /// xorshift64, so every run does the same edits
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n.max(1) as u64) as usize
+        }
+    }
+
+    /// One edit, as the replica that made it sends it
+    #[derive(Clone)]
+    enum Op {
+        Insert(FugueBlock),
+        Delete(Vec<NodeId>, NodeId),
+    }
+
+    fn insert(text: &mut FugueText, position: usize, s: &str) -> Op {
+        let id = text.insert(position, s).unwrap();
+        Op::Insert(text.block(&id).unwrap().clone())
+    }
+
+    fn delete(text: &mut FugueText, position: usize, length: usize) -> Op {
+        let ids = (position..position + length)
+            .map(|position| text.get_node_id_at_position(position).unwrap())
+            .collect();
+        text.delete(position, length).unwrap();
+        Op::Delete(ids, NodeId::new(text.client_id(), text.clock(), 0))
+    }
+
+    fn apply(text: &mut FugueText, op: Op) -> Vec<TextEvent> {
+        match op {
+            Op::Insert(block) => text.apply_remote_insert(block).unwrap(),
+            Op::Delete(ids, stamp) => text.apply_remote_delete_at(&ids, &stamp).unwrap(),
+        }
+    }
+
+    /// Insert or delete somewhere at random
+    fn edit(text: &mut FugueText, rng: &mut Rng) -> Op {
+        let len = text.len();
+        if len > 8 && rng.below(3) == 0 {
+            let position = rng.below(len - 1);
+            let length = 1 + rng.below(3.min(len - position));
+            delete(text, position, length)
+        } else {
+            let s = ["a", "bc", "def", "é", "👋🏽"][rng.below(5)];
+            insert(text, rng.below(len + 1), s)
+        }
+    }
+
+    /// The rope and units match what rebuilding them from the blocks gives
+    fn assert_consistent(text: &FugueText) {
+        let mut rebuilt = text.clone();
+        rebuilt.rebuild_rope();
+        assert_eq!(text.to_string(), rebuilt.to_string());
+        let offsets = |text: &FugueText| {
+            (0..=text.len())
+                .map(|unit| text.units.char_offset(unit))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(offsets(text), offsets(&rebuilt));
+    }
+
+    #[test]
+    fn test_remote_ops_match_merge() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        // Bob's twin gets Alice's edits by state-based merge instead
+        let mut twin = FugueText::new("bob".to_string());
+
+        let ops = [
+            insert(&mut alice, 0, "Hello World"),
+            insert(&mut alice, 5, ","),
+            delete(&mut alice, 7, 5),
+            insert(&mut alice, 7, "there"),
+        ];
+        for (i, op) in ops.into_iter().enumerate() {
+            bob.insert(bob.len(), &i.to_string()).unwrap();
+            twin.insert(twin.len(), &i.to_string()).unwrap();
+            apply(&mut bob, op);
+            assert_consistent(&bob);
+        }
+        twin.merge(&alice).unwrap();
+
+        assert_eq!(alice.to_string(), "Hello, there");
+        assert_eq!(bob.to_string(), twin.to_string());
+    }
+
+    #[test]
+    fn test_remote_ops_return_events() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+
+        let events = apply(&mut bob, insert(&mut alice, 0, "Hello"));
+        assert_eq!(
+            events,
+            vec![TextEvent::Insert {
+                position: 0,
+                text: "Hello".to_string()
+            }]
+        );
+
+        // Deleted characters need not be adjacent
+        let mut ids = match delete(&mut alice, 0, 1) {
+            Op::Delete(ids, _) => ids,
+            Op::Insert(_) => unreachable!(),
+        };
+        ids.push(alice.get_node_id_at_position(2).unwrap());
+        ids.push(alice.get_node_id_at_position(3).unwrap());
+        alice.delete(2, 2).unwrap();
+        let events = bob.apply_remote_delete(&ids).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                TextEvent::Delete {
+                    position: 0,
+                    length: 1
+                },
+                TextEvent::Delete {
+                    position: 2,
+                    length: 2
+                },
+            ]
+        );
+        assert_eq!(bob.to_string(), "el");
+
+        // Deleting again changes nothing
+        assert!(bob.apply_remote_delete(&ids).unwrap().is_empty());
+        assert_consistent(&bob);
+    }
+
+    #[test]
+    fn test_out_of_order_ops() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+
+        let first = insert(&mut alice, 0, "ac");
+        let second = insert(&mut alice, 1, "b");
+        let gone = delete(&mut alice, 0, 1);
+
+        // A delete for characters not yet inserted is refused
+        assert!(matches!(
+            apply_checked(&mut bob, gone.clone()),
+            Err(TextError::InvalidDelta(_))
+        ));
+        assert!(bob.is_empty());
+
+        // A block arriving after one that hangs from it still lands right
+        apply(&mut bob, second);
+        apply(&mut bob, first.clone());
+        assert_eq!(bob.to_string(), "abc");
+        apply(&mut bob, gone);
+        assert_eq!(bob.to_string(), alice.to_string());
+
+        // As does one we already have
+        apply(&mut bob, first);
+        assert_eq!(bob.to_string(), alice.to_string());
+        assert_consistent(&bob);
+    }
+
+    fn apply_checked(text: &mut FugueText, op: Op) -> Result<Vec<TextEvent>, TextError> {
+        match op {
+            Op::Insert(block) => text.apply_remote_insert(block),
+            Op::Delete(ids, stamp) => text.apply_remote_delete_at(&ids, &stamp),
+        }
+    }
+
+    #[test]
+    fn test_random_interleaving_matches_merge() {
+        const SEEDS: u64 = 4;
+        const ROUNDS: usize = 48;
+
+        for seed in 1..=SEEDS {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            // Low enough that re-chunking joins blocks on both sides between
+            // operations
+            let options = FugueTextOptions {
+                max_blocks_per_char: 1,
+                min_blocks: 8,
+                rechunk_budget: 4,
+            };
+            let mut alice = FugueText::with_options("alice".to_string(), options);
+            let mut bob = FugueText::with_options("bob".to_string(), options);
+            let mut twin = FugueText::with_options("bob".to_string(), options);
+            let (mut to_alice, mut to_bob) = (Vec::new(), Vec::new());
+
+            for round in 0..ROUNDS {
+                to_bob.push(edit(&mut alice, &mut rng));
+
+                // Bob and his twin make the same edits; only Bob gets
+                // Alice's ops, and only now and then
+                let mut twin_rng = Rng(rng.0);
+                to_alice.push(edit(&mut bob, &mut rng));
+                edit(&mut twin, &mut twin_rng);
+                if rng.below(3) == 0 {
+                    for op in to_bob.drain(..) {
+                        apply(&mut bob, op);
+                    }
+                    for op in to_alice.drain(..) {
+                        apply(&mut alice, op);
+                    }
+                    twin.merge(&alice).unwrap();
+                    assert_eq!(
+                        bob.to_string(),
+                        twin.to_string(),
+                        "seed {} round {}",
+                        seed,
+                        round
+                    );
+                    assert_eq!(alice.to_string(), bob.to_string());
+                    assert_consistent(&bob);
+                    assert_consistent(&alice);
+                }
+            }
+        }
+    }
+}
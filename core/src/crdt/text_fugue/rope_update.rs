@@ -0,0 +1,378 @@
+//! Bringing the rope up to date after a merge or delta
+//!
+//! Rebuilding the rope from every block costs O(n) in the length of the
+//! text, even when a single remote character arrived. Visible characters
+//! keep their order through a merge, so comparing the visible runs from
+//! before with the blocks after finds the runs that came or went. Only
+//! those are spliced into or cut out of the rope, and they are also the
+//! position changes the history records.
+//!
+//! If the characters both sides show don't line up, which would take a
+//! character moving, the rope is rebuilt after all.
+
+use super::delta::TextEvent;
+use super::history::{CharRun, PositionOp};
+use super::text::FugueText;
+use std::collections::{BTreeMap, HashMap};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Clock ranges (start to end) per client
+#[derive(Default)]
+struct Ranges<'a>(HashMap<&'a str, BTreeMap<u64, u64>>);
+
+impl<'a> Ranges<'a> {
+    fn add(&mut self, client: &'a str, start: u64, end: u64) {
+        self.0.entry(client).or_default().insert(start, end);
+    }
+
+    /// Cut `start..=end` of `client` into pieces, each wholly inside the
+    /// ranges (`true`) or wholly outside them
+    fn cut(&self, client: &str, start: u64, end: u64, mut piece: impl FnMut(u64, u64, bool)) {
+        let mut next = start;
+        if let Some(ranges) = self.0.get(client) {
+            let first = ranges
+                .range(..=start)
+                .next_back()
+                .map_or(start, |(&first, _)| first);
+            for (&range_start, &range_end) in ranges.range(first..=end) {
+                if range_end < next {
+                    continue;
+                }
+                if range_start > next {
+                    piece(next, range_start - 1, false);
+                }
+                let piece_end = range_end.min(end);
+                piece(range_start.max(next), piece_end, true);
+                next = piece_end + 1;
+            }
+        }
+        if next <= end {
+            piece(next, end, false);
+        }
+    }
+}
+
+/// Part of a visible run, shown before and after the change or on one
+/// side only
+struct Piece<'a> {
+    client: &'a str,
+    start: u64,
+    end: u64,
+    shared: bool,
+    /// Text of a piece only shown after
+    text: &'a str,
+}
+
+impl Piece<'_> {
+    fn len(&self) -> usize {
+        (self.end + 1 - self.start) as usize
+    }
+}
+
+/// One change to the rope, in units
+enum Splice {
+    Insert {
+        position: usize,
+        length: usize,
+        text: String,
+    },
+    Delete {
+        position: usize,
+        length: usize,
+    },
+}
+
+/// Units `from..=to` of `text`
+fn units_of(text: &str, units: usize, from: usize, to: usize) -> &str {
+    if from == 0 && to + 1 == units {
+        return text;
+    }
+    let mut bounds = text
+        .grapheme_indices(true)
+        .map(|(byte, _)| byte)
+        .chain(std::iter::once(text.len()))
+        .skip(from);
+    let start = bounds.next().unwrap_or(text.len());
+    let end = bounds.nth(to - from).unwrap_or(text.len());
+    &text[start..end]
+}
+
+impl FugueText {
+    /// Bring the rope, the units and the position history up to date with
+    /// blocks a merge or delta changed, given `visible_runs` from before
+    ///
+    /// Returns the changes to the visible text, or None if the rope had to
+    /// be rebuilt (diff snapshots for the changes then).
+    pub(super) fn update_rope(&mut self, before: Vec<CharRun>) -> Option<Vec<TextEvent>> {
+        let Some(splices) = self.rope_splices(&before) else {
+            trace_debug!("visible characters moved, rebuilding the rope");
+            trace_record!("rope", "rebuild");
+            self.rebuild_rope();
+            self.record_remote_change(before);
+            return None;
+        };
+        trace_record!("rope", "update");
+
+        let mut ops: Vec<PositionOp> = Vec::new();
+        let mut events = Vec::new();
+        for splice in splices {
+            let (op, event) = match splice {
+                Splice::Insert {
+                    position,
+                    length,
+                    text,
+                } => {
+                    let at = self.units.char_offset(position);
+                    self.rope.insert(at, &text);
+                    self.units.insert(position, &text);
+                    (
+                        PositionOp::Insert { position, length },
+                        TextEvent::Insert { position, text },
+                    )
+                }
+                Splice::Delete { position, length } => {
+                    let chars = self.units.char_range(position..position + length);
+                    self.rope.remove(chars);
+                    self.units.remove(position, length);
+                    (
+                        PositionOp::Delete { position, length },
+                        TextEvent::Delete { position, length },
+                    )
+                }
+            };
+            // Runs of several blocks make one op and one event
+            if !ops.last_mut().is_some_and(|last| last.extend(op)) {
+                ops.push(op);
+                events.push(event);
+                continue;
+            }
+            match (events.last_mut(), event) {
+                (Some(TextEvent::Insert { text, .. }), TextEvent::Insert { text: more, .. }) => {
+                    text.push_str(&more)
+                }
+                (
+                    Some(TextEvent::Delete { length, .. }),
+                    TextEvent::Delete { length: more, .. },
+                ) => *length += more,
+                _ => unreachable!("ops only extend ops of their own kind"),
+            }
+        }
+        self.cache_valid = false;
+
+        self.rechunk.restart();
+        self.record_remote_ops(ops);
+        Some(events)
+    }
+
+    /// The splices turning the text `before` (visible runs) into the text
+    /// of the blocks now, or None if its characters aren't all in the
+    /// same order any more
+    fn rope_splices(&self, before: &[CharRun]) -> Option<Vec<Splice>> {
+        let order = self.get_document_order();
+        let after: Vec<_> = order
+            .iter()
+            .map(|id| (id, &self.blocks[id]))
+            .filter(|(_, block)| !block.is_empty())
+            .map(|(id, block)| (id, block, block.len()))
+            .collect();
+
+        let mut was_visible = Ranges::default();
+        for (client, end, len) in before {
+            was_visible.add(client, end + 1 - *len as u64, *end);
+        }
+        let mut is_visible = Ranges::default();
+        for &(id, _, len) in &after {
+            is_visible.add(&id.client_id, id.clock + 1 - len as u64, id.clock);
+        }
+
+        let mut old = Vec::new();
+        for (client, end, len) in before {
+            is_visible.cut(client, end + 1 - *len as u64, *end, |start, end, shared| {
+                old.push(Piece {
+                    client,
+                    start,
+                    end,
+                    shared,
+                    text: "",
+                })
+            });
+        }
+        let mut new = Vec::new();
+        for &(id, block, len) in &after {
+            let block_start = id.clock + 1 - len as u64;
+            was_visible.cut(
+                &id.client_id,
+                block_start,
+                id.clock,
+                |start, end, shared| {
+                    let text = match shared {
+                        true => "",
+                        false => units_of(
+                            &block.text,
+                            len,
+                            (start - block_start) as usize,
+                            (end - block_start) as usize,
+                        ),
+                    };
+                    new.push(Piece {
+                        client: &id.client_id,
+                        start,
+                        end,
+                        shared,
+                        text,
+                    })
+                },
+            );
+        }
+
+        // Walk both, cutting out what went and splicing in what came
+        let mut splices = Vec::new();
+        let (mut i, mut j, mut position) = (0, 0, 0);
+        loop {
+            match (old.get_mut(i), new.get_mut(j)) {
+                (Some(gone), _) if !gone.shared => {
+                    splices.push(Splice::Delete {
+                        position,
+                        length: gone.len(),
+                    });
+                    i += 1;
+                }
+                (_, Some(came)) if !came.shared => {
+                    splices.push(Splice::Insert {
+                        position,
+                        length: came.len(),
+                        text: came.text.to_string(),
+                    });
+                    position += came.len();
+                    j += 1;
+                }
+                (Some(was), Some(is)) => {
+                    if was.client != is.client || was.start != is.start {
+                        return None;
+                    }
+                    let step = was.end.min(is.end) + 1 - was.start;
+                    was.start += step;
+                    is.start += step;
+                    position += step as usize;
+                    if was.start > was.end {
+                        i += 1;
+                    }
+                    if is.start > is.end {
+                        j += 1;
+                    }
+                }
+                (None, None) => return Some(splices),
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift64, so every run does the same edits
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n.max(1) as u64) as usize
+        }
+    }
+
+    /// Insert or delete somewhere at random
+    fn edit(text: &mut FugueText, rng: &mut Rng) {
+        let len = text.len();
+        if len > 8 && rng.below(3) == 0 {
+            let position = rng.below(len - 1);
+            text.delete(position, 1 + rng.below(4.min(len - position)))
+                .unwrap();
+        } else {
+            let s = ["a", "bc", "def", "e\u{301}", "👋🏽", "\r\n"][rng.below(6)];
+            text.insert(rng.below(len + 1), s).unwrap();
+        }
+    }
+
+    /// The rope and units are what rebuilding them from the blocks gives
+    fn assert_rope_matches_blocks(text: &FugueText) {
+        let mut rebuilt = text.clone();
+        rebuilt.rebuild_rope();
+        assert_eq!(text.to_string(), rebuilt.to_string());
+        let offsets = |text: &FugueText| {
+            (0..=text.len())
+                .map(|unit| text.units.char_offset(unit))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(offsets(text), offsets(&rebuilt));
+    }
+
+    #[test]
+    fn test_units_of() {
+        let text = "ae\u{301}👋🏽b";
+        assert_eq!(units_of(text, 4, 0, 3), text);
+        assert_eq!(units_of(text, 4, 1, 2), "e\u{301}👋🏽");
+        assert_eq!(units_of(text, 4, 3, 3), "b");
+    }
+
+    #[test]
+    fn test_merge_splices_only_what_changed() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello World").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        bob.insert(5, ",").unwrap();
+        bob.delete(7, 4).unwrap();
+        alice.insert(11, "!").unwrap();
+        alice.merge(&bob).unwrap();
+
+        assert_eq!(alice.to_string(), "Hello, d!");
+        assert_rope_matches_blocks(&alice);
+    }
+
+    #[test]
+    fn test_random_merges_match_rebuilt_rope() {
+        const SEEDS: u64 = 6;
+        const ROUNDS: usize = 40;
+
+        for seed in 1..=SEEDS {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut replicas: Vec<FugueText> = ["a", "b", "c"]
+                .iter()
+                .map(|client| FugueText::new(client.to_string()))
+                .collect();
+
+            for _ in 0..ROUNDS {
+                let edited = rng.below(3);
+                for _ in 0..=rng.below(3) {
+                    edit(&mut replicas[edited], &mut rng);
+                }
+                let (from, to) = (rng.below(3), rng.below(3));
+                if from != to {
+                    let remote = replicas[from].clone();
+                    if rng.below(2) == 0 {
+                        replicas[to].merge(&remote).unwrap();
+                    } else {
+                        let delta = remote.diff_since(&replicas[to].state_vector());
+                        replicas[to].apply_delta(&delta).unwrap();
+                    }
+                    assert_rope_matches_blocks(&replicas[to]);
+                }
+            }
+
+            let all = replicas.clone();
+            for replica in &mut replicas {
+                for remote in &all {
+                    replica.merge(remote).unwrap();
+                }
+                assert_rope_matches_blocks(replica);
+            }
+            assert_eq!(replicas[0].to_string(), replicas[1].to_string());
+            assert_eq!(replicas[1].to_string(), replicas[2].to_string());
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Finding text, as anchors that survive later edits
+//!
+//! An offset from `to_string().find(..)` is stale as soon as a remote
+//! insert lands before it. These searches return [`Anchor`]s instead: the
+//! start sticks to the first character of the match and the end to its
+//! last, so a match keeps covering the same characters through any merge,
+//! and text typed at either edge stays outside it.
+//!
+//! Matching walks the rope's chars (Knuth-Morris-Pratt, so no char is
+//! read twice) without copying the text out. Matches are whole
+//! characters: a needle never matches part of a grapheme cluster, such as
+//! a letter without the accent combined with it.
+
+use super::anchor::{Anchor, AnchorBias};
+use super::text::FugueText;
+use std::ops::Range;
+
+impl FugueText {
+    /// Start of the first match of `needle`, or None if there is none (or
+    /// `needle` is empty)
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello World").unwrap();
+    /// let world = text.find("World").unwrap();
+    ///
+    /// text.insert(0, "Oh, ").unwrap();
+    /// assert_eq!(text.resolve_anchor(&world), Some(10));
+    /// ```
+    pub fn find(&mut self, needle: &str) -> Option<Anchor> {
+        self.find_from_position(0, needle).map(|(start, _)| start)
+    }
+
+    /// Start and end of every match of `needle`, in order and not
+    /// overlapping
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "to be or not to be").unwrap();
+    ///
+    /// let matches = text.find_all("be");
+    /// let positions: Vec<_> = matches
+    ///     .iter()
+    ///     .map(|(start, end)| (text.resolve_anchor(start), text.resolve_anchor(end)))
+    ///     .collect();
+    /// assert_eq!(positions, [(Some(3), Some(5)), (Some(16), Some(18))]);
+    /// ```
+    pub fn find_all(&mut self, needle: &str) -> Vec<(Anchor, Anchor)> {
+        let Some(needle) = Needle::new(needle) else {
+            return Vec::new();
+        };
+        let matches = self.match_chars(&needle, 0, usize::MAX);
+        matches
+            .into_iter()
+            .filter_map(|chars| self.match_anchors(chars))
+            .collect()
+    }
+
+    /// Start and end of the first match of `needle` at or after `anchor`,
+    /// or None if there is none or the anchor doesn't resolve here
+    ///
+    /// Passing the end of one match finds the next, so a search can go on
+    /// match by match while the text is edited in between.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::{AnchorBias, FugueText};
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "one fish two fish").unwrap();
+    /// let top = text.create_anchor(0, AnchorBias::Left).unwrap();
+    /// let (_, end) = text.find_from(&top, "fish").unwrap();
+    ///
+    /// text.insert(0, "red fish, ").unwrap();
+    /// let (next, _) = text.find_from(&end, "fish").unwrap();
+    /// assert_eq!(text.resolve_anchor(&next), Some(23));
+    /// ```
+    pub fn find_from(&mut self, anchor: &Anchor, needle: &str) -> Option<(Anchor, Anchor)> {
+        let position = self.resolve_anchor(anchor)?;
+        self.find_from_position(position, needle)
+    }
+
+    /// First match of `needle` at or after unit `position`
+    fn find_from_position(&mut self, position: usize, needle: &str) -> Option<(Anchor, Anchor)> {
+        let needle = Needle::new(needle)?;
+        let from = self.units.char_offset(position);
+        let chars = self.match_chars(&needle, from, 1).pop()?;
+        self.match_anchors(chars)
+    }
+
+    /// Char ranges of up to `limit` matches starting at or after char
+    /// `from`, each on unit boundaries
+    fn match_chars(&self, needle: &Needle, from: usize, limit: usize) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let mut matched = 0;
+        for (index, c) in (from..).zip(self.rope.chars_at(from)) {
+            while matched > 0 && needle.chars[matched] != c {
+                matched = needle.fallback[matched - 1];
+            }
+            if needle.chars[matched] == c {
+                matched += 1;
+            }
+            if matched < needle.chars.len() {
+                continue;
+            }
+
+            let start = index + 1 - needle.chars.len();
+            if self.on_unit_boundary(start) && self.on_unit_boundary(index + 1) {
+                matches.push(start..index + 1);
+                if matches.len() == limit {
+                    break;
+                }
+                matched = 0;
+            } else {
+                matched = needle.fallback[matched - 1];
+            }
+        }
+        matches
+    }
+
+    /// Whether char `index` starts a unit (or ends the text)
+    fn on_unit_boundary(&self, index: usize) -> bool {
+        let unit = self.units.units_before_char(index);
+        self.units.char_offset(unit) == index
+    }
+
+    /// Anchors to the first and last character of a match
+    fn match_anchors(&mut self, chars: Range<usize>) -> Option<(Anchor, Anchor)> {
+        let start = self.units.units_before_char(chars.start);
+        let end = self.units.units_before_char(chars.end);
+        let start = self.create_anchor(start, AnchorBias::Right).ok()?;
+        let end = self.create_anchor(end, AnchorBias::Left).ok()?;
+        Some((start, end))
+    }
+}
+
+/// A needle's chars and its KMP failure table
+struct Needle {
+    chars: Vec<char>,
+    /// Length of the longest proper prefix of `chars[..=i]` that is also
+    /// its suffix
+    fallback: Vec<usize>,
+}
+
+impl Needle {
+    /// None for an empty needle, which matches nothing
+    fn new(needle: &str) -> Option<Self> {
+        let chars: Vec<char> = needle.chars().collect();
+        if chars.is_empty() {
+            return None;
+        }
+        let mut fallback = vec![0; chars.len()];
+        let mut matched = 0;
+        for i in 1..chars.len() {
+            while matched > 0 && chars[i] != chars[matched] {
+                matched = fallback[matched - 1];
+            }
+            if chars[i] == chars[matched] {
+                matched += 1;
+            }
+            fallback[i] = matched;
+        }
+        Some(Self { chars, fallback })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(text: &mut FugueText, needle: &str) -> Vec<(usize, usize)> {
+        text.find_all(needle)
+            .iter()
+            .map(|(start, end)| {
+                (
+                    text.resolve_anchor(start).unwrap(),
+                    text.resolve_anchor(end).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_match_follows_its_text_through_a_merge() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "The quick brown fox").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        let (start, end) = alice.find_all("brown").pop().unwrap();
+        bob.insert(0, "Look: ").unwrap();
+        bob.insert(16, "very ").unwrap();
+        alice.merge(&bob).unwrap();
+
+        assert_eq!(alice.to_string(), "Look: The quick very brown fox");
+        let range = alice.resolve_anchor(&start).unwrap()..alice.resolve_anchor(&end).unwrap();
+        assert_eq!(alice.slice(range).unwrap(), "brown");
+        let found = alice.find("brown").unwrap();
+        assert_eq!(alice.resolve_anchor(&found), Some(21));
+
+        // The anchors mean the same on the other replica
+        assert_eq!(bob.resolve_anchor(&start), Some(21));
+        assert_eq!(bob.resolve_anchor(&end), Some(26));
+    }
+
+    #[test]
+    fn test_matches_across_blocks_and_overlapping_prefixes() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "aab").unwrap();
+        text.insert(0, "aaa").unwrap();
+        text.insert(6, "aaab").unwrap();
+
+        assert_eq!(text.to_string(), "aaaaabaaab");
+        assert_eq!(positions(&mut text, "aab"), [(3, 6), (7, 10)]);
+        assert_eq!(positions(&mut text, "aa"), [(0, 2), (2, 4), (6, 8)]);
+        assert!(text.find("aaaaaa").is_none());
+        assert!(text.find("").is_none());
+        assert!(text.find_all("").is_empty());
+    }
+
+    #[test]
+    fn test_needle_never_splits_a_cluster() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "cafe\u{301} cafe 👋🏽 👋").unwrap();
+
+        assert_eq!(positions(&mut text, "cafe"), [(5, 9)]);
+        assert_eq!(positions(&mut text, "e\u{301}"), [(3, 4)]);
+        assert_eq!(positions(&mut text, "👋"), [(12, 13)]);
+        assert_eq!(positions(&mut text, "👋🏽"), [(10, 11)]);
+    }
+
+    #[test]
+    fn test_incremental_search_while_editing() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "x1 x2 x3").unwrap();
+
+        let mut cursor = text.create_anchor(0, AnchorBias::Left).unwrap();
+        let mut found = Vec::new();
+        while let Some((start, end)) = text.find_from(&cursor, "x") {
+            let position = text.resolve_anchor(&start).unwrap();
+            found.push(text.slice(position..position + 2).unwrap());
+            // Edit behind the cursor between steps
+            text.insert(0, "x0 ").unwrap();
+            cursor = end;
+        }
+        assert_eq!(found, ["x1", "x2", "x3"]);
+    }
+}
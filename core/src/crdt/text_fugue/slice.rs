@@ -0,0 +1,133 @@
+//! Reading part of the text straight from the rope
+//!
+//! `to_string()` copies the whole text. These read a range of it from the
+//! rope's chunks, so a view of a few lines costs the same in a large
+//! document as in a small one.
+
+use super::text::{FugueText, TextError};
+use std::borrow::Cow;
+use std::ops::Range;
+
+impl FugueText {
+    /// The text of the units in `range`
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::RangeOutOfBounds` if the range is reversed or
+    /// ends past the text
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello World").unwrap();
+    ///
+    /// assert_eq!(text.slice(6..11).unwrap(), "World");
+    /// ```
+    pub fn slice(&self, range: Range<usize>) -> Result<String, TextError> {
+        self.check_range(&range)?;
+        Ok(self.rope.slice(self.units.char_range(range)).to_string())
+    }
+
+    /// The first char of the unit at `position`, None past the end
+    pub fn char_at(&self, position: usize) -> Option<char> {
+        (position < self.len()).then(|| self.rope.char(self.units.char_offset(position)))
+    }
+
+    /// The units in `range`, one string each
+    ///
+    /// A unit inside one rope chunk is borrowed from it; one spanning two
+    /// chunks is copied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::RangeOutOfBounds` like `slice`
+    pub fn graphemes_in(
+        &self,
+        range: Range<usize>,
+    ) -> Result<impl Iterator<Item = Cow<'_, str>> + '_, TextError> {
+        self.check_range(&range)?;
+        let mut char_start = self.units.char_offset(range.start);
+        let lengths = self.units.lengths_from(range.start).take(range.len());
+        Ok(lengths.map(move |chars| {
+            let char_end = char_start + chars;
+            let grapheme = self.rope.slice(char_start..char_end);
+            char_start = char_end;
+            grapheme
+                .as_str()
+                .map_or_else(|| Cow::Owned(grapheme.to_string()), Cow::Borrowed)
+        }))
+    }
+
+    fn check_range(&self, range: &Range<usize>) -> Result<(), TextError> {
+        let length = self.len();
+        if range.start > range.end || range.end > length {
+            return Err(TextError::RangeOutOfBounds {
+                start: range.start,
+                end: range.end,
+                length,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slice_multibyte_text() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "añb👩‍🚀c日本").unwrap();
+        text.insert(3, "e\u{301}").unwrap();
+
+        assert_eq!(text.len(), 8);
+        assert_eq!(text.slice(1..5).unwrap(), "ñbe\u{301}👩‍🚀");
+        assert_eq!(text.slice(8..8).unwrap(), "");
+        assert_eq!(text.char_at(3), Some('e'));
+        assert_eq!(text.char_at(4), Some('👩'));
+        assert_eq!(text.char_at(7), Some('本'));
+        assert_eq!(text.char_at(8), None);
+
+        let graphemes: Vec<_> = text.graphemes_in(2..6).unwrap().collect();
+        assert_eq!(graphemes, ["b", "e\u{301}", "👩‍🚀", "c"]);
+    }
+
+    #[test]
+    fn test_graphemes_across_rope_chunks() {
+        let mut text = FugueText::new("alice".to_string());
+        let source = "👋🏽ab".repeat(2_000);
+        text.insert(0, &source).unwrap();
+
+        let graphemes: String = text.graphemes_in(0..text.len()).unwrap().collect();
+        assert_eq!(graphemes, source);
+        assert!(text
+            .graphemes_in(0..text.len())
+            .unwrap()
+            .step_by(3)
+            .all(|grapheme| grapheme == "👋🏽"));
+    }
+
+    #[test]
+    fn test_out_of_range_reads_fail() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "Hello").unwrap();
+
+        assert!(matches!(
+            text.slice(3..6),
+            Err(TextError::RangeOutOfBounds {
+                start: 3,
+                end: 6,
+                length: 5
+            })
+        ));
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 4..2;
+        assert!(text.slice(reversed.clone()).is_err());
+        assert!(text.graphemes_in(reversed).is_err());
+        assert!(text.graphemes_in(0..6).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! BlockText: block text stored inline when short
+//!
+//! Typing creates one block per keystroke, so most blocks hold a handful
+//! of bytes. A `String` costs a heap allocation for each of them (and
+//! another on every clone during merges). `BlockText` keeps up to
+//! `INLINE_CAPACITY` bytes inside the value itself, the same 24 bytes a
+//! `String` takes, and moves longer text into a boxed `str`.
+//!
+//! It derefs to `str` and serializes as a plain string, so the serde
+//! representation of `FugueBlock` is unchanged.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::ops::Deref;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Longest text (in bytes) stored without a heap allocation
+pub const INLINE_CAPACITY: usize = 22;
+
+/// Immutable text of a `FugueBlock`
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BlockText(Repr);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Inline {
+        len: u8,
+        bytes: [u8; INLINE_CAPACITY],
+    },
+    Heap(Box<str>),
+}
+
+impl BlockText {
+    /// Create from a string slice, inline if it fits
+    pub fn new(text: &str) -> Self {
+        if text.len() <= INLINE_CAPACITY {
+            let mut bytes = [0; INLINE_CAPACITY];
+            bytes[..text.len()].copy_from_slice(text.as_bytes());
+            BlockText(Repr::Inline {
+                len: text.len() as u8,
+                bytes,
+            })
+        } else {
+            BlockText(Repr::Heap(text.into()))
+        }
+    }
+
+    /// The text
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            Repr::Inline { len, bytes } => core::str::from_utf8(&bytes[..usize::from(*len)])
+                .expect("inline bytes are copied from a str"),
+            Repr::Heap(text) => text,
+        }
+    }
+
+    /// Check if the text is stored without a heap allocation
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+}
+
+impl Default for BlockText {
+    fn default() -> Self {
+        BlockText::new("")
+    }
+}
+
+impl Deref for BlockText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for BlockText {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for BlockText {
+    fn from(text: &str) -> Self {
+        BlockText::new(text)
+    }
+}
+
+impl From<String> for BlockText {
+    fn from(text: String) -> Self {
+        if text.len() <= INLINE_CAPACITY {
+            BlockText::new(&text)
+        } else {
+            BlockText(Repr::Heap(text.into_boxed_str()))
+        }
+    }
+}
+
+impl From<BlockText> for String {
+    fn from(text: BlockText) -> Self {
+        match text.0 {
+            Repr::Heap(text) => text.into_string(),
+            Repr::Inline { .. } => text.as_str().to_string(),
+        }
+    }
+}
+
+impl PartialEq<str> for BlockText {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for BlockText {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for BlockText {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl fmt::Debug for BlockText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for BlockText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for BlockText {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = BlockText;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<BlockText, E> {
+                Ok(BlockText::new(text))
+            }
+
+            fn visit_string<E: serde::de::Error>(self, text: String) -> Result<BlockText, E> {
+                Ok(BlockText::from(text))
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_larger_than_string() {
+        assert!(std::mem::size_of::<BlockText>() <= std::mem::size_of::<String>());
+    }
+
+    #[test]
+    fn test_short_text_is_inline() {
+        let text = BlockText::from("👋 hi".to_string());
+        assert!(text.is_inline());
+        assert_eq!(text, "👋 hi");
+        assert_eq!(text.len(), 7);
+
+        let long = BlockText::new(&"x".repeat(INLINE_CAPACITY + 1));
+        assert!(!long.is_inline());
+        assert_eq!(long.len(), INLINE_CAPACITY + 1);
+        assert_eq!(String::from(long.clone()), long.as_str());
+    }
+
+    #[test]
+    fn test_serializes_as_a_string() {
+        for text in ["", "abc", &"long text ".repeat(5)] {
+            let block_text = BlockText::new(text);
+            assert_eq!(
+                serde_json::to_string(&block_text).unwrap(),
+                serde_json::to_string(text).unwrap()
+            );
+            let back: BlockText =
+                serde_json::from_str(&serde_json::to_string(text).unwrap()).unwrap();
+            assert_eq!(back, block_text);
+        }
+    }
+}
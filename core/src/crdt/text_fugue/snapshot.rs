@@ -0,0 +1,141 @@
+//! TextSnapshot: Immutable view of a FugueText's visible content
+//!
+//! Ropes are persistent trees with shared nodes, so cloning one is O(1)
+//! and later edits to the source copy only the nodes they touch. A
+//! snapshot therefore costs almost nothing to create and stays frozen at
+//! the content it was taken from, no matter how the source changes.
+
+use super::text::{FugueText, TextError};
+use super::units::UnitTable;
+use ropey::Rope;
+
+/// Read-only, point-in-time view of a FugueText
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::crdt::text_fugue::FugueText;
+///
+/// let mut text = FugueText::new("client1".to_string());
+/// text.insert(0, "Hello").unwrap();
+///
+/// let snapshot = text.snapshot();
+/// text.insert(5, " World").unwrap();
+///
+/// assert_eq!(snapshot.to_string(), "Hello");
+/// assert_eq!(text.to_string(), "Hello World");
+/// ```
+#[derive(Debug, Clone)]
+pub struct TextSnapshot {
+    rope: Rope,
+    units: UnitTable,
+}
+
+impl TextSnapshot {
+    /// Get the length (same units as `FugueText::len`)
+    pub fn len(&self) -> usize {
+        self.units.len()
+    }
+
+    /// Check if the snapshot is empty
+    pub fn is_empty(&self) -> bool {
+        self.units.len() == 0
+    }
+
+    /// Get the text between `start` and `end` (exclusive)
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::RangeOutOfBounds` if the range is invalid
+    pub fn slice(&self, start: usize, end: usize) -> Result<String, TextError> {
+        let length = self.len();
+        if start > end || end > length {
+            return Err(TextError::RangeOutOfBounds { start, end, length });
+        }
+
+        Ok(self
+            .rope
+            .slice(self.units.char_range(start..end))
+            .to_string())
+    }
+
+    /// `text`, this snapshot's `to_string()`, cut into position units
+    pub(super) fn units<'a>(&'a self, text: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.units.split(text)
+    }
+}
+
+impl std::fmt::Display for TextSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl FugueText {
+    /// Take a read-only snapshot of the current visible text
+    ///
+    /// O(1): the snapshot shares storage with this replica instead of
+    /// copying the text (and, for text with multi-char clusters, the
+    /// position table, until the next edit).
+    pub fn snapshot(&self) -> TextSnapshot {
+        TextSnapshot {
+            rope: self.rope.clone(),
+            units: self.units.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_frozen_while_source_mutates() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello World").unwrap();
+
+        let snapshot = text.snapshot();
+        text.delete(0, 6).unwrap();
+        text.insert(0, "Goodbye ").unwrap();
+
+        assert_eq!(snapshot.to_string(), "Hello World");
+        assert_eq!(snapshot.len(), 11);
+        assert_eq!(text.to_string(), "Goodbye World");
+    }
+
+    #[test]
+    fn test_snapshot_outlives_source() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello").unwrap();
+
+        let snapshot = text.snapshot();
+        drop(text);
+
+        assert_eq!(snapshot.to_string(), "Hello");
+    }
+
+    #[test]
+    fn test_snapshot_slice() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello World").unwrap();
+
+        let snapshot = text.snapshot();
+        assert_eq!(snapshot.slice(6, 11).unwrap(), "World");
+        assert!(snapshot.slice(6, 12).is_err());
+        assert!(snapshot.slice(7, 6).is_err());
+    }
+
+    #[test]
+    fn test_many_snapshots_of_large_text() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, &"a".repeat(1024 * 1024)).unwrap();
+
+        // Would be ~1 GB if each snapshot copied the text
+        let snapshots: Vec<_> = (0..1000).map(|_| text.snapshot()).collect();
+
+        assert!(snapshots.iter().all(|s| s.len() == 1024 * 1024));
+    }
+}
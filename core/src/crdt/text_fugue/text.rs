@@ -8,128 +8,28 @@
 //! - O(log n) position lookup (Phase 1.5 - binary search with position cache)
 
 use super::block::FugueBlock;
-use super::node::NodeId;
+use super::clients::ClientTable;
+use super::clock::LamportClock;
+use super::delta::{DeleteRange, Persisted, TextEvent};
+use super::history::PositionHistory;
+use super::limits::{TextLimit, TextLimits};
+use super::marks::Marks;
+use super::node::{ClientId, NodeId};
+use super::order;
+use super::rechunk::{FugueTextOptions, Rechunk};
+use super::units::UnitTable;
+use crate::annotations::Annotations;
+use crate::notify::Notifier;
+use crate::sync::VectorClock;
+use crate::time::SharedTime;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 
-#[cfg(feature = "text-crdt")]
 use ropey::Rope;
 
-#[cfg(feature = "text-crdt")]
 use unicode_segmentation::UnicodeSegmentation;
 
-/// Side of a node in the Fugue tree (left or right child of parent)
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Side {
-    Left,
-    Right,
-}
-
-/// Tree node for Fugue tree reconstruction.
-///
-/// The Fugue algorithm requires building an explicit tree structure from
-/// the implicit tree encoded in left_origin/right_origin metadata.
-/// This struct represents a node in that reconstructed tree.
-#[derive(Debug, Clone)]
-struct TreeNode {
-    id: NodeId,
-    parent: Option<NodeId>,
-    side: Side,
-    deleted: bool,
-}
-
-/// Lamport timestamp for causality tracking
-///
-/// Lamport clocks provide a "happens-before" partial ordering of events
-/// in a distributed system. Each replica maintains its own clock and
-/// increments it on local operations.
-///
-/// # Properties
-///
-/// - Monotonically increasing: clock never decreases
-/// - Always > 0: clock starts at 1 (0 reserved for initial state)
-/// - Update on merge: clock = max(local, remote) + 1
-///
-/// # Example
-///
-/// ```rust
-/// use synckit_core::crdt::text_fugue::LamportClock;
-///
-/// let mut clock = LamportClock::new();
-/// assert_eq!(clock.value(), 0);
-///
-/// let ts1 = clock.tick();
-/// assert_eq!(ts1, 1);
-///
-/// clock.update(5);  // Merge from remote
-/// let ts2 = clock.tick();
-/// assert_eq!(ts2, 6);  // max(1, 5) + 1
-/// ```
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub struct LamportClock {
-    value: u64,
-}
-
-impl LamportClock {
-    /// Create a new Lamport clock starting at 0
-    pub fn new() -> Self {
-        Self { value: 0 }
-    }
-
-    /// Get the current clock value
-    pub fn value(&self) -> u64 {
-        self.value
-    }
-
-    /// Increment clock and return new value (for local operations)
-    pub fn tick(&mut self) -> u64 {
-        self.value += 1;
-        self.value
-    }
-
-    /// Tick by N values (for per-character clock allocation)
-    ///
-    /// This method increments the clock by N values instead of 1, enabling
-    /// per-character clock allocation for RLE blocks. Each character in a block
-    /// gets its own unique clock value.
-    ///
-    /// # Arguments
-    ///
-    /// * `count` - Number of clock values to allocate
-    ///
-    /// # Returns
-    ///
-    /// The new clock value after incrementing by count
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use synckit_core::crdt::text_fugue::LamportClock;
-    ///
-    /// let mut clock = LamportClock::new();
-    /// let ts = clock.tick_by(5);  // Allocate 5 clock values
-    /// assert_eq!(ts, 5);
-    /// assert_eq!(clock.value(), 5);
-    /// ```
-    pub fn tick_by(&mut self, count: usize) -> u64 {
-        self.value += count as u64;
-        self.value
-    }
-
-    /// Update clock from remote timestamp (for merge operations)
-    ///
-    /// Sets clock to max(local, remote) to maintain causality
-    pub fn update(&mut self, remote: u64) {
-        self.value = self.value.max(remote);
-    }
-}
-
-impl Default for LamportClock {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 /// Error types for FugueText operations
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TextError {
@@ -159,6 +59,48 @@ pub enum TextError {
 
     /// Rope operation failed
     RopeError(String),
+
+    /// Delta from a remote replica is malformed
+    InvalidDelta(String),
+
+    /// UTF-16 position falls inside a character rather than between two
+    InvalidUtf16Position {
+        position: usize,
+        /// Between the halves of a surrogate pair, rather than between the
+        /// code points of a grapheme cluster
+        surrogate_pair: bool,
+    },
+
+    /// Yjs update could not be decoded or uses unsupported features
+    #[cfg(feature = "yjs-interop")]
+    InvalidYjsUpdate(String),
+
+    /// Two replicas hold different text under the same character ID
+    ///
+    /// Happens when a replica is restored from an old snapshot and edits
+    /// before it has caught up: its clock reuses IDs it had already handed
+    /// out. Nothing is merged. To recover, load a fresh replica from the
+    /// server and re-apply the local edits to it as new inserts.
+    ReplicaConflict {
+        /// The first character the replicas disagree on
+        id: NodeId,
+        /// Our text from `id` to the end of what both hold
+        local: String,
+        /// The other replica's text over the same characters
+        remote: String,
+    },
+
+    /// The edit or merge would take the text past its `TextLimits`, or a
+    /// text loaded with limits (`FugueText::from_json_with_limits`,
+    /// `from_proto_bytes_with_limits`) is over them; nothing is changed
+    LimitExceeded {
+        /// Which limit
+        limit: TextLimit,
+        /// What the text would have held
+        size: usize,
+        /// The limit's value
+        max: usize,
+    },
 }
 
 impl std::fmt::Display for TextError {
@@ -199,12 +141,64 @@ impl std::fmt::Display for TextError {
             TextError::RopeError(msg) => {
                 write!(f, "Rope error: {}", msg)
             }
+            TextError::InvalidDelta(msg) => {
+                write!(f, "Invalid text delta: {}", msg)
+            }
+            TextError::InvalidUtf16Position {
+                position,
+                surrogate_pair: true,
+            } => {
+                write!(
+                    f,
+                    "UTF-16 position {} splits a surrogate pair (astral-plane character)",
+                    position
+                )
+            }
+            TextError::InvalidUtf16Position {
+                position,
+                surrogate_pair: false,
+            } => {
+                write!(
+                    f,
+                    "UTF-16 position {} is inside a grapheme cluster",
+                    position
+                )
+            }
+            #[cfg(feature = "yjs-interop")]
+            TextError::InvalidYjsUpdate(msg) => {
+                write!(f, "Invalid Yjs update: {}", msg)
+            }
+            TextError::ReplicaConflict { id, local, remote } => {
+                write!(
+                    f,
+                    "Replica conflict at {}: local {:?}, remote {:?}. The replica was probably \
+                     restored from an old snapshot; load a fresh replica from the server and \
+                     re-apply local edits as new inserts",
+                    id, local, remote
+                )
+            }
+            TextError::LimitExceeded { limit, size, max } => {
+                write!(
+                    f,
+                    "Text would hold {} {}, over the limit of {}",
+                    size, limit, max
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for TextError {}
 
+/// What a `FugueText::splice` changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpliceResult {
+    /// Blocks tombstoned by the deletion
+    pub deleted: Vec<NodeId>,
+    /// Block holding the inserted text, None if nothing was inserted
+    pub inserted: Option<NodeId>,
+}
+
 /// Fugue Text CRDT
 ///
 /// FugueText implements collaborative text editing with mathematically proven
@@ -229,7 +223,8 @@ impl std::error::Error for TextError {}
 /// # Performance (Phase 1.5)
 ///
 /// - Insert: O(log n) - Rope O(log n) + position lookup O(log n)
-/// - Delete: O(n) - Still needs full scan for range deletion
+/// - Delete: O(log n) to find the range in the position cache, plus O(k)
+///   to shift the k blocks after it
 /// - Merge: O(m log n) - m remote blocks, n local blocks
 /// - Memory: ~7 bytes/char with RLE (vs 61 bytes without!)
 /// - Position cache: O(n) rebuild, amortized O(1) per operation
@@ -246,90 +241,234 @@ impl std::error::Error for TextError {}
 /// assert_eq!(text.to_string(), "Hello World");
 /// assert_eq!(text.len(), 11);
 /// ```
-#[cfg(feature = "text-crdt")]
 #[derive(Debug, Clone)]
 pub struct FugueText {
     /// Rope for efficient text storage
     /// Note: Rope is rebuilt from blocks during deserialization
-    rope: Rope,
+    pub(super) rope: Rope,
 
     /// CRDT metadata: BTreeMap maintains Fugue ordering via NodeId Ord
-    blocks: BTreeMap<NodeId, FugueBlock>,
+    ///
+    /// Shared between clones and copied on the first write (see
+    /// `blocks_mut`), so forking a replica is O(1). Each block is shared
+    /// too, so that copy only clones pointers, and a block is copied only
+    /// when it is written (see `block_mut`).
+    pub(super) blocks: Arc<BTreeMap<NodeId, Arc<FugueBlock>>>,
 
     /// Lamport clock for causality tracking
-    clock: LamportClock,
+    pub(super) clock: LamportClock,
 
-    /// Client/replica identifier
-    client_id: String,
+    /// Client/replica identifier, shared by the IDs of our own blocks
+    pub(super) client_id: ClientId,
+
+    /// The client IDs our blocks name, one copy each (not serialized;
+    /// rebuilt from the blocks on load)
+    pub(super) clients: ClientTable,
 
     /// Cache validity flag (avoids O(n) scan to check if rebuild needed)
     /// Set to false on insert/delete (O(1)), checked before find_origins (O(1))
-    cache_valid: bool,
+    pub(super) cache_valid: bool,
 
     /// Cached vector of non-deleted blocks for O(log n) binary search
     /// Rebuilt when cache_valid is false. Avoids O(n) allocation on every insert!
-    #[cfg(feature = "text-crdt")]
-    cached_blocks: Vec<NodeId>,
+    pub(super) cached_blocks: Arc<Vec<NodeId>>,
+
+    /// Start position of each block in `cached_blocks`; kept here rather
+    /// than in the blocks, so caching never copies blocks a clone shares
+    pub(super) cached_positions: Arc<HashMap<NodeId, usize>>,
+
+    /// Baseline for `take_dirty`
+    pub(super) persisted: Persisted,
+
+    /// Bumped by every change to the blocks, so a `TextMergeJob` can tell
+    /// whether the text was edited while it ran
+    pub(super) revision: u64,
+
+    /// Tags on ranges of inserts (see [`crate::annotations`])
+    pub(super) annotations: Annotations,
+
+    /// Formatting on ranges of the text (see `marks.rs`)
+    pub(super) marks: Marks,
+
+    /// How positions moved, for `transform_position` (not serialized)
+    pub(super) history: PositionHistory,
+
+    /// Change observers (not serialized, not cloned)
+    pub(super) notifier: Notifier<TextEvent>,
+
+    /// Clock for time-based behaviour; the thread default if unset (not
+    /// serialized)
+    pub(super) time: Option<SharedTime>,
+
+    /// Tuning (not serialized)
+    pub(super) options: FugueTextOptions,
+
+    /// How large the text may grow (not serialized)
+    pub(super) limits: TextLimits,
+
+    /// Re-chunking pass in progress and its counters (not serialized)
+    pub(super) rechunk: Rechunk,
+
+    /// Char length of each position unit, to address the rope (not
+    /// serialized; rebuilt with the rope)
+    pub(super) units: UnitTable,
+
+    /// Highest clock per client below which `gc` removed tombstones, so
+    /// merges drop them when a peer sends them back (not serialized)
+    pub(super) collected: VectorClock,
+}
+
+/// Work phase 2 of `merge` leaves for phases 3-5 (see `finish_merge`)
+#[derive(Debug, Clone, Default)]
+pub(super) struct PendingMerge {
+    /// Clock ranges the remote deleted in blocks we split differently
+    deletions: Vec<DeleteRange>,
+
+    /// New blocks, whose origins may need splitting
+    integrated: Vec<NodeId>,
+}
+
+/// What `merge` does with a remote block (see `classify_remote_block`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RemoteBlock {
+    /// Same ID exists locally: merge the deletion flag
+    Known,
+    /// Zero-length block: store it as is
+    Empty,
+    /// Overlaps a local block from the same client: only propagate its
+    /// deletion over `start..=id.clock`
+    SplitPiece { start: u64 },
+    /// A tombstone `gc` removed here: drop it
+    Collected,
+    /// Genuinely new: insert and integrate
+    New,
 }
 
-#[cfg(feature = "text-crdt")]
 impl Serialize for FugueText {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
         use serde::ser::SerializeStruct;
-        let mut state = serializer.serialize_struct("FugueText", 3)?;
+        let mut state = serializer.serialize_struct("FugueText", 4)?;
 
         // Convert BTreeMap to Vec for JSON compatibility (JSON requires string keys)
-        let blocks_vec: Vec<(&NodeId, &FugueBlock)> = self.blocks.iter().collect();
+        let blocks_vec: Vec<(&NodeId, &FugueBlock)> = self
+            .blocks
+            .iter()
+            .map(|(id, block)| (id, block.as_ref()))
+            .collect();
         state.serialize_field("blocks", &blocks_vec)?;
 
         state.serialize_field("clock", &self.clock)?;
         state.serialize_field("client_id", &self.client_id)?;
+        state.serialize_field("annotations", &self.annotations)?;
+        state.serialize_field("marks", &self.marks)?;
         state.end()
     }
 }
 
-#[cfg(feature = "text-crdt")]
+/// The serialized fields of a `FugueText`, before its rope is built
+#[derive(Deserialize)]
+pub(super) struct TextParts {
+    pub(super) blocks: Vec<(NodeId, FugueBlock)>,
+    clock: LamportClock,
+    client_id: ClientId,
+    #[serde(default)]
+    annotations: Annotations,
+    #[serde(default)]
+    marks: Marks,
+}
+
+impl TextParts {
+    /// The text, with an empty rope and no position cache yet
+    pub(super) fn into_text(self) -> FugueText {
+        // Convert Vec back to BTreeMap
+        let blocks: BTreeMap<NodeId, Arc<FugueBlock>> = self
+            .blocks
+            .into_iter()
+            .map(|(id, block)| (id, Arc::new(block)))
+            .collect();
+        let mut text = FugueText {
+            rope: Rope::new(), // Start with empty rope
+            blocks: Arc::new(blocks),
+            clock: self.clock,
+            client_id: self.client_id,
+            clients: ClientTable::default(),
+            cache_valid: false,
+            cached_blocks: Arc::default(),
+            cached_positions: Arc::default(),
+            persisted: Persisted::loaded(),
+            revision: 0,
+            annotations: self.annotations,
+            marks: self.marks,
+            history: PositionHistory::default(),
+            notifier: Notifier::default(),
+            time: None,
+            options: FugueTextOptions::default(),
+            limits: TextLimits::default(),
+            rechunk: Rechunk::default(),
+            units: UnitTable::default(),
+            collected: VectorClock::new(),
+        };
+        text.intern_all_clients();
+        text
+    }
+}
+
+/// For the protobuf form (`protocol::serialize`)
+#[cfg(feature = "protocol-binary")]
+impl FugueText {
+    /// Every block, tombstones included, in ID order
+    pub(crate) fn all_blocks(&self) -> impl Iterator<Item = &FugueBlock> + '_ {
+        self.blocks.values().map(Arc::as_ref)
+    }
+
+    /// Build a text from the fields it serializes, as loading it with
+    /// `limits` does: checked against them before the rope is built
+    pub(crate) fn from_state(
+        client_id: String,
+        clock: u64,
+        blocks: Vec<FugueBlock>,
+        annotations: Annotations,
+        marks: Marks,
+        limits: TextLimits,
+    ) -> Result<Self, TextError> {
+        let mut lamport = LamportClock::new();
+        lamport.update(clock);
+        TextParts {
+            blocks: blocks
+                .into_iter()
+                .map(|block| (block.id.clone(), block))
+                .collect(),
+            clock: lamport,
+            client_id: client_id.into(),
+            annotations,
+            marks,
+        }
+        .into_limited_text(limits)
+    }
+}
+
 impl<'de> Deserialize<'de> for FugueText {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        #[derive(Deserialize)]
-        struct FugueTextHelper {
-            blocks: Vec<(NodeId, FugueBlock)>,
-            clock: LamportClock,
-            client_id: String,
-        }
-
-        let helper = FugueTextHelper::deserialize(deserializer)?;
-
-        // Convert Vec back to BTreeMap
-        let blocks: BTreeMap<NodeId, FugueBlock> = helper.blocks.into_iter().collect();
-
         // CRITICAL FIX: Build rope in Fugue document order, NOT BTreeMap order!
         // BTreeMap iteration gives causal/timestamp order (by NodeId), which differs
         // from document order when blocks are split or inserted mid-text.
         // We must use rebuild_rope() which correctly traverses the Fugue tree.
-        let mut fugue = Self {
-            rope: Rope::new(), // Start with empty rope
-            blocks,
-            clock: helper.clock,
-            client_id: helper.client_id,
-            cache_valid: false,
-            cached_blocks: Vec::new(),
-        };
+        let mut fugue = TextParts::deserialize(deserializer)?.into_text();
 
         // Rebuild rope in correct Fugue tree document order
         fugue.rebuild_rope();
+        fugue.restart_history();
 
         Ok(fugue)
     }
 }
 
-#[cfg(feature = "text-crdt")]
 impl FugueText {
     /// Create a new empty FugueText
     ///
@@ -347,19 +486,38 @@ impl FugueText {
     /// assert_eq!(text.to_string(), "");
     /// ```
     pub fn new(client_id: String) -> Self {
+        let mut clients = ClientTable::default();
+        let client_id = clients.intern(&client_id.into());
         Self {
             rope: Rope::new(),
-            blocks: BTreeMap::new(),
+            blocks: Arc::default(),
             clock: LamportClock::new(),
             client_id,
-            cache_valid: true,         // Empty document has valid (empty) cache
-            cached_blocks: Vec::new(), // Empty document has empty blocks vector
+            clients,
+            cache_valid: true,             // Empty document has valid (empty) cache
+            cached_blocks: Arc::default(), // Empty document has empty blocks vector
+            cached_positions: Arc::default(),
+            persisted: Persisted::default(),
+            revision: 0,
+            annotations: Annotations::default(),
+            marks: Marks::default(),
+            history: PositionHistory::default(),
+            notifier: Notifier::default(),
+            time: None,
+            options: FugueTextOptions::default(),
+            limits: TextLimits::default(),
+            rechunk: Rechunk::default(),
+            units: UnitTable::default(),
+            collected: VectorClock::new(),
         }
     }
 
     /// Get the number of grapheme clusters (user-perceived characters)
     ///
     /// This is the length users expect - counts emoji as 1, not 7 code points.
+    /// Clusters are counted as each insert wrote them (see the module docs
+    /// on positions), and every position the API takes or reports uses the
+    /// same unit.
     ///
     /// # Example
     ///
@@ -370,14 +528,17 @@ impl FugueText {
     /// text.insert(0, "Hello 👋").unwrap();
     ///
     /// assert_eq!(text.len(), 7);  // Not 10 (byte length)
+    ///
+    /// text.insert(0, "👨‍👩‍👧").unwrap();
+    /// assert_eq!(text.len(), 8);  // Not 12 (chars)
     /// ```
     pub fn len(&self) -> usize {
-        self.rope.len_chars()
+        self.units.len()
     }
 
     /// Check if the text is empty
     pub fn is_empty(&self) -> bool {
-        self.rope.len_chars() == 0
+        self.units.len() == 0
     }
 
     /// Convert to String
@@ -425,7 +586,8 @@ impl FugueText {
     ///
     /// # Errors
     ///
-    /// Returns `TextError::PositionOutOfBounds` if position > length
+    /// Returns `TextError::PositionOutOfBounds` if position > length, or
+    /// `TextError::LimitExceeded` if the text would outgrow its limits
     ///
     /// # Example
     ///
@@ -439,6 +601,9 @@ impl FugueText {
     /// assert_eq!(text.to_string(), "Hello World");
     /// ```
     pub fn insert(&mut self, position: usize, text: &str) -> Result<NodeId, TextError> {
+        self.check_insert_limits(text, 0)?;
+        self.touch();
+
         // 1. Validate position
         let len = self.len();
         if position > len {
@@ -450,12 +615,25 @@ impl FugueText {
 
         // 2. Find CRDT origins (Phase 1.5: O(log n) with cache!)
         let (left_origin, right_origin) = self.find_origins(position)?;
+        Ok(self.insert_between(position, text, left_origin, right_origin))
+    }
+
+    /// Steps 2-9 of `insert`, with the origins already found
+    fn insert_between(
+        &mut self,
+        position: usize,
+        text: &str,
+        left_origin: Option<NodeId>,
+        right_origin: Option<NodeId>,
+    ) -> NodeId {
+        if self.split_at_origins(left_origin.as_ref(), right_origin.as_ref()) {
+            self.cache_valid = false;
+        }
+        self.rechunk
+            .note_origins(left_origin.as_ref(), right_origin.as_ref());
 
         // 3. Calculate grapheme length for per-character clock allocation
-        #[cfg(feature = "text-crdt")]
         let char_count = text.graphemes(true).count();
-        #[cfg(not(feature = "text-crdt"))]
-        let char_count = text.chars().count();
 
         // 4. Generate timestamp range and NodeId (one clock value per character!)
         // This allocates clock values [timestamp - char_count + 1, timestamp]
@@ -464,25 +642,36 @@ impl FugueText {
         let id = NodeId::new(self.client_id.clone(), timestamp, 0);
 
         // 5. Cache the insert length for later use
-        #[cfg(feature = "text-crdt")]
         let insert_len = char_count;
 
         // 6. Create FugueBlock with RLE (entire text as one block!)
         let block = FugueBlock::new(id.clone(), text.to_string(), left_origin, right_origin);
 
-        // 7. Insert into BTreeMap (maintains Fugue ordering)
-        self.blocks.insert(id.clone(), block);
+        // 7. Insert into BTreeMap (maintains Fugue ordering), or append to
+        // the block the previous keystroke went into
+        if let Some(block) = self.extend_typed_block(block) {
+            self.blocks_mut().insert(id.clone(), Arc::new(block));
+        }
 
-        // 8. Insert into rope (O(log n))
-        let byte_pos = self.char_to_byte(position)?;
-        self.rope.insert(byte_pos, text);
+        // 8. Insert into rope (O(log n)); ropey indexes chars, not bytes
+        let char_pos = self.units.char_offset(position);
+        self.rope.insert(char_pos, text);
+        self.units.insert(position, text);
 
         // 9. Update position cache incrementally (O(k) instead of O(n) rebuild!)
-        self.invalidate_position_cache(byte_pos); // Rope cache separate
-        #[cfg(feature = "text-crdt")]
         self.update_cache_after_insert(position, insert_len, &id);
 
-        Ok(id)
+        if insert_len > 0 {
+            self.history.record_insert(position, insert_len, id.clock);
+        }
+        if !text.is_empty() {
+            self.notifier.push(TextEvent::Insert {
+                position,
+                text: text.to_string(),
+            });
+        }
+        self.rechunk_step();
+        id
     }
 
     /// Delete text at the given position
@@ -501,7 +690,9 @@ impl FugueText {
     ///
     /// # Errors
     ///
-    /// Returns `TextError::RangeOutOfBounds` if range exceeds document length
+    /// Returns `TextError::RangeOutOfBounds` if range exceeds document length,
+    /// or if the rope doesn't hold exactly the text the range tombstones;
+    /// nothing is changed then.
     ///
     /// # Example
     ///
@@ -515,6 +706,8 @@ impl FugueText {
     /// assert_eq!(text.to_string(), "Hello");
     /// ```
     pub fn delete(&mut self, position: usize, length: usize) -> Result<Vec<NodeId>, TextError> {
+        self.touch();
+
         // 1. Validate range
         let doc_len = self.len();
         if position + length > doc_len {
@@ -525,57 +718,57 @@ impl FugueText {
             });
         }
 
-        // 2. Find blocks that overlap deletion range and split if needed
-        let mut blocks_to_split = Vec::new();
-        let mut deleted_ids = Vec::new();
-        let mut current_pos = 0;
+        if length == 0 {
+            return Ok(Vec::new());
+        }
 
-        // First pass: identify blocks that need splitting
+        // 2. Find blocks that overlap deletion range and split if needed
+        //
+        // Graphemes are counted per block, and a cluster the visible text
+        // shows as one (a ZWJ emoji typed in parts by two clients) counts
+        // as several. Track the rope chars before the range and the text
+        // the tombstones will cover, to remove exactly that from the rope.
+        //
         // CRITICAL: Must use document order (Fugue tree), NOT BTreeMap order!
-        // BTreeMap order is causal/timestamp order, which differs from document
-        // order when blocks from multiple clients are interleaved after merge.
-        let document_order = self.get_document_order();
-        for id in &document_order {
-            let block = match self.blocks.get(id) {
-                Some(b) => b,
-                None => continue,
-            };
-
-            if block.is_deleted() {
-                continue;
-            }
-
-            let block_len = block.len();
-            let block_start = current_pos;
-            let block_end = current_pos + block_len;
-
-            // Check if block overlaps deletion range
-            if block_start < position + length && block_end > position {
-                // Calculate overlap boundaries
-                let delete_start = position.max(block_start);
-                let delete_end = (position + length).min(block_end);
-                let offset_in_block_start = delete_start - block_start;
-                let offset_in_block_end = delete_end - block_start;
-
-                blocks_to_split.push((
-                    id.clone(),
-                    block.clone(),
-                    block_start,
-                    offset_in_block_start,
-                    offset_in_block_end,
-                ));
-            }
-
-            current_pos += block_len;
+        // The position cache holds the visible blocks in that order.
+        if !self.cache_valid {
+            self.rebuild_position_cache();
+            self.cache_valid = true;
         }
+        let (blocks_to_split, cached, chars_before, removed) =
+            self.overlaps_from_cache(position, length);
+        let rope_range =
+            self.rope_range_of_removal(position, length, self.len(), chars_before, &removed)?;
+
+        // The deletion gets a clock of its own, for `snapshot_at`
+        let stamp = NodeId::new(self.client_id.clone(), self.clock.tick(), 0);
 
-        // Second pass: split blocks and create new ones
-        for (orig_id, orig_block, _block_start, offset_start, offset_end) in blocks_to_split {
+        // Second pass: split blocks and create new ones, noting the visible
+        // pieces left either side of the range
+        let mut deleted_ids = Vec::new();
+        let mut kept = Vec::new();
+        for (orig_id, orig_block, block_start, offset_start, offset_end) in blocks_to_split {
             let block_len = orig_block.len();
+            if block_len == 0 {
+                kept.push((orig_id, position));
+                continue;
+            }
 
             // Check if we need to split (partial deletion)
             let needs_left_split = offset_start > 0;
             let needs_right_split = offset_end < block_len;
+            if needs_left_split {
+                let first_clock = orig_id.clock + 1 - block_len as u64;
+                let left_id = NodeId::new(
+                    orig_id.client_id.clone(),
+                    first_clock + offset_start as u64 - 1,
+                    0,
+                );
+                kept.push((left_id, block_start));
+            }
+            if needs_right_split {
+                kept.push((orig_id.clone(), position));
+            }
 
             if needs_left_split || needs_right_split {
                 // Block splitting: create up to 3 blocks
@@ -584,12 +777,13 @@ impl FugueText {
                     &orig_block,
                     offset_start,
                     offset_end,
+                    Some(&stamp),
                     &mut deleted_ids,
                 )?;
             } else {
                 // Entire block is deleted - just mark it
-                if let Some(block) = self.blocks.get_mut(&orig_id) {
-                    block.mark_deleted();
+                if let Some(block) = self.block_mut(&orig_id) {
+                    block.mark_deleted_at(stamp.clone());
                     deleted_ids.push(orig_id);
                 }
             }
@@ -597,22 +791,135 @@ impl FugueText {
 
         // 3. Delete from rope (O(log n))
         if !deleted_ids.is_empty() {
-            let byte_start = self.char_to_byte(position)?;
-            let byte_end = self.char_to_byte(position + length)?;
-            self.rope.remove(byte_start..byte_end);
-
-            // 4. Invalidate position cache (block splitting creates new blocks)
-            self.invalidate_position_cache(byte_start); // Rope cache separate
-            #[cfg(feature = "text-crdt")]
-            {
-                // Invalidate cache - block splitting changes the block structure
-                self.cache_valid = false;
-            }
+            self.rope.remove(rope_range);
+            self.units.remove(position, length);
+
+            // 4. Update the position cache for the split and deleted blocks
+            self.update_cache_after_delete(cached, kept, length);
+            self.history.record_delete(position, length);
         }
 
+        if length > 0 {
+            self.notifier.push(TextEvent::Delete { position, length });
+        }
+        self.rechunk_step();
         Ok(deleted_ids)
     }
 
+    /// Replace `delete_len` graphemes at `position` with `text`
+    ///
+    /// Does what `delete(position, delete_len)` followed by
+    /// `insert(position, text)` does, to the same blocks, but finds the
+    /// inserted text's origins before deleting so the position cache is
+    /// walked once. An empty `text` inserts nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::RangeOutOfBounds` if the range ends past the
+    /// text, or `TextError::LimitExceeded` if the text would outgrow its
+    /// limits; nothing is changed then.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello World").unwrap();
+    /// text.splice(6, 5, "there").unwrap();
+    ///
+    /// assert_eq!(text.to_string(), "Hello there");
+    /// ```
+    pub fn splice(
+        &mut self,
+        position: usize,
+        delete_len: usize,
+        text: &str,
+    ) -> Result<SpliceResult, TextError> {
+        let length = self.len();
+        let end = position
+            .checked_add(delete_len)
+            .filter(|end| *end <= length)
+            .ok_or(TextError::RangeOutOfBounds {
+                start: position,
+                end: position.saturating_add(delete_len),
+                length,
+            })?;
+        if !text.is_empty() {
+            self.check_insert_limits(text, delete_len)?;
+        }
+
+        // The characters either side of the range are the origins the
+        // insert would find once the range is gone
+        let origins = match text.is_empty() {
+            true => None,
+            false => {
+                let (left_origin, _) = self.find_origins(position)?;
+                let (_, right_origin) = self.find_origins(end)?;
+                Some((left_origin, right_origin))
+            }
+        };
+        let deleted = self.delete(position, delete_len)?;
+        let inserted = origins.map(|(left_origin, right_origin)| {
+            self.insert_between(position, text, left_origin, right_origin)
+        });
+        Ok(SpliceResult { deleted, inserted })
+    }
+
+    /// Replace the graphemes in `range` with `text` (see `splice`)
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::RangeOutOfBounds` if the range is reversed or
+    /// ends past the text.
+    pub fn replace_range(
+        &mut self,
+        range: std::ops::Range<usize>,
+        text: &str,
+    ) -> Result<SpliceResult, TextError> {
+        if range.start > range.end {
+            return Err(TextError::RangeOutOfBounds {
+                start: range.start,
+                end: range.end,
+                length: self.len(),
+            });
+        }
+        self.splice(range.start, range.len(), text)
+    }
+
+    /// Char range of the rope to remove along with a deletion's tombstones
+    ///
+    /// `removed` is the text of the graphemes `position..position + length`
+    /// of the blocks, starting `chars_before` chars into the rope; `units`
+    /// is how many graphemes the blocks hold. Fails unless the blocks
+    /// cover the whole range and the rope holds exactly `removed` there,
+    /// so the rope and the tombstones can't disagree about what went.
+    fn rope_range_of_removal(
+        &self,
+        position: usize,
+        length: usize,
+        units: usize,
+        chars_before: usize,
+        removed: &str,
+    ) -> Result<std::ops::Range<usize>, TextError> {
+        let out_of_bounds = || TextError::RangeOutOfBounds {
+            start: position,
+            end: position + length,
+            length: units,
+        };
+        if position + length > units {
+            return Err(out_of_bounds());
+        }
+        let char_end = chars_before + removed.chars().count();
+        if char_end > self.rope.len_chars() {
+            return Err(out_of_bounds());
+        }
+        if self.rope.slice(chars_before..char_end) != removed {
+            return Err(out_of_bounds());
+        }
+        Ok(chars_before..char_end)
+    }
+
     /// Split a block when deleting a portion of it (Clock-based IDs)
     ///
     /// Creates up to 3 blocks with clock-based IDs (all with offset=0):
@@ -636,21 +943,18 @@ impl FugueText {
     /// * `orig_block` - Original block to split
     /// * `offset_start` - Start offset within block (in graphemes)
     /// * `offset_end` - End offset within block (in graphemes)
+    /// * `deleted_at` - Stamp of the deletion, if known
     /// * `deleted_ids` - Vector to collect IDs of deleted blocks
-    #[cfg(feature = "text-crdt")]
     fn split_block_for_deletion(
         &mut self,
         orig_id: &NodeId,
         orig_block: &FugueBlock,
         offset_start: usize,
         offset_end: usize,
+        deleted_at: Option<&NodeId>,
         deleted_ids: &mut Vec<NodeId>,
     ) -> Result<(), TextError> {
-        use unicode_segmentation::UnicodeSegmentation;
-
-        // Split text into grapheme clusters
-        let graphemes: Vec<&str> = orig_block.text.graphemes(true).collect();
-        let block_len = graphemes.len();
+        let block_len = orig_block.len();
 
         // Validate offsets
         if offset_start >= block_len || offset_end > block_len || offset_start >= offset_end {
@@ -662,71 +966,29 @@ impl FugueText {
             });
         }
 
-        // Extract text segments
-        let left_text: String = graphemes[..offset_start].join("");
-        let middle_text: String = graphemes[offset_start..offset_end].join("");
-        let right_text: String = graphemes[offset_end..].join("");
-
-        // Calculate the block's starting clock value
-        // Block ID stores the LAST clock value, so start = end - len + 1
-        // Example: block@15:0 with len=15 → start_clock = 15 - 15 + 1 = 1
-        let block_start_clock = orig_id.clock - (block_len as u64) + 1;
-
-        // Remove original block (if it still exists)
         if !self.blocks.contains_key(orig_id) {
             // Block was already removed or doesn't exist
             return Ok(());
         }
-        self.blocks.remove(orig_id);
-
-        // IMPORTANT: All split blocks maintain the SAME origins as the original block!
-        // They represent parts of the same insert operation, so they have the same
-        // position in the Fugue tree. The clock ranges differentiate them.
-
-        // Create left block (if needed)
-        if !left_text.is_empty() {
-            let left_len = offset_start as u64;
-            let left_end_clock = block_start_clock + left_len - 1;
-            let left_id = NodeId::new(orig_id.client_id.clone(), left_end_clock, 0);
-
-            let left_block = FugueBlock::new(
-                left_id.clone(),
-                left_text,
-                orig_block.left_origin.clone(),  // Same as original!
-                orig_block.right_origin.clone(), // Same as original!
-            );
-            self.blocks.insert(left_id.clone(), left_block);
-        }
-
-        // Create middle block (deleted)
-        let _middle_len = (offset_end - offset_start) as u64;
-        let middle_end_clock = block_start_clock + offset_end as u64 - 1;
-        let middle_id = NodeId::new(orig_id.client_id.clone(), middle_end_clock, 0);
 
-        let mut middle_block = FugueBlock::new(
-            middle_id.clone(),
-            middle_text,
-            orig_block.left_origin.clone(),  // Same as original!
-            orig_block.right_origin.clone(), // Same as original!
+        // Split at the end of the range, then at its start; the piece in
+        // between is the deleted middle
+        let block_start_clock = orig_id.clock - (block_len as u64) + 1;
+        let middle_id = NodeId::new(
+            orig_id.client_id.clone(),
+            block_start_clock + offset_end as u64 - 1,
+            0,
         );
-        middle_block.mark_deleted();
-        self.blocks.insert(middle_id.clone(), middle_block);
-        deleted_ids.push(middle_id.clone());
-
-        // Create right block (if needed)
-        if !right_text.is_empty() {
-            let _right_len = (block_len - offset_end) as u64;
-            let right_end_clock = block_start_clock + block_len as u64 - 1;
-            let right_id = NodeId::new(orig_id.client_id.clone(), right_end_clock, 0);
-
-            let right_block = FugueBlock::new(
-                right_id.clone(),
-                right_text,
-                orig_block.left_origin.clone(),  // Same as original!
-                orig_block.right_origin.clone(), // Same as original!
-            );
-            self.blocks.insert(right_id, right_block);
+        self.split_block_at(orig_id, offset_end);
+        self.split_block_at(&middle_id, offset_start);
+
+        if let Some(middle) = self.block_mut(&middle_id) {
+            match deleted_at {
+                Some(stamp) => middle.mark_deleted_at(stamp.clone()),
+                None => middle.mark_deleted(),
+            }
         }
+        deleted_ids.push(middle_id);
 
         Ok(())
     }
@@ -794,7 +1056,7 @@ impl FugueText {
 
         let search_result = self.cached_blocks.binary_search_by(|id| {
             let block = &self.blocks[id];
-            let block_start = block.cached_position().unwrap_or(0);
+            let block_start = self.cached_position(id).unwrap_or(0);
             let block_end = block_start + block.len();
 
             if position < block_start {
@@ -811,7 +1073,7 @@ impl FugueText {
             Ok(idx) => {
                 let block_id = &self.cached_blocks[idx];
                 let block = &self.blocks[block_id];
-                let block_start = block.cached_position().unwrap_or(0);
+                let block_start = self.cached_position(block_id).unwrap_or(0);
                 let offset_in_block = position - block_start;
 
                 // Calculate the actual clock value for this character
@@ -892,7 +1154,7 @@ impl FugueText {
             }
 
             // 5. Get block's starting position and add offset
-            if let Some(block_start) = block.cached_position() {
+            if let Some(block_start) = self.cached_position(&block_id) {
                 return Some(block_start + offset_in_block);
             }
         }
@@ -901,6 +1163,30 @@ impl FugueText {
         None
     }
 
+    /// Where a character is, even if it was deleted
+    ///
+    /// Returns the number of visible characters before it and whether it
+    /// is still visible, or None if this replica has never seen it.
+    pub(super) fn locate_node_id(&mut self, node_id: &NodeId) -> Option<(usize, bool)> {
+        if let Some(position) = self.get_position_of_node_id(node_id) {
+            return Some((position, true));
+        }
+
+        // Deleted: count the visible text before its block
+        let block_id = self.find_block_for_nodeid(node_id)?;
+        let mut before = 0;
+        for id in self.document_order_with_tombstones() {
+            if id == block_id {
+                return Some((before, false));
+            }
+            let block = &self.blocks[&id];
+            if !block.is_deleted() {
+                before += block.len();
+            }
+        }
+        None
+    }
+
     /// Merge with another FugueText replica
     ///
     /// Merges remote blocks into local state, ensuring convergence.
@@ -910,6 +1196,13 @@ impl FugueText {
     ///
     /// * `remote` - Remote FugueText to merge
     ///
+    /// # Errors
+    ///
+    /// Returns `TextError::ReplicaConflict`, leaving this replica
+    /// untouched, if `remote` holds different text under one of the
+    /// character IDs we have, or `TextError::LimitExceeded`, likewise, if
+    /// its new blocks would take this text past its limits
+    ///
     /// # Example
     ///
     /// ```rust
@@ -927,16 +1220,51 @@ impl FugueText {
     /// // Both converge to same result
     /// assert_eq!(text1.to_string(), text2.to_string());
     /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "FugueText::merge",
+            skip_all,
+            fields(
+                client_id = %self.client_id,
+                local_blocks = self.blocks.len(),
+                remote_blocks = remote.blocks.len(),
+                blocks_after = tracing::field::Empty,
+                rope = tracing::field::Empty,
+            )
+        )
+    )]
     pub fn merge(&mut self, remote: &FugueText) -> Result<(), TextError> {
-        // Phase 1: Split-to-match normalization.
-        // When remote has the same block ID but shorter text, it means remote
-        // split the block (via delete). We must split our local block to match,
-        // creating explicit blocks for the split-off characters. This prevents
-        // the truncation approach from silently losing characters.
-        // Iterate until no more splits are needed (splits can cascade).
+        self.check_replica_conflicts(remote.blocks.values().map(Arc::as_ref))?;
+        self.check_remote_limits(remote.blocks.iter().map(|(id, block)| (id, block.as_ref())))?;
+        self.touch();
+        let before = self.text_before_change();
+        let visible = self.visible_runs();
+        self.split_to_match(remote);
+        self.merge_blocks(remote, |text, id, block| {
+            text.classify_remote_block(id, block, |start, end| {
+                text.overlaps_local_clock_range(&id.client_id, start, end)
+            })
+        });
+        self.annotations.merge(&remote.annotations);
+        match self.update_rope(visible) {
+            Some(events) if before.is_some() => self.notifier.extend(events),
+            _ => self.notify_changes_since(before),
+        }
+        Ok(())
+    }
+
+    /// Phase 1 of `merge`: split-to-match normalization.
+    ///
+    /// When remote has the same block ID but shorter text, it means remote
+    /// split the block (via delete). We must split our local block to match,
+    /// creating explicit blocks for the split-off characters. This prevents
+    /// the truncation approach from silently losing characters.
+    /// Iterate until no more splits are needed (splits can cascade).
+    pub(super) fn split_to_match(&mut self, remote: &FugueText) {
         loop {
             let mut splits_needed: Vec<(NodeId, usize)> = Vec::new();
-            for (remote_id, remote_block) in &remote.blocks {
+            for (remote_id, remote_block) in remote.blocks.iter() {
                 if let Some(local_block) = self.blocks.get(remote_id) {
                     if remote_block.len() < local_block.len() {
                         splits_needed.push((remote_id.clone(), remote_block.len()));
@@ -947,84 +1275,271 @@ impl FugueText {
                 break;
             }
             for (block_id, keep_right_len) in splits_needed {
+                trace_debug!(block = %block_id, keep_right_len, "splitting block to match remote");
                 self.split_block_to_match(&block_id, keep_right_len);
             }
         }
+    }
+
+    /// Decide what phase 2 of `merge` does with one remote block
+    ///
+    /// `overlaps(start, end)` reports whether a local block from the same
+    /// client covers part of that clock range.
+    pub(super) fn classify_remote_block(
+        &self,
+        remote_id: &NodeId,
+        remote_block: &FugueBlock,
+        overlaps: impl Fn(u64, u64) -> bool,
+    ) -> RemoteBlock {
+        if self.blocks.contains_key(remote_id) {
+            return RemoteBlock::Known;
+        }
+        let remote_len = remote_block.len() as u64;
+        if remote_len == 0 {
+            return RemoteBlock::Empty;
+        }
+        let remote_start = remote_id.clock.saturating_sub(remote_len - 1);
+        if overlaps(remote_start, remote_id.clock) {
+            RemoteBlock::SplitPiece {
+                start: remote_start,
+            }
+        } else if remote_id.clock <= self.collected.get(&remote_id.client_id) {
+            RemoteBlock::Collected
+        } else {
+            RemoteBlock::New
+        }
+    }
 
+    /// Phases 2-5 of `merge`, with `classify` deciding each remote block
+    pub(super) fn merge_blocks(
+        &mut self,
+        remote: &FugueText,
+        classify: impl Fn(&Self, &NodeId, &FugueBlock) -> RemoteBlock,
+    ) {
         // Phase 2: Merge remote blocks into local.
-        // After normalization, same-ID blocks have matching lengths.
-        // New remote blocks either overlap local blocks (skip + propagate deletion)
-        // or are genuinely new (insert).
-        let mut deletions_to_propagate: Vec<(String, u64, u64)> = Vec::new();
-
-        for (remote_id, remote_block) in &remote.blocks {
-            match self.blocks.get_mut(remote_id) {
-                Some(local_block) => {
-                    // Block exists locally (same ID, same length after normalization)
-                    // Merge deletion status: deleted in remote → delete locally
-                    if remote_block.is_deleted() && !local_block.is_deleted() {
-                        local_block.mark_deleted();
-                    }
+        let mut pending = PendingMerge::default();
+        for (remote_id, remote_block) in remote.blocks.iter() {
+            let action = classify(self, remote_id, remote_block);
+            self.integrate_remote_block(remote_id, remote_block, action, &mut pending);
+        }
+
+        self.finish_merge(pending, remote.highest_clock());
+        self.marks.merge(&remote.marks);
+        // Having all the remote has, we have seen what it collected
+        self.collected.merge(&remote.collected);
+    }
+
+    /// The highest clock this text has seen: its own clock, which counts
+    /// ticks that left no block (a deletion that found nothing left to
+    /// delete), and the clocks of every insert, deletion and mark
+    ///
+    /// A merge fast-forwards past all of them, so the next local operation
+    /// is ordered after everything the remote had done.
+    pub(super) fn highest_clock(&self) -> u64 {
+        let blocks = self.blocks.values().flat_map(|block| {
+            let deleted_at = block.deleted_at.as_ref().map(|stamp| stamp.clock);
+            std::iter::once(block.id.clock).chain(deleted_at)
+        });
+        blocks
+            .chain(self.marks.iter().map(|mark| mark.clock))
+            .fold(self.clock.value(), u64::max)
+    }
+
+    /// Phase 2 of `merge` for one remote block
+    ///
+    /// After normalization, same-ID blocks have matching lengths. New
+    /// remote blocks either overlap local blocks (skip + propagate
+    /// deletion) or are genuinely new (insert).
+    pub(super) fn integrate_remote_block(
+        &mut self,
+        remote_id: &NodeId,
+        remote_block: &FugueBlock,
+        action: RemoteBlock,
+        pending: &mut PendingMerge,
+    ) {
+        match action {
+            RemoteBlock::Known => {
+                // Block exists locally (same ID, same length after normalization)
+                // Merge deletion status: deleted in remote → delete locally
+                let local_len = self
+                    .merge_remote_deletion(remote_id, remote_block)
+                    .unwrap_or(0);
+                // We split the block further than remote did: its
+                // deletion covers our pieces to the left too
+                let remote_len = remote_block.len() as u64;
+                if remote_block.is_deleted() && remote_len > local_len {
+                    pending.deletions.push(DeleteRange {
+                        client_id: remote_id.client_id.clone(),
+                        start: remote_id.clock.saturating_sub(remote_len - 1),
+                        end: remote_id.clock,
+                        deleted_at: remote_block.deleted_at.clone(),
+                    });
                 }
-                None => {
-                    // Block doesn't exist locally. Check if its clock range overlaps
-                    // with any local block from the same client (split piece).
-                    let remote_len = remote_block.len() as u64;
-                    if remote_len == 0 {
-                        self.blocks.insert(remote_id.clone(), remote_block.clone());
-                        continue;
-                    }
-                    let remote_start = remote_id.clock.saturating_sub(remote_len - 1);
-                    let remote_end = remote_id.clock;
-
-                    let overlaps_local = self.blocks.iter().any(|(local_id, local_block)| {
-                        if local_id.client_id != remote_id.client_id {
-                            return false;
-                        }
-                        let local_len = local_block.len() as u64;
-                        if local_len == 0 {
-                            return false;
-                        }
-                        let local_start = local_id.clock.saturating_sub(local_len - 1);
-                        let local_end = local_id.clock;
-                        remote_start <= local_end && local_start <= remote_end
+            }
+            RemoteBlock::Empty => {
+                self.store_remote_block(remote_block.clone());
+            }
+            RemoteBlock::Collected => {
+                trace_debug!(block = %remote_id, "dropping a collected tombstone");
+            }
+            RemoteBlock::SplitPiece { start } => {
+                // Split piece — don't insert what we have (would duplicate text).
+                // If remote deleted it, propagate deletion to local blocks.
+                trace_debug!(block = %remote_id, "skipping split piece of a known block");
+                if remote_block.is_deleted() {
+                    pending.deletions.push(DeleteRange {
+                        client_id: remote_id.client_id.clone(),
+                        start,
+                        end: remote_id.clock,
+                        deleted_at: remote_block.deleted_at.clone(),
                     });
-
-                    if overlaps_local {
-                        // Split piece — don't insert (would duplicate text).
-                        // If remote deleted it, propagate deletion to local blocks.
-                        if remote_block.is_deleted() {
-                            deletions_to_propagate.push((
-                                remote_id.client_id.clone(),
-                                remote_start,
-                                remote_end,
-                            ));
-                        }
-                    } else {
-                        // Genuinely new block from remote
-                        self.blocks.insert(remote_id.clone(), remote_block.clone());
-                    }
                 }
+                if let Some(tail) = self.missing_tail(remote_id, remote_block) {
+                    trace_debug!(block = %remote_id, len = tail.len(), "integrating the new tail of a re-chunked block");
+                    self.store_remote_block(tail);
+                    pending.integrated.push(remote_id.clone());
+                }
+            }
+            RemoteBlock::New => {
+                // Genuinely new block from remote
+                trace_debug!(block = %remote_id, len = remote_block.len(), "integrating remote block");
+                self.store_remote_block(remote_block.clone());
+                pending.integrated.push(remote_id.clone());
             }
         }
+    }
 
+    /// Phases 3-5 of `merge`, once every remote block went through
+    /// `integrate_remote_block`
+    pub(super) fn finish_merge(&mut self, pending: PendingMerge, remote_max_clock: u64) {
         // Phase 3: Propagate deletions for overlapping split blocks.
-        for (client_id, del_start, del_end) in deletions_to_propagate {
-            self.propagate_clock_range_deletion(&client_id, del_start, del_end);
+        for range in pending.deletions {
+            self.propagate_clock_range_deletion(
+                &range.client_id,
+                range.start,
+                range.end,
+                range.deleted_at.as_ref(),
+            );
         }
 
-        // Phase 4: Rebuild rope from blocks
-        self.rebuild_rope();
+        // Phase 4: Split blocks that new blocks were inserted into the
+        // middle of (the caller brings the rope up to date)
+        self.split_at_new_block_origins(&pending.integrated);
+        trace_record!("blocks_after", self.blocks.len());
+
+        // Phase 5: Update Lamport clock. This also fast-forwards past our
+        // own inserts that we lost by restoring an old snapshot, so the
+        // next local insert can't reuse their IDs.
+        self.clock.update(remote_max_clock);
+    }
+
+    /// Check whether any local block from `client_id` covers part of the
+    /// clock range `start..=end`.
+    ///
+    /// Remote blocks whose range overlaps a local block are split pieces of
+    /// an insert we already have, so inserting them would duplicate text.
+    pub(super) fn overlaps_local_clock_range(&self, client_id: &str, start: u64, end: u64) -> bool {
+        self.blocks.iter().any(|(local_id, local_block)| {
+            if local_id.client_id != client_id {
+                return false;
+            }
+            let local_len = local_block.len() as u64;
+            if local_len == 0 {
+                return false;
+            }
+            let local_start = local_id.clock.saturating_sub(local_len - 1);
+            let local_end = local_id.clock;
+            start <= local_end && local_start <= end
+        })
+    }
 
-        // Phase 5: Update Lamport clock
-        let remote_max_clock = remote
+    /// The part of a remote block past the last of its clocks we have
+    ///
+    /// A replica that re-chunked its blocks can send one block joining
+    /// characters we already have to characters typed after them. The new
+    /// characters come back as the right piece `split_block_at` would
+    /// have cut: the remote block's ID and right origin, anchored after
+    /// the last character we have. None if we have the whole block.
+    pub(super) fn missing_tail(
+        &self,
+        remote_id: &NodeId,
+        remote_block: &FugueBlock,
+    ) -> Option<FugueBlock> {
+        let remote_len = remote_block.len() as u64;
+        if remote_len == 0 || remote_len > remote_id.clock {
+            return None;
+        }
+        let remote_start = remote_id.clock + 1 - remote_len;
+        // Clocks up to the floor were collected here, not lost
+        let floor = self.collected.get(&remote_id.client_id);
+        if remote_id.clock <= floor {
+            return None;
+        }
+        let covered = self
             .blocks
-            .values()
-            .map(|b| b.id.clock)
+            .iter()
+            .filter(|(local_id, local_block)| {
+                let local_len = local_block.len() as u64;
+                local_id.client_id == remote_id.client_id
+                    && local_len > 0
+                    && local_id.clock.saturating_sub(local_len - 1) <= remote_id.clock
+                    && remote_start <= local_id.clock
+            })
+            .map(|(local_id, _)| local_id.clock)
             .max()
-            .unwrap_or(0);
-        self.clock.update(remote_max_clock);
+            .or((floor >= remote_start).then_some(floor))?
+            .max(floor);
+        if covered >= remote_id.clock {
+            return None;
+        }
+
+        let offset = (covered + 1 - remote_start) as usize;
+        let text: String = remote_block.text.graphemes(true).skip(offset).collect();
+        let mut tail = FugueBlock::new(
+            remote_id.clone(),
+            text,
+            Some(NodeId::new(remote_id.client_id.clone(), covered, 0)),
+            remote_block.right_origin.clone(),
+        );
+        tail.merge_deletion(remote_block);
+        Some(tail)
+    }
+
+    /// Fail with `TextError::ReplicaConflict` if a remote block holds
+    /// different text than we do under the same character IDs
+    ///
+    /// Blocks may be split differently on each side, so characters are
+    /// compared clock by clock over the overlap of each pair of ranges.
+    pub(super) fn check_replica_conflicts<'a>(
+        &self,
+        remote: impl IntoIterator<Item = &'a FugueBlock>,
+    ) -> Result<(), TextError> {
+        // Clock ranges of non-empty local blocks, per client
+        let mut local: HashMap<&str, Vec<(u64, &FugueBlock)>> = HashMap::new();
+        for (id, block) in self.blocks.iter() {
+            let len = block.len() as u64;
+            if len > 0 {
+                local
+                    .entry(id.client_id.as_str())
+                    .or_default()
+                    .push((id.clock + 1 - len, block));
+            }
+        }
 
+        for remote_block in remote {
+            let remote_len = remote_block.len() as u64;
+            let Some(ranges) = local.get(remote_block.id.client_id.as_str()) else {
+                continue;
+            };
+            if remote_len == 0 || remote_len > remote_block.id.clock {
+                continue;
+            }
+            let remote_start = remote_block.id.clock + 1 - remote_len;
+
+            for &(local_start, local_block) in ranges {
+                check_block_conflict(local_block, local_start, remote_block, remote_start)?;
+            }
+        }
         Ok(())
     }
 
@@ -1033,54 +1548,170 @@ impl FugueText {
     ///
     /// This is used during merge normalization when a remote replica has split
     /// a block (via delete) and our local copy still has the larger unsplit version.
-    #[cfg(feature = "text-crdt")]
-    fn split_block_to_match(&mut self, block_id: &NodeId, keep_right_len: usize) {
+    pub(super) fn split_block_to_match(&mut self, block_id: &NodeId, keep_right_len: usize) {
+        let block_len = match self.blocks.get(block_id) {
+            Some(b) => b.len(),
+            None => return,
+        };
+        if keep_right_len < block_len {
+            self.split_block_at(block_id, block_len - keep_right_len);
+        }
+    }
+
+    /// Split local blocks at the origins of newly integrated blocks, and
+    /// the new blocks at the origins of blocks that arrived before them
+    /// (operations applied out of order)
+    pub(super) fn split_at_new_block_origins(&mut self, new_blocks: &[NodeId]) {
+        // Clock ranges of the new blocks, per client
+        let mut ranges: HashMap<&str, Vec<(u64, u64)>> = HashMap::new();
+        for id in new_blocks {
+            if let Some(block) = self.blocks.get(id).filter(|block| !block.is_empty()) {
+                let start = id.clock.saturating_sub(block.len() as u64 - 1);
+                ranges
+                    .entry(id.client_id.as_str())
+                    .or_default()
+                    .push((start, id.clock));
+            }
+        }
+        for client_ranges in ranges.values_mut() {
+            client_ranges.sort_unstable();
+        }
+        let in_new_block = |origin: &Option<NodeId>| {
+            origin.as_ref().is_some_and(|origin| {
+                ranges
+                    .get(origin.client_id.as_str())
+                    .is_some_and(|client_ranges| {
+                        let next =
+                            client_ranges.partition_point(|&(start, _)| start <= origin.clock);
+                        next > 0 && client_ranges[next - 1].1 >= origin.clock
+                    })
+            })
+        };
+
+        // Collect every origin first: a split hands the block's origins to
+        // its left piece
+        let mut origins: Vec<(Option<NodeId>, Option<NodeId>)> = new_blocks
+            .iter()
+            .filter_map(|id| self.blocks.get(id))
+            .map(|b| (b.left_origin.clone(), b.right_origin.clone()))
+            .collect();
+        origins.extend(
+            self.blocks
+                .values()
+                .filter(|b| in_new_block(&b.left_origin) || in_new_block(&b.right_origin))
+                .map(|b| (b.left_origin.clone(), b.right_origin.clone())),
+        );
+        for (left, right) in origins {
+            self.split_at_origins(left.as_ref(), right.as_ref());
+        }
+        self.cache_valid = false;
+    }
+
+    /// Split a block after its first `offset` graphemes
+    ///
+    /// The left part becomes a new block whose ID ends at the last clock it
+    /// covers and keeps the original origins. The right part keeps the
+    /// original ID and is re-anchored with the left part's last character
+    /// as its left origin, so the two stay adjacent in the Fugue tree even
+    /// when other blocks share the original origins. Splitting is therefore
+    /// order-independent: replicas that split the same block at the same
+    /// points end up with identical blocks.
+    ///
+    /// Returns false (and changes nothing) if `offset` is not strictly
+    /// inside the block.
+    fn split_block_at(&mut self, block_id: &NodeId, offset: usize) -> bool {
         use unicode_segmentation::UnicodeSegmentation;
 
         let block = match self.blocks.get(block_id) {
-            Some(b) => b.clone(),
-            None => return,
+            Some(b) => b,
+            None => return false,
         };
-
         let block_len = block.len();
-        if keep_right_len >= block_len || block_len == 0 {
-            return;
+        if offset == 0 || offset >= block_len {
+            return false;
         }
 
-        let graphemes: Vec<&str> = block.text.graphemes(true).collect();
-        let split_offset = block_len - keep_right_len;
-
-        let left_text: String = graphemes[..split_offset].join("");
-        let right_text: String = graphemes[split_offset..].join("");
+        let (left_text, right_text) = if block.one_unit_per_byte() {
+            let (left, right) = block.text.split_at(offset);
+            (left.to_string(), right.to_string())
+        } else {
+            let graphemes: Vec<&str> = block.text.graphemes(true).collect();
+            (graphemes[..offset].join(""), graphemes[offset..].join(""))
+        };
 
+        // Block ID stores the LAST clock value, so start = end - len + 1
         let block_start_clock = block_id.clock - (block_len as u64) + 1;
+        let left_id = NodeId::new(
+            block_id.client_id.clone(),
+            block_start_clock + offset as u64 - 1,
+            0,
+        );
 
-        // Create left block (split-off portion)
-        let left_end_clock = block_start_clock + split_offset as u64 - 1;
-        let left_id = NodeId::new(block_id.client_id.clone(), left_end_clock, 0);
         let mut left_block = FugueBlock::new(
             left_id.clone(),
             left_text,
             block.left_origin.clone(),
             block.right_origin.clone(),
         );
-        // Preserve deletion status on split
-        if block.is_deleted() {
-            left_block.mark_deleted();
+        left_block.merge_deletion(block);
+
+        if let Some(orig) = self.block_mut(block_id) {
+            orig.text = right_text.into();
+            orig.left_origin = Some(left_id.clone());
+        }
+        self.blocks_mut().insert(left_id, Arc::new(left_block));
+        true
+    }
+
+    /// Split the blocks containing the given origins so that `left` is the
+    /// last character of a block and `right` the first character of one
+    ///
+    /// Origins can point into the middle of a block (an insert inside an
+    /// RLE run). The Fugue tree is built from whole blocks, so the
+    /// containing block has to be split at the origin for the insert to land
+    /// between the right characters. Returns true if anything was split.
+    pub(super) fn split_at_origins(
+        &mut self,
+        left: Option<&NodeId>,
+        right: Option<&NodeId>,
+    ) -> bool {
+        let mut split = false;
+
+        if let Some(origin) = left {
+            if let Some(block_id) = self.find_block_for_nodeid(origin) {
+                let block_len = self.blocks[&block_id].len() as u64;
+                let block_start_clock = block_id.clock - block_len + 1;
+                let offset = (origin.clock - block_start_clock + 1) as usize;
+                split |= self.split_block_at(&block_id, offset);
+            }
         }
-        self.blocks.insert(left_id, left_block);
 
-        // Update original block to keep only the right portion
-        if let Some(orig) = self.blocks.get_mut(block_id) {
-            orig.text = right_text;
+        if let Some(origin) = right {
+            if let Some(block_id) = self.find_block_for_nodeid(origin) {
+                let block_len = self.blocks[&block_id].len() as u64;
+                let block_start_clock = block_id.clock - block_len + 1;
+                let offset = (origin.clock - block_start_clock) as usize;
+                split |= self.split_block_at(&block_id, offset);
+            }
         }
+
+        split
     }
 
     /// Propagate deletion from a remote split block to overlapping local blocks.
     ///
     /// When remote deleted characters that local still has in a larger block,
-    /// we split the local block and mark the deleted portion.
-    fn propagate_clock_range_deletion(&mut self, client_id: &str, del_start: u64, del_end: u64) {
+    /// we split the local block and mark the deleted portion. Tombstones
+    /// take the deletion's stamp `deleted_at` if it is earlier than theirs.
+    pub(super) fn propagate_clock_range_deletion(
+        &mut self,
+        client_id: &str,
+        del_start: u64,
+        del_end: u64,
+        deleted_at: Option<&NodeId>,
+    ) {
+        let deleted_at = deleted_at.map(|stamp| self.clients.intern_node(stamp));
+        let deleted_at = deleted_at.as_ref();
         let block_ids: Vec<NodeId> = self
             .blocks
             .keys()
@@ -1094,7 +1725,13 @@ impl FugueText {
                 None => continue,
             };
 
-            if block.is_deleted() {
+            // Already deleted, and no earlier stamp to take
+            let restamps = match (&block.deleted_at, deleted_at) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(ours), Some(theirs)) => theirs < ours,
+            };
+            if block.is_deleted() && !restamps {
                 continue;
             }
 
@@ -1118,8 +1755,11 @@ impl FugueText {
 
             if offset_start == 0 && offset_end as u64 == block_len {
                 // Entire block should be deleted
-                if let Some(b) = self.blocks.get_mut(&block_id) {
-                    b.mark_deleted();
+                if let Some(b) = self.block_mut(&block_id) {
+                    match deleted_at {
+                        Some(stamp) => b.mark_deleted_at(stamp.clone()),
+                        None => b.mark_deleted(),
+                    }
                 }
             } else {
                 // Partial deletion — split the block and delete the middle
@@ -1129,6 +1769,7 @@ impl FugueText {
                     &block,
                     offset_start,
                     offset_end,
+                    deleted_at,
                     &mut deleted_ids,
                 );
             }
@@ -1138,7 +1779,7 @@ impl FugueText {
     /// Find CRDT origins for insertion at given position (Phase 1.5 optimized)
     ///
     /// **Phase 1.5 Optimization: Binary Search O(log n)**
-    /// - Uses cached_start_pos for O(log n) binary search
+    /// - Uses cached_positions for O(log n) binary search
     /// - Uses cached_blocks vector (O(1) access, no O(n) allocation!)
     /// - Lazy cache rebuild if invalid (O(1) check via cache_valid flag)
     /// - Reduces complexity from O(n²) → O(n log n) for sequential ops
@@ -1168,7 +1809,7 @@ impl FugueText {
         // Binary search using cached positions (O(log n))
         let search_result = self.cached_blocks.binary_search_by(|id| {
             let block = &self.blocks[id];
-            let block_start = block.cached_position().unwrap();
+            let block_start = self.cached_position(id).unwrap();
             let block_end = block_start + block.len();
 
             if grapheme_pos < block_start {
@@ -1188,7 +1829,7 @@ impl FugueText {
                 // Found exact block containing position
                 let id = &self.cached_blocks[idx];
                 let block = &self.blocks[id];
-                let block_start = block.cached_position().unwrap();
+                let block_start = self.cached_position(id).unwrap();
                 let block_end = block_start + block.len();
 
                 if grapheme_pos == block_start {
@@ -1268,329 +1909,77 @@ impl FugueText {
                         right_id.client_id.clone(),
                         right_start_clock,
                         0,
-                    ));
-                }
-            }
-        }
-
-        Ok((left_origin, right_origin))
-    }
-
-    /// Convert grapheme position to byte position (for rope operations)
-    fn char_to_byte(&self, char_pos: usize) -> Result<usize, TextError> {
-        if char_pos > self.rope.len_chars() {
-            return Err(TextError::PositionOutOfBounds {
-                position: char_pos,
-                length: self.rope.len_chars(),
-            });
-        }
-        Ok(self.rope.char_to_byte(char_pos))
-    }
-
-    /// Invalidate position cache for blocks after given byte position
-    fn invalidate_position_cache(&mut self, from_byte_pos: usize) {
-        for block in self.blocks.values_mut() {
-            if let Some(rope_pos) = block.rope_position() {
-                if rope_pos >= from_byte_pos {
-                    block.invalidate_rope_position();
-                }
-            }
-        }
-    }
-
-    /// Rebuild rope from scratch (Phase 1: simple O(n) implementation)
-    ///
-    /// This is used after merge to ensure rope matches CRDT state.
-    /// Phase 2 optimization: incremental updates instead of full rebuild.
-    fn rebuild_rope(&mut self) {
-        // CRITICAL: Build text in DOCUMENT ORDER (Fugue tree), NOT BTreeMap order!
-        // BTreeMap order is causal/timestamp order, which differs from document
-        // order in concurrent scenarios.
-        let document_order = self.get_document_order();
-        let mut text = String::new();
-
-        for id in document_order {
-            if let Some(block) = self.blocks.get(&id) {
-                if !block.is_deleted() {
-                    text.push_str(&block.text);
-                }
-            }
-        }
-
-        // Replace rope
-        self.rope = Rope::from_str(&text);
-
-        // Invalidate all position caches (Phase 1.5: O(1) flag + O(n) rope invalidation)
-        for block in self.blocks.values_mut() {
-            block.invalidate_rope_position();
-        }
-        self.cache_valid = false; // Mark cache as stale
-    }
-
-    /// Get blocks in document order using Fugue tree traversal.
-    ///
-    /// CRITICAL: This is the ONLY correct way to determine character positions.
-    /// BTreeMap iteration gives causal/timestamp order, NOT document order.
-    ///
-    /// # Algorithm
-    /// 1. Reconstruct Fugue tree from left_origin/right_origin metadata
-    /// 2. Perform in-order traversal of the tree
-    /// 3. Return NodeIds in document order
-    ///
-    /// # Complexity
-    /// - Time: O(n²) for tree reconstruction, O(n) for traversal
-    /// - Space: O(n) for tree storage
-    ///
-    /// # Returns
-    /// Vector of NodeIds in document order (how characters appear in text)
-    fn get_document_order(&self) -> Vec<NodeId> {
-        // Step 1: Reconstruct the Fugue tree
-        let tree = self.reconstruct_fugue_tree();
-
-        // Step 2: In-order traversal to get document order
-        self.in_order_traversal(&tree)
-    }
-
-    /// Find the block that contains a given character-level NodeId.
-    ///
-    /// With per-character clock allocation, each block represents a RANGE of clock values,
-    /// not just a single clock. Block IDs always have offset=0.
-    ///
-    /// # Clock Range Calculation
-    ///
-    /// A block with ID `client@5:0` and length=3 contains characters at clocks [3, 4, 5]:
-    /// - block_start_clock = 5 - 3 + 1 = 3
-    /// - block_end_clock = 5
-    ///
-    /// # Arguments
-    /// * `node_id` - Character-level NodeId pointing to a specific clock value
-    ///
-    /// # Returns
-    /// Block ID that contains this clock value, None if not found
-    fn find_block_for_nodeid(&self, node_id: &NodeId) -> Option<NodeId> {
-        // Find block with matching client_id whose clock range contains node_id.clock
-        for (block_id, block) in &self.blocks {
-            if block_id.client_id == node_id.client_id {
-                // Block represents clock range [start_clock, end_clock]
-                // Example: block@5:0 with len=3 → clocks [3, 4, 5]
-                let block_len = block.len() as u64;
-                if block_len == 0 {
-                    continue; // Empty blocks don't contain any characters
-                }
-
-                let block_start_clock = block_id.clock.saturating_sub(block_len - 1);
-                let block_end_clock = block_id.clock;
-
-                if node_id.clock >= block_start_clock && node_id.clock <= block_end_clock {
-                    return Some(block_id.clone());
+                    ));
                 }
             }
         }
-        None
+
+        Ok((left_origin, right_origin))
     }
 
-    /// Reconstruct the Fugue tree from left_origin and right_origin metadata.
-    ///
-    /// This builds an explicit tree structure with parent-child relationships
-    /// from the implicit tree encoded in left_origin/right_origin fields.
-    ///
-    /// **NOTE**: This works at BLOCK level. Blocks are the atomic units in the tree.
-    /// Character-level NodeIds in origins are mapped to their containing blocks.
-    ///
-    /// # Algorithm
-    /// Process blocks in timestamp order (NodeId order). For each block:
-    /// 1. Map character-level origins to their containing blocks
-    /// 2. Check if left_origin block is an ancestor of right_origin block
-    /// 3. If YES: block becomes left child of right_origin block
-    /// 4. If NO: block becomes right child of left_origin block
-    ///
-    /// # Returns
-    /// HashMap mapping NodeId → TreeNode with parent/side information
-    fn reconstruct_fugue_tree(&self) -> HashMap<NodeId, TreeNode> {
-        let mut tree = HashMap::new();
-
-        // Process blocks in timestamp order (critical for ancestor checks)
-        let mut sorted_blocks: Vec<_> = self.blocks.iter().collect();
-        sorted_blocks.sort_by_key(|(id, _)| *id);
-
-        for (id, block) in sorted_blocks {
-            // Map character-level NodeIds to their containing blocks
-            let left_block = block
-                .left_origin
-                .as_ref()
-                .and_then(|node_id| self.find_block_for_nodeid(node_id));
-            let right_block = block
-                .right_origin
-                .as_ref()
-                .and_then(|node_id| self.find_block_for_nodeid(node_id));
-
-            let (parent, side) = self.determine_parent_and_side(&left_block, &right_block, &tree);
-
-            tree.insert(
-                id.clone(),
-                TreeNode {
-                    id: id.clone(),
-                    parent,
-                    side,
-                    deleted: block.is_deleted(),
-                },
-            );
-        }
+    /// Start position of block `id`, if the position cache holds it
+    pub(super) fn cached_position(&self, id: &NodeId) -> Option<usize> {
+        self.cached_positions.get(id).copied()
+    }
 
-        tree
+    /// Blocks for writing, copied first if a clone still shares them
+    pub(super) fn blocks_mut(&mut self) -> &mut BTreeMap<NodeId, Arc<FugueBlock>> {
+        Arc::make_mut(&mut self.blocks)
     }
 
-    /// Determine parent and side for a new node based on Fugue algorithm.
-    ///
-    /// # Fugue Rule
-    /// When inserting between positions a and b:
-    /// - If a is NOT an ancestor of b: new node is right child of a
-    /// - If a IS an ancestor of b: new node is left child of b
-    ///
-    /// # Arguments
-    /// * `left_origin` - Block to the left (a)
-    /// * `right_origin` - Block to the right (b)
-    /// * `tree` - Partial tree built from earlier blocks
-    fn determine_parent_and_side(
-        &self,
-        left_origin: &Option<NodeId>,
-        right_origin: &Option<NodeId>,
-        tree: &HashMap<NodeId, TreeNode>,
-    ) -> (Option<NodeId>, Side) {
-        match (left_origin, right_origin) {
-            // No origins: root node (first block ever inserted)
-            (None, None) => (None, Side::Right),
-
-            // Only left origin: insert at end
-            (Some(a), None) => (Some(a.clone()), Side::Right),
-
-            // Only right origin: insert at start
-            (None, Some(b)) => (Some(b.clone()), Side::Left),
-
-            // Both origins: check ancestor relationship
-            (Some(a), Some(b)) => {
-                if self.is_ancestor_in_tree(a, b, tree) {
-                    // a is ancestor of b → new node is left child of b
-                    (Some(b.clone()), Side::Left)
-                } else {
-                    // a is NOT ancestor of b → new node is right child of a
-                    (Some(a.clone()), Side::Right)
-                }
-            }
-        }
+    /// Block `id` for writing, copied first if a clone still shares it
+    pub(super) fn block_mut(&mut self, id: &NodeId) -> Option<&mut FugueBlock> {
+        self.blocks_mut().get_mut(id).map(Arc::make_mut)
     }
 
-    /// Check if node `a` is an ancestor of node `b` in the tree.
-    ///
-    /// Walks up from b to root, checking if we encounter a.
+    /// Rebuild rope from scratch (Phase 1: simple O(n) implementation)
     ///
-    /// # Arguments
-    /// * `a` - Potential ancestor
-    /// * `b` - Potential descendant
-    /// * `tree` - Tree structure to search
-    fn is_ancestor_in_tree(
-        &self,
-        a: &NodeId,
-        b: &NodeId,
-        tree: &HashMap<NodeId, TreeNode>,
-    ) -> bool {
-        let mut current = Some(b.clone());
+    /// This is used after merge to ensure rope matches CRDT state.
+    /// Phase 2 optimization: incremental updates instead of full rebuild.
+    pub(super) fn rebuild_rope(&mut self) {
+        // CRITICAL: Build text in DOCUMENT ORDER (Fugue tree), NOT BTreeMap order!
+        // BTreeMap order is causal/timestamp order, which differs from document
+        // order in concurrent scenarios.
+        let document_order = self.get_document_order();
+        let mut text = String::new();
+        let mut units = UnitTable::default();
 
-        while let Some(node_id) = current {
-            if &node_id == a {
-                return true;
+        for id in document_order {
+            if let Some(block) = self.blocks.get(&id) {
+                if !block.is_deleted() {
+                    text.push_str(&block.text);
+                    units.push(block.text.as_str());
+                }
             }
-
-            // Walk to parent
-            current = tree.get(&node_id).and_then(|node| node.parent.clone());
         }
 
-        false
+        // Replace rope
+        self.rope = Rope::from_str(&text);
+        self.units = units;
+
+        self.cache_valid = false; // Mark cache as stale
     }
 
-    /// Perform in-order traversal of the Fugue tree to get document order.
-    ///
-    /// # In-Order Traversal Algorithm
-    /// 1. Traverse left children (sorted by NodeId for determinism)
-    /// 2. Visit the node
-    /// 3. Traverse right children (sorted by NodeId)
+    /// Get blocks in document order (see [`order::document_order`])
     ///
-    /// This produces the correct document order for Fugue CRDT.
-    ///
-    /// # Arguments
-    /// * `tree` - Reconstructed Fugue tree
-    ///
-    /// # Returns
-    /// Vector of NodeIds in document order
-    fn in_order_traversal(&self, tree: &HashMap<NodeId, TreeNode>) -> Vec<NodeId> {
-        // Find root nodes (nodes with no parent)
-        let mut roots: Vec<NodeId> = tree
-            .values()
-            .filter(|node| node.parent.is_none())
-            .map(|node| node.id.clone())
-            .collect();
-
-        // Sort roots by NodeId for deterministic ordering
-        // This ensures concurrent inserts at position 0 converge
-        roots.sort();
-
-        let mut result = Vec::new();
-
-        // Traverse from each root (usually just one, but handle multiple)
-        for root_id in roots {
-            self.in_order_visit(&root_id, tree, &mut result);
-        }
-
-        result
+    /// CRITICAL: This is the ONLY correct way to determine character positions.
+    /// BTreeMap iteration gives causal/timestamp order, NOT document order.
+    pub(super) fn get_document_order(&self) -> Vec<NodeId> {
+        order::document_order(&self.blocks, false)
     }
 
-    /// Recursive in-order tree traversal helper.
-    fn in_order_visit(
-        &self,
-        node_id: &NodeId,
-        tree: &HashMap<NodeId, TreeNode>,
-        result: &mut Vec<NodeId>,
-    ) {
-        let node = &tree[node_id];
-
-        // 1. Traverse left children (sorted by NodeId)
-        // IMPORTANT: Include deleted nodes in traversal (they may have non-deleted children)
-        let mut left_children: Vec<NodeId> = tree
-            .values()
-            .filter(|n| {
-                n.parent.as_ref() == Some(node_id) && n.side == Side::Left
-                // Don't filter by deleted here - deleted nodes can have children!
-            })
-            .map(|n| n.id.clone())
-            .collect();
-
-        left_children.sort(); // Deterministic ordering by causal dot (NodeId)
-
-        for child_id in left_children {
-            self.in_order_visit(&child_id, tree, result);
-        }
-
-        // 2. Visit this node (if not deleted)
-        if !node.deleted {
-            result.push(node_id.clone());
-        }
-
-        // 3. Traverse right children (sorted by NodeId)
-        // IMPORTANT: Include deleted nodes in traversal (they may have non-deleted children)
-        let mut right_children: Vec<NodeId> = tree
-            .values()
-            .filter(|n| {
-                n.parent.as_ref() == Some(node_id) && n.side == Side::Right
-                // Don't filter by deleted here - deleted nodes can have children!
-            })
-            .map(|n| n.id.clone())
-            .collect();
-
-        right_children.sort(); // Deterministic ordering by causal dot (NodeId)
+    /// Get all blocks in document order, including deleted ones
+    ///
+    /// Used by exporters that need to preserve tombstones and to place
+    /// anchors whose character was deleted.
+    pub(super) fn document_order_with_tombstones(&self) -> Vec<NodeId> {
+        order::document_order(&self.blocks, true)
+    }
 
-        for child_id in right_children {
-            self.in_order_visit(&child_id, tree, result);
-        }
+    /// Find the block that contains a given character-level NodeId
+    /// (see [`order::block_containing`])
+    pub(super) fn find_block_for_nodeid(&self, node_id: &NodeId) -> Option<NodeId> {
+        order::block_containing(&self.blocks, node_id)
     }
 
     /// Rebuild position cache for all blocks (Phase 1.5 optimization)
@@ -1614,16 +2003,17 @@ impl FugueText {
     ///
     /// ```text
     /// Before:
-    ///   Block A: text="Hello", cached_start_pos=MAX (invalid)
-    ///   Block B: text=" World", cached_start_pos=MAX (invalid)
+    ///   Block A: text="Hello", cached_positions[A] absent
+    ///   Block B: text=" World", cached_positions[B] absent
     ///
     /// After rebuild_position_cache():
-    ///   Block A: text="Hello", cached_start_pos=0   (starts at pos 0)
-    ///   Block B: text=" World", cached_start_pos=5  (starts at pos 5)
+    ///   Block A: text="Hello", cached_positions[A]=0   (starts at pos 0)
+    ///   Block B: text=" World", cached_positions[B]=5  (starts at pos 5)
     /// ```
-    fn rebuild_position_cache(&mut self) {
+    pub(super) fn rebuild_position_cache(&mut self) {
         let mut current_pos = 0;
-        self.cached_blocks.clear();
+        let mut cached_blocks = Vec::new();
+        let mut cached_positions = HashMap::new();
 
         // CRITICAL: Must use document order (Fugue tree), NOT BTreeMap order!
         // BTreeMap order is causal/timestamp order, which differs from document
@@ -1631,14 +2021,16 @@ impl FugueText {
         let document_order = self.get_document_order();
 
         for id in document_order {
-            if let Some(block) = self.blocks.get_mut(&id) {
+            if let Some(block) = self.blocks.get(&id) {
                 if !block.is_deleted() {
-                    block.set_cached_position(current_pos);
+                    cached_positions.insert(id.clone(), current_pos);
                     current_pos += block.len();
-                    self.cached_blocks.push(id.clone()); // Cache non-deleted block IDs
+                    cached_blocks.push(id); // Cache non-deleted block IDs
                 }
             }
         }
+        self.cached_blocks = Arc::new(cached_blocks);
+        self.cached_positions = Arc::new(cached_positions);
     }
 
     /// Update cache after insert
@@ -1653,7 +2045,7 @@ impl FugueText {
     /// * `insert_pos` - Grapheme position where text was inserted (unused)
     /// * `insert_len` - Number of graphemes inserted (unused)
     /// * `new_block_id` - NodeId of the newly created block (unused)
-    fn update_cache_after_insert(
+    pub(super) fn update_cache_after_insert(
         &mut self,
         _insert_pos: usize,
         _insert_len: usize,
@@ -1665,80 +2057,169 @@ impl FugueText {
 
     /// Update cache incrementally after delete (Phase 1.5 optimization)
     ///
-    /// Similar to insert, but shifts positions backward and may remove blocks.
-    ///
-    /// **Performance:** O(log n) + O(k) where k = blocks after delete
+    /// Replaces the entries at `cached` (the blocks the deletion
+    /// overlapped) with the visible pieces left of them, `kept` with their
+    /// start positions, and shifts the positions after them back.
     ///
-    /// # Arguments
-    /// * `delete_pos` - Grapheme position where text was deleted
-    /// * `delete_len` - Number of graphemes deleted
-    #[allow(dead_code)]
-    fn update_cache_after_delete(&mut self, delete_pos: usize, _delete_len: usize) {
-        if !self.cache_valid {
-            // Cache is already invalid, will rebuild on next find_origins
-            return;
+    /// **Performance:** O(k log n) where k = blocks after delete, with no
+    /// traversal of the Fugue tree
+    fn update_cache_after_delete(
+        &mut self,
+        cached: std::ops::Range<usize>,
+        kept: Vec<(NodeId, usize)>,
+        length: usize,
+    ) {
+        let shifted = cached.start + kept.len();
+        let positions = Arc::make_mut(&mut self.cached_positions);
+        for id in Arc::make_mut(&mut self.cached_blocks)
+            .splice(cached, kept.iter().map(|(id, _)| id.clone()))
+        {
+            positions.remove(&id);
         }
 
-        // 1. Find deletion point using binary search - O(log n)
-        let _delete_idx = self
-            .cached_blocks
-            .binary_search_by(|id| {
-                let block = &self.blocks[id];
-                let block_start = block.cached_position().unwrap_or(0);
-                let block_end = block_start + block.len();
-
-                if delete_pos < block_start {
-                    std::cmp::Ordering::Greater
-                } else if delete_pos >= block_end {
-                    std::cmp::Ordering::Less
-                } else {
-                    std::cmp::Ordering::Equal
-                }
-            })
-            .unwrap_or_else(|idx| idx);
+        positions.extend(kept);
+        for id in &self.cached_blocks[shifted..] {
+            if let Some(start) = positions.get_mut(id) {
+                *start -= length;
+            }
+        }
+    }
 
-        // 2. Remove deleted blocks from cached_blocks - O(k)
-        // Note: We need to check which blocks were deleted and remove them
-        self.cached_blocks.retain(|id| {
+    /// The visible blocks `length` units at `position` overlap, found by
+    /// binary search in the position cache (which must be valid) and a
+    /// walk over just those blocks
+    ///
+    /// Returns them as `delete` splits them, the range of cache entries
+    /// they take, the rope chars before `position` and the text they hold
+    /// in the range.
+    #[allow(clippy::type_complexity)]
+    fn overlaps_from_cache(
+        &self,
+        position: usize,
+        length: usize,
+    ) -> (
+        Vec<(NodeId, Arc<FugueBlock>, usize, usize, usize)>,
+        std::ops::Range<usize>,
+        usize,
+        String,
+    ) {
+        let end = position + length;
+        let first = self.cached_blocks.partition_point(|id| {
             let block = &self.blocks[id];
-            !block.is_deleted()
+            self.cached_position(id).unwrap_or(0) + block.len() <= position
         });
 
-        // 3. Rebuild cached_blocks to ensure correct ordering after deletion
-        // This is necessary because deletion might affect multiple blocks
-        // CRITICAL: Must use document order (Fugue tree), NOT BTreeMap order!
-        let mut current_pos = 0;
-        self.cached_blocks.clear();
-
-        let document_order = self.get_document_order();
-
-        for id in document_order {
-            if let Some(block) = self.blocks.get_mut(&id) {
-                if !block.is_deleted() {
-                    block.set_cached_position(current_pos);
-                    current_pos += block.len();
-                    self.cached_blocks.push(id.clone());
-                }
+        let mut overlaps = Vec::new();
+        let mut removed = String::new();
+        let mut last = first;
+        for id in &self.cached_blocks[first..] {
+            let block = &self.blocks[id];
+            let block_start = self.cached_position(id).unwrap_or(0);
+            if block_start >= end {
+                break;
             }
+            last += 1;
+            let offset_start = position.saturating_sub(block_start);
+            // An empty block inside the range gets 0..0 and stays
+            let offset_end = (end - block_start).min(block.len()).max(offset_start);
+            removed.extend(
+                block
+                    .text
+                    .graphemes(true)
+                    .skip(offset_start)
+                    .take(offset_end - offset_start),
+            );
+            overlaps.push((
+                id.clone(),
+                block.clone(),
+                block_start,
+                offset_start,
+                offset_end,
+            ));
         }
+        let chars_before = self.units.char_offset(position);
+        (overlaps, first..last, chars_before, removed)
+    }
+}
 
-        // Cache remains valid after update
+/// Fail with `TextError::ReplicaConflict` if `remote_block` holds
+/// different text than `local_block` where their clock ranges overlap
+///
+/// `local_start` and `remote_start` are the blocks' first clocks.
+pub(super) fn check_block_conflict(
+    local_block: &FugueBlock,
+    local_start: u64,
+    remote_block: &FugueBlock,
+    remote_start: u64,
+) -> Result<(), TextError> {
+    let start = remote_start.max(local_start);
+    let end = remote_block.id.clock.min(local_block.id.clock);
+    if start > end {
+        return Ok(());
+    }
+    // The first differing character, and what each side holds from it to
+    // the end of the overlap
+    let mismatch = if local_block.one_unit_per_byte() && remote_block.one_unit_per_byte() {
+        let local_bytes = bytes_between(local_block, local_start, start, end);
+        let remote_bytes = bytes_between(remote_block, remote_start, start, end);
+        match local_bytes == remote_bytes {
+            true => None,
+            false => local_bytes
+                .iter()
+                .zip(remote_bytes)
+                .position(|(a, b)| a != b)
+                .map(|offset| {
+                    let from =
+                        |bytes: &[u8]| String::from_utf8_lossy(&bytes[offset..]).into_owned();
+                    (offset, from(local_bytes), from(remote_bytes))
+                }),
+        }
+    } else {
+        let local_chars = graphemes_between(local_block, local_start, start, end);
+        let remote_chars = graphemes_between(remote_block, remote_start, start, end);
+        local_chars
+            .iter()
+            .zip(&remote_chars)
+            .position(|(a, b)| a != b)
+            .map(|offset| {
+                (
+                    offset,
+                    local_chars[offset..].concat(),
+                    remote_chars[offset..].concat(),
+                )
+            })
+    };
+    match mismatch {
+        Some((offset, local, remote)) => {
+            trace_debug!(block = %remote_block.id, "replica conflict");
+            Err(TextError::ReplicaConflict {
+                id: NodeId::new(remote_block.id.client_id.clone(), start + offset as u64, 0),
+                local,
+                remote,
+            })
+        }
+        None => Ok(()),
     }
 }
 
-// Placeholder for when text-crdt feature is disabled
-#[cfg(not(feature = "text-crdt"))]
-#[derive(Debug, Clone)]
-pub struct FugueText;
+/// Bytes of `block` (which starts at clock `block_start`, one grapheme per
+/// byte) at clocks `start..=end`
+fn bytes_between(block: &FugueBlock, block_start: u64, start: u64, end: u64) -> &[u8] {
+    &block.text.as_bytes()[(start - block_start) as usize..=(end - block_start) as usize]
+}
 
-#[cfg(not(feature = "text-crdt"))]
-impl FugueText {
-    pub fn new(_client_id: String) -> Self {
-        panic!("FugueText requires 'text-crdt' feature to be enabled");
-    }
+/// Graphemes of `block` (which starts at clock `block_start`) at clocks
+/// `start..=end`
+fn graphemes_between(block: &FugueBlock, block_start: u64, start: u64, end: u64) -> Vec<&str> {
+    block
+        .text
+        .graphemes(true)
+        .skip((start - block_start) as usize)
+        .take((end - start + 1) as usize)
+        .collect()
 }
 
-#[cfg(all(test, feature = "text-crdt"))]
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -1787,6 +2268,190 @@ mod tests {
         }
     }
 
+    /// Alice types `first`, Bob appends `rest` after merging it, and
+    /// Alice merges Bob back; the visible text joins them into clusters
+    fn built_across_replicas(first: &str, rest: &str) -> FugueText {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, first).unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+        let units = first.graphemes(true).count();
+        bob.insert(units, rest).unwrap();
+        alice.merge(&bob).unwrap();
+        assert_eq!(alice.to_string(), format!("{}{}", first, rest));
+        alice
+    }
+
+    /// The rope, `len()` and the live blocks tell the same story
+    fn assert_blocks_match_rope(text: &FugueText, expected: &str) {
+        let live: Vec<&FugueBlock> = text
+            .get_document_order()
+            .iter()
+            .filter_map(|id| text.block(id))
+            .filter(|block| !block.is_deleted())
+            .collect();
+        let content: String = live.iter().map(|block| block.text.as_str()).collect();
+        assert_eq!(text.to_string(), expected);
+        assert_eq!(content, expected);
+        assert_eq!(
+            text.len(),
+            live.iter().map(|block| block.len()).sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn test_delete_after_clusters_joined_by_merge() {
+        let cases = [
+            // ZWJ sequence: woman + (ZWJ, rocket)
+            ("👩", "\u{200D}🚀!"),
+            // Flags: the regional indicators pair up differently once joined
+            ("🇺", "🇸🇩🇪!"),
+            // Skin tone modifier on its own, then joined to the hand
+            ("👋", "🏽!"),
+        ];
+        for (first, rest) in cases {
+            let text = built_across_replicas(first, rest);
+            let units: usize = first.graphemes(true).count() + rest.graphemes(true).count();
+            let visible = text.to_string();
+
+            // The "!" is the last unit, though not the last rope char
+            // counting from the front
+            let mut bang = text.clone();
+            bang.delete(units - 1, 1).unwrap();
+            assert_blocks_match_rope(&bang, visible.trim_end_matches('!'));
+
+            // Everything but the "!"
+            let mut cluster = text.clone();
+            cluster.delete(0, units - 1).unwrap();
+            assert_blocks_match_rope(&cluster, "!");
+
+            // Only Bob's part of the cluster
+            let mut tail = text.clone();
+            tail.delete(1, units - 2).unwrap();
+            assert_blocks_match_rope(&tail, &format!("{}!", first));
+
+            // Past the end in units, though not in rope chars
+            let mut past = text.clone();
+            assert!(matches!(
+                past.delete(units - 1, 2),
+                Err(TextError::RangeOutOfBounds { length, .. }) if length == units
+            ));
+            assert_blocks_match_rope(&past, &visible);
+        }
+    }
+
+    #[test]
+    fn test_edits_after_multibyte_chars() {
+        let cases = [
+            ("héllo", "h-éllo!", "héllx"),
+            ("日本語", "日-本語!", "日本x"),
+        ];
+        for (prefix, inserted, replaced) in cases {
+            let units = prefix.chars().count();
+            let mut text = FugueText::new("client1".to_string());
+            text.insert(0, prefix).unwrap();
+            text.insert(units, "!").unwrap();
+            text.insert(1, "-").unwrap();
+            assert_eq!(text.to_string(), inserted);
+            assert_eq!(text.len(), units + 2);
+
+            text.delete(units + 1, 1).unwrap();
+            text.delete(1, 1).unwrap();
+            assert_eq!(text.to_string(), prefix);
+            text.delete(units - 1, 1).unwrap();
+            text.insert(units - 1, "x").unwrap();
+            assert_eq!(text.to_string(), replaced);
+        }
+    }
+
+    #[test]
+    fn test_positions_count_clusters() {
+        let family = "👨\u{200D}👩\u{200D}👧";
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "漢字").unwrap();
+        text.insert(1, family).unwrap();
+        // A precomposed-style cluster in one insert is one unit
+        text.insert(3, "e\u{301}!").unwrap();
+        assert_eq!(text.len(), 5);
+        assert_eq!(text.to_string(), format!("漢{}字e\u{301}!", family));
+
+        // An accent typed after its letter counts on its own
+        text.insert(0, "a").unwrap();
+        text.insert(1, "\u{301}").unwrap();
+        assert_eq!(text.len(), 7);
+        let snapshot = text.snapshot();
+        assert_eq!(snapshot.len(), 7);
+        assert_eq!(
+            snapshot.slice(1, 4).unwrap(),
+            format!("\u{301}漢{}", family)
+        );
+        assert_eq!(snapshot.slice(5, 6).unwrap(), "e\u{301}");
+
+        text.delete(3, 1).unwrap();
+        assert_eq!(text.to_string(), "a\u{301}漢字e\u{301}!");
+        text.delete(5, 1).unwrap();
+        text.delete(1, 1).unwrap();
+        assert_eq!(text.to_string(), "a漢字e\u{301}");
+        assert_eq!(text.len(), 4);
+        assert!(text.delete(3, 2).is_err());
+
+        // A replica rebuilt from the blocks agrees on every position
+        let mut fresh = FugueText::new("client2".to_string());
+        fresh.merge(&text).unwrap();
+        assert_eq!(fresh.len(), 4);
+        fresh.insert(4, "😀").unwrap();
+        fresh.insert(3, family).unwrap();
+        assert_eq!(fresh.to_string(), format!("a漢字{}e\u{301}😀", family));
+        fresh.delete(0, 3).unwrap();
+        assert_eq!(
+            fresh.snapshot().slice(0, 2).unwrap(),
+            format!("{}e\u{301}", family)
+        );
+    }
+
+    #[test]
+    fn test_delete_refuses_rope_out_of_step_with_blocks() {
+        let mut text = built_across_replicas("👩", "\u{200D}🚀!");
+        // Simulate the rope losing a char the blocks still hold
+        text.rope.remove(0..1);
+        // Delete reads the position cache; build it first, so only a
+        // change to the blocks themselves shows below
+        text.rebuild_position_cache();
+        text.cache_valid = true;
+
+        let before = text.blocks.clone();
+        assert!(matches!(
+            text.delete(1, 2),
+            Err(TextError::RangeOutOfBounds {
+                start: 1,
+                end: 3,
+                ..
+            })
+        ));
+        assert_eq!(text.blocks, before);
+        assert_eq!(text.to_string(), "\u{200D}🚀!");
+    }
+
+    #[test]
+    fn test_deletes_keep_position_cache_in_step() {
+        let mut text = built_across_replicas("Hello, ", "wonderful world!");
+        text.insert(3, "👋🏽").unwrap();
+        let mut expected: Vec<String> =
+            text.to_string().graphemes(true).map(String::from).collect();
+
+        for (position, length) in [(4, 1), (0, 2), (5, 6), (8, 3), (0, 1), (2, 1)] {
+            text.delete(position, length).unwrap();
+            expected.drain(position..position + length);
+            assert!(text.cache_valid);
+
+            let mut rebuilt = text.clone();
+            rebuilt.rebuild_position_cache();
+            assert_eq!(text.cached_blocks, rebuilt.cached_blocks);
+            assert_eq!(text.cached_positions, rebuilt.cached_positions);
+            assert_blocks_match_rope(&text, &expected.concat());
+        }
+    }
+
     #[test]
     fn test_delete_single() {
         let mut text = FugueText::new("client1".to_string());
@@ -1870,6 +2535,43 @@ mod tests {
         assert_eq!(ts3, 6);
     }
 
+    #[test]
+    fn test_merge_passes_clocks_that_left_no_block() {
+        // Bob's deletion takes clock 5, and collecting its tombstone drops
+        // the one block that recorded it
+        let mut bob = FugueText::new("bob".to_string());
+        bob.insert(0, "abc").unwrap();
+        bob.insert(3, "d").unwrap();
+        bob.delete(0, 3).unwrap();
+        bob.gc(&bob.current_version());
+        assert_eq!(bob.clock(), 5);
+        assert!(bob.blocks.keys().all(|id| id.clock < 5));
+
+        // Carol relays bob's state; bob deletes again, stamped 6
+        let mut carol = FugueText::new("carol".to_string());
+        carol.merge(&bob).unwrap();
+        bob.delete(0, 1).unwrap();
+        let mut dave = FugueText::new("dave".to_string());
+        dave.merge(&bob).unwrap();
+
+        // Every operation after a merge is ordered after all the remote did
+        let mut alice = FugueText::new("alice".to_string());
+        alice.merge(&carol).unwrap();
+        let after_carol = alice.insert(0, "x").unwrap();
+        alice.merge(&dave).unwrap();
+        let after_dave = alice.insert(0, "y").unwrap();
+        let mut erin = FugueText::new("erin".to_string());
+        erin.apply_delta(&dave.diff_since(&erin.state_vector()))
+            .unwrap();
+        let after_delta = erin.insert(0, "z").unwrap();
+
+        // Going by block IDs alone, alice's "x" would take clock 5 again:
+        // the same timestamp as a deletion it causally follows
+        assert_eq!(after_carol.clock, 6);
+        assert_eq!(after_dave.clock, 7);
+        assert_eq!(after_delta.clock, 7);
+    }
+
     // ============================================================
     // Additional comprehensive tests (expanding coverage to 50+)
     // ============================================================
@@ -1909,6 +2611,102 @@ mod tests {
         assert_eq!(text.to_string(), "Hello Rust");
     }
 
+    #[test]
+    fn test_splice_start_middle_end() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello World").unwrap();
+
+        let result = text.splice(0, 5, "Howdy").unwrap();
+        assert_eq!(text.to_string(), "Howdy World");
+        assert_eq!(result.deleted.len(), 1);
+        assert!(result.inserted.is_some());
+
+        text.splice(5, 1, ", ").unwrap();
+        assert_eq!(text.to_string(), "Howdy, World");
+
+        text.replace_range(7..12, "there").unwrap();
+        assert_eq!(text.to_string(), "Howdy, there");
+    }
+
+    #[test]
+    fn test_splice_zero_length_delete_or_insert() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello World").unwrap();
+
+        let result = text.splice(5, 0, ",").unwrap();
+        assert_eq!(text.to_string(), "Hello, World");
+        assert!(result.deleted.is_empty());
+        assert!(result.inserted.is_some());
+
+        let result = text.splice(5, 1, "").unwrap();
+        assert_eq!(text.to_string(), "Hello World");
+        assert_eq!(result.deleted.len(), 1);
+        assert_eq!(result.inserted, None);
+
+        text.delete(3, 0).unwrap();
+        assert_eq!(text.to_string(), "Hello World");
+    }
+
+    #[test]
+    fn test_splice_out_of_bounds_changes_nothing() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello").unwrap();
+        assert!(matches!(
+            text.splice(3, 3, "p!"),
+            Err(TextError::RangeOutOfBounds { .. })
+        ));
+        assert!(text.splice(3, usize::MAX, "p!").is_err());
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 4..2;
+        assert!(text.replace_range(reversed, "p!").is_err());
+        assert_eq!(text.to_string(), "Hello");
+    }
+
+    #[test]
+    fn test_splice_converges_like_delete_then_insert() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello World").unwrap();
+        alice.insert(5, ",").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        let mut spliced = alice.clone();
+        spliced.splice(4, 4, "o there, w").unwrap();
+        let mut two_steps = alice.clone();
+        two_steps.delete(4, 4).unwrap();
+        two_steps.insert(4, "o there, w").unwrap();
+        assert_eq!(spliced.to_string(), "Hello there, world");
+        let crdt_state = |text: &FugueText| {
+            text.blocks
+                .values()
+                .map(|block| {
+                    let origins = (block.left_origin.clone(), block.right_origin.clone());
+                    (
+                        block.id.clone(),
+                        block.text.to_string(),
+                        origins,
+                        block.deleted,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(crdt_state(&spliced), crdt_state(&two_steps));
+
+        bob.insert(12, "!").unwrap();
+        bob.insert(4, "o").unwrap();
+        for replica in [&mut spliced, &mut two_steps] {
+            replica.merge(&bob).unwrap();
+        }
+        assert_eq!(spliced.to_string(), two_steps.to_string());
+        let mut bob_from_delta = bob.clone();
+        bob_from_delta
+            .apply_delta(&spliced.diff_since(&bob.state_vector()))
+            .unwrap();
+        bob.merge(&two_steps).unwrap();
+        assert_eq!(bob_from_delta.to_string(), bob.to_string());
+        assert_eq!(bob.to_string(), spliced.to_string());
+    }
+
     #[test]
     fn test_three_way_concurrent_insert() {
         let mut text1 = FugueText::new("client1".to_string());
@@ -2082,6 +2880,30 @@ mod tests {
         assert_eq!(text.to_string(), "HelloWorld");
     }
 
+    #[test]
+    fn test_delete_inside_block_survives_rebuild() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello World").unwrap();
+        text.delete(2, 3).unwrap();
+        assert_eq!(text.to_string(), "He World");
+
+        // Only the deleted middle is a tombstone
+        let live: usize = text
+            .blocks
+            .values()
+            .filter(|block| !block.is_deleted())
+            .map(|block| block.len())
+            .sum();
+        assert_eq!(live, 8);
+
+        let mut fresh = FugueText::new("client2".to_string());
+        fresh.merge(&text).unwrap();
+        assert_eq!(fresh.to_string(), "He World");
+        text.merge(&fresh).unwrap();
+        text.rebuild_rope();
+        assert_eq!(text.to_string(), "He World");
+    }
+
     #[test]
     fn test_delete_beginning() {
         let mut text = FugueText::new("client1".to_string());
@@ -2243,6 +3065,108 @@ mod tests {
         let result = text.get_node_id_at_position(5);
         assert!(result.is_err());
     }
+
+    /// Alice snapshots her replica, types more and syncs it to the server,
+    /// then loses her disk and restores the snapshot
+    fn restored_replica() -> (FugueText, FugueText) {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "abc").unwrap();
+        let snapshot = serde_json::to_string(&alice).unwrap();
+        alice.insert(3, "def").unwrap();
+
+        let mut server = FugueText::new("server".to_string());
+        server.merge(&alice).unwrap();
+
+        let restored: FugueText = serde_json::from_str(&snapshot).unwrap();
+        assert_eq!(restored.clock(), 3);
+        (restored, server)
+    }
+
+    #[test]
+    fn test_restored_replica_editing_offline_conflicts() {
+        let (mut alice, mut server) = restored_replica();
+        let id = alice.insert(3, "XYZ").unwrap();
+        assert_eq!(id.clock, 6); // alice@4..=6 again, now "XYZ" instead of "def"
+
+        let before = serde_json::to_string(&server).unwrap();
+        match server.merge(&alice) {
+            Err(TextError::ReplicaConflict { id, local, remote }) => {
+                assert_eq!(id, NodeId::new("alice".to_string(), 4, 0));
+                assert_eq!(local, "def");
+                assert_eq!(remote, "XYZ");
+            }
+            other => panic!("expected a replica conflict, got {:?}", other),
+        }
+        assert_eq!(serde_json::to_string(&server).unwrap(), before);
+
+        // The other direction and the delta path catch it too
+        let server_copy = server.clone();
+        assert!(matches!(
+            alice.merge(&server_copy),
+            Err(TextError::ReplicaConflict { .. })
+        ));
+        let delta = alice.diff_since(&crate::sync::VectorClock::new());
+        assert!(matches!(
+            server.apply_delta(&delta),
+            Err(TextError::ReplicaConflict { .. })
+        ));
+        assert_eq!(server.to_string(), "abcdef");
+    }
+
+    #[test]
+    fn test_restored_replica_fast_forwards_on_merge() {
+        let (mut alice, mut server) = restored_replica();
+        alice.merge(&server).unwrap();
+        assert_eq!(alice.to_string(), "abcdef");
+
+        let id = alice.insert(6, "XYZ").unwrap();
+        assert!(id.clock > 6);
+        server.merge(&alice).unwrap();
+        assert_eq!(server.to_string(), "abcdefXYZ");
+    }
+
+    #[test]
+    fn test_restored_replica_fast_forwards_on_state_vector() {
+        let (mut alice, mut server) = restored_replica();
+        // Handshake: swap state vectors, then edit before the missing
+        // text arrives
+        let handshake = alice.state_vector();
+        alice.observe_state_vector(&server.state_vector());
+        assert_eq!(alice.clock(), 6);
+        alice.insert(0, "XYZ").unwrap();
+
+        server.merge(&alice).unwrap();
+        alice.apply_delta(&server.diff_since(&handshake)).unwrap();
+        assert_eq!(server.to_string(), "XYZabcdef");
+        assert_eq!(alice.to_string(), server.to_string());
+    }
+
+    #[test]
+    fn test_deleted_block_covers_locally_split_pieces() {
+        let mut a = FugueText::new("a".to_string());
+        a.insert(0, "xy").unwrap();
+        let mut b = a.clone();
+        a.delete(0, 2).unwrap();
+        b.insert(1, "-").unwrap(); // splits a@1..=2 into a@1 and a@2
+
+        b.merge(&a).unwrap();
+        a.merge(&b).unwrap();
+        assert_eq!(b.to_string(), "-");
+        assert_eq!(a.to_string(), b.to_string());
+    }
+
+    #[test]
+    fn test_split_blocks_are_not_conflicts() {
+        let mut a = FugueText::new("a".to_string());
+        a.insert(0, "Hello World").unwrap();
+        let mut b = a.clone();
+        a.delete(2, 3).unwrap();
+        b.delete(6, 2).unwrap();
+        a.merge(&b).unwrap();
+        b.merge(&a).unwrap();
+        assert_eq!(a.to_string(), "He rld");
+        assert_eq!(a.to_string(), b.to_string());
+    }
 }
 
 #[cfg(test)]
@@ -2445,6 +3369,84 @@ mod fugue_tree_tests {
         );
     }
 
+    /// Who deleted each deleted character, and at which clock
+    fn deletions(text: &FugueText) -> BTreeMap<(String, u64), (Option<String>, Option<u64>)> {
+        let mut deletions = BTreeMap::new();
+        for (id, block) in text.blocks.iter().filter(|(_, block)| block.is_deleted()) {
+            let len = block.len() as u64;
+            for clock in id.clock + 1 - len..=id.clock {
+                let stamp = (
+                    block.deleted_by().map(String::from),
+                    block.deleted_at_clock(),
+                );
+                deletions.insert((id.client_id.to_string(), clock), stamp);
+            }
+        }
+        deletions
+    }
+
+    #[test]
+    fn test_concurrent_deletions_converge_on_the_earliest_stamp() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello world").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+        let mut carol = FugueText::new("carol".to_string());
+        carol.merge(&alice).unwrap();
+        let base = carol.state_vector();
+
+        // Both delete "lo"; bob's deletion has the lower clock
+        alice.insert(11, "!").unwrap();
+        alice.delete(0, 5).unwrap();
+        bob.delete(3, 5).unwrap();
+        assert_eq!(
+            alice
+                .blocks
+                .values()
+                .find_map(|block| block.deleted_at_clock()),
+            Some(13)
+        );
+
+        let (from_alice, from_bob) = (alice.diff_since(&base), bob.diff_since(&base));
+        alice.merge(&bob).unwrap();
+        bob.merge(&alice).unwrap();
+        carol.apply_delta(&from_bob).unwrap();
+        carol.apply_delta(&from_alice).unwrap();
+
+        assert_eq!(alice.to_string(), "rld!");
+        assert_eq!(carol.to_string(), alice.to_string());
+        let stamps = deletions(&alice);
+        assert_eq!(deletions(&bob), stamps);
+        assert_eq!(deletions(&carol), stamps);
+        let by = |clock: u64| stamps[&("alice".to_string(), clock)].clone();
+        assert_eq!(by(1), (Some("alice".to_string()), Some(13)));
+        assert_eq!(by(4), (Some("bob".to_string()), Some(12)));
+        assert_eq!(by(8), (Some("bob".to_string()), Some(12)));
+    }
+
+    #[test]
+    fn test_deletion_stamp_survives_concurrent_insert() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello world").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        // Alice deletes "world" while bob types into it
+        alice.delete(6, 5).unwrap();
+        bob.insert(8, "-").unwrap();
+        alice.merge(&bob).unwrap();
+        bob.merge(&alice).unwrap();
+
+        assert_eq!(alice.to_string(), "Hello -");
+        assert_eq!(bob.to_string(), alice.to_string());
+        let stamps = deletions(&alice);
+        assert_eq!(deletions(&bob), stamps);
+        assert_eq!(stamps.len(), 5);
+        assert!(stamps
+            .values()
+            .all(|stamp| *stamp == (Some("alice".to_string()), Some(12))));
+    }
+
     #[test]
     fn test_concurrent_delete_first_and_last_chars() {
         // Edge case: delete first and last characters of a block concurrently
@@ -2492,4 +3494,110 @@ mod fugue_tree_tests {
         assert_eq!(result1, result2, "Clients diverged");
         assert_eq!(result1, "ADE", "Expected 'ADE', got '{}'", result1);
     }
+
+    #[test]
+    fn test_insert_inside_block_survives_rebuild() {
+        // Found by the network simulation: an insert inside an RLE block
+        // must keep its place when the rope is rebuilt from blocks
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "s").unwrap();
+        text.delete(0, 1).unwrap();
+        text.insert(0, "qvr").unwrap();
+        text.insert(1, "h").unwrap();
+        assert_eq!(text.to_string(), "qhvr");
+
+        let reloaded: FugueText =
+            serde_json::from_str(&serde_json::to_string(&text).unwrap()).unwrap();
+        assert_eq!(reloaded.to_string(), "qhvr");
+
+        let mut fresh = FugueText::new("bob".to_string());
+        fresh.merge(&text).unwrap();
+        assert_eq!(fresh.to_string(), "qhvr");
+    }
+
+    #[test]
+    fn test_converge_when_replicas_split_a_block_differently() {
+        // One replica deletes "h" and "r" separately (splitting the block),
+        // the other receives both deletions as one range (no split); the
+        // sibling order must not depend on the split
+        let mut a = FugueText::new("a".to_string());
+        let mut b = FugueText::new("b".to_string());
+        a.insert(0, "hr").unwrap();
+        b.insert(0, "mb").unwrap();
+        b.apply_delta(&a.diff_since(&b.state_vector())).unwrap();
+        b.insert(2, "q").unwrap(); // "hrqmb"
+        b.delete(4, 1).unwrap();
+        a.delete(1, 1).unwrap();
+        a.delete(0, 1).unwrap();
+
+        a.apply_delta(&b.diff_since(&a.state_vector())).unwrap();
+        b.apply_delta(&a.diff_since(&b.state_vector())).unwrap();
+        assert_eq!(a.to_string(), "qm");
+        assert_eq!(b.to_string(), "qm");
+    }
+
+    #[test]
+    fn test_clone_shares_blocks_until_written() {
+        let mut original = FugueText::new("client1".to_string());
+        original.insert(0, "Hello World").unwrap();
+        original.delete(5, 1).unwrap();
+        let snapshot = serde_json::to_string(&original).unwrap();
+
+        let mut fork = original.clone();
+        assert!(Arc::ptr_eq(&fork.blocks, &original.blocks));
+
+        fork.insert(5, ", ").unwrap();
+        assert!(!Arc::ptr_eq(&fork.blocks, &original.blocks));
+        assert_eq!(fork.to_string(), "Hello, World");
+        assert_eq!(original.to_string(), "HelloWorld");
+        assert_eq!(serde_json::to_string(&original).unwrap(), snapshot);
+
+        // Merging the fork back matches merging a deep copy of it
+        let deep: FugueText = serde_json::from_str(&serde_json::to_string(&fork).unwrap()).unwrap();
+        let mut via_fork = original.clone();
+        via_fork.merge(&fork).unwrap();
+        original.merge(&deep).unwrap();
+        assert_eq!(via_fork.to_string(), original.to_string());
+        assert_eq!(
+            serde_json::to_string(&via_fork).unwrap(),
+            serde_json::to_string(&original).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_forks_edit_independently() {
+        fn base() -> FugueText {
+            let mut text = FugueText::new("client1".to_string());
+            text.insert(0, "Hello World").unwrap();
+            text.delete(5, 1).unwrap();
+            text
+        }
+        let mut original = base();
+        let mut fork = original.clone();
+
+        // A write copies the map, but not the blocks it leaves alone
+        fork.insert(5, ", ").unwrap();
+        fork.delete(0, 1).unwrap();
+        assert!(fork.blocks.iter().any(|(id, block)| original
+            .blocks
+            .get(id)
+            .is_some_and(|shared| Arc::ptr_eq(block, shared))));
+        original.insert(10, "!").unwrap();
+        original.delete(0, 5).unwrap();
+
+        // Each side ends as if it had been edited alone
+        let mut fork_alone = base();
+        fork_alone.insert(5, ", ").unwrap();
+        fork_alone.delete(0, 1).unwrap();
+        let mut original_alone = base();
+        original_alone.insert(10, "!").unwrap();
+        original_alone.delete(0, 5).unwrap();
+        assert_eq!(fork.to_string(), "ello, World");
+        assert_eq!(original.to_string(), "World!");
+        assert_eq!(fork.structure_hash(), fork_alone.structure_hash());
+        assert_eq!(fork.content_hash(), fork_alone.content_hash());
+        assert_eq!(original.structure_hash(), original_alone.structure_hash());
+        assert_eq!(original.content_hash(), original_alone.content_hash());
+        assert_ne!(fork.structure_hash(), original.structure_hash());
+    }
 }
@@ -0,0 +1,302 @@
+//! Bringing a text in line with a new string
+//!
+//! An editor that only hands over its whole value after each input, such
+//! as a plain `<textarea>`, can still edit a `FugueText` character by
+//! character: the new value is diffed against the text (common prefix and
+//! suffix, then Myers' O(ND) diff on what is left) and the differences
+//! applied as inserts and deletes, so unchanged text keeps its IDs and
+//! concurrent edits to it merge as usual.
+
+use super::change::{ChangeSpan, TextChange};
+use super::text::{FugueText, TextError};
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Units that may differ between the two texts, after the common prefix
+/// and suffix, before the diff gives up and replaces them all
+const MAX_EDIT_DISTANCE: usize = 1024;
+
+/// One step of an edit script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Keep,
+    Remove,
+    /// Add the unit of the new text at this index
+    Add(usize),
+}
+
+impl FugueText {
+    /// The change that turns this text into `new_text`
+    ///
+    /// Units of `new_text` are counted by grapheme cluster. Nothing is
+    /// changed; see `apply_text_diff`.
+    pub fn text_diff(&self, new_text: &str) -> TextChange {
+        let old: Vec<Cow<'_, str>> = match self.graphemes_in(0..self.len()) {
+            Ok(units) => units.collect(),
+            Err(_) => unreachable!("the whole text is in range"),
+        };
+        let new: Vec<&str> = new_text.graphemes(true).collect();
+        if old.len() == new.len() && old.iter().zip(&new).all(|(a, b)| a == b) {
+            return TextChange::default();
+        }
+
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+        let suffix = old_rest
+            .iter()
+            .rev()
+            .zip(new_rest.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old_middle = &old_rest[..old_rest.len() - suffix];
+        let new_middle = &new_rest[..new_rest.len() - suffix];
+
+        let edits = shortest_edit(old_middle, new_middle).unwrap_or_else(|| {
+            // Too different to be worth it: replace the middle whole
+            (0..new_middle.len())
+                .map(Edit::Add)
+                .chain(old_middle.iter().map(|_| Edit::Remove))
+                .collect()
+        });
+
+        let mut spans = Vec::new();
+        if prefix > 0 {
+            spans.push(ChangeSpan::Retain(prefix));
+        }
+        let (mut inserted, mut deleted) = (String::new(), 0);
+        let mut edits = edits.into_iter().peekable();
+        while let Some(edit) = edits.next() {
+            match edit {
+                Edit::Keep => {
+                    let mut kept = 1;
+                    while edits.next_if_eq(&Edit::Keep).is_some() {
+                        kept += 1;
+                    }
+                    flush(&mut spans, &mut inserted, &mut deleted);
+                    spans.push(ChangeSpan::Retain(kept));
+                }
+                Edit::Remove => deleted += 1,
+                Edit::Add(index) => inserted.push_str(new_middle[index]),
+            }
+        }
+        flush(&mut spans, &mut inserted, &mut deleted);
+        TextChange::from_spans(spans)
+    }
+
+    /// Turn this text into `new_text` with as few inserts and deletes as
+    /// `text_diff` finds
+    ///
+    /// # Returns
+    ///
+    /// The change made
+    ///
+    /// # Errors
+    ///
+    /// None in practice: the change is made with `splice` at positions the
+    /// diff computed from this text.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::{ChangeSpan, FugueText};
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello World").unwrap();
+    ///
+    /// // The textarea's value after the user typed
+    /// let change = text.apply_text_diff("Hello, World!").unwrap();
+    ///
+    /// assert_eq!(text.to_string(), "Hello, World!");
+    /// assert_eq!(change.spans()[1], ChangeSpan::Insert(",".to_string()));
+    /// ```
+    pub fn apply_text_diff(&mut self, new_text: &str) -> Result<TextChange, TextError> {
+        let change = self.text_diff(new_text);
+        for (position, delete_len, text) in change.splices() {
+            self.splice(position, delete_len, text)?;
+        }
+        Ok(change)
+    }
+}
+
+/// End the inserts and deletes between two retains
+fn flush(spans: &mut Vec<ChangeSpan>, inserted: &mut String, deleted: &mut usize) {
+    if !inserted.is_empty() {
+        spans.push(ChangeSpan::Insert(std::mem::take(inserted)));
+    }
+    if *deleted > 0 {
+        spans.push(ChangeSpan::Delete(std::mem::take(deleted)));
+    }
+}
+
+/// Myers' shortest edit script from `old` to `new`, or None if it takes
+/// more than `MAX_EDIT_DISTANCE` inserts and deletes
+fn shortest_edit(old: &[Cow<'_, str>], new: &[&str]) -> Option<Vec<Edit>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (old.len() + new.len()).min(MAX_EDIT_DISTANCE) as isize;
+    // Furthest x reached on each diagonal k = x - y, at index k + offset
+    let offset = max + 1;
+    let mut furthest = vec![0isize; 2 * max as usize + 3];
+    let mut trace = Vec::new();
+
+    for d in 0..=max {
+        trace.push(furthest.clone());
+        for k in (-d..=d).step_by(2) {
+            let at = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && furthest[at - 1] < furthest[at + 1]) {
+                furthest[at + 1]
+            } else {
+                furthest[at - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[at] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, offset, n, m));
+            }
+        }
+    }
+    None
+}
+
+/// Walk the furthest-reaching paths back from the end
+fn backtrack(trace: &[Vec<isize>], offset: isize, n: isize, m: isize) -> Vec<Edit> {
+    let (mut x, mut y) = (n, m);
+    let mut edits = Vec::new();
+    for (d, furthest) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let at = |k: isize| furthest[(k + offset) as usize];
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            edits.push(match x == prev_x {
+                true => Edit::Add(prev_y as usize),
+                false => Edit::Remove,
+            });
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> FugueText {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, s).unwrap();
+        text
+    }
+
+    /// Diff, apply, and check the text and the change agree
+    fn assert_diff(from: &str, to: &str, spans: &[ChangeSpan]) {
+        let mut text = text(from);
+        let change = text.apply_text_diff(to).unwrap();
+        assert_eq!(text.to_string(), to);
+        assert_eq!(change.spans(), spans, "{:?} -> {:?}", from, to);
+        assert_eq!(change.apply(from), to);
+    }
+
+    #[test]
+    fn test_edits_at_start_middle_and_end() {
+        use ChangeSpan::*;
+        assert_diff("world", "Hello world", &[Insert("Hello ".into())]);
+        assert_diff(
+            "Hello world",
+            "Hello, world",
+            &[Retain(5), Insert(",".into())],
+        );
+        assert_diff("Hello world", "Hello", &[Retain(5), Delete(6)]);
+        assert_diff(
+            "The quick brown fox",
+            "The slow brown dog",
+            &[
+                Retain(4),
+                Insert("slow".into()),
+                Delete(5),
+                Retain(7),
+                Insert("d".into()),
+                Delete(1),
+                Retain(1),
+                Insert("g".into()),
+                Delete(1),
+            ],
+        );
+        assert_diff("", "new", &[Insert("new".into())]);
+        assert_diff("same", "same", &[]);
+    }
+
+    #[test]
+    fn test_complete_replacement() {
+        use ChangeSpan::*;
+        assert_diff("abc", "xyz", &[Insert("xyz".into()), Delete(3)]);
+        assert_diff("Hello", "", &[Delete(5)]);
+
+        // Past the edit distance limit the middle is replaced whole
+        let from = format!("<{}>", "a".repeat(MAX_EDIT_DISTANCE));
+        let to = format!("<{}>", "b".repeat(MAX_EDIT_DISTANCE));
+        assert_diff(
+            &from,
+            &to,
+            &[
+                Retain(1),
+                Insert("b".repeat(MAX_EDIT_DISTANCE)),
+                Delete(MAX_EDIT_DISTANCE),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_emoji_boundaries() {
+        use ChangeSpan::*;
+        // Skin tone changes replace the one cluster, not half of it
+        assert_diff(
+            "Hi 👋🏽!",
+            "Hi 👋🏿!",
+            &[Retain(3), Insert("👋🏿".into()), Delete(1)],
+        );
+        assert_diff("👨‍👩‍👧 family", "👨‍👩‍👧‍👦 family", &[Insert("👨‍👩‍👧‍👦".into()), Delete(1)]);
+        assert_diff("é", "e", &[Insert("e".into()), Delete(1)]);
+    }
+
+    #[test]
+    fn test_unchanged_text_keeps_its_ids() {
+        let mut text = text("Hello world");
+        let w = text.get_node_id_at_position(6).unwrap();
+        text.apply_text_diff("Hello brave new world").unwrap();
+        assert_eq!(text.get_node_id_at_position(16).unwrap(), w);
+    }
+
+    #[test]
+    fn test_textarea_replicas_converge() {
+        let mut alice = text("The cat sat on the mat");
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+
+        // Each textarea reports only its new value
+        alice
+            .apply_text_diff("The black cat sat on the mat")
+            .unwrap();
+        bob.apply_text_diff("The cat sat on the red mat.").unwrap();
+        alice.merge(&bob).unwrap();
+        bob.merge(&alice).unwrap();
+
+        assert_eq!(alice.to_string(), "The black cat sat on the red mat.");
+        assert_eq!(bob.to_string(), alice.to_string());
+    }
+}
@@ -0,0 +1,185 @@
+//! Reading the text as it was at an earlier version
+//!
+//! Tombstones keep every character ever inserted, and each deletion is
+//! stamped with the client and clock that made it (see
+//! [`FugueBlock::deleted_at`](super::FugueBlock::deleted_at)). A version
+//! vector therefore picks out exactly what a replica at that version saw:
+//! the characters whose insert it had seen, less those whose deletion it
+//! had seen.
+//!
+//! Two things limit how far back a text can look: `gc` removes
+//! tombstones, and their text with them, and tombstones that arrived
+//! without a stamp (older payloads, Yjs updates, operation-based deletes)
+//! count as deleted at every version that has their insert. A character
+//! keeps only the earliest stamp, whether several replicas deleted it
+//! concurrently or re-chunking joined it with tombstones deleted before
+//! it, so a version may show it deleted a little early, or still show it
+//! after a later deletion.
+
+use super::text::FugueText;
+use crate::sync::VectorClock;
+use unicode_segmentation::UnicodeSegmentation;
+
+impl FugueText {
+    /// The version this text is at: for each client, the highest clock of
+    /// an insert or deletion seen from it
+    ///
+    /// Pass it to [`snapshot_at`](Self::snapshot_at) later to read the
+    /// text as it is now.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello").unwrap();
+    /// text.delete(0, 1).unwrap();
+    ///
+    /// // Five inserted characters, then one deletion
+    /// assert_eq!(text.current_version().get("client1"), 6);
+    /// ```
+    pub fn current_version(&self) -> VectorClock {
+        let mut version = self.state_vector();
+        for stamp in self
+            .blocks
+            .values()
+            .filter_map(|block| block.deleted_at.as_ref())
+        {
+            if stamp.clock > version.get(&stamp.client_id) {
+                version.update(&stamp.client_id, stamp.clock);
+            }
+        }
+        version
+    }
+
+    /// The visible text as it was at `version`
+    ///
+    /// Holds the characters inserted at or before `version` and not
+    /// deleted by then, in document order. Edits merged since, from any
+    /// replica, are left out. O(n) in the number of blocks, tombstones
+    /// included.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello World").unwrap();
+    /// let yesterday = text.current_version();
+    ///
+    /// text.delete(5, 6).unwrap();
+    /// text.insert(5, "!").unwrap();
+    ///
+    /// assert_eq!(text.to_string(), "Hello!");
+    /// assert_eq!(text.snapshot_at(&yesterday), "Hello World");
+    /// ```
+    pub fn snapshot_at(&self, version: &VectorClock) -> String {
+        let mut snapshot = String::new();
+
+        for id in self.document_order_with_tombstones() {
+            let block = &self.blocks[&id];
+            let len = block.len() as u64;
+            if len == 0 {
+                continue;
+            }
+            let seen = version.get(&id.client_id);
+            let first = id.clock + 1 - len;
+            if seen < first {
+                continue;
+            }
+            if block.is_deleted() {
+                match &block.deleted_at {
+                    Some(stamp) if stamp.clock > version.get(&stamp.client_id) => {}
+                    _ => continue,
+                }
+            }
+            if seen >= id.clock {
+                snapshot.push_str(block.text.as_str());
+            } else {
+                let count = (seen + 1 - first) as usize;
+                snapshot.extend(block.text.graphemes(true).take(count));
+            }
+        }
+
+        snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replays_a_scripted_history() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        let mut versions = Vec::new();
+
+        alice.insert(0, "Hello World").unwrap();
+        versions.push((alice.current_version(), "Hello World"));
+
+        bob.merge(&alice).unwrap();
+        bob.insert(5, ",").unwrap();
+        alice.delete(6, 5).unwrap();
+        alice.insert(6, "there").unwrap();
+        versions.push((alice.current_version(), "Hello there"));
+        versions.push((bob.current_version(), "Hello, World"));
+
+        alice.merge(&bob).unwrap();
+        bob.merge(&alice).unwrap();
+        versions.push((bob.current_version(), "Hello, there"));
+
+        // Deletes across blocks from both clients
+        bob.delete(4, 3).unwrap();
+        bob.insert(bob.len(), "!").unwrap();
+        versions.push((bob.current_version(), "Hellthere!"));
+        alice.merge(&bob).unwrap();
+
+        for (version, expected) in &versions {
+            assert_eq!(alice.snapshot_at(version), *expected);
+            assert_eq!(bob.snapshot_at(version), *expected);
+        }
+        assert_eq!(alice.snapshot_at(&VectorClock::new()), "");
+        assert_eq!(
+            alice.snapshot_at(&alice.current_version()),
+            alice.to_string()
+        );
+    }
+
+    #[test]
+    fn test_version_can_end_inside_a_block() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hi 👋🏽!").unwrap();
+
+        let mut version = VectorClock::new();
+        version.update("client1", 4);
+        assert_eq!(text.snapshot_at(&version), "Hi 👋🏽");
+
+        // A deletion the version hasn't seen yet
+        text.delete(0, 3).unwrap();
+        assert_eq!(text.to_string(), "👋🏽!");
+        assert_eq!(text.snapshot_at(&version), "Hi 👋🏽");
+    }
+
+    #[test]
+    fn test_earliest_concurrent_deletion_is_kept() {
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "abc").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+        bob.insert(0, "xyz").unwrap();
+
+        // Both delete the "b", bob's deletion at a higher clock
+        alice.delete(1, 1).unwrap();
+        bob.delete(4, 1).unwrap();
+        let before_bob = alice.current_version();
+        alice.merge(&bob).unwrap();
+        bob.merge(&alice).unwrap();
+
+        assert_eq!(alice.current_version(), bob.current_version());
+        assert_eq!(alice.snapshot_at(&before_bob), "ac");
+        assert_eq!(bob.snapshot_at(&before_bob), "ac");
+    }
+}
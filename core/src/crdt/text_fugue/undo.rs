@@ -0,0 +1,185 @@
+//! `UndoTarget` for `FugueText`
+//!
+//! Undoing an insert deletes whichever of its characters are still
+//! visible; undoing a delete re-inserts the text as new characters where
+//! the first deleted one sits, even if remote edits moved it since.
+
+use super::{FugueText, NodeId, TextError};
+use crate::error::{Result, SyncKitError};
+use crate::undo::{char_ids, id_runs, DeletedText, UndoStep, UndoTarget};
+use crate::ClientID;
+use unicode_segmentation::UnicodeSegmentation;
+
+impl FugueText {
+    /// Length of `text` in the units positions count (one clock value each)
+    pub(crate) fn text_len(text: &str) -> usize {
+        text.graphemes(true).count()
+    }
+
+    /// Delete whichever of the characters are still visible
+    fn revert_insert(&mut self, runs: &[(NodeId, usize)]) -> Result<UndoStep> {
+        let mut positions: Vec<usize> = char_ids(runs)
+            .filter_map(|id| self.get_position_of_node_id(&id))
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        // Contiguous ranges, as (start, length)
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for position in positions {
+            match ranges.last_mut() {
+                Some((start, length)) if *start + *length == position => *length += 1,
+                _ => ranges.push((position, 1)),
+            }
+        }
+
+        let before = self.snapshot();
+        let mut deleted = Vec::with_capacity(ranges.len());
+        // Back to front, so earlier positions stay put
+        for &(start, length) in ranges.iter().rev() {
+            let ids = (start..start + length)
+                .map(|position| self.get_node_id_at_position(position))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            deleted.push(DeletedText {
+                ids: id_runs(ids),
+                text: before.slice(start, start + length)?,
+            });
+            self.delete(start, length)?;
+        }
+        deleted.reverse();
+        Ok(UndoStep::TextDelete(deleted))
+    }
+
+    /// Re-insert each stretch where its first character was
+    fn revert_delete(&mut self, runs: &[DeletedText]) -> Result<UndoStep> {
+        let mut targets = Vec::with_capacity(runs.len());
+        for (index, run) in runs.iter().enumerate() {
+            if run.text.is_empty() {
+                continue;
+            }
+            let first = char_ids(&run.ids)
+                .next()
+                .ok_or_else(|| SyncKitError::invalid_input("deleted text without IDs"))?;
+            let (position, _) = self
+                .locate_node_id(&first)
+                .ok_or(TextError::BlockNotFound(first))?;
+            targets.push((position, index, run.text.as_str()));
+        }
+
+        // Back to front; stretches that now meet keep their order
+        targets.sort_unstable_by_key(|&(position, index, _)| std::cmp::Reverse((position, index)));
+        let mut inserted = Vec::with_capacity(targets.len());
+        for (position, index, text) in targets {
+            inserted.push((index, self.insert(position, text)?, Self::text_len(text)));
+        }
+        inserted.sort_unstable_by_key(|(index, _, _)| *index);
+        Ok(UndoStep::TextInsert(
+            inserted
+                .into_iter()
+                .map(|(_, id, length)| (id, length))
+                .collect(),
+        ))
+    }
+}
+
+impl UndoTarget for FugueText {
+    /// Reverts as this replica's client; `client_id` is not used
+    fn revert(&mut self, step: &UndoStep, _client_id: &ClientID) -> Result<UndoStep> {
+        self.transaction(|text| match step {
+            UndoStep::TextInsert(runs) => text.revert_insert(runs),
+            UndoStep::TextDelete(runs) => text.revert_delete(runs),
+            UndoStep::Fields(_) => Err(SyncKitError::invalid_input(
+                "field undo step applied to a text",
+            )),
+        })
+    }
+
+    fn can_revert(&mut self, step: &UndoStep) -> bool {
+        match step {
+            UndoStep::TextInsert(runs) => {
+                char_ids(runs).all(|id| self.locate_node_id(&id).is_some())
+            }
+            UndoStep::TextDelete(runs) => runs
+                .iter()
+                .flat_map(|run| char_ids(&run.ids))
+                .all(|id| self.locate_node_id(&id).is_some()),
+            UndoStep::Fields(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::undo::UndoManager;
+
+    #[test]
+    fn test_undo_redo_text() {
+        let mut text = FugueText::new("alice".to_string());
+        let mut undo = UndoManager::new("alice".to_string());
+        undo.insert_text(&mut text, 0, "Hello World").unwrap();
+        undo.delete_text(&mut text, 5, 6).unwrap();
+        undo.insert_text(&mut text, 5, "!").unwrap();
+        assert_eq!(text.to_string(), "Hello!");
+
+        undo.undo(&mut text).unwrap();
+        assert_eq!(text.to_string(), "Hello");
+        undo.undo(&mut text).unwrap();
+        assert_eq!(text.to_string(), "Hello World");
+        undo.redo(&mut text).unwrap();
+        assert_eq!(text.to_string(), "Hello");
+        undo.undo(&mut text).unwrap();
+        assert_eq!(text.to_string(), "Hello World");
+
+        // " World" is back as new characters; undoing the insert that
+        // first typed it still removes it
+        undo.undo(&mut text).unwrap();
+        assert_eq!(text.to_string(), "");
+        undo.redo(&mut text).unwrap();
+        undo.redo(&mut text).unwrap();
+        assert_eq!(text.to_string(), "Hello");
+    }
+
+    #[test]
+    fn test_undo_follows_remote_edits() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        alice.insert(0, "abcdef").unwrap();
+        bob.merge(&alice).unwrap();
+
+        let mut undo = UndoManager::new("alice".to_string());
+        undo.delete_text(&mut alice, 2, 2).unwrap();
+        undo.insert_text(&mut alice, 0, "XY").unwrap();
+        bob.insert(6, "!").unwrap();
+        bob.delete(4, 1).unwrap(); // the "e"
+        alice.merge(&bob).unwrap();
+        assert_eq!(alice.to_string(), "XYabf!");
+
+        undo.undo(&mut alice).unwrap();
+        assert_eq!(alice.to_string(), "abf!");
+        undo.undo(&mut alice).unwrap();
+        assert_eq!(alice.to_string(), "abcdf!");
+    }
+
+    #[test]
+    fn test_undo_leaves_remote_text_inside_local_insert() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        let mut undo = UndoManager::new("alice".to_string());
+        undo.insert_text(&mut alice, 0, "Hello World").unwrap();
+        bob.merge(&alice).unwrap();
+
+        bob.insert(5, ", brave").unwrap();
+        alice.merge(&bob).unwrap();
+        assert_eq!(alice.to_string(), "Hello, brave World");
+
+        // Only Alice's characters go, Bob's stay where they were typed
+        undo.undo(&mut alice).unwrap();
+        assert_eq!(alice.to_string(), ", brave");
+        bob.merge(&alice).unwrap();
+        assert_eq!(bob.to_string(), ", brave");
+
+        undo.redo(&mut alice).unwrap();
+        assert_eq!(alice.to_string(), "Hello, brave World");
+    }
+}
@@ -0,0 +1,325 @@
+//! Position units: one per grapheme cluster, as inserted
+//!
+//! Every position, length and offset in the text API counts units. A unit
+//! is a grapheme cluster of the text one insert added, so "é", a flag or a
+//! ZWJ family emoji is one unit however many chars it spans, and CJK
+//! ideographs are one each. Units are also what clocks count (one clock
+//! value per unit), which is why a cluster that only forms across separate
+//! inserts, such as an accent inserted after its letter, stays two units:
+//! each half keeps its own ID and position.
+//!
+//! The rope counts chars, so the text keeps the char length of every
+//! visible unit, in order, and converts through it.
+
+use crate::memory::HeapSize;
+use std::ops::Range;
+use std::sync::Arc;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Units per chunk of lengths; a chunk is cut up again past twice as many
+const CHUNK: usize = 512;
+
+/// Char lengths of a run of consecutive units, with their sum
+#[derive(Debug, Clone)]
+struct Chunk {
+    lengths: Vec<u32>,
+    chars: usize,
+}
+
+impl Chunk {
+    fn new(lengths: &[u32]) -> Self {
+        Chunk {
+            lengths: lengths.to_vec(),
+            chars: lengths.iter().map(|&chars| chars as usize).sum(),
+        }
+    }
+}
+
+/// Char length of each visible unit, in document order
+///
+/// Most text is one char per unit throughout, so the lengths are only
+/// stored once a unit spans several chars. They are kept in chunks that
+/// know their own char count, so converting a position skips whole chunks:
+/// lookups, inserts and removals cost O(n / 512 + 512) rather than O(n).
+/// Shared with snapshots until the next edit copies it.
+#[derive(Debug, Clone, Default)]
+pub(super) struct UnitTable {
+    len: usize,
+
+    /// Empty while every unit is one char, else every unit, chunked
+    chunks: Arc<Vec<Chunk>>,
+}
+
+impl UnitTable {
+    /// Number of units
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Char length of the text in the first `units` units
+    pub(super) fn char_offset(&self, units: usize) -> usize {
+        let mut units = units.min(self.len);
+        if self.chunks.is_empty() {
+            return units;
+        }
+        let mut offset = 0;
+        for chunk in self.chunks.iter() {
+            if units < chunk.lengths.len() {
+                let within: usize = chunk.lengths[..units]
+                    .iter()
+                    .map(|&chars| chars as usize)
+                    .sum();
+                return offset + within;
+            }
+            units -= chunk.lengths.len();
+            offset += chunk.chars;
+        }
+        offset
+    }
+
+    /// Number of units starting before char `chars`
+    pub(super) fn units_before_char(&self, chars: usize) -> usize {
+        if self.chunks.is_empty() {
+            return chars.min(self.len);
+        }
+        let mut units = 0;
+        let mut offset = 0;
+        for chunk in self.chunks.iter() {
+            if offset + chunk.chars < chars {
+                units += chunk.lengths.len();
+                offset += chunk.chars;
+                continue;
+            }
+            return units
+                + chunk
+                    .lengths
+                    .iter()
+                    .take_while(|&&unit| {
+                        let starts_before = offset < chars;
+                        offset += unit as usize;
+                        starts_before
+                    })
+                    .count();
+        }
+        units
+    }
+
+    /// Char range of the units in `units`
+    pub(super) fn char_range(&self, units: Range<usize>) -> Range<usize> {
+        self.char_offset(units.start)..self.char_offset(units.end)
+    }
+
+    /// Add the units of `text` (one insert) at the end
+    pub(super) fn push(&mut self, text: &str) {
+        self.insert(self.len, text);
+    }
+
+    /// Add the units of `text` (one insert) before unit `at`
+    pub(super) fn insert(&mut self, at: usize, text: &str) {
+        let lengths: Vec<u32> = text
+            .graphemes(true)
+            .map(|grapheme| grapheme.chars().count() as u32)
+            .collect();
+        if self.chunks.is_empty() && lengths.iter().all(|&chars| chars == 1) {
+            self.len += lengths.len();
+            return;
+        }
+        let chunks = Arc::make_mut(&mut self.chunks);
+        if chunks.is_empty() {
+            let ones = vec![1; self.len.max(1)];
+            chunks.extend(ones.chunks(CHUNK).map(Chunk::new));
+            if self.len == 0 {
+                chunks[0] = Chunk::new(&[]);
+            }
+        }
+        self.len += lengths.len();
+
+        // The chunk `at` falls in, or the end of the last one
+        let mut index = 0;
+        let mut at = at;
+        while at > chunks[index].lengths.len() {
+            at -= chunks[index].lengths.len();
+            index += 1;
+        }
+        let chunk = &mut chunks[index];
+        chunk.chars += lengths.iter().map(|&chars| chars as usize).sum::<usize>();
+        chunk.lengths.splice(at..at, lengths);
+        if chunk.lengths.len() > 2 * CHUNK {
+            let lengths = std::mem::take(&mut chunk.lengths);
+            chunks.splice(index..=index, lengths.chunks(CHUNK).map(Chunk::new));
+        }
+    }
+
+    /// Remove `count` units starting at `at`
+    pub(super) fn remove(&mut self, at: usize, count: usize) {
+        self.len -= count;
+        if self.chunks.is_empty() {
+            return;
+        }
+        let chunks = Arc::make_mut(&mut self.chunks);
+        let mut index = 0;
+        let mut at = at;
+        let mut count = count;
+        while count > 0 {
+            let chunk = &mut chunks[index];
+            if at >= chunk.lengths.len() {
+                at -= chunk.lengths.len();
+                index += 1;
+                continue;
+            }
+            let end = chunk.lengths.len().min(at + count);
+            let removed: usize = chunk.lengths.drain(at..end).map(|c| c as usize).sum();
+            chunk.chars -= removed;
+            count -= end - at;
+            at = 0;
+            if chunk.lengths.is_empty() {
+                chunks.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+
+        // Fold a chunk left small into the one after it, so removals do
+        // not leave ever more, ever shorter chunks
+        let touched = index.saturating_sub(1);
+        if touched + 1 < chunks.len()
+            && chunks[touched].lengths.len() + chunks[touched + 1].lengths.len() <= CHUNK
+        {
+            let next = chunks.remove(touched + 1);
+            let chunk = &mut chunks[touched];
+            chunk.lengths.extend(next.lengths);
+            chunk.chars += next.chars;
+        }
+    }
+
+    /// Char length of each unit from `unit` on, in order
+    pub(super) fn lengths_from(&self, unit: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut skip = unit.min(self.len);
+        let ones = if self.chunks.is_empty() {
+            self.len - skip
+        } else {
+            0
+        };
+        self.chunks
+            .iter()
+            .flat_map(move |chunk| {
+                let from = skip.min(chunk.lengths.len());
+                skip -= from;
+                &chunk.lengths[from..]
+            })
+            .map(|&chars| chars as usize)
+            .chain(std::iter::repeat_n(1, ones))
+    }
+
+    /// `text`, which these units describe, cut into units
+    pub(super) fn split<'a>(&'a self, text: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        let mut rest = text;
+        self.lengths_from(0).map(move |chars| {
+            let bytes = rest
+                .char_indices()
+                .nth(chars)
+                .map_or(rest.len(), |(byte, _)| byte);
+            let (unit, tail) = rest.split_at(bytes);
+            rest = tail;
+            unit
+        })
+    }
+}
+
+impl HeapSize for UnitTable {
+    fn heap_size(&self) -> usize {
+        self.chunks.capacity() * std::mem::size_of::<Chunk>()
+            + self
+                .chunks
+                .iter()
+                .map(|chunk| chunk.lengths.capacity() * std::mem::size_of::<u32>())
+                .sum::<usize>()
+    }
+}
+
+/// Unit position of each char position of `text` (one insert), for
+/// converting char ranges: entry `i` is the number of units that start
+/// before char `i`, and there is one entry past the end
+pub(super) fn units_by_char(text: &str) -> Vec<usize> {
+    let mut units = Vec::with_capacity(text.len() + 1);
+    for (unit, grapheme) in text.graphemes(true).enumerate() {
+        units.push(unit);
+        units.extend(std::iter::repeat_n(unit + 1, grapheme.chars().count() - 1));
+    }
+    units.push(text.graphemes(true).count());
+    units
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIXED: &str = "a👨\u{200D}👩\u{200D}👧e\u{301}漢字🇺🇸\r\n";
+
+    #[test]
+    fn test_units_are_clusters_of_each_insert() {
+        let mut table = UnitTable::default();
+        table.push(MIXED);
+        assert_eq!(table.len(), 7);
+        assert_eq!(table.char_offset(2), 6);
+        assert_eq!(table.char_range(2..4), 6..9);
+        assert_eq!(table.units_before_char(6), 2);
+        assert_eq!(table.units_before_char(7), 3);
+        assert_eq!(table.units_before_char(100), 7);
+
+        // Inserted on its own, an accent is a unit of its own
+        table.insert(1, "\u{301}");
+        assert_eq!(table.len(), 8);
+        let text = format!("a\u{301}{}", &MIXED[1..]);
+        let units: Vec<&str> = table.split(&text).collect();
+        assert_eq!(units[1], "\u{301}");
+        assert_eq!(units[2], "👨\u{200D}👩\u{200D}👧");
+        assert_eq!(units[7], "\r\n");
+
+        table.remove(1, 2);
+        assert_eq!(table.len(), 6);
+        assert_eq!(table.char_offset(6), MIXED.chars().count() - 5);
+    }
+
+    #[test]
+    fn test_chunks_agree_with_plain_lengths() {
+        let mut table = UnitTable::default();
+        let mut plain: Vec<usize> = Vec::new();
+        let insert = |table: &mut UnitTable, plain: &mut Vec<usize>, at: usize, text: &str| {
+            table.insert(at, text);
+            let lengths = text.graphemes(true).map(|unit| unit.chars().count());
+            plain.splice(at..at, lengths);
+        };
+        insert(&mut table, &mut plain, 0, &"ab".repeat(700));
+        insert(&mut table, &mut plain, 900, &MIXED.repeat(300));
+        for step in 0..200 {
+            let at = (step * 37) % (plain.len() + 1);
+            insert(&mut table, &mut plain, at, "e\u{301}🇺🇸x");
+            if step % 3 == 0 {
+                let count = (plain.len() - at).min(step / 10);
+                table.remove(at, count);
+                plain.drain(at..at + count);
+            }
+        }
+        table.remove(100, 2000);
+        plain.drain(100..2100);
+
+        assert_eq!(table.len(), plain.len());
+        let mut offset = 0;
+        for (unit, &chars) in plain.iter().enumerate() {
+            assert_eq!(table.char_offset(unit), offset);
+            assert_eq!(table.units_before_char(offset), unit);
+            assert_eq!(table.units_before_char(offset + 1), unit + 1);
+            offset += chars;
+        }
+        assert_eq!(table.char_offset(plain.len()), offset);
+        assert!(table.lengths_from(0).eq(plain.iter().copied()));
+        assert!(table.lengths_from(1234).eq(plain[1234..].iter().copied()));
+    }
+
+    #[test]
+    fn test_units_by_char() {
+        let units = units_by_char("e\u{301}x");
+        assert_eq!(units, [0, 1, 1, 2]);
+    }
+}
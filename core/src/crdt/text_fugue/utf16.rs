@@ -0,0 +1,228 @@
+//! Positions in UTF-16 code units, as JavaScript strings count them
+//!
+//! Editors on the web report selections as offsets into a JS string, so a
+//! character outside the Basic Multilingual Plane (most emoji) counts two,
+//! once per surrogate. The rope tracks UTF-16 lengths alongside chars, so
+//! converting to units is a rope lookup plus the unit table's char
+//! mapping. An offset that doesn't fall between two units is an error, not
+//! rounded: a binding that computes one has lost track of the text, and
+//! guessing which side was meant would edit the wrong character.
+
+use super::node::NodeId;
+use super::text::{FugueText, TextError};
+
+impl FugueText {
+    /// Length of the visible text in UTF-16 code units (JavaScript's
+    /// `string.length`)
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hi 😀").unwrap();
+    ///
+    /// assert_eq!(text.len(), 4);
+    /// assert_eq!(text.len_utf16(), 5);
+    /// ```
+    pub fn len_utf16(&self) -> usize {
+        self.rope.len_utf16_cu()
+    }
+
+    /// The position (in units, see `len`) at UTF-16 offset `position`
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::PositionOutOfBounds` past the end of the text,
+    /// and `TextError::InvalidUtf16Position` for an offset between the
+    /// halves of a surrogate pair or inside a grapheme cluster.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "😀!").unwrap();
+    ///
+    /// assert_eq!(text.utf16_to_grapheme(2).unwrap(), 1);
+    /// assert!(text.utf16_to_grapheme(1).is_err());
+    /// ```
+    pub fn utf16_to_grapheme(&self, position: usize) -> Result<usize, TextError> {
+        let length = self.len_utf16();
+        if position > length {
+            return Err(TextError::PositionOutOfBounds { position, length });
+        }
+
+        // Ropey rounds an offset inside a surrogate pair down to its char
+        let char_index = self.rope.utf16_cu_to_char(position);
+        if self.rope.char_to_utf16_cu(char_index) != position {
+            return Err(TextError::InvalidUtf16Position {
+                position,
+                surrogate_pair: true,
+            });
+        }
+        let unit = self.units.units_before_char(char_index);
+        if self.units.char_offset(unit) != char_index {
+            return Err(TextError::InvalidUtf16Position {
+                position,
+                surrogate_pair: false,
+            });
+        }
+        Ok(unit)
+    }
+
+    /// Insert `text` at UTF-16 offset `position`
+    ///
+    /// The same as `insert` at the matching position.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`utf16_to_grapheme`](Self::utf16_to_grapheme)
+    /// for `position`; nothing is inserted.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "😀😀").unwrap();
+    /// text.insert_utf16(2, " and ").unwrap();
+    ///
+    /// assert_eq!(text.to_string(), "😀 and 😀");
+    /// ```
+    pub fn insert_utf16(&mut self, position: usize, text: &str) -> Result<NodeId, TextError> {
+        let position = self.utf16_to_grapheme(position)?;
+        self.insert(position, text)
+    }
+
+    /// Delete the `length` UTF-16 code units from offset `position`
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::RangeOutOfBounds` if the range runs past the end
+    /// of the text, and `TextError::InvalidUtf16Position` if either end
+    /// falls inside a character; nothing is deleted.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "a😀b").unwrap();
+    /// text.delete_utf16(1, 2).unwrap();
+    ///
+    /// assert_eq!(text.to_string(), "ab");
+    /// ```
+    pub fn delete_utf16(
+        &mut self,
+        position: usize,
+        length: usize,
+    ) -> Result<Vec<NodeId>, TextError> {
+        let end = position.saturating_add(length);
+        let text_length = self.len_utf16();
+        if end > text_length {
+            return Err(TextError::RangeOutOfBounds {
+                start: position,
+                end,
+                length: text_length,
+            });
+        }
+        let start = self.utf16_to_grapheme(position)?;
+        let end = self.utf16_to_grapheme(end)?;
+        self.delete(start, end - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_astral_emoji_count_two() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "a😀b𝄞c").unwrap();
+        assert_eq!(text.len_utf16(), 7);
+
+        let positions: Vec<_> = (0..=7).map(|i| text.utf16_to_grapheme(i).ok()).collect();
+        assert_eq!(
+            positions,
+            [
+                Some(0),
+                Some(1),
+                None,
+                Some(2),
+                Some(3),
+                None,
+                Some(4),
+                Some(5)
+            ]
+        );
+        assert_eq!(
+            text.utf16_to_grapheme(2),
+            Err(TextError::InvalidUtf16Position {
+                position: 2,
+                surrogate_pair: true,
+            })
+        );
+        assert_eq!(
+            text.utf16_to_grapheme(8),
+            Err(TextError::PositionOutOfBounds {
+                position: 8,
+                length: 7,
+            })
+        );
+
+        text.insert_utf16(3, "!").unwrap();
+        text.delete_utf16(4, 3).unwrap();
+        assert_eq!(text.to_string(), "a😀!c");
+    }
+
+    #[test]
+    fn test_mixed_content_never_splits_a_character() {
+        // BMP, combining marks, CJK, a skin-tone modifier and a ZWJ family
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "e\u{301}日👋🏽x👨‍👩‍👧").unwrap();
+        assert_eq!(text.len(), 5);
+        assert_eq!(text.len_utf16(), "e\u{301}日👋🏽x👨‍👩‍👧".encode_utf16().count());
+
+        // e + U+0301, then 日: the accent is inside the first cluster
+        assert_eq!(
+            text.utf16_to_grapheme(1),
+            Err(TextError::InvalidUtf16Position {
+                position: 1,
+                surrogate_pair: false,
+            })
+        );
+        assert_eq!(text.utf16_to_grapheme(3).unwrap(), 2);
+        // Between the waving hand and its modifier
+        assert_eq!(
+            text.utf16_to_grapheme(5),
+            Err(TextError::InvalidUtf16Position {
+                position: 5,
+                surrogate_pair: false,
+            })
+        );
+        assert!(matches!(
+            text.utf16_to_grapheme(4),
+            Err(TextError::InvalidUtf16Position {
+                surrogate_pair: true,
+                ..
+            })
+        ));
+
+        let before = text.to_string();
+        assert!(text.insert_utf16(4, "?").is_err());
+        assert!(text.delete_utf16(3, 2).is_err());
+        assert!(text.delete_utf16(3, 100).is_err());
+        assert_eq!(text.to_string(), before);
+
+        // Delete the waving hand with its modifier, then type at the end
+        text.delete_utf16(3, 4).unwrap();
+        text.insert_utf16(text.len_utf16(), "!").unwrap();
+        assert_eq!(text.to_string(), "e\u{301}日x👨‍👩‍👧!");
+    }
+}
@@ -0,0 +1,872 @@
+//! Yjs interop: import and export the Yjs v1 update format for text
+//!
+//! Lets documents move between SyncKit and Yjs-based tooling (y-websocket
+//! servers, editor bindings) in one shot. Live bidirectional sync is out of
+//! scope: an imported FugueText starts its own history.
+//!
+//! # Mapping
+//!
+//! - **Clients:** Yjs client numbers become decimal `client_id` strings, and
+//!   numeric client ids are exported unchanged, so attribution survives a
+//!   round trip. Other client ids are hashed to a stable 32-bit number.
+//! - **Order:** Yjs items are integrated with YATA (the same conflict
+//!   resolution Yjs uses), then laid out as origin-less Fugue blocks with
+//!   increasing Lamport clocks. This reproduces the Yjs order exactly and
+//!   keeps the Fugue tree flat regardless of document size.
+//! - **Export:** Blocks are written in document order, each with the previous
+//!   block's last character as its origin, so Yjs integrates them as a chain.
+//! - **Deletions:** Tombstones are exported as `ContentDeleted` items plus a
+//!   delete set. Deleted content received from Yjs has no text; it is kept as
+//!   U+FFFD placeholders so clock ranges stay intact.
+//!
+//! Only the content types plain YText uses are supported: strings, deleted
+//! content and (invisible) formatting marks. Embeds, nested types and map
+//! entries are rejected with `TextError::InvalidYjsUpdate`.
+
+use super::block::FugueBlock;
+use super::node::NodeId;
+use super::text::{FugueText, TextError};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Placeholder for deleted characters whose content Yjs didn't send
+const DELETED_PLACEHOLDER: char = '\u{FFFD}';
+
+// Yjs struct info bits and content refs
+const BIT_ORIGIN: u8 = 0x80;
+const BIT_RIGHT_ORIGIN: u8 = 0x40;
+const BIT_PARENT_SUB: u8 = 0x20;
+const CONTENT_REF_MASK: u8 = 0x1f;
+
+const REF_GC: u8 = 0;
+const REF_DELETED: u8 = 1;
+const REF_STRING: u8 = 4;
+const REF_FORMAT: u8 = 6;
+const REF_SKIP: u8 = 10;
+
+/// Yjs struct ID: (client, clock), clock counted in UTF-16 code units
+type YId = (u64, u64);
+
+impl FugueText {
+    /// Import a text document from a Yjs v1 update
+    ///
+    /// Accepts the output of `Y.encodeStateAsUpdate(doc)` for a document
+    /// whose content lives in a single root `Y.Text`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - Client ID for local edits made after the import
+    /// * `update` - Yjs v1 update bytes
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// // Y.Doc with client 1 and getText("text").insert(0, "abc")
+    /// let update = [1, 1, 1, 0, 4, 1, 4, b't', b'e', b'x', b't', 3, b'a', b'b', b'c', 0];
+    ///
+    /// let text = FugueText::from_yjs_update("client1".to_string(), &update).unwrap();
+    /// assert_eq!(text.to_string(), "abc");
+    /// ```
+    pub fn from_yjs_update(client_id: String, update: &[u8]) -> Result<Self, TextError> {
+        let mut decoder = Decoder::new(update);
+        let structs = read_structs(&mut decoder)?;
+        let delete_set = read_delete_set(&mut decoder)?;
+
+        let mut list = YataList::default();
+        list.integrate_all(structs)?;
+        for (client, clock, len) in delete_set {
+            list.delete_range(client, clock, len);
+        }
+
+        let mut text = FugueText::new(client_id);
+        let mut lamport = 0u64;
+
+        for run in list.runs()? {
+            let len = run.text.graphemes(true).count() as u64;
+            if len == 0 {
+                continue;
+            }
+            lamport += len;
+
+            let id = NodeId::new(run.client.to_string(), lamport, 0);
+            let mut block = FugueBlock::new(id, run.text, None, None);
+            if run.deleted {
+                block.mark_deleted();
+            }
+            text.store_remote_block(block);
+        }
+
+        text.clock.update(lamport);
+        text.rebuild_rope();
+        text.restart_history();
+
+        Ok(text)
+    }
+
+    /// Export this text as a Yjs v1 update
+    ///
+    /// The result can be applied with `Y.applyUpdate(doc, update)` and read
+    /// back with `doc.getText(root_name)`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("1".to_string());
+    /// text.insert(0, "abc").unwrap();
+    ///
+    /// let update = text.to_yjs_update("text");
+    /// assert_eq!(
+    ///     update,
+    ///     [1, 1, 1, 0, 4, 1, 4, b't', b'e', b'x', b't', 3, b'a', b'b', b'c', 0]
+    /// );
+    /// ```
+    pub fn to_yjs_update(&self, root_name: &str) -> Vec<u8> {
+        let order = self.document_order_with_tombstones();
+        let clients = yjs_client_numbers(order.iter().map(|id| id.client_id.as_str()));
+
+        // Items per Yjs client, in clock order: (origin, content)
+        let mut items: BTreeMap<u64, Vec<(Option<YId>, &FugueBlock)>> = BTreeMap::new();
+        let mut next_clock: HashMap<u64, u64> = HashMap::new();
+        let mut deleted: BTreeMap<u64, Vec<(u64, u64)>> = BTreeMap::new();
+        let mut origin: Option<YId> = None;
+
+        for id in &order {
+            let block = &self.blocks[id];
+            let len = block.text.encode_utf16().count() as u64;
+            if len == 0 {
+                continue;
+            }
+
+            let client = clients[id.client_id.as_str()];
+            let clock = next_clock.entry(client).or_insert(0);
+            let start = *clock;
+            *clock += len;
+
+            items.entry(client).or_default().push((origin, block));
+            if block.is_deleted() {
+                let ranges = deleted.entry(client).or_default();
+                match ranges.last_mut() {
+                    Some((last_start, last_len)) if *last_start + *last_len == start => {
+                        *last_len += len;
+                    }
+                    _ => ranges.push((start, len)),
+                }
+            }
+
+            origin = Some((client, start + len - 1));
+        }
+
+        let mut encoder = Encoder::default();
+
+        // Yjs writes clients in descending order
+        encoder.write_var_uint(items.len() as u64);
+        for (client, client_items) in items.iter().rev() {
+            encoder.write_var_uint(client_items.len() as u64);
+            encoder.write_var_uint(*client);
+            encoder.write_var_uint(0);
+
+            for (origin, block) in client_items {
+                let content_ref = if block.is_deleted() {
+                    REF_DELETED
+                } else {
+                    REF_STRING
+                };
+
+                match origin {
+                    Some((origin_client, origin_clock)) => {
+                        encoder.write_u8(BIT_ORIGIN | content_ref);
+                        encoder.write_var_uint(*origin_client);
+                        encoder.write_var_uint(*origin_clock);
+                    }
+                    None => {
+                        encoder.write_u8(content_ref);
+                        encoder.write_var_uint(1); // parent is a root type
+                        encoder.write_var_string(root_name);
+                    }
+                }
+
+                if block.is_deleted() {
+                    encoder.write_var_uint(block.text.encode_utf16().count() as u64);
+                } else {
+                    encoder.write_var_string(&block.text);
+                }
+            }
+        }
+
+        encoder.write_var_uint(deleted.len() as u64);
+        for (client, ranges) in deleted.iter().rev() {
+            encoder.write_var_uint(*client);
+            encoder.write_var_uint(ranges.len() as u64);
+            for (clock, len) in ranges {
+                encoder.write_var_uint(*clock);
+                encoder.write_var_uint(*len);
+            }
+        }
+
+        encoder.buf
+    }
+}
+
+/// Map client IDs to Yjs client numbers
+///
+/// Numeric IDs (as produced by `from_yjs_update`) are kept; others are
+/// hashed with FNV-1a and probed linearly until unique.
+fn yjs_client_numbers<'a>(client_ids: impl Iterator<Item = &'a str>) -> HashMap<&'a str, u64> {
+    let mut client_ids: Vec<&str> = client_ids.collect();
+    client_ids.sort_unstable();
+    client_ids.dedup();
+
+    let mut numbers = HashMap::new();
+    let mut used = HashSet::new();
+
+    let (numeric, named): (Vec<&str>, Vec<&str>) = client_ids
+        .into_iter()
+        .partition(|id| id.parse::<u32>().is_ok());
+
+    for id in numeric {
+        let number = id.parse::<u32>().unwrap() as u64;
+        used.insert(number);
+        numbers.insert(id, number);
+    }
+
+    for id in named {
+        let mut number = id.bytes().fold(0x811c_9dc5u32, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        }) as u64;
+        while !used.insert(number) {
+            number = (number + 1) & 0xffff_ffff;
+        }
+        numbers.insert(id, number);
+    }
+
+    numbers
+}
+
+// ============================================================================
+// Decoding
+// ============================================================================
+
+fn invalid(msg: impl Into<String>) -> TextError {
+    TextError::InvalidYjsUpdate(msg.into())
+}
+
+/// Content of a single Yjs struct
+enum Content {
+    /// Garbage-collected range (no position information left)
+    Gc(u64),
+    /// Visible text as UTF-16 code units
+    String(Vec<u16>),
+    /// Deleted content of the given length
+    Deleted(u64),
+    /// Formatting mark (takes one clock, invisible)
+    Format,
+}
+
+impl Content {
+    fn len(&self) -> u64 {
+        match self {
+            Content::Gc(len) | Content::Deleted(len) => *len,
+            Content::String(units) => units.len() as u64,
+            Content::Format => 1,
+        }
+    }
+}
+
+struct YStruct {
+    id: YId,
+    origin: Option<YId>,
+    right_origin: Option<YId>,
+    content: Content,
+}
+
+fn read_structs(decoder: &mut Decoder) -> Result<Vec<YStruct>, TextError> {
+    let mut structs = Vec::new();
+    let mut root_name: Option<String> = None;
+
+    let num_clients = decoder.read_var_uint()?;
+    for _ in 0..num_clients {
+        let num_structs = decoder.read_var_uint()?;
+        let client = decoder.read_var_uint()?;
+        let mut clock = decoder.read_var_uint()?;
+
+        for _ in 0..num_structs {
+            let info = decoder.read_u8()?;
+            let content_ref = info & CONTENT_REF_MASK;
+
+            match content_ref {
+                REF_GC => {
+                    let len = decoder.read_var_uint()?;
+                    structs.push(YStruct {
+                        id: (client, clock),
+                        origin: None,
+                        right_origin: None,
+                        content: Content::Gc(len),
+                    });
+                    clock += len;
+                    continue;
+                }
+                REF_SKIP => {
+                    clock += decoder.read_var_uint()?;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let origin = if info & BIT_ORIGIN != 0 {
+                Some((decoder.read_var_uint()?, decoder.read_var_uint()?))
+            } else {
+                None
+            };
+            let right_origin = if info & BIT_RIGHT_ORIGIN != 0 {
+                Some((decoder.read_var_uint()?, decoder.read_var_uint()?))
+            } else {
+                None
+            };
+
+            // Parent info is only written when it can't be taken from an origin
+            if origin.is_none() && right_origin.is_none() {
+                if decoder.read_var_uint()? != 1 {
+                    return Err(invalid("nested types are not supported"));
+                }
+                let name = decoder.read_var_string()?;
+                match &root_name {
+                    Some(existing) if *existing != name => {
+                        return Err(invalid(format!(
+                            "update contains several root types ({} and {})",
+                            existing, name
+                        )));
+                    }
+                    _ => root_name = Some(name),
+                }
+                if info & BIT_PARENT_SUB != 0 {
+                    return Err(invalid("map entries are not supported"));
+                }
+            }
+
+            let content = match content_ref {
+                REF_DELETED => Content::Deleted(decoder.read_var_uint()?),
+                REF_STRING => Content::String(decoder.read_var_string()?.encode_utf16().collect()),
+                REF_FORMAT => {
+                    decoder.read_var_string()?; // key
+                    decoder.read_var_string()?; // JSON value
+                    Content::Format
+                }
+                other => {
+                    return Err(invalid(format!("unsupported content type {}", other)));
+                }
+            };
+
+            let len = content.len();
+            structs.push(YStruct {
+                id: (client, clock),
+                origin,
+                right_origin,
+                content,
+            });
+            clock += len;
+        }
+    }
+
+    Ok(structs)
+}
+
+fn read_delete_set(decoder: &mut Decoder) -> Result<Vec<(u64, u64, u64)>, TextError> {
+    let mut ranges = Vec::new();
+
+    let num_clients = decoder.read_var_uint()?;
+    for _ in 0..num_clients {
+        let client = decoder.read_var_uint()?;
+        let num_ranges = decoder.read_var_uint()?;
+        for _ in 0..num_ranges {
+            let clock = decoder.read_var_uint()?;
+            let len = decoder.read_var_uint()?;
+            ranges.push((client, clock, len));
+        }
+    }
+
+    Ok(ranges)
+}
+
+// ============================================================================
+// YATA integration
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum UnitContent {
+    Char(u16),
+    Deleted,
+    Format,
+}
+
+/// A single clock unit of a Yjs item (items are split to unit granularity)
+struct Unit {
+    id: YId,
+    origin: Option<YId>,
+    right_origin: Option<YId>,
+    content: UnitContent,
+    deleted: bool,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+/// A run of consecutive units ready to become a Fugue block
+struct Run {
+    client: u64,
+    text: String,
+    deleted: bool,
+}
+
+#[derive(Default)]
+struct YataList {
+    units: Vec<Unit>,
+    index: HashMap<YId, usize>,
+    gc: HashSet<YId>,
+    head: Option<usize>,
+}
+
+impl YataList {
+    /// Integrate all structs, respecting origin dependencies
+    fn integrate_all(&mut self, structs: Vec<YStruct>) -> Result<(), TextError> {
+        // Explode items into units; unit i's origin is unit i-1 (as when Yjs splits)
+        let mut queues: BTreeMap<u64, Vec<Unit>> = BTreeMap::new();
+        let mut known: HashSet<YId> = HashSet::new();
+
+        for s in structs {
+            let (client, clock) = s.id;
+            let len = s.content.len();
+
+            if let Content::Gc(_) = s.content {
+                for i in 0..len {
+                    self.gc.insert((client, clock + i));
+                }
+                continue;
+            }
+
+            let queue = queues.entry(client).or_default();
+            for i in 0..len {
+                let content = match &s.content {
+                    Content::String(units) => UnitContent::Char(units[i as usize]),
+                    Content::Deleted(_) => UnitContent::Deleted,
+                    _ => UnitContent::Format,
+                };
+                known.insert((client, clock + i));
+                queue.push(Unit {
+                    id: (client, clock + i),
+                    origin: if i == 0 {
+                        s.origin
+                    } else {
+                        Some((client, clock + i - 1))
+                    },
+                    right_origin: s.right_origin,
+                    deleted: content == UnitContent::Deleted,
+                    content,
+                    left: None,
+                    right: None,
+                });
+            }
+        }
+
+        for (client, queue) in queues.iter_mut() {
+            queue.reverse(); // pop() from the front in clock order
+            for unit in queue.iter() {
+                for dep in unit.origin.iter().chain(unit.right_origin.iter()) {
+                    if !known.contains(dep) && !self.gc.contains(dep) {
+                        return Err(invalid(format!(
+                            "struct {}:{} depends on {}:{}, which is not in the update",
+                            client, unit.id.1, dep.0, dep.1
+                        )));
+                    }
+                }
+            }
+        }
+
+        // Integrate whatever is ready until every queue drains
+        loop {
+            let mut progress = false;
+
+            for queue in queues.values_mut().rev() {
+                while let Some(unit) = queue.last() {
+                    let deps: Vec<YId> = unit.origin.into_iter().chain(unit.right_origin).collect();
+
+                    if deps.iter().any(|dep| self.gc.contains(dep)) {
+                        // Neighbour was garbage collected: Yjs GCs this unit too
+                        let unit = queue.pop().unwrap();
+                        self.gc.insert(unit.id);
+                        progress = true;
+                        continue;
+                    }
+                    if !deps.iter().all(|dep| self.index.contains_key(dep)) {
+                        break;
+                    }
+
+                    let unit = queue.pop().unwrap();
+                    self.integrate(unit);
+                    progress = true;
+                }
+            }
+
+            if queues.values().all(|queue| queue.is_empty()) {
+                return Ok(());
+            }
+            if !progress {
+                return Err(invalid("update contains a dependency cycle"));
+            }
+        }
+    }
+
+    /// YATA integration of a single unit (mirrors `Item.integrate` in Yjs)
+    fn integrate(&mut self, mut unit: Unit) {
+        let origin = unit.origin.map(|id| self.index[&id]);
+        let right = unit.right_origin.map(|id| self.index[&id]);
+        let mut left = origin;
+
+        let conflict = match left {
+            None => right.is_none_or(|r| self.units[r].left.is_some()),
+            Some(l) => self.units[l].right != right,
+        };
+
+        if conflict {
+            let mut o = match left {
+                Some(l) => self.units[l].right,
+                None => self.head,
+            };
+            let mut conflicting: HashSet<usize> = HashSet::new();
+            let mut before_origin: HashSet<usize> = HashSet::new();
+
+            while let Some(oi) = o {
+                if Some(oi) == right {
+                    break;
+                }
+                before_origin.insert(oi);
+                conflicting.insert(oi);
+
+                let other = &self.units[oi];
+                if other.origin == unit.origin {
+                    if other.id.0 < unit.id.0 {
+                        left = Some(oi);
+                        conflicting.clear();
+                    } else if other.right_origin == unit.right_origin {
+                        break;
+                    }
+                } else if let Some(other_origin) = other
+                    .origin
+                    .and_then(|id| self.index.get(&id).copied())
+                    .filter(|idx| before_origin.contains(idx))
+                {
+                    if !conflicting.contains(&other_origin) {
+                        left = Some(oi);
+                        conflicting.clear();
+                    }
+                } else {
+                    break;
+                }
+
+                o = self.units[oi].right;
+            }
+        }
+
+        let idx = self.units.len();
+        unit.left = left;
+        unit.right = match left {
+            Some(l) => self.units[l].right,
+            None => self.head,
+        };
+        match left {
+            Some(l) => self.units[l].right = Some(idx),
+            None => self.head = Some(idx),
+        }
+        if let Some(r) = unit.right {
+            self.units[r].left = Some(idx);
+        }
+
+        self.index.insert(unit.id, idx);
+        self.units.push(unit);
+    }
+
+    fn delete_range(&mut self, client: u64, clock: u64, len: u64) {
+        for c in clock..clock + len {
+            if let Some(&idx) = self.index.get(&(client, c)) {
+                self.units[idx].deleted = true;
+            }
+        }
+    }
+
+    /// Group units in document order into runs of consecutive clocks
+    fn runs(&self) -> Result<Vec<Run>, TextError> {
+        let mut runs = Vec::new();
+        let mut current: Option<(YId, bool, Vec<u16>)> = None;
+
+        let mut flush = |current: &mut Option<(YId, bool, Vec<u16>)>| -> Result<(), TextError> {
+            if let Some(((client, clock), deleted, units)) = current.take() {
+                let text = String::from_utf16(&units).map_err(|_| {
+                    invalid(format!(
+                        "struct {}:{} splits a surrogate pair",
+                        client, clock
+                    ))
+                })?;
+                runs.push(Run {
+                    client,
+                    text,
+                    deleted,
+                });
+            }
+            Ok(())
+        };
+
+        let mut next = self.head;
+        while let Some(idx) = next {
+            let unit = &self.units[idx];
+            next = unit.right;
+
+            let code_unit = match unit.content {
+                UnitContent::Char(c) => c,
+                UnitContent::Deleted => DELETED_PLACEHOLDER as u16,
+                UnitContent::Format => continue,
+            };
+
+            let extends = matches!(
+                &current,
+                Some(((client, clock), deleted, units))
+                    if *client == unit.id.0
+                        && *clock + units.len() as u64 == unit.id.1
+                        && *deleted == unit.deleted
+            );
+            if !extends {
+                flush(&mut current)?;
+                current = Some((unit.id, unit.deleted, Vec::new()));
+            }
+            if let Some((_, _, units)) = current.as_mut() {
+                units.push(code_unit);
+            }
+        }
+        flush(&mut current)?;
+
+        Ok(runs)
+    }
+}
+
+// ============================================================================
+// lib0 encoding
+// ============================================================================
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn read_u8(&mut self) -> Result<u8, TextError> {
+        let byte = *self
+            .buf
+            .get(self.pos)
+            .ok_or_else(|| invalid("unexpected end of update"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn read_var_uint(&mut self) -> Result<u64, TextError> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift > 63 {
+                return Err(invalid("varint overflow"));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn read_var_string(&mut self) -> Result<String, TextError> {
+        let len = self.read_var_uint()? as usize;
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| invalid("unexpected end of update"))?;
+        let s = std::str::from_utf8(&self.buf[self.pos..end])
+            .map_err(|_| invalid("string is not valid UTF-8"))?;
+        self.pos = end;
+        Ok(s.to_string())
+    }
+}
+
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn write_u8(&mut self, byte: u8) {
+        self.buf.push(byte);
+    }
+
+    fn write_var_uint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn write_var_string(&mut self, s: &str) {
+        self.write_var_uint(s.len() as u64);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/tests/fixtures/yjs/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    fn import(name: &str) -> FugueText {
+        FugueText::from_yjs_update("local".to_string(), &fixture(name)).unwrap()
+    }
+
+    fn visible_clients(text: &FugueText) -> Vec<(String, String)> {
+        text.document_order_with_tombstones()
+            .into_iter()
+            .filter(|id| !text.blocks[id].is_deleted())
+            .map(|id| (id.client_id.to_string(), text.blocks[&id].text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_import_plain_text() {
+        let text = import("hello.bin");
+        assert_eq!(text.to_string(), "hello");
+        assert_eq!(
+            visible_clients(&text),
+            vec![("1".to_string(), "hello".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_import_deleted_content() {
+        let text = import("hello_world_deleted.bin");
+        assert_eq!(text.to_string(), "hello");
+        assert_eq!(text.blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_import_insert_between_origins() {
+        let text = import("concurrent_insert_middle.bin");
+        assert_eq!(text.to_string(), "aXb");
+        assert_eq!(
+            visible_clients(&text),
+            vec![
+                ("1".to_string(), "a".to_string()),
+                ("2".to_string(), "X".to_string()),
+                ("1".to_string(), "b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_concurrent_inserts_at_start() {
+        // Lower client number wins the left slot, as in Yjs
+        assert_eq!(import("concurrent_insert_start.bin").to_string(), "AB");
+    }
+
+    #[test]
+    fn test_import_unicode() {
+        assert_eq!(import("unicode.bin").to_string(), "héllo 👋");
+    }
+
+    #[test]
+    fn test_export_matches_yjs_encoding() {
+        let mut text = FugueText::new("1".to_string());
+        text.insert(0, "hello").unwrap();
+        assert_eq!(text.to_yjs_update("text"), fixture("hello.bin"));
+
+        let mut text = FugueText::new("1".to_string());
+        text.insert(0, "hello world").unwrap();
+        text.delete(5, 6).unwrap();
+        assert_eq!(
+            text.to_yjs_update("text"),
+            fixture("hello_world_deleted.bin")
+        );
+    }
+
+    #[test]
+    fn test_round_trip_preserves_text_and_attribution() {
+        let mut text1 = FugueText::new("alice".to_string());
+        let mut text2 = FugueText::new("bob".to_string());
+
+        text1.insert(0, "Hello World").unwrap();
+        text2.merge(&text1).unwrap();
+        text2.insert(11, "!").unwrap();
+        text1.delete(0, 6).unwrap();
+        text1.merge(&text2).unwrap();
+
+        let imported =
+            FugueText::from_yjs_update("carol".to_string(), &text1.to_yjs_update("text")).unwrap();
+        assert_eq!(imported.to_string(), text1.to_string());
+
+        // Named clients are hashed to stable numbers; distinct authors stay distinct
+        let authors: HashSet<String> = visible_clients(&imported)
+            .into_iter()
+            .map(|(client, _)| client)
+            .collect();
+        assert_eq!(authors.len(), 2);
+
+        // Numeric client IDs survive a second round trip unchanged
+        let again = FugueText::from_yjs_update("dave".to_string(), &imported.to_yjs_update("text"))
+            .unwrap();
+        assert_eq!(visible_clients(&again), visible_clients(&imported));
+    }
+
+    #[test]
+    fn test_imported_text_accepts_local_edits() {
+        let mut text = import("concurrent_insert_middle.bin");
+        text.insert(3, "c").unwrap();
+        text.insert(0, ">").unwrap();
+        assert_eq!(text.to_string(), ">aXbc");
+
+        let mut other = FugueText::new("other".to_string());
+        other.merge(&text).unwrap();
+        assert_eq!(other.to_string(), ">aXbc");
+    }
+
+    #[test]
+    fn test_rejects_malformed_updates() {
+        // Truncated
+        assert!(matches!(
+            FugueText::from_yjs_update("local".to_string(), &fixture("hello.bin")[..8]),
+            Err(TextError::InvalidYjsUpdate(_))
+        ));
+
+        // Origin points outside the update
+        let dangling = [1, 1, 1, 0, 0x84, 7, 3, 1, b'x', 0];
+        assert!(FugueText::from_yjs_update("local".to_string(), &dangling).is_err());
+
+        // Embed content
+        let embed = [1, 1, 1, 0, 5, 1, 1, b't', 2, b'{', b'}', 0];
+        assert!(FugueText::from_yjs_update("local".to_string(), &embed).is_err());
+    }
+
+    #[test]
+    fn test_var_uint_round_trip() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, (1 << 53) - 1] {
+            let mut encoder = Encoder::default();
+            encoder.write_var_uint(value);
+            assert_eq!(Decoder::new(&encoder.buf).read_var_uint().unwrap(), value);
+        }
+    }
+}
@@ -9,16 +9,40 @@
 //! - Idempotence: Applying operation twice has no effect
 //! - Commutativity: Order of merges doesn't matter
 
+use crate::annotations::{Annotation, AnnotationRetention, Annotations};
+use crate::conflicts::{ConflictId, ConflictLog, Keep};
+use crate::list::{List, ListMut};
+use crate::locks::AdvisoryLocks;
+use crate::memory::{DocumentMemoryStats, HeapSize, MemoryUsage};
+use crate::merge_job::MergeJob;
+use crate::notify::{CoalescePolicy, FieldEvent, Notifier, SubscriptionId};
+use crate::refs::Ref;
+use crate::register::MVRegister;
 use crate::sync::{Timestamp, VectorClock};
+use crate::time::{self, SharedTime, TimeProvider};
 use crate::{ClientID, DocumentID, FieldPath};
 // TODO: Will be used when implementing full error handling
 // use crate::error::{Result, SyncError};
-use serde::{Deserialize, Serialize};
+use crate::value_store::{SharedValues, StoredValue, StoredValueRef, ValueHash};
+use serde::de::Error as _;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value as JsonValue;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// A document with field-level LWW conflict resolution
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Serialized, large values that several fields hold are written once (see
+/// [`crate::value_store`]).
+///
+/// Clones share the lists, multi-value fields, refs, locks and annotations
+/// until one side writes them, so forking a document copies only its
+/// fields.
+#[derive(Debug, Clone)]
 pub struct Document {
     /// Unique document identifier
     pub id: DocumentID,
@@ -28,18 +52,153 @@ pub struct Document {
 
     /// Vector clock for causality tracking
     pub version: VectorClock,
+
+    /// List fields (see [`Document::list_mut`])
+    lists: Arc<HashMap<FieldPath, List>>,
+
+    /// Multi-value fields (see [`Document::set_multi_field`])
+    registers: Arc<HashMap<FieldPath, MVRegister>>,
+
+    /// Links to other documents (see [`crate::refs`])
+    refs: Arc<HashMap<FieldPath, Ref>>,
+
+    /// Advisory locks on paths (see [`Document::acquire_lock`]); not
+    /// part of deltas or dirty chunks
+    locks: Arc<AdvisoryLocks>,
+
+    /// Tags on ranges of edits (see [`crate::annotations`])
+    annotations: Arc<Annotations>,
+
+    /// Values that lost LWW in merges (see [`crate::conflicts`]); saved,
+    /// not synced
+    conflicts: ConflictLog,
+
+    /// Changes since the last `take_dirty` (not persisted)
+    dirty: DirtyTracker,
+
+    /// Paths written in the running `annotated_transaction` (not
+    /// persisted)
+    annotating: Option<HashSet<FieldPath>>,
+
+    /// Change observers (not persisted, not cloned)
+    notifier: Notifier<FieldEvent>,
+
+    /// Clock for time-based behaviour; the thread default if unset (not
+    /// persisted)
+    time: Option<SharedTime>,
+}
+
+/// Paths written or deleted since the last `take_dirty`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct DirtyTracker {
+    paths: HashSet<FieldPath>,
+    annotations: Vec<Annotation>,
+    version: bool,
+}
+
+/// Fields changed since the last `Document::take_dirty`
+///
+/// Small enough to append to an op log or upsert row by row instead of
+/// rewriting the whole document. Replaying the chunks in order over the
+/// last full snapshot with `Document::apply_dirty` restores the document
+/// exactly.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DirtyState {
+    /// Current value of each field written since the last call
+    pub fields: HashMap<FieldPath, Field>,
+
+    /// Fields deleted since the last call
+    pub deleted: Vec<FieldPath>,
+
+    /// Current state of each list changed since the last call
+    #[serde(default)]
+    pub lists: HashMap<FieldPath, List>,
+
+    /// Current state of each multi-value field changed since the last
+    /// call
+    #[serde(default)]
+    pub registers: HashMap<FieldPath, MVRegister>,
+
+    /// Current state of each ref changed since the last call
+    #[serde(default)]
+    pub refs: HashMap<FieldPath, Ref>,
+
+    /// Annotations added since the last call
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+
+    /// The document version when the chunk was taken
+    pub version: VectorClock,
 }
 
 /// A single field with LWW metadata
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Field {
     /// Field value (JSON-like)
+    #[serde(with = "crate::codec::json_value")]
     pub value: JsonValue,
 
     /// Timestamp for LWW conflict resolution
     pub timestamp: Timestamp,
 }
 
+impl Field {
+    /// Whether this field replaces `local` under LWW (see
+    /// [`Document::merge_field`])
+    pub(crate) fn wins_over(&self, local: &Field) -> bool {
+        match self.timestamp.compare_lww(&local.timestamp) {
+            // Newer timestamp or higher client_id
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => {
+                // Exact same timestamp - use value comparison for determinism
+                // This handles the edge case where same client writes same timestamp
+                // with different values (which shouldn't happen in practice, but
+                // we handle it for total ordering)
+                let local_json = serde_json::to_string(&local.value).unwrap();
+                let remote_json = serde_json::to_string(&self.value).unwrap();
+                remote_json > local_json
+            }
+        }
+    }
+}
+
+/// The value at a path: a plain field, a list, a multi-value field or a
+/// ref
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldValue<'a> {
+    /// A last-writer-wins field
+    Value(&'a JsonValue),
+
+    /// A list field
+    List(&'a List),
+
+    /// A multi-value field, possibly holding concurrent writes
+    Multi(&'a MVRegister),
+
+    /// A link to another document (see [`crate::refs`])
+    Ref {
+        /// ID of the document linked to
+        workspace_scoped_id: &'a DocumentID,
+    },
+}
+
+impl FieldValue<'_> {
+    /// The value as JSON (a list renders as an array, concurrent values
+    /// as the marker described in [`crate::register`], a ref as the one
+    /// described in [`crate::refs`])
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            FieldValue::Value(value) => (*value).clone(),
+            FieldValue::List(list) => list.to_json(),
+            FieldValue::Multi(register) => register.to_json(),
+            FieldValue::Ref {
+                workspace_scoped_id,
+            } => serde_json::json!({ crate::refs::REF_MARKER: workspace_scoped_id }),
+        }
+    }
+}
+
 impl Document {
     /// Create a new empty document
     pub fn new(id: DocumentID) -> Self {
@@ -47,6 +206,16 @@ impl Document {
             id,
             fields: HashMap::new(),
             version: VectorClock::new(),
+            lists: Arc::default(),
+            registers: Arc::default(),
+            refs: Arc::default(),
+            locks: Arc::default(),
+            annotations: Arc::default(),
+            conflicts: ConflictLog::default(),
+            dirty: DirtyTracker::default(),
+            annotating: None,
+            notifier: Notifier::default(),
+            time: None,
         }
     }
 
@@ -64,8 +233,8 @@ impl Document {
         let timestamp = Timestamp::new(clock, client_id);
         let new_field = Field { value, timestamp };
 
-        // Use merge_field to respect LWW semantics
-        self.merge_field(field_path, new_field);
+        // Use LWW semantics, but a local write isn't a conflict
+        self.write_field(field_path, new_field);
     }
 
     /// Get a field value
@@ -73,6 +242,202 @@ impl Document {
         self.fields.get(field_path).map(|f| &f.value)
     }
 
+    /// Get the value at a path, whether a plain field, a list, a
+    /// multi-value field or a ref
+    ///
+    /// A path holds one of them; if replicas wrote several, a list wins
+    /// over a multi-value field, then a ref, then a plain field, here and
+    /// in `to_json`. A cleared ref is skipped.
+    pub fn get_value(&self, field_path: &FieldPath) -> Option<FieldValue<'_>> {
+        if let Some(list) = self.lists.get(field_path) {
+            return Some(FieldValue::List(list));
+        }
+        if let Some(register) = self.registers.get(field_path) {
+            return Some(FieldValue::Multi(register));
+        }
+        match self.get_ref(field_path) {
+            Some(workspace_scoped_id) => Some(FieldValue::Ref {
+                workspace_scoped_id,
+            }),
+            None => self.get_field(field_path).map(FieldValue::Value),
+        }
+    }
+
+    /// Write a multi-value field: concurrent writes to it are all kept
+    /// until one is picked with [`Document::resolve`] (see
+    /// [`crate::register`])
+    ///
+    /// The write replaces every value this replica holds. Returns false,
+    /// changing nothing, if the field has already seen a write from
+    /// `client_id` at `clock` or later.
+    pub fn set_multi_field(
+        &mut self,
+        field_path: FieldPath,
+        value: JsonValue,
+        clock: u64,
+        client_id: ClientID,
+    ) -> bool {
+        let timestamp = Timestamp::new(clock, client_id);
+        let written = Arc::make_mut(&mut self.registers)
+            .entry(field_path.clone())
+            .or_default()
+            .write(value, timestamp);
+        if written {
+            self.mark_dirty(&field_path);
+        }
+        written
+    }
+
+    /// Every value at a path with the clock and client that wrote it
+    ///
+    /// Several for a multi-value field with concurrent writes, one for a
+    /// plain field, none for a list or an unknown path.
+    pub fn get_all(&self, field_path: &FieldPath) -> Vec<(JsonValue, u64, ClientID)> {
+        let entry = |field: &Field| {
+            let timestamp = &field.timestamp;
+            (
+                field.value.clone(),
+                timestamp.clock,
+                timestamp.client_id.clone(),
+            )
+        };
+        if self.lists.contains_key(field_path) {
+            return Vec::new();
+        }
+        match self.registers.get(field_path) {
+            Some(register) => register.values().iter().map(entry).collect(),
+            None => self.fields.get(field_path).map(entry).into_iter().collect(),
+        }
+    }
+
+    /// Pick `value` as the winner of a multi-value field's concurrent
+    /// writes
+    ///
+    /// The write has seen every value this replica holds, so it replaces
+    /// them here and, once merged, on every replica that has no newer
+    /// concurrent write. `value` needn't be one of them. Returns false,
+    /// changing nothing, if the field has already seen a write from
+    /// `client_id` at `clock` or later.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `INVALID_INPUT` if the path isn't a multi-value field
+    pub fn resolve(
+        &mut self,
+        field_path: &FieldPath,
+        value: JsonValue,
+        clock: u64,
+        client_id: ClientID,
+    ) -> crate::Result<bool> {
+        if !self.registers.contains_key(field_path) {
+            return Err(crate::SyncKitError::invalid_input(
+                "only multi-value fields can be resolved",
+            )
+            .with_document(self.id.as_str())
+            .with_path(field_path.as_str()));
+        }
+        Ok(self.set_multi_field(field_path.clone(), value, clock, client_id))
+    }
+
+    /// Get a multi-value field
+    pub fn register(&self, field_path: &FieldPath) -> Option<&MVRegister> {
+        self.registers.get(field_path)
+    }
+
+    /// Get all multi-value fields
+    pub fn registers(&self) -> &HashMap<FieldPath, MVRegister> {
+        &self.registers
+    }
+
+    /// Point a ref field at `target`, another document of the workspace
+    /// (see [`crate::refs`])
+    ///
+    /// Last writer wins, as for [`Document::set_field`]; returns whether
+    /// the write did.
+    pub fn set_ref(
+        &mut self,
+        field_path: FieldPath,
+        target: DocumentID,
+        clock: u64,
+        client_id: ClientID,
+    ) -> bool {
+        let link = Ref {
+            target: Some(target),
+            timestamp: Timestamp::new(clock, client_id),
+        };
+        self.merge_ref(&field_path, &link)
+    }
+
+    /// Clear a ref field, so it links nowhere
+    ///
+    /// The clearing is an LWW write like any other, so an older
+    /// concurrent `set_ref` can't bring the link back.
+    pub fn clear_ref(&mut self, field_path: FieldPath, clock: u64, client_id: ClientID) -> bool {
+        let link = Ref {
+            target: None,
+            timestamp: Timestamp::new(clock, client_id),
+        };
+        self.merge_ref(&field_path, &link)
+    }
+
+    /// Get the ID of the document a ref field links to
+    pub fn get_ref(&self, field_path: &FieldPath) -> Option<&DocumentID> {
+        self.refs.get(field_path)?.target.as_ref()
+    }
+
+    /// Get all ref fields, cleared ones included
+    pub fn refs(&self) -> &HashMap<FieldPath, Ref> {
+        &self.refs
+    }
+
+    /// Get a list field
+    pub fn list(&self, field_path: &FieldPath) -> Option<&List> {
+        self.lists.get(field_path)
+    }
+
+    /// Edit a list field
+    ///
+    /// Items inserted and moved through the handle get ids from
+    /// `client_id`, so concurrent appends from different clients all
+    /// survive a merge.
+    pub fn list_mut(&mut self, field_path: FieldPath, client_id: ClientID) -> ListMut<'_> {
+        ListMut::new(self, field_path, client_id)
+    }
+
+    /// Get all list fields
+    pub fn lists(&self) -> &HashMap<FieldPath, List> {
+        &self.lists
+    }
+
+    pub(crate) fn list_entry(&mut self, field_path: &FieldPath) -> &mut List {
+        Arc::make_mut(&mut self.lists)
+            .entry(field_path.clone())
+            .or_default()
+    }
+
+    /// Run a local edit on a list, creating it unless the edit fails
+    pub(crate) fn edit_list<R>(
+        &mut self,
+        field_path: &FieldPath,
+        f: impl FnOnce(&mut List) -> crate::Result<R>,
+    ) -> crate::Result<R> {
+        let existed = self.lists.contains_key(field_path);
+        match f(self.list_entry(field_path)) {
+            Ok(result) => {
+                self.mark_dirty(field_path);
+                Ok(result)
+            }
+            Err(error) => {
+                if !existed {
+                    Arc::make_mut(&mut self.lists).remove(field_path);
+                }
+                Err(error
+                    .with_document(self.id.as_str())
+                    .with_path(field_path.as_str()))
+            }
+        }
+    }
+
     /// Merge a remote field using LWW algorithm
     ///
     /// This is the core LWW merge algorithm verified by TLA+.
@@ -82,66 +447,382 @@ impl Document {
     /// 1. Higher timestamp wins
     /// 2. If timestamps equal, higher client_id wins
     /// 3. If both equal (duplicate), use value comparison for determinism
+    ///
+    /// A different value than the local one is recorded in
+    /// [`Document::conflicts`], whichever wins.
     pub fn merge_field(&mut self, field_path: FieldPath, remote_field: Field) -> bool {
-        match self.fields.get(&field_path) {
-            Some(local_field) => {
-                // Compare timestamps for LWW
-                match remote_field.timestamp.compare_lww(&local_field.timestamp) {
-                    std::cmp::Ordering::Greater => {
-                        // Remote wins (newer timestamp or higher client_id)
-                        self.fields.insert(field_path, remote_field);
-                        true
-                    }
-                    std::cmp::Ordering::Less => {
-                        // Local wins (newer timestamp or higher client_id)
-                        false
-                    }
-                    std::cmp::Ordering::Equal => {
-                        // Exact same timestamp - use value comparison for determinism
-                        // This handles the edge case where same client writes same timestamp
-                        // with different values (which shouldn't happen in practice, but
-                        // we handle it for total ordering)
-                        let local_json = serde_json::to_string(&local_field.value).unwrap();
-                        let remote_json = serde_json::to_string(&remote_field.value).unwrap();
-
-                        if remote_json > local_json {
-                            self.fields.insert(field_path, remote_field);
-                            true
-                        } else {
-                            // Keep local (or keep existing if values are also equal)
-                            false
-                        }
-                    }
-                }
-            }
-            None => {
-                // No local value, remote wins
-                self.fields.insert(field_path, remote_field);
-                true
-            }
+        self.record_conflict(&field_path, &remote_field);
+        self.write_field(field_path, remote_field)
+    }
+
+    /// Write a field under LWW without recording a conflict; returns
+    /// whether it won
+    pub(crate) fn write_field(&mut self, field_path: FieldPath, remote_field: Field) -> bool {
+        let wins = self
+            .fields
+            .get(&field_path)
+            .is_none_or(|local_field| remote_field.wins_over(local_field));
+        if wins {
+            self.fields.insert(field_path.clone(), remote_field);
+            self.mark_dirty(&field_path);
         }
+        wins
     }
 
     /// Merge an entire remote document
     ///
     /// Merges all fields and vector clocks.
     /// Returns the number of fields updated.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "Document::merge",
+            skip_all,
+            fields(
+                document_id = %self.id,
+                remote_fields = remote.fields.len(),
+                updated = tracing::field::Empty,
+            )
+        )
+    )]
     pub fn merge(&mut self, remote: &Document) -> usize {
+        self.notifier.begin();
         let mut updated_count = 0;
 
         // Merge each remote field
         for (field_path, remote_field) in &remote.fields {
             if self.merge_field(field_path.clone(), remote_field.clone()) {
+                trace_debug!(field = %field_path, clock = remote_field.timestamp.clock, "remote field won");
+                updated_count += 1;
+            }
+        }
+
+        // Lists and multi-value fields merge as CRDTs, not by timestamp
+        for (field_path, remote_list) in remote.lists.iter() {
+            if self.merge_list(field_path, remote_list) {
+                updated_count += 1;
+            }
+        }
+        for (field_path, remote_register) in remote.registers.iter() {
+            if self.merge_register(field_path, remote_register) {
+                updated_count += 1;
+            }
+        }
+        for (field_path, remote_ref) in remote.refs.iter() {
+            if self.merge_ref(field_path, remote_ref) {
                 updated_count += 1;
             }
         }
 
-        // Merge vector clocks
-        self.version.merge(&remote.version);
+        self.merge_locks(&remote.locks);
+        self.merge_annotations(&remote.annotations);
+        self.merge_version(&remote.version);
+        self.notifier.end();
 
+        trace_record!("updated", updated_count);
         updated_count
     }
 
+    /// Record a conflict if `remote` meets a different value at `path`
+    pub(crate) fn record_conflict(&mut self, path: &FieldPath, remote: &Field) {
+        if let Some(local) = self.fields.get(path) {
+            let now_ms = self.time_provider().now_ms();
+            if self.conflicts.record(path, local, remote, now_ms) {
+                trace_debug!(field = %path, "recorded conflict");
+            }
+        }
+    }
+
+    /// Values that lost LWW in merges and incoming deltas, not yet
+    /// resolved (see [`crate::conflicts`])
+    pub fn conflicts(&self) -> &ConflictLog {
+        &self.conflicts
+    }
+
+    /// Keep at most `limit` conflicts; returns how many of the oldest
+    /// were dropped to fit
+    pub fn set_conflict_limit(&mut self, limit: usize) -> usize {
+        self.conflicts.set_limit(limit)
+    }
+
+    /// Settle a conflict by writing the value `keep` picks at `clock`,
+    /// and remove it from [`Document::conflicts`]
+    ///
+    /// The write syncs like any other. Returns false, changing nothing, if
+    /// it would lose to the field's current value; pick a higher `clock`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `INVALID_INPUT` if there is no unresolved conflict with
+    /// `id`
+    pub fn resolve_conflict(
+        &mut self,
+        id: &ConflictId,
+        keep: Keep,
+        clock: u64,
+        client_id: ClientID,
+    ) -> crate::Result<bool> {
+        let Some(conflict) = self.conflicts.get(id) else {
+            return Err(crate::SyncKitError::invalid_input(format!(
+                "no unresolved conflict {}",
+                id
+            ))
+            .with_document(self.id.as_str()));
+        };
+        let path = conflict.path.clone();
+        let value = match keep {
+            Keep::Local => conflict.local.value.clone(),
+            Keep::Remote => conflict.remote.value.clone(),
+            Keep::Custom(value) => value,
+        };
+        let field = Field {
+            value,
+            timestamp: Timestamp::new(clock, client_id),
+        };
+        if !self.write_field(path, field) {
+            return Ok(false);
+        }
+        self.conflicts.take(id);
+        Ok(true)
+    }
+
+    /// Merge a remote list field; returns whether the list changed
+    pub(crate) fn merge_list(&mut self, field_path: &FieldPath, remote: &List) -> bool {
+        let changed = self.list_entry(field_path).merge(remote);
+        if changed {
+            self.mark_dirty(field_path);
+        }
+        changed
+    }
+
+    /// Merge a remote multi-value field; returns whether it changed
+    pub(crate) fn merge_register(&mut self, field_path: &FieldPath, remote: &MVRegister) -> bool {
+        let changed = Arc::make_mut(&mut self.registers)
+            .entry(field_path.clone())
+            .or_default()
+            .merge(remote);
+        if changed {
+            self.mark_dirty(field_path);
+        }
+        changed
+    }
+
+    /// Merge a remote ref field under LWW; returns whether it changed
+    pub(crate) fn merge_ref(&mut self, field_path: &FieldPath, remote: &Ref) -> bool {
+        let wins = self
+            .refs
+            .get(field_path)
+            .is_none_or(|local| remote.wins_over(local));
+        if wins {
+            Arc::make_mut(&mut self.refs).insert(field_path.clone(), remote.clone());
+            self.mark_dirty(field_path);
+        }
+        wins
+    }
+
+    /// Merge a remote vector clock into ours
+    pub(crate) fn merge_version(&mut self, remote: &VectorClock) {
+        let before = self.version.clone();
+        self.version.merge(remote);
+        if self.version != before {
+            self.dirty.version = true;
+        }
+    }
+
+    /// Merge remote advisory locks, telling observers about each path
+    /// whose lock changed
+    pub(crate) fn merge_locks(&mut self, remote: &AdvisoryLocks) {
+        for path in Arc::make_mut(&mut self.locks).merge(remote) {
+            self.push_lock_event(path);
+        }
+    }
+
+    /// Merge remote annotations and their retention horizon
+    pub(crate) fn merge_annotations(&mut self, remote: &Annotations) {
+        let added = Arc::make_mut(&mut self.annotations).merge(remote);
+        self.dirty.annotations.extend(added);
+    }
+
+    /// Add annotations received in a delta
+    pub(crate) fn extend_annotations(&mut self, annotations: impl IntoIterator<Item = Annotation>) {
+        let added = Arc::make_mut(&mut self.annotations).extend(annotations);
+        self.dirty.annotations.extend(added);
+    }
+
+    /// Fields, lists, multi-value fields, refs, locks, annotations and
+    /// version, for consuming the document
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn into_parts(
+        self,
+    ) -> (
+        HashMap<FieldPath, Field>,
+        HashMap<FieldPath, List>,
+        HashMap<FieldPath, MVRegister>,
+        HashMap<FieldPath, Ref>,
+        AdvisoryLocks,
+        Annotations,
+        VectorClock,
+    ) {
+        (
+            self.fields,
+            Arc::unwrap_or_clone(self.lists),
+            Arc::unwrap_or_clone(self.registers),
+            Arc::unwrap_or_clone(self.refs),
+            Arc::unwrap_or_clone(self.locks),
+            Arc::unwrap_or_clone(self.annotations),
+            self.version,
+        )
+    }
+
+    /// Start merging `remote` in slices (see [`MergeJob`])
+    ///
+    /// Nothing changes until the job runs; the document stays readable
+    /// and editable between slices.
+    pub fn start_merge(&self, remote: Document) -> MergeJob {
+        MergeJob::new(remote)
+    }
+
+    /// Take an advisory lock on `path` for `holder`, lasting `ttl` (see
+    /// [`crate::locks`])
+    ///
+    /// Returns false if someone else holds the path. Holding a lock
+    /// doesn't stop anyone writing the path, and a concurrent acquisition
+    /// elsewhere may still win when the replicas merge; observers then
+    /// receive a [`FieldEvent::Lock`] naming the winner. Expiry is timed
+    /// by [`Document::time_provider`].
+    pub fn acquire_lock(&mut self, path: &FieldPath, holder: &str, ttl: Duration) -> bool {
+        let now_ms = self.time_provider().now_ms();
+        let acquired = Arc::make_mut(&mut self.locks).acquire(path, holder, ttl, now_ms);
+        if acquired {
+            self.push_lock_event(path.clone());
+        }
+        acquired
+    }
+
+    /// Release `holder`'s lock on `path`; returns false, changing
+    /// nothing, unless `holder` holds it
+    pub fn release_lock(&mut self, path: &FieldPath, holder: &str) -> bool {
+        let released = Arc::make_mut(&mut self.locks).release(path, holder);
+        if released {
+            self.push_lock_event(path.clone());
+        }
+        released
+    }
+
+    /// Who holds the advisory lock on `path` now, if anyone
+    pub fn lock_holder(&self, path: &FieldPath) -> Option<&str> {
+        self.locks.holder_of(path, self.time_provider().now_ms())
+    }
+
+    /// The document's advisory locks
+    pub fn locks(&self) -> &AdvisoryLocks {
+        &self.locks
+    }
+
+    /// Run `f` as one [`Document::transaction`] and tag the fields it
+    /// writes with `payload` (see [`crate::annotations`])
+    ///
+    /// Each client whose writes `f` makes gets one annotation, covering
+    /// its clocks from the lowest to the highest one written. Lists,
+    /// multi-value fields and deletions aren't tagged.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `ANNOTATION_TOO_LARGE`, without running `f`, if the
+    /// payload is over [`crate::annotations::MAX_ANNOTATION_BYTES`]
+    pub fn annotated_transaction<R>(
+        &mut self,
+        payload: JsonValue,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> crate::Result<R> {
+        Annotation::check_payload(&payload).map_err(|e| e.with_document(self.id.as_str()))?;
+        let outer = self.annotating.replace(HashSet::new());
+        let result = self.transaction(f);
+        let written = std::mem::replace(&mut self.annotating, outer).unwrap_or_default();
+        if let Some(outer) = &mut self.annotating {
+            outer.extend(written.iter().cloned());
+        }
+
+        let mut ranges: HashMap<&ClientID, (u64, u64)> = HashMap::new();
+        for path in &written {
+            if let Some(field) = self.fields.get(path) {
+                let Timestamp { clock, client_id } = &field.timestamp;
+                let range = ranges.entry(client_id).or_insert((*clock, *clock));
+                range.0 = range.0.min(*clock);
+                range.1 = range.1.max(*clock);
+            }
+        }
+        let created_ms = self.time_provider().now_ms();
+        let annotations: Vec<Annotation> = ranges
+            .into_iter()
+            .map(|(client_id, (start, end))| Annotation {
+                client_id: client_id.clone(),
+                start,
+                end,
+                payload: payload.clone(),
+                created_ms,
+            })
+            .collect();
+        self.extend_annotations(annotations);
+        Ok(result)
+    }
+
+    /// Tag `client_id`'s edits with clocks in `clocks` with `payload`
+    ///
+    /// For writes made with explicit clocks outside an
+    /// [`Document::annotated_transaction`].
+    ///
+    /// # Errors
+    ///
+    /// Fails with `ANNOTATION_TOO_LARGE` if the payload is over
+    /// [`crate::annotations::MAX_ANNOTATION_BYTES`]
+    pub fn annotate(
+        &mut self,
+        client_id: ClientID,
+        clocks: RangeInclusive<u64>,
+        payload: JsonValue,
+    ) -> crate::Result<()> {
+        Annotation::check_payload(&payload).map_err(|e| e.with_document(self.id.as_str()))?;
+        let annotation = Annotation {
+            client_id,
+            start: *clocks.start(),
+            end: *clocks.end(),
+            payload,
+            created_ms: self.time_provider().now_ms(),
+        };
+        self.extend_annotations([annotation]);
+        Ok(())
+    }
+
+    /// The annotation on the current value of a field, if any
+    pub fn annotations_for(&self, path: &FieldPath) -> Option<&Annotation> {
+        let timestamp = &self.fields.get(path)?.timestamp;
+        self.annotations
+            .covering(&timestamp.client_id, timestamp.clock)
+    }
+
+    /// Every annotation on the document
+    pub fn annotations(&self) -> &Annotations {
+        &self.annotations
+    }
+
+    /// Drop the annotations `retention` doesn't keep, timed by
+    /// [`Document::time_provider`]; returns how many were dropped
+    ///
+    /// Replicas learn the new horizon on the next full merge and drop the
+    /// same annotations.
+    pub fn prune_annotations(&mut self, retention: &AnnotationRetention) -> usize {
+        let now_ms = self.time_provider().now_ms();
+        Arc::make_mut(&mut self.annotations).prune(retention, now_ms)
+    }
+
+    fn push_lock_event(&mut self, path: FieldPath) {
+        if self.notifier.is_active() {
+            let holder = self.lock_holder(&path).map(str::to_string);
+            self.notifier.push(FieldEvent::Lock { path, holder });
+        }
+    }
+
     /// Convert document to JSON for serialization
     pub fn to_json(&self) -> JsonValue {
         let mut obj = serde_json::Map::new();
@@ -149,10 +830,107 @@ impl Document {
         for (field_path, field) in &self.fields {
             obj.insert(field_path.clone(), field.value.clone());
         }
+        for (field_path, link) in self.refs.iter() {
+            if link.target.is_some() {
+                obj.insert(field_path.clone(), link.to_json());
+            }
+        }
+        for (field_path, register) in self.registers.iter() {
+            obj.insert(field_path.clone(), register.to_json());
+        }
+        for (field_path, list) in self.lists.iter() {
+            obj.insert(field_path.clone(), list.to_json());
+        }
 
         JsonValue::Object(obj)
     }
 
+    /// Stable 64-bit hash of the fields, lists, multi-value fields and
+    /// refs, with their metadata
+    ///
+    /// Replicas holding the same state hash equally, whatever order they
+    /// received it in; the version and advisory locks aren't included.
+    /// Not cryptographic.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        fields.sort_by(|a, b| a.0.cmp(b.0));
+        for (path, field) in fields {
+            hasher.write(path.as_bytes());
+            hasher.write(field.value.to_string().as_bytes());
+            hasher.write(&field.timestamp.clock.to_le_bytes());
+            hasher.write(field.timestamp.client_id.as_bytes());
+        }
+        let mut lists: Vec<_> = self.lists.iter().collect();
+        lists.sort_by(|a, b| a.0.cmp(b.0));
+        for (path, list) in lists {
+            // Lists and fields share the path namespace
+            hasher.write(b"[]");
+            hasher.write(path.as_bytes());
+            for (id, value) in list.iter() {
+                hasher.write(&id.clock.to_le_bytes());
+                hasher.write(id.client_id.as_bytes());
+                hasher.write(value.to_string().as_bytes());
+            }
+        }
+        let mut registers: Vec<_> = self.registers.iter().collect();
+        registers.sort_by(|a, b| a.0.cmp(b.0));
+        for (path, register) in registers {
+            hasher.write(b"{}");
+            hasher.write(path.as_bytes());
+            for field in register.values() {
+                hasher.write(field.value.to_string().as_bytes());
+                hasher.write(&field.timestamp.clock.to_le_bytes());
+                hasher.write(field.timestamp.client_id.as_bytes());
+            }
+        }
+        let mut refs: Vec<_> = self.refs.iter().collect();
+        refs.sort_by(|a, b| a.0.cmp(b.0));
+        for (path, link) in refs {
+            hasher.write(b"->");
+            hasher.write(path.as_bytes());
+            if let Some(target) = &link.target {
+                hasher.write(target.as_bytes());
+            }
+            hasher.write(&link.timestamp.clock.to_le_bytes());
+            hasher.write(link.timestamp.client_id.as_bytes());
+        }
+        hasher.finish()
+    }
+
+    /// Stable 64-bit hash of the whole CRDT state: `content_hash`, the
+    /// version, and removed list items
+    ///
+    /// Replicas that merged the same writes hash equally; ones that only
+    /// read the same by luck, such as one that removed and re-added a list
+    /// item, don't. Advisory locks aren't included. Not cryptographic.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        hasher.write(&self.content_hash().to_le_bytes());
+        let mut clocks: Vec<_> = self
+            .version
+            .clocks()
+            .iter()
+            .filter(|(_, clock)| **clock > 0)
+            .collect();
+        clocks.sort();
+        for (client_id, clock) in clocks {
+            hasher.write(client_id.as_bytes());
+            hasher.write(&clock.to_le_bytes());
+        }
+        let mut lists: Vec<_> = self.lists.iter().collect();
+        lists.sort_by(|a, b| a.0.cmp(b.0));
+        for (path, list) in lists {
+            // Tombstones and slots too, in the list's stored form
+            hasher.write(path.as_bytes());
+            match serde_json::to_vec(list) {
+                Ok(bytes) => hasher.write(&bytes),
+                Err(_) => unreachable!("lists serialize to JSON"),
+            }
+        }
+        hasher.finish()
+    }
+
     /// Get all field paths
     pub fn field_paths(&self) -> Vec<&FieldPath> {
         self.fields.keys().collect()
@@ -161,9 +939,13 @@ impl Document {
     /// Check if document has any fields
     pub fn is_empty(&self) -> bool {
         self.fields.is_empty()
+            && self.lists.is_empty()
+            && self.registers.is_empty()
+            && self.refs.is_empty()
     }
 
-    /// Get number of fields
+    /// Get number of fields, not counting lists, multi-value fields and
+    /// refs
     pub fn field_count(&self) -> usize {
         self.fields.len()
     }
@@ -178,6 +960,50 @@ impl Document {
         &self.version
     }
 
+    /// Estimated heap use of each part of the document (see
+    /// [`crate::memory`])
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::new();
+        usage.record("fields", self.fields.heap_size());
+        usage.record("version", self.version.heap_size());
+        // What each part holds, not counting the small `Arc` around it
+        usage.record("lists", self.lists.as_ref().heap_size());
+        usage.record("registers", self.registers.as_ref().heap_size());
+        usage.record("refs", self.refs.as_ref().heap_size());
+        usage.record("locks", self.locks.as_ref().heap_size());
+        usage.record("annotations", self.annotations.as_ref().heap_size());
+        usage.record("conflicts", self.conflicts.heap_size());
+        usage.record(
+            "dirty",
+            self.dirty.heap_size() + self.annotating.heap_size(),
+        );
+        usage.record("notifier", self.notifier.queued_bytes());
+        usage
+    }
+
+    /// Value and metadata bytes against the field count (see
+    /// [`crate::memory`])
+    pub fn memory_stats(&self) -> DocumentMemoryStats {
+        let total = self.memory_usage().total();
+        let value_bytes: usize = self
+            .fields
+            .values()
+            .map(|field| size_of::<JsonValue>() + field.value.heap_size())
+            .sum();
+        let field_count = self.fields.len();
+        let bytes_per_field = match field_count {
+            0 => 0.0,
+            fields => total as f64 / fields as f64,
+        };
+
+        DocumentMemoryStats {
+            field_count,
+            value_bytes,
+            metadata_bytes: total.saturating_sub(value_bytes),
+            bytes_per_field,
+        }
+    }
+
     /// Get all fields with metadata
     pub fn fields(&self) -> &HashMap<FieldPath, Field> {
         &self.fields
@@ -185,7 +1011,372 @@ impl Document {
 
     /// Delete a field
     pub fn delete_field(&mut self, field_path: &FieldPath) {
-        self.fields.remove(field_path);
+        if self.fields.remove(field_path).is_some() {
+            self.mark_dirty(field_path);
+        }
+    }
+
+    /// Record a write that bypassed the field methods, after making it
+    ///
+    /// Also notifies subscribers of the field's new value.
+    pub(crate) fn mark_dirty(&mut self, field_path: &FieldPath) {
+        self.dirty.paths.insert(field_path.clone());
+        if let Some(written) = &mut self.annotating {
+            written.insert(field_path.clone());
+        }
+        if self.notifier.is_active() {
+            let path = field_path.clone();
+            self.notifier.push(match self.get_value(field_path) {
+                Some(value) => FieldEvent::Set {
+                    path,
+                    value: value.to_json(),
+                },
+                None => FieldEvent::Delete { path },
+            });
+        }
+    }
+
+    /// Record a version change that bypassed `merge`
+    pub(crate) fn mark_version_dirty(&mut self) {
+        self.dirty.version = true;
+    }
+
+    /// The changes `take_dirty` would return, as tracked; serialize it
+    /// next to the document to keep them across a reload
+    #[cfg(feature = "server")]
+    pub(crate) fn dirty_tracker(&self) -> &DirtyTracker {
+        &self.dirty
+    }
+
+    /// Restore changes saved with `dirty_tracker`
+    #[cfg(feature = "server")]
+    pub(crate) fn restore_dirty_tracker(&mut self, tracker: DirtyTracker) {
+        self.dirty = tracker;
+    }
+
+    /// Check if anything changed since the last `take_dirty`
+    ///
+    /// Local writes, merges and deletions all count; a freshly loaded
+    /// document is clean.
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.paths.is_empty() || !self.dirty.annotations.is_empty() || self.dirty.version
+    }
+
+    /// Take the changes made since the last call and mark the document
+    /// clean
+    pub fn take_dirty(&mut self) -> DirtyState {
+        let tracker = std::mem::take(&mut self.dirty);
+        let mut dirty = DirtyState {
+            annotations: tracker.annotations,
+            version: self.version.clone(),
+            ..DirtyState::default()
+        };
+        for path in tracker.paths {
+            if let Some(list) = self.lists.get(&path) {
+                dirty.lists.insert(path.clone(), list.clone());
+            }
+            if let Some(register) = self.registers.get(&path) {
+                dirty.registers.insert(path.clone(), register.clone());
+            }
+            if let Some(link) = self.refs.get(&path) {
+                dirty.refs.insert(path.clone(), link.clone());
+            }
+            match self.fields.get(&path) {
+                Some(field) => {
+                    dirty.fields.insert(path, field.clone());
+                }
+                None if !self.lists.contains_key(&path)
+                    && !self.registers.contains_key(&path)
+                    && !self.refs.contains_key(&path) =>
+                {
+                    dirty.deleted.push(path)
+                }
+                None => {}
+            }
+        }
+        dirty.deleted.sort();
+        dirty
+    }
+
+    /// Replay a chunk from `take_dirty` (restoring from storage)
+    ///
+    /// Fields are overwritten as recorded, not LWW-merged, and the
+    /// document stays clean: the chunk is already persisted.
+    pub fn apply_dirty(&mut self, dirty: &DirtyState) {
+        let notify = self.notifier.is_active();
+        self.notifier.begin();
+        for (path, field) in &dirty.fields {
+            self.fields.insert(path.clone(), field.clone());
+            if notify {
+                self.notifier.push(FieldEvent::Set {
+                    path: path.clone(),
+                    value: field.value.clone(),
+                });
+            }
+        }
+        for path in &dirty.deleted {
+            if self.fields.remove(path).is_some() && notify {
+                self.notifier
+                    .push(FieldEvent::Delete { path: path.clone() });
+            }
+        }
+        for (path, list) in &dirty.lists {
+            Arc::make_mut(&mut self.lists).insert(path.clone(), list.clone());
+            if notify {
+                self.notifier.push(FieldEvent::Set {
+                    path: path.clone(),
+                    value: list.to_json(),
+                });
+            }
+        }
+        for (path, register) in &dirty.registers {
+            Arc::make_mut(&mut self.registers).insert(path.clone(), register.clone());
+            if notify {
+                self.notifier.push(FieldEvent::Set {
+                    path: path.clone(),
+                    value: register.to_json(),
+                });
+            }
+        }
+        for (path, link) in &dirty.refs {
+            Arc::make_mut(&mut self.refs).insert(path.clone(), link.clone());
+            if notify {
+                self.notifier.push(FieldEvent::Set {
+                    path: path.clone(),
+                    value: link.to_json(),
+                });
+            }
+        }
+        Arc::make_mut(&mut self.annotations).extend(dirty.annotations.iter().cloned());
+        self.version = dirty.version.clone();
+        self.notifier.end();
+    }
+
+    /// Call `callback` with each batch of field changes
+    ///
+    /// Everything one call changes (a `merge`, an applied delta, a
+    /// `transaction`) arrives as one batch, with at most one change per
+    /// path: the last one. Applying a batch in order to a copy of the
+    /// fields reproduces the document. Observers are not cloned with the
+    /// document.
+    pub fn subscribe(
+        &mut self,
+        callback: impl FnMut(&[FieldEvent]) + Send + Sync + 'static,
+    ) -> SubscriptionId {
+        self.notifier.add(Box::new(callback))
+    }
+
+    /// Remove a callback; returns false if it was already removed
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.notifier.remove(id)
+    }
+
+    /// Run `f` as one operation: its changes are delivered as one batch
+    /// when it returns
+    pub fn transaction<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.notifier.begin();
+        let result = f(self);
+        self.notifier.end();
+        result
+    }
+
+    /// Merge consecutive batches according to `policy` before delivering
+    /// them (see [`CoalescePolicy`])
+    ///
+    /// `now_ms` measures `max_delay`; any [`TimeProvider`], including a
+    /// `Fn() -> u64` closure or a [`crate::time::MockTime`] in tests.
+    pub fn set_coalescing(
+        &mut self,
+        policy: CoalescePolicy,
+        now_ms: impl TimeProvider + Send + Sync + 'static,
+    ) {
+        self.notifier
+            .set_coalescing(policy, SharedTime::new(now_ms));
+    }
+
+    /// Like [`Document::set_coalescing`], timed by [`Document::time_provider`]
+    pub fn set_coalescing_policy(&mut self, policy: CoalescePolicy) {
+        let time = self.time_provider();
+        self.notifier.set_coalescing(policy, time);
+    }
+
+    /// Use `provider` wherever this document needs the current time
+    pub fn set_time_provider(&mut self, provider: impl TimeProvider + Send + Sync + 'static) {
+        self.time = Some(SharedTime::new(provider));
+    }
+
+    /// The document's clock: the one set with
+    /// [`Document::set_time_provider`], else the thread default
+    pub fn time_provider(&self) -> SharedTime {
+        self.time.clone().unwrap_or_else(time::default_provider)
+    }
+
+    /// Deliver held changes and deliver every batch right away from now on
+    pub fn stop_coalescing(&mut self) {
+        self.notifier.stop_coalescing();
+    }
+
+    /// Deliver changes held back by the coalescer now
+    pub fn flush_notifications(&mut self) {
+        self.notifier.flush();
+    }
+}
+
+/// How a `Document` is serialized: each shared value once in `values`,
+/// and the fields holding it with a null `value` and its hash in
+/// `value_ref`
+///
+/// Documents written before `values` existed still load: every field has
+/// its value inline.
+#[derive(Deserialize)]
+struct StoredDocument {
+    id: DocumentID,
+    #[serde(default)]
+    values: HashMap<ValueHash, StoredValue>,
+    fields: HashMap<FieldPath, StoredField>,
+    version: VectorClock,
+    #[serde(default)]
+    lists: HashMap<FieldPath, List>,
+    #[serde(default)]
+    registers: HashMap<FieldPath, MVRegister>,
+    #[serde(default)]
+    refs: HashMap<FieldPath, Ref>,
+    #[serde(default)]
+    locks: AdvisoryLocks,
+    #[serde(default)]
+    annotations: Annotations,
+    #[serde(default)]
+    conflicts: ConflictLog,
+}
+
+#[derive(Deserialize)]
+struct StoredField {
+    value: StoredValue,
+    #[serde(default)]
+    value_ref: Option<ValueHash>,
+    timestamp: Timestamp,
+}
+
+#[derive(Serialize)]
+struct StoredFieldRef<'a> {
+    value: StoredValueRef<'a>,
+    value_ref: Option<ValueHash>,
+    timestamp: &'a Timestamp,
+}
+
+impl Serialize for Document {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let shared = SharedValues::of(
+            self.fields
+                .iter()
+                .map(|(path, field)| (path.as_str(), &field.value)),
+        );
+        let fields: HashMap<&str, StoredFieldRef> = self
+            .fields
+            .iter()
+            .map(|(path, field)| {
+                let value_ref = shared.refs.get(path.as_str()).copied();
+                let value = match value_ref {
+                    Some(_) => StoredValueRef(&JsonValue::Null),
+                    None => StoredValueRef(&field.value),
+                };
+                let stored = StoredFieldRef {
+                    value,
+                    value_ref,
+                    timestamp: &field.timestamp,
+                };
+                (path.as_str(), stored)
+            })
+            .collect();
+
+        let mut state = serializer.serialize_struct("Document", 10)?;
+        state.serialize_field("id", &self.id)?;
+        state.serialize_field("values", &shared.table)?;
+        state.serialize_field("fields", &fields)?;
+        state.serialize_field("version", &self.version)?;
+        state.serialize_field("lists", &*self.lists)?;
+        state.serialize_field("registers", &*self.registers)?;
+        state.serialize_field("refs", &*self.refs)?;
+        state.serialize_field("locks", &*self.locks)?;
+        state.serialize_field("annotations", &*self.annotations)?;
+        state.serialize_field("conflicts", &self.conflicts)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Document {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredDocument::deserialize(deserializer)?;
+        let mut fields = HashMap::with_capacity(stored.fields.len());
+        for (path, field) in stored.fields {
+            let value = match field.value_ref {
+                Some(hash) => match stored.values.get(&hash) {
+                    Some(value) => value.0.clone(),
+                    None => {
+                        return Err(D::Error::custom(format!(
+                            "field {:?} refers to unknown value {}",
+                            path, hash
+                        )))
+                    }
+                },
+                None => field.value.0,
+            };
+            let timestamp = field.timestamp;
+            fields.insert(path, Field { value, timestamp });
+        }
+        Ok(Self {
+            id: stored.id,
+            fields,
+            version: stored.version,
+            lists: Arc::new(stored.lists),
+            registers: Arc::new(stored.registers),
+            refs: Arc::new(stored.refs),
+            locks: Arc::new(stored.locks),
+            annotations: Arc::new(stored.annotations),
+            conflicts: stored.conflicts,
+            dirty: DirtyTracker::default(),
+            annotating: None,
+            notifier: Notifier::default(),
+            time: None,
+        })
+    }
+}
+
+/// 64-bit FNV-1a, with a separator after every item
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        self.write_part(bytes);
+        self.write_part(&[0xff]);
+    }
+
+    /// Write part of an item without the separator; `write(&[])` ends it
+    pub(crate) fn write_part(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl HeapSize for Field {
+    fn heap_size(&self) -> usize {
+        self.value.heap_size() + self.timestamp.heap_size()
+    }
+}
+
+impl HeapSize for DirtyTracker {
+    fn heap_size(&self) -> usize {
+        self.paths.heap_size() + self.annotations.heap_size()
     }
 }
 
@@ -389,7 +1580,7 @@ mod tests {
                 );
                 map
             },
-            version: VectorClock::new(),
+            ..Document::new("doc-123".to_string())
         };
 
         // Client2 writes
@@ -406,7 +1597,7 @@ mod tests {
                 );
                 map
             },
-            version: VectorClock::new(),
+            ..Document::new("doc-123".to_string())
         };
 
         // Replica1 merges in order: client1, then client2
@@ -427,4 +1618,336 @@ mod tests {
         assert_eq!(replica1.get_field(&"field1".to_string()), Some(&json!("B")));
         assert_eq!(replica2.get_field(&"field1".to_string()), Some(&json!("B")));
     }
+
+    #[test]
+    fn test_take_dirty_returns_only_changes() {
+        let mut doc = Document::new("doc-123".to_string());
+        assert!(!doc.is_dirty());
+
+        doc.set_field("a".to_string(), json!(1), 1, "client1".to_string());
+        doc.set_field("b".to_string(), json!(2), 1, "client1".to_string());
+        assert!(doc.is_dirty());
+        assert_eq!(doc.take_dirty().fields.len(), 2);
+        assert!(!doc.is_dirty());
+
+        // A losing write changes nothing
+        doc.set_field("a".to_string(), json!(0), 0, "client1".to_string());
+        assert!(!doc.is_dirty());
+
+        doc.set_field("a".to_string(), json!(3), 2, "client1".to_string());
+        doc.delete_field(&"b".to_string());
+        let dirty = doc.take_dirty();
+        assert_eq!(dirty.fields.keys().collect::<Vec<_>>(), vec!["a"]);
+        assert_eq!(dirty.deleted, vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn test_memory_usage_by_subsystem() {
+        let mut doc = Document::new("doc-123".to_string());
+        let empty = doc.memory_usage();
+
+        doc.set_field(
+            "body".to_string(),
+            json!("x".repeat(1000)),
+            1,
+            "client1".to_string(),
+        );
+        let written = doc.memory_usage();
+        assert!(written.get("fields") >= empty.get("fields") + 1000);
+        assert!(written.get("dirty") > empty.get("dirty"));
+        assert_eq!(written.get("lists"), 0);
+
+        doc.take_dirty();
+        assert_eq!(doc.memory_usage().get("dirty"), 0);
+    }
+
+    #[test]
+    fn test_memory_stats_split_values_from_metadata() {
+        let mut doc = Document::new("doc-123".to_string());
+        assert_eq!(doc.memory_stats().bytes_per_field, 0.0);
+
+        for i in 0..100 {
+            doc.set_field(
+                format!("field{}", i),
+                json!("x".repeat(100)),
+                1,
+                "client1".to_string(),
+            );
+        }
+        let stats = doc.memory_stats();
+        assert_eq!(stats.field_count, 100);
+        assert!(stats.value_bytes >= 100 * 100);
+        assert!(stats.metadata_bytes > 0);
+        assert_eq!(
+            stats.value_bytes + stats.metadata_bytes,
+            doc.memory_usage().total()
+        );
+        assert!(stats.bytes_per_field > 100.0);
+    }
+
+    #[test]
+    fn test_merge_marks_incoming_changes_dirty() {
+        let mut relay = Document::new("doc-123".to_string());
+        let mut remote = Document::new("doc-123".to_string());
+        remote.set_field("title".to_string(), json!("Hi"), 1, "client2".to_string());
+        remote.version.tick(&"client2".to_string());
+
+        relay.merge(&remote);
+        let dirty = relay.take_dirty();
+        assert_eq!(dirty.fields["title"].value, json!("Hi"));
+        assert_eq!(dirty.version, remote.version);
+
+        // Merging the same state again is a no-op
+        relay.merge(&remote);
+        assert!(!relay.is_dirty());
+    }
+
+    /// A ~10 KB object
+    fn style() -> JsonValue {
+        let rules: serde_json::Map<String, JsonValue> = (0..330)
+            .map(|i| (format!("rule-{:03}", i), json!("#336699 solid 1px")))
+            .collect();
+        JsonValue::Object(rules)
+    }
+
+    #[test]
+    fn test_serializes_repeated_value_once() {
+        let style = style();
+        assert!(serde_json::to_vec(&style).unwrap().len() >= 10_000);
+        let mut doc = Document::new("doc".to_string());
+        for i in 0..500 {
+            doc.set_field(format!("cell-{}", i), style.clone(), 1, "c".to_string());
+        }
+        doc.set_field("title".to_string(), json!("Sheet"), 2, "c".to_string());
+
+        let bytes = serde_json::to_vec(&doc).unwrap();
+        // ~10 KB for the value plus ~100 bytes per field, not 5 MB
+        assert!(bytes.len() < 70_000, "{} bytes", bytes.len());
+
+        let restored: Document = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(restored.fields(), doc.fields());
+        assert_eq!(restored.version(), doc.version());
+    }
+
+    #[test]
+    fn test_loads_documents_without_value_table() {
+        let restored: Document = serde_json::from_str(
+            r#"{"id":"doc","fields":{"a":{"value":1,"timestamp":{"clock":1,"client_id":"c"}}},"version":{"clocks":{}}}"#,
+        )
+        .unwrap();
+        assert_eq!(restored.get_field(&"a".to_string()), Some(&json!(1)));
+
+        let dangling = r#"{"id":"doc","values":{},"fields":{"a":{"value":null,"value_ref":"00000000000000000000000000000001","timestamp":{"clock":1,"client_id":"c"}}},"version":{"clocks":{}}}"#;
+        assert!(serde_json::from_str::<Document>(dangling).is_err());
+    }
+
+    #[test]
+    fn test_lock_loser_is_notified() {
+        use crate::time::MockTime;
+        use std::sync::{Arc, Mutex};
+
+        let time = MockTime::new(1_000);
+        let title = "title".to_string();
+        let mut alice = Document::new("doc".to_string());
+        alice.set_time_provider(time.clone());
+        let mut bob = alice.clone();
+        bob.set_time_provider(time.clone());
+        assert!(alice.acquire_lock(&title, "alice", Duration::from_secs(30)));
+        assert!(bob.acquire_lock(&title, "bob", Duration::from_secs(30)));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        alice.subscribe(move |batch: &[FieldEvent]| sink.lock().unwrap().extend_from_slice(batch));
+        alice.merge(&bob);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![FieldEvent::Lock {
+                path: title.clone(),
+                holder: Some("bob".to_string()),
+            }]
+        );
+        assert_eq!(alice.lock_holder(&title), Some("bob"));
+        assert!(!alice.release_lock(&title, "alice"));
+
+        // Locks survive serialization, and expire with the clock
+        let restored: Document =
+            serde_json::from_slice(&serde_json::to_vec(&alice).unwrap()).unwrap();
+        assert_eq!(restored.locks(), alice.locks());
+        time.advance(30_000);
+        assert_eq!(alice.lock_holder(&title), None);
+    }
+
+    #[test]
+    fn test_content_hash_ignores_merge_order() {
+        let mut alice = Document::new("doc".to_string());
+        alice.set_field("title".to_string(), json!("A"), 1, "alice".to_string());
+        alice
+            .list_mut("items".to_string(), "alice".to_string())
+            .push(json!(1));
+        let mut bob = Document::new("doc".to_string());
+        bob.set_field("title".to_string(), json!("B"), 2, "bob".to_string());
+        bob.set_field("body".to_string(), json!("text"), 1, "bob".to_string());
+
+        let mut alice_first = alice.clone();
+        alice_first.merge(&bob);
+        let mut bob_first = bob.clone();
+        bob_first.merge(&alice);
+        assert_eq!(alice_first.content_hash(), bob_first.content_hash());
+        assert_ne!(alice_first.content_hash(), alice.content_hash());
+
+        // Same value, different write: not the same state
+        let mut rewritten = alice_first.clone();
+        rewritten.set_field("body".to_string(), json!("text"), 1, "carol".to_string());
+        assert_ne!(rewritten.content_hash(), alice_first.content_hash());
+    }
+
+    #[test]
+    fn test_state_hash_sees_removed_items() {
+        let mut alice = Document::new("doc".to_string());
+        alice
+            .list_mut("items".to_string(), "alice".to_string())
+            .push(json!(1));
+        let mut bob = Document::new("doc".to_string());
+        bob.set_field("title".to_string(), json!("B"), 1, "bob".to_string());
+
+        let mut alice_first = alice.clone();
+        alice_first.merge(&bob);
+        let mut bob_first = bob.clone();
+        bob_first.merge(&alice);
+        assert_eq!(alice_first.state_hash(), bob_first.state_hash());
+
+        // Removed and pushed again: same content, one more tombstone
+        let mut repushed = alice_first.clone();
+        let mut items = repushed.list_mut("items".to_string(), "alice".to_string());
+        items.remove(0).unwrap();
+        items.push(json!(1));
+        assert_eq!(repushed.to_json(), alice_first.to_json());
+        assert_ne!(repushed.state_hash(), alice_first.state_hash());
+    }
+
+    #[test]
+    fn test_forks_edit_independently() {
+        fn base() -> Document {
+            let mut doc = Document::new("doc".to_string());
+            doc.list_mut("items".to_string(), "alice".to_string())
+                .push(json!(1));
+            doc.set_multi_field("tags".to_string(), json!("a"), 1, "alice".to_string());
+            doc
+        }
+        fn edit_fork(doc: &mut Document) {
+            doc.list_mut("items".to_string(), "bob".to_string())
+                .push(json!(2));
+            doc.set_multi_field("tags".to_string(), json!("b"), 2, "bob".to_string());
+        }
+        fn edit_original(doc: &mut Document) {
+            doc.list_mut("items".to_string(), "alice".to_string())
+                .remove(0)
+                .unwrap();
+            doc.set_field("title".to_string(), json!("A"), 3, "alice".to_string());
+        }
+
+        let mut original = base();
+        let mut fork = original.clone();
+        assert!(Arc::ptr_eq(&fork.lists, &original.lists));
+        edit_fork(&mut fork);
+        assert!(Arc::ptr_eq(&fork.refs, &original.refs));
+        edit_original(&mut original);
+
+        let mut fork_alone = base();
+        edit_fork(&mut fork_alone);
+        let mut original_alone = base();
+        edit_original(&mut original_alone);
+        assert_eq!(fork.state_hash(), fork_alone.state_hash());
+        assert_eq!(original.state_hash(), original_alone.state_hash());
+        assert_ne!(fork.state_hash(), original.state_hash());
+    }
+
+    #[test]
+    fn test_multi_value_field_keeps_three_way_conflict() {
+        let path = "address".to_string();
+        let mut replicas: Vec<Document> = ["alice", "bob", "carol"]
+            .iter()
+            .enumerate()
+            .map(|(index, client)| {
+                let mut doc = Document::new("doc".to_string());
+                let value = json!(format!("{} St", index));
+                doc.set_multi_field(path.clone(), value, 1, client.to_string());
+                doc
+            })
+            .collect();
+
+        // Everyone syncs with everyone, in different orders
+        let snapshot = replicas.clone();
+        for (index, doc) in replicas.iter_mut().enumerate() {
+            for offset in 1..3 {
+                doc.merge(&snapshot[(index + offset) % 3]);
+            }
+        }
+        for doc in &replicas {
+            assert_eq!(doc.content_hash(), replicas[0].content_hash());
+            let all = doc.get_all(&path);
+            assert_eq!(all.len(), 3);
+            assert_eq!(all[1], (json!("1 St"), 1, "bob".to_string()));
+            assert_eq!(
+                doc.to_json()["address"],
+                json!({"$conflict": ["0 St", "1 St", "2 St"]})
+            );
+        }
+
+        // Carol resolves; Bob writes concurrently without having seen it
+        let carol = &mut replicas[2];
+        assert!(carol
+            .resolve(&path, json!("1 St"), 2, "carol".to_string())
+            .unwrap());
+        let resolved = replicas[2].clone();
+        replicas[1].set_multi_field(path.clone(), json!("9 St"), 2, "bob".to_string());
+        let bob = replicas[1].clone();
+
+        // Alice sees the resolution first, then Bob's later write
+        replicas[0].merge(&resolved);
+        assert_eq!(replicas[0].get_field(&path), None);
+        assert_eq!(replicas[0].to_json()["address"], json!("1 St"));
+        replicas[0].merge(&bob);
+        replicas[2].merge(&bob);
+        replicas[1].merge(&resolved);
+        for doc in &replicas {
+            assert_eq!(
+                doc.to_json()["address"],
+                json!({"$conflict": ["9 St", "1 St"]})
+            );
+        }
+
+        // A resolution that has seen everything collapses it everywhere
+        replicas[0]
+            .resolve(&path, json!("9 St"), 3, "alice".to_string())
+            .unwrap();
+        let final_state = replicas[0].clone();
+        for doc in &mut replicas[1..] {
+            doc.merge(&final_state);
+            assert_eq!(doc.get_all(&path).len(), 1);
+            assert_eq!(doc.to_json()["address"], json!("9 St"));
+        }
+
+        // Survives a snapshot and a dirty chunk
+        let restored: Document =
+            serde_json::from_slice(&serde_json::to_vec(&final_state).unwrap()).unwrap();
+        assert_eq!(restored.register(&path), final_state.register(&path));
+        let mut chunked = Document::new("doc".to_string());
+        chunked.apply_dirty(&replicas[0].take_dirty());
+        assert_eq!(chunked.register(&path), final_state.register(&path));
+    }
+
+    #[test]
+    fn test_resolve_needs_a_multi_value_field() {
+        let mut doc = Document::new("doc".to_string());
+        doc.set_field("title".to_string(), json!("A"), 1, "alice".to_string());
+        let error = doc
+            .resolve(&"title".to_string(), json!("B"), 2, "alice".to_string())
+            .unwrap_err();
+        assert_eq!(error.code_name(), "INVALID_INPUT");
+        assert_eq!(
+            doc.get_all(&"title".to_string()),
+            vec![(json!("A"), 1, "alice".to_string())]
+        );
+    }
 }
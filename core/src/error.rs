@@ -1,9 +1,27 @@
 //! Error types for SyncKit
+//!
+//! [`SyncKitError`] is the crate-wide error returned by public fallible
+//! APIs. It wraps the specific error enums ([`SyncError`], and `TextError`
+//! with the `text-crdt` feature) so callers can still match on them, and
+//! adds:
+//!
+//! - a [`category`](SyncKitError::category) for coarse handling,
+//! - a stable numeric [`code`](SyncKitError::code) shared with the WASM and
+//!   FFI layers (never renumber or reuse a code),
+//! - optional context: document id, field path and text position.
+//!
+//! Low-level CRDT methods (e.g. `FugueText::insert`) keep returning their
+//! specific enum; `?` converts it into a `SyncKitError`.
 
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use thiserror::Error;
 
+#[cfg(feature = "text-crdt")]
+use crate::crdt::TextError;
+
 /// Result type alias for SyncKit operations
-pub type Result<T> = std::result::Result<T, SyncError>;
+pub type Result<T> = std::result::Result<T, SyncKitError>;
 
 /// Main error type for SyncKit operations
 #[derive(Error, Debug, Clone)]
@@ -37,6 +55,18 @@ pub enum SyncError {
 
     #[error("Protocol error: {0}")]
     Protocol(String),
+
+    #[error("Nested deeper than the limit of {0} levels")]
+    DepthLimitExceeded(usize),
+
+    #[error("Annotation payload is {size} bytes, over the limit of {limit}")]
+    AnnotationTooLarge { size: usize, limit: usize },
+
+    #[error("Document holds schema version {found:?}, expected {expected}")]
+    SchemaMismatch { expected: u32, found: Option<u32> },
+
+    #[error("Field is a read-only shadow of a {0} the peer can't sync")]
+    ShadowedField(String),
 }
 
 impl SyncError {
@@ -61,6 +91,569 @@ impl SyncError {
             SyncError::ConflictError(_) => "CONFLICT_ERROR",
             SyncError::InvalidOperation(_) => "INVALID_OPERATION",
             SyncError::Protocol(_) => "PROTOCOL_ERROR",
+            SyncError::DepthLimitExceeded(_) => "DEPTH_LIMIT_EXCEEDED",
+            SyncError::AnnotationTooLarge { .. } => "ANNOTATION_TOO_LARGE",
+            SyncError::SchemaMismatch { .. } => "SCHEMA_MISMATCH",
+            SyncError::ShadowedField(_) => "SHADOWED_FIELD",
+        }
+    }
+}
+
+/// Coarse classification of a [`SyncKitError`]
+///
+/// The numeric code of an error is `category base + n`, e.g. every text
+/// error has a code in `1000..2000`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorCategory {
+    /// Text CRDT operations (positions, blocks, text deltas)
+    Text,
+
+    /// Document lookups (missing documents or fields)
+    Document,
+
+    /// Encoding and decoding (JSON, protobuf, deltas)
+    Protocol,
+
+    /// Storage backends
+    Storage,
+
+    /// Network and replication
+    Sync,
+
+    /// Invalid arguments or operations
+    Validation,
+}
+
+impl ErrorCategory {
+    /// First code of this category's range
+    pub fn base_code(self) -> u32 {
+        match self {
+            ErrorCategory::Text => 1000,
+            ErrorCategory::Document => 2000,
+            ErrorCategory::Protocol => 3000,
+            ErrorCategory::Storage => 4000,
+            ErrorCategory::Sync => 5000,
+            ErrorCategory::Validation => 6000,
+        }
+    }
+
+    /// Lowercase name, as serialized (e.g. `"text"`)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCategory::Text => "text",
+            ErrorCategory::Document => "document",
+            ErrorCategory::Protocol => "protocol",
+            ErrorCategory::Storage => "storage",
+            ErrorCategory::Sync => "sync",
+            ErrorCategory::Validation => "validation",
+        }
+    }
+}
+
+/// The specific error wrapped by a [`SyncKitError`]
+#[derive(Error, Debug, Clone)]
+pub enum ErrorKind {
+    /// Error from the document, protocol or sync layers
+    #[error(transparent)]
+    Sync(#[from] SyncError),
+
+    /// Error from a text CRDT operation
+    #[cfg(feature = "text-crdt")]
+    #[error(transparent)]
+    Text(#[from] TextError),
+
+    /// A caller-supplied argument was malformed (e.g. invalid JSON)
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    /// A `try_` call on a shared handle found the lock held elsewhere
+    #[error("Lock is held by another caller")]
+    WouldBlock,
+}
+
+/// Where an error happened, attached as it propagates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// Document being operated on
+    pub document_id: Option<String>,
+
+    /// Field path within the document
+    pub path: Option<String>,
+
+    /// Position within a text, in characters
+    pub position: Option<usize>,
+}
+
+impl ErrorContext {
+    /// Whether no context has been attached
+    pub fn is_empty(&self) -> bool {
+        self.document_id.is_none() && self.path.is_none() && self.position.is_none()
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(id) = &self.document_id {
+            parts.push(format!("document: {}", id));
+        }
+        if let Some(path) = &self.path {
+            parts.push(format!("path: {}", path));
         }
+        if let Some(position) = self.position {
+            parts.push(format!("position: {}", position));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Crate-wide error: a specific error plus its category, code and context
+#[derive(Debug, Clone)]
+pub struct SyncKitError {
+    kind: ErrorKind,
+    // Boxed to keep `Result<T, SyncKitError>` small on the happy path
+    context: Box<ErrorContext>,
+}
+
+impl SyncKitError {
+    /// Wrap a specific error with no context
+    pub fn new(kind: impl Into<ErrorKind>) -> Self {
+        Self {
+            kind: kind.into(),
+            context: Box::default(),
+        }
+    }
+
+    /// A caller-supplied argument was malformed
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::InvalidInput(message.into()))
+    }
+
+    /// A `try_` call could not take a lock without blocking
+    pub fn would_block() -> Self {
+        Self::new(ErrorKind::WouldBlock)
+    }
+
+    /// Serializing a value failed
+    pub fn serialization(message: impl fmt::Display) -> Self {
+        Self::new(SyncError::SerializationError(message.to_string()))
+    }
+
+    /// Decoding bytes or a string failed
+    pub fn deserialization(message: impl fmt::Display) -> Self {
+        Self::new(SyncError::DeserializationError(message.to_string()))
+    }
+
+    /// The specific error
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Context attached so far
+    pub fn context(&self) -> &ErrorContext {
+        &self.context
+    }
+
+    /// Attach the id of the document being operated on
+    pub fn with_document(mut self, document_id: impl Into<String>) -> Self {
+        self.context.document_id = Some(document_id.into());
+        self
+    }
+
+    /// Attach the field path being operated on
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.context.path = Some(path.into());
+        self
+    }
+
+    /// Attach a text position (in characters)
+    pub fn with_position(mut self, position: usize) -> Self {
+        self.context.position = Some(position);
+        self
+    }
+
+    /// Coarse classification of this error
+    pub fn category(&self) -> ErrorCategory {
+        match &self.kind {
+            ErrorKind::Sync(e) => match e {
+                SyncError::DocumentNotFound(_) | SyncError::FieldNotFound(_) => {
+                    ErrorCategory::Document
+                }
+                SyncError::SerializationError(_)
+                | SyncError::DeserializationError(_)
+                | SyncError::Protocol(_) => ErrorCategory::Protocol,
+                SyncError::StorageError(_) => ErrorCategory::Storage,
+                SyncError::NetworkError(_) | SyncError::ConflictError(_) => ErrorCategory::Sync,
+                SyncError::InvalidTimestamp(_)
+                | SyncError::InvalidOperation(_)
+                | SyncError::DepthLimitExceeded(_)
+                | SyncError::AnnotationTooLarge { .. }
+                | SyncError::SchemaMismatch { .. }
+                | SyncError::ShadowedField(_) => ErrorCategory::Validation,
+            },
+            #[cfg(feature = "text-crdt")]
+            ErrorKind::Text(_) => ErrorCategory::Text,
+            ErrorKind::InvalidInput(_) => ErrorCategory::Validation,
+            ErrorKind::WouldBlock => ErrorCategory::Sync,
+        }
+    }
+
+    /// Stable numeric code, shared with the WASM and FFI layers
+    ///
+    /// Codes are part of the public API: new errors get new codes and
+    /// existing codes are never changed or reused.
+    pub fn code(&self) -> u32 {
+        match &self.kind {
+            ErrorKind::Sync(e) => match e {
+                SyncError::DocumentNotFound(_) => 2001,
+                SyncError::FieldNotFound(_) => 2002,
+                SyncError::SerializationError(_) => 3001,
+                SyncError::DeserializationError(_) => 3002,
+                SyncError::Protocol(_) => 3003,
+                SyncError::StorageError(_) => 4001,
+                SyncError::NetworkError(_) => 5001,
+                SyncError::ConflictError(_) => 5002,
+                SyncError::InvalidTimestamp(_) => 6001,
+                SyncError::InvalidOperation(_) => 6002,
+                SyncError::DepthLimitExceeded(_) => 6004,
+                SyncError::AnnotationTooLarge { .. } => 6005,
+                SyncError::SchemaMismatch { .. } => 6006,
+                SyncError::ShadowedField(_) => 6007,
+            },
+            #[cfg(feature = "text-crdt")]
+            ErrorKind::Text(e) => match e {
+                TextError::PositionOutOfBounds { .. } => 1001,
+                TextError::RangeOutOfBounds { .. } => 1002,
+                TextError::BlockNotFound(_) => 1003,
+                TextError::BlockSplitRequired => 1004,
+                TextError::InvalidBlockSplit { .. } => 1005,
+                TextError::RopeError(_) => 1006,
+                TextError::InvalidDelta(_) => 1007,
+                TextError::InvalidUtf16Position { .. } => 1010,
+                #[cfg(feature = "yjs-interop")]
+                TextError::InvalidYjsUpdate(_) => 1008,
+                TextError::ReplicaConflict { .. } => 1009,
+                TextError::LimitExceeded { .. } => 1011,
+            },
+            ErrorKind::InvalidInput(_) => 6003,
+            ErrorKind::WouldBlock => 5003,
+        }
+    }
+
+    /// Symbolic name of the code, e.g. `"POSITION_OUT_OF_BOUNDS"`
+    pub fn code_name(&self) -> &'static str {
+        match &self.kind {
+            ErrorKind::Sync(e) => e.code(),
+            #[cfg(feature = "text-crdt")]
+            ErrorKind::Text(e) => match e {
+                TextError::PositionOutOfBounds { .. } => "POSITION_OUT_OF_BOUNDS",
+                TextError::RangeOutOfBounds { .. } => "RANGE_OUT_OF_BOUNDS",
+                TextError::BlockNotFound(_) => "BLOCK_NOT_FOUND",
+                TextError::BlockSplitRequired => "BLOCK_SPLIT_REQUIRED",
+                TextError::InvalidBlockSplit { .. } => "INVALID_BLOCK_SPLIT",
+                TextError::RopeError(_) => "ROPE_ERROR",
+                TextError::InvalidDelta(_) => "INVALID_TEXT_DELTA",
+                TextError::InvalidUtf16Position { .. } => "INVALID_UTF16_POSITION",
+                #[cfg(feature = "yjs-interop")]
+                TextError::InvalidYjsUpdate(_) => "INVALID_YJS_UPDATE",
+                TextError::ReplicaConflict { .. } => "REPLICA_CONFLICT",
+                TextError::LimitExceeded { .. } => "LIMIT_EXCEEDED",
+            },
+            ErrorKind::InvalidInput(_) => "INVALID_INPUT",
+            ErrorKind::WouldBlock => "WOULD_BLOCK",
+        }
+    }
+
+    /// Check if retrying the operation may succeed
+    pub fn is_retryable(&self) -> bool {
+        match &self.kind {
+            ErrorKind::Sync(e) => e.is_retryable(),
+            ErrorKind::WouldBlock => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for SyncKitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if !self.context.is_empty() {
+            write!(f, " ({})", self.context)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SyncKitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Sync(e) => Some(e),
+            #[cfg(feature = "text-crdt")]
+            ErrorKind::Text(e) => Some(e),
+            ErrorKind::InvalidInput(_) | ErrorKind::WouldBlock => None,
+        }
+    }
+}
+
+impl From<ErrorKind> for SyncKitError {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<SyncError> for SyncKitError {
+    fn from(error: SyncError) -> Self {
+        Self::new(error)
+    }
+}
+
+#[cfg(feature = "text-crdt")]
+impl From<TextError> for SyncKitError {
+    /// Out-of-bounds errors carry their position into the context
+    fn from(error: TextError) -> Self {
+        let position = match &error {
+            TextError::PositionOutOfBounds { position, .. } => Some(*position),
+            TextError::RangeOutOfBounds { start, .. } => Some(*start),
+            TextError::InvalidUtf16Position { position, .. } => Some(*position),
+            _ => None,
+        };
+        let mut error = Self::new(error);
+        error.context.position = position;
+        error
+    }
+}
+
+/// Attach context to any result whose error converts into [`SyncKitError`]
+pub trait ResultExt<T> {
+    /// Attach the id of the document being operated on
+    fn with_document(self, document_id: impl Into<String>) -> Result<T>;
+
+    /// Attach the field path being operated on
+    fn with_path(self, path: impl Into<String>) -> Result<T>;
+
+    /// Attach a text position (in characters)
+    fn with_position(self, position: usize) -> Result<T>;
+}
+
+impl<T, E: Into<SyncKitError>> ResultExt<T> for std::result::Result<T, E> {
+    fn with_document(self, document_id: impl Into<String>) -> Result<T> {
+        self.map_err(|e| e.into().with_document(document_id))
+    }
+
+    fn with_path(self, path: impl Into<String>) -> Result<T> {
+        self.map_err(|e| e.into().with_path(path))
+    }
+
+    fn with_position(self, position: usize) -> Result<T> {
+        self.map_err(|e| e.into().with_position(position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_stable() {
+        // These values are public API (WASM `error.code`, FFI
+        // `synckit_last_error_code`); this test must never be updated to
+        // match a renumbering.
+        let cases: Vec<(SyncKitError, u32, &str)> = vec![
+            (
+                SyncError::DocumentNotFound("d".into()).into(),
+                2001,
+                "DOCUMENT_NOT_FOUND",
+            ),
+            (
+                SyncError::FieldNotFound("f".into()).into(),
+                2002,
+                "FIELD_NOT_FOUND",
+            ),
+            (
+                SyncError::SerializationError("s".into()).into(),
+                3001,
+                "SERIALIZATION_ERROR",
+            ),
+            (
+                SyncError::DeserializationError("s".into()).into(),
+                3002,
+                "DESERIALIZATION_ERROR",
+            ),
+            (
+                SyncError::Protocol("p".into()).into(),
+                3003,
+                "PROTOCOL_ERROR",
+            ),
+            (
+                SyncError::StorageError("s".into()).into(),
+                4001,
+                "STORAGE_ERROR",
+            ),
+            (
+                SyncError::NetworkError("n".into()).into(),
+                5001,
+                "NETWORK_ERROR",
+            ),
+            (
+                SyncError::ConflictError("c".into()).into(),
+                5002,
+                "CONFLICT_ERROR",
+            ),
+            (
+                SyncError::InvalidTimestamp("t".into()).into(),
+                6001,
+                "INVALID_TIMESTAMP",
+            ),
+            (
+                SyncError::InvalidOperation("o".into()).into(),
+                6002,
+                "INVALID_OPERATION",
+            ),
+            (SyncKitError::invalid_input("bad"), 6003, "INVALID_INPUT"),
+            (
+                SyncError::DepthLimitExceeded(32).into(),
+                6004,
+                "DEPTH_LIMIT_EXCEEDED",
+            ),
+            (
+                SyncError::AnnotationTooLarge {
+                    size: 2048,
+                    limit: 1024,
+                }
+                .into(),
+                6005,
+                "ANNOTATION_TOO_LARGE",
+            ),
+            (
+                SyncError::SchemaMismatch {
+                    expected: 2,
+                    found: Some(1),
+                }
+                .into(),
+                6006,
+                "SCHEMA_MISMATCH",
+            ),
+            (
+                SyncError::ShadowedField("list".into()).into(),
+                6007,
+                "SHADOWED_FIELD",
+            ),
+            (SyncKitError::would_block(), 5003, "WOULD_BLOCK"),
+        ];
+
+        for (error, code, name) in cases {
+            assert_eq!(error.code(), code, "{}", error);
+            assert_eq!(error.code_name(), name);
+            assert_eq!(code / 1000 * 1000, error.category().base_code());
+        }
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_codes_are_stable() {
+        use crate::crdt::NodeId;
+
+        let node = NodeId::new("c".to_string(), 1, 0);
+        let cases: Vec<(TextError, u32)> = vec![
+            (
+                TextError::PositionOutOfBounds {
+                    position: 9,
+                    length: 2,
+                },
+                1001,
+            ),
+            (
+                TextError::RangeOutOfBounds {
+                    start: 1,
+                    end: 9,
+                    length: 2,
+                },
+                1002,
+            ),
+            (TextError::BlockNotFound(node.clone()), 1003),
+            (TextError::BlockSplitRequired, 1004),
+            (
+                TextError::InvalidBlockSplit {
+                    block_id: node,
+                    offset_start: 0,
+                    offset_end: 9,
+                    block_len: 2,
+                },
+                1005,
+            ),
+            (TextError::RopeError("r".into()), 1006),
+            (TextError::InvalidDelta("d".into()), 1007),
+            #[cfg(feature = "yjs-interop")]
+            (TextError::InvalidYjsUpdate("y".into()), 1008),
+            (
+                TextError::ReplicaConflict {
+                    id: NodeId::new("c", 1, 0),
+                    local: "a".into(),
+                    remote: "b".into(),
+                },
+                1009,
+            ),
+            (
+                TextError::InvalidUtf16Position {
+                    position: 1,
+                    surrogate_pair: true,
+                },
+                1010,
+            ),
+            (
+                TextError::LimitExceeded {
+                    limit: crate::crdt::text_fugue::TextLimit::Blocks,
+                    size: 11,
+                    max: 10,
+                },
+                1011,
+            ),
+        ];
+
+        for (error, code) in cases {
+            let error = SyncKitError::from(error);
+            assert_eq!(error.code(), code);
+            assert_eq!(error.category(), ErrorCategory::Text);
+        }
+    }
+
+    #[test]
+    fn test_context_is_displayed_and_kept_through_result_ext() {
+        let result: std::result::Result<(), SyncError> =
+            Err(SyncError::FieldNotFound("title".into()));
+        let error = result
+            .with_document("doc-1")
+            .with_path("title")
+            .unwrap_err();
+
+        assert_eq!(error.context().document_id.as_deref(), Some("doc-1"));
+        assert_eq!(error.context().path.as_deref(), Some("title"));
+        assert_eq!(
+            error.to_string(),
+            "Field not found: title (document: doc-1, path: title)"
+        );
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Sync(SyncError::FieldNotFound(_))
+        ));
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_error_position_becomes_context() {
+        let error = SyncKitError::from(TextError::PositionOutOfBounds {
+            position: 7,
+            length: 3,
+        });
+        assert_eq!(error.context().position, Some(7));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[test]
+    fn test_retryable_follows_sync_error() {
+        assert!(SyncKitError::from(SyncError::NetworkError("down".into())).is_retryable());
+        assert!(SyncKitError::would_block().is_retryable());
+        assert!(!SyncKitError::invalid_input("bad").is_retryable());
     }
 }
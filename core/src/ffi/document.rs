@@ -0,0 +1,308 @@
+//! Document handle: field-level LWW documents over the C ABI
+
+use super::{
+    bytes_arg, bytes_out, ffi_ptr, ffi_status, handle, str_arg, string_out, FfiError, SynckitStatus,
+};
+use crate::error::SyncKitError;
+use crate::Document;
+use std::ffi::c_char;
+
+/// Opaque document handle
+///
+/// Not thread-safe: may be moved between threads, but must not be used
+/// from two threads at once.
+pub struct SynckitDocument {
+    inner: Document,
+}
+
+fn invalid_json(doc: &SynckitDocument, path: &str, e: serde_json::Error) -> FfiError {
+    FfiError::from_error(
+        SynckitStatus::InvalidJson,
+        SyncKitError::invalid_input(format!("Invalid JSON: {}", e))
+            .with_document(doc.inner.id().as_str())
+            .with_path(path),
+    )
+}
+
+/// Create an empty document
+///
+/// Returns NULL on error. Free with `synckit_document_free`.
+///
+/// # Safety
+///
+/// `id` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_new(id: *const c_char) -> *mut SynckitDocument {
+    ffi_ptr(|| {
+        let id = str_arg(id, "id")?;
+        Ok(Box::into_raw(Box::new(SynckitDocument {
+            inner: Document::new(id.to_string()),
+        })))
+    })
+}
+
+/// Free a document handle
+///
+/// # Safety
+///
+/// `doc` must be NULL or a handle from SyncKit that has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_free(doc: *mut SynckitDocument) {
+    if !doc.is_null() {
+        drop(Box::from_raw(doc));
+    }
+}
+
+/// Set a field from a JSON value (last-writer-wins on `clock`, `client_id`)
+///
+/// # Safety
+///
+/// `doc` must be a live handle; string arguments must be NUL-terminated
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_set_field_json(
+    doc: *mut SynckitDocument,
+    path: *const c_char,
+    value_json: *const c_char,
+    clock: u64,
+    client_id: *const c_char,
+) -> SynckitStatus {
+    ffi_status(|| {
+        let doc = handle(doc, "doc")?;
+        let path = str_arg(path, "path")?;
+        let value = serde_json::from_str(str_arg(value_json, "value_json")?)
+            .map_err(|e| invalid_json(doc, path, e))?;
+        let client_id = str_arg(client_id, "client_id")?;
+
+        doc.inner
+            .set_field(path.to_string(), value, clock, client_id.to_string());
+        Ok(())
+    })
+}
+
+/// Get a field's value as JSON
+///
+/// Returns NULL if the field doesn't exist (last error unset) or on error
+/// (last error set). Free the result with `synckit_string_free`.
+///
+/// # Safety
+///
+/// `doc` must be a live handle; `path` must be NUL-terminated
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_get_field_json(
+    doc: *mut SynckitDocument,
+    path: *const c_char,
+) -> *mut c_char {
+    ffi_ptr(|| {
+        let doc = handle(doc, "doc")?;
+        let path = str_arg(path, "path")?;
+
+        match doc.inner.get_field(&path.to_string()) {
+            Some(value) => string_out(value.to_string()),
+            None => Ok(std::ptr::null_mut()),
+        }
+    })
+}
+
+/// Delete a field
+///
+/// # Safety
+///
+/// `doc` must be a live handle; `path` must be NUL-terminated
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_delete_field(
+    doc: *mut SynckitDocument,
+    path: *const c_char,
+) -> SynckitStatus {
+    ffi_status(|| {
+        let doc = handle(doc, "doc")?;
+        let path = str_arg(path, "path")?;
+        doc.inner.delete_field(&path.to_string());
+        Ok(())
+    })
+}
+
+/// Merge `other` into `doc`
+///
+/// # Safety
+///
+/// Both must be live handles; they may be the same handle
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_merge(
+    doc: *mut SynckitDocument,
+    other: *const SynckitDocument,
+) -> SynckitStatus {
+    ffi_status(|| {
+        let other = handle(other as *mut SynckitDocument, "other")?
+            .inner
+            .clone();
+        handle(doc, "doc")?.inner.merge(&other);
+        Ok(())
+    })
+}
+
+/// Merge a document serialized with `synckit_document_to_bytes` into `doc`
+///
+/// # Safety
+///
+/// `doc` must be a live handle; `bytes` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_merge_bytes(
+    doc: *mut SynckitDocument,
+    bytes: *const u8,
+    len: usize,
+) -> SynckitStatus {
+    ffi_status(|| {
+        let doc = handle(doc, "doc")?;
+        let remote: Document = decode(bytes_arg(bytes, len, "bytes")?)?;
+        doc.inner.merge(&remote);
+        Ok(())
+    })
+}
+
+/// Get the document's field values as a JSON object
+///
+/// Returns NULL on error. Free the result with `synckit_string_free`.
+///
+/// # Safety
+///
+/// `doc` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_to_json(doc: *mut SynckitDocument) -> *mut c_char {
+    ffi_ptr(|| string_out(handle(doc, "doc")?.inner.to_json().to_string()))
+}
+
+/// Serialize the document, including LWW metadata, for storage or sync
+///
+/// Returns NULL on error. Free the result with `synckit_bytes_free`.
+///
+/// # Safety
+///
+/// `doc` must be a live handle; `out_len` must point to writable memory
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_to_bytes(
+    doc: *mut SynckitDocument,
+    out_len: *mut usize,
+) -> *mut u8 {
+    ffi_ptr(|| {
+        let doc = handle(doc, "doc")?;
+        let bytes = serde_json::to_vec(&doc.inner).map_err(|e| {
+            FfiError::from_error(
+                SynckitStatus::InvalidJson,
+                SyncKitError::serialization(e).with_document(doc.inner.id().as_str()),
+            )
+        })?;
+        bytes_out(bytes, out_len)
+    })
+}
+
+/// Load a document serialized with `synckit_document_to_bytes`
+///
+/// Returns NULL on error. Free with `synckit_document_free`.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn synckit_document_from_bytes(
+    bytes: *const u8,
+    len: usize,
+) -> *mut SynckitDocument {
+    ffi_ptr(|| {
+        let inner = decode(bytes_arg(bytes, len, "bytes")?)?;
+        Ok(Box::into_raw(Box::new(SynckitDocument { inner })))
+    })
+}
+
+fn decode(bytes: &[u8]) -> Result<Document, FfiError> {
+    serde_json::from_slice(bytes).map_err(|e| {
+        FfiError::from_error(
+            SynckitStatus::InvalidBytes,
+            SyncKitError::deserialization(format!("Invalid document bytes: {}", e)),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::{CStr, CString};
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn take_string(ptr: *mut c_char) -> String {
+        let s = CStr::from_ptr(ptr).to_str().unwrap().to_string();
+        super::super::synckit_string_free(ptr);
+        s
+    }
+
+    #[test]
+    fn test_set_get_and_round_trip() {
+        unsafe {
+            let doc = synckit_document_new(c("doc1").as_ptr());
+            let status = synckit_document_set_field_json(
+                doc,
+                c("title").as_ptr(),
+                c("\"Hello\"").as_ptr(),
+                1,
+                c("client1").as_ptr(),
+            );
+            assert_eq!(status, SynckitStatus::Ok);
+            assert_eq!(
+                take_string(synckit_document_get_field_json(doc, c("title").as_ptr())),
+                "\"Hello\""
+            );
+            assert!(synckit_document_get_field_json(doc, c("missing").as_ptr()).is_null());
+
+            let mut len = 0;
+            let bytes = synckit_document_to_bytes(doc, &mut len);
+            let copy = synckit_document_from_bytes(bytes, len);
+            super::super::synckit_bytes_free(bytes, len);
+
+            assert_eq!(
+                take_string(synckit_document_to_json(copy)),
+                r#"{"title":"Hello"}"#
+            );
+
+            synckit_document_free(copy);
+            synckit_document_free(doc);
+        }
+    }
+
+    #[test]
+    fn test_invalid_json_rejected() {
+        unsafe {
+            let doc = synckit_document_new(c("doc1").as_ptr());
+            let status = synckit_document_set_field_json(
+                doc,
+                c("title").as_ptr(),
+                c("{not json").as_ptr(),
+                1,
+                c("client1").as_ptr(),
+            );
+            assert_eq!(status, SynckitStatus::InvalidJson);
+            assert_eq!(super::super::synckit_last_error_code(), 6003);
+
+            let message = CStr::from_ptr(super::super::synckit_last_error_message());
+            let message = message.to_str().unwrap();
+            assert!(
+                message.contains("document: doc1, path: title"),
+                "{}",
+                message
+            );
+            synckit_document_free(doc);
+        }
+    }
+
+    #[test]
+    fn test_null_handle_rejected() {
+        unsafe {
+            assert!(synckit_document_new(std::ptr::null()).is_null());
+            assert_eq!(
+                synckit_document_merge(std::ptr::null_mut(), std::ptr::null()),
+                SynckitStatus::NullPointer
+            );
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! C ABI for embedding SyncKit in native apps (iOS, Android, C/C++)
+//!
+//! Enabled with the `ffi` feature. The header `include/synckit.h` is
+//! generated from this module by cbindgen (`cbindgen --config cbindgen.toml
+//! --output include/synckit.h`).
+//!
+//! # Handles
+//!
+//! Documents and texts are opaque handles created by `synckit_*_new` (or
+//! `_from_bytes`) and released with the matching `synckit_*_free`. Handles
+//! are `Send` but not `Sync`: a handle may be passed to another thread, but
+//! must never be used from two threads at once. Callers that share a handle
+//! need their own lock.
+//!
+//! # Ownership
+//!
+//! - Strings passed in are borrowed, NUL-terminated UTF-8, and only read
+//!   during the call.
+//! - Strings returned (`char *`) are owned by the caller and must be freed
+//!   with `synckit_string_free`.
+//! - Byte buffers returned (`uint8_t *` plus an out-length) are owned by the
+//!   caller and must be freed with `synckit_bytes_free`, passing the same
+//!   length.
+//! - `synckit_last_error_message` returns a borrowed pointer, valid until the
+//!   next SyncKit call on the same thread.
+//!
+//! # Errors
+//!
+//! Fallible calls return a `SynckitStatus` (or NULL for calls returning a
+//! pointer) and store a message in per-thread last-error storage, along
+//! with the same numeric `SyncKitError` code the Rust and WASM APIs report
+//! (`synckit_last_error_code`). A successful call clears both. Panics are caught at the boundary and
+//! reported as `SYNCKIT_STATUS_PANIC`; the handle involved should then be
+//! discarded.
+
+mod document;
+mod text;
+
+pub use document::{
+    synckit_document_delete_field, synckit_document_free, synckit_document_from_bytes,
+    synckit_document_get_field_json, synckit_document_merge, synckit_document_merge_bytes,
+    synckit_document_new, synckit_document_set_field_json, synckit_document_to_bytes,
+    synckit_document_to_json, SynckitDocument,
+};
+pub use text::{
+    synckit_text_delete, synckit_text_free, synckit_text_from_bytes, synckit_text_insert,
+    synckit_text_len, synckit_text_merge, synckit_text_merge_bytes, synckit_text_new,
+    synckit_text_to_bytes, synckit_text_to_string, SynckitText,
+};
+
+use crate::error::SyncKitError;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Result code returned by fallible FFI calls
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SynckitStatus {
+    /// The call succeeded
+    Ok = 0,
+
+    /// A required pointer argument was NULL
+    NullPointer = 1,
+
+    /// A string argument was not valid UTF-8
+    InvalidUtf8 = 2,
+
+    /// A JSON argument could not be parsed
+    InvalidJson = 3,
+
+    /// Serialized bytes could not be decoded
+    InvalidBytes = 4,
+
+    /// A text operation failed (e.g. position out of bounds)
+    TextError = 5,
+
+    /// Rust code panicked; the handle should be discarded
+    Panic = 6,
+}
+
+impl SynckitStatus {
+    /// `SyncKitError` code reported for errors raised directly at the
+    /// boundary (argument checks) rather than by the core
+    fn default_code(self) -> u32 {
+        match self {
+            SynckitStatus::Ok | SynckitStatus::Panic => 0,
+            SynckitStatus::NullPointer
+            | SynckitStatus::InvalidUtf8
+            | SynckitStatus::InvalidJson => SyncKitError::invalid_input("").code(),
+            SynckitStatus::InvalidBytes => SyncKitError::deserialization("").code(),
+            SynckitStatus::TextError => 0,
+        }
+    }
+}
+
+/// Error raised inside an FFI call, before it is stored as the last error
+pub(crate) struct FfiError {
+    status: SynckitStatus,
+    code: u32,
+    message: String,
+}
+
+impl FfiError {
+    pub(crate) fn new(status: SynckitStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code: status.default_code(),
+            message: message.into(),
+        }
+    }
+
+    /// Report a core error, keeping its `SyncKitError` code
+    pub(crate) fn from_error(status: SynckitStatus, error: impl Into<SyncKitError>) -> Self {
+        let error = error.into();
+        Self {
+            status,
+            code: error.code(),
+            message: error.to_string(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<(CString, u32)>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: &str, code: u32) {
+    // Interior NULs would truncate the C string; replace them instead
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = Some((message, code)));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = None);
+}
+
+/// Run an FFI call body, converting errors and panics into a status
+pub(crate) fn ffi_status(body: impl FnOnce() -> Result<(), FfiError>) -> SynckitStatus {
+    match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => {
+            clear_last_error();
+            SynckitStatus::Ok
+        }
+        Ok(Err(error)) => {
+            set_last_error(&error.message, error.code);
+            error.status
+        }
+        Err(_) => {
+            set_last_error("SyncKit panicked", 0);
+            SynckitStatus::Panic
+        }
+    }
+}
+
+/// Run an FFI call body returning a pointer; NULL on error or panic
+pub(crate) fn ffi_ptr<T>(body: impl FnOnce() -> Result<*mut T, FfiError>) -> *mut T {
+    let mut result = std::ptr::null_mut();
+    ffi_status(|| {
+        result = body()?;
+        Ok(())
+    });
+    result
+}
+
+/// Borrow a handle, failing on NULL
+///
+/// # Safety
+///
+/// `ptr` must be NULL or point to a live `T` not used concurrently
+pub(crate) unsafe fn handle<'a, T>(ptr: *mut T, name: &str) -> Result<&'a mut T, FfiError> {
+    ptr.as_mut().ok_or_else(|| {
+        FfiError::new(
+            SynckitStatus::NullPointer,
+            format!("{} must not be NULL", name),
+        )
+    })
+}
+
+/// Borrow a NUL-terminated UTF-8 string argument
+///
+/// # Safety
+///
+/// `ptr` must be NULL or point to a NUL-terminated string
+pub(crate) unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::new(
+            SynckitStatus::NullPointer,
+            format!("{} must not be NULL", name),
+        ));
+    }
+
+    CStr::from_ptr(ptr).to_str().map_err(|e| {
+        FfiError::new(
+            SynckitStatus::InvalidUtf8,
+            format!("{} is not valid UTF-8: {}", name, e),
+        )
+    })
+}
+
+/// Borrow a byte buffer argument
+///
+/// # Safety
+///
+/// `ptr` must be NULL or point to `len` readable bytes
+pub(crate) unsafe fn bytes_arg<'a>(
+    ptr: *const u8,
+    len: usize,
+    name: &str,
+) -> Result<&'a [u8], FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::new(
+            SynckitStatus::NullPointer,
+            format!("{} must not be NULL", name),
+        ));
+    }
+
+    Ok(std::slice::from_raw_parts(ptr, len))
+}
+
+/// Hand a string to the caller (freed with `synckit_string_free`)
+pub(crate) fn string_out(s: String) -> Result<*mut c_char, FfiError> {
+    CString::new(s).map(CString::into_raw).map_err(|_| {
+        FfiError::new(
+            SynckitStatus::InvalidUtf8,
+            "string contains an interior NUL byte",
+        )
+    })
+}
+
+/// Hand a byte buffer to the caller (freed with `synckit_bytes_free`)
+///
+/// # Safety
+///
+/// `out_len` must be NULL or point to writable memory for a `usize`
+pub(crate) unsafe fn bytes_out(bytes: Vec<u8>, out_len: *mut usize) -> Result<*mut u8, FfiError> {
+    if out_len.is_null() {
+        return Err(FfiError::new(
+            SynckitStatus::NullPointer,
+            "out_len must not be NULL",
+        ));
+    }
+
+    let bytes = bytes.into_boxed_slice();
+    *out_len = bytes.len();
+    Ok(Box::into_raw(bytes) as *mut u8)
+}
+
+/// Get the last error message for the calling thread
+///
+/// Returns NULL if the last call on this thread succeeded. The pointer is
+/// borrowed and stays valid until the next SyncKit call on this thread.
+#[no_mangle]
+pub extern "C" fn synckit_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|slot| {
+        slot.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |(message, _)| message.as_ptr())
+    })
+}
+
+/// Get the `SyncKitError` code of the last error on the calling thread
+///
+/// Codes are stable across releases and match `SyncKitError::code` in Rust
+/// and `err.code` in JavaScript (e.g. 1001 for a text position out of
+/// bounds). Returns 0 if the last call succeeded or panicked.
+#[no_mangle]
+pub extern "C" fn synckit_last_error_code() -> u32 {
+    LAST_ERROR.with(|slot| slot.borrow().as_ref().map_or(0, |(_, code)| *code))
+}
+
+/// Free a string returned by SyncKit
+///
+/// # Safety
+///
+/// `s` must be NULL or a string returned by SyncKit that has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn synckit_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Free a byte buffer returned by SyncKit
+///
+/// # Safety
+///
+/// `bytes` must be NULL or a buffer returned by SyncKit that has not been
+/// freed, and `len` must be the length SyncKit reported for it
+#[no_mangle]
+pub unsafe extern "C" fn synckit_bytes_free(bytes: *mut u8, len: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            bytes, len,
+        )));
+    }
+}
+
+/// Get the SyncKit version as a static NUL-terminated string
+#[no_mangle]
+pub extern "C" fn synckit_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> Option<String> {
+        let ptr = synckit_last_error_message();
+        (!ptr.is_null()).then(|| {
+            unsafe { CStr::from_ptr(ptr) }
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    #[test]
+    fn test_error_is_per_call() {
+        let status = ffi_status(|| Err(FfiError::new(SynckitStatus::InvalidJson, "bad json")));
+        assert_eq!(status, SynckitStatus::InvalidJson);
+        assert_eq!(last_error().as_deref(), Some("bad json"));
+        assert_eq!(synckit_last_error_code(), 6003);
+
+        assert_eq!(ffi_status(|| Ok(())), SynckitStatus::Ok);
+        assert_eq!(last_error(), None);
+        assert_eq!(synckit_last_error_code(), 0);
+    }
+
+    #[test]
+    fn test_error_is_per_thread() {
+        ffi_status(|| Err(FfiError::new(SynckitStatus::InvalidJson, "main thread")));
+
+        std::thread::spawn(|| assert_eq!(last_error(), None))
+            .join()
+            .unwrap();
+        assert_eq!(last_error().as_deref(), Some("main thread"));
+    }
+
+    #[test]
+    fn test_panic_is_caught() {
+        let status = ffi_status(|| panic!("boom"));
+        assert_eq!(status, SynckitStatus::Panic);
+        assert!(last_error().is_some());
+    }
+
+    #[test]
+    fn test_invalid_utf8_rejected() {
+        let bytes = b"\xff\xfe\0";
+        let result = unsafe { str_arg(bytes.as_ptr() as *const c_char, "path") };
+        assert_eq!(
+            result.err().map(|e| e.status),
+            Some(SynckitStatus::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut len = 0;
+        let ptr = unsafe { bytes_out(vec![1, 2, 3], &mut len) }.ok().unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(unsafe { std::slice::from_raw_parts(ptr, len) }, &[1, 2, 3]);
+        unsafe { synckit_bytes_free(ptr, len) };
+    }
+}
@@ -0,0 +1,254 @@
+//! Text handle: FugueText collaborative text over the C ABI
+//!
+//! Positions and lengths are in characters (Unicode scalar values), the
+//! same units as `FugueText`.
+
+use super::{
+    bytes_arg, bytes_out, ffi_ptr, ffi_status, handle, str_arg, string_out, FfiError, SynckitStatus,
+};
+use crate::crdt::{FugueText, TextError};
+use crate::error::SyncKitError;
+use std::ffi::c_char;
+
+/// Opaque collaborative text handle
+///
+/// Not thread-safe: may be moved between threads, but must not be used
+/// from two threads at once.
+pub struct SynckitText {
+    inner: FugueText,
+}
+
+fn text_error(e: TextError) -> FfiError {
+    FfiError::from_error(SynckitStatus::TextError, e)
+}
+
+/// Create an empty text replica
+///
+/// Returns NULL on error. Free with `synckit_text_free`.
+///
+/// # Safety
+///
+/// `client_id` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_new(client_id: *const c_char) -> *mut SynckitText {
+    ffi_ptr(|| {
+        let client_id = str_arg(client_id, "client_id")?;
+        Ok(Box::into_raw(Box::new(SynckitText {
+            inner: FugueText::new(client_id.to_string()),
+        })))
+    })
+}
+
+/// Free a text handle
+///
+/// # Safety
+///
+/// `text` must be NULL or a handle from SyncKit that has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_free(text: *mut SynckitText) {
+    if !text.is_null() {
+        drop(Box::from_raw(text));
+    }
+}
+
+/// Insert UTF-8 text at a character position
+///
+/// # Safety
+///
+/// `text` must be a live handle; `utf8` must be NUL-terminated
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_insert(
+    text: *mut SynckitText,
+    position: usize,
+    utf8: *const c_char,
+) -> SynckitStatus {
+    ffi_status(|| {
+        let text = handle(text, "text")?;
+        let utf8 = str_arg(utf8, "utf8")?;
+        text.inner.insert(position, utf8).map_err(text_error)?;
+        Ok(())
+    })
+}
+
+/// Delete `length` characters starting at `position`
+///
+/// # Safety
+///
+/// `text` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_delete(
+    text: *mut SynckitText,
+    position: usize,
+    length: usize,
+) -> SynckitStatus {
+    ffi_status(|| {
+        let text = handle(text, "text")?;
+        text.inner.delete(position, length).map_err(text_error)?;
+        Ok(())
+    })
+}
+
+/// Get the length in characters (0 if `text` is NULL)
+///
+/// # Safety
+///
+/// `text` must be NULL or a live handle
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_len(text: *const SynckitText) -> usize {
+    text.as_ref().map_or(0, |text| text.inner.len())
+}
+
+/// Get the current text as a UTF-8 string
+///
+/// Returns NULL on error. Free the result with `synckit_string_free`.
+///
+/// # Safety
+///
+/// `text` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_to_string(text: *mut SynckitText) -> *mut c_char {
+    ffi_ptr(|| string_out(handle(text, "text")?.inner.to_string()))
+}
+
+/// Merge `other` into `text`
+///
+/// # Safety
+///
+/// Both must be live handles; they may be the same handle
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_merge(
+    text: *mut SynckitText,
+    other: *const SynckitText,
+) -> SynckitStatus {
+    ffi_status(|| {
+        let other = handle(other as *mut SynckitText, "other")?.inner.clone();
+        handle(text, "text")?
+            .inner
+            .merge(&other)
+            .map_err(text_error)
+    })
+}
+
+/// Merge a replica serialized with `synckit_text_to_bytes` into `text`
+///
+/// # Safety
+///
+/// `text` must be a live handle; `bytes` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_merge_bytes(
+    text: *mut SynckitText,
+    bytes: *const u8,
+    len: usize,
+) -> SynckitStatus {
+    ffi_status(|| {
+        let text = handle(text, "text")?;
+        let remote = decode(bytes_arg(bytes, len, "bytes")?)?;
+        text.inner.merge(&remote).map_err(text_error)
+    })
+}
+
+/// Serialize the replica, including CRDT metadata, for storage or sync
+///
+/// Returns NULL on error. Free the result with `synckit_bytes_free`.
+///
+/// # Safety
+///
+/// `text` must be a live handle; `out_len` must point to writable memory
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_to_bytes(
+    text: *mut SynckitText,
+    out_len: *mut usize,
+) -> *mut u8 {
+    ffi_ptr(|| {
+        let text = handle(text, "text")?;
+        let bytes = serde_json::to_vec(&text.inner).map_err(|e| {
+            FfiError::from_error(SynckitStatus::InvalidJson, SyncKitError::serialization(e))
+        })?;
+        bytes_out(bytes, out_len)
+    })
+}
+
+/// Load a replica serialized with `synckit_text_to_bytes`
+///
+/// Returns NULL on error. Free with `synckit_text_free`.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn synckit_text_from_bytes(bytes: *const u8, len: usize) -> *mut SynckitText {
+    ffi_ptr(|| {
+        let inner = decode(bytes_arg(bytes, len, "bytes")?)?;
+        Ok(Box::into_raw(Box::new(SynckitText { inner })))
+    })
+}
+
+fn decode(bytes: &[u8]) -> Result<FugueText, FfiError> {
+    serde_json::from_slice(bytes).map_err(|e| {
+        FfiError::from_error(
+            SynckitStatus::InvalidBytes,
+            SyncKitError::deserialization(format!("Invalid text bytes: {}", e)),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::{CStr, CString};
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn read(text: *mut SynckitText) -> String {
+        let ptr = synckit_text_to_string(text);
+        let s = CStr::from_ptr(ptr).to_str().unwrap().to_string();
+        super::super::synckit_string_free(ptr);
+        s
+    }
+
+    #[test]
+    fn test_merge_bytes_converges() {
+        unsafe {
+            let a = synckit_text_new(c("client1").as_ptr());
+            let b = synckit_text_new(c("client2").as_ptr());
+            synckit_text_insert(a, 0, c("Hello").as_ptr());
+            synckit_text_insert(b, 0, c("World").as_ptr());
+
+            let mut len = 0;
+            let bytes = synckit_text_to_bytes(b, &mut len);
+            assert_eq!(synckit_text_merge_bytes(a, bytes, len), SynckitStatus::Ok);
+            super::super::synckit_bytes_free(bytes, len);
+            assert_eq!(synckit_text_merge(b, a), SynckitStatus::Ok);
+
+            assert_eq!(read(a), read(b));
+            assert_eq!(synckit_text_len(a), 10);
+
+            synckit_text_free(a);
+            synckit_text_free(b);
+        }
+    }
+
+    #[test]
+    fn test_out_of_bounds_insert_reports_text_error() {
+        unsafe {
+            let text = synckit_text_new(c("client1").as_ptr());
+            assert_eq!(
+                synckit_text_insert(text, 5, c("x").as_ptr()),
+                SynckitStatus::TextError
+            );
+            assert!(!super::super::synckit_last_error_message().is_null());
+            assert_eq!(super::super::synckit_last_error_code(), 1001);
+            synckit_text_free(text);
+        }
+    }
+
+    #[test]
+    fn test_invalid_bytes_rejected() {
+        unsafe {
+            let bytes = b"garbage";
+            assert!(synckit_text_from_bytes(bytes.as_ptr(), bytes.len()).is_null());
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! gRPC replication between relays (`grpc` feature)
+//!
+//! Relays in different regions replicate a [`Workspace`] of documents over
+//! the `Replication` service from `protocol/specs/sync.proto`.
+//! [`ReplicationService`] serves it with tonic, and
+//! [`ReplicationService::replicate`] runs the same session from the
+//! connecting side, so either relay can dial the other.
+//!
+//! # Session
+//!
+//! Both sides start by sending the version of every document they have.
+//! Once a side knows the peer's version of a document it sends every field
+//! the peer may be missing, then a delta after each later change. Incoming
+//! deltas are LWW-merged, so the two workspaces converge whatever the
+//! interleaving. Either side may send an anti-entropy probe
+//! ([`VersionFilter`]) at any time; the other sends every document whose
+//! version the probe lacks again in full. Outgoing and incoming changes go through the service's
+//! `SyncCoordinator`, so its filters apply to replication as well.
+//!
+//! TLS, authentication and reconnects are left to the caller: configure
+//! them on the tonic `Server` and `Channel`.
+
+use crate::concurrent::SharedDocument;
+use crate::error::SyncKitError;
+use crate::protocol::delta::{
+    document_version_to_protocol, vector_clock_to_protocol, version_summary_from_protocol,
+    DocumentDelta,
+};
+use crate::protocol::replication_client::ReplicationClient;
+use crate::protocol::replication_server::{Replication, ReplicationServer};
+use crate::protocol::sync::{peer_baseline, SyncCoordinator, VersionFilter};
+use crate::protocol::{
+    self, sync_message, DocumentId, DocumentVersion, GetSnapshotRequest, GetVersionRequest,
+    SyncMessage, VersionSummary,
+};
+use crate::{Document, DocumentID};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::Channel;
+use tonic::{Request, Response, Status, Streaming};
+
+pub use crate::server::Workspace;
+
+/// Messages buffered per direction of a session
+const SESSION_BUFFER: usize = 64;
+
+/// The `Replication` gRPC service over a `Workspace`
+///
+/// Cheap to clone; clones share the workspace and coordinator.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use synckit_core::grpc::{ReplicationService, Workspace};
+/// use synckit_core::protocol::replication_client::ReplicationClient;
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let service = ReplicationService::new("eu-west", Workspace::new());
+///
+/// // Serve it...
+/// let server = tonic::transport::Server::builder()
+///     .add_service(service.clone().into_server())
+///     .serve("0.0.0.0:50051".parse()?);
+/// tokio::spawn(server);
+///
+/// // ...and replicate with another region
+/// let peer = ReplicationClient::connect("http://us-east.internal:50051").await?;
+/// service.replicate(peer).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReplicationService {
+    relay_id: String,
+    workspace: Workspace,
+    coordinator: Arc<SyncCoordinator>,
+}
+
+impl ReplicationService {
+    /// Create a service replicating `workspace`, without filters
+    ///
+    /// `relay_id` tags applied changes (see `DocumentDelta::apply_to`).
+    pub fn new(relay_id: impl Into<String>, workspace: Workspace) -> Self {
+        Self {
+            relay_id: relay_id.into(),
+            workspace,
+            coordinator: Arc::new(SyncCoordinator::new()),
+        }
+    }
+
+    /// Route replication through `coordinator` (and its filters)
+    pub fn with_coordinator(mut self, coordinator: SyncCoordinator) -> Self {
+        self.coordinator = Arc::new(coordinator);
+        self
+    }
+
+    /// The replicated workspace
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspace
+    }
+
+    /// Wrap the service for `tonic::transport::Server::add_service`
+    pub fn into_server(self) -> ReplicationServer<Self> {
+        ReplicationServer::new(self)
+    }
+
+    /// Run a replication session with the relay behind `client`
+    ///
+    /// Returns when the peer closes the stream.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the call can't be made, the peer fails the stream, or it
+    /// sends a malformed message
+    pub async fn replicate(&self, mut client: ReplicationClient<Channel>) -> Result<(), Status> {
+        let (outbound, rx) = mpsc::channel(SESSION_BUFFER);
+        // Errors are returned from here rather than sent to the peer
+        let requests = ReceiverStream::new(rx).map_while(Result::ok);
+        let inbound = client.sync(requests).await?.into_inner();
+        Session::new(self.clone(), outbound).run(inbound).await
+    }
+
+    fn document(&self, id: Option<&DocumentId>) -> Result<SharedDocument, Status> {
+        let id = id
+            .map(|id| id.id.as_str())
+            .ok_or_else(|| Status::invalid_argument("missing document ID"))?;
+        self.workspace
+            .document(id)
+            .ok_or_else(|| Status::not_found(format!("no document {}", id)))
+    }
+}
+
+#[tonic::async_trait]
+impl Replication for ReplicationService {
+    type SyncStream = ReceiverStream<Result<SyncMessage, Status>>;
+
+    async fn sync(
+        &self,
+        request: Request<Streaming<SyncMessage>>,
+    ) -> Result<Response<Self::SyncStream>, Status> {
+        let (outbound, rx) = mpsc::channel(SESSION_BUFFER);
+        let session = Session::new(self.clone(), outbound.clone());
+        let inbound = request.into_inner();
+        tokio::spawn(async move {
+            if let Err(status) = session.run(inbound).await {
+                let _ = outbound.send(Err(status)).await;
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn get_snapshot(
+        &self,
+        request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<protocol::Document>, Status> {
+        let document = self.document(request.get_ref().document_id.as_ref())?;
+        let snapshot = self
+            .coordinator
+            .outgoing_snapshot(&document.read_snapshot());
+        Ok(Response::new(document_to_protocol(&snapshot)?))
+    }
+
+    async fn get_version(
+        &self,
+        request: Request<GetVersionRequest>,
+    ) -> Result<Response<DocumentVersion>, Status> {
+        let document = self.document(request.get_ref().document_id.as_ref())?;
+        Ok(Response::new(document_version_to_protocol(
+            document.id(),
+            &document.version(),
+        )))
+    }
+}
+
+/// Encode a document as a protobuf `Document`
+///
+/// # Errors
+///
+/// Never fails for a well-formed document; the `Result` mirrors
+/// `DocumentDelta::compute`.
+pub fn document_to_protocol(document: &Document) -> Result<protocol::Document, Status> {
+    let empty = Document::new(document.id().clone());
+    let delta = DocumentDelta::compute(&empty, document)
+        .map_err(to_status)?
+        .to_protocol();
+    Ok(protocol::Document {
+        id: Some(DocumentId {
+            id: document.id().clone(),
+        }),
+        version: Some(vector_clock_to_protocol(document.version())),
+        fields: delta.changes,
+        created_at: None,
+        updated_at: None,
+        created_by: None,
+        lists: delta.lists,
+        annotations: delta.annotations,
+        registers: delta.registers,
+        refs: delta.refs,
+    })
+}
+
+/// Decode a protobuf `Document` produced by `document_to_protocol`
+///
+/// # Errors
+///
+/// Fails with `INVALID_ARGUMENT` if the document ID or a field is missing
+/// or malformed
+pub fn document_from_protocol(proto: &protocol::Document) -> Result<Document, Status> {
+    let delta = protocol::Delta {
+        document_id: proto.id.clone(),
+        base_version: None,
+        new_version: proto.version.clone(),
+        changes: proto.fields.clone(),
+        client_id: None,
+        created_at: None,
+        lists: proto.lists.clone(),
+        annotations: proto.annotations.clone(),
+        registers: proto.registers.clone(),
+        refs: proto.refs.clone(),
+    };
+    let delta = DocumentDelta::from_protocol(&delta, "").map_err(to_status)?;
+    let mut document = Document::new(delta.document_id.clone());
+    delta.apply_to(&mut document, "").map_err(to_status)?;
+    document.version = delta.new_version;
+    Ok(document)
+}
+
+fn to_status(error: SyncKitError) -> Status {
+    Status::invalid_argument(error.to_string())
+}
+
+/// One side of a `Sync` stream
+struct Session {
+    service: ReplicationService,
+    outbound: mpsc::Sender<Result<SyncMessage, Status>>,
+    /// Set once the peer's versions have arrived; nothing is pushed before
+    peer_ready: bool,
+    /// What the peer has of each document, as far as we know
+    baselines: HashMap<DocumentID, Document>,
+}
+
+impl Session {
+    fn new(
+        service: ReplicationService,
+        outbound: mpsc::Sender<Result<SyncMessage, Status>>,
+    ) -> Self {
+        Self {
+            service,
+            outbound,
+            peer_ready: false,
+            baselines: HashMap::new(),
+        }
+    }
+
+    async fn run(
+        mut self,
+        mut inbound: impl Stream<Item = Result<SyncMessage, Status>> + Unpin,
+    ) -> Result<(), Status> {
+        let mut changes = self.service.workspace.changes();
+        let versions = self
+            .service
+            .workspace
+            .documents()
+            .iter()
+            .map(|document| document_version_to_protocol(document.id(), &document.version()))
+            .collect();
+        self.send(sync_message::Payload::Versions(VersionSummary {
+            documents: versions,
+        }))
+        .await?;
+
+        loop {
+            tokio::select! {
+                message = inbound.next() => match message {
+                    Some(message) => self.receive(message?).await?,
+                    None => return Ok(()),
+                },
+                Some(id) = changes.recv() => self.push(&id).await?,
+            }
+        }
+    }
+
+    async fn receive(&mut self, message: SyncMessage) -> Result<(), Status> {
+        match message.payload {
+            Some(sync_message::Payload::Versions(summary)) => {
+                let peer = version_summary_from_protocol(&summary);
+                for document in self.service.workspace.documents() {
+                    let current = document.read_snapshot();
+                    let baseline = peer_baseline(&current, peer.get(current.id()));
+                    self.baselines.insert(current.id().clone(), baseline);
+                }
+                self.peer_ready = true;
+                let ids: Vec<DocumentID> = self.baselines.keys().cloned().collect();
+                for id in ids {
+                    self.push(&id).await?;
+                }
+            }
+            Some(sync_message::Payload::Delta(proto)) => {
+                let delta = DocumentDelta::from_protocol(&proto, &self.service.relay_id)
+                    .map_err(to_status)?;
+                let document = self.service.workspace.get_or_create(&delta.document_id);
+                let coordinator = &self.service.coordinator;
+                let relay_id = &self.service.relay_id;
+                document
+                    .write(|document| coordinator.apply_incoming(&delta, document, relay_id))
+                    .map_err(to_status)?;
+
+                // The peer has this now: don't echo it back
+                let baseline = self
+                    .baselines
+                    .entry(delta.document_id.clone())
+                    .or_insert_with(|| Document::new(delta.document_id.clone()));
+                coordinator
+                    .apply_incoming(&delta, baseline, relay_id)
+                    .map_err(to_status)?;
+            }
+            Some(sync_message::Payload::Probe(proto)) => {
+                let probe = VersionFilter::from_protocol(&proto).map_err(to_status)?;
+                let documents: Vec<Arc<Document>> = self
+                    .service
+                    .workspace
+                    .documents()
+                    .iter()
+                    .map(SharedDocument::read_snapshot)
+                    .collect();
+                let ids = self
+                    .service
+                    .coordinator
+                    .probable_mismatches(&probe, documents.iter().map(|document| &**document));
+                // The peer's copy differs from what we assumed: send it all
+                for id in ids {
+                    self.baselines.insert(id.clone(), Document::new(id.clone()));
+                    self.push(&id).await?;
+                }
+            }
+            None => return Err(Status::invalid_argument("empty sync message")),
+        }
+        Ok(())
+    }
+
+    /// Send the peer whatever changed in a document since the baseline
+    async fn push(&mut self, id: &DocumentID) -> Result<(), Status> {
+        if !self.peer_ready {
+            return Ok(());
+        }
+        let Some(document) = self.service.workspace.document(id) else {
+            return Ok(());
+        };
+        let current = document.read_snapshot();
+        let baseline = self
+            .baselines
+            .entry(id.clone())
+            .or_insert_with(|| Document::new(id.clone()));
+        let delta = self
+            .service
+            .coordinator
+            .outgoing_delta(baseline, &current)
+            .map_err(to_status)?;
+        *baseline = (*current).clone();
+        if delta.is_empty() {
+            return Ok(());
+        }
+        trace_debug!(document_id = %id, changes = delta.changes.len(), "replicating delta");
+        self.send(sync_message::Payload::Delta(delta.to_protocol()))
+            .await
+    }
+
+    async fn send(&self, payload: sync_message::Payload) -> Result<(), Status> {
+        self.outbound
+            .send(Ok(SyncMessage {
+                payload: Some(payload),
+            }))
+            .await
+            .map_err(|_| Status::cancelled("peer closed the session"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_document_protocol_round_trip() {
+        let mut document = Document::new("doc".to_string());
+        document.set_field("title".to_string(), json!("Hi"), 3, "east".to_string());
+        document.set_field("tags".to_string(), json!(["a", 1]), 4, "west".to_string());
+        document.version.update("east", 3);
+        document.version.update("west", 4);
+
+        let decoded = document_from_protocol(&document_to_protocol(&document).unwrap()).unwrap();
+        assert_eq!(decoded.fields(), document.fields());
+        assert_eq!(decoded.version(), document.version());
+    }
+}
@@ -0,0 +1,462 @@
+//! Seeding a document from a plain JSON tree, and exporting it back
+//!
+//! [`Document::import_json`] flattens nested objects into dotted field
+//! paths (`{"user": {"name": "Ada"}}` sets `user.name`) and turns arrays
+//! into list fields, so an existing REST payload can be loaded without
+//! hand-written `set_field` calls. [`Document::export_json`] rebuilds the
+//! tree from the paths.
+//!
+//! Every field an import writes gets the same timestamp: one tick of the
+//! importing client, or a clock supplied in [`ImportOptions`]. Paths that
+//! already hold the imported value are left alone, so importing the same
+//! payload again changes nothing, and replicas that import the same
+//! payload with the same options end up identical.
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde_json::json;
+//! use synckit_core::import::ImportOptions;
+//! use synckit_core::Document;
+//!
+//! let payload = json!({"user": {"name": "Ada", "tags": ["admin"]}, "draft": null});
+//! let options = ImportOptions::new("seed".to_string());
+//! let mut document = Document::new("doc-1".to_string());
+//!
+//! let report = document.import_json(payload.clone(), &options).unwrap();
+//! assert_eq!(report.created, vec!["draft", "user.name", "user.tags"]);
+//! assert_eq!(document.export_json(), payload);
+//!
+//! // Importing it again is a no-op
+//! let report = document.import_json(payload, &options).unwrap();
+//! assert!(report.is_unchanged());
+//! ```
+
+use crate::document::{Document, Field};
+use crate::error::{Result, SyncError, SyncKitError};
+use crate::sync::Timestamp;
+use crate::{ClientID, FieldPath};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+
+/// Default for [`ImportOptions::max_depth`]
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+/// How [`Document::import_json`] writes fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportOptions {
+    /// Client the fields are written as
+    pub client_id: ClientID,
+
+    /// Clock of every field written; None ticks the client's entry in
+    /// the document version once (only if anything changes)
+    pub clock: Option<u64>,
+
+    /// Deepest nesting of objects and arrays accepted, counting the
+    /// top-level object as 1
+    pub max_depth: usize,
+}
+
+impl ImportOptions {
+    /// Import as `client_id` with one tick and the default depth limit
+    pub fn new(client_id: ClientID) -> Self {
+        Self {
+            client_id,
+            clock: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+/// What [`Document::import_json`] did to each path it imported, in path
+/// order
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Paths that held nothing before
+    pub created: Vec<FieldPath>,
+
+    /// Paths whose value changed
+    pub updated: Vec<FieldPath>,
+
+    /// Paths left alone: they already held the imported value, or a
+    /// newer write than the import's clock
+    pub skipped: Vec<FieldPath>,
+}
+
+impl ImportReport {
+    /// Whether the import changed nothing
+    pub fn is_unchanged(&self) -> bool {
+        self.created.is_empty() && self.updated.is_empty()
+    }
+}
+
+impl Document {
+    /// Write the fields of a JSON object into the document (see
+    /// [`crate::import`])
+    ///
+    /// Nested non-empty objects become dotted paths; arrays become list
+    /// fields; everything else, including `null` and `{}`, is stored as a
+    /// field. The import is all or nothing: it fails before changing
+    /// anything if `value` is not an object, if an object key contains a
+    /// `.`, or with [`SyncError::DepthLimitExceeded`] if it nests deeper
+    /// than `options.max_depth`. Observers receive the changes as one
+    /// batch.
+    pub fn import_json(
+        &mut self,
+        value: JsonValue,
+        options: &ImportOptions,
+    ) -> Result<ImportReport> {
+        let document_id = self.id.clone();
+        let JsonValue::Object(root) = value else {
+            return Err(
+                SyncKitError::invalid_input("only a JSON object can be imported")
+                    .with_document(document_id),
+            );
+        };
+        let mut leaves = Vec::new();
+        check_depth(&root, 1, options.max_depth, "")
+            .and_then(|()| flatten("", root, &mut leaves))
+            .map_err(|e| e.with_document(document_id.as_str()))?;
+
+        let client_id = &options.client_id;
+        let clock = options
+            .clock
+            .unwrap_or_else(|| self.version.get(client_id) + 1);
+        let mut report = self.transaction(|document| {
+            let mut report = ImportReport::default();
+            for (path, value) in leaves {
+                let existed = document.get_value(&path).is_some();
+                let changed = match value {
+                    JsonValue::Array(items) => document.import_list(&path, items, client_id)?,
+                    value if document.get_field(&path) == Some(&value) => false,
+                    value => {
+                        let timestamp = Timestamp::new(clock, client_id.clone());
+                        document.write_field(path.clone(), Field { value, timestamp })
+                    }
+                };
+                match (changed, existed) {
+                    (false, _) => report.skipped.push(path),
+                    (true, false) => report.created.push(path),
+                    (true, true) => report.updated.push(path),
+                }
+            }
+            Ok::<_, SyncKitError>(report)
+        })?;
+
+        if !report.is_unchanged() && self.version.get(client_id) < clock {
+            self.version.update(client_id, clock);
+            self.mark_version_dirty();
+        }
+        report.created.sort();
+        report.updated.sort();
+        report.skipped.sort();
+        Ok(report)
+    }
+
+    /// The document as a nested JSON tree: the inverse of
+    /// [`Document::import_json`]
+    ///
+    /// Dotted paths become nested objects and lists become arrays. Where
+    /// a path is also the parent of another path (`a` and `a.b`), the
+    /// nested fields win over a non-object value at the parent.
+    pub fn export_json(&self) -> JsonValue {
+        let mut paths: Vec<&FieldPath> = self.fields.keys().chain(self.lists().keys()).collect();
+        // A parent path sorts before the paths nested under it
+        paths.sort();
+        paths.dedup();
+
+        let mut root = JsonValue::Object(Map::new());
+        for path in paths {
+            if let Some(value) = self.get_value(path) {
+                insert_path(&mut root, path, value.to_json());
+            }
+        }
+        root
+    }
+
+    /// Make the list at `path` hold `items`, editing only what differs;
+    /// returns whether it changed
+    fn import_list(
+        &mut self,
+        path: &FieldPath,
+        items: Vec<JsonValue>,
+        client_id: &ClientID,
+    ) -> Result<bool> {
+        let current: Vec<JsonValue> = match self.list(path) {
+            Some(list) => list.iter().map(|(_, value)| value.clone()).collect(),
+            // An empty array still creates the (empty) list
+            None if items.is_empty() => return self.edit_list(path, |_| Ok(true)),
+            None => Vec::new(),
+        };
+        if current == items {
+            return Ok(false);
+        }
+
+        let imported = items.len();
+        let mut list = self.list_mut(path.clone(), client_id.clone());
+        for (index, item) in items.into_iter().enumerate() {
+            match current.get(index) {
+                Some(value) if *value == item => {}
+                Some(_) => list.set(index, item)?,
+                None => {
+                    list.push(item);
+                }
+            }
+        }
+        for index in (imported..current.len()).rev() {
+            list.remove(index)?;
+        }
+        Ok(true)
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Fail if a container inside `object`, at nesting `level`, is deeper
+/// than `max_depth`
+fn check_depth(
+    object: &Map<String, JsonValue>,
+    level: usize,
+    max_depth: usize,
+    path: &str,
+) -> Result<()> {
+    if level > max_depth {
+        return Err(SyncKitError::from(SyncError::DepthLimitExceeded(max_depth)).with_path(path));
+    }
+    for (key, value) in object {
+        match value {
+            JsonValue::Object(child) => check_depth(child, level + 1, max_depth, &join(path, key))?,
+            JsonValue::Array(items) => {
+                let path = join(path, key);
+                check_array_depth(items, level + 1, max_depth, &path)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn check_array_depth(
+    items: &[JsonValue],
+    level: usize,
+    max_depth: usize,
+    path: &str,
+) -> Result<()> {
+    if level > max_depth {
+        return Err(SyncKitError::from(SyncError::DepthLimitExceeded(max_depth)).with_path(path));
+    }
+    for (index, value) in items.iter().enumerate() {
+        let path = join(path, &index.to_string());
+        match value {
+            JsonValue::Object(child) => check_depth(child, level + 1, max_depth, &path)?,
+            JsonValue::Array(items) => check_array_depth(items, level + 1, max_depth, &path)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Collect the fields of `object` under `prefix`, with nested non-empty
+/// objects flattened
+fn flatten(
+    prefix: &str,
+    object: Map<String, JsonValue>,
+    leaves: &mut Vec<(FieldPath, JsonValue)>,
+) -> Result<()> {
+    for (key, value) in object {
+        let path = join(prefix, &key);
+        if key.contains('.') {
+            return Err(SyncKitError::invalid_input(format!(
+                "object key {:?} contains a '.'",
+                key
+            ))
+            .with_path(path));
+        }
+        match value {
+            JsonValue::Object(child) if !child.is_empty() => flatten(&path, child, leaves)?,
+            value => leaves.push((path, value)),
+        }
+    }
+    Ok(())
+}
+
+/// Set the value at a dotted path, turning non-objects on the way into
+/// objects
+fn insert_path(root: &mut JsonValue, path: &str, value: JsonValue) {
+    let mut node = root;
+    let mut segments = path.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        if !node.is_object() {
+            *node = JsonValue::Object(Map::new());
+        }
+        let JsonValue::Object(map) = node else {
+            unreachable!("just made an object");
+        };
+        if segments.peek().is_none() {
+            map.insert(segment.to_string(), value);
+            return;
+        }
+        node = map
+            .entry(segment)
+            .or_insert_with(|| JsonValue::Object(Map::new()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCategory;
+    use serde_json::json;
+
+    fn payload() -> JsonValue {
+        json!({
+            "title": "Quarterly report",
+            "draft": null,
+            "meta": {},
+            "tags": [],
+            "owner": {
+                "name": "Zoë",
+                "contact": {"email": "zoe@example.com", "phones": ["+1", {"kind": "work"}]}
+            },
+            "数据": {"ключ": "значение", "emoji 🎉": true},
+            "sections": [{"heading": "Intro", "body": null}, [1, 2.5, "three"]]
+        })
+    }
+
+    fn options() -> ImportOptions {
+        ImportOptions::new("seed".to_string())
+    }
+
+    #[test]
+    fn test_round_trip_keeps_the_tree() {
+        let mut document = Document::new("doc".to_string());
+        let report = document.import_json(payload(), &options()).unwrap();
+        assert_eq!(
+            report.created,
+            vec![
+                "draft",
+                "meta",
+                "owner.contact.email",
+                "owner.contact.phones",
+                "owner.name",
+                "sections",
+                "tags",
+                "title",
+                "数据.emoji 🎉",
+                "数据.ключ",
+            ]
+        );
+        assert!(document.list(&"tags".to_string()).unwrap().is_empty());
+        assert_eq!(document.export_json(), payload());
+
+        // Through serialization too
+        let bytes = serde_json::to_vec(&document).unwrap();
+        let restored: Document = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(restored.export_json(), payload());
+    }
+
+    #[test]
+    fn test_reimport_is_a_no_op() {
+        let mut document = Document::new("doc".to_string());
+        document.import_json(payload(), &options()).unwrap();
+        assert_eq!(document.version().get("seed"), 1);
+        document.take_dirty();
+
+        let report = document.import_json(payload(), &options()).unwrap();
+        assert!(report.is_unchanged());
+        assert_eq!(report.skipped.len(), 10);
+        assert!(!document.is_dirty());
+        assert_eq!(document.version().get("seed"), 1);
+    }
+
+    #[test]
+    fn test_replicas_importing_the_same_payload_are_identical() {
+        let options = ImportOptions {
+            clock: Some(7),
+            ..options()
+        };
+        let mut a = Document::new("doc".to_string());
+        let mut b = Document::new("doc".to_string());
+        a.import_json(payload(), &options).unwrap();
+        b.import_json(payload(), &options).unwrap();
+        assert_eq!(a.fields(), b.fields());
+        assert_eq!(a.lists(), b.lists());
+        assert_eq!(
+            a.fields()["title"].timestamp,
+            Timestamp::new(7, "seed".to_string())
+        );
+
+        assert_eq!(a.merge(&b), 0);
+    }
+
+    #[test]
+    fn test_changed_payload_reports_updates() {
+        let mut document = Document::new("doc".to_string());
+        document.import_json(payload(), &options()).unwrap();
+        document.set_field("title".to_string(), json!("Mine"), 10, "alice".to_string());
+
+        let mut changed = payload();
+        changed["title"] = json!("Annual report");
+        changed["owner"]["name"] = json!("Zoe");
+        changed["tags"] = json!(["q3"]);
+        changed["sections"] = json!([{"heading": "Intro", "body": null}]);
+        let report = document.import_json(changed.clone(), &options()).unwrap();
+        assert!(report.created.is_empty());
+        assert_eq!(report.updated, vec!["owner.name", "sections", "tags"]);
+        // Alice's write is newer than the import
+        assert!(report.skipped.contains(&"title".to_string()));
+        assert_eq!(document.version().get("seed"), 2);
+
+        changed["title"] = json!("Mine");
+        assert_eq!(document.export_json(), changed);
+    }
+
+    #[test]
+    fn test_rejects_bad_trees_without_changes() {
+        let mut document = Document::new("doc".to_string());
+        let options = ImportOptions {
+            max_depth: 4,
+            ..options()
+        };
+        assert!(document
+            .import_json(json!({"a": {"b": [1, {"c": 2}]}}), &options)
+            .is_ok());
+
+        let error = document
+            .import_json(json!({"x": 1, "a": {"b": [1, [{"c": 2}]]}}), &options)
+            .unwrap_err();
+        assert_eq!(error.code_name(), "DEPTH_LIMIT_EXCEEDED");
+        assert_eq!(error.category(), ErrorCategory::Validation);
+        assert_eq!(error.context().path.as_deref(), Some("a.b.1.0"));
+        assert_eq!(error.context().document_id.as_deref(), Some("doc"));
+
+        let error = document
+            .import_json(json!({"x": 1, "a": {"b.c": 2}}), &options)
+            .unwrap_err();
+        assert_eq!(error.code_name(), "INVALID_INPUT");
+        assert!(document
+            .import_json(json!(["not", "an", "object"]), &options)
+            .is_err());
+
+        assert_eq!(document.get_field(&"x".to_string()), None);
+    }
+
+    #[test]
+    fn test_export_prefers_nested_fields() {
+        let mut document = Document::new("doc".to_string());
+        document.set_field("a".to_string(), json!(1), 1, "c".to_string());
+        document.set_field("a.b".to_string(), json!(2), 1, "c".to_string());
+        document.set_field("c".to_string(), json!({"x": 1}), 1, "c".to_string());
+        document.set_field("c.y".to_string(), json!(2), 1, "c".to_string());
+        assert_eq!(
+            document.export_json(),
+            json!({"a": {"b": 2}, "c": {"x": 1, "y": 2}})
+        );
+    }
+}
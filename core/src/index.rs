@@ -0,0 +1,471 @@
+//! Full-text search over many documents
+//!
+//! A [`SearchIndex`] maps lowercased word tokens to where they occur: the
+//! document, the field (or list, or named text) and the token's position
+//! in it. Queries match every query word as a prefix, so "syn co" finds
+//! "SyncKit core" as the user types.
+//!
+//! The index is kept up to date incrementally. [`SearchIndex::update_document`]
+//! compares the document's fields with what was indexed, by timestamp, and
+//! re-tokenizes only those that changed, so calling it after every change
+//! (a local edit, a merge, an applied delta) costs about as much as the
+//! change. A `Workspace` (`server` feature) does this itself once
+//! `enable_search` is called.
+//!
+//! The index serializes as its per-field token lists; the postings are
+//! rebuilt from them on load, without tokenizing anything again.
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde_json::json;
+//! use synckit_core::index::SearchIndex;
+//! use synckit_core::Document;
+//!
+//! let mut notes = Document::new("notes".to_string());
+//! notes.set_field("title".to_string(), json!("Sync design"), 1, "alice".to_string());
+//! let mut todo = Document::new("todo".to_string());
+//! todo.set_field("title".to_string(), json!("Write the sync tests"), 1, "alice".to_string());
+//!
+//! let mut index = SearchIndex::new();
+//! index.update_document(&notes);
+//! index.update_document(&todo);
+//!
+//! let hits = index.search("sync des", 10);
+//! assert_eq!(hits.len(), 1);
+//! assert_eq!(hits[0].document_id, "notes");
+//! assert_eq!(hits[0].fields, vec!["title".to_string()]);
+//! ```
+
+use crate::document::{Document, Fnv1a};
+use crate::{DocumentID, FieldPath};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// One document matching a query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+    /// The matching document
+    pub document_id: DocumentID,
+
+    /// Higher is better: matching token occurrences, exact matches
+    /// counting double
+    pub score: u32,
+
+    /// Fields, lists and texts holding a match, sorted
+    pub fields: Vec<FieldPath>,
+}
+
+/// What the index holds of one field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexedField {
+    /// Identifies the indexed content: a hash of the field's timestamp,
+    /// or of the list or text contents
+    stamp: u64,
+
+    /// Positions of each token in the field
+    tokens: BTreeMap<String, Vec<u32>>,
+}
+
+/// Inverted index over the text of many documents (see the module docs)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "StoredIndex", into = "StoredIndex")]
+pub struct SearchIndex {
+    /// Token -> document -> field -> positions
+    postings: BTreeMap<String, HashMap<DocumentID, HashMap<FieldPath, Vec<u32>>>>,
+
+    /// Document -> field -> what was indexed
+    documents: HashMap<DocumentID, HashMap<FieldPath, IndexedField>>,
+
+    /// Fields tokenized since the index was created or loaded (not
+    /// persisted)
+    tokenized: u64,
+}
+
+/// Serialized form of a [`SearchIndex`]: the forward index only
+#[derive(Serialize, Deserialize)]
+struct StoredIndex {
+    documents: BTreeMap<DocumentID, BTreeMap<FieldPath, IndexedField>>,
+}
+
+impl From<StoredIndex> for SearchIndex {
+    fn from(stored: StoredIndex) -> Self {
+        let mut index = SearchIndex::new();
+        for (document_id, fields) in stored.documents {
+            for (path, field) in fields {
+                index.insert_field(&document_id, path, field);
+            }
+        }
+        index
+    }
+}
+
+impl From<SearchIndex> for StoredIndex {
+    fn from(index: SearchIndex) -> Self {
+        StoredIndex {
+            documents: index
+                .documents
+                .into_iter()
+                .map(|(id, fields)| (id, fields.into_iter().collect()))
+                .collect(),
+        }
+    }
+}
+
+impl SearchIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bring the index up to date with `document`
+    ///
+    /// Fields and lists whose contents changed since the last update are
+    /// re-tokenized, and those that are gone are dropped; named texts
+    /// (`update_text`) are kept. Returns how many were re-tokenized.
+    pub fn update_document(&mut self, document: &Document) -> usize {
+        let id = document.id();
+        let mut tokenized = 0;
+        for (path, field) in document.fields() {
+            let mut stamp = Fnv1a::new();
+            stamp.write(&field.timestamp.clock.to_le_bytes());
+            stamp.write(field.timestamp.client_id.as_bytes());
+            if self.set_field(id, path, stamp.finish(), || tokenize_value(&field.value)) {
+                tokenized += 1;
+            }
+        }
+        for (path, list) in document.lists() {
+            let value = list.to_json();
+            let mut stamp = Fnv1a::new();
+            stamp.write(value.to_string().as_bytes());
+            if self.set_field(id, path, stamp.finish(), || tokenize_value(&value)) {
+                tokenized += 1;
+            }
+        }
+
+        let gone: Vec<FieldPath> = self
+            .documents
+            .get(id)
+            .into_iter()
+            .flat_map(|fields| fields.keys())
+            .filter(|path| {
+                !path.starts_with(TEXT_PREFIX)
+                    && !document.fields().contains_key(*path)
+                    && document.list(path).is_none()
+            })
+            .cloned()
+            .collect();
+        for path in gone {
+            self.remove_field(id, &path);
+        }
+        tokenized
+    }
+
+    /// Index the text `name` of a document, replacing what was indexed
+    /// under that name; returns whether it changed
+    ///
+    /// A hit lists the text as `"text:<name>"`; fields whose path starts
+    /// with `text:` are indexed, but mistaken for named texts.
+    pub fn update_text(&mut self, document_id: &str, name: &str, text: &str) -> bool {
+        let mut stamp = Fnv1a::new();
+        stamp.write(text.as_bytes());
+        let path = format!("{}{}", TEXT_PREFIX, name);
+        self.set_field(document_id, &path, stamp.finish(), || tokenize(text))
+    }
+
+    /// Drop a document, named texts included
+    pub fn remove_document(&mut self, document_id: &str) {
+        let paths: Vec<FieldPath> = self
+            .documents
+            .get(document_id)
+            .map(|fields| fields.keys().cloned().collect())
+            .unwrap_or_default();
+        for path in paths {
+            self.remove_field(document_id, &path);
+        }
+    }
+
+    /// IDs of the indexed documents, sorted
+    pub fn document_ids(&self) -> Vec<&DocumentID> {
+        let mut ids: Vec<&DocumentID> = self.documents.keys().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Number of distinct tokens
+    pub fn token_count(&self) -> usize {
+        self.postings.len()
+    }
+
+    /// Fields and texts tokenized since the index was created or loaded
+    pub fn tokenized(&self) -> u64 {
+        self.tokenized
+    }
+
+    /// Documents holding every word of `query` (as a prefix), best first
+    ///
+    /// Ties are broken by document ID. An empty query matches nothing.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let words: BTreeSet<String> = tokenize(query).into_keys().collect();
+        let mut hits: Option<HashMap<&DocumentID, (u32, BTreeSet<&FieldPath>)>> = None;
+        for word in &words {
+            let mut matches: HashMap<&DocumentID, (u32, BTreeSet<&FieldPath>)> = HashMap::new();
+            let tokens = self
+                .postings
+                .range::<str, _>((
+                    std::ops::Bound::Included(word.as_str()),
+                    std::ops::Bound::Unbounded,
+                ))
+                .take_while(|(token, _)| token.starts_with(word.as_str()));
+            for (token, documents) in tokens {
+                let weight = if token == word { 2 } else { 1 };
+                for (document_id, fields) in documents {
+                    let (score, paths) = matches.entry(document_id).or_default();
+                    for (path, positions) in fields {
+                        *score += weight * positions.len() as u32;
+                        paths.insert(path);
+                    }
+                }
+            }
+            hits = Some(match hits {
+                None => matches,
+                Some(mut hits) => {
+                    hits.retain(|document_id, _| matches.contains_key(document_id));
+                    for (document_id, (score, paths)) in matches {
+                        if let Some(hit) = hits.get_mut(document_id) {
+                            hit.0 += score;
+                            hit.1.extend(paths);
+                        }
+                    }
+                    hits
+                }
+            });
+        }
+
+        let mut hits: Vec<SearchHit> = hits
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(document_id, (score, paths))| SearchHit {
+                document_id: document_id.clone(),
+                score,
+                fields: paths.into_iter().cloned().collect(),
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.document_id.cmp(&b.document_id))
+        });
+        hits.truncate(limit);
+        hits
+    }
+
+    /// Index a field unless it is indexed with the same stamp; returns
+    /// whether it was (re-)tokenized
+    fn set_field(
+        &mut self,
+        document_id: &str,
+        path: &FieldPath,
+        stamp: u64,
+        tokens: impl FnOnce() -> BTreeMap<String, Vec<u32>>,
+    ) -> bool {
+        let current = self
+            .documents
+            .get(document_id)
+            .and_then(|fields| fields.get(path));
+        if current.is_some_and(|field| field.stamp == stamp) {
+            return false;
+        }
+        self.remove_field(document_id, path);
+        let tokens = tokens();
+        self.tokenized += 1;
+        self.insert_field(document_id, path.clone(), IndexedField { stamp, tokens });
+        true
+    }
+
+    fn insert_field(&mut self, document_id: &str, path: FieldPath, field: IndexedField) {
+        for (token, positions) in &field.tokens {
+            self.postings
+                .entry(token.clone())
+                .or_default()
+                .entry(document_id.to_string())
+                .or_default()
+                .insert(path.clone(), positions.clone());
+        }
+        self.documents
+            .entry(document_id.to_string())
+            .or_default()
+            .insert(path, field);
+    }
+
+    fn remove_field(&mut self, document_id: &str, path: &FieldPath) {
+        let Some(fields) = self.documents.get_mut(document_id) else {
+            return;
+        };
+        let Some(field) = fields.remove(path) else {
+            return;
+        };
+        if fields.is_empty() {
+            self.documents.remove(document_id);
+        }
+        for token in field.tokens.keys() {
+            let Some(documents) = self.postings.get_mut(token) else {
+                continue;
+            };
+            if let Some(paths) = documents.get_mut(document_id) {
+                paths.remove(path);
+                if paths.is_empty() {
+                    documents.remove(document_id);
+                }
+            }
+            if documents.is_empty() {
+                self.postings.remove(token);
+            }
+        }
+    }
+}
+
+/// Prefix of the keys named texts are indexed under
+const TEXT_PREFIX: &str = "text:";
+
+/// Lowercased alphanumeric words of `text` and their positions
+fn tokenize(text: &str) -> BTreeMap<String, Vec<u32>> {
+    let mut tokens = BTreeMap::new();
+    let mut position = 0;
+    tokenize_into(text, &mut position, &mut tokens);
+    tokens
+}
+
+/// Tokens of every string in a JSON value, numbered across the value
+fn tokenize_value(value: &JsonValue) -> BTreeMap<String, Vec<u32>> {
+    fn walk(value: &JsonValue, position: &mut u32, tokens: &mut BTreeMap<String, Vec<u32>>) {
+        match value {
+            JsonValue::String(text) => tokenize_into(text, position, tokens),
+            JsonValue::Array(items) => {
+                for item in items {
+                    walk(item, position, tokens);
+                }
+            }
+            JsonValue::Object(entries) => {
+                for item in entries.values() {
+                    walk(item, position, tokens);
+                }
+            }
+            JsonValue::Null | JsonValue::Bool(_) | JsonValue::Number(_) => {}
+        }
+    }
+    let mut tokens = BTreeMap::new();
+    walk(value, &mut 0, &mut tokens);
+    tokens
+}
+
+fn tokenize_into(text: &str, position: &mut u32, tokens: &mut BTreeMap<String, Vec<u32>>) {
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        tokens
+            .entry(word.to_lowercase())
+            .or_default()
+            .push(*position);
+        *position += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn doc(id: &str, fields: &[(&str, JsonValue)]) -> Document {
+        let mut document = Document::new(id.to_string());
+        for (clock, (path, value)) in fields.iter().enumerate() {
+            document.set_field(
+                path.to_string(),
+                value.clone(),
+                clock as u64 + 1,
+                "alice".to_string(),
+            );
+        }
+        document
+    }
+
+    fn ids(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.document_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_prefix_queries_and_ranking() {
+        let mut index = SearchIndex::new();
+        index.update_document(&doc(
+            "a",
+            &[
+                ("title", json!("Sync sync SYNC")),
+                ("tags", json!(["rust"])),
+            ],
+        ));
+        index.update_document(&doc("b", &[("title", json!("Synchronous rust"))]));
+        index.update_document(&doc("c", &[("body", json!({"text": "nothing here"}))]));
+
+        // Exact matches count double
+        assert_eq!(ids(&index.search("sync", 10)), vec!["a", "b"]);
+        assert_eq!(index.search("sync", 10)[0].score, 6);
+        // Every word must match
+        let hits = index.search("syn RUST", 10);
+        assert_eq!(ids(&hits), vec!["a", "b"]);
+        assert_eq!(
+            hits[0].fields,
+            vec!["tags".to_string(), "title".to_string()]
+        );
+        assert_eq!(ids(&index.search("nothing", 10)), vec!["c"]);
+        assert_eq!(ids(&index.search("sync", 1)), vec!["a"]);
+        assert!(index.search("", 10).is_empty());
+        assert!(index.search("sync zebra", 10).is_empty());
+    }
+
+    #[test]
+    fn test_update_retokenizes_only_changes() {
+        let mut index = SearchIndex::new();
+        let mut document = doc(
+            "a",
+            &[("title", json!("old title")), ("body", json!("body"))],
+        );
+        assert_eq!(index.update_document(&document), 2);
+        assert_eq!(index.update_document(&document), 0);
+
+        document.set_field("title".to_string(), json!("new"), 10, "bob".to_string());
+        document.delete_field(&"body".to_string());
+        assert_eq!(index.update_document(&document), 1);
+        assert!(index.search("old", 10).is_empty());
+        assert!(index.search("body", 10).is_empty());
+        assert_eq!(ids(&index.search("new", 10)), vec!["a"]);
+        // Tokens nothing holds any more are gone
+        assert_eq!(index.token_count(), 1);
+
+        assert!(index.update_text("a", "notes", "Meeting notes"));
+        assert!(!index.update_text("a", "notes", "Meeting notes"));
+        index.update_document(&document);
+        assert_eq!(
+            index.search("meet", 10)[0].fields,
+            vec!["text:notes".to_string()]
+        );
+
+        index.remove_document("a");
+        assert_eq!(index.token_count(), 0);
+        assert!(index.document_ids().is_empty());
+    }
+
+    #[test]
+    fn test_survives_serialization_without_retokenizing() {
+        let mut index = SearchIndex::new();
+        let document = doc("a", &[("title", json!("Persisted index"))]);
+        index.update_document(&document);
+
+        let mut restored: SearchIndex =
+            serde_json::from_slice(&serde_json::to_vec(&index).unwrap()).unwrap();
+        assert_eq!(restored.search("pers", 10), index.search("pers", 10));
+        assert_eq!(restored.update_document(&document), 0);
+        assert_eq!(restored.tokenized(), 0);
+    }
+}
@@ -0,0 +1,250 @@
+//! Automerge import: migrate Automerge documents into SyncKit
+//!
+//! Loads an Automerge save file with the `automerge` crate and maps it onto
+//! SyncKit's data model:
+//!
+//! - **Root keys** become document fields. Each field's LWW timestamp is
+//!   synthesized from the winning Automerge op ID (`counter@actor`), so the
+//!   ordering Automerge used to pick the winner is preserved.
+//! - **Maps and lists** are materialized as JSON values.
+//! - **Text objects** are stored as strings in the document and, with the
+//!   `text-crdt` feature, also returned as `FugueText` replicas keyed by
+//!   field path.
+//! - **Conflicts** (concurrent writes Automerge kept as siblings) land in the
+//!   `_conflicts` field: an object mapping each conflicted path to the
+//!   losing values.
+//!
+//! Per-operation history is not carried over: the imported document starts
+//! with the merged state and a version vector holding each actor's max op.
+
+use crate::document::{Document, Field};
+use crate::error::{Result, ResultExt, SyncError};
+use crate::sync::{Timestamp, VectorClock};
+use crate::DocumentID;
+use automerge::{Automerge, ObjId as ExId, ObjType, ReadDoc, ScalarValue, Value, ROOT};
+use serde_json::{Map, Value as JsonValue};
+use std::collections::BTreeMap;
+
+#[cfg(feature = "text-crdt")]
+use crate::crdt::FugueText;
+#[cfg(feature = "text-crdt")]
+use crate::FieldPath;
+#[cfg(feature = "text-crdt")]
+use std::collections::HashMap;
+
+/// Field holding values that lost a concurrent write in Automerge
+pub const CONFLICTS_FIELD: &str = "_conflicts";
+
+/// Result of importing an Automerge document
+#[derive(Debug, Clone)]
+pub struct AutomergeImport {
+    /// Imported document (text objects materialized as strings)
+    pub document: Document,
+
+    /// Text objects as FugueText replicas, keyed by dotted field path
+    #[cfg(feature = "text-crdt")]
+    pub texts: HashMap<FieldPath, FugueText>,
+}
+
+impl Document {
+    /// Import a document from an Automerge save file
+    ///
+    /// See [`import_automerge`] to also get text objects as `FugueText`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SyncError::DeserializationError` (inside a `SyncKitError`
+    /// carrying the document id) if the bytes are not a valid Automerge
+    /// document
+    pub fn from_automerge(id: DocumentID, bytes: &[u8]) -> Result<Document> {
+        import_automerge(id, bytes).map(|import| import.document)
+    }
+}
+
+/// Import an Automerge save file
+///
+/// # Errors
+///
+/// Returns `SyncError::DeserializationError` (inside a `SyncKitError`
+/// carrying the document id) if the bytes are not a valid Automerge document
+pub fn import_automerge(id: DocumentID, bytes: &[u8]) -> Result<AutomergeImport> {
+    let doc = Automerge::load(bytes)
+        .map_err(|e| SyncError::DeserializationError(format!("Invalid Automerge document: {}", e)))
+        .with_document(id.as_str())?;
+
+    let mut importer = Importer {
+        doc: &doc,
+        conflicts: BTreeMap::new(),
+        #[cfg(feature = "text-crdt")]
+        texts: HashMap::new(),
+    };
+
+    let mut document = Document::new(id);
+    let mut version = VectorClock::new();
+
+    for key in doc.keys(ROOT) {
+        let Some((value, op_id)) = importer.get_with_conflicts(&ROOT, key.as_str(), &key)? else {
+            continue;
+        };
+        let value = importer.materialize(value, &op_id, &key)?;
+
+        let timestamp = op_timestamp(&op_id);
+        if timestamp.clock > version.get(&timestamp.client_id) {
+            version.update(&timestamp.client_id, timestamp.clock);
+        }
+        document.fields.insert(key, Field { value, timestamp });
+    }
+
+    if !importer.conflicts.is_empty() {
+        // Stamped after every imported op so it never loses to a real field
+        let max_clock = version.clocks.values().copied().max().unwrap_or(0);
+        let conflicts: Map<String, JsonValue> = importer
+            .conflicts
+            .into_iter()
+            .map(|(path, values)| (path, JsonValue::Array(values)))
+            .collect();
+
+        document.fields.insert(
+            CONFLICTS_FIELD.to_string(),
+            Field {
+                value: JsonValue::Object(conflicts),
+                timestamp: Timestamp::new(max_clock, "automerge-import".to_string()),
+            },
+        );
+    }
+
+    for change in doc.get_changes(&[]) {
+        let actor = change.actor_id().to_hex_string();
+        if change.max_op() > version.get(&actor) {
+            version.update(&actor, change.max_op());
+        }
+    }
+    document.version = version;
+
+    Ok(AutomergeImport {
+        document,
+        #[cfg(feature = "text-crdt")]
+        texts: importer.texts,
+    })
+}
+
+/// Synthesize an LWW timestamp from an Automerge op ID
+fn op_timestamp(op_id: &ExId) -> Timestamp {
+    match op_id {
+        ExId::Root => Timestamp::new(0, String::new()),
+        ExId::Id(counter, actor, _) => Timestamp::new(*counter, actor.to_hex_string()),
+    }
+}
+
+struct Importer<'a> {
+    doc: &'a Automerge,
+    conflicts: BTreeMap<String, Vec<JsonValue>>,
+    #[cfg(feature = "text-crdt")]
+    texts: HashMap<FieldPath, FugueText>,
+}
+
+impl Importer<'_> {
+    /// Get the winning value of a property and record any losing siblings
+    fn get_with_conflicts<P: Into<automerge::Prop>>(
+        &mut self,
+        obj: &ExId,
+        prop: P,
+        path: &str,
+    ) -> Result<Option<(Value<'static>, ExId)>> {
+        let all = self
+            .doc
+            .get_all(obj, prop)
+            .map_err(|e| SyncError::DeserializationError(e.to_string()))
+            .with_path(path)?;
+
+        // Automerge's winner is the value with the highest op ID (last)
+        let mut values: Vec<(Value<'static>, ExId)> = all
+            .into_iter()
+            .map(|(value, id)| (value.into_owned(), id))
+            .collect();
+        let Some(winner) = values.pop() else {
+            return Ok(None);
+        };
+
+        if !values.is_empty() {
+            let mut losers = Vec::with_capacity(values.len());
+            for (index, (value, id)) in values.into_iter().enumerate() {
+                let conflict_path = format!("{}.{}[{}]", CONFLICTS_FIELD, path, index);
+                losers.push(self.materialize(value, &id, &conflict_path)?);
+            }
+            self.conflicts.insert(path.to_string(), losers);
+        }
+
+        Ok(Some(winner))
+    }
+
+    /// Convert an Automerge value (recursively) to JSON
+    fn materialize(&mut self, value: Value<'_>, id: &ExId, path: &str) -> Result<JsonValue> {
+        match value {
+            Value::Scalar(scalar) => Ok(scalar_to_json(&scalar)),
+            Value::Object(ObjType::Map) | Value::Object(ObjType::Table) => {
+                let mut map = Map::new();
+                for key in self.doc.keys(id) {
+                    let child_path = format!("{}.{}", path, key);
+                    if let Some((child, child_id)) =
+                        self.get_with_conflicts(id, key.as_str(), &child_path)?
+                    {
+                        let child = self.materialize(child, &child_id, &child_path)?;
+                        map.insert(key, child);
+                    }
+                }
+                Ok(JsonValue::Object(map))
+            }
+            Value::Object(ObjType::List) => {
+                let mut items = Vec::new();
+                for index in 0..self.doc.length(id) {
+                    let child_path = format!("{}[{}]", path, index);
+                    if let Some((child, child_id)) =
+                        self.get_with_conflicts(id, index, &child_path)?
+                    {
+                        items.push(self.materialize(child, &child_id, &child_path)?);
+                    }
+                }
+                Ok(JsonValue::Array(items))
+            }
+            Value::Object(ObjType::Text) => {
+                let text = self
+                    .doc
+                    .text(id)
+                    .map_err(|e| SyncError::DeserializationError(e.to_string()))
+                    .with_path(path)?;
+
+                #[cfg(feature = "text-crdt")]
+                {
+                    let client_id = op_timestamp(id).client_id;
+                    let mut fugue = FugueText::new(client_id);
+                    fugue
+                        .insert(0, &text)
+                        .map_err(|e| {
+                            SyncError::DeserializationError(format!("Text import failed: {}", e))
+                        })
+                        .with_path(path)?;
+                    self.texts.insert(path.to_string(), fugue);
+                }
+
+                Ok(JsonValue::String(text))
+            }
+        }
+    }
+}
+
+fn scalar_to_json(scalar: &ScalarValue) -> JsonValue {
+    match scalar {
+        ScalarValue::Str(s) => JsonValue::String(s.to_string()),
+        ScalarValue::Int(n) => JsonValue::from(*n),
+        ScalarValue::Uint(n) => JsonValue::from(*n),
+        ScalarValue::F64(n) => serde_json::Number::from_f64(*n)
+            .map(JsonValue::Number)
+            .unwrap_or(JsonValue::Null),
+        ScalarValue::Counter(counter) => JsonValue::from(i64::from(counter)),
+        ScalarValue::Timestamp(millis) => JsonValue::from(*millis),
+        ScalarValue::Boolean(b) => JsonValue::Bool(*b),
+        ScalarValue::Bytes(bytes) => JsonValue::from(bytes.clone()),
+        ScalarValue::Null | ScalarValue::Unknown { .. } => JsonValue::Null,
+    }
+}
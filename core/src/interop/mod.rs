@@ -0,0 +1,10 @@
+//! Interop with other CRDT libraries
+//!
+//! Importers for migrating existing data into SyncKit. Each format lives
+//! behind its own feature flag so the dependency is opt-in.
+
+#[cfg(feature = "automerge-interop")]
+pub mod automerge;
+
+#[cfg(feature = "automerge-interop")]
+pub use self::automerge::{import_automerge, AutomergeImport, CONFLICTS_FIELD};
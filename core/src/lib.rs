@@ -5,11 +5,12 @@
 //! - Document structure with field-level LWW
 //! - Vector clocks for causality tracking
 //! - CRDT data structures (OR-Set, PN-Counter, Text)
-//! - Binary protocol encoding/decoding (when prost feature enabled)
+//! - Binary protocol encoding/decoding (`protocol-binary` feature)
 //!
 //! # Examples
 //!
 //! ```rust
+//! # #[cfg(feature = "std")] {
 //! use synckit_core::{Document, ClientID, VectorClock};
 //!
 //! let mut doc = Document::new("doc-123".to_string());
@@ -19,21 +20,99 @@
 //!     1,
 //!     "client-1".to_string()
 //! );
+//! # }
 //! ```
+//!
+//! # no_std kernel
+//!
+//! With `--no-default-features --features alloc-kernel` the crate builds
+//! without std (only `alloc`) and exposes the merge layer: [`sync`]'s
+//! clocks, timestamps and LWW fields, the PN-Counter and OR-Set, and the
+//! Fugue block metadata and ordering in [`crdt::text_fugue`]. All of it
+//! serializes through serde, so postcard's `alloc` mode works for storage.
+//! `Document`, rope-backed `FugueText`, storage and the protocol stack
+//! need the `std` feature, which every other feature turns on.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(not(any(feature = "std", feature = "alloc-kernel")))]
+compile_error!("synckit-core needs the `std` feature or the `alloc-kernel` feature");
+
+// Features that need threads or a native host would build for wasm32 and
+// then fail at runtime
+#[cfg(all(target_arch = "wasm32", feature = "parallel"))]
+compile_error!("the `parallel` feature needs threads, which wasm32 doesn't have");
+
+#[cfg(all(target_arch = "wasm32", feature = "server"))]
+compile_error!(
+    "the `server` feature (and `grpc`, `redis-fanout`, `testserver` on top of it) is native-only"
+);
+
+#[cfg(all(target_arch = "wasm32", any(feature = "ffi", feature = "python")))]
+compile_error!("the `ffi` and `python` bindings are native-only; use the `wasm` feature on wasm32");
 
 // Use wee_alloc when building for WASM with core-lite feature (size optimization)
 #[cfg(all(target_arch = "wasm32", feature = "wee_alloc"))]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+#[cfg(feature = "std")]
+#[macro_use]
+mod trace;
+
+mod collections;
+
+#[cfg(feature = "std")]
+pub mod annotations;
+#[cfg(feature = "std")]
 pub mod awareness;
+pub mod codec;
+#[cfg(feature = "std")]
+pub mod concurrent;
+#[cfg(feature = "std")]
+pub mod conflicts;
+#[cfg(feature = "std")]
 pub mod document;
+#[cfg(feature = "std")]
 pub mod error;
+#[cfg(feature = "std")]
+pub mod import;
+#[cfg(feature = "std")]
+pub mod index;
+#[cfg(feature = "std")]
+pub mod list;
+#[cfg(feature = "std")]
+pub mod locks;
+#[cfg(feature = "std")]
+pub mod memory;
+#[cfg(feature = "std")]
+pub mod merge_job;
+#[cfg(feature = "std")]
+pub mod migrate;
+#[cfg(feature = "std")]
+pub mod model;
+#[cfg(feature = "std")]
+pub mod notify;
+#[cfg(feature = "std")]
+pub mod ops_jsonl;
+#[cfg(feature = "std")]
+pub mod refs;
+#[cfg(feature = "std")]
+pub mod register;
+#[cfg(feature = "std")]
 pub mod storage;
 pub mod sync;
+#[cfg(feature = "std")]
+pub mod time;
+#[cfg(feature = "std")]
+pub mod undo;
+#[cfg(feature = "std")]
+pub mod value_store;
 
-// Protocol module only included if prost feature is enabled
-#[cfg(feature = "prost")]
+// Protocol module only included with the protocol-binary feature
+#[cfg(feature = "protocol-binary")]
 pub mod protocol;
 
 // CRDTs are feature-gated (only compile if needed)
@@ -41,18 +120,49 @@ pub mod protocol;
     feature = "text-crdt",
     feature = "counters",
     feature = "sets",
-    feature = "fractional-index"
+    feature = "fractional-index",
+    feature = "alloc-kernel"
 ))]
 pub mod crdt;
 
+#[cfg(feature = "automerge-interop")]
+pub mod interop;
+
 #[cfg(feature = "wasm")]
 pub mod wasm;
 
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "testing")]
+pub mod sim;
+
+#[cfg(feature = "parallel")]
+pub mod parallel;
+
+#[cfg(feature = "server")]
+pub mod server;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
 // Re-exports for convenience
-pub use awareness::{Awareness, AwarenessState, AwarenessUpdate};
-pub use document::Document;
-pub use error::{Result, SyncError};
+#[cfg(feature = "std")]
+pub use awareness::{
+    Awareness, AwarenessDiff, AwarenessEvent, AwarenessState, AwarenessUpdate, AwarenessVersion,
+};
+#[cfg(feature = "std")]
+pub use document::{DirtyState, Document, FieldValue};
+#[cfg(feature = "std")]
+pub use error::{ErrorCategory, ErrorKind, Result, ResultExt, SyncError, SyncKitError};
 pub use sync::{Timestamp, VectorClock};
+#[cfg(feature = "std")]
+pub use undo::{UndoManager, UndoStep, UndoTarget};
+
+use alloc::string::String;
 
 /// Client identifier type
 pub type ClientID = String;
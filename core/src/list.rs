@@ -0,0 +1,722 @@
+//! List fields: an ordered CRDT list with stable item ids
+//!
+//! An LWW array drops one side of concurrent appends. A [`List`] keeps
+//! every item instead, and orders them deterministically on every replica.
+//!
+//! # Algorithm
+//!
+//! Items sit in *slots*, ordered like RGA: each slot is created right
+//! after another one (or at the start), and slots created after the same
+//! one are ordered newest first. An item is identified by the slot it was
+//! inserted in ([`ListId`]). Moving it creates a new slot at the target
+//! and assigns the item to it; an item always shows at the newest slot
+//! assigned to it, so concurrent moves of one item resolve to a single
+//! position instead of duplicating it.
+//!
+//! Values are last-writer-wins per item; removal is final (a removed item
+//! stays removed even if a concurrent move or write reaches it).
+//!
+//! Merging unions the slots and items, so it is commutative, associative
+//! and idempotent. Deltas ship only the operations the receiver lacks
+//! ([`List::ops_since`]).
+
+use crate::document::Document;
+use crate::error::{Result, SyncError, SyncKitError};
+use crate::memory::HeapSize;
+use crate::{ClientID, FieldPath};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Identifier of a list slot, and of the item inserted in it
+///
+/// Ordered by clock, then client, which is also the order in which
+/// concurrent writes to an item win.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ListId {
+    /// Lamport clock of the list when the slot was created
+    pub clock: u64,
+
+    /// Client that created it
+    pub client_id: ClientID,
+}
+
+impl fmt::Display for ListId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.client_id, self.clock)
+    }
+}
+
+/// A position in the list, holding `item` unless a newer slot does
+#[derive(Debug, Clone, PartialEq)]
+struct Slot {
+    after: Option<ListId>,
+    item: ListId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Item {
+    value: JsonValue,
+    /// The write that set `value` (LWW)
+    set_at: ListId,
+    removed: bool,
+}
+
+/// An operation on a list, as shipped in a `DocumentDelta`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ListOp {
+    /// A new item, in the slot `id` after `after` (None: at the start)
+    Insert {
+        id: ListId,
+        after: Option<ListId>,
+        #[serde(with = "crate::codec::json_value")]
+        value: JsonValue,
+        set_at: ListId,
+    },
+
+    /// `item` moved to the new slot `slot` after `after`
+    Move {
+        slot: ListId,
+        after: Option<ListId>,
+        item: ListId,
+    },
+
+    /// `item` now holds `value`
+    Set {
+        item: ListId,
+        #[serde(with = "crate::codec::json_value")]
+        value: JsonValue,
+        set_at: ListId,
+    },
+
+    /// `item` was removed
+    Remove { item: ListId },
+}
+
+/// Ordered list CRDT with stable item ids (see the module docs)
+///
+/// Edit it through [`Document::list_mut`]; `List` itself only offers
+/// reads, merging and operation-based sync.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredList", into = "StoredList")]
+pub struct List {
+    slots: BTreeMap<ListId, Slot>,
+    items: BTreeMap<ListId, Item>,
+    /// Highest clock seen
+    clock: u64,
+    /// Visible items in order, with the slot each one shows at
+    order: Vec<(ListId, ListId)>,
+}
+
+impl List {
+    /// Create an empty list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of visible items
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Check if no item is visible
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Value of the item at `index`
+    pub fn get(&self, index: usize) -> Option<&JsonValue> {
+        let (item, _) = self.order.get(index)?;
+        Some(&self.items[item].value)
+    }
+
+    /// Id of the item at `index`
+    pub fn id_at(&self, index: usize) -> Option<&ListId> {
+        self.order.get(index).map(|(item, _)| item)
+    }
+
+    /// Current index of an item, or None if it was removed or is unknown
+    pub fn index_of(&self, item: &ListId) -> Option<usize> {
+        self.order.iter().position(|(id, _)| id == item)
+    }
+
+    /// Visible items in order, with their ids
+    pub fn iter(&self) -> impl Iterator<Item = (&ListId, &JsonValue)> + '_ {
+        self.order
+            .iter()
+            .map(|(item, _)| (item, &self.items[item].value))
+    }
+
+    /// The visible values as a JSON array
+    pub fn to_json(&self) -> JsonValue {
+        JsonValue::Array(self.iter().map(|(_, value)| value.clone()).collect())
+    }
+
+    /// Number that grows with every edit or merge that changes the list
+    ///
+    /// Inserts, moves and sets raise the clock, and merges only ever add
+    /// slots and items; removals add to the count of removed items.
+    #[cfg(feature = "protocol-binary")]
+    pub(crate) fn revision(&self) -> u64 {
+        let removed = self.items.values().filter(|item| item.removed).count();
+        self.clock + (self.slots.len() + self.items.len() + removed) as u64
+    }
+
+    /// Merge another replica of this list
+    ///
+    /// Returns true if anything changed, visible or not.
+    pub fn merge(&mut self, other: &List) -> bool {
+        let mut changed = false;
+        for (id, slot) in &other.slots {
+            if !self.slots.contains_key(id) {
+                self.slots.insert(id.clone(), slot.clone());
+                changed = true;
+            }
+        }
+        for (id, remote) in &other.items {
+            match self.items.get_mut(id) {
+                None => {
+                    self.items.insert(id.clone(), remote.clone());
+                    changed = true;
+                }
+                Some(local) => {
+                    if remote.set_at > local.set_at {
+                        local.value = remote.value.clone();
+                        local.set_at = remote.set_at.clone();
+                        changed = true;
+                    }
+                    if remote.removed && !local.removed {
+                        local.removed = true;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        self.clock = self.clock.max(other.clock);
+        if changed {
+            self.rebuild();
+        }
+        changed
+    }
+
+    /// Operations that bring `base` (None: an empty list) up to this list
+    ///
+    /// Slots come first, oldest first, so every anchor precedes its use.
+    pub fn ops_since(&self, base: Option<&List>) -> Vec<ListOp> {
+        let empty = List::new();
+        let base = base.unwrap_or(&empty);
+        let mut ops = Vec::new();
+
+        for (id, slot) in &self.slots {
+            if base.slots.contains_key(id) {
+                continue;
+            }
+            if *id == slot.item {
+                let item = &self.items[id];
+                ops.push(ListOp::Insert {
+                    id: id.clone(),
+                    after: slot.after.clone(),
+                    value: item.value.clone(),
+                    set_at: item.set_at.clone(),
+                });
+            } else {
+                ops.push(ListOp::Move {
+                    slot: id.clone(),
+                    after: slot.after.clone(),
+                    item: slot.item.clone(),
+                });
+            }
+        }
+
+        for (id, item) in &self.items {
+            let known = base.items.get(id);
+            if let Some(known) = known {
+                if item.set_at > known.set_at {
+                    ops.push(ListOp::Set {
+                        item: id.clone(),
+                        value: item.value.clone(),
+                        set_at: item.set_at.clone(),
+                    });
+                }
+            }
+            if item.removed && !known.is_some_and(|known| known.removed) {
+                ops.push(ListOp::Remove { item: id.clone() });
+            }
+        }
+        ops
+    }
+
+    /// Apply operations from `ops_since` on another replica
+    ///
+    /// All or nothing: an operation referring to a slot or item this list
+    /// doesn't have (and no earlier operation created) is a
+    /// `PROTOCOL_ERROR`, and leaves the list unchanged. Returns true if
+    /// anything changed.
+    pub fn apply_ops(&mut self, ops: &[ListOp]) -> Result<bool> {
+        let mut next = self.clone();
+        for op in ops {
+            next.apply_op(op)?;
+        }
+        next.rebuild();
+        let changed = next != *self;
+        *self = next;
+        Ok(changed)
+    }
+
+    fn apply_op(&mut self, op: &ListOp) -> Result<()> {
+        match op {
+            ListOp::Insert {
+                id,
+                after,
+                value,
+                set_at,
+            } => {
+                self.check_slot(after.as_ref())?;
+                self.observe(id);
+                self.observe(set_at);
+                if !self.slots.contains_key(id) {
+                    self.slots.insert(
+                        id.clone(),
+                        Slot {
+                            after: after.clone(),
+                            item: id.clone(),
+                        },
+                    );
+                }
+                self.write(id, value, set_at, true);
+            }
+            ListOp::Move { slot, after, item } => {
+                self.check_slot(after.as_ref())?;
+                self.check_item(item)?;
+                self.observe(slot);
+                self.slots.entry(slot.clone()).or_insert_with(|| Slot {
+                    after: after.clone(),
+                    item: item.clone(),
+                });
+            }
+            ListOp::Set {
+                item,
+                value,
+                set_at,
+            } => {
+                self.check_item(item)?;
+                self.observe(set_at);
+                self.write(item, value, set_at, false);
+            }
+            ListOp::Remove { item } => {
+                self.check_item(item)?;
+                if let Some(item) = self.items.get_mut(item) {
+                    item.removed = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Set an item's value if `set_at` is newer, creating it if `create`
+    fn write(&mut self, id: &ListId, value: &JsonValue, set_at: &ListId, create: bool) {
+        match self.items.get_mut(id) {
+            Some(item) if *set_at > item.set_at => {
+                item.value = value.clone();
+                item.set_at = set_at.clone();
+            }
+            Some(_) => {}
+            None if create => {
+                self.items.insert(
+                    id.clone(),
+                    Item {
+                        value: value.clone(),
+                        set_at: set_at.clone(),
+                        removed: false,
+                    },
+                );
+            }
+            None => {}
+        }
+    }
+
+    fn check_slot(&self, slot: Option<&ListId>) -> Result<()> {
+        match slot {
+            Some(slot) if !self.slots.contains_key(slot) => Err(SyncError::Protocol(format!(
+                "list operation after unknown slot {}",
+                slot
+            ))
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_item(&self, item: &ListId) -> Result<()> {
+        if self.items.contains_key(item) {
+            Ok(())
+        } else {
+            Err(SyncError::Protocol(format!("list operation on unknown item {}", item)).into())
+        }
+    }
+
+    fn observe(&mut self, id: &ListId) {
+        self.clock = self.clock.max(id.clock);
+    }
+
+    fn next_id(&mut self, client_id: &str) -> ListId {
+        self.clock += 1;
+        ListId {
+            clock: self.clock,
+            client_id: client_id.to_string(),
+        }
+    }
+
+    /// Index `index` in `0..=len` (or `0..len` unless `end_ok`)
+    fn check_index(&self, index: usize, end_ok: bool) -> Result<()> {
+        let len = self.len();
+        if index < len || (end_ok && index == len) {
+            Ok(())
+        } else {
+            Err(SyncKitError::invalid_input(format!(
+                "list index {} out of bounds (length {})",
+                index, len
+            ))
+            .with_position(index))
+        }
+    }
+
+    fn insert(&mut self, index: usize, value: JsonValue, client_id: &str) -> Result<ListId> {
+        self.check_index(index, true)?;
+        let after = index
+            .checked_sub(1)
+            .map(|previous| self.order[previous].1.clone());
+        let id = self.next_id(client_id);
+        self.slots.insert(
+            id.clone(),
+            Slot {
+                after,
+                item: id.clone(),
+            },
+        );
+        self.items.insert(
+            id.clone(),
+            Item {
+                value,
+                set_at: id.clone(),
+                removed: false,
+            },
+        );
+        self.rebuild();
+        Ok(id)
+    }
+
+    fn move_item(&mut self, from: usize, to: usize, client_id: &str) -> Result<()> {
+        self.check_index(from, false)?;
+        self.check_index(to, false)?;
+        if from == to {
+            return Ok(());
+        }
+        let (item, _) = self.order[from].clone();
+        // Anchor at the item that ends up just before it
+        let previous = if to > from {
+            Some(to)
+        } else {
+            to.checked_sub(1)
+        };
+        let after = previous.map(|previous| self.order[previous].1.clone());
+        let slot = self.next_id(client_id);
+        self.slots.insert(slot, Slot { after, item });
+        self.rebuild();
+        Ok(())
+    }
+
+    fn remove(&mut self, index: usize) -> Result<ListId> {
+        self.check_index(index, false)?;
+        let item = self.order[index].0.clone();
+        if let Some(removed) = self.items.get_mut(&item) {
+            removed.removed = true;
+        }
+        self.rebuild();
+        Ok(item)
+    }
+
+    fn set(&mut self, index: usize, value: JsonValue, client_id: &str) -> Result<()> {
+        self.check_index(index, false)?;
+        let item = self.order[index].0.clone();
+        let set_at = self.next_id(client_id);
+        self.write(&item, &value, &set_at, false);
+        Ok(())
+    }
+
+    /// Recompute `order` from the slots
+    fn rebuild(&mut self) {
+        // Slots ascend, so each item ends up at its newest slot
+        let mut current: HashMap<&ListId, &ListId> = HashMap::new();
+        let mut children: HashMap<Option<&ListId>, Vec<&ListId>> = HashMap::new();
+        for (id, slot) in &self.slots {
+            current.insert(&slot.item, id);
+            children.entry(slot.after.as_ref()).or_default().push(id);
+        }
+
+        // Pre-order walk, newest sibling first: children are pushed
+        // oldest first, so the newest is popped first
+        let mut order = Vec::with_capacity(self.items.len());
+        let mut stack: Vec<&ListId> = children.get(&None).cloned().unwrap_or_default();
+        while let Some(id) = stack.pop() {
+            let item = &self.slots[id].item;
+            if current.get(item) == Some(&id) && !self.items[item].removed {
+                order.push((item.clone(), id.clone()));
+            }
+            if let Some(next) = children.get(&Some(id)) {
+                stack.extend(next);
+            }
+        }
+        self.order = order;
+    }
+}
+
+/// Editing handle for a list field (see [`Document::list_mut`])
+///
+/// Each edit is a separate change: it marks the field dirty and notifies
+/// the document's subscribers with the whole list. The first successful
+/// edit creates the list.
+pub struct ListMut<'a> {
+    document: &'a mut Document,
+    path: FieldPath,
+    client_id: ClientID,
+}
+
+impl<'a> ListMut<'a> {
+    pub(crate) fn new(document: &'a mut Document, path: FieldPath, client_id: ClientID) -> Self {
+        Self {
+            document,
+            path,
+            client_id,
+        }
+    }
+
+    /// Append `value`, returning the new item's id
+    pub fn push(&mut self, value: JsonValue) -> ListId {
+        self.edit(|list, client_id| list.insert(list.len(), value, client_id))
+            .expect("the end of the list is a valid index")
+    }
+
+    /// Insert `value` so that it ends up at `index` (at most the length)
+    pub fn insert(&mut self, index: usize, value: JsonValue) -> Result<ListId> {
+        self.edit(|list, client_id| list.insert(index, value, client_id))
+    }
+
+    /// Move the item at `from` so that it ends up at `to`
+    pub fn move_item(&mut self, from: usize, to: usize) -> Result<()> {
+        self.edit(|list, client_id| list.move_item(from, to, client_id))
+    }
+
+    /// Remove the item at `index`, returning its id
+    pub fn remove(&mut self, index: usize) -> Result<ListId> {
+        self.edit(|list, _| list.remove(index))
+    }
+
+    /// Replace the value of the item at `index`
+    pub fn set(&mut self, index: usize, value: JsonValue) -> Result<()> {
+        self.edit(|list, client_id| list.set(index, value, client_id))
+    }
+
+    fn edit<R>(&mut self, f: impl FnOnce(&mut List, &str) -> Result<R>) -> Result<R> {
+        let client_id = &self.client_id;
+        self.document
+            .edit_list(&self.path, |list| f(list, client_id))
+    }
+}
+
+/// How a `List` is serialized (JSON maps need string keys)
+#[derive(Serialize, Deserialize)]
+struct StoredList {
+    slots: Vec<StoredSlot>,
+    items: Vec<StoredItem>,
+    clock: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredSlot {
+    id: ListId,
+    after: Option<ListId>,
+    item: ListId,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredItem {
+    id: ListId,
+    #[serde(with = "crate::codec::json_value")]
+    value: JsonValue,
+    set_at: ListId,
+    removed: bool,
+}
+
+impl From<List> for StoredList {
+    fn from(list: List) -> Self {
+        Self {
+            slots: list
+                .slots
+                .into_iter()
+                .map(|(id, slot)| StoredSlot {
+                    id,
+                    after: slot.after,
+                    item: slot.item,
+                })
+                .collect(),
+            items: list
+                .items
+                .into_iter()
+                .map(|(id, item)| StoredItem {
+                    id,
+                    value: item.value,
+                    set_at: item.set_at,
+                    removed: item.removed,
+                })
+                .collect(),
+            clock: list.clock,
+        }
+    }
+}
+
+impl From<StoredList> for List {
+    fn from(stored: StoredList) -> Self {
+        let mut list = List {
+            slots: stored
+                .slots
+                .into_iter()
+                .map(|slot| {
+                    let id = slot.id;
+                    let slot = Slot {
+                        after: slot.after,
+                        item: slot.item,
+                    };
+                    (id, slot)
+                })
+                .collect(),
+            items: stored
+                .items
+                .into_iter()
+                .map(|item| {
+                    let stored = Item {
+                        value: item.value,
+                        set_at: item.set_at,
+                        removed: item.removed,
+                    };
+                    (item.id, stored)
+                })
+                .collect(),
+            clock: stored.clock,
+            order: Vec::new(),
+        };
+        list.rebuild();
+        list
+    }
+}
+
+impl HeapSize for ListId {
+    fn heap_size(&self) -> usize {
+        self.client_id.heap_size()
+    }
+}
+
+impl HeapSize for Slot {
+    fn heap_size(&self) -> usize {
+        self.after.heap_size() + self.item.heap_size()
+    }
+}
+
+impl HeapSize for Item {
+    fn heap_size(&self) -> usize {
+        self.value.heap_size() + self.set_at.heap_size()
+    }
+}
+
+impl HeapSize for List {
+    fn heap_size(&self) -> usize {
+        self.slots.heap_size() + self.items.heap_size() + self.order.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn values(list: &List) -> Vec<JsonValue> {
+        list.iter().map(|(_, value)| value.clone()).collect()
+    }
+
+    #[test]
+    fn test_index_edits() {
+        let mut list = List::new();
+        for value in ["a", "b", "c", "d"] {
+            let len = list.len();
+            list.insert(len, json!(value), "alice").unwrap();
+        }
+        list.insert(0, json!("start"), "alice").unwrap();
+        assert_eq!(values(&list), vec!["start", "a", "b", "c", "d"]);
+
+        list.move_item(1, 3, "alice").unwrap();
+        assert_eq!(values(&list), vec!["start", "b", "c", "a", "d"]);
+        list.move_item(4, 0, "alice").unwrap();
+        assert_eq!(values(&list), vec!["d", "start", "b", "c", "a"]);
+
+        let removed = list.remove(1).unwrap();
+        list.set(0, json!("D"), "alice").unwrap();
+        assert_eq!(list.to_json(), json!(["D", "b", "c", "a"]));
+        assert_eq!(list.index_of(&removed), None);
+        assert!(list.insert(5, json!("x"), "alice").is_err());
+        assert!(list.move_item(0, 4, "alice").is_err());
+    }
+
+    #[test]
+    fn test_concurrent_moves_of_one_item_keep_one_copy() {
+        let mut alice = List::new();
+        for value in ["a", "b", "c"] {
+            let len = alice.len();
+            alice.insert(len, json!(value), "alice").unwrap();
+        }
+        let mut bob = alice.clone();
+        alice.move_item(0, 2, "alice").unwrap();
+        bob.move_item(0, 1, "bob").unwrap();
+
+        let mut merged = alice.clone();
+        merged.merge(&bob);
+        bob.merge(&alice);
+        assert_eq!(merged, bob);
+        assert_eq!(merged.len(), 3);
+    }
+
+    #[test]
+    fn test_ops_since_rebuild_the_list() {
+        let mut base = List::new();
+        base.insert(0, json!("a"), "alice").unwrap();
+        let mut list = base.clone();
+        list.insert(1, json!("b"), "bob").unwrap();
+        list.move_item(1, 0, "bob").unwrap();
+        list.set(1, json!("A"), "bob").unwrap();
+        list.remove(0).unwrap();
+
+        let ops = list.ops_since(Some(&base));
+        assert_eq!(ops.len(), 4);
+        assert!(base.apply_ops(&ops).unwrap());
+        assert_eq!(base, list);
+        assert!(!base.apply_ops(&ops).unwrap());
+
+        // An op whose anchor never arrived changes nothing
+        let mut empty = List::new();
+        let error = empty.apply_ops(&ops[1..]).unwrap_err();
+        assert_eq!(error.code_name(), "PROTOCOL_ERROR");
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut list = List::new();
+        list.insert(0, json!({"title": "milk"}), "alice").unwrap();
+        list.insert(0, json!({"title": "eggs"}), "bob").unwrap();
+        list.remove(1).unwrap();
+        let json = serde_json::to_string(&list).unwrap();
+        let loaded: List = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, list);
+        assert_eq!(loaded.to_json(), json!([{"title": "eggs"}]));
+    }
+}
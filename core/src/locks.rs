@@ -0,0 +1,262 @@
+//! Advisory locks: soft, expiring claims on document paths
+//!
+//! Some workflows want to show "Alice is editing the title" and keep
+//! others from starting a competing edit, without a server to arbitrate.
+//! [`AdvisoryLocks`] holds one lease per path. A lease names its holder
+//! and an expiry time; merging keeps, per path, the lease ranked highest
+//! by (expiry, holder, released), so replicas that both acquired a path
+//! concurrently settle on the same winner whichever merge runs first.
+//!
+//! Locks are advisory: nothing stops a write to a locked path. A lease
+//! ends when its holder releases it or when it expires, so a holder that
+//! goes offline can't keep a path locked forever. Expiry uses wall-clock
+//! time, so replicas whose clocks disagree may briefly disagree on
+//! whether a lease has expired; they never disagree on who holds it.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use synckit_core::locks::AdvisoryLocks;
+//!
+//! let mut alice = AdvisoryLocks::default();
+//! let mut bob = AdvisoryLocks::default();
+//! let title = "title".to_string();
+//! assert!(alice.acquire(&title, "alice", Duration::from_secs(30), 1_000));
+//! assert!(bob.acquire(&title, "bob", Duration::from_secs(30), 1_000));
+//!
+//! // Same expiry: the higher holder id wins on both sides
+//! let snapshot = alice.clone();
+//! alice.merge(&bob);
+//! bob.merge(&snapshot);
+//! assert_eq!(alice.holder_of(&title, 2_000), Some("bob"));
+//! assert_eq!(bob.holder_of(&title, 2_000), Some("bob"));
+//!
+//! // Expired leases hold nothing
+//! assert_eq!(alice.holder_of(&title, 31_000), None);
+//! ```
+
+use crate::memory::HeapSize;
+use crate::FieldPath;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// One holder's claim on a path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    /// Who holds (or held) the path
+    pub holder: String,
+
+    /// When the lease ends, in milliseconds since the Unix epoch
+    pub expires_ms: u64,
+
+    /// Whether the holder released it before it expired
+    #[serde(default)]
+    pub released: bool,
+}
+
+impl Lease {
+    /// Whether the lease holds the path at `now_ms`
+    pub fn is_held(&self, now_ms: u64) -> bool {
+        !self.released && now_ms < self.expires_ms
+    }
+
+    /// Merge order: the higher rank wins
+    fn rank(&self) -> (u64, &str, bool) {
+        (self.expires_ms, &self.holder, self.released)
+    }
+}
+
+/// Advisory locks on the paths of a document
+///
+/// A state-based CRDT: [`AdvisoryLocks::merge`] is commutative,
+/// associative and idempotent.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AdvisoryLocks {
+    leases: BTreeMap<FieldPath, Lease>,
+}
+
+impl AdvisoryLocks {
+    /// Lock `path` for `holder` until `ttl` after `now_ms`
+    ///
+    /// Returns false, changing nothing, if someone else holds the path.
+    /// Acquiring a path the holder already has renews the lease; a lease
+    /// is never shortened.
+    pub fn acquire(&mut self, path: &FieldPath, holder: &str, ttl: Duration, now_ms: u64) -> bool {
+        let expires_ms = now_ms.saturating_add(ttl.as_millis() as u64);
+        let expires_ms = match self.leases.get(path) {
+            Some(lease) if lease.is_held(now_ms) && lease.holder != holder => return false,
+            Some(lease) if lease.is_held(now_ms) => expires_ms.max(lease.expires_ms),
+            // Outrank the old lease so the new one survives merges with
+            // replicas that still have it
+            Some(lease) => expires_ms.max(lease.expires_ms.saturating_add(1)),
+            None => expires_ms,
+        };
+        self.leases.insert(
+            path.clone(),
+            Lease {
+                holder: holder.to_string(),
+                expires_ms,
+                released: false,
+            },
+        );
+        true
+    }
+
+    /// Release `holder`'s lease on `path`
+    ///
+    /// Returns false, changing nothing, unless `holder` has the current
+    /// lease on the path.
+    pub fn release(&mut self, path: &FieldPath, holder: &str) -> bool {
+        match self.leases.get_mut(path) {
+            Some(lease) if lease.holder == holder && !lease.released => {
+                lease.released = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Who holds `path` at `now_ms`, if anyone
+    pub fn holder_of(&self, path: &FieldPath, now_ms: u64) -> Option<&str> {
+        self.leases
+            .get(path)
+            .filter(|lease| lease.is_held(now_ms))
+            .map(|lease| lease.holder.as_str())
+    }
+
+    /// The current lease on `path`, held or not
+    pub fn lease(&self, path: &FieldPath) -> Option<&Lease> {
+        self.leases.get(path)
+    }
+
+    /// Check if no path was ever locked
+    pub fn is_empty(&self) -> bool {
+        self.leases.is_empty()
+    }
+
+    /// Merge a remote replica's locks; returns the paths whose lease
+    /// changed, in path order
+    pub fn merge(&mut self, remote: &AdvisoryLocks) -> Vec<FieldPath> {
+        let mut changed = Vec::new();
+        for (path, remote_lease) in &remote.leases {
+            let wins = self
+                .leases
+                .get(path)
+                .is_none_or(|local| remote_lease.rank() > local.rank());
+            if wins {
+                self.leases.insert(path.clone(), remote_lease.clone());
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+impl HeapSize for Lease {
+    fn heap_size(&self) -> usize {
+        self.holder.heap_size()
+    }
+}
+
+impl HeapSize for AdvisoryLocks {
+    fn heap_size(&self) -> usize {
+        self.leases.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(10);
+
+    fn title() -> FieldPath {
+        "title".to_string()
+    }
+
+    #[test]
+    fn test_concurrent_acquire_converges_in_both_orders() {
+        let mut alice = AdvisoryLocks::default();
+        let mut bob = AdvisoryLocks::default();
+        assert!(alice.acquire(&title(), "alice", TTL, 1_000));
+        assert!(bob.acquire(&title(), "bob", TTL, 1_500));
+
+        let mut alice_first = alice.clone();
+        alice_first.merge(&bob);
+        let mut bob_first = bob.clone();
+        bob_first.merge(&alice);
+        assert_eq!(alice_first, bob_first);
+
+        // The later expiry wins, and the loser can no longer acquire
+        assert_eq!(alice_first.holder_of(&title(), 2_000), Some("bob"));
+        assert!(!alice_first.acquire(&title(), "alice", TTL, 2_000));
+
+        // Merging again changes nothing
+        assert!(alice_first.merge(&bob_first).is_empty());
+        assert!(bob_first.merge(&alice).is_empty());
+    }
+
+    #[test]
+    fn test_equal_expiry_is_won_by_higher_holder() {
+        let mut alice = AdvisoryLocks::default();
+        let mut bob = AdvisoryLocks::default();
+        alice.acquire(&title(), "alice", TTL, 1_000);
+        bob.acquire(&title(), "bob", TTL, 1_000);
+
+        assert_eq!(alice.merge(&bob), vec![title()]);
+        assert!(bob.merge(&alice).is_empty());
+        assert_eq!(alice.holder_of(&title(), 1_000), Some("bob"));
+        assert_eq!(bob.holder_of(&title(), 1_000), Some("bob"));
+    }
+
+    #[test]
+    fn test_lease_expires() {
+        let mut locks = AdvisoryLocks::default();
+        assert!(locks.acquire(&title(), "alice", TTL, 1_000));
+        assert_eq!(locks.holder_of(&title(), 10_999), Some("alice"));
+        assert_eq!(locks.holder_of(&title(), 11_000), None);
+
+        // Anyone may take an expired lock, and the new lease outranks
+        // the old one on replicas that still have it
+        let stale = locks.clone();
+        assert!(locks.acquire(&title(), "bob", Duration::ZERO, 11_000));
+        assert_eq!(locks.holder_of(&title(), 11_000), Some("bob"));
+        let mut replica = stale.clone();
+        replica.merge(&locks);
+        assert_eq!(replica, locks);
+    }
+
+    #[test]
+    fn test_renewal_extends_lease() {
+        let mut locks = AdvisoryLocks::default();
+        locks.acquire(&title(), "alice", TTL, 1_000);
+        assert!(locks.acquire(&title(), "alice", TTL, 5_000));
+        assert_eq!(locks.lease(&title()).unwrap().expires_ms, 15_000);
+
+        // A shorter renewal doesn't shorten it
+        assert!(locks.acquire(&title(), "alice", Duration::ZERO, 6_000));
+        assert_eq!(locks.lease(&title()).unwrap().expires_ms, 15_000);
+    }
+
+    #[test]
+    fn test_release_by_non_holder_is_ignored() {
+        let mut locks = AdvisoryLocks::default();
+        locks.acquire(&title(), "alice", TTL, 1_000);
+        assert!(!locks.release(&title(), "bob"));
+        assert!(!locks.release(&"body".to_string(), "alice"));
+        assert_eq!(locks.holder_of(&title(), 1_000), Some("alice"));
+
+        let mut replica = locks.clone();
+        assert!(locks.release(&title(), "alice"));
+        assert!(!locks.release(&title(), "alice"));
+        assert_eq!(locks.holder_of(&title(), 1_000), None);
+
+        // The release reaches replicas that still see the lease held
+        assert_eq!(replica.merge(&locks), vec![title()]);
+        assert_eq!(replica.holder_of(&title(), 1_000), None);
+        assert!(replica.acquire(&title(), "bob", TTL, 2_000));
+    }
+}
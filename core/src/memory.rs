@@ -0,0 +1,253 @@
+//! Approximate heap use, by subsystem
+//!
+//! [`Document::memory_usage`](crate::Document::memory_usage) and
+//! `FugueText::memory_usage` add up what each part of their state holds
+//! on the heap: collection capacity times entry size, plus the strings and
+//! JSON values inside. The figures are estimates: allocator overhead and
+//! tree node slack aren't counted, and state shared between clones is
+//! counted by each clone. They are meant for watching growth over time and
+//! pinning it on a subsystem, not for exact accounting.
+
+use crate::sync::{Timestamp, VectorClock};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem::size_of;
+use std::sync::Arc;
+
+/// Estimated heap bytes per subsystem of a document or text
+///
+/// Some reports also carry counters that explain the figures (how many
+/// blocks a text holds, how often it was re-chunked). Serializes as
+/// `{"subsystems": {name: bytes}}`, with `"counters": {name: count}` added
+/// when there are any.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryUsage {
+    subsystems: BTreeMap<&'static str, usize>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    counters: BTreeMap<&'static str, u64>,
+}
+
+impl MemoryUsage {
+    /// An empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `bytes` to a subsystem
+    pub(crate) fn record(&mut self, subsystem: &'static str, bytes: usize) {
+        *self.subsystems.entry(subsystem).or_default() += bytes;
+    }
+
+    /// Add `count` to a counter
+    pub(crate) fn count(&mut self, counter: &'static str, count: u64) {
+        *self.counters.entry(counter).or_default() += count;
+    }
+
+    /// Estimated bytes of one subsystem (0 if it isn't reported)
+    pub fn get(&self, subsystem: &str) -> usize {
+        self.subsystems.get(subsystem).copied().unwrap_or_default()
+    }
+
+    /// Estimated bytes of every subsystem together
+    pub fn total(&self) -> usize {
+        self.subsystems.values().sum()
+    }
+
+    /// Each subsystem and its estimated bytes, by name
+    pub fn subsystems(&self) -> impl Iterator<Item = (&'static str, usize)> + '_ {
+        self.subsystems.iter().map(|(name, bytes)| (*name, *bytes))
+    }
+
+    /// One counter (0 if it isn't reported)
+    pub fn counter(&self, counter: &str) -> u64 {
+        self.counters.get(counter).copied().unwrap_or_default()
+    }
+
+    /// Each counter and its value, by name
+    pub fn counters(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        self.counters.iter().map(|(name, count)| (*name, *count))
+    }
+
+    /// Add another report's figures to this one, subsystem by subsystem
+    /// and counter by counter
+    pub fn add(&mut self, other: &MemoryUsage) {
+        for (subsystem, bytes) in other.subsystems() {
+            self.record(subsystem, bytes);
+        }
+        for (counter, count) in other.counters() {
+            self.count(counter, count);
+        }
+    }
+}
+
+/// How much a document holds against its fields, from
+/// [`Document::memory_stats`](crate::Document::memory_stats)
+///
+/// Byte figures are estimates, like those of [`MemoryUsage`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DocumentMemoryStats {
+    /// Fields (LWW), not counting lists, multi-value fields and refs
+    pub field_count: usize,
+
+    /// Bytes of the fields' JSON values
+    pub value_bytes: usize,
+
+    /// Bytes of everything else: field paths and timestamps, the
+    /// version, lists, registers, refs, annotations and the rest
+    pub metadata_bytes: usize,
+
+    /// All bytes per field (0 for a document without fields)
+    pub bytes_per_field: f64,
+}
+
+/// Bytes a value owns on the heap, beyond its own `size_of`
+pub(crate) trait HeapSize {
+    fn heap_size(&self) -> usize;
+}
+
+macro_rules! no_heap {
+    ($($ty:ty),*) => {
+        $(impl HeapSize for $ty {
+            fn heap_size(&self) -> usize {
+                0
+            }
+        })*
+    };
+}
+
+no_heap!(bool, u8, u32, u64, usize, i64, f64);
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for JsonValue {
+    fn heap_size(&self) -> usize {
+        match self {
+            JsonValue::String(s) => s.capacity(),
+            JsonValue::Array(items) => items.heap_size(),
+            JsonValue::Object(entries) => entries
+                .iter()
+                .map(|(key, value)| {
+                    size_of::<String>()
+                        + size_of::<JsonValue>()
+                        + key.heap_size()
+                        + value.heap_size()
+                })
+                .sum(),
+            JsonValue::Null | JsonValue::Bool(_) | JsonValue::Number(_) => 0,
+        }
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Arc<T> {
+    fn heap_size(&self) -> usize {
+        size_of::<T>() + T::heap_size(self)
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<K: HeapSize, V: HeapSize, S> HeapSize for HashMap<K, V, S> {
+    fn heap_size(&self) -> usize {
+        // One control byte per bucket
+        self.capacity() * (size_of::<K>() + size_of::<V>() + 1)
+            + self
+                .iter()
+                .map(|(key, value)| key.heap_size() + value.heap_size())
+                .sum::<usize>()
+    }
+}
+
+impl<T: HeapSize, S> HeapSize for HashSet<T, S> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * (size_of::<T>() + 1) + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<K: HeapSize, V: HeapSize> HeapSize for BTreeMap<K, V> {
+    fn heap_size(&self) -> usize {
+        self.iter()
+            .map(|(key, value)| {
+                size_of::<K>() + size_of::<V>() + key.heap_size() + value.heap_size()
+            })
+            .sum()
+    }
+}
+
+impl HeapSize for Timestamp {
+    fn heap_size(&self) -> usize {
+        self.client_id.heap_size()
+    }
+}
+
+impl HeapSize for VectorClock {
+    fn heap_size(&self) -> usize {
+        self.clocks.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_report_adds_up() {
+        let mut usage = MemoryUsage::new();
+        usage.record("fields", 100);
+        usage.record("lists", 20);
+        usage.record("fields", 5);
+        let mut other = MemoryUsage::new();
+        other.record("lists", 1);
+        usage.add(&other);
+
+        assert_eq!(usage.get("fields"), 105);
+        assert_eq!(usage.get("lists"), 21);
+        assert_eq!(usage.get("refs"), 0);
+        assert_eq!(usage.total(), 126);
+        assert_eq!(
+            serde_json::to_value(&usage).unwrap(),
+            json!({"subsystems": {"fields": 105, "lists": 21}})
+        );
+
+        other.count("blocks", 3);
+        usage.add(&other);
+        assert_eq!(usage.counter("blocks"), 3);
+        assert_eq!(
+            serde_json::to_value(&usage).unwrap()["counters"],
+            json!({"blocks": 3})
+        );
+    }
+
+    #[test]
+    fn test_estimates_grow_with_content() {
+        let small = json!({"a": "x"});
+        let large = json!({"a": "x".repeat(1000), "b": [1, 2, 3]});
+        assert!(large.heap_size() >= small.heap_size() + 1000);
+
+        let mut clock = VectorClock::new();
+        let empty = clock.heap_size();
+        clock.update("a-long-client-identifier", 1);
+        assert!(clock.heap_size() > empty + "a-long-client-identifier".len());
+    }
+}
@@ -0,0 +1,287 @@
+//! Merging a large remote replica in bounded slices
+//!
+//! `merge` on a big replica (the initial sync of a multi-megabyte
+//! document) runs for as long as it takes, which freezes the UI when it
+//! runs on the main thread, as in WASM. A merge job does the same work a
+//! bit at a time instead: `start_merge` returns a job, and each
+//! `run_for` call processes up to a [`MergeBudget`] of it, returning
+//! [`MergeProgress`] so the caller can yield to the event loop in
+//! between.
+//!
+//! - [`MergeJob`] merges into a [`Document`] field by field. Every field
+//!   merge is a complete LWW merge, so the document stays consistent and
+//!   editable between slices; it just holds some of the remote fields
+//!   already.
+//! - `TextMergeJob` (`text-crdt` feature) merges into a private copy of
+//!   the text and swaps the result in when done, replaying local edits
+//!   made in the meantime, so the text never shows a half-merged state.
+//!
+//! A sliced merge ends in exactly the state `merge` would have produced.
+
+use crate::annotations::Annotations;
+use crate::document::{Document, Field};
+use crate::list::List;
+use crate::locks::AdvisoryLocks;
+use crate::refs::Ref;
+use crate::register::MVRegister;
+use crate::sync::VectorClock;
+use crate::time::{SharedTime, TimeProvider};
+use crate::FieldPath;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map;
+
+/// How much of a merge job one `run_for` call may do
+///
+/// A slice always makes progress, so even a zero budget processes one
+/// step. Some steps can't be divided (the final rope rebuild of a text
+/// merge), so a slice may overrun a time budget by one step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeBudget {
+    /// Process at most this many steps (fields or blocks)
+    Ops(usize),
+
+    /// Stop once this many milliseconds have passed, measured with the
+    /// replica's `time_provider`
+    Millis(u64),
+}
+
+/// How far a merge job got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeProgress {
+    /// Steps done so far
+    pub done: usize,
+
+    /// Steps in the whole job (an estimate until `complete`)
+    pub total: usize,
+
+    /// Whether the merge is finished and the result visible
+    pub complete: bool,
+}
+
+/// One `run_for` call's share of the budget
+#[derive(Debug)]
+pub(crate) struct Slice {
+    budget: MergeBudget,
+    time: SharedTime,
+    started_ms: u64,
+    ops: usize,
+}
+
+impl Slice {
+    pub(crate) fn new(budget: MergeBudget, time: SharedTime) -> Self {
+        let started_ms = match budget {
+            MergeBudget::Millis(_) => time.now_ms(),
+            MergeBudget::Ops(_) => 0,
+        };
+        Self {
+            budget,
+            time,
+            started_ms,
+            ops: 0,
+        }
+    }
+
+    /// Whether the slice may take another step
+    pub(crate) fn has_budget(&self) -> bool {
+        if self.ops == 0 {
+            return true;
+        }
+        match self.budget {
+            MergeBudget::Ops(max) => self.ops < max,
+            MergeBudget::Millis(max) => self.time.now_ms().saturating_sub(self.started_ms) < max,
+        }
+    }
+
+    /// Count a step taken
+    pub(crate) fn spend(&mut self) {
+        self.ops += 1;
+    }
+}
+
+/// A [`Document::merge`] in progress, from [`Document::start_merge`]
+///
+/// Remote fields are merged first, then lists, then advisory locks,
+/// annotations and the version vector together, so the document only claims the remote
+/// version once it holds everything the remote had. Each field or list
+/// is one step.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+/// use synckit_core::merge_job::MergeBudget;
+/// use synckit_core::Document;
+///
+/// let mut local = Document::new("doc-1".to_string());
+/// let mut remote = Document::new("doc-1".to_string());
+/// for i in 0..10 {
+///     remote.set_field(format!("f{}", i), json!(i), 1, "remote".to_string());
+/// }
+///
+/// let mut job = local.start_merge(remote);
+/// while !job.run_for(&mut local, MergeBudget::Ops(4)).complete {
+///     // yield to the event loop
+/// }
+/// assert_eq!(local.fields().len(), 10);
+/// assert_eq!(job.updated(), 10);
+/// ```
+#[derive(Debug)]
+pub struct MergeJob {
+    fields: hash_map::IntoIter<FieldPath, Field>,
+    lists: hash_map::IntoIter<FieldPath, List>,
+    registers: hash_map::IntoIter<FieldPath, MVRegister>,
+    refs: hash_map::IntoIter<FieldPath, Ref>,
+    locks: AdvisoryLocks,
+    annotations: Annotations,
+    version: Option<VectorClock>,
+    done: usize,
+    total: usize,
+    updated: usize,
+}
+
+impl MergeJob {
+    pub(crate) fn new(remote: Document) -> Self {
+        let (fields, lists, registers, refs, locks, annotations, version) = remote.into_parts();
+        let total = fields.len() + lists.len() + registers.len() + refs.len() + 1;
+        Self {
+            fields: fields.into_iter(),
+            lists: lists.into_iter(),
+            registers: registers.into_iter(),
+            refs: refs.into_iter(),
+            locks,
+            annotations,
+            version: Some(version),
+            done: 0,
+            total,
+            updated: 0,
+        }
+    }
+
+    /// Merge the next slice into `document`, the one the job was started
+    /// on
+    ///
+    /// Changes made by a slice are delivered to observers as one batch.
+    /// Once `complete`, further calls do nothing.
+    pub fn run_for(&mut self, document: &mut Document, budget: MergeBudget) -> MergeProgress {
+        let mut slice = Slice::new(budget, document.time_provider());
+        document.transaction(|document| {
+            while slice.has_budget() {
+                if let Some((path, field)) = self.fields.next() {
+                    if document.merge_field(path, field) {
+                        self.updated += 1;
+                    }
+                } else if let Some((path, list)) = self.lists.next() {
+                    if document.merge_list(&path, &list) {
+                        self.updated += 1;
+                    }
+                } else if let Some((path, register)) = self.registers.next() {
+                    if document.merge_register(&path, &register) {
+                        self.updated += 1;
+                    }
+                } else if let Some((path, link)) = self.refs.next() {
+                    if document.merge_ref(&path, &link) {
+                        self.updated += 1;
+                    }
+                } else if let Some(version) = self.version.take() {
+                    document.merge_locks(&self.locks);
+                    document.merge_annotations(&self.annotations);
+                    document.merge_version(&version);
+                } else {
+                    break;
+                }
+                self.done += 1;
+                slice.spend();
+            }
+        });
+        self.progress()
+    }
+
+    /// How far the job got
+    pub fn progress(&self) -> MergeProgress {
+        MergeProgress {
+            done: self.done,
+            total: self.total,
+            complete: self.version.is_none(),
+        }
+    }
+
+    /// Fields, lists and multi-value fields updated so far (what `merge` returns)
+    pub fn updated(&self) -> usize {
+        self.updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn replica(client: &str, fields: usize) -> Document {
+        let mut document = Document::new("doc-1".to_string());
+        for i in 0..fields {
+            document.set_field(
+                format!("field{}", i),
+                json!(format!("{} {}", client, i)),
+                1 + (i as u64 % 3),
+                client.to_string(),
+            );
+        }
+        document
+            .list_mut("items".to_string(), client.to_string())
+            .push(json!(client));
+        document.version.update(client, fields as u64);
+        document
+    }
+
+    #[test]
+    fn test_sliced_merge_matches_merge() {
+        let local = replica("alice", 40);
+        let remote = replica("bob", 60);
+        let mut monolithic = local.clone();
+        let updated = monolithic.merge(&remote);
+
+        let mut sliced = local.clone();
+        let mut job = sliced.start_merge(remote);
+        let mut slices = 0;
+        loop {
+            let progress = job.run_for(&mut sliced, MergeBudget::Ops(16));
+            slices += 1;
+            if progress.complete {
+                assert_eq!(progress.done, progress.total);
+                break;
+            }
+            // The remote version is only claimed at the end
+            assert_eq!(sliced.version(), local.version());
+        }
+
+        assert_eq!(slices, 62usize.div_ceil(16));
+        assert_eq!(job.updated(), updated);
+        assert_eq!(sliced.to_json(), monolithic.to_json());
+        assert_eq!(sliced.version(), monolithic.version());
+    }
+
+    #[test]
+    fn test_local_edit_mid_job_is_kept() {
+        let mut local = replica("alice", 10);
+        let remote = replica("bob", 10);
+        let mut job = local.start_merge(remote);
+        job.run_for(&mut local, MergeBudget::Ops(3));
+
+        local.set_field(
+            "field5".to_string(),
+            json!("typed"),
+            100,
+            "alice".to_string(),
+        );
+        while !job.run_for(&mut local, MergeBudget::Ops(3)).complete {}
+
+        assert_eq!(
+            local.get_field(&"field5".to_string()),
+            Some(&json!("typed"))
+        );
+        assert_eq!(
+            local.get_field(&"field4".to_string()),
+            Some(&json!("bob 4"))
+        );
+    }
+}
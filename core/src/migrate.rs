@@ -0,0 +1,301 @@
+//! Converters between persisted state formats
+//!
+//! Every CRDT type persists as its serde JSON (see [`crate::codec`]).
+//! Most changes to a persisted type are additive and load through
+//! `#[serde(default)]`. When a change isn't, [`FORMAT_VERSION`] goes up and
+//! a converter here rewrites a state saved in the previous format into the
+//! new one, as JSON, before it is deserialized. Converters chain, so a
+//! state saved in any earlier format upgrades step by step.
+//!
+//! Store states in a [`VersionedState`] when they may outlive the crate
+//! version that wrote them.
+//!
+//! # Format history
+//!
+//! - **1**: documents hold every field value inline and nothing but their
+//!   fields and version
+//! - **2**: documents share repeated values through a `values` table
+//!   (fields point into it with `value_ref`) and carry lists, multi-value
+//!   fields, refs, locks and annotations
+//! - **3**: documents carry their conflict inbox (see
+//!   [`crate::conflicts`])
+//! - **4**: texts carry their formatting marks (see
+//!   `crdt::text_fugue::Marks`)
+//! - **5**: text blocks carry the stamp of their deletion (see
+//!   `crdt::text_fugue::FugueBlock::deleted_at`)
+//!
+//! # Compatibility corpus
+//!
+//! `tests/fixtures/state/` holds a state of every type in every format,
+//! written by `cargo run --bin gen-fixtures`. `tests/fixture_compat.rs`
+//! loads each one through [`upgrade`] and checks it re-serializes to
+//! exactly the current format, so changing how a type serializes without
+//! bumping the version and adding its converter fails the suite.
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde_json::json;
+//! use synckit_core::migrate::{StateKind, VersionedState};
+//! use synckit_core::Document;
+//!
+//! // A document saved by a release using format 1
+//! let saved = VersionedState {
+//!     kind: StateKind::Document,
+//!     format: 1,
+//!     state: json!({
+//!         "id": "doc-1",
+//!         "fields": {
+//!             "title": {"value": "Hello", "timestamp": {"clock": 1, "client_id": "a"}}
+//!         },
+//!         "version": {"clocks": {"a": 1}}
+//!     }),
+//! };
+//! let doc: Document = saved.load().unwrap();
+//! assert_eq!(doc.get_field(&"title".to_string()), Some(&json!("Hello")));
+//! ```
+
+use crate::error::{Result, SyncKitError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value as JsonValue};
+
+/// Format the types of this crate serialize in
+pub const FORMAT_VERSION: u32 = 5;
+
+/// Type of a persisted state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateKind {
+    /// [`crate::Document`]
+    Document,
+
+    /// [`crate::VectorClock`]
+    VectorClock,
+
+    /// [`crate::sync::LWWField`]
+    LwwField,
+
+    /// [`crate::register::MVRegister`]
+    MvRegister,
+
+    /// [`crate::list::List`]
+    List,
+
+    /// `crdt::PNCounter`
+    PnCounter,
+
+    /// `crdt::ORSet` of strings
+    OrSet,
+
+    /// `crdt::FugueText`
+    FugueText,
+
+    /// A sequence of `crdt::FractionalIndex` positions
+    FractionalIndex,
+}
+
+impl StateKind {
+    /// Every kind, in declaration order
+    pub const ALL: [StateKind; 9] = [
+        StateKind::Document,
+        StateKind::VectorClock,
+        StateKind::LwwField,
+        StateKind::MvRegister,
+        StateKind::List,
+        StateKind::PnCounter,
+        StateKind::OrSet,
+        StateKind::FugueText,
+        StateKind::FractionalIndex,
+    ];
+
+    /// Stable name, as stored in a [`VersionedState`]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StateKind::Document => "document",
+            StateKind::VectorClock => "vector_clock",
+            StateKind::LwwField => "lww_field",
+            StateKind::MvRegister => "mv_register",
+            StateKind::List => "list",
+            StateKind::PnCounter => "pn_counter",
+            StateKind::OrSet => "or_set",
+            StateKind::FugueText => "fugue_text",
+            StateKind::FractionalIndex => "fractional_index",
+        }
+    }
+}
+
+/// A serialized state tagged with its type and the format it was saved in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionedState {
+    /// Type of the state
+    pub kind: StateKind,
+
+    /// [`FORMAT_VERSION`] of the release that saved it
+    pub format: u32,
+
+    /// The state's serde JSON
+    pub state: JsonValue,
+}
+
+impl VersionedState {
+    /// Save `value` in the current format
+    pub fn new<T: Serialize>(kind: StateKind, value: &T) -> Result<Self> {
+        Ok(Self {
+            kind,
+            format: FORMAT_VERSION,
+            state: serde_json::to_value(value).map_err(SyncKitError::serialization)?,
+        })
+    }
+
+    /// The state in the current format
+    ///
+    /// # Errors
+    ///
+    /// See [`upgrade`]
+    pub fn into_current(self) -> Result<JsonValue> {
+        upgrade(self.kind, self.format, self.state)
+    }
+
+    /// Upgrade the state to the current format and deserialize it
+    ///
+    /// # Errors
+    ///
+    /// See [`upgrade`]; also fails if the state isn't a valid `T`
+    pub fn load<T: DeserializeOwned>(self) -> Result<T> {
+        serde_json::from_value(self.into_current()?).map_err(SyncKitError::deserialization)
+    }
+}
+
+/// Convert a `kind` state saved in `format` to the current format
+///
+/// # Errors
+///
+/// Returns `SyncError::DeserializationError` for a format this release
+/// doesn't know (0, or newer than [`FORMAT_VERSION`]) and for a state a
+/// converter can't read
+pub fn upgrade(kind: StateKind, format: u32, state: JsonValue) -> Result<JsonValue> {
+    if format == 0 || format > FORMAT_VERSION {
+        return Err(SyncKitError::deserialization(format!(
+            "{} state is in format {}, this release reads formats 1 to {}",
+            kind.as_str(),
+            format,
+            FORMAT_VERSION
+        )));
+    }
+    let mut state = state;
+    for from in format..FORMAT_VERSION {
+        state = match (kind, from) {
+            (StateKind::Document, 1) => document_v1_to_v2(state)?,
+            (StateKind::Document, 2) => document_v2_to_v3(state)?,
+            (StateKind::FugueText, 3) => fugue_text_v3_to_v4(state)?,
+            (StateKind::FugueText, 4) => fugue_text_v4_to_v5(state)?,
+            _ => state,
+        };
+    }
+    Ok(state)
+}
+
+/// Give a format 1 document the sections format 2 always writes
+///
+/// Values stay inline (`value_ref` null), which format 2 reads as is.
+fn document_v1_to_v2(state: JsonValue) -> Result<JsonValue> {
+    let JsonValue::Object(mut document) = state else {
+        return Err(SyncKitError::deserialization(
+            "format 1 document is not a JSON object",
+        ));
+    };
+    if let Some(JsonValue::Object(fields)) = document.get_mut("fields") {
+        for field in fields.values_mut() {
+            if let JsonValue::Object(field) = field {
+                field.entry("value_ref").or_insert(JsonValue::Null);
+            }
+        }
+    }
+    for section in ["values", "lists", "registers", "refs", "locks"] {
+        document
+            .entry(section)
+            .or_insert_with(|| JsonValue::Object(Map::new()));
+    }
+    document
+        .entry("annotations")
+        .or_insert_with(|| json!({"entries": [], "horizon_ms": 0}));
+    Ok(JsonValue::Object(document))
+}
+
+/// Give a format 2 document an empty conflict inbox
+fn document_v2_to_v3(state: JsonValue) -> Result<JsonValue> {
+    let JsonValue::Object(mut document) = state else {
+        return Err(SyncKitError::deserialization(
+            "format 2 document is not a JSON object",
+        ));
+    };
+    document
+        .entry("conflicts")
+        .or_insert_with(|| json!({"entries": [], "limit": 100, "resolved": {}}));
+    Ok(JsonValue::Object(document))
+}
+
+/// Give a format 3 text an empty set of marks
+fn fugue_text_v3_to_v4(state: JsonValue) -> Result<JsonValue> {
+    let JsonValue::Object(mut text) = state else {
+        return Err(SyncKitError::deserialization(
+            "format 3 text is not a JSON object",
+        ));
+    };
+    text.entry("marks").or_insert_with(|| json!([]));
+    Ok(JsonValue::Object(text))
+}
+
+/// Leave the deletions of a format 4 text unstamped
+fn fugue_text_v4_to_v5(state: JsonValue) -> Result<JsonValue> {
+    let JsonValue::Object(mut text) = state else {
+        return Err(SyncKitError::deserialization(
+            "format 4 text is not a JSON object",
+        ));
+    };
+    // Blocks are `[id, block]` pairs
+    if let Some(JsonValue::Array(blocks)) = text.get_mut("blocks") {
+        for entry in blocks {
+            if let Some(JsonValue::Object(block)) = entry.get_mut(1) {
+                block.entry("deleted_at").or_insert(JsonValue::Null);
+            }
+        }
+    }
+    Ok(JsonValue::Object(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Document;
+
+    #[test]
+    fn test_upgrade_rejects_unknown_formats() {
+        for format in [0, FORMAT_VERSION + 1] {
+            let error = upgrade(StateKind::Document, format, json!({})).unwrap_err();
+            assert!(error.to_string().contains("this release reads formats"));
+        }
+    }
+
+    #[test]
+    fn test_document_v1_upgrades_to_the_current_format() {
+        let v1 = json!({
+            "id": "doc-1",
+            "fields": {
+                "title": {"value": "Hi", "timestamp": {"clock": 2, "client_id": "b"}}
+            },
+            "version": {"clocks": {"b": 2}}
+        });
+        let upgraded = upgrade(StateKind::Document, 1, v1).unwrap();
+        let doc: Document = serde_json::from_value(upgraded.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&doc).unwrap(), upgraded);
+
+        // Other kinds pass through untouched
+        let clock = json!({"clocks": {"a": 1}});
+        assert_eq!(
+            upgrade(StateKind::VectorClock, 1, clock.clone()).unwrap(),
+            clock
+        );
+    }
+}
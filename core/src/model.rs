@@ -0,0 +1,527 @@
+//! Typed documents: a struct's fields mapped onto document paths
+//!
+//! `#[derive(SyncModel)]` (the `derive` feature) turns a plain struct into
+//! a schema for a [`Document`]. For `struct Todo` it generates:
+//!
+//! - `impl SyncModel for Todo`, converting between a `Todo` and a
+//!   document ([`SyncModel::from_document`], [`SyncModel::into_document`])
+//! - `TodoDocument`, a wrapper over a [`ModelDocument`] with a typed
+//!   getter and setter per field
+//! - `TodoChange`, one unit variant per field, which [`FieldEvent`]s are
+//!   projected into (`TodoDocument::subscribe`)
+//!
+//! Each field is stored at the path of its name (or
+//! `#[synckit(rename = "...")]`), as one of these kinds:
+//!
+//! | Kind | Field type | Stored as | Accessors for `x` |
+//! |------|------------|-----------|-------------------|
+//! | value (default) | any serde type | a last-writer-wins field | `x()`, `set_x(value)` |
+//! | `#[synckit(counter)]` | `i64` | a field per client, `x.<client>`, summed | `x()`, `increment_x(by)` |
+//! | `#[synckit(text)]` | `String` | a [`List`](crate::list::List) of characters | `x()`, `set_x(text)`, `splice_x(index, delete, insert)` |
+//! | set | `ORSet<T>` | a list of members | `x()`, `add_x(member)`, `remove_x(&member)` |
+//!
+//! Counter increments from different clients add up, concurrent edits of
+//! a text interleave instead of one replacing the other, and a member
+//! added concurrently with its removal stays in the set. Text fields keep
+//! one list item per character, which suits titles and labels; use
+//! [`FugueText`](crate::crdt::FugueText) for long documents.
+//!
+//! The struct's schema version (`#[synckit(version = N)]`, 1 by default)
+//! is stored in the reserved field [`SCHEMA_FIELD`], and opening a
+//! document with another version fails with `SyncError::SchemaMismatch`.
+//! Migrating old documents is up to the app: open them untyped, rewrite
+//! them and store the new version with [`ModelDocument::set_schema`].
+//!
+//! # Example
+//!
+//! ```rust
+//! # #[cfg(feature = "derive")] {
+//! use synckit_core::model::SyncModel;
+//!
+//! #[derive(SyncModel)]
+//! struct Todo {
+//!     done: bool,
+//!     #[synckit(text)]
+//!     title: String,
+//!     #[synckit(counter)]
+//!     votes: i64,
+//! }
+//!
+//! let todo = Todo {
+//!     done: false,
+//!     title: "Buy milk".to_string(),
+//!     votes: 0,
+//! };
+//! let mut doc = TodoDocument::new(todo, "todo-1".to_string(), "alice".to_string()).unwrap();
+//! doc.set_done(true).unwrap();
+//! doc.splice_title(4, 0, "oat ").unwrap();
+//! doc.increment_votes(2);
+//!
+//! let todo = doc.snapshot().unwrap();
+//! assert!(todo.done);
+//! assert_eq!(todo.title, "Buy oat milk");
+//! assert_eq!(todo.votes, 2);
+//! # }
+//! ```
+
+use crate::document::Document;
+use crate::error::{Result, SyncError, SyncKitError};
+use crate::notify::FieldEvent;
+use crate::{ClientID, DocumentID, FieldPath};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::hash::Hash;
+
+#[cfg(feature = "derive")]
+pub use synckit_derive::SyncModel;
+
+/// Reserved field holding a typed document's schema version
+pub const SCHEMA_FIELD: &str = "$schema";
+
+/// A struct stored as a document (see the module docs)
+///
+/// Implemented by `#[derive(SyncModel)]`; [`read`](Self::read),
+/// [`write`](Self::write) and [`change_for`](Self::change_for) are what
+/// the derive generates, the rest is built on them.
+pub trait SyncModel: Sized {
+    /// Stored in [`SCHEMA_FIELD`] and checked on open
+    const SCHEMA_VERSION: u32;
+
+    /// Which field changed
+    type Change: fmt::Debug + Clone + Copy + PartialEq + Eq + Hash;
+
+    /// Read every field, without checking the schema version
+    ///
+    /// `client_id` becomes the replica of set fields.
+    fn read(document: &Document, client_id: &str) -> Result<Self>;
+
+    /// Write every field through `model`
+    fn write(self, model: &mut ModelDocument) -> Result<()>;
+
+    /// The field a change at `path` belongs to, if any
+    fn change_for(path: &str) -> Option<Self::Change>;
+
+    /// Read a document written with this schema version
+    ///
+    /// # Errors
+    ///
+    /// Returns `SyncError::SchemaMismatch` if the document holds another
+    /// version or none, and a deserialization error if a field is missing
+    /// or doesn't hold its type
+    fn from_document(document: &Document, client_id: &str) -> Result<Self> {
+        check_schema(document, Self::SCHEMA_VERSION)?;
+        Self::read(document, client_id)
+    }
+
+    /// A new document holding `self`, written by `client_id`
+    ///
+    /// # Errors
+    ///
+    /// Returns a serialization error if a field value doesn't serialize
+    /// to JSON
+    fn into_document(self, id: DocumentID, client_id: ClientID) -> Result<Document> {
+        let mut model = ModelDocument::create(id, client_id, Self::SCHEMA_VERSION);
+        self.write(&mut model)?;
+        Ok(model.into_document())
+    }
+
+    /// The fields a batch of document events changed, each once, in the
+    /// order first changed
+    fn changes(events: &[FieldEvent]) -> Vec<Self::Change> {
+        let mut changes = Vec::new();
+        for change in events.iter().filter_map(|event| match event {
+            FieldEvent::Lock { .. } => None,
+            _ => Self::change_for(event.path()),
+        }) {
+            if !changes.contains(&change) {
+                changes.push(change);
+            }
+        }
+        changes
+    }
+}
+
+/// The schema version stored in `document`, if any
+pub fn schema_version(document: &Document) -> Option<u32> {
+    let version = document.get_field(&SCHEMA_FIELD.to_string())?.as_u64()?;
+    u32::try_from(version).ok()
+}
+
+/// Check that `document` holds schema version `expected`
+///
+/// # Errors
+///
+/// Returns `SyncError::SchemaMismatch` otherwise
+pub fn check_schema(document: &Document, expected: u32) -> Result<()> {
+    match schema_version(document) {
+        Some(found) if found == expected => Ok(()),
+        found => Err(
+            SyncKitError::from(SyncError::SchemaMismatch { expected, found })
+                .with_document(document.id().as_str()),
+        ),
+    }
+}
+
+/// Read a value field
+///
+/// A missing field reads as JSON `null`, so `Option` fields come back as
+/// `None`.
+///
+/// # Errors
+///
+/// Returns a deserialization error if the field doesn't hold a `T`
+pub fn read_value<T: DeserializeOwned>(document: &Document, path: &str) -> Result<T> {
+    let value = document
+        .get_field(&path.to_string())
+        .cloned()
+        .unwrap_or(JsonValue::Null);
+    serde_json::from_value(value).map_err(|error| {
+        SyncKitError::deserialization(error)
+            .with_document(document.id().as_str())
+            .with_path(path)
+    })
+}
+
+/// Read a counter field: the sum of every client's count
+pub fn read_counter(document: &Document, path: &str) -> i64 {
+    document
+        .fields()
+        .iter()
+        .filter(|(field_path, _)| counter_client(path, field_path).is_some())
+        .filter_map(|(_, field)| field.value.as_i64())
+        .fold(0, i64::wrapping_add)
+}
+
+/// Read a text field
+pub fn read_text(document: &Document, path: &str) -> String {
+    let Some(list) = document.list(&path.to_string()) else {
+        return String::new();
+    };
+    list.iter()
+        .filter_map(|(_, value)| value.as_str())
+        .collect()
+}
+
+/// Read the members of a set field, each once, in list order
+///
+/// # Errors
+///
+/// Returns a deserialization error if a member isn't a `T`
+pub fn read_set<T: DeserializeOwned>(document: &Document, path: &str) -> Result<Vec<T>> {
+    set_members(document, path)
+        .into_iter()
+        .map(|value| {
+            serde_json::from_value(value.clone()).map_err(|error| {
+                SyncKitError::deserialization(error)
+                    .with_document(document.id().as_str())
+                    .with_path(path)
+            })
+        })
+        .collect()
+}
+
+/// Check if `path` is one client's count of the counter at `counter`
+fn counter_client<'a>(counter: &str, path: &'a str) -> Option<&'a str> {
+    path.strip_prefix(counter)?.strip_prefix('.')
+}
+
+fn set_members<'a>(document: &'a Document, path: &str) -> Vec<&'a JsonValue> {
+    let mut members: Vec<&JsonValue> = Vec::new();
+    if let Some(list) = document.list(&path.to_string()) {
+        for (_, value) in list.iter() {
+            if !members.contains(&value) {
+                members.push(value);
+            }
+        }
+    }
+    members
+}
+
+fn to_json<T: Serialize>(value: &T, path: &str) -> Result<JsonValue> {
+    serde_json::to_value(value).map_err(|error| SyncKitError::serialization(error).with_path(path))
+}
+
+/// A document and the client editing it, with the writes typed
+/// documents are built on
+///
+/// Writes take their clock from the document: one past the newest clock
+/// in its version vector and the written field, so a write always wins
+/// over what this replica has seen. The version vector is advanced with
+/// it.
+#[derive(Debug)]
+pub struct ModelDocument {
+    document: Document,
+    client_id: ClientID,
+}
+
+impl ModelDocument {
+    /// A new document with schema version `version`
+    pub fn create(id: DocumentID, client_id: ClientID, version: u32) -> Self {
+        let mut model = Self {
+            document: Document::new(id),
+            client_id,
+        };
+        model.set_schema(version);
+        model
+    }
+
+    /// Edit `document` as `client_id`, checking that it holds schema
+    /// version `version`
+    ///
+    /// # Errors
+    ///
+    /// Returns `SyncError::SchemaMismatch` if it holds another version or
+    /// none
+    pub fn open(document: Document, client_id: ClientID, version: u32) -> Result<Self> {
+        check_schema(&document, version)?;
+        Ok(Self {
+            document,
+            client_id,
+        })
+    }
+
+    /// The document
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// The document, e.g. to apply an incoming delta
+    pub fn document_mut(&mut self) -> &mut Document {
+        &mut self.document
+    }
+
+    /// Unwrap the document
+    pub fn into_document(self) -> Document {
+        self.document
+    }
+
+    /// The client writes are made by
+    pub fn client_id(&self) -> &ClientID {
+        &self.client_id
+    }
+
+    /// Store schema version `version`
+    pub fn set_schema(&mut self, version: u32) {
+        self.write(SCHEMA_FIELD, JsonValue::from(version));
+    }
+
+    /// Write a value field
+    ///
+    /// # Errors
+    ///
+    /// Returns a serialization error if `value` doesn't serialize to JSON
+    pub fn write_value<T: Serialize>(&mut self, path: &str, value: &T) -> Result<()> {
+        let value = to_json(value, path)?;
+        self.write(path, value);
+        Ok(())
+    }
+
+    /// Add `by` to this client's count of a counter field
+    pub fn increment(&mut self, path: &str, by: i64) {
+        let own = format!("{}.{}", path, self.client_id);
+        let count = self
+            .document
+            .get_field(&own)
+            .and_then(JsonValue::as_i64)
+            .unwrap_or(0);
+        self.write(&own, JsonValue::from(count.wrapping_add(by)));
+    }
+
+    /// Replace `delete` characters of a text field at `index` (in
+    /// characters) with `insert`
+    ///
+    /// # Errors
+    ///
+    /// Returns `SyncError::InvalidOperation` if the range is past the end
+    /// of the text
+    pub fn splice_text(
+        &mut self,
+        path: &str,
+        index: usize,
+        delete: usize,
+        insert: &str,
+    ) -> Result<()> {
+        let len = self
+            .document
+            .list(&path.to_string())
+            .map_or(0, |list| list.len());
+        if index.saturating_add(delete) > len {
+            return Err(SyncKitError::from(SyncError::InvalidOperation(format!(
+                "text range {}..{} is past its length {}",
+                index,
+                index.saturating_add(delete),
+                len
+            )))
+            .with_document(self.document.id().as_str())
+            .with_path(path));
+        }
+        let client_id = self.client_id.clone();
+        self.document.transaction(|document| {
+            let mut list = document.list_mut(path.to_string(), client_id);
+            for _ in 0..delete {
+                list.remove(index)?;
+            }
+            for (offset, ch) in insert.chars().enumerate() {
+                list.insert(index + offset, JsonValue::from(ch.to_string()))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Set a text field to `text`, editing only the part that changed
+    pub fn write_text(&mut self, path: &str, text: &str) {
+        let current: Vec<char> = read_text(&self.document, path).chars().collect();
+        let target: Vec<char> = text.chars().collect();
+        let prefix = current
+            .iter()
+            .zip(&target)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = current[prefix..]
+            .iter()
+            .rev()
+            .zip(target[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let insert: String = target[prefix..target.len() - suffix].iter().collect();
+        self.splice_text(path, prefix, current.len() - prefix - suffix, &insert)
+            .expect("the changed range is within the text");
+    }
+
+    /// Add `member` to a set field; returns false if it was already in
+    ///
+    /// # Errors
+    ///
+    /// Returns a serialization error if `member` doesn't serialize to JSON
+    pub fn add_to_set<T: Serialize>(&mut self, path: &str, member: &T) -> Result<bool> {
+        let value = to_json(member, path)?;
+        if set_members(&self.document, path).contains(&&value) {
+            return Ok(false);
+        }
+        self.document
+            .list_mut(path.to_string(), self.client_id.clone())
+            .push(value);
+        Ok(true)
+    }
+
+    /// Remove `member` from a set field; returns false if it wasn't in
+    ///
+    /// Removes the additions of it this replica has seen, so an addition
+    /// made concurrently elsewhere survives.
+    ///
+    /// # Errors
+    ///
+    /// Returns a serialization error if `member` doesn't serialize to JSON
+    pub fn remove_from_set<T: Serialize>(&mut self, path: &str, member: &T) -> Result<bool> {
+        let value = to_json(member, path)?;
+        let Some(list) = self.document.list(&path.to_string()) else {
+            return Ok(false);
+        };
+        let indices: Vec<usize> = (0..list.len())
+            .filter(|&index| list.get(index) == Some(&value))
+            .collect();
+        let client_id = self.client_id.clone();
+        self.document.transaction(|document| {
+            let mut list = document.list_mut(path.to_string(), client_id);
+            for &index in indices.iter().rev() {
+                list.remove(index)?;
+            }
+            Ok(!indices.is_empty())
+        })
+    }
+
+    fn write(&mut self, path: &str, value: JsonValue) {
+        let path: FieldPath = path.to_string();
+        let seen = self.document.version().clocks().values().copied().max();
+        let current = self
+            .document
+            .fields()
+            .get(&path)
+            .map(|field| field.timestamp.clock);
+        let clock = seen.max(current).unwrap_or(0) + 1;
+        self.document
+            .set_field(path, value, clock, self.client_id.clone());
+        self.document.version.update(&self.client_id, clock);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn model() -> ModelDocument {
+        ModelDocument::create("doc".to_string(), "alice".to_string(), 3)
+    }
+
+    #[test]
+    fn test_schema_checked_on_open() {
+        let document = model().into_document();
+        assert_eq!(schema_version(&document), Some(3));
+        assert!(ModelDocument::open(document.clone(), "bob".to_string(), 3).is_ok());
+
+        let error = ModelDocument::open(document, "bob".to_string(), 4).unwrap_err();
+        assert_eq!(error.code_name(), "SCHEMA_MISMATCH");
+        let untyped = Document::new("doc".to_string());
+        assert!(matches!(
+            check_schema(&untyped, 1).unwrap_err().kind(),
+            crate::ErrorKind::Sync(SyncError::SchemaMismatch { found: None, .. })
+        ));
+    }
+
+    #[test]
+    fn test_writes_win_over_what_was_seen() {
+        let mut model = model();
+        let mut remote = Document::new("doc".to_string());
+        remote.set_field("title".to_string(), json!("theirs"), 40, "zed".to_string());
+        model.document_mut().merge(&remote);
+
+        model.write_value("title", &"mine").unwrap();
+        assert_eq!(
+            read_value::<String>(model.document(), "title").unwrap(),
+            "mine"
+        );
+        assert_eq!(model.document().version().get("alice"), 41);
+        assert!(read_value::<u32>(model.document(), "title").is_err());
+        assert_eq!(
+            read_value::<Option<u32>>(model.document(), "missing").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_text_edits() {
+        let mut model = model();
+        model.write_text("title", "Hello world");
+        model.write_text("title", "Hello, brave world");
+        assert_eq!(read_text(model.document(), "title"), "Hello, brave world");
+        let list = model.document().list(&"title".to_string()).unwrap();
+        assert_eq!(list.len(), "Hello, brave world".len());
+
+        model.splice_text("title", 0, 5, "Goodbye").unwrap();
+        assert_eq!(read_text(model.document(), "title"), "Goodbye, brave world");
+        assert!(model.splice_text("title", 18, 5, "").is_err());
+    }
+
+    #[test]
+    fn test_set_and_counter() {
+        let mut model = model();
+        assert!(model.add_to_set("tags", &"home").unwrap());
+        assert!(!model.add_to_set("tags", &"home").unwrap());
+        assert!(model.add_to_set("tags", &"urgent").unwrap());
+        assert!(model.remove_from_set("tags", &"home").unwrap());
+        assert!(!model.remove_from_set("tags", &"home").unwrap());
+        assert_eq!(
+            read_set::<String>(model.document(), "tags").unwrap(),
+            ["urgent"]
+        );
+
+        model.increment("votes", 3);
+        model.increment("votes", -1);
+        // A field that only shares the prefix isn't a count
+        model.write_value("votes_total", &100).unwrap();
+        assert_eq!(read_counter(model.document(), "votes"), 2);
+    }
+}
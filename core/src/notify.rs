@@ -0,0 +1,382 @@
+//! Batched change notifications for `Document` and `FugueText`
+//!
+//! Observers registered with `subscribe` receive changes in batches, never
+//! one call per change:
+//!
+//! - Everything a single operation changes (a merge, an applied delta, a
+//!   `transaction`) is delivered as one batch, after the operation ends
+//! - With [`CoalescePolicy`] set, consecutive batches are held back and
+//!   merged until enough changes or time have accumulated
+//! - `flush_notifications` delivers whatever is held back right away
+//!
+//! Changes to the same key (a document field path) are deduplicated within
+//! a batch, keeping the last one in the position it was made, so a batch
+//! applied in order still ends at the final state. Text events have no key
+//! and are only concatenated.
+
+use crate::time::{SharedTime, TimeProvider};
+use crate::FieldPath;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Handle returned by `subscribe`, used to unsubscribe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+impl SubscriptionId {
+    pub(crate) fn new(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+/// A change that a later change may replace within a batch
+pub trait Coalesce: Clone {
+    /// Changes with equal keys replace each other; None is never replaced
+    fn key(&self) -> Option<&str>;
+}
+
+/// A change to one document field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FieldEvent {
+    /// The field now holds `value`
+    Set {
+        path: FieldPath,
+        #[serde(with = "crate::codec::json_value")]
+        value: JsonValue,
+    },
+
+    /// The field was deleted
+    Delete { path: FieldPath },
+
+    /// The advisory lock on the path changed (see [`crate::locks`]):
+    /// `holder` holds it now, or nobody does
+    Lock {
+        path: FieldPath,
+        holder: Option<String>,
+    },
+}
+
+impl FieldEvent {
+    /// Path of the changed field
+    pub fn path(&self) -> &FieldPath {
+        match self {
+            FieldEvent::Set { path, .. }
+            | FieldEvent::Delete { path }
+            | FieldEvent::Lock { path, .. } => path,
+        }
+    }
+}
+
+impl Coalesce for FieldEvent {
+    fn key(&self) -> Option<&str> {
+        // Lock changes must not replace the value change of their path
+        match self {
+            FieldEvent::Lock { .. } => None,
+            _ => Some(self.path()),
+        }
+    }
+}
+
+#[cfg(feature = "text-crdt")]
+impl Coalesce for crate::crdt::TextEvent {
+    fn key(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// When to deliver batches held back for merging
+///
+/// A held batch goes out once `max_changes` changes are held, or when a
+/// later batch arrives `max_delay` or more after the first held one. There
+/// is no timer: call `flush_notifications` to deliver held changes once
+/// activity stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoalescePolicy {
+    /// Longest a batch is held while later ones keep arriving
+    pub max_delay: Duration,
+
+    /// Number of held changes that triggers delivery
+    pub max_changes: usize,
+}
+
+// Sync so that `Document` and `FugueText` stay Sync
+type Callback<C> = Box<dyn FnMut(&[C]) + Send + Sync>;
+
+/// Observers of one value and the changes waiting for them
+pub(crate) struct Notifier<C> {
+    next_id: u64,
+    callbacks: Vec<(SubscriptionId, Callback<C>)>,
+    /// Nesting depth of open operations
+    depth: usize,
+    /// Changes of the open operation
+    current: Vec<C>,
+    /// Batches held back by the coalescer, merged
+    held: Vec<C>,
+    /// When the first held batch arrived
+    held_since_ms: Option<u64>,
+    coalesce: Option<(CoalescePolicy, SharedTime)>,
+}
+
+impl<C: Coalesce> Notifier<C> {
+    pub(crate) fn add(&mut self, callback: Callback<C>) -> SubscriptionId {
+        let id = SubscriptionId::new(self.next_id);
+        self.next_id += 1;
+        self.callbacks.push((id, callback));
+        id
+    }
+
+    /// Remove an observer; the last one going discards held changes
+    pub(crate) fn remove(&mut self, id: SubscriptionId) -> bool {
+        let before = self.callbacks.len();
+        self.callbacks.retain(|(existing, _)| *existing != id);
+        if self.callbacks.is_empty() {
+            self.current.clear();
+            self.held.clear();
+            self.held_since_ms = None;
+        }
+        self.callbacks.len() != before
+    }
+
+    /// Check if anyone is listening (callers skip computing changes if not)
+    pub(crate) fn is_active(&self) -> bool {
+        !self.callbacks.is_empty()
+    }
+
+    /// Heap held by the observer list and the changes waiting in it
+    /// (not counting what the changes themselves point to)
+    pub(crate) fn queued_bytes(&self) -> usize {
+        self.callbacks.capacity() * std::mem::size_of::<(SubscriptionId, Callback<C>)>()
+            + (self.current.capacity() + self.held.capacity()) * std::mem::size_of::<C>()
+    }
+
+    pub(crate) fn set_coalescing(&mut self, policy: CoalescePolicy, time: SharedTime) {
+        self.coalesce = Some((policy, time));
+    }
+
+    /// Deliver held changes and stop coalescing
+    pub(crate) fn stop_coalescing(&mut self) {
+        self.flush();
+        self.coalesce = None;
+    }
+
+    /// Start an operation: changes are held until the matching `end`
+    pub(crate) fn begin(&mut self) {
+        self.depth += 1;
+    }
+
+    /// End an operation, sealing its changes into one batch once the
+    /// outermost one ends
+    pub(crate) fn end(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 && !self.current.is_empty() {
+            let batch = std::mem::take(&mut self.current);
+            self.seal(batch);
+        }
+    }
+
+    /// Record a change; outside an operation it is a batch of its own
+    pub(crate) fn push(&mut self, change: C) {
+        if !self.is_active() {
+            return;
+        }
+        self.current.push(change);
+        if self.depth == 0 {
+            self.end();
+        }
+    }
+
+    /// Record several changes as part of the same operation
+    #[cfg_attr(not(feature = "text-crdt"), allow(dead_code))]
+    pub(crate) fn extend(&mut self, changes: impl IntoIterator<Item = C>) {
+        if !self.is_active() {
+            return;
+        }
+        self.begin();
+        self.current.extend(changes);
+        self.end();
+    }
+
+    /// Deliver held changes now
+    pub(crate) fn flush(&mut self) {
+        self.held_since_ms = None;
+        let held = std::mem::take(&mut self.held);
+        self.deliver(held);
+    }
+
+    fn seal(&mut self, mut batch: Vec<C>) {
+        let Some((policy, time)) = &self.coalesce else {
+            dedup(&mut batch);
+            self.deliver(batch);
+            return;
+        };
+
+        let now = time.now_ms();
+        let held_since = *self.held_since_ms.get_or_insert(now);
+        let max_changes = policy.max_changes;
+        let due = now.saturating_sub(held_since) >= policy.max_delay.as_millis() as u64;
+        self.held.append(&mut batch);
+        dedup(&mut self.held);
+        if due || self.held.len() >= max_changes {
+            self.flush();
+        }
+    }
+
+    fn deliver(&mut self, batch: Vec<C>) {
+        if batch.is_empty() {
+            return;
+        }
+        for (_, callback) in &mut self.callbacks {
+            callback(&batch);
+        }
+    }
+}
+
+/// Keep only the last change per key, where it was made
+fn dedup<C: Coalesce>(changes: &mut Vec<C>) {
+    let mut last: HashMap<&str, usize> = HashMap::new();
+    for (index, change) in changes.iter().enumerate() {
+        if let Some(key) = change.key() {
+            last.insert(key, index);
+        }
+    }
+    if last.len() == changes.iter().filter(|c| c.key().is_some()).count() {
+        return;
+    }
+
+    let keep: Vec<bool> = changes
+        .iter()
+        .enumerate()
+        .map(|(index, change)| change.key().is_none_or(|key| last[key] == index))
+        .collect();
+    let mut keep = keep.into_iter();
+    changes.retain(|_| keep.next().unwrap_or(true));
+}
+
+impl<C> Default for Notifier<C> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            callbacks: Vec::new(),
+            depth: 0,
+            current: Vec::new(),
+            held: Vec::new(),
+            held_since_ms: None,
+            coalesce: None,
+        }
+    }
+}
+
+/// Observers stay with the original: a clone starts without any
+impl<C> Clone for Notifier<C> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<C> std::fmt::Debug for Notifier<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Notifier")
+            .field("count", &self.callbacks.len())
+            .field("held", &self.held.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::MockTime;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    type Batches = Arc<Mutex<Vec<Vec<FieldEvent>>>>;
+
+    fn recording(notifier: &mut Notifier<FieldEvent>) -> Batches {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = batches.clone();
+        notifier.add(Box::new(move |batch| {
+            sink.lock().unwrap().push(batch.to_vec())
+        }));
+        batches
+    }
+
+    fn set(path: &str, value: i64) -> FieldEvent {
+        FieldEvent::Set {
+            path: path.to_string(),
+            value: json!(value),
+        }
+    }
+
+    #[test]
+    fn test_operation_is_one_deduplicated_batch() {
+        let mut notifier = Notifier::default();
+        let batches = recording(&mut notifier);
+
+        notifier.begin();
+        notifier.push(set("a", 1));
+        notifier.push(set("b", 1));
+        notifier.begin();
+        notifier.push(set("a", 2));
+        notifier.end();
+        assert!(batches.lock().unwrap().is_empty());
+        notifier.end();
+        notifier.push(set("c", 1));
+
+        assert_eq!(
+            *batches.lock().unwrap(),
+            vec![vec![set("b", 1), set("a", 2)], vec![set("c", 1)]]
+        );
+    }
+
+    #[test]
+    fn test_coalescer_merges_batches_until_due() {
+        let time = MockTime::new(0);
+        let mut notifier = Notifier::default();
+        let batches = recording(&mut notifier);
+        notifier.set_coalescing(
+            CoalescePolicy {
+                max_delay: Duration::from_millis(100),
+                max_changes: 3,
+            },
+            SharedTime::new(time.clone()),
+        );
+
+        notifier.push(set("a", 1));
+        time.advance(50);
+        notifier.push(set("a", 2));
+        assert!(batches.lock().unwrap().is_empty());
+
+        // A batch arriving after max_delay releases everything held
+        time.advance(50);
+        notifier.push(set("b", 1));
+        assert_eq!(
+            batches.lock().unwrap().pop(),
+            Some(vec![set("a", 2), set("b", 1)])
+        );
+
+        // So does reaching max_changes
+        notifier.extend([set("x", 1), set("y", 1), set("z", 1)]);
+        assert_eq!(batches.lock().unwrap().len(), 1);
+
+        // And flushing
+        notifier.push(set("a", 3));
+        notifier.flush();
+        assert_eq!(batches.lock().unwrap().pop(), Some(vec![set("a", 3)]));
+    }
+
+    #[test]
+    fn test_clone_and_inactive_notifier_record_nothing() {
+        let mut notifier = Notifier::default();
+        notifier.push(set("a", 1));
+        assert!(notifier.current.is_empty());
+
+        let batches = recording(&mut notifier);
+        let mut clone = notifier.clone();
+        clone.push(set("a", 1));
+        assert!(!clone.is_active());
+        assert!(batches.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,582 @@
+//! JSON Lines operation export and import
+//!
+//! Dumps a replica as one JSON object per line so it can be inspected with
+//! `jq`, grepped, or loaded into a data pipeline without binary tooling,
+//! and replays such a dump into a fresh replica.
+//!
+//! The first line is a header naming the kind of replica and the format
+//! version; every later line is one operation:
+//!
+//! ```text
+//! {"type":"document","format_version":1,"id":"doc-1"}
+//! {"type":"set","path":"title","clock":1,"client":"alice","value":"Hello"}
+//! {"type":"version","client":"alice","clock":1}
+//! ```
+//!
+//! A text dump has an `insert` line per block (with its Fugue origins) and
+//! a `delete` line per deleted clock range.
+//!
+//! With [`Redaction::Hash`] values and text are replaced by a stable hash
+//! (`value_hash`/`text_hash`), keeping paths, clocks, clients and lengths.
+//! Importing a redacted dump restores the structure with `null` values and
+//! U+FFFD placeholder characters.
+//!
+//! Neither `Document` nor `FugueText` records wall-clock time, so records
+//! carry logical clocks only. New fields may be added to records within a
+//! format version; removing or changing one bumps [`OPS_JSONL_VERSION`].
+
+use crate::document::Field;
+use crate::sync::Timestamp;
+use crate::{ClientID, Document, DocumentID, FieldPath};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::io::{BufRead, Write};
+use thiserror::Error;
+
+#[cfg(feature = "text-crdt")]
+use crate::crdt::text_fugue::{DeleteRange, FugueBlock, FugueText, NodeId, TextDelta, TextError};
+
+/// Current format version, written in every header
+pub const OPS_JSONL_VERSION: u32 = 1;
+
+/// Character standing in for redacted text on import
+pub const REDACTED_CHAR: char = '\u{FFFD}';
+
+/// Whether exported records carry values or only their hashes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Redaction {
+    /// Write values and text as they are
+    #[default]
+    None,
+    /// Write a hash of each value and text instead
+    Hash,
+}
+
+/// One line of a JSON Lines dump
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OpRecord {
+    /// Header of a `Document` dump
+    Document { format_version: u32, id: DocumentID },
+
+    /// Header of a `FugueText` dump
+    Text {
+        format_version: u32,
+        client: ClientID,
+        /// Lamport clock of the exporting replica
+        clock: u64,
+    },
+
+    /// A field write
+    Set {
+        path: FieldPath,
+        clock: u64,
+        client: ClientID,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<JsonValue>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value_hash: Option<String>,
+    },
+
+    /// A document vector clock entry
+    Version { client: ClientID, clock: u64 },
+
+    /// A text block: `len` characters ending at `clock`
+    #[cfg(feature = "text-crdt")]
+    Insert {
+        client: ClientID,
+        clock: u64,
+        len: usize,
+        left_origin: Option<NodeId>,
+        right_origin: Option<NodeId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text_hash: Option<String>,
+    },
+
+    /// Deleted characters `start..=end` inserted by `client`
+    #[cfg(feature = "text-crdt")]
+    Delete {
+        client: ClientID,
+        start: u64,
+        end: u64,
+        /// Stamp of the deletion, if known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deleted_at: Option<NodeId>,
+    },
+}
+
+impl OpRecord {
+    fn kind(&self) -> &'static str {
+        match self {
+            OpRecord::Document { .. } => "document",
+            OpRecord::Text { .. } => "text",
+            OpRecord::Set { .. } => "set",
+            OpRecord::Version { .. } => "version",
+            #[cfg(feature = "text-crdt")]
+            OpRecord::Insert { .. } => "insert",
+            #[cfg(feature = "text-crdt")]
+            OpRecord::Delete { .. } => "delete",
+        }
+    }
+}
+
+/// Errors from exporting or importing a JSON Lines dump
+#[derive(Error, Debug)]
+pub enum OpsJsonlError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+
+    #[error("dump has no header line")]
+    MissingHeader,
+
+    #[error("unsupported format version {0} (newest supported is {OPS_JSONL_VERSION})")]
+    UnsupportedVersion(u32),
+
+    #[error("line {line}: unexpected '{record}' record in a {kind} dump")]
+    UnexpectedRecord {
+        line: usize,
+        record: &'static str,
+        kind: &'static str,
+    },
+
+    #[cfg(feature = "text-crdt")]
+    #[error("replay failed: {0}")]
+    Text(#[from] TextError),
+}
+
+/// Stable 64-bit FNV-1a hash, hex encoded
+///
+/// `DefaultHasher` may change between Rust releases; dumps are compared
+/// across versions, so the hash is spelled out here.
+fn stable_hash(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("fnv1a64:{:016x}", hash)
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &OpRecord) -> Result<(), OpsJsonlError> {
+    serde_json::to_writer(&mut *writer, record).map_err(std::io::Error::from)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Parse the non-empty lines of a dump, checking the header version
+fn read_records<R: BufRead>(reader: R) -> Result<Vec<(usize, OpRecord)>, OpsJsonlError> {
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| OpsJsonlError::Parse {
+            line: index + 1,
+            message: e.to_string(),
+        })?;
+        records.push((index + 1, record));
+    }
+
+    match records.first() {
+        Some((_, OpRecord::Document { format_version, .. }))
+        | Some((_, OpRecord::Text { format_version, .. })) => {
+            if *format_version > OPS_JSONL_VERSION {
+                return Err(OpsJsonlError::UnsupportedVersion(*format_version));
+            }
+        }
+        _ => return Err(OpsJsonlError::MissingHeader),
+    }
+    Ok(records)
+}
+
+impl Document {
+    /// Write the document as JSON Lines: a header, one `set` per field in
+    /// timestamp order, then the vector clock
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::ops_jsonl::Redaction;
+    /// use synckit_core::Document;
+    ///
+    /// let mut doc = Document::new("doc-1".to_string());
+    /// doc.set_field("title".to_string(), serde_json::json!("Hi"), 1, "alice".to_string());
+    ///
+    /// let mut dump = Vec::new();
+    /// doc.export_ops_jsonl(&mut dump, Redaction::None).unwrap();
+    ///
+    /// let restored = Document::import_ops_jsonl(dump.as_slice()).unwrap();
+    /// assert_eq!(restored.fields(), doc.fields());
+    /// ```
+    pub fn export_ops_jsonl<W: Write>(
+        &self,
+        mut writer: W,
+        redaction: Redaction,
+    ) -> Result<(), OpsJsonlError> {
+        write_record(
+            &mut writer,
+            &OpRecord::Document {
+                format_version: OPS_JSONL_VERSION,
+                id: self.id.clone(),
+            },
+        )?;
+
+        let mut fields: Vec<(&FieldPath, &Field)> = self.fields.iter().collect();
+        fields.sort_by(|(a_path, a), (b_path, b)| {
+            (&a.timestamp, a_path).cmp(&(&b.timestamp, b_path))
+        });
+        for (path, field) in fields {
+            let (value, value_hash) = match redaction {
+                Redaction::None => (Some(field.value.clone()), None),
+                Redaction::Hash => (None, Some(stable_hash(field.value.to_string().as_bytes()))),
+            };
+            write_record(
+                &mut writer,
+                &OpRecord::Set {
+                    path: path.clone(),
+                    clock: field.timestamp.clock,
+                    client: field.timestamp.client_id.clone(),
+                    value,
+                    value_hash,
+                },
+            )?;
+        }
+
+        let mut clocks: Vec<(&ClientID, &u64)> = self.version.clocks().iter().collect();
+        clocks.sort();
+        for (client, clock) in clocks {
+            write_record(
+                &mut writer,
+                &OpRecord::Version {
+                    client: client.clone(),
+                    clock: *clock,
+                },
+            )?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Replay a dump written by `export_ops_jsonl` into a new document
+    ///
+    /// Redacted values come back as `null`.
+    ///
+    /// # Errors
+    ///
+    /// Fails on unreadable or malformed lines, a missing header, a newer
+    /// format version, or records that belong to a text dump
+    pub fn import_ops_jsonl<R: BufRead>(reader: R) -> Result<Document, OpsJsonlError> {
+        let records = read_records(reader)?;
+        let mut doc = match &records[0].1 {
+            OpRecord::Document { id, .. } => Document::new(id.clone()),
+            other => {
+                return Err(OpsJsonlError::UnexpectedRecord {
+                    line: records[0].0,
+                    record: other.kind(),
+                    kind: "document",
+                })
+            }
+        };
+
+        for (line, record) in records.into_iter().skip(1) {
+            match record {
+                OpRecord::Set {
+                    path,
+                    clock,
+                    client,
+                    value,
+                    ..
+                } => {
+                    let field = Field {
+                        value: value.unwrap_or(JsonValue::Null),
+                        timestamp: Timestamp::new(clock, client),
+                    };
+                    doc.write_field(path, field);
+                }
+                OpRecord::Version { client, clock } => {
+                    doc.version.update(&client, clock);
+                    doc.mark_version_dirty();
+                }
+                other => {
+                    return Err(OpsJsonlError::UnexpectedRecord {
+                        line,
+                        record: other.kind(),
+                        kind: "document",
+                    })
+                }
+            }
+        }
+        Ok(doc)
+    }
+}
+
+#[cfg(feature = "text-crdt")]
+impl FugueText {
+    /// Write the text as JSON Lines: a header, one `insert` per block in
+    /// causal order, then the deleted clock ranges
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    /// use synckit_core::ops_jsonl::Redaction;
+    ///
+    /// let mut text = FugueText::new("alice".to_string());
+    /// text.insert(0, "Hello").unwrap();
+    ///
+    /// let mut dump = Vec::new();
+    /// text.export_ops_jsonl(&mut dump, Redaction::None).unwrap();
+    ///
+    /// let restored = FugueText::import_ops_jsonl(dump.as_slice()).unwrap();
+    /// assert_eq!(restored.to_string(), "Hello");
+    /// ```
+    pub fn export_ops_jsonl<W: Write>(
+        &self,
+        mut writer: W,
+        redaction: Redaction,
+    ) -> Result<(), OpsJsonlError> {
+        let all = self.diff_since(&crate::VectorClock::new());
+        write_record(
+            &mut writer,
+            &OpRecord::Text {
+                format_version: OPS_JSONL_VERSION,
+                client: self.client_id().to_string(),
+                clock: all.clock,
+            },
+        )?;
+
+        for block in &all.blocks {
+            let (text, text_hash) = match redaction {
+                Redaction::None => (Some(block.text.to_string()), None),
+                Redaction::Hash => (None, Some(stable_hash(block.text.as_bytes()))),
+            };
+            write_record(
+                &mut writer,
+                &OpRecord::Insert {
+                    client: block.id.client_id.to_string(),
+                    clock: block.id.clock,
+                    len: block.len(),
+                    left_origin: block.left_origin.clone(),
+                    right_origin: block.right_origin.clone(),
+                    text,
+                    text_hash,
+                },
+            )?;
+        }
+
+        for range in all.deleted {
+            write_record(
+                &mut writer,
+                &OpRecord::Delete {
+                    client: range.client_id.into(),
+                    start: range.start,
+                    end: range.end,
+                    deleted_at: range.deleted_at,
+                },
+            )?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Replay a dump written by `export_ops_jsonl` into a new replica with
+    /// the exporter's client ID
+    ///
+    /// Redacted text comes back as `REDACTED_CHAR` repeated to the
+    /// original length, so positions and lengths are preserved.
+    ///
+    /// # Errors
+    ///
+    /// Fails on unreadable or malformed lines, a missing header, a newer
+    /// format version, records that belong to a document dump, or blocks
+    /// the text rejects
+    pub fn import_ops_jsonl<R: BufRead>(reader: R) -> Result<FugueText, OpsJsonlError> {
+        let records = read_records(reader)?;
+        let (mut text, clock) = match &records[0].1 {
+            OpRecord::Text { client, clock, .. } => (FugueText::new(client.clone()), *clock),
+            other => {
+                return Err(OpsJsonlError::UnexpectedRecord {
+                    line: records[0].0,
+                    record: other.kind(),
+                    kind: "text",
+                })
+            }
+        };
+
+        let mut delta = TextDelta {
+            clock,
+            ..TextDelta::default()
+        };
+        for (line, record) in records.into_iter().skip(1) {
+            match record {
+                OpRecord::Insert {
+                    client,
+                    clock,
+                    len,
+                    left_origin,
+                    right_origin,
+                    text,
+                    ..
+                } => {
+                    let text =
+                        text.unwrap_or_else(|| std::iter::repeat_n(REDACTED_CHAR, len).collect());
+                    delta.blocks.push(FugueBlock::new(
+                        NodeId::new(client, clock, 0),
+                        text,
+                        left_origin,
+                        right_origin,
+                    ));
+                }
+                OpRecord::Delete {
+                    client,
+                    start,
+                    end,
+                    deleted_at,
+                } => delta.deleted.push(DeleteRange {
+                    client_id: client.into(),
+                    start,
+                    end,
+                    deleted_at,
+                }),
+                other => {
+                    return Err(OpsJsonlError::UnexpectedRecord {
+                        line,
+                        record: other.kind(),
+                        kind: "text",
+                    })
+                }
+            }
+        }
+
+        text.apply_delta(&delta)?;
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn session() -> Document {
+        let mut doc = Document::new("doc-1".to_string());
+        doc.set_field("title".to_string(), json!("Draft"), 1, "alice".to_string());
+        doc.set_field("tags".to_string(), json!(["a", "b"]), 2, "bob".to_string());
+        doc.set_field("title".to_string(), json!("Final"), 3, "bob".to_string());
+        doc.version.update("alice", 1);
+        doc.version.update("bob", 3);
+        doc
+    }
+
+    fn export(doc: &Document, redaction: Redaction) -> String {
+        let mut dump = Vec::new();
+        doc.export_ops_jsonl(&mut dump, redaction).unwrap();
+        String::from_utf8(dump).unwrap()
+    }
+
+    #[test]
+    fn test_document_round_trip() {
+        let doc = session();
+        let dump = export(&doc, Redaction::None);
+        assert_eq!(dump.lines().count(), 5);
+        assert_eq!(
+            dump.lines().next().unwrap(),
+            r#"{"type":"document","format_version":1,"id":"doc-1"}"#
+        );
+
+        let restored = Document::import_ops_jsonl(dump.as_bytes()).unwrap();
+        assert_eq!(restored.id(), doc.id());
+        assert_eq!(restored.fields(), doc.fields());
+        assert_eq!(restored.version(), doc.version());
+        assert_eq!(export(&restored, Redaction::None), dump);
+    }
+
+    #[test]
+    fn test_document_redaction_keeps_structure() {
+        let doc = session();
+        let dump = export(&doc, Redaction::Hash);
+        assert!(!dump.contains("Final"));
+        assert!(dump.contains("value_hash"));
+
+        let restored = Document::import_ops_jsonl(dump.as_bytes()).unwrap();
+        assert_eq!(restored.field_count(), doc.field_count());
+        assert_eq!(restored.version(), doc.version());
+        for (path, field) in doc.fields() {
+            assert_eq!(restored.fields()[path].timestamp, field.timestamp);
+            assert_eq!(restored.fields()[path].value, JsonValue::Null);
+        }
+
+        // Same value, same hash
+        assert_eq!(export(&doc, Redaction::Hash), dump);
+    }
+
+    #[test]
+    fn test_rejects_bad_dumps() {
+        assert!(matches!(
+            Document::import_ops_jsonl(&b""[..]),
+            Err(OpsJsonlError::MissingHeader)
+        ));
+        assert!(matches!(
+            Document::import_ops_jsonl(&br#"{"type":"document","format_version":2,"id":"d"}"#[..]),
+            Err(OpsJsonlError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            Document::import_ops_jsonl(
+                &b"{\"type\":\"document\",\"format_version\":1,\"id\":\"d\"}\nnot json"[..]
+            ),
+            Err(OpsJsonlError::Parse { line: 2, .. })
+        ));
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_round_trip() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        alice.insert(0, "Hello World").unwrap();
+        bob.merge(&alice).unwrap();
+        bob.insert(5, ",").unwrap();
+        alice.delete(6, 5).unwrap();
+        alice.insert(6, "there").unwrap();
+        alice.merge(&bob).unwrap();
+
+        let mut dump = Vec::new();
+        alice.export_ops_jsonl(&mut dump, Redaction::None).unwrap();
+        let restored = FugueText::import_ops_jsonl(dump.as_slice()).unwrap();
+
+        assert_eq!(restored.to_string(), alice.to_string());
+        assert_eq!(
+            serde_json::to_string(&restored).unwrap(),
+            serde_json::to_string(&alice).unwrap()
+        );
+        assert!(matches!(
+            Document::import_ops_jsonl(dump.as_slice()),
+            Err(OpsJsonlError::UnexpectedRecord { line: 1, .. })
+        ));
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_redaction_keeps_structure() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "secret 👋").unwrap();
+        text.delete(0, 2).unwrap();
+
+        let mut dump = Vec::new();
+        text.export_ops_jsonl(&mut dump, Redaction::Hash).unwrap();
+        assert!(!String::from_utf8(dump.clone()).unwrap().contains("cret"));
+
+        let restored = FugueText::import_ops_jsonl(dump.as_slice()).unwrap();
+        assert_eq!(restored.len(), text.len());
+        assert_eq!(restored.state_vector(), text.state_vector());
+        assert!(restored.to_string().chars().all(|c| c == REDACTED_CHAR));
+    }
+}
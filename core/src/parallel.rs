@@ -0,0 +1,129 @@
+//! Parallel bulk merges (`parallel` feature)
+//!
+//! Server-side reconciliation after a long partition merges thousands of
+//! independent documents, which is embarrassingly parallel. The functions
+//! here spread that work over the global rayon pool (configure it with
+//! `rayon::ThreadPoolBuilder`, or call them inside `ThreadPool::install`).
+//!
+//! Results are identical to merging sequentially: each document is merged
+//! by exactly one thread with the ordinary `Document::merge`.
+//!
+//! For a single large text, see `FugueText::merge_parallel`.
+
+use crate::{Document, DocumentID};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Merge every remote document into the local one with the same ID
+///
+/// Documents only present remotely are copied in. Returns the number of
+/// fields updated, summed over all documents.
+///
+/// # Example
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use synckit_core::{parallel, Document};
+///
+/// let mut local = HashMap::new();
+/// local.insert("a".to_string(), Document::new("a".to_string()));
+///
+/// let mut doc = Document::new("a".to_string());
+/// doc.set_field("title".to_string(), serde_json::json!("Hi"), 1, "c1".to_string());
+/// let mut remote = HashMap::new();
+/// remote.insert("a".to_string(), doc);
+///
+/// assert_eq!(parallel::merge_documents(&mut local, &remote), 1);
+/// ```
+pub fn merge_documents(
+    local: &mut HashMap<DocumentID, Document>,
+    remote: &HashMap<DocumentID, Document>,
+) -> usize {
+    let updated: usize = local
+        .par_iter_mut()
+        .map(|(id, document)| remote.get(id).map_or(0, |other| document.merge(other)))
+        .sum();
+
+    let mut added = 0;
+    for (id, document) in remote {
+        if !local.contains_key(id) {
+            added += document.field_count();
+            local.insert(id.clone(), document.clone());
+        }
+    }
+    updated + added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn workspace(
+        client: &str,
+        docs: usize,
+        fields: usize,
+        clock: u64,
+    ) -> HashMap<DocumentID, Document> {
+        (0..docs)
+            .map(|d| {
+                let id = format!("doc{}", d);
+                let mut document = Document::new(id.clone());
+                for f in 0..fields {
+                    // Each replica wins a different subset of fields
+                    let clock = clock + ((d + f) % 3) as u64;
+                    document.set_field(
+                        format!("f{}", f),
+                        json!(format!("{}-{}", client, f)),
+                        clock,
+                        client.to_string(),
+                    );
+                }
+                document.version.update(client, clock);
+                (id, document)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_merge_matches_sequential() {
+        let local = workspace("alice", 200, 20, 1);
+        let remote = workspace("bob", 250, 20, 2);
+
+        let mut sequential = local.clone();
+        let mut expected = 0;
+        for (id, document) in &remote {
+            match sequential.get_mut(id) {
+                Some(existing) => expected += existing.merge(document),
+                None => {
+                    expected += document.field_count();
+                    sequential.insert(id.clone(), document.clone());
+                }
+            }
+        }
+
+        let mut parallel = local;
+        assert_eq!(merge_documents(&mut parallel, &remote), expected);
+        assert_eq!(parallel.len(), sequential.len());
+        for (id, document) in &sequential {
+            let merged = &parallel[id];
+            assert_eq!(
+                serde_json::to_string(
+                    &merged
+                        .fields
+                        .iter()
+                        .collect::<std::collections::BTreeMap<_, _>>()
+                )
+                .unwrap(),
+                serde_json::to_string(
+                    &document
+                        .fields
+                        .iter()
+                        .collect::<std::collections::BTreeMap<_, _>>()
+                )
+                .unwrap()
+            );
+            assert_eq!(merged.version, document.version);
+        }
+    }
+}
@@ -3,14 +3,19 @@
 //! This module computes deltas (minimal change sets) between document states
 //! for efficient synchronization over the network.
 
+use crate::annotations::Annotation as DocAnnotation;
 use crate::document::{Document, Field as DocField};
-use crate::error::{Result, SyncError};
+use crate::error::{Result, SyncError, SyncKitError};
+use crate::list::{List, ListOp};
 use crate::protocol::*;
-use crate::sync::VectorClock;
-use std::collections::HashMap;
+use crate::refs::Ref;
+use crate::register::MVRegister;
+use crate::sync::{ChangeOrigin, SyncFilter, VectorClock};
+use crate::value_store::{ValueHash, ValueStore};
+use std::collections::{HashMap, HashSet};
 
 /// Represents a change in a single field
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct FieldChange {
     /// Path to the field (e.g., "user.name")
     pub path: String,
@@ -20,6 +25,171 @@ pub struct FieldChange {
 
     /// Whether this is a deletion
     pub is_delete: bool,
+
+    /// Set on computed deltas when the receiver already has the value:
+    /// `field.value` is then null and `apply_to` looks the value up by
+    /// this hash in the document
+    #[serde(default)]
+    pub value_ref: Option<ValueHash>,
+
+    /// Set on the changes returned by `DocumentDelta::apply_to`; computed
+    /// and decoded deltas carry the default `Remote`
+    #[serde(default)]
+    pub origin: ChangeOrigin,
+}
+
+/// Operations on a single list field
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ListChange {
+    /// Path to the list
+    pub path: String,
+
+    /// Operations the receiver lacks, in the order to apply them
+    pub ops: Vec<ListOp>,
+}
+
+/// State of a single multi-value field
+///
+/// Registers are small and merge idempotently, so they travel whole.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RegisterChange {
+    /// Path to the field
+    pub path: String,
+
+    /// The sender's register
+    pub register: MVRegister,
+}
+
+/// State of a single ref field (see [`crate::refs`])
+///
+/// A type of its own rather than a field change, so receivers can tell
+/// links from values without parsing them.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RefChange {
+    /// Path to the field
+    pub path: String,
+
+    /// The sender's ref
+    pub link: Ref,
+}
+
+/// Drop echoes of our own changes from the result of `apply_to`
+///
+/// What a change feed should do by default before notifying subscribers;
+/// callers that want every applied change just skip it.
+pub fn without_echoes(changes: Vec<FieldChange>) -> Vec<FieldChange> {
+    changes
+        .into_iter()
+        .filter(|change| !change.origin.is_echo())
+        .collect()
+}
+
+/// What applying a delta would do, from [`DocumentDelta::dry_run`]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DryRunReport {
+    /// The changes `apply_to` would return, in order, with their origins
+    pub changes: Vec<FieldChange>,
+
+    /// Fields, lists, multi-value fields and refs whose value would
+    /// change, in path order
+    pub changed_paths: Vec<String>,
+
+    /// Writes to fields that hold a different value, and who wins
+    pub conflicts: Vec<ResolvedConflict>,
+
+    /// Paths the sync filter refuses to change
+    pub refused: Vec<String>,
+
+    /// The document version once the delta is applied with
+    /// `SyncCoordinator::apply_incoming`
+    pub new_version: VectorClock,
+}
+
+/// A delta's write to a field that holds a different value, resolved by
+/// LWW
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ResolvedConflict {
+    /// Path of the field
+    pub path: String,
+
+    /// Timestamp of the value the document holds
+    pub local: crate::sync::Timestamp,
+
+    /// Timestamp of the value the delta writes
+    pub remote: crate::sync::Timestamp,
+
+    /// Whether the delta's value replaces the document's
+    pub remote_wins: bool,
+}
+
+/// What `apply_to` does, decided before changing anything
+///
+/// `apply_to` executes a plan and `dry_run` reports one, so the two can't
+/// disagree.
+#[derive(Default)]
+struct DeltaPlan<'a> {
+    /// Every allowed change, value resolved and origin set
+    fields: Vec<PlannedChange>,
+    /// Lists the delta changes, with its operations applied
+    lists: Vec<(&'a String, List)>,
+    /// Multi-value fields the delta changes, merged
+    registers: Vec<(&'a String, MVRegister)>,
+    /// Refs the delta changes
+    refs: Vec<&'a RefChange>,
+    annotations: &'a [DocAnnotation],
+    conflicts: Vec<ResolvedConflict>,
+    refused: Vec<&'a String>,
+}
+
+struct PlannedChange {
+    change: FieldChange,
+    effect: Effect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Effect {
+    Write,
+    Delete,
+    Nothing,
+}
+
+impl DeltaPlan<'_> {
+    /// Carry out the plan on the document it was made for, as one
+    /// notification batch
+    fn execute(self, document: &mut Document) -> Vec<FieldChange> {
+        document.transaction(|document| {
+            let mut applied = Vec::with_capacity(self.fields.len());
+            for PlannedChange { change, effect } in self.fields {
+                trace_debug!(field = %change.path, delete = change.is_delete, "applying change");
+                if !change.is_delete {
+                    document.record_conflict(&change.path, &change.field);
+                }
+                match effect {
+                    Effect::Write => {
+                        document
+                            .fields
+                            .insert(change.path.clone(), change.field.clone());
+                        document.mark_dirty(&change.path);
+                    }
+                    Effect::Delete => document.delete_field(&change.path),
+                    Effect::Nothing => {}
+                }
+                applied.push(change);
+            }
+            for (path, list) in self.lists {
+                *document.list_entry(path) = list;
+                document.mark_dirty(path);
+            }
+            for (path, register) in self.registers {
+                document.merge_register(path, &register);
+            }
+            for change in self.refs {
+                document.merge_ref(&change.path, &change.link);
+            }
+            document.extend_annotations(self.annotations.iter().cloned());
+            applied
+        })
+    }
 }
 
 /// A delta represents changes between two document states
@@ -31,6 +201,22 @@ pub struct DocumentDelta {
     /// Field changes
     pub changes: Vec<FieldChange>,
 
+    /// List changes, as operations rather than whole lists
+    #[serde(default)]
+    pub lists: Vec<ListChange>,
+
+    /// Multi-value fields the receiver holds differently
+    #[serde(default)]
+    pub registers: Vec<RegisterChange>,
+
+    /// Refs the receiver holds differently
+    #[serde(default)]
+    pub refs: Vec<RefChange>,
+
+    /// Annotations the receiver lacks (see [`crate::annotations`])
+    #[serde(default)]
+    pub annotations: Vec<DocAnnotation>,
+
     /// Base version (before changes)
     pub base_version: VectorClock,
 
@@ -44,19 +230,54 @@ impl DocumentDelta {
         Self {
             document_id,
             changes: Vec::new(),
+            lists: Vec::new(),
+            registers: Vec::new(),
+            refs: Vec::new(),
+            annotations: Vec::new(),
             base_version: VectorClock::new(),
             new_version: VectorClock::new(),
         }
     }
 
+    /// Check if the delta changes no field, list, multi-value field or
+    /// ref, and carries no annotation
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+            && self.lists.is_empty()
+            && self.registers.is_empty()
+            && self.refs.is_empty()
+            && self.annotations.is_empty()
+    }
+
     /// Compute delta between two documents
     ///
-    /// Returns the minimal set of changes to transform `from` into `to`
+    /// Returns the minimal set of changes to transform `from` into `to`.
+    /// Large values `from` already holds under some field are sent as a
+    /// `value_ref` only.
     pub fn compute(from: &Document, to: &Document) -> Result<Self> {
+        Self::compute_filtered(from, to, &SyncFilter::new())
+    }
+
+    /// Compute delta between two documents, leaving out the fields
+    /// `filter` rejects
+    ///
+    /// The versions are copied unfiltered, so held-back writes don't make
+    /// the receiver think it is missing data. Annotations are only sent
+    /// with a change they cover.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "DocumentDelta::compute",
+            skip_all,
+            fields(document_id = %to.id(), changes = tracing::field::Empty)
+        )
+    )]
+    pub fn compute_filtered(from: &Document, to: &Document, filter: &SyncFilter) -> Result<Self> {
         if from.id() != to.id() {
-            return Err(SyncError::InvalidOperation(
+            return Err(SyncKitError::from(SyncError::InvalidOperation(
                 "Cannot compute delta between different documents".to_string(),
-            ));
+            ))
+            .with_document(from.id().as_str()));
         }
 
         let mut delta = DocumentDelta::new(from.id().to_string());
@@ -77,6 +298,8 @@ impl DocumentDelta {
                         path: path.clone(),
                         field: to_field.clone(),
                         is_delete: false,
+                        value_ref: None,
+                        origin: ChangeOrigin::default(),
                     });
                 }
             } else {
@@ -85,6 +308,8 @@ impl DocumentDelta {
                     path: path.clone(),
                     field: to_field.clone(),
                     is_delete: false,
+                    value_ref: None,
+                    origin: ChangeOrigin::default(),
                 });
             }
         }
@@ -96,42 +321,322 @@ impl DocumentDelta {
                     path: path.clone(),
                     field: from_field.clone(),
                     is_delete: true,
+                    value_ref: None,
+                    origin: ChangeOrigin::default(),
+                });
+            }
+        }
+
+        for (path, to_list) in to.lists() {
+            let ops = to_list.ops_since(from.list(path));
+            if !ops.is_empty() {
+                delta.lists.push(ListChange {
+                    path: path.clone(),
+                    ops,
+                });
+            }
+        }
+        delta.lists.sort_by(|a, b| a.path.cmp(&b.path));
+
+        for (path, to_register) in to.registers() {
+            if from.register(path) != Some(to_register) {
+                delta.registers.push(RegisterChange {
+                    path: path.clone(),
+                    register: to_register.clone(),
                 });
             }
         }
+        delta.registers.sort_by(|a, b| a.path.cmp(&b.path));
 
+        for (path, to_ref) in to.refs() {
+            if from.refs().get(path) != Some(to_ref) {
+                delta.refs.push(RefChange {
+                    path: path.clone(),
+                    link: to_ref.clone(),
+                });
+            }
+        }
+        delta.refs.sort_by(|a, b| a.path.cmp(&b.path));
+
+        delta.annotations = to.annotations().missing_from(from.annotations());
+        if !filter.is_empty() {
+            delta.changes.retain(|change| filter.allows(&change.path));
+            delta.lists.retain(|change| filter.allows(&change.path));
+            delta.registers.retain(|change| filter.allows(&change.path));
+            delta.refs.retain(|change| filter.allows(&change.path));
+            let changes = &delta.changes;
+            delta.annotations.retain(|annotation| {
+                changes.iter().any(|change| {
+                    let timestamp = &change.field.timestamp;
+                    annotation.covers(&timestamp.client_id, timestamp.clock)
+                })
+            });
+        }
+        refer_to_known_values(&mut delta.changes, from);
+
+        trace_record!("changes", delta.changes.len());
         Ok(delta)
     }
 
     /// Apply this delta to a document
-    pub fn apply_to(&self, document: &mut Document, _client_id: &str) -> Result<()> {
+    ///
+    /// Returns every change tagged with its origin relative to
+    /// `client_id`: a change authored by `client_id` that left the field
+    /// as it was is an `Echo` (our own delta coming back from the server).
+    /// The author of a change is the field's timestamp client; for a
+    /// deletion that is the last writer of the deleted field.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `PROTOCOL_ERROR`, changing nothing, if a `value_ref`
+    /// names a value the document doesn't hold, or a list operation an
+    /// item or position it doesn't have (it is no longer the state the
+    /// delta was computed from); fall back to a full sync
+    pub fn apply_to(&self, document: &mut Document, client_id: &str) -> Result<Vec<FieldChange>> {
+        self.apply_to_filtered(document, client_id, &SyncFilter::new())
+    }
+
+    /// Apply this delta to a document, refusing changes to the paths
+    /// `filter` rejects
+    ///
+    /// Refused changes are skipped and left out of the result.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "DocumentDelta::apply_to",
+            skip_all,
+            fields(document_id = %self.document_id, changes = self.changes.len())
+        )
+    )]
+    pub fn apply_to_filtered(
+        &self,
+        document: &mut Document,
+        client_id: &str,
+        filter: &SyncFilter,
+    ) -> Result<Vec<FieldChange>> {
+        let plan = self.plan(document, client_id, filter)?;
+        Ok(plan.execute(document))
+    }
+
+    /// Work out what `apply_to` would do to `target`, without changing it
+    ///
+    /// `apply_to` carries out exactly this plan, so the report's `changes`
+    /// are what it would return. Nothing is copied but the changed values
+    /// and the lists the delta edits.
+    ///
+    /// # Errors
+    ///
+    /// Fails exactly when `apply_to` would (see there)
+    pub fn dry_run(&self, target: &Document, client_id: &str) -> Result<DryRunReport> {
+        self.dry_run_filtered(target, client_id, &SyncFilter::new())
+    }
+
+    /// Like [`DocumentDelta::dry_run`], for `apply_to_filtered`
+    pub fn dry_run_filtered(
+        &self,
+        target: &Document,
+        client_id: &str,
+        filter: &SyncFilter,
+    ) -> Result<DryRunReport> {
+        let plan = self.plan(target, client_id, filter)?;
+        let mut changed_paths: Vec<String> = plan
+            .fields
+            .iter()
+            .filter(|planned| planned.effect != Effect::Nothing)
+            .map(|planned| planned.change.path.clone())
+            .chain(plan.lists.iter().map(|(path, _)| path.to_string()))
+            .chain(plan.registers.iter().map(|(path, _)| path.to_string()))
+            .chain(plan.refs.iter().map(|change| change.path.clone()))
+            .collect();
+        changed_paths.sort();
+        changed_paths.dedup();
+
+        let mut new_version = target.version().clone();
+        new_version.merge(&self.new_version);
+        Ok(DryRunReport {
+            changes: plan
+                .fields
+                .into_iter()
+                .map(|planned| planned.change)
+                .collect(),
+            changed_paths,
+            conflicts: plan.conflicts,
+            refused: plan.refused.into_iter().cloned().collect(),
+            new_version,
+        })
+    }
+
+    /// Decide what each change does to `document`
+    ///
+    /// Fails, before anything is changed, if a `value_ref` or list
+    /// operation doesn't fit the document.
+    fn plan(
+        &self,
+        document: &Document,
+        client_id: &str,
+        filter: &SyncFilter,
+    ) -> Result<DeltaPlan<'_>> {
         if document.id() != &self.document_id {
-            return Err(SyncError::InvalidOperation(
+            return Err(SyncKitError::from(SyncError::InvalidOperation(
                 "Cannot apply delta to different document".to_string(),
-            ));
+            ))
+            .with_document(self.document_id.as_str()));
         }
 
-        for change in &self.changes {
-            if !change.is_delete {
-                // Use the field's original timestamp
-                let clock = change.field.timestamp.clock;
-                let original_client = &change.field.timestamp.client_id;
-                document.set_field(
-                    change.path.clone(),
-                    change.field.value.clone(),
-                    clock,
-                    original_client.clone(),
-                );
+        let resolved = self.resolve_value_refs(document, filter)?;
+        let mut plan = DeltaPlan {
+            annotations: &self.annotations,
+            ..DeltaPlan::default()
+        };
+        // Where earlier changes in the delta left a path: the index of
+        // the planned write, or None if deleted
+        let mut written: HashMap<&str, Option<usize>> = HashMap::new();
+        for original in &self.changes {
+            let path = original.path.as_str();
+            if !filter.allows(path) {
+                trace_debug!(field = %path, "refusing filtered change");
+                plan.refused.push(&original.path);
+                continue;
+            }
+            let mut change = original.clone();
+            if let Some(hash) = change.value_ref.take() {
+                change.field.value = resolved[&hash].clone();
+            }
+
+            let current = match written.get(path) {
+                Some(Some(index)) => Some(&plan.fields[*index].change.field),
+                Some(None) => None,
+                None => document.fields().get(path),
+            };
+            let effect = if change.is_delete {
+                match current {
+                    Some(_) => Effect::Delete,
+                    None => Effect::Nothing,
+                }
             } else {
-                document.delete_field(&change.path);
+                let wins = current.is_none_or(|local| change.field.wins_over(local));
+                if let Some(local) = current.filter(|local| local.value != change.field.value) {
+                    plan.conflicts.push(ResolvedConflict {
+                        path: change.path.clone(),
+                        local: local.timestamp.clone(),
+                        remote: change.field.timestamp.clone(),
+                        remote_wins: wins,
+                    });
+                }
+                if wins {
+                    Effect::Write
+                } else {
+                    Effect::Nothing
+                }
+            };
+
+            change.origin = ChangeOrigin::classify(
+                &change.field.timestamp.client_id,
+                client_id,
+                effect != Effect::Nothing,
+            );
+            match effect {
+                Effect::Write => {
+                    written.insert(path, Some(plan.fields.len()));
+                }
+                Effect::Delete => {
+                    written.insert(path, None);
+                }
+                Effect::Nothing => {}
+            }
+            plan.fields.push(PlannedChange { change, effect });
+        }
+
+        // List operations go to copies of the lists they edit
+        for change in &self.lists {
+            if !filter.allows(&change.path) {
+                trace_debug!(field = %change.path, "refusing filtered list change");
+                plan.refused.push(&change.path);
+                continue;
+            }
+            let mut list = document.list(&change.path).cloned().unwrap_or_default();
+            let changed = list.apply_ops(&change.ops).map_err(|error| {
+                error
+                    .with_document(self.document_id.as_str())
+                    .with_path(change.path.as_str())
+            })?;
+            if changed {
+                plan.lists.push((&change.path, list));
+            }
+        }
+
+        for change in &self.registers {
+            if !filter.allows(&change.path) {
+                trace_debug!(field = %change.path, "refusing filtered register change");
+                plan.refused.push(&change.path);
+                continue;
+            }
+            let mut register = document.register(&change.path).cloned().unwrap_or_default();
+            if register.merge(&change.register) {
+                plan.registers.push((&change.path, register));
             }
         }
 
-        Ok(())
+        for change in &self.refs {
+            if !filter.allows(&change.path) {
+                trace_debug!(field = %change.path, "refusing filtered ref change");
+                plan.refused.push(&change.path);
+                continue;
+            }
+            let wins = document
+                .refs()
+                .get(&change.path)
+                .is_none_or(|local| change.link.wins_over(local));
+            if wins {
+                plan.refs.push(change);
+            }
+        }
+        Ok(plan)
     }
 
-    /// Convert to protocol format
+    /// Look up the values the allowed changes refer to by hash
+    fn resolve_value_refs(
+        &self,
+        document: &Document,
+        filter: &SyncFilter,
+    ) -> Result<HashMap<ValueHash, serde_json::Value>> {
+        let refs = || {
+            self.changes
+                .iter()
+                .filter(|change| filter.allows(&change.path))
+                .filter_map(|change| change.value_ref)
+        };
+        let mut wanted: HashSet<ValueHash> = refs().collect();
+        let mut resolved = HashMap::with_capacity(wanted.len());
+        for field in document.fields().values() {
+            if wanted.is_empty() {
+                break;
+            }
+            if let Some(hash) = ValueHash::of_shareable(&field.value) {
+                if wanted.remove(&hash) {
+                    resolved.insert(hash, field.value.clone());
+                }
+            }
+        }
+
+        match refs().find(|hash| !resolved.contains_key(hash)) {
+            Some(hash) => Err(SyncKitError::from(SyncError::Protocol(format!(
+                "delta refers to value {} this document doesn't have",
+                hash
+            )))
+            .with_document(self.document_id.as_str())),
+            None => Ok(resolved),
+        }
+    }
+
+    /// Convert to protocol format, stamping tombstones with the thread's
+    /// default clock (see [`crate::time`])
     pub fn to_protocol(&self) -> Delta {
+        self.to_protocol_at(crate::time::now_ms())
+    }
+
+    /// Convert to protocol format, stamping tombstones with `now_ms`
+    pub fn to_protocol_at(&self, now_ms: u64) -> Delta {
         let changes = self
             .changes
             .iter()
@@ -147,10 +652,12 @@ impl DocumentDelta {
                             id: change.field.timestamp.client_id.clone(),
                         }),
                     }),
-                    content: if change.is_delete {
+                    content: if let Some(hash) = change.value_ref {
+                        Some(field::Content::ValueRef(hash.to_bytes().to_vec()))
+                    } else if change.is_delete {
                         Some(field::Content::Tombstone(Tombstone {
                             deleted_at: Some(Timestamp {
-                                millis: chrono::Utc::now().timestamp_millis(),
+                                millis: now_ms as i64,
                                 client_id: Some(ClientId {
                                     id: change.field.timestamp.client_id.clone(),
                                 }),
@@ -174,6 +681,18 @@ impl DocumentDelta {
             changes,
             client_id: None,
             created_at: None,
+            lists: self.lists.iter().map(list_change_to_protocol).collect(),
+            registers: self
+                .registers
+                .iter()
+                .map(register_change_to_protocol)
+                .collect(),
+            refs: self.refs.iter().map(ref_change_to_protocol).collect(),
+            annotations: self
+                .annotations
+                .iter()
+                .map(annotation_to_protocol)
+                .collect(),
         }
     }
 
@@ -230,25 +749,196 @@ impl DocumentDelta {
                     serde_json::Value::Null
                 };
 
+                let value_ref = match &field.content {
+                    Some(field::Content::ValueRef(bytes)) => {
+                        Some(ValueHash::from_bytes(bytes).ok_or_else(|| {
+                            SyncError::Protocol("Malformed value_ref".to_string())
+                        })?)
+                    }
+                    _ => None,
+                };
+
                 Ok(FieldChange {
                     path,
                     field: DocField { value, timestamp },
                     is_delete,
+                    value_ref,
+                    origin: ChangeOrigin::default(),
                 })
             })
             .collect::<Result<Vec<_>>>()?;
 
+        let lists = proto
+            .lists
+            .iter()
+            .map(list_change_from_protocol)
+            .collect::<Result<Vec<_>>>()?;
+
+        let registers = proto
+            .registers
+            .iter()
+            .map(register_change_from_protocol)
+            .collect::<Result<Vec<_>>>()?;
+
+        let refs = proto
+            .refs
+            .iter()
+            .map(ref_change_from_protocol)
+            .collect::<Result<Vec<_>>>()?;
+
+        let annotations = proto
+            .annotations
+            .iter()
+            .map(annotation_from_protocol)
+            .collect::<Result<Vec<_>>>()?;
+
         Ok(Self {
             document_id,
             changes,
+            lists,
+            registers,
+            refs,
+            annotations,
             base_version,
             new_version,
         })
     }
 }
 
+pub(crate) fn list_change_to_protocol(change: &ListChange) -> crate::protocol::ListChange {
+    crate::protocol::ListChange {
+        path: change.path.clone(),
+        // Serializing plain data to JSON can't fail
+        ops: serde_json::to_vec(&change.ops).unwrap_or_default(),
+    }
+}
+
+pub(crate) fn list_change_from_protocol(proto: &crate::protocol::ListChange) -> Result<ListChange> {
+    let ops = serde_json::from_slice(&proto.ops).map_err(|e| {
+        SyncKitError::from(SyncError::Protocol(format!(
+            "Malformed list operations: {}",
+            e
+        )))
+        .with_path(proto.path.as_str())
+    })?;
+    Ok(ListChange {
+        path: proto.path.clone(),
+        ops,
+    })
+}
+
+pub(crate) fn register_change_to_protocol(
+    change: &RegisterChange,
+) -> crate::protocol::RegisterChange {
+    crate::protocol::RegisterChange {
+        path: change.path.clone(),
+        // Serializing plain data to JSON can't fail
+        register: serde_json::to_vec(&change.register).unwrap_or_default(),
+    }
+}
+
+pub(crate) fn register_change_from_protocol(
+    proto: &crate::protocol::RegisterChange,
+) -> Result<RegisterChange> {
+    let register = serde_json::from_slice(&proto.register).map_err(|e| {
+        SyncKitError::from(SyncError::Protocol(format!(
+            "Malformed register state: {}",
+            e
+        )))
+        .with_path(proto.path.as_str())
+    })?;
+    Ok(RegisterChange {
+        path: proto.path.clone(),
+        register,
+    })
+}
+
+pub(crate) fn ref_change_to_protocol(change: &RefChange) -> crate::protocol::RefChange {
+    let timestamp = &change.link.timestamp;
+    crate::protocol::RefChange {
+        path: change.path.clone(),
+        target: change.link.target.clone().unwrap_or_default(),
+        timestamp: Some(Timestamp {
+            millis: timestamp.clock as i64,
+            client_id: Some(ClientId {
+                id: timestamp.client_id.clone(),
+            }),
+        }),
+    }
+}
+
+pub(crate) fn ref_change_from_protocol(proto: &crate::protocol::RefChange) -> Result<RefChange> {
+    let timestamp = proto
+        .timestamp
+        .as_ref()
+        .and_then(|timestamp| {
+            let client_id = timestamp.client_id.as_ref()?.id.clone();
+            Some(crate::sync::Timestamp::new(
+                timestamp.millis as u64,
+                client_id,
+            ))
+        })
+        .ok_or_else(|| {
+            SyncKitError::from(SyncError::Protocol("Missing ref timestamp".to_string()))
+                .with_path(proto.path.as_str())
+        })?;
+    Ok(RefChange {
+        path: proto.path.clone(),
+        link: Ref {
+            target: Some(proto.target.clone()).filter(|target| !target.is_empty()),
+            timestamp,
+        },
+    })
+}
+
+pub(crate) fn annotation_to_protocol(annotation: &DocAnnotation) -> crate::protocol::Annotation {
+    crate::protocol::Annotation {
+        client_id: annotation.client_id.clone(),
+        start: annotation.start,
+        end: annotation.end,
+        payload: annotation.payload.to_string().into_bytes(),
+        created_ms: annotation.created_ms,
+    }
+}
+
+pub(crate) fn annotation_from_protocol(
+    proto: &crate::protocol::Annotation,
+) -> Result<DocAnnotation> {
+    let payload = serde_json::from_slice(&proto.payload).map_err(|e| {
+        SyncKitError::from(SyncError::Protocol(format!(
+            "Malformed annotation payload: {}",
+            e
+        )))
+    })?;
+    DocAnnotation::check_payload(&payload)?;
+    Ok(DocAnnotation {
+        client_id: proto.client_id.clone(),
+        start: proto.start,
+        end: proto.end,
+        payload,
+        created_ms: proto.created_ms,
+    })
+}
+
+/// Send large values `from` already holds by hash only
+fn refer_to_known_values(changes: &mut [FieldChange], from: &Document) {
+    let mut known = None;
+    for change in changes.iter_mut().filter(|change| !change.is_delete) {
+        let Some(hash) = ValueHash::of_shareable(&change.field.value) else {
+            continue;
+        };
+        let known = known.get_or_insert_with(|| {
+            ValueStore::from_values(from.fields().values().map(|field| &field.value))
+        });
+        if known.get(&hash) == Some(&change.field.value) {
+            change.field.value = serde_json::Value::Null;
+            change.value_ref = Some(hash);
+        }
+    }
+}
+
 /// Convert VectorClock to protocol format
-fn vector_clock_to_protocol(vc: &VectorClock) -> crate::protocol::VectorClock {
+pub(crate) fn vector_clock_to_protocol(vc: &VectorClock) -> crate::protocol::VectorClock {
     let mut clocks = HashMap::new();
     for (client_id, clock) in &vc.clocks {
         clocks.insert(client_id.clone(), *clock as i64);
@@ -258,7 +948,7 @@ fn vector_clock_to_protocol(vc: &VectorClock) -> crate::protocol::VectorClock {
 }
 
 /// Convert protocol VectorClock to internal format
-fn vector_clock_from_protocol(proto: &crate::protocol::VectorClock) -> VectorClock {
+pub(crate) fn vector_clock_from_protocol(proto: &crate::protocol::VectorClock) -> VectorClock {
     let mut vc = VectorClock::new();
     for (client_id, clock) in &proto.clocks {
         vc.update(client_id, *clock as u64);
@@ -266,6 +956,252 @@ fn vector_clock_from_protocol(proto: &crate::protocol::VectorClock) -> VectorClo
     vc
 }
 
+/// A document's ID and version as a protobuf `DocumentVersion`
+pub(crate) fn document_version_to_protocol(id: &str, version: &VectorClock) -> DocumentVersion {
+    DocumentVersion {
+        document_id: Some(DocumentId { id: id.to_string() }),
+        version: Some(vector_clock_to_protocol(version)),
+    }
+}
+
+/// Versions by document ID; entries without an ID are skipped
+pub(crate) fn version_summary_from_protocol(
+    summary: &VersionSummary,
+) -> HashMap<String, VectorClock> {
+    summary
+        .documents
+        .iter()
+        .filter_map(|entry| {
+            let id = entry.document_id.as_ref()?.id.clone();
+            let version = entry
+                .version
+                .as_ref()
+                .map(vector_clock_from_protocol)
+                .unwrap_or_default();
+            Some((id, version))
+        })
+        .collect()
+}
+
+#[cfg(feature = "text-crdt")]
+use crate::crdt::text_fugue::{
+    Anchor, AnchorBias, DeleteRange, FugueBlock, FugueText, Mark, NodeId, TextDelta, TextEvent,
+};
+
+#[cfg(feature = "text-crdt")]
+impl FugueText {
+    /// Encode this replica's state vector to request a delta from a peer
+    pub fn encode_state_vector(&self) -> Vec<u8> {
+        prost::Message::encode_to_vec(&vector_clock_to_protocol(&self.state_vector()))
+    }
+
+    /// Encode the delta a peer with the given (encoded) state vector is missing
+    ///
+    /// Holds only the blocks the peer hasn't seen, plus the delete set as
+    /// coalesced clock ranges, so a small edit to a large text stays small.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "FugueText::encode_diff",
+            skip_all,
+            fields(
+                client_id = %self.client_id(),
+                blocks = tracing::field::Empty,
+                bytes = tracing::field::Empty,
+            )
+        )
+    )]
+    pub fn encode_diff(&self, remote_state_vector: &[u8]) -> Result<Vec<u8>> {
+        let proto: crate::protocol::VectorClock =
+            crate::protocol::serialize::decode_message(remote_state_vector)?;
+        let delta = self.diff_since(&vector_clock_from_protocol(&proto));
+
+        let bytes = prost::Message::encode_to_vec(&text_delta_to_protocol(&delta));
+        trace_record!("blocks", delta.blocks.len());
+        trace_record!("bytes", bytes.len());
+        Ok(bytes)
+    }
+
+    /// Decode and apply a delta produced by `encode_diff`
+    ///
+    /// Returns the resulting changes to the visible text.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "FugueText::apply_diff",
+            skip_all,
+            fields(client_id = %self.client_id(), bytes = bytes.len())
+        )
+    )]
+    pub fn apply_diff(&mut self, bytes: &[u8]) -> Result<Vec<TextEvent>> {
+        let proto: TextBlockDelta = crate::protocol::serialize::decode_message(bytes)?;
+        let delta = text_delta_from_protocol(&proto)?;
+
+        Ok(self.apply_delta(&delta)?)
+    }
+}
+
+/// Convert TextDelta to protocol format
+#[cfg(feature = "text-crdt")]
+fn text_delta_to_protocol(delta: &TextDelta) -> TextBlockDelta {
+    TextBlockDelta {
+        blocks: delta
+            .blocks
+            .iter()
+            .map(|block| TextBlock {
+                id: Some(text_node_id_to_protocol(&block.id)),
+                text: block.text.to_string(),
+                left_origin: block.left_origin.as_ref().map(text_node_id_to_protocol),
+                right_origin: block.right_origin.as_ref().map(text_node_id_to_protocol),
+                deleted: block.is_deleted(),
+                deleted_at: block.deleted_at.as_ref().map(text_node_id_to_protocol),
+            })
+            .collect(),
+        deleted: delta
+            .deleted
+            .iter()
+            .map(|range| TextDeleteRange {
+                client_id: range.client_id.to_string(),
+                start: range.start,
+                end: range.end,
+                deleted_at: range.deleted_at.as_ref().map(text_node_id_to_protocol),
+            })
+            .collect(),
+        clock: delta.clock,
+        annotations: delta
+            .annotations
+            .iter()
+            .map(annotation_to_protocol)
+            .collect(),
+        marks: delta.marks.iter().map(text_mark_to_protocol).collect(),
+    }
+}
+
+/// Convert protocol TextBlockDelta to internal format
+#[cfg(feature = "text-crdt")]
+fn text_delta_from_protocol(proto: &TextBlockDelta) -> Result<TextDelta> {
+    let blocks = proto
+        .blocks
+        .iter()
+        .map(|block| {
+            let id = block
+                .id
+                .as_ref()
+                .map(text_node_id_from_protocol)
+                .ok_or_else(|| SyncError::Protocol("Missing text block ID".to_string()))?;
+
+            let mut fugue_block = FugueBlock::new(
+                id,
+                block.text.clone(),
+                block.left_origin.as_ref().map(text_node_id_from_protocol),
+                block.right_origin.as_ref().map(text_node_id_from_protocol),
+            );
+            if block.deleted {
+                match &block.deleted_at {
+                    Some(stamp) => fugue_block.mark_deleted_at(text_node_id_from_protocol(stamp)),
+                    None => fugue_block.mark_deleted(),
+                }
+            }
+
+            Ok(fugue_block)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let deleted = proto
+        .deleted
+        .iter()
+        .map(|range| DeleteRange {
+            client_id: range.client_id.as_str().into(),
+            start: range.start,
+            end: range.end,
+            deleted_at: range.deleted_at.as_ref().map(text_node_id_from_protocol),
+        })
+        .collect();
+
+    let annotations = proto
+        .annotations
+        .iter()
+        .map(annotation_from_protocol)
+        .collect::<Result<Vec<_>>>()?;
+
+    let marks = proto
+        .marks
+        .iter()
+        .map(text_mark_from_protocol)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(TextDelta {
+        blocks,
+        deleted,
+        clock: proto.clock,
+        annotations,
+        marks,
+    })
+}
+
+#[cfg(feature = "text-crdt")]
+fn text_node_id_to_protocol(id: &NodeId) -> TextNodeId {
+    TextNodeId {
+        client_id: id.client_id.to_string(),
+        clock: id.clock,
+        offset: id.offset as u64,
+    }
+}
+
+#[cfg(feature = "text-crdt")]
+fn text_node_id_from_protocol(proto: &TextNodeId) -> NodeId {
+    NodeId::new(proto.client_id.clone(), proto.clock, proto.offset as usize)
+}
+
+#[cfg(feature = "text-crdt")]
+pub(crate) fn text_mark_to_protocol(mark: &Mark) -> TextMark {
+    let anchor = |anchor: &Anchor| TextAnchor {
+        target: anchor.target.as_ref().map(text_node_id_to_protocol),
+        right: anchor.bias == AnchorBias::Right,
+    };
+    TextMark {
+        client_id: mark.client_id.clone(),
+        clock: mark.clock,
+        start: Some(anchor(&mark.start)),
+        end: Some(anchor(&mark.end)),
+        key: mark.key.clone(),
+        value: mark
+            .value
+            .as_ref()
+            .map(|value| value.to_string().into_bytes()),
+    }
+}
+
+#[cfg(feature = "text-crdt")]
+pub(crate) fn text_mark_from_protocol(proto: &TextMark) -> Result<Mark> {
+    let anchor = |anchor: &Option<TextAnchor>| {
+        let anchor = anchor
+            .as_ref()
+            .ok_or_else(|| SyncError::Protocol("Missing text mark anchor".to_string()))?;
+        Ok::<_, SyncError>(Anchor {
+            target: anchor.target.as_ref().map(text_node_id_from_protocol),
+            bias: match anchor.right {
+                true => AnchorBias::Right,
+                false => AnchorBias::Left,
+            },
+        })
+    };
+    let value = proto
+        .value
+        .as_ref()
+        .map(|value| serde_json::from_slice(value))
+        .transpose()
+        .map_err(|e| SyncError::Protocol(format!("Malformed text mark value: {}", e)))?;
+    Ok(Mark {
+        client_id: proto.client_id.clone(),
+        clock: proto.clock,
+        start: anchor(&proto.start)?,
+        end: anchor(&proto.end)?,
+        key: proto.key.clone(),
+        value,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -306,6 +1242,185 @@ mod tests {
         assert_eq!(delta.changes.len(), 2);
     }
 
+    #[test]
+    fn test_known_values_ship_as_hash() {
+        let template = serde_json::json!({ "body": "lorem ipsum ".repeat(1000) });
+        let mut from = Document::new("doc-1".to_string());
+        from.set_field("a".to_string(), template.clone(), 1, "c1".to_string());
+        let mut to = from.clone();
+        to.set_field("b".to_string(), template.clone(), 2, "c1".to_string());
+
+        let delta = DocumentDelta::compute(&from, &to).unwrap();
+        assert!(delta.changes[0].value_ref.is_some());
+        let size = serde_json::to_vec(&delta).unwrap().len();
+        assert!(size < 1_000, "{} bytes", size);
+
+        // Through protobuf too
+        let delta = DocumentDelta::from_protocol(&delta.to_protocol(), "c1").unwrap();
+        let mut receiver = from.clone();
+        let applied = delta.apply_to(&mut receiver, "c2").unwrap();
+        assert_eq!(applied[0].field.value, template);
+        assert_eq!(applied[0].value_ref, None);
+        assert_eq!(receiver.fields(), to.fields());
+
+        // A receiver without the value can't resolve it
+        let mut stranger = Document::new("doc-1".to_string());
+        let error = delta.apply_to(&mut stranger, "c2").unwrap_err();
+        assert_eq!(error.code_name(), "PROTOCOL_ERROR");
+        assert!(stranger.is_empty());
+    }
+
+    #[test]
+    fn test_multi_value_fields_travel_with_the_delta() {
+        use serde_json::json;
+
+        let path = "title".to_string();
+        let mut alice = Document::new("doc-1".to_string());
+        let mut bob = alice.clone();
+        alice.set_multi_field(path.clone(), json!("Draft"), 1, "alice".to_string());
+        bob.set_multi_field(path.clone(), json!("Final"), 1, "bob".to_string());
+
+        let delta = DocumentDelta::compute(&Document::new("doc-1".to_string()), &alice).unwrap();
+        let delta = DocumentDelta::from_protocol(&delta.to_protocol(), "alice").unwrap();
+        assert_eq!(delta.registers.len(), 1);
+        let report = delta.dry_run(&bob, "bob").unwrap();
+        assert_eq!(report.changed_paths, vec![path.clone()]);
+        delta.apply_to(&mut bob, "bob").unwrap();
+        assert_eq!(bob.get_all(&path).len(), 2);
+
+        bob.resolve(&path, json!("Final"), 2, "bob".to_string())
+            .unwrap();
+        let delta = DocumentDelta::compute(&alice, &bob).unwrap();
+        delta.apply_to(&mut alice, "alice").unwrap();
+        assert_eq!(alice.register(&path), bob.register(&path));
+        assert_eq!(alice.to_json()["title"], json!("Final"));
+        assert!(DocumentDelta::compute(&alice, &bob).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_refs_travel_as_refs() {
+        let path = "assignee".to_string();
+        let mut alice = Document::new("task".to_string());
+        let mut bob = alice.clone();
+        alice.set_ref(path.clone(), "user-1".to_string(), 2, "alice".to_string());
+        alice.clear_ref("reviewer".to_string(), 1, "alice".to_string());
+        bob.set_ref(path.clone(), "user-2".to_string(), 1, "bob".to_string());
+
+        let delta = DocumentDelta::compute(&bob, &alice).unwrap();
+        let delta = DocumentDelta::from_protocol(&delta.to_protocol(), "alice").unwrap();
+        assert!(delta.changes.is_empty());
+        assert_eq!(delta.refs.len(), 2);
+        assert_eq!(delta.refs[1].link.target, None);
+        let report = delta.dry_run(&bob, "bob").unwrap();
+        assert_eq!(
+            report.changed_paths,
+            vec![path.clone(), "reviewer".to_string()]
+        );
+        delta.apply_to(&mut bob, "bob").unwrap();
+        assert_eq!(bob.refs(), alice.refs());
+        assert_eq!(bob.get_ref(&path), Some(&"user-1".to_string()));
+
+        // The older write loses on the way back
+        let stale = DocumentDelta::compute(&Document::new("task".to_string()), &bob).unwrap();
+        let mut older = Document::new("task".to_string());
+        older.set_ref(path.clone(), "user-3".to_string(), 9, "carol".to_string());
+        let report = stale.dry_run(&older, "carol").unwrap();
+        assert_eq!(report.changed_paths, vec!["reviewer".to_string()]);
+    }
+
+    #[test]
+    fn test_annotations_travel_with_the_delta() {
+        use crate::annotations::{AnnotationRetention, MAX_ANNOTATION_BYTES};
+        use serde_json::json;
+
+        let mut alice = Document::new("doc-1".to_string());
+        let mut bob = alice.clone();
+        alice
+            .annotated_transaction(json!({"tag": "bulk-edit"}), |doc| {
+                doc.set_field("a".to_string(), json!(1), 1, "alice".to_string());
+                doc.set_field("b".to_string(), json!(2), 2, "alice".to_string());
+            })
+            .unwrap();
+        alice.set_field("c".to_string(), json!(3), 3, "alice".to_string());
+
+        let delta = DocumentDelta::compute(&bob, &alice).unwrap();
+        let delta = DocumentDelta::from_protocol(&delta.to_protocol(), "alice").unwrap();
+        assert_eq!(delta.annotations.len(), 1);
+        delta.apply_to(&mut bob, "bob").unwrap();
+        let annotation = bob.annotations_for(&"b".to_string()).unwrap();
+        assert_eq!(annotation.payload, json!({"tag": "bulk-edit"}));
+        assert_eq!((annotation.start, annotation.end), (1, 2));
+        assert!(bob.annotations_for(&"c".to_string()).is_none());
+        assert!(DocumentDelta::compute(&bob, &alice).unwrap().is_empty());
+
+        // Overwriting a field leaves its old annotation behind
+        bob.set_field("a".to_string(), json!(10), 5, "bob".to_string());
+        assert!(bob.annotations_for(&"a".to_string()).is_none());
+
+        // Survives a snapshot, and the retention policy drops it
+        let mut restored: Document =
+            serde_json::from_str(&serde_json::to_string(&bob).unwrap()).unwrap();
+        assert_eq!(restored.annotations(), alice.annotations());
+        let retention = AnnotationRetention {
+            max_count: Some(0),
+            ..AnnotationRetention::default()
+        };
+        assert_eq!(restored.prune_annotations(&retention), 1);
+        assert!(restored.annotations_for(&"b".to_string()).is_none());
+
+        let oversized = json!("x".repeat(MAX_ANNOTATION_BYTES));
+        let error = alice
+            .annotated_transaction(oversized, |doc| {
+                doc.set_field("d".to_string(), json!(4), 4, "alice".to_string());
+            })
+            .unwrap_err();
+        assert_eq!(error.code_name(), "ANNOTATION_TOO_LARGE");
+        assert!(alice.get_field(&"d".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_dry_run_reports_conflicts_and_refusals() {
+        use serde_json::json;
+
+        let mut base = Document::new("doc-1".to_string());
+        base.set_field("title".to_string(), json!("Draft"), 1, "alice".to_string());
+        base.set_field(
+            "_local.cursor".to_string(),
+            json!(3),
+            1,
+            "alice".to_string(),
+        );
+        let mut remote = base.clone();
+        remote.set_field("title".to_string(), json!("Final"), 3, "bob".to_string());
+        remote.set_field("_local.cursor".to_string(), json!(9), 3, "bob".to_string());
+        remote.delete_field(&"missing".to_string());
+        remote.version.update("bob", 3);
+        let delta = DocumentDelta::compute(&base, &remote).unwrap();
+
+        let mut target = base.clone();
+        target.set_field("title".to_string(), json!("Mine"), 5, "alice".to_string());
+        let filter = SyncFilter::new().exclude("_local.*");
+        let report = delta.dry_run_filtered(&target, "alice", &filter).unwrap();
+        assert_eq!(report.refused, vec!["_local.cursor"]);
+        assert!(report.changed_paths.is_empty());
+        assert_eq!(
+            report.conflicts,
+            vec![ResolvedConflict {
+                path: "title".to_string(),
+                local: crate::sync::Timestamp::new(5, "alice".to_string()),
+                remote: crate::sync::Timestamp::new(3, "bob".to_string()),
+                remote_wins: false,
+            }]
+        );
+        assert_eq!(report.new_version.get("bob"), 3);
+
+        let applied = delta
+            .apply_to_filtered(&mut target, "alice", &filter)
+            .unwrap();
+        assert_eq!(applied, report.changes);
+        assert_eq!(target.get_field(&"title".to_string()), Some(&json!("Mine")));
+    }
+
     #[test]
     fn test_delta_protocol_conversion() {
         let mut doc1 = Document::new("doc-1".to_string());
@@ -333,4 +1448,200 @@ mod tests {
         assert_eq!(delta.document_id, delta2.document_id);
         assert_eq!(delta.changes.len(), delta2.changes.len());
     }
+
+    #[test]
+    fn test_author_sees_no_echo_notifications() {
+        let id = "doc1".to_string();
+        let mut alice = Document::new(id.clone());
+        let mut bob = Document::new(id.clone());
+        let mut server = Document::new(id.clone());
+        let mut notified: HashMap<&str, Vec<FieldChange>> = HashMap::new();
+
+        // Alice edits, sends her delta up, the server broadcasts it to all
+        let before = alice.clone();
+        alice.set_field(
+            "title".to_string(),
+            serde_json::json!("Hi"),
+            1,
+            "alice".to_string(),
+        );
+        let upload = DocumentDelta::compute(&before, &alice).unwrap();
+        let server_before = server.clone();
+        upload.apply_to(&mut server, "server").unwrap();
+        let broadcast = DocumentDelta::compute(&server_before, &server).unwrap();
+
+        for (client, doc) in [("alice", &mut alice), ("bob", &mut bob)] {
+            let changes = broadcast.apply_to(doc, client).unwrap();
+            notified
+                .entry(client)
+                .or_default()
+                .extend(without_echoes(changes));
+        }
+
+        assert!(notified["alice"].is_empty());
+        assert_eq!(notified["bob"].len(), 1);
+        assert_eq!(notified["bob"][0].origin, ChangeOrigin::Remote);
+
+        // Opting out of the filter still shows the echo, tagged
+        let changes = broadcast.apply_to(&mut alice, "alice").unwrap();
+        assert_eq!(changes[0].origin, ChangeOrigin::Echo);
+    }
+
+    #[test]
+    fn test_local_author_with_effect_is_local() {
+        let mut doc1 = Document::new("doc1".to_string());
+        let doc2 = {
+            let mut doc = doc1.clone();
+            doc.set_field(
+                "n".to_string(),
+                serde_json::json!(1),
+                1,
+                "alice".to_string(),
+            );
+            doc
+        };
+        // e.g. alice restored an older snapshot of her own document
+        let delta = DocumentDelta::compute(&doc1, &doc2).unwrap();
+        let changes = delta.apply_to(&mut doc1, "alice").unwrap();
+        assert_eq!(changes[0].origin, ChangeOrigin::Local);
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_diff_round_trip() {
+        let mut text1 = FugueText::new("client1".to_string());
+        let mut text2 = FugueText::new("client2".to_string());
+
+        text1.insert(0, "Hello World").unwrap();
+        text1.delete(5, 6).unwrap();
+
+        let diff = text1.encode_diff(&text2.encode_state_vector()).unwrap();
+        let events = text2.apply_diff(&diff).unwrap();
+
+        assert_eq!(text2.to_string(), "Hello");
+        assert_eq!(
+            events,
+            vec![TextEvent::Insert {
+                position: 0,
+                text: "Hello".to_string()
+            }]
+        );
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_diff_carries_deletion_stamps() {
+        let mut text1 = FugueText::new("client1".to_string());
+        let mut text2 = FugueText::new("client2".to_string());
+        text1.insert(0, "Hello World").unwrap();
+        let before = text1.current_version();
+        text1.delete(5, 6).unwrap();
+
+        // The deleted block travels as a tombstone, and again in the delete set
+        let diff = text1.encode_diff(&text2.encode_state_vector()).unwrap();
+        text2.apply_diff(&diff).unwrap();
+        assert_eq!(text2.current_version(), text1.current_version());
+        assert_eq!(text2.snapshot_at(&before), "Hello World");
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_diff_carries_marks() {
+        use crate::crdt::text_fugue::MarkExpand;
+
+        let mut text1 = FugueText::new("client1".to_string());
+        let mut text2 = FugueText::new("client2".to_string());
+        text1.insert(0, "Hello World").unwrap();
+        text1
+            .add_mark(
+                0..5,
+                "link",
+                serde_json::json!("a.example"),
+                MarkExpand::None,
+            )
+            .unwrap();
+        text1.remove_mark(6..11, "bold").unwrap();
+
+        let diff = text1.encode_diff(&text2.encode_state_vector()).unwrap();
+        text2.apply_diff(&diff).unwrap();
+        assert_eq!(text2.marks(), text1.marks());
+
+        // Nothing left to send
+        let diff = text1.encode_diff(&text2.encode_state_vector()).unwrap();
+        let proto: TextBlockDelta = crate::protocol::serialize::decode_message(&diff).unwrap();
+        assert!(proto.marks.is_empty());
+    }
+
+    /// Send `from` what `to` is missing, the way peers do over the wire
+    #[cfg(feature = "text-crdt")]
+    fn exchange(from: &FugueText, to: &mut FugueText) -> usize {
+        let diff = from.encode_diff(&to.encode_state_vector()).unwrap();
+        to.apply_diff(&diff).unwrap();
+        diff.len()
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_diffs_exchanged_repeatedly_converge() {
+        let mut alice = FugueText::new("alice".to_string());
+        let mut bob = FugueText::new("bob".to_string());
+        for round in 0..30 {
+            let len = alice.len();
+            alice
+                .insert(round % (len + 1), &format!("a{} ", round))
+                .unwrap();
+            let len = bob.len();
+            bob.insert(len - round % (len + 1), &format!("b{} ", round))
+                .unwrap();
+            if round % 3 == 2 && bob.len() > 4 {
+                bob.delete(round % (bob.len() - 4), 3).unwrap();
+            }
+
+            // Sometimes only one direction gets through
+            exchange(&alice, &mut bob);
+            if round % 4 != 1 {
+                exchange(&bob, &mut alice);
+                assert_eq!(alice.to_string(), bob.to_string());
+            }
+        }
+        exchange(&bob, &mut alice);
+        assert_eq!(alice.to_string(), bob.to_string());
+
+        // Nothing left to send either way
+        let before = alice.to_string();
+        exchange(&alice, &mut bob);
+        exchange(&bob, &mut alice);
+        assert_eq!(alice.to_string(), before);
+        assert_eq!(bob.to_string(), before);
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_diff_of_small_edit_is_small() {
+        let mut alice = FugueText::new("alice".to_string());
+        let paragraph = "Lorem ipsum dolor sit amet, consectetur adipiscing elit.\n";
+        alice.insert(0, &paragraph.repeat(50_000 / 57)).unwrap();
+        for at in [1_000, 12_000, 40_000] {
+            alice.insert(at, paragraph).unwrap();
+            alice.delete(at + 100, 10).unwrap();
+        }
+        let mut bob = FugueText::new("bob".to_string());
+        assert!(exchange(&alice, &mut bob) > 50_000);
+
+        alice.insert(25_000, "x").unwrap();
+        let sent = exchange(&alice, &mut bob);
+        assert!(sent < 300, "{} bytes for a one character edit", sent);
+        assert_eq!(alice.to_string(), bob.to_string());
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_diff_rejects_malformed_bytes() {
+        let mut text = FugueText::new("client1".to_string());
+        text.insert(0, "Hello").unwrap();
+
+        assert!(text.encode_diff(&[0xff, 0xff, 0xff]).is_err());
+        assert!(text.apply_diff(&[0x0a, 0x05, 0x01]).is_err());
+        assert_eq!(text.to_string(), "Hello");
+    }
 }
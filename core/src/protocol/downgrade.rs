@@ -0,0 +1,491 @@
+// Field-kind negotiation - Sync with peers that lack some field kinds
+//!
+//! Lists, multi-value fields, refs and annotations each need support on
+//! both ends of a sync; plain LWW values are all a peer is guaranteed to
+//! apply. Each side advertises the kinds it can apply as a [`FieldKinds`]
+//! bitmask in the `field_kinds` of its `SyncRequest` or `SyncResponse`,
+//! and hands the peer's to [`SyncCoordinator::negotiate`]. A mask of 0,
+//! what peers from before the handshake send, stands for every kind.
+//!
+//! A coordinator whose peer lacks a kind downgrades what it sends:
+//!
+//! | Kind | Sent as |
+//! |------|---------|
+//! | list | shadow of the list's values |
+//! | multi-value field | shadow of the value, or the conflict marker |
+//! | ref | shadow of the ref marker, or null once cleared |
+//! | annotation | withheld |
+//!
+//! A shadow is an ordinary LWW field at the structure's path, holding
+//! `{"$shadow": {"kind": "list", "value": [...]}}` (read it with
+//! [`ShadowValue::from_json`]). Its timestamp grows with the structure,
+//! so a newer shadow replaces an older one on the peer.
+//!
+//! Shadows are read-only. The peer's writes to a shadowed path are
+//! refused on apply, like writes to filtered paths, and reported as
+//! `SHADOWED_FIELD` errors by [`SyncCoordinator::shadowed_writes`]; the
+//! rest of the delta is applied and the session goes on. Shadows coming
+//! back from the peer are dropped. A peer holding shadows can check a
+//! path before writing with [`check_writable`].
+//!
+//! Every kind this crate knows is built with `std`, so two coordinators
+//! of this release share them all unless one is restricted with
+//! [`SyncCoordinator::set_field_kinds`]; the handshake is for peers on
+//! other SDKs and older releases.
+//!
+//! [`SyncCoordinator::negotiate`]: crate::protocol::sync::SyncCoordinator::negotiate
+//! [`SyncCoordinator::shadowed_writes`]: crate::protocol::sync::SyncCoordinator::shadowed_writes
+//! [`SyncCoordinator::set_field_kinds`]: crate::protocol::sync::SyncCoordinator::set_field_kinds
+
+use crate::document::{Document, Field};
+use crate::error::{Result, SyncError, SyncKitError};
+use crate::protocol::delta::{DocumentDelta, FieldChange};
+use crate::sync::{ChangeOrigin, Timestamp};
+use crate::FieldPath;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use std::collections::{HashMap, HashSet};
+use std::ops::BitOr;
+
+/// Key of the object a shadow is stored as
+pub const SHADOW_MARKER: &str = "$shadow";
+
+/// Client ID on the timestamps of shadows
+pub const SHADOW_CLIENT: &str = "$shadow";
+
+/// Field kinds a peer can apply, as advertised in the sync handshake
+///
+/// Plain values are always included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FieldKinds(u32);
+
+impl FieldKinds {
+    /// Plain LWW values
+    pub const VALUES: Self = Self(1);
+    /// List fields
+    pub const LISTS: Self = Self(1 << 1);
+    /// Multi-value fields
+    pub const REGISTERS: Self = Self(1 << 2);
+    /// Ref fields
+    pub const REFS: Self = Self(1 << 3);
+    /// Annotations
+    pub const ANNOTATIONS: Self = Self(1 << 4);
+    /// Every kind this crate knows
+    pub const ALL: Self = Self(0b1_1111);
+
+    /// Read an advertised mask
+    ///
+    /// 0 (not advertised) reads as every kind; bits for kinds this crate
+    /// doesn't know are dropped.
+    pub fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => Self::ALL,
+            bits => Self((bits & Self::ALL.0) | Self::VALUES.0),
+        }
+    }
+
+    /// The mask to advertise
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Check if every kind of `other` is included
+    pub fn contains(self, other: FieldKinds) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Kinds included in both
+    pub fn intersection(self, other: FieldKinds) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+impl Default for FieldKinds {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl BitOr for FieldKinds {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// What a shadow stands in for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShadowKind {
+    /// A list field
+    List,
+    /// A multi-value field
+    Register,
+    /// A ref field
+    Ref,
+}
+
+impl ShadowKind {
+    /// Name stored in the shadow (e.g. `"list"`)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ShadowKind::List => "list",
+            ShadowKind::Register => "register",
+            ShadowKind::Ref => "ref",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "list" => Some(ShadowKind::List),
+            "register" => Some(ShadowKind::Register),
+            "ref" => Some(ShadowKind::Ref),
+            _ => None,
+        }
+    }
+
+    /// Check if a peer with `kinds` syncs this kind of field itself
+    pub fn synced_by(self, kinds: FieldKinds) -> bool {
+        kinds.contains(match self {
+            ShadowKind::List => FieldKinds::LISTS,
+            ShadowKind::Register => FieldKinds::REGISTERS,
+            ShadowKind::Ref => FieldKinds::REFS,
+        })
+    }
+}
+
+/// A read-only copy of a field the peer can't sync: the placeholder
+/// described in the module docs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowValue {
+    /// Kind of the field it copies
+    pub kind: ShadowKind,
+    /// The field's value as its `to_json` renders it
+    pub value: JsonValue,
+}
+
+impl ShadowValue {
+    /// Read a shadow, `None` if `value` isn't one
+    pub fn from_json(value: &JsonValue) -> Option<Self> {
+        let shadow = value.as_object()?;
+        if shadow.len() != 1 {
+            return None;
+        }
+        let inner = shadow.get(SHADOW_MARKER)?;
+        Some(Self {
+            kind: ShadowKind::parse(inner.get("kind")?.as_str()?)?,
+            value: inner.get("value")?.clone(),
+        })
+    }
+
+    /// The placeholder to store in place of the field
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            SHADOW_MARKER: {
+                "kind": self.kind.as_str(),
+                "value": self.value,
+            }
+        })
+    }
+}
+
+/// Check that `path` may be written: fails with `SHADOWED_FIELD` if
+/// `document` holds a shadow there
+pub fn check_writable(document: &Document, path: &str) -> Result<()> {
+    match document
+        .get_field(&path.to_string())
+        .and_then(ShadowValue::from_json)
+    {
+        Some(shadow) => Err(shadowed_error(shadow.kind, document, path)),
+        None => Ok(()),
+    }
+}
+
+fn shadowed_error(kind: ShadowKind, document: &Document, path: &str) -> SyncKitError {
+    SyncKitError::from(SyncError::ShadowedField(kind.as_str().to_string()))
+        .with_document(document.id().as_str())
+        .with_path(path)
+}
+
+fn shadow_field(kind: ShadowKind, value: JsonValue, revision: u64) -> Field {
+    Field {
+        value: ShadowValue { kind, value }.to_json(),
+        timestamp: Timestamp::new(revision, SHADOW_CLIENT.to_string()),
+    }
+}
+
+/// Shadows of `document`'s structures of kinds missing from `kinds`,
+/// keyed by path
+fn shadows(document: &Document, kinds: FieldKinds) -> Vec<(FieldPath, Field)> {
+    let mut shadows = Vec::new();
+    if !kinds.contains(FieldKinds::LISTS) {
+        shadows.extend(document.lists().iter().map(|(path, list)| {
+            let field = shadow_field(ShadowKind::List, list.to_json(), list.revision());
+            (path.clone(), field)
+        }));
+    }
+    if !kinds.contains(FieldKinds::REGISTERS) {
+        shadows.extend(document.registers().iter().map(|(path, register)| {
+            let revision = register.seen().clocks().values().sum();
+            let field = shadow_field(ShadowKind::Register, register.to_json(), revision);
+            (path.clone(), field)
+        }));
+    }
+    if !kinds.contains(FieldKinds::REFS) {
+        shadows.extend(document.refs().iter().map(|(path, link)| {
+            let field = shadow_field(ShadowKind::Ref, link.to_json(), link.timestamp.clock);
+            (path.clone(), field)
+        }));
+    }
+    shadows
+}
+
+/// Paths `document` would send as shadows to a peer with `kinds`, and
+/// the kind of each
+fn shadowed_paths(document: &Document, kinds: FieldKinds) -> Vec<(&FieldPath, ShadowKind)> {
+    let mut paths = Vec::new();
+    if !kinds.contains(FieldKinds::LISTS) {
+        paths.extend(document.lists().keys().map(|path| (path, ShadowKind::List)));
+    }
+    if !kinds.contains(FieldKinds::REGISTERS) {
+        paths.extend(
+            document
+                .registers()
+                .keys()
+                .map(|path| (path, ShadowKind::Register)),
+        );
+    }
+    if !kinds.contains(FieldKinds::REFS) {
+        paths.extend(document.refs().keys().map(|path| (path, ShadowKind::Ref)));
+    }
+    paths
+}
+
+/// Rewrite `delta`, computed from `document`, for a peer with `kinds`
+///
+/// Each list, multi-value field and ref of a missing kind the delta
+/// changes is sent as a shadow instead; annotations are dropped unless
+/// the peer takes them.
+pub fn downgrade_delta(delta: &mut DocumentDelta, document: &Document, kinds: FieldKinds) {
+    if kinds.contains(FieldKinds::ALL) {
+        return;
+    }
+    let mut changed: HashSet<&str> = HashSet::new();
+    if !kinds.contains(FieldKinds::LISTS) {
+        changed.extend(delta.lists.iter().map(|change| change.path.as_str()));
+    }
+    if !kinds.contains(FieldKinds::REGISTERS) {
+        changed.extend(delta.registers.iter().map(|change| change.path.as_str()));
+    }
+    if !kinds.contains(FieldKinds::REFS) {
+        changed.extend(delta.refs.iter().map(|change| change.path.as_str()));
+    }
+    let mut shadows: Vec<FieldChange> = shadows(document, kinds)
+        .into_iter()
+        .filter(|(path, _)| changed.contains(path.as_str()))
+        .map(|(path, field)| FieldChange {
+            path,
+            field,
+            is_delete: false,
+            value_ref: None,
+            origin: ChangeOrigin::default(),
+        })
+        .collect();
+    shadows.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if !kinds.contains(FieldKinds::LISTS) {
+        delta.lists.clear();
+    }
+    if !kinds.contains(FieldKinds::REGISTERS) {
+        delta.registers.clear();
+    }
+    if !kinds.contains(FieldKinds::REFS) {
+        delta.refs.clear();
+    }
+    if !kinds.contains(FieldKinds::ANNOTATIONS) {
+        delta.annotations.clear();
+    }
+    let shadowed: HashSet<&String> = shadows.iter().map(|change| &change.path).collect();
+    delta
+        .changes
+        .retain(|change| !shadowed.contains(&change.path));
+    delta.changes.extend(shadows);
+}
+
+/// Copy of `document` to send a peer with `kinds` as a snapshot, with
+/// structures of missing kinds as shadows
+pub fn downgrade_document(document: &Document, kinds: FieldKinds) -> Document {
+    if kinds.contains(FieldKinds::ALL) {
+        return document.clone();
+    }
+    let shadows = shadows(document, kinds);
+    let (fields, lists, registers, refs, locks, annotations, version) =
+        document.clone().into_parts();
+    let mut downgraded = Document::new(document.id().clone());
+    downgraded.fields = fields;
+    for (path, field) in shadows {
+        downgraded.fields.insert(path, field);
+    }
+    if kinds.contains(FieldKinds::LISTS) {
+        for (path, list) in &lists {
+            downgraded.merge_list(path, list);
+        }
+    }
+    if kinds.contains(FieldKinds::REGISTERS) {
+        for (path, register) in &registers {
+            downgraded.merge_register(path, register);
+        }
+    }
+    if kinds.contains(FieldKinds::REFS) {
+        for (path, link) in &refs {
+            downgraded.merge_ref(path, link);
+        }
+    }
+    if kinds.contains(FieldKinds::ANNOTATIONS) {
+        downgraded.merge_annotations(&annotations);
+    }
+    downgraded.merge_locks(&locks);
+    downgraded.version = version;
+    downgraded
+}
+
+/// Check if an incoming field change is to be refused: a shadow coming
+/// back, or a write to one of the `shadowed` paths
+///
+/// Returns the kind of the shadow for writes, which are errors.
+fn refusal(
+    change: &FieldChange,
+    shadowed: &HashMap<&FieldPath, ShadowKind>,
+) -> Option<Option<ShadowKind>> {
+    if ShadowValue::from_json(&change.field.value).is_some() {
+        return Some(None);
+    }
+    shadowed.get(&change.path).map(|kind| Some(*kind))
+}
+
+/// `delta` from a peer with `kinds` without the field changes `document`
+/// refuses (see the module docs), and the refused paths; `None` if it
+/// refuses nothing
+pub(crate) fn withhold_shadowed(
+    delta: &DocumentDelta,
+    document: &Document,
+    kinds: FieldKinds,
+) -> Option<(DocumentDelta, Vec<String>)> {
+    if kinds.contains(FieldKinds::ALL) {
+        return None;
+    }
+    let shadowed: HashMap<_, _> = shadowed_paths(document, kinds).into_iter().collect();
+    let mut refused = Vec::new();
+    let mut kept = delta.clone();
+    kept.changes.retain(|change| {
+        let refuse = refusal(change, &shadowed).is_some();
+        if refuse {
+            refused.push(change.path.clone());
+        }
+        !refuse
+    });
+    (!refused.is_empty()).then_some((kept, refused))
+}
+
+/// `SHADOWED_FIELD` errors for the writes in `delta`, from a peer with
+/// `kinds`, to paths `document` sends that peer as shadows
+pub(crate) fn shadowed_writes(
+    delta: &DocumentDelta,
+    document: &Document,
+    kinds: FieldKinds,
+) -> Vec<SyncKitError> {
+    if kinds.contains(FieldKinds::ALL) {
+        return Vec::new();
+    }
+    let shadowed: HashMap<_, _> = shadowed_paths(document, kinds).into_iter().collect();
+    delta
+        .changes
+        .iter()
+        .filter_map(|change| {
+            let kind = refusal(change, &shadowed)??;
+            Some(shadowed_error(kind, document, &change.path))
+        })
+        .collect()
+}
+
+/// `remote`, a snapshot from a peer with `kinds`, without the fields
+/// `document` refuses; `None` if it takes them all
+pub(crate) fn withhold_shadowed_snapshot(
+    document: &Document,
+    remote: &Document,
+    kinds: FieldKinds,
+) -> Option<Document> {
+    if kinds.contains(FieldKinds::ALL) {
+        return None;
+    }
+    let shadowed: HashSet<&FieldPath> = shadowed_paths(document, kinds)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    let mut kept = remote.clone();
+    kept.fields.retain(|path, field| {
+        !shadowed.contains(path) && ShadowValue::from_json(&field.value).is_none()
+    });
+    Some(kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_and_shadow_placeholders() {
+        assert_eq!(FieldKinds::from_bits(0), FieldKinds::ALL);
+        let lite = FieldKinds::from_bits(1 << 20);
+        assert_eq!(lite, FieldKinds::VALUES);
+        assert!(!lite.contains(FieldKinds::LISTS));
+        let kinds = FieldKinds::VALUES | FieldKinds::REFS;
+        assert_eq!(FieldKinds::from_bits(kinds.bits()), kinds);
+        assert_eq!(kinds.intersection(FieldKinds::ALL), kinds);
+        assert!(ShadowKind::Ref.synced_by(kinds));
+        assert!(!ShadowKind::List.synced_by(kinds));
+
+        let shadow = ShadowValue {
+            kind: ShadowKind::List,
+            value: json!([1, 2]),
+        };
+        let value = shadow.to_json();
+        assert_eq!(value[SHADOW_MARKER]["kind"], json!("list"));
+        assert_eq!(ShadowValue::from_json(&value), Some(shadow));
+        assert_eq!(
+            ShadowValue::from_json(&json!({SHADOW_MARKER: {"kind": "set", "value": []}})),
+            None
+        );
+        assert_eq!(ShadowValue::from_json(&json!([1, 2])), None);
+    }
+
+    #[test]
+    fn test_list_shadow_replaces_older_ones() {
+        let mut doc = Document::new("doc".to_string());
+        doc.list_mut("items".to_string(), "alice".to_string())
+            .push(json!("a"));
+        let mut delta = DocumentDelta::compute(&Document::new("doc".to_string()), &doc).unwrap();
+        downgrade_delta(&mut delta, &doc, FieldKinds::VALUES);
+        assert!(delta.lists.is_empty());
+        assert_eq!(delta.changes.len(), 1);
+        let first = delta.changes[0].field.clone();
+        assert_eq!(
+            ShadowValue::from_json(&first.value).unwrap().value,
+            json!(["a"])
+        );
+
+        doc.list_mut("items".to_string(), "alice".to_string())
+            .remove(0)
+            .unwrap();
+        let snapshot = downgrade_document(&doc, FieldKinds::VALUES);
+        assert!(snapshot.lists().is_empty());
+        let second = snapshot.fields()[&"items".to_string()].clone();
+        assert_eq!(
+            ShadowValue::from_json(&second.value).unwrap().value,
+            json!([])
+        );
+        assert!(second.timestamp > first.timestamp);
+        assert!(check_writable(&snapshot, "items").is_err());
+        assert!(check_writable(&snapshot, "title").is_ok());
+    }
+}
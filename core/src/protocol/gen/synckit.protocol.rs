@@ -169,7 +169,7 @@ pub struct Field {
     #[prost(message, optional, tag = "4")]
     pub timestamp: ::core::option::Option<Timestamp>,
     /// Current value (or tombstone if deleted)
-    #[prost(oneof = "field::Content", tags = "2, 3")]
+    #[prost(oneof = "field::Content", tags = "2, 3, 5")]
     pub content: ::core::option::Option<field::Content>,
 }
 /// Nested message and enum types in `Field`.
@@ -182,6 +182,10 @@ pub mod field {
         Value(super::Value),
         #[prost(message, tag = "3")]
         Tombstone(super::Tombstone),
+        /// 128-bit content hash (big-endian) of a value the receiver already
+        /// holds under another field, sent instead of the value
+        #[prost(bytes, tag = "5")]
+        ValueRef(::prost::alloc::vec::Vec<u8>),
     }
 }
 /// Complete document state
@@ -204,6 +208,71 @@ pub struct Document {
     pub updated_at: ::core::option::Option<Timestamp>,
     #[prost(message, optional, tag = "6")]
     pub created_by: ::core::option::Option<ClientId>,
+    /// List fields, as the operations that build them
+    #[prost(message, repeated, tag = "7")]
+    pub lists: ::prost::alloc::vec::Vec<ListChange>,
+    /// Annotations on the fields' edits
+    #[prost(message, repeated, tag = "8")]
+    pub annotations: ::prost::alloc::vec::Vec<Annotation>,
+    /// Multi-value fields, whole
+    #[prost(message, repeated, tag = "9")]
+    pub registers: ::prost::alloc::vec::Vec<RegisterChange>,
+    /// Links to other documents
+    #[prost(message, repeated, tag = "10")]
+    pub refs: ::prost::alloc::vec::Vec<RefChange>,
+}
+/// Operations on one list field (Tier 1: list CRDT)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ListChange {
+    /// Field path within document
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    /// JSON array of list operations (see synckit_core::list::ListOp)
+    #[prost(bytes = "vec", tag = "2")]
+    pub ops: ::prost::alloc::vec::Vec<u8>,
+}
+/// State of one multi-value field (Tier 1: multi-value register)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct RegisterChange {
+    /// Field path within document
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    /// JSON register state (see synckit_core::register::MVRegister)
+    #[prost(bytes = "vec", tag = "2")]
+    pub register: ::prost::alloc::vec::Vec<u8>,
+}
+/// Link from one field to another document of the workspace (Tier 1: LWW)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct RefChange {
+    /// Field path within document
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    /// ID of the document linked to; empty once the link is cleared
+    #[prost(string, tag = "2")]
+    pub target: ::prost::alloc::string::String,
+    /// Last-write timestamp for LWW resolution
+    #[prost(message, optional, tag = "3")]
+    pub timestamp: ::core::option::Option<Timestamp>,
+}
+/// App-level tag on the edits one client made with clocks start..=end
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Annotation {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub start: u64,
+    #[prost(uint64, tag = "3")]
+    pub end: u64,
+    /// JSON payload (see synckit_core::annotations)
+    #[prost(bytes = "vec", tag = "4")]
+    pub payload: ::prost::alloc::vec::Vec<u8>,
+    /// Milliseconds since the Unix epoch
+    #[prost(uint64, tag = "5")]
+    pub created_ms: u64,
 }
 /// Delta representing changes between states
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -227,6 +296,18 @@ pub struct Delta {
     /// Timestamp when delta was created
     #[prost(message, optional, tag = "6")]
     pub created_at: ::core::option::Option<Timestamp>,
+    /// Operations on list fields (only those the receiver lacks)
+    #[prost(message, repeated, tag = "7")]
+    pub lists: ::prost::alloc::vec::Vec<ListChange>,
+    /// Annotations on the edits (only those the receiver lacks)
+    #[prost(message, repeated, tag = "8")]
+    pub annotations: ::prost::alloc::vec::Vec<Annotation>,
+    /// Multi-value fields the receiver holds differently, whole
+    #[prost(message, repeated, tag = "9")]
+    pub registers: ::prost::alloc::vec::Vec<RegisterChange>,
+    /// Links the receiver holds differently
+    #[prost(message, repeated, tag = "10")]
+    pub refs: ::prost::alloc::vec::Vec<RefChange>,
 }
 /// Checkpoint for resuming sync
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -311,6 +392,159 @@ pub mod text_operation {
         }
     }
 }
+/// Identifier of a Fugue text block (Tier 2)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TextNodeId {
+    /// Client that inserted the block
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    /// Lamport clock of the block's last character
+    #[prost(uint64, tag = "2")]
+    pub clock: u64,
+    /// Character offset within the original insert
+    #[prost(uint64, tag = "3")]
+    pub offset: u64,
+}
+/// Run of characters inserted by one client (Tier 2)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TextBlock {
+    #[prost(message, optional, tag = "1")]
+    pub id: ::core::option::Option<TextNodeId>,
+    /// Block content
+    #[prost(string, tag = "2")]
+    pub text: ::prost::alloc::string::String,
+    /// Fugue origins (absent = document start / end)
+    #[prost(message, optional, tag = "3")]
+    pub left_origin: ::core::option::Option<TextNodeId>,
+    #[prost(message, optional, tag = "4")]
+    pub right_origin: ::core::option::Option<TextNodeId>,
+    /// Tombstone flag
+    #[prost(bool, tag = "5")]
+    pub deleted: bool,
+    /// Deleting client and clock (absent = not deleted, or unknown)
+    #[prost(message, optional, tag = "6")]
+    pub deleted_at: ::core::option::Option<TextNodeId>,
+}
+/// Contiguous range of deleted characters from one client (Tier 2)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TextDeleteRange {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    /// Inclusive clock range
+    #[prost(uint64, tag = "2")]
+    pub start: u64,
+    #[prost(uint64, tag = "3")]
+    pub end: u64,
+    /// Deleting client and clock (absent = unknown)
+    #[prost(message, optional, tag = "4")]
+    pub deleted_at: ::core::option::Option<TextNodeId>,
+}
+/// Position in a text that follows its character (Tier 2)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TextAnchor {
+    /// Character the anchor sticks to (absent = document start / end)
+    #[prost(message, optional, tag = "1")]
+    pub target: ::core::option::Option<TextNodeId>,
+    /// Sticks to the character after the position rather than before
+    #[prost(bool, tag = "2")]
+    pub right: bool,
+}
+/// Formatting key set over a range of text (Tier 2)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TextMark {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    /// Lamport clock of the mark
+    #[prost(uint64, tag = "2")]
+    pub clock: u64,
+    #[prost(message, optional, tag = "3")]
+    pub start: ::core::option::Option<TextAnchor>,
+    #[prost(message, optional, tag = "4")]
+    pub end: ::core::option::Option<TextAnchor>,
+    #[prost(string, tag = "5")]
+    pub key: ::prost::alloc::string::String,
+    /// JSON value (absent = the key is removed)
+    #[prost(bytes = "vec", optional, tag = "6")]
+    pub value: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+}
+/// State-vector based text delta (Tier 2)
+///
+/// Requested by sending the receiver's VectorClock (highest insert clock
+/// per client); carries only the blocks above it plus the full delete set.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TextBlockDelta {
+    #[prost(message, repeated, tag = "1")]
+    pub blocks: ::prost::alloc::vec::Vec<TextBlock>,
+    #[prost(message, repeated, tag = "2")]
+    pub deleted: ::prost::alloc::vec::Vec<TextDeleteRange>,
+    /// Sender's Lamport clock
+    #[prost(uint64, tag = "3")]
+    pub clock: u64,
+    /// Annotations on the blocks' clocks
+    #[prost(message, repeated, tag = "4")]
+    pub annotations: ::prost::alloc::vec::Vec<Annotation>,
+    /// Formatting marks above the receiver's state vector
+    #[prost(message, repeated, tag = "5")]
+    pub marks: ::prost::alloc::vec::Vec<TextMark>,
+}
+/// TextNodeId naming its client by index into TextState.clients (Tier 2)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TextStateId {
+    #[prost(uint32, tag = "1")]
+    pub client_index: u32,
+    #[prost(uint64, tag = "2")]
+    pub clock: u64,
+    #[prost(uint64, tag = "3")]
+    pub offset: u64,
+}
+/// Block of a TextState; its tombstone flag is in TextState.deleted
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TextStateBlock {
+    #[prost(message, optional, tag = "1")]
+    pub id: ::core::option::Option<TextStateId>,
+    #[prost(string, tag = "2")]
+    pub text: ::prost::alloc::string::String,
+    /// Fugue origins (absent = document start / end)
+    #[prost(message, optional, tag = "3")]
+    pub left_origin: ::core::option::Option<TextStateId>,
+    #[prost(message, optional, tag = "4")]
+    pub right_origin: ::core::option::Option<TextStateId>,
+    /// Deleting client and clock (absent = not deleted, or unknown)
+    #[prost(message, optional, tag = "5")]
+    pub deleted_at: ::core::option::Option<TextStateId>,
+}
+/// Whole state of a text (Tier 2), the binary counterpart of its JSON
+///
+/// Each client id is written once, in a table the block IDs index into.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TextState {
+    #[prost(string, repeated, tag = "1")]
+    pub clients: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// Every block, tombstones included, in ID order
+    #[prost(message, repeated, tag = "2")]
+    pub blocks: ::prost::alloc::vec::Vec<TextStateBlock>,
+    /// Tombstone flag of each block, in the same order
+    #[prost(bool, repeated, tag = "3")]
+    pub deleted: ::prost::alloc::vec::Vec<bool>,
+    /// Owner's Lamport clock and client
+    #[prost(uint64, tag = "4")]
+    pub clock: u64,
+    #[prost(uint32, tag = "5")]
+    pub client_index: u32,
+    #[prost(message, repeated, tag = "6")]
+    pub annotations: ::prost::alloc::vec::Vec<Annotation>,
+    #[prost(message, repeated, tag = "7")]
+    pub marks: ::prost::alloc::vec::Vec<TextMark>,
+}
 /// Set operation for OR-Set CRDT (Tier 3)
 #[derive(serde::Serialize, serde::Deserialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -506,6 +740,58 @@ pub mod crdt_operation {
         CounterOp(super::CounterOperation),
     }
 }
+/// Awareness (ephemeral presence) update from one client
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct AwarenessUpdate {
+    /// Per-client sequence number
+    #[prost(uint64, tag = "3")]
+    pub clock: u64,
+    /// Sequence number the patch applies to (absent = full state)
+    #[prost(uint64, optional, tag = "6")]
+    pub base_clock: ::core::option::Option<u64>,
+    /// Sender: inline for a standalone update, or an index into the client
+    /// table of the enclosing AwarenessDiff
+    #[prost(oneof = "awareness_update::Client", tags = "1, 2")]
+    pub client: ::core::option::Option<awareness_update::Client>,
+    /// New state, or a JSON merge patch when base_clock is set
+    /// (absent = the client left)
+    #[prost(oneof = "awareness_update::State", tags = "4, 5")]
+    pub state: ::core::option::Option<awareness_update::State>,
+}
+/// Nested message and enum types in `AwarenessUpdate`.
+pub mod awareness_update {
+    /// Sender: inline for a standalone update, or an index into the client
+    /// table of the enclosing AwarenessDiff
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[derive(Clone, PartialEq, Eq, Hash, ::prost::Oneof)]
+    pub enum Client {
+        #[prost(string, tag = "1")]
+        ClientId(::prost::alloc::string::String),
+        #[prost(uint32, tag = "2")]
+        ClientIndex(u32),
+    }
+    /// New state, or a JSON merge patch when base_clock is set
+    /// (absent = the client left)
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[derive(Clone, PartialEq, Eq, Hash, ::prost::Oneof)]
+    pub enum State {
+        #[prost(bytes, tag = "4")]
+        StateJson(::prost::alloc::vec::Vec<u8>),
+        #[prost(bytes, tag = "5")]
+        StateCbor(::prost::alloc::vec::Vec<u8>),
+    }
+}
+/// Awareness updates a peer is missing (answer to an awareness version)
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AwarenessDiff {
+    /// Client ids referenced by AwarenessUpdate.client_index
+    #[prost(string, repeated, tag = "1")]
+    pub clients: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(message, repeated, tag = "2")]
+    pub updates: ::prost::alloc::vec::Vec<AwarenessUpdate>,
+}
 /// Client initiates sync session
 #[derive(serde::Serialize, serde::Deserialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -528,6 +814,10 @@ pub struct SyncRequest {
     /// Maximum deltas to receive in response
     #[prost(int32, tag = "6")]
     pub max_deltas: i32,
+    /// Field kinds the client can apply, a FieldKinds bitmask
+    /// (0 = not advertised: every kind)
+    #[prost(uint32, tag = "7")]
+    pub field_kinds: u32,
 }
 /// Server responds with changes
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -554,6 +844,10 @@ pub struct SyncResponse {
     /// Next page token (if has_more = true)
     #[prost(string, tag = "7")]
     pub next_page_token: ::prost::alloc::string::String,
+    /// Field kinds the server can apply, a FieldKinds bitmask
+    /// (0 = not advertised: every kind)
+    #[prost(uint32, tag = "8")]
+    pub field_kinds: u32,
 }
 /// Real-time update notification (server push)
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -755,3 +1049,73 @@ pub struct Pong {
     #[prost(message, optional, tag = "2")]
     pub pong_sent_at: ::core::option::Option<Timestamp>,
 }
+/// Version of one document
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DocumentVersion {
+    #[prost(message, optional, tag = "1")]
+    pub document_id: ::core::option::Option<DocumentId>,
+    #[prost(message, optional, tag = "2")]
+    pub version: ::core::option::Option<VectorClock>,
+}
+/// One message on a replication stream
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SyncMessage {
+    #[prost(oneof = "sync_message::Payload", tags = "1, 2, 3")]
+    pub payload: ::core::option::Option<sync_message::Payload>,
+}
+/// Nested message and enum types in `SyncMessage`.
+pub mod sync_message {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Payload {
+        /// Versions of every document the sender has (sent first by both sides)
+        #[prost(message, tag = "1")]
+        Versions(super::VersionSummary),
+        /// Changes to one document
+        #[prost(message, tag = "2")]
+        Delta(super::Delta),
+        /// Anti-entropy probe: which document versions the sender has
+        #[prost(message, tag = "3")]
+        Probe(super::VersionFilter),
+    }
+}
+/// Versions of all documents on a relay
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VersionSummary {
+    #[prost(message, repeated, tag = "1")]
+    pub documents: ::prost::alloc::vec::Vec<DocumentVersion>,
+}
+/// Bloom filter over (document ID, version hash) pairs
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct VersionFilter {
+    /// Filter bits, little-endian 64-bit words
+    #[prost(bytes = "vec", tag = "1")]
+    pub bits: ::prost::alloc::vec::Vec<u8>,
+    /// Number of bits in use
+    #[prost(uint64, tag = "2")]
+    pub num_bits: u64,
+    /// Bit positions set per entry
+    #[prost(uint32, tag = "3")]
+    pub hashes: u32,
+    /// Mixed into every hash; a new seed moves false positives elsewhere
+    #[prost(uint64, tag = "4")]
+    pub seed: u64,
+}
+/// Request the full state of one document
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct GetSnapshotRequest {
+    #[prost(message, optional, tag = "1")]
+    pub document_id: ::core::option::Option<DocumentId>,
+}
+/// Request the version of one document
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct GetVersionRequest {
+    #[prost(message, optional, tag = "1")]
+    pub document_id: ::core::option::Option<DocumentId>,
+}
@@ -16,6 +16,17 @@ mod gen {
 // Re-export protocol types for convenience
 pub use gen::*;
 
+// gRPC stubs for the Replication service (generated into OUT_DIR)
+#[cfg(feature = "grpc")]
+#[allow(clippy::all)]
+#[allow(warnings)]
+mod grpc_gen {
+    include!(concat!(env!("OUT_DIR"), "/synckit.protocol.rs"));
+}
+
+#[cfg(feature = "grpc")]
+pub use grpc_gen::{replication_client, replication_server};
+
 // Custom serialization implementations
 pub mod serialize;
 
@@ -24,3 +35,9 @@ pub mod delta;
 
 // Sync coordinator
 pub mod sync;
+
+// Field-kind negotiation with peers that lack some kinds
+pub mod downgrade;
+
+// Bandwidth accounting and quotas
+pub mod quota;
@@ -0,0 +1,646 @@
+//! Bandwidth accounting and per-session quotas
+//!
+//! A multi-tenant server needs to bill clients by the traffic they cause
+//! and to stop one of them from taking all of it. The
+//! [`SyncCoordinator`] counts every message a session sends or receives,
+//! per session and per principal (the user or tenant the server
+//! authenticated the session as), in [`SyncMetrics`]. With a
+//! [`QuotaPolicy`] set, it asks the policy before each message:
+//!
+//! - `Allow`: the message goes through and is counted
+//! - `Throttle(delay)`: the session is paused for `delay`; every message
+//!   it sends or is sent until then is held back without asking the
+//!   policy again, as backpressure towards the caller
+//! - `Reject(code)`: this message is refused; the session stays open
+//!
+//! Held-back messages come back to the caller as a [`QuotaExceeded`],
+//! which converts into the protocol `ErrorMessage` to send the client,
+//! with the quota state in its details, so SDKs can show "sync paused:
+//! quota exceeded" and retry after `retry_after_ms`.
+//!
+//! # Resuming sessions
+//!
+//! Counters are kept by session ID, so a client that reconnects with the
+//! same session ID continues where it left off, pause included.
+//! [`SyncMetrics`] serializes, to keep the counters across server
+//! restarts with [`SyncCoordinator::restore_metrics`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use synckit_core::protocol::quota::{Direction, OverLimit, WindowQuota};
+//! use synckit_core::protocol::sync::SyncCoordinator;
+//!
+//! let mut coordinator = SyncCoordinator::new();
+//! coordinator.set_quota_policy(WindowQuota {
+//!     window: Duration::from_secs(60),
+//!     max_bytes: Some(1_000),
+//!     max_messages: None,
+//!     over_limit: OverLimit::Throttle,
+//! });
+//! coordinator.open_session("session-1", "tenant-a");
+//!
+//! assert!(coordinator.admit("session-1", Direction::Outgoing, 800).is_ok());
+//! let exceeded = coordinator
+//!     .admit("session-1", Direction::Outgoing, 800)
+//!     .unwrap_err();
+//! assert!(exceeded.retry_after().is_some());
+//! let tenant = coordinator.metrics().principal("tenant-a").unwrap();
+//! assert_eq!((tenant.bytes_sent, tenant.throttled), (800, 1));
+//! ```
+//!
+//! [`SyncCoordinator`]: crate::protocol::sync::SyncCoordinator
+//! [`SyncCoordinator::restore_metrics`]: crate::protocol::sync::SyncCoordinator::restore_metrics
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Which way a message travels, seen from this replica
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Direction {
+    /// Sent to the session's client
+    Outgoing,
+
+    /// Received from the session's client
+    Incoming,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Outgoing => "outgoing",
+            Direction::Incoming => "incoming",
+        }
+    }
+}
+
+/// Traffic of a session or principal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficCounters {
+    /// Bytes of the messages sent
+    pub bytes_sent: u64,
+
+    /// Bytes of the messages received
+    pub bytes_received: u64,
+
+    /// Messages sent
+    pub messages_sent: u64,
+
+    /// Messages received
+    pub messages_received: u64,
+
+    /// Messages held back because of a throttle
+    pub throttled: u64,
+
+    /// Messages the policy rejected
+    pub rejected: u64,
+}
+
+impl TrafficCounters {
+    /// Bytes sent and received
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes_sent + self.bytes_received
+    }
+
+    /// Messages sent and received
+    pub fn total_messages(&self) -> u64 {
+        self.messages_sent + self.messages_received
+    }
+
+    fn record(&mut self, direction: Direction, bytes: u64) {
+        match direction {
+            Direction::Outgoing => {
+                self.bytes_sent += bytes;
+                self.messages_sent += 1;
+            }
+            Direction::Incoming => {
+                self.bytes_received += bytes;
+                self.messages_received += 1;
+            }
+        }
+    }
+
+    fn add(&mut self, other: &TrafficCounters) {
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.messages_sent += other.messages_sent;
+        self.messages_received += other.messages_received;
+        self.throttled += other.throttled;
+        self.rejected += other.rejected;
+    }
+}
+
+/// Traffic of one session
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionMetrics {
+    /// Who the session is authenticated as
+    pub principal: String,
+
+    /// What the session sent and received
+    pub traffic: TrafficCounters,
+
+    /// End of the session's throttle, in milliseconds from the
+    /// coordinator's clock
+    pub paused_until_ms: Option<u64>,
+}
+
+/// Traffic counters of a coordinator's sessions, from
+/// `SyncCoordinator::metrics`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncMetrics {
+    sessions: HashMap<String, SessionMetrics>,
+    principals: HashMap<String, TrafficCounters>,
+}
+
+impl SyncMetrics {
+    /// A session's counters
+    pub fn session(&self, session_id: &str) -> Option<&SessionMetrics> {
+        self.sessions.get(session_id)
+    }
+
+    /// A principal's counters, over all its sessions
+    pub fn principal(&self, principal: &str) -> Option<&TrafficCounters> {
+        self.principals.get(principal)
+    }
+
+    /// IDs of the sessions counted, sorted
+    pub fn session_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.sessions.keys().map(String::as_str).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Counters of every session added up
+    pub fn total(&self) -> TrafficCounters {
+        let mut total = TrafficCounters::default();
+        for session in self.sessions.values() {
+            total.add(&session.traffic);
+        }
+        total
+    }
+
+    /// Start counting a session, or continue counting a resumed one
+    pub(crate) fn open(&mut self, session_id: &str, principal: &str) {
+        let session = self.sessions.entry(session_id.to_string()).or_default();
+        session.principal = principal.to_string();
+        self.principals.entry(principal.to_string()).or_default();
+    }
+
+    /// Stop counting a session; its traffic stays in its principal's
+    pub(crate) fn forget(&mut self, session_id: &str) -> Option<SessionMetrics> {
+        self.sessions.remove(session_id)
+    }
+
+    /// The session's counters, opening it as its own principal if needed
+    pub(crate) fn session_mut(&mut self, session_id: &str) -> &mut SessionMetrics {
+        if !self.sessions.contains_key(session_id) {
+            self.open(session_id, session_id);
+        }
+        self.sessions
+            .get_mut(session_id)
+            .expect("session was just opened")
+    }
+
+    pub(crate) fn principal_or_default(&self, principal: &str) -> TrafficCounters {
+        self.principals.get(principal).copied().unwrap_or_default()
+    }
+
+    /// Count a message that went through
+    pub(crate) fn record(&mut self, session_id: &str, direction: Direction, bytes: u64) {
+        let session = self.session_mut(session_id);
+        session.traffic.record(direction, bytes);
+        let principal = session.principal.clone();
+        self.principals
+            .entry(principal)
+            .or_default()
+            .record(direction, bytes);
+    }
+
+    /// Count a message held back, throttled or rejected
+    pub(crate) fn record_refusal(&mut self, session_id: &str, throttled: bool) {
+        let session = self.session_mut(session_id);
+        let count = |traffic: &mut TrafficCounters| match throttled {
+            true => traffic.throttled += 1,
+            false => traffic.rejected += 1,
+        };
+        count(&mut session.traffic);
+        let principal = session.principal.clone();
+        count(self.principals.entry(principal).or_default());
+    }
+}
+
+/// What a [`QuotaPolicy`] is asked about
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaRequest<'a> {
+    /// Session the message belongs to
+    pub session_id: &'a str,
+
+    /// Who the session is authenticated as
+    pub principal: &'a str,
+
+    /// Which way the message travels
+    pub direction: Direction,
+
+    /// Size of the encoded message
+    pub bytes: u64,
+
+    /// Current time from the coordinator's clock
+    pub now_ms: u64,
+
+    /// The session's traffic so far, this message not included
+    pub session: &'a TrafficCounters,
+
+    /// The principal's traffic so far, over all its sessions
+    pub principal_total: &'a TrafficCounters,
+}
+
+/// Why a policy refused a message outright
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum QuotaCode {
+    /// The byte quota is used up
+    BytesExceeded,
+
+    /// The message quota is used up
+    MessagesExceeded,
+
+    /// The principal may not sync at all right now (e.g. unpaid plan)
+    Suspended,
+}
+
+impl QuotaCode {
+    /// Stable name, as sent in error details
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuotaCode::BytesExceeded => "bytes_exceeded",
+            QuotaCode::MessagesExceeded => "messages_exceeded",
+            QuotaCode::Suspended => "suspended",
+        }
+    }
+}
+
+/// A policy's verdict on a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaOutcome {
+    /// Let the message through
+    Allow,
+
+    /// Hold back this and every message of the session for the delay
+    Throttle(Duration),
+
+    /// Refuse this message
+    Reject(QuotaCode),
+}
+
+/// Decides whether a session may send or receive a message (see the
+/// [module docs](self))
+///
+/// Called once per message, with the lock of the coordinator's policy
+/// held; not called for a session that is paused.
+pub trait QuotaPolicy: Send {
+    /// The verdict on the message described by `request`
+    fn check(&mut self, request: &QuotaRequest<'_>) -> QuotaOutcome;
+}
+
+/// What [`WindowQuota`] does to a principal over its quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverLimit {
+    /// Pause the session until the window ends
+    Throttle,
+
+    /// Refuse the message
+    Reject,
+}
+
+/// Caps each principal's traffic per fixed window of time, both
+/// directions together
+///
+/// Each principal's window starts with its first message; limits left
+/// unset don't apply.
+#[derive(Debug, Clone)]
+pub struct WindowQuota {
+    /// Length of a window
+    pub window: Duration,
+
+    /// Bytes a principal may move per window
+    pub max_bytes: Option<u64>,
+
+    /// Messages a principal may move per window
+    pub max_messages: Option<u64>,
+
+    /// What happens to a message over the limit
+    pub over_limit: OverLimit,
+}
+
+impl WindowQuota {
+    /// The policy, ready to set on a coordinator
+    fn into_policy(self) -> WindowQuotaPolicy {
+        WindowQuotaPolicy {
+            quota: self,
+            windows: HashMap::new(),
+        }
+    }
+}
+
+/// A principal's current window
+struct Window {
+    start_ms: u64,
+    bytes: u64,
+    messages: u64,
+}
+
+struct WindowQuotaPolicy {
+    quota: WindowQuota,
+    windows: HashMap<String, Window>,
+}
+
+impl QuotaPolicy for WindowQuotaPolicy {
+    fn check(&mut self, request: &QuotaRequest<'_>) -> QuotaOutcome {
+        let window_ms = self.quota.window.as_millis() as u64;
+        let window = self
+            .windows
+            .entry(request.principal.to_string())
+            .or_insert(Window {
+                start_ms: request.now_ms,
+                bytes: 0,
+                messages: 0,
+            });
+        if request.now_ms.saturating_sub(window.start_ms) >= window_ms {
+            *window = Window {
+                start_ms: request.now_ms,
+                bytes: 0,
+                messages: 0,
+            };
+        }
+
+        let over = if self
+            .quota
+            .max_bytes
+            .is_some_and(|max| window.bytes + request.bytes > max)
+        {
+            Some(QuotaCode::BytesExceeded)
+        } else if self
+            .quota
+            .max_messages
+            .is_some_and(|max| window.messages + 1 > max)
+        {
+            Some(QuotaCode::MessagesExceeded)
+        } else {
+            None
+        };
+        match (over, self.quota.over_limit) {
+            (None, _) => {
+                window.bytes += request.bytes;
+                window.messages += 1;
+                QuotaOutcome::Allow
+            }
+            (Some(_), OverLimit::Throttle) => {
+                let end_ms = window.start_ms + window_ms;
+                QuotaOutcome::Throttle(Duration::from_millis(end_ms - request.now_ms))
+            }
+            (Some(code), OverLimit::Reject) => QuotaOutcome::Reject(code),
+        }
+    }
+}
+
+impl From<WindowQuota> for SharedQuota {
+    fn from(quota: WindowQuota) -> Self {
+        SharedQuota::new(quota.into_policy())
+    }
+}
+
+/// Shared handle to a policy, as stored by the coordinator; clones of a
+/// coordinator share their policy's state
+#[derive(Clone)]
+pub struct SharedQuota(Arc<Mutex<dyn QuotaPolicy>>);
+
+impl SharedQuota {
+    /// Share `policy`
+    pub fn new(policy: impl QuotaPolicy + 'static) -> Self {
+        Self(Arc::new(Mutex::new(policy)))
+    }
+
+    pub(crate) fn check(&self, request: &QuotaRequest<'_>) -> QuotaOutcome {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .check(request)
+    }
+}
+
+impl fmt::Debug for SharedQuota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedQuota")
+    }
+}
+
+/// How a message was held back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaRefusal {
+    /// The session is paused; retry after the delay
+    Throttled {
+        /// Time left until the pause ends
+        retry_after: Duration,
+    },
+
+    /// The policy refused the message
+    Rejected(QuotaCode),
+}
+
+/// A message held back by the quota policy, with the quota state to tell
+/// the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    /// Session the message belongs to
+    pub session_id: String,
+
+    /// Who the session is authenticated as
+    pub principal: String,
+
+    /// Which way the message was going
+    pub direction: Direction,
+
+    /// Whether the session is paused or the message refused
+    pub refusal: QuotaRefusal,
+
+    /// The session's traffic so far
+    pub session: TrafficCounters,
+}
+
+impl QuotaExceeded {
+    /// When to try again, if the session is only paused
+    pub fn retry_after(&self) -> Option<Duration> {
+        match &self.refusal {
+            QuotaRefusal::Throttled { retry_after } => Some(*retry_after),
+            QuotaRefusal::Rejected(_) => None,
+        }
+    }
+
+    /// Protocol error to send the over-limit client
+    pub fn to_error_message(&self) -> crate::protocol::ErrorMessage {
+        use crate::protocol::{ErrorMessage, Status};
+
+        let mut details = HashMap::new();
+        details.insert("limit".to_string(), "quota".to_string());
+        details.insert("session_id".to_string(), self.session_id.clone());
+        details.insert("principal".to_string(), self.principal.clone());
+        details.insert("direction".to_string(), self.direction.as_str().to_string());
+        let traffic = &self.session;
+        for (key, value) in [
+            ("bytes_sent", traffic.bytes_sent),
+            ("bytes_received", traffic.bytes_received),
+            ("messages_sent", traffic.messages_sent),
+            ("messages_received", traffic.messages_received),
+        ] {
+            details.insert(key.to_string(), value.to_string());
+        }
+        match &self.refusal {
+            QuotaRefusal::Throttled { retry_after } => {
+                details.insert(
+                    "retry_after_ms".to_string(),
+                    retry_after.as_millis().to_string(),
+                );
+            }
+            QuotaRefusal::Rejected(code) => {
+                details.insert("code".to_string(), code.as_str().to_string());
+            }
+        }
+
+        ErrorMessage {
+            status: Status::RateLimited as i32,
+            message: self.to_string(),
+            details,
+        }
+    }
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.refusal {
+            QuotaRefusal::Throttled { retry_after } => write!(
+                f,
+                "sync paused: quota exceeded for {} (retry in {} ms)",
+                self.principal,
+                retry_after.as_millis()
+            ),
+            QuotaRefusal::Rejected(code) => write!(
+                f,
+                "sync refused: quota exceeded for {} ({})",
+                self.principal,
+                code.as_str()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::sync::SyncCoordinator;
+    use crate::protocol::Status;
+    use crate::time::MockTime;
+
+    /// Coordinator allowing each principal 1000 bytes a minute, on a
+    /// clock the test advances by hand
+    fn limited(over_limit: OverLimit) -> (SyncCoordinator, MockTime) {
+        let time = MockTime::new(0);
+        let mut coordinator = SyncCoordinator::new();
+        coordinator.set_time_provider(time.clone());
+        coordinator.set_quota_policy(WindowQuota {
+            window: Duration::from_secs(60),
+            max_bytes: Some(1_000),
+            max_messages: None,
+            over_limit,
+        });
+        coordinator.open_session("s1", "tenant");
+        (coordinator, time)
+    }
+
+    #[test]
+    fn test_throttles_until_the_window_ends() {
+        let (mut coordinator, time) = limited(OverLimit::Throttle);
+        for _ in 0..10 {
+            coordinator.admit("s1", Direction::Outgoing, 60).unwrap();
+            coordinator.admit("s1", Direction::Incoming, 40).unwrap();
+        }
+        time.advance(20_000);
+        let exceeded = coordinator.admit("s1", Direction::Incoming, 1).unwrap_err();
+        assert_eq!(exceeded.retry_after(), Some(Duration::from_secs(40)));
+        assert_eq!(exceeded.session.total_bytes(), 1_000);
+
+        // Paused both ways, whatever the policy would say
+        time.advance(39_999);
+        let exceeded = coordinator.admit("s1", Direction::Outgoing, 1).unwrap_err();
+        assert_eq!(exceeded.retry_after(), Some(Duration::from_millis(1)));
+        assert!(coordinator.quota_paused("s1"));
+
+        time.advance(1);
+        assert!(!coordinator.quota_paused("s1"));
+        coordinator.admit("s1", Direction::Outgoing, 500).unwrap();
+
+        let session = coordinator.metrics().session("s1").unwrap();
+        assert_eq!(session.principal, "tenant");
+        assert_eq!(session.traffic.bytes_sent, 1_100);
+        assert_eq!(session.traffic.bytes_received, 400);
+        assert_eq!(session.traffic.messages_sent, 11);
+        assert_eq!(session.traffic.messages_received, 10);
+        assert_eq!(session.traffic.throttled, 2);
+        assert_eq!(coordinator.metrics().total(), session.traffic);
+    }
+
+    #[test]
+    fn test_principal_quota_spans_sessions_and_resumes() {
+        let (mut coordinator, _) = limited(OverLimit::Reject);
+        coordinator.open_session("s2", "tenant");
+        coordinator.open_session("other", "someone-else");
+        coordinator.admit("s1", Direction::Outgoing, 700).unwrap();
+        let exceeded = coordinator
+            .admit("s2", Direction::Outgoing, 400)
+            .unwrap_err();
+        assert_eq!(
+            exceeded.refusal,
+            QuotaRefusal::Rejected(QuotaCode::BytesExceeded)
+        );
+        assert_eq!(exceeded.retry_after(), None);
+        // Rejecting doesn't pause: a smaller message still fits
+        coordinator.admit("s2", Direction::Outgoing, 300).unwrap();
+        coordinator
+            .admit("other", Direction::Outgoing, 900)
+            .unwrap();
+
+        let tenant = coordinator.metrics().principal("tenant").unwrap();
+        assert_eq!((tenant.bytes_sent, tenant.rejected), (1_000, 1));
+
+        // A session reconnecting, even to a restarted server, keeps its
+        // counters
+        let saved = serde_json::to_vec(coordinator.metrics()).unwrap();
+        let mut restarted = SyncCoordinator::new();
+        restarted.restore_metrics(serde_json::from_slice(&saved).unwrap());
+        restarted.open_session("s1", "tenant");
+        restarted.admit("s1", Direction::Incoming, 5).unwrap();
+        let session = restarted.metrics().session("s1").unwrap();
+        assert_eq!(session.traffic.bytes_sent, 700);
+        assert_eq!(session.traffic.bytes_received, 5);
+        assert_eq!(restarted.metrics().total().total_bytes(), 1_905);
+    }
+
+    #[test]
+    fn test_quota_error_carries_the_quota_state() {
+        let (mut coordinator, _) = limited(OverLimit::Throttle);
+        coordinator.admit("s1", Direction::Outgoing, 900).unwrap();
+        let error = coordinator
+            .admit("s1", Direction::Outgoing, 200)
+            .unwrap_err()
+            .to_error_message();
+        assert_eq!(error.status, Status::RateLimited as i32);
+        assert!(error.message.starts_with("sync paused: quota exceeded"));
+        assert_eq!(error.details["principal"], "tenant");
+        assert_eq!(error.details["bytes_sent"], "900");
+        assert_eq!(error.details["retry_after_ms"], "60000");
+    }
+}
@@ -3,6 +3,7 @@
 //! This module provides conversion between our internal CRDT types
 //! and the Protocol Buffer message format for network transmission.
 
+use crate::codec::{self, ValueFormat};
 use crate::error::{Result, SyncError};
 use crate::protocol::*;
 use bytes::{Bytes, BytesMut};
@@ -15,6 +16,11 @@ use crate::crdt::PNCounter;
 #[cfg(feature = "sets")]
 use crate::crdt::ORSet;
 
+#[cfg(feature = "text-crdt")]
+use crate::crdt::text_fugue::{
+    ClientId as TextClientId, FugueBlock, FugueText, Marks, NodeId, TextLimits,
+};
+
 /// Serialize a PN-Counter to protocol format
 #[cfg(feature = "counters")]
 pub fn serialize_pn_counter(counter: &PNCounter, client_id: &str) -> CounterOperation {
@@ -51,9 +57,7 @@ pub fn deserialize_pn_counter(op: &CounterOperation, client_id: &str) -> Result<
             }
         }
         Err(_) => {
-            return Err(SyncError::Protocol(
-                "Invalid counter operation type".to_string(),
-            ))
+            return Err(SyncError::Protocol("Invalid counter operation type".to_string()).into())
         }
     }
 
@@ -106,9 +110,7 @@ where
                 // Handle remove operations if needed
             }
             Err(_) => {
-                return Err(SyncError::Protocol(
-                    "Invalid set operation type".to_string(),
-                ))
+                return Err(SyncError::Protocol("Invalid set operation type".to_string()).into())
             }
         }
     }
@@ -197,7 +199,352 @@ pub fn protocol_value_to_json(proto: &Value) -> Result<serde_json::Value> {
     }
 }
 
+/// Convert an awareness update to protocol format, with its state in
+/// `format`
+///
+/// `client` is the inline client id for a standalone update, or an index
+/// into the client table of an `AwarenessDiff`.
+fn awareness_update_to_protocol(
+    update: &crate::awareness::AwarenessUpdate,
+    client: awareness_update::Client,
+    format: ValueFormat,
+) -> Result<AwarenessUpdate> {
+    let state = match &update.state {
+        Some(state) => {
+            let bytes = codec::encode_value(state, format)?;
+            Some(match format {
+                ValueFormat::Json => awareness_update::State::StateJson(bytes),
+                #[cfg(feature = "cbor")]
+                ValueFormat::Cbor => awareness_update::State::StateCbor(bytes),
+            })
+        }
+        None => None,
+    };
+
+    Ok(AwarenessUpdate {
+        client: Some(client),
+        clock: update.clock,
+        state,
+        base_clock: update.base_clock,
+    })
+}
+
+/// Convert protocol AwarenessUpdate to internal format, resolving client
+/// indexes against `clients`
+fn awareness_update_from_protocol(
+    proto: &AwarenessUpdate,
+    clients: &[String],
+) -> Result<crate::awareness::AwarenessUpdate> {
+    let client_id = match &proto.client {
+        Some(awareness_update::Client::ClientId(client_id)) => client_id.clone(),
+        Some(awareness_update::Client::ClientIndex(index)) => {
+            clients.get(*index as usize).cloned().ok_or_else(|| {
+                SyncError::Protocol(format!("Awareness client index {} out of range", index))
+            })?
+        }
+        None => return Err(SyncError::Protocol("Missing awareness client".to_string()).into()),
+    };
+
+    let state = match &proto.state {
+        Some(awareness_update::State::StateJson(bytes)) => {
+            Some(codec::decode_value(bytes, ValueFormat::Json)?)
+        }
+        #[cfg(feature = "cbor")]
+        Some(awareness_update::State::StateCbor(bytes)) => {
+            Some(codec::decode_value(bytes, ValueFormat::Cbor)?)
+        }
+        #[cfg(not(feature = "cbor"))]
+        Some(awareness_update::State::StateCbor(_)) => {
+            return Err(SyncError::Protocol(
+                "CBOR awareness state requires the cbor feature".to_string(),
+            )
+            .into())
+        }
+        None => None,
+    };
+
+    Ok(crate::awareness::AwarenessUpdate {
+        client_id,
+        state,
+        clock: proto.clock,
+        base_clock: proto.base_clock,
+    })
+}
+
+impl crate::awareness::AwarenessUpdate {
+    /// Encode as a protobuf `AwarenessUpdate`, with the state as JSON
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.to_bytes_as(ValueFormat::Json)
+    }
+
+    /// Encode as a protobuf `AwarenessUpdate`, with the state in `format`
+    pub fn to_bytes_as(&self, format: ValueFormat) -> Result<Vec<u8>> {
+        let client = awareness_update::Client::ClientId(self.client_id.clone());
+        let proto = awareness_update_to_protocol(self, client, format)?;
+        Ok(prost::Message::encode_to_vec(&proto))
+    }
+
+    /// Decode an update produced by `to_bytes` or `to_bytes_as`
+    ///
+    /// # Errors
+    ///
+    /// Returns `SyncError::Protocol` if the bytes are not a standalone
+    /// awareness update
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let proto: AwarenessUpdate = decode_message(bytes)?;
+        awareness_update_from_protocol(&proto, &[])
+    }
+}
+
+impl crate::awareness::AwarenessDiff {
+    /// Encode as a protobuf `AwarenessDiff`, with states as JSON
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.to_bytes_as(ValueFormat::Json)
+    }
+
+    /// Encode as a protobuf `AwarenessDiff`, with states in `format`
+    ///
+    /// Each client id is written once, in a table the updates index into.
+    pub fn to_bytes_as(&self, format: ValueFormat) -> Result<Vec<u8>> {
+        let mut clients: Vec<String> = Vec::new();
+        let mut indexes: std::collections::HashMap<&str, u32> = std::collections::HashMap::new();
+        let updates = self
+            .updates
+            .iter()
+            .map(|update| {
+                let index = *indexes.entry(&update.client_id).or_insert_with(|| {
+                    clients.push(update.client_id.clone());
+                    (clients.len() - 1) as u32
+                });
+                awareness_update_to_protocol(
+                    update,
+                    awareness_update::Client::ClientIndex(index),
+                    format,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(prost::Message::encode_to_vec(&AwarenessDiff {
+            clients,
+            updates,
+        }))
+    }
+
+    /// Decode a diff produced by `to_bytes` or `to_bytes_as`
+    ///
+    /// # Errors
+    ///
+    /// Returns `SyncError::Protocol` if the bytes are malformed or an update
+    /// refers to a client missing from the table
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let proto: AwarenessDiff = decode_message(bytes)?;
+        let updates = proto
+            .updates
+            .iter()
+            .map(|update| awareness_update_from_protocol(update, &proto.clients))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(crate::awareness::AwarenessDiff { updates })
+    }
+}
+
+/// Client table of a `TextState`, each client id once in first-seen order
+#[cfg(feature = "text-crdt")]
+#[derive(Default)]
+struct TextClients<'a> {
+    clients: Vec<String>,
+    indexes: std::collections::HashMap<&'a str, u32>,
+}
+
+#[cfg(feature = "text-crdt")]
+impl<'a> TextClients<'a> {
+    fn index(&mut self, client_id: &'a str) -> u32 {
+        let clients = &mut self.clients;
+        *self.indexes.entry(client_id).or_insert_with(|| {
+            clients.push(client_id.to_string());
+            (clients.len() - 1) as u32
+        })
+    }
+
+    fn id(&mut self, id: &'a NodeId) -> TextStateId {
+        TextStateId {
+            client_index: self.index(&id.client_id),
+            clock: id.clock,
+            offset: id.offset as u64,
+        }
+    }
+}
+
+#[cfg(feature = "text-crdt")]
+fn text_state_id_from_protocol(proto: &TextStateId, clients: &[TextClientId]) -> Result<NodeId> {
+    let client_id = clients.get(proto.client_index as usize).ok_or_else(|| {
+        SyncError::Protocol(format!(
+            "Text client index {} out of range",
+            proto.client_index
+        ))
+    })?;
+    Ok(NodeId::new(
+        client_id.clone(),
+        proto.clock,
+        proto.offset as usize,
+    ))
+}
+
+#[cfg(feature = "text-crdt")]
+impl FugueText {
+    /// Encode the whole text as a protobuf `TextState`
+    ///
+    /// The binary counterpart of its JSON, and several times smaller: each
+    /// client id is written once, in a table the block IDs index into.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::crdt::text_fugue::FugueText;
+    ///
+    /// let mut text = FugueText::new("client1".to_string());
+    /// text.insert(0, "Hello World").unwrap();
+    /// text.delete(5, 6).unwrap();
+    ///
+    /// let loaded = FugueText::from_proto_bytes(&text.to_proto_bytes()).unwrap();
+    /// assert_eq!(loaded.to_string(), "Hello");
+    /// assert_eq!(loaded.structure_hash(), text.structure_hash());
+    /// ```
+    pub fn to_proto_bytes(&self) -> Vec<u8> {
+        let mut clients = TextClients::default();
+        let client_index = clients.index(self.client_id());
+        let (blocks, deleted) = self
+            .all_blocks()
+            .map(|block| {
+                let state_block = TextStateBlock {
+                    id: Some(clients.id(&block.id)),
+                    text: block.text.to_string(),
+                    left_origin: block.left_origin.as_ref().map(|id| clients.id(id)),
+                    right_origin: block.right_origin.as_ref().map(|id| clients.id(id)),
+                    deleted_at: block.deleted_at.as_ref().map(|id| clients.id(id)),
+                };
+                (state_block, block.is_deleted())
+            })
+            .unzip();
+
+        prost::Message::encode_to_vec(&TextState {
+            clients: clients.clients,
+            blocks,
+            deleted,
+            clock: self.clock(),
+            client_index,
+            annotations: self
+                .annotations()
+                .iter()
+                .map(crate::protocol::delta::annotation_to_protocol)
+                .collect(),
+            marks: self
+                .marks()
+                .iter()
+                .map(crate::protocol::delta::text_mark_to_protocol)
+                .collect(),
+        })
+    }
+
+    /// Decode a text encoded by `to_proto_bytes`, unlimited
+    ///
+    /// # Errors
+    ///
+    /// Returns `SyncError::Protocol` if the bytes are malformed, an ID
+    /// refers to a client missing from the table, or there isn't one
+    /// tombstone flag per block
+    pub fn from_proto_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_proto_bytes_with_limits(bytes, TextLimits::default())
+    }
+
+    /// Decode a text encoded by `to_proto_bytes`, refusing one over
+    /// `limits`; the text keeps them for later edits and merges
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextError::LimitExceeded` if the text is over `limits`,
+    /// checked before its rope and caches are built, or
+    /// `SyncError::Protocol` as [`from_proto_bytes`](Self::from_proto_bytes)
+    /// does
+    pub fn from_proto_bytes_with_limits(bytes: &[u8], limits: TextLimits) -> Result<Self> {
+        let proto: TextState = decode_message(bytes)?;
+        if proto.deleted.len() != proto.blocks.len() {
+            return Err(SyncError::Protocol(format!(
+                "{} tombstone flags for {} text blocks",
+                proto.deleted.len(),
+                proto.blocks.len()
+            ))
+            .into());
+        }
+        let clients: Vec<TextClientId> = proto.clients.iter().map(TextClientId::from).collect();
+        let id = |id: &TextStateId| text_state_id_from_protocol(id, &clients);
+        let origin = |origin: &Option<TextStateId>| origin.as_ref().map(id).transpose();
+
+        let blocks = proto
+            .blocks
+            .iter()
+            .zip(&proto.deleted)
+            .map(|(block, &deleted)| {
+                let block_id = block
+                    .id
+                    .as_ref()
+                    .ok_or_else(|| SyncError::Protocol("Missing text block ID".to_string()))?;
+                let mut fugue_block = FugueBlock::new(
+                    id(block_id)?,
+                    block.text.clone(),
+                    origin(&block.left_origin)?,
+                    origin(&block.right_origin)?,
+                );
+                if deleted {
+                    match origin(&block.deleted_at)? {
+                        Some(stamp) => fugue_block.mark_deleted_at(stamp),
+                        None => fugue_block.mark_deleted(),
+                    }
+                }
+                Ok(fugue_block)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let client_id = clients
+            .get(proto.client_index as usize)
+            .ok_or_else(|| {
+                SyncError::Protocol(format!(
+                    "Text client index {} out of range",
+                    proto.client_index
+                ))
+            })?
+            .to_string();
+        let mut annotations = crate::annotations::Annotations::default();
+        for annotation in &proto.annotations {
+            annotations.insert(crate::protocol::delta::annotation_from_protocol(
+                annotation,
+            )?);
+        }
+        let marks = proto
+            .marks
+            .iter()
+            .map(crate::protocol::delta::text_mark_from_protocol)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(FugueText::from_state(
+            client_id,
+            proto.clock,
+            blocks,
+            annotations,
+            Marks::from(marks),
+            limits,
+        )?)
+    }
+}
+
 /// Serialize any protocol message to bytes
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "encode_message",
+        skip_all,
+        fields(message = message_name::<M>(), bytes = msg.encoded_len())
+    )
+)]
 pub fn encode_message<M: Message>(msg: &M) -> Result<Bytes> {
     let mut buf = BytesMut::with_capacity(msg.encoded_len());
     msg.encode(&mut buf)
@@ -206,8 +553,24 @@ pub fn encode_message<M: Message>(msg: &M) -> Result<Bytes> {
 }
 
 /// Deserialize a protocol message from bytes
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "decode_message",
+        skip_all,
+        fields(message = message_name::<M>(), bytes = bytes.len())
+    )
+)]
 pub fn decode_message<M: Message + Default>(bytes: &[u8]) -> Result<M> {
-    M::decode(bytes).map_err(|e| SyncError::Protocol(format!("Failed to decode message: {}", e)))
+    M::decode(bytes)
+        .map_err(|e| SyncError::Protocol(format!("Failed to decode message: {}", e)).into())
+}
+
+/// Short message type name for span fields, e.g. `Delta`
+#[cfg(feature = "tracing")]
+fn message_name<M>() -> &'static str {
+    let name = std::any::type_name::<M>();
+    name.rsplit("::").next().unwrap_or(name)
 }
 
 #[cfg(test)]
@@ -250,4 +613,206 @@ mod tests {
         let ops = serialize_or_set(&set, "client1");
         assert_eq!(ops.len(), 2);
     }
+
+    fn cursor_move() -> crate::awareness::AwarenessUpdate {
+        let mut awareness = crate::awareness::Awareness::new("client-1".to_string());
+        awareness
+            .set_local_state(serde_json::json!({"name": "Alice", "cursor": [0, 0]}))
+            .unwrap();
+        awareness
+            .update_local_state(serde_json::json!({"cursor": [12, 40]}))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_awareness_update_round_trip() {
+        let patch = cursor_move();
+        let leave = crate::awareness::AwarenessUpdate {
+            state: None,
+            base_clock: None,
+            ..patch.clone()
+        };
+
+        for update in [patch, leave] {
+            let decoded =
+                crate::awareness::AwarenessUpdate::from_bytes(&update.to_bytes().unwrap()).unwrap();
+            assert_eq!(decoded.client_id, update.client_id);
+            assert_eq!(decoded.state, update.state);
+            assert_eq!(decoded.clock, update.clock);
+            assert_eq!(decoded.base_clock, update.base_clock);
+        }
+    }
+
+    #[test]
+    fn test_cursor_move_encodes_in_a_few_dozen_bytes() {
+        let update = cursor_move();
+        let bytes = update.to_bytes().unwrap();
+        let json = serde_json::to_string(&update).unwrap();
+
+        assert!(bytes.len() <= 40, "encoded in {} bytes", bytes.len());
+        assert!(bytes.len() * 2 < json.len(), "JSON is {} bytes", json.len());
+
+        #[cfg(feature = "cbor")]
+        assert!(update.to_bytes_as(ValueFormat::Cbor).unwrap().len() < bytes.len());
+    }
+
+    #[test]
+    fn test_awareness_diff_shares_client_table() {
+        let mut alice = crate::awareness::Awareness::new("alice".to_string());
+        let mut relay = crate::awareness::Awareness::new("relay".to_string());
+        for n in 0..3 {
+            let id = format!("peer-with-a-long-client-id-{}", n);
+            relay.apply_update(crate::awareness::AwarenessUpdate {
+                client_id: id,
+                state: Some(serde_json::json!({"n": n})),
+                clock: 1,
+                base_clock: None,
+            });
+        }
+        relay.apply_update(crate::awareness::AwarenessUpdate {
+            client_id: "gone".to_string(),
+            state: None,
+            clock: 2,
+            base_clock: None,
+        });
+
+        let diff = relay.encode_diff(&alice.version());
+        let bytes = diff.to_bytes().unwrap();
+        let proto: AwarenessDiff = decode_message(&bytes).unwrap();
+        assert_eq!(proto.clients.len(), 4);
+
+        alice.apply_diff(crate::awareness::AwarenessDiff::from_bytes(&bytes).unwrap());
+        assert_eq!(alice.client_count(), 3);
+        assert_eq!(alice.version(), relay.version());
+    }
+
+    #[test]
+    fn test_awareness_diff_rejects_unknown_client_index() {
+        let proto = AwarenessDiff {
+            clients: vec!["alice".to_string()],
+            updates: vec![AwarenessUpdate {
+                client: Some(awareness_update::Client::ClientIndex(1)),
+                clock: 1,
+                state: None,
+                base_clock: None,
+            }],
+        };
+        let bytes = prost::Message::encode_to_vec(&proto);
+        assert!(crate::awareness::AwarenessDiff::from_bytes(&bytes).is_err());
+
+        // A standalone update has no table to index into
+        let bytes = prost::Message::encode_to_vec(&proto.updates[0]);
+        assert!(crate::awareness::AwarenessUpdate::from_bytes(&bytes).is_err());
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_proto_round_trip() {
+        use crate::crdt::text_fugue::MarkExpand;
+
+        let mut alice = FugueText::new("alice".to_string());
+        alice.insert(0, "Hello World").unwrap();
+        let mut bob = FugueText::new("bob".to_string());
+        bob.merge(&alice).unwrap();
+        alice.delete(5, 6).unwrap();
+        bob.insert(6, "there, 👋 ").unwrap();
+        bob.annotated(serde_json::json!({"source": "paste"}), |text| {
+            text.insert(0, ">> ").unwrap()
+        })
+        .unwrap();
+        bob.add_mark(0..5, "bold", serde_json::json!(true), MarkExpand::After)
+            .unwrap();
+        alice.merge(&bob).unwrap();
+
+        let bytes = alice.to_proto_bytes();
+        let proto: TextState = decode_message(&bytes).unwrap();
+        assert_eq!(proto.clients, vec!["alice", "bob"]);
+
+        let loaded = FugueText::from_proto_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_string(), alice.to_string());
+        assert_eq!(loaded.client_id(), "alice");
+        assert_eq!(loaded.clock(), alice.clock());
+        assert_eq!(loaded.structure_hash(), alice.structure_hash());
+        assert_eq!(
+            serde_json::to_string(&loaded).unwrap(),
+            serde_json::to_string(&alice).unwrap()
+        );
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_proto_is_a_third_of_the_json() {
+        let mut text = FugueText::new("client-0123456789abcdef".to_string());
+        let mut seed = 7u64;
+        while text.len() < 10_000 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            let position = (seed >> 33) as usize % (text.len() + 1);
+            text.insert(position, "a few words typed. ").unwrap();
+        }
+
+        let bytes = text.to_proto_bytes();
+        let json = serde_json::to_string(&text).unwrap();
+        assert!(
+            bytes.len() * 3 <= json.len(),
+            "{} bytes as protobuf, {} as JSON",
+            bytes.len(),
+            json.len()
+        );
+        assert_eq!(
+            FugueText::from_proto_bytes(&bytes).unwrap().to_string(),
+            text.to_string()
+        );
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_proto_over_limits_is_rejected() {
+        use crate::crdt::text_fugue::{TextError, TextLimit};
+        use crate::error::ErrorKind;
+
+        let mut text = FugueText::new("alice".to_string());
+        for _ in 0..10 {
+            text.insert(0, "ab").unwrap();
+        }
+        let bytes = text.to_proto_bytes();
+        let small = TextLimits {
+            max_blocks: 5,
+            ..TextLimits::default()
+        };
+
+        let error = FugueText::from_proto_bytes_with_limits(&bytes, small).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Text(TextError::LimitExceeded {
+                limit: TextLimit::Blocks,
+                max: 5,
+                ..
+            })
+        ));
+
+        let roomy = TextLimits {
+            max_blocks: 10,
+            ..TextLimits::default()
+        };
+        let loaded = FugueText::from_proto_bytes_with_limits(&bytes, roomy).unwrap();
+        assert_eq!(loaded.to_string(), text.to_string());
+        assert_eq!(loaded.limits(), roomy);
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_proto_rejects_malformed_state() {
+        let mut text = FugueText::new("alice".to_string());
+        text.insert(0, "Hello").unwrap();
+        let mut proto: TextState = decode_message(&text.to_proto_bytes()).unwrap();
+
+        proto.deleted.clear();
+        let bytes = prost::Message::encode_to_vec(&proto);
+        assert!(FugueText::from_proto_bytes(&bytes).is_err());
+
+        proto.deleted = vec![false];
+        proto.blocks[0].id.as_mut().unwrap().client_index = 1;
+        let bytes = prost::Message::encode_to_vec(&proto);
+        assert!(FugueText::from_proto_bytes(&bytes).is_err());
+    }
 }
@@ -1,7 +1,1040 @@
 // Sync coordinator module
 //!
-//! This module provides sync coordination logic
+//! This module provides sync coordination logic: the per-document
+//! [`SyncFilter`]s applied to everything that leaves or enters a replica,
+//! and anti-entropy probes.
+//!
+//! # Anti-entropy
+//!
+//! Exchanging every document's version vector to find the few that
+//! differ costs as much as the workspace is large. Instead, one side sends
+//! a [`VersionFilter`], a Bloom filter over its `(document ID, version)`
+//! pairs, sized by the coordinator's [`AntiEntropyConfig`]. The receiver
+//! checks its own documents against it and asks for only those that are
+//! missing from it, with the `VersionSummary` from
+//! [`SyncCoordinator::reconciliation_request`]; the prober answers with
+//! [`SyncCoordinator::answer_reconciliation`].
+//!
+//! A false positive hides a document that differs. Each probe takes a
+//! seed that moves the false positives to other documents, so probing
+//! again with a new seed finds what the last round missed. Both sides
+//! probe: the receiver can only ask for documents it has.
+//!
+//! # Convergence status
+//!
+//! "No pending changes" doesn't prove two replicas hold the same state.
+//! To confirm it, peers exchange [`ConvergenceChecksum`]s, a document's
+//! version and [`Document::content_hash`], from
+//! [`SyncCoordinator::checksum`], and check each other's with
+//! [`SyncCoordinator::receive_checksum`]. [`SyncCoordinator::sync_status`]
+//! then reports `in_sync` only while the last exchange found matching
+//! hashes at the version we hold, and `diverged` when the hashes differ
+//! at equal versions. Deltas can't repair a divergence, as neither side
+//! is missing a change by version: exchange snapshots
+//! ([`SyncCoordinator::outgoing_snapshot`] and
+//! [`SyncCoordinator::merge_incoming_snapshot`]) and checksums again.
+//!
+//! # Field kinds
+//!
+//! Peers that can't apply every field kind say so in the sync handshake;
+//! [`SyncCoordinator::negotiate`] records what the peer takes, and what
+//! the coordinator sends and accepts is downgraded to match (see
+//! [`downgrade`](crate::protocol::downgrade)).
+//!
+//! # Quotas
+//!
+//! [`SyncCoordinator::admit`] counts each message of a session and holds
+//! back those over the coordinator's quota policy; see
+//! [`quota`](crate::protocol::quota).
+
+use crate::document::Document;
+use crate::error::{Result, SyncError, SyncKitError};
+use crate::protocol::delta::{
+    document_version_to_protocol, version_summary_from_protocol, DocumentDelta, DryRunReport,
+    FieldChange,
+};
+use crate::protocol::downgrade::{self, FieldKinds};
+use crate::protocol::quota::{
+    Direction, QuotaExceeded, QuotaOutcome, QuotaRefusal, QuotaRequest, SessionMetrics,
+    SharedQuota, SyncMetrics,
+};
+use crate::protocol::serialize::{decode_message, encode_message};
+use crate::protocol::VersionSummary;
+use crate::sync::{SyncFilter, VectorClock};
+use crate::time::{SharedTime, TimeProvider};
+use crate::DocumentID;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Sizing of anti-entropy probes and of what they trigger
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AntiEntropyConfig {
+    /// Chance that a document that differs passes a probe unnoticed
+    pub false_positive_rate: f64,
+
+    /// Most documents one probe asks to reconcile; the rest wait for the
+    /// next probe
+    pub max_follow_ups: usize,
+}
+
+impl Default for AntiEntropyConfig {
+    fn default() -> Self {
+        Self {
+            false_positive_rate: 0.01,
+            max_follow_ups: 64,
+        }
+    }
+}
+
+/// Bloom filter over `(document ID, version)` pairs (see the module docs)
+///
+/// Never reports a pair that was inserted as missing. A pair that wasn't
+/// inserted is reported present with about the false-positive rate it was
+/// sized for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    hashes: u32,
+    seed: u64,
+}
+
+impl VersionFilter {
+    /// Most bit positions set per entry
+    const MAX_HASHES: u32 = 16;
+
+    /// Empty filter for `expected` entries at `false_positive_rate`
+    pub fn new(expected: usize, false_positive_rate: f64, seed: u64) -> Self {
+        let rate = false_positive_rate.clamp(1e-9, 0.5);
+        let n = expected.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(n * rate.ln()) / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let hashes = ((num_bits as f64 / n) * ln2).round() as u32;
+        Self {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            hashes: hashes.clamp(1, Self::MAX_HASHES),
+            seed,
+        }
+    }
+
+    /// Add a document's version
+    pub fn insert(&mut self, document_id: &str, version: &VectorClock) {
+        for bit in self.positions(document_id, version) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Check if a document's version was (probably) inserted
+    pub fn contains(&self, document_id: &str, version: &VectorClock) -> bool {
+        self.positions(document_id, version)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// The seed the filter was built with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Size of the filter bits in bytes
+    pub fn byte_len(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// Convert to protocol format
+    pub fn to_protocol(&self) -> crate::protocol::VersionFilter {
+        crate::protocol::VersionFilter {
+            bits: self
+                .bits
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect(),
+            num_bits: self.num_bits,
+            hashes: self.hashes,
+            seed: self.seed,
+        }
+    }
+
+    /// Create from protocol format
+    ///
+    /// # Errors
+    ///
+    /// Fails with `PROTOCOL_ERROR` if the sizes don't fit together
+    pub fn from_protocol(proto: &crate::protocol::VersionFilter) -> Result<Self> {
+        let words = proto.bits.len() / 8;
+        if !proto.bits.len().is_multiple_of(8)
+            || proto.num_bits == 0
+            || proto.num_bits.div_ceil(64) != words as u64
+            || !(1..=Self::MAX_HASHES).contains(&proto.hashes)
+        {
+            return Err(SyncError::Protocol("Malformed version filter".to_string()).into());
+        }
+        let bits = proto
+            .bits
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().expect("8-byte chunk")))
+            .collect();
+        Ok(Self {
+            bits,
+            num_bits: proto.num_bits,
+            hashes: proto.hashes,
+            seed: proto.seed,
+        })
+    }
+
+    /// Bits of an entry, by double hashing
+    fn positions(&self, document_id: &str, version: &VectorClock) -> impl Iterator<Item = u64> {
+        let first = version_hash(self.seed, document_id, version);
+        // Odd, so the steps visit distinct bits
+        let step = mix(first) | 1;
+        let num_bits = self.num_bits;
+        (0..u64::from(self.hashes))
+            .map(move |i| first.wrapping_add(i.wrapping_mul(step)) % num_bits)
+    }
+}
+
+/// 64-bit FNV-1a of a document ID and its version, seeded
+///
+/// Clients at zero count as absent, so equal clocks always hash equally.
+fn version_hash(seed: u64, document_id: &str, version: &VectorClock) -> u64 {
+    let mut clocks: Vec<_> = version
+        .clocks()
+        .iter()
+        .filter(|(_, clock)| **clock > 0)
+        .collect();
+    clocks.sort();
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes.iter().chain(&[0xff]) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    write(&seed.to_le_bytes());
+    write(document_id.as_bytes());
+    for (client_id, clock) in clocks {
+        write(client_id.as_bytes());
+        write(&clock.to_le_bytes());
+    }
+    mix(hash)
+}
+
+/// SplitMix64 finalizer, to spread FNV's weak low bits
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// What a peer that reported `peer_version` is known to have of `current`
+///
+/// If the peer has seen every change counted in our version that is all
+/// of `current`; otherwise assume nothing, so a delta from the result
+/// sends every field.
+pub(crate) fn peer_baseline(current: &Document, peer_version: Option<&VectorClock>) -> Document {
+    let peer_version = peer_version.cloned().unwrap_or_default();
+    let covered = current
+        .version()
+        .clocks()
+        .iter()
+        .all(|(client_id, clock)| peer_version.get(client_id) >= *clock);
+    if covered {
+        current.clone()
+    } else {
+        let mut baseline = Document::new(current.id().clone());
+        baseline.version = peer_version;
+        baseline
+    }
+}
+
+/// A replica's version and content hash of a document, sent to a peer to
+/// confirm convergence (see the module docs)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConvergenceChecksum {
+    /// Document the checksum is of
+    pub document_id: DocumentID,
+
+    /// The sender's version of the document
+    pub version: VectorClock,
+
+    /// The sender's [`Document::content_hash`], as 16 hex digits so
+    /// JavaScript doesn't round it
+    pub hash: String,
+}
+
+impl ConvergenceChecksum {
+    /// Checksum of `document` as it is now
+    pub fn of(document: &Document) -> Self {
+        Self {
+            document_id: document.id().clone(),
+            version: document.version().clone(),
+            hash: format!("{:016x}", document.content_hash()),
+        }
+    }
+}
+
+/// How a document stands with the peer, from
+/// [`SyncCoordinator::sync_status`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncStatus {
+    /// Whether the peer confirmed holding the same state at our current
+    /// version, and has reported nothing newer since
+    pub in_sync: bool,
+
+    /// Local changes the peer hasn't reported having, in clock ticks
+    pub pending_local: u64,
+
+    /// Changes the peer reported that we lack, in clock ticks; as fresh as
+    /// the peer's last checksum
+    pub pending_remote_estimate: u64,
+
+    /// When the peer last confirmed matching state, in milliseconds from
+    /// the coordinator's clock
+    pub last_verified_at: Option<u64>,
 
+    /// Whether the peer reported different state at the same version;
+    /// cleared by the next matching checksum
+    pub diverged: bool,
+}
+
+/// What a coordinator knows of a document's convergence with the peer
+#[derive(Debug, Clone, Default)]
+struct Convergence {
+    local: VectorClock,
+    remote: VectorClock,
+    verified: Option<VectorClock>,
+    last_verified_at: Option<u64>,
+    diverged: bool,
+}
+
+impl Convergence {
+    fn status(&self) -> SyncStatus {
+        let pending_local = ticks_ahead(&self.local, &self.remote);
+        let pending_remote_estimate = ticks_ahead(&self.remote, &self.local);
+        let verified_now = self.verified.as_ref().is_some_and(|verified| {
+            ticks_ahead(&self.local, verified) == 0 && ticks_ahead(verified, &self.local) == 0
+        });
+        SyncStatus {
+            in_sync: verified_now
+                && !self.diverged
+                && pending_local == 0
+                && pending_remote_estimate == 0,
+            pending_local,
+            pending_remote_estimate,
+            last_verified_at: self.last_verified_at,
+            diverged: self.diverged,
+        }
+    }
+}
+
+/// Clock ticks `a` has that `b` lacks
+fn ticks_ahead(a: &VectorClock, b: &VectorClock) -> u64 {
+    a.clocks()
+        .iter()
+        .map(|(client_id, clock)| clock.saturating_sub(b.get(client_id)))
+        .sum()
+}
+
+/// Coordinates what a replica sends and accepts
+///
+/// Documents without a filter sync every field.
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::protocol::sync::SyncCoordinator;
+/// use synckit_core::sync::SyncFilter;
+/// use synckit_core::Document;
+///
+/// let mut coordinator = SyncCoordinator::new();
+/// coordinator.set_filter("doc-1", SyncFilter::new().exclude("_local"));
+///
+/// let before = Document::new("doc-1".to_string());
+/// let mut after = before.clone();
+/// after.set_field("title".to_string(), serde_json::json!("Hi"), 1, "alice".to_string());
+/// after.set_field("_local.draft".to_string(), serde_json::json!("..."), 2, "alice".to_string());
+///
+/// let delta = coordinator.outgoing_delta(&before, &after).unwrap();
+/// assert_eq!(delta.changes.len(), 1);
+/// assert_eq!(delta.changes[0].path, "title");
+/// ```
+#[derive(Debug, Clone, Default)]
 pub struct SyncCoordinator {
-    // TODO: Implement sync coordinator
+    filters: HashMap<DocumentID, SyncFilter>,
+    anti_entropy: AntiEntropyConfig,
+    time: Option<SharedTime>,
+    convergence: HashMap<DocumentID, Convergence>,
+    quota: Option<SharedQuota>,
+    metrics: SyncMetrics,
+    field_kinds: FieldKinds,
+    peer_kinds: FieldKinds,
+}
+
+impl SyncCoordinator {
+    /// Create a coordinator with no filters
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the filter for a document, replacing any previous one
+    pub fn set_filter(&mut self, document_id: impl Into<DocumentID>, filter: SyncFilter) {
+        self.filters.insert(document_id.into(), filter);
+    }
+
+    /// Remove the filter for a document, returning it
+    pub fn remove_filter(&mut self, document_id: &str) -> Option<SyncFilter> {
+        self.filters.remove(document_id)
+    }
+
+    /// The filter for a document, if it has one
+    pub fn filter(&self, document_id: &str) -> Option<&SyncFilter> {
+        self.filters.get(document_id)
+    }
+
+    /// Set how anti-entropy probes are sized
+    pub fn set_anti_entropy(&mut self, config: AntiEntropyConfig) {
+        self.anti_entropy = config;
+    }
+
+    /// How anti-entropy probes are sized
+    pub fn anti_entropy(&self) -> &AntiEntropyConfig {
+        &self.anti_entropy
+    }
+
+    /// Advertise only `kinds` in the handshake, e.g. to act as a peer
+    /// that can't apply the others
+    pub fn set_field_kinds(&mut self, kinds: FieldKinds) {
+        self.field_kinds = kinds;
+    }
+
+    /// Field kinds to advertise in the handshake: every kind unless set
+    /// with `set_field_kinds`
+    pub fn field_kinds(&self) -> FieldKinds {
+        self.field_kinds
+    }
+
+    /// Record the `field_kinds` the peer advertised in its handshake,
+    /// returning the kinds both sides apply
+    ///
+    /// From now on, what the peer can't apply is sent as shadows or
+    /// withheld, and its writes to shadowed paths are refused (see
+    /// [`downgrade`](crate::protocol::downgrade)).
+    pub fn negotiate(&mut self, peer_bits: u32) -> FieldKinds {
+        self.peer_kinds = FieldKinds::from_bits(peer_bits);
+        self.peer_kinds.intersection(self.field_kinds)
+    }
+
+    /// Field kinds the peer applies, as last negotiated
+    pub fn peer_field_kinds(&self) -> FieldKinds {
+        self.peer_kinds
+    }
+
+    /// `SHADOWED_FIELD` errors for the peer's writes in `delta` to paths
+    /// it only holds shadows of
+    ///
+    /// `apply_incoming` refuses those writes and applies the rest; send
+    /// the peer these errors so it can drop its edits. Call before
+    /// applying, with the document the delta is for.
+    pub fn shadowed_writes(&self, delta: &DocumentDelta, document: &Document) -> Vec<SyncKitError> {
+        downgrade::shadowed_writes(delta, document, self.peer_kinds)
+    }
+
+    /// Stamp outgoing tombstones with `provider` instead of the thread's
+    /// default clock
+    pub fn set_time_provider(&mut self, provider: impl TimeProvider + Send + Sync + 'static) {
+        self.time = Some(SharedTime::new(provider));
+    }
+
+    /// Current time in milliseconds from the coordinator's clock
+    pub fn now_ms(&self) -> u64 {
+        match &self.time {
+            Some(time) => time.now_ms(),
+            None => crate::time::now_ms(),
+        }
+    }
+
+    /// Hold sessions to `policy` from now on (see [`quota`]); replaces any
+    /// previous policy
+    ///
+    /// [`quota`]: crate::protocol::quota
+    pub fn set_quota_policy(&mut self, policy: impl Into<SharedQuota>) {
+        self.quota = Some(policy.into());
+    }
+
+    /// Start counting a session's traffic under `principal`
+    ///
+    /// A session ID seen before resumes its counters and any pause.
+    pub fn open_session(&mut self, session_id: &str, principal: &str) {
+        self.metrics.open(session_id, principal);
+    }
+
+    /// Stop counting a session, returning its counters; its traffic stays
+    /// in its principal's
+    pub fn close_session(&mut self, session_id: &str) -> Option<SessionMetrics> {
+        self.metrics.forget(session_id)
+    }
+
+    /// Count a message of `bytes` going `direction` for a session, or hold
+    /// it back if the session is over its quota
+    ///
+    /// A session not opened with `open_session` counts as its own
+    /// principal. Only messages let through are counted as traffic.
+    ///
+    /// # Errors
+    ///
+    /// Fails while the session is paused, and when the policy throttles or
+    /// rejects the message; send the client `to_error_message()` and don't
+    /// send or apply the message.
+    pub fn admit(
+        &mut self,
+        session_id: &str,
+        direction: Direction,
+        bytes: usize,
+    ) -> std::result::Result<(), QuotaExceeded> {
+        let now_ms = self.now_ms();
+        let bytes = bytes as u64;
+        let session = self.metrics.session_mut(session_id);
+        let paused = session
+            .paused_until_ms
+            .filter(|until| *until > now_ms)
+            .map(|until| until - now_ms);
+        if paused.is_none() {
+            session.paused_until_ms = None;
+        }
+        let outcome = match (paused, &self.quota) {
+            (Some(remaining), _) => QuotaOutcome::Throttle(Duration::from_millis(remaining)),
+            (None, None) => QuotaOutcome::Allow,
+            (None, Some(quota)) => {
+                let session = self
+                    .metrics
+                    .session(session_id)
+                    .expect("session is counted");
+                let principal_total = self.metrics.principal_or_default(&session.principal);
+                quota.check(&QuotaRequest {
+                    session_id,
+                    principal: &session.principal,
+                    direction,
+                    bytes,
+                    now_ms,
+                    session: &session.traffic,
+                    principal_total: &principal_total,
+                })
+            }
+        };
+
+        let refusal = match outcome {
+            QuotaOutcome::Allow => {
+                self.metrics.record(session_id, direction, bytes);
+                return Ok(());
+            }
+            QuotaOutcome::Throttle(delay) => {
+                let session = self.metrics.session_mut(session_id);
+                let until = now_ms + delay.as_millis() as u64;
+                session.paused_until_ms = Some(session.paused_until_ms.unwrap_or(0).max(until));
+                QuotaRefusal::Throttled { retry_after: delay }
+            }
+            QuotaOutcome::Reject(code) => QuotaRefusal::Rejected(code),
+        };
+        let throttled = matches!(refusal, QuotaRefusal::Throttled { .. });
+        self.metrics.record_refusal(session_id, throttled);
+        let session = self.metrics.session_mut(session_id);
+        trace_debug!(session_id = %session_id, principal = %session.principal, throttled, "quota exceeded");
+        Err(QuotaExceeded {
+            session_id: session_id.to_string(),
+            principal: session.principal.clone(),
+            direction,
+            refusal,
+            session: session.traffic,
+        })
+    }
+
+    /// Check if a session is paused by a throttle
+    pub fn quota_paused(&self, session_id: &str) -> bool {
+        let now_ms = self.now_ms();
+        self.metrics
+            .session(session_id)
+            .and_then(|session| session.paused_until_ms)
+            .is_some_and(|until| until > now_ms)
+    }
+
+    /// Traffic counted so far, per session and per principal
+    pub fn metrics(&self) -> &SyncMetrics {
+        &self.metrics
+    }
+
+    /// Replace the counters with saved ones, e.g. after a restart
+    pub fn restore_metrics(&mut self, metrics: SyncMetrics) {
+        self.metrics = metrics;
+    }
+
+    /// Record `document`'s current version, for `sync_status`
+    ///
+    /// Call after local edits and after applying what the peer sent;
+    /// `checksum` and `receive_checksum` record it too.
+    pub fn track_local(&mut self, document: &Document) {
+        self.convergence
+            .entry(document.id().clone())
+            .or_default()
+            .local = document.version().clone();
+    }
+
+    /// Checksum of `document` to send the peer
+    pub fn checksum(&mut self, document: &Document) -> ConvergenceChecksum {
+        self.track_local(document);
+        ConvergenceChecksum::of(document)
+    }
+
+    /// Check the peer's checksum against `document`, our replica of it
+    ///
+    /// Equal versions with equal hashes verify the document; equal
+    /// versions with different hashes mark it diverged. Reply with our own
+    /// `checksum` so the peer can verify too.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the checksum is of another document
+    pub fn receive_checksum(
+        &mut self,
+        document: &Document,
+        remote: &ConvergenceChecksum,
+    ) -> Result<SyncStatus> {
+        if remote.document_id != *document.id() {
+            return Err(SyncKitError::from(SyncError::InvalidOperation(format!(
+                "Checksum is for document {}",
+                remote.document_id
+            )))
+            .with_document(document.id().as_str()));
+        }
+        let now_ms = self.now_ms();
+        let local = ConvergenceChecksum::of(document);
+        let state = self.convergence.entry(document.id().clone()).or_default();
+        state.local = local.version;
+        state.remote = remote.version.clone();
+        let same_version = ticks_ahead(&state.local, &state.remote) == 0
+            && ticks_ahead(&state.remote, &state.local) == 0;
+        if same_version && local.hash == remote.hash {
+            state.verified = Some(state.local.clone());
+            state.last_verified_at = Some(now_ms);
+            state.diverged = false;
+        } else if same_version {
+            state.verified = None;
+            state.diverged = true;
+        }
+        Ok(state.status())
+    }
+
+    /// How `document_id` stands with the peer
+    ///
+    /// A document with no recorded version or checksum reports the
+    /// default: not in sync, nothing pending.
+    pub fn sync_status(&self, document_id: &str) -> SyncStatus {
+        self.convergence
+            .get(document_id)
+            .map(Convergence::status)
+            .unwrap_or_default()
+    }
+
+    /// Build the probe to send a peer: a filter over every document's
+    /// version
+    ///
+    /// Use a different `seed` for each probe, so a document a false
+    /// positive hid from one round shows up in the next.
+    pub fn version_probe<'a>(
+        &self,
+        documents: impl IntoIterator<Item = &'a Document>,
+        seed: u64,
+    ) -> VersionFilter {
+        let documents: Vec<&Document> = documents.into_iter().collect();
+        let mut filter =
+            VersionFilter::new(documents.len(), self.anti_entropy.false_positive_rate, seed);
+        for document in documents {
+            filter.insert(document.id(), document.version());
+        }
+        filter
+    }
+
+    /// IDs of the documents whose version is missing from a peer's probe
+    ///
+    /// Sorted, and at most `max_follow_ups` of them.
+    pub fn probable_mismatches<'a>(
+        &self,
+        probe: &VersionFilter,
+        documents: impl IntoIterator<Item = &'a Document>,
+    ) -> Vec<DocumentID> {
+        self.mismatched(probe, documents)
+            .into_iter()
+            .map(|document| document.id().clone())
+            .collect()
+    }
+
+    /// Answer a peer's probe: our versions of the probable mismatches,
+    /// asking the peer for what we lack of them
+    pub fn reconciliation_request<'a>(
+        &self,
+        probe: &VersionFilter,
+        documents: impl IntoIterator<Item = &'a Document>,
+    ) -> VersionSummary {
+        VersionSummary {
+            documents: self
+                .mismatched(probe, documents)
+                .into_iter()
+                .map(|document| document_version_to_protocol(document.id(), document.version()))
+                .collect(),
+        }
+    }
+
+    fn mismatched<'a>(
+        &self,
+        probe: &VersionFilter,
+        documents: impl IntoIterator<Item = &'a Document>,
+    ) -> Vec<&'a Document> {
+        let mut mismatched: Vec<&Document> = documents
+            .into_iter()
+            .filter(|document| !probe.contains(document.id(), document.version()))
+            .collect();
+        mismatched.sort_by(|a, b| a.id().cmp(b.id()));
+        mismatched.truncate(self.anti_entropy.max_follow_ups);
+        mismatched
+    }
+
+    /// Deltas with what the requester of a reconciliation may lack of the
+    /// documents it listed
+    ///
+    /// Documents we don't have, or the requester already has in full,
+    /// give no delta.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a delta can't be computed (never for well-formed documents)
+    pub fn answer_reconciliation<'a>(
+        &self,
+        request: &VersionSummary,
+        documents: impl IntoIterator<Item = &'a Document>,
+    ) -> Result<Vec<DocumentDelta>> {
+        let versions = version_summary_from_protocol(request);
+        let mut deltas = Vec::new();
+        for document in documents {
+            let Some(version) = versions.get(document.id()) else {
+                continue;
+            };
+            let baseline = peer_baseline(document, Some(version));
+            let delta = self
+                .outgoing_delta(&baseline, document)
+                .map_err(|error| error.with_document(document.id().as_str()))?;
+            if !delta.is_empty() {
+                deltas.push(delta);
+            }
+        }
+        deltas.sort_by(|a, b| a.document_id.cmp(&b.document_id));
+        Ok(deltas)
+    }
+
+    /// Compute the delta to send for the changes from `from` to `to`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the documents have different IDs
+    pub fn outgoing_delta(&self, from: &Document, to: &Document) -> Result<DocumentDelta> {
+        let mut delta = match self.filter(to.id()) {
+            Some(filter) => DocumentDelta::compute_filtered(from, to, filter)?,
+            None => DocumentDelta::compute(from, to)?,
+        };
+        downgrade::downgrade_delta(&mut delta, to, self.peer_kinds);
+        Ok(delta)
+    }
+
+    /// Encode the delta to send as a protobuf `Delta`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the documents have different IDs
+    pub fn encode_outgoing_delta(&self, from: &Document, to: &Document) -> Result<Vec<u8>> {
+        let delta = self.outgoing_delta(from, to)?;
+        Ok(encode_message(&delta.to_protocol_at(self.now_ms()))?.to_vec())
+    }
+
+    /// Copy of `document` to send as a full snapshot
+    pub fn outgoing_snapshot(&self, document: &Document) -> Document {
+        let downgraded = downgrade::downgrade_document(document, self.peer_kinds);
+        match self.filter(document.id()) {
+            Some(filter) => filter.filter_document(&downgraded),
+            None => downgraded,
+        }
+    }
+
+    /// Apply a received delta, refusing changes to filtered and shadowed
+    /// paths
+    ///
+    /// Returns the applied changes, tagged as `DocumentDelta::apply_to`
+    /// does. The document's version is merged with the delta's in full, so
+    /// refused changes don't get requested again.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the delta is for another document
+    pub fn apply_incoming(
+        &self,
+        delta: &DocumentDelta,
+        document: &mut Document,
+        client_id: &str,
+    ) -> Result<Vec<FieldChange>> {
+        let withheld = downgrade::withhold_shadowed(delta, document, self.peer_kinds);
+        let delta = withheld.as_ref().map_or(delta, |(kept, _)| kept);
+        let changes = match self.filter(document.id()) {
+            Some(filter) => delta.apply_to_filtered(document, client_id, filter)?,
+            None => delta.apply_to(document, client_id)?,
+        };
+        let before = document.version.clone();
+        document.version.merge(&delta.new_version);
+        if document.version != before {
+            document.mark_version_dirty();
+        }
+        Ok(changes)
+    }
+
+    /// What `apply_incoming` would do, without changing `document`
+    ///
+    /// Changes to filtered and shadowed paths show up in the report's
+    /// `refused`.
+    pub fn dry_run_incoming(
+        &self,
+        delta: &DocumentDelta,
+        document: &Document,
+        client_id: &str,
+    ) -> Result<DryRunReport> {
+        let (delta, shadowed) = match downgrade::withhold_shadowed(delta, document, self.peer_kinds)
+        {
+            Some((kept, refused)) => (std::borrow::Cow::Owned(kept), refused),
+            None => (std::borrow::Cow::Borrowed(delta), Vec::new()),
+        };
+        let mut report = match self.filter(document.id()) {
+            Some(filter) => delta.dry_run_filtered(document, client_id, filter)?,
+            None => delta.dry_run(document, client_id)?,
+        };
+        report.refused.extend(shadowed);
+        Ok(report)
+    }
+
+    /// Decode a protobuf `Delta` and apply it with `apply_incoming`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the bytes are malformed or the delta is for another
+    /// document
+    pub fn decode_incoming(
+        &self,
+        bytes: &[u8],
+        document: &mut Document,
+        client_id: &str,
+    ) -> Result<Vec<FieldChange>> {
+        let proto = decode_message(bytes)?;
+        let delta = DocumentDelta::from_protocol(&proto, client_id)?;
+        self.apply_incoming(&delta, document, client_id)
+    }
+
+    /// Merge a received snapshot, refusing filtered and shadowed paths
+    ///
+    /// Returns the number of fields updated.
+    pub fn merge_incoming_snapshot(&self, document: &mut Document, remote: &Document) -> usize {
+        let withheld = downgrade::withhold_shadowed_snapshot(document, remote, self.peer_kinds);
+        let remote = withheld.as_ref().unwrap_or(remote);
+        match self.filter(document.id()) {
+            Some(filter) => document.merge(&filter.filter_document(remote)),
+            None => document.merge(remote),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write(doc: &mut Document, path: &str, value: serde_json::Value, clock: u64) {
+        doc.set_field(path.to_string(), value, clock, "alice".to_string());
+        doc.version.update("alice", clock);
+    }
+
+    #[test]
+    fn test_local_subtree_never_leaves_the_device() {
+        let mut coordinator = SyncCoordinator::new();
+        coordinator.set_filter("doc", SyncFilter::new().exclude("_local.*"));
+
+        let mut alice = Document::new("doc".to_string());
+        let mut bob = Document::new("doc".to_string());
+        let mut outgoing: Vec<Vec<u8>> = Vec::new();
+
+        let mut clock = 0;
+        for round in 0..10 {
+            let before = alice.clone();
+            for keystroke in 0..20 {
+                clock += 1;
+                let draft = format!("draft {} {}", round, keystroke);
+                write(&mut alice, "_local.draft.body", json!(draft), clock);
+                write(&mut alice, "_local.ui.scroll", json!(keystroke), clock);
+            }
+            clock += 1;
+            write(&mut alice, "title", json!(format!("v{}", round)), clock);
+
+            let bytes = coordinator.encode_outgoing_delta(&before, &alice).unwrap();
+            coordinator
+                .decode_incoming(&bytes, &mut bob, "bob")
+                .unwrap();
+            outgoing.push(bytes);
+        }
+        let snapshot = coordinator.outgoing_snapshot(&alice);
+        outgoing.push(serde_json::to_vec(&snapshot).unwrap());
+
+        for message in &outgoing {
+            let text = String::from_utf8_lossy(message);
+            assert!(!text.contains("_local"), "leaked: {}", text);
+            assert!(!text.contains("draft"), "leaked: {}", text);
+        }
+
+        assert_eq!(bob.get_field(&"title".to_string()), Some(&json!("v9")));
+        assert_eq!(bob.field_count(), 1);
+        // Held-back writes don't leave bob behind
+        assert_eq!(bob.version(), alice.version());
+    }
+
+    #[test]
+    fn test_version_filter_round_trips_without_false_negatives() {
+        let mut version = VectorClock::new();
+        let mut filter = VersionFilter::new(1000, 0.01, 7);
+        for i in 0..1000 {
+            version.update("alice", i);
+            filter.insert(&format!("doc-{}", i), &version);
+        }
+        let decoded = VersionFilter::from_protocol(&filter.to_protocol()).unwrap();
+        assert_eq!(decoded, filter);
+
+        let mut version = VectorClock::new();
+        let mut false_positives = 0;
+        for i in 0..1000 {
+            version.update("alice", i);
+            assert!(decoded.contains(&format!("doc-{}", i), &version));
+            false_positives += usize::from(decoded.contains(&format!("other-{}", i), &version));
+        }
+        assert!(false_positives < 30, "{}", false_positives);
+
+        let mut malformed = filter.to_protocol();
+        malformed.num_bits *= 2;
+        let error = VersionFilter::from_protocol(&malformed).unwrap_err();
+        assert_eq!(error.code_name(), "PROTOCOL_ERROR");
+    }
+
+    #[test]
+    fn test_refuses_filtered_paths_on_ingestion() {
+        let sender = SyncCoordinator::new();
+        let mut receiver = SyncCoordinator::new();
+        receiver.set_filter("doc", SyncFilter::new().exclude("presence"));
+
+        let before = Document::new("doc".to_string());
+        let mut alice = before.clone();
+        write(&mut alice, "title", json!("Hi"), 1);
+        write(&mut alice, "presence.cursor", json!(3), 2);
+
+        let mut bob = Document::new("doc".to_string());
+        let delta = sender.outgoing_delta(&before, &alice).unwrap();
+        assert_eq!(delta.changes.len(), 2);
+        let applied = receiver.apply_incoming(&delta, &mut bob, "bob").unwrap();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(bob.field_count(), 1);
+        assert_eq!(bob.version(), alice.version());
+
+        let mut carol = Document::new("doc".to_string());
+        assert_eq!(receiver.merge_incoming_snapshot(&mut carol, &alice), 1);
+        assert_eq!(carol.get_field(&"presence.cursor".to_string()), None);
+    }
+
+    #[test]
+    fn test_sync_status_through_a_session() {
+        use crate::time::MockTime;
+
+        let time = MockTime::new(1_000);
+        let mut alice_sync = SyncCoordinator::new();
+        alice_sync.set_time_provider(time.clone());
+        let mut bob_sync = SyncCoordinator::new();
+        bob_sync.set_time_provider(time.clone());
+        let mut alice = Document::new("doc".to_string());
+        let mut bob = Document::new("doc".to_string());
+        let exchange = |alice_sync: &mut SyncCoordinator,
+                        bob_sync: &mut SyncCoordinator,
+                        alice: &Document,
+                        bob: &Document| {
+            let checksum = alice_sync.checksum(alice);
+            bob_sync.receive_checksum(bob, &checksum).unwrap();
+            let reply = bob_sync.checksum(bob);
+            alice_sync.receive_checksum(alice, &reply).unwrap()
+        };
+        assert_eq!(alice_sync.sync_status("doc"), SyncStatus::default());
+
+        // Edit: three changes nobody else has
+        let start = alice.clone();
+        write(&mut alice, "title", json!("Hi"), 1);
+        write(&mut alice, "body", json!("..."), 2);
+        let partial = alice.clone();
+        write(&mut alice, "tags", json!(["a"]), 3);
+        alice_sync.track_local(&alice);
+        let status = alice_sync.sync_status("doc");
+        assert!(!status.in_sync);
+        assert_eq!(status.pending_local, 3);
+
+        // Partial sync: bob gets two of them
+        let delta = alice_sync.outgoing_delta(&start, &partial).unwrap();
+        bob_sync.apply_incoming(&delta, &mut bob, "bob").unwrap();
+        let status = exchange(&mut alice_sync, &mut bob_sync, &alice, &bob);
+        assert!(!status.in_sync);
+        assert_eq!(status.pending_local, 1);
+        let status = bob_sync.sync_status("doc");
+        assert!(!status.in_sync);
+        assert_eq!(status.pending_remote_estimate, 1);
+        assert_eq!(status.last_verified_at, None);
+
+        // Full sync: both sides verify
+        time.advance(500);
+        let delta = alice_sync.outgoing_delta(&partial, &alice).unwrap();
+        bob_sync.apply_incoming(&delta, &mut bob, "bob").unwrap();
+        let status = exchange(&mut alice_sync, &mut bob_sync, &alice, &bob);
+        assert!(status.in_sync);
+        assert_eq!(status.pending_local, 0);
+        assert_eq!(status.last_verified_at, Some(1_500));
+        assert!(bob_sync.sync_status("doc").in_sync);
+
+        // A local edit leaves the verified version behind
+        let before = alice.clone();
+        write(&mut alice, "title", json!("Hello"), 4);
+        alice_sync.track_local(&alice);
+        assert!(!alice_sync.sync_status("doc").in_sync);
+        let delta = alice_sync.outgoing_delta(&before, &alice).unwrap();
+        bob_sync.apply_incoming(&delta, &mut bob, "bob").unwrap();
+        assert!(exchange(&mut alice_sync, &mut bob_sync, &alice, &bob).in_sync);
+
+        // Injected divergence: bob's state changes without its version
+        time.advance(500);
+        bob.set_field("title".to_string(), json!("Oops"), 9, "bob".to_string());
+        let status = exchange(&mut alice_sync, &mut bob_sync, &alice, &bob);
+        assert!(status.diverged);
+        assert!(!status.in_sync);
+        assert_eq!(status.last_verified_at, Some(1_500));
+        assert!(bob_sync.sync_status("doc").diverged);
+
+        // Recovery: neither side lacks a version, so exchange snapshots
+        let snapshot = alice_sync.outgoing_snapshot(&alice);
+        bob_sync.merge_incoming_snapshot(&mut bob, &snapshot);
+        let snapshot = bob_sync.outgoing_snapshot(&bob);
+        alice_sync.merge_incoming_snapshot(&mut alice, &snapshot);
+        let status = exchange(&mut alice_sync, &mut bob_sync, &alice, &bob);
+        assert!(status.in_sync);
+        assert!(!status.diverged);
+        assert_eq!(status.last_verified_at, Some(2_000));
+        assert!(bob_sync.sync_status("doc").in_sync);
+
+        let other = Document::new("other".to_string());
+        let checksum = alice_sync.checksum(&alice);
+        let error = bob_sync.receive_checksum(&other, &checksum).unwrap_err();
+        assert_eq!(error.code_name(), "INVALID_OPERATION");
+    }
 }
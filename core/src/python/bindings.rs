@@ -0,0 +1,437 @@
+//! PyO3 classes wrapping SyncKit's document and CRDT types
+//!
+//! Every class saves to and loads from `bytes` (the same serde JSON
+//! encoding the other bindings use), and every `merge` releases the GIL so
+//! large merges don't block other Python threads.
+
+use super::convert::{json_to_py, py_to_json};
+use crate::crdt::{FugueText, ORSet, PNCounter, TextError};
+use crate::protocol::delta::DocumentDelta;
+use crate::Document;
+use pyo3::exceptions::{PyIndexError, PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Client ID used for local writes when none is given
+const DEFAULT_CLIENT_ID: &str = "python";
+
+fn save<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<Py<PyBytes>> {
+    serde_json::to_vec(value)
+        .map(|bytes| PyBytes::new(py, &bytes).unbind())
+        .map_err(|e| PyValueError::new_err(format!("Serialization failed: {}", e)))
+}
+
+fn load<T: DeserializeOwned>(data: &[u8]) -> PyResult<T> {
+    serde_json::from_slice(data)
+        .map_err(|e| PyValueError::new_err(format!("Deserialization failed: {}", e)))
+}
+
+fn text_error(e: TextError) -> PyErr {
+    match e {
+        TextError::PositionOutOfBounds { .. } | TextError::RangeOutOfBounds { .. } => {
+            PyIndexError::new_err(e.to_string())
+        }
+        _ => PyValueError::new_err(e.to_string()),
+    }
+}
+
+/// Python `synckit.Document`: a dict-like document with field-level LWW
+///
+/// ```python
+/// doc = synckit.Document("doc-1", client_id="etl")
+/// doc["user.name"] = "Ada"
+/// dict(doc)  # {"user.name": "Ada"}
+/// ```
+#[pyclass(name = "Document", module = "synckit")]
+pub struct PyDocument {
+    inner: Document,
+    client_id: String,
+
+    /// Highest clock seen, so local writes win over everything merged so far
+    clock: u64,
+}
+
+impl PyDocument {
+    fn from_document(inner: Document, client_id: String) -> Self {
+        let mut doc = Self {
+            inner,
+            client_id,
+            clock: 0,
+        };
+        doc.observe_clocks();
+        doc
+    }
+
+    fn observe_clocks(&mut self) {
+        let max_field_clock = self
+            .inner
+            .fields()
+            .values()
+            .map(|field| field.timestamp.clock)
+            .max()
+            .unwrap_or(0);
+        self.clock = self.clock.max(max_field_clock);
+    }
+}
+
+#[pymethods]
+impl PyDocument {
+    #[new]
+    #[pyo3(signature = (id, client_id = DEFAULT_CLIENT_ID.to_string()))]
+    fn new(id: String, client_id: String) -> Self {
+        Self::from_document(Document::new(id), client_id)
+    }
+
+    /// Document ID
+    #[getter]
+    fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    /// Client ID stamped on local writes
+    #[getter]
+    fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    fn __getitem__(&self, py: Python<'_>, path: &str) -> PyResult<PyObject> {
+        match self.inner.get_field(&path.to_string()) {
+            Some(value) => json_to_py(py, value),
+            None => Err(PyKeyError::new_err(path.to_string())),
+        }
+    }
+
+    fn __setitem__(&mut self, path: String, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let value = py_to_json(value)?;
+        self.clock += 1;
+        self.inner
+            .set_field(path, value, self.clock, self.client_id.clone());
+        Ok(())
+    }
+
+    fn __delitem__(&mut self, path: &str) -> PyResult<()> {
+        let path = path.to_string();
+        if self.inner.get_field(&path).is_none() {
+            return Err(PyKeyError::new_err(path));
+        }
+        self.inner.delete_field(&path);
+        Ok(())
+    }
+
+    fn __contains__(&self, path: &str) -> bool {
+        self.inner.get_field(&path.to_string()).is_some()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.field_count()
+    }
+
+    fn __iter__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(PyList::new(py, self.keys())?
+            .call_method0("__iter__")?
+            .unbind())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Document(id={:?}, fields={})",
+            self.inner.id(),
+            self.inner.field_count()
+        )
+    }
+
+    /// Field paths, sorted
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.inner.field_paths().into_iter().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// All fields as a dict
+    fn to_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        json_to_py(py, &self.inner.to_json())
+    }
+
+    /// Merge another document into this one (GIL released)
+    ///
+    /// Returns the number of fields that changed.
+    fn merge(&mut self, py: Python<'_>, other: PyRef<'_, PyDocument>) -> usize {
+        let remote = &other.inner;
+        let inner = &mut self.inner;
+        let changed = py.allow_threads(|| inner.merge(remote));
+        self.observe_clocks();
+        changed
+    }
+
+    /// Serialize the document, including LWW metadata
+    fn save(&self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+        save(py, &self.inner)
+    }
+
+    /// Load a document produced by `save` (or the Rust/WASM serializers)
+    #[staticmethod]
+    #[pyo3(signature = (data, client_id = DEFAULT_CLIENT_ID.to_string()))]
+    fn load(data: &[u8], client_id: String) -> PyResult<Self> {
+        Ok(Self::from_document(load(data)?, client_id))
+    }
+
+    /// Compute the changes from `base` to this document
+    fn delta(&self, py: Python<'_>, base: PyRef<'_, PyDocument>) -> PyResult<Py<PyBytes>> {
+        let delta = DocumentDelta::compute(&base.inner, &self.inner)
+            .map_err(|e| PyValueError::new_err(format!("Delta computation failed: {}", e)))?;
+        save(py, &delta)
+    }
+
+    /// Apply a delta produced by `delta`
+    fn apply_delta(&mut self, data: &[u8]) -> PyResult<()> {
+        let delta: DocumentDelta = load(data)?;
+        delta
+            .apply_to(&mut self.inner, &self.client_id)
+            .map_err(|e| PyValueError::new_err(format!("Delta application failed: {}", e)))?;
+        self.observe_clocks();
+        Ok(())
+    }
+}
+
+/// Python `synckit.FugueText`: collaborative plain text
+#[pyclass(name = "FugueText", module = "synckit")]
+pub struct PyFugueText {
+    inner: FugueText,
+}
+
+#[pymethods]
+impl PyFugueText {
+    #[new]
+    fn new(client_id: String) -> Self {
+        Self {
+            inner: FugueText::new(client_id),
+        }
+    }
+
+    /// Insert text at a character position
+    fn insert(&mut self, position: usize, text: &str) -> PyResult<()> {
+        self.inner.insert(position, text).map_err(text_error)?;
+        Ok(())
+    }
+
+    /// Delete `length` characters starting at `position`
+    fn delete(&mut self, position: usize, length: usize) -> PyResult<()> {
+        self.inner.delete(position, length).map_err(text_error)?;
+        Ok(())
+    }
+
+    fn __str__(&self) -> String {
+        self.inner.to_string()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("FugueText({:?})", self.inner.to_string())
+    }
+
+    /// Merge another replica into this one (GIL released)
+    fn merge(&mut self, py: Python<'_>, other: PyRef<'_, PyFugueText>) -> PyResult<()> {
+        let remote = &other.inner;
+        let inner = &mut self.inner;
+        py.allow_threads(|| inner.merge(remote)).map_err(text_error)
+    }
+
+    /// Serialize the replica, including CRDT metadata
+    fn save(&self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+        save(py, &self.inner)
+    }
+
+    /// Load a replica produced by `save`
+    #[staticmethod]
+    fn load(data: &[u8]) -> PyResult<Self> {
+        Ok(Self { inner: load(data)? })
+    }
+
+    /// Encode this replica's state vector for delta sync
+    fn state_vector(&self, py: Python<'_>) -> Py<PyBytes> {
+        PyBytes::new(py, &self.inner.encode_state_vector()).unbind()
+    }
+
+    /// Encode what a peer with `state_vector` is missing
+    fn diff(&self, py: Python<'_>, state_vector: &[u8]) -> PyResult<Py<PyBytes>> {
+        let diff = self
+            .inner
+            .encode_diff(state_vector)
+            .map_err(|e| PyValueError::new_err(format!("Delta encoding failed: {}", e)))?;
+        Ok(PyBytes::new(py, &diff).unbind())
+    }
+
+    /// Apply a diff from `diff`, returning the resulting text events as dicts
+    fn apply_diff(&mut self, py: Python<'_>, data: &[u8]) -> PyResult<PyObject> {
+        let events = self
+            .inner
+            .apply_diff(data)
+            .map_err(|e| PyValueError::new_err(format!("Delta application failed: {}", e)))?;
+        let events = serde_json::to_value(events)
+            .map_err(|e| PyValueError::new_err(format!("Serialization failed: {}", e)))?;
+        json_to_py(py, &events)
+    }
+}
+
+/// Python `synckit.Counter`: PN-Counter supporting increment and decrement
+#[pyclass(name = "Counter", module = "synckit")]
+pub struct PyCounter {
+    inner: PNCounter,
+}
+
+#[pymethods]
+impl PyCounter {
+    #[new]
+    fn new(replica_id: String) -> Self {
+        Self {
+            inner: PNCounter::new(replica_id),
+        }
+    }
+
+    #[pyo3(signature = (amount = 1))]
+    fn increment(&mut self, amount: i64) {
+        self.inner.increment(amount);
+    }
+
+    #[pyo3(signature = (amount = 1))]
+    fn decrement(&mut self, amount: i64) {
+        self.inner.decrement(amount);
+    }
+
+    /// Current value
+    #[getter]
+    fn value(&self) -> i64 {
+        self.inner.value()
+    }
+
+    fn __int__(&self) -> i64 {
+        self.inner.value()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Counter({})", self.inner.value())
+    }
+
+    /// Merge another replica into this one (GIL released)
+    fn merge(&mut self, py: Python<'_>, other: PyRef<'_, PyCounter>) {
+        let remote = &other.inner;
+        let inner = &mut self.inner;
+        py.allow_threads(|| inner.merge(remote));
+    }
+
+    /// Take the local changes since the last call, for delta-state sync
+    fn split_delta(&mut self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+        let delta = self.inner.split_delta();
+        save(py, &delta)
+    }
+
+    /// Apply a delta produced by `split_delta`
+    fn apply_delta(&mut self, data: &[u8]) -> PyResult<()> {
+        let delta: PNCounter = load(data)?;
+        self.inner.apply_delta(&delta);
+        Ok(())
+    }
+
+    fn save(&self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+        save(py, &self.inner)
+    }
+
+    #[staticmethod]
+    fn load(data: &[u8]) -> PyResult<Self> {
+        Ok(Self { inner: load(data)? })
+    }
+}
+
+/// Python `synckit.Set`: add-wins observed-remove set of strings
+#[pyclass(name = "Set", module = "synckit")]
+pub struct PySet {
+    inner: ORSet<String>,
+}
+
+impl PySet {
+    fn sorted(&self) -> Vec<String> {
+        let mut elements: Vec<String> = self.inner.iter().cloned().collect();
+        elements.sort();
+        elements
+    }
+}
+
+#[pymethods]
+impl PySet {
+    #[new]
+    fn new(replica_id: String) -> Self {
+        Self {
+            inner: ORSet::new(replica_id),
+        }
+    }
+
+    fn add(&mut self, element: String) {
+        self.inner.add(element);
+    }
+
+    /// Remove an element, raising `KeyError` if absent
+    fn remove(&mut self, element: String) -> PyResult<()> {
+        if !self.inner.contains(&element) {
+            return Err(PyKeyError::new_err(element));
+        }
+        self.inner.remove(&element);
+        Ok(())
+    }
+
+    /// Remove an element if present
+    fn discard(&mut self, element: String) {
+        self.inner.remove(&element);
+    }
+
+    fn __contains__(&self, element: &str) -> bool {
+        self.inner.contains(&element.to_string())
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __iter__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(PyList::new(py, self.sorted())?
+            .call_method0("__iter__")?
+            .unbind())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Set({:?})", self.sorted())
+    }
+
+    /// Merge another replica into this one (GIL released)
+    fn merge(&mut self, py: Python<'_>, other: PyRef<'_, PySet>) {
+        let remote = &other.inner;
+        let inner = &mut self.inner;
+        py.allow_threads(|| inner.merge(remote));
+    }
+
+    /// Take the local changes since the last call, for delta-state sync
+    fn split_delta(&mut self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+        let delta = self.inner.split_delta();
+        save(py, &delta)
+    }
+
+    /// Apply a delta produced by `split_delta`
+    fn apply_delta(&mut self, data: &[u8]) -> PyResult<()> {
+        let delta: ORSet<String> = load(data)?;
+        self.inner.apply_delta(&delta);
+        Ok(())
+    }
+
+    fn save(&self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+        save(py, &self.inner)
+    }
+
+    #[staticmethod]
+    fn load(data: &[u8]) -> PyResult<Self> {
+        Ok(Self { inner: load(data)? })
+    }
+}
@@ -0,0 +1,95 @@
+//! Conversions between JSON values and native Python objects
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use serde_json::{Map, Number, Value as JsonValue};
+
+/// Convert a JSON value into the equivalent Python object
+pub fn json_to_py(py: Python<'_>, value: &JsonValue) -> PyResult<PyObject> {
+    Ok(match value {
+        JsonValue::Null => py.None(),
+        JsonValue::Bool(b) => PyBool::new(py, *b).to_owned().into_any().unbind(),
+        JsonValue::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                i.into_pyobject(py)?.into_any().unbind()
+            } else if let Some(u) = n.as_u64() {
+                u.into_pyobject(py)?.into_any().unbind()
+            } else {
+                n.as_f64()
+                    .unwrap_or(f64::NAN)
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind()
+            }
+        }
+        JsonValue::String(s) => PyString::new(py, s).into_any().unbind(),
+        JsonValue::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(json_to_py(py, item)?)?;
+            }
+            list.into_any().unbind()
+        }
+        JsonValue::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, item) in map {
+                dict.set_item(key, json_to_py(py, item)?)?;
+            }
+            dict.into_any().unbind()
+        }
+    })
+}
+
+/// Convert a Python object into a JSON value
+///
+/// Accepts `None`, `bool`, `int`, `float`, `str`, `list`/`tuple` and `dict`
+/// with string keys, nested arbitrarily.
+pub fn py_to_json(obj: &Bound<'_, PyAny>) -> PyResult<JsonValue> {
+    if obj.is_none() {
+        return Ok(JsonValue::Null);
+    }
+    // bool is a subclass of int, so check it first
+    if obj.is_instance_of::<PyBool>() {
+        return Ok(JsonValue::Bool(obj.extract()?));
+    }
+    if obj.is_instance_of::<PyInt>() {
+        if let Ok(i) = obj.extract::<i64>() {
+            return Ok(JsonValue::from(i));
+        }
+        return obj
+            .extract::<u64>()
+            .map(JsonValue::from)
+            .map_err(|_| PyValueError::new_err("int does not fit in 64 bits"));
+    }
+    if obj.is_instance_of::<PyFloat>() {
+        let f: f64 = obj.extract()?;
+        return Number::from_f64(f)
+            .map(JsonValue::Number)
+            .ok_or_else(|| PyValueError::new_err("NaN and infinity are not valid JSON"));
+    }
+    if let Ok(s) = obj.downcast::<PyString>() {
+        return Ok(JsonValue::String(s.to_str()?.to_string()));
+    }
+    if let Ok(list) = obj.downcast::<PyList>() {
+        return list.iter().map(|item| py_to_json(&item)).collect();
+    }
+    if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        return tuple.iter().map(|item| py_to_json(&item)).collect();
+    }
+    if let Ok(dict) = obj.downcast::<PyDict>() {
+        let mut map = Map::new();
+        for (key, item) in dict.iter() {
+            let key = key
+                .downcast::<PyString>()
+                .map_err(|_| PyTypeError::new_err("dict keys must be str"))?;
+            map.insert(key.to_str()?.to_string(), py_to_json(&item)?);
+        }
+        return Ok(JsonValue::Object(map));
+    }
+
+    Err(PyTypeError::new_err(format!(
+        "cannot store {} in a SyncKit document",
+        obj.get_type().name()?
+    )))
+}
@@ -0,0 +1,171 @@
+//! Python bindings for SyncKit (PyO3)
+//!
+//! Enabled with the `python` feature and built as the `synckit` extension
+//! module with maturin (see `python/pyproject.toml`):
+//!
+//! ```text
+//! cd python
+//! maturin develop
+//! pytest
+//! ```
+//!
+//! Classes follow Python conventions rather than the JS ones: `Document` is
+//! dict-like (`doc["user.name"] = "x"`, `dict(doc)`), `FugueText` supports
+//! `str()`/`len()`, and `Set` iterates like a set.
+
+pub mod bindings;
+mod convert;
+
+pub use bindings::{PyCounter, PyDocument, PyFugueText, PySet};
+
+use pyo3::prelude::*;
+
+/// The `synckit` Python module
+#[pymodule]
+#[pyo3(name = "synckit")]
+pub fn synckit_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyDocument>()?;
+    m.add_class::<PyFugueText>()?;
+    m.add_class::<PyCounter>()?;
+    m.add_class::<PySet>()?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+    use std::ffi::CString;
+
+    /// Run a Python snippet with the `synckit` module imported
+    fn run(code: &str) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = pyo3::wrap_pymodule!(synckit_module)(py);
+            let globals = PyDict::new(py);
+            globals.set_item("synckit", module).unwrap();
+
+            let code = CString::new(code).unwrap();
+            if let Err(e) = py.run(&code, Some(&globals), None) {
+                e.print(py);
+                panic!("Python snippet failed");
+            }
+        });
+    }
+
+    #[test]
+    fn test_document_is_dict_like() {
+        run(r#"
+doc = synckit.Document("doc-1", client_id="etl")
+doc["user.name"] = "Ada"
+doc["tags"] = ["a", {"nested": True}]
+doc["score"] = 1.5
+assert doc["user.name"] == "Ada"
+assert dict(doc) == {"user.name": "Ada", "tags": ["a", {"nested": True}], "score": 1.5}
+assert "tags" in doc and len(doc) == 3
+del doc["score"]
+try:
+    doc["score"]
+    raise AssertionError("expected KeyError")
+except KeyError:
+    pass
+"#);
+    }
+
+    #[test]
+    fn test_document_merge_and_delta_converge() {
+        run(r#"
+a = synckit.Document("doc-1", client_id="a")
+b = synckit.Document("doc-1", client_id="b")
+a["title"] = "from a"
+b["title"] = "from b"
+b["body"] = "text"
+
+a.merge(b)
+b.merge(a)
+assert dict(a) == dict(b)
+
+# A write after merging wins over everything merged so far
+a["title"] = "latest"
+b.apply_delta(a.delta(b))
+assert b["title"] == "latest"
+assert dict(a) == dict(b)
+"#);
+    }
+
+    #[test]
+    fn test_text_counter_and_set() {
+        run(r#"
+t1 = synckit.FugueText("c1")
+t2 = synckit.FugueText("c2")
+t1.insert(0, "Hello")
+t2.merge(t1)
+t2.insert(5, " World")
+events = t1.apply_diff(t2.diff(t1.state_vector()))
+assert str(t1) == "Hello World" and len(t1) == 11
+assert events == [{"type": "insert", "position": 5, "text": " World"}]
+try:
+    t1.insert(100, "x")
+    raise AssertionError("expected IndexError")
+except IndexError:
+    pass
+
+c1 = synckit.Counter("c1")
+c2 = synckit.Counter("c2")
+c1.increment(5)
+c2.decrement()
+c1.apply_delta(c2.split_delta())
+assert c1.value == 4 and int(synckit.Counter.load(c1.save())) == 4
+
+s1 = synckit.Set("c1")
+s2 = synckit.Set("c2")
+s1.add("x")
+s2.add("y")
+s1.merge(s2)
+s1.remove("x")
+s1.discard("missing")
+assert list(s1) == ["y"] and "y" in s1
+"#);
+    }
+
+    #[test]
+    fn test_loads_document_saved_by_rust() {
+        let path = format!(
+            "{}/tests/fixtures/python/document.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let fixture = std::fs::read_to_string(&path).unwrap();
+        let loaded: crate::Document = serde_json::from_str(&fixture).unwrap();
+        assert_eq!(loaded.to_json(), fixture_document().to_json());
+
+        run(&format!(
+            r#"
+doc = synckit.Document.load(open({:?}, "rb").read())
+assert doc["title"] == "Quarterly report"
+assert doc["stats"] == {{"views": 42, "ratio": 0.5, "tags": ["q3", None, False]}}
+assert dict(synckit.Document.load(doc.save())) == dict(doc)
+"#,
+            path
+        ));
+    }
+
+    /// Document stored in `tests/fixtures/python/document.json`, which the
+    /// pytest suite round-trips
+    fn fixture_document() -> crate::Document {
+        let mut doc = crate::Document::new("report-1".to_string());
+        doc.set_field(
+            "title".to_string(),
+            serde_json::json!("Quarterly report"),
+            1,
+            "rust".to_string(),
+        );
+        doc.set_field(
+            "stats".to_string(),
+            serde_json::json!({"views": 42, "ratio": 0.5, "tags": ["q3", null, false]}),
+            2,
+            "rust".to_string(),
+        );
+        doc
+    }
+}
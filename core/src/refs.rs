@@ -0,0 +1,241 @@
+//! Cross-document references
+//!
+//! A ref field links a document to another one of the same workspace,
+//! such as a task's assignee pointing at a user document. Refs are their
+//! own kind of field rather than JSON values, so deltas carry them as refs
+//! and a receiver can keep its [`Backlinks`] current without looking at
+//! values:
+//!
+//! - [`Document::set_ref`] and [`Document::clear_ref`] write one, last
+//!   writer wins as for plain fields
+//! - [`Document::get_ref`] reads the target ID, and `to_json` renders
+//!   `{"$ref": "user-42"}`
+//! - with the `server` feature, `Workspace::resolve_ref` returns the
+//!   target's handle and `Workspace::backlinks` the refs pointing at a
+//!   document
+//!
+//! # Deleted targets
+//!
+//! Deleting a document leaves the refs pointing at it in place, since they
+//! are other documents' data, but they resolve to nothing and drop out of
+//! the backlinks. Deletion wins: a ref made concurrently with the deletion
+//! of its target ends up dangling on every replica, whichever arrives
+//! first.
+//!
+//! # Example
+//!
+//! ```rust
+//! use synckit_core::refs::Backlinks;
+//! use synckit_core::Document;
+//!
+//! let mut task = Document::new("task-1".to_string());
+//! task.set_ref("assignee".to_string(), "user-42".to_string(), 1, "alice".to_string());
+//! assert_eq!(task.get_ref(&"assignee".to_string()), Some(&"user-42".to_string()));
+//! assert_eq!(task.to_json()["assignee"], serde_json::json!({"$ref": "user-42"}));
+//!
+//! let mut backlinks = Backlinks::new();
+//! backlinks.update_document(&task);
+//! assert_eq!(
+//!     backlinks.referrers("user-42"),
+//!     vec![("task-1".to_string(), "assignee".to_string())]
+//! );
+//! ```
+//!
+//! [`Document::set_ref`]: crate::Document::set_ref
+//! [`Document::clear_ref`]: crate::Document::clear_ref
+//! [`Document::get_ref`]: crate::Document::get_ref
+
+use crate::memory::HeapSize;
+use crate::sync::Timestamp;
+use crate::{Document, DocumentID, FieldPath};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Key of the object `to_json` renders a ref as
+pub const REF_MARKER: &str = "$ref";
+
+/// A link to another document, with LWW metadata
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ref {
+    /// ID of the document linked to; None once cleared
+    pub target: Option<DocumentID>,
+
+    /// Timestamp for LWW conflict resolution
+    pub timestamp: Timestamp,
+}
+
+impl Ref {
+    /// Whether this ref replaces `local` under LWW
+    ///
+    /// Equal timestamps (the same write seen twice, or a broken client)
+    /// compare the targets, so every replica picks the same one.
+    pub(crate) fn wins_over(&self, local: &Ref) -> bool {
+        match self.timestamp.compare_lww(&local.timestamp) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => self.target > local.target,
+        }
+    }
+
+    /// The marker object `to_json` renders a ref as; null once cleared
+    pub fn to_json(&self) -> JsonValue {
+        match &self.target {
+            Some(target) => {
+                let mut marker = serde_json::Map::new();
+                marker.insert(REF_MARKER.to_string(), JsonValue::String(target.clone()));
+                JsonValue::Object(marker)
+            }
+            None => JsonValue::Null,
+        }
+    }
+}
+
+/// Reverse index of the refs between a set of documents: which refs
+/// point at a document
+///
+/// Update it with each document as it changes; only that document's refs
+/// are compared, so keeping it current costs nothing per unchanged
+/// document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backlinks {
+    /// Each document's refs, by path
+    outgoing: HashMap<DocumentID, BTreeMap<FieldPath, DocumentID>>,
+    /// Each target's referrers
+    incoming: HashMap<DocumentID, BTreeSet<(DocumentID, FieldPath)>>,
+}
+
+impl Backlinks {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index a document's current refs; returns whether any changed
+    pub fn update_document(&mut self, document: &Document) -> bool {
+        let refs: BTreeMap<FieldPath, DocumentID> = document
+            .refs()
+            .iter()
+            .filter_map(|(path, link)| Some((path.clone(), link.target.clone()?)))
+            .collect();
+        if self.outgoing.get(document.id()) == Some(&refs) {
+            return false;
+        }
+        self.remove_document(document.id());
+        for (path, target) in &refs {
+            self.incoming
+                .entry(target.clone())
+                .or_default()
+                .insert((document.id().clone(), path.clone()));
+        }
+        if !refs.is_empty() {
+            self.outgoing.insert(document.id().clone(), refs);
+        }
+        true
+    }
+
+    /// Drop a document's refs from the index; refs to it stay
+    pub fn remove_document(&mut self, id: &str) {
+        let Some(refs) = self.outgoing.remove(id) else {
+            return;
+        };
+        for (path, target) in refs {
+            if let Some(referrers) = self.incoming.get_mut(&target) {
+                referrers.remove(&(id.to_string(), path));
+                if referrers.is_empty() {
+                    self.incoming.remove(&target);
+                }
+            }
+        }
+    }
+
+    /// The documents and paths whose refs point at `target`, sorted
+    pub fn referrers(&self, target: &str) -> Vec<(DocumentID, FieldPath)> {
+        self.incoming
+            .get(target)
+            .map(|referrers| referrers.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Number of refs indexed
+    pub fn len(&self) -> usize {
+        self.outgoing.values().map(BTreeMap::len).sum()
+    }
+
+    /// Check if no refs are indexed
+    pub fn is_empty(&self) -> bool {
+        self.outgoing.is_empty()
+    }
+}
+
+impl HeapSize for Ref {
+    fn heap_size(&self) -> usize {
+        self.target.heap_size() + self.timestamp.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(document: &mut Document, path: &str, target: &str, clock: u64) {
+        document.set_ref(
+            path.to_string(),
+            target.to_string(),
+            clock,
+            "alice".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_backlinks_follow_retargeted_and_cleared_refs() {
+        let mut task = Document::new("task".to_string());
+        link(&mut task, "assignee", "ada", 1);
+        link(&mut task, "reviewer", "ada", 2);
+        let mut backlinks = Backlinks::new();
+        assert!(backlinks.update_document(&task));
+        assert!(!backlinks.update_document(&task));
+        assert_eq!(backlinks.referrers("ada").len(), 2);
+
+        link(&mut task, "assignee", "bob", 3);
+        task.clear_ref("reviewer".to_string(), 4, "alice".to_string());
+        assert!(backlinks.update_document(&task));
+        assert!(backlinks.referrers("ada").is_empty());
+        assert_eq!(
+            backlinks.referrers("bob"),
+            vec![("task".to_string(), "assignee".to_string())]
+        );
+        assert_eq!(backlinks.len(), 1);
+
+        backlinks.remove_document("task");
+        assert!(backlinks.is_empty());
+        assert!(backlinks.referrers("bob").is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_refs_converge() {
+        let mut a = Document::new("task".to_string());
+        let mut b = Document::new("task".to_string());
+        link(&mut a, "assignee", "ada", 5);
+        b.set_ref(
+            "assignee".to_string(),
+            "bob".to_string(),
+            5,
+            "bob".to_string(),
+        );
+        b.clear_ref("old".to_string(), 1, "bob".to_string());
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+        assert_eq!(ab.refs(), ba.refs());
+        assert_eq!(ab.content_hash(), ba.content_hash());
+        assert_eq!(
+            ab.get_ref(&"assignee".to_string()),
+            Some(&"bob".to_string())
+        );
+        assert_eq!(ab.get_ref(&"old".to_string()), None);
+        assert_eq!(ab.to_json()["old"], JsonValue::Null);
+    }
+}
@@ -0,0 +1,238 @@
+//! Multi-value registers: fields that keep concurrent writes
+//!
+//! LWW drops the losing side of two concurrent writes without telling
+//! anyone. For fields where that is unacceptable (a billing address, the
+//! title of a legal document) an [`MVRegister`] keeps every write no other
+//! write has seen, so the app can show all of them and ask the user.
+//!
+//! # Algorithm
+//!
+//! Each write is identified by its dot, the `(clock, client)` timestamp it
+//! was made with, and the register remembers every dot it has seen in a
+//! vector clock. A write replaces all values the writing replica holds,
+//! since it has seen them. Merging keeps a value if both sides hold it or
+//! the other side has never seen it, so concurrent writes survive and a
+//! write made after seeing them (such as [`Document::resolve`]) collapses
+//! the register back to one value on every replica.
+//!
+//! Merging is commutative, associative and idempotent.
+//!
+//! # JSON shape
+//!
+//! [`MVRegister::to_json`] (and `Document::to_json`) renders one value as
+//! itself and concurrent values as a marker object, values ordered by
+//! clock, then client:
+//!
+//! ```json
+//! {"$conflict": ["12 Main St", "4 High St"]}
+//! ```
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde_json::json;
+//! use synckit_core::Document;
+//!
+//! let mut alice = Document::new("doc-1".to_string());
+//! let mut bob = Document::new("doc-1".to_string());
+//! let path = "address".to_string();
+//! alice.set_multi_field(path.clone(), json!("12 Main St"), 1, "alice".to_string());
+//! bob.set_multi_field(path.clone(), json!("4 High St"), 1, "bob".to_string());
+//!
+//! alice.merge(&bob);
+//! assert_eq!(alice.get_all(&path).len(), 2);
+//!
+//! alice.resolve(&path, json!("4 High St"), 2, "alice".to_string()).unwrap();
+//! bob.merge(&alice);
+//! assert_eq!(bob.to_json()["address"], json!("4 High St"));
+//! ```
+//!
+//! [`Document::resolve`]: crate::Document::resolve
+
+use crate::document::Field;
+use crate::memory::HeapSize;
+use crate::sync::{Timestamp, VectorClock};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// Key of the marker object `to_json` renders concurrent values in
+pub const CONFLICT_MARKER: &str = "$conflict";
+
+/// Register keeping all concurrent writes (see the module docs)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MVRegister {
+    /// Values no other write has seen, ordered by timestamp
+    values: Vec<Field>,
+
+    /// Every write the register has seen
+    seen: VectorClock,
+}
+
+impl MVRegister {
+    /// Create an empty register
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write `value` as `timestamp`, replacing every value held
+    ///
+    /// Returns false, changing nothing, if the register has already seen
+    /// a write from that client at that clock or later.
+    pub fn write(&mut self, value: JsonValue, timestamp: Timestamp) -> bool {
+        if self.has_seen(&timestamp) {
+            return false;
+        }
+        self.seen.update(&timestamp.client_id, timestamp.clock);
+        self.values = vec![Field { value, timestamp }];
+        true
+    }
+
+    /// Merge another replica's register; returns whether anything changed
+    pub fn merge(&mut self, other: &MVRegister) -> bool {
+        let before = self.clone();
+
+        let mut values: Vec<Field> = self
+            .values
+            .iter()
+            .filter(|field| other.holds(&field.timestamp) || !other.has_seen(&field.timestamp))
+            .cloned()
+            .collect();
+        for field in &other.values {
+            if !self.has_seen(&field.timestamp) {
+                values.push(field.clone());
+            } else if let Some(local) = values
+                .iter_mut()
+                .find(|local| local.timestamp == field.timestamp)
+            {
+                // Same write, different value: can't happen between honest
+                // replicas, but keep merges deterministic anyway
+                if field.wins_over(local) {
+                    *local = field.clone();
+                }
+            }
+        }
+        values.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+
+        self.values = values;
+        self.seen.merge(&other.seen);
+        *self != before
+    }
+
+    /// The concurrent values, ordered by clock, then client
+    pub fn values(&self) -> &[Field] {
+        &self.values
+    }
+
+    /// The value, if there is exactly one
+    pub fn value(&self) -> Option<&JsonValue> {
+        match self.values.as_slice() {
+            [field] => Some(&field.value),
+            _ => None,
+        }
+    }
+
+    /// Check if concurrent writes are waiting to be resolved
+    pub fn is_conflicted(&self) -> bool {
+        self.values.len() > 1
+    }
+
+    /// Check if the register was never written
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Every write the register has seen
+    pub fn seen(&self) -> &VectorClock {
+        &self.seen
+    }
+
+    /// The value, or the conflict marker if there are several (see the
+    /// module docs); null if never written
+    pub fn to_json(&self) -> JsonValue {
+        match self.values.as_slice() {
+            [] => JsonValue::Null,
+            [field] => field.value.clone(),
+            fields => {
+                let values = fields.iter().map(|field| field.value.clone()).collect();
+                let mut marker = serde_json::Map::new();
+                marker.insert(CONFLICT_MARKER.to_string(), JsonValue::Array(values));
+                JsonValue::Object(marker)
+            }
+        }
+    }
+
+    fn has_seen(&self, timestamp: &Timestamp) -> bool {
+        self.seen.get(&timestamp.client_id) >= timestamp.clock
+    }
+
+    fn holds(&self, timestamp: &Timestamp) -> bool {
+        self.values
+            .iter()
+            .any(|field| &field.timestamp == timestamp)
+    }
+}
+
+impl HeapSize for MVRegister {
+    fn heap_size(&self) -> usize {
+        self.values.heap_size() + self.seen.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write(register: &mut MVRegister, value: &str, clock: u64, client: &str) {
+        assert!(register.write(json!(value), Timestamp::new(clock, client.to_string())));
+    }
+
+    fn values(register: &MVRegister) -> Vec<&JsonValue> {
+        register.values().iter().map(|field| &field.value).collect()
+    }
+
+    #[test]
+    fn test_concurrent_writes_are_kept() {
+        let mut a = MVRegister::new();
+        let mut b = MVRegister::new();
+        write(&mut a, "a", 1, "alice");
+        write(&mut b, "b", 1, "bob");
+
+        let mut ab = a.clone();
+        assert!(ab.merge(&b));
+        let mut ba = b.clone();
+        assert!(ba.merge(&a));
+        assert_eq!(ab, ba);
+        assert!(ab.is_conflicted());
+        assert_eq!(values(&ab), vec![&json!("a"), &json!("b")]);
+        assert_eq!(ab.to_json(), json!({"$conflict": ["a", "b"]}));
+
+        // Idempotent
+        assert!(!ab.merge(&ba));
+    }
+
+    #[test]
+    fn test_write_after_merge_collapses() {
+        let mut a = MVRegister::new();
+        let mut b = MVRegister::new();
+        write(&mut a, "a", 1, "alice");
+        write(&mut b, "b", 1, "bob");
+        a.merge(&b);
+        write(&mut a, "both", 2, "alice");
+        assert_eq!(a.value(), Some(&json!("both")));
+
+        // Bob still holds his write, but Alice's replacement has seen it
+        b.merge(&a);
+        assert_eq!(b.value(), Some(&json!("both")));
+        assert_eq!(b, a);
+    }
+
+    #[test]
+    fn test_stale_write_is_ignored() {
+        let mut register = MVRegister::new();
+        write(&mut register, "new", 5, "alice");
+        assert!(!register.write(json!("old"), Timestamp::new(3, "alice".to_string())));
+        assert_eq!(register.value(), Some(&json!("new")));
+        assert_eq!(MVRegister::new().to_json(), JsonValue::Null);
+    }
+}
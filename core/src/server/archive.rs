@@ -0,0 +1,470 @@
+//! Workspace archives: a whole workspace in one file, for backups and
+//! moving tenants between servers
+//!
+//! [`Workspace::export_archive`] writes every document, evicted ones
+//! included, and the deleted IDs; [`Workspace::import_archive`] restores
+//! them into another workspace, checking each document against its
+//! checksum. Both stream: one document is in memory at a time, however
+//! large the workspace.
+//!
+//! # Format
+//!
+//! A length-prefixed container, integers little-endian:
+//!
+//! ```text
+//! "SYNCKIT-ARCHIVE\n"   magic
+//! u32                   ARCHIVE_VERSION
+//! u32                   migrate::FORMAT_VERSION of the writer
+//! then records, each starting with a u8 tag:
+//!   1  document   u32 ID length, ID, u64 checksum, u64 state length, state
+//!   2  deleted    u32 ID length, ID
+//!   0  manifest   u64 length, manifest JSON, u64 checksum; always last
+//! ```
+//!
+//! A document's state is its serde JSON, the canonical persisted format,
+//! so an archive written by an older release loads through
+//! [`migrate`](crate::migrate). Checksums are FNV-1a over the ID and the
+//! state: they catch damage, not tampering. The [`ArchiveManifest`] comes
+//! last, so the export never looks at a document twice, and lists every
+//! document with its checksum, the deleted IDs and the workspace version
+//! (every document's version merged). Awareness isn't part of a
+//! workspace and isn't archived.
+//!
+//! # Import
+//!
+//! A document whose state doesn't match its checksum or doesn't decode is
+//! corrupted, and so is one the manifest lists that never came. With
+//! [`ImportOptions::skip_corrupted`] the import goes on without it and
+//! reports its ID in [`ImportReport::corrupted`]; otherwise it stops with
+//! [`ArchiveError::Corrupted`]. Damage to the lengths that frame the
+//! records stops the import with [`ArchiveError::Format`], as nothing
+//! after it can be found. Documents restored before an error stay
+//! restored.
+
+use super::Workspace;
+use crate::document::Fnv1a;
+use crate::error::SyncKitError;
+use crate::migrate::{self, StateKind, FORMAT_VERSION};
+use crate::sync::VectorClock;
+use crate::{Document, DocumentID};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Version of the container, written in every archive
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// First bytes of every archive
+const MAGIC: &[u8; 16] = b"SYNCKIT-ARCHIVE\n";
+
+const TAG_MANIFEST: u8 = 0;
+const TAG_DOCUMENT: u8 = 1;
+const TAG_DELETED: u8 = 2;
+
+/// Longest document ID an archive may hold, so a damaged length is
+/// caught before it is read
+const MAX_ID_LEN: u32 = 64 * 1024;
+
+/// What to do with documents the workspace already has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImportMode {
+    /// Merge archived documents into the ones there, as
+    /// [`Workspace::merge`] does; archived deletions win
+    #[default]
+    Merge,
+    /// Stop with [`ArchiveError::Conflict`] at the first document the
+    /// workspace already has or deleted
+    FailOnConflict,
+}
+
+/// How [`Workspace::import_archive`] restores an archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportOptions {
+    /// What to do with documents the workspace already has
+    pub mode: ImportMode,
+
+    /// Go on past corrupted documents, reporting them, instead of
+    /// stopping at the first
+    pub skip_corrupted: bool,
+}
+
+/// A document as listed in the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// ID of the document
+    pub id: DocumentID,
+
+    /// Checksum of the ID and state
+    pub checksum: u64,
+
+    /// Length of the state in bytes
+    pub size: u64,
+}
+
+/// What an archive holds, written after its records
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    /// [`ARCHIVE_VERSION`] of the writer
+    pub archive_version: u32,
+
+    /// [`FORMAT_VERSION`] the states were saved in
+    pub format: u32,
+
+    /// Every document's version merged
+    pub version: VectorClock,
+
+    /// The documents, sorted by ID
+    pub documents: Vec<ManifestEntry>,
+
+    /// The deleted IDs, sorted
+    pub deleted: Vec<DocumentID>,
+}
+
+/// What [`Workspace::import_archive`] did
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportReport {
+    /// Documents restored, in archive order
+    pub restored: Vec<DocumentID>,
+
+    /// Documents skipped as corrupted (see the [module docs](self))
+    pub corrupted: Vec<DocumentID>,
+
+    /// Documents newly deleted
+    pub deleted: usize,
+
+    /// The archive's manifest
+    pub manifest: ArchiveManifest,
+}
+
+/// Errors from exporting or importing an archive
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Sync(#[from] SyncKitError),
+
+    #[error("not a readable archive: {0}")]
+    Format(String),
+
+    #[error("unsupported archive version {0} (newest supported is {ARCHIVE_VERSION})")]
+    UnsupportedVersion(u32),
+
+    #[error("document {document_id} is corrupted")]
+    Corrupted { document_id: DocumentID },
+
+    #[error("document {document_id} is already in the workspace")]
+    Conflict { document_id: DocumentID },
+}
+
+impl Workspace {
+    /// Write every document, evicted ones included, and the deleted IDs
+    /// to `writer` as an archive (see [`archive`](self)); returns the
+    /// manifest written
+    ///
+    /// Documents are written as they are when reached: changes landing
+    /// during the export may or may not be included.
+    ///
+    /// # Errors
+    ///
+    /// Fails if writing fails, or an evicted document can't be read back
+    /// from storage
+    pub fn export_archive<W: Write>(&self, mut writer: W) -> Result<ArchiveManifest, ArchiveError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+
+        let mut deleted: Vec<DocumentID> = self.deleted().iter().cloned().collect();
+        deleted.sort();
+        for id in &deleted {
+            writer.write_all(&[TAG_DELETED])?;
+            write_id(&mut writer, id)?;
+        }
+
+        let mut ids: Vec<DocumentID> = self
+            .documents()
+            .iter()
+            .map(|document| document.id().clone())
+            .chain(self.evicted_ids())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        let mut version = VectorClock::new();
+        let mut documents = Vec::with_capacity(ids.len());
+        for id in ids {
+            let document = match self.resident(&id) {
+                Some(document) => document.read_snapshot(),
+                None => match self.read_evicted(&id)? {
+                    Some(document) => Arc::new(document),
+                    // Loaded and deleted meanwhile
+                    None => continue,
+                },
+            };
+            let state = serde_json::to_vec(&*document)
+                .map_err(|e| SyncKitError::serialization(e).with_document(id.as_str()))?;
+            let checksum = checksum(&id, &state);
+            writer.write_all(&[TAG_DOCUMENT])?;
+            write_id(&mut writer, &id)?;
+            writer.write_all(&checksum.to_le_bytes())?;
+            writer.write_all(&(state.len() as u64).to_le_bytes())?;
+            writer.write_all(&state)?;
+            version.merge(document.version());
+            documents.push(ManifestEntry {
+                id,
+                checksum,
+                size: state.len() as u64,
+            });
+        }
+
+        let manifest = ArchiveManifest {
+            archive_version: ARCHIVE_VERSION,
+            format: FORMAT_VERSION,
+            version,
+            documents,
+            deleted,
+        };
+        let bytes = serde_json::to_vec(&manifest).map_err(SyncKitError::serialization)?;
+        writer.write_all(&[TAG_MANIFEST])?;
+        writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+        writer.write_all(&bytes)?;
+        writer.write_all(&manifest_checksum(&bytes).to_le_bytes())?;
+        writer.flush()?;
+        Ok(manifest)
+    }
+
+    /// Restore an archive written by `export_archive` into this workspace
+    /// (see [`archive`](self))
+    ///
+    /// # Errors
+    ///
+    /// Fails if reading fails, the archive is damaged beyond its
+    /// documents' states, a document is corrupted (unless
+    /// `skip_corrupted`), or, with `FailOnConflict`, the workspace already
+    /// has an archived document
+    pub fn import_archive<R: Read>(
+        &self,
+        mut reader: R,
+        options: ImportOptions,
+    ) -> Result<ImportReport, ArchiveError> {
+        let mut magic = [0; MAGIC.len()];
+        read_exact(&mut reader, &mut magic)?;
+        if &magic != MAGIC {
+            return Err(ArchiveError::Format("missing archive header".to_string()));
+        }
+        let archive_version = read_u32(&mut reader)?;
+        if archive_version == 0 || archive_version > ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(archive_version));
+        }
+        let format = read_u32(&mut reader)?;
+
+        let mut restored = Vec::new();
+        let mut corrupted = Vec::new();
+        let mut deleted = 0;
+        let mut seen = HashSet::new();
+        let manifest = loop {
+            let mut tag = [0];
+            read_exact(&mut reader, &mut tag)?;
+            match tag[0] {
+                TAG_DELETED => {
+                    let id = read_id(&mut reader)?;
+                    if options.mode == ImportMode::FailOnConflict && self.holds(&id) {
+                        return Err(ArchiveError::Conflict { document_id: id });
+                    }
+                    if self.delete(&id) {
+                        deleted += 1;
+                    }
+                }
+                TAG_DOCUMENT => {
+                    let id = read_id(&mut reader)?;
+                    let expected = read_u64(&mut reader)?;
+                    let state = read_block(&mut reader)?;
+                    seen.insert(id.clone());
+                    let document = (checksum(&id, &state) == expected)
+                        .then(|| decode(format, &state))
+                        .flatten()
+                        .filter(|document| *document.id() == id);
+                    let Some(document) = document else {
+                        corrupt(&mut corrupted, options, id)?;
+                        continue;
+                    };
+                    match options.mode {
+                        ImportMode::FailOnConflict if self.holds(&id) || self.is_deleted(&id) => {
+                            return Err(ArchiveError::Conflict { document_id: id });
+                        }
+                        _ if self.is_deleted(&id) => continue,
+                        _ => {
+                            self.get_or_create(&id).merge(&document);
+                            restored.push(id);
+                        }
+                    }
+                }
+                TAG_MANIFEST => {
+                    let bytes = read_block(&mut reader)?;
+                    if read_u64(&mut reader)? != manifest_checksum(&bytes) {
+                        return Err(ArchiveError::Format("damaged manifest".to_string()));
+                    }
+                    break serde_json::from_slice::<ArchiveManifest>(&bytes)
+                        .map_err(|e| ArchiveError::Format(format!("damaged manifest: {}", e)))?;
+                }
+                tag => {
+                    return Err(ArchiveError::Format(format!("unknown record tag {}", tag)));
+                }
+            }
+        };
+
+        for entry in &manifest.documents {
+            if !seen.contains(&entry.id) {
+                corrupt(&mut corrupted, options, entry.id.clone())?;
+            }
+        }
+        Ok(ImportReport {
+            restored,
+            corrupted,
+            deleted,
+            manifest,
+        })
+    }
+
+    /// Check if the workspace has a document, in memory or evicted
+    fn holds(&self, id: &str) -> bool {
+        self.resident(id).is_some() || self.is_evicted(id)
+    }
+}
+
+/// Report a corrupted document, or stop at it
+fn corrupt(
+    corrupted: &mut Vec<DocumentID>,
+    options: ImportOptions,
+    document_id: DocumentID,
+) -> Result<(), ArchiveError> {
+    trace_debug!(document_id = %document_id, "corrupted document in archive");
+    if !options.skip_corrupted {
+        return Err(ArchiveError::Corrupted { document_id });
+    }
+    corrupted.push(document_id);
+    Ok(())
+}
+
+/// A document's state as saved in `format`, None if it doesn't decode
+fn decode(format: u32, state: &[u8]) -> Option<Document> {
+    let state = serde_json::from_slice(state).ok()?;
+    let state = migrate::upgrade(StateKind::Document, format, state).ok()?;
+    serde_json::from_value(state).ok()
+}
+
+fn checksum(id: &str, state: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::new();
+    hasher.write(id.as_bytes());
+    hasher.write(state);
+    hasher.finish()
+}
+
+fn manifest_checksum(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+fn write_id<W: Write>(writer: &mut W, id: &str) -> std::io::Result<()> {
+    writer.write_all(&(id.len() as u32).to_le_bytes())?;
+    writer.write_all(id.as_bytes())
+}
+
+/// Fill `buf`, reading the end of the archive as a format error
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), ArchiveError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => ArchiveError::Format("archive ends early".to_string()),
+        _ => ArchiveError::Io(e),
+    })
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, ArchiveError> {
+    let mut bytes = [0; 4];
+    read_exact(reader, &mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, ArchiveError> {
+    let mut bytes = [0; 8];
+    read_exact(reader, &mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Read `len` bytes, growing the buffer as they arrive rather than
+/// trusting the length up front
+fn read_bytes<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>, ArchiveError> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(ArchiveError::Format("archive ends early".to_string()));
+    }
+    Ok(bytes)
+}
+
+/// Read a u64 length and that many bytes
+fn read_block<R: Read>(reader: &mut R) -> Result<Vec<u8>, ArchiveError> {
+    let len = read_u64(reader)?;
+    read_bytes(reader, len)
+}
+
+fn read_id<R: Read>(reader: &mut R) -> Result<DocumentID, ArchiveError> {
+    let len = read_u32(reader)?;
+    if len > MAX_ID_LEN {
+        return Err(ArchiveError::Format(format!(
+            "document ID of {} bytes",
+            len
+        )));
+    }
+    String::from_utf8(read_bytes(reader, u64::from(len))?)
+        .map_err(|_| ArchiveError::Format("document ID isn't UTF-8".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn archive(workspace: &Workspace) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        workspace.export_archive(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_conflicts_and_damaged_framing() {
+        let source = Workspace::new();
+        source
+            .get_or_create("doc")
+            .set_field("title".to_string(), json!("Hi"), 1, "a".to_string());
+        source.delete("gone");
+        let bytes = archive(&source);
+
+        let target = Workspace::new();
+        target.get_or_create("doc");
+        let strict = ImportOptions {
+            mode: ImportMode::FailOnConflict,
+            ..ImportOptions::default()
+        };
+        let error = target.import_archive(bytes.as_slice(), strict).unwrap_err();
+        assert!(matches!(error, ArchiveError::Conflict { document_id } if document_id == "doc"));
+        let report = target
+            .import_archive(bytes.as_slice(), ImportOptions::default())
+            .unwrap();
+        assert_eq!(report.restored, ["doc"]);
+        assert!(target.is_deleted("gone"));
+
+        let truncated = &bytes[..bytes.len() - 4];
+        assert!(matches!(
+            Workspace::new().import_archive(truncated, ImportOptions::default()),
+            Err(ArchiveError::Format(_))
+        ));
+        assert!(matches!(
+            Workspace::new()
+                .import_archive(&b"not an archive at all"[..], ImportOptions::default()),
+            Err(ArchiveError::Format(_))
+        ));
+    }
+}
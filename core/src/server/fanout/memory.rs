@@ -0,0 +1,135 @@
+//! In-process `FanOut`
+
+use super::{matches_pattern, network_error, FanOut, FanOutEvent, FanOutMessage};
+use crate::error::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+
+/// Messages buffered for a slow subscriber before it misses some
+const BUFFER: usize = 1024;
+
+/// `FanOut` between relays in one process, for tests and single-process
+/// deployments
+///
+/// Clones share the same bus. `disconnect` and `reconnect` simulate a
+/// transport outage: while disconnected publishing fails and nothing is
+/// delivered. A subscriber that falls too far behind gets a
+/// `FanOutEvent::Reconnected`, as if its connection had dropped.
+#[derive(Debug, Clone)]
+pub struct MemoryFanOut {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    connected: AtomicBool,
+    bus: broadcast::Sender<FanOutEvent>,
+}
+
+impl MemoryFanOut {
+    /// Create a connected bus
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                connected: AtomicBool::new(true),
+                bus: broadcast::Sender::new(BUFFER),
+            }),
+        }
+    }
+
+    /// Check if the bus is connected
+    pub fn is_connected(&self) -> bool {
+        self.inner.connected.load(Ordering::SeqCst)
+    }
+
+    /// Simulate an outage: publishes fail until `reconnect`
+    pub fn disconnect(&self) {
+        self.inner.connected.store(false, Ordering::SeqCst);
+    }
+
+    /// End an outage, telling every subscriber it reconnected
+    pub fn reconnect(&self) {
+        if !self.inner.connected.swap(true, Ordering::SeqCst) {
+            let _ = self.inner.bus.send(FanOutEvent::Reconnected);
+        }
+    }
+}
+
+impl Default for MemoryFanOut {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FanOut for MemoryFanOut {
+    async fn publish(&self, channel: &str, payload: Vec<u8>) -> Result<()> {
+        if !self.is_connected() {
+            return Err(network_error("fan-out disconnected"));
+        }
+        // No receivers just means no one is subscribed
+        let _ = self.inner.bus.send(FanOutEvent::Message(FanOutMessage {
+            channel: channel.to_string(),
+            payload,
+        }));
+        Ok(())
+    }
+
+    async fn subscribe(&self, pattern: &str) -> Result<mpsc::Receiver<FanOutEvent>> {
+        let mut bus = self.inner.bus.subscribe();
+        let (events, rx) = mpsc::channel(BUFFER);
+        let pattern = pattern.to_string();
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = bus.recv() => event,
+                    _ = events.closed() => return,
+                };
+                let event = match event {
+                    Ok(FanOutEvent::Message(message)) => {
+                        if !matches_pattern(&pattern, &message.channel) {
+                            continue;
+                        }
+                        FanOutEvent::Message(message)
+                    }
+                    Ok(FanOutEvent::Reconnected) | Err(RecvError::Lagged(_)) => {
+                        FanOutEvent::Reconnected
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                if events.send(event).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_delivers_matching_channels_and_reconnects() {
+        let fanout = MemoryFanOut::new();
+        let mut docs = fanout.subscribe("app:doc:*").await.unwrap();
+
+        fanout.publish("app:other", vec![0]).await.unwrap();
+        fanout.publish("app:doc:a", vec![1]).await.unwrap();
+        assert_eq!(
+            docs.recv().await,
+            Some(FanOutEvent::Message(FanOutMessage {
+                channel: "app:doc:a".to_string(),
+                payload: vec![1],
+            }))
+        );
+
+        fanout.disconnect();
+        let error = fanout.publish("app:doc:a", vec![2]).await.unwrap_err();
+        assert!(error.is_retryable());
+        fanout.reconnect();
+        assert_eq!(docs.recv().await, Some(FanOutEvent::Reconnected));
+    }
+}
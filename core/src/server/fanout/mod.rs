@@ -0,0 +1,564 @@
+//! Pub/sub fan-out between server instances (`redis-fanout` feature)
+//!
+//! Behind a load balancer, clients of one document can be attached to
+//! different server instances. A [`FanOutRelay`] publishes every change
+//! applied to its instance's [`Workspace`] on a per-document channel and
+//! merges what the other instances publish, so clients see each other's
+//! edits wherever they are attached.
+//!
+//! [`FanOut`] is the transport: [`RedisFanOut`] in production,
+//! [`MemoryFanOut`] for tests and single-process setups.
+//!
+//! # Loops and echoes
+//!
+//! Each relay remembers, per document, what the other instances already
+//! have. It publishes only the difference between that and the document,
+//! and records ingested changes there too, so merging a peer's change
+//! publishes nothing back. Its own messages, which pub/sub delivers to
+//! every subscriber, are recognised by their author and dropped.
+//!
+//! # Disconnects
+//!
+//! Changes that fail to publish are kept and sent again once the
+//! transport reports [`FanOutEvent::Reconnected`]. The relay then also
+//! asks the other instances for anything it missed meanwhile: each one
+//! answers with the full state of every document the relay is behind on.
+//! An instance may publish an anti-entropy probe
+//! ([`VersionFilter`](crate::protocol::sync::VersionFilter)) on its resync
+//! channel instead of its versions; the others then publish the full state
+//! of the documents whose version the probe lacks.
+
+mod memory;
+mod redis;
+
+pub use self::memory::MemoryFanOut;
+pub use self::redis::RedisFanOut;
+
+use super::Workspace;
+use crate::error::{Result, SyncError, SyncKitError};
+use crate::protocol::delta::{
+    document_version_to_protocol, version_summary_from_protocol, DocumentDelta,
+};
+use crate::protocol::serialize::{decode_message, encode_message};
+use crate::protocol::sync::{peer_baseline, SyncCoordinator, VersionFilter};
+use crate::protocol::{sync_message, ClientId, SyncMessage, VersionSummary};
+use crate::{Document, DocumentID};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Channel prefix used unless `FanOutRelay::with_channel_prefix` says
+/// otherwise
+pub const DEFAULT_CHANNEL_PREFIX: &str = "synckit";
+
+/// A message received on a subscribed channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FanOutMessage {
+    /// Channel it was published on
+    pub channel: String,
+    /// Opaque payload
+    pub payload: Vec<u8>,
+}
+
+/// What a subscription delivers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FanOutEvent {
+    /// A message on a channel matching the pattern
+    Message(FanOutMessage),
+    /// The connection dropped and is back; messages published in between
+    /// were missed
+    Reconnected,
+}
+
+/// Pub/sub transport between server instances
+///
+/// Channels are plain strings. Patterns use `*` to match any run of
+/// characters, as in Redis `PSUBSCRIBE`.
+pub trait FanOut: Send + Sync + 'static {
+    /// Publish `payload` to every subscriber of `channel`, including this
+    /// instance's own
+    ///
+    /// # Errors
+    ///
+    /// Fails if the message could not be handed to the transport;
+    /// retryable errors mean the connection is down
+    fn publish(&self, channel: &str, payload: Vec<u8>) -> impl Future<Output = Result<()>> + Send;
+
+    /// Subscribe to every channel matching `pattern`
+    ///
+    /// The subscription ends when the receiver is dropped. If it closes
+    /// on its own the transport gave up reconnecting.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the subscription could not be set up
+    fn subscribe(
+        &self,
+        pattern: &str,
+    ) -> impl Future<Output = Result<mpsc::Receiver<FanOutEvent>>> + Send;
+}
+
+/// Exponential backoff for reconnecting to the fan-out transport
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts before giving up, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+    /// Upper bound on any delay
+    pub max_delay: Duration,
+    /// Factor applied to the delay after each retry
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (starting at 1)
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.powi(retry.saturating_sub(1) as i32);
+        self.initial_delay.mul_f64(factor).min(self.max_delay)
+    }
+
+    /// Run `operation` until it succeeds, fails with an error that is not
+    /// retryable, or runs out of attempts
+    ///
+    /// # Errors
+    ///
+    /// Returns the last error
+    pub async fn run<T, F, Fut>(&self, operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.run_with_sleep(operation, tokio::time::sleep).await
+    }
+
+    /// [`RetryPolicy::run`], waiting out each delay with `sleep` (advance
+    /// a [`crate::time::MockTime`] in tests instead of sleeping)
+    ///
+    /// # Errors
+    ///
+    /// Returns the last error
+    pub async fn run_with_sleep<T, F, Fut, S, SleepFut>(
+        &self,
+        mut operation: F,
+        mut sleep: S,
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+        S: FnMut(Duration) -> SleepFut,
+        SleepFut: Future<Output = ()>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(error) if error.is_retryable() && attempt < self.max_attempts => {
+                    trace_debug!(attempt, error = %error, "retrying fan-out operation");
+                    sleep(self.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Channel carrying the changes to one document
+pub fn document_channel(prefix: &str, document_id: &str) -> String {
+    format!("{}:doc:{}", prefix, document_id)
+}
+
+/// Channel on which `instance_id` asks for what it missed
+fn resync_channel(prefix: &str, instance_id: &str) -> String {
+    format!("{}:resync:{}", prefix, instance_id)
+}
+
+/// Publishes a workspace's changes on a `FanOut` and merges the other
+/// instances'
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::server::fanout::{FanOutRelay, MemoryFanOut};
+/// use synckit_core::server::Workspace;
+///
+/// # async fn run() -> synckit_core::Result<()> {
+/// let fanout = MemoryFanOut::new();
+/// let relay = FanOutRelay::new("instance-1", Workspace::new(), fanout.clone());
+/// tokio::spawn(async move { relay.run().await });
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FanOutRelay<F> {
+    instance_id: String,
+    workspace: Workspace,
+    fanout: F,
+    coordinator: SyncCoordinator,
+    prefix: String,
+}
+
+/// Per-run state of a relay
+#[derive(Default)]
+struct RelayState {
+    /// What the other instances have of each document, as far as we know
+    baselines: HashMap<DocumentID, Document>,
+    /// Documents with changes that failed to publish
+    pending: HashSet<DocumentID>,
+}
+
+impl<F: FanOut> FanOutRelay<F> {
+    /// Create a relay for the instance `instance_id`
+    ///
+    /// Every instance sharing the transport needs a distinct ID; it tags
+    /// published messages and applied changes.
+    pub fn new(instance_id: impl Into<String>, workspace: Workspace, fanout: F) -> Self {
+        Self {
+            instance_id: instance_id.into(),
+            workspace,
+            fanout,
+            coordinator: SyncCoordinator::new(),
+            prefix: DEFAULT_CHANNEL_PREFIX.to_string(),
+        }
+    }
+
+    /// Route changes through `coordinator` (and its filters)
+    pub fn with_coordinator(mut self, coordinator: SyncCoordinator) -> Self {
+        self.coordinator = coordinator;
+        self
+    }
+
+    /// Use channels under `prefix`, to share a transport between
+    /// deployments
+    pub fn with_channel_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// The relayed workspace
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspace
+    }
+
+    /// Relay changes until the subscription ends
+    ///
+    /// Malformed messages from other instances are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the subscription can't be set up, or with a
+    /// `NETWORK_ERROR` once the transport gives up reconnecting
+    pub async fn run(&self) -> Result<()> {
+        let mut inbound = self.fanout.subscribe(&format!("{}:*", self.prefix)).await?;
+        let mut changes = self.workspace.changes();
+        let mut state = RelayState::default();
+        // Catch up with what the other instances wrote before we started
+        self.request_resync().await;
+
+        loop {
+            tokio::select! {
+                event = inbound.recv() => match event {
+                    Some(FanOutEvent::Message(message)) => self.receive(&mut state, message).await,
+                    Some(FanOutEvent::Reconnected) => self.resync(&mut state).await,
+                    None => {
+                        return Err(SyncError::NetworkError(
+                            "fan-out subscription closed".to_string(),
+                        )
+                        .into())
+                    }
+                },
+                Some(id) = changes.recv() => self.push(&mut state, &id).await,
+            }
+        }
+    }
+
+    async fn receive(&self, state: &mut RelayState, message: FanOutMessage) {
+        let result = match decode_message::<SyncMessage>(&message.payload) {
+            Ok(SyncMessage {
+                payload: Some(sync_message::Payload::Delta(delta)),
+            }) => self.ingest(state, &delta),
+            Ok(SyncMessage {
+                payload: Some(sync_message::Payload::Versions(summary)),
+            }) => {
+                if message.channel != resync_channel(&self.prefix, &self.instance_id) {
+                    self.answer_resync(&summary).await;
+                }
+                Ok(())
+            }
+            Ok(SyncMessage {
+                payload: Some(sync_message::Payload::Probe(proto)),
+            }) => match VersionFilter::from_protocol(&proto) {
+                Ok(probe) if message.channel != resync_channel(&self.prefix, &self.instance_id) => {
+                    self.answer_probe(&probe).await;
+                    Ok(())
+                }
+                Ok(_) => Ok(()),
+                Err(error) => Err(error),
+            },
+            Ok(SyncMessage { payload: None }) => {
+                Err(SyncError::Protocol("empty sync message".to_string()).into())
+            }
+            Err(error) => Err(error),
+        };
+        if let Err(_error) = result {
+            trace_debug!(channel = %message.channel, error = %_error, "skipping fan-out message");
+        }
+    }
+
+    /// Merge a delta another instance published
+    fn ingest(&self, state: &mut RelayState, proto: &crate::protocol::Delta) -> Result<()> {
+        let author = proto.client_id.as_ref().map(|id| id.id.as_str());
+        if author == Some(self.instance_id.as_str()) {
+            // Our own publish coming back
+            return Ok(());
+        }
+        let delta = DocumentDelta::from_protocol(proto, &self.instance_id)?;
+        let document = self.workspace.get_or_create(&delta.document_id);
+        document.write(|document| {
+            self.coordinator
+                .apply_incoming(&delta, document, &self.instance_id)
+        })?;
+
+        // The others have this already: don't publish it again
+        let baseline = state
+            .baselines
+            .entry(delta.document_id.clone())
+            .or_insert_with(|| Document::new(delta.document_id.clone()));
+        self.coordinator
+            .apply_incoming(&delta, baseline, &self.instance_id)?;
+        Ok(())
+    }
+
+    /// Publish whatever changed in a document since the baseline
+    async fn push(&self, state: &mut RelayState, id: &DocumentID) {
+        let Some(document) = self.workspace.document(id) else {
+            return;
+        };
+        let current = document.read_snapshot();
+        let baseline = state
+            .baselines
+            .entry(id.clone())
+            .or_insert_with(|| Document::new(id.clone()));
+        match self.publish_delta(baseline, &current).await {
+            Ok(()) => {
+                *baseline = (*current).clone();
+                state.pending.remove(id);
+            }
+            Err(_error) => {
+                // Keep the baseline, so the next push includes these changes
+                trace_debug!(document_id = %id, error = %_error, "fan-out publish failed");
+                state.pending.insert(id.clone());
+            }
+        }
+    }
+
+    /// Publish the changes from `from` to `to`, if there are any
+    async fn publish_delta(&self, from: &Document, to: &Document) -> Result<()> {
+        let delta = self.coordinator.outgoing_delta(from, to)?;
+        if delta.is_empty() {
+            return Ok(());
+        }
+        trace_debug!(document_id = %to.id(), changes = delta.changes.len(), "publishing delta");
+        let mut proto = delta.to_protocol();
+        proto.client_id = Some(ClientId {
+            id: self.instance_id.clone(),
+        });
+        let message = SyncMessage {
+            payload: Some(sync_message::Payload::Delta(proto)),
+        };
+        let channel = document_channel(&self.prefix, to.id());
+        self.fanout
+            .publish(&channel, encode_message(&message)?.to_vec())
+            .await
+    }
+
+    /// Send what failed to publish and catch up on what we missed
+    async fn resync(&self, state: &mut RelayState) {
+        let pending: Vec<DocumentID> = state.pending.iter().cloned().collect();
+        for id in pending {
+            self.push(state, &id).await;
+        }
+        self.request_resync().await;
+    }
+
+    /// Ask the other instances for every change we don't have
+    async fn request_resync(&self) {
+        let documents = self
+            .workspace
+            .documents()
+            .iter()
+            .map(|document| document_version_to_protocol(document.id(), &document.version()))
+            .collect();
+        let message = SyncMessage {
+            payload: Some(sync_message::Payload::Versions(VersionSummary {
+                documents,
+            })),
+        };
+        let channel = resync_channel(&self.prefix, &self.instance_id);
+        let result = match encode_message(&message) {
+            Ok(bytes) => self.fanout.publish(&channel, bytes.to_vec()).await,
+            Err(error) => Err(error),
+        };
+        if let Err(_error) = result {
+            // The next reconnect asks again
+            trace_debug!(error = %_error, "fan-out resync request failed");
+        }
+    }
+
+    /// Publish the full state of the documents whose version is missing
+    /// from an anti-entropy probe (at most `max_follow_ups` of them)
+    async fn answer_probe(&self, probe: &VersionFilter) {
+        let documents: Vec<_> = self
+            .workspace
+            .documents()
+            .iter()
+            .map(|document| document.read_snapshot())
+            .collect();
+        let ids = self
+            .coordinator
+            .probable_mismatches(probe, documents.iter().map(|document| &**document));
+        for current in documents
+            .iter()
+            .filter(|document| ids.contains(document.id()))
+        {
+            let empty = Document::new(current.id().clone());
+            if let Err(_error) = self.publish_delta(&empty, current).await {
+                trace_debug!(document_id = %current.id(), error = %_error, "fan-out probe answer failed");
+            }
+        }
+    }
+
+    /// Publish the full state of every document the requester is behind on
+    async fn answer_resync(&self, summary: &VersionSummary) {
+        let versions = version_summary_from_protocol(summary);
+        for document in self.workspace.documents() {
+            let current = document.read_snapshot();
+            let baseline = peer_baseline(&current, versions.get(current.id()));
+            if let Err(_error) = self.publish_delta(&baseline, &current).await {
+                trace_debug!(document_id = %current.id(), error = %_error, "fan-out resync answer failed");
+            }
+        }
+    }
+}
+
+/// Check if `pattern` (with `*` wildcards) matches `channel`
+pub(crate) fn matches_pattern(pattern: &str, channel: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = channel.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard: exact match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Wrap a transport error as a retryable `NETWORK_ERROR`
+pub(crate) fn network_error(error: impl std::fmt::Display) -> SyncKitError {
+    SyncError::NetworkError(error.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::{MockTime, TimeProvider};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_patterns() {
+        assert!(matches_pattern("synckit:*", "synckit:doc:a"));
+        assert!(matches_pattern("synckit:doc:*", "synckit:doc:"));
+        assert!(matches_pattern("*:doc:*", "x:doc:a"));
+        assert!(matches_pattern("a*b*c", "abc"));
+        assert!(!matches_pattern("a*b*c", "acb"));
+        assert!(!matches_pattern("synckit:*", "other:doc:a"));
+        assert!(matches_pattern("exact", "exact"));
+        assert!(!matches_pattern("exact", "exactly"));
+    }
+
+    #[test]
+    fn test_retry_delays_back_off_to_the_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(350),
+            multiplier: 2.0,
+        };
+        let delays: Vec<u128> = (1..=4).map(|n| policy.delay(n).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 350, 350]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_stops_on_fatal_errors_and_after_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            ..RetryPolicy::default()
+        };
+        let time = MockTime::new(0);
+        let sleep = |delay: Duration| {
+            time.advance(delay.as_millis() as u64);
+            std::future::ready(())
+        };
+
+        let calls = AtomicU32::new(0);
+        let result: Result<()> = policy
+            .run_with_sleep(
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err(network_error("down"))
+                },
+                sleep,
+            )
+            .await;
+        assert!(result.unwrap_err().is_retryable());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(time.now_ms(), 100 + 200);
+
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<()> = policy
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(SyncKitError::invalid_input("bad"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        calls.store(0, Ordering::SeqCst);
+        let result = policy
+            .run_with_sleep(
+                || async {
+                    match calls.fetch_add(1, Ordering::SeqCst) {
+                        0 => Err(network_error("blip")),
+                        n => Ok(n),
+                    }
+                },
+                sleep,
+            )
+            .await;
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(time.now_ms(), 300 + 100);
+    }
+}
@@ -0,0 +1,185 @@
+//! Redis pub/sub `FanOut`
+
+use super::{network_error, FanOut, FanOutEvent, FanOutMessage, RetryPolicy};
+use crate::error::{Result, SyncError, SyncKitError};
+use redis::aio::{MultiplexedConnection, PubSub};
+use redis::RedisError;
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::StreamExt;
+
+/// Events buffered per subscription
+const SUBSCRIPTION_BUFFER: usize = 1024;
+
+/// `FanOut` over Redis pub/sub
+///
+/// Publishing shares one multiplexed connection; each subscription has
+/// its own. Dropped connections are re-established with the retry policy.
+/// A subscription that comes back reports `FanOutEvent::Reconnected`; one
+/// that runs out of attempts closes.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use synckit_core::server::fanout::{FanOutRelay, RedisFanOut, RetryPolicy};
+/// use synckit_core::server::Workspace;
+///
+/// # async fn run() -> synckit_core::Result<()> {
+/// let fanout = RedisFanOut::new("redis://127.0.0.1/")?.with_retry_policy(RetryPolicy {
+///     max_attempts: 10,
+///     ..RetryPolicy::default()
+/// });
+/// FanOutRelay::new("instance-1", Workspace::new(), fanout).run().await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RedisFanOut {
+    client: redis::Client,
+    publisher: Mutex<Option<MultiplexedConnection>>,
+    retry: RetryPolicy,
+}
+
+impl RedisFanOut {
+    /// Create a fan-out for the server at `url` (`redis://host:port/db`)
+    ///
+    /// Connects lazily, on the first publish or subscribe.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `INVALID_INPUT` if the URL can't be parsed
+    pub fn new(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url).map_err(|error| {
+            SyncKitError::invalid_input(format!("invalid Redis URL: {}", error))
+        })?;
+        Ok(Self {
+            client,
+            publisher: Mutex::new(None),
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    /// Reconnect with `policy` instead of `RetryPolicy::default()`
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    async fn try_publish(&self, channel: &str, payload: &[u8]) -> Result<()> {
+        let mut publisher = self.publisher.lock().await;
+        let connection = match publisher.as_mut() {
+            Some(connection) => connection,
+            None => publisher.insert(
+                self.client
+                    .get_multiplexed_async_connection()
+                    .await
+                    .map_err(to_error)?,
+            ),
+        };
+        let result = redis::cmd("PUBLISH")
+            .arg(channel)
+            .arg(payload)
+            .query_async::<()>(connection)
+            .await;
+        if let Err(error) = result {
+            if is_disconnect(&error) {
+                // Connect afresh on the next attempt
+                *publisher = None;
+            }
+            return Err(to_error(error));
+        }
+        Ok(())
+    }
+}
+
+impl FanOut for RedisFanOut {
+    async fn publish(&self, channel: &str, payload: Vec<u8>) -> Result<()> {
+        self.retry.run(|| self.try_publish(channel, &payload)).await
+    }
+
+    async fn subscribe(&self, pattern: &str) -> Result<mpsc::Receiver<FanOutEvent>> {
+        let mut pubsub = self.retry.run(|| connect(&self.client, pattern)).await?;
+        let (events, rx) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        let (client, retry, pattern) =
+            (self.client.clone(), self.retry.clone(), pattern.to_string());
+        tokio::spawn(async move {
+            loop {
+                {
+                    let mut messages = pubsub.on_message();
+                    loop {
+                        let message = tokio::select! {
+                            message = messages.next() => message,
+                            _ = events.closed() => return,
+                        };
+                        // The stream ends when the connection drops
+                        let Some(message) = message else { break };
+                        let event = FanOutEvent::Message(FanOutMessage {
+                            channel: message.get_channel_name().to_string(),
+                            payload: message.get_payload_bytes().to_vec(),
+                        });
+                        if events.send(event).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                trace_debug!(pattern = %pattern, "redis subscription dropped, reconnecting");
+                pubsub = match retry.run(|| connect(&client, &pattern)).await {
+                    Ok(pubsub) => pubsub,
+                    // Gave up: closing the receiver tells the subscriber
+                    Err(_) => return,
+                };
+                if events.send(FanOutEvent::Reconnected).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(rx)
+    }
+}
+
+/// Open a pub/sub connection subscribed to `pattern`
+async fn connect(client: &redis::Client, pattern: &str) -> Result<PubSub> {
+    let mut pubsub = client.get_async_pubsub().await.map_err(to_error)?;
+    pubsub.psubscribe(pattern).await.map_err(to_error)?;
+    Ok(pubsub)
+}
+
+fn is_disconnect(error: &RedisError) -> bool {
+    error.is_io_error()
+        || error.is_connection_dropped()
+        || error.is_connection_refusal()
+        || error.is_timeout()
+}
+
+/// Connection trouble is a retryable `NETWORK_ERROR`, the rest a
+/// `PROTOCOL_ERROR`
+fn to_error(error: RedisError) -> SyncKitError {
+    if is_disconnect(&error) {
+        network_error(error)
+    } else {
+        SyncError::Protocol(format!("redis: {}", error)).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_bad_urls() {
+        assert!(RedisFanOut::new("redis://127.0.0.1:6379/0").is_ok());
+        assert!(RedisFanOut::new("not a url").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_is_a_retryable_error() {
+        // Port 1 is reserved and never listening
+        let fanout = RedisFanOut::new("redis://127.0.0.1:1/")
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 2,
+                initial_delay: std::time::Duration::from_millis(1),
+                ..RetryPolicy::default()
+            });
+        let error = fanout.publish("synckit:doc:a", vec![1]).await.unwrap_err();
+        assert!(error.is_retryable(), "{}", error);
+    }
+}
@@ -0,0 +1,654 @@
+//! Evicting idle documents to storage (see [`Workspace::with_storage`])
+//!
+//! A server that keeps every document it ever served runs out of memory
+//! eventually. With storage attached, a workspace writes documents nobody
+//! is using to it and drops them from memory; `document` and
+//! `get_or_create` load them back when they are next asked for.
+//!
+//! # When a document is evicted
+//!
+//! - Never while another handle to it is alive. Sync sessions, request
+//!   handlers and sliced merges keep their `SharedDocument` while they use
+//!   it, which pins the document; the workspace's own change and search
+//!   subscriptions don't.
+//! - Above [`EvictionPolicy::capacity`], the least recently looked-up
+//!   documents go first. Pinned documents are skipped, so the bound is
+//!   exceeded while more than `capacity` are in use.
+//! - [`Workspace::evict_idle`] evicts documents nobody looked up or
+//!   changed for their idle interval.
+//!
+//! A write that lands while a document is being written to storage
+//! cancels its eviction.
+//!
+//! # Adaptive idle interval
+//!
+//! Each document starts with [`EvictionPolicy::idle`]. One loaded again
+//! within its interval of being evicted was evicted too early: its
+//! interval doubles, up to `max_idle`. One that stayed idle for twice its
+//! interval is halved again, down to `idle`.
+//!
+//! # Storage
+//!
+//! A document is written as one blob, `<id>.evicted`, under a temporary
+//! key first and then renamed over the old one. The blob includes the
+//! changes `take_dirty` hasn't returned yet, so a persistence task still
+//! sees them after the reload. `Storage` can't list keys: after a restart
+//! a document is found when it is first asked for.
+
+use super::Workspace;
+use crate::concurrent::SharedDocument;
+use crate::document::DirtyTracker;
+use crate::error::{Result, ResultExt, SyncError};
+use crate::storage::Storage;
+use crate::time::{SharedTime, TimeProvider};
+use crate::{Document, DocumentID};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// When a workspace with storage evicts documents (see the
+/// [module docs](self))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictionPolicy {
+    /// Documents kept in memory before the least recently used go
+    pub capacity: usize,
+
+    /// How long a document goes unused before `evict_idle` evicts it
+    pub idle: Duration,
+
+    /// Longest idle interval a document that keeps coming back adapts to
+    pub max_idle: Duration,
+}
+
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            idle: Duration::from_secs(300),
+            max_idle: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Counters of a workspace with storage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LifecycleMetrics {
+    /// Documents in memory
+    pub resident: usize,
+
+    /// Documents on storage only
+    pub evicted: usize,
+
+    /// Documents loaded back from storage
+    pub loads: u64,
+
+    /// Documents written to storage and dropped from memory
+    pub evictions: u64,
+
+    /// Evictions skipped because the document was in use or changed
+    /// while being written
+    pub pinned: u64,
+
+    /// Storage reads and writes that failed (the document stays where
+    /// it was)
+    pub failures: u64,
+}
+
+/// Eviction state of a workspace with storage
+pub(super) struct Lifecycle {
+    policy: EvictionPolicy,
+    storage: Mutex<Box<dyn Storage + Send>>,
+    state: Mutex<LifecycleState>,
+}
+
+struct LifecycleState {
+    resident: HashMap<DocumentID, Usage>,
+    /// Resident documents by last lookup, oldest first
+    recency: BTreeMap<u64, DocumentID>,
+    next_tick: u64,
+    evicted: HashMap<DocumentID, Evicted>,
+    metrics: LifecycleMetrics,
+    time: SharedTime,
+}
+
+/// How a resident document was used
+struct Usage {
+    tick: u64,
+    /// `SharedDocument::change_count` when last looked at
+    changes: u64,
+    active_ms: u64,
+    idle_ms: u64,
+}
+
+/// A document on storage only
+struct Evicted {
+    at_ms: u64,
+    idle_ms: u64,
+}
+
+impl Lifecycle {
+    pub(super) fn new(storage: Box<dyn Storage + Send>, policy: EvictionPolicy) -> Self {
+        Self {
+            policy,
+            storage: Mutex::new(storage),
+            state: Mutex::new(LifecycleState {
+                resident: HashMap::new(),
+                recency: BTreeMap::new(),
+                next_tick: 0,
+                evicted: HashMap::new(),
+                metrics: LifecycleMetrics::default(),
+                time: crate::time::default_provider(),
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, LifecycleState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn storage(&self) -> MutexGuard<'_, Box<dyn Storage + Send>> {
+        self.storage.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Start tracking a document that just came into memory
+    fn admit(&self, document: &SharedDocument) {
+        let mut state = self.state();
+        let now_ms = state.time.now_ms();
+        let max_idle_ms = self.policy.max_idle.as_millis() as u64;
+        let idle_ms = match state.evicted.remove(document.id()) {
+            // Evicted too early: wait longer next time
+            Some(evicted) if now_ms.saturating_sub(evicted.at_ms) < evicted.idle_ms => {
+                state.metrics.loads += 1;
+                evicted.idle_ms.saturating_mul(2).min(max_idle_ms)
+            }
+            Some(evicted) => {
+                state.metrics.loads += 1;
+                evicted.idle_ms
+            }
+            None => self.policy.idle.as_millis() as u64,
+        };
+        let tick = state.next_tick();
+        state.recency.insert(tick, document.id().clone());
+        state.resident.insert(
+            document.id().clone(),
+            Usage {
+                tick,
+                changes: document.change_count(),
+                active_ms: now_ms,
+                idle_ms,
+            },
+        );
+    }
+
+    /// Move a looked-up document to the back of the eviction order
+    fn touch(&self, id: &str) {
+        let mut state = self.state();
+        let tick = state.next_tick();
+        let now_ms = state.time.now_ms();
+        let state = &mut *state;
+        if let Some(usage) = state.resident.get_mut(id) {
+            state.recency.remove(&usage.tick);
+            state.recency.insert(tick, id.to_string());
+            usage.tick = tick;
+            usage.active_ms = now_ms;
+        }
+    }
+
+    /// The oldest resident document looked up after `tick`
+    fn next_oldest(&self, tick: Option<u64>) -> Option<(u64, DocumentID)> {
+        let state = self.state();
+        let mut range = match tick {
+            Some(tick) => state.recency.range(tick + 1..),
+            None => state.recency.range(..),
+        };
+        range.next().map(|(tick, id)| (*tick, id.clone()))
+    }
+
+    /// Note whether the document changed since last looked at; returns
+    /// whether it has been idle for its interval
+    fn is_idle(&self, document: &SharedDocument) -> bool {
+        let mut state = self.state();
+        let now_ms = state.time.now_ms();
+        let Some(usage) = state.resident.get_mut(document.id()) else {
+            return false;
+        };
+        let changes = document.change_count();
+        if changes != usage.changes {
+            usage.changes = changes;
+            usage.active_ms = now_ms;
+        }
+        now_ms.saturating_sub(usage.active_ms) >= usage.idle_ms
+    }
+
+    /// Record an eviction
+    fn evicted(&self, id: &str) {
+        let mut state = self.state();
+        let now_ms = state.time.now_ms();
+        let min_idle_ms = self.policy.idle.as_millis() as u64;
+        let Some(usage) = state.resident.remove(id) else {
+            return;
+        };
+        state.recency.remove(&usage.tick);
+        let idle_for = now_ms.saturating_sub(usage.active_ms);
+        let idle_ms = if idle_for >= usage.idle_ms.saturating_mul(2) {
+            (usage.idle_ms / 2).max(min_idle_ms)
+        } else {
+            usage.idle_ms
+        };
+        state.evicted.insert(
+            id.to_string(),
+            Evicted {
+                at_ms: now_ms,
+                idle_ms,
+            },
+        );
+        state.metrics.evictions += 1;
+    }
+
+    /// Write a document and its unflushed changes to storage
+    fn save(&self, document: &Document) -> Result<()> {
+        let id = document.id();
+        let bytes = serde_json::to_vec(&(document, document.dirty_tracker()))
+            .map_err(|e| SyncError::SerializationError(e.to_string()))
+            .with_document(id.as_str())?;
+        let mut storage = self.storage();
+        storage
+            .write(&temp_key(id), &bytes)
+            .with_document(id.as_str())?;
+        storage
+            .rename(&temp_key(id), &evicted_key(id))
+            .with_document(id.as_str())?;
+        Ok(())
+    }
+
+    /// Read a document written by `save`, None if there is none
+    fn load(&self, id: &str) -> Result<Option<Document>> {
+        let Some(bytes) = self.storage().read(&evicted_key(id)).with_document(id)? else {
+            return Ok(None);
+        };
+        let (mut document, pending): (Document, DirtyTracker) = serde_json::from_slice(&bytes)
+            .map_err(|e| SyncError::StorageError(format!("corrupt evicted document: {}", e)))
+            .with_document(id)?;
+        document.restore_dirty_tracker(pending);
+        Ok(Some(document))
+    }
+
+    fn count_failure(&self) {
+        self.state().metrics.failures += 1;
+    }
+}
+
+impl LifecycleState {
+    fn next_tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+}
+
+impl Workspace {
+    /// Create an empty workspace that evicts documents nobody uses to
+    /// `storage` (see [`lifecycle`](self))
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use synckit_core::server::{EvictionPolicy, Workspace};
+    /// use synckit_core::storage::MemoryStorage;
+    ///
+    /// let policy = EvictionPolicy {
+    ///     capacity: 1,
+    ///     ..EvictionPolicy::default()
+    /// };
+    /// let workspace = Workspace::with_storage(MemoryStorage::new(), policy);
+    /// workspace
+    ///     .get_or_create("a")
+    ///     .set_field("title".to_string(), serde_json::json!("A"), 1, "alice".to_string());
+    /// workspace.get_or_create("b");
+    ///
+    /// // "a" was evicted to make room, and comes back when asked for
+    /// assert_eq!(workspace.lifecycle_metrics().unwrap().evicted, 1);
+    /// let a = workspace.document("a").unwrap();
+    /// assert_eq!(a.get_field(&"title".to_string()), Some(serde_json::json!("A")));
+    /// ```
+    pub fn with_storage(storage: impl Storage + Send + 'static, policy: EvictionPolicy) -> Self {
+        Self::build(Some(Lifecycle::new(Box::new(storage), policy)))
+    }
+
+    /// Clock the idle intervals are measured with; the default provider
+    /// of the thread that created the workspace if unset
+    ///
+    /// No effect without storage.
+    pub fn set_time_provider(&self, provider: impl TimeProvider + Send + Sync + 'static) {
+        if let Some(lifecycle) = &self.inner.lifecycle {
+            lifecycle.state().time = SharedTime::new(provider);
+        }
+    }
+
+    /// Eviction counters; None without storage
+    pub fn lifecycle_metrics(&self) -> Option<LifecycleMetrics> {
+        let lifecycle = self.inner.lifecycle.as_ref()?;
+        let state = lifecycle.state();
+        Some(LifecycleMetrics {
+            resident: state.resident.len(),
+            evicted: state.evicted.len(),
+            ..state.metrics
+        })
+    }
+
+    /// Evict every document nobody has used or changed for its idle
+    /// interval; returns how many were evicted
+    ///
+    /// Changes are noticed here, so a document changed since the last
+    /// call counts as used now. Call it from a timer. No effect without
+    /// storage.
+    pub fn evict_idle(&self) -> usize {
+        let Some(lifecycle) = &self.inner.lifecycle else {
+            return 0;
+        };
+        let idle: Vec<DocumentID> = self
+            .documents()
+            .iter()
+            .filter(|document| lifecycle.is_idle(document))
+            .map(|document| document.id().clone())
+            .collect();
+        idle.iter().filter(|id| self.evict(lifecycle, id)).count()
+    }
+
+    /// Number of documents on storage only
+    pub(super) fn evicted_len(&self) -> usize {
+        self.inner
+            .lifecycle
+            .as_ref()
+            .map_or(0, |lifecycle| lifecycle.state().evicted.len())
+    }
+
+    /// Check if a document is on storage only
+    pub(super) fn is_evicted(&self, id: &str) -> bool {
+        self.inner
+            .lifecycle
+            .as_ref()
+            .is_some_and(|lifecycle| lifecycle.state().evicted.contains_key(id))
+    }
+
+    /// Documents on storage only, read without loading them
+    pub(super) fn evicted_documents(&self) -> Vec<Arc<Document>> {
+        self.evicted_ids()
+            .iter()
+            .filter_map(|id| match self.read_evicted(id) {
+                Ok(document) => document.map(Arc::new),
+                Err(_error) => {
+                    trace_debug!(document_id = %id, error = %_error, "reading evicted document failed");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// IDs of the documents on storage only
+    pub(super) fn evicted_ids(&self) -> Vec<DocumentID> {
+        self.inner
+            .lifecycle
+            .as_ref()
+            .map_or_else(Vec::new, |lifecycle| {
+                lifecycle.state().evicted.keys().cloned().collect()
+            })
+    }
+
+    /// A document on storage only, read without loading it; None if
+    /// storage doesn't have it
+    pub(super) fn read_evicted(&self, id: &str) -> Result<Option<Document>> {
+        match &self.inner.lifecycle {
+            Some(lifecycle) => lifecycle.load(id),
+            None => Ok(None),
+        }
+    }
+
+    /// Note a lookup of a resident document
+    pub(super) fn touch(&self, id: &str) {
+        if let Some(lifecycle) = &self.inner.lifecycle {
+            lifecycle.touch(id);
+        }
+    }
+
+    /// Start tracking a document just put in memory, evicting others if
+    /// that goes over capacity
+    ///
+    /// Call with the documents lock released; `document` stays pinned.
+    pub(super) fn admit(&self, document: &SharedDocument) {
+        let Some(lifecycle) = &self.inner.lifecycle else {
+            return;
+        };
+        lifecycle.admit(document);
+
+        let mut cursor = None;
+        while self.resident_len() > lifecycle.policy.capacity {
+            let Some((tick, id)) = lifecycle.next_oldest(cursor) else {
+                break;
+            };
+            cursor = Some(tick);
+            self.evict(lifecycle, &id);
+        }
+    }
+
+    /// Load an evicted document back into memory; None if storage
+    /// doesn't have it
+    pub(super) fn load(&self, id: &str) -> Option<SharedDocument> {
+        let lifecycle = self.inner.lifecycle.as_ref()?;
+        let document = match lifecycle.load(id) {
+            Ok(document) => SharedDocument::from(document?),
+            Err(_error) => {
+                trace_debug!(document_id = %id, error = %_error, "loading evicted document failed");
+                lifecycle.count_failure();
+                return None;
+            }
+        };
+
+        let mut documents = self.documents_mut();
+        if let Some(loaded) = documents.get(id) {
+            // Someone else loaded it meanwhile
+            return Some(loaded.clone());
+        }
+        if self.is_deleted(id) {
+            return None;
+        }
+        documents.insert(id.to_string(), document.clone());
+        drop(documents);
+        trace_debug!(document_id = %id, "loaded evicted document");
+        self.announce(&document);
+        self.admit(&document);
+        Some(document)
+    }
+
+    /// Stop tracking a deleted document and delete it from storage
+    pub(super) fn discard(&self, id: &str) {
+        let Some(lifecycle) = &self.inner.lifecycle else {
+            return;
+        };
+        {
+            let mut state = lifecycle.state();
+            if let Some(usage) = state.resident.remove(id) {
+                state.recency.remove(&usage.tick);
+            }
+            state.evicted.remove(id);
+        }
+        let mut storage = lifecycle.storage();
+        if let Err(_error) = storage.delete(&evicted_key(id)) {
+            trace_debug!(document_id = %id, error = %_error, "deleting evicted document failed");
+            drop(storage);
+            lifecycle.count_failure();
+        }
+    }
+
+    /// Write a document to storage and drop it from memory, unless it is
+    /// in use; returns whether it was evicted
+    fn evict(&self, lifecycle: &Lifecycle, id: &str) -> bool {
+        let Some(document) = self.resident(id) else {
+            return false;
+        };
+        // The workspace's handle and ours
+        if document.handle_count() > 2 {
+            lifecycle.state().metrics.pinned += 1;
+            return false;
+        }
+        let changes = document.change_count();
+        let snapshot = document.read_snapshot();
+        drop(document);
+        if let Err(_error) = lifecycle.save(&snapshot) {
+            trace_debug!(document_id = %id, error = %_error, "evicting document failed");
+            lifecycle.count_failure();
+            return false;
+        }
+
+        let mut documents = self.documents_mut();
+        match documents.get(id) {
+            Some(document)
+                if document.handle_count() == 1 && document.change_count() == changes =>
+            {
+                documents.remove(id);
+            }
+            _ => {
+                // Picked up or written meanwhile; the blob is overwritten
+                // by the next eviction
+                drop(documents);
+                lifecycle.state().metrics.pinned += 1;
+                return false;
+            }
+        }
+        // Record it before a lookup can miss it in memory and load it
+        lifecycle.evicted(id);
+        drop(documents);
+        self.index_evicted(&snapshot);
+        trace_debug!(document_id = %id, "evicted document");
+        true
+    }
+}
+
+fn evicted_key(id: &str) -> String {
+    format!("{}.evicted", id)
+}
+
+fn temp_key(id: &str) -> String {
+    format!("{}.evicted.tmp", id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::time::MockTime;
+    use serde_json::json;
+
+    fn workspace(capacity: usize) -> (Workspace, MockTime) {
+        let policy = EvictionPolicy {
+            capacity,
+            idle: Duration::from_secs(60),
+            max_idle: Duration::from_secs(240),
+        };
+        let workspace = Workspace::with_storage(MemoryStorage::new(), policy);
+        let time = MockTime::new(0);
+        workspace.set_time_provider(time.clone());
+        (workspace, time)
+    }
+
+    fn write(workspace: &Workspace, id: &str, value: i64) {
+        workspace.get_or_create(id).set_field(
+            "n".to_string(),
+            json!(value),
+            value as u64,
+            "c".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_pinned_documents_stay_in_memory() {
+        let (workspace, _) = workspace(2);
+        let held = workspace.get_or_create("held");
+        write(&workspace, "a", 1);
+        write(&workspace, "b", 2);
+
+        // "held" is the oldest, but in use: "a" goes instead
+        let metrics = workspace.lifecycle_metrics().unwrap();
+        assert_eq!((metrics.resident, metrics.evicted), (2, 1));
+        assert_eq!(metrics.pinned, 1);
+        assert!(workspace.resident("held").is_some());
+        assert!(workspace.resident("a").is_none());
+        assert_eq!(workspace.len(), 3);
+
+        // A held handle keeps writing to the document in memory
+        held.set_field("n".to_string(), json!(9), 1, "c".to_string());
+        drop(held);
+        write(&workspace, "c", 3);
+        assert!(workspace.resident("held").is_none());
+        let held = workspace.document("held").unwrap();
+        assert_eq!(held.get_field(&"n".to_string()), Some(json!(9)));
+    }
+
+    #[test]
+    fn test_unflushed_changes_survive_eviction() {
+        let (workspace, _) = workspace(1);
+        let document = workspace.get_or_create("a");
+        document.set_field("x".to_string(), json!(1), 1, "c".to_string());
+        document.write(|document| document.take_dirty());
+        document.set_field("y".to_string(), json!(2), 2, "c".to_string());
+        drop(document);
+        workspace.get_or_create("b");
+        assert!(workspace.resident("a").is_none());
+
+        let pending = workspace
+            .document("a")
+            .unwrap()
+            .write(|document| document.take_dirty());
+        assert_eq!(pending.fields.keys().collect::<Vec<_>>(), vec!["y"]);
+    }
+
+    #[test]
+    fn test_idle_interval_adapts_to_reloads() {
+        let (workspace, time) = workspace(10);
+        write(&workspace, "a", 1);
+        time.advance(59_000);
+        // The write since the last sweep counts as use
+        assert_eq!(workspace.evict_idle(), 0);
+        time.advance(59_000);
+        assert_eq!(workspace.evict_idle(), 0);
+        time.advance(1_000);
+        assert_eq!(workspace.evict_idle(), 1);
+
+        // Back within a minute: it now gets two
+        time.advance(10_000);
+        workspace.document("a").unwrap();
+        time.advance(60_000);
+        assert_eq!(workspace.evict_idle(), 0);
+        time.advance(60_000);
+        assert_eq!(workspace.evict_idle(), 1);
+
+        // Left alone for twice that before the sweep: back to one
+        time.advance(130_000);
+        workspace.document("a").unwrap();
+        time.advance(240_000);
+        assert_eq!(workspace.evict_idle(), 1);
+        time.advance(100_000);
+        workspace.document("a").unwrap();
+        time.advance(60_000);
+        assert_eq!(workspace.evict_idle(), 1);
+
+        let metrics = workspace.lifecycle_metrics().unwrap();
+        assert_eq!((metrics.loads, metrics.evictions), (3, 4));
+    }
+
+    #[test]
+    fn test_failed_write_keeps_the_document() {
+        let mut storage = MemoryStorage::new();
+        storage.crash_after(0);
+        let policy = EvictionPolicy {
+            capacity: 1,
+            ..EvictionPolicy::default()
+        };
+        let workspace = Workspace::with_storage(storage, policy);
+        write(&workspace, "a", 1);
+        write(&workspace, "b", 2);
+
+        let metrics = workspace.lifecycle_metrics().unwrap();
+        assert_eq!((metrics.resident, metrics.failures), (2, 1));
+        assert!(workspace.resident("a").is_some());
+    }
+}
@@ -0,0 +1,269 @@
+//! Refs between a workspace's documents (see [`crate::refs`])
+//!
+//! Every workspace indexes the refs its documents hold, so
+//! [`Workspace::backlinks`] can list the ones pointing at a document
+//! without scanning. Like the search index, the index follows the
+//! documents' change events and catches up before each query; an evicted
+//! document's refs stay indexed as they were when it left memory.
+//!
+//! # Deleting documents
+//!
+//! [`Workspace::delete`] drops a document and remembers its ID:
+//!
+//! - refs to it resolve to None with [`Workspace::resolve_ref`], and
+//!   [`Workspace::backlinks`] lists nothing for it; its own refs are gone
+//!   from everyone else's backlinks
+//! - `document` returns None for it, and `get_or_create` a handle that
+//!   isn't part of the workspace
+//! - [`Workspace::deletions`] subscribers receive a [`DeletedTarget`]
+//!   naming the refs left dangling
+//!
+//! Deletions reach other workspaces with [`Workspace::merge`], and win:
+//! a ref made concurrently with the deletion of its target dangles on
+//! both sides once they have merged, in either order. Replication
+//! (`grpc`, `redis-fanout`) carries document contents only.
+
+use super::{DocumentWatch, Workspace};
+use crate::concurrent::SharedDocument;
+use crate::refs::Backlinks;
+use crate::{Document, DocumentID, FieldPath};
+use std::sync::{Arc, PoisonError};
+use tokio::sync::broadcast;
+
+/// A document was deleted while refs pointed at it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletedTarget {
+    /// ID of the deleted document
+    pub document_id: DocumentID,
+
+    /// The documents and paths whose refs pointed at it, sorted
+    pub referrers: Vec<(DocumentID, FieldPath)>,
+}
+
+/// The workspace's backlink index and the subscriptions that keep it
+/// current
+pub(super) struct WorkspaceLinks {
+    backlinks: Backlinks,
+    watch: DocumentWatch,
+}
+
+impl WorkspaceLinks {
+    pub(super) fn new(watch: DocumentWatch) -> Self {
+        Self {
+            backlinks: Backlinks::new(),
+            watch,
+        }
+    }
+
+    /// Index the refs of the documents changed since the last catch-up
+    fn catch_up(&mut self, workspace: &Workspace) {
+        for document in self.watch.changed(workspace) {
+            self.backlinks.update_document(&document.read_snapshot());
+        }
+    }
+
+    /// Index a document's last refs before it leaves memory
+    pub(super) fn evicted(&mut self, document: &Document) {
+        self.watch.forget(document.id());
+        self.backlinks.update_document(document);
+    }
+
+    fn deleted(&mut self, id: &str) {
+        self.watch.forget(id);
+        self.backlinks.remove_document(id);
+    }
+}
+
+impl Workspace {
+    /// Delete a document; returns false if it was deleted already (see
+    /// the [module docs](self))
+    ///
+    /// The ID stays deleted even if the workspace never had the document,
+    /// so a copy merged in later is dropped. Handles held elsewhere keep
+    /// working on a copy that is no longer part of the workspace.
+    pub fn delete(&self, id: &str) -> bool {
+        let referrers = self.backlinks(id);
+        let mut documents = self.documents_mut();
+        let newly_deleted = self
+            .inner
+            .deleted
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id.to_string());
+        let removed = documents.remove(id);
+        drop(documents);
+        drop(removed);
+        if !newly_deleted {
+            return false;
+        }
+
+        self.discard(id);
+        if let Some(search) = self.search_state().as_mut() {
+            search.watch.forget(id);
+            search.index.remove_document(id);
+        }
+        self.links().deleted(id);
+        trace_debug!(document_id = %id, referrers = referrers.len(), "deleted document");
+        // No receivers just means nobody is listening for deletions
+        let _ = self.inner.deletions.send(DeletedTarget {
+            document_id: id.to_string(),
+            referrers,
+        });
+        true
+    }
+
+    /// Check if a document was deleted
+    pub fn is_deleted(&self, id: &str) -> bool {
+        self.deleted().contains(id)
+    }
+
+    /// Deletions from now on, with the refs each left dangling
+    ///
+    /// A subscriber more than 64 deletions behind receives
+    /// `RecvError::Lagged` and misses the oldest.
+    pub fn deletions(&self) -> broadcast::Receiver<DeletedTarget> {
+        self.inner.deletions.subscribe()
+    }
+
+    /// The document a ref field links to; None if the field isn't a ref,
+    /// or either document doesn't exist or was deleted
+    ///
+    /// An evicted target is loaded back from storage.
+    pub fn resolve_ref(&self, document_id: &str, field_path: &FieldPath) -> Option<SharedDocument> {
+        let target = self
+            .document(document_id)?
+            .read(|document| document.get_ref(field_path).cloned())?;
+        self.document(&target)
+    }
+
+    /// The documents and paths whose refs point at `id`, sorted; empty
+    /// once `id` is deleted
+    ///
+    /// Evicted documents are included without loading them.
+    pub fn backlinks(&self, id: &str) -> Vec<(DocumentID, FieldPath)> {
+        if self.is_deleted(id) {
+            return Vec::new();
+        }
+        let mut links = self.links();
+        links.catch_up(self);
+        let referrers = links.backlinks.referrers(id);
+        drop(links);
+        referrers
+            .into_iter()
+            .filter(|(referrer, _)| !self.is_deleted(referrer))
+            .collect()
+    }
+
+    /// Merge another workspace into this one: its deletions, then every
+    /// document it has, evicted ones included; returns how many
+    /// documents were deleted or changed
+    ///
+    /// Merging is commutative: two workspaces that merged each other hold
+    /// the same documents, whatever order they were written in.
+    pub fn merge(&self, other: &Workspace) -> usize {
+        let deleted: Vec<DocumentID> = other.deleted().iter().cloned().collect();
+        let mut changed = deleted.iter().filter(|id| self.delete(id)).count();
+
+        let mut documents: Vec<Arc<Document>> = other
+            .documents()
+            .iter()
+            .map(SharedDocument::read_snapshot)
+            .collect();
+        documents.extend(other.evicted_documents());
+        for remote in documents {
+            if self.is_deleted(remote.id()) {
+                continue;
+            }
+            if self.get_or_create(remote.id()).merge(&remote) > 0 {
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::EvictionPolicy;
+    use crate::storage::MemoryStorage;
+
+    fn link(workspace: &Workspace, from: &str, path: &str, to: &str) {
+        workspace.get_or_create(from).write(|document| {
+            document.set_ref(path.to_string(), to.to_string(), 1, "alice".to_string())
+        });
+    }
+
+    #[test]
+    fn test_backlinks_cover_evicted_referrers() {
+        let policy = EvictionPolicy {
+            capacity: 2,
+            ..EvictionPolicy::default()
+        };
+        let workspace = Workspace::with_storage(MemoryStorage::new(), policy);
+        workspace.get_or_create("user");
+        link(&workspace, "task-1", "assignee", "user");
+        link(&workspace, "task-2", "reviewer", "user");
+        for n in 0..5 {
+            workspace.get_or_create(&format!("other-{}", n));
+        }
+        assert!(workspace.is_evicted("task-1"));
+
+        let expected = vec![
+            ("task-1".to_string(), "assignee".to_string()),
+            ("task-2".to_string(), "reviewer".to_string()),
+        ];
+        assert_eq!(workspace.backlinks("user"), expected);
+        let resolved = workspace.resolve_ref("task-1", &"assignee".to_string());
+        assert_eq!(resolved.unwrap().id(), "user");
+
+        // Retargeting an evicted referrer loads it and moves its backlink
+        workspace.document("task-1").unwrap().write(|document| {
+            document.set_ref(
+                "assignee".to_string(),
+                "other-4".to_string(),
+                2,
+                "alice".to_string(),
+            )
+        });
+        assert_eq!(workspace.backlinks("user"), expected[1..]);
+        assert_eq!(workspace.backlinks("other-4"), expected[..1]);
+    }
+
+    #[test]
+    fn test_deleting_a_target_notifies_and_stays_deleted() {
+        let workspace = Workspace::new();
+        let mut deletions = workspace.deletions();
+        link(&workspace, "task", "assignee", "user");
+        link(&workspace, "user", "manager", "boss");
+        workspace.get_or_create("user");
+
+        assert!(workspace.delete("user"));
+        assert!(!workspace.delete("user"));
+        let deleted = deletions.try_recv().unwrap();
+        assert_eq!(deleted.document_id, "user");
+        assert_eq!(
+            deleted.referrers,
+            vec![("task".to_string(), "assignee".to_string())]
+        );
+        assert!(deletions.try_recv().is_err());
+
+        // The ref stays, dangling, and the deleted document's own are gone
+        assert!(workspace
+            .resolve_ref("task", &"assignee".to_string())
+            .is_none());
+        assert!(workspace.backlinks("user").is_empty());
+        assert!(workspace.backlinks("boss").is_empty());
+        assert!(workspace.document("user").is_none());
+        assert_eq!(workspace.len(), 1);
+
+        // Writes to a deleted ID go nowhere
+        workspace.get_or_create("user").set_field(
+            "name".to_string(),
+            serde_json::json!("Ada"),
+            5,
+            "bob".to_string(),
+        );
+        assert!(workspace.document("user").is_none());
+    }
+}
@@ -0,0 +1,593 @@
+//! Server-side document store (`server` feature)
+//!
+//! A [`Workspace`] holds the documents a sync server instance serves.
+//! Replication between servers plugs into it: gRPC between relays
+//! (`grpc` feature) and pub/sub fan-out between horizontally scaled
+//! instances ([`fanout`], `redis-fanout` feature). With storage attached,
+//! documents nobody uses are evicted to it ([`lifecycle`]). Refs between
+//! the documents are indexed both ways ([`links`]). A whole workspace
+//! exports to a single file and restores from it ([`archive`]).
+
+pub mod archive;
+#[cfg(feature = "redis-fanout")]
+pub mod fanout;
+pub mod lifecycle;
+pub mod links;
+
+pub use archive::{ArchiveError, ArchiveManifest, ImportMode, ImportOptions, ImportReport};
+pub use lifecycle::{EvictionPolicy, LifecycleMetrics};
+pub use links::DeletedTarget;
+
+use crate::concurrent::SharedDocument;
+use crate::document::Fnv1a;
+use crate::index::{SearchHit, SearchIndex};
+use crate::{Document, DocumentID};
+use lifecycle::Lifecycle;
+use links::WorkspaceLinks;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::{broadcast, mpsc, watch};
+
+/// Additions buffered for slow change listeners before they rescan
+const ADDED_BUFFER: usize = 64;
+
+/// Deletions buffered for slow `deletions` subscribers
+const DELETED_BUFFER: usize = 64;
+
+/// The documents a server instance holds
+///
+/// Clones refer to the same workspace. Write to the documents through
+/// their `SharedDocument` handles; replication picks the changes up.
+#[derive(Clone)]
+pub struct Workspace {
+    inner: Arc<WorkspaceInner>,
+}
+
+struct WorkspaceInner {
+    documents: RwLock<HashMap<DocumentID, SharedDocument>>,
+    /// IDs of documents put in memory; IDs rather than handles, so the
+    /// buffer pins nothing
+    added: broadcast::Sender<DocumentID>,
+    search: Mutex<Option<WorkspaceSearch>>,
+    links: Mutex<WorkspaceLinks>,
+    /// IDs of deleted documents; locked after `documents` when both are
+    deleted: RwLock<HashSet<DocumentID>>,
+    deletions: broadcast::Sender<DeletedTarget>,
+    lifecycle: Option<Lifecycle>,
+}
+
+/// The search index and the change subscriptions that keep it current
+struct WorkspaceSearch {
+    index: SearchIndex,
+    watch: DocumentWatch,
+}
+
+impl WorkspaceSearch {
+    /// Index what changed since the last catch-up
+    ///
+    /// Evicted documents stay indexed as they were; a reload watches them
+    /// again.
+    fn catch_up(&mut self, workspace: &Workspace) {
+        for document in self.watch.changed(workspace) {
+            self.index.update_document(&document.read_snapshot());
+        }
+    }
+}
+
+/// Change subscriptions to the documents in memory, for state kept
+/// current with them
+///
+/// Holds no handles, so the documents can still be evicted.
+struct DocumentWatch {
+    added: broadcast::Receiver<DocumentID>,
+    watched: HashMap<DocumentID, watch::Receiver<u64>>,
+}
+
+impl DocumentWatch {
+    /// Watch documents as they are added; subscribe before listing the
+    /// ones already there, so none slips through
+    fn new(added: broadcast::Receiver<DocumentID>) -> Self {
+        Self {
+            added,
+            watched: HashMap::new(),
+        }
+    }
+
+    /// The documents in memory that changed, or were added or loaded
+    /// again, since the last call
+    fn changed(&mut self, workspace: &Workspace) -> Vec<SharedDocument> {
+        let mut rescan = false;
+        loop {
+            match self.added.try_recv() {
+                Ok(id) => {
+                    if let Some(document) = workspace.resident(&id) {
+                        self.watch(&document);
+                    }
+                }
+                Err(TryRecvError::Lagged(_)) => rescan = true,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+        if rescan {
+            for document in workspace.documents() {
+                self.watch(&document);
+            }
+        }
+        let mut changed = Vec::new();
+        self.watched
+            .retain(|id, updates| match updates.has_changed() {
+                Ok(true) => {
+                    updates.mark_unchanged();
+                    changed.extend(workspace.resident(id));
+                    true
+                }
+                Ok(false) => true,
+                Err(_) => false,
+            });
+        changed
+    }
+
+    /// Subscribe to a document not watched yet, or loaded again since,
+    /// reporting it on the next `changed`
+    fn watch(&mut self, document: &SharedDocument) {
+        if is_stale(self.watched.get(document.id())) {
+            let mut updates = document.subscribe();
+            updates.mark_changed();
+            self.watched.insert(document.id().clone(), updates);
+        }
+    }
+
+    /// Stop watching a document leaving memory
+    fn forget(&mut self, id: &str) {
+        self.watched.remove(id);
+    }
+}
+
+impl Workspace {
+    /// Create an empty workspace
+    pub fn new() -> Self {
+        Self::build(None)
+    }
+
+    fn build(lifecycle: Option<Lifecycle>) -> Self {
+        let added = broadcast::Sender::new(ADDED_BUFFER);
+        let links = WorkspaceLinks::new(DocumentWatch::new(added.subscribe()));
+        Self {
+            inner: Arc::new(WorkspaceInner {
+                documents: RwLock::new(HashMap::new()),
+                added,
+                search: Mutex::new(None),
+                links: Mutex::new(links),
+                deleted: RwLock::new(HashSet::new()),
+                deletions: broadcast::Sender::new(DELETED_BUFFER),
+                lifecycle,
+            }),
+        }
+    }
+
+    /// The document with the given ID, if the workspace has it
+    ///
+    /// An evicted document is loaded back from storage. None once
+    /// deleted.
+    pub fn document(&self, id: &str) -> Option<SharedDocument> {
+        if self.is_deleted(id) {
+            return None;
+        }
+        match self.resident(id) {
+            Some(document) => {
+                self.touch(id);
+                Some(document)
+            }
+            None => self.load(id),
+        }
+    }
+
+    /// The document with the given ID, loaded from storage or created
+    /// empty if needed
+    ///
+    /// A deleted ID stays deleted: the handle returned is not part of the
+    /// workspace, and writes to it are dropped with it.
+    pub fn get_or_create(&self, id: &str) -> SharedDocument {
+        if let Some(document) = self.document(id) {
+            return document;
+        }
+        let mut documents = self.documents_mut();
+        if let Some(document) = documents.get(id) {
+            return document.clone();
+        }
+        if self.is_deleted(id) {
+            return SharedDocument::new(id.to_string());
+        }
+        let document = SharedDocument::new(id.to_string());
+        documents.insert(id.to_string(), document.clone());
+        drop(documents);
+        self.announce(&document);
+        self.admit(&document);
+        document
+    }
+
+    /// The document with the given ID if it is in memory
+    fn resident(&self, id: &str) -> Option<SharedDocument> {
+        self.inner
+            .documents
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+            .cloned()
+    }
+
+    fn resident_len(&self) -> usize {
+        self.inner
+            .documents
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    fn documents_mut(&self) -> RwLockWriteGuard<'_, HashMap<DocumentID, SharedDocument>> {
+        self.inner
+            .documents
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Tell change, search and link listeners about a document put in
+    /// memory
+    fn announce(&self, document: &SharedDocument) {
+        // No receivers just means nothing is listening for changes
+        let _ = self.inner.added.send(document.id().clone());
+    }
+
+    /// Handles to every document in memory
+    ///
+    /// Evicted documents are left out; replication and search pick them
+    /// up again when they are loaded.
+    pub fn documents(&self) -> Vec<SharedDocument> {
+        self.inner
+            .documents
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Number of documents, in memory or evicted
+    pub fn len(&self) -> usize {
+        self.resident_len() + self.evicted_len()
+    }
+
+    /// Check if the workspace has no documents
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// IDs of the documents to look at: every document once, then again
+    /// after each change to it
+    ///
+    /// Documents added later are included. Consecutive changes may be
+    /// reported once, so read the document when the ID arrives. Stops
+    /// when the receiver is dropped; must be called inside a tokio runtime.
+    pub fn changes(&self) -> mpsc::UnboundedReceiver<DocumentID> {
+        let (changes, rx) = mpsc::unbounded_channel();
+        // Subscribe before listing, so no new document slips through
+        let mut added = self.inner.added.subscribe();
+        let workspace = self.clone();
+        tokio::spawn(async move {
+            let mut watched = HashMap::new();
+            let mut rescan = true;
+            loop {
+                if rescan {
+                    for document in workspace.documents() {
+                        watch_document(&mut watched, &document, &changes);
+                    }
+                    rescan = false;
+                }
+                tokio::select! {
+                    id = added.recv() => match id {
+                        Ok(id) => {
+                            if let Some(document) = workspace.resident(&id) {
+                                watch_document(&mut watched, &document, &changes);
+                            }
+                        }
+                        Err(RecvError::Lagged(_)) => rescan = true,
+                        Err(RecvError::Closed) => return,
+                    },
+                    _ = changes.closed() => return,
+                }
+            }
+        });
+        rx
+    }
+
+    /// Keep a search index over the documents, starting from `index`
+    ///
+    /// Pass a new index, or one saved with `search_index` to skip
+    /// tokenizing what hasn't changed since. The index follows every
+    /// document's changes (writes, merges, replication) and catches up
+    /// before each `search`; documents the workspace doesn't have are
+    /// dropped from it, evicted ones are kept as they were. Replaces any index kept before.
+    pub fn enable_search(&self, mut index: SearchIndex) {
+        let mut watch = DocumentWatch::new(self.inner.added.subscribe());
+        let documents = self.documents();
+        let ids: HashSet<&DocumentID> = documents.iter().map(SharedDocument::id).collect();
+        let stale: Vec<DocumentID> = index
+            .document_ids()
+            .into_iter()
+            .filter(|id| !ids.contains(id) && !self.is_evicted(id))
+            .cloned()
+            .collect();
+        for id in stale {
+            index.remove_document(&id);
+        }
+
+        for document in &documents {
+            watch.watch(document);
+        }
+        *self.search_state() = Some(WorkspaceSearch { index, watch });
+    }
+
+    /// Documents matching `query`, best first (see [`SearchIndex::search`])
+    ///
+    /// Empty unless `enable_search` was called.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        match self.search_state().as_mut() {
+            Some(search) => {
+                search.catch_up(self);
+                search.index.search(query, limit)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Up-to-date copy of the search index, to save alongside the
+    /// documents; None unless `enable_search` was called
+    pub fn search_index(&self) -> Option<SearchIndex> {
+        self.search_state().as_mut().map(|search| {
+            search.catch_up(self);
+            search.index.clone()
+        })
+    }
+
+    /// Index a document's last state before it leaves memory, so it
+    /// stays searchable and its refs stay indexed while evicted
+    fn index_evicted(&self, document: &Document) {
+        if let Some(search) = self.search_state().as_mut() {
+            search.watch.forget(document.id());
+            search.index.update_document(document);
+        }
+        self.links().evicted(document);
+    }
+
+    fn search_state(&self) -> std::sync::MutexGuard<'_, Option<WorkspaceSearch>> {
+        self.inner
+            .search
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn links(&self) -> std::sync::MutexGuard<'_, WorkspaceLinks> {
+        self.inner
+            .links
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn deleted(&self) -> RwLockReadGuard<'_, HashSet<DocumentID>> {
+        self.inner
+            .deleted
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Stable 64-bit checksum of every document's fields, refs and
+    /// version, and of the deleted IDs
+    ///
+    /// Two servers that have converged report the same checksum, whatever
+    /// order they received the changes in and whichever documents they
+    /// evicted.
+    pub fn checksum(&self) -> u64 {
+        let mut documents: Vec<Arc<Document>> = self
+            .documents()
+            .iter()
+            .map(SharedDocument::read_snapshot)
+            .collect();
+        documents.extend(self.evicted_documents());
+        documents.sort_by(|a, b| a.id().cmp(b.id()));
+
+        let mut hasher = Fnv1a::new();
+        for document in documents {
+            hasher.write(document.id().as_bytes());
+            let mut fields: Vec<_> = document.fields().iter().collect();
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            for (path, field) in fields {
+                hasher.write(path.as_bytes());
+                hasher.write(field.value.to_string().as_bytes());
+                hasher.write(&field.timestamp.clock.to_le_bytes());
+                hasher.write(field.timestamp.client_id.as_bytes());
+            }
+            let mut refs: Vec<_> = document.refs().iter().collect();
+            refs.sort_by(|a, b| a.0.cmp(b.0));
+            for (path, link) in refs {
+                hasher.write(path.as_bytes());
+                hasher.write(link.target.as_deref().unwrap_or_default().as_bytes());
+                hasher.write(&link.timestamp.clock.to_le_bytes());
+                hasher.write(link.timestamp.client_id.as_bytes());
+            }
+            let mut clocks: Vec<_> = document.version().clocks().iter().collect();
+            clocks.sort();
+            for (client_id, clock) in clocks {
+                hasher.write(client_id.as_bytes());
+                hasher.write(&clock.to_le_bytes());
+            }
+        }
+        let deleted = self.deleted();
+        let mut deleted: Vec<&DocumentID> = deleted.iter().collect();
+        deleted.sort();
+        for id in deleted {
+            hasher.write(b"deleted");
+            hasher.write(id.as_bytes());
+        }
+        hasher.finish()
+    }
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Workspace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Workspace")
+            .field("documents", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Whether a document needs subscribing to: never subscribed, or
+/// evicted since
+fn is_stale(updates: Option<&watch::Receiver<u64>>) -> bool {
+    updates.is_none_or(|updates| updates.has_changed().is_err())
+}
+
+/// Send the document's ID now and after each of its changes, until it is
+/// evicted, unless already watched
+///
+/// Holds no handle, so the document can still be evicted.
+fn watch_document(
+    watched: &mut HashMap<DocumentID, watch::Receiver<u64>>,
+    document: &SharedDocument,
+    changes: &mpsc::UnboundedSender<DocumentID>,
+) {
+    if !is_stale(watched.get(document.id())) {
+        return;
+    }
+    // Subscribe first, so a write right after the first send is not missed
+    let mut updates = document.subscribe();
+    watched.insert(document.id().clone(), updates.clone());
+    let id = document.id().clone();
+    let changes = changes.clone();
+    if changes.send(id.clone()).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                updated = updates.changed() => {
+                    if updated.is_err() || changes.send(id.clone()).is_err() {
+                        return;
+                    }
+                }
+                _ = changes.closed() => return,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_checksum_ignores_insertion_order() {
+        let a = Workspace::new();
+        let b = Workspace::new();
+        for (workspace, order) in [(&a, ["x", "y"]), (&b, ["y", "x"])] {
+            for id in order {
+                workspace.get_or_create(id).set_field(
+                    "f".to_string(),
+                    json!(id),
+                    1,
+                    "c".to_string(),
+                );
+            }
+        }
+        assert_eq!(a.checksum(), b.checksum());
+
+        a.get_or_create("x")
+            .set_field("f".to_string(), json!("changed"), 2, "c".to_string());
+        assert_ne!(a.checksum(), b.checksum());
+    }
+
+    #[test]
+    fn test_search_follows_merges_without_rebuilding() {
+        let workspace = Workspace::new();
+        for i in 0..500 {
+            let document = workspace.get_or_create(&format!("doc-{:03}", i));
+            document.set_field(
+                "title".to_string(),
+                json!(format!("Note {}", i)),
+                1,
+                "a".to_string(),
+            );
+            document.set_field("body".to_string(), json!("draft text"), 1, "a".to_string());
+        }
+        workspace.enable_search(SearchIndex::new());
+        assert_eq!(workspace.search("draft", 1000).len(), 500);
+        assert_eq!(workspace.search_index().unwrap().tokenized(), 1000);
+
+        // Concurrent edits from other replicas arrive as merges
+        std::thread::scope(|scope| {
+            for client in ["bob", "carol"] {
+                let workspace = workspace.clone();
+                scope.spawn(move || {
+                    for i in (0..500).step_by(50) {
+                        let mut remote = Document::new(format!("doc-{:03}", i));
+                        let body = format!("reviewed by {}", client);
+                        let clock = if client == "bob" { 2 } else { 3 };
+                        remote.set_field(
+                            "body".to_string(),
+                            json!(body),
+                            clock,
+                            client.to_string(),
+                        );
+                        workspace.document(remote.id()).unwrap().merge(&remote);
+                    }
+                });
+            }
+        });
+        workspace.get_or_create("doc-new").set_field(
+            "title".to_string(),
+            json!("Reviewed later"),
+            1,
+            "a".to_string(),
+        );
+
+        // carol's writes won every merge; only the merged fields were retokenized
+        let reviewed = workspace.search("review", 1000);
+        assert_eq!(reviewed.len(), 11);
+        assert_eq!(workspace.search("review carol", 1000).len(), 10);
+        assert!(workspace.search("review bob", 1000).is_empty());
+        assert_eq!(workspace.search("draft", 1000).len(), 490);
+        assert_eq!(workspace.search_index().unwrap().tokenized(), 1000 + 10 + 1);
+
+        // A saved index picks up where it left off
+        let saved = serde_json::to_vec(&workspace.search_index().unwrap()).unwrap();
+        workspace.document("doc-001").unwrap().set_field(
+            "body".to_string(),
+            json!("final"),
+            5,
+            "a".to_string(),
+        );
+        workspace.enable_search(serde_json::from_slice(&saved).unwrap());
+        assert_eq!(workspace.search("final", 10)[0].document_id, "doc-001");
+        assert_eq!(workspace.search_index().unwrap().tokenized(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_changes_cover_existing_added_and_written_documents() {
+        let workspace = Workspace::new();
+        workspace.get_or_create("old");
+        let mut changes = workspace.changes();
+        assert_eq!(changes.recv().await.unwrap(), "old");
+
+        let new = workspace.get_or_create("new");
+        assert_eq!(changes.recv().await.unwrap(), "new");
+        new.set_field("f".to_string(), json!(1), 1, "c".to_string());
+        assert_eq!(changes.recv().await.unwrap(), "new");
+    }
+}
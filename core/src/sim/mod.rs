@@ -0,0 +1,590 @@
+//! Deterministic network simulation with fault injection
+//!
+//! Runs N replicas (a `Document` plus a `FugueText` each) on a virtual
+//! clock, connected by simulated links with seeded latency, loss,
+//! duplication and reordering. Scenarios script partitions and
+//! crash-restarts (replicas reload from their persisted bytes), then call
+//! [`Simulation::settle`], which stops the faults and checks the invariants
+//! at quiescence:
+//!
+//! - **convergence**: all replicas end up with the same document and text,
+//! - **no panic**: a panic while handling a message or edit is caught and
+//!   reported,
+//! - **bounded memory**: packets in flight and persisted replica size stay
+//!   under the configured limits.
+//!
+//! Everything random comes from one `u64` seed, so a failing run is
+//! replayed exactly by rerunning the scenario with the same seed. A
+//! [`SimFailure`] prints the seed and the event trace.
+//!
+//! # Example
+//!
+//! ```rust
+//! use synckit_core::sim::{LinkConfig, SimConfig, Simulation};
+//!
+//! let mut config = SimConfig::new(42, 3);
+//! config.link = LinkConfig::lossy();
+//!
+//! let mut sim = Simulation::new(config);
+//! sim.run_for(2_000);
+//! sim.partition(&[0], &[1, 2]);
+//! sim.run_for(2_000);
+//! let report = sim.settle().unwrap();
+//! assert!(report.edits > 0);
+//! ```
+
+mod network;
+mod node;
+mod rng;
+
+pub use network::LinkConfig;
+
+use crate::Document;
+use network::{EventQueue, Network};
+use node::{SimMessage, SimNode};
+use rng::SimRng;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Environment variable read by [`seed_from_env`]
+pub const SEED_ENV: &str = "SYNCKIT_SIM_SEED";
+
+/// Number of trace lines printed with a failure
+const TRACE_TAIL: usize = 200;
+
+/// Use the seed from `SYNCKIT_SIM_SEED` if set, else `default`
+///
+/// Lets a failing scenario be replayed with the seed it printed.
+pub fn seed_from_env(default: u64) -> u64 {
+    std::env::var(SEED_ENV)
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Simulation parameters
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub seed: u64,
+    pub nodes: usize,
+    /// Default fault profile for every link
+    pub link: LinkConfig,
+    /// Average time between local edits on each node
+    pub edit_interval_ms: u64,
+    /// Average time between sync attempts from each node to a random peer
+    pub sync_interval_ms: u64,
+    /// Packets allowed in flight at once
+    pub max_in_flight: usize,
+    /// Persisted replica size allowed per local edit (plus a fixed 4 KiB)
+    pub max_bytes_per_edit: usize,
+    /// Anti-entropy rounds `settle` may take before reporting divergence
+    pub max_settle_rounds: usize,
+}
+
+impl SimConfig {
+    /// Defaults: reliable 10ms links, an edit every 100ms and a sync every
+    /// 250ms per node
+    pub fn new(seed: u64, nodes: usize) -> Self {
+        Self {
+            seed,
+            nodes,
+            link: LinkConfig::default(),
+            edit_interval_ms: 100,
+            sync_interval_ms: 250,
+            max_in_flight: 10_000,
+            max_bytes_per_edit: 1_024,
+            max_settle_rounds: 5,
+        }
+    }
+}
+
+/// One line of the event trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// Virtual time in milliseconds
+    pub time: u64,
+    pub description: String,
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:>8}ms] {}", self.time, self.description)
+    }
+}
+
+/// Invariant broken during a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    Panicked {
+        node: usize,
+        message: String,
+    },
+    Diverged {
+        rounds: usize,
+    },
+    InFlightExceeded {
+        in_flight: usize,
+        limit: usize,
+    },
+    StateTooLarge {
+        node: usize,
+        bytes: usize,
+        limit: usize,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Panicked { node, message } => {
+                write!(f, "node {} panicked: {}", node, message)
+            }
+            Violation::Diverged { rounds } => {
+                write!(f, "replicas did not converge after {} sync rounds", rounds)
+            }
+            Violation::InFlightExceeded { in_flight, limit } => {
+                write!(f, "{} packets in flight (limit {})", in_flight, limit)
+            }
+            Violation::StateTooLarge { node, bytes, limit } => write!(
+                f,
+                "node {} persisted {} bytes (limit {})",
+                node, bytes, limit
+            ),
+        }
+    }
+}
+
+/// A failed run: the violation, the seed and the event trace
+#[derive(Clone)]
+pub struct SimFailure {
+    pub seed: u64,
+    pub violation: Violation,
+    pub trace: Vec<TraceEvent>,
+}
+
+impl fmt::Display for SimFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "simulation failed: {}", self.violation)?;
+        writeln!(
+            f,
+            "seed: {} (replay with {}={})",
+            self.seed, SEED_ENV, self.seed
+        )?;
+        let skipped = self.trace.len().saturating_sub(TRACE_TAIL);
+        writeln!(
+            f,
+            "trace ({} events, {} omitted):",
+            self.trace.len(),
+            skipped
+        )?;
+        for event in &self.trace[skipped..] {
+            writeln!(f, "  {}", event)?;
+        }
+        Ok(())
+    }
+}
+
+// `unwrap()` on a failed run should print the seed and trace readably
+impl fmt::Debug for SimFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for SimFailure {}
+
+/// Summary of a successful run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimReport {
+    pub seed: u64,
+    /// Virtual time at quiescence
+    pub time_ms: u64,
+    pub edits: usize,
+    pub sent: usize,
+    pub delivered: usize,
+    pub dropped: usize,
+    pub duplicated: usize,
+    /// Packets lost to partitions or crashed receivers
+    pub undeliverable: usize,
+    pub max_in_flight: usize,
+    /// Length of the converged text
+    pub text_len: usize,
+}
+
+enum Event {
+    Deliver {
+        from: usize,
+        to: usize,
+        message: Box<SimMessage>,
+    },
+    Edit {
+        node: usize,
+    },
+    Sync {
+        node: usize,
+    },
+}
+
+/// A seeded run over N simulated replicas
+pub struct Simulation {
+    config: SimConfig,
+    rng: SimRng,
+    queue: EventQueue<Event>,
+    network: Network,
+    nodes: Vec<SimNode>,
+    trace: Vec<TraceEvent>,
+    /// Edits and periodic syncs stop once settling starts
+    settling: bool,
+    in_flight: usize,
+    violation: Option<Violation>,
+    report: SimReport,
+}
+
+impl Simulation {
+    /// Create the replicas and schedule their first edits and syncs
+    pub fn new(config: SimConfig) -> Self {
+        let mut sim = Self {
+            rng: SimRng::new(config.seed),
+            queue: EventQueue::new(),
+            network: Network::new(config.link.clone()),
+            nodes: (0..config.nodes).map(SimNode::new).collect(),
+            trace: Vec::new(),
+            settling: false,
+            in_flight: 0,
+            violation: None,
+            report: SimReport {
+                seed: config.seed,
+                ..SimReport::default()
+            },
+            config,
+        };
+        for node in 0..sim.nodes.len() {
+            let edit_at = sim.rng.range(0, sim.config.edit_interval_ms);
+            let sync_at = sim.rng.range(0, sim.config.sync_interval_ms);
+            sim.queue.schedule(edit_at, Event::Edit { node });
+            sim.queue.schedule(sync_at, Event::Sync { node });
+        }
+        sim
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.config.seed
+    }
+
+    /// Current virtual time in milliseconds
+    pub fn now(&self) -> u64 {
+        self.queue.now()
+    }
+
+    /// Events so far
+    pub fn trace(&self) -> &[TraceEvent] {
+        &self.trace
+    }
+
+    /// Current text of a node (None while crashed)
+    pub fn text(&self, node: usize) -> Option<String> {
+        self.nodes[node]
+            .replica
+            .as_ref()
+            .map(|replica| replica.text.to_string())
+    }
+
+    /// Current document of a node (None while crashed)
+    pub fn document(&self, node: usize) -> Option<&Document> {
+        self.nodes[node]
+            .replica
+            .as_ref()
+            .map(|replica| &replica.document)
+    }
+
+    /// Override the fault profile between two nodes (both directions)
+    pub fn set_link(&mut self, a: usize, b: usize, config: LinkConfig) {
+        self.log(format!("link {}<->{} set to {:?}", a, b, config));
+        self.network.set_link(a, b, config);
+    }
+
+    /// Cut all links between two groups of nodes
+    pub fn partition(&mut self, left: &[usize], right: &[usize]) {
+        self.log(format!("partition {:?} | {:?}", left, right));
+        self.network.partition(left, right);
+    }
+
+    /// Remove all partitions
+    pub fn heal(&mut self) {
+        self.log("heal partitions".to_string());
+        self.network.heal();
+    }
+
+    /// Crash a node: in-memory state and packets addressed to it are lost
+    pub fn crash(&mut self, node: usize) {
+        self.log(format!("node {} crashed", node));
+        self.nodes[node].crash();
+    }
+
+    /// Restart a crashed node from its persisted state
+    pub fn restart(&mut self, node: usize) {
+        if !self.nodes[node].is_up() {
+            self.log(format!("node {} restarted from persisted state", node));
+            self.nodes[node].restart();
+        }
+    }
+
+    /// Start a sync handshake from `from` to `to` now
+    pub fn sync(&mut self, from: usize, to: usize) {
+        if let Some(request) = self.nodes[from].sync_request() {
+            self.send(from, to, request);
+        }
+    }
+
+    /// Process events for `ms` of virtual time (or until an invariant
+    /// breaks)
+    pub fn run_for(&mut self, ms: u64) {
+        let deadline = self.now() + ms;
+        while self.violation.is_none() {
+            match self.queue.pop_until(deadline) {
+                Some(scheduled) => self.dispatch(scheduled.event),
+                None => break,
+            }
+        }
+        self.queue.advance_to(deadline);
+    }
+
+    /// Stop the faults and check the invariants at quiescence
+    ///
+    /// Heals partitions, restarts crashed nodes, switches every link to
+    /// reliable, stops local edits, drains the network and then runs
+    /// pairwise sync rounds until a full round changes nothing.
+    pub fn settle(&mut self) -> Result<SimReport, SimFailure> {
+        self.settling = true;
+        self.log("settle: stop edits, heal, restart all nodes".to_string());
+        self.network.heal();
+        let latency = self.config.link.min_latency_ms.max(1);
+        self.network.set_all_links(LinkConfig::reliable(latency));
+        for node in 0..self.nodes.len() {
+            self.restart(node);
+        }
+        self.drain();
+
+        let mut converged = false;
+        let mut rounds = 0;
+        while self.violation.is_none() && rounds < self.config.max_settle_rounds {
+            rounds += 1;
+            let before = self.digests();
+            for a in 0..self.nodes.len() {
+                for b in a + 1..self.nodes.len() {
+                    self.sync(a, b);
+                    self.drain();
+                }
+            }
+            let after = self.digests();
+            if before == after {
+                converged = after.windows(2).all(|pair| pair[0] == pair[1]);
+                break;
+            }
+        }
+        if self.violation.is_none() && !converged {
+            self.violation = Some(Violation::Diverged { rounds });
+        }
+
+        if self.violation.is_none() {
+            self.check_state_size();
+        }
+
+        match self.violation.take() {
+            Some(violation) => Err(SimFailure {
+                seed: self.config.seed,
+                violation,
+                trace: self.trace.clone(),
+            }),
+            None => {
+                self.report.time_ms = self.now();
+                self.report.text_len = self.text(0).map_or(0, |text| text.chars().count());
+                Ok(self.report.clone())
+            }
+        }
+    }
+
+    fn drain(&mut self) {
+        while self.violation.is_none() {
+            match self.queue.pop_until(u64::MAX) {
+                Some(scheduled) => self.dispatch(scheduled.event),
+                None => break,
+            }
+        }
+    }
+
+    fn digests(&self) -> Vec<Option<(serde_json::Value, String)>> {
+        self.nodes.iter().map(SimNode::digest).collect()
+    }
+
+    fn check_state_size(&mut self) {
+        let limit = 4_096 + self.report.edits * self.config.max_bytes_per_edit;
+        for (node, state) in self.nodes.iter().enumerate() {
+            if state.persisted.len() > limit {
+                self.violation = Some(Violation::StateTooLarge {
+                    node,
+                    bytes: state.persisted.len(),
+                    limit,
+                });
+                return;
+            }
+        }
+    }
+
+    fn dispatch(&mut self, event: Event) {
+        match event {
+            Event::Edit { node } => {
+                if self.settling {
+                    return;
+                }
+                let mut rng = self.rng.clone();
+                if let Some(Some(description)) =
+                    self.guarded(node, |state| state.random_edit(&mut rng))
+                {
+                    self.report.edits += 1;
+                    self.log(format!("node {}: {}", node, description));
+                }
+                self.rng = rng;
+                let interval = self.config.edit_interval_ms;
+                let at = self.now() + self.rng.range(interval / 2, interval * 3 / 2);
+                self.queue.schedule(at, Event::Edit { node });
+            }
+            Event::Sync { node } => {
+                if self.settling {
+                    return;
+                }
+                if self.nodes.len() > 1 {
+                    let mut peer = self.rng.index(self.nodes.len() - 1);
+                    if peer >= node {
+                        peer += 1;
+                    }
+                    self.sync(node, peer);
+                }
+                let interval = self.config.sync_interval_ms;
+                let at = self.now() + self.rng.range(interval / 2, interval * 3 / 2);
+                self.queue.schedule(at, Event::Sync { node });
+            }
+            Event::Deliver { from, to, message } => {
+                self.in_flight -= 1;
+                let kind = message.kind();
+                if self.network.is_cut(from, to) {
+                    self.report.undeliverable += 1;
+                    self.log(format!("lost {} {}->{} (partitioned)", kind, from, to));
+                } else if !self.nodes[to].is_up() {
+                    self.report.undeliverable += 1;
+                    self.log(format!("lost {} {}->{} (node down)", kind, from, to));
+                } else {
+                    self.report.delivered += 1;
+                    self.log(format!("deliver {} {}->{}", kind, from, to));
+                    if let Some(Some(reply)) = self.guarded(to, |state| state.handle(*message)) {
+                        self.send(to, from, reply);
+                    }
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, message: SimMessage) {
+        self.report.sent += 1;
+        let kind = message.kind();
+        let now = self.now();
+        let times = self.network.route(&mut self.rng, now, from, to);
+        if times.is_empty() {
+            self.report.dropped += 1;
+            self.log(format!("drop {} {}->{}", kind, from, to));
+            return;
+        }
+
+        let bytes = message.encoded_len();
+        let arrivals: Vec<String> = times.iter().map(|t| format!("{}ms", t)).collect();
+        self.log(format!(
+            "send {} {}->{} ({} bytes, arrives {})",
+            kind,
+            from,
+            to,
+            bytes,
+            arrivals.join(" and ")
+        ));
+        if times.len() > 1 {
+            self.report.duplicated += 1;
+        }
+        for at in times {
+            self.queue.schedule(
+                at,
+                Event::Deliver {
+                    from,
+                    to,
+                    message: Box::new(message.clone()),
+                },
+            );
+            self.in_flight += 1;
+        }
+
+        self.report.max_in_flight = self.report.max_in_flight.max(self.in_flight);
+        if self.in_flight > self.config.max_in_flight {
+            self.violation = Some(Violation::InFlightExceeded {
+                in_flight: self.in_flight,
+                limit: self.config.max_in_flight,
+            });
+        }
+    }
+
+    /// Run `f` on a node, turning a panic into a violation
+    fn guarded<R>(&mut self, node: usize, f: impl FnOnce(&mut SimNode) -> R) -> Option<R> {
+        match catch_unwind(AssertUnwindSafe(|| f(&mut self.nodes[node]))) {
+            Ok(result) => Some(result),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "non-string panic payload".to_string());
+                self.log(format!("node {} panicked: {}", node, message));
+                self.violation = Some(Violation::Panicked { node, message });
+                None
+            }
+        }
+    }
+
+    fn log(&mut self, description: String) {
+        self.trace.push(TraceEvent {
+            time: self.now(),
+            description,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(seed: u64) -> (SimReport, Vec<TraceEvent>) {
+        let mut config = SimConfig::new(seed, 3);
+        config.link = LinkConfig::lossy();
+        let mut sim = Simulation::new(config);
+        sim.run_for(1_000);
+        let report = sim.settle().unwrap();
+        (report, sim.trace().to_vec())
+    }
+
+    #[test]
+    fn test_same_seed_replays_identically() {
+        assert_eq!(run(9), run(9));
+        assert_ne!(run(9).1, run(10).1);
+    }
+
+    #[test]
+    fn test_failure_prints_seed_and_trace() {
+        let failure = SimFailure {
+            seed: 77,
+            violation: Violation::Diverged { rounds: 5 },
+            trace: vec![TraceEvent {
+                time: 12,
+                description: "node 0 crashed".to_string(),
+            }],
+        };
+        let printed = format!("{:?}", failure);
+        assert!(printed.contains("SYNCKIT_SIM_SEED=77"));
+        assert!(printed.contains("[      12ms] node 0 crashed"));
+    }
+}
@@ -0,0 +1,234 @@
+//! Simulated links: latency, loss, duplication, reordering and partitions
+
+use super::rng::SimRng;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Fault profile of a directed link
+///
+/// Every packet gets a latency drawn uniformly from
+/// `min_latency_ms..=max_latency_ms`. A reordered packet is held back by up
+/// to `reorder_window_ms` extra so later packets can overtake it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkConfig {
+    pub min_latency_ms: u64,
+    pub max_latency_ms: u64,
+    /// Probability a packet is lost
+    pub drop: f64,
+    /// Probability a packet is delivered twice
+    pub duplicate: f64,
+    /// Probability a packet is held back
+    pub reorder: f64,
+    pub reorder_window_ms: u64,
+}
+
+impl LinkConfig {
+    /// Fixed latency, no faults
+    pub fn reliable(latency_ms: u64) -> Self {
+        Self {
+            min_latency_ms: latency_ms,
+            max_latency_ms: latency_ms,
+            drop: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_window_ms: 0,
+        }
+    }
+
+    /// Variable latency with some loss, duplication and reordering
+    pub fn lossy() -> Self {
+        Self {
+            min_latency_ms: 5,
+            max_latency_ms: 200,
+            drop: 0.1,
+            duplicate: 0.05,
+            reorder: 0.1,
+            reorder_window_ms: 500,
+        }
+    }
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self::reliable(10)
+    }
+}
+
+/// Something scheduled to happen at a virtual time
+pub(crate) struct Scheduled<E> {
+    pub(crate) at: u64,
+    seq: u64,
+    pub(crate) event: E,
+}
+
+impl<E> PartialEq for Scheduled<E> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl<E> Eq for Scheduled<E> {}
+
+impl<E> PartialOrd for Scheduled<E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<E> Ord for Scheduled<E> {
+    // Reversed: BinaryHeap pops the earliest event, ties in insertion order
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+/// Virtual clock plus the queue of pending events
+pub(crate) struct EventQueue<E> {
+    now: u64,
+    next_seq: u64,
+    queue: BinaryHeap<Scheduled<E>>,
+}
+
+impl<E> EventQueue<E> {
+    pub(crate) fn new() -> Self {
+        Self {
+            now: 0,
+            next_seq: 0,
+            queue: BinaryHeap::new(),
+        }
+    }
+
+    pub(crate) fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub(crate) fn schedule(&mut self, at: u64, event: E) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Scheduled { at, seq, event });
+    }
+
+    /// Pop the next event due at or before `until`, advancing the clock
+    pub(crate) fn pop_until(&mut self, until: u64) -> Option<Scheduled<E>> {
+        if self.queue.peek()?.at > until {
+            return None;
+        }
+        let next = self.queue.pop()?;
+        self.now = self.now.max(next.at);
+        Some(next)
+    }
+
+    /// Advance the clock without running anything
+    pub(crate) fn advance_to(&mut self, at: u64) {
+        self.now = self.now.max(at);
+    }
+}
+
+/// Link configuration and partitions between `n` nodes
+pub(crate) struct Network {
+    default_link: LinkConfig,
+    links: HashMap<(usize, usize), LinkConfig>,
+    /// Directed pairs that currently can't talk
+    cut: HashSet<(usize, usize)>,
+}
+
+impl Network {
+    pub(crate) fn new(default_link: LinkConfig) -> Self {
+        Self {
+            default_link,
+            links: HashMap::new(),
+            cut: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn set_link(&mut self, a: usize, b: usize, config: LinkConfig) {
+        self.links.insert((a, b), config.clone());
+        self.links.insert((b, a), config);
+    }
+
+    pub(crate) fn set_all_links(&mut self, config: LinkConfig) {
+        self.links.clear();
+        self.default_link = config;
+    }
+
+    fn link(&self, from: usize, to: usize) -> &LinkConfig {
+        self.links.get(&(from, to)).unwrap_or(&self.default_link)
+    }
+
+    /// Cut every link between the two groups, in both directions
+    pub(crate) fn partition(&mut self, left: &[usize], right: &[usize]) {
+        for &a in left {
+            for &b in right {
+                self.cut.insert((a, b));
+                self.cut.insert((b, a));
+            }
+        }
+    }
+
+    pub(crate) fn heal(&mut self) {
+        self.cut.clear();
+    }
+
+    pub(crate) fn is_cut(&self, from: usize, to: usize) -> bool {
+        self.cut.contains(&(from, to))
+    }
+
+    /// Delivery times for a packet sent at `now`: none if it is lost,
+    /// two if it is duplicated
+    pub(crate) fn route(&self, rng: &mut SimRng, now: u64, from: usize, to: usize) -> Vec<u64> {
+        let link = self.link(from, to);
+        if rng.chance(link.drop) {
+            return Vec::new();
+        }
+
+        let copies = if rng.chance(link.duplicate) { 2 } else { 1 };
+        (0..copies)
+            .map(|_| {
+                let mut at = now + rng.range(link.min_latency_ms, link.max_latency_ms);
+                if rng.chance(link.reorder) {
+                    at += rng.range(1, link.reorder_window_ms.max(1));
+                }
+                at
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_pops_in_time_then_insertion_order() {
+        let mut queue = EventQueue::new();
+        queue.schedule(20, "late");
+        queue.schedule(10, "first");
+        queue.schedule(10, "second");
+
+        assert!(queue.pop_until(5).is_none());
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop_until(100))
+            .map(|s| s.event)
+            .collect();
+        assert_eq!(order, vec!["first", "second", "late"]);
+        assert_eq!(queue.now(), 20);
+    }
+
+    #[test]
+    fn test_reliable_link_never_faults() {
+        let network = Network::new(LinkConfig::reliable(15));
+        let mut rng = SimRng::new(3);
+        for _ in 0..100 {
+            assert_eq!(network.route(&mut rng, 100, 0, 1), vec![115]);
+        }
+    }
+
+    #[test]
+    fn test_partition_is_symmetric() {
+        let mut network = Network::new(LinkConfig::default());
+        network.partition(&[0], &[1, 2]);
+        assert!(network.is_cut(0, 2) && network.is_cut(2, 0));
+        assert!(!network.is_cut(1, 2));
+        network.heal();
+        assert!(!network.is_cut(0, 1));
+    }
+}
@@ -0,0 +1,234 @@
+//! A simulated replica: a document and a text, synced by a three-step
+//! handshake and persisted after every change
+
+use super::rng::SimRng;
+use crate::crdt::{FugueText, TextDelta};
+use crate::sync::VectorClock;
+use crate::Document;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Sync handshake between two replicas
+///
+/// `Request` carries the requester's document and text state vector,
+/// the reply carries the responder's document and the text the requester
+/// is missing, and the ack sends back what the responder is missing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum SimMessage {
+    Request {
+        document: Document,
+        state_vector: VectorClock,
+    },
+    Reply {
+        document: Document,
+        delta: TextDelta,
+        state_vector: VectorClock,
+    },
+    Ack {
+        delta: TextDelta,
+    },
+}
+
+impl SimMessage {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            SimMessage::Request { .. } => "Request",
+            SimMessage::Reply { .. } => "Reply",
+            SimMessage::Ack { .. } => "Ack",
+        }
+    }
+
+    /// Encoded size, as it would go over the wire
+    pub(crate) fn encoded_len(&self) -> usize {
+        serde_json::to_vec(self).map_or(0, |bytes| bytes.len())
+    }
+}
+
+/// In-memory state of a running replica
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Replica {
+    pub(crate) document: Document,
+    pub(crate) text: FugueText,
+}
+
+/// A replica that can crash and restart from its persisted bytes
+pub(crate) struct SimNode {
+    pub(crate) client_id: String,
+    pub(crate) replica: Option<Replica>,
+    pub(crate) persisted: Vec<u8>,
+}
+
+impl SimNode {
+    pub(crate) fn new(index: usize) -> Self {
+        let client_id = format!("node-{}", index);
+        let replica = Replica {
+            document: Document::new("sim-doc".to_string()),
+            text: FugueText::new(client_id.clone()),
+        };
+        let mut node = Self {
+            client_id,
+            replica: Some(replica),
+            persisted: Vec::new(),
+        };
+        node.persist();
+        node
+    }
+
+    pub(crate) fn is_up(&self) -> bool {
+        self.replica.is_some()
+    }
+
+    /// Write the replica to "disk" (write-through after every change)
+    fn persist(&mut self) {
+        if let Some(replica) = &self.replica {
+            self.persisted = serde_json::to_vec(replica).expect("replica serializes");
+        }
+    }
+
+    /// Lose all in-memory state
+    pub(crate) fn crash(&mut self) {
+        self.replica = None;
+    }
+
+    /// Reload the replica from its persisted bytes
+    pub(crate) fn restart(&mut self) {
+        if self.replica.is_none() {
+            self.replica =
+                Some(serde_json::from_slice(&self.persisted).expect("persisted replica"));
+        }
+    }
+
+    /// Apply one random local edit; returns a trace description
+    pub(crate) fn random_edit(&mut self, rng: &mut SimRng) -> Option<String> {
+        let replica = self.replica.as_mut()?;
+        let text = &mut replica.text;
+
+        let description = match rng.index(10) {
+            0..=4 => {
+                let word: String = (0..rng.range(1, 4))
+                    .map(|_| (b'a' + rng.index(26) as u8) as char)
+                    .collect();
+                let position = rng.index(text.len() + 1);
+                text.insert(position, &word).expect("position in bounds");
+                format!("insert {:?} at {}", word, position)
+            }
+            5..=6 if !text.is_empty() => {
+                let position = rng.index(text.len());
+                let length = (rng.range(1, 3) as usize).min(text.len() - position);
+                text.delete(position, length).expect("range in bounds");
+                format!("delete {} at {}", length, position)
+            }
+            _ => {
+                let field = format!("f{}", rng.index(5));
+                let value = rng.range(0, 999);
+                let document = &mut replica.document;
+                let clock = document.version.get(&self.client_id).max(
+                    document
+                        .fields
+                        .values()
+                        .map(|f| f.timestamp.clock)
+                        .max()
+                        .unwrap_or(0),
+                ) + 1;
+                document.set_field(field.clone(), json!(value), clock, self.client_id.clone());
+                document.version.update(&self.client_id, clock);
+                format!("set {} = {}", field, value)
+            }
+        };
+
+        self.persist();
+        Some(description)
+    }
+
+    /// Start a handshake with a peer
+    pub(crate) fn sync_request(&self) -> Option<SimMessage> {
+        let replica = self.replica.as_ref()?;
+        Some(SimMessage::Request {
+            document: replica.document.clone(),
+            state_vector: replica.text.state_vector(),
+        })
+    }
+
+    /// Handle a message; returns the reply, if any
+    ///
+    /// Messages to a crashed node are lost.
+    pub(crate) fn handle(&mut self, message: SimMessage) -> Option<SimMessage> {
+        let replica = self.replica.as_mut()?;
+        let reply = match message {
+            SimMessage::Request {
+                document,
+                state_vector,
+            } => {
+                replica.document.merge(&document);
+                Some(SimMessage::Reply {
+                    document: replica.document.clone(),
+                    delta: replica.text.diff_since(&state_vector),
+                    state_vector: replica.text.state_vector(),
+                })
+            }
+            SimMessage::Reply {
+                document,
+                delta,
+                state_vector,
+            } => {
+                replica.document.merge(&document);
+                replica.text.apply_delta(&delta).expect("valid delta");
+                Some(SimMessage::Ack {
+                    delta: replica.text.diff_since(&state_vector),
+                })
+            }
+            SimMessage::Ack { delta } => {
+                replica.text.apply_delta(&delta).expect("valid delta");
+                None
+            }
+        };
+        self.persist();
+        reply
+    }
+
+    /// Comparable view of the replica for convergence checks
+    pub(crate) fn digest(&self) -> Option<(serde_json::Value, String)> {
+        let replica = self.replica.as_ref()?;
+        Some((replica.document.to_json(), replica.text.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_converges_both_sides() {
+        let mut rng = SimRng::new(11);
+        let mut a = SimNode::new(0);
+        let mut b = SimNode::new(1);
+        for _ in 0..20 {
+            a.random_edit(&mut rng);
+            b.random_edit(&mut rng);
+        }
+
+        let reply = b.handle(a.sync_request().unwrap()).unwrap();
+        let ack = a.handle(reply).unwrap();
+        assert!(b.handle(ack).is_none());
+        assert_eq!(a.digest(), b.digest());
+    }
+
+    #[test]
+    fn test_restart_restores_persisted_state() {
+        let mut rng = SimRng::new(5);
+        let mut node = SimNode::new(0);
+        for _ in 0..10 {
+            node.random_edit(&mut rng);
+        }
+        let before = node.digest();
+
+        node.crash();
+        assert!(node.digest().is_none());
+        assert!(node
+            .handle(SimNode::new(1).sync_request().unwrap())
+            .is_none());
+
+        node.restart();
+        assert_eq!(node.digest(), before);
+    }
+}
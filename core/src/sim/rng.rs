@@ -0,0 +1,65 @@
+//! Seeded random numbers for the simulation
+
+/// SplitMix64: small, fast and fully determined by its seed
+#[derive(Debug, Clone)]
+pub(crate) struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `min..=max`
+    pub(crate) fn range(&mut self, min: u64, max: u64) -> u64 {
+        if max <= min {
+            return min;
+        }
+        min + self.next_u64() % (max - min + 1)
+    }
+
+    /// Uniform index in `0..len` (`len` must be non-zero)
+    pub(crate) fn index(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+
+    /// True with probability `p`
+    pub(crate) fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = SimRng::new(42);
+        let mut b = SimRng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(SimRng::new(1).next_u64(), SimRng::new(2).next_u64());
+    }
+
+    #[test]
+    fn test_range_and_chance_bounds() {
+        let mut rng = SimRng::new(7);
+        for _ in 0..1000 {
+            assert!((3..=5).contains(&rng.range(3, 5)));
+            assert!(rng.index(4) < 4);
+        }
+        assert!(!(0..1000).any(|_| rng.chance(0.0)));
+        assert!((0..1000).all(|_| rng.chance(1.0)));
+    }
+}
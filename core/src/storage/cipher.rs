@@ -0,0 +1,359 @@
+//! Per-field encryption at rest
+//!
+//! A [`FieldCipher`] encrypts the values of chosen field paths (PII such
+//! as `users.*.email`) before a document reaches storage, and leaves the
+//! rest in plaintext. The encryption itself comes from the caller through
+//! [`Cipher`]; this module only decides what gets encrypted and how it is
+//! stored.
+//!
+//! An encrypted value is stored in place of the plaintext as an ordinary
+//! JSON value, `{"$encrypted": {"key_id": "k1", "ciphertext": "<hex>"}}`,
+//! under the field's original timestamp. A replica without the key keeps
+//! it as that placeholder: it merges last-writer-wins and travels in
+//! deltas like any other value, and `to_json` shows plainly which fields
+//! couldn't be read. Decrypt again with [`FieldCipher::open_document`]
+//! whenever sealed values may have arrived by merge.
+//!
+//! Only plain fields are encrypted; lists, multi-value fields and refs
+//! are stored as they are, and so is the undo history of a
+//! [`PersistentDocument`](super::PersistentDocument).
+
+use crate::document::{DirtyState, Document, Field};
+use crate::error::{Result, SyncKitError};
+use crate::sync::filter::matches_pattern;
+use crate::FieldPath;
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Marker key of an encrypted placeholder value
+pub const ENCRYPTED_MARKER: &str = "$encrypted";
+
+/// Encryption supplied by the caller
+///
+/// Keys are named by ID, stored next to each ciphertext, so values
+/// written under an old key still decrypt after a rotation.
+pub trait Cipher {
+    /// ID of the key `encrypt` uses
+    fn key_id(&self) -> &str;
+
+    /// Encrypt `plaintext` with the current key
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decrypt `ciphertext` written with key `key_id`, or `Ok(None)` if
+    /// that key isn't available here
+    fn decrypt(&self, key_id: &str, ciphertext: &[u8]) -> Result<Option<Vec<u8>>>;
+}
+
+/// A value as stored encrypted: the placeholder described in the module
+/// docs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedValue {
+    /// ID of the key it was encrypted with
+    pub key_id: String,
+    /// Encrypted JSON text of the value
+    pub ciphertext: Vec<u8>,
+}
+
+impl EncryptedValue {
+    /// Read a placeholder, `None` if `value` isn't one
+    pub fn from_json(value: &JsonValue) -> Option<Self> {
+        let sealed = value.as_object()?;
+        if sealed.len() != 1 {
+            return None;
+        }
+        let inner = sealed.get(ENCRYPTED_MARKER)?;
+        Some(Self {
+            key_id: inner.get("key_id")?.as_str()?.to_string(),
+            ciphertext: decode_hex(inner.get("ciphertext")?.as_str()?)?,
+        })
+    }
+
+    /// The placeholder to store in place of the value
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            ENCRYPTED_MARKER: {
+                "key_id": self.key_id,
+                "ciphertext": encode_hex(&self.ciphertext),
+            }
+        })
+    }
+}
+
+/// Which fields to encrypt, and the [`Cipher`] to do it with
+///
+/// Paths are matched like [`SyncFilter`](crate::sync::SyncFilter)
+/// patterns: segment by segment, `*` for any one segment, and a pattern
+/// covering a path covers the subtree below it.
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::error::Result;
+/// use synckit_core::storage::{Cipher, EncryptedValue, FieldCipher};
+/// use synckit_core::Document;
+/// use serde_json::json;
+///
+/// /// Stand-in for a real cipher
+/// struct Reverse;
+///
+/// impl Cipher for Reverse {
+///     fn key_id(&self) -> &str {
+///         "k1"
+///     }
+///     fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+///         Ok(plaintext.iter().rev().copied().collect())
+///     }
+///     fn decrypt(&self, key_id: &str, ciphertext: &[u8]) -> Result<Option<Vec<u8>>> {
+///         Ok((key_id == "k1").then(|| ciphertext.iter().rev().copied().collect()))
+///     }
+/// }
+///
+/// let cipher = FieldCipher::new(Reverse).encrypt("email");
+/// let mut doc = Document::new("user-1".to_string());
+/// doc.set_field("email".to_string(), json!("ada@example.com"), 1, "a".to_string());
+/// doc.set_field("name".to_string(), json!("Ada"), 2, "a".to_string());
+///
+/// let mut sealed = cipher.seal_document(&doc).unwrap();
+/// let email = sealed.get_field(&"email".to_string()).unwrap();
+/// assert_eq!(EncryptedValue::from_json(email).unwrap().key_id, "k1");
+/// assert_eq!(sealed.get_field(&"name".to_string()), Some(&json!("Ada")));
+///
+/// assert_eq!(cipher.open_document(&mut sealed).unwrap(), 0);
+/// assert_eq!(sealed.get_field(&"email".to_string()), Some(&json!("ada@example.com")));
+/// ```
+#[derive(Clone)]
+pub struct FieldCipher {
+    patterns: Vec<String>,
+    cipher: Arc<dyn Cipher + Send + Sync>,
+}
+
+impl fmt::Debug for FieldCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldCipher")
+            .field("patterns", &self.patterns)
+            .field("key_id", &self.cipher.key_id())
+            .finish()
+    }
+}
+
+impl FieldCipher {
+    /// Encrypt nothing yet, with `cipher`
+    pub fn new(cipher: impl Cipher + Send + Sync + 'static) -> Self {
+        Self {
+            patterns: Vec::new(),
+            cipher: Arc::new(cipher),
+        }
+    }
+
+    /// Also encrypt paths matching `pattern`
+    pub fn encrypt(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    /// Check if values at `path` are encrypted
+    pub fn covers(&self, path: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| matches_pattern(pattern, path))
+    }
+
+    /// Encrypt `value` into a placeholder (left alone if it already is one)
+    ///
+    /// # Errors
+    ///
+    /// Fails if the cipher does
+    pub fn seal_value(&self, value: &JsonValue) -> Result<JsonValue> {
+        if EncryptedValue::from_json(value).is_some() {
+            return Ok(value.clone());
+        }
+        let plaintext = serde_json::to_vec(value).map_err(SyncKitError::serialization)?;
+        let sealed = EncryptedValue {
+            key_id: self.cipher.key_id().to_string(),
+            ciphertext: self.cipher.encrypt(&plaintext)?,
+        };
+        Ok(sealed.to_json())
+    }
+
+    /// Decrypt a placeholder, `Ok(None)` if its key isn't available (a
+    /// value that isn't a placeholder is returned as is)
+    ///
+    /// # Errors
+    ///
+    /// Fails if the cipher does, or the plaintext isn't JSON
+    pub fn open_value(&self, value: &JsonValue) -> Result<Option<JsonValue>> {
+        let Some(sealed) = EncryptedValue::from_json(value) else {
+            return Ok(Some(value.clone()));
+        };
+        match self.cipher.decrypt(&sealed.key_id, &sealed.ciphertext)? {
+            Some(plaintext) => serde_json::from_slice(&plaintext)
+                .map(Some)
+                .map_err(SyncKitError::deserialization),
+            None => Ok(None),
+        }
+    }
+
+    /// Copy of `document` with the covered fields encrypted, for storage
+    ///
+    /// Timestamps and the version are kept, so the copy merges like the
+    /// original.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the cipher does
+    pub fn seal_document(&self, document: &Document) -> Result<Document> {
+        let mut sealed = document.clone();
+        self.seal_fields(&mut sealed.fields)?;
+        Ok(sealed)
+    }
+
+    /// Encrypt the covered fields of a dirty chunk, for the op log
+    ///
+    /// # Errors
+    ///
+    /// Fails if the cipher does
+    pub fn seal_dirty(&self, chunk: &mut DirtyState) -> Result<()> {
+        self.seal_fields(&mut chunk.fields)
+    }
+
+    /// Decrypt every placeholder of `document` whose key is available,
+    /// returning how many stay encrypted
+    ///
+    /// Fields keep their timestamps and the document isn't marked dirty:
+    /// this changes how values are held, not what they are.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the cipher does, or a plaintext isn't JSON
+    pub fn open_document(&self, document: &mut Document) -> Result<usize> {
+        let mut sealed = 0;
+        for field in document.fields.values_mut() {
+            if EncryptedValue::from_json(&field.value).is_none() {
+                continue;
+            }
+            match self.open_value(&field.value)? {
+                Some(value) => field.value = value,
+                None => sealed += 1,
+            }
+        }
+        Ok(sealed)
+    }
+
+    fn seal_fields(&self, fields: &mut HashMap<FieldPath, Field>) -> Result<()> {
+        for (path, field) in fields.iter_mut() {
+            if self.covers(path) {
+                field.value = self.seal_value(&field.value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// XOR with a one-byte key per key ID; only knows the keys it holds
+    struct XorCipher {
+        current: &'static str,
+        keys: Vec<(&'static str, u8)>,
+    }
+
+    impl XorCipher {
+        fn new(current: &'static str, keys: &[(&'static str, u8)]) -> Self {
+            Self {
+                current,
+                keys: keys.to_vec(),
+            }
+        }
+
+        fn key(&self, key_id: &str) -> Option<u8> {
+            self.keys
+                .iter()
+                .find(|(id, _)| *id == key_id)
+                .map(|(_, key)| *key)
+        }
+    }
+
+    impl Cipher for XorCipher {
+        fn key_id(&self) -> &str {
+            self.current
+        }
+
+        fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+            let key = self
+                .key(self.current)
+                .ok_or_else(|| SyncKitError::invalid_input("no current key"))?;
+            Ok(plaintext.iter().map(|byte| byte ^ key).collect())
+        }
+
+        fn decrypt(&self, key_id: &str, ciphertext: &[u8]) -> Result<Option<Vec<u8>>> {
+            Ok(self
+                .key(key_id)
+                .map(|key| ciphertext.iter().map(|byte| byte ^ key).collect()))
+        }
+    }
+
+    #[test]
+    fn test_placeholder_round_trips() {
+        let sealed = EncryptedValue {
+            key_id: "k1".to_string(),
+            ciphertext: vec![0, 15, 255],
+        };
+        let value = sealed.to_json();
+        assert_eq!(value[ENCRYPTED_MARKER]["ciphertext"], json!("000fff"));
+        assert_eq!(EncryptedValue::from_json(&value), Some(sealed));
+        assert_eq!(EncryptedValue::from_json(&json!({"key_id": "k1"})), None);
+        assert_eq!(EncryptedValue::from_json(&json!("000fff")), None);
+    }
+
+    #[test]
+    fn test_only_covered_paths_are_sealed_and_old_keys_still_open() {
+        let old = FieldCipher::new(XorCipher::new("k1", &[("k1", 7)])).encrypt("users.*.email");
+        let mut doc = Document::new("doc".to_string());
+        doc.set_field(
+            "users.ada.email".to_string(),
+            json!("ada@example.com"),
+            1,
+            "a".to_string(),
+        );
+        doc.set_field(
+            "users.ada.name".to_string(),
+            json!("Ada"),
+            2,
+            "a".to_string(),
+        );
+
+        let mut sealed = old.seal_document(&doc).unwrap();
+        let email = sealed.get_field(&"users.ada.email".to_string()).unwrap();
+        assert!(!email.to_string().contains("ada@"));
+        assert_eq!(
+            sealed.get_field(&"users.ada.name".to_string()),
+            Some(&json!("Ada"))
+        );
+        // Sealing twice doesn't encrypt the placeholder
+        assert_eq!(old.seal_document(&sealed).unwrap().fields, sealed.fields);
+
+        // After a rotation to k2, values under k1 still open
+        let rotated = FieldCipher::new(XorCipher::new("k2", &[("k1", 7), ("k2", 9)]))
+            .encrypt("users.*.email");
+        assert_eq!(rotated.open_document(&mut sealed).unwrap(), 0);
+        assert_eq!(sealed.fields, doc.fields);
+    }
+}
@@ -1,12 +1,149 @@
 //! Storage abstraction layer
 //!
-//! Phase 2 implementation:
-//! - Storage trait
-//! - In-memory storage (for testing)
+//! - [`Storage`]: the blob store a backend provides
+//! - [`MemoryStorage`]: in-memory backend (tests), with crash injection
+//! - [`PersistentDocument`]: a `Document` kept as a snapshot plus an op log,
+//!   compacted automatically by a [`CompactionPolicy`]
+//! - [`FieldCipher`]: encryption at rest for chosen fields, with a
+//!   caller-supplied [`Cipher`]
 //!
 //! Future:
 //! - IndexedDB adapter
 //! - OPFS adapter
 //! - SQLite adapter
 
-// Placeholder for Phase 2
+mod cipher;
+mod persistent;
+
+pub use cipher::{Cipher, EncryptedValue, FieldCipher, ENCRYPTED_MARKER};
+pub use persistent::{CompactionPolicy, PersistentDocument};
+
+use crate::error::SyncError;
+use std::collections::HashMap;
+
+/// A key-value blob store
+///
+/// Every mutating call must be durable when it returns `Ok`. `rename` must
+/// replace the target atomically: after a crash the target holds either
+/// its old or its new contents, never a mix.
+pub trait Storage {
+    /// Read a blob, `None` if it doesn't exist
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, SyncError>;
+
+    /// Create or overwrite a blob
+    fn write(&mut self, key: &str, bytes: &[u8]) -> Result<(), SyncError>;
+
+    /// Append to a blob, creating it if needed
+    fn append(&mut self, key: &str, bytes: &[u8]) -> Result<(), SyncError>;
+
+    /// Atomically move a blob over `to`, replacing it
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), SyncError>;
+
+    /// Delete a blob (no-op if it doesn't exist)
+    fn delete(&mut self, key: &str) -> Result<(), SyncError>;
+}
+
+/// In-memory `Storage`
+///
+/// `crash_after` simulates the process dying partway through a sequence of
+/// writes: the blobs keep whatever the completed writes left, and every
+/// later mutating call fails.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    blobs: HashMap<String, Vec<u8>>,
+    writes_left: Option<usize>,
+}
+
+impl MemoryStorage {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let `writes` more mutating calls succeed, then fail every one after
+    pub fn crash_after(&mut self, writes: usize) {
+        self.writes_left = Some(writes);
+    }
+
+    /// The blobs as they are on "disk", without the crash (a restart)
+    pub fn restarted(&self) -> Self {
+        Self {
+            blobs: self.blobs.clone(),
+            writes_left: None,
+        }
+    }
+
+    /// Keys of all stored blobs, sorted
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.blobs.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    fn begin_write(&mut self) -> Result<(), SyncError> {
+        match &mut self.writes_left {
+            Some(0) => Err(SyncError::StorageError("simulated crash".to_string())),
+            Some(left) => {
+                *left -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, SyncError> {
+        Ok(self.blobs.get(key).cloned())
+    }
+
+    fn write(&mut self, key: &str, bytes: &[u8]) -> Result<(), SyncError> {
+        self.begin_write()?;
+        self.blobs.insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn append(&mut self, key: &str, bytes: &[u8]) -> Result<(), SyncError> {
+        self.begin_write()?;
+        self.blobs
+            .entry(key.to_string())
+            .or_default()
+            .extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), SyncError> {
+        self.begin_write()?;
+        let blob = self
+            .blobs
+            .remove(from)
+            .ok_or_else(|| SyncError::StorageError(format!("no blob named {}", from)))?;
+        self.blobs.insert(to.to_string(), blob);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), SyncError> {
+        self.begin_write()?;
+        self.blobs.remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crash_keeps_completed_writes() {
+        let mut storage = MemoryStorage::new();
+        storage.crash_after(2);
+        storage.write("a", b"1").unwrap();
+        storage.append("a", b"2").unwrap();
+        assert!(storage.write("b", b"3").is_err());
+        assert!(storage.delete("a").is_err());
+
+        let restarted = storage.restarted();
+        assert_eq!(restarted.read("a").unwrap(), Some(b"12".to_vec()));
+        assert_eq!(restarted.keys(), vec!["a"]);
+    }
+}
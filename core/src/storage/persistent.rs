@@ -0,0 +1,717 @@
+//! PersistentDocument: snapshot plus op log, with automatic compaction
+//!
+//! Each `persist` appends the document's dirty chunk (`take_dirty`) to an
+//! op log. When the `CompactionPolicy` says so, the whole document is
+//! written as a fresh snapshot and the log is truncated.
+//!
+//! # Crash safety
+//!
+//! Compaction writes the new snapshot under a temporary key, renames it
+//! over the old one, and only then deletes the log. The old snapshot is
+//! never touched before the new one is durable. Snapshots and log lines
+//! carry a generation number, so a log left behind by a crash between the
+//! rename and the delete is recognised as stale and skipped on recovery.
+//!
+//! `Document` keeps no tombstones (deleted fields are removed outright), so
+//! a snapshot has nothing to garbage-collect.
+//!
+//! # Undo history
+//!
+//! With `enable_undo`, edits made through `document_mut_with_undo` can be
+//! undone after a restart: `persist` saves the `UndoManager` next to the
+//! log whenever it changed, and `open` restores it.
+//!
+//! # Encryption
+//!
+//! Opened with `open_with_cipher`, the fields a [`FieldCipher`] covers are
+//! encrypted in every snapshot and log line, and decrypted on load where
+//! the key is available (see [`super::cipher`]).
+
+use super::{FieldCipher, Storage};
+use crate::awareness::TimeSource;
+use crate::document::DirtyState;
+use crate::error::{Result, ResultExt, SyncError};
+use crate::time::TimeProvider;
+use crate::undo::UndoManager;
+use crate::{ClientID, Document, DocumentID};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+/// When `PersistentDocument` compacts its op log into a snapshot
+///
+/// Compaction runs when either size threshold is reached and at least
+/// `min_interval` has passed since the last one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionPolicy {
+    /// Field writes and deletions logged since the last snapshot
+    pub max_ops_since_snapshot: usize,
+
+    /// Size of the op log in bytes
+    pub max_log_bytes: usize,
+
+    /// Minimum time between two compactions
+    pub min_interval: Duration,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            max_ops_since_snapshot: 1000,
+            max_log_bytes: 1024 * 1024,
+            min_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    generation: u64,
+    document: Document,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LogEntry {
+    generation: u64,
+    chunk: DirtyState,
+}
+
+/// A `Document` persisted to a `Storage` as a snapshot plus an op log
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::storage::{CompactionPolicy, MemoryStorage, PersistentDocument};
+///
+/// let storage = MemoryStorage::new();
+/// let mut doc =
+///     PersistentDocument::open("doc-1".to_string(), storage, CompactionPolicy::default()).unwrap();
+/// doc.document_mut()
+///     .set_field("title".to_string(), serde_json::json!("Hi"), 1, "alice".to_string());
+/// doc.persist().unwrap();
+///
+/// let reopened = PersistentDocument::open(
+///     "doc-1".to_string(),
+///     doc.storage().clone(),
+///     CompactionPolicy::default(),
+/// )
+/// .unwrap();
+/// assert_eq!(reopened.document().fields(), doc.document().fields());
+/// ```
+#[derive(Debug)]
+pub struct PersistentDocument<S: Storage> {
+    document: Document,
+    storage: S,
+    policy: CompactionPolicy,
+    generation: u64,
+    ops_since_snapshot: usize,
+    log_bytes: usize,
+    last_compaction_ms: Option<u64>,
+    time_source: Option<TimeSource>,
+    #[cfg(not(target_arch = "wasm32"))]
+    started: Instant,
+    undo: Option<UndoManager>,
+    /// The undo history as last saved
+    saved_undo: Option<Vec<u8>>,
+    cipher: Option<FieldCipher>,
+}
+
+impl<S: Storage> PersistentDocument<S> {
+    /// Load a document from `storage`, or start an empty one
+    ///
+    /// Recovers from a crash at any point of a compaction: a leftover
+    /// temporary snapshot is discarded, stale log entries are skipped, and
+    /// a torn last log line is cut off.
+    ///
+    /// A saved undo history is restored without the steps that no longer
+    /// apply; one that can't be read is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the storage can't be read or the snapshot is corrupt
+    pub fn open(id: DocumentID, storage: S, policy: CompactionPolicy) -> Result<Self> {
+        Self::load(id, storage, policy, None)
+    }
+
+    /// Like `open`, encrypting the fields `cipher` covers on storage
+    ///
+    /// Stored values are decrypted where the cipher has their key; the
+    /// others stay [`EncryptedValue`](super::EncryptedValue) placeholders.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the storage can't be read, the snapshot is corrupt or the
+    /// cipher fails
+    pub fn open_with_cipher(
+        id: DocumentID,
+        storage: S,
+        policy: CompactionPolicy,
+        cipher: FieldCipher,
+    ) -> Result<Self> {
+        Self::load(id, storage, policy, Some(cipher))
+    }
+
+    fn load(
+        id: DocumentID,
+        mut storage: S,
+        policy: CompactionPolicy,
+        cipher: Option<FieldCipher>,
+    ) -> Result<Self> {
+        let (mut document, generation) = match storage.read(&snapshot_key(&id))? {
+            Some(bytes) => {
+                let snapshot: Snapshot = serde_json::from_slice(&bytes)
+                    .map_err(|e| SyncError::DeserializationError(e.to_string()))
+                    .with_document(id.as_str())?;
+                (snapshot.document, snapshot.generation)
+            }
+            None => (Document::new(id.clone()), 0),
+        };
+
+        if storage.read(&temp_key(&id))?.is_some() {
+            storage.delete(&temp_key(&id))?;
+        }
+
+        let mut ops_since_snapshot = 0;
+        let mut log = storage.read(&log_key(&id))?.unwrap_or_default();
+        let mut valid_len = 0;
+        for line in log.split_inclusive(|byte| *byte == b'\n') {
+            // A crash mid-append leaves a torn last line
+            let entry = match serde_json::from_slice::<LogEntry>(line) {
+                Ok(entry) if line.ends_with(b"\n") => entry,
+                _ => break,
+            };
+            valid_len += line.len();
+            if entry.generation == generation {
+                ops_since_snapshot += chunk_ops(&entry.chunk);
+                document.apply_dirty(&entry.chunk);
+            }
+        }
+        if valid_len < log.len() {
+            // Cut it off so later appends start on a fresh line
+            log.truncate(valid_len);
+            storage.write(&log_key(&id), &log)?;
+        }
+        if let Some(cipher) = &cipher {
+            cipher
+                .open_document(&mut document)
+                .with_document(id.as_str())?;
+        }
+
+        let saved_undo = storage.read(&undo_key(&id))?;
+        let undo = saved_undo.as_ref().and_then(|bytes| {
+            UndoManager::from_bytes(bytes, &mut document)
+                .inspect_err(|_error| {
+                    trace_debug!(document_id = %id, error = %_error, "dropped unreadable undo history");
+                })
+                .ok()
+        });
+
+        Ok(Self {
+            document,
+            storage,
+            policy,
+            generation,
+            ops_since_snapshot,
+            log_bytes: log.len(),
+            last_compaction_ms: None,
+            time_source: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            started: Instant::now(),
+            undo,
+            saved_undo,
+            cipher,
+        })
+    }
+
+    /// Measure `min_interval` with `now_ms` (current time in milliseconds)
+    /// instead of `Instant`
+    ///
+    /// Without a time source on WASM, `min_interval` is not enforced.
+    pub fn set_time_source(&mut self, now_ms: impl TimeProvider + Send + 'static) {
+        self.time_source = Some(TimeSource::new(now_ms));
+    }
+
+    /// The document
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// The document, for edits (call `persist` afterwards)
+    pub fn document_mut(&mut self) -> &mut Document {
+        &mut self.document
+    }
+
+    /// Record undo history for edits made through
+    /// `document_mut_with_undo`, as `client_id`
+    ///
+    /// Keeps the history `open` restored, if there was one.
+    pub fn enable_undo(&mut self, client_id: ClientID) {
+        if self.undo.is_none() {
+            self.undo = Some(UndoManager::new(client_id));
+        }
+    }
+
+    /// The undo history (None until `enable_undo` or a restore)
+    pub fn undo_manager(&self) -> Option<&UndoManager> {
+        self.undo.as_ref()
+    }
+
+    /// The document and its undo history, for undoable edits (call
+    /// `persist` afterwards)
+    pub fn document_mut_with_undo(&mut self) -> Option<(&mut Document, &mut UndoManager)> {
+        let undo = self.undo.as_mut()?;
+        Some((&mut self.document, undo))
+    }
+
+    /// Revert the last recorded edit (call `persist` afterwards)
+    ///
+    /// Returns false if there is nothing to undo or undo is not enabled.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the step can't be reverted
+    pub fn undo(&mut self) -> Result<bool> {
+        match self.undo.as_mut() {
+            Some(undo) => undo
+                .undo(&mut self.document)
+                .with_document(self.document.id().as_str()),
+            None => Ok(false),
+        }
+    }
+
+    /// Reapply the last undone edit (call `persist` afterwards)
+    ///
+    /// Returns false if there is nothing to redo or undo is not enabled.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the step can't be reverted
+    pub fn redo(&mut self) -> Result<bool> {
+        match self.undo.as_mut() {
+            Some(undo) => undo
+                .redo(&mut self.document)
+                .with_document(self.document.id().as_str()),
+            None => Ok(false),
+        }
+    }
+
+    /// The underlying storage
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// The underlying storage, mutably
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// The policy in use
+    pub fn policy(&self) -> &CompactionPolicy {
+        &self.policy
+    }
+
+    /// The field cipher, if opened with one
+    pub fn cipher(&self) -> Option<&FieldCipher> {
+        self.cipher.as_ref()
+    }
+
+    /// Field writes and deletions logged since the last snapshot
+    pub fn ops_since_snapshot(&self) -> usize {
+        self.ops_since_snapshot
+    }
+
+    /// Current size of the op log in bytes
+    pub fn log_bytes(&self) -> usize {
+        self.log_bytes
+    }
+
+    /// Append unpersisted changes to the op log, then compact if the
+    /// policy says so
+    ///
+    /// Returns true if a compaction ran. If the append fails the changes
+    /// stay dirty and are retried by the next call. The undo history is
+    /// saved too if it changed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the storage rejects a write
+    pub fn persist(&mut self) -> Result<bool> {
+        if self.document.is_dirty() {
+            let id = self.document.id().clone();
+            let mut entry = LogEntry {
+                generation: self.generation,
+                chunk: self.document.take_dirty(),
+            };
+            let appended = self.log_line(&mut entry).and_then(|line| {
+                self.storage.append(&log_key(&id), &line)?;
+                Ok(line.len())
+            });
+            match appended {
+                Ok(bytes) => {
+                    self.ops_since_snapshot += chunk_ops(&entry.chunk);
+                    self.log_bytes += bytes;
+                }
+                Err(error) => {
+                    // Hand the chunk back to the dirty tracker
+                    for path in entry.chunk.fields.keys().chain(&entry.chunk.deleted) {
+                        self.document.mark_dirty(path);
+                    }
+                    self.document.mark_version_dirty();
+                    return Err(error).with_document(id);
+                }
+            }
+        }
+        self.save_undo()?;
+
+        if self.should_compact() {
+            self.compact()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Check if the policy calls for a compaction now
+    pub fn should_compact(&self) -> bool {
+        let over_threshold = self.ops_since_snapshot >= self.policy.max_ops_since_snapshot
+            || self.log_bytes >= self.policy.max_log_bytes;
+        if !over_threshold {
+            return false;
+        }
+        match (self.last_compaction_ms, self.now_ms()) {
+            (Some(last), Some(now)) => {
+                now.saturating_sub(last) >= self.policy.min_interval.as_millis() as u64
+            }
+            _ => true,
+        }
+    }
+
+    /// Write a fresh snapshot and truncate the op log, regardless of the
+    /// policy
+    ///
+    /// Unpersisted changes go into the snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the storage rejects a write. The data on storage stays
+    /// recoverable by `open` whichever step failed.
+    pub fn compact(&mut self) -> Result<()> {
+        let id = self.document.id().clone();
+        let document = match &self.cipher {
+            Some(cipher) => cipher
+                .seal_document(&self.document)
+                .with_document(id.as_str())?,
+            None => self.document.clone(),
+        };
+        let bytes = serde_json::to_vec(&Snapshot {
+            generation: self.generation + 1,
+            document,
+        })
+        .map_err(|e| SyncError::SerializationError(e.to_string()))
+        .with_document(id.as_str())?;
+
+        // 1. New snapshot under a temporary key
+        self.storage
+            .write(&temp_key(&id), &bytes)
+            .with_document(id.as_str())?;
+
+        // 2. Swap it in; from here on the old log is stale
+        self.storage
+            .rename(&temp_key(&id), &snapshot_key(&id))
+            .with_document(id.as_str())?;
+        self.generation += 1;
+        self.ops_since_snapshot = 0;
+        self.document.take_dirty();
+        self.last_compaction_ms = self.now_ms();
+
+        // 3. Truncate the log
+        self.storage
+            .delete(&log_key(&id))
+            .with_document(id.as_str())?;
+        self.log_bytes = 0;
+
+        trace_debug!(document_id = %id, generation = self.generation, "compacted op log");
+        Ok(())
+    }
+
+    /// Encode a log entry as one line, encrypting what the cipher covers
+    fn log_line(&self, entry: &mut LogEntry) -> Result<Vec<u8>> {
+        if let Some(cipher) = &self.cipher {
+            cipher.seal_dirty(&mut entry.chunk)?;
+        }
+        let mut line =
+            serde_json::to_vec(entry).map_err(|e| SyncError::SerializationError(e.to_string()))?;
+        line.push(b'\n');
+        Ok(line)
+    }
+
+    /// Write the undo history if it changed since it was last saved
+    fn save_undo(&mut self) -> Result<()> {
+        let Some(undo) = &self.undo else {
+            return Ok(());
+        };
+        let id = self.document.id().as_str();
+        let bytes = undo.to_bytes().with_document(id)?;
+        if self.saved_undo.as_ref() != Some(&bytes) {
+            self.storage
+                .write(&undo_key(id), &bytes)
+                .with_document(id)?;
+            self.saved_undo = Some(bytes);
+        }
+        Ok(())
+    }
+
+    /// Current time in milliseconds (None on WASM without a time source)
+    fn now_ms(&self) -> Option<u64> {
+        if let Some(time_source) = &self.time_source {
+            return Some(time_source.now_ms());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        return Some(self.started.elapsed().as_millis() as u64);
+        #[cfg(target_arch = "wasm32")]
+        None
+    }
+}
+
+fn snapshot_key(id: &str) -> String {
+    format!("{}.snapshot", id)
+}
+
+fn temp_key(id: &str) -> String {
+    format!("{}.snapshot.tmp", id)
+}
+
+fn log_key(id: &str) -> String {
+    format!("{}.log", id)
+}
+
+fn undo_key(id: &str) -> String {
+    format!("{}.undo", id)
+}
+
+fn chunk_ops(chunk: &DirtyState) -> usize {
+    chunk.fields.len() + chunk.deleted.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::time::MockTime;
+    use serde_json::json;
+
+    fn policy(max_ops: usize) -> CompactionPolicy {
+        CompactionPolicy {
+            max_ops_since_snapshot: max_ops,
+            max_log_bytes: usize::MAX,
+            min_interval: Duration::ZERO,
+        }
+    }
+
+    fn open(storage: MemoryStorage, policy: CompactionPolicy) -> PersistentDocument<MemoryStorage> {
+        PersistentDocument::open("doc".to_string(), storage, policy).unwrap()
+    }
+
+    fn write(doc: &mut PersistentDocument<MemoryStorage>, field: &str, clock: u64) {
+        doc.document_mut()
+            .set_field(field.to_string(), json!(clock), clock, "alice".to_string());
+        doc.document_mut().version.update("alice", clock);
+    }
+
+    #[test]
+    fn test_reopen_replays_log() {
+        let mut doc = open(MemoryStorage::new(), policy(100));
+        write(&mut doc, "a", 1);
+        doc.persist().unwrap();
+        write(&mut doc, "b", 2);
+        doc.document_mut().delete_field(&"a".to_string());
+        doc.persist().unwrap();
+        assert_eq!(doc.ops_since_snapshot(), 3);
+
+        let reopened = open(doc.storage().restarted(), policy(100));
+        assert_eq!(reopened.document().fields(), doc.document().fields());
+        assert_eq!(reopened.document().version(), doc.document().version());
+        assert_eq!(reopened.ops_since_snapshot(), 3);
+        assert!(!reopened.document().is_dirty());
+    }
+
+    #[test]
+    fn test_compacts_at_op_threshold() {
+        let mut doc = open(MemoryStorage::new(), policy(3));
+        for clock in 1..=2 {
+            write(&mut doc, &format!("f{}", clock), clock);
+            assert!(!doc.persist().unwrap());
+        }
+        write(&mut doc, "f3", 3);
+        assert!(doc.persist().unwrap());
+        assert_eq!(doc.ops_since_snapshot(), 0);
+        assert_eq!(doc.storage().keys(), vec!["doc.snapshot"]);
+
+        let reopened = open(doc.storage().restarted(), policy(3));
+        assert_eq!(reopened.document().fields(), doc.document().fields());
+    }
+
+    #[test]
+    fn test_compacts_at_byte_threshold() {
+        let mut doc = open(
+            MemoryStorage::new(),
+            CompactionPolicy {
+                max_ops_since_snapshot: usize::MAX,
+                max_log_bytes: 200,
+                min_interval: Duration::ZERO,
+            },
+        );
+        let mut compactions = 0;
+        for clock in 1..=10 {
+            write(&mut doc, "f", clock);
+            if doc.persist().unwrap() {
+                compactions += 1;
+            }
+            assert!(doc.log_bytes() < 200);
+        }
+        assert!(compactions > 0);
+    }
+
+    #[test]
+    fn test_min_interval_uses_injected_clock() {
+        let now = MockTime::new(0);
+        let mut doc = open(
+            MemoryStorage::new(),
+            CompactionPolicy {
+                min_interval: Duration::from_secs(10),
+                ..policy(1)
+            },
+        );
+        doc.set_time_source(now.clone());
+
+        write(&mut doc, "a", 1);
+        assert!(doc.persist().unwrap());
+
+        now.set(9_999);
+        write(&mut doc, "b", 2);
+        assert!(!doc.persist().unwrap());
+        assert_eq!(doc.ops_since_snapshot(), 1);
+
+        now.set(10_000);
+        write(&mut doc, "c", 3);
+        assert!(doc.persist().unwrap());
+    }
+
+    #[test]
+    fn test_recovers_from_crash_at_every_compaction_step() {
+        // Write tmp snapshot, rename, delete log: crash before each one,
+        // and also with all three done
+        for crash_after in 0..=3 {
+            let mut doc = open(MemoryStorage::new(), policy(usize::MAX));
+            write(&mut doc, "a", 1);
+            doc.persist().unwrap();
+            doc.compact().unwrap();
+            write(&mut doc, "b", 2);
+            doc.document_mut().delete_field(&"a".to_string());
+            doc.persist().unwrap();
+            write(&mut doc, "c", 3);
+            doc.persist().unwrap();
+            let expected = doc.document().clone();
+
+            doc.storage_mut().crash_after(crash_after);
+            assert_eq!(doc.compact().is_ok(), crash_after == 3);
+
+            let mut recovered = open(doc.storage().restarted(), policy(usize::MAX));
+            assert_eq!(
+                recovered.document().fields(),
+                expected.fields(),
+                "crash after {} writes",
+                crash_after
+            );
+            assert_eq!(recovered.document().version(), expected.version());
+            assert!(!recovered.storage().keys().contains(&"doc.snapshot.tmp"));
+
+            // Writing on after recovery is recovered as well
+            write(&mut recovered, "d", 4);
+            recovered.persist().unwrap();
+            let reopened = open(recovered.storage().restarted(), policy(usize::MAX));
+            assert_eq!(
+                reopened.document().fields(),
+                recovered.document().fields(),
+                "crash after {} writes",
+                crash_after
+            );
+        }
+    }
+
+    #[test]
+    fn test_failed_append_stays_dirty() {
+        let mut doc = open(MemoryStorage::new(), policy(100));
+        write(&mut doc, "a", 1);
+        doc.storage_mut().crash_after(0);
+        assert!(doc.persist().is_err());
+        assert!(doc.document().is_dirty());
+
+        let mut restarted = PersistentDocument {
+            storage: doc.storage().restarted(),
+            ..doc
+        };
+        restarted.persist().unwrap();
+        let reopened = open(restarted.storage().restarted(), policy(100));
+        assert_eq!(
+            reopened.document().get_field(&"a".to_string()),
+            Some(&json!(1))
+        );
+    }
+
+    #[test]
+    fn test_ignores_torn_log_line() {
+        let mut doc = open(MemoryStorage::new(), policy(100));
+        write(&mut doc, "a", 1);
+        doc.persist().unwrap();
+        let mut storage = doc.storage().restarted();
+        storage
+            .append("doc.log", b"{\"generation\":0,\"chu")
+            .unwrap();
+
+        let mut reopened = open(storage, policy(100));
+        assert_eq!(reopened.document().fields(), doc.document().fields());
+
+        write(&mut reopened, "b", 2);
+        reopened.persist().unwrap();
+        let reopened_again = open(reopened.storage().restarted(), policy(100));
+        assert_eq!(reopened_again.document().field_count(), 2);
+    }
+
+    #[test]
+    fn test_undo_history_survives_reopen() {
+        let mut doc = open(MemoryStorage::new(), policy(2));
+        doc.enable_undo("alice".to_string());
+        for value in ["a", "b", "c"] {
+            let (document, undo) = doc.document_mut_with_undo().unwrap();
+            undo.set_field(document, "title".to_string(), json!(value));
+            doc.persist().unwrap();
+        }
+
+        let mut reopened = open(doc.storage().restarted(), policy(2));
+        assert_eq!(reopened.undo_manager(), doc.undo_manager());
+        assert!(reopened.undo().unwrap());
+        assert!(reopened.undo().unwrap());
+        reopened.persist().unwrap();
+
+        let mut again = open(reopened.storage().restarted(), policy(2));
+        assert_eq!(
+            again.document().get_field(&"title".to_string()),
+            Some(&json!("a"))
+        );
+        assert!(again.redo().unwrap());
+        assert_eq!(
+            again.document().get_field(&"title".to_string()),
+            Some(&json!("b"))
+        );
+    }
+
+    #[test]
+    fn test_unreadable_undo_history_is_dropped() {
+        let mut storage = MemoryStorage::new();
+        storage.write("doc.undo", b"not json").unwrap();
+        let doc = open(storage, policy(100));
+        assert!(doc.undo_manager().is_none());
+    }
+}
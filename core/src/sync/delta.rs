@@ -73,6 +73,14 @@ impl Delta {
 /// let delta = compute_delta(&old, &new);
 /// assert_eq!(delta.len(), 1); // Only "title" field changed
 /// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "compute_delta",
+        skip_all,
+        fields(document_id = %new.id, changes = tracing::field::Empty)
+    )
+)]
 pub fn compute_delta(old: &Document, new: &Document) -> Delta {
     let mut changed_fields = HashMap::new();
 
@@ -95,6 +103,7 @@ pub fn compute_delta(old: &Document, new: &Document) -> Delta {
     // Note: Deleted fields would be represented as tombstones in a full implementation
     // For now, we only track additions and modifications
 
+    trace_record!("changes", changed_fields.len());
     Delta::new(new.id.clone(), changed_fields, new.version.clone())
 }
 
@@ -109,38 +118,56 @@ pub fn compute_delta(old: &Document, new: &Document) -> Delta {
 /// let delta = Delta { /* ... */ };
 /// apply_delta(&mut doc, &delta);
 /// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "apply_delta",
+        skip_all,
+        fields(document_id = %delta.document_id, changes = delta.fields.len())
+    )
+)]
 pub fn apply_delta(doc: &mut Document, delta: &Delta) {
     // Verify we're applying to the correct document
     assert_eq!(doc.id, delta.document_id, "Delta document ID mismatch");
 
-    // Apply each changed field using LWW merge
-    for (field_path, delta_field) in &delta.fields {
-        match doc.fields.get(field_path) {
-            Some(local_field) => {
-                // Field exists locally - use LWW merge
-                match delta_field.timestamp.cmp(&local_field.timestamp) {
-                    std::cmp::Ordering::Greater => {
-                        doc.fields.insert(field_path.clone(), delta_field.clone());
-                    }
-                    std::cmp::Ordering::Equal => {
-                        // Tie-breaking: use client_id comparison
-                        if delta_field.timestamp.client_id > local_field.timestamp.client_id {
+    // Apply each changed field using LWW merge, as one notification batch
+    doc.transaction(|doc| {
+        for (field_path, delta_field) in &delta.fields {
+            doc.record_conflict(field_path, delta_field);
+            match doc.fields.get(field_path) {
+                Some(local_field) => {
+                    // Field exists locally - use LWW merge
+                    match delta_field.timestamp.cmp(&local_field.timestamp) {
+                        std::cmp::Ordering::Greater => {
                             doc.fields.insert(field_path.clone(), delta_field.clone());
+                            doc.mark_dirty(field_path);
                         }
+                        std::cmp::Ordering::Equal => {
+                            // Tie-breaking: use client_id comparison
+                            if delta_field.timestamp.client_id > local_field.timestamp.client_id {
+                                doc.fields.insert(field_path.clone(), delta_field.clone());
+                                doc.mark_dirty(field_path);
+                            }
+                        }
+                        std::cmp::Ordering::Less => {} // local is newer, keep local
                     }
-                    std::cmp::Ordering::Less => {} // local is newer, keep local
+                    // else: local is newer, keep local
+                }
+                None => {
+                    // New field - insert it
+                    doc.fields.insert(field_path.clone(), delta_field.clone());
+                    doc.mark_dirty(field_path);
                 }
-                // else: local is newer, keep local
-            }
-            None => {
-                // New field - insert it
-                doc.fields.insert(field_path.clone(), delta_field.clone());
             }
         }
-    }
+    });
 
     // Merge vector clocks
+    let before = doc.version.clone();
     doc.version.merge(&delta.version);
+    if doc.version != before {
+        doc.mark_version_dirty();
+    }
 }
 
 /// Merge two deltas into a single delta
@@ -0,0 +1,151 @@
+//! Field-level selective sync
+//!
+//! A `SyncFilter` decides which field paths of a document take part in
+//! sync. Fields it rejects (draft buffers, UI state) stay on this device:
+//! they are left out of outgoing deltas and snapshots, and incoming
+//! changes to them are refused.
+//!
+//! Filtering never touches version vectors. A peer that receives our
+//! version has seen everything we are willing to send for it, so
+//! comparing versions must not make it think it is missing the writes we
+//! held back.
+
+use crate::document::Document;
+use serde::{Deserialize, Serialize};
+
+/// Include/exclude path patterns for one document
+///
+/// Patterns are matched segment by segment on dot-separated paths. `*`
+/// matches any one segment, and a pattern that matches the start of a
+/// path matches the whole subtree below it: `_local.*` covers
+/// `_local.draft` and `_local.draft.body`, but not `_local` itself.
+///
+/// A path syncs if it matches some include pattern (or there are none)
+/// and no exclude pattern.
+///
+/// # Example
+///
+/// ```rust
+/// use synckit_core::sync::SyncFilter;
+///
+/// let filter = SyncFilter::new().exclude("_local.*");
+/// assert!(filter.allows("title"));
+/// assert!(!filter.allows("_local.draft"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl SyncFilter {
+    /// Create a filter that lets every path through
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only sync paths matching `pattern` (or another include pattern)
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Never sync paths matching `pattern`
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Check if the filter lets every path through
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Check if `path` takes part in sync
+    pub fn allows(&self, path: &str) -> bool {
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| matches_pattern(pattern, path));
+        included
+            && !self
+                .exclude
+                .iter()
+                .any(|pattern| matches_pattern(pattern, path))
+    }
+
+    /// Copy of `document` without the fields this filter rejects, for
+    /// sending as a snapshot
+    ///
+    /// The version is kept as is (see the module docs).
+    pub fn filter_document(&self, document: &Document) -> Document {
+        let mut filtered = Document::new(document.id().clone());
+        filtered.fields = document
+            .fields()
+            .iter()
+            .filter(|(path, _)| self.allows(path))
+            .map(|(path, field)| (path.clone(), field.clone()))
+            .collect();
+        filtered.version = document.version().clone();
+        filtered
+    }
+}
+
+/// Check if `pattern` matches `path` or one of its ancestors
+pub(crate) fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let mut path_segments = path.split('.');
+    pattern.split('.').all(|pattern_segment| {
+        path_segments
+            .next()
+            .is_some_and(|segment| pattern_segment == "*" || pattern_segment == segment)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_patterns_match_subtrees() {
+        assert!(matches_pattern("_local", "_local"));
+        assert!(matches_pattern("_local", "_local.draft.body"));
+        assert!(matches_pattern("_local.*", "_local.draft"));
+        assert!(!matches_pattern("_local.*", "_local"));
+        assert!(!matches_pattern("_local", "_localized"));
+        assert!(matches_pattern("users.*.cursor", "users.alice.cursor.line"));
+        assert!(!matches_pattern("users.*.cursor", "users.alice.name"));
+    }
+
+    #[test]
+    fn test_include_then_exclude() {
+        let filter = SyncFilter::new()
+            .include("settings")
+            .include("title")
+            .exclude("settings.theme");
+        assert!(filter.allows("title"));
+        assert!(filter.allows("settings.font"));
+        assert!(!filter.allows("settings.theme"));
+        assert!(!filter.allows("body"));
+        assert!(SyncFilter::new().allows("anything"));
+    }
+
+    #[test]
+    fn test_filter_document_keeps_version() {
+        let mut doc = Document::new("doc".to_string());
+        doc.set_field("title".to_string(), json!("Hi"), 1, "alice".to_string());
+        doc.set_field(
+            "_local.draft".to_string(),
+            json!("..."),
+            2,
+            "alice".to_string(),
+        );
+        doc.version.update("alice", 2);
+
+        let filtered = SyncFilter::new().exclude("_local").filter_document(&doc);
+        assert_eq!(filtered.field_count(), 1);
+        assert_eq!(filtered.get_field(&"title".to_string()), Some(&json!("Hi")));
+        assert_eq!(filtered.version(), doc.version());
+    }
+}
@@ -10,6 +10,7 @@ use serde_json::Value as JsonValue;
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LWWField {
     /// The actual field value (JSON-like)
+    #[serde(with = "crate::codec::json_value")]
     pub value: JsonValue,
 
     /// Timestamp for conflict resolution
@@ -30,15 +31,15 @@ impl LWWField {
     /// - If equal timestamps, use deterministic tie-breaking via client_id
     pub fn merge(&self, other: &LWWField) -> LWWField {
         match self.timestamp.compare_lww(&other.timestamp) {
-            std::cmp::Ordering::Less => {
+            core::cmp::Ordering::Less => {
                 // Remote is newer - use it
                 other.clone()
             }
-            std::cmp::Ordering::Greater => {
+            core::cmp::Ordering::Greater => {
                 // Local is newer - keep it
                 self.clone()
             }
-            std::cmp::Ordering::Equal => {
+            core::cmp::Ordering::Equal => {
                 // Equal timestamps - already handled by compare_lww via client_id
                 self.clone()
             }
@@ -5,12 +5,19 @@
 //! - Timestamps for LWW conflict resolution
 //! - LWW merge algorithm
 //! - Delta computation
+//! - Field-level sync filters
 
+#[cfg(feature = "std")]
 pub mod delta;
+#[cfg(feature = "std")]
+pub mod filter;
 pub mod lww;
 pub mod vector_clock;
 
+#[cfg(feature = "std")]
 pub use delta::{apply_delta, compute_delta, merge_deltas, Delta};
+#[cfg(feature = "std")]
+pub use filter::SyncFilter;
 pub use lww::LWWField;
 pub use vector_clock::VectorClock;
 
@@ -43,9 +50,9 @@ impl Timestamp {
     /// - Ordering::Greater if self is more recent
     /// - Ordering::Less if other is more recent
     /// - Ordering::Equal if timestamps are identical (same clock and client)
-    pub fn compare_lww(&self, other: &Timestamp) -> std::cmp::Ordering {
+    pub fn compare_lww(&self, other: &Timestamp) -> core::cmp::Ordering {
         match self.clock.cmp(&other.clock) {
-            std::cmp::Ordering::Equal => {
+            core::cmp::Ordering::Equal => {
                 // Tie-breaking by client ID (deterministic)
                 self.client_id.cmp(&other.client_id)
             }
@@ -55,7 +62,40 @@ impl Timestamp {
 
     /// Check if this timestamp is more recent than another (for LWW)
     pub fn is_newer_than(&self, other: &Timestamp) -> bool {
-        self.compare_lww(other) == std::cmp::Ordering::Greater
+        self.compare_lww(other) == core::cmp::Ordering::Greater
+    }
+}
+
+/// Where an applied change came from, for change notifications
+///
+/// A client's own delta comes back to it in the server's broadcast.
+/// Re-applying it changes nothing, so it is tagged `Echo` and the UI can
+/// skip the redundant re-render.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeOrigin {
+    /// Authored by this client and changed the local state
+    Local,
+    /// Authored by another client
+    #[default]
+    Remote,
+    /// Authored by this client and re-applied with no observable effect
+    Echo,
+}
+
+impl ChangeOrigin {
+    /// Tag a change authored by `author` that `local_client` just applied
+    pub fn classify(author: &str, local_client: &str, changed: bool) -> Self {
+        match (author == local_client, changed) {
+            (false, _) => ChangeOrigin::Remote,
+            (true, true) => ChangeOrigin::Local,
+            (true, false) => ChangeOrigin::Echo,
+        }
+    }
+
+    /// Check if this is an echo of one of our own changes
+    pub fn is_echo(self) -> bool {
+        self == ChangeOrigin::Echo
     }
 }
 
@@ -93,4 +133,15 @@ mod tests {
         assert!(!ts1.is_newer_than(&ts2));
         assert!(!ts2.is_newer_than(&ts1));
     }
+
+    #[test]
+    fn test_change_origin_classification() {
+        assert_eq!(ChangeOrigin::classify("a", "b", true), ChangeOrigin::Remote);
+        assert_eq!(
+            ChangeOrigin::classify("a", "b", false),
+            ChangeOrigin::Remote
+        );
+        assert_eq!(ChangeOrigin::classify("a", "a", true), ChangeOrigin::Local);
+        assert!(ChangeOrigin::classify("a", "a", false).is_echo());
+    }
 }
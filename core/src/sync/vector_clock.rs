@@ -10,10 +10,10 @@
 //! - ConcurrentDetection: Concurrent operations detected correctly
 //! - MergeCorrectness: Clock merging preserves causality
 
+use crate::collections::{HashMap, HashSet};
 use crate::ClientID;
+use core::cmp::Ordering;
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
-use std::collections::HashMap;
 
 /// Vector clock for tracking causality between operations
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -46,13 +46,13 @@ impl VectorClock {
     }
 
     /// Get the clock value for a specific client
-    pub fn get(&self, client_id: &ClientID) -> u64 {
+    pub fn get(&self, client_id: &str) -> u64 {
         *self.clocks.get(client_id).unwrap_or(&0)
     }
 
     /// Update the clock for a specific client to a specific value
-    pub fn update(&mut self, client_id: &ClientID, value: u64) {
-        self.clocks.insert(client_id.clone(), value);
+    pub fn update(&mut self, client_id: &str, value: u64) {
+        self.clocks.insert(client_id.into(), value);
     }
 
     /// Get all client clocks
@@ -85,17 +85,16 @@ impl VectorClock {
         let mut greater = false;
 
         // Get all unique client IDs from both clocks
-        let all_clients: std::collections::HashSet<_> =
-            self.clocks.keys().chain(other.clocks.keys()).collect();
+        let all_clients: HashSet<_> = self.clocks.keys().chain(other.clocks.keys()).collect();
 
         for client_id in all_clients {
             let self_clock = self.get(client_id);
             let other_clock = other.get(client_id);
 
             match self_clock.cmp(&other_clock) {
-                std::cmp::Ordering::Less => less = true,
-                std::cmp::Ordering::Greater => greater = true,
-                std::cmp::Ordering::Equal => {}
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => {}
             }
         }
 
@@ -112,17 +111,16 @@ impl VectorClock {
         let mut less = false;
         let mut greater = false;
 
-        let all_clients: std::collections::HashSet<_> =
-            self.clocks.keys().chain(other.clocks.keys()).collect();
+        let all_clients: HashSet<_> = self.clocks.keys().chain(other.clocks.keys()).collect();
 
         for client_id in all_clients {
             let self_clock = self.get(client_id);
             let other_clock = other.get(client_id);
 
             match self_clock.cmp(&other_clock) {
-                std::cmp::Ordering::Less => less = true,
-                std::cmp::Ordering::Greater => greater = true,
-                std::cmp::Ordering::Equal => {}
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => {}
             }
         }
 
@@ -142,7 +140,10 @@ impl Default for VectorClock {
     }
 }
 
+// The older tests pass `&String`, as `get` took before it took `&str`, so
+// they keep that call form compiling
 #[cfg(test)]
+#[allow(clippy::unnecessary_to_owned)]
 mod tests {
     use super::*;
 
@@ -240,4 +241,14 @@ mod tests {
         assert!(clock_merged.compare(&clock_a) != Ordering::Less);
         assert!(clock_merged.compare(&clock_b) != Ordering::Less);
     }
+
+    #[test]
+    fn test_get_and_update_take_str() {
+        let mut clock = VectorClock::new();
+        clock.update("c1", 5);
+        assert_eq!(clock.get("c1"), 5);
+        assert_eq!(clock.get("c2"), 0);
+        clock.update("c1", 7);
+        assert_eq!(clock.get("c1"), 7);
+    }
 }
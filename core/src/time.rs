@@ -0,0 +1,149 @@
+//! Wall-clock time behind a pluggable [`TimeProvider`]
+//!
+//! Staleness, expiry and rate limits need the current time, but
+//! `std::time::SystemTime` panics on `wasm32-unknown-unknown` and makes
+//! tests depend on the real clock. Types that need the time take a
+//! provider instead:
+//!
+//! - [`SystemTimeProvider`] reads the system clock (native only)
+//! - the WASM module installs a `Date.now()` provider as the thread's
+//!   default when it loads
+//! - [`MockTime`] only moves when a test calls [`MockTime::advance`]
+//!
+//! Any `Fn() -> u64` closure is a provider too, so existing callers that
+//! pass one keep working. Types configured without a provider use the
+//! thread's default ([`default_provider`]).
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Source of the current time
+pub trait TimeProvider {
+    /// Milliseconds since the Unix epoch (or any fixed origin, for tests)
+    fn now_ms(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> TimeProvider for F {
+    fn now_ms(&self) -> u64 {
+        self()
+    }
+}
+
+/// The system clock
+///
+/// Panics on `wasm32-unknown-unknown`, where there is none; the WASM
+/// module installs a `Date.now()` provider instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemTimeProvider;
+
+impl TimeProvider for SystemTimeProvider {
+    fn now_ms(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+}
+
+/// Manually advanced clock for tests
+///
+/// Clones share the same time, so a test keeps one and hands clones to
+/// the types under test.
+#[derive(Debug, Clone, Default)]
+pub struct MockTime(Arc<AtomicU64>);
+
+impl MockTime {
+    /// Clock reading `start_ms`
+    pub fn new(start_ms: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(start_ms)))
+    }
+
+    /// Move the clock forward by `ms`
+    pub fn advance(&self, ms: u64) {
+        self.0.fetch_add(ms, Ordering::SeqCst);
+    }
+
+    /// Set the clock to `now_ms`
+    pub fn set(&self, now_ms: u64) {
+        self.0.store(now_ms, Ordering::SeqCst);
+    }
+}
+
+impl TimeProvider for MockTime {
+    fn now_ms(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Shared handle to a provider, as stored by the types that use one
+#[derive(Clone)]
+pub struct SharedTime(Arc<dyn TimeProvider + Send + Sync>);
+
+impl SharedTime {
+    /// Share `provider`
+    pub fn new(provider: impl TimeProvider + Send + Sync + 'static) -> Self {
+        Self(Arc::new(provider))
+    }
+}
+
+impl TimeProvider for SharedTime {
+    fn now_ms(&self) -> u64 {
+        self.0.now_ms()
+    }
+}
+
+impl fmt::Debug for SharedTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedTime")
+    }
+}
+
+thread_local! {
+    static DEFAULT: RefCell<Option<SharedTime>> = const { RefCell::new(None) };
+}
+
+/// Make `provider` the default for this thread
+///
+/// Types without a provider of their own read it whenever they need the
+/// time.
+pub fn set_default_provider(provider: impl TimeProvider + Send + Sync + 'static) {
+    DEFAULT.with(|default| *default.borrow_mut() = Some(SharedTime::new(provider)));
+}
+
+/// This thread's default provider: the installed one, else the system
+/// clock
+pub fn default_provider() -> SharedTime {
+    DEFAULT.with(|default| {
+        default
+            .borrow()
+            .clone()
+            .unwrap_or_else(|| SharedTime::new(SystemTimeProvider))
+    })
+}
+
+/// Current time from this thread's default provider
+pub fn now_ms() -> u64 {
+    default_provider().now_ms()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_time_and_thread_default() {
+        let time = MockTime::new(1_000);
+        let shared = SharedTime::new(time.clone());
+        time.advance(250);
+        assert_eq!(shared.now_ms(), 1_250);
+
+        assert!(now_ms() > 1_600_000_000_000);
+        set_default_provider(time.clone());
+        assert_eq!(now_ms(), 1_250);
+        std::thread::spawn(|| assert!(now_ms() > 1_600_000_000_000))
+            .join()
+            .unwrap();
+        set_default_provider(SystemTimeProvider);
+    }
+}
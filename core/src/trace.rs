@@ -0,0 +1,26 @@
+//! Internal tracing helpers
+//!
+//! Spans are attached with
+//! `#[cfg_attr(feature = "tracing", tracing::instrument(...))]` and the
+//! macros below wrap events and late-recorded span fields. Without the
+//! `tracing` feature they expand to nothing, so field values are never
+//! computed or formatted.
+//!
+//! Spans are `info` level and only carry low-cardinality fields (ids,
+//! counts, byte sizes); per-block and per-field detail is `debug` events.
+
+/// `tracing::debug!`, compiled out without the `tracing` feature
+macro_rules! trace_debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!($($arg)*);
+    }};
+}
+
+/// Record a field declared as `Empty` on the current span
+macro_rules! trace_record {
+    ($field:literal, $value:expr) => {{
+        #[cfg(feature = "tracing")]
+        ::tracing::Span::current().record($field, $value);
+    }};
+}
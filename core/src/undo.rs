@@ -0,0 +1,574 @@
+//! Undo/redo history that survives a reload
+//!
+//! [`UndoManager`] records local edits as [`UndoStep`]s and reverts them on
+//! request. Steps name what they touch by field path or text character ID,
+//! never by position, so they stay correct while remote edits arrive and can
+//! be saved with [`UndoManager::to_bytes`] next to the document.
+//!
+//! Undoing is itself an edit: it writes the old field values back, deletes
+//! the inserted characters, or re-inserts the deleted text as new
+//! characters, and syncs to other replicas like any other change.
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde_json::json;
+//! use synckit_core::undo::UndoManager;
+//! use synckit_core::Document;
+//!
+//! let mut doc = Document::new("doc-1".to_string());
+//! let mut undo = UndoManager::new("alice".to_string());
+//! undo.set_field(&mut doc, "title".to_string(), json!("Draft"));
+//! undo.set_field(&mut doc, "title".to_string(), json!("Final"));
+//!
+//! // Reload: the history is restored against the reloaded document
+//! let bytes = undo.to_bytes().unwrap();
+//! let mut undo = UndoManager::from_bytes(&bytes, &mut doc).unwrap();
+//!
+//! undo.undo(&mut doc).unwrap();
+//! assert_eq!(doc.get_field(&"title".to_string()), Some(&json!("Draft")));
+//! ```
+
+use crate::error::{Result, SyncKitError};
+use crate::{ClientID, Document, FieldPath};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+#[cfg(feature = "text-crdt")]
+use std::collections::HashMap;
+
+#[cfg(feature = "text-crdt")]
+use crate::crdt::text_fugue::{FugueText, NodeId, SpliceResult, TextChange};
+
+/// Version of the `to_bytes` format
+const FORMAT_VERSION: u32 = 1;
+
+/// Steps kept by `UndoManager::new`
+const DEFAULT_MAX_STEPS: usize = 100;
+
+/// One undoable edit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "runs", rename_all = "snake_case")]
+pub enum UndoStep {
+    /// Fields were written or deleted: each path with its value before
+    /// the edit (`None` if it didn't exist)
+    Fields(Vec<(FieldPath, Option<JsonValue>)>),
+
+    /// Text was inserted: the characters, as ID runs (see `DeletedText`)
+    #[cfg(feature = "text-crdt")]
+    TextInsert(Vec<(NodeId, usize)>),
+
+    /// Text was deleted: each stretch that was contiguous when deleted
+    #[cfg(feature = "text-crdt")]
+    TextDelete(Vec<DeletedText>),
+}
+
+/// Text removed by one stretch of a delete
+#[cfg(feature = "text-crdt")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeletedText {
+    /// The removed characters in document order, as runs of consecutive
+    /// clocks from one client: each run's last character ID (as
+    /// `FugueText::insert` returns it) and its length
+    pub ids: Vec<(NodeId, usize)>,
+
+    /// The removed text
+    pub text: String,
+}
+
+/// Something an `UndoManager` can revert steps on
+pub trait UndoTarget {
+    /// Revert `step` as `client_id`, returning the step that reverts the
+    /// reversal (what redo applies)
+    ///
+    /// # Errors
+    ///
+    /// Fails with `INVALID_INPUT` if the step is for another kind of
+    /// target, or if it refers to something this replica has never seen
+    fn revert(&mut self, step: &UndoStep, client_id: &ClientID) -> Result<UndoStep>;
+
+    /// Check if everything `step` refers to exists here
+    fn can_revert(&mut self, step: &UndoStep) -> bool;
+}
+
+/// Undo and redo stacks for one client's edits to one target
+///
+/// Make edits through the recording methods (`set_field`, `insert_text`,
+/// ...) or record steps built elsewhere with `record`. A new edit clears
+/// the redo stack; past `max_steps` the oldest steps are dropped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UndoManager {
+    client_id: ClientID,
+    max_steps: usize,
+    undo: Vec<UndoStep>,
+    redo: Vec<UndoStep>,
+}
+
+/// What `to_bytes` writes
+#[derive(Serialize, Deserialize)]
+struct SavedHistory<M> {
+    version: u32,
+    manager: M,
+}
+
+impl UndoManager {
+    /// Create an empty history for `client_id`, keeping 100 steps
+    pub fn new(client_id: ClientID) -> Self {
+        Self {
+            client_id,
+            max_steps: DEFAULT_MAX_STEPS,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Keep at most `max_steps` undo steps
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self.trim();
+        self
+    }
+
+    /// The client edits are made as
+    pub fn client_id(&self) -> &ClientID {
+        &self.client_id
+    }
+
+    /// Steps `undo` would revert, oldest first
+    pub fn undo_steps(&self) -> &[UndoStep] {
+        &self.undo
+    }
+
+    /// Steps `redo` would reapply, oldest first
+    pub fn redo_steps(&self) -> &[UndoStep] {
+        &self.redo
+    }
+
+    /// Check if there is anything to undo
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Check if there is anything to redo
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forget both stacks
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Record an edit made elsewhere
+    pub fn record(&mut self, step: UndoStep) {
+        self.undo.push(step);
+        self.redo.clear();
+        self.trim();
+    }
+
+    /// Revert the last edit
+    ///
+    /// Returns false if there was nothing to undo. On error the step stays
+    /// on the stack.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `target` can't revert the step (see `UndoTarget::revert`)
+    pub fn undo(&mut self, target: &mut impl UndoTarget) -> Result<bool> {
+        let Some(step) = self.undo.pop() else {
+            return Ok(false);
+        };
+        match target.revert(&step, &self.client_id) {
+            Ok(inverse) => {
+                #[cfg(feature = "text-crdt")]
+                self.follow_reinserted(&step, &inverse);
+                self.redo.push(inverse);
+                Ok(true)
+            }
+            Err(error) => {
+                self.undo.push(step);
+                Err(error)
+            }
+        }
+    }
+
+    /// Reapply the last undone edit
+    ///
+    /// Returns false if there was nothing to redo. On error the step stays
+    /// on the stack.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `target` can't revert the step (see `UndoTarget::revert`)
+    pub fn redo(&mut self, target: &mut impl UndoTarget) -> Result<bool> {
+        let Some(step) = self.redo.pop() else {
+            return Ok(false);
+        };
+        match target.revert(&step, &self.client_id) {
+            Ok(inverse) => {
+                #[cfg(feature = "text-crdt")]
+                self.follow_reinserted(&step, &inverse);
+                self.undo.push(inverse);
+                self.trim();
+                Ok(true)
+            }
+            Err(error) => {
+                self.redo.push(step);
+                Err(error)
+            }
+        }
+    }
+
+    /// Write a field and record it
+    pub fn set_field(&mut self, document: &mut Document, path: FieldPath, value: JsonValue) {
+        let previous = document.get_field(&path).cloned();
+        write_field(document, &path, Some(value), &self.client_id);
+        self.record(UndoStep::Fields(vec![(path, previous)]));
+    }
+
+    /// Delete a field and record it (nothing is recorded if it didn't
+    /// exist)
+    pub fn delete_field(&mut self, document: &mut Document, path: FieldPath) {
+        if let Some(previous) = document.get_field(&path).cloned() {
+            document.delete_field(&path);
+            self.record(UndoStep::Fields(vec![(path, Some(previous))]));
+        }
+    }
+
+    /// Insert text and record it
+    ///
+    /// # Errors
+    ///
+    /// Fails like `FugueText::insert`; nothing is recorded then
+    #[cfg(feature = "text-crdt")]
+    pub fn insert_text(
+        &mut self,
+        text: &mut FugueText,
+        position: usize,
+        content: &str,
+    ) -> Result<NodeId> {
+        let id = text.insert(position, content)?;
+        let length = FugueText::text_len(content);
+        if length > 0 {
+            self.record(UndoStep::TextInsert(vec![(id.clone(), length)]));
+        }
+        Ok(id)
+    }
+
+    /// Delete text and record it
+    ///
+    /// # Errors
+    ///
+    /// Fails like `FugueText::delete`; nothing is recorded then
+    #[cfg(feature = "text-crdt")]
+    pub fn delete_text(
+        &mut self,
+        text: &mut FugueText,
+        position: usize,
+        length: usize,
+    ) -> Result<Vec<NodeId>> {
+        let removed = deleted_text(text, position, length)?;
+        let deleted = text.delete(position, length)?;
+        if length > 0 {
+            self.record(UndoStep::TextDelete(vec![removed]));
+        }
+        Ok(deleted)
+    }
+
+    /// Replace text and record it, as a delete and an insert that are
+    /// undone one at a time
+    ///
+    /// # Errors
+    ///
+    /// Fails like `FugueText::splice`; nothing is recorded then
+    #[cfg(feature = "text-crdt")]
+    pub fn splice_text(
+        &mut self,
+        text: &mut FugueText,
+        position: usize,
+        delete_len: usize,
+        content: &str,
+    ) -> Result<SpliceResult> {
+        let removed = deleted_text(text, position, delete_len)?;
+        let result = text.splice(position, delete_len, content)?;
+        if delete_len > 0 {
+            self.record(UndoStep::TextDelete(vec![removed]));
+        }
+        if let Some(id) = &result.inserted {
+            let length = FugueText::text_len(content);
+            self.record(UndoStep::TextInsert(vec![(id.clone(), length)]));
+        }
+        Ok(result)
+    }
+
+    /// Turn the text into `new_text` and record it, each splice of the
+    /// diff as `splice_text` records it
+    ///
+    /// # Errors
+    ///
+    /// Fails like `FugueText::apply_text_diff`
+    #[cfg(feature = "text-crdt")]
+    pub fn apply_text_diff(&mut self, text: &mut FugueText, new_text: &str) -> Result<TextChange> {
+        let change = text.text_diff(new_text);
+        for (position, delete_len, content) in change.splices() {
+            self.splice_text(text, position, delete_len, content)?;
+        }
+        Ok(change)
+    }
+
+    /// Serialize both stacks, to restore with `from_bytes`
+    ///
+    /// # Errors
+    ///
+    /// Fails with `SERIALIZATION_ERROR` if a field value can't be encoded
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(&SavedHistory {
+            version: FORMAT_VERSION,
+            manager: self,
+        })
+        .map_err(SyncKitError::serialization)
+    }
+
+    /// Restore a history saved by `to_bytes` for use on `target`
+    ///
+    /// Steps referring to something `target` doesn't have (a character
+    /// this replica never saw, or a step for another kind of target) are
+    /// dropped, so a history saved next to a newer document than the one
+    /// restored still loads.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `DESERIALIZATION_ERROR` if the bytes are corrupt or from
+    /// a newer format
+    pub fn from_bytes(bytes: &[u8], target: &mut impl UndoTarget) -> Result<Self> {
+        let saved: SavedHistory<UndoManager> =
+            serde_json::from_slice(bytes).map_err(SyncKitError::deserialization)?;
+        if saved.version != FORMAT_VERSION {
+            return Err(SyncKitError::deserialization(format!(
+                "unsupported undo history version {}",
+                saved.version
+            )));
+        }
+        let mut manager = saved.manager;
+        let before = manager.undo.len() + manager.redo.len();
+        manager.undo.retain(|step| target.can_revert(step));
+        manager.redo.retain(|step| target.can_revert(step));
+        let _dropped = before - manager.undo.len() - manager.redo.len();
+        trace_debug!(dropped = _dropped, "restored undo history");
+        Ok(manager)
+    }
+
+    /// Point the insert steps at the characters that reverting `reverted`
+    /// re-inserted in place of theirs
+    ///
+    /// Deleted characters can't come back, so undoing a delete inserts new
+    /// ones; without this, undoing the insert that first typed them would
+    /// leave them behind.
+    #[cfg(feature = "text-crdt")]
+    fn follow_reinserted(&mut self, reverted: &UndoStep, inverse: &UndoStep) {
+        let (UndoStep::TextDelete(runs), UndoStep::TextInsert(inserted)) = (reverted, inverse)
+        else {
+            return;
+        };
+        let mut moved = HashMap::new();
+        let runs = runs.iter().filter(|run| !run.text.is_empty());
+        for (run, new) in runs.zip(inserted) {
+            moved.extend(char_ids(&run.ids).zip(char_ids(std::slice::from_ref(new))));
+        }
+        if moved.is_empty() {
+            return;
+        }
+        for step in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            if let UndoStep::TextInsert(runs) = step {
+                let added: Vec<NodeId> = char_ids(runs)
+                    .filter_map(|id| moved.get(&id).cloned())
+                    .collect();
+                runs.extend(id_runs(added));
+            }
+        }
+    }
+
+    fn trim(&mut self) {
+        if self.undo.len() > self.max_steps {
+            let excess = self.undo.len() - self.max_steps;
+            self.undo.drain(..excess);
+        }
+    }
+}
+
+impl UndoTarget for Document {
+    fn revert(&mut self, step: &UndoStep, client_id: &ClientID) -> Result<UndoStep> {
+        match step {
+            UndoStep::Fields(fields) => Ok(self.transaction(|document| {
+                let mut inverse = Vec::with_capacity(fields.len());
+                for (path, previous) in fields.iter().rev() {
+                    inverse.push((path.clone(), document.get_field(path).cloned()));
+                    write_field(document, path, previous.clone(), client_id);
+                }
+                inverse.reverse();
+                UndoStep::Fields(inverse)
+            })),
+            #[cfg(feature = "text-crdt")]
+            _ => Err(SyncKitError::invalid_input(
+                "text undo step applied to a document",
+            )),
+        }
+    }
+
+    fn can_revert(&mut self, step: &UndoStep) -> bool {
+        matches!(step, UndoStep::Fields(_))
+    }
+}
+
+/// The characters of ID runs, in order
+#[cfg(feature = "text-crdt")]
+pub(crate) fn char_ids(runs: &[(NodeId, usize)]) -> impl Iterator<Item = NodeId> + '_ {
+    runs.iter().flat_map(|(id, length)| {
+        let first = (id.clock + 1).saturating_sub(*length as u64);
+        (first..=id.clock).map(|clock| NodeId::new(id.client_id.clone(), clock, 0))
+    })
+}
+
+/// Characters as ID runs, joining consecutive clocks from one client
+#[cfg(feature = "text-crdt")]
+pub(crate) fn id_runs(ids: impl IntoIterator<Item = NodeId>) -> Vec<(NodeId, usize)> {
+    let mut runs: Vec<(NodeId, usize)> = Vec::new();
+    for id in ids {
+        match runs.last_mut() {
+            Some((last, length))
+                if last.client_id == id.client_id && last.clock + 1 == id.clock =>
+            {
+                *last = id;
+                *length += 1;
+            }
+            _ => runs.push((id, 1)),
+        }
+    }
+    runs
+}
+
+/// The text and characters of `length` graphemes at `position`, to
+/// record before deleting them
+#[cfg(feature = "text-crdt")]
+fn deleted_text(text: &mut FugueText, position: usize, length: usize) -> Result<DeletedText> {
+    let removed = text.snapshot().slice(position, position + length)?;
+    let ids = (position..position + length)
+        .map(|position| text.get_node_id_at_position(position))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(DeletedText {
+        ids: id_runs(ids),
+        text: removed,
+    })
+}
+
+/// Set or delete a field as a new local write by `client_id`
+///
+/// The write's clock is past both our version and the field's current
+/// timestamp, so LWW keeps it even if the field was last written remotely.
+fn write_field(
+    document: &mut Document,
+    path: &FieldPath,
+    value: Option<JsonValue>,
+    client_id: &ClientID,
+) {
+    let Some(value) = value else {
+        document.delete_field(path);
+        return;
+    };
+    let current = document
+        .fields()
+        .get(path)
+        .map_or(0, |field| field.timestamp.clock);
+    let clock = document.version.get(client_id).max(current) + 1;
+    document.version.update(client_id, clock);
+    document.set_field(path.clone(), value, clock, client_id.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn title(doc: &Document) -> Option<&JsonValue> {
+        doc.get_field(&"title".to_string())
+    }
+
+    #[test]
+    fn test_undo_redo_fields() {
+        let mut doc = Document::new("doc".to_string());
+        let mut undo = UndoManager::new("alice".to_string());
+        undo.set_field(&mut doc, "title".to_string(), json!("a"));
+        undo.set_field(&mut doc, "title".to_string(), json!("b"));
+
+        assert!(undo.undo(&mut doc).unwrap());
+        assert_eq!(title(&doc), Some(&json!("a")));
+        assert!(undo.undo(&mut doc).unwrap());
+        assert_eq!(title(&doc), None);
+        assert!(!undo.undo(&mut doc).unwrap());
+
+        assert!(undo.redo(&mut doc).unwrap());
+        assert!(undo.redo(&mut doc).unwrap());
+        assert_eq!(title(&doc), Some(&json!("b")));
+        assert!(!undo.can_redo());
+    }
+
+    #[test]
+    fn test_undo_outranks_remote_write() {
+        let mut doc = Document::new("doc".to_string());
+        let mut undo = UndoManager::new("alice".to_string());
+        undo.set_field(&mut doc, "title".to_string(), json!("mine"));
+        doc.set_field("title".to_string(), json!("theirs"), 50, "bob".to_string());
+
+        undo.undo(&mut doc).unwrap();
+        assert_eq!(title(&doc), None);
+        undo.redo(&mut doc).unwrap();
+        assert_eq!(title(&doc), Some(&json!("theirs")));
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo_and_history_is_capped() {
+        let mut doc = Document::new("doc".to_string());
+        let mut undo = UndoManager::new("alice".to_string()).with_max_steps(2);
+        for value in 0..3 {
+            undo.set_field(&mut doc, "n".to_string(), json!(value));
+        }
+        assert_eq!(undo.undo_steps().len(), 2);
+
+        undo.undo(&mut doc).unwrap();
+        assert!(undo.can_redo());
+        undo.delete_field(&mut doc, "n".to_string());
+        assert!(!undo.can_redo());
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_unknown_version() {
+        let mut doc = Document::new("doc".to_string());
+        let bytes = br#"{"version":99,"manager":{}}"#;
+        assert!(UndoManager::from_bytes(bytes, &mut doc).is_err());
+        assert!(UndoManager::from_bytes(b"garbage", &mut doc).is_err());
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_restore_drops_steps_for_unknown_characters() {
+        let mut text = FugueText::new("alice".to_string());
+        let mut undo = UndoManager::new("alice".to_string());
+        undo.insert_text(&mut text, 0, "Hello").unwrap();
+        undo.delete_text(&mut text, 1, 2).unwrap();
+        let bytes = undo.to_bytes().unwrap();
+
+        // A replica restored from before the edits has none of the IDs
+        let mut stale = FugueText::new("alice".to_string());
+        let restored = UndoManager::from_bytes(&bytes, &mut stale).unwrap();
+        assert!(!restored.can_undo());
+
+        let restored = UndoManager::from_bytes(&bytes, &mut text).unwrap();
+        assert_eq!(restored, undo);
+
+        // Text steps don't apply to a document
+        let mut doc = Document::new("doc".to_string());
+        let restored = UndoManager::from_bytes(&bytes, &mut doc).unwrap();
+        assert!(!restored.can_undo());
+    }
+}
@@ -0,0 +1,267 @@
+//! Content-addressed storage for large repeated field values
+//!
+//! Documents often hold the same large JSON value under many fields
+//! (shared style objects, repeated templates). A serialized `Document`
+//! writes each such value once, into a table keyed by its [`ValueHash`],
+//! and has the fields refer to it. A `DocumentDelta` refers to values the
+//! receiver already has by hash instead of shipping them again.
+//!
+//! Only values of at least [`MIN_SHARED_BYTES`] bytes of JSON are shared;
+//! a reference to anything smaller costs more than the value. In memory,
+//! fields keep their own `serde_json::Value`, so the public API is
+//! unchanged.
+//!
+//! `ORSet` needs none of this: it keys its elements by value, so each one
+//! is already stored once.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Smallest value, in bytes of JSON, worth storing once and referring to
+pub const MIN_SHARED_BYTES: usize = 64;
+
+/// 128-bit content hash of a JSON value (FNV-1a over its JSON text)
+///
+/// Not cryptographic: it only has to tell apart the values one document
+/// holds. Writers check for collisions and keep a colliding value inline.
+/// Serialized as 32 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueHash(u128);
+
+impl ValueHash {
+    /// Hash of `value`, or None if it is too small to share
+    pub fn of_shareable(value: &JsonValue) -> Option<Self> {
+        match value {
+            JsonValue::Null | JsonValue::Bool(_) | JsonValue::Number(_) => None,
+            // Quotes and escapes only make it longer
+            JsonValue::String(s) if s.len() + 2 < MIN_SHARED_BYTES => None,
+            _ => {
+                let text = serde_json::to_vec(value).ok()?;
+                (text.len() >= MIN_SHARED_BYTES).then(|| Self::of_bytes(&text))
+            }
+        }
+    }
+
+    /// The hash as 16 big-endian bytes (protobuf `value_ref`)
+    pub fn to_bytes(self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    /// Parse the output of `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self(u128::from_be_bytes(bytes.try_into().ok()?)))
+    }
+
+    fn of_bytes(bytes: &[u8]) -> Self {
+        let mut hash: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+        for byte in bytes {
+            hash ^= u128::from(*byte);
+            hash = hash.wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+        }
+        Self(hash)
+    }
+}
+
+impl fmt::Display for ValueHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for ValueHash {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u128::from_str_radix(s, 16).map(Self)
+    }
+}
+
+impl Serialize for ValueHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ValueHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse()
+            .map_err(|_| D::Error::custom(format!("invalid value hash {:?}", text)))
+    }
+}
+
+/// Values by content hash
+///
+/// What a document's fields refer to once serialized; merging two stores
+/// unifies their tables.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValueStore {
+    values: HashMap<ValueHash, JsonValue>,
+}
+
+impl ValueStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The shareable values of `values`
+    pub fn from_values<'a>(values: impl IntoIterator<Item = &'a JsonValue>) -> Self {
+        let mut store = Self::new();
+        for value in values {
+            store.insert(value);
+        }
+        store
+    }
+
+    /// Store `value` if it is large enough to share, returning its hash
+    ///
+    /// Returns None, storing nothing, for a small value or one whose hash
+    /// collides with a different stored value.
+    pub fn insert(&mut self, value: &JsonValue) -> Option<ValueHash> {
+        let hash = ValueHash::of_shareable(value)?;
+        match self.values.get(&hash) {
+            Some(stored) if stored != value => None,
+            Some(_) => Some(hash),
+            None => {
+                self.values.insert(hash, value.clone());
+                Some(hash)
+            }
+        }
+    }
+
+    /// The value with the given hash
+    pub fn get(&self, hash: &ValueHash) -> Option<&JsonValue> {
+        self.values.get(hash)
+    }
+
+    /// Check if a value with the given hash is stored
+    pub fn contains(&self, hash: &ValueHash) -> bool {
+        self.values.contains_key(hash)
+    }
+
+    /// Add every value of `other`
+    pub fn merge(&mut self, other: &ValueStore) {
+        for (hash, value) in &other.values {
+            self.values.entry(*hash).or_insert_with(|| value.clone());
+        }
+    }
+
+    /// Number of stored values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check if nothing is stored
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Which of a set of named values to write once, for serialization
+///
+/// A value is shared if it is large enough and occurs more than once.
+pub(crate) struct SharedValues<'a> {
+    /// Shared values in hash order
+    pub table: BTreeMap<ValueHash, StoredValueRef<'a>>,
+    /// Hash of each name whose value is shared
+    pub refs: HashMap<&'a str, ValueHash>,
+}
+
+impl<'a> SharedValues<'a> {
+    pub fn of(values: impl IntoIterator<Item = (&'a str, &'a JsonValue)>) -> Self {
+        // Hash -> first value seen and the names holding an equal value
+        let mut seen: HashMap<ValueHash, (&JsonValue, Vec<&str>)> = HashMap::new();
+        for (name, value) in values {
+            let Some(hash) = ValueHash::of_shareable(value) else {
+                continue;
+            };
+            let (first, names) = seen.entry(hash).or_insert((value, Vec::new()));
+            // A collision stays inline
+            if *first == value {
+                names.push(name);
+            }
+        }
+
+        let mut table = BTreeMap::new();
+        let mut refs = HashMap::new();
+        for (hash, (value, names)) in seen {
+            if names.len() > 1 {
+                table.insert(hash, StoredValueRef(value));
+                refs.extend(names.into_iter().map(|name| (name, hash)));
+            }
+        }
+        Self { table, refs }
+    }
+}
+
+/// A JSON value written like `Field::value` (see `codec::json_value`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct StoredValue(#[serde(with = "crate::codec::json_value")] pub JsonValue);
+
+/// Borrowed `StoredValue`, for writing
+pub(crate) struct StoredValueRef<'a>(pub &'a JsonValue);
+
+impl Serialize for StoredValueRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::codec::json_value::serialize(self.0, serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn large(tag: &str) -> JsonValue {
+        json!({ "tag": tag, "padding": "x".repeat(MIN_SHARED_BYTES) })
+    }
+
+    #[test]
+    fn test_only_large_values_are_shareable() {
+        assert_eq!(ValueHash::of_shareable(&json!(42)), None);
+        assert_eq!(ValueHash::of_shareable(&json!({"a": 1})), None);
+        assert_eq!(
+            ValueHash::of_shareable(&large("a")),
+            ValueHash::of_shareable(&large("a"))
+        );
+        assert_ne!(
+            ValueHash::of_shareable(&large("a")),
+            ValueHash::of_shareable(&large("b"))
+        );
+    }
+
+    #[test]
+    fn test_hash_round_trips_as_text_and_bytes() {
+        let hash = ValueHash::of_shareable(&large("a")).unwrap();
+        let text = serde_json::to_string(&hash).unwrap();
+        assert_eq!(text.len(), 34);
+        assert_eq!(serde_json::from_str::<ValueHash>(&text).unwrap(), hash);
+        assert_eq!(ValueHash::from_bytes(&hash.to_bytes()), Some(hash));
+        assert!(serde_json::from_str::<ValueHash>("\"xyz\"").is_err());
+    }
+
+    #[test]
+    fn test_store_merges_tables() {
+        let mut a = ValueStore::from_values([&large("a"), &json!(1)]);
+        let b = ValueStore::from_values([&large("a"), &large("b")]);
+        assert_eq!(a.len(), 1);
+        a.merge(&b);
+        assert_eq!(a.len(), 2);
+        let hash = ValueHash::of_shareable(&large("b")).unwrap();
+        assert_eq!(a.get(&hash), Some(&large("b")));
+    }
+
+    #[test]
+    fn test_shares_only_repeated_values() {
+        let (a, b) = (large("a"), large("b"));
+        let shared = SharedValues::of([("x", &a), ("y", &a), ("z", &b)]);
+        assert_eq!(shared.table.len(), 1);
+        assert_eq!(shared.refs.len(), 2);
+        assert!(!shared.refs.contains_key("z"));
+    }
+}
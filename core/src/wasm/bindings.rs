@@ -1,17 +1,121 @@
 //! JavaScript bindings for SyncKit core types
 
+use super::types::SyncKitErrorInfo;
 use crate::document::Document;
+use crate::error::SyncKitError;
 use crate::sync::VectorClock;
 use wasm_bindgen::prelude::*;
 
 // DocumentDelta is only available with protocol support
-#[cfg(feature = "prost")]
+#[cfg(feature = "protocol-binary")]
 use crate::protocol::delta::DocumentDelta;
 
+/// Convert an error into a JS `Error` named `SyncKitError`
+///
+/// The thrown object carries the fields of `SyncKitErrorInfo` (`code`,
+/// `codeName`, `category` and any context) as own properties, so callers
+/// can branch on `err.code` instead of parsing the message. It is typed as
+/// the `SyncKitError` interface in the `.d.ts` output.
+fn js_error(error: impl Into<SyncKitError>) -> JsValue {
+    let info = SyncKitErrorInfo::from(&error.into());
+    let js = js_sys::Error::new(&info.message);
+    js.set_name("SyncKitError");
+
+    let set = |key: &str, value: JsValue| {
+        // Setting a property on a fresh Error object cannot fail
+        let _ = js_sys::Reflect::set(&js, &JsValue::from_str(key), &value);
+    };
+    set("code", JsValue::from(info.code));
+    set("codeName", JsValue::from_str(&info.code_name));
+    set("category", JsValue::from_str(info.category.as_str()));
+    if let Some(id) = &info.document_id {
+        set("documentId", JsValue::from_str(id));
+    }
+    if let Some(path) = &info.path {
+        set("path", JsValue::from_str(path));
+    }
+    if let Some(position) = info.position {
+        set("position", JsValue::from(position as f64));
+    }
+
+    js.into()
+}
+
+/// Change batches collected by a `subscribe` callback, handed to the JS
+/// `onChange` callback after each call
+struct ChangeSink<C> {
+    batches: std::sync::Arc<std::sync::Mutex<Vec<Vec<C>>>>,
+    on_change: Option<js_sys::Function>,
+}
+
+impl<C: serde::Serialize + Clone + Send + 'static> ChangeSink<C> {
+    fn new() -> Self {
+        Self {
+            batches: std::sync::Arc::default(),
+            on_change: None,
+        }
+    }
+
+    /// Register the JS callback; returns the Rust callback to subscribe
+    /// the first time
+    fn set_callback(
+        &mut self,
+        callback: js_sys::Function,
+    ) -> Option<impl FnMut(&[C]) + Send + Sync + 'static> {
+        let sink = self.batches.clone();
+        self.on_change
+            .replace(callback)
+            .is_none()
+            .then_some(move |batch: &[C]| sink.lock().unwrap().push(batch.to_vec()))
+    }
+
+    /// Call the JS callback once per collected batch
+    fn deliver(&self) -> Result<(), JsValue> {
+        let batches = std::mem::take(&mut *self.batches.lock().unwrap());
+        let Some(callback) = &self.on_change else {
+            return Ok(());
+        };
+        for batch in batches {
+            let json = serde_json::to_string(&batch)
+                .map_err(|e| js_error(SyncKitError::serialization(e)))?;
+            callback.call1(&JsValue::NULL, &js_sys::JSON::parse(&json)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// The JS wall clock, `Date.now()`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsTimeProvider;
+
+impl crate::time::TimeProvider for JsTimeProvider {
+    fn now_ms(&self) -> u64 {
+        js_sys::Date::now() as u64
+    }
+}
+
+/// Make `Date.now()` the default clock when the module loads, since
+/// there is no system clock on `wasm32-unknown-unknown`
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(start)]
+pub fn install_js_time_provider() {
+    crate::time::set_default_provider(JsTimeProvider);
+}
+
+/// `CoalescePolicy` from JS arguments
+fn coalesce_policy(max_delay_ms: u32, max_changes: usize) -> crate::notify::CoalescePolicy {
+    crate::notify::CoalescePolicy {
+        max_delay: std::time::Duration::from_millis(max_delay_ms.into()),
+        max_changes,
+    }
+}
+
 /// JavaScript-friendly wrapper for Document
 #[wasm_bindgen]
 pub struct WasmDocument {
     inner: Document,
+    changes: ChangeSink<crate::notify::FieldEvent>,
+    merge_job: Option<crate::merge_job::MergeJob>,
 }
 
 #[wasm_bindgen]
@@ -21,6 +125,8 @@ impl WasmDocument {
     pub fn new(id: String) -> Self {
         Self {
             inner: Document::new(id),
+            changes: ChangeSink::new(),
+            merge_job: None,
         }
     }
 
@@ -33,25 +139,162 @@ impl WasmDocument {
         clock: u64,
         client_id: String,
     ) -> Result<(), JsValue> {
-        let value: serde_json::Value = serde_json::from_str(&value_json)
-            .map_err(|e| JsValue::from_str(&format!("Invalid JSON: {}", e)))?;
-
+        let value = self.parse_value(&path, &value_json)?;
         self.inner.set_field(path, value, clock, client_id);
-        Ok(())
+        self.changes.deliver()
     }
 
     /// Get a field value (returns JSON string)
     #[wasm_bindgen(js_name = getField)]
-    pub fn get_field(&self, path: String) -> Option<String> {
+    pub fn get_field(&self, path: String) -> Result<Option<String>, JsValue> {
         self.inner
             .get_field(&path)
-            .map(|field| serde_json::to_string(&field).unwrap())
+            .map(|field| {
+                serde_json::to_string(&field).map_err(|e| {
+                    js_error(
+                        SyncKitError::serialization(e)
+                            .with_document(self.inner.id().as_str())
+                            .with_path(path.as_str()),
+                    )
+                })
+            })
+            .transpose()
     }
 
     /// Delete a field
     #[wasm_bindgen(js_name = deleteField)]
-    pub fn delete_field(&mut self, path: String) {
+    pub fn delete_field(&mut self, path: String) -> Result<(), JsValue> {
         self.inner.delete_field(&path);
+        self.changes.deliver()
+    }
+
+    /// Write a multi-value field, which keeps concurrent writes instead
+    /// of picking one; returns false if a write at `clock` was already seen
+    #[wasm_bindgen(js_name = setMultiField)]
+    pub fn set_multi_field(
+        &mut self,
+        path: String,
+        value_json: String,
+        clock: u64,
+        client_id: String,
+    ) -> Result<bool, JsValue> {
+        let value = self.parse_value(&path, &value_json)?;
+        let written = self.inner.set_multi_field(path, value, clock, client_id);
+        self.changes.deliver()?;
+        Ok(written)
+    }
+
+    /// Every value at `path` (`FieldVersion[]` JSON), several if a
+    /// multi-value field holds concurrent writes
+    #[wasm_bindgen(js_name = getAll)]
+    pub fn get_all(&self, path: String) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.get_all(&path)).map_err(|e| {
+            js_error(
+                SyncKitError::serialization(e)
+                    .with_document(self.inner.id().as_str())
+                    .with_path(path.as_str()),
+            )
+        })
+    }
+
+    /// Replace the concurrent values of a multi-value field with one,
+    /// on every replica once synced
+    #[wasm_bindgen(js_name = resolve)]
+    pub fn resolve(
+        &mut self,
+        path: String,
+        value_json: String,
+        clock: u64,
+        client_id: String,
+    ) -> Result<bool, JsValue> {
+        let value = self.parse_value(&path, &value_json)?;
+        let written = self
+            .inner
+            .resolve(&path, value, clock, client_id)
+            .map_err(js_error)?;
+        self.changes.deliver()?;
+        Ok(written)
+    }
+
+    /// Append a value (JSON string) to a list field, creating the list
+    ///
+    /// Returns the new item's `ListId` as a JSON string.
+    #[wasm_bindgen(js_name = listPush)]
+    pub fn list_push(
+        &mut self,
+        path: String,
+        value_json: String,
+        client_id: String,
+    ) -> Result<String, JsValue> {
+        let value = self.parse_value(&path, &value_json)?;
+        let id = self.inner.list_mut(path.clone(), client_id).push(value);
+        self.changes.deliver()?;
+        self.list_id_json(&path, &id)
+    }
+
+    /// Insert a value (JSON string) so that it ends up at `index`
+    ///
+    /// Returns the new item's `ListId` as a JSON string.
+    #[wasm_bindgen(js_name = listInsert)]
+    pub fn list_insert(
+        &mut self,
+        path: String,
+        index: usize,
+        value_json: String,
+        client_id: String,
+    ) -> Result<String, JsValue> {
+        let value = self.parse_value(&path, &value_json)?;
+        let id = self
+            .inner
+            .list_mut(path.clone(), client_id)
+            .insert(index, value)
+            .map_err(js_error)?;
+        self.changes.deliver()?;
+        self.list_id_json(&path, &id)
+    }
+
+    /// Move the list item at `from` so that it ends up at `to`
+    #[wasm_bindgen(js_name = listMove)]
+    pub fn list_move(
+        &mut self,
+        path: String,
+        from: usize,
+        to: usize,
+        client_id: String,
+    ) -> Result<(), JsValue> {
+        self.inner
+            .list_mut(path, client_id)
+            .move_item(from, to)
+            .map_err(js_error)?;
+        self.changes.deliver()
+    }
+
+    /// Remove the list item at `index`
+    #[wasm_bindgen(js_name = listRemove)]
+    pub fn list_remove(&mut self, path: String, index: usize) -> Result<(), JsValue> {
+        // Removal creates no ids, so no client is needed
+        self.inner
+            .list_mut(path, String::new())
+            .remove(index)
+            .map_err(js_error)?;
+        self.changes.deliver()
+    }
+
+    /// Get a list field as a JSON array string
+    #[wasm_bindgen(js_name = listGet)]
+    pub fn list_get(&self, path: String) -> Result<Option<String>, JsValue> {
+        self.inner
+            .list(&path)
+            .map(|list| {
+                serde_json::to_string(&list.to_json()).map_err(|e| {
+                    js_error(
+                        SyncKitError::serialization(e)
+                            .with_document(self.inner.id().as_str())
+                            .with_path(path.as_str()),
+                    )
+                })
+            })
+            .transpose()
     }
 
     /// Get document ID
@@ -68,14 +311,398 @@ impl WasmDocument {
 
     /// Export document as JSON string
     #[wasm_bindgen(js_name = toJSON)]
-    pub fn to_json(&self) -> String {
-        serde_json::to_string(&self.inner.to_json()).unwrap()
+    pub fn to_json(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.to_json()).map_err(|e| {
+            js_error(SyncKitError::serialization(e).with_document(self.inner.id().as_str()))
+        })
+    }
+
+    /// Estimated heap bytes per subsystem (JSON `MemoryUsage`,
+    /// `{"subsystems": {name: bytes}}`)
+    #[wasm_bindgen(js_name = memoryUsage)]
+    pub fn memory_usage(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.memory_usage())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Field count against value and metadata bytes (JSON
+    /// `DocumentMemoryStats`)
+    #[wasm_bindgen(js_name = memoryStats)]
+    pub fn memory_stats(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.memory_stats())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
     /// Merge with another document
     #[wasm_bindgen(js_name = merge)]
-    pub fn merge(&mut self, other: &WasmDocument) {
+    pub fn merge(&mut self, other: &WasmDocument) -> Result<(), JsValue> {
         self.inner.merge(&other.inner);
+        self.changes.deliver()
+    }
+
+    /// Start merging `other` in slices run by `mergeIncremental`,
+    /// replacing any merge still in progress
+    #[wasm_bindgen(js_name = startMerge)]
+    pub fn start_merge(&mut self, other: &WasmDocument) {
+        self.merge_job = Some(self.inner.start_merge(other.inner.clone()));
+    }
+
+    /// Run the merge from `startMerge` for about `budgetMs` milliseconds
+    /// (JSON `MergeProgress`)
+    ///
+    /// Fields merged by the slice reach `onChange` before it returns.
+    /// Reports a complete merge when none is in progress.
+    ///
+    /// # Example (JavaScript)
+    /// ```javascript
+    /// doc.startMerge(remote);
+    /// while (!JSON.parse(doc.mergeIncremental(8)).complete) {
+    ///   await new Promise((resolve) => setTimeout(resolve));
+    /// }
+    /// ```
+    #[wasm_bindgen(js_name = mergeIncremental)]
+    pub fn merge_incremental(&mut self, budget_ms: u32) -> Result<String, JsValue> {
+        let budget = crate::merge_job::MergeBudget::Millis(budget_ms.into());
+        let progress = match &mut self.merge_job {
+            Some(job) => job.run_for(&mut self.inner, budget),
+            None => crate::merge_job::MergeProgress {
+                complete: true,
+                ..Default::default()
+            },
+        };
+        if progress.complete {
+            self.merge_job = None;
+        }
+        self.changes.deliver()?;
+        serde_json::to_string(&progress).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Register a callback for field changes
+    ///
+    /// Called with an array of `FieldEvent` objects per batch: each call
+    /// that changes fields (`setField`, `merge`, `WasmDelta.applyTo`, ...)
+    /// delivers one, at most one event per path. Replaces any previous
+    /// callback.
+    #[wasm_bindgen(js_name = onChange)]
+    pub fn on_change(&mut self, callback: js_sys::Function) {
+        if let Some(sink) = self.changes.set_callback(callback) {
+            self.inner.subscribe(sink);
+        }
+    }
+
+    /// Hold back change batches and deliver them merged, once
+    /// `maxChanges` changes are held or a batch arrives `maxDelayMs` after
+    /// the first held one
+    #[wasm_bindgen(js_name = setCoalescing)]
+    pub fn set_coalescing(&mut self, max_delay_ms: u32, max_changes: usize) {
+        self.inner
+            .set_coalescing_policy(coalesce_policy(max_delay_ms, max_changes));
+    }
+
+    /// Deliver held-back changes to the `onChange` callback now
+    #[wasm_bindgen(js_name = flushNotifications)]
+    pub fn flush_notifications(&mut self) -> Result<(), JsValue> {
+        self.inner.flush_notifications();
+        self.changes.deliver()
+    }
+
+    /// Check if anything changed since the last `takeDirty`
+    #[wasm_bindgen(js_name = isDirty)]
+    pub fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+
+    /// Take the fields changed since the last call, as JSON bytes
+    ///
+    /// Store each chunk instead of rewriting the whole document; replay
+    /// them in order with `applyDirty` to restore it.
+    #[wasm_bindgen(js_name = takeDirty)]
+    pub fn take_dirty(&mut self) -> Result<Vec<u8>, JsValue> {
+        serde_json::to_vec(&self.inner.take_dirty()).map_err(|e| {
+            js_error(SyncKitError::serialization(e).with_document(self.inner.id().as_str()))
+        })
+    }
+
+    /// Replay a chunk from `takeDirty`
+    #[wasm_bindgen(js_name = applyDirty)]
+    pub fn apply_dirty(&mut self, chunk: &[u8]) -> Result<(), JsValue> {
+        let dirty: crate::DirtyState = serde_json::from_slice(chunk).map_err(|e| {
+            js_error(SyncKitError::deserialization(e).with_document(self.inner.id().as_str()))
+        })?;
+        self.inner.apply_dirty(&dirty);
+        self.changes.deliver()
+    }
+
+    /// Take the advisory lock on `path` for `holder` for `ttlMs`
+    /// milliseconds; returns false if someone else holds it
+    ///
+    /// A concurrent acquisition that wins a later merge shows up as a
+    /// `lock` event in `onChange`.
+    #[wasm_bindgen(js_name = acquireLock)]
+    pub fn acquire_lock(
+        &mut self,
+        path: String,
+        holder: String,
+        ttl_ms: u32,
+    ) -> Result<bool, JsValue> {
+        let ttl = std::time::Duration::from_millis(ttl_ms.into());
+        let acquired = self.inner.acquire_lock(&path, &holder, ttl);
+        self.changes.deliver()?;
+        Ok(acquired)
+    }
+
+    /// Release `holder`'s lock on `path`; returns false unless `holder`
+    /// holds it
+    #[wasm_bindgen(js_name = releaseLock)]
+    pub fn release_lock(&mut self, path: String, holder: String) -> Result<bool, JsValue> {
+        let released = self.inner.release_lock(&path, &holder);
+        self.changes.deliver()?;
+        Ok(released)
+    }
+
+    /// Who holds the advisory lock on `path` now, if anyone
+    #[wasm_bindgen(js_name = holderOf)]
+    pub fn holder_of(&self, path: String) -> Option<String> {
+        self.inner.lock_holder(&path).map(str::to_string)
+    }
+
+    /// Set several fields in one transaction, tagged with an annotation
+    ///
+    /// `fieldsJson` is a JSON object of path to value, all written with
+    /// `clock`; `annotationJson` is any JSON of at most 1 KiB, readable on
+    /// every replica with `annotationFor`.
+    #[wasm_bindgen(js_name = setFieldsAnnotated)]
+    pub fn set_fields_annotated(
+        &mut self,
+        fields_json: String,
+        clock: u64,
+        client_id: String,
+        annotation_json: String,
+    ) -> Result<(), JsValue> {
+        let fields: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&fields_json)
+            .map_err(|e| {
+                js_error(
+                    SyncKitError::invalid_input(format!("Invalid fields: {}", e))
+                        .with_document(self.inner.id().as_str()),
+                )
+            })?;
+        let payload = serde_json::from_str(&annotation_json).map_err(|e| {
+            js_error(
+                SyncKitError::invalid_input(format!("Invalid annotation: {}", e))
+                    .with_document(self.inner.id().as_str()),
+            )
+        })?;
+        self.inner
+            .annotated_transaction(payload, |document| {
+                for (path, value) in fields {
+                    document.set_field(path, value, clock, client_id.clone());
+                }
+            })
+            .map_err(js_error)?;
+        self.changes.deliver()
+    }
+
+    /// The annotation on the current value of `path` (`Annotation` JSON),
+    /// if any
+    #[wasm_bindgen(js_name = annotationFor)]
+    pub fn annotation_for(&self, path: String) -> Result<Option<String>, JsValue> {
+        self.inner
+            .annotations_for(&path)
+            .map(|annotation| {
+                serde_json::to_string(annotation).map_err(|e| {
+                    js_error(
+                        SyncKitError::serialization(e)
+                            .with_document(self.inner.id().as_str())
+                            .with_path(path.as_str()),
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    /// Values that lost LWW in merges and incoming deltas, oldest first
+    /// (`Conflict[]` JSON)
+    #[wasm_bindgen(js_name = getConflicts)]
+    pub fn get_conflicts(&self) -> Result<String, JsValue> {
+        serde_json::to_string(self.inner.conflicts().entries()).map_err(|e| {
+            js_error(SyncKitError::serialization(e).with_document(self.inner.id().as_str()))
+        })
+    }
+
+    /// Settle a conflict by writing the value `keepJson` (`ConflictKeep`)
+    /// picks at `clock`, and drop it from `getConflicts`; returns false if
+    /// the write would lose to the field's current value
+    #[wasm_bindgen(js_name = resolveConflict)]
+    pub fn resolve_conflict(
+        &mut self,
+        id: String,
+        keep_json: String,
+        clock: u64,
+        client_id: String,
+    ) -> Result<bool, JsValue> {
+        let id = id.parse().map_err(|e| {
+            js_error(
+                SyncKitError::invalid_input(format!("Invalid conflict id: {}", e))
+                    .with_document(self.inner.id().as_str()),
+            )
+        })?;
+        let keep = serde_json::from_str(&keep_json).map_err(|e| {
+            js_error(
+                SyncKitError::invalid_input(format!("Invalid keep: {}", e))
+                    .with_document(self.inner.id().as_str()),
+            )
+        })?;
+        let written = self
+            .inner
+            .resolve_conflict(&id, keep, clock, client_id)
+            .map_err(js_error)?;
+        self.changes.deliver()?;
+        Ok(written)
+    }
+
+    /// Create a read-only view frozen at the current state
+    ///
+    /// The view owns its data, so it stays valid after this document is
+    /// mutated or freed.
+    #[wasm_bindgen(js_name = readOnlyView)]
+    pub fn read_only_view(&self) -> WasmDocumentView {
+        WasmDocumentView {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl WasmDocument {
+    fn parse_value(&self, path: &str, value_json: &str) -> Result<serde_json::Value, JsValue> {
+        serde_json::from_str(value_json).map_err(|e| {
+            js_error(
+                SyncKitError::invalid_input(format!("Invalid JSON: {}", e))
+                    .with_document(self.inner.id().as_str())
+                    .with_path(path),
+            )
+        })
+    }
+
+    fn list_id_json(&self, path: &str, id: &crate::list::ListId) -> Result<String, JsValue> {
+        serde_json::to_string(id).map_err(|e| {
+            js_error(
+                SyncKitError::serialization(e)
+                    .with_document(self.inner.id().as_str())
+                    .with_path(path),
+            )
+        })
+    }
+}
+
+/// Search over the documents an app has open
+///
+/// Holds the search index only: call `updateDocument` with a document
+/// after it changes (from its `onChange` callback), and only the changed
+/// fields are tokenized again. Save the index with `toJSON` and restore it
+/// with `fromJSON` so a reload doesn't start over.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct WasmWorkspace {
+    index: crate::index::SearchIndex,
+}
+
+#[wasm_bindgen]
+impl WasmWorkspace {
+    /// Create a workspace with an empty index
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restore a workspace from `toJSON` output
+    #[wasm_bindgen(js_name = fromJSON)]
+    pub fn from_json(json: String) -> Result<WasmWorkspace, JsValue> {
+        let index =
+            serde_json::from_str(&json).map_err(|e| js_error(SyncKitError::deserialization(e)))?;
+        Ok(Self { index })
+    }
+
+    /// Bring the index up to date with a document; returns how many
+    /// fields were tokenized again
+    #[wasm_bindgen(js_name = updateDocument)]
+    pub fn update_document(&mut self, document: &WasmDocument) -> usize {
+        self.index.update_document(&document.inner)
+    }
+
+    /// Index a text of a document under `name`; returns whether it changed
+    #[cfg(feature = "text-crdt")]
+    #[wasm_bindgen(js_name = updateText)]
+    pub fn update_text(&mut self, document_id: String, name: String, text: &WasmFugueText) -> bool {
+        self.index
+            .update_text(&document_id, &name, &text.inner.to_string())
+    }
+
+    /// Drop a document from the index
+    #[wasm_bindgen(js_name = removeDocument)]
+    pub fn remove_document(&mut self, document_id: String) {
+        self.index.remove_document(&document_id);
+    }
+
+    /// Documents matching every word of `query` as a prefix, best first
+    ///
+    /// # Returns
+    /// JSON array of `SearchHit`
+    #[wasm_bindgen(js_name = search)]
+    pub fn search(&self, query: String, limit: usize) -> Result<String, JsValue> {
+        serde_json::to_string(&self.index.search(&query, limit))
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Export the index as JSON
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.index).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+}
+
+/// Read-only view of a Document (see `WasmDocument.readOnlyView`)
+#[wasm_bindgen]
+pub struct WasmDocumentView {
+    inner: Document,
+}
+
+#[wasm_bindgen]
+impl WasmDocumentView {
+    /// Get a field value (returns JSON string)
+    #[wasm_bindgen(js_name = getField)]
+    pub fn get_field(&self, path: String) -> Result<Option<String>, JsValue> {
+        self.inner
+            .get_field(&path)
+            .map(|field| {
+                serde_json::to_string(&field).map_err(|e| {
+                    js_error(
+                        SyncKitError::serialization(e)
+                            .with_document(self.inner.id().as_str())
+                            .with_path(path.as_str()),
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    /// Get document ID
+    #[wasm_bindgen(js_name = getId)]
+    pub fn get_id(&self) -> String {
+        self.inner.id().clone()
+    }
+
+    /// Get field count
+    #[wasm_bindgen(js_name = fieldCount)]
+    pub fn field_count(&self) -> usize {
+        self.inner.field_count()
+    }
+
+    /// Export document as JSON string
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.to_json()).map_err(|e| {
+            js_error(SyncKitError::serialization(e).with_document(self.inner.id().as_str()))
+        })
     }
 }
 
@@ -127,20 +754,20 @@ impl WasmVectorClock {
 
     /// Export as JSON string
     #[wasm_bindgen(js_name = toJSON)]
-    pub fn to_json(&self) -> String {
-        serde_json::to_string(&self.inner).unwrap()
+    pub fn to_json(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner).map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 }
 
 /// JavaScript-friendly wrapper for DocumentDelta
 /// Only available when protocol support is enabled (core variant, not core-lite)
-#[cfg(feature = "prost")]
+#[cfg(feature = "protocol-binary")]
 #[wasm_bindgen]
 pub struct WasmDelta {
     inner: DocumentDelta,
 }
 
-#[cfg(feature = "prost")]
+#[cfg(feature = "protocol-binary")]
 #[wasm_bindgen]
 impl WasmDelta {
     /// Compute delta between two documents
@@ -148,15 +775,44 @@ impl WasmDelta {
     pub fn compute(from: &WasmDocument, to: &WasmDocument) -> Result<WasmDelta, JsValue> {
         DocumentDelta::compute(&from.inner, &to.inner)
             .map(|delta| WasmDelta { inner: delta })
-            .map_err(|e| JsValue::from_str(&format!("Delta computation failed: {}", e)))
+            .map_err(js_error)
     }
 
     /// Apply delta to a document
+    ///
+    /// # Returns
+    /// JSON array of the applied changes (`FieldChange`), without echoes of
+    /// `client_id`'s own changes
     #[wasm_bindgen(js_name = applyTo)]
-    pub fn apply_to(&self, document: &mut WasmDocument, client_id: String) -> Result<(), JsValue> {
-        self.inner
+    pub fn apply_to(
+        &self,
+        document: &mut WasmDocument,
+        client_id: String,
+    ) -> Result<String, JsValue> {
+        let changes = self
+            .inner
             .apply_to(&mut document.inner, &client_id)
-            .map_err(|e| JsValue::from_str(&format!("Delta application failed: {}", e)))
+            .map_err(js_error)?;
+        document.changes.deliver()?;
+
+        serde_json::to_string(&crate::protocol::delta::without_echoes(changes))
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Work out what `applyTo` would do to `document`, without changing
+    /// it
+    ///
+    /// # Returns
+    /// JSON `DryRunReport`; its `changes` are what `applyTo` would return
+    /// before dropping echoes
+    #[wasm_bindgen(js_name = dryRun)]
+    pub fn dry_run(&self, document: &WasmDocument, client_id: String) -> Result<String, JsValue> {
+        let report = self
+            .inner
+            .dry_run(&document.inner, &client_id)
+            .map_err(js_error)?;
+
+        serde_json::to_string(&report).map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
     /// Get document ID this delta applies to
@@ -174,8 +830,67 @@ impl WasmDelta {
     /// Export as JSON string
     #[wasm_bindgen(js_name = toJSON)]
     pub fn to_json(&self) -> Result<String, JsValue> {
-        serde_json::to_string(&self.inner)
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+        serde_json::to_string(&self.inner).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+}
+
+/// JavaScript-friendly wrapper for SyncCoordinator's convergence status
+/// Only available when protocol support is enabled
+#[cfg(feature = "protocol-binary")]
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct WasmSyncCoordinator {
+    inner: crate::protocol::sync::SyncCoordinator,
+}
+
+#[cfg(feature = "protocol-binary")]
+#[wasm_bindgen]
+impl WasmSyncCoordinator {
+    /// Create a coordinator that knows nothing of the peer yet
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the document's current version; call after local edits
+    /// and after applying what the peer sent
+    #[wasm_bindgen(js_name = trackLocal)]
+    pub fn track_local(&mut self, document: &WasmDocument) {
+        self.inner.track_local(&document.inner);
+    }
+
+    /// `ConvergenceChecksum` JSON of the document, to send the peer
+    #[wasm_bindgen(js_name = checksum)]
+    pub fn checksum(&mut self, document: &WasmDocument) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.checksum(&document.inner))
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Check the peer's `ConvergenceChecksum` JSON against the document;
+    /// reply with `checksum` so the peer can check ours
+    ///
+    /// # Returns
+    /// JSON `SyncStatus` of the document afterwards
+    #[wasm_bindgen(js_name = receiveChecksum)]
+    pub fn receive_checksum(
+        &mut self,
+        document: &WasmDocument,
+        checksum_json: String,
+    ) -> Result<String, JsValue> {
+        let checksum = serde_json::from_str(&checksum_json)
+            .map_err(|e| js_error(SyncKitError::deserialization(e)))?;
+        let status = self
+            .inner
+            .receive_checksum(&document.inner, &checksum)
+            .map_err(js_error)?;
+        serde_json::to_string(&status).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// JSON `SyncStatus` of a document (backs the SDK's `useSyncState`)
+    #[wasm_bindgen(js_name = getSyncStatus)]
+    pub fn get_sync_status(&self, document_id: String) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.sync_status(&document_id))
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 }
 
@@ -185,6 +900,9 @@ impl WasmDelta {
 #[wasm_bindgen]
 pub struct WasmFugueText {
     inner: crate::crdt::FugueText,
+    undo: crate::undo::UndoManager,
+    changes: ChangeSink<crate::crdt::TextEvent>,
+    merge_job: Option<crate::crdt::TextMergeJob>,
 }
 
 #[cfg(feature = "text-crdt")]
@@ -194,11 +912,14 @@ impl WasmFugueText {
     #[wasm_bindgen(constructor)]
     pub fn new(client_id: String) -> Self {
         Self {
+            undo: crate::undo::UndoManager::new(client_id.clone()),
             inner: crate::crdt::FugueText::new(client_id),
+            changes: ChangeSink::new(),
+            merge_job: None,
         }
     }
 
-    /// Insert text at the given position
+    /// Insert text at the given position (undoable)
     ///
     /// # Arguments
     /// * `position` - Grapheme index (user-facing position)
@@ -209,15 +930,15 @@ impl WasmFugueText {
     #[wasm_bindgen(js_name = insert)]
     pub fn insert(&mut self, position: usize, text: String) -> Result<String, JsValue> {
         let node_id = self
-            .inner
-            .insert(position, &text)
-            .map_err(|e| JsValue::from_str(&format!("Insert failed: {}", e)))?;
+            .undo
+            .insert_text(&mut self.inner, position, &text)
+            .map_err(js_error)?;
+        self.changes.deliver()?;
 
-        serde_json::to_string(&node_id)
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+        serde_json::to_string(&node_id).map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
-    /// Delete text at the given position
+    /// Delete text at the given position (undoable)
     ///
     /// # Arguments
     /// * `position` - Starting grapheme index
@@ -228,12 +949,177 @@ impl WasmFugueText {
     #[wasm_bindgen(js_name = delete)]
     pub fn delete(&mut self, position: usize, length: usize) -> Result<String, JsValue> {
         let deleted_ids = self
+            .undo
+            .delete_text(&mut self.inner, position, length)
+            .map_err(js_error)?;
+        self.changes.deliver()?;
+
+        serde_json::to_string(&deleted_ids).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Insert text at a UTF-16 offset, as JavaScript strings and editor
+    /// selections count (undoable)
+    ///
+    /// @throws {SyncKitError} `INVALID_UTF16_POSITION` for an offset inside
+    /// a character, such as between the halves of an emoji's surrogate pair.
+    ///
+    /// # Returns
+    /// JSON string of NodeId for the created block
+    #[wasm_bindgen(js_name = insertUtf16)]
+    pub fn insert_utf16(&mut self, position: usize, text: String) -> Result<String, JsValue> {
+        let position = self.inner.utf16_to_grapheme(position).map_err(js_error)?;
+        self.insert(position, text)
+    }
+
+    /// Delete `length` UTF-16 code units from a UTF-16 offset (undoable)
+    ///
+    /// @throws {SyncKitError} `INVALID_UTF16_POSITION` if either end falls
+    /// inside a character.
+    ///
+    /// # Returns
+    /// JSON string of array of deleted NodeIds
+    #[wasm_bindgen(js_name = deleteUtf16)]
+    pub fn delete_utf16(&mut self, position: usize, length: usize) -> Result<String, JsValue> {
+        let end = position.saturating_add(length);
+        let text_length = self.inner.len_utf16();
+        if end > text_length {
+            return Err(js_error(crate::crdt::TextError::RangeOutOfBounds {
+                start: position,
+                end,
+                length: text_length,
+            }));
+        }
+        let start = self.inner.utf16_to_grapheme(position).map_err(js_error)?;
+        let end = self.inner.utf16_to_grapheme(end).map_err(js_error)?;
+        self.delete(start, end - start)
+    }
+
+    /// Replace `delete_len` characters at `position` with `text` in one
+    /// pass (undoable, the insert and the delete one at a time)
+    ///
+    /// # Returns
+    /// JSON string of `{deleted: [NodeId], inserted: NodeId | null}`
+    #[wasm_bindgen(js_name = splice)]
+    pub fn splice(
+        &mut self,
+        position: usize,
+        delete_len: usize,
+        text: String,
+    ) -> Result<String, JsValue> {
+        let result = self
+            .undo
+            .splice_text(&mut self.inner, position, delete_len, &text)
+            .map_err(js_error)?;
+        self.changes.deliver()?;
+
+        serde_json::to_string(&result).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Turn the text into `new_text`, such as a textarea's value after
+    /// an input event, with the fewest inserts and deletes a diff finds
+    /// (undoable, like `splice`)
+    ///
+    /// # Returns
+    /// JSON string of the change as Quill spans:
+    /// `[{"retain": n} | {"insert": text} | {"delete": n}]`
+    #[wasm_bindgen(js_name = applyTextDiff)]
+    pub fn apply_text_diff(&mut self, new_text: String) -> Result<String, JsValue> {
+        let change = self
+            .undo
+            .apply_text_diff(&mut self.inner, &new_text)
+            .map_err(js_error)?;
+        self.changes.deliver()?;
+
+        serde_json::to_string(&change).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Insert text at the given position (undoable), tagged with an
+    /// annotation (any JSON of at most 1 KiB)
+    ///
+    /// # Returns
+    /// JSON string of NodeId for the created block
+    #[wasm_bindgen(js_name = insertAnnotated)]
+    pub fn insert_annotated(
+        &mut self,
+        position: usize,
+        text: String,
+        annotation_json: String,
+    ) -> Result<String, JsValue> {
+        let payload = serde_json::from_str(&annotation_json)
+            .map_err(|e| js_error(SyncKitError::invalid_input(format!("Invalid JSON: {}", e))))?;
+        let undo = &mut self.undo;
+        let node_id = self
             .inner
-            .delete(position, length)
-            .map_err(|e| JsValue::from_str(&format!("Delete failed: {}", e)))?;
+            .annotated(payload, |inner| undo.insert_text(inner, position, &text))
+            .map_err(js_error)?
+            .map_err(js_error)?;
+        self.changes.deliver()?;
+
+        serde_json::to_string(&node_id).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// The annotations on the characters from `start` to `end` (JSON
+    /// array of `Annotation`)
+    #[wasm_bindgen(js_name = annotationsFor)]
+    pub fn annotations_for(&mut self, start: usize, end: usize) -> Result<String, JsValue> {
+        let annotations = self.inner.annotations_for(start..end).map_err(js_error)?;
+        serde_json::to_string(&annotations).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Set formatting `key` to a JSON value from `start` to `end`
+    ///
+    /// `expand` is "none", "before", "after" or "both": whether text typed
+    /// at either edge later takes the mark too.
+    #[wasm_bindgen(js_name = addMark)]
+    pub fn add_mark(
+        &mut self,
+        start: usize,
+        end: usize,
+        key: String,
+        value_json: String,
+        expand: String,
+    ) -> Result<(), JsValue> {
+        let value = serde_json::from_str(&value_json)
+            .map_err(|e| js_error(SyncKitError::invalid_input(format!("Invalid JSON: {}", e))))?;
+        let expand = serde_json::from_value(serde_json::Value::String(expand)).map_err(|e| {
+            js_error(SyncKitError::invalid_input(format!(
+                "Invalid expand: {}",
+                e
+            )))
+        })?;
+        self.inner
+            .add_mark(start..end, &key, value, expand)
+            .map_err(js_error)
+    }
+
+    /// Remove formatting `key` from `start` to `end`
+    #[wasm_bindgen(js_name = removeMark)]
+    pub fn remove_mark(&mut self, start: usize, end: usize, key: String) -> Result<(), JsValue> {
+        self.inner.remove_mark(start..end, &key).map_err(js_error)
+    }
+
+    /// The formatting of the character at `position` (JSON object of key
+    /// to value)
+    #[wasm_bindgen(js_name = marksAt)]
+    pub fn marks_at(&mut self, position: usize) -> Result<String, JsValue> {
+        let marks = self.inner.marks_at(position).map_err(js_error)?;
+        serde_json::to_string(&marks).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
 
-        serde_json::to_string(&deleted_ids)
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+    /// The formatted ranges of the text (JSON array of
+    /// `{range: {start, end}, key, value}`)
+    #[wasm_bindgen(js_name = markedRanges)]
+    pub fn marked_ranges(&mut self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.marked_ranges())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Who wrote each run of the text, for author colors (JSON array of
+    /// `{range: {start, end}, client_id, clock}`)
+    #[wasm_bindgen(js_name = attributedSpans)]
+    pub fn attributed_spans(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.attributed_spans())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
     /// Get the NodeId of the character at the given position
@@ -260,49 +1146,311 @@ impl WasmFugueText {
         let node_id = self
             .inner
             .get_node_id_at_position(position)
-            .map_err(|e| JsValue::from_str(&format!("Get NodeId failed: {}", e)))?;
+            .map_err(js_error)?;
+
+        serde_json::to_string(&node_id).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Get the current position of a character identified by NodeId
+    ///
+    /// This is the reverse of `getNodeIdAtPosition`. Given a stable NodeId,
+    /// returns the character's current position in the text. Returns -1 if
+    /// the character doesn't exist (e.g., was deleted).
+    ///
+    /// # Arguments
+    /// * `node_id_json` - JSON string of NodeId (format: {client_id, clock, offset})
+    ///
+    /// # Returns
+    /// Current position (0-based index), or -1 if character doesn't exist
+    ///
+    /// # Example
+    /// ```javascript
+    /// const nodeId = '{"client_id":"client1","clock":1,"offset":2}';
+    /// const position = text.getPositionOfNodeId(nodeId);
+    /// // Returns: 2 (or -1 if deleted)
+    /// ```
+    #[wasm_bindgen(js_name = getPositionOfNodeId)]
+    pub fn get_position_of_node_id(&mut self, node_id_json: &str) -> Result<i32, JsValue> {
+        let node_id: crate::crdt::text_fugue::NodeId = serde_json::from_str(node_id_json)
+            .map_err(|e| js_error(SyncKitError::invalid_input(format!("Invalid JSON: {}", e))))?;
+
+        match self.inner.get_position_of_node_id(&node_id) {
+            Some(pos) => Ok(pos as i32),
+            None => Ok(-1), // Character doesn't exist (deleted)
+        }
+    }
+
+    /// Get the text content as a string
+    #[wasm_bindgen(js_name = toString)]
+    #[allow(clippy::inherent_to_string)]
+    pub fn to_string(&self) -> String {
+        self.inner.to_string()
+    }
+
+    /// Get the length in graphemes (user-perceived characters)
+    #[wasm_bindgen(js_name = length)]
+    pub fn length(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Get the length in UTF-16 code units (JavaScript's `string.length`)
+    #[wasm_bindgen(js_name = lengthUtf16)]
+    pub fn length_utf16(&self) -> usize {
+        self.inner.len_utf16()
+    }
+
+    /// Check if the text is empty
+    #[wasm_bindgen(js_name = isEmpty)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Get the client ID
+    #[wasm_bindgen(js_name = getClientId)]
+    pub fn get_client_id(&self) -> String {
+        self.inner.client_id().to_string()
+    }
+
+    /// Get the current Lamport clock value
+    #[wasm_bindgen(js_name = getClock)]
+    pub fn get_clock(&self) -> u64 {
+        self.inner.clock()
+    }
+
+    /// Merge with another FugueText
+    #[wasm_bindgen(js_name = merge)]
+    pub fn merge(&mut self, other: &WasmFugueText) -> Result<(), JsValue> {
+        self.inner.merge(&other.inner).map_err(js_error)?;
+        self.changes.deliver()
+    }
+
+    /// Start merging `other` in slices run by `mergeIncremental`,
+    /// replacing any merge still in progress
+    ///
+    /// The text stays editable meanwhile; edits made before the merge
+    /// completes are kept.
+    #[wasm_bindgen(js_name = startMerge)]
+    pub fn start_merge(&mut self, other: &WasmFugueText) {
+        self.merge_job = Some(self.inner.start_merge(other.inner.clone()));
+    }
+
+    /// Run the merge from `startMerge` for about `budgetMs` milliseconds
+    /// (JSON `MergeProgress`)
+    ///
+    /// The merged text appears, and reaches `onChange`, in the call that
+    /// reports `complete`. Reports a complete merge when none is in
+    /// progress.
+    #[wasm_bindgen(js_name = mergeIncremental)]
+    pub fn merge_incremental(&mut self, budget_ms: u32) -> Result<String, JsValue> {
+        let budget = crate::merge_job::MergeBudget::Millis(budget_ms.into());
+        let progress = match &mut self.merge_job {
+            Some(job) => job.run_for(&mut self.inner, budget).map_err(js_error)?,
+            None => crate::merge_job::MergeProgress {
+                complete: true,
+                ..Default::default()
+            },
+        };
+        if progress.complete {
+            self.merge_job = None;
+        }
+        self.changes.deliver()?;
+        serde_json::to_string(&progress).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Register a callback for text changes
+    ///
+    /// Called with an array of `TextEvent` objects per batch: each call
+    /// that changes the text (`insert`, `merge`, `applyDelta`, `undo`, ...)
+    /// delivers one. Replaces any previous callback.
+    #[wasm_bindgen(js_name = onChange)]
+    pub fn on_change(&mut self, callback: js_sys::Function) {
+        if let Some(sink) = self.changes.set_callback(callback) {
+            self.inner.subscribe(sink);
+        }
+    }
+
+    /// Hold back change batches and deliver them merged, once
+    /// `maxChanges` events are held or a batch arrives `maxDelayMs` after
+    /// the first held one
+    #[wasm_bindgen(js_name = setCoalescing)]
+    pub fn set_coalescing(&mut self, max_delay_ms: u32, max_changes: usize) {
+        self.inner
+            .set_coalescing_policy(coalesce_policy(max_delay_ms, max_changes));
+    }
+
+    /// Deliver held-back changes to the `onChange` callback now
+    #[wasm_bindgen(js_name = flushNotifications)]
+    pub fn flush_notifications(&mut self) -> Result<(), JsValue> {
+        self.inner.flush_notifications();
+        self.changes.deliver()
+    }
+
+    /// Estimated heap bytes per subsystem (JSON `MemoryUsage`)
+    #[wasm_bindgen(js_name = memoryUsage)]
+    pub fn memory_usage(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.memory_usage())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Block, tombstone and byte counts against the visible text (JSON
+    /// `TextMemoryStats`)
+    #[wasm_bindgen(js_name = memoryStats)]
+    pub fn memory_stats(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.memory_stats())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Export as JSON string (for persistence/network)
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Import from JSON string (for loading from persistence/network)
+    ///
+    /// With `limits` (JSON `TextLimits`, omitted fields unlimited), the
+    /// text keeps them for later edits and merges.
+    ///
+    /// @throws {SyncKitError} `LIMIT_EXCEEDED` for a text over `limits`,
+    /// before building it
+    #[wasm_bindgen(js_name = fromJSON)]
+    pub fn from_json(json: String, limits: Option<String>) -> Result<WasmFugueText, JsValue> {
+        let limits = match limits {
+            Some(limits) => serde_json::from_str(&limits)
+                .map_err(|e| js_error(SyncKitError::deserialization(e)))?,
+            None => crate::crdt::text_fugue::TextLimits::default(),
+        };
+        let inner =
+            crate::crdt::FugueText::from_json_with_limits(&json, limits).map_err(js_error)?;
+
+        Ok(Self {
+            undo: crate::undo::UndoManager::new(inner.client_id().to_string()),
+            inner,
+            changes: ChangeSink::new(),
+            merge_job: None,
+        })
+    }
+
+    /// Bound how large the text may grow (JSON `TextLimits`, omitted
+    /// fields unlimited)
+    ///
+    /// @throws {SyncKitError} `LIMIT_EXCEEDED` from later edits and merges
+    /// past the limits, which change nothing
+    #[wasm_bindgen(js_name = setLimits)]
+    pub fn set_limits(&mut self, limits: String) -> Result<(), JsValue> {
+        let limits = serde_json::from_str(&limits)
+            .map_err(|e| js_error(SyncKitError::deserialization(e)))?;
+        self.inner.set_limits(limits);
+        Ok(())
+    }
+
+    /// Undo the last local insert or delete
+    ///
+    /// Returns false if there was nothing to undo.
+    #[wasm_bindgen(js_name = undo)]
+    pub fn undo(&mut self) -> Result<bool, JsValue> {
+        let undone = self.undo.undo(&mut self.inner).map_err(js_error)?;
+        self.changes.deliver()?;
+        Ok(undone)
+    }
+
+    /// Redo the last undone edit
+    ///
+    /// Returns false if there was nothing to redo.
+    #[wasm_bindgen(js_name = redo)]
+    pub fn redo(&mut self) -> Result<bool, JsValue> {
+        let redone = self.undo.redo(&mut self.inner).map_err(js_error)?;
+        self.changes.deliver()?;
+        Ok(redone)
+    }
+
+    /// Check if there is anything to undo
+    #[wasm_bindgen(js_name = canUndo)]
+    pub fn can_undo(&self) -> bool {
+        self.undo.can_undo()
+    }
 
-        serde_json::to_string(&node_id)
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+    /// Check if there is anything to redo
+    #[wasm_bindgen(js_name = canRedo)]
+    pub fn can_redo(&self) -> bool {
+        self.undo.can_redo()
     }
 
-    /// Get the current position of a character identified by NodeId
-    ///
-    /// This is the reverse of `getNodeIdAtPosition`. Given a stable NodeId,
-    /// returns the character's current position in the text. Returns -1 if
-    /// the character doesn't exist (e.g., was deleted).
+    /// Export the undo and redo stacks (bytes)
     ///
-    /// # Arguments
-    /// * `node_id_json` - JSON string of NodeId (format: {client_id, clock, offset})
+    /// Store them next to the `toJSON` snapshot; after `fromJSON`, hand
+    /// them to `importUndoHistory` so undo keeps working across a reload.
+    #[wasm_bindgen(js_name = exportUndoHistory)]
+    pub fn export_undo_history(&self) -> Result<Vec<u8>, JsValue> {
+        self.undo.to_bytes().map_err(js_error)
+    }
+
+    /// Replace the undo history with one from `exportUndoHistory`
     ///
-    /// # Returns
-    /// Current position (0-based index), or -1 if character doesn't exist
+    /// Steps about text this replica doesn't have are dropped.
+    #[wasm_bindgen(js_name = importUndoHistory)]
+    pub fn import_undo_history(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.undo =
+            crate::undo::UndoManager::from_bytes(bytes, &mut self.inner).map_err(js_error)?;
+        Ok(())
+    }
+
+    /// Check if anything may have changed since the last `takeDirty`
+    #[wasm_bindgen(js_name = isDirty)]
+    pub fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+
+    /// Take the blocks and deletions added since the last call, as JSON
+    /// bytes
     ///
-    /// # Example
-    /// ```javascript
-    /// const nodeId = '{"client_id":"client1","clock":1,"offset":2}';
-    /// const position = text.getPositionOfNodeId(nodeId);
-    /// // Returns: 2 (or -1 if deleted)
-    /// ```
-    #[wasm_bindgen(js_name = getPositionOfNodeId)]
-    pub fn get_position_of_node_id(&mut self, node_id_json: &str) -> Result<i32, JsValue> {
-        let node_id: crate::crdt::text_fugue::NodeId = serde_json::from_str(node_id_json)
-            .map_err(|e| JsValue::from_str(&format!("JSON parse failed: {}", e)))?;
+    /// Store each chunk next to the last `toJSON` snapshot; after
+    /// `fromJSON`, replay them in order with `applyDirty`.
+    #[wasm_bindgen(js_name = takeDirty)]
+    pub fn take_dirty(&mut self) -> Result<Vec<u8>, JsValue> {
+        serde_json::to_vec(&self.inner.take_dirty())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
 
-        match self.inner.get_position_of_node_id(&node_id) {
-            Some(pos) => Ok(pos as i32),
-            None => Ok(-1), // Character doesn't exist (deleted)
+    /// Replay a chunk from `takeDirty`
+    #[wasm_bindgen(js_name = applyDirty)]
+    pub fn apply_dirty(&mut self, chunk: &[u8]) -> Result<(), JsValue> {
+        let delta: crate::crdt::text_fugue::TextDelta = serde_json::from_slice(chunk)
+            .map_err(|e| js_error(SyncKitError::deserialization(e)))?;
+        self.inner.apply_dirty(&delta).map_err(js_error)?;
+        self.changes.deliver()
+    }
+
+    /// Create a read-only view frozen at the current text
+    ///
+    /// O(1): the view shares storage with this text instead of copying it,
+    /// and stays valid after this text is mutated or freed.
+    #[wasm_bindgen(js_name = snapshotView)]
+    pub fn snapshot_view(&self) -> WasmTextView {
+        WasmTextView {
+            inner: self.inner.snapshot(),
         }
     }
+}
 
-    /// Get the text content as a string
+/// Read-only view of a FugueText (see `WasmFugueText.snapshotView`)
+#[cfg(feature = "text-crdt")]
+#[wasm_bindgen]
+pub struct WasmTextView {
+    inner: crate::crdt::TextSnapshot,
+}
+
+#[cfg(feature = "text-crdt")]
+#[wasm_bindgen]
+impl WasmTextView {
+    /// Get the text content
     #[wasm_bindgen(js_name = toString)]
     #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         self.inner.to_string()
     }
 
-    /// Get the length in graphemes (user-perceived characters)
+    /// Get the length (same units as `WasmFugueText.length`)
     #[wasm_bindgen(js_name = length)]
     pub fn length(&self) -> usize {
         self.inner.len()
@@ -314,40 +1462,163 @@ impl WasmFugueText {
         self.inner.is_empty()
     }
 
-    /// Get the client ID
-    #[wasm_bindgen(js_name = getClientId)]
-    pub fn get_client_id(&self) -> String {
-        self.inner.client_id().to_string()
+    /// Get the text between `start` and `end` (exclusive)
+    #[wasm_bindgen(js_name = slice)]
+    pub fn slice(&self, start: usize, end: usize) -> Result<String, JsValue> {
+        self.inner.slice(start, end).map_err(js_error)
     }
+}
 
-    /// Get the current Lamport clock value
-    #[wasm_bindgen(js_name = getClock)]
-    pub fn get_clock(&self) -> u64 {
-        self.inner.clock()
+/// Line index of a FugueText, for converting between offsets and
+/// (line, column) without rescanning the text
+///
+/// Feed it every batch from the text's `onChange` callback:
+///
+/// ```javascript
+/// const lines = new WasmLineIndex(text);
+/// text.onChange((events) => lines.applyChanges(JSON.stringify(events)));
+/// ```
+#[cfg(feature = "text-crdt")]
+#[wasm_bindgen]
+pub struct WasmLineIndex {
+    inner: crate::crdt::LineIndex,
+}
+
+#[cfg(feature = "text-crdt")]
+#[wasm_bindgen]
+impl WasmLineIndex {
+    /// Index the text's current content
+    #[wasm_bindgen(constructor)]
+    pub fn new(text: &WasmFugueText) -> Self {
+        Self {
+            inner: crate::crdt::LineIndex::of(&text.inner),
+        }
     }
 
-    /// Merge with another FugueText
-    #[wasm_bindgen(js_name = merge)]
-    pub fn merge(&mut self, other: &WasmFugueText) -> Result<(), JsValue> {
+    /// Apply one `onChange` batch (`TextEvent[]` JSON)
+    #[wasm_bindgen(js_name = applyChanges)]
+    pub fn apply_changes(&mut self, events_json: String) -> Result<(), JsValue> {
+        let events: Vec<crate::crdt::TextEvent> =
+            serde_json::from_str(&events_json).map_err(|e| {
+                js_error(SyncKitError::invalid_input(format!(
+                    "Invalid text events: {}",
+                    e
+                )))
+            })?;
+        self.inner.apply(&events).map_err(js_error)
+    }
+
+    /// Number of lines; an empty text has one
+    #[wasm_bindgen(js_name = lineCount)]
+    pub fn line_count(&self) -> usize {
+        self.inner.line_count()
+    }
+
+    /// Batches applied so far, for `linesChangedSince`
+    #[wasm_bindgen(js_name = generation)]
+    pub fn generation(&self) -> u64 {
+        self.inner.generation()
+    }
+
+    /// The `LinePosition` JSON of `offset`, or undefined past the end
+    #[wasm_bindgen(js_name = offsetToPosition)]
+    pub fn offset_to_position(&self, offset: usize) -> Result<Option<String>, JsValue> {
         self.inner
-            .merge(&other.inner)
-            .map_err(|e| JsValue::from_str(&format!("Merge failed: {}", e)))
+            .offset_to_position(offset)
+            .map(|position| {
+                serde_json::to_string(&position)
+                    .map_err(|e| js_error(SyncKitError::serialization(e)))
+            })
+            .transpose()
+    }
+
+    /// The offset of `line` and `column`, or undefined if there is no
+    /// such position
+    #[wasm_bindgen(js_name = positionToOffset)]
+    pub fn position_to_offset(&self, line: usize, column: usize) -> Option<usize> {
+        self.inner
+            .position_to_offset(crate::crdt::LinePosition { line, column })
     }
 
-    /// Export as JSON string (for persistence/network)
-    #[wasm_bindgen(js_name = toJSON)]
-    pub fn to_json(&self) -> Result<String, JsValue> {
-        serde_json::to_string(&self.inner)
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+    /// The lines changed since `generation`, as `[start, end]` (end
+    /// exclusive), or undefined if none
+    #[wasm_bindgen(js_name = linesChangedSince)]
+    pub fn lines_changed_since(&self, generation: u64) -> Option<Vec<usize>> {
+        self.inner
+            .lines_changed_since(generation)
+            .map(|range| vec![range.start, range.end])
     }
+}
 
-    /// Import from JSON string (for loading from persistence/network)
-    #[wasm_bindgen(js_name = fromJSON)]
-    pub fn from_json(json: String) -> Result<WasmFugueText, JsValue> {
-        let inner: crate::crdt::FugueText = serde_json::from_str(&json)
-            .map_err(|e| JsValue::from_str(&format!("JSON deserialization failed: {}", e)))?;
+/// Binary delta sync for FugueText
+/// Only available when protocol support is enabled (core variant, not core-lite)
+#[cfg(all(feature = "text-crdt", feature = "protocol-binary"))]
+#[wasm_bindgen]
+impl WasmFugueText {
+    /// Encode this replica's state vector (protobuf bytes)
+    ///
+    /// Send this to a peer so it can reply with `encodeDelta`.
+    #[wasm_bindgen(js_name = encodeStateVector)]
+    pub fn encode_state_vector(&self) -> Vec<u8> {
+        self.inner.encode_state_vector()
+    }
 
-        Ok(Self { inner })
+    /// Encode the changes a peer with the given state vector is missing
+    ///
+    /// # Example (JavaScript)
+    /// ```javascript
+    /// const delta = local.encodeDelta(remote.encodeStateVector());
+    /// const events = JSON.parse(remote.applyDelta(delta));
+    /// // events: [{"type":"insert","position":0,"text":"Hello"}]
+    /// ```
+    #[wasm_bindgen(js_name = encodeDelta)]
+    pub fn encode_delta(&self, remote_state_vector: &[u8]) -> Result<Vec<u8>, JsValue> {
+        self.inner
+            .encode_diff(remote_state_vector)
+            .map_err(js_error)
+    }
+
+    /// Apply a delta produced by `encodeDelta` on another replica
+    ///
+    /// # Returns
+    /// JSON array of text change events (`insert` / `delete`)
+    #[wasm_bindgen(js_name = applyDelta)]
+    pub fn apply_delta(&mut self, delta: &[u8]) -> Result<String, JsValue> {
+        let events = self.inner.apply_diff(delta).map_err(js_error)?;
+        self.changes.deliver()?;
+
+        serde_json::to_string(&events).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Export as protobuf bytes, several times smaller than `toJSON`
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.inner.to_proto_bytes()
+    }
+
+    /// Import from `toBytes` output
+    ///
+    /// With `limits` (JSON `TextLimits`, omitted fields unlimited), the
+    /// text keeps them for later edits and merges.
+    ///
+    /// @throws {SyncKitError} `LIMIT_EXCEEDED` for a text over `limits`,
+    /// before building it
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8], limits: Option<String>) -> Result<WasmFugueText, JsValue> {
+        let limits = match limits {
+            Some(limits) => serde_json::from_str(&limits)
+                .map_err(|e| js_error(SyncKitError::deserialization(e)))?,
+            None => crate::crdt::text_fugue::TextLimits::default(),
+        };
+        let inner = crate::crdt::FugueText::from_proto_bytes_with_limits(bytes, limits)
+            .map_err(js_error)?;
+
+        Ok(Self {
+            undo: crate::undo::UndoManager::new(inner.client_id().to_string()),
+            inner,
+            changes: ChangeSink::new(),
+            merge_job: None,
+        })
     }
 }
 
@@ -401,9 +1672,37 @@ impl WasmCounter {
     }
 
     /// Merge with another counter
+    ///
+    /// # Returns
+    /// JSON `CounterMergeReport`: `{"changed": bool, "valueBefore": n, "valueAfter": n}`
     #[wasm_bindgen(js_name = merge)]
-    pub fn merge(&mut self, other: &WasmCounter) {
+    pub fn merge(&mut self, other: &WasmCounter) -> Result<String, JsValue> {
+        let value_before = self.inner.value();
         self.inner.merge(&other.inner);
+        self.merge_summary(value_before)
+    }
+
+    /// Take the local changes since the last call as delta bytes
+    ///
+    /// Send the bytes to other replicas and pass them to `applyDelta`.
+    #[wasm_bindgen(js_name = splitDelta)]
+    pub fn split_delta(&mut self) -> Result<Vec<u8>, JsValue> {
+        serde_json::to_vec(&self.inner.split_delta())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Apply delta bytes produced by `splitDelta` on another replica
+    ///
+    /// # Returns
+    /// JSON `CounterMergeReport`, same shape as `merge`
+    #[wasm_bindgen(js_name = applyDelta)]
+    pub fn apply_delta(&mut self, delta: &[u8]) -> Result<String, JsValue> {
+        let delta: crate::crdt::PNCounter = serde_json::from_slice(delta)
+            .map_err(|e| js_error(SyncKitError::deserialization(e)))?;
+
+        let value_before = self.inner.value();
+        self.inner.apply_delta(&delta);
+        self.merge_summary(value_before)
     }
 
     /// Reset the counter to zero (local operation)
@@ -415,20 +1714,33 @@ impl WasmCounter {
     /// Export as JSON string
     #[wasm_bindgen(js_name = toJSON)]
     pub fn to_json(&self) -> Result<String, JsValue> {
-        serde_json::to_string(&self.inner)
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+        serde_json::to_string(&self.inner).map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
     /// Import from JSON string
     #[wasm_bindgen(js_name = fromJSON)]
     pub fn from_json(json: String) -> Result<WasmCounter, JsValue> {
-        let inner: crate::crdt::PNCounter = serde_json::from_str(&json)
-            .map_err(|e| JsValue::from_str(&format!("JSON deserialization failed: {}", e)))?;
+        let inner: crate::crdt::PNCounter =
+            serde_json::from_str(&json).map_err(|e| js_error(SyncKitError::deserialization(e)))?;
 
         Ok(Self { inner })
     }
 }
 
+#[cfg(feature = "counters")]
+impl WasmCounter {
+    fn merge_summary(&self, value_before: i64) -> Result<String, JsValue> {
+        let value_after = self.inner.value();
+        let report = super::types::CounterMergeReport {
+            changed: value_before != value_after,
+            value_before,
+            value_after,
+        };
+
+        serde_json::to_string(&report).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+}
+
 /// JavaScript-friendly wrapper for ORSet CRDT
 /// Only available when sets feature is enabled
 #[cfg(feature = "sets")]
@@ -491,42 +1803,118 @@ impl WasmSet {
     #[wasm_bindgen(js_name = values)]
     pub fn values(&self) -> Result<String, JsValue> {
         let values: Vec<_> = self.inner.iter().collect();
-        serde_json::to_string(&values)
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+        serde_json::to_string(&values).map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
-    /// Clear all elements from the set
+    /// Remove every element this replica has seen
+    ///
+    /// Elements another replica adds concurrently survive the merge, so
+    /// a "remove all" button never loses a tag someone added at the same
+    /// time. Use `reset` to drop those too.
     #[wasm_bindgen(js_name = clear)]
     pub fn clear(&mut self) {
-        self.inner.clear();
+        self.inner.clear_all_observed();
+    }
+
+    /// Empty the set on every replica the reset reaches, dropping adds
+    /// made concurrently with it
+    #[wasm_bindgen(js_name = reset)]
+    pub fn reset(&mut self) {
+        self.inner.reset();
     }
 
     /// Merge with another set
+    ///
+    /// # Returns
+    /// JSON `SetMergeReport`: `{"added": [...], "removed": [...]}`
     #[wasm_bindgen(js_name = merge)]
-    pub fn merge(&mut self, other: &WasmSet) {
+    pub fn merge(&mut self, other: &WasmSet) -> Result<String, JsValue> {
+        let before = self.snapshot();
         self.inner.merge(&other.inner);
+        self.merge_summary(before)
+    }
+
+    /// Take the local adds and removes since the last call as delta bytes
+    ///
+    /// Send the bytes to other replicas and pass them to `applyDelta`.
+    #[wasm_bindgen(js_name = splitDelta)]
+    pub fn split_delta(&mut self) -> Result<Vec<u8>, JsValue> {
+        serde_json::to_vec(&self.inner.split_delta())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Apply delta bytes produced by `splitDelta` on another replica
+    ///
+    /// # Returns
+    /// JSON `SetMergeReport`, same shape as `merge`
+    #[wasm_bindgen(js_name = applyDelta)]
+    pub fn apply_delta(&mut self, delta: &[u8]) -> Result<String, JsValue> {
+        let delta: crate::crdt::ORSet<String> = serde_json::from_slice(delta)
+            .map_err(|e| js_error(SyncKitError::deserialization(e)))?;
+
+        let before = self.snapshot();
+        self.inner.apply_delta(&delta);
+        self.merge_summary(before)
     }
 
     /// Export as JSON string
     #[wasm_bindgen(js_name = toJSON)]
     pub fn to_json(&self) -> Result<String, JsValue> {
-        serde_json::to_string(&self.inner)
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+        serde_json::to_string(&self.inner).map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
     /// Import from JSON string
     #[wasm_bindgen(js_name = fromJSON)]
     pub fn from_json(json: String) -> Result<WasmSet, JsValue> {
-        let inner: crate::crdt::ORSet<String> = serde_json::from_str(&json)
-            .map_err(|e| JsValue::from_str(&format!("JSON deserialization failed: {}", e)))?;
+        let inner: crate::crdt::ORSet<String> =
+            serde_json::from_str(&json).map_err(|e| js_error(SyncKitError::deserialization(e)))?;
 
         Ok(Self { inner })
     }
 }
+
+#[cfg(feature = "sets")]
+impl WasmSet {
+    fn snapshot(&self) -> std::collections::BTreeSet<String> {
+        self.inner.iter().cloned().collect()
+    }
+
+    fn merge_summary(&self, before: std::collections::BTreeSet<String>) -> Result<String, JsValue> {
+        let after = self.snapshot();
+        let report = super::types::SetMergeReport {
+            added: after.difference(&before).cloned().collect(),
+            removed: before.difference(&after).cloned().collect(),
+        };
+
+        serde_json::to_string(&report).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+}
+
 /// JavaScript-friendly wrapper for Awareness
 #[wasm_bindgen]
 pub struct WasmAwareness {
     inner: crate::awareness::Awareness,
+    /// Events fired by the last call, handed to the change callback
+    pending: std::sync::Arc<std::sync::Mutex<Vec<crate::awareness::AwarenessEvent>>>,
+    on_change: Option<js_sys::Function>,
+}
+
+impl WasmAwareness {
+    /// Deliver the events fired by the last call to the change callback
+    fn flush_events(&mut self) -> Result<(), JsValue> {
+        let events = std::mem::take(&mut *self.pending.lock().unwrap());
+        let Some(callback) = &self.on_change else {
+            return Ok(());
+        };
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let json =
+            serde_json::to_string(&events).map_err(|e| js_error(SyncKitError::serialization(e)))?;
+        callback.call1(&JsValue::NULL, &js_sys::JSON::parse(&json)?)?;
+        Ok(())
+    }
 }
 
 #[wasm_bindgen]
@@ -534,11 +1922,30 @@ impl WasmAwareness {
     /// Create a new awareness instance
     #[wasm_bindgen(constructor)]
     pub fn new(client_id: String) -> Self {
+        let mut inner = crate::awareness::Awareness::new(client_id);
+        let pending = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = pending.clone();
+        inner.subscribe(move |event| sink.lock().unwrap().push(event.clone()));
+        #[cfg(target_arch = "wasm32")]
+        inner.set_time_source(JsTimeProvider);
+
         Self {
-            inner: crate::awareness::Awareness::new(client_id),
+            inner,
+            pending,
+            on_change: None,
         }
     }
 
+    /// Register a callback for presence changes
+    ///
+    /// Called after `setLocalState`, `applyUpdate`, `applyDiff` and
+    /// `removeStaleClients` with an array of `AwarenessEvent` objects, if
+    /// anything visible changed. Replaces any previous callback.
+    #[wasm_bindgen(js_name = onAwarenessChange)]
+    pub fn on_awareness_change(&mut self, callback: js_sys::Function) {
+        self.on_change = Some(callback);
+    }
+
     /// Get the local client ID
     #[wasm_bindgen(js_name = getClientId)]
     pub fn get_client_id(&self) -> String {
@@ -549,29 +1956,188 @@ impl WasmAwareness {
     #[wasm_bindgen(js_name = setLocalState)]
     pub fn set_local_state(&mut self, state_json: String) -> Result<String, JsValue> {
         let state: serde_json::Value = serde_json::from_str(&state_json)
-            .map_err(|e| JsValue::from_str(&format!("Invalid JSON: {}", e)))?;
+            .map_err(|e| js_error(SyncKitError::invalid_input(format!("Invalid JSON: {}", e))))?;
 
-        let update = self.inner.set_local_state(state);
+        let update = self.inner.set_local_state(state).map_err(js_error)?;
+        self.flush_events()?;
 
-        serde_json::to_string(&update)
-            .map_err(|e| JsValue::from_str(&format!("Serialization failed: {}", e)))
+        serde_json::to_string(&update).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Merge a JSON merge patch into the local state (pass JSON string)
+    ///
+    /// Returns the update to broadcast, carrying only the changed keys.
+    #[wasm_bindgen(js_name = updateLocalState)]
+    pub fn update_local_state(&mut self, patch_json: String) -> Result<String, JsValue> {
+        let patch: serde_json::Value = serde_json::from_str(&patch_json)
+            .map_err(|e| js_error(SyncKitError::invalid_input(format!("Invalid JSON: {}", e))))?;
+
+        let update = self.inner.update_local_state(patch).map_err(js_error)?;
+        self.flush_events()?;
+
+        serde_json::to_string(&update).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Full local state as an update JSON string, to answer a peer whose
+    /// `applyUpdate` returned true
+    #[wasm_bindgen(js_name = getLocalStateUpdate)]
+    pub fn get_local_state_update(&self) -> Result<Option<String>, JsValue> {
+        match self.inner.local_state_update() {
+            Some(update) => serde_json::to_string(&update)
+                .map(Some)
+                .map_err(|e| js_error(SyncKitError::serialization(e))),
+            None => Ok(None),
+        }
     }
 
     /// Apply remote awareness update (pass JSON string)
+    ///
+    /// Returns true if the update was a patch onto a state we don't have;
+    /// ask the sender for `getLocalStateUpdate()`.
     #[wasm_bindgen(js_name = applyUpdate)]
-    pub fn apply_update(&mut self, update_json: String) -> Result<(), JsValue> {
+    pub fn apply_update(&mut self, update_json: String) -> Result<bool, JsValue> {
         let update: crate::awareness::AwarenessUpdate = serde_json::from_str(&update_json)
-            .map_err(|e| JsValue::from_str(&format!("Invalid update JSON: {}", e)))?;
+            .map_err(|e| {
+                js_error(SyncKitError::invalid_input(format!(
+                    "Invalid update JSON: {}",
+                    e
+                )))
+            })?;
+
+        let outcome = self.inner.apply_update(update);
+        self.flush_events()?;
+        Ok(outcome == crate::awareness::UpdateOutcome::NeedsFullState)
+    }
+
+    /// Newest clock seen per client as JSON (`AwarenessVersion`), to send
+    /// to a peer that answers with `encodeDiff`
+    #[wasm_bindgen(js_name = getVersion)]
+    pub fn get_version(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.inner.version())
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Clients that changed or left since a peer's version, as JSON
+    /// (`AwarenessDiff`)
+    #[wasm_bindgen(js_name = encodeDiff)]
+    pub fn encode_diff(&self, version_json: String) -> Result<String, JsValue> {
+        let since: crate::awareness::AwarenessVersion = serde_json::from_str(&version_json)
+            .map_err(|e| {
+                js_error(SyncKitError::invalid_input(format!(
+                    "Invalid version JSON: {}",
+                    e
+                )))
+            })?;
+
+        serde_json::to_string(&self.inner.encode_diff(&since))
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Apply a diff from `encodeDiff` (pass JSON string)
+    #[wasm_bindgen(js_name = applyDiff)]
+    pub fn apply_diff(&mut self, diff_json: String) -> Result<(), JsValue> {
+        let diff: crate::awareness::AwarenessDiff =
+            serde_json::from_str(&diff_json).map_err(|e| {
+                js_error(SyncKitError::invalid_input(format!(
+                    "Invalid diff JSON: {}",
+                    e
+                )))
+            })?;
+
+        self.inner.apply_diff(diff);
+        self.flush_events()
+    }
+
+    /// Put our cursor at `position` in a document's text, optionally
+    /// selecting up to `selectionEnd`; returns the update JSON to broadcast
+    #[cfg(feature = "text-crdt")]
+    #[wasm_bindgen(js_name = setCursor)]
+    pub fn set_cursor(
+        &mut self,
+        document_id: String,
+        text: &mut WasmFugueText,
+        position: usize,
+        selection_end: Option<usize>,
+    ) -> Result<String, JsValue> {
+        let update = self
+            .inner
+            .set_cursor(&document_id, &mut text.inner, position, selection_end)
+            .map_err(js_error)?;
+        self.flush_events()?;
+
+        serde_json::to_string(&update).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Other clients' cursors in a document resolved against `text`, as a
+    /// JSON array of `RemoteCursor`
+    #[cfg(feature = "text-crdt")]
+    #[wasm_bindgen(js_name = remoteCursors)]
+    pub fn remote_cursors(
+        &self,
+        document_id: String,
+        text: &mut WasmFugueText,
+    ) -> Result<String, JsValue> {
+        let cursors: Vec<super::types::RemoteCursor> = self
+            .inner
+            .remote_cursors(&document_id, &mut text.inner)
+            .into_iter()
+            .map(|(client_id, cursor)| super::types::RemoteCursor { client_id, cursor })
+            .collect();
 
-        self.inner.apply_update(update);
-        Ok(())
+        serde_json::to_string(&cursors).map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
     /// Get all client states as JSON string
     #[wasm_bindgen(js_name = getStates)]
     pub fn get_states(&self) -> Result<String, JsValue> {
         serde_json::to_string(self.inner.get_states())
-            .map_err(|e| JsValue::from_str(&format!("Serialization failed: {}", e)))
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Validate presence states against a JSON Schema (subset, see
+    /// `StateSchema`): invalid local states throw, invalid remote ones are
+    /// dropped, or with `sanitize` stripped of keys the schema doesn't
+    /// list. Violations arrive as `invalid` events.
+    ///
+    /// @throws {SyncKitError} if `schema_json` isn't JSON or a schema
+    /// `StateSchema` supports
+    #[wasm_bindgen(js_name = setStateSchema)]
+    pub fn set_state_schema(&mut self, schema_json: String, sanitize: bool) -> Result<(), JsValue> {
+        let schema: serde_json::Value = serde_json::from_str(&schema_json).map_err(|e| {
+            js_error(SyncKitError::invalid_input(format!(
+                "Invalid schema JSON: {}",
+                e
+            )))
+        })?;
+        let schema = crate::awareness::StateSchema::from_json(&schema).map_err(js_error)?;
+
+        let policy = if sanitize {
+            crate::awareness::ValidationPolicy::Sanitize {
+                allowed_keys: schema.property_names(),
+            }
+        } else {
+            crate::awareness::ValidationPolicy::Reject
+        };
+        self.inner
+            .set_validator(move |_, state| schema.validate(state), policy);
+        Ok(())
+    }
+
+    /// Hold timed-out clients for `grace_ms` so a quick reconnect fires
+    /// `rejoined` instead of `left` + `joined`
+    #[wasm_bindgen(js_name = setGracePeriod)]
+    pub fn set_grace_period(&mut self, grace_ms: u64) {
+        self.inner
+            .set_grace_period(std::time::Duration::from_millis(grace_ms));
+    }
+
+    /// Timed-out clients still within the grace period, as a JSON array
+    /// of states (for a "reconnecting…" UI)
+    #[wasm_bindgen(js_name = getRecentlyLeft)]
+    pub fn get_recently_left(&self) -> Result<String, JsValue> {
+        let mut held: Vec<_> = self.inner.recently_left().collect();
+        held.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        serde_json::to_string(&held).map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
     /// Get state for specific client as JSON string
@@ -580,7 +2146,7 @@ impl WasmAwareness {
         match self.inner.get_state(&client_id) {
             Some(state) => serde_json::to_string(state)
                 .map(Some)
-                .map_err(|e| JsValue::from_str(&format!("Serialization failed: {}", e))),
+                .map_err(|e| js_error(SyncKitError::serialization(e))),
             None => Ok(None),
         }
     }
@@ -591,7 +2157,7 @@ impl WasmAwareness {
         match self.inner.get_local_state() {
             Some(state) => serde_json::to_string(state)
                 .map(Some)
-                .map_err(|e| JsValue::from_str(&format!("Serialization failed: {}", e))),
+                .map_err(|e| js_error(SyncKitError::serialization(e))),
             None => Ok(None),
         }
     }
@@ -608,9 +2174,55 @@ impl WasmAwareness {
 
         #[cfg(target_arch = "wasm32")]
         let removed = self.inner.remove_stale_clients(timeout_ms);
+        self.flush_events()?;
+
+        serde_json::to_string(&removed).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Whether a heartbeat should be sent (`now_ms` from `Date.now()`)
+    #[wasm_bindgen(js_name = heartbeatDue)]
+    pub fn heartbeat_due(&self, now_ms: u64, interval_ms: u64) -> bool {
+        self.inner.heartbeat_due(now_ms, interval_ms)
+    }
+
+    /// Create a keepalive update (JSON string) that bumps our sequence
+    /// without changing state; undefined before `setLocalState`
+    ///
+    /// # Example (JavaScript)
+    /// ```javascript
+    /// setInterval(() => {
+    ///   if (awareness.heartbeatDue(BigInt(Date.now()), 10_000n)) {
+    ///     send(awareness.createHeartbeatUpdate(BigInt(Date.now())));
+    ///   }
+    /// }, 1_000);
+    /// ```
+    #[wasm_bindgen(js_name = createHeartbeatUpdate)]
+    pub fn create_heartbeat_update(&mut self, now_ms: u64) -> Result<Option<String>, JsValue> {
+        let Some(update) = self.inner.create_heartbeat_update(now_ms) else {
+            return Ok(None);
+        };
+        self.flush_events()?;
+        serde_json::to_string(&update)
+            .map(Some)
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// `heartbeatDue` at `Date.now()`
+    #[wasm_bindgen(js_name = heartbeatDueNow)]
+    pub fn heartbeat_due_now(&self, interval_ms: u64) -> bool {
+        self.inner.heartbeat_due_now(interval_ms)
+    }
 
-        serde_json::to_string(&removed)
-            .map_err(|e| JsValue::from_str(&format!("Serialization failed: {}", e)))
+    /// `createHeartbeatUpdate` at `Date.now()`
+    #[wasm_bindgen(js_name = createHeartbeatUpdateNow)]
+    pub fn create_heartbeat_update_now(&mut self) -> Result<Option<String>, JsValue> {
+        let Some(update) = self.inner.create_heartbeat_update_now() else {
+            return Ok(None);
+        };
+        self.flush_events()?;
+        serde_json::to_string(&update)
+            .map(Some)
+            .map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
     /// Create update to signal leaving
@@ -618,8 +2230,7 @@ impl WasmAwareness {
     pub fn create_leave_update(&self) -> Result<String, JsValue> {
         let update = self.inner.create_leave_update();
 
-        serde_json::to_string(&update)
-            .map_err(|e| JsValue::from_str(&format!("Serialization failed: {}", e)))
+        serde_json::to_string(&update).map_err(|e| js_error(SyncKitError::serialization(e)))
     }
 
     /// Get number of online clients
@@ -634,3 +2245,708 @@ impl WasmAwareness {
         self.inner.other_client_count()
     }
 }
+
+/// Binary (protobuf) encoding of awareness updates and diffs
+/// Only available when protocol support is enabled (core variant, not core-lite)
+#[cfg(feature = "protocol-binary")]
+#[wasm_bindgen]
+impl WasmAwareness {
+    /// Encode an update (JSON string, `AwarenessUpdate`) as protobuf bytes
+    ///
+    /// # Example (JavaScript)
+    /// ```javascript
+    /// const bytes = WasmAwareness.updateToBytes(awareness.setLocalState(state));
+    /// socket.send(bytes);
+    /// // receiver
+    /// remote.applyUpdate(WasmAwareness.updateFromBytes(new Uint8Array(data)));
+    /// ```
+    #[wasm_bindgen(js_name = updateToBytes)]
+    pub fn update_to_bytes(update_json: String) -> Result<Vec<u8>, JsValue> {
+        let update: crate::awareness::AwarenessUpdate = serde_json::from_str(&update_json)
+            .map_err(|e| {
+                js_error(SyncKitError::invalid_input(format!(
+                    "Invalid update JSON: {}",
+                    e
+                )))
+            })?;
+        update.to_bytes().map_err(js_error)
+    }
+
+    /// Decode bytes from `updateToBytes` back to an update JSON string
+    #[wasm_bindgen(js_name = updateFromBytes)]
+    pub fn update_from_bytes(bytes: &[u8]) -> Result<String, JsValue> {
+        let update = crate::awareness::AwarenessUpdate::from_bytes(bytes).map_err(js_error)?;
+        serde_json::to_string(&update).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Encode a diff (JSON string from `encodeDiff`) as protobuf bytes
+    #[wasm_bindgen(js_name = diffToBytes)]
+    pub fn diff_to_bytes(diff_json: String) -> Result<Vec<u8>, JsValue> {
+        let diff: crate::awareness::AwarenessDiff =
+            serde_json::from_str(&diff_json).map_err(|e| {
+                js_error(SyncKitError::invalid_input(format!(
+                    "Invalid diff JSON: {}",
+                    e
+                )))
+            })?;
+        diff.to_bytes().map_err(js_error)
+    }
+
+    /// Decode bytes from `diffToBytes` back to a diff JSON string
+    #[wasm_bindgen(js_name = diffFromBytes)]
+    pub fn diff_from_bytes(bytes: &[u8]) -> Result<String, JsValue> {
+        let diff = crate::awareness::AwarenessDiff::from_bytes(bytes).map_err(js_error)?;
+        serde_json::to_string(&diff).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+}
+
+/// Parse a JSON object argument
+fn parse_object(json: &str) -> Result<serde_json::Map<String, serde_json::Value>, JsValue> {
+    serde_json::from_str(json).map_err(|e| {
+        js_error(SyncKitError::invalid_input(format!(
+            "Expected a JSON object: {}",
+            e
+        )))
+    })
+}
+
+/// JavaScript-friendly wrapper for AwarenessHub (one room per document)
+#[wasm_bindgen]
+pub struct WasmAwarenessHub {
+    inner: crate::awareness::AwarenessHub,
+}
+
+#[wasm_bindgen]
+impl WasmAwarenessHub {
+    /// Create a hub with no rooms
+    #[wasm_bindgen(constructor)]
+    pub fn new(client_id: String) -> Self {
+        Self {
+            inner: crate::awareness::AwarenessHub::new(client_id),
+        }
+    }
+
+    /// Open the room for a document (incoming updates for rooms that
+    /// aren't open are ignored)
+    #[wasm_bindgen(js_name = openRoom)]
+    pub fn open_room(&mut self, document_id: String) {
+        self.inner.room(&document_id);
+    }
+
+    /// Leave a room; returns the `RoomUpdate` JSON to broadcast, if it
+    /// was open
+    #[wasm_bindgen(js_name = closeRoom)]
+    pub fn close_room(&mut self, document_id: String) -> Result<Option<String>, JsValue> {
+        match self.inner.close_room(&document_id) {
+            Some(update) => serde_json::to_string(&update)
+                .map(Some)
+                .map_err(|e| js_error(SyncKitError::serialization(e))),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the shared identity (JSON object); returns a JSON array of
+    /// `RoomUpdate`s, one per room we are present in
+    #[wasm_bindgen(js_name = setIdentity)]
+    pub fn set_identity(&mut self, identity_json: String) -> Result<String, JsValue> {
+        let updates = self
+            .inner
+            .set_identity(parse_object(&identity_json)?)
+            .map_err(js_error)?;
+        serde_json::to_string(&updates).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Replace our state in one room (JSON object); returns `RoomUpdate` JSON
+    #[wasm_bindgen(js_name = setRoomState)]
+    pub fn set_room_state(
+        &mut self,
+        document_id: String,
+        state_json: String,
+    ) -> Result<String, JsValue> {
+        let update = self
+            .inner
+            .set_room_state(&document_id, parse_object(&state_json)?)
+            .map_err(js_error)?;
+        serde_json::to_string(&update).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Patch our state in one room (JSON merge patch); returns
+    /// `RoomUpdate` JSON
+    #[wasm_bindgen(js_name = updateRoomState)]
+    pub fn update_room_state(
+        &mut self,
+        document_id: String,
+        patch_json: String,
+    ) -> Result<String, JsValue> {
+        let update = self
+            .inner
+            .update_room_state(&document_id, parse_object(&patch_json)?)
+            .map_err(js_error)?;
+        serde_json::to_string(&update).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Apply a remote `RoomUpdate` (pass JSON string)
+    ///
+    /// Returns true if the sender's full state is needed (see
+    /// `WasmAwareness.applyUpdate`).
+    #[wasm_bindgen(js_name = applyUpdate)]
+    pub fn apply_update(&mut self, update_json: String) -> Result<bool, JsValue> {
+        let update: crate::awareness::RoomUpdate =
+            serde_json::from_str(&update_json).map_err(|e| {
+                js_error(SyncKitError::invalid_input(format!(
+                    "Invalid update JSON: {}",
+                    e
+                )))
+            })?;
+
+        let outcome = self.inner.apply_update(update);
+        Ok(outcome == crate::awareness::UpdateOutcome::NeedsFullState)
+    }
+
+    /// All client states in one room as JSON string (`{}` if not open)
+    #[wasm_bindgen(js_name = getStates)]
+    pub fn get_states(&self, document_id: String) -> Result<String, JsValue> {
+        let states = self
+            .inner
+            .get_room(&document_id)
+            .map(|room| room.get_states().clone())
+            .unwrap_or_default();
+        serde_json::to_string(&states).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+
+    /// Ids of the open rooms as a JSON array
+    pub fn rooms(&self) -> Result<String, JsValue> {
+        let mut rooms: Vec<_> = self.inner.rooms().collect();
+        rooms.sort();
+        serde_json::to_string(&rooms).map_err(|e| js_error(SyncKitError::serialization(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_view_frozen_while_source_mutates() {
+        let mut doc = WasmDocument::new("doc-1".to_string());
+        doc.set_field(
+            "name".to_string(),
+            "\"Alice\"".to_string(),
+            1,
+            "client1".to_string(),
+        )
+        .unwrap();
+
+        let view = doc.read_only_view();
+        doc.set_field(
+            "name".to_string(),
+            "\"Bob\"".to_string(),
+            2,
+            "client1".to_string(),
+        )
+        .unwrap();
+        drop(doc);
+
+        assert_eq!(
+            view.get_field("name".to_string()).unwrap().as_deref(),
+            Some("\"Alice\"")
+        );
+        assert_eq!(view.field_count(), 1);
+    }
+
+    #[test]
+    fn test_document_list_methods() {
+        let mut doc = WasmDocument::new("doc-1".to_string());
+        let path = "items".to_string();
+        for title in ["\"milk\"", "\"eggs\""] {
+            doc.list_push(path.clone(), title.to_string(), "client1".to_string())
+                .unwrap();
+        }
+        let id = doc
+            .list_insert(
+                path.clone(),
+                0,
+                "\"bread\"".to_string(),
+                "client1".to_string(),
+            )
+            .unwrap();
+        assert_eq!(id, r#"{"clock":3,"client_id":"client1"}"#);
+        doc.list_move(path.clone(), 0, 2, "client1".to_string())
+            .unwrap();
+        doc.list_remove(path.clone(), 0).unwrap();
+
+        assert_eq!(
+            doc.list_get(path).unwrap().as_deref(),
+            Some(r#"["eggs","bread"]"#)
+        );
+        assert_eq!(doc.to_json().unwrap(), r#"{"items":["eggs","bread"]}"#);
+        assert_eq!(doc.list_get("missing".to_string()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_document_dirty_chunks_restore() {
+        let mut doc = WasmDocument::new("doc-1".to_string());
+        let mut chunks = Vec::new();
+        for (clock, name) in [(1, "\"Alice\""), (2, "\"Bob\"")] {
+            doc.set_field(
+                "name".to_string(),
+                name.to_string(),
+                clock,
+                "c1".to_string(),
+            )
+            .unwrap();
+            chunks.push(doc.take_dirty().unwrap());
+        }
+        doc.delete_field("name".to_string()).unwrap();
+        assert!(doc.is_dirty());
+        chunks.push(doc.take_dirty().unwrap());
+
+        let mut restored = WasmDocument::new("doc-1".to_string());
+        restored.apply_dirty(&chunks[1]).unwrap();
+        assert_eq!(
+            restored.get_field("name".to_string()).unwrap().as_deref(),
+            Some("\"Bob\"")
+        );
+        restored.apply_dirty(&chunks[2]).unwrap();
+        assert_eq!(restored.field_count(), 0);
+        assert!(!restored.is_dirty());
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_view_frozen_while_source_mutates() {
+        let mut text = WasmFugueText::new("client1".to_string());
+        text.insert(0, "Hello".to_string()).unwrap();
+
+        let view = text.snapshot_view();
+        text.insert(5, " World".to_string()).unwrap();
+        assert_eq!(view.to_string(), "Hello");
+
+        drop(text);
+        assert_eq!(view.slice(1, 3).unwrap(), "el");
+        assert_eq!(view.length(), 5);
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_undo_history_survives_reload() {
+        let mut text = WasmFugueText::new("client1".to_string());
+        text.insert(0, "Hello".to_string()).unwrap();
+        text.insert(5, " World".to_string()).unwrap();
+        text.delete(0, 1).unwrap();
+
+        let mut reloaded = WasmFugueText::from_json(text.to_json().unwrap(), None).unwrap();
+        assert!(!reloaded.can_undo());
+        reloaded
+            .import_undo_history(&text.export_undo_history().unwrap())
+            .unwrap();
+        assert!(reloaded.undo().unwrap());
+        assert!(reloaded.undo().unwrap());
+        assert_eq!(reloaded.to_string(), "Hello");
+        assert!(reloaded.redo().unwrap());
+        assert_eq!(reloaded.to_string(), "Hello World");
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_limits_carry_over_from_json() {
+        let mut text = WasmFugueText::new("client1".to_string());
+        text.insert(0, "Hello".to_string()).unwrap();
+
+        let limits = r#"{"max_chars": 8}"#.to_string();
+        let reloaded = WasmFugueText::from_json(text.to_json().unwrap(), Some(limits)).unwrap();
+        assert_eq!(reloaded.inner.limits().max_chars, 8);
+        assert_eq!(reloaded.inner.limits().max_blocks, usize::MAX);
+
+        let mut inner = reloaded.inner;
+        let error = inner.insert(5, " World").unwrap_err();
+        let info = SyncKitErrorInfo::from(&SyncKitError::from(error));
+        assert_eq!(info.code, 1011);
+        assert_eq!(info.code_name, "LIMIT_EXCEEDED");
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_splice_undoes_insert_then_delete() {
+        let mut text = WasmFugueText::new("client1".to_string());
+        text.insert(0, "Hello World".to_string()).unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&text.splice(6, 5, "there".to_string()).unwrap()).unwrap();
+        assert_eq!(text.to_string(), "Hello there");
+        assert_eq!(result["deleted"].as_array().unwrap().len(), 1);
+        assert!(result["inserted"].is_object());
+
+        assert!(text.undo().unwrap());
+        assert_eq!(text.to_string(), "Hello ");
+        assert!(text.undo().unwrap());
+        assert_eq!(text.to_string(), "Hello World");
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_apply_text_diff_returns_spans() {
+        let mut text = WasmFugueText::new("client1".to_string());
+        text.insert(0, "Hello World".to_string()).unwrap();
+        let change = text.apply_text_diff("Hello, World!".to_string()).unwrap();
+        assert_eq!(
+            change,
+            r#"[{"retain":5},{"insert":","},{"retain":6},{"insert":"!"}]"#
+        );
+        assert_eq!(text.to_string(), "Hello, World!");
+
+        assert!(text.undo().unwrap());
+        assert!(text.undo().unwrap());
+        assert_eq!(text.to_string(), "Hello World");
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_attributed_spans_json() {
+        let mut text = WasmFugueText::new("client1".to_string());
+        text.insert(0, "Hello".to_string()).unwrap();
+        assert_eq!(
+            text.attributed_spans().unwrap(),
+            r#"[{"range":{"start":0,"end":5},"client_id":"client1","clock":1}]"#
+        );
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_memory_stats_json() {
+        let mut text = WasmFugueText::new("client1".to_string());
+        text.insert(0, "Hello".to_string()).unwrap();
+        let stats: serde_json::Value = serde_json::from_str(&text.memory_stats().unwrap()).unwrap();
+        assert_eq!(stats["block_count"], 1);
+        assert_eq!(stats["visible_chars"], 5);
+        assert!(stats["bytes_per_char"].as_f64().unwrap() > 0.0);
+
+        let doc = WasmDocument::new("doc-1".to_string());
+        let stats: serde_json::Value = serde_json::from_str(&doc.memory_stats().unwrap()).unwrap();
+        assert_eq!(stats["field_count"], 0);
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_utf16_edits_are_undoable() {
+        let mut text = WasmFugueText::new("client1".to_string());
+        text.insert(0, "a😀b".to_string()).unwrap();
+        assert_eq!(text.length_utf16(), 4);
+
+        text.insert_utf16(3, "!".to_string()).unwrap();
+        text.delete_utf16(1, 2).unwrap();
+        assert_eq!(text.to_string(), "a!b");
+        text.undo().unwrap();
+        assert_eq!(text.to_string(), "a😀!b");
+
+        let error = text.inner.insert_utf16(2, "x").unwrap_err();
+        let info = SyncKitErrorInfo::from(&SyncKitError::from(error));
+        assert_eq!(info.code_name, "INVALID_UTF16_POSITION");
+        assert_eq!(info.position, Some(2));
+    }
+
+    #[cfg(feature = "text-crdt")]
+    #[test]
+    fn test_text_error_info_keeps_code_and_position() {
+        // `js_error` copies these fields onto the thrown JS object
+        let mut text = WasmFugueText::new("client1".to_string());
+        let error = text.inner.insert(10, "x").unwrap_err();
+        let info = SyncKitErrorInfo::from(&SyncKitError::from(error));
+
+        assert_eq!(info.code, 1001);
+        assert_eq!(info.code_name, "POSITION_OUT_OF_BOUNDS");
+        assert_eq!(info.position, Some(10));
+    }
+
+    #[cfg(all(feature = "text-crdt", feature = "protocol-binary"))]
+    fn sync(from: &WasmFugueText, to: &mut WasmFugueText) -> String {
+        let delta = from.encode_delta(&to.encode_state_vector()).unwrap();
+        to.apply_delta(&delta).unwrap()
+    }
+
+    #[cfg(all(feature = "text-crdt", feature = "protocol-binary"))]
+    #[test]
+    fn test_fugue_text_delta_sync_converges() {
+        let mut text1 = WasmFugueText::new("client1".to_string());
+        let mut text2 = WasmFugueText::new("client2".to_string());
+
+        text1.insert(0, "Hello World".to_string()).unwrap();
+        sync(&text1, &mut text2);
+
+        text1.insert(11, "!".to_string()).unwrap();
+        text2.insert(0, ">> ".to_string()).unwrap();
+
+        let delta1 = text1.encode_delta(&text2.encode_state_vector()).unwrap();
+        let delta2 = text2.encode_delta(&text1.encode_state_vector()).unwrap();
+        text2.apply_delta(&delta1).unwrap();
+        text1.apply_delta(&delta2).unwrap();
+
+        assert_eq!(text1.to_string(), ">> Hello World!");
+        assert_eq!(text1.to_string(), text2.to_string());
+    }
+
+    #[cfg(all(feature = "text-crdt", feature = "protocol-binary"))]
+    #[test]
+    fn test_fugue_text_single_keystroke_delta_is_small() {
+        let mut text1 = WasmFugueText::new("client1".to_string());
+        let mut text2 = WasmFugueText::new("client2".to_string());
+
+        text1
+            .insert(
+                0,
+                "The quick brown fox jumps over the lazy dog. ".repeat(100),
+            )
+            .unwrap();
+        sync(&text1, &mut text2);
+
+        let end = text1.length();
+        text1.insert(end, "x".to_string()).unwrap();
+        let delta = text1.encode_delta(&text2.encode_state_vector()).unwrap();
+
+        assert!(delta.len() < 100, "delta was {} bytes", delta.len());
+        assert_eq!(
+            text2.apply_delta(&delta).unwrap(),
+            format!(r#"[{{"type":"insert","position":{},"text":"x"}}]"#, end)
+        );
+    }
+
+    #[cfg(all(feature = "text-crdt", feature = "protocol-binary"))]
+    #[test]
+    fn test_fugue_text_bytes_round_trip() {
+        let mut text = WasmFugueText::new("client1".to_string());
+        text.insert(0, "Hello World".to_string()).unwrap();
+        text.delete(5, 6).unwrap();
+
+        let bytes = text.to_bytes();
+        assert!(bytes.len() < text.to_json().unwrap().len());
+        let mut loaded = WasmFugueText::from_bytes(&bytes, None).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), text.to_json().unwrap());
+        loaded.insert(5, "!".to_string()).unwrap();
+        assert_eq!(loaded.to_string(), "Hello!");
+
+        let limits = r#"{"max_chars": 8}"#.to_string();
+        let bounded = WasmFugueText::from_bytes(&bytes, Some(limits)).unwrap();
+        assert_eq!(bounded.inner.limits().max_chars, 8);
+    }
+
+    #[cfg(feature = "protocol-binary")]
+    #[test]
+    fn test_awareness_update_bytes_round_trip() {
+        let mut alice = WasmAwareness::new("alice".to_string());
+        let mut bob = WasmAwareness::new("bob".to_string());
+
+        let update = alice
+            .set_local_state(r#"{"cursor":[12,40]}"#.to_string())
+            .unwrap();
+        let bytes = WasmAwareness::update_to_bytes(update.clone()).unwrap();
+        assert!(bytes.len() < update.len());
+        bob.apply_update(WasmAwareness::update_from_bytes(&bytes).unwrap())
+            .unwrap();
+
+        let diff = bob.encode_diff("{}".to_string()).unwrap();
+        let bytes = WasmAwareness::diff_to_bytes(diff).unwrap();
+        let mut carol = WasmAwareness::new("carol".to_string());
+        carol
+            .apply_diff(WasmAwareness::diff_from_bytes(&bytes).unwrap())
+            .unwrap();
+        assert_eq!(carol.client_count(), 1);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn test_counter_delta_redelivery_is_idempotent() {
+        let mut counter1 = WasmCounter::new("replica1".to_string());
+        let mut counter2 = WasmCounter::new("replica2".to_string());
+
+        counter1.increment(Some(5.0));
+        let delta = counter1.split_delta().unwrap();
+
+        assert_eq!(
+            counter2.apply_delta(&delta).unwrap(),
+            r#"{"changed":true,"valueBefore":0,"valueAfter":5}"#
+        );
+        assert_eq!(
+            counter2.apply_delta(&delta).unwrap(),
+            r#"{"changed":false,"valueBefore":5,"valueAfter":5}"#
+        );
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn test_counter_noop_merge_reports_unchanged() {
+        let mut counter1 = WasmCounter::new("replica1".to_string());
+        let mut counter2 = WasmCounter::new("replica2".to_string());
+
+        counter2.increment(Some(2.0));
+        counter1.merge(&counter2).unwrap();
+
+        let summary: serde_json::Value =
+            serde_json::from_str(&counter1.merge(&counter2).unwrap()).unwrap();
+        assert_eq!(summary["changed"], false);
+        assert_eq!(summary["valueAfter"], 2);
+    }
+
+    #[cfg(feature = "sets")]
+    #[test]
+    fn test_set_delta_redelivery_is_idempotent() {
+        let mut set1 = WasmSet::new("replica1".to_string());
+        let mut set2 = WasmSet::new("replica2".to_string());
+
+        set1.add("apple".to_string());
+        set1.add("banana".to_string());
+        let delta = set1.split_delta().unwrap();
+
+        assert_eq!(
+            set2.apply_delta(&delta).unwrap(),
+            r#"{"added":["apple","banana"],"removed":[]}"#
+        );
+        assert_eq!(
+            set2.apply_delta(&delta).unwrap(),
+            r#"{"added":[],"removed":[]}"#
+        );
+
+        set1.remove("apple".to_string());
+        assert_eq!(
+            set2.apply_delta(&set1.split_delta().unwrap()).unwrap(),
+            r#"{"added":[],"removed":["apple"]}"#
+        );
+    }
+
+    #[cfg(feature = "sets")]
+    #[test]
+    fn test_set_clear_keeps_concurrent_adds() {
+        let mut set1 = WasmSet::new("replica1".to_string());
+        let mut set2 = WasmSet::new("replica2".to_string());
+        set1.add("apple".to_string());
+        set2.merge(&set1).unwrap();
+
+        set1.clear();
+        set2.add("banana".to_string());
+        let from_set2 = set2.split_delta().unwrap();
+        let from_set1 = set1.split_delta().unwrap();
+
+        assert_eq!(
+            set1.apply_delta(&from_set2).unwrap(),
+            r#"{"added":["banana"],"removed":[]}"#
+        );
+        assert_eq!(
+            set2.apply_delta(&from_set1).unwrap(),
+            r#"{"added":[],"removed":["apple"]}"#
+        );
+        assert_eq!(set1.values().unwrap(), r#"["banana"]"#);
+        assert_eq!(set2.values().unwrap(), r#"["banana"]"#);
+
+        // A reset drops the concurrent add as well
+        set2.reset();
+        set1.add("cherry".to_string());
+        assert_eq!(
+            set1.merge(&set2).unwrap(),
+            r#"{"added":[],"removed":["banana","cherry"]}"#
+        );
+    }
+
+    #[cfg(feature = "sets")]
+    #[test]
+    fn test_set_noop_merge_reports_no_changes() {
+        let mut set1 = WasmSet::new("replica1".to_string());
+        let mut set2 = WasmSet::new("replica2".to_string());
+
+        set2.add("apple".to_string());
+        set1.merge(&set2).unwrap();
+
+        assert_eq!(set1.merge(&set2).unwrap(), r#"{"added":[],"removed":[]}"#);
+    }
+
+    #[cfg(feature = "protocol-binary")]
+    #[test]
+    fn test_sync_status_after_checksum_round_trip() {
+        let mut alice = WasmSyncCoordinator::new();
+        let mut bob = WasmSyncCoordinator::new();
+        let doc = WasmDocument::new("doc-1".to_string());
+
+        let checksum = alice.checksum(&doc).unwrap();
+        let status = bob.receive_checksum(&doc, checksum).unwrap();
+        assert!(status.contains("\"in_sync\":true"), "{}", status);
+        let reply = bob.checksum(&doc).unwrap();
+        alice.receive_checksum(&doc, reply).unwrap();
+        let status = alice.get_sync_status("doc-1".to_string()).unwrap();
+        assert!(status.contains("\"in_sync\":true"), "{}", status);
+        let status = alice.get_sync_status("doc-2".to_string()).unwrap();
+        assert!(status.contains("\"in_sync\":false"), "{}", status);
+    }
+
+    #[test]
+    fn test_workspace_search_follows_document_updates() {
+        let mut doc = WasmDocument::new("doc-1".to_string());
+        doc.set_field(
+            "title".to_string(),
+            "\"Quarterly plan\"".to_string(),
+            1,
+            "client1".to_string(),
+        )
+        .unwrap();
+        let mut workspace = WasmWorkspace::new();
+        assert_eq!(workspace.update_document(&doc), 1);
+        assert_eq!(workspace.update_document(&doc), 0);
+
+        let mut restored = WasmWorkspace::from_json(workspace.to_json().unwrap()).unwrap();
+        let hits = restored.search("quart".to_string(), 10).unwrap();
+        assert!(hits.contains("\"doc-1\""), "{}", hits);
+        doc.set_field(
+            "title".to_string(),
+            "\"Yearly plan\"".to_string(),
+            2,
+            "client1".to_string(),
+        )
+        .unwrap();
+        assert_eq!(restored.update_document(&doc), 1);
+        assert_eq!(restored.search("quart".to_string(), 10).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_annotations_reach_the_other_replica() {
+        let mut alice = WasmDocument::new("doc-1".to_string());
+        alice
+            .set_fields_annotated(
+                r#"{"name": "Ada", "role": "admin"}"#.to_string(),
+                1,
+                "alice".to_string(),
+                r#"{"source": "csv"}"#.to_string(),
+            )
+            .unwrap();
+        let mut bob = WasmDocument::new("doc-1".to_string());
+        bob.merge(&alice).unwrap();
+        let annotation = bob.annotation_for("role".to_string()).unwrap().unwrap();
+        assert!(
+            annotation.contains(r#""payload":{"source":"csv"}"#),
+            "{}",
+            annotation
+        );
+
+        #[cfg(feature = "text-crdt")]
+        {
+            let mut text = WasmFugueText::new("alice".to_string());
+            text.insert(0, "Hello".to_string()).unwrap();
+            text.insert_annotated(5, " World".to_string(), r#""paste""#.to_string())
+                .unwrap();
+            assert_eq!(text.annotations_for(0, 5).unwrap(), "[]");
+            assert!(text
+                .annotations_for(4, 7)
+                .unwrap()
+                .contains(r#""payload":"paste""#));
+
+            text.add_mark(
+                0,
+                5,
+                "bold".to_string(),
+                "true".to_string(),
+                "after".to_string(),
+            )
+            .unwrap();
+            assert_eq!(text.marks_at(0).unwrap(), r#"{"bold":true}"#);
+            assert_eq!(
+                text.marked_ranges().unwrap(),
+                r#"[{"range":{"start":0,"end":5},"key":"bold","value":true}]"#
+            );
+        }
+    }
+}
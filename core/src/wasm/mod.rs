@@ -5,13 +5,22 @@
 #[cfg(feature = "wasm")]
 pub mod bindings;
 
+#[cfg(feature = "wasm")]
+pub mod types;
+
 #[cfg(feature = "wasm")]
 pub mod utils;
 
 // Re-export main types
 #[cfg(feature = "wasm")]
-pub use bindings::{WasmAwareness, WasmDocument, WasmVectorClock};
+pub use bindings::{
+    JsTimeProvider, WasmAwareness, WasmAwarenessHub, WasmDocument, WasmDocumentView,
+    WasmVectorClock, WasmWorkspace,
+};
+
+#[cfg(feature = "wasm")]
+pub use types::{CounterMergeReport, SetMergeReport, SyncKitErrorInfo};
 
 // WasmDelta only available with protocol support
-#[cfg(all(feature = "wasm", feature = "prost"))]
-pub use bindings::WasmDelta;
+#[cfg(all(feature = "wasm", feature = "protocol-binary"))]
+pub use bindings::{WasmDelta, WasmSyncCoordinator};
@@ -0,0 +1,329 @@
+//! TypeScript definitions for JSON payloads crossing the WASM boundary
+//!
+//! Most bindings exchange JSON strings rather than JS objects. The shapes
+//! below are written by hand next to the serde structs they describe and
+//! are emitted into the wasm-pack `.d.ts` output, so the SDK can import
+//! them instead of re-declaring them.
+//!
+//! `core/tests/wasm_payload_shapes.rs` round-trips fixtures through the
+//! serde structs; update both when a payload changes.
+
+use crate::error::{ErrorCategory, SyncKitError};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// Result of merging or applying a delta to a `WasmCounter`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CounterMergeReport {
+    /// Whether the visible value changed
+    pub changed: bool,
+
+    /// Value before the merge
+    pub value_before: i64,
+
+    /// Value after the merge
+    pub value_after: i64,
+}
+
+/// Result of merging or applying a delta to a `WasmSet`
+///
+/// Both lists are sorted so reports are deterministic.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetMergeReport {
+    /// Elements that became visible
+    pub added: Vec<String>,
+
+    /// Elements that stopped being visible
+    pub removed: Vec<String>,
+}
+
+/// Another client's cursor, returned by `WasmAwareness.remoteCursors`
+#[cfg(feature = "text-crdt")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteCursor {
+    pub client_id: String,
+
+    #[serde(flatten)]
+    pub cursor: crate::awareness::ResolvedCursor,
+}
+
+/// Properties of the `SyncKitError` objects thrown by the bindings
+///
+/// Context fields are omitted when the error does not carry them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncKitErrorInfo {
+    /// Stable numeric code (see `SyncKitError::code`)
+    pub code: u32,
+
+    /// Symbolic code, e.g. `"POSITION_OUT_OF_BOUNDS"`
+    pub code_name: String,
+
+    /// Coarse category
+    pub category: ErrorCategory,
+
+    /// Human-readable message, including any context
+    pub message: String,
+
+    /// Document being operated on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<String>,
+
+    /// Field path within the document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    /// Position within a text, in characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+}
+
+impl From<&SyncKitError> for SyncKitErrorInfo {
+    fn from(error: &SyncKitError) -> Self {
+        let context = error.context();
+        Self {
+            code: error.code(),
+            code_name: error.code_name().to_string(),
+            category: error.category(),
+            message: error.to_string(),
+            document_id: context.document_id.clone(),
+            path: context.path.clone(),
+            position: context.position,
+        }
+    }
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_PAYLOAD_TYPES: &'static str = r#"
+/** Stable identifier of a character in a FugueText (`getNodeIdAtPosition`, `insert`). */
+export interface NodeId {
+  client_id: string;
+  clock: number;
+  offset: number;
+}
+
+/**
+ * Awareness update exchanged between peers. `state: null` means the client left.
+ * With `base_clock` set, `state` is a JSON merge patch onto the sender's state at that clock.
+ */
+export interface AwarenessUpdate {
+  client_id: string;
+  state: Record<string, unknown> | null;
+  clock: number;
+  base_clock?: number | null;
+}
+
+/** Awareness update tagged with its document (`WasmAwarenessHub`). */
+export interface RoomUpdate {
+  document_id: string;
+  update: AwarenessUpdate;
+}
+
+/** Another client's cursor resolved against the local text (`WasmAwareness.remoteCursors`). */
+export interface RemoteCursor {
+  client_id: string;
+  position: number;
+  selection_end: number | null;
+  meta: unknown;
+}
+
+/** Newest awareness clock seen per client (`WasmAwareness.getVersion`). */
+export type AwarenessVersion = Record<string, number>;
+
+/** Clients that changed (or left, `state: null`) since a version (`WasmAwareness.encodeDiff`). */
+export interface AwarenessDiff {
+  updates: AwarenessUpdate[];
+}
+
+/** Presence change passed (in arrays) to `WasmAwareness.onAwarenessChange` callbacks. */
+export type AwarenessEvent =
+  | { type: "joined"; client_id: string; state: Record<string, unknown> }
+  | { type: "rejoined"; client_id: string; state: Record<string, unknown> }
+  | { type: "updated"; client_id: string; old: Record<string, unknown>; new: Record<string, unknown> }
+  | { type: "left"; client_id: string }
+  | { type: "invalid"; client_id: string; reason: string; stripped: string[] };
+
+/** LWW timestamp attached to each document field. */
+export interface Timestamp {
+  clock: number;
+  client_id: string;
+}
+
+/** A value at a path with the clock and client that wrote it (`WasmDocument.getAll`). */
+export type FieldVersion = [value: unknown, clock: number, client_id: string];
+
+/** Stable identifier of a list item (`WasmDocument.listPush`, `listInsert`). */
+export interface ListId {
+  clock: number;
+  client_id: string;
+}
+
+/** Where an applied change came from; `echo` is our own change coming back. */
+export type ChangeOrigin = "local" | "remote" | "echo";
+
+/** A single field change inside a document delta (`WasmDelta.toJSON`). */
+export interface FieldChange {
+  path: string;
+  field: {
+    value: unknown;
+    timestamp: Timestamp;
+  };
+  is_delete: boolean;
+  /** Hash of a value the receiver already has (`field.value` is then null); always null on applied changes. */
+  value_ref: string | null;
+  origin: ChangeOrigin;
+}
+
+/** Returned by `WasmDelta.dryRun`: what `applyTo` would do, without doing it. */
+export interface DryRunReport {
+  /** What `applyTo` would return, echoes included. */
+  changes: FieldChange[];
+  /** Fields and lists whose value would change, sorted. */
+  changed_paths: string[];
+  conflicts: ResolvedConflict[];
+  /** Paths the sync filter refuses to change. */
+  refused: string[];
+  /** Document version once the delta is applied. */
+  new_version: { clocks: Record<string, number> };
+}
+
+/** A delta's write to a field holding a different value, resolved by LWW. */
+export interface ResolvedConflict {
+  path: string;
+  local: Timestamp;
+  remote: Timestamp;
+  remote_wins: boolean;
+}
+
+/** A value that lost LWW in a merge, kept until resolved (`WasmDocument.getConflicts`). */
+export interface Conflict {
+  /** 16 hex digits; pass to `WasmDocument.resolveConflict`. */
+  id: string;
+  path: string;
+  /** The value this replica held. */
+  local: { value: unknown; timestamp: Timestamp };
+  /** The value that arrived. */
+  remote: { value: unknown; timestamp: Timestamp };
+  remote_won: boolean;
+  /** Milliseconds since the Unix epoch. */
+  recorded_ms: number;
+}
+
+/** Which value `WasmDocument.resolveConflict` writes. */
+export type ConflictKeep = "local" | "remote" | { custom: unknown };
+
+/** A document matching a query (`WasmWorkspace.search` returns an array, best first). */
+export interface SearchHit {
+  document_id: string;
+  /** Matching word occurrences, exact matches counting double. */
+  score: number;
+  /** Fields, lists and texts (`text:<name>`) holding a match, sorted. */
+  fields: string[];
+}
+
+/** App-level tag on one client's edits with clocks `start..=end` (`WasmDocument.annotationFor`, `WasmFugueText.annotationsFor`). */
+export interface Annotation {
+  client_id: string;
+  start: number;
+  end: number;
+  /** Any JSON, at most 1 KiB serialized. */
+  payload: unknown;
+  /** Milliseconds since the Unix epoch. */
+  created_ms: number;
+}
+
+/** Sent between peers to confirm convergence (`WasmSyncCoordinator.checksum`, `receiveChecksum`). */
+export interface ConvergenceChecksum {
+  document_id: string;
+  version: { clocks: Record<string, number> };
+  /** Content hash as 16 hex digits. */
+  hash: string;
+}
+
+/** How a document stands with the peer (`WasmSyncCoordinator.getSyncStatus`). */
+export interface SyncStatus {
+  /** The peer confirmed the same state at our current version. */
+  in_sync: boolean;
+  /** Local changes the peer hasn't reported having, in clock ticks. */
+  pending_local: number;
+  /** Changes the peer reported that we lack, in clock ticks. */
+  pending_remote_estimate: number;
+  /** Milliseconds since the epoch of the last confirmation, or null if never. */
+  last_verified_at: number | null;
+  /** The peer reported different state at the same version; exchange snapshots to recover. */
+  diverged: boolean;
+}
+
+/** Change to the visible text returned by `WasmFugueText.applyDelta` and passed (in arrays) to `WasmFugueText.onChange` callbacks. */
+export type TextEvent =
+  | { type: "insert"; position: number; text: string }
+  | { type: "delete"; position: number; length: number };
+
+/** Zero-based line and column (`WasmLineIndex.offsetToPosition`). */
+export interface LinePosition {
+  line: number;
+  column: number;
+}
+
+/** Field change passed (in arrays) to `WasmDocument.onChange` callbacks. */
+export type FieldEvent =
+  | { type: "set"; path: string; value: unknown }
+  | { type: "delete"; path: string }
+  | { type: "lock"; path: string; holder: string | null };
+
+/** Returned by `WasmCounter.merge` / `WasmCounter.applyDelta`. */
+export interface CounterMergeReport {
+  changed: boolean;
+  valueBefore: number;
+  valueAfter: number;
+}
+
+/** Returned by `WasmSet.merge` / `WasmSet.applyDelta`. */
+export interface SetMergeReport {
+  added: string[];
+  removed: string[];
+}
+
+export type MergeReport = CounterMergeReport | SetMergeReport;
+
+/** Returned by `WasmDocument.mergeIncremental` / `WasmFugueText.mergeIncremental`. */
+export interface MergeProgress {
+  done: number;
+  /** Estimate until `complete`. */
+  total: number;
+  complete: boolean;
+}
+
+/** Category of a `SyncKitError`; codes are grouped by thousands per category. */
+export type SyncKitErrorCategory =
+  | "text"
+  | "document"
+  | "protocol"
+  | "storage"
+  | "sync"
+  | "validation";
+
+/**
+ * Properties of the `Error` objects thrown by the bindings (`err.name` is
+ * `"SyncKitError"`). `code` values are stable across releases.
+ */
+export interface SyncKitErrorInfo {
+  code: number;
+  codeName: string;
+  category: SyncKitErrorCategory;
+  message: string;
+  documentId?: string;
+  path?: string;
+  position?: number;
+}
+
+/**
+ * What the bindings throw (`@throws {SyncKitError}`): a JS `Error` carrying
+ * the `SyncKitErrorInfo` code, message and any context as own properties.
+ */
+export interface SyncKitError extends Error, SyncKitErrorInfo {
+  name: "SyncKitError";
+  message: string;
+}
+"#;
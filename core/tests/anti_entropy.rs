@@ -0,0 +1,175 @@
+//! Anti-entropy probes between two replicas of a large workspace: the
+//! bytes exchanged must track the number of changed documents, and
+//! replicas must still converge when a probe misses a difference
+
+#![cfg(feature = "protocol-binary")]
+
+use serde_json::json;
+use std::collections::BTreeMap;
+use synckit_core::protocol::delta::DocumentDelta;
+use synckit_core::protocol::serialize::{decode_message, encode_message};
+use synckit_core::protocol::sync::{AntiEntropyConfig, SyncCoordinator, VersionFilter};
+use synckit_core::protocol::{self, sync_message, SyncMessage, VersionSummary};
+use synckit_core::Document;
+
+type Replica = BTreeMap<String, Document>;
+
+fn workspace(documents: usize) -> Replica {
+    (0..documents)
+        .map(|i| {
+            let id = format!("doc-{:05}", i);
+            let mut document = Document::new(id.clone());
+            document.set_field(
+                "title".to_string(),
+                json!(format!("todo {}", i)),
+                1,
+                "seed".to_string(),
+            );
+            document.version.update("seed", 1);
+            (id, document)
+        })
+        .collect()
+}
+
+fn edit(replica: &mut Replica, index: usize, client: &str) {
+    let document = replica.get_mut(&format!("doc-{:05}", index)).unwrap();
+    let clock = document.version().get(client) + 1;
+    document.set_field(
+        "title".to_string(),
+        json!(format!("{} edit", client)),
+        10 + clock,
+        client.to_string(),
+    );
+    document.version.update(client, clock);
+}
+
+fn encode(payload: sync_message::Payload) -> Vec<u8> {
+    encode_message(&SyncMessage {
+        payload: Some(payload),
+    })
+    .unwrap()
+    .to_vec()
+}
+
+/// `prober` probes `receiver`, which pulls what it lacks of the probable
+/// mismatches; returns the follow-up bytes (request and answer)
+fn pull(
+    coordinator: &SyncCoordinator,
+    prober: &Replica,
+    receiver: &mut Replica,
+    seed: u64,
+    bytes: &mut usize,
+) -> usize {
+    let probe = coordinator.version_probe(prober.values(), seed);
+    let message = encode(sync_message::Payload::Probe(probe.to_protocol()));
+    *bytes += message.len();
+
+    let Some(sync_message::Payload::Probe(proto)) =
+        decode_message::<SyncMessage>(&message).unwrap().payload
+    else {
+        panic!("expected a probe");
+    };
+    let probe = VersionFilter::from_protocol(&proto).unwrap();
+    let request = coordinator.reconciliation_request(&probe, receiver.values());
+    let request_bytes = encode_message(&request).unwrap().to_vec();
+
+    let request: VersionSummary = decode_message(&request_bytes).unwrap();
+    let deltas = coordinator
+        .answer_reconciliation(&request, prober.values())
+        .unwrap();
+    let mut follow_up = request_bytes.len();
+    for delta in deltas {
+        let delta_bytes = encode_message(&delta.to_protocol()).unwrap();
+        follow_up += delta_bytes.len();
+        let proto: protocol::Delta = decode_message(&delta_bytes).unwrap();
+        let delta = DocumentDelta::from_protocol(&proto, "receiver").unwrap();
+        let document = receiver.get_mut(&delta.document_id).unwrap();
+        coordinator
+            .apply_incoming(&delta, document, "receiver")
+            .unwrap();
+    }
+    *bytes += follow_up;
+    follow_up
+}
+
+fn converged(a: &Replica, b: &Replica) -> bool {
+    a.iter().all(|(id, document)| {
+        let other = &b[id];
+        document.to_json() == other.to_json() && document.version() == other.version()
+    })
+}
+
+#[test]
+fn test_payload_tracks_changed_documents() {
+    let coordinator = SyncCoordinator::new();
+    let mut server = workspace(10_000);
+    let mut client = server.clone();
+    edit(&mut server, 17, "server");
+    edit(&mut client, 4242, "client");
+    edit(&mut client, 9999, "client");
+
+    let mut bytes = 0;
+    let to_client = pull(&coordinator, &server, &mut client, 1, &mut bytes);
+    let to_server = pull(&coordinator, &client, &mut server, 2, &mut bytes);
+    assert!(converged(&server, &client));
+
+    // Follow-ups stay small per changed document...
+    assert!(
+        to_client + to_server < 3 * 400,
+        "{} + {}",
+        to_client,
+        to_server
+    );
+    // ...and the rest is the two fixed-size filters
+    let filter = coordinator.version_probe(server.values(), 0).byte_len();
+    assert!(bytes < 2 * (filter + 64) + 3 * 400);
+
+    // One side's full version vectors alone cost several times as much
+    let summary = VersionSummary {
+        documents: client
+            .values()
+            .map(|document| synckit_core::protocol::DocumentVersion {
+                document_id: Some(protocol::DocumentId {
+                    id: document.id().clone(),
+                }),
+                version: Some(protocol::VectorClock {
+                    clocks: [("seed".to_string(), 1)].into(),
+                }),
+            })
+            .collect(),
+    };
+    assert!(bytes * 4 < encode_message(&summary).unwrap().len());
+}
+
+#[test]
+fn test_converges_despite_false_positives() {
+    let mut coordinator = SyncCoordinator::new();
+    coordinator.set_anti_entropy(AntiEntropyConfig {
+        false_positive_rate: 0.3,
+        max_follow_ups: 8,
+    });
+    let mut server = workspace(500);
+    let mut client = server.clone();
+    for i in 0..20 {
+        edit(&mut client, i * 25, "client");
+    }
+
+    // The first probe hides some of the changed documents
+    let probe = coordinator.version_probe(client.values(), 0);
+    let hidden = server
+        .values()
+        .filter(|document| document.version() != client[document.id()].version())
+        .filter(|document| probe.contains(document.id(), document.version()))
+        .count();
+    assert!(hidden > 0);
+
+    // New seeds find them, at most 8 per round
+    let mut bytes = 0;
+    let mut rounds = 0;
+    while !converged(&server, &client) {
+        assert!(rounds < 50, "no convergence after {} rounds", rounds);
+        pull(&coordinator, &client, &mut server, rounds, &mut bytes);
+        rounds += 1;
+    }
+    assert!(rounds >= 3);
+}
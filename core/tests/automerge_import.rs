@@ -0,0 +1,90 @@
+//! Import a committed Automerge save file and compare against a snapshot
+//!
+//! See `tests/fixtures/automerge/README.md` for how the fixture was built.
+
+#![cfg(feature = "automerge-interop")]
+
+use serde_json::Value;
+use synckit_core::interop::CONFLICTS_FIELD;
+use synckit_core::Document;
+
+fn fixture_path(name: &str) -> String {
+    format!(
+        "{}/tests/fixtures/automerge/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    )
+}
+
+fn load_fixture() -> Document {
+    let bytes = std::fs::read(fixture_path("document.automerge")).unwrap();
+    Document::from_automerge("imported".to_string(), &bytes).unwrap()
+}
+
+fn expected_snapshot() -> Value {
+    let json = std::fs::read_to_string(fixture_path("document.expected.json")).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+#[test]
+fn test_import_matches_snapshot() {
+    let doc = load_fixture();
+    assert_eq!(doc.to_json(), expected_snapshot());
+}
+
+#[test]
+fn test_import_round_trips_through_serialization() {
+    let doc = load_fixture();
+
+    let serialized = serde_json::to_string(&doc).unwrap();
+    let restored: Document = serde_json::from_str(&serialized).unwrap();
+
+    assert_eq!(restored.to_json(), expected_snapshot());
+    assert_eq!(restored.version, doc.version);
+}
+
+#[test]
+fn test_conflict_winner_keeps_higher_timestamp() {
+    let doc = load_fixture();
+
+    let title = &doc.fields()["title"];
+    assert_eq!(title.value, "Bob's title");
+
+    let conflicts = &doc.fields()[CONFLICTS_FIELD];
+    assert!(conflicts.timestamp.clock >= title.timestamp.clock);
+}
+
+#[test]
+fn test_imported_fields_merge_with_later_edits() {
+    let mut doc = load_fixture();
+    let mut remote = load_fixture();
+
+    let clock = remote.version.clocks.values().copied().max().unwrap() + 1;
+    remote.set_field(
+        "title".to_string(),
+        Value::String("Final".to_string()),
+        clock,
+        "client1".to_string(),
+    );
+
+    doc.merge(&remote);
+    assert_eq!(doc.get_field(&"title".to_string()).unwrap(), "Final");
+}
+
+#[test]
+fn test_invalid_bytes_rejected() {
+    let error = Document::from_automerge("bad".to_string(), b"not automerge").unwrap_err();
+    assert_eq!(error.code(), 3002);
+    assert_eq!(error.context().document_id.as_deref(), Some("bad"));
+}
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn test_text_imported_as_fugue_text() {
+    let bytes = std::fs::read(fixture_path("document.automerge")).unwrap();
+    let import = synckit_core::interop::import_automerge("imported".to_string(), &bytes).unwrap();
+
+    let body = &import.texts["body"];
+    assert_eq!(body.to_string(), "Hello, world");
+    assert_eq!(import.document.to_json()["body"], "Hello, world");
+}
@@ -0,0 +1,98 @@
+//! Heap used per typed character by `FugueBlock`, and by reading a range
+//!
+//! Counts live allocations with a wrapping global allocator, so this runs
+//! as its own test binary.
+
+#![cfg(feature = "std")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct Counting;
+
+// Per thread, so allocations by the test harness's other threads don't
+// show up in the count
+thread_local! {
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    static LIVE_ALLOCS: Cell<isize> = const { Cell::new(0) };
+    static ALLOCATED_BYTES: Cell<isize> = const { Cell::new(0) };
+}
+
+fn add(counter: &'static std::thread::LocalKey<Cell<isize>>, delta: isize) {
+    // Ignore allocations made while the thread is being torn down
+    let _ = counter.try_with(|count| count.set(count.get() + delta));
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        add(&LIVE_BYTES, layout.size() as isize);
+        add(&LIVE_ALLOCS, 1);
+        add(&ALLOCATED_BYTES, layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        add(&LIVE_BYTES, -(layout.size() as isize));
+        add(&LIVE_ALLOCS, -1);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        add(&LIVE_BYTES, new_size as isize - layout.size() as isize);
+        add(&ALLOCATED_BYTES, new_size as isize);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn test_keystroke_heap_per_character() {
+    use synckit_core::crdt::text_fugue::ClientId;
+    use synckit_core::crdt::{FugueBlock, NodeId};
+
+    const KEYSTROKES: u64 = 100_000;
+
+    // One block per keystroke, as typing produces. Origins are left out;
+    // every ID shares the text's one copy of its client id.
+    let client = ClientId::from("client1");
+    let mut blocks = Vec::with_capacity(KEYSTROKES as usize);
+    let bytes_before = LIVE_BYTES.with(Cell::get);
+    let allocs_before = LIVE_ALLOCS.with(Cell::get);
+
+    for clock in 1..=KEYSTROKES {
+        let id = NodeId::new(client.clone(), clock, 0);
+        blocks.push(FugueBlock::new(id, "e".to_string(), None, None));
+    }
+
+    let bytes = (LIVE_BYTES.with(Cell::get) - bytes_before) as f64 / KEYSTROKES as f64;
+    let allocs = (LIVE_ALLOCS.with(Cell::get) - allocs_before) as f64 / KEYSTROKES as f64;
+    let per_character = format!("{bytes:.1} heap bytes, {allocs:.2} allocations per character");
+
+    // A `String` per block, for its text or its client id, would add an
+    // allocation for every keystroke
+    assert_eq!(allocs, 0.0, "{per_character}");
+    assert_eq!(bytes, 0.0, "{per_character}");
+    assert!(blocks.iter().all(|block| block.text.is_inline()));
+}
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn test_slice_allocates_only_the_slice() {
+    use synckit_core::crdt::text_fugue::FugueText;
+
+    let mut text = FugueText::new("client1".to_string());
+    text.insert(0, &"abcdefghij".repeat(100_000)).unwrap();
+    let middle = text.len() / 2;
+
+    let before = ALLOCATED_BYTES.with(Cell::get);
+    let slice = text.slice(middle..middle + 100).unwrap();
+    let graphemes = text.graphemes_in(middle..middle + 100).unwrap().count();
+    let allocated = ALLOCATED_BYTES.with(Cell::get) - before;
+
+    assert_eq!(slice.len(), 100);
+    assert_eq!(graphemes, 100);
+    assert!(allocated < 1_000, "{allocated} bytes allocated");
+}
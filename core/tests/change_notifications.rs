@@ -0,0 +1,145 @@
+//! A large merge must reach subscribers as a single deduplicated batch that
+//! turns a shadow copy taken before it into the merged state
+
+#![cfg(feature = "std")]
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use synckit_core::notify::FieldEvent;
+use synckit_core::Document;
+
+const OPS: u64 = 10_000;
+
+/// Single-character text edits are far slower; fewer show the same
+#[cfg(feature = "text-crdt")]
+const TEXT_OPS: usize = 300;
+
+fn values(document: &Document) -> HashMap<String, Value> {
+    document
+        .fields()
+        .iter()
+        .map(|(path, field)| (path.clone(), field.value.clone()))
+        .collect()
+}
+
+#[test]
+fn test_large_merge_is_one_deduplicated_batch() {
+    let mut local = Document::new("doc".to_string());
+    for i in 0..500 {
+        local.set_field(
+            format!("field.{}", i),
+            json!("local"),
+            1,
+            "alice".to_string(),
+        );
+    }
+
+    // 10k writes from bob over 2k paths, some of them deleted again
+    let mut remote = local.clone();
+    for op in 1..=OPS {
+        let path = format!("field.{}", op % 2_000);
+        if op % 7 == 0 {
+            remote.delete_field(&path);
+        } else {
+            remote.set_field(path, json!(op), op + 1, "bob".to_string());
+        }
+    }
+
+    let mut shadow = values(&local);
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let sink = batches.clone();
+    local.subscribe(move |batch: &[FieldEvent]| sink.lock().unwrap().push(batch.to_vec()));
+    local.merge(&remote);
+    local.flush_notifications();
+
+    let batches = batches.lock().unwrap();
+    assert_eq!(batches.len(), 1);
+    let batch = &batches[0];
+    let mut paths: Vec<&String> = batch.iter().map(FieldEvent::path).collect();
+    paths.sort();
+    paths.dedup();
+    assert_eq!(paths.len(), batch.len());
+
+    for event in batch {
+        match event {
+            FieldEvent::Set { path, value } => {
+                shadow.insert(path.clone(), value.clone());
+            }
+            FieldEvent::Delete { path } => {
+                shadow.remove(path);
+            }
+            FieldEvent::Lock { .. } => {}
+        }
+    }
+    assert_eq!(shadow, values(&local));
+}
+
+#[test]
+fn test_transaction_dedups_rewrites() {
+    let mut document = Document::new("doc".to_string());
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let sink = batches.clone();
+    document.subscribe(move |batch: &[FieldEvent]| sink.lock().unwrap().push(batch.to_vec()));
+
+    document.transaction(|document| {
+        for op in 1..=OPS {
+            let path = format!("field.{}", op % 100);
+            document.set_field(path, json!(op), op, "alice".to_string());
+        }
+    });
+
+    let batches = batches.lock().unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].len(), 100);
+    assert_eq!(
+        batches[0].last(),
+        Some(&FieldEvent::Set {
+            path: "field.0".to_string(),
+            value: json!(OPS),
+        })
+    );
+}
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn test_large_text_merge_is_one_batch() {
+    use synckit_core::crdt::{FugueText, TextEvent};
+
+    let mut local = FugueText::new("alice".to_string());
+    local.insert(0, "shared start").unwrap();
+    let mut remote = FugueText::new("bob".to_string());
+    remote.merge(&local).unwrap();
+
+    // Typing at the end, with the odd correction and edit at the start
+    for op in 0..TEXT_OPS {
+        if op % 10 == 9 {
+            remote.delete(remote.len() - 1, 1).unwrap();
+        } else if op % 50 == 0 {
+            remote.insert(0, "^").unwrap();
+        } else {
+            remote.insert(remote.len(), "x").unwrap();
+        }
+    }
+    local.insert(0, "> ").unwrap();
+
+    let mut shadow: Vec<char> = local.to_string().chars().collect();
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let sink = batches.clone();
+    local.subscribe(move |batch: &[TextEvent]| sink.lock().unwrap().push(batch.to_vec()));
+    local.merge(&remote).unwrap();
+
+    let batches = batches.lock().unwrap();
+    assert_eq!(batches.len(), 1);
+    for event in &batches[0] {
+        match event {
+            TextEvent::Insert { position, text } => {
+                shadow.splice(*position..*position, text.chars());
+            }
+            TextEvent::Delete { position, length } => {
+                shadow.drain(*position..*position + *length);
+            }
+        }
+    }
+    assert_eq!(shadow.into_iter().collect::<String>(), local.to_string());
+}
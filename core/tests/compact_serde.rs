@@ -0,0 +1,218 @@
+//! Round-trip every persisted type through JSON, bincode and (with the
+//! `serde-compact` feature) postcard, and check all three decode to the
+//! same structure, including tombstones and clocks.
+
+#![cfg(feature = "std")]
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use std::fmt::Debug;
+use synckit_core::sync::LWWField;
+use synckit_core::{AwarenessState, AwarenessUpdate, Document, Timestamp};
+
+/// Decode `value` from each format, in the order JSON, bincode, postcard
+fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> Vec<T> {
+    let json = serde_json::to_vec(value).unwrap();
+    let bincode = bincode::serialize(value).unwrap();
+    #[cfg_attr(not(feature = "serde-compact"), allow(unused_mut))]
+    let mut decoded = vec![
+        serde_json::from_slice(&json).unwrap(),
+        bincode::deserialize(&bincode).unwrap(),
+    ];
+
+    #[cfg(feature = "serde-compact")]
+    {
+        let postcard = synckit_core::codec::to_postcard(value).unwrap();
+        assert!(postcard.len() <= bincode.len());
+        decoded.push(synckit_core::codec::from_postcard(&postcard).unwrap());
+    }
+    decoded
+}
+
+/// Round-trip and compare with `PartialEq`
+fn assert_round_trip_eq<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) {
+    for decoded in round_trip(value) {
+        assert_eq!(&decoded, value);
+    }
+}
+
+/// Round-trip and compare the JSON form (for types without `PartialEq`)
+fn assert_round_trip_json<T: Serialize + DeserializeOwned>(value: &T) -> Vec<T> {
+    let expected = serde_json::to_value(value).unwrap();
+    let decoded = round_trip(value);
+    for item in &decoded {
+        assert_eq!(serde_json::to_value(item).unwrap(), expected);
+    }
+    decoded
+}
+
+#[test]
+fn test_document_and_sync_types() {
+    let mut doc = Document::new("doc-1".to_string());
+    doc.set_field("title".to_string(), json!("Hello"), 3, "a".to_string());
+    doc.set_field(
+        "meta".to_string(),
+        json!({"tags": ["x", null], "score": 1.5, "big": u64::MAX}),
+        7,
+        "b".to_string(),
+    );
+    doc.version.update("a", 3);
+    doc.version.update("b", 7);
+
+    for decoded in assert_round_trip_json(&doc) {
+        assert_eq!(decoded.version, doc.version);
+        assert_eq!(decoded.fields, doc.fields);
+    }
+
+    assert_round_trip_eq(&doc.version);
+    assert_round_trip_eq(&Timestamp::new(9, "c".to_string()));
+    assert_round_trip_eq(&LWWField::new(
+        json!([1, {"deep": true}]),
+        Timestamp::new(1, "a".to_string()),
+    ));
+
+    let delta = synckit_core::sync::compute_delta(&Document::new("doc-1".to_string()), &doc);
+    assert_round_trip_eq(&delta);
+}
+
+#[test]
+fn test_awareness_types() {
+    let state = AwarenessState {
+        client_id: "a".to_string(),
+        state: json!({"cursor": 4}),
+        clock: 2,
+        #[cfg(not(target_arch = "wasm32"))]
+        last_updated: None,
+    };
+    for decoded in assert_round_trip_json(&state) {
+        assert_eq!(decoded.state, state.state);
+    }
+
+    for update in [
+        AwarenessUpdate {
+            client_id: "a".to_string(),
+            state: Some(json!({"name": "Ada"})),
+            clock: 3,
+            base_clock: None,
+        },
+        AwarenessUpdate {
+            client_id: "a".to_string(),
+            state: None,
+            clock: 4,
+            base_clock: None,
+        },
+        AwarenessUpdate {
+            client_id: "a".to_string(),
+            state: Some(json!({"cursor": 7})),
+            clock: 5,
+            base_clock: Some(4),
+        },
+    ] {
+        for decoded in assert_round_trip_json(&update) {
+            assert_eq!(decoded.state, update.state);
+        }
+    }
+}
+
+#[cfg(feature = "protocol-binary")]
+#[test]
+fn test_document_delta() {
+    use synckit_core::protocol::delta::DocumentDelta;
+
+    let from = Document::new("doc-1".to_string());
+    let mut to = from.clone();
+    to.set_field("a".to_string(), json!({"k": [1, 2]}), 1, "c".to_string());
+
+    let delta = DocumentDelta::compute(&from, &to).unwrap();
+    for decoded in assert_round_trip_json(&delta) {
+        assert_eq!(decoded.changes[0].field, delta.changes[0].field);
+    }
+}
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn test_text_types_keep_tombstones_and_clocks() {
+    use synckit_core::crdt::FugueText;
+    use synckit_core::VectorClock;
+
+    let mut text = FugueText::new("a".to_string());
+    text.insert(0, "Hello brave world").unwrap();
+    text.delete(5, 6).unwrap();
+    let mut other = FugueText::new("b".to_string());
+    other.merge(&text).unwrap();
+    other.insert(5, ", new").unwrap();
+    text.merge(&other).unwrap();
+
+    let tombstones = |t: &FugueText| {
+        serde_json::to_value(t).unwrap()["blocks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|pair| pair[1]["deleted"] == json!(true))
+            .count()
+    };
+    assert!(tombstones(&text) > 0);
+
+    for decoded in assert_round_trip_json(&text) {
+        assert_eq!(decoded.to_string(), text.to_string());
+        assert_eq!(decoded.clock(), text.clock());
+        assert_eq!(tombstones(&decoded), tombstones(&text));
+    }
+
+    let delta = text.diff_since(&VectorClock::new());
+    assert!(!delta.deleted.is_empty());
+    assert_round_trip_eq(&delta);
+    assert_round_trip_eq(&delta.blocks[0]);
+    assert_round_trip_eq(&delta.blocks[0].id);
+}
+
+#[cfg(feature = "counters")]
+#[test]
+fn test_pn_counter() {
+    use synckit_core::crdt::PNCounter;
+
+    let mut counter = PNCounter::new("a".to_string());
+    counter.increment(5);
+    counter.decrement(2);
+    let mut other = PNCounter::new("b".to_string());
+    other.increment(10);
+    counter.merge(&other);
+
+    for decoded in round_trip(&counter) {
+        assert_eq!(decoded, counter);
+        assert_eq!(decoded.value(), 13);
+    }
+}
+
+#[cfg(feature = "sets")]
+#[test]
+fn test_or_set_keeps_removed_tags() {
+    use synckit_core::crdt::ORSet;
+
+    let mut set = ORSet::new("a".to_string());
+    set.add("x".to_string());
+    set.add("y".to_string());
+    let before_remove = set.clone();
+    set.remove(&"x".to_string());
+
+    for mut decoded in round_trip(&set) {
+        assert_eq!(decoded, set);
+        assert!(!decoded.contains(&"x".to_string()));
+
+        // The removed tags survive: re-merging the old add does not revive it
+        decoded.merge(&before_remove);
+        assert!(!decoded.contains(&"x".to_string()));
+    }
+}
+
+#[cfg(feature = "fractional-index")]
+#[test]
+fn test_fractional_index() {
+    use synckit_core::crdt::FractionalIndex;
+
+    let first = FractionalIndex::first();
+    let after = FractionalIndex::between(&first, &FractionalIndex::from_str("z".to_string()));
+    assert_round_trip_eq(&first);
+    assert_round_trip_eq(&after);
+}
@@ -0,0 +1,96 @@
+//! Stress tests for the shared handles: 8 threads writing and merging at
+//! once must never deadlock and must converge.
+
+#![cfg(feature = "std")]
+
+use serde_json::json;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use synckit_core::concurrent::SharedDocument;
+
+const THREADS: usize = 8;
+const ROUNDS: u64 = 200;
+
+#[test]
+fn test_concurrent_set_field_and_merge_converge() {
+    let a = SharedDocument::new("doc-1".to_string());
+    let b = SharedDocument::new("doc-1".to_string());
+    let barrier = Arc::new(Barrier::new(THREADS));
+
+    let workers: Vec<_> = (0..THREADS)
+        .map(|n| {
+            let (a, b, barrier) = (a.clone(), b.clone(), Arc::clone(&barrier));
+            thread::spawn(move || {
+                let client = format!("client-{}", n);
+                barrier.wait();
+                for round in 1..=ROUNDS {
+                    // Half the threads write to each replica
+                    let (mine, other) = if n % 2 == 0 { (&a, &b) } else { (&b, &a) };
+                    mine.set_field(format!("own.{}", n), json!(round), round, client.clone());
+                    mine.set_field("shared".to_string(), json!(n), round, client.clone());
+
+                    // Opposite-direction merges must not deadlock
+                    if round % 10 == 0 {
+                        other.merge_shared(mine);
+                        mine.merge_shared(other);
+                    }
+                    let snapshot = mine.read_snapshot();
+                    assert!(snapshot.field_count() <= THREADS + 1);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    a.merge_shared(&b);
+    b.merge_shared(&a);
+    assert_eq!(a.to_json(), b.to_json());
+
+    for n in 0..THREADS {
+        assert_eq!(a.get_field(&format!("own.{}", n)), Some(json!(ROUNDS)));
+    }
+    // Every thread wrote "shared" at clock ROUNDS last; the highest client ID
+    // wins the tie
+    assert_eq!(a.get_field(&"shared".to_string()), Some(json!(THREADS - 1)));
+}
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn test_concurrent_text_edits_keep_every_insert() {
+    use synckit_core::concurrent::SharedText;
+
+    // Text merges are much costlier than field writes; keep this one short
+    const TEXT_ROUNDS: usize = 25;
+
+    let text = SharedText::new("server".to_string());
+    let mirror = SharedText::new("mirror".to_string());
+    let barrier = Arc::new(Barrier::new(THREADS));
+
+    let workers: Vec<_> = (0..THREADS)
+        .map(|n| {
+            let (text, mirror, barrier) = (text.clone(), mirror.clone(), Arc::clone(&barrier));
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..TEXT_ROUNDS {
+                    text.write(|t| t.insert(t.len(), &n.to_string()).unwrap());
+                    if n == 0 {
+                        mirror.merge_shared(&text).unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    mirror.merge_shared(&text).unwrap();
+    assert_eq!(text.len(), THREADS * TEXT_ROUNDS);
+    assert_eq!(mirror.to_string(), text.to_string());
+    for n in 0..THREADS {
+        let count = text.to_string().matches(&n.to_string()).count();
+        assert_eq!(count, TEXT_ROUNDS);
+    }
+}
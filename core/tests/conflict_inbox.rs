@@ -0,0 +1,178 @@
+//! Values that lose LWW in sync land in the document's conflict inbox
+//! once, however often the losing write comes around again, and stay
+//! there until resolved
+
+#![cfg(feature = "protocol-binary")]
+
+use serde_json::json;
+use synckit_core::conflicts::Keep;
+use synckit_core::protocol::delta::DocumentDelta;
+use synckit_core::protocol::sync::SyncCoordinator;
+use synckit_core::Document;
+
+fn write(doc: &mut Document, path: &str, value: serde_json::Value, clock: u64, client: &str) {
+    doc.set_field(path.to_string(), value, clock, client.to_string());
+    doc.version.update(client, clock);
+}
+
+/// A replica and its sync coordinator
+struct Peer {
+    name: &'static str,
+    doc: Document,
+    coordinator: SyncCoordinator,
+}
+
+impl Peer {
+    fn new(name: &'static str, base: &Document) -> Self {
+        Self {
+            name,
+            doc: base.clone(),
+            coordinator: SyncCoordinator::new(),
+        }
+    }
+
+    fn delta_since(&self, base: &Document) -> DocumentDelta {
+        self.coordinator.outgoing_delta(base, &self.doc).unwrap()
+    }
+
+    fn receive(&mut self, delta: &DocumentDelta) {
+        self.coordinator
+            .apply_incoming(delta, &mut self.doc, self.name)
+            .unwrap();
+    }
+}
+
+/// Alice and Bob edit the title concurrently from a shared base, and each
+/// receives the other's delta
+fn concurrent_titles() -> (Document, Peer, Peer, DocumentDelta, DocumentDelta) {
+    let mut base = Document::new("doc".to_string());
+    write(&mut base, "title", json!("Draft"), 1, "carol");
+    let mut alice = Peer::new("alice", &base);
+    let mut bob = Peer::new("bob", &base);
+    write(&mut alice.doc, "title", json!("Alice's title"), 2, "alice");
+    write(&mut alice.doc, "body", json!("Hello"), 3, "alice");
+    write(&mut bob.doc, "title", json!("Bob's title"), 2, "bob");
+    let from_alice = alice.delta_since(&base);
+    let from_bob = bob.delta_since(&base);
+    alice.receive(&from_bob);
+    bob.receive(&from_alice);
+    (base, alice, bob, from_alice, from_bob)
+}
+
+#[test]
+fn test_losing_write_recorded_once_on_both_sides() {
+    let (_, alice, bob, _, _) = concurrent_titles();
+    assert_eq!(
+        alice.doc.get_field(&"title".to_string()),
+        Some(&json!("Bob's title"))
+    );
+    assert_eq!(
+        bob.doc.get_field(&"title".to_string()),
+        Some(&json!("Bob's title"))
+    );
+
+    // Alice's value lost locally; on Bob's side the remote write lost
+    let mine = alice.doc.conflicts().entries();
+    let theirs = bob.doc.conflicts().entries();
+    assert_eq!((mine.len(), theirs.len()), (1, 1));
+    assert!(mine[0].remote_won && !theirs[0].remote_won);
+    assert_eq!(mine[0].lost(), theirs[0].lost());
+    assert_eq!(mine[0].lost().value, json!("Alice's title"));
+    assert_eq!(mine[0].id, theirs[0].id);
+}
+
+#[test]
+fn test_sync_echoes_do_not_duplicate_conflicts() {
+    let (base, mut alice, mut bob, from_alice, from_bob) = concurrent_titles();
+    for _ in 0..5 {
+        // Resends, and each side's full state relayed back to it
+        alice.receive(&from_bob);
+        bob.receive(&from_alice);
+        let echo_to_alice = bob.delta_since(&base);
+        let echo_to_bob = alice.delta_since(&base);
+        alice.receive(&echo_to_alice);
+        bob.receive(&echo_to_bob);
+        alice.doc.merge(&bob.doc.clone());
+        bob.doc.merge(&alice.doc.clone());
+    }
+    assert_eq!(alice.doc.conflicts().len(), 1);
+    assert_eq!(bob.doc.conflicts().len(), 1);
+}
+
+#[test]
+fn test_resolving_writes_the_choice_and_stays_resolved() {
+    let (base, mut alice, mut bob, from_alice, _) = concurrent_titles();
+    let id = alice.doc.conflicts().entries()[0].id;
+
+    // A clock that loses to the current value changes nothing
+    assert!(!alice
+        .doc
+        .resolve_conflict(&id, Keep::Local, 1, "alice".to_string())
+        .unwrap());
+    assert_eq!(alice.doc.conflicts().len(), 1);
+
+    assert!(alice
+        .doc
+        .resolve_conflict(&id, Keep::Local, 4, "alice".to_string())
+        .unwrap());
+    alice.doc.version.update("alice", 4);
+    assert!(alice.doc.conflicts().is_empty());
+    assert!(alice
+        .doc
+        .resolve_conflict(&id, Keep::Remote, 5, "alice".to_string())
+        .is_err());
+
+    // The choice syncs as an ordinary write
+    bob.receive(&alice.delta_since(&base));
+    assert_eq!(
+        bob.doc.get_field(&"title".to_string()),
+        Some(&json!("Alice's title"))
+    );
+
+    // Old writes coming around again don't reopen it
+    alice.receive(&from_alice);
+    alice.receive(&bob.delta_since(&base));
+    assert!(alice.doc.conflicts().is_empty());
+
+    // A new concurrent write does
+    write(&mut bob.doc, "title", json!("Bob again"), 4, "bob");
+    alice.receive(&bob.delta_since(&base));
+    assert_eq!(alice.doc.conflicts().len(), 1);
+}
+
+#[test]
+fn test_custom_resolution_and_persistence() {
+    let (_, mut alice, _, _, _) = concurrent_titles();
+    let saved: Document =
+        serde_json::from_str(&serde_json::to_string(&alice.doc).unwrap()).unwrap();
+    assert_eq!(saved.conflicts(), alice.doc.conflicts());
+
+    let id = alice.doc.conflicts().entries()[0].id;
+    let merged = json!("Alice's and Bob's title");
+    alice
+        .doc
+        .resolve_conflict(&id, Keep::Custom(merged.clone()), 9, "alice".to_string())
+        .unwrap();
+    assert_eq!(alice.doc.get_field(&"title".to_string()), Some(&merged));
+}
+
+#[test]
+fn test_log_keeps_the_newest_conflicts() {
+    let mut doc = Document::new("doc".to_string());
+    doc.set_conflict_limit(3);
+    for clock in 1..=5 {
+        let path = format!("field{}", clock);
+        doc.set_field(path.clone(), json!("mine"), clock, "alice".to_string());
+        let mut remote = Document::new("doc".to_string());
+        remote.set_field(path, json!("theirs"), clock, "bob".to_string());
+        doc.merge(&remote);
+    }
+    let paths: Vec<&str> = doc
+        .conflicts()
+        .entries()
+        .iter()
+        .map(|conflict| conflict.path.as_str())
+        .collect();
+    assert_eq!(paths, ["field3", "field4", "field5"]);
+    assert_eq!(doc.set_conflict_limit(1), 2);
+}
@@ -0,0 +1,778 @@
+//! Algebraic laws checked for every CRDT type
+//!
+//! `CrdtLaws<T>` runs, for any type implementing `Crdt`:
+//!
+//! - Commutativity: `a ∪ b == b ∪ a`
+//! - Associativity: `(a ∪ b) ∪ c == a ∪ (b ∪ c)`
+//! - Idempotence: `a ∪ a == a`
+//! - Convergence: replicas running an arbitrary interleaving of local ops
+//!   and one-way syncs agree once everyone has synced with everyone
+//!
+//! States are compared by a hash of their observable state, so replica-local
+//! bookkeeping (replica IDs, sequence counters) doesn't count. Failures
+//! shrink to a minimal `Script`, printed one step per line so it can be
+//! replayed by hand.
+//!
+//! A new CRDT type gets the same coverage by implementing `Crdt` and adding
+//! a test that calls `CrdtLaws::<NewType>::check()`.
+
+#![cfg(feature = "std")]
+
+use proptest::prelude::*;
+use proptest::test_runner::{Config, TestCaseError, TestRunner};
+use serde_json::json;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::time::Duration;
+use synckit_core::list::{self, List, ListId};
+use synckit_core::locks::AdvisoryLocks;
+use synckit_core::register::MVRegister;
+use synckit_core::{Document, Timestamp, VectorClock};
+
+#[cfg(feature = "text-crdt")]
+use synckit_core::crdt::FugueText;
+#[cfg(feature = "sets")]
+use synckit_core::crdt::ORSet;
+#[cfg(feature = "counters")]
+use synckit_core::crdt::PNCounter;
+
+/// Replicas taking part in each script
+const REPLICAS: usize = 3;
+
+/// A state-based CRDT with a generator for its local operations
+trait Crdt: Clone {
+    type Op: Debug + Clone + 'static;
+
+    /// Cases per law; lower for types whose ops are expensive
+    const CASES: u32 = 256;
+
+    /// Longest script generated
+    const MAX_STEPS: usize = 24;
+
+    /// Empty replica `index` (0..REPLICAS)
+    fn replica(index: usize) -> Self;
+
+    /// Generator for local operations
+    fn op() -> BoxedStrategy<Self::Op>;
+
+    /// Apply a local operation on replica `index` (invalid ones, e.g. out
+    /// of range, are no-ops)
+    fn apply(&mut self, index: usize, op: &Self::Op);
+
+    /// Merge another replica's state into this one
+    fn merge(&mut self, other: &Self);
+
+    /// Hash of the state every converged replica must agree on
+    fn state_hash(&self) -> u64;
+}
+
+fn hash_of(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// One step of a script
+#[derive(Clone)]
+enum Step<Op> {
+    /// Replica applies a local operation
+    Local(usize, Op),
+    /// Replica `to` merges the state of replica `from`
+    Sync { from: usize, to: usize },
+}
+
+/// A replayable sequence of steps
+#[derive(Clone)]
+struct Script<Op>(Vec<Step<Op>>);
+
+impl<Op: Debug> Debug for Step<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Local(replica, op) => write!(f, "r{}.apply({:?})", replica, op),
+            Step::Sync { from, to } => write!(f, "r{}.merge(&r{})", to, from),
+        }
+    }
+}
+
+impl<Op: Debug> Debug for Script<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Script [")?;
+        for step in &self.0 {
+            writeln!(f, "    {:?}", step)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl<Op: Clone> Script<Op> {
+    /// Run the script on fresh replicas
+    fn run<T: Crdt<Op = Op>>(&self) -> Vec<T> {
+        let mut replicas: Vec<T> = (0..REPLICAS).map(T::replica).collect();
+        for step in &self.0 {
+            match step {
+                Step::Local(replica, op) => replicas[*replica].apply(*replica, op),
+                Step::Sync { from, to } => {
+                    let source = replicas[*from].clone();
+                    replicas[*to].merge(&source);
+                }
+            }
+        }
+        replicas
+    }
+}
+
+/// Generic law checker for a `Crdt`
+struct CrdtLaws<T>(PhantomData<T>);
+
+impl<T: Crdt> CrdtLaws<T> {
+    fn script() -> impl Strategy<Value = Script<T::Op>> {
+        let step = prop_oneof![
+            3 => (0..REPLICAS, T::op()).prop_map(|(replica, op)| Step::Local(replica, op)),
+            1 => (0..REPLICAS, 0..REPLICAS)
+                .prop_filter("sync with self", |(from, to)| from != to)
+                .prop_map(|(from, to)| Step::Sync { from, to }),
+        ];
+        prop::collection::vec(step, 0..=T::MAX_STEPS).prop_map(Script)
+    }
+
+    fn merged(a: &T, b: &T) -> T {
+        let mut merged = a.clone();
+        merged.merge(b);
+        merged
+    }
+
+    fn run(name: &str, law: impl Fn(&Script<T::Op>) -> Result<(), TestCaseError>) {
+        let mut runner = TestRunner::new(Config {
+            cases: T::CASES,
+            ..Config::default()
+        });
+        if let Err(failure) = runner.run(&Self::script(), |script| law(&script)) {
+            panic!(
+                "{} law failed for {}: {}",
+                name,
+                std::any::type_name::<T>(),
+                failure
+            );
+        }
+    }
+
+    fn check() {
+        Self::run("commutativity", |script| {
+            let replicas = script.run::<T>();
+            let (a, b) = (&replicas[0], &replicas[1]);
+            prop_assert_eq!(
+                Self::merged(a, b).state_hash(),
+                Self::merged(b, a).state_hash()
+            );
+            Ok(())
+        });
+
+        Self::run("associativity", |script| {
+            let replicas = script.run::<T>();
+            let (a, b, c) = (&replicas[0], &replicas[1], &replicas[2]);
+            prop_assert_eq!(
+                Self::merged(&Self::merged(a, b), c).state_hash(),
+                Self::merged(a, &Self::merged(b, c)).state_hash()
+            );
+            Ok(())
+        });
+
+        Self::run("idempotence", |script| {
+            let replicas = script.run::<T>();
+            for replica in &replicas {
+                prop_assert_eq!(
+                    Self::merged(replica, replica).state_hash(),
+                    replica.state_hash()
+                );
+                let merged = Self::merged(&replicas[0], replica);
+                prop_assert_eq!(
+                    Self::merged(&merged, replica).state_hash(),
+                    merged.state_hash()
+                );
+            }
+            Ok(())
+        });
+
+        Self::run("convergence", |script| {
+            let mut replicas = script.run::<T>();
+            // Two rounds of all-pairs sync reach every replica with everything
+            for _ in 0..2 {
+                for to in 0..REPLICAS {
+                    for from in 0..REPLICAS {
+                        if from != to {
+                            let source = replicas[from].clone();
+                            replicas[to].merge(&source);
+                        }
+                    }
+                }
+            }
+            for replica in &replicas[1..] {
+                prop_assert_eq!(replica.state_hash(), replicas[0].state_hash());
+            }
+            Ok(())
+        });
+    }
+}
+
+fn client(index: usize) -> String {
+    format!("client{}", index)
+}
+
+// ---------------------------------------------------------------------------
+// Document
+
+#[derive(Debug, Clone)]
+enum DocumentOp {
+    Set {
+        field: String,
+        value: serde_json::Value,
+        clock: u64,
+    },
+    Delete(String),
+}
+
+impl Crdt for Document {
+    type Op = DocumentOp;
+
+    fn replica(_index: usize) -> Self {
+        Document::new("doc".to_string())
+    }
+
+    fn op() -> BoxedStrategy<DocumentOp> {
+        let field = prop::sample::select(vec!["a", "b", "c"]).prop_map(str::to_string);
+        prop_oneof![
+            4 => (field.clone(), any::<i8>(), 1u64..10).prop_map(|(field, value, clock)| {
+                DocumentOp::Set {
+                    field,
+                    value: json!(value),
+                    clock,
+                }
+            }),
+            1 => field.prop_map(DocumentOp::Delete),
+        ]
+        .boxed()
+    }
+
+    fn apply(&mut self, index: usize, op: &DocumentOp) {
+        let me = client(index);
+        match op {
+            DocumentOp::Set {
+                field,
+                value,
+                clock,
+            } => {
+                self.set_field(field.clone(), value.clone(), *clock, me.clone());
+                self.version.tick(&me);
+            }
+            DocumentOp::Delete(field) => self.delete_field(field),
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        Document::merge(self, other);
+    }
+
+    fn state_hash(&self) -> u64 {
+        let fields: BTreeMap<_, _> = self
+            .fields()
+            .iter()
+            .map(|(path, field)| {
+                (
+                    path.clone(),
+                    (
+                        field.value.to_string(),
+                        field.timestamp.clock,
+                        field.timestamp.client_id.clone(),
+                    ),
+                )
+            })
+            .collect();
+        let version: BTreeMap<_, _> = self.version().clocks().iter().collect();
+        hash_of(&(fields, version))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// VectorClock
+
+#[derive(Debug, Clone)]
+enum VectorClockOp {
+    Tick,
+    Observe { client: usize, clock: u64 },
+}
+
+impl Crdt for VectorClock {
+    type Op = VectorClockOp;
+
+    fn replica(_index: usize) -> Self {
+        VectorClock::new()
+    }
+
+    fn op() -> BoxedStrategy<VectorClockOp> {
+        prop_oneof![
+            Just(VectorClockOp::Tick),
+            (0..REPLICAS, 0u64..20)
+                .prop_map(|(client, clock)| VectorClockOp::Observe { client, clock }),
+        ]
+        .boxed()
+    }
+
+    fn apply(&mut self, index: usize, op: &VectorClockOp) {
+        match op {
+            VectorClockOp::Tick => self.tick(&client(index)),
+            VectorClockOp::Observe {
+                client: seen,
+                clock,
+            } => {
+                let mut observed = VectorClock::new();
+                observed.update(&client(*seen), *clock);
+                VectorClock::merge(self, &observed);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        VectorClock::merge(self, other);
+    }
+
+    fn state_hash(&self) -> u64 {
+        // A client at 0 is the same as one never seen
+        let clocks: BTreeMap<_, _> = self
+            .clocks()
+            .iter()
+            .filter(|(_, clock)| **clock > 0)
+            .collect();
+        hash_of(&clocks)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// MVRegister
+
+#[derive(Debug, Clone)]
+struct RegisterOp(i8);
+
+impl Crdt for MVRegister {
+    type Op = RegisterOp;
+
+    fn replica(_index: usize) -> Self {
+        MVRegister::new()
+    }
+
+    fn op() -> BoxedStrategy<RegisterOp> {
+        any::<i8>().prop_map(RegisterOp).boxed()
+    }
+
+    fn apply(&mut self, index: usize, op: &RegisterOp) {
+        // Each replica writes as its own client, with its next clock
+        let me = client(index);
+        let clock = self.seen().get(&me) + 1;
+        assert!(self.write(json!(op.0), Timestamp::new(clock, me)));
+    }
+
+    fn merge(&mut self, other: &Self) {
+        MVRegister::merge(self, other);
+    }
+
+    fn state_hash(&self) -> u64 {
+        let values: Vec<_> = self
+            .values()
+            .iter()
+            .map(|field| {
+                (
+                    field.value.to_string(),
+                    field.timestamp.clock,
+                    field.timestamp.client_id.clone(),
+                )
+            })
+            .collect();
+        let seen: BTreeMap<_, _> = self.seen().clocks().iter().collect();
+        hash_of(&(values, seen))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// List
+
+#[derive(Debug, Clone)]
+enum ListOp {
+    /// Insert at `index % (len + 1)`
+    Insert { index: usize, value: i8 },
+    /// Move the item at `from % len` so it ends up at `to % len`
+    Move { from: usize, to: usize },
+    /// Set the item at `index % len`
+    Set { index: usize, value: i8 },
+    /// Remove the item at `index % len`
+    Remove { index: usize },
+}
+
+/// Highest clock in `list`, and the slot each of its items shows at
+fn list_slots(list: &List) -> (u64, HashMap<ListId, ListId>) {
+    let mut clock = 0;
+    let mut slots = HashMap::new();
+    // Slots come oldest first, so each item ends up at its newest one
+    for op in list.ops_since(None) {
+        match op {
+            list::ListOp::Insert { id, set_at, .. } => {
+                clock = clock.max(id.clock).max(set_at.clock);
+                slots.insert(id.clone(), id);
+            }
+            list::ListOp::Move { slot, item, .. } => {
+                clock = clock.max(slot.clock);
+                slots.insert(item, slot);
+            }
+            list::ListOp::Set { .. } | list::ListOp::Remove { .. } => {}
+        }
+    }
+    (clock, slots)
+}
+
+impl Crdt for List {
+    type Op = ListOp;
+
+    fn replica(_index: usize) -> Self {
+        List::new()
+    }
+
+    fn op() -> BoxedStrategy<ListOp> {
+        prop_oneof![
+            3 => (0usize..16, any::<i8>()).prop_map(|(index, value)| ListOp::Insert { index, value }),
+            1 => (0usize..16, 0usize..16).prop_map(|(from, to)| ListOp::Move { from, to }),
+            1 => (0usize..16, any::<i8>()).prop_map(|(index, value)| ListOp::Set { index, value }),
+            1 => (0usize..16).prop_map(|index| ListOp::Remove { index }),
+        ]
+        .boxed()
+    }
+
+    fn apply(&mut self, index: usize, op: &ListOp) {
+        // A standalone list is only edited through operations, so build
+        // the ones `Document::list_mut` would make for this edit
+        let len = self.len();
+        let (clock, slots) = list_slots(self);
+        let next = ListId {
+            clock: clock + 1,
+            client_id: client(index),
+        };
+        let item = |index: usize| self.id_at(index).unwrap().clone();
+        let slot = |index: usize| slots[self.id_at(index).unwrap()].clone();
+        let op = match *op {
+            ListOp::Insert { index, value } => list::ListOp::Insert {
+                id: next.clone(),
+                after: (index % (len + 1)).checked_sub(1).map(slot),
+                value: json!(value),
+                set_at: next,
+            },
+            _ if len == 0 => return,
+            ListOp::Move { from, to } => {
+                let (from, to) = (from % len, to % len);
+                if from == to {
+                    return;
+                }
+                // Anchor at the item that ends up just before it
+                let previous = if to > from {
+                    Some(to)
+                } else {
+                    to.checked_sub(1)
+                };
+                list::ListOp::Move {
+                    slot: next,
+                    after: previous.map(slot),
+                    item: item(from),
+                }
+            }
+            ListOp::Set { index, value } => list::ListOp::Set {
+                item: item(index % len),
+                value: json!(value),
+                set_at: next,
+            },
+            ListOp::Remove { index } => list::ListOp::Remove {
+                item: item(index % len),
+            },
+        };
+        self.apply_ops(&[op]).unwrap();
+    }
+
+    fn merge(&mut self, other: &Self) {
+        List::merge(self, other);
+    }
+
+    fn state_hash(&self) -> u64 {
+        let items: Vec<_> = self
+            .iter()
+            .map(|(id, value)| (id.clone(), value.to_string()))
+            .collect();
+        hash_of(&items)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// AdvisoryLocks
+
+#[derive(Debug, Clone)]
+enum LockOp {
+    /// Acquire `path` at `now_ms` for `ttl_ms`
+    Acquire {
+        path: String,
+        now_ms: u64,
+        ttl_ms: u64,
+    },
+    Release(String),
+}
+
+impl Crdt for AdvisoryLocks {
+    type Op = LockOp;
+
+    fn replica(_index: usize) -> Self {
+        AdvisoryLocks::default()
+    }
+
+    fn op() -> BoxedStrategy<LockOp> {
+        let path = prop::sample::select(vec!["title", "body"]).prop_map(str::to_string);
+        prop_oneof![
+            2 => (path.clone(), 0u64..100, 1u64..50)
+                .prop_map(|(path, now_ms, ttl_ms)| LockOp::Acquire { path, now_ms, ttl_ms }),
+            1 => path.prop_map(LockOp::Release),
+        ]
+        .boxed()
+    }
+
+    fn apply(&mut self, index: usize, op: &LockOp) {
+        // Refusals (someone else holds the path) leave the state as is
+        match op {
+            LockOp::Acquire {
+                path,
+                now_ms,
+                ttl_ms,
+            } => {
+                let ttl = Duration::from_millis(*ttl_ms);
+                self.acquire(path, &client(index), ttl, *now_ms);
+            }
+            LockOp::Release(path) => {
+                self.release(path, &client(index));
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        AdvisoryLocks::merge(self, other);
+    }
+
+    fn state_hash(&self) -> u64 {
+        // Leases are kept in path order, so the JSON is canonical
+        hash_of(&serde_json::to_string(self).unwrap())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PNCounter
+
+#[cfg(feature = "counters")]
+#[derive(Debug, Clone)]
+enum CounterOp {
+    Increment(i64),
+    Decrement(i64),
+}
+
+#[cfg(feature = "counters")]
+impl Crdt for PNCounter {
+    type Op = CounterOp;
+
+    fn replica(index: usize) -> Self {
+        PNCounter::new(client(index))
+    }
+
+    fn op() -> BoxedStrategy<CounterOp> {
+        prop_oneof![
+            (0i64..100).prop_map(CounterOp::Increment),
+            (0i64..100).prop_map(CounterOp::Decrement),
+        ]
+        .boxed()
+    }
+
+    fn apply(&mut self, _index: usize, op: &CounterOp) {
+        match op {
+            CounterOp::Increment(amount) => self.increment(*amount),
+            CounterOp::Decrement(amount) => self.decrement(*amount),
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        PNCounter::merge(self, other);
+    }
+
+    fn state_hash(&self) -> u64 {
+        // Per-replica totals, without the owning replica's ID
+        let mut state = serde_json::to_value(self).unwrap();
+        state.as_object_mut().unwrap().remove("replica_id");
+        hash_of(&(self.value(), state.to_string()))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ORSet
+
+#[cfg(feature = "sets")]
+#[derive(Debug, Clone)]
+enum SetOp {
+    Add(String),
+    Remove(String),
+}
+
+#[cfg(feature = "sets")]
+impl Crdt for ORSet<String> {
+    type Op = SetOp;
+
+    fn replica(index: usize) -> Self {
+        ORSet::new(client(index))
+    }
+
+    fn op() -> BoxedStrategy<SetOp> {
+        let element = prop::sample::select(vec!["x", "y", "z"]).prop_map(str::to_string);
+        prop_oneof![
+            2 => element.clone().prop_map(SetOp::Add),
+            1 => element.prop_map(SetOp::Remove),
+        ]
+        .boxed()
+    }
+
+    fn apply(&mut self, _index: usize, op: &SetOp) {
+        match op {
+            SetOp::Add(element) => self.add(element.clone()),
+            SetOp::Remove(element) => self.remove(element),
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        ORSet::merge(self, other);
+    }
+
+    fn state_hash(&self) -> u64 {
+        let mut elements: Vec<&String> = self.iter().collect();
+        elements.sort();
+        hash_of(&elements)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FugueText
+
+#[cfg(feature = "text-crdt")]
+#[derive(Debug, Clone)]
+enum TextOp {
+    /// Insert at `position % (len + 1)`
+    Insert { position: usize, text: String },
+    /// Delete up to `len` characters from `position % len`
+    Delete { position: usize, len: usize },
+}
+
+#[cfg(feature = "text-crdt")]
+impl Crdt for FugueText {
+    type Op = TextOp;
+
+    // Every edit rebuilds the document order, so keep scripts short
+    const CASES: u32 = 128;
+    const MAX_STEPS: usize = 12;
+
+    fn replica(index: usize) -> Self {
+        FugueText::new(client(index))
+    }
+
+    fn op() -> BoxedStrategy<TextOp> {
+        prop_oneof![
+            2 => (0usize..16, "[a-c]{1,3}")
+                .prop_map(|(position, text)| TextOp::Insert { position, text }),
+            1 => (0usize..16, 1usize..4).prop_map(|(position, len)| TextOp::Delete { position, len }),
+        ]
+        .boxed()
+    }
+
+    fn apply(&mut self, _index: usize, op: &TextOp) {
+        let len = self.len();
+        match op {
+            TextOp::Insert { position, text } => {
+                self.insert(position % (len + 1), text).unwrap();
+            }
+            TextOp::Delete {
+                position,
+                len: count,
+            } => {
+                if len > 0 {
+                    let start = position % len;
+                    self.delete(start, (*count).min(len - start)).unwrap();
+                }
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        FugueText::merge(self, other).unwrap();
+    }
+
+    fn state_hash(&self) -> u64 {
+        let state_vector: BTreeMap<_, _> = self
+            .state_vector()
+            .clocks()
+            .iter()
+            .map(|(client, clock)| (client.clone(), *clock))
+            .collect();
+        hash_of(&(self.to_string(), state_vector))
+    }
+}
+
+#[test]
+fn document_laws() {
+    CrdtLaws::<Document>::check();
+}
+
+#[test]
+fn vector_clock_laws() {
+    CrdtLaws::<VectorClock>::check();
+}
+
+#[test]
+fn mv_register_laws() {
+    CrdtLaws::<MVRegister>::check();
+}
+
+#[test]
+fn list_laws() {
+    CrdtLaws::<List>::check();
+}
+
+#[test]
+fn advisory_locks_laws() {
+    CrdtLaws::<AdvisoryLocks>::check();
+}
+
+#[cfg(feature = "counters")]
+#[test]
+fn pn_counter_laws() {
+    CrdtLaws::<PNCounter>::check();
+}
+
+#[cfg(feature = "sets")]
+#[test]
+fn or_set_laws() {
+    CrdtLaws::<ORSet<String>>::check();
+}
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn fugue_text_laws() {
+    CrdtLaws::<FugueText>::check();
+}
+
+#[test]
+fn script_prints_replayable_steps() {
+    let script = Script(vec![
+        Step::Local(0, VectorClockOp::Tick),
+        Step::Sync { from: 0, to: 2 },
+    ]);
+    assert_eq!(
+        format!("{:?}", script),
+        "Script [\n    r0.apply(Tick)\n    r2.merge(&r0)\n]"
+    );
+}
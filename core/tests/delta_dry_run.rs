@@ -0,0 +1,195 @@
+//! `DocumentDelta::dry_run` predicts exactly what `apply_to` does, and
+//! works on the target in place
+//!
+//! Tracks the peak heap with a wrapping global allocator, so this runs as
+//! its own test binary.
+
+#![cfg(feature = "protocol-binary")]
+
+use proptest::prelude::*;
+use serde_json::{json, Value};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::BTreeSet;
+use synckit_core::protocol::delta::DocumentDelta;
+use synckit_core::Document;
+
+struct Counting;
+
+// Per thread, so allocations by the test harness's other threads don't
+// show up in the count
+thread_local! {
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    static PEAK_BYTES: Cell<isize> = const { Cell::new(0) };
+}
+
+fn add(delta: isize) {
+    // Ignore allocations made while the thread is being torn down
+    let _ = LIVE_BYTES.try_with(|live| {
+        live.set(live.get() + delta);
+        let _ = PEAK_BYTES.try_with(|peak| peak.set(peak.get().max(live.get())));
+    });
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        add(layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        add(-(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        add(new_size as isize - layout.size() as isize);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Heap `f` needs at its peak beyond what was live before
+fn peak_bytes<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let live = LIVE_BYTES.with(Cell::get);
+    PEAK_BYTES.with(|peak| peak.set(live));
+    let result = f();
+    let peak = PEAK_BYTES.with(Cell::get);
+    (result, (peak - live) as usize)
+}
+
+#[test]
+fn test_dry_run_copies_no_document() {
+    // ~4 MB of distinct values, plus one large value both sides hold
+    let template = json!({ "body": "lorem ipsum ".repeat(1000) });
+    let mut base = Document::new("doc".to_string());
+    for i in 0..2_000 {
+        let value = json!(format!("{:04} {}", i, "x".repeat(2_000)));
+        base.set_field(format!("cell-{}", i), value, 1, "alice".to_string());
+    }
+    base.set_field(
+        "template".to_string(),
+        template.clone(),
+        1,
+        "alice".to_string(),
+    );
+    base.list_mut("rows".to_string(), "alice".to_string())
+        .push(json!(1));
+    let document_bytes = serde_json::to_vec(&base).unwrap().len();
+    assert!(document_bytes > 4_000_000);
+
+    let mut remote = base.clone();
+    for i in 0..10 {
+        remote.set_field(format!("cell-{}", i * 7), json!(i), 2, "bob".to_string());
+    }
+    remote.set_field("copy".to_string(), template, 2, "bob".to_string());
+    remote
+        .list_mut("rows".to_string(), "bob".to_string())
+        .push(json!(2));
+    let delta = DocumentDelta::compute(&base, &remote).unwrap();
+    assert!(delta
+        .changes
+        .iter()
+        .any(|change| change.value_ref.is_some()));
+
+    let (report, peak) = peak_bytes(|| delta.dry_run(&base, "carol").unwrap());
+    assert_eq!(report.changed_paths.len(), 12);
+    // The changed values (12 KB for the template), never the document
+    assert!(peak < 100_000, "{} bytes at peak", peak);
+}
+
+/// An edit of one replica: set, delete or list push, at one of a few
+/// paths so that edits collide
+#[derive(Debug, Clone)]
+enum Edit {
+    Set(usize, Value, u64, usize),
+    Delete(usize),
+    Push(usize, usize),
+}
+
+fn edit() -> impl Strategy<Value = Edit> {
+    let value = prop_oneof![
+        Just(json!(null)),
+        any::<i32>().prop_map(|i| json!(i)),
+        "[a-c]{0,3}".prop_map(|s| json!(s)),
+    ];
+    prop_oneof![
+        4 => (0..6usize, value, 1..6u64, 0..3usize)
+            .prop_map(|(path, value, clock, client)| Edit::Set(path, value, clock, client)),
+        1 => (0..6usize).prop_map(Edit::Delete),
+        1 => (0..2usize, 0..3usize).prop_map(|(path, client)| Edit::Push(path, client)),
+    ]
+}
+
+fn apply(document: &mut Document, edits: &[Edit]) {
+    let clients = ["alice", "bob", "carol"];
+    for edit in edits {
+        match edit {
+            Edit::Set(path, value, clock, client) => document.set_field(
+                format!("f{}", path),
+                value.clone(),
+                *clock,
+                clients[*client].to_string(),
+            ),
+            Edit::Delete(path) => document.delete_field(&format!("f{}", path)),
+            Edit::Push(path, client) => {
+                let len = document.list(&format!("l{}", path)).map_or(0, |l| l.len());
+                document
+                    .list_mut(format!("l{}", path), clients[*client].to_string())
+                    .push(json!(len));
+            }
+        }
+    }
+}
+
+/// Paths whose field or list differs between two states of a document
+fn changed_paths(before: &Document, after: &Document) -> Vec<String> {
+    let paths: BTreeSet<&String> = [before, after]
+        .into_iter()
+        .flat_map(|document| document.fields().keys().chain(document.lists().keys()))
+        .collect();
+    paths
+        .into_iter()
+        .filter(|path| {
+            before.fields().get(*path) != after.fields().get(*path)
+                || before.list(path) != after.list(path)
+        })
+        .cloned()
+        .collect()
+}
+
+proptest! {
+    #[test]
+    fn prop_apply_does_what_dry_run_predicts(
+        shared in prop::collection::vec(edit(), 0..10),
+        local in prop::collection::vec(edit(), 0..10),
+        remote in prop::collection::vec(edit(), 0..10),
+    ) {
+        let mut base = Document::new("doc".to_string());
+        apply(&mut base, &shared);
+        let mut target = base.clone();
+        apply(&mut target, &local);
+        let mut source = base.clone();
+        apply(&mut source, &remote);
+
+        let delta = DocumentDelta::compute(&base, &source).unwrap();
+        let before = target.clone();
+        let report = delta.dry_run(&target, "alice");
+        prop_assert!(changed_paths(&before, &target).is_empty());
+
+        match report {
+            Ok(report) => {
+                let applied = delta.apply_to(&mut target, "alice").unwrap();
+                prop_assert_eq!(&applied, &report.changes);
+                prop_assert_eq!(changed_paths(&before, &target), report.changed_paths);
+            }
+            Err(expected) => {
+                let error = delta.apply_to(&mut target, "alice").unwrap_err();
+                prop_assert_eq!(error.code(), expected.code());
+                prop_assert!(changed_paths(&before, &target).is_empty());
+            }
+        }
+    }
+}
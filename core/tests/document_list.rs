@@ -0,0 +1,117 @@
+//! The canonical todo scenario: two offline clients each append two items
+//! and reorder one, then sync both ways and must agree on all four items
+
+#![cfg(feature = "std")]
+
+use serde_json::{json, Value};
+use synckit_core::{Document, FieldValue};
+
+fn todo(title: &str) -> Value {
+    json!({ "title": title, "done": false })
+}
+
+fn titles(document: &Document) -> Vec<String> {
+    let list = document.list(&"items".to_string()).expect("items list");
+    list.iter()
+        .map(|(_, item)| item["title"].as_str().unwrap().to_string())
+        .collect()
+}
+
+/// Each client appends two todos and moves its second one to the top
+fn offline_edits(client: &str) -> Document {
+    let mut document = Document::new("todos".to_string());
+    let mut items = document.list_mut("items".to_string(), client.to_string());
+    items.push(todo(&format!("{} 1", client)));
+    items.push(todo(&format!("{} 2", client)));
+    items.move_item(1, 0).unwrap();
+    document
+}
+
+#[test]
+fn test_offline_appends_and_moves_converge() {
+    let mut alice = offline_edits("alice");
+    let mut bob = offline_edits("bob");
+    assert_eq!(titles(&alice), ["alice 2", "alice 1"]);
+
+    let alice_before = alice.clone();
+    alice.merge(&bob);
+    bob.merge(&alice_before);
+
+    assert_eq!(titles(&alice), titles(&bob));
+    assert_eq!(titles(&alice), ["bob 2", "alice 2", "bob 1", "alice 1"]);
+    assert_eq!(alice.to_json(), bob.to_json());
+    assert!(matches!(
+        alice.get_value(&"items".to_string()),
+        Some(FieldValue::List(list)) if list.len() == 4
+    ));
+
+    // Merging again changes nothing
+    assert_eq!(alice.merge(&bob), 0);
+}
+
+#[test]
+fn test_list_survives_serialization_and_dirty_chunks() {
+    let mut alice = offline_edits("alice");
+    let chunk = alice.take_dirty();
+    assert!(chunk.lists.contains_key("items"));
+
+    let json = serde_json::to_string(&alice).unwrap();
+    let loaded: Document = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.to_json(), alice.to_json());
+
+    let mut restored = Document::new("todos".to_string());
+    restored.apply_dirty(&chunk);
+    assert_eq!(restored.to_json(), alice.to_json());
+}
+
+#[cfg(feature = "protocol-binary")]
+#[test]
+fn test_deltas_carry_only_list_operations() {
+    use synckit_core::list::ListOp;
+    use synckit_core::protocol::delta::DocumentDelta;
+
+    let mut alice = offline_edits("alice");
+    let mut bob = offline_edits("bob");
+    let empty = Document::new("todos".to_string());
+
+    let to_bob = DocumentDelta::compute(&empty, &alice).unwrap();
+    let to_alice = DocumentDelta::compute(&empty, &bob).unwrap();
+    assert!(to_bob.changes.is_empty());
+    let ops = &to_bob.lists[0].ops;
+    assert_eq!(ops.len(), 3);
+    assert!(matches!(ops[2], ListOp::Move { .. }));
+
+    // Through protobuf, both ways
+    let to_bob = DocumentDelta::from_protocol(&to_bob.to_protocol(), "alice").unwrap();
+    to_bob.apply_to(&mut bob, "bob").unwrap();
+    to_alice.apply_to(&mut alice, "alice").unwrap();
+    assert_eq!(titles(&alice), titles(&bob));
+    assert_eq!(titles(&alice).len(), 4);
+
+    // A later edit ships as that one operation, not the whole list
+    let before = alice.clone();
+    alice
+        .list_mut("items".to_string(), "alice".to_string())
+        .remove(0)
+        .unwrap();
+    let delta = DocumentDelta::compute(&before, &alice).unwrap();
+    assert_eq!(
+        delta.lists[0].ops,
+        [ListOp::Remove {
+            item: bob
+                .list(&"items".to_string())
+                .unwrap()
+                .id_at(0)
+                .unwrap()
+                .clone(),
+        }]
+    );
+    delta.apply_to(&mut bob, "bob").unwrap();
+    assert_eq!(titles(&bob), ["alice 2", "bob 1", "alice 1"]);
+
+    // Operations on items the receiver lacks are refused whole
+    let mut stranger = Document::new("todos".to_string());
+    let error = delta.apply_to(&mut stranger, "carol").unwrap_err();
+    assert_eq!(error.code_name(), "PROTOCOL_ERROR");
+    assert!(stranger.list(&"items".to_string()).is_none());
+}
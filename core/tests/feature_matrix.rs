@@ -0,0 +1,206 @@
+//! Build and unit-test the crate under each meaningful feature combination
+//!
+//! The matrix is slow (a build per row), so it is `#[ignore]`d; run it with
+//! the cargo alias:
+//!
+//! ```text
+//! cargo feature-matrix                          # every row
+//! SYNCKIT_FEATURE_MATRIX=wasm cargo feature-matrix  # rows whose name contains "wasm"
+//! ```
+//!
+//! Each row runs `cargo check --all-targets` and `cargo test --lib` with
+//! warnings denied, in `target/feature-matrix` so the main build isn't
+//! invalidated. Rows expected not to build check for their
+//! `compile_error!` instead. `test_matrix_covers_every_feature` (not
+//! ignored) keeps the matrix in step with `[features]` in `Cargo.toml`.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+
+/// One feature combination and what building it should do
+struct Row {
+    name: &'static str,
+    /// Build without the default features
+    no_default: bool,
+    features: &'static [&'static str],
+    /// `Some(message)` if the build must fail with a `compile_error!`
+    /// containing it
+    compile_error: Option<&'static str>,
+}
+
+const fn row(name: &'static str, features: &'static [&'static str]) -> Row {
+    Row {
+        name,
+        no_default: true,
+        features,
+        compile_error: None,
+    }
+}
+
+/// A single feature on its own, without the defaults
+const fn alone(feature: &'static [&'static str]) -> Row {
+    row(feature[0], feature)
+}
+
+const MATRIX: &[Row] = &[
+    Row {
+        name: "no-default-features",
+        no_default: true,
+        features: &[],
+        compile_error: Some("needs the `std` feature or the `alloc-kernel` feature"),
+    },
+    Row {
+        name: "default",
+        no_default: false,
+        features: &[],
+        compile_error: None,
+    },
+    alone(&["alloc-kernel"]),
+    alone(&["std"]),
+    alone(&["core"]),
+    alone(&["core-lite"]),
+    alone(&["datetime"]),
+    alone(&["protocol-binary"]),
+    alone(&["protocol"]),
+    alone(&["text-crdt"]),
+    alone(&["counters"]),
+    alone(&["sets"]),
+    alone(&["fractional-index"]),
+    alone(&["text"]),
+    alone(&["advanced"]),
+    alone(&["yjs-interop"]),
+    alone(&["automerge-interop"]),
+    alone(&["serde-compact"]),
+    alone(&["cbor"]),
+    alone(&["async"]),
+    alone(&["testing"]),
+    alone(&["long-proptests"]),
+    alone(&["server"]),
+    alone(&["grpc"]),
+    alone(&["redis-fanout"]),
+    alone(&["testserver"]),
+    alone(&["parallel"]),
+    alone(&["tracing"]),
+    alone(&["derive"]),
+    alone(&["wasm"]),
+    alone(&["python"]),
+    alone(&["ffi"]),
+    alone(&["full"]),
+    // Combinations with code of their own
+    row(
+        "wasm-protocol-text",
+        &["wasm", "protocol-binary", "text-crdt"],
+    ),
+    row("wasm-advanced", &["wasm", "advanced"]),
+    row("parallel-text", &["parallel", "text-crdt"]),
+    row("compact-text", &["serde-compact", "text-crdt"]),
+    row(
+        "tracing-protocol-text",
+        &["tracing", "protocol-binary", "text-crdt"],
+    ),
+    row("server-advanced-text", &["server", "advanced", "text"]),
+    row(
+        "kernel-with-std-crdts",
+        &["alloc-kernel", "advanced", "text"],
+    ),
+];
+
+/// Features `[features]` in `Cargo.toml` declares
+fn declared_features() -> BTreeSet<String> {
+    let manifest =
+        std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+            .expect("Cargo.toml");
+    manifest
+        .lines()
+        .skip_while(|line| line.trim() != "[features]")
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('=').map(|(name, _)| name.trim()))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn test_matrix_covers_every_feature() {
+    let declared = declared_features();
+    assert!(declared.contains("std") && declared.contains("full"));
+    let covered: BTreeSet<String> = MATRIX
+        .iter()
+        .flat_map(|row| row.features.iter().map(|feature| feature.to_string()))
+        .chain(["default".to_string()])
+        .collect();
+    let missing: Vec<&String> = declared.difference(&covered).collect();
+    assert!(
+        missing.is_empty(),
+        "features missing from the matrix in tests/feature_matrix.rs: {:?}",
+        missing
+    );
+    let unknown: Vec<&String> = covered.difference(&declared).collect();
+    assert!(
+        unknown.is_empty(),
+        "matrix rows name unknown features: {:?}",
+        unknown
+    );
+}
+
+/// Run `cargo <args>` for a row, returning its stderr on failure
+fn cargo(row: &Row, args: &[&str]) -> Result<(), String> {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let mut command = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    command
+        .args(args)
+        .arg("--manifest-path")
+        .arg(Path::new(manifest_dir).join("Cargo.toml"))
+        .env(
+            "CARGO_TARGET_DIR",
+            Path::new(manifest_dir).join("target/feature-matrix"),
+        )
+        .env("RUSTFLAGS", "-D warnings");
+    if row.no_default {
+        command.arg("--no-default-features");
+    }
+    if !row.features.is_empty() {
+        command.arg("--features").arg(row.features.join(","));
+    }
+    let output = command.output().map_err(|e| e.to_string())?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+    }
+}
+
+/// Build and test one row, or describe how it went wrong
+fn check(row: &Row) -> Result<(), String> {
+    if let Some(message) = row.compile_error {
+        return match cargo(row, &["check", "--lib"]) {
+            Ok(()) => Err("built, but should have hit a compile_error!".to_string()),
+            Err(stderr) if stderr.contains(message) => Ok(()),
+            Err(stderr) => Err(format!(
+                "failed without the expected compile_error!:\n{}",
+                stderr
+            )),
+        };
+    }
+    cargo(row, &["check", "--all-targets"])?;
+    cargo(row, &["test", "--lib"])
+}
+
+#[test]
+#[ignore = "builds the crate once per row; run with `cargo feature-matrix`"]
+fn test_feature_matrix() {
+    let filter = std::env::var("SYNCKIT_FEATURE_MATRIX").unwrap_or_default();
+    let mut failures = Vec::new();
+    let mut ran = 0;
+    for row in MATRIX.iter().filter(|row| row.name.contains(&filter)) {
+        ran += 1;
+        eprintln!("feature matrix: {}", row.name);
+        if let Err(e) = check(row) {
+            failures.push(format!("{} ({:?}): {}", row.name, row.features, e));
+        }
+    }
+    assert!(ran > 0, "no matrix row matches {:?}", filter);
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
@@ -0,0 +1,22 @@
+//! Run the C test program in `tests/ffi/` against the C ABI
+//!
+//! build.rs compiles `tests/ffi/ffi_test.c` and links it into this test
+//! when the `ffi` feature is enabled.
+
+#![cfg(feature = "ffi")]
+
+use std::ffi::c_int;
+
+// Keep the Rust side of the ABI linked in for the C object to resolve against
+#[allow(unused_imports)]
+use synckit_core::ffi;
+
+extern "C" {
+    fn synckit_ffi_run_tests() -> c_int;
+}
+
+#[test]
+fn test_c_program() {
+    let failures = unsafe { synckit_ffi_run_tests() };
+    assert_eq!(failures, 0, "C FFI test program reported failures");
+}
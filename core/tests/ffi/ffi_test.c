@@ -0,0 +1,166 @@
+/*
+ * C test program for the SyncKit C ABI.
+ *
+ * Built by build.rs when the `ffi` feature is enabled and run from
+ * tests/ffi.rs. tests/ffi/run_asan.sh builds the same file against the
+ * shared library with AddressSanitizer to check for leaks.
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "synckit.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                         \
+    do {                                                                    \
+        if (!(cond)) {                                                      \
+            const char *err = synckit_last_error_message();                 \
+            fprintf(stderr, "%s:%d: check failed: %s (last error: %s)\n",   \
+                    __FILE__, __LINE__, #cond, err ? err : "none");         \
+            failures++;                                                     \
+        }                                                                   \
+    } while (0)
+
+/* Compare an owned string with an expected value, then free it */
+static int take_equals(char *actual, const char *expected) {
+    int equal = actual != NULL && strcmp(actual, expected) == 0;
+    if (!equal) {
+        fprintf(stderr, "expected %s, got %s\n", expected, actual ? actual : "NULL");
+    }
+    synckit_string_free(actual);
+    return equal;
+}
+
+static void test_document_create_edit_merge(void) {
+    SynckitDocument *a = synckit_document_new("doc-1");
+    SynckitDocument *b = synckit_document_new("doc-1");
+    CHECK(a != NULL && b != NULL);
+
+    CHECK(synckit_document_set_field_json(a, "title", "\"Hello\"", 1, "client-a") ==
+          SYNCKIT_STATUS_OK);
+    CHECK(synckit_document_set_field_json(b, "title", "\"World\"", 2, "client-b") ==
+          SYNCKIT_STATUS_OK);
+    CHECK(synckit_document_set_field_json(b, "count", "42", 1, "client-b") == SYNCKIT_STATUS_OK);
+
+    CHECK(synckit_document_merge(a, b) == SYNCKIT_STATUS_OK);
+    CHECK(take_equals(synckit_document_get_field_json(a, "title"), "\"World\""));
+    CHECK(take_equals(synckit_document_get_field_json(a, "count"), "42"));
+    CHECK(synckit_document_get_field_json(a, "missing") == NULL);
+    CHECK(synckit_last_error_message() == NULL);
+
+    CHECK(synckit_document_delete_field(a, "count") == SYNCKIT_STATUS_OK);
+    CHECK(take_equals(synckit_document_to_json(a), "{\"title\":\"World\"}"));
+
+    synckit_document_free(a);
+    synckit_document_free(b);
+}
+
+static void test_document_serialize(void) {
+    SynckitDocument *doc = synckit_document_new("doc-1");
+    CHECK(synckit_document_set_field_json(doc, "user.name", "\"Ada\"", 1, "client-a") ==
+          SYNCKIT_STATUS_OK);
+
+    size_t len = 0;
+    uint8_t *bytes = synckit_document_to_bytes(doc, &len);
+    CHECK(bytes != NULL && len > 0);
+
+    SynckitDocument *copy = synckit_document_from_bytes(bytes, len);
+    CHECK(copy != NULL);
+    CHECK(take_equals(synckit_document_get_field_json(copy, "user.name"), "\"Ada\""));
+
+    SynckitDocument *other = synckit_document_new("doc-1");
+    CHECK(synckit_document_merge_bytes(other, bytes, len) == SYNCKIT_STATUS_OK);
+    CHECK(take_equals(synckit_document_to_json(other), "{\"user.name\":\"Ada\"}"));
+
+    synckit_bytes_free(bytes, len);
+    synckit_document_free(copy);
+    synckit_document_free(other);
+    synckit_document_free(doc);
+}
+
+static void test_text_edit_merge_serialize(void) {
+    SynckitText *a = synckit_text_new("client-a");
+    SynckitText *b = synckit_text_new("client-b");
+
+    CHECK(synckit_text_insert(a, 0, "Hello") == SYNCKIT_STATUS_OK);
+    CHECK(synckit_text_insert(b, 0, "World") == SYNCKIT_STATUS_OK);
+
+    size_t len = 0;
+    uint8_t *bytes = synckit_text_to_bytes(b, &len);
+    CHECK(bytes != NULL);
+    CHECK(synckit_text_merge_bytes(a, bytes, len) == SYNCKIT_STATUS_OK);
+    synckit_bytes_free(bytes, len);
+    CHECK(synckit_text_merge(b, a) == SYNCKIT_STATUS_OK);
+
+    char *text_a = synckit_text_to_string(a);
+    char *text_b = synckit_text_to_string(b);
+    CHECK(text_a != NULL && text_b != NULL && strcmp(text_a, text_b) == 0);
+    CHECK(synckit_text_len(a) == 10);
+    synckit_string_free(text_a);
+    synckit_string_free(text_b);
+
+    /* Positions count characters, not bytes */
+    SynckitText *unicode = synckit_text_new("client-c");
+    CHECK(synckit_text_insert(unicode, 0, "h\xC3\xA9llo") == SYNCKIT_STATUS_OK);
+    CHECK(synckit_text_len(unicode) == 5);
+    CHECK(synckit_text_delete(unicode, 0, 1) == SYNCKIT_STATUS_OK);
+    CHECK(take_equals(synckit_text_to_string(unicode), "\xC3\xA9llo"));
+
+    bytes = synckit_text_to_bytes(unicode, &len);
+    SynckitText *copy = synckit_text_from_bytes(bytes, len);
+    synckit_bytes_free(bytes, len);
+    CHECK(take_equals(synckit_text_to_string(copy), "\xC3\xA9llo"));
+
+    synckit_text_free(copy);
+    synckit_text_free(unicode);
+    synckit_text_free(a);
+    synckit_text_free(b);
+}
+
+static void test_errors(void) {
+    SynckitDocument *doc = synckit_document_new("doc-1");
+
+    CHECK(synckit_document_set_field_json(doc, "title", "{oops", 1, "client-a") ==
+          SYNCKIT_STATUS_INVALID_JSON);
+    CHECK(synckit_last_error_message() != NULL);
+    CHECK(synckit_last_error_code() == 6003); /* INVALID_INPUT */
+
+    /* A successful call clears the last error */
+    CHECK(synckit_document_set_field_json(doc, "title", "1", 1, "client-a") == SYNCKIT_STATUS_OK);
+    CHECK(synckit_last_error_message() == NULL);
+    CHECK(synckit_last_error_code() == 0);
+
+    CHECK(synckit_document_set_field_json(doc, NULL, "1", 1, "client-a") ==
+          SYNCKIT_STATUS_NULL_POINTER);
+    CHECK(synckit_document_set_field_json(doc, "\xFF", "1", 1, "client-a") ==
+          SYNCKIT_STATUS_INVALID_UTF8);
+    CHECK(synckit_document_from_bytes((const uint8_t *)"nope", 4) == NULL);
+    CHECK(synckit_last_error_message() != NULL);
+
+    SynckitText *text = synckit_text_new("client-a");
+    CHECK(synckit_text_insert(text, 3, "x") == SYNCKIT_STATUS_TEXT_ERROR);
+    CHECK(synckit_text_insert(NULL, 0, "x") == SYNCKIT_STATUS_NULL_POINTER);
+
+    /* Freeing NULL is a no-op */
+    synckit_document_free(NULL);
+    synckit_text_free(NULL);
+    synckit_string_free(NULL);
+    synckit_bytes_free(NULL, 0);
+
+    synckit_text_free(text);
+    synckit_document_free(doc);
+}
+
+int synckit_ffi_run_tests(void) {
+    failures = 0;
+
+    CHECK(synckit_version() != NULL);
+    test_document_create_edit_merge();
+    test_document_serialize();
+    test_text_edit_merge_serialize();
+    test_errors();
+
+    return failures;
+}
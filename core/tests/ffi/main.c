@@ -0,0 +1,13 @@
+/* Standalone entry point for the C test program (see run_asan.sh) */
+
+#include <stdio.h>
+
+int synckit_ffi_run_tests(void);
+
+int main(void) {
+    int failures = synckit_ffi_run_tests();
+    if (failures == 0) {
+        printf("ffi tests passed\n");
+    }
+    return failures == 0 ? 0 : 1;
+}
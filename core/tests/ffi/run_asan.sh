@@ -0,0 +1,19 @@
+#!/bin/sh
+# Build the C test program against the SyncKit shared library with
+# AddressSanitizer (including LeakSanitizer) and run it. Any leaked
+# handle, string or buffer fails the run.
+#
+# Usage (from core/): tests/ffi/run_asan.sh
+set -eu
+
+cargo build --features ffi
+out="$(mktemp -d)"
+trap 'rm -rf "$out"' EXIT
+
+cc -fsanitize=address -fno-omit-frame-pointer -g \
+    -Iinclude tests/ffi/main.c tests/ffi/ffi_test.c \
+    -Ltarget/debug -lsynckit_core \
+    -o "$out/ffi_test"
+
+ASAN_OPTIONS=detect_leaks=1 LD_LIBRARY_PATH=target/debug DYLD_LIBRARY_PATH=target/debug \
+    "$out/ffi_test"
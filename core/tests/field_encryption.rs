@@ -0,0 +1,213 @@
+//! Fields a `FieldCipher` covers must never reach storage in plaintext,
+//! must come back on a load with the key, and must stay usable (merging
+//! last-writer-wins, visibly unreadable in JSON) on a load without it
+
+#![cfg(feature = "std")]
+
+use serde_json::json;
+use synckit_core::error::{Result, SyncKitError};
+use synckit_core::storage::{
+    Cipher, CompactionPolicy, EncryptedValue, FieldCipher, MemoryStorage, PersistentDocument,
+    Storage, ENCRYPTED_MARKER,
+};
+use synckit_core::Document;
+
+/// XOR with a one-byte key, holding only some keys
+struct XorCipher {
+    keys: Vec<(&'static str, u8)>,
+}
+
+impl Cipher for XorCipher {
+    fn key_id(&self) -> &str {
+        self.keys.first().map_or("none", |(id, _)| id)
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let (_, key) = self
+            .keys
+            .first()
+            .ok_or_else(|| SyncKitError::invalid_input("no key to encrypt with"))?;
+        Ok(plaintext.iter().map(|byte| byte ^ key).collect())
+    }
+
+    fn decrypt(&self, key_id: &str, ciphertext: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = self.keys.iter().find(|(id, _)| *id == key_id);
+        Ok(key.map(|(_, key)| ciphertext.iter().map(|byte| byte ^ key).collect()))
+    }
+}
+
+fn cipher(keys: &[(&'static str, u8)]) -> FieldCipher {
+    FieldCipher::new(XorCipher {
+        keys: keys.to_vec(),
+    })
+    .encrypt("users.*.email")
+}
+
+fn policy() -> CompactionPolicy {
+    CompactionPolicy {
+        max_ops_since_snapshot: 3,
+        ..CompactionPolicy::default()
+    }
+}
+
+fn open(storage: MemoryStorage, keys: &[(&'static str, u8)]) -> PersistentDocument<MemoryStorage> {
+    PersistentDocument::open_with_cipher("doc".to_string(), storage, policy(), cipher(keys))
+        .unwrap()
+}
+
+fn set(doc: &mut PersistentDocument<MemoryStorage>, path: &str, value: &str, clock: u64, by: &str) {
+    let document = doc.document_mut();
+    document.set_field(path.to_string(), json!(value), clock, by.to_string());
+    document.version.update(by, clock);
+}
+
+/// Every blob on storage, as text
+fn stored_text(storage: &MemoryStorage) -> String {
+    storage
+        .keys()
+        .into_iter()
+        .map(|key| String::from_utf8_lossy(&storage.read(key).unwrap().unwrap()).into_owned())
+        .collect()
+}
+
+/// Alice's document, with the email in the snapshot and an edit of it in
+/// the log
+fn saved() -> MemoryStorage {
+    let mut doc = open(MemoryStorage::new(), &[("k1", 0x5a)]);
+    set(&mut doc, "users.ada.email", "ada@example.com", 1, "alice");
+    set(&mut doc, "users.ada.name", "Ada", 2, "alice");
+    set(&mut doc, "title", "Team", 3, "alice");
+    assert!(doc.persist().unwrap());
+    set(&mut doc, "users.ada.email", "ada@lovelace.org", 4, "alice");
+    assert!(!doc.persist().unwrap());
+    doc.storage().restarted()
+}
+
+#[test]
+fn test_covered_fields_never_stored_in_plaintext() {
+    let storage = saved();
+    let stored = stored_text(&storage);
+    assert!(storage.keys().contains(&"doc.snapshot") && storage.keys().contains(&"doc.log"));
+    assert!(!stored.contains("ada@"), "plaintext email on storage");
+    assert!(stored.contains("Ada") && stored.contains("Team"));
+    assert!(stored.contains(ENCRYPTED_MARKER));
+}
+
+#[test]
+fn test_load_with_key_decrypts() {
+    let doc = open(saved(), &[("k1", 0x5a)]);
+    let document = doc.document();
+    assert_eq!(
+        document.get_field(&"users.ada.email".to_string()),
+        Some(&json!("ada@lovelace.org"))
+    );
+    assert_eq!(
+        document.get_field(&"users.ada.name".to_string()),
+        Some(&json!("Ada"))
+    );
+    assert!(!document.is_dirty());
+}
+
+#[test]
+fn test_load_without_key_keeps_placeholder() {
+    let mut doc = open(saved(), &[]);
+    let email = doc
+        .document()
+        .get_field(&"users.ada.email".to_string())
+        .unwrap()
+        .clone();
+    assert_eq!(EncryptedValue::from_json(&email).unwrap().key_id, "k1");
+
+    // The JSON export marks the field as unreadable and shows the rest
+    let exported = doc.document().to_json();
+    assert_eq!(
+        exported["users.ada.email"][ENCRYPTED_MARKER]["key_id"],
+        json!("k1")
+    );
+    assert_eq!(exported["users.ada.name"], json!("Ada"));
+
+    // Saving the placeholder again leaves it readable for key holders
+    set(&mut doc, "title", "Team 2", 5, "bob");
+    doc.persist().unwrap();
+    doc.compact().unwrap();
+    let reopened = open(doc.storage().restarted(), &[("k1", 0x5a)]);
+    assert_eq!(
+        reopened
+            .document()
+            .get_field(&"users.ada.email".to_string()),
+        Some(&json!("ada@lovelace.org"))
+    );
+}
+
+#[test]
+fn test_plain_open_sees_only_placeholders() {
+    let doc = PersistentDocument::open("doc".to_string(), saved(), policy()).unwrap();
+    let email = doc
+        .document()
+        .get_field(&"users.ada.email".to_string())
+        .unwrap();
+    assert!(EncryptedValue::from_json(email).is_some());
+}
+
+#[test]
+fn test_encrypted_field_edited_on_two_replicas_merges() {
+    // Alice and Bob both hold the key and edit the email concurrently;
+    // Carol merges both without it
+    let mut alice = open(saved(), &[("k1", 0x5a)]);
+    let mut bob = open(saved(), &[("k1", 0x5a)]);
+    set(
+        &mut alice,
+        "users.ada.email",
+        "ada@alice.example",
+        10,
+        "alice",
+    );
+    set(&mut bob, "users.ada.email", "ada@bob.example", 11, "bob");
+    alice.compact().unwrap();
+    bob.compact().unwrap();
+
+    let mut carol = open(saved(), &[]);
+    for storage in [alice.storage(), bob.storage()] {
+        let sealed: Document = open(storage.restarted(), &[]).document().clone();
+        carol.document_mut().merge(&sealed);
+    }
+    let merged = carol
+        .document()
+        .get_field(&"users.ada.email".to_string())
+        .unwrap();
+    assert!(EncryptedValue::from_json(merged).is_some());
+    carol.persist().unwrap();
+    carol.compact().unwrap();
+
+    // Whoever has the key reads the LWW winner: Bob's later write
+    let reread = open(carol.storage().restarted(), &[("k1", 0x5a)]);
+    assert_eq!(
+        reread.document().get_field(&"users.ada.email".to_string()),
+        Some(&json!("ada@bob.example"))
+    );
+
+    // Key holders merging the same states converge on the same value
+    let mut alice_doc = alice.document().clone();
+    alice_doc.merge(bob.document());
+    assert_eq!(
+        alice_doc.get_field(&"users.ada.email".to_string()),
+        Some(&json!("ada@bob.example"))
+    );
+}
+
+#[test]
+fn test_rotated_key_reads_old_values_and_compaction_reencrypts() {
+    let mut doc = open(saved(), &[("k2", 0x33), ("k1", 0x5a)]);
+    assert_eq!(
+        doc.document().get_field(&"users.ada.email".to_string()),
+        Some(&json!("ada@lovelace.org"))
+    );
+    set(&mut doc, "users.bob.email", "bob@example.com", 20, "alice");
+    doc.compact().unwrap();
+    let stored = stored_text(doc.storage());
+    assert!(!stored.contains("bob@") && !stored.contains("ada@"));
+
+    // Everything was rewritten under k2, so k1 is no longer needed
+    let only_new = open(doc.storage().restarted(), &[("k2", 0x33)]);
+    assert_eq!(only_new.document().fields(), doc.document().fields());
+}
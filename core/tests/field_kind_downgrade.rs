@@ -0,0 +1,227 @@
+//! A coordinator syncing with a peer that can't apply every field kind
+//! sends it read-only shadows instead, and refuses the peer's writes to
+//! them without ending the session
+
+#![cfg(feature = "protocol-binary")]
+
+use serde_json::json;
+use synckit_core::protocol::delta::DocumentDelta;
+use synckit_core::protocol::downgrade::{check_writable, FieldKinds, ShadowKind, ShadowValue};
+use synckit_core::protocol::sync::SyncCoordinator;
+use synckit_core::Document;
+
+fn write(doc: &mut Document, path: &str, value: serde_json::Value, clock: u64, client: &str) {
+    doc.set_field(path.to_string(), value, clock, client.to_string());
+    doc.version.update(client, clock);
+}
+
+/// A replica and its sync coordinator
+struct Peer {
+    name: &'static str,
+    doc: Document,
+    coordinator: SyncCoordinator,
+}
+
+impl Peer {
+    fn new(name: &'static str, base: &Document, kinds: FieldKinds) -> Self {
+        let mut coordinator = SyncCoordinator::new();
+        coordinator.set_field_kinds(kinds);
+        Self {
+            name,
+            doc: base.clone(),
+            coordinator,
+        }
+    }
+
+    fn delta_since(&self, base: &Document) -> DocumentDelta {
+        self.coordinator.outgoing_delta(base, &self.doc).unwrap()
+    }
+
+    fn receive(&mut self, delta: &DocumentDelta) {
+        self.coordinator
+            .apply_incoming(delta, &mut self.doc, self.name)
+            .unwrap();
+    }
+
+    fn shadow(&self, path: &str) -> ShadowValue {
+        ShadowValue::from_json(self.doc.get_field(&path.to_string()).unwrap()).unwrap()
+    }
+}
+
+/// Each side advertises its kinds and records the other's
+fn handshake(lite: &mut Peer, full: &mut Peer) {
+    let lite_bits = lite.coordinator.field_kinds().bits();
+    let full_bits = full.coordinator.field_kinds().bits();
+    assert_eq!(lite.coordinator.negotiate(full_bits), FieldKinds::VALUES);
+    assert_eq!(full.coordinator.negotiate(lite_bits), FieldKinds::VALUES);
+}
+
+/// A full peer holding a list, a multi-value field and a ref, and a lite
+/// peer that only applies plain values, after the handshake
+fn pair() -> (Document, Peer, Peer) {
+    let mut base = Document::new("doc".to_string());
+    write(&mut base, "title", json!("Groceries"), 1, "carol");
+    let mut lite = Peer::new("lite", &base, FieldKinds::VALUES);
+    let mut full = Peer::new("full", &base, FieldKinds::ALL);
+    handshake(&mut lite, &mut full);
+
+    let mut items = full.doc.list_mut("items".to_string(), "full".to_string());
+    items.push(json!("milk"));
+    items.push(json!("eggs"));
+    full.doc
+        .set_multi_field("status".to_string(), json!("open"), 2, "full".to_string());
+    full.doc.set_ref(
+        "owner".to_string(),
+        "user-1".to_string(),
+        3,
+        "full".to_string(),
+    );
+    full.doc.version.update("full", 3);
+    (base, lite, full)
+}
+
+#[test]
+fn test_unsupported_kinds_arrive_as_shadows() {
+    let (base, mut lite, full) = pair();
+    let delta = full.delta_since(&base);
+    assert!(delta.lists.is_empty() && delta.registers.is_empty() && delta.refs.is_empty());
+    lite.receive(&delta);
+
+    assert!(lite.doc.lists().is_empty());
+    assert_eq!(lite.shadow("items").kind, ShadowKind::List);
+    assert_eq!(lite.shadow("items").value, json!(["milk", "eggs"]));
+    assert_eq!(lite.shadow("status").value, json!("open"));
+    assert_eq!(
+        lite.shadow("owner").value,
+        full.doc.refs()["owner"].to_json()
+    );
+
+    // Shadows from a full snapshot say the same
+    let snapshot = full.coordinator.outgoing_snapshot(&full.doc);
+    assert!(snapshot.lists().is_empty() && snapshot.refs().is_empty());
+    assert_eq!(
+        snapshot.get_field(&"items".to_string()),
+        lite.doc.get_field(&"items".to_string())
+    );
+}
+
+#[test]
+fn test_newer_shadows_replace_older_ones() {
+    let (base, mut lite, mut full) = pair();
+    lite.receive(&full.delta_since(&base));
+    let before = full.doc.clone();
+
+    full.doc
+        .list_mut("items".to_string(), "full".to_string())
+        .remove(0)
+        .unwrap();
+    full.doc
+        .set_multi_field("status".to_string(), json!("done"), 4, "full".to_string());
+    full.doc.version.update("full", 4);
+    let delta = full.delta_since(&before);
+    assert_eq!(delta.changes.len(), 2);
+    lite.receive(&delta);
+
+    assert_eq!(lite.shadow("items").value, json!(["eggs"]));
+    assert_eq!(lite.shadow("status").value, json!("done"));
+}
+
+#[test]
+fn test_lite_writes_to_shadows_are_refused_and_the_session_goes_on() {
+    let (base, mut lite, mut full) = pair();
+    lite.receive(&full.delta_since(&base));
+
+    let error = check_writable(&lite.doc, "items").unwrap_err();
+    assert_eq!(error.code_name(), "SHADOWED_FIELD");
+    assert!(check_writable(&lite.doc, "title").is_ok());
+
+    // The lite peer writes anyway; its delta also carries the shadows back
+    write(&mut lite.doc, "title", json!("Weekly groceries"), 5, "lite");
+    write(&mut lite.doc, "items", json!(["bread"]), 6, "lite");
+    let delta = lite.delta_since(&base);
+
+    let errors = full.coordinator.shadowed_writes(&delta, &full.doc);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code_name(), "SHADOWED_FIELD");
+    assert_eq!(errors[0].context().path.as_deref(), Some("items"));
+    let report = full
+        .coordinator
+        .dry_run_incoming(&delta, &full.doc, "full")
+        .unwrap();
+    let mut refused = report.refused.clone();
+    refused.sort();
+    assert_eq!(refused, ["items", "owner", "status"]);
+
+    full.receive(&delta);
+    assert_eq!(
+        full.doc.get_field(&"title".to_string()),
+        Some(&json!("Weekly groceries"))
+    );
+    assert!(full.doc.get_field(&"items".to_string()).is_none());
+    assert!(full.doc.get_field(&"owner".to_string()).is_none());
+    assert_eq!(full.doc.list(&"items".to_string()).unwrap().len(), 2);
+
+    // Later rounds still sync both ways
+    let before = full.doc.clone();
+    full.doc
+        .list_mut("items".to_string(), "full".to_string())
+        .push(json!("tea"));
+    write(&mut full.doc, "note", json!("by Friday"), 7, "full");
+    lite.receive(&full.delta_since(&before));
+    assert_eq!(lite.shadow("items").value, json!(["milk", "eggs", "tea"]));
+    assert_eq!(
+        lite.doc.get_field(&"note".to_string()),
+        Some(&json!("by Friday"))
+    );
+
+    let before = lite.doc.clone();
+    write(
+        &mut lite.doc,
+        "title",
+        json!("Groceries, weekly"),
+        8,
+        "lite",
+    );
+    let delta = lite.delta_since(&before);
+    assert!(full
+        .coordinator
+        .shadowed_writes(&delta, &full.doc)
+        .is_empty());
+    full.receive(&delta);
+    assert_eq!(
+        full.doc.get_field(&"title".to_string()),
+        Some(&json!("Groceries, weekly"))
+    );
+}
+
+#[test]
+fn test_lite_snapshots_leave_full_structures_alone() {
+    let (base, mut lite, mut full) = pair();
+    lite.receive(&full.delta_since(&base));
+    write(&mut lite.doc, "title", json!("Weekly groceries"), 5, "lite");
+
+    let snapshot = lite.coordinator.outgoing_snapshot(&lite.doc);
+    full.coordinator
+        .merge_incoming_snapshot(&mut full.doc, &snapshot);
+    assert_eq!(
+        full.doc.get_field(&"title".to_string()),
+        Some(&json!("Weekly groceries"))
+    );
+    assert!(full.doc.get_field(&"items".to_string()).is_none());
+    assert_eq!(full.doc.list(&"items".to_string()).unwrap().len(), 2);
+}
+
+#[test]
+fn test_peers_that_do_not_advertise_get_every_kind() {
+    let (base, _, full) = pair();
+    let mut old = SyncCoordinator::new();
+    assert_eq!(old.negotiate(0), FieldKinds::ALL);
+    let delta = old.outgoing_delta(&base, &full.doc).unwrap();
+    assert_eq!(delta.lists.len(), 1);
+    assert_eq!(delta.registers.len(), 1);
+    assert_eq!(delta.refs.len(), 1);
+
+    let mut peer = base.clone();
+    old.apply_incoming(&delta, &mut peer, "old").unwrap();
+    assert_eq!(peer.list(&"items".to_string()).unwrap().len(), 2);
+}
@@ -0,0 +1,355 @@
+//! Load the committed state corpus in `tests/fixtures/state/` and check
+//! every fixture still reads, merges and serializes as it did
+//!
+//! The corpus holds states as releases saved them, in every format since
+//! the first; `cargo run --bin gen-fixtures --features advanced,text` adds
+//! the current format's. For each fixture:
+//!
+//! 1. upgrade it to the current format with `migrate`,
+//! 2. check what it reads as against the recorded state hash,
+//! 3. merge it with a replica built by this build, both ways,
+//! 4. re-serialize it and check the output is exactly the upgraded state.
+//!
+//! Step 4 fails when a type serializes differently without a migration:
+//! bump `migrate::FORMAT_VERSION`, add the converter, and run
+//! `gen-fixtures` to add the new format's fixtures.
+
+#![cfg(feature = "std")]
+
+#[allow(dead_code)]
+#[macro_use]
+#[path = "../src/bin/gen_fixtures/corpus.rs"]
+mod corpus;
+
+use corpus::{state_hash, Fixture, FixtureState, FIXTURE_DIR};
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashSet;
+use synckit_core::list::List;
+use synckit_core::migrate::{StateKind, VersionedState, FORMAT_VERSION};
+use synckit_core::register::MVRegister;
+use synckit_core::sync::LWWField;
+use synckit_core::{Document, Timestamp, VectorClock};
+
+/// Every fixture file, by file name, sorted
+fn fixtures() -> Vec<(String, Fixture)> {
+    let mut fixtures: Vec<(String, Fixture)> = std::fs::read_dir(FIXTURE_DIR)
+        .expect("fixture directory exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let bytes = std::fs::read(&path).unwrap();
+            let fixture = serde_json::from_slice(&bytes)
+                .unwrap_or_else(|e| panic!("{}: not a fixture: {}", name, e));
+            (name, fixture)
+        })
+        .collect();
+    fixtures.sort_by(|a, b| a.0.cmp(&b.0));
+    assert!(!fixtures.is_empty(), "no fixtures in {}", FIXTURE_DIR);
+    fixtures
+}
+
+/// The fixture's state in the current format
+fn upgraded(name: &str, fixture: &Fixture) -> JsonValue {
+    VersionedState {
+        kind: fixture.kind,
+        format: fixture.format,
+        state: fixture.state.clone(),
+    }
+    .into_current()
+    .unwrap_or_else(|e| panic!("{}: can't upgrade: {}", name, e))
+}
+
+/// `value` with every array sorted, recursively
+///
+/// Arrays written from hash sets (OR-Set tags) come out in any order, so
+/// re-serialized states are compared as if no array order mattered; order
+/// that does matter is part of what the state reads as, which the state
+/// hash covers.
+fn canonical(value: &JsonValue) -> JsonValue {
+    match value {
+        JsonValue::Array(items) => {
+            let mut items: Vec<JsonValue> = items.iter().map(canonical).collect();
+            items.sort_by_cached_key(|item| item.to_string());
+            JsonValue::Array(items)
+        }
+        JsonValue::Object(entries) => JsonValue::Object(
+            entries
+                .iter()
+                .map(|(key, value)| (key.clone(), canonical(value)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Re-serialize a current-format state, or describe how it came out
+/// different
+fn reserialize<T: FixtureState>(state: &JsonValue) -> Result<(), String> {
+    let value: T = serde_json::from_value(state.clone()).map_err(|e| e.to_string())?;
+    let written = serde_json::to_value(&value).map_err(|e| e.to_string())?;
+    if canonical(&written) == canonical(state) {
+        Ok(())
+    } else {
+        Err(format!("serializes as\n{}\ninstead of\n{}", written, state))
+    }
+}
+
+#[test]
+fn test_fixtures_read_as_recorded() {
+    let mut failures = Vec::new();
+    let mut checked = 0;
+    for (name, fixture) in fixtures() {
+        let state = upgraded(&name, &fixture);
+        let Some(hash) = state_hash(fixture.kind, &state) else {
+            continue;
+        };
+        checked += 1;
+        let hash = format!("{:016x}", hash);
+        if hash != fixture.state_hash {
+            failures.push(format!(
+                "{}: reads differently than when saved (state hash {}, recorded {})",
+                name, hash, fixture.state_hash
+            ));
+        }
+    }
+    assert!(checked > 0);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_fixtures_reserialize_in_the_current_format() {
+    let mut failures = Vec::new();
+    for (name, fixture) in fixtures() {
+        let state = upgraded(&name, &fixture);
+        let Some(result) = with_state_type!(fixture.kind, reserialize(&state)) else {
+            continue;
+        };
+        if let Err(difference) = result {
+            failures.push(format!(
+                "{} ({} format {}): {}",
+                name,
+                fixture.kind.as_str(),
+                fixture.format,
+                difference
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\n\nThe serialization format changed without a migration: bump \
+         migrate::FORMAT_VERSION, add a converter to migrate::upgrade and \
+         run gen-fixtures",
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+fn test_corpus_covers_every_kind_in_the_current_format() {
+    let covered: HashSet<StateKind> = fixtures()
+        .into_iter()
+        .filter(|(_, fixture)| fixture.format == FORMAT_VERSION)
+        .map(|(_, fixture)| fixture.kind)
+        .collect();
+    let missing: Vec<&str> = StateKind::ALL
+        .iter()
+        .filter(|kind| !covered.contains(kind))
+        .map(|kind| kind.as_str())
+        .collect();
+    assert!(
+        missing.is_empty(),
+        "no format {} fixtures of {:?}; run gen-fixtures",
+        FORMAT_VERSION,
+        missing
+    );
+}
+
+#[test]
+fn test_saved_states_are_never_from_the_future() {
+    for (name, fixture) in fixtures() {
+        assert!(
+            (1..=FORMAT_VERSION).contains(&fixture.format),
+            "{}: format {}",
+            name,
+            fixture.format
+        );
+        assert!(name.ends_with(&format!(".v{}.json", fixture.format)));
+    }
+}
+
+/// A fixture type merged with a replica this build made
+trait MergeFresh: FixtureState + Clone {
+    /// Merge `self` with a fresh replica both ways; both results must read
+    /// the same and keep what `self` held
+    fn merge_fresh(&self) -> Result<(), String>;
+}
+
+/// Fail unless `a` and `b` read the same
+fn converged<T: FixtureState>(a: &T, b: &T) -> Result<(), String> {
+    let (a, b) = (a.observe(), b.observe());
+    match a == b {
+        true => Ok(()),
+        false => Err(format!("replicas diverged:\n{}\n{}", a, b)),
+    }
+}
+
+fn load_and_merge<T: MergeFresh>(state: &JsonValue) -> Result<(), String> {
+    let value: T = serde_json::from_value(state.clone()).map_err(|e| e.to_string())?;
+    value.merge_fresh()
+}
+
+#[test]
+fn test_fixtures_merge_with_fresh_replicas() {
+    let mut failures = Vec::new();
+    for (name, fixture) in fixtures() {
+        let state = upgraded(&name, &fixture);
+        if let Some(Err(e)) = with_state_type!(fixture.kind, load_and_merge(&state)) {
+            failures.push(format!("{}: {}", name, e));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+impl MergeFresh for Document {
+    fn merge_fresh(&self) -> Result<(), String> {
+        let mut fresh = Document::new(self.id().clone());
+        fresh.set_field("fresh".to_string(), json!("neu ✨"), 1, "fresh".to_string());
+        let mut merged = self.clone();
+        merged.merge(&fresh);
+        fresh.merge(self);
+        converged(&merged, &fresh)?;
+        for (path, field) in self.fields() {
+            if merged.get_field(path) != Some(&field.value) {
+                return Err(format!("lost field {}", path));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MergeFresh for VectorClock {
+    fn merge_fresh(&self) -> Result<(), String> {
+        let mut fresh = VectorClock::new();
+        fresh.update("fresh", 1);
+        let mut merged = self.clone();
+        merged.merge(&fresh);
+        fresh.merge(self);
+        converged(&merged, &fresh)?;
+        match self.happened_before(&merged) {
+            true => Ok(()),
+            false => Err("merge isn't ahead of the fixture".to_string()),
+        }
+    }
+}
+
+impl MergeFresh for LWWField {
+    fn merge_fresh(&self) -> Result<(), String> {
+        let fresh = LWWField::new(json!("fresh"), Timestamp::new(1, "fresh".to_string()));
+        let merged = self.merge(&fresh);
+        converged(&merged, &fresh.merge(self))?;
+        converged(&merged, self)
+    }
+}
+
+impl MergeFresh for MVRegister {
+    fn merge_fresh(&self) -> Result<(), String> {
+        let mut fresh = MVRegister::new();
+        fresh.write(json!("fresh"), Timestamp::new(1, "fresh".to_string()));
+        let mut merged = self.clone();
+        merged.merge(&fresh);
+        fresh.merge(self);
+        converged(&merged, &fresh)?;
+        match merged.values().len() == self.values().len() + 1 {
+            true => Ok(()),
+            false => Err("concurrent write wasn't kept".to_string()),
+        }
+    }
+}
+
+impl MergeFresh for List {
+    fn merge_fresh(&self) -> Result<(), String> {
+        let path = "items".to_string();
+        let mut doc = Document::new("fresh".to_string());
+        doc.list_mut(path.clone(), "fresh".to_string())
+            .push(json!("fresh"));
+        let mut fresh = doc.list(&path).unwrap().clone();
+        let mut merged = self.clone();
+        merged.merge(&fresh);
+        fresh.merge(self);
+        converged(&merged, &fresh)?;
+        match merged.len() == self.len() + 1 {
+            true => Ok(()),
+            false => Err("fresh item wasn't merged in".to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "counters")]
+impl MergeFresh for synckit_core::crdt::PNCounter {
+    fn merge_fresh(&self) -> Result<(), String> {
+        let mut fresh = Self::new("fresh".to_string());
+        fresh.increment(5);
+        let mut merged = self.clone();
+        merged.merge(&fresh);
+        fresh.merge(self);
+        converged(&merged, &fresh)?;
+        match merged.value() == self.value() + 5 {
+            true => Ok(()),
+            false => Err(format!("{} + 5 merged to {}", self.value(), merged.value())),
+        }
+    }
+}
+
+#[cfg(feature = "sets")]
+impl MergeFresh for synckit_core::crdt::ORSet<String> {
+    fn merge_fresh(&self) -> Result<(), String> {
+        let mut fresh = Self::new("fresh".to_string());
+        fresh.add_at("fresh".to_string(), 1);
+        let mut merged = self.clone();
+        merged.merge(&fresh);
+        fresh.merge(self);
+        converged(&merged, &fresh)?;
+        match self.iter().all(|element| merged.contains(element)) {
+            true => Ok(()),
+            false => Err("lost elements".to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "text-crdt")]
+impl MergeFresh for synckit_core::crdt::FugueText {
+    fn merge_fresh(&self) -> Result<(), String> {
+        let mut fresh = Self::new("fresh".to_string());
+        fresh.insert(0, "neu ✨ ").map_err(|e| e.to_string())?;
+        let mut merged = self.clone();
+        merged.merge(&fresh).map_err(|e| e.to_string())?;
+        fresh.merge(self).map_err(|e| e.to_string())?;
+        converged(&merged, &fresh)?;
+        match merged.to_string().contains(&self.to_string()) {
+            true => Ok(()),
+            false => Err(format!("{:?} got interleaved", self.to_string())),
+        }
+    }
+}
+
+/// Positions don't merge; check this build still orders them and places
+/// new ones between them
+#[cfg(feature = "fractional-index")]
+impl MergeFresh for Vec<synckit_core::crdt::FractionalIndex> {
+    fn merge_fresh(&self) -> Result<(), String> {
+        use synckit_core::crdt::FractionalIndex;
+
+        for pair in self.windows(2) {
+            let between = FractionalIndex::between(&pair[0], &pair[1]);
+            if !(pair[0] < between && between < pair[1]) {
+                return Err(format!(
+                    "{} isn't between {} and {}",
+                    between.as_str(),
+                    pair[0].as_str(),
+                    pair[1].as_str()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
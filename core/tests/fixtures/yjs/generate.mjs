@@ -0,0 +1,75 @@
+// Regenerate the Yjs update fixtures used by src/crdt/text_fugue/yjs.rs
+//
+// Usage (from this directory):
+//   npm install yjs@13
+//   node generate.mjs
+//
+// Client IDs are fixed so the output is deterministic. The script prints
+// the Yjs version it ran with; record it below when committing new output.
+//
+// Provenance of the checked-in .bin files: not yet produced by this script
+// under Yjs itself (no npm registry access when last updated). They are
+// byte-identical to what yrs 0.21.3, the Rust port of Yjs, encodes for the
+// same five scripts (Doc::with_client_id, encode_state_as_update_v1, and
+// OffsetKind::Utf16 for unicode.bin).
+// Yjs version: (not yet run)
+
+import * as Y from 'yjs'
+import { readFileSync, writeFileSync } from 'node:fs'
+
+const yjs = JSON.parse(
+  readFileSync(new URL('node_modules/yjs/package.json', import.meta.url))
+)
+console.log(`Generating with yjs ${yjs.version}`)
+
+function doc(clientID) {
+  const d = new Y.Doc()
+  d.clientID = clientID
+  return d
+}
+
+function write(name, d) {
+  writeFileSync(new URL(name, import.meta.url), Y.encodeStateAsUpdate(d))
+}
+
+// Plain insert
+{
+  const d = doc(1)
+  d.getText('text').insert(0, 'hello')
+  write('hello.bin', d)
+}
+
+// Insert then delete (deleted content is GC'd into ContentDeleted)
+{
+  const d = doc(1)
+  d.getText('text').insert(0, 'hello world')
+  d.getText('text').delete(5, 6)
+  write('hello_world_deleted.bin', d)
+}
+
+// Client 2 inserts between two characters written by client 1
+{
+  const d1 = doc(1)
+  d1.getText('text').insert(0, 'ab')
+  const d2 = doc(2)
+  Y.applyUpdate(d2, Y.encodeStateAsUpdate(d1))
+  d2.getText('text').insert(1, 'X')
+  write('concurrent_insert_middle.bin', d2)
+}
+
+// Concurrent inserts into an empty document
+{
+  const d1 = doc(1)
+  const d2 = doc(2)
+  d1.getText('text').insert(0, 'A')
+  d2.getText('text').insert(0, 'B')
+  Y.applyUpdate(d1, Y.encodeStateAsUpdate(d2))
+  write('concurrent_insert_start.bin', d1)
+}
+
+// Non-ASCII content (clocks count UTF-16 code units)
+{
+  const d = doc(1)
+  d.getText('text').insert(0, 'héllo 👋')
+  write('unicode.bin', d)
+}
@@ -0,0 +1,159 @@
+//! Two in-process relays replicating a workspace over gRPC while both sides
+//! write: they must converge to the same documents and checksum
+
+#![cfg(feature = "grpc")]
+
+use serde_json::json;
+use std::net::SocketAddr;
+use std::time::Duration;
+use synckit_core::concurrent::SharedDocument;
+use synckit_core::grpc::{document_from_protocol, ReplicationService, Workspace};
+use synckit_core::protocol::replication_client::ReplicationClient;
+use synckit_core::protocol::{DocumentId, GetSnapshotRequest, GetVersionRequest};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+
+const DOCUMENTS: usize = 4;
+const WRITES: u64 = 50;
+
+async fn serve(service: &ReplicationService) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::builder()
+        .add_service(service.clone().into_server())
+        .serve_with_incoming(TcpListenerStream::new(listener));
+    tokio::spawn(server);
+    addr
+}
+
+async fn client(addr: SocketAddr) -> ReplicationClient<tonic::transport::Channel> {
+    ReplicationClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+fn write(document: &SharedDocument, relay: &str, path: &str, value: serde_json::Value) {
+    let relay = relay.to_string();
+    document.write(|document| {
+        document.version.tick(&relay);
+        let clock = document.version.get(&relay);
+        document.set_field(path.to_string(), value, clock, relay);
+    });
+}
+
+/// Write to every document, with some paths shared between the relays
+async fn writer(workspace: Workspace, relay: &'static str) {
+    for n in 0..WRITES {
+        let id = format!("doc-{}", n as usize % DOCUMENTS);
+        let document = workspace.get_or_create(&id);
+        write(
+            &document,
+            relay,
+            "shared",
+            json!(format!("{} {}", relay, n)),
+        );
+        write(&document, relay, &format!("{}.count", relay), json!(n));
+        if n % 5 == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_relays_converge_under_concurrent_writes() {
+    let west = ReplicationService::new("west", Workspace::new());
+    let east = ReplicationService::new("east", Workspace::new());
+    // Documents that exist before the session starts
+    write(
+        &west.workspace().get_or_create("doc-0"),
+        "west",
+        "title",
+        json!("West"),
+    );
+    write(
+        &east.workspace().get_or_create("doc-0"),
+        "east",
+        "title",
+        json!("East"),
+    );
+    write(
+        &east.workspace().get_or_create("east-only"),
+        "east",
+        "x",
+        json!(1),
+    );
+
+    serve(&west).await;
+    let east_addr = serve(&east).await;
+
+    let session = {
+        let west = west.clone();
+        let peer = client(east_addr).await;
+        tokio::spawn(async move { west.replicate(peer).await })
+    };
+
+    let writers = [
+        tokio::spawn(writer(west.workspace().clone(), "west")),
+        tokio::spawn(writer(east.workspace().clone(), "east")),
+    ];
+    for writer in writers {
+        writer.await.unwrap();
+    }
+
+    let converged = tokio::time::timeout(Duration::from_secs(10), async {
+        while west.workspace().checksum() != east.workspace().checksum() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert!(converged.is_ok(), "relays did not converge");
+    assert!(!session.is_finished(), "session ended early");
+
+    assert_eq!(west.workspace().len(), DOCUMENTS + 1);
+    assert_eq!(east.workspace().len(), DOCUMENTS + 1);
+    for document in west.workspace().documents() {
+        let other = east.workspace().document(document.id()).unwrap();
+        assert_eq!(document.to_json(), other.to_json());
+        assert_eq!(document.version(), other.version());
+    }
+    let doc = west.workspace().document("doc-1").unwrap();
+    assert_eq!(doc.get_field(&"west.count".to_string()), Some(json!(49)));
+    assert_eq!(doc.get_field(&"east.count".to_string()), Some(json!(49)));
+
+    // Unary calls see the replicated state
+    let mut east_client = client(east_addr).await;
+    let id = Some(DocumentId {
+        id: "doc-1".to_string(),
+    });
+    let version = east_client
+        .get_version(GetVersionRequest {
+            document_id: id.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(version.document_id, id);
+    let snapshot = east_client
+        .get_snapshot(GetSnapshotRequest {
+            document_id: id.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let snapshot = document_from_protocol(&snapshot).unwrap();
+    assert_eq!(snapshot.to_json(), doc.to_json());
+    assert_eq!(snapshot.version(), &doc.version());
+
+    let missing = east_client
+        .get_version(GetVersionRequest {
+            document_id: Some(DocumentId {
+                id: "nope".to_string(),
+            }),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(missing.code(), tonic::Code::NotFound);
+
+    session.abort();
+}
@@ -0,0 +1,109 @@
+//! The no_std kernel's merge layer, run under std
+//!
+//! The kernel (`--no-default-features --features alloc-kernel`) is the
+//! same code the std build uses, so these tests drive its entry points
+//! directly and check them against the std types built on top: Fugue
+//! ordering over stored blocks must render what `FugueText` renders, and
+//! clocks, counters and sets must merge the same whichever API made them.
+
+#![cfg(all(feature = "text-crdt", feature = "counters", feature = "sets"))]
+
+use std::collections::BTreeMap;
+use synckit_core::crdt::text_fugue::{order, FugueBlock, FugueText, NodeId};
+use synckit_core::crdt::{ORSet, PNCounter};
+use synckit_core::VectorClock;
+
+/// The blocks of a text as an embedded replica would load them from
+/// storage
+fn stored_blocks(text: &FugueText) -> BTreeMap<NodeId, FugueBlock> {
+    let json = serde_json::to_value(text).unwrap();
+    let blocks: Vec<(NodeId, FugueBlock)> = serde_json::from_value(json["blocks"].clone()).unwrap();
+    blocks.into_iter().collect()
+}
+
+fn render(blocks: &BTreeMap<NodeId, FugueBlock>) -> String {
+    order::document_order(blocks, false)
+        .iter()
+        .map(|id| blocks[id].text.as_str())
+        .collect()
+}
+
+#[test]
+fn test_ordering_matches_fugue_text_after_concurrent_edits() {
+    let mut alice = FugueText::new("alice".to_string());
+    let mut bob = FugueText::new("bob".to_string());
+    alice.insert(0, "Hello World").unwrap();
+    bob.merge(&alice).unwrap();
+
+    alice.insert(5, ", dear").unwrap();
+    bob.insert(5, " there").unwrap();
+    bob.delete(0, 1).unwrap();
+    alice.insert(0, ">> ").unwrap();
+    alice.merge(&bob).unwrap();
+    bob.merge(&alice).unwrap();
+
+    let blocks = stored_blocks(&alice);
+    assert_eq!(render(&blocks), alice.to_string());
+    assert_eq!(render(&stored_blocks(&bob)), bob.to_string());
+
+    // Tombstones stay in place when asked for
+    let with_tombstones = order::document_order(&blocks, true);
+    assert!(with_tombstones.len() > order::document_order(&blocks, false).len());
+    assert!(with_tombstones.iter().any(|id| blocks[id].is_deleted()));
+}
+
+#[test]
+fn test_block_containing_finds_character_ids() {
+    let mut text = FugueText::new("alice".to_string());
+    text.insert(0, "abc").unwrap();
+    // Before "abc": typed after it, "def" would extend its block
+    text.insert(0, "def").unwrap();
+    let blocks = stored_blocks(&text);
+
+    // Characters are numbered by clock: "abc" is 1..=3, "def" 4..=6
+    let first = order::block_containing(&blocks, &NodeId::new("alice".to_string(), 2, 0));
+    let second = order::block_containing(&blocks, &NodeId::new("alice".to_string(), 6, 0));
+    assert_eq!(blocks[&first.unwrap()].text, "abc");
+    assert_eq!(blocks[&second.unwrap()].text, "def");
+    assert!(order::block_containing(&blocks, &NodeId::new("bob".to_string(), 1, 0)).is_none());
+}
+
+#[test]
+fn test_clocks_counters_and_sets_merge_identically() {
+    let mut a = VectorClock::new();
+    let mut b = VectorClock::new();
+    a.tick(&"alice".to_string());
+    b.tick(&"bob".to_string());
+    b.tick(&"bob".to_string());
+    let mut ab = a.clone();
+    ab.merge(&b);
+    let mut ba = b.clone();
+    ba.merge(&a);
+    assert_eq!(ab, ba);
+    assert_eq!(ab.get("bob"), 2);
+
+    let mut left = PNCounter::new("alice".to_string());
+    let mut right = PNCounter::new("bob".to_string());
+    left.increment(5);
+    right.decrement(2);
+    left.merge(&right);
+    right.merge(&left);
+    assert_eq!(left.value(), 3);
+    assert_eq!(right.value(), 3);
+
+    // add_at (the kernel's clock-free add) and add produce sets that merge
+    // the same way
+    let mut std_set = ORSet::new("alice".to_string());
+    let mut kernel_set = ORSet::new("bob".to_string());
+    std_set.add("apple".to_string());
+    kernel_set.add_at("banana".to_string(), 0);
+    kernel_set.add_at("apple".to_string(), 0);
+    kernel_set.remove(&"apple".to_string());
+    std_set.merge(&kernel_set);
+    kernel_set.merge(&std_set);
+    for set in [&std_set, &kernel_set] {
+        assert!(set.contains(&"apple".to_string()));
+        assert!(set.contains(&"banana".to_string()));
+        assert_eq!(set.len(), 2);
+    }
+}
@@ -9,6 +9,9 @@
 //! - Idempotence: Applying operation twice has same effect as once
 //! - Commutativity: Concurrent operations can be applied in any order
 //! - No Data Loss: All operations affect final state
+//! - Incremental persistence: dirty chunks replay to the current state
+
+#![cfg(feature = "std")]
 
 use proptest::prelude::*;
 use serde_json::json;
@@ -64,6 +67,29 @@ fn operations(count: usize) -> impl Strategy<Value = Vec<Operation>> {
     prop::collection::vec(operation(), 1..=count)
 }
 
+/// One step of an editing session that persists incrementally
+#[derive(Debug, Clone)]
+enum PersistStep {
+    Set(Operation),
+    Delete(String),
+    /// Merge a remote replica holding this write
+    Merge(Operation),
+    /// Persist the dirty state
+    Flush,
+}
+
+fn persist_steps(count: usize) -> impl Strategy<Value = Vec<PersistStep>> {
+    prop::collection::vec(
+        prop_oneof![
+            3 => operation().prop_map(PersistStep::Set),
+            1 => field_name().prop_map(PersistStep::Delete),
+            1 => operation().prop_map(PersistStep::Merge),
+            1 => Just(PersistStep::Flush),
+        ],
+        1..=count,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -370,4 +396,46 @@ mod tests {
             }
         });
     }
+
+    /// Property: Incremental persistence
+    ///
+    /// Replaying every `take_dirty` chunk over the last full snapshot
+    /// reconstructs the exact current document.
+    #[test]
+    fn prop_dirty_chunks_reconstruct_document() {
+        proptest!(|(base in operations(10), steps in persist_steps(40))| {
+            let mut doc = Document::new("test-doc".to_string());
+            for op in &base {
+                doc.set_field(op.field.clone(), op.value.clone(), op.timestamp, op.client_id.clone());
+            }
+            let snapshot = serde_json::to_string(&doc).unwrap();
+            doc.take_dirty();
+
+            let mut log = Vec::new();
+            for step in &steps {
+                match step {
+                    PersistStep::Set(op) => {
+                        doc.set_field(op.field.clone(), op.value.clone(), op.timestamp, op.client_id.clone());
+                    }
+                    PersistStep::Delete(field) => doc.delete_field(field),
+                    PersistStep::Merge(op) => {
+                        let mut remote = Document::new("test-doc".to_string());
+                        remote.set_field(op.field.clone(), op.value.clone(), op.timestamp, op.client_id.clone());
+                        remote.version.update(&op.client_id, op.timestamp);
+                        doc.merge(&remote);
+                    }
+                    PersistStep::Flush => log.push(serde_json::to_string(&doc.take_dirty()).unwrap()),
+                }
+            }
+            log.push(serde_json::to_string(&doc.take_dirty()).unwrap());
+
+            let mut restored: Document = serde_json::from_str(&snapshot).unwrap();
+            for chunk in &log {
+                restored.apply_dirty(&serde_json::from_str(chunk).unwrap());
+            }
+            prop_assert_eq!(&restored.fields, &doc.fields);
+            prop_assert_eq!(&restored.version, &doc.version);
+            prop_assert!(!restored.is_dirty());
+        });
+    }
 }
@@ -0,0 +1,150 @@
+//! Two server instances joined by the in-memory fan-out: clients attached
+//! to different instances must converge, including across an outage
+
+#![cfg(feature = "redis-fanout")]
+
+use serde_json::json;
+use std::time::Duration;
+use synckit_core::protocol::delta::DocumentDelta;
+use synckit_core::server::fanout::{FanOutRelay, MemoryFanOut};
+use synckit_core::server::Workspace;
+use synckit_core::Document;
+
+const DOCUMENT: &str = "doc-1";
+
+/// A client connected to one server instance
+struct Client {
+    id: String,
+    document: Document,
+    /// What the server has from us
+    sent: Document,
+    workspace: Workspace,
+}
+
+impl Client {
+    fn new(id: &str, workspace: &Workspace) -> Self {
+        Self {
+            id: id.to_string(),
+            document: Document::new(DOCUMENT.to_string()),
+            sent: Document::new(DOCUMENT.to_string()),
+            workspace: workspace.clone(),
+        }
+    }
+
+    fn write(&mut self, path: &str, value: serde_json::Value) {
+        self.document.version.tick(&self.id);
+        let clock = self.document.version.get(&self.id);
+        self.document
+            .set_field(path.to_string(), value, clock, self.id.clone());
+    }
+
+    /// Send our changes to the server, as its websocket handler would
+    fn push(&mut self) {
+        let delta = DocumentDelta::compute(&self.sent, &self.document).unwrap();
+        self.workspace.get_or_create(DOCUMENT).write(|document| {
+            delta.apply_to(document, "server").unwrap();
+            document.version.merge(&delta.new_version);
+        });
+        self.sent = self.document.clone();
+    }
+
+    /// Take the server's state
+    fn pull(&mut self) {
+        let server = self.workspace.get_or_create(DOCUMENT).read_snapshot();
+        self.document.merge(&server);
+        self.document.version.merge(server.version());
+    }
+}
+
+async fn converge(a: &Workspace, b: &Workspace) {
+    let converged = tokio::time::timeout(Duration::from_secs(10), async {
+        while a.checksum() != b.checksum() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await;
+    assert!(converged.is_ok(), "instances did not converge");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_clients_on_different_instances_converge() {
+    let fanout = MemoryFanOut::new();
+    let east = Workspace::new();
+    let west = Workspace::new();
+    for (name, workspace) in [("east", &east), ("west", &west)] {
+        let relay = FanOutRelay::new(name, workspace.clone(), fanout.clone());
+        tokio::spawn(async move { relay.run().await });
+    }
+
+    let mut alice = Client::new("alice", &east);
+    let mut bob = Client::new("bob", &east);
+    let mut carol = Client::new("carol", &west);
+
+    for round in 0..20 {
+        alice.write("title", json!(format!("alice {}", round)));
+        alice.write("alice.round", json!(round));
+        carol.write("title", json!(format!("carol {}", round)));
+        carol.write("carol.round", json!(round));
+        alice.push();
+        carol.push();
+        if round % 5 == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+    converge(&east, &west).await;
+
+    // An outage: both sides keep writing, then catch up on reconnect
+    fanout.disconnect();
+    bob.write("bob.offline", json!(true));
+    bob.push();
+    carol.write("carol.offline", json!(true));
+    carol.push();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_ne!(east.checksum(), west.checksum());
+    fanout.reconnect();
+    converge(&east, &west).await;
+
+    for client in [&mut alice, &mut bob, &mut carol] {
+        client.pull();
+    }
+    assert_eq!(alice.document.to_json(), carol.document.to_json());
+    assert_eq!(bob.document.to_json(), carol.document.to_json());
+    assert_eq!(
+        carol.document.get_field(&"alice.round".to_string()),
+        Some(&json!(19))
+    );
+    assert_eq!(
+        alice.document.get_field(&"bob.offline".to_string()),
+        Some(&json!(true))
+    );
+    assert_eq!(
+        alice.document.get_field(&"carol.offline".to_string()),
+        Some(&json!(true))
+    );
+    let east_doc = east.document(DOCUMENT).unwrap();
+    let west_doc = west.document(DOCUMENT).unwrap();
+    assert_eq!(east_doc.version(), west_doc.version());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_late_instance_catches_up() {
+    let fanout = MemoryFanOut::new();
+    let east = Workspace::new();
+    let relay = FanOutRelay::new("east", east.clone(), fanout.clone());
+    tokio::spawn(async move { relay.run().await });
+
+    let mut alice = Client::new("alice", &east);
+    alice.write("title", json!("Hello"));
+    alice.push();
+
+    let west = Workspace::new();
+    let relay = FanOutRelay::new("west", west.clone(), fanout.clone());
+    tokio::spawn(async move { relay.run().await });
+    converge(&east, &west).await;
+    assert_eq!(
+        west.document(DOCUMENT)
+            .unwrap()
+            .get_field(&"title".to_string()),
+        Some(json!("Hello"))
+    );
+}
@@ -0,0 +1,82 @@
+//! Canned fault-injection scenarios for the network simulation
+//!
+//! Each scenario runs with a fixed seed; set `SYNCKIT_SIM_SEED` to replay
+//! a failure with the seed it printed.
+
+#![cfg(feature = "testing")]
+
+use synckit_core::sim::{seed_from_env, LinkConfig, SimConfig, Simulation};
+
+#[test]
+fn test_flappy_link() {
+    let mut config = SimConfig::new(seed_from_env(0xF1A9), 3);
+    config.link = LinkConfig::lossy();
+    let mut sim = Simulation::new(config);
+
+    // Node 0's links lose a third of all packets and keep going down
+    let flappy = LinkConfig {
+        drop: 0.35,
+        ..LinkConfig::lossy()
+    };
+    sim.set_link(0, 1, flappy.clone());
+    sim.set_link(0, 2, flappy);
+    for _ in 0..20 {
+        sim.run_for(400);
+        sim.partition(&[0], &[1, 2]);
+        sim.run_for(300);
+        sim.heal();
+    }
+
+    let report = sim.settle().unwrap();
+    assert!(report.dropped > 0);
+    assert!(report.undeliverable > 0);
+}
+
+#[test]
+fn test_long_partition_with_heavy_divergence() {
+    let mut config = SimConfig::new(seed_from_env(0x5917), 4);
+    config.edit_interval_ms = 40;
+    let mut sim = Simulation::new(config);
+
+    sim.run_for(1_000);
+    sim.partition(&[0, 1], &[2, 3]);
+    sim.run_for(8_000);
+
+    // Both sides edited a lot on their own before healing
+    assert_ne!(sim.text(0), sim.text(2));
+    let report = sim.settle().unwrap();
+    assert!(report.edits > 500);
+    assert!(report.text_len > 0);
+}
+
+#[test]
+fn test_crash_restart_mid_handshake() {
+    let mut config = SimConfig::new(seed_from_env(0xC7A5), 3);
+    config.link = LinkConfig::reliable(50);
+    let mut sim = Simulation::new(config);
+    sim.run_for(2_000);
+
+    // Responder crashes with the request in flight
+    sim.sync(0, 1);
+    sim.run_for(20);
+    sim.crash(1);
+    sim.run_for(100);
+    sim.restart(1);
+
+    // Requester crashes after the request lands but before the reply does
+    sim.sync(2, 0);
+    sim.run_for(70);
+    sim.crash(2);
+    sim.run_for(500);
+    sim.restart(2);
+
+    // Crash again, left down until settle restarts it
+    sim.run_for(1_000);
+    sim.sync(1, 2);
+    sim.run_for(60);
+    sim.crash(1);
+    sim.run_for(1_000);
+
+    let report = sim.settle().unwrap();
+    assert!(report.undeliverable > 0);
+}
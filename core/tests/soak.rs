@@ -0,0 +1,595 @@
+//! Soak test: memory must stay flat under long steady workloads
+//!
+//! Each phase runs one workload for `SOAK_ITERATIONS` steps (creating and
+//! dropping documents, field writes, text edits, merges, annotation GC,
+//! serialization round-trips) over a bounded working set, so a healthy
+//! build's memory levels off. Every `SOAK_SAMPLE_EVERY` steps it samples
+//! the process RSS (Linux only) and the summed `memory_usage()` of the live
+//! documents and texts. After the warm-up (`SOAK_WARMUP`, a fraction of the
+//! phase) it fits a line through the samples and fails if either slope,
+//! in bytes per 1000 steps, is over its ceiling. The failure names the
+//! phase, the subsystem that grew fastest and the whole timeline.
+//!
+//! Slow, so `#[ignore]`d; run it in release with the cargo alias:
+//!
+//! ```text
+//! cargo soak                                   # every phase
+//! SOAK_PHASES=text,merge cargo soak            # some phases
+//! SOAK_ITERATIONS=5000000 cargo soak           # longer
+//! ```
+//!
+//! | Variable             | Default   | Meaning                                  |
+//! |----------------------|-----------|------------------------------------------|
+//! | `SOAK_ITERATIONS`    | 200000    | Steps per phase                          |
+//! | `SOAK_SAMPLE_EVERY`  | 10000     | Steps between samples                    |
+//! | `SOAK_WARMUP`        | 0.25      | Fraction of each phase not checked       |
+//! | `SOAK_MAX_SLOPE`     | 512       | Ceiling on `memory_usage()` growth       |
+//! | `SOAK_MAX_RSS_SLOPE` | 4096      | Ceiling on RSS growth                    |
+//! | `SOAK_PHASES`        | all       | Comma-separated phase names              |
+//! | `SOAK_SEED`          | 1         | Seed of the workload's choices           |
+//!
+//! `tests/soak_wasm.rs` runs the same idea under wasm-bindgen-test against
+//! `WebAssembly.Memory`.
+
+#![cfg(feature = "text-crdt")]
+
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use synckit_core::annotations::AnnotationRetention;
+use synckit_core::crdt::text_fugue::FugueText;
+use synckit_core::memory::MemoryUsage;
+use synckit_core::Document;
+
+/// Soak settings, from the environment
+struct Config {
+    iterations: u64,
+    sample_every: u64,
+    warmup: f64,
+    max_slope: f64,
+    max_rss_slope: f64,
+    phases: Option<Vec<String>>,
+    seed: u64,
+}
+
+fn env<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a number, got {:?}", name, value)),
+        Err(_) => default,
+    }
+}
+
+impl Config {
+    fn from_env() -> Self {
+        Self {
+            iterations: env("SOAK_ITERATIONS", 200_000),
+            sample_every: env("SOAK_SAMPLE_EVERY", 10_000).max(1),
+            warmup: env("SOAK_WARMUP", 0.25),
+            max_slope: env("SOAK_MAX_SLOPE", 512.0),
+            max_rss_slope: env("SOAK_MAX_RSS_SLOPE", 4096.0),
+            phases: std::env::var("SOAK_PHASES").ok().map(|list| {
+                list.split(',')
+                    .map(|name| name.trim().to_string())
+                    .collect()
+            }),
+            seed: env("SOAK_SEED", 1),
+        }
+    }
+}
+
+/// xorshift64, so runs with the same seed do the same work
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n.max(1)
+    }
+
+    fn chance(&mut self, one_in: u64) -> bool {
+        self.below(one_in) == 0
+    }
+}
+
+/// Resident set size of this process, in bytes
+fn rss_bytes() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// A workload over a bounded working set
+trait Workload {
+    fn step(&mut self, i: u64, rng: &mut Rng);
+
+    /// `memory_usage()` of everything the workload holds
+    fn usage(&self) -> MemoryUsage;
+}
+
+fn total_usage(usages: impl IntoIterator<Item = MemoryUsage>) -> MemoryUsage {
+    let mut total = MemoryUsage::new();
+    for usage in usages {
+        total.add(&usage);
+    }
+    total
+}
+
+/// Edit a text, keeping it under `max_len` characters
+fn edit_text(text: &mut FugueText, max_len: usize, rng: &mut Rng) {
+    let len = text.len();
+    if len >= max_len || (len > 0 && rng.chance(3)) {
+        let position = rng.below(len as u64) as usize;
+        let length = (rng.below(8) as usize + 1).min(len - position);
+        text.delete(position, length).unwrap();
+    } else {
+        let position = rng.below(len as u64 + 1) as usize;
+        let words = ["lorem ", "ipsum ", "dolor ", "sit ", "amet, "];
+        text.insert(position, words[rng.below(5) as usize]).unwrap();
+    }
+}
+
+/// Documents created, written and dropped at random in a fixed pool
+struct Documents {
+    pool: Vec<Option<Document>>,
+}
+
+impl Workload for Documents {
+    fn step(&mut self, i: u64, rng: &mut Rng) {
+        let slot = rng.below(self.pool.len() as u64) as usize;
+        if rng.chance(8) {
+            self.pool[slot] = None;
+            return;
+        }
+        let doc = self.pool[slot].get_or_insert_with(|| Document::new(format!("doc-{}", slot)));
+        let key = format!("field-{}", rng.below(16));
+        doc.set_field(
+            key,
+            json!({ "n": i, "tag": "soak" }),
+            i,
+            "client-1".to_string(),
+        );
+        let full = doc
+            .list(&"items".to_string())
+            .is_some_and(|list| list.len() >= 8);
+        let mut list = doc.list_mut("items".to_string(), "client-1".to_string());
+        if full {
+            list.remove(0).unwrap();
+        }
+        list.push(json!(i));
+        doc.take_dirty();
+    }
+
+    fn usage(&self) -> MemoryUsage {
+        total_usage(self.pool.iter().flatten().map(Document::memory_usage))
+    }
+}
+
+/// Field writes and deletes over a fixed key space
+struct Fields {
+    docs: Vec<Document>,
+}
+
+impl Workload for Fields {
+    fn step(&mut self, i: u64, rng: &mut Rng) {
+        let doc = &mut self.docs[(i % 4) as usize];
+        let key = format!("field-{}", rng.below(64));
+        match rng.chance(5) {
+            true => doc.delete_field(&key),
+            false => doc.set_field(
+                key,
+                json!("x".repeat(rng.below(64) as usize)),
+                i,
+                "client-1".to_string(),
+            ),
+        }
+        // Each document in turn
+        if i % 100 < 4 {
+            doc.take_dirty();
+        }
+    }
+
+    fn usage(&self) -> MemoryUsage {
+        total_usage(self.docs.iter().map(Document::memory_usage))
+    }
+}
+
+/// Text edits, with each text replaced after a fixed number of edits
+///
+/// A CRDT text keeps its tombstones, so a single text grows for ever;
+/// replacing them bounds the working set the way closing documents does.
+struct Texts {
+    texts: Vec<(FugueText, u64)>,
+    generation: u64,
+}
+
+/// Edits a text gets before it is replaced
+///
+/// Kept short because each edit recomputes document order, which is
+/// quadratic in the number of blocks, and prime so that samples land at
+/// different points of a generation.
+const TEXT_EDITS_PER_GENERATION: u64 = 97;
+
+/// Longest text the edits grow
+const TEXT_MAX_LEN: usize = 100;
+
+impl Workload for Texts {
+    fn step(&mut self, i: u64, rng: &mut Rng) {
+        let index = (i % self.texts.len() as u64) as usize;
+        let (text, edits) = &mut self.texts[index];
+        if *edits == TEXT_EDITS_PER_GENERATION {
+            self.generation += 1;
+            *text = FugueText::new(format!("client-{}", self.generation % 16));
+            *edits = 0;
+        }
+        edit_text(text, TEXT_MAX_LEN, rng);
+        *edits += 1;
+        if edits.is_multiple_of(100) {
+            text.take_dirty();
+        }
+    }
+
+    fn usage(&self) -> MemoryUsage {
+        total_usage(self.texts.iter().map(|(text, _)| text.memory_usage()))
+    }
+}
+
+/// Two replicas of a document and of a text, edited apart and merged
+struct Merges {
+    docs: (Document, Document),
+    texts: (FugueText, FugueText),
+    text_edits: u64,
+}
+
+fn text_pair() -> (FugueText, FugueText) {
+    (
+        FugueText::new("replica-a".to_string()),
+        FugueText::new("replica-b".to_string()),
+    )
+}
+
+impl Workload for Merges {
+    fn step(&mut self, i: u64, rng: &mut Rng) {
+        // The replicas take turns
+        let (doc, text, client) = match i.is_multiple_of(2) {
+            true => (&mut self.docs.0, &mut self.texts.0, "replica-a"),
+            false => (&mut self.docs.1, &mut self.texts.1, "replica-b"),
+        };
+        let key = format!("field-{}", rng.below(32));
+        doc.set_field(key, json!(i), i, client.to_string());
+        edit_text(text, TEXT_MAX_LEN, rng);
+        self.text_edits += 1;
+
+        if i.is_multiple_of(50) {
+            self.docs.0.merge(&self.docs.1);
+            self.docs.1.merge(&self.docs.0);
+            self.docs.0.take_dirty();
+            self.docs.1.take_dirty();
+            self.texts.0.merge(&self.texts.1).unwrap();
+            self.texts.1.merge(&self.texts.0).unwrap();
+            self.texts.0.take_dirty();
+            self.texts.1.take_dirty();
+        }
+        if self.text_edits >= TEXT_EDITS_PER_GENERATION {
+            self.texts = text_pair();
+            self.text_edits = 0;
+        }
+    }
+
+    fn usage(&self) -> MemoryUsage {
+        total_usage([
+            self.docs.0.memory_usage(),
+            self.docs.1.memory_usage(),
+            self.texts.0.memory_usage(),
+            self.texts.1.memory_usage(),
+        ])
+    }
+}
+
+/// Annotated edits, with annotations pruned and dirty state taken
+struct Gc {
+    doc: Document,
+    text: FugueText,
+    text_edits: u64,
+}
+
+impl Workload for Gc {
+    fn step(&mut self, i: u64, rng: &mut Rng) {
+        let payload = json!({ "source": "soak", "batch": i % 8 });
+        let key = format!("field-{}", rng.below(32));
+        self.doc
+            .annotated_transaction(payload.clone(), |doc| {
+                doc.set_field(key, json!(i), i, "client-1".to_string())
+            })
+            .unwrap();
+        let text = &mut self.text;
+        text.annotated(payload, |text| edit_text(text, TEXT_MAX_LEN, rng))
+            .unwrap();
+        self.text_edits += 1;
+
+        if i.is_multiple_of(100) {
+            let retention = AnnotationRetention {
+                max_age: None,
+                max_count: Some(16),
+            };
+            self.doc.prune_annotations(&retention);
+            self.text.prune_annotations(&retention);
+            self.doc.take_dirty();
+            self.text.take_dirty();
+        }
+        if self.text_edits >= TEXT_EDITS_PER_GENERATION {
+            self.text = FugueText::new("client-1".to_string());
+            self.text_edits = 0;
+        }
+    }
+
+    fn usage(&self) -> MemoryUsage {
+        total_usage([self.doc.memory_usage(), self.text.memory_usage()])
+    }
+}
+
+/// Edits with the document and text regularly replaced by a copy
+/// round-tripped through JSON
+struct RoundTrips {
+    doc: Document,
+    text: FugueText,
+    text_edits: u64,
+}
+
+impl Workload for RoundTrips {
+    fn step(&mut self, i: u64, rng: &mut Rng) {
+        let key = format!("field-{}", rng.below(32));
+        self.doc
+            .set_field(key, json!({ "n": i }), i, "client-1".to_string());
+        edit_text(&mut self.text, TEXT_MAX_LEN, rng);
+        self.text_edits += 1;
+
+        if i.is_multiple_of(50) {
+            let json = serde_json::to_string(&self.doc).unwrap();
+            self.doc = serde_json::from_str(&json).unwrap();
+            let json = serde_json::to_string(&self.text).unwrap();
+            self.text = serde_json::from_str(&json).unwrap();
+        }
+        if self.text_edits >= TEXT_EDITS_PER_GENERATION {
+            self.text = FugueText::new("client-1".to_string());
+            self.text_edits = 0;
+        }
+    }
+
+    fn usage(&self) -> MemoryUsage {
+        total_usage([self.doc.memory_usage(), self.text.memory_usage()])
+    }
+}
+
+fn phases() -> Vec<(&'static str, Box<dyn Workload>)> {
+    vec![
+        (
+            "documents",
+            Box::new(Documents {
+                pool: (0..64).map(|_| None).collect(),
+            }),
+        ),
+        (
+            "fields",
+            Box::new(Fields {
+                docs: (0..4)
+                    .map(|n| Document::new(format!("doc-{}", n)))
+                    .collect(),
+            }),
+        ),
+        (
+            "text",
+            Box::new(Texts {
+                texts: (0..4)
+                    .map(|n| (FugueText::new(format!("client-{}", n)), 0))
+                    .collect(),
+                generation: 0,
+            }),
+        ),
+        (
+            "merge",
+            Box::new(Merges {
+                docs: (
+                    Document::new("doc".to_string()),
+                    Document::new("doc".to_string()),
+                ),
+                texts: text_pair(),
+                text_edits: 0,
+            }),
+        ),
+        (
+            "gc",
+            Box::new(Gc {
+                doc: Document::new("doc".to_string()),
+                text: FugueText::new("client-1".to_string()),
+                text_edits: 0,
+            }),
+        ),
+        (
+            "round-trip",
+            Box::new(RoundTrips {
+                doc: Document::new("doc".to_string()),
+                text: FugueText::new("client-1".to_string()),
+                text_edits: 0,
+            }),
+        ),
+    ]
+}
+
+struct Sample {
+    iteration: u64,
+    rss: Option<usize>,
+    usage: MemoryUsage,
+}
+
+/// Least-squares slope of `points`, in bytes per 1000 iterations
+fn slope(points: &[(u64, f64)]) -> f64 {
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| *x as f64).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (x, y) in points {
+        let dx = *x as f64 - mean_x;
+        covariance += dx * (y - mean_y);
+        variance += dx * dx;
+    }
+    match variance == 0.0 {
+        true => 0.0,
+        false => covariance / variance * 1000.0,
+    }
+}
+
+/// The samples' timeline, one row per sample and a column per subsystem
+fn timeline(samples: &[Sample]) -> String {
+    let subsystems: Vec<&str> = samples
+        .iter()
+        .flat_map(|sample| sample.usage.subsystems().map(|(name, _)| name))
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut table = format!("{:>10} {:>12} {:>12}", "iteration", "rss", "total");
+    for name in &subsystems {
+        let _ = write!(table, " {:>14}", name);
+    }
+    for sample in samples {
+        let rss = sample.rss.map_or("-".to_string(), |rss| rss.to_string());
+        let _ = write!(
+            table,
+            "\n{:>10} {:>12} {:>12}",
+            sample.iteration,
+            rss,
+            sample.usage.total()
+        );
+        for name in &subsystems {
+            let _ = write!(table, " {:>14}", sample.usage.get(name));
+        }
+    }
+    table
+}
+
+/// Run a phase and return its failure report, if it grew too fast
+fn run_phase(name: &str, workload: &mut dyn Workload, config: &Config) -> Option<String> {
+    let mut rng = Rng::new(config.seed);
+    let mut samples = Vec::new();
+    for i in 1..=config.iterations {
+        workload.step(i, &mut rng);
+        if i.is_multiple_of(config.sample_every) || i == config.iterations {
+            samples.push(Sample {
+                iteration: i,
+                rss: rss_bytes(),
+                usage: workload.usage(),
+            });
+        }
+    }
+
+    let warm = (config.iterations as f64 * config.warmup) as u64;
+    let checked: Vec<&Sample> = samples.iter().filter(|s| s.iteration > warm).collect();
+    let series = |value: &dyn Fn(&Sample) -> f64| -> Vec<(u64, f64)> {
+        checked
+            .iter()
+            .map(|sample| (sample.iteration, value(sample)))
+            .collect()
+    };
+    let internal = slope(&series(&|sample| sample.usage.total() as f64));
+    let rss = match checked.iter().all(|sample| sample.rss.is_some()) {
+        true => Some(slope(&series(&|sample| sample.rss.unwrap_or(0) as f64))),
+        false => None,
+    };
+    let mut by_subsystem: BTreeMap<&str, f64> = BTreeMap::new();
+    if let Some(last) = samples.last() {
+        for (subsystem, _) in last.usage.subsystems() {
+            let growth = slope(&series(&|sample| sample.usage.get(subsystem) as f64));
+            by_subsystem.insert(subsystem, growth);
+        }
+    }
+    let fastest = by_subsystem
+        .iter()
+        .filter(|(_, growth)| **growth > 0.0)
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(name, growth)| format!("{} ({:+.1} bytes/1000 iterations)", name, growth))
+        .unwrap_or_else(|| "none".to_string());
+
+    eprintln!(
+        "soak {}: memory_usage {:+.1} bytes/1000 iterations, rss {}, fastest subsystem {}",
+        name,
+        internal,
+        rss.map_or("n/a".to_string(), |rss| format!(
+            "{:+.1} bytes/1000 iterations",
+            rss
+        )),
+        fastest
+    );
+
+    let mut failures = Vec::new();
+    if internal > config.max_slope {
+        failures.push(format!(
+            "memory_usage() grew {:.1} bytes/1000 iterations (ceiling {})",
+            internal, config.max_slope
+        ));
+    }
+    if let Some(rss) = rss.filter(|rss| *rss > config.max_rss_slope) {
+        failures.push(format!(
+            "RSS grew {:.1} bytes/1000 iterations (ceiling {})",
+            rss, config.max_rss_slope
+        ));
+    }
+    match failures.is_empty() {
+        true => None,
+        false => Some(format!(
+            "phase {:?} after {} warm-up iterations: {}\nfastest-growing subsystem: {}\n{}",
+            name,
+            warm,
+            failures.join("; "),
+            fastest,
+            timeline(&samples)
+        )),
+    }
+}
+
+#[test]
+#[ignore = "runs millions of operations; run with `cargo soak`"]
+fn test_soak_memory_stays_flat() {
+    let config = Config::from_env();
+    let mut failures = Vec::new();
+    let mut ran = 0;
+    for (name, mut workload) in phases() {
+        if let Some(selected) = &config.phases {
+            if !selected.iter().any(|phase| phase == name) {
+                continue;
+            }
+        }
+        ran += 1;
+        if let Some(failure) = run_phase(name, workload.as_mut(), &config) {
+            failures.push(failure);
+        }
+    }
+    assert!(ran > 0, "SOAK_PHASES matches no phase");
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn test_slope_fits_growth() {
+    let flat: Vec<(u64, f64)> = (1..=10).map(|i| (i * 1000, 5000.0)).collect();
+    assert_eq!(slope(&flat), 0.0);
+
+    let growing: Vec<(u64, f64)> = (1..=10).map(|i| (i * 1000, (i * 200) as f64)).collect();
+    assert!((slope(&growing) - 200.0).abs() < 1e-9);
+
+    let samples = vec![Sample {
+        iteration: 10,
+        rss: None,
+        usage: MemoryUsage::new(),
+    }];
+    assert!(timeline(&samples).contains("iteration"));
+}
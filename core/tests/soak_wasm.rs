@@ -0,0 +1,155 @@
+//! Soak test for the WASM build: `WebAssembly.Memory` must stop growing
+//!
+//! The wasm32 counterpart of `tests/soak.rs`, driving the JavaScript
+//! bindings (`WasmDocument`, `WasmFugueText`) through document churn,
+//! field writes, text edits, merges and JSON round-trips. Linear memory
+//! never shrinks, so instead of a slope it checks how many pages the
+//! memory grew after the warm-up. A failure prints the timeline of pages
+//! and `memoryUsage()` per subsystem.
+//!
+//! Slow, so ignored; run it under Node with:
+//!
+//! ```text
+//! wasm-pack test --node --release -- --features wasm,text-crdt --test soak_wasm -- --include-ignored
+//! ```
+
+#![cfg(all(target_arch = "wasm32", feature = "wasm", feature = "text-crdt"))]
+
+use serde_json::Value as JsonValue;
+use synckit_core::wasm::bindings::WasmFugueText;
+use synckit_core::wasm::WasmDocument;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const ITERATIONS: u64 = 100_000;
+const SAMPLE_EVERY: u64 = 5_000;
+const WARMUP: u64 = ITERATIONS / 4;
+
+/// Pages (64 KiB each) the memory may still grow after the warm-up
+const MAX_GROWTH_PAGES: u32 = 16;
+
+/// Edits a text gets before it is replaced (see `tests/soak.rs`)
+const TEXT_EDITS_PER_GENERATION: u64 = 97;
+const TEXT_MAX_LEN: usize = 100;
+
+/// Current size of this module's `WebAssembly.Memory`, in 64 KiB pages
+fn memory_pages() -> u32 {
+    let memory: js_sys::WebAssembly::Memory = wasm_bindgen::memory().unchecked_into();
+    let buffer: js_sys::ArrayBuffer = memory.buffer().unchecked_into();
+    buffer.byte_length() / 65_536
+}
+
+/// xorshift64, so every run does the same work
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n.max(1)
+    }
+}
+
+fn edit_text(text: &mut WasmFugueText, rng: &mut Rng) {
+    let len = text.length();
+    if len >= TEXT_MAX_LEN || (len > 0 && rng.below(3) == 0) {
+        let position = rng.below(len as u64) as usize;
+        let length = (rng.below(8) as usize + 1).min(len - position);
+        text.delete(position, length).unwrap();
+    } else {
+        let position = rng.below(len as u64 + 1) as usize;
+        text.insert(position, "lorem ".to_string()).unwrap();
+    }
+}
+
+/// Summed `memoryUsage()` subsystems of the live values
+fn usage(docs: &[Option<WasmDocument>], texts: &[WasmFugueText]) -> JsonValue {
+    let mut total = serde_json::Map::new();
+    let reports = docs
+        .iter()
+        .flatten()
+        .map(|doc| doc.memory_usage().unwrap())
+        .chain(texts.iter().map(|text| text.memory_usage().unwrap()));
+    for report in reports {
+        let report: JsonValue = serde_json::from_str(&report).unwrap();
+        for (name, bytes) in report["subsystems"].as_object().unwrap() {
+            let sum = total.get(name).and_then(JsonValue::as_u64).unwrap_or(0);
+            total.insert(name.clone(), (sum + bytes.as_u64().unwrap()).into());
+        }
+    }
+    JsonValue::Object(total)
+}
+
+#[wasm_bindgen_test]
+#[ignore]
+fn test_soak_memory_stops_growing() {
+    let mut rng = Rng(1);
+    let mut docs: Vec<Option<WasmDocument>> = (0..16).map(|_| None).collect();
+    let mut texts = vec![
+        WasmFugueText::new("replica-a".to_string()),
+        WasmFugueText::new("replica-b".to_string()),
+    ];
+    let mut text_edits = 0;
+    let mut timeline = Vec::new();
+    let mut warm_pages = None;
+
+    for i in 1..=ITERATIONS {
+        // Documents come and go, and are written while they live
+        let slot = rng.below(docs.len() as u64) as usize;
+        match rng.below(8) {
+            0 => docs[slot] = None,
+            _ => {
+                let doc =
+                    docs[slot].get_or_insert_with(|| WasmDocument::new(format!("doc-{}", slot)));
+                let key = format!("field-{}", rng.below(16));
+                doc.set_field(key, format!("{{\"n\":{}}}", i), i, "client-1".to_string())
+                    .unwrap();
+            }
+        }
+
+        // Two text replicas edited apart and merged
+        edit_text(&mut texts[(i % 2) as usize], &mut rng);
+        text_edits += 1;
+        if i % 50 == 0 {
+            let (a, b) = texts.split_at_mut(1);
+            a[0].merge(&b[0]).unwrap();
+            b[0].merge(&a[0]).unwrap();
+        }
+        if text_edits >= TEXT_EDITS_PER_GENERATION {
+            texts = vec![
+                WasmFugueText::new("replica-a".to_string()),
+                WasmFugueText::new("replica-b".to_string()),
+            ];
+            text_edits = 0;
+        }
+
+        // JSON round-trips replace a text with its copy
+        if i % 200 == 0 {
+            let json = texts[0].to_json().unwrap();
+            texts[0] = WasmFugueText::from_json(json, None).unwrap();
+        }
+
+        if i % SAMPLE_EVERY == 0 {
+            let pages = memory_pages();
+            if i >= WARMUP && warm_pages.is_none() {
+                warm_pages = Some(pages);
+            }
+            timeline.push((i, pages, usage(&docs, &texts)));
+        }
+    }
+
+    let warm_pages = warm_pages.expect("a sample after the warm-up");
+    let grown = memory_pages() - warm_pages;
+    let rows: Vec<String> = timeline
+        .iter()
+        .map(|(i, pages, usage)| format!("{:>8} {:>6} pages  {}", i, pages, usage))
+        .collect();
+    assert!(
+        grown <= MAX_GROWTH_PAGES,
+        "WebAssembly.Memory grew {} pages after the warm-up (ceiling {})\n{}",
+        grown,
+        MAX_GROWTH_PAGES,
+        rows.join("\n")
+    );
+}
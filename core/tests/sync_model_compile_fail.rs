@@ -0,0 +1,10 @@
+//! Structs `#[derive(SyncModel)]` can't store are rejected at compile
+//! time, with the error on the offending field or attribute
+
+#![cfg(feature = "derive")]
+
+#[test]
+fn test_unsupported_models_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/sync_model/*.rs");
+}
@@ -0,0 +1,351 @@
+//! Drive the `synckit-testserver` binary as a child process, as SDK test
+//! suites do: sync through it, impair the link, run a scenario and read
+//! the introspection endpoint
+
+#![cfg(feature = "testserver")]
+
+#[allow(dead_code)]
+#[path = "../src/bin/synckit_testserver/wire.rs"]
+mod wire;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value as JsonValue};
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Instant};
+use tokio_tungstenite::tungstenite::Message as Frame;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use wire::Message;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// How long to wait for a message that should come
+const WAIT: Duration = Duration::from_secs(5);
+
+/// A running server, killed on drop
+struct TestServer {
+    child: Child,
+    ws: String,
+    http: String,
+}
+
+impl TestServer {
+    fn start(flags: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_synckit-testserver"))
+            .args(["--listen", "127.0.0.1:0", "--http", "127.0.0.1:0"])
+            .args(flags)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("test server starts");
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let addresses: JsonValue = serde_json::from_str(&line)
+            .unwrap_or_else(|e| panic!("first line {:?} isn't JSON: {}", line, e));
+        Self {
+            child,
+            ws: addresses["ws"].as_str().unwrap().to_string(),
+            http: addresses["http"].as_str().unwrap().to_string(),
+        }
+    }
+
+    async fn connect(&self) -> Socket {
+        tokio_tungstenite::connect_async(self.ws.as_str())
+            .await
+            .expect("WebSocket connects")
+            .0
+    }
+
+    /// A client that has authenticated and subscribed to `document_id`
+    async fn subscribed(&self, document_id: &str) -> Socket {
+        let mut socket = self.connect().await;
+        send(&mut socket, json!({"type": "auth", "token": "t"})).await;
+        assert_eq!(recv(&mut socket).await["type"], "auth_success");
+        send(
+            &mut socket,
+            json!({"type": "subscribe", "id": "s1", "documentId": document_id}),
+        )
+        .await;
+        let response = recv(&mut socket).await;
+        assert_eq!(response["type"], "sync_response");
+        assert_eq!(response["documentId"], document_id);
+        socket
+    }
+
+    /// `GET path` on the introspection endpoint
+    async fn get(&self, path: &str) -> (String, String) {
+        let address = self.http.trim_start_matches("http://");
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nhost: {}\r\n\r\n", path, address);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.lines().next().unwrap().to_string();
+        (status, body.to_string())
+    }
+
+    async fn state(&self) -> JsonValue {
+        let (status, body) = self.get("/state").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        serde_json::from_str(&body).unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn message(value: JsonValue) -> Message {
+    let mut message = match value {
+        JsonValue::Object(message) => message,
+        _ => panic!("messages are objects"),
+    };
+    message.insert("timestamp".to_string(), json!(1_700_000_000_000u64));
+    message
+}
+
+/// Send as a binary frame, the SDK's default
+async fn send(socket: &mut Socket, value: JsonValue) {
+    let frame = wire::encode_binary(&message(value));
+    socket.send(Frame::Binary(frame.into())).await.unwrap();
+}
+
+/// The next message, or `None` if the connection closes or nothing comes
+/// within `wait`
+async fn try_recv(socket: &mut Socket, wait: Duration) -> Option<Message> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let frame = timeout(deadline - Instant::now(), socket.next())
+            .await
+            .ok()??;
+        match frame.ok()? {
+            Frame::Binary(bytes) => return Some(wire::decode_binary(&bytes).unwrap()),
+            Frame::Text(text) => return Some(wire::decode_text(&text).unwrap()),
+            Frame::Close(_) => return None,
+            _ => continue,
+        }
+    }
+}
+
+async fn recv(socket: &mut Socket) -> Message {
+    try_recv(socket, WAIT).await.expect("a message")
+}
+
+/// Wait until the server closes the connection
+async fn closed(socket: &mut Socket) -> bool {
+    timeout(WAIT, async {
+        loop {
+            match socket.next().await {
+                Some(Ok(Frame::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            }
+        }
+    })
+    .await
+    .is_ok()
+}
+
+#[tokio::test]
+async fn test_deltas_sync_between_clients() {
+    let server = TestServer::start(&[]);
+    let mut alice = server.subscribed("doc-1").await;
+    let mut bob = server.subscribed("doc-1").await;
+
+    send(
+        &mut alice,
+        json!({
+            "type": "delta",
+            "id": "m1",
+            "documentId": "doc-1",
+            "delta": {"title": "Hello ✨"},
+            "vectorClock": {"alice": 1},
+        }),
+    )
+    .await;
+    let ack = recv(&mut alice).await;
+    assert_eq!(ack["type"], "ack");
+    assert_eq!(ack["messageId"], "m1");
+    for socket in [&mut alice, &mut bob] {
+        let delta = recv(socket).await;
+        assert_eq!(delta["type"], "delta");
+        assert_eq!(delta["field"], "title");
+        assert_eq!(delta["value"], "Hello ✨");
+        assert_eq!(delta["clock"], json!({"alice": 1}));
+    }
+
+    // A later client gets the state on subscribing
+    let mut carol = server.connect().await;
+    send(&mut carol, json!({"type": "auth"})).await;
+    recv(&mut carol).await;
+    send(
+        &mut carol,
+        json!({"type": "sync_request", "id": "r1", "documentId": "doc-1"}),
+    )
+    .await;
+    let response = recv(&mut carol).await;
+    assert_eq!(response["requestId"], "r1");
+    assert_eq!(response["state"], json!({"title": "Hello ✨"}));
+
+    let state = server.state().await;
+    let document = &state["documents"]["doc-1"];
+    assert_eq!(document["fields"], json!({"title": "Hello ✨"}));
+    assert_eq!(document["version"], json!({"alice": 1}));
+    assert_eq!(document["checksum"].as_str().unwrap().len(), 16);
+    assert_eq!(state["metrics"]["deltas_applied"], 1);
+    assert_eq!(state["metrics"]["connections_open"], 3);
+    assert_eq!(server.get("/health").await.1, "ok");
+    assert_eq!(server.get("/nope").await.0, "HTTP/1.1 404 Not Found");
+}
+
+#[tokio::test]
+async fn test_deletion_and_text_frames() {
+    let server = TestServer::start(&[]);
+    let mut socket = server.connect().await;
+    // Replies come in the format the client used
+    socket
+        .send(Frame::Text(r#"{"type":"auth","timestamp":1}"#.into()))
+        .await
+        .unwrap();
+    match socket.next().await.unwrap().unwrap() {
+        Frame::Text(text) => assert!(text.contains("auth_success")),
+        other => panic!("expected a text frame, got {:?}", other),
+    }
+    send(
+        &mut socket,
+        json!({"type": "delta", "id": "m1", "documentId": "doc-1", "field": "title", "value": "x"}),
+    )
+    .await;
+    send(
+        &mut socket,
+        json!({"type": "delta", "id": "m2", "documentId": "doc-1", "delta": {"title": {"__deleted": true}}}),
+    )
+    .await;
+    let mut deltas = Vec::new();
+    while deltas.len() < 2 {
+        let message = recv(&mut socket).await;
+        if message["type"] == "delta" {
+            deltas.push(message["value"].clone());
+        }
+    }
+    assert_eq!(deltas, vec![json!("x"), json!({"__deleted": true})]);
+    assert_eq!(
+        server.state().await["documents"]["doc-1"]["fields"],
+        json!({})
+    );
+
+    send(&mut socket, json!({"type": "awareness_update"})).await;
+    let error = recv(&mut socket).await;
+    assert_eq!(error["type"], "error");
+    assert_eq!(error["details"], json!({"type": "awareness_update"}));
+}
+
+#[tokio::test]
+async fn test_auth_token() {
+    let server = TestServer::start(&["--auth-token", "secret"]);
+    let mut socket = server.connect().await;
+    send(
+        &mut socket,
+        json!({"type": "subscribe", "documentId": "doc-1"}),
+    )
+    .await;
+    assert_eq!(recv(&mut socket).await["error"], "Not authenticated");
+    send(&mut socket, json!({"type": "auth", "token": "wrong"})).await;
+    assert_eq!(recv(&mut socket).await["type"], "auth_error");
+    assert!(closed(&mut socket).await);
+
+    let mut socket = server.connect().await;
+    send(&mut socket, json!({"type": "auth", "token": "secret"})).await;
+    assert_eq!(recv(&mut socket).await["type"], "auth_success");
+}
+
+#[tokio::test]
+async fn test_latency_applies_both_ways() {
+    let server = TestServer::start(&["--latency", "150ms"]);
+    let mut socket = server.connect().await;
+    let started = Instant::now();
+    send(&mut socket, json!({"type": "ping"})).await;
+    assert_eq!(recv(&mut socket).await["type"], "pong");
+    assert!(started.elapsed() >= Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn test_drop_rate_drops_everything() {
+    let server = TestServer::start(&["--drop-rate", "1", "--seed", "7"]);
+    let mut socket = server.connect().await;
+    for _ in 0..3 {
+        send(&mut socket, json!({"type": "ping"})).await;
+    }
+    assert!(try_recv(&mut socket, Duration::from_millis(300))
+        .await
+        .is_none());
+    let metrics = &server.state().await["metrics"];
+    assert_eq!(metrics["messages_received"], 3);
+    assert_eq!(metrics["dropped_incoming"], 3);
+    assert_eq!(metrics["messages_sent"], 0);
+}
+
+#[tokio::test]
+async fn test_forced_disconnects() {
+    let server = TestServer::start(&["--disconnect-every", "300ms"]);
+    let mut socket = server.connect().await;
+    let started = Instant::now();
+    assert!(closed(&mut socket).await);
+    assert!(started.elapsed() >= Duration::from_millis(250));
+
+    // Clients reconnect as usual
+    let _socket = server.connect().await;
+    let metrics = &server.state().await["metrics"];
+    assert_eq!(metrics["forced_disconnects"], 1);
+    assert_eq!(metrics["connections_opened"], 2);
+}
+
+#[tokio::test]
+async fn test_scenario_script() {
+    let path = std::env::temp_dir().join(format!(
+        "synckit-testserver-scenario-{}.txt",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        "# written by the server, then everyone is dropped\n\
+         at 200ms write doc-1 title \"from the script\"\n\
+         at 400ms disconnect\n",
+    )
+    .unwrap();
+    let server = TestServer::start(&["--scenario", path.to_str().unwrap()]);
+    let mut socket = server.subscribed("doc-1").await;
+
+    let delta = recv(&mut socket).await;
+    assert_eq!(delta["type"], "delta");
+    assert_eq!(delta["value"], "from the script");
+    assert_eq!(delta["clientId"], "server");
+    assert!(closed(&mut socket).await);
+
+    let state = server.state().await;
+    assert_eq!(
+        state["documents"]["doc-1"]["fields"]["title"],
+        "from the script"
+    );
+    assert_eq!(state["metrics"]["scenario_steps"], 2);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_bad_flags_exit_with_usage() {
+    let output = Command::new(env!("CARGO_BIN_EXE_synckit-testserver"))
+        .args(["--drop-rate", "2"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("isn't between 0 and 1"), "{}", stderr);
+    assert!(stderr.contains("usage: synckit-testserver"), "{}", stderr);
+}
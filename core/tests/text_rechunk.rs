@@ -0,0 +1,95 @@
+//! Re-chunking keeps a text's block count bounded under edit patterns
+//! that split it into more blocks than characters, without changing what
+//! replicas converge to
+
+#![cfg(feature = "text-crdt")]
+
+use synckit_core::crdt::text_fugue::{FugueText, FugueTextOptions};
+
+const OPTIONS: FugueTextOptions = FugueTextOptions {
+    max_blocks_per_char: 2,
+    min_blocks: 64,
+    rechunk_budget: 32,
+};
+
+fn blocks(text: &FugueText) -> usize {
+    text.memory_usage().counter("block_count") as usize
+}
+
+/// Most blocks `OPTIONS` allow, plus what one pass leaves behind while
+/// the edits that started it keep adding blocks
+fn bound(text: &FugueText) -> usize {
+    let allowed = OPTIONS
+        .min_blocks
+        .max(OPTIONS.max_blocks_per_char * text.len());
+    allowed + allowed / 2
+}
+
+/// Type six characters one at a time at `cursor`, then backspace four
+fn burst(text: &mut FugueText, cursor: usize, round: usize) -> usize {
+    for (i, c) in format!("{:06}", round).chars().enumerate() {
+        text.insert(cursor + i, &c.to_string()).unwrap();
+    }
+    for i in 0..4 {
+        text.delete(cursor + 5 - i, 1).unwrap();
+    }
+    2
+}
+
+/// Sync `to` with what `from` has that it hasn't
+fn sync(from: &FugueText, to: &mut FugueText) {
+    to.apply_delta(&from.diff_since(&to.state_vector()))
+        .unwrap();
+}
+
+#[test]
+fn test_alternating_cursors_stay_within_the_bound_and_converge() {
+    let mut writer = FugueText::with_options("writer".to_string(), OPTIONS);
+    let mut plain = FugueText::with_options(
+        "reader".to_string(),
+        FugueTextOptions {
+            max_blocks_per_char: 0,
+            ..OPTIONS
+        },
+    );
+
+    // Typing at the front and the back in turn: each keystroke is a block
+    // of its own, and every backspace leaves a tombstone
+    let mut front = 0;
+    for round in 0..48 {
+        front += burst(&mut writer, front, round);
+        let back = writer.len();
+        burst(&mut writer, back, round);
+        assert!(
+            blocks(&writer) <= bound(&writer),
+            "round {}: {} blocks for {} characters",
+            round,
+            blocks(&writer),
+            writer.len()
+        );
+
+        // The reader catches up now and then, sometimes halfway through a
+        // run the writer joins later, and edits too
+        if round % 7 == 3 {
+            sync(&writer, &mut plain);
+            plain.insert(plain.len() / 2, "|").unwrap();
+            sync(&plain, &mut writer);
+        }
+    }
+
+    let usage = writer.memory_usage();
+    assert!(usage.counter("rechunk_passes") > 0);
+    assert!(usage.counter("blocks_joined") > 0);
+    assert_eq!(plain.memory_usage().counter("blocks_joined"), 0);
+
+    sync(&writer, &mut plain);
+    sync(&plain, &mut writer);
+    assert_eq!(writer.to_string(), plain.to_string());
+    assert!(blocks(&writer) < blocks(&plain));
+
+    // A replica that never saw the writer's split blocks converges too
+    let mut fresh = FugueText::new("fresh".to_string());
+    fresh.merge(&writer).unwrap();
+    fresh.merge(&plain).unwrap();
+    assert_eq!(fresh.to_string(), writer.to_string());
+}
@@ -0,0 +1,157 @@
+//! Capture the spans emitted during a scripted sync exchange and check the
+//! key fields are present
+
+#![cfg(all(
+    feature = "tracing",
+    feature = "protocol-binary",
+    feature = "text-crdt"
+))]
+
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use synckit_core::crdt::FugueText;
+use synckit_core::protocol::delta::DocumentDelta;
+use synckit_core::protocol::serialize::{decode_message, encode_message};
+use synckit_core::Document;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+
+type Fields = HashMap<String, String>;
+
+#[derive(Clone, Default)]
+struct Captured {
+    spans: Arc<Mutex<Vec<(String, Fields)>>>,
+    open: Arc<Mutex<HashMap<u64, usize>>>,
+    debug_events: Arc<Mutex<Vec<Fields>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Captured {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _: Context<'_, S>) {
+        let mut fields = Fields::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((attrs.metadata().name().to_string(), fields));
+        self.open
+            .lock()
+            .unwrap()
+            .insert(id.into_u64(), spans.len() - 1);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, _: Context<'_, S>) {
+        let index = self.open.lock().unwrap()[&id.into_u64()];
+        values.record(&mut FieldVisitor(&mut self.spans.lock().unwrap()[index].1));
+    }
+
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        if *event.metadata().level() == Level::DEBUG {
+            let mut fields = Fields::new();
+            event.record(&mut FieldVisitor(&mut fields));
+            self.debug_events.lock().unwrap().push(fields);
+        }
+    }
+}
+
+impl Captured {
+    fn span(&self, name: &str) -> Fields {
+        self.spans
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(span, _)| span == name)
+            .unwrap_or_else(|| panic!("no span named {}", name))
+            .1
+            .clone()
+    }
+}
+
+#[test]
+fn test_sync_exchange_emits_spans() {
+    let captured = Captured::default();
+    let subscriber = Registry::default().with(captured.clone());
+
+    tracing::subscriber::with_default(subscriber, || {
+        // Document: A computes a delta, sends it over the wire, B applies it
+        let base = Document::new("doc-1".to_string());
+        let mut a = base.clone();
+        a.set_field("title".to_string(), json!("Hello"), 1, "a".to_string());
+        a.set_field("done".to_string(), json!(false), 2, "a".to_string());
+
+        let delta = DocumentDelta::compute(&base, &a).unwrap();
+        let wire = encode_message(&delta.to_protocol()).unwrap();
+        let received = DocumentDelta::from_protocol(&decode_message(&wire).unwrap(), "b").unwrap();
+        let mut b = base.clone();
+        received.apply_to(&mut b, "b").unwrap();
+        b.set_field("title".to_string(), json!("Hi"), 3, "b".to_string());
+        assert_eq!(a.merge(&b), 1);
+
+        // Text: state vector / diff exchange, then a full merge back
+        let mut text_a = FugueText::new("a".to_string());
+        let mut text_b = FugueText::new("b".to_string());
+        text_a.insert(0, "Hello").unwrap();
+        let diff = text_a.encode_diff(&text_b.encode_state_vector()).unwrap();
+        text_b.apply_diff(&diff).unwrap();
+        text_b.insert(5, " World").unwrap();
+        text_a.merge(&text_b).unwrap();
+        assert_eq!(text_a.to_string(), "Hello World");
+    });
+
+    let compute = captured.span("DocumentDelta::compute");
+    assert_eq!(compute["document_id"], "doc-1");
+    assert_eq!(compute["changes"], "2");
+
+    let encode = captured.span("encode_message");
+    assert_eq!(encode["message"], "Delta");
+    assert!(encode["bytes"].parse::<usize>().unwrap() > 0);
+    assert_eq!(captured.span("decode_message")["bytes"], encode["bytes"]);
+
+    let apply = captured.span("DocumentDelta::apply_to");
+    assert_eq!(apply["document_id"], "doc-1");
+    assert_eq!(apply["changes"], "2");
+
+    let merge = captured.span("Document::merge");
+    assert_eq!(merge["document_id"], "doc-1");
+    assert_eq!(merge["remote_fields"], "2");
+    assert_eq!(merge["updated"], "1");
+
+    let encode_diff = captured.span("FugueText::encode_diff");
+    assert_eq!(encode_diff["client_id"], "a");
+    assert_eq!(encode_diff["blocks"], "1");
+    assert_eq!(
+        captured.span("FugueText::apply_diff")["bytes"],
+        encode_diff["bytes"]
+    );
+
+    let text_merge = captured.span("FugueText::merge");
+    assert_eq!(text_merge["client_id"], "a");
+    assert_eq!(text_merge["local_blocks"], "1");
+    assert_eq!(text_merge["remote_blocks"], "2");
+    assert_eq!(text_merge["blocks_after"], "2");
+    assert_eq!(text_merge["rope"], "update");
+
+    // Per-block detail is debug events, not span fields
+    let events = captured.debug_events.lock().unwrap();
+    assert!(events
+        .iter()
+        .any(|event| event["message"] == "integrating remote block"));
+    assert!(events
+        .iter()
+        .any(|event| event["message"] == "remote field won" && event["field"] == "title"));
+}
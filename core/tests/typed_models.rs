@@ -0,0 +1,188 @@
+//! `#[derive(SyncModel)]` documents converge like untyped ones: two typed
+//! replicas editing concurrently and syncing through the coordinator end
+//! with the same struct, and their subscribers see typed changes
+
+#![cfg(all(feature = "derive", feature = "sets", feature = "protocol-binary"))]
+
+use std::sync::{Arc, Mutex};
+use synckit_core::crdt::ORSet;
+use synckit_core::model::{SyncModel, SCHEMA_FIELD};
+use synckit_core::protocol::sync::SyncCoordinator;
+use synckit_core::Document;
+
+#[derive(SyncModel)]
+struct Todo {
+    #[synckit(text)]
+    title: String,
+    done: bool,
+    tags: ORSet<String>,
+    #[synckit(counter)]
+    votes: i64,
+    #[synckit(rename = "due")]
+    due_date: Option<String>,
+}
+
+/// The same document, a schema version later
+#[derive(Debug, SyncModel)]
+#[synckit(version = 2)]
+struct TodoV2 {
+    title: String,
+}
+
+fn todo() -> Todo {
+    let mut tags = ORSet::new("carol".to_string());
+    tags.add("errand".to_string());
+    Todo {
+        title: "Buy milk".to_string(),
+        done: false,
+        tags,
+        votes: 1,
+        due_date: None,
+    }
+}
+
+/// A typed replica and its sync coordinator
+struct Peer {
+    name: &'static str,
+    todo: TodoDocument,
+    coordinator: SyncCoordinator,
+}
+
+impl Peer {
+    fn new(name: &'static str, base: &Document) -> Self {
+        Self {
+            name,
+            todo: TodoDocument::open(base.clone(), name.to_string()).unwrap(),
+            coordinator: SyncCoordinator::new(),
+        }
+    }
+
+    fn send(&self, base: &Document, to: &mut Peer) {
+        let delta = self
+            .coordinator
+            .outgoing_delta(base, self.todo.document())
+            .unwrap();
+        to.coordinator
+            .apply_incoming(&delta, to.todo.document_mut(), to.name)
+            .unwrap();
+    }
+}
+
+fn sorted(mut tags: Vec<String>) -> Vec<String> {
+    tags.sort();
+    tags
+}
+
+#[test]
+fn test_round_trip_and_schema_check() {
+    let document = todo()
+        .into_document("todo".to_string(), "carol".to_string())
+        .unwrap();
+    assert_eq!(
+        document.get_field(&SCHEMA_FIELD.to_string()),
+        Some(&1.into())
+    );
+
+    let read = Todo::from_document(&document, "dave").unwrap();
+    assert_eq!(read.title, "Buy milk");
+    assert!(!read.done);
+    assert_eq!(read.tags.iter().collect::<Vec<_>>(), ["errand"]);
+    assert_eq!(read.votes, 1);
+    assert_eq!(read.due_date, None);
+
+    let error = TodoV2::from_document(&document, "dave").unwrap_err();
+    assert_eq!(error.code_name(), "SCHEMA_MISMATCH");
+    assert!(TodoV2Document::open(document, "dave".to_string()).is_err());
+    let untyped = Document::new("todo".to_string());
+    assert!(TodoDocument::open(untyped, "dave".to_string()).is_err());
+}
+
+#[test]
+fn test_typed_replicas_converge() {
+    let base = TodoDocument::new(todo(), "todo".to_string(), "carol".to_string())
+        .unwrap()
+        .into_document();
+    let mut alice = Peer::new("alice", &base);
+    let mut bob = Peer::new("bob", &base);
+
+    alice.todo.splice_title(4, 0, "oat ").unwrap();
+    alice.todo.set_done(true).unwrap();
+    alice.todo.increment_votes(2);
+    alice.todo.add_tags("home".to_string()).unwrap();
+    alice
+        .todo
+        .set_due_date(Some("2026-10-20".to_string()))
+        .unwrap();
+
+    bob.todo.set_title("Buy milk today");
+    bob.todo.increment_votes(3);
+    bob.todo.remove_tags(&"errand".to_string()).unwrap();
+    bob.todo.add_tags("urgent".to_string()).unwrap();
+
+    alice.send(&base, &mut bob);
+    bob.send(&base, &mut alice);
+
+    for peer in [&alice, &bob] {
+        let todo = &peer.todo;
+        assert_eq!(todo.title(), "Buy oat milk today", "{}", peer.name);
+        assert!(todo.done().unwrap());
+        assert_eq!(todo.votes(), 6);
+        assert_eq!(sorted(todo.tags().unwrap()), ["home", "urgent"]);
+        assert_eq!(todo.due_date().unwrap().as_deref(), Some("2026-10-20"));
+    }
+    assert_eq!(
+        alice.todo.document().content_hash(),
+        bob.todo.document().content_hash()
+    );
+}
+
+#[test]
+fn test_concurrent_add_survives_remove() {
+    let base = TodoDocument::new(todo(), "todo".to_string(), "carol".to_string())
+        .unwrap()
+        .into_document();
+    let mut alice = Peer::new("alice", &base);
+    let mut bob = Peer::new("bob", &base);
+
+    // Bob re-adds the tag Alice removes: his addition is one she never saw
+    alice.todo.remove_tags(&"errand".to_string()).unwrap();
+    bob.todo.remove_tags(&"errand".to_string()).unwrap();
+    bob.todo.add_tags("errand".to_string()).unwrap();
+    alice.send(&base, &mut bob);
+    bob.send(&base, &mut alice);
+
+    assert_eq!(alice.todo.tags().unwrap(), ["errand"]);
+    assert_eq!(bob.todo.tags().unwrap(), ["errand"]);
+    let snapshot = alice.todo.snapshot().unwrap();
+    assert!(snapshot.tags.contains(&"errand".to_string()));
+}
+
+#[test]
+fn test_incoming_changes_are_projected_to_fields() {
+    let base = TodoDocument::new(todo(), "todo".to_string(), "carol".to_string())
+        .unwrap()
+        .into_document();
+    let mut alice = Peer::new("alice", &base);
+    let mut bob = Peer::new("bob", &base);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&seen);
+    bob.todo
+        .subscribe(move |changes| sink.lock().unwrap().push(changes.to_vec()));
+
+    alice.todo.increment_votes(1);
+    alice.todo.set_done(true).unwrap();
+    alice.todo.set_title("Buy bread");
+    alice.send(&base, &mut bob);
+
+    let batches = seen.lock().unwrap();
+    assert_eq!(batches.len(), 1);
+    let mut changes = batches[0].clone();
+    changes.sort_by_key(|change| format!("{:?}", change));
+    assert_eq!(
+        changes,
+        [TodoChange::Done, TodoChange::Title, TodoChange::Votes]
+    );
+    assert_eq!(Todo::change_for("due"), Some(TodoChange::DueDate));
+    assert_eq!(Todo::change_for("votes.alice"), Some(TodoChange::Votes));
+    assert_eq!(Todo::change_for(SCHEMA_FIELD), None);
+}
@@ -0,0 +1,8 @@
+use synckit_core::model::SyncModel;
+
+#[derive(SyncModel)]
+struct Todo {
+    title: &'static str,
+}
+
+fn main() {}
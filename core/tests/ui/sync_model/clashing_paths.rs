@@ -0,0 +1,11 @@
+use synckit_core::model::SyncModel;
+
+#[derive(SyncModel)]
+struct Poll {
+    #[synckit(counter)]
+    votes: i64,
+    #[synckit(rename = "votes.alice")]
+    alice_votes: i64,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use synckit_core::model::SyncModel;
+
+#[derive(SyncModel)]
+struct Poll {
+    #[synckit(counter)]
+    votes: u32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use synckit_core::model::SyncModel;
+
+#[derive(SyncModel)]
+enum Status {
+    Open,
+    Done,
+}
+
+fn main() {}
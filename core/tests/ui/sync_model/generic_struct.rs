@@ -0,0 +1,8 @@
+use synckit_core::model::SyncModel;
+
+#[derive(SyncModel)]
+struct Todo<T> {
+    title: T,
+}
+
+fn main() {}
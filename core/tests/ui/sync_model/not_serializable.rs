@@ -0,0 +1,11 @@
+use synckit_core::model::SyncModel;
+
+struct Opaque;
+
+#[derive(SyncModel)]
+struct Todo {
+    title: String,
+    handle: Opaque,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use std::collections::BTreeSet;
+use synckit_core::model::SyncModel;
+
+#[derive(SyncModel)]
+struct Todo {
+    #[synckit(set)]
+    tags: BTreeSet<String>,
+}
+
+fn main() {}
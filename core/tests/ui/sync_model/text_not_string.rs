@@ -0,0 +1,9 @@
+use synckit_core::model::SyncModel;
+
+#[derive(SyncModel)]
+struct Note {
+    #[synckit(text)]
+    body: Vec<char>,
+}
+
+fn main() {}
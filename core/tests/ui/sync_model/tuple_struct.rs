@@ -0,0 +1,6 @@
+use synckit_core::model::SyncModel;
+
+#[derive(SyncModel)]
+struct Point(i64, i64);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use synckit_core::model::SyncModel;
+
+#[derive(SyncModel)]
+struct Todo {
+    #[synckit(map)]
+    labels: Vec<String>,
+}
+
+fn main() {}
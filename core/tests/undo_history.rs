@@ -0,0 +1,51 @@
+//! Undo history saved next to a text and restored after a reload must undo
+//! exactly what the never-reloaded replica undoes
+
+#![cfg(feature = "text-crdt")]
+
+use synckit_core::crdt::text_fugue::FugueText;
+use synckit_core::undo::UndoManager;
+
+/// Alice's edits, recorded by `undo`, with Bob editing concurrently
+fn edit(alice: &mut FugueText, undo: &mut UndoManager) {
+    let mut bob = FugueText::new("bob".to_string());
+    undo.insert_text(alice, 0, "The quick fox").unwrap();
+    bob.merge(alice).unwrap();
+
+    undo.insert_text(alice, 10, "brown ").unwrap();
+    bob.insert(13, " jumps").unwrap();
+    alice.merge(&bob).unwrap();
+
+    undo.delete_text(alice, 4, 6).unwrap();
+    undo.insert_text(alice, 0, ">> ").unwrap();
+    bob.merge(alice).unwrap();
+    bob.delete(0, 3).unwrap();
+    alice.merge(&bob).unwrap();
+}
+
+#[test]
+fn test_reloaded_history_undoes_like_the_original() {
+    let mut control = FugueText::new("alice".to_string());
+    let mut control_undo = UndoManager::new("alice".to_string());
+    edit(&mut control, &mut control_undo);
+    assert_eq!(control.to_string(), "The brown fox jumps");
+
+    // Save both, then load them into fresh instances
+    let saved_text = serde_json::to_vec(&control).unwrap();
+    let saved_undo = control_undo.to_bytes().unwrap();
+    let mut text: FugueText = serde_json::from_slice(&saved_text).unwrap();
+    let mut undo = UndoManager::from_bytes(&saved_undo, &mut text).unwrap();
+    assert_eq!(undo, control_undo);
+
+    for _ in 0..3 {
+        assert!(control_undo.undo(&mut control).unwrap());
+        assert!(undo.undo(&mut text).unwrap());
+        assert_eq!(text.to_string(), control.to_string());
+    }
+    assert_eq!(text.to_string(), "The quick fox jumps");
+
+    // Redo after the reload too
+    assert!(control_undo.redo(&mut control).unwrap());
+    assert!(undo.redo(&mut text).unwrap());
+    assert_eq!(text.to_string(), control.to_string());
+}
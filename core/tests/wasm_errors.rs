@@ -0,0 +1,78 @@
+//! The errors the JavaScript bindings throw, as JavaScript sees them
+//!
+//! `js_error` builds a JS `Error` named `SyncKitError` carrying the fields
+//! of `SyncKitErrorInfo` as own properties; the native tests in
+//! `src/wasm/bindings.rs` only see the Rust side. Run under Node with:
+//!
+//! ```text
+//! wasm-pack test --node -- --features wasm,text-crdt --test wasm_errors
+//! ```
+
+#![cfg(all(target_arch = "wasm32", feature = "wasm", feature = "text-crdt"))]
+
+use synckit_core::wasm::bindings::WasmFugueText;
+use synckit_core::wasm::WasmDocument;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// `error[key]` if it is an own property of the thrown object
+fn own(error: &JsValue, key: &str) -> Option<JsValue> {
+    let object: &js_sys::Object = error.unchecked_ref();
+    object
+        .has_own_property(&JsValue::from_str(key))
+        .then(|| js_sys::Reflect::get(error, &JsValue::from_str(key)).unwrap())
+}
+
+#[wasm_bindgen_test]
+fn test_thrown_error_is_a_sync_kit_error() {
+    let mut doc = WasmDocument::new("doc-1".to_string());
+    let error = doc
+        .set_field(
+            "user.name".to_string(),
+            "{not json".to_string(),
+            1,
+            "client1".to_string(),
+        )
+        .unwrap_err();
+
+    let js = error.dyn_ref::<js_sys::Error>().expect("an Error");
+    assert_eq!(js.name(), "SyncKitError");
+    assert!(String::from(js.message()).contains("Invalid JSON"));
+    assert_eq!(own(&error, "code").unwrap().as_f64(), Some(6003.0));
+    assert_eq!(
+        own(&error, "codeName").unwrap().as_string().as_deref(),
+        Some("INVALID_INPUT")
+    );
+    assert_eq!(
+        own(&error, "category").unwrap().as_string().as_deref(),
+        Some("validation")
+    );
+    assert_eq!(
+        own(&error, "documentId").unwrap().as_string().as_deref(),
+        Some("doc-1")
+    );
+    assert_eq!(
+        own(&error, "path").unwrap().as_string().as_deref(),
+        Some("user.name")
+    );
+    assert!(own(&error, "position").is_none());
+}
+
+#[wasm_bindgen_test]
+fn test_thrown_text_error_carries_its_position() {
+    let mut text = WasmFugueText::new("client1".to_string());
+    let error = text.insert(10, "x".to_string()).unwrap_err();
+
+    assert!(error.is_instance_of::<js_sys::Error>());
+    assert_eq!(
+        own(&error, "name").unwrap().as_string().as_deref(),
+        Some("SyncKitError")
+    );
+    assert_eq!(own(&error, "code").unwrap().as_f64(), Some(1001.0));
+    assert_eq!(
+        own(&error, "codeName").unwrap().as_string().as_deref(),
+        Some("POSITION_OUT_OF_BOUNDS")
+    );
+    assert_eq!(own(&error, "position").unwrap().as_f64(), Some(10.0));
+    assert!(own(&error, "documentId").is_none());
+}
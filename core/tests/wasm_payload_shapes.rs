@@ -0,0 +1,234 @@
+//! Round-trip JSON fixtures through the serde structs behind the WASM bindings
+//!
+//! The fixtures in `tests/fixtures/wasm/` mirror the TypeScript definitions
+//! in `src/wasm/types.rs`. If a struct's serialized shape changes, these
+//! tests fail and the `.d.ts` definitions need updating too.
+
+#![cfg(feature = "std")]
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Debug;
+
+fn fixture(name: &str) -> Value {
+    let path = format!(
+        "{}/tests/fixtures/wasm/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let json = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    serde_json::from_str(&json).unwrap()
+}
+
+/// Deserialize the fixture into `T` and check it serializes back unchanged
+fn assert_round_trip<T: Serialize + DeserializeOwned + Debug>(name: &str) -> T {
+    let expected = fixture(name);
+    let parsed: T = serde_json::from_value(expected.clone())
+        .unwrap_or_else(|e| panic!("{} does not match the Rust type: {}", name, e));
+
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), expected, "{}", name);
+    parsed
+}
+
+#[test]
+fn test_awareness_update_shape() {
+    use synckit_core::AwarenessUpdate;
+
+    let update: AwarenessUpdate = assert_round_trip("awareness_update.json");
+    assert_eq!(update.clock, 4);
+
+    let leave: AwarenessUpdate = assert_round_trip("awareness_update_leave.json");
+    assert!(leave.state.is_none());
+
+    let patch: AwarenessUpdate = assert_round_trip("awareness_update_patch.json");
+    assert_eq!(patch.base_clock, Some(5));
+
+    // Updates from senders that predate partial updates still parse
+    let legacy: AwarenessUpdate =
+        serde_json::from_str(r#"{"client_id":"client1","state":null,"clock":5}"#).unwrap();
+    assert_eq!(legacy.base_clock, None);
+}
+
+#[test]
+fn test_awareness_diff_shapes() {
+    use synckit_core::{AwarenessDiff, AwarenessVersion};
+
+    let version: AwarenessVersion = assert_round_trip("awareness_version.json");
+    assert_eq!(version.get("client1"), 5);
+
+    let diff: AwarenessDiff = assert_round_trip("awareness_diff.json");
+    assert_eq!(diff.len(), 2);
+}
+
+#[test]
+fn test_room_update_shape() {
+    use synckit_core::awareness::RoomUpdate;
+
+    let update: RoomUpdate = assert_round_trip("room_update.json");
+    assert_eq!(update.document_id, "doc-1");
+}
+
+#[test]
+fn test_awareness_event_shape() {
+    use synckit_core::AwarenessEvent;
+
+    let events: Vec<AwarenessEvent> = assert_round_trip("awareness_events.json");
+    assert!(matches!(events[2], AwarenessEvent::Left { .. }));
+    assert!(matches!(events[3], AwarenessEvent::Rejoined { .. }));
+    assert!(matches!(events[4], AwarenessEvent::Invalid { .. }));
+}
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn test_node_id_shape() {
+    use synckit_core::crdt::NodeId;
+
+    let id: NodeId = assert_round_trip("node_id.json");
+    assert_eq!(id, NodeId::new("client1".to_string(), 5, 2));
+}
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn test_text_event_shape() {
+    use synckit_core::crdt::TextEvent;
+
+    let events: Vec<TextEvent> = assert_round_trip("text_events.json");
+    assert_eq!(events, TextEvent::diff("Hello World", "Hello!"));
+}
+
+#[cfg(feature = "text-crdt")]
+#[test]
+fn test_line_position_shape() {
+    use synckit_core::crdt::LinePosition;
+
+    let position: LinePosition = assert_round_trip("line_position.json");
+    assert_eq!((position.line, position.column), (3, 7));
+}
+
+#[test]
+fn test_field_event_shape() {
+    use synckit_core::notify::FieldEvent;
+
+    let events: Vec<FieldEvent> = assert_round_trip("field_events.json");
+    assert_eq!(events[1].path(), "user.email");
+    assert!(matches!(events[1], FieldEvent::Delete { .. }));
+    assert!(
+        matches!(&events[2], FieldEvent::Lock { holder: Some(holder), .. } if holder == "alice")
+    );
+}
+
+#[test]
+fn test_list_id_shape() {
+    use synckit_core::list::ListId;
+
+    let id: ListId = assert_round_trip("list_id.json");
+    assert_eq!(id.clock, 3);
+    assert_eq!(id.client_id, "client1");
+}
+
+#[cfg(feature = "protocol-binary")]
+#[test]
+fn test_field_change_shape() {
+    use synckit_core::protocol::delta::FieldChange;
+
+    let change: FieldChange = assert_round_trip("field_change.json");
+    assert_eq!(change.path, "user.name");
+    assert!(!change.is_delete);
+    assert_eq!(change.origin, synckit_core::sync::ChangeOrigin::Remote);
+}
+
+#[cfg(feature = "protocol-binary")]
+#[test]
+fn test_dry_run_report_shape() {
+    use synckit_core::protocol::delta::DryRunReport;
+
+    let report: DryRunReport = assert_round_trip("dry_run_report.json");
+    assert_eq!(report.changed_paths, vec!["user.name"]);
+    assert!(report.conflicts[0].remote_wins);
+    assert_eq!(report.new_version.get("client1"), 3);
+}
+
+#[cfg(feature = "protocol-binary")]
+#[test]
+fn test_sync_status_shapes() {
+    use synckit_core::protocol::sync::{ConvergenceChecksum, SyncStatus};
+
+    let checksum: ConvergenceChecksum = assert_round_trip("convergence_checksum.json");
+    assert_eq!(checksum.hash.len(), 16);
+
+    let status: SyncStatus = assert_round_trip("sync_status.json");
+    assert!(status.diverged);
+    assert_eq!(status.last_verified_at, Some(1_700_000_000_000));
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn test_merge_report_shapes() {
+    use synckit_core::wasm::{CounterMergeReport, SetMergeReport};
+
+    let report: CounterMergeReport = assert_round_trip("counter_merge_report.json");
+    assert!(report.changed);
+
+    let report: SetMergeReport = assert_round_trip("set_merge_report.json");
+    assert_eq!(report.removed, vec!["cherry".to_string()]);
+}
+
+#[test]
+fn test_search_hits_shape() {
+    use synckit_core::index::SearchHit;
+
+    let hits: Vec<SearchHit> = assert_round_trip("search_hits.json");
+    assert_eq!(hits[0].fields, vec!["text:notes", "title"]);
+    assert!(hits[0].score > hits[1].score);
+}
+
+#[test]
+fn test_annotations_shape() {
+    use synckit_core::annotations::Annotation;
+
+    let annotations: Vec<Annotation> = assert_round_trip("annotations.json");
+    assert_eq!(annotations[0].payload["source"], "csv-import");
+    assert_eq!((annotations[1].start, annotations[1].end), (7, 7));
+}
+
+#[test]
+fn test_conflicts_shape() {
+    use synckit_core::conflicts::{Conflict, Keep};
+
+    let conflicts: Vec<Conflict> = assert_round_trip("conflicts.json");
+    assert_eq!(conflicts[0].id.to_string(), "3968eab21835284b");
+    assert_eq!(conflicts[0].lost().timestamp.client_id, "alice");
+
+    let keeps: Vec<Keep> = assert_round_trip("conflict_keeps.json");
+    assert_eq!(keeps[..2], [Keep::Local, Keep::Remote]);
+}
+
+#[test]
+fn test_field_versions_shape() {
+    let versions: Vec<(Value, u64, String)> = assert_round_trip("field_versions.json");
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[1].2, "bob");
+}
+
+#[test]
+fn test_merge_progress_shape() {
+    use synckit_core::merge_job::MergeProgress;
+
+    let progress: MergeProgress = assert_round_trip("merge_progress.json");
+    assert!(!progress.complete);
+    assert_eq!(progress.total, 120);
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn test_error_info_shape() {
+    use synckit_core::wasm::SyncKitErrorInfo;
+    use synckit_core::SyncKitError;
+
+    let info: SyncKitErrorInfo = assert_round_trip("error_info.json");
+    let error = SyncKitError::invalid_input("Invalid JSON: expected value at line 1 column 1")
+        .with_document("doc-1")
+        .with_path("user.name");
+    assert_eq!(SyncKitErrorInfo::from(&error), info);
+}
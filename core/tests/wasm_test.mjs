@@ -61,6 +61,19 @@ async function test() {
         const json = doc.toJSON();
         console.log(`✅ Document JSON:\n${json}\n`);
         
+        // Test errors
+        console.log('--- Testing Errors ---');
+        try {
+            doc.setField('bad', '{not json', 5n, 'client-1');
+            throw new Error('setField accepted invalid JSON');
+        } catch (err) {
+            if (err.name !== 'SyncKitError' || err.code !== 6003 ||
+                err.documentId !== 'test-doc-1' || err.path !== 'bad') {
+                throw err;
+            }
+            console.log(`✅ Invalid JSON rejected with code ${err.code} (${err.codeName})\n`);
+        }
+        
         console.log('✅ All Tests Passed!');
         
     } catch (error) {
@@ -0,0 +1,138 @@
+//! A workspace exported to an archive restores elsewhere with the same
+//! state, and damage to one document's entry costs that document only
+
+#![cfg(feature = "server")]
+
+use serde_json::json;
+use std::collections::HashMap;
+use synckit_core::server::{ArchiveError, EvictionPolicy, ImportMode, ImportOptions, Workspace};
+use synckit_core::storage::MemoryStorage;
+
+const DOCUMENTS: usize = 200;
+
+fn id(n: usize) -> String {
+    format!("doc-{:04}", n)
+}
+
+/// A workspace that evicted most of its documents, with lists, refs and
+/// a deleted document
+fn populated() -> Workspace {
+    let policy = EvictionPolicy {
+        capacity: 20,
+        ..EvictionPolicy::default()
+    };
+    let workspace = Workspace::with_storage(MemoryStorage::new(), policy);
+    for n in 0..DOCUMENTS {
+        workspace.get_or_create(&id(n)).write(|document| {
+            let client = format!("client-{}", n % 3);
+            document.set_field(
+                "title".to_string(),
+                json!(format!("Note {}", n)),
+                1,
+                client.clone(),
+            );
+            document.set_field("body".to_string(), json!("x".repeat(n)), 2, client.clone());
+            document
+                .list_mut("tags".to_string(), client.clone())
+                .push(json!(n % 7));
+            document.set_ref(
+                "next".to_string(),
+                id((n + 1) % DOCUMENTS),
+                3,
+                client.clone(),
+            );
+            document.version.update(&client, 3);
+        });
+    }
+    workspace.delete(&id(DOCUMENTS - 1));
+    assert!(workspace.lifecycle_metrics().unwrap().evicted > 0);
+    workspace
+}
+
+/// Every document's content hash, evicted ones loaded
+fn hashes(workspace: &Workspace) -> HashMap<String, u64> {
+    (0..DOCUMENTS)
+        .filter_map(|n| {
+            let document = workspace.document(&id(n))?;
+            Some((id(n), document.read_snapshot().content_hash()))
+        })
+        .collect()
+}
+
+fn export(workspace: &Workspace) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let manifest = workspace.export_archive(&mut bytes).unwrap();
+    assert_eq!(manifest.documents.len(), DOCUMENTS - 1);
+    assert_eq!(manifest.deleted, [id(DOCUMENTS - 1)]);
+    assert_eq!(manifest.version.get("client-1"), 3);
+    bytes
+}
+
+/// Flip bytes inside the archived state of document `n`
+fn damage(bytes: &mut [u8], n: usize) {
+    let needle = format!("\"id\":\"{}\"", id(n));
+    let at = bytes
+        .windows(needle.len())
+        .position(|window| window == needle.as_bytes())
+        .unwrap();
+    for byte in &mut bytes[at + needle.len()..at + needle.len() + 4] {
+        *byte ^= 0x5a;
+    }
+}
+
+#[test]
+fn test_restores_bit_identical_state() {
+    let source = populated();
+    let bytes = export(&source);
+
+    let target = Workspace::new();
+    let report = target
+        .import_archive(bytes.as_slice(), ImportOptions::default())
+        .unwrap();
+    assert_eq!(report.restored.len(), DOCUMENTS - 1);
+    assert!(report.corrupted.is_empty());
+    assert_eq!(report.deleted, 1);
+    assert!(target.is_deleted(&id(DOCUMENTS - 1)));
+    assert_eq!(hashes(&target), hashes(&source));
+    assert_eq!(target.checksum(), source.checksum());
+
+    // Importing again merges into what is there, changing nothing
+    target
+        .import_archive(bytes.as_slice(), ImportOptions::default())
+        .unwrap();
+    assert_eq!(target.checksum(), source.checksum());
+    let strict = ImportOptions {
+        mode: ImportMode::FailOnConflict,
+        ..ImportOptions::default()
+    };
+    assert!(matches!(
+        target.import_archive(bytes.as_slice(), strict),
+        Err(ArchiveError::Conflict { .. })
+    ));
+}
+
+#[test]
+fn test_damaged_entry_is_reported_and_the_rest_restored() {
+    let source = populated();
+    let mut bytes = export(&source);
+    damage(&mut bytes, 42);
+
+    let error = Workspace::new()
+        .import_archive(bytes.as_slice(), ImportOptions::default())
+        .unwrap_err();
+    assert!(matches!(error, ArchiveError::Corrupted { document_id } if document_id == id(42)));
+
+    let target = Workspace::new();
+    let options = ImportOptions {
+        skip_corrupted: true,
+        ..ImportOptions::default()
+    };
+    let report = target.import_archive(bytes.as_slice(), options).unwrap();
+    assert_eq!(report.corrupted, [id(42)]);
+    assert_eq!(report.restored.len(), DOCUMENTS - 2);
+    assert!(target.document(&id(42)).is_none());
+
+    let mut expected = hashes(&source);
+    expected.remove(&id(42));
+    assert_eq!(hashes(&target), expected);
+}
@@ -0,0 +1,164 @@
+//! A workspace serving far more documents than it may keep in memory:
+//! whatever it evicts must come back unchanged, and the number of
+//! documents in memory must stay at the cap
+
+#![cfg(feature = "server")]
+
+use serde_json::json;
+use std::collections::HashMap;
+use synckit_core::index::SearchIndex;
+use synckit_core::merge_job::MergeBudget;
+use synckit_core::server::{EvictionPolicy, Workspace};
+use synckit_core::storage::MemoryStorage;
+use synckit_core::Document;
+
+const DOCUMENTS: usize = 10_000;
+const CAPACITY: usize = 100;
+
+fn capped() -> Workspace {
+    let policy = EvictionPolicy {
+        capacity: CAPACITY,
+        ..EvictionPolicy::default()
+    };
+    Workspace::with_storage(MemoryStorage::new(), policy)
+}
+
+fn id(n: usize) -> String {
+    format!("doc-{:05}", n)
+}
+
+/// Deterministic pseudo-random sequence (xorshift), so failures replay
+struct Script(u64);
+
+impl Script {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+#[test]
+fn test_serves_evicted_documents_unchanged() {
+    let workspace = capped();
+    let reference = Workspace::new();
+    for n in 0..DOCUMENTS {
+        for target in [&workspace, &reference] {
+            target.get_or_create(&id(n)).set_field(
+                "title".to_string(),
+                json!(format!("Note {}", n)),
+                1,
+                "server".to_string(),
+            );
+        }
+        assert!(workspace.lifecycle_metrics().unwrap().resident <= CAPACITY);
+    }
+    let metrics = workspace.lifecycle_metrics().unwrap();
+    assert_eq!(metrics.resident, CAPACITY);
+    assert_eq!(metrics.evicted, DOCUMENTS - CAPACITY);
+    assert_eq!(workspace.len(), DOCUMENTS);
+    assert_eq!(workspace.checksum(), reference.checksum());
+
+    // Deltas for evicted documents land on the reloaded copy
+    let mut remote = Document::new(id(7));
+    remote.set_field("body".to_string(), json!("edited"), 2, "client".to_string());
+    workspace.get_or_create(&id(7)).merge(&remote);
+    reference.get_or_create(&id(7)).merge(&remote);
+    let served = workspace.document(&id(7)).unwrap().read_snapshot();
+    assert_eq!(
+        served.get_field(&"title".to_string()),
+        Some(&json!("Note 7"))
+    );
+    assert_eq!(
+        served.get_field(&"body".to_string()),
+        Some(&json!("edited"))
+    );
+    assert_eq!(workspace.checksum(), reference.checksum());
+    assert!(workspace.document("never-written").is_none());
+}
+
+#[test]
+fn test_memory_stays_bounded_over_a_long_workload() {
+    let workspace = capped();
+    let reference = Workspace::new();
+    workspace.enable_search(SearchIndex::new());
+    let mut script = Script(0x5eed);
+    let mut clocks: HashMap<usize, u64> = HashMap::new();
+
+    for step in 0..50_000 {
+        let n = script.next(DOCUMENTS);
+        if script.next(4) == 0 {
+            // A read, e.g. a client subscribing
+            let served = workspace.document(&id(n)).map(|d| d.to_json());
+            let expected = reference.document(&id(n)).map(|d| d.to_json());
+            assert_eq!(served, expected, "step {}", step);
+        } else {
+            let clock = clocks.entry(n).or_default();
+            *clock += 1;
+            for target in [&workspace, &reference] {
+                target.get_or_create(&id(n)).set_field(
+                    "body".to_string(),
+                    json!(format!("rev {} of {}", clock, n)),
+                    *clock,
+                    "client".to_string(),
+                );
+            }
+        }
+        assert!(
+            workspace.lifecycle_metrics().unwrap().resident <= CAPACITY,
+            "step {}",
+            step
+        );
+    }
+
+    let metrics = workspace.lifecycle_metrics().unwrap();
+    assert!(metrics.loads > 0);
+    assert!(metrics.evictions >= metrics.loads);
+    assert_eq!(metrics.failures, 0);
+    assert_eq!(workspace.len(), reference.len());
+    assert_eq!(workspace.checksum(), reference.checksum());
+
+    // Search still finds documents that were evicted after indexing
+    let (&n, clock) = clocks.iter().min().unwrap();
+    let query = format!("rev {} of {}", clock, n);
+    let hits = workspace.search(&query, 10);
+    assert!(hits.iter().any(|hit| hit.document_id == id(n)));
+}
+
+#[test]
+fn test_documents_being_merged_are_never_evicted() {
+    let workspace = capped();
+    let mut remote = Document::new(id(0));
+    for field in 0..1_000 {
+        remote.set_field(format!("f{}", field), json!(field), 1, "peer".to_string());
+    }
+
+    // A session merges in slices, holding its handle in between
+    let session = workspace.get_or_create(&id(0));
+    let mut job = session.read(|document| document.start_merge(remote.clone()));
+    let mut created = 1;
+    loop {
+        let progress = session.write(|document| job.run_for(document, MergeBudget::Ops(50)));
+        // Meanwhile other clients fill the workspace past its cap
+        for _ in 0..20 {
+            workspace.get_or_create(&id(created));
+            created += 1;
+        }
+        if progress.complete {
+            break;
+        }
+    }
+    // It was the oldest all along, and skipped every time
+    assert!(workspace.lifecycle_metrics().unwrap().pinned >= 300);
+    assert!(workspace.document(&id(0)).unwrap().ptr_eq(&session));
+    drop(session);
+
+    // Released, it can go, and comes back whole
+    for n in created..created + CAPACITY {
+        workspace.get_or_create(&id(n));
+    }
+    assert!(workspace.lifecycle_metrics().unwrap().evicted > 0);
+    let merged = workspace.document(&id(0)).unwrap().read_snapshot();
+    assert_eq!(merged.fields(), remote.fields());
+}
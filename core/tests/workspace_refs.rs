@@ -0,0 +1,188 @@
+//! Refs between workspace documents: deleting a target must win over a
+//! concurrent ref to it, and the backlink index must match the refs the
+//! documents actually hold after any sequence of merges
+
+#![cfg(feature = "server")]
+
+use serde_json::json;
+use std::collections::BTreeMap;
+use synckit_core::server::{EvictionPolicy, Workspace};
+use synckit_core::storage::MemoryStorage;
+use synckit_core::{DocumentID, FieldPath};
+
+const DOCUMENTS: usize = 60;
+
+fn id(n: usize) -> String {
+    format!("doc-{:02}", n)
+}
+
+/// Deterministic pseudo-random sequence (xorshift), so failures replay
+struct Script(u64);
+
+impl Script {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+/// Two servers that both had "user"; one deletes it while the other
+/// points a task at it
+fn diverged() -> (Workspace, Workspace) {
+    let (a, b) = (Workspace::new(), Workspace::new());
+    for workspace in [&a, &b] {
+        workspace.get_or_create("user").set_field(
+            "name".to_string(),
+            json!("Ada"),
+            1,
+            "server".to_string(),
+        );
+    }
+    assert!(a.delete("user"));
+    b.get_or_create("task").write(|document| {
+        document.set_ref(
+            "assignee".to_string(),
+            "user".to_string(),
+            1,
+            "bob".to_string(),
+        )
+    });
+    (a, b)
+}
+
+#[test]
+fn test_delete_wins_over_a_concurrent_ref_in_either_merge_order() {
+    let assignee = "assignee".to_string();
+    for deleting_side_first in [true, false] {
+        let (a, b) = diverged();
+        let mut a_deletions = a.deletions();
+        let mut b_deletions = b.deletions();
+        if deleting_side_first {
+            a.merge(&b);
+            b.merge(&a);
+        } else {
+            b.merge(&a);
+            a.merge(&b);
+        }
+
+        assert_eq!(a.checksum(), b.checksum());
+        for workspace in [&a, &b] {
+            assert!(workspace.document("user").is_none());
+            assert!(workspace.resolve_ref("task", &assignee).is_none());
+            assert!(workspace.backlinks("user").is_empty());
+            // The ref itself survives, dangling
+            let target = workspace
+                .document("task")
+                .unwrap()
+                .read(|document| document.get_ref(&assignee).cloned());
+            assert_eq!(target.as_deref(), Some("user"));
+        }
+
+        // Only the side that held the ref learns it now dangles
+        let deleted = b_deletions.try_recv().unwrap();
+        assert_eq!(deleted.document_id, "user");
+        assert_eq!(
+            deleted.referrers,
+            vec![("task".to_string(), assignee.clone())]
+        );
+        assert!(a_deletions.try_recv().is_err());
+    }
+}
+
+/// The backlinks of every document, worked out from the documents
+fn brute_force(workspace: &Workspace) -> BTreeMap<DocumentID, Vec<(DocumentID, FieldPath)>> {
+    let mut expected: BTreeMap<DocumentID, Vec<(DocumentID, FieldPath)>> = BTreeMap::new();
+    for n in 0..DOCUMENTS {
+        let Some(document) = workspace.document(&id(n)) else {
+            continue;
+        };
+        for (path, link) in document.read_snapshot().refs() {
+            if let Some(target) = &link.target {
+                if !workspace.is_deleted(target) {
+                    expected
+                        .entry(target.clone())
+                        .or_default()
+                        .push((id(n), path.clone()));
+                }
+            }
+        }
+    }
+    for referrers in expected.values_mut() {
+        referrers.sort();
+    }
+    expected
+}
+
+fn indexed(workspace: &Workspace) -> BTreeMap<DocumentID, Vec<(DocumentID, FieldPath)>> {
+    (0..DOCUMENTS)
+        .map(|n| (id(n), workspace.backlinks(&id(n))))
+        .filter(|(_, referrers)| !referrers.is_empty())
+        .collect()
+}
+
+#[test]
+fn test_backlinks_stay_consistent_across_bulk_merges() {
+    let policy = EvictionPolicy {
+        capacity: 20,
+        ..EvictionPolicy::default()
+    };
+    let workspaces = [
+        Workspace::new(),
+        Workspace::new(),
+        Workspace::with_storage(MemoryStorage::new(), policy),
+    ];
+    let clients = ["east", "west", "north"];
+    let mut script = Script(0x11_2e_f5);
+
+    for step in 0..3_000u64 {
+        let side = script.next(workspaces.len());
+        let workspace = &workspaces[side];
+        let client = clients[side].to_string();
+        let from = id(script.next(DOCUMENTS));
+        let path = format!("link-{}", script.next(3));
+        match script.next(20) {
+            0 => {
+                workspace.delete(&from);
+            }
+            1..=3 => {
+                workspace
+                    .get_or_create(&from)
+                    .write(|document| document.clear_ref(path, step, client));
+            }
+            4..=6 => {
+                workspace
+                    .get_or_create(&from)
+                    .set_field(path, json!(step), step, client);
+            }
+            _ => {
+                let to = id(script.next(DOCUMENTS));
+                workspace
+                    .get_or_create(&from)
+                    .write(|document| document.set_ref(path, to, step, client));
+            }
+        }
+
+        if step % 250 == 249 {
+            let other = (side + 1 + script.next(workspaces.len() - 1)) % workspaces.len();
+            workspace.merge(&workspaces[other]);
+            assert_eq!(indexed(workspace), brute_force(workspace), "step {}", step);
+        }
+    }
+
+    // Gossip until everyone has everything
+    for _ in 0..2 {
+        for (n, workspace) in workspaces.iter().enumerate() {
+            workspace.merge(&workspaces[(n + 1) % workspaces.len()]);
+        }
+    }
+    let checksum = workspaces[0].checksum();
+    let expected = brute_force(&workspaces[0]);
+    assert!(!expected.is_empty());
+    for workspace in &workspaces {
+        assert_eq!(workspace.checksum(), checksum);
+        assert_eq!(indexed(workspace), expected);
+    }
+    assert!(workspaces[2].lifecycle_metrics().unwrap().evictions > 0);
+}
@@ -31,6 +31,9 @@ export interface WasmDocument {
   fieldCount(): number
   toJSON(): string
   merge(other: WasmDocument): void
+  isDirty(): boolean
+  takeDirty(): Uint8Array
+  applyDirty(chunk: Uint8Array): void
   free(): void
 }
 
@@ -44,7 +47,7 @@ export interface WasmVectorClock {
 }
 
 export interface WasmDelta {
-  applyTo(document: WasmDocument, clientId: string): void
+  applyTo(document: WasmDocument, clientId: string): string
   getDocumentId(): string
   changeCount(): number
   toJSON(): string
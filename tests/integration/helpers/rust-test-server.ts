@@ -0,0 +1,123 @@
+/**
+ * Rust Test Server Harness
+ *
+ * Runs `synckit-testserver` (core/src/bin/synckit_testserver) as a child
+ * process: the sync protocol over a link with simulated latency, jitter,
+ * drops and forced disconnects, plus an HTTP endpoint reporting document
+ * checksums and metrics
+ */
+
+import { spawn, type ChildProcess } from 'node:child_process';
+import { createInterface } from 'node:readline';
+import path from 'node:path';
+
+/**
+ * How the server impairs the link
+ */
+export interface RustTestServerOptions {
+  /** Delay added to each message, both ways, e.g. '50ms' */
+  latency?: string;
+  /** Up to this much more delay, at random */
+  jitter?: string;
+  /** Share of messages dropped, both ways (0 to 1) */
+  dropRate?: number;
+  /** Close every connection this long after it opened, e.g. '10s' */
+  disconnectEvery?: string;
+  /** Path to a scenario script of scheduled server writes and disconnects */
+  scenario?: string;
+  /** Accept only this token */
+  authToken?: string;
+  /** Seed for jitter and drops, to replay a run */
+  seed?: number;
+  /** Path to the binary; defaults to SYNCKIT_TESTSERVER or the debug build */
+  binary?: string;
+}
+
+/**
+ * One document as the server holds it
+ */
+export interface RustDocumentState {
+  checksum: string;
+  version: Record<string, number>;
+  fields: Record<string, unknown>;
+}
+
+/**
+ * What `GET /state` returns
+ */
+export interface RustServerState {
+  documents: Record<string, RustDocumentState>;
+  workspace_checksum: string;
+  metrics: Record<string, number>;
+}
+
+const DEFAULT_BINARY = path.resolve(
+  import.meta.dir,
+  '../../../core/target/debug/synckit-testserver'
+);
+
+/**
+ * A running synckit-testserver
+ */
+export class RustTestServer {
+  private constructor(
+    private readonly child: ChildProcess,
+    /** WebSocket URL to point clients at */
+    readonly wsUrl: string,
+    /** Base URL of the introspection endpoint */
+    readonly httpUrl: string
+  ) {}
+
+  /**
+   * Start the server on free ports
+   */
+  static async start(options: RustTestServerOptions = {}): Promise<RustTestServer> {
+    const args = ['--listen', '127.0.0.1:0', '--http', '127.0.0.1:0'];
+    if (options.latency) args.push('--latency', options.latency);
+    if (options.jitter) args.push('--jitter', options.jitter);
+    if (options.dropRate !== undefined) args.push('--drop-rate', String(options.dropRate));
+    if (options.disconnectEvery) args.push('--disconnect-every', options.disconnectEvery);
+    if (options.scenario) args.push('--scenario', options.scenario);
+    if (options.authToken) args.push('--auth-token', options.authToken);
+    if (options.seed !== undefined) args.push('--seed', String(options.seed));
+
+    const binary = options.binary ?? process.env.SYNCKIT_TESTSERVER ?? DEFAULT_BINARY;
+    const child = spawn(binary, args, { stdio: ['ignore', 'pipe', 'inherit'] });
+
+    // The first line on stdout is the bound addresses as JSON
+    const addresses = await new Promise<{ ws: string; http: string }>((resolve, reject) => {
+      const lines = createInterface({ input: child.stdout! });
+      lines.once('line', (line) => {
+        lines.close();
+        try {
+          resolve(JSON.parse(line));
+        } catch (error) {
+          reject(new Error(`synckit-testserver printed ${line}: ${error}`));
+        }
+      });
+      child.once('error', reject);
+      child.once('exit', (code) =>
+        reject(new Error(`synckit-testserver exited with ${code}; was it built with --features testserver?`))
+      );
+    });
+    return new RustTestServer(child, addresses.ws, addresses.http);
+  }
+
+  /**
+   * Documents, checksums and metrics
+   */
+  async state(): Promise<RustServerState> {
+    const response = await fetch(`${this.httpUrl}/state`);
+    return (await response.json()) as RustServerState;
+  }
+
+  /**
+   * Stop the server
+   */
+  async stop(): Promise<void> {
+    if (this.child.exitCode !== null) return;
+    const exited = new Promise((resolve) => this.child.once('exit', resolve));
+    this.child.kill();
+    await exited;
+  }
+}